@@ -4,4 +4,16 @@ fn main() {
     ShadowBuilder::builder()
         .build()
         .expect("shadow init for nyx_space failed");
+
+    // Compile the protobuf API without requiring protoc
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto");
+        let fds = protox::compile(["nyx/v1/nyx.proto"], ["proto"])
+            .expect("could not parse the protobuf API");
+        tonic_build::configure()
+            .build_client(true)
+            .compile_fds(fds)
+            .expect("could not generate the gRPC service");
+    }
 }
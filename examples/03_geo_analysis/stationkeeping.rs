@@ -40,11 +40,10 @@ fn main() -> Result<(), Box<dyn Error>> {
         .orbit(orbit)
         .mass(Mass::from_dry_and_prop_masses(1000.0, 1000.0)) // 1000 kg of dry mass and prop, totalling 2.0 tons
         .srp(SRPData::from_area(3.0 * 6.0)) // Assuming 1 kW/m^2 or 18 kW, giving a margin of 4.35 kW for on-propulsion consumption
-        .thruster(Thruster {
+        .thruster(
             // "NEXT-STEP" row in Table 2
-            isp_s: 4435.0,
-            thrust_N: 0.472,
-        })
+            Thruster::builder().isp_s(4435.0).thrust_N(0.472).build(),
+        )
         .mode(GuidanceMode::Thrust) // Start thrusting immediately.
         .build();
 
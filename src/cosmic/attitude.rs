@@ -0,0 +1,234 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::cosmic::Orbit;
+use crate::linalg::{Matrix3, Vector3};
+use anise::almanac::Almanac;
+use anise::constants::frames::SUN_J2000;
+use anise::errors::AlmanacResult;
+use nalgebra::{Rotation3, UnitQuaternion};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+/// Commanded attitude profile of a spacecraft.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AttitudeProfile {
+    /// Fixed orientation of the body frame with respect to the inertial frame of the orbit
+    Inertial(UnitQuaternion<f64>),
+    /// Body Z axis towards the center of the central body, body Y axis along the negative orbit normal (i.e. the LVLH frame)
+    Nadir,
+    /// Body Z axis towards the Sun, body X axis perpendicular to the orbit normal
+    SunPointing,
+}
+
+/// Attitude of a spacecraft, propagated kinematically from its commanded profile, i.e. the body frame follows the profile exactly.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Attitude {
+    pub profile: AttitudeProfile,
+    /// Rotation from the body frame to the inertial frame of the orbit
+    pub q_body_to_inertial: UnitQuaternion<f64>,
+}
+
+impl Attitude {
+    /// Initializes the attitude of this profile, which is only set once updated at an orbit (except for inertial profiles).
+    pub fn new(profile: AttitudeProfile) -> Self {
+        let q_body_to_inertial = match profile {
+            AttitudeProfile::Inertial(q) => q,
+            _ => UnitQuaternion::identity(),
+        };
+        Self {
+            profile,
+            q_body_to_inertial,
+        }
+    }
+
+    /// Returns the DCM from the body frame to the inertial frame of the orbit
+    pub fn dcm_body_to_inertial(&self) -> Matrix3<f64> {
+        self.q_body_to_inertial.to_rotation_matrix().into_inner()
+    }
+
+    /// Returns the body vector rotated in the inertial frame of the orbit, e.g. the boresight of an antenna.
+    pub fn body_to_inertial(&self, body_vec: Vector3<f64>) -> Vector3<f64> {
+        self.q_body_to_inertial * body_vec
+    }
+
+    /// Updates the attitude from the commanded profile at this orbit. The Sun pointing profile requires the ephemeris of the Sun.
+    pub fn update(&mut self, orbit: Orbit, almanac: Arc<Almanac>) -> AlmanacResult<()> {
+        let orbit_normal = orbit.radius_km.cross(&orbit.velocity_km_s);
+        self.q_body_to_inertial = match self.profile {
+            AttitudeProfile::Inertial(q) => q,
+            AttitudeProfile::Nadir => {
+                let z_b = -orbit.radius_km.normalize();
+                let y_b = -orbit_normal.normalize();
+                frame_from_axes(y_b.cross(&z_b), y_b, z_b)
+            }
+            AttitudeProfile::SunPointing => {
+                // The position of the spacecraft as seen from the Sun, which is opposite to the Sun direction
+                let z_b = -almanac
+                    .transform_to(orbit, SUN_J2000, None)?
+                    .radius_km
+                    .normalize();
+                let x_b = orbit_normal
+                    .cross(&z_b)
+                    .try_normalize(f64::EPSILON)
+                    // The orbit normal points to the Sun, so any perpendicular axis works
+                    .unwrap_or_else(|| z_b.cross(&Vector3::x()).normalize());
+                frame_from_axes(x_b, z_b.cross(&x_b), z_b)
+            }
+        };
+        Ok(())
+    }
+}
+
+/// Projected areas of a box shaped spacecraft along each of its body axes, in m^2.
+///
+/// The cross-section seen from a direction is the sum of the areas weighted by the absolute value of the components of
+/// that direction in the body frame, such that a fixed-attitude vehicle presents a different area to the Sun and to the flow.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AxisAreas {
+    pub x_m2: f64,
+    pub y_m2: f64,
+    pub z_m2: f64,
+}
+
+impl AxisAreas {
+    pub fn new(x_m2: f64, y_m2: f64, z_m2: f64) -> Self {
+        Self { x_m2, y_m2, z_m2 }
+    }
+
+    /// Returns the cross-section seen from this direction expressed in the body frame, in m^2
+    pub fn projected_m2(&self, dir_body: &Vector3<f64>) -> f64 {
+        let dir = dir_body.normalize();
+        dir.x.abs() * self.x_m2 + dir.y.abs() * self.y_m2 + dir.z.abs() * self.z_m2
+    }
+}
+
+impl fmt::Display for AxisAreas {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "areas = [{}, {}, {}] m^2",
+            self.x_m2, self.y_m2, self.z_m2
+        )
+    }
+}
+
+/// Returns the rotation from a body frame to the inertial frame given the body axes expressed in the inertial frame.
+fn frame_from_axes(x_b: Vector3<f64>, y_b: Vector3<f64>, z_b: Vector3<f64>) -> UnitQuaternion<f64> {
+    UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix_unchecked(Matrix3::from_columns(
+        &[x_b, y_b, z_b],
+    )))
+}
+
+impl fmt::Display for Attitude {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let q = self.q_body_to_inertial;
+        write!(
+            f,
+            "{:?} attitude q = [{:.6}, {:.6}, {:.6}, {:.6}]",
+            self.profile, q.w, q.i, q.j, q.k
+        )
+    }
+}
+
+#[cfg(test)]
+mod ut_attitude {
+    use super::*;
+    use crate::cosmic::Spacecraft;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::propagators::Propagator;
+    use crate::time::Unit;
+
+    #[test]
+    fn kinematic_profiles() {
+        let orbit = fixtures::keplerian(7_000.0, 0.01, 28.5, 10.0, 20.0, 30.0);
+        let almanac = fixtures::almanac();
+
+        let mut nadir = Attitude::new(AttitudeProfile::Nadir);
+        nadir.update(orbit, almanac.clone()).unwrap();
+        let boresight = nadir.body_to_inertial(Vector3::z());
+        assert!((boresight + orbit.radius_km.normalize()).norm() < 1e-12);
+        // The body X axis is along the velocity for a circular orbit, and close to it here
+        let x_b = nadir.body_to_inertial(Vector3::x());
+        assert!(x_b.dot(&orbit.velocity_km_s.normalize()) > 0.99);
+        assert!((nadir.dcm_body_to_inertial().determinant() - 1.0).abs() < 1e-12);
+
+        let q = UnitQuaternion::from_euler_angles(0.1, 0.2, 0.3);
+        let mut inertial = Attitude::new(AttitudeProfile::Inertial(q));
+        inertial.update(orbit, almanac.clone()).unwrap();
+        assert_eq!(inertial.q_body_to_inertial, q);
+
+        // The Sun pointing profile requires the ephemeris of the Sun
+        let mut sun = Attitude::new(AttitudeProfile::SunPointing);
+        assert!(sun.update(orbit, almanac.clone()).is_err());
+
+        // The attitude follows its profile during the propagation
+        let sc = Spacecraft::from(orbit).with_attitude(AttitudeProfile::Nadir);
+        let end = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()))
+            .with(sc, almanac)
+            .for_duration(Unit::Minute * 45)
+            .unwrap();
+        let boresight = end.attitude.unwrap().body_to_inertial(Vector3::z());
+        assert!((boresight + end.orbit.radius_km.normalize()).norm() < 1e-12);
+    }
+
+    #[test]
+    fn axis_areas() {
+        use crate::dynamics::{ConstantDrag, ForceModel};
+
+        let epoch = fixtures::epoch();
+        let eme2k = fixtures::eme2k();
+        let orbit = Orbit::cartesian(7_000.0, 0.0, 0.0, 0.0, 7.5, 0.0, epoch, eme2k);
+        let areas = AxisAreas::new(1.0, 4.0, 10.0);
+        assert_eq!(areas.projected_m2(&Vector3::new(0.0, -2.0, 0.0)), 4.0);
+        let diag = Vector3::new(1.0, 1.0, 0.0);
+        assert!((areas.projected_m2(&diag) - 5.0 / 2.0_f64.sqrt()).abs() < 1e-12);
+
+        // Without areas, the scalar areas are used
+        let sc = Spacecraft::from(orbit)
+            .with_srp(2.0, 1.8)
+            .with_drag(3.0, 2.2);
+        assert_eq!(sc.srp_area_m2(&Vector3::x()), 2.0);
+        assert_eq!(sc.drag_area_m2(&Vector3::y()), 3.0);
+
+        // Without attitude, the body frame is aligned with the inertial frame
+        let boxed = sc.with_areas(areas);
+        assert_eq!(boxed.srp_area_m2(&Vector3::x()), 1.0);
+        assert_eq!(boxed.drag_area_m2(&Vector3::y()), 4.0);
+
+        // Rotating the body by 90 degrees about Z swaps the X and Y cross-sections
+        let q = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), std::f64::consts::FRAC_PI_2);
+        let rotated = boxed.with_attitude(AttitudeProfile::Inertial(q));
+        assert!((rotated.srp_area_m2(&Vector3::x()) - 4.0).abs() < 1e-12);
+        assert!((rotated.drag_area_m2(&Vector3::y()) - 1.0).abs() < 1e-12);
+
+        // The drag acceleration follows the cross-section along the flow
+        let drag = ConstantDrag {
+            rho: 1e-12,
+            drag_frame: eme2k,
+            estimate: false,
+        };
+        let almanac = fixtures::almanac();
+        let acc_scalar = drag.eom(&sc, almanac.clone()).unwrap().norm();
+        let acc_rotated = drag.eom(&rotated, almanac).unwrap().norm();
+        assert!((acc_rotated / acc_scalar - 1.0 / 3.0).abs() < 1e-9);
+    }
+}
@@ -16,11 +16,12 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use anise::prelude::{Frame, Orbit};
+use anise::prelude::{Almanac, Frame, Orbit};
 
-use super::{AstroError, AstroPhysicsSnafu, OrbitDual, OrbitPartial};
-use crate::cosmic::NotHyperbolicSnafu;
-use crate::linalg::{Matrix2, Matrix3, Vector2, Vector3};
+use super::{AstroAlmanacSnafu, AstroError, AstroPhysicsSnafu, OrbitDual, OrbitPartial};
+use crate::cosmic::{FrameTransform, NotHyperbolicSnafu};
+use crate::linalg::{Matrix2, Matrix3, Matrix3x6, Matrix6, Vector2, Vector3};
+use crate::mc::DispersionEllipse;
 use crate::md::objective::Objective;
 use crate::md::{AstroSnafu, StateParameter, TargetingError};
 use crate::time::{Duration, Epoch, Unit};
@@ -139,6 +140,35 @@ impl BPlane {
         Self::from_dual(OrbitDual::from(orbit))
     }
 
+    /// Returns the B-Plane of this orbit about the target body of this frame, e.g. as returned by
+    /// `almanac.frame_from_uid(MOON_J2000)`, or an error if the orbit is not hyperbolic about that body.
+    pub fn about(orbit: Orbit, target: Frame, almanac: &Almanac) -> Result<Self, AstroError> {
+        let orbit = almanac
+            .transform_to(orbit, target, None)
+            .context(AstroAlmanacSnafu)?;
+        Self::new(orbit)
+    }
+
+    /// Returns the B-Plane of this orbit about the target body of this frame, and its covariance mapped from this 6x6
+    /// Cartesian covariance of the orbit, expressed in the frame of the orbit (km and km/s).
+    pub fn about_with_covariance(
+        orbit: Orbit,
+        covar: &Matrix6<f64>,
+        target: Frame,
+        almanac: &Almanac,
+    ) -> Result<(Self, BPlaneCovariance), AstroError> {
+        let transform = FrameTransform::compute(orbit.frame, target, orbit.epoch, almanac)
+            .context(AstroAlmanacSnafu)?;
+        let bplane = Self::new(Orbit::from_cartesian_pos_vel(
+            transform.apply(&orbit.to_cartesian_pos_vel()),
+            orbit.epoch,
+            target,
+        ))?;
+        let covar = transform.matrix * covar * transform.matrix.transpose();
+        let bplane_covar = bplane.map_covariance(&covar);
+        Ok((bplane, bplane_covar))
+    }
+
     /// Returns the DCM to convert to the B Plane from the inertial frame
     pub fn inertial_to_bplane(&self) -> Matrix3<f64> {
         self.str_dcm
@@ -159,6 +189,32 @@ impl BPlane {
         )
     }
 
+    /// Returns the Jacobian of the B plane (BR, BT, LTOF) with respect to the Cartesian state (X, Y, Z, VX, VY, VZ) in the
+    /// frame of this B plane.
+    pub fn state_jacobian(&self) -> Matrix3x6<f64> {
+        let mut jac = Matrix3x6::zeros();
+        for (i, partial) in [self.b_r, self.b_t, self.ltof_s].iter().enumerate() {
+            jac[(i, 0)] = partial.wtr_x();
+            jac[(i, 1)] = partial.wtr_y();
+            jac[(i, 2)] = partial.wtr_z();
+            jac[(i, 3)] = partial.wtr_vx();
+            jac[(i, 4)] = partial.wtr_vy();
+            jac[(i, 5)] = partial.wtr_vz();
+        }
+        jac
+    }
+
+    /// Maps this 6x6 Cartesian covariance of the orbit (in km and km/s, in the frame of this B plane) into the covariance
+    /// of the B plane coordinates, to first order.
+    pub fn map_covariance(&self, covar: &Matrix6<f64>) -> BPlaneCovariance {
+        let jac = self.state_jacobian();
+        BPlaneCovariance {
+            b_dot_t_km: self.b_dot_t(),
+            b_dot_r_km: self.b_dot_r(),
+            covar: jac * covar * jac.transpose(),
+        }
+    }
+
     /// Returns the Jacobian of the B plane (BR, BT) with respect to two of the velocity components
     pub fn jacobian2(&self, invariant: StateParameter) -> Result<Matrix2<f64>, AstroError> {
         match invariant {
@@ -223,6 +279,67 @@ impl fmt::Display for BPlane {
     }
 }
 
+/// Covariance of the B plane coordinates, mapped from the covariance of the orbit with the partials of the B plane.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BPlaneCovariance {
+    /// The nominal $B_T$ component, in kilometers
+    pub b_dot_t_km: f64,
+    /// The nominal $B_R$ component, in kilometers
+    pub b_dot_r_km: f64,
+    /// Covariance of (BR, BT, LTOF), in km^2, km s and s^2
+    pub covar: Matrix3<f64>,
+}
+
+impl BPlaneCovariance {
+    /// Returns the 1-sigma dispersion of B∙R, in kilometers
+    pub fn sigma_b_dot_r_km(&self) -> f64 {
+        self.covar[(0, 0)].sqrt()
+    }
+
+    /// Returns the 1-sigma dispersion of B∙T, in kilometers
+    pub fn sigma_b_dot_t_km(&self) -> f64 {
+        self.covar[(1, 1)].sqrt()
+    }
+
+    /// Returns the 1-sigma dispersion of the linearized time of flight
+    pub fn sigma_ltof(&self) -> Duration {
+        self.covar[(2, 2)].sqrt() * Unit::Second
+    }
+
+    /// Returns the covariance of (B∙T, B∙R), in km^2, as in the B-Plane scatter of a Monte Carlo
+    pub fn b_plane_covar(&self) -> Matrix2<f64> {
+        Matrix2::new(
+            self.covar[(1, 1)],
+            self.covar[(1, 0)],
+            self.covar[(0, 1)],
+            self.covar[(0, 0)],
+        )
+    }
+
+    /// Returns the dispersion ellipse at the requested number of standard deviations, centered on the nominal B plane.
+    pub fn ellipse(&self, sigma: f64) -> DispersionEllipse {
+        DispersionEllipse::from_covariance(
+            Vector2::new(self.b_dot_t_km, self.b_dot_r_km),
+            &self.b_plane_covar(),
+            sigma,
+        )
+    }
+}
+
+impl fmt::Display for BPlaneCovariance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "B∙R = {:.3} ± {:.3} km\tB∙T = {:.3} ± {:.3} km\tLTOF ± {}",
+            self.b_dot_r_km,
+            self.sigma_b_dot_r_km(),
+            self.b_dot_t_km,
+            self.sigma_b_dot_t_km(),
+            self.sigma_ltof()
+        )
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct BPlaneTarget {
     /// The $B_T$ component, in kilometers
@@ -326,7 +443,9 @@ pub fn try_achieve_b_plane(
         // If no LTOF is targeted, we'll solve this with a least squared approach.
         loop {
             if attempt_no > max_iter {
-                return Err(TargetingError::TooManyIterations);
+                return Err(TargetingError::TooManyIterations {
+                    iterations: max_iter,
+                });
             }
 
             // Build current B Plane
@@ -374,7 +493,9 @@ pub fn try_achieve_b_plane(
         // The LTOF targeting seems to break often, but it's still implemented
         loop {
             if attempt_no > max_iter {
-                return Err(TargetingError::TooManyIterations);
+                return Err(TargetingError::TooManyIterations {
+                    iterations: max_iter,
+                });
             }
 
             // Build current B Plane
@@ -420,3 +541,68 @@ pub fn try_achieve_b_plane(
         }
     }
 }
+
+#[cfg(test)]
+mod ut_bplane {
+    use super::*;
+    use crate::fixtures;
+    use crate::linalg::Vector6;
+
+    #[test]
+    fn covariance_and_partials() {
+        let eme2k = fixtures::eme2k();
+        let epoch = fixtures::epoch();
+        let orbit = Orbit::new(-150_000.0, 80_000.0, 20_000.0, 2.5, -1.8, 0.3, epoch, eme2k);
+        let bplane = BPlane::new(orbit).unwrap();
+
+        // The state partials match central finite differences
+        let jac = bplane.state_jacobian();
+        let state = orbit.to_cartesian_pos_vel();
+        for j in 0..6 {
+            let h = if j < 3 { 1e-1 } else { 1e-5 };
+            let mut dx = Vector6::zeros();
+            dx[j] = h;
+            let plus =
+                BPlane::new(Orbit::from_cartesian_pos_vel(state + dx, epoch, eme2k)).unwrap();
+            let minus =
+                BPlane::new(Orbit::from_cartesian_pos_vel(state - dx, epoch, eme2k)).unwrap();
+            let fd = [
+                plus.b_dot_r() - minus.b_dot_r(),
+                plus.b_dot_t() - minus.b_dot_t(),
+                plus.ltof_s.real() - minus.ltof_s.real(),
+            ];
+            for i in 0..3 {
+                let expected = fd[i] / (2.0 * h);
+                assert!(
+                    (jac[(i, j)] - expected).abs() <= 1e-5 * expected.abs().max(1.0),
+                    "({i}, {j}): {} != {expected}",
+                    jac[(i, j)]
+                );
+            }
+        }
+        assert_eq!(jac.fixed_columns::<3>(3), bplane.jacobian());
+
+        // A velocity uncertainty along VX only maps through the VX partials
+        let mut covar = Matrix6::zeros();
+        covar[(3, 3)] = 1e-6;
+        let bplane_covar = bplane.map_covariance(&covar);
+        println!("{bplane_covar}");
+        assert!((bplane_covar.sigma_b_dot_r_km() - 1e-3 * jac[(0, 3)].abs()).abs() < 1e-9);
+        assert!((bplane_covar.sigma_b_dot_t_km() - 1e-3 * jac[(1, 3)].abs()).abs() < 1e-9);
+        let ellipse = bplane_covar.ellipse(3.0);
+        assert_eq!(ellipse.center_b_dot_t_km, bplane.b_dot_t());
+        assert!(ellipse.semi_minor_km < 1e-6);
+        assert!((ellipse.semi_major_km - 3e-3 * jac.fixed_view::<2, 1>(0, 3).norm()).abs() < 1e-9);
+
+        // About the central body of the orbit, the covariance is mapped as is
+        let almanac = Almanac::default();
+        let (about, about_covar) =
+            BPlane::about_with_covariance(orbit, &covar, eme2k, &almanac).unwrap();
+        assert_eq!(about.b_dot_r(), bplane.b_dot_r());
+        assert_eq!(about_covar, bplane_covar);
+        assert_eq!(
+            BPlane::about(orbit, eme2k, &almanac).unwrap().b_dot_t(),
+            bplane.b_dot_t()
+        );
+    }
+}
@@ -51,6 +51,61 @@ impl fmt::Display for EclipseLocator {
     }
 }
 
+/// Illumination state of an observer, as per the conical shadow model of the eclipse locator.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EclipseState {
+    /// The light source is entirely hidden
+    Umbra,
+    /// The light source is partially hidden, with this illumination factor strictly between 0.0 and 1.0
+    Penumbra(f64),
+    /// The light source is entirely visible
+    Visibilis,
+}
+
+impl EclipseState {
+    /// Returns the eclipse state of this illumination factor, between 0.0 (umbra) and 1.0 (fully lit)
+    pub fn from_illumination(illumination: f64) -> Self {
+        if illumination <= 0.0 {
+            Self::Umbra
+        } else if illumination >= 1.0 {
+            Self::Visibilis
+        } else {
+            Self::Penumbra(illumination)
+        }
+    }
+
+    /// Returns the illumination factor, between 0.0 (umbra) and 1.0 (fully lit)
+    pub fn illumination(&self) -> f64 {
+        match self {
+            Self::Umbra => 0.0,
+            Self::Penumbra(illumination) => *illumination,
+            Self::Visibilis => 1.0,
+        }
+    }
+}
+
+impl fmt::Display for EclipseState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Umbra => write!(f, "umbra"),
+            Self::Penumbra(illumination) => {
+                write!(f, "penumbra ({:.3}% illuminated)", illumination * 100.0)
+            }
+            Self::Visibilis => write!(f, "full sun"),
+        }
+    }
+}
+
+impl Default for EclipseLocator {
+    /// The Sun occulted by the Earth and the Moon, whose frame data is fetched from the almanac when computing the occultation.
+    fn default() -> Self {
+        Self {
+            light_source: SUN_J2000,
+            shadow_bodies: vec![EARTH_J2000, MOON_J2000],
+        }
+    }
+}
+
 impl EclipseLocator {
     /// Creates a new typical eclipse locator.
     /// The light source is the Sun, and the shadow bodies are the Earth and the Moon.
@@ -63,16 +118,43 @@ impl EclipseLocator {
         }
     }
 
-    /// Compute the visibility/eclipse between an observer and an observed state
+    /// Compute the occultation of the light source by each of the shadow bodies, in the order they are listed.
+    pub fn compute_all(
+        &self,
+        observer: Orbit,
+        almanac: Arc<Almanac>,
+    ) -> AlmanacResult<Vec<Occultation>> {
+        self.shadow_bodies
+            .iter()
+            .map(|eclipsing_body| {
+                almanac.occultation(self.light_source, *eclipsing_body, observer, None)
+            })
+            .collect()
+    }
+
+    /// Compute the visibility/eclipse between an observer and an observed state.
+    ///
+    /// All of the shadow bodies are considered simultaneously and the worst obstruction is returned,
+    /// e.g. a cislunar spacecraft will be in the shadow of whichever of the Earth or the Moon hides most of the Sun.
     pub fn compute(&self, observer: Orbit, almanac: Arc<Almanac>) -> AlmanacResult<Occultation> {
+        self.occultation(observer, &almanac)
+    }
+
+    /// Compute the worst obstruction of the light source by any of the shadow bodies, cf. [EclipseLocator::compute].
+    pub(crate) fn occultation(
+        &self,
+        observer: Orbit,
+        almanac: &Almanac,
+    ) -> AlmanacResult<Occultation> {
         let mut state = Occultation {
             epoch: observer.epoch,
-            back_frame: SUN_J2000,
+            back_frame: self.light_source,
             front_frame: observer.frame,
             percentage: 0.0,
         };
         for eclipsing_body in &self.shadow_bodies {
-            let this_state = almanac.solar_eclipsing(*eclipsing_body, observer, None)?;
+            let this_state =
+                almanac.occultation(self.light_source, *eclipsing_body, observer, None)?;
             if this_state.percentage > state.percentage {
                 state = this_state;
             }
@@ -80,6 +162,23 @@ impl EclipseLocator {
         Ok(state)
     }
 
+    /// Returns the illumination factor of the observer, between 0.0 (umbra) and 1.0 (fully lit), accounting for all shadow bodies.
+    pub fn illumination(&self, observer: Orbit, almanac: Arc<Almanac>) -> AlmanacResult<f64> {
+        // ANISE returns the occultation percentage (or factor), which is the opposite as the illumination factor.
+        Ok((self.compute(observer, almanac)?.factor() - 1.0).abs())
+    }
+
+    /// Returns whether the observer is in umbra, penumbra, or fully lit, accounting for all shadow bodies.
+    pub fn eclipse_state(
+        &self,
+        observer: Orbit,
+        almanac: Arc<Almanac>,
+    ) -> AlmanacResult<EclipseState> {
+        Ok(EclipseState::from_illumination(
+            self.illumination(observer, almanac)?,
+        ))
+    }
+
     /// Creates an umbra event from this eclipse locator.
     /// Evaluation of the event, returns 0.0 for umbra, 1.0 for visibility (no shadow) and some value in between for penumbra
     pub fn to_umbra_event(&self) -> UmbraEvent {
@@ -118,15 +217,6 @@ impl EventEvaluator<Spacecraft> for UmbraEvent {
             .factor();
 
         Ok((occult - 1.0).abs())
-        // match self
-        //     .e_loc
-        //     .compute(sc.orbit, almanac)
-        //     .context(EventAlmanacSnafu)?
-        // {
-        //     EclipseState::Umbra => Ok(0.0),
-        //     EclipseState::Visibilis => Ok(1.0),
-        //     EclipseState::Penumbra(val) => Ok(val),
-        // }
     }
 
     /// Stop searching when the time has converged to less than 0.1 seconds
@@ -187,3 +277,33 @@ impl EventEvaluator<Spacecraft> for PenumbraEvent {
         ))
     }
 }
+
+#[cfg(test)]
+mod ut_eclipse {
+    use super::*;
+    use crate::fixtures;
+    use crate::md::StateParameter;
+    use crate::State;
+
+    #[test]
+    fn eclipse_states() {
+        assert_eq!(EclipseState::from_illumination(0.0), EclipseState::Umbra);
+        assert_eq!(
+            EclipseState::from_illumination(1.0),
+            EclipseState::Visibilis
+        );
+        let penumbra = EclipseState::from_illumination(0.25);
+        assert_eq!(penumbra, EclipseState::Penumbra(0.25));
+        assert_eq!(penumbra.illumination(), 0.25);
+        assert_eq!(format!("{penumbra}"), "penumbra (25.000% illuminated)");
+        assert_eq!(EclipseState::Umbra.illumination(), 0.0);
+
+        // The illumination requires the ephemerides of the light source and the shadow bodies
+        let param = StateParameter::Illumination;
+        assert!(param.requires_almanac());
+        let orbit = fixtures::keplerian(7_000.0, 0.01, 28.5, 0.0, 0.0, 0.0);
+        assert!(Spacecraft::from(orbit)
+            .value_with_almanac(param, &Almanac::default())
+            .is_err());
+    }
+}
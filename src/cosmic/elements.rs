@@ -0,0 +1,422 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{Frame, Orbit};
+use crate::linalg::{Vector3, Vector6};
+use crate::time::Epoch;
+use anise::astro::PhysicsResult;
+use anise::errors::PhysicsError;
+
+/// Equinoctial elements with the mean longitude as the fast variable (prograde formulation).
+///
+/// These are non-singular for circular and equatorial orbits, which makes them the usual choice
+/// for averaging methods. Retrograde equatorial orbits (i = 180 deg) remain singular.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EquinoctialElements {
+    /// Semi-major axis, in kilometers
+    pub sma_km: f64,
+    /// e sin(ω + Ω)
+    pub h: f64,
+    /// e cos(ω + Ω)
+    pub k: f64,
+    /// tan(i/2) sin(Ω)
+    pub p: f64,
+    /// tan(i/2) cos(Ω)
+    pub q: f64,
+    /// Mean longitude λ = M + ω + Ω, in radians. This is _not_ wrapped during propagation.
+    pub lambda_rad: f64,
+}
+
+impl EquinoctialElements {
+    /// Builds the equinoctial elements of the provided orbit, which must be elliptical.
+    pub fn from_orbit(orbit: &Orbit) -> PhysicsResult<Self> {
+        let mu_km3_s2 = orbit.frame.mu_km3_s2()?;
+        Ok(Self::from_cartesian(
+            &orbit.radius_km,
+            &orbit.velocity_km_s,
+            mu_km3_s2,
+        ))
+    }
+
+    /// Builds the equinoctial elements from a Cartesian position and velocity.
+    pub fn from_cartesian(r: &Vector3<f64>, v: &Vector3<f64>, mu_km3_s2: f64) -> Self {
+        let rmag = r.norm();
+        let hvec = r.cross(v);
+        let w = hvec / hvec.norm();
+        let p = w.x / (1.0 + w.z);
+        let q = -w.y / (1.0 + w.z);
+
+        let (f_hat, g_hat) = Self::basis(p, q);
+
+        let evec = v.cross(&hvec) / mu_km3_s2 - r / rmag;
+        let k = evec.dot(&f_hat);
+        let h = evec.dot(&g_hat);
+
+        let sma_km = 1.0 / (2.0 / rmag - v.norm_squared() / mu_km3_s2);
+
+        let x = r.dot(&f_hat);
+        let y = r.dot(&g_hat);
+        let beta = (1.0 - h.powi(2) - k.powi(2)).sqrt();
+        let b = 1.0 / (1.0 + beta);
+
+        let sin_f = h + ((1.0 - h.powi(2) * b) * y - h * k * b * x) / (sma_km * beta);
+        let cos_f = k + ((1.0 - k.powi(2) * b) * x - h * k * b * y) / (sma_km * beta);
+        let ecc_lon = sin_f.atan2(cos_f);
+
+        Self {
+            sma_km,
+            h,
+            k,
+            p,
+            q,
+            lambda_rad: ecc_lon + h * cos_f - k * sin_f,
+        }
+    }
+
+    /// Returns the Cartesian position and velocity of these elements.
+    pub fn to_cartesian(&self, mu_km3_s2: f64) -> (Vector3<f64>, Vector3<f64>) {
+        let (h, k, a) = (self.h, self.k, self.sma_km);
+        let (f_hat, g_hat) = Self::basis(self.p, self.q);
+
+        // Solve the generalized Kepler equation λ = F + h cos F - k sin F.
+        let mut ecc_lon = self.lambda_rad;
+        for _ in 0..50 {
+            let (sin_f, cos_f) = ecc_lon.sin_cos();
+            let g = ecc_lon + h * cos_f - k * sin_f - self.lambda_rad;
+            let dg = 1.0 - h * sin_f - k * cos_f;
+            let delta = g / dg;
+            ecc_lon -= delta;
+            if delta.abs() < 1e-15 {
+                break;
+            }
+        }
+
+        let (sin_f, cos_f) = ecc_lon.sin_cos();
+        let b = 1.0 / (1.0 + (1.0 - h.powi(2) - k.powi(2)).sqrt());
+        let n = (mu_km3_s2 / a.powi(3)).sqrt();
+        let rmag = a * (1.0 - k * cos_f - h * sin_f);
+
+        let x = a * ((1.0 - h.powi(2) * b) * cos_f + h * k * b * sin_f - k);
+        let y = a * ((1.0 - k.powi(2) * b) * sin_f + h * k * b * cos_f - h);
+        let x_dot = a.powi(2) * n / rmag * (h * k * b * cos_f - (1.0 - h.powi(2) * b) * sin_f);
+        let y_dot = a.powi(2) * n / rmag * ((1.0 - k.powi(2) * b) * cos_f - h * k * b * sin_f);
+
+        (x * f_hat + y * g_hat, x_dot * f_hat + y_dot * g_hat)
+    }
+
+    /// Returns the orbit corresponding to these elements at the provided epoch in the provided frame.
+    pub fn to_orbit(&self, epoch: Epoch, frame: Frame) -> PhysicsResult<Orbit> {
+        let (r, v) = self.to_cartesian(frame.mu_km3_s2()?);
+        Ok(Orbit::new(r.x, r.y, r.z, v.x, v.y, v.z, epoch, frame))
+    }
+
+    /// Returns the eccentricity
+    pub fn ecc(&self) -> f64 {
+        (self.h.powi(2) + self.k.powi(2)).sqrt()
+    }
+
+    /// Returns the inclination in degrees
+    pub fn inc_deg(&self) -> f64 {
+        (2.0 * (self.p.powi(2) + self.q.powi(2)).sqrt().atan()).to_degrees()
+    }
+
+    /// Returns the right ascension of the ascending node in degrees, in [0; 360)
+    pub fn raan_deg(&self) -> f64 {
+        self.p.atan2(self.q).to_degrees().rem_euclid(360.0)
+    }
+
+    /// Returns the elements as a vector ordered as [a, h, k, p, q, λ]
+    pub fn to_vector(&self) -> Vector6<f64> {
+        Vector6::new(self.sma_km, self.h, self.k, self.p, self.q, self.lambda_rad)
+    }
+
+    /// Builds the elements from a vector ordered as [a, h, k, p, q, λ]
+    pub fn from_vector(vector: &Vector6<f64>) -> Self {
+        Self {
+            sma_km: vector[0],
+            h: vector[1],
+            k: vector[2],
+            p: vector[3],
+            q: vector[4],
+            lambda_rad: vector[5],
+        }
+    }
+
+    /// Returns the f and g unit vectors of the equinoctial frame.
+    fn basis(p: f64, q: f64) -> (Vector3<f64>, Vector3<f64>) {
+        let s = 1.0 + p.powi(2) + q.powi(2);
+        (
+            Vector3::new(1.0 - p.powi(2) + q.powi(2), 2.0 * p * q, -2.0 * p) / s,
+            Vector3::new(2.0 * p * q, 1.0 + p.powi(2) - q.powi(2), 2.0 * q) / s,
+        )
+    }
+}
+
+/// Delaunay elements, the canonical action-angle variables of the two-body problem.
+///
+/// The momenta are conjugate to the angles: L to the mean anomaly, G to the argument of periapsis, and H to the right
+/// ascension of the ascending node. These are singular for circular and equatorial orbits, cf. [PoincareElements].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DelaunayElements {
+    /// Mean anomaly l, in radians
+    pub l_rad: f64,
+    /// Argument of periapsis g, in radians
+    pub g_rad: f64,
+    /// Right ascension of the ascending node h, in radians
+    pub h_rad: f64,
+    /// L = sqrt(μ a), in km^2/s
+    pub big_l: f64,
+    /// G = L sqrt(1 - e^2), the norm of the orbital momentum, in km^2/s
+    pub big_g: f64,
+    /// H = G cos(i), the Z component of the orbital momentum, in km^2/s
+    pub big_h: f64,
+}
+
+impl DelaunayElements {
+    /// Builds the Delaunay elements of the provided orbit, which must be elliptical.
+    pub fn from_orbit(orbit: &Orbit) -> PhysicsResult<Self> {
+        let ecc = orbit.ecc()?;
+        if ecc >= 1.0 {
+            return Err(PhysicsError::ParabolicEccentricity { limit: 1.0 });
+        }
+        let big_l = (orbit.frame.mu_km3_s2()? * orbit.sma_km()?).sqrt();
+        let big_g = big_l * (1.0 - ecc.powi(2)).sqrt();
+        Ok(Self {
+            l_rad: orbit.ma_deg()?.to_radians(),
+            g_rad: orbit.aop_deg()?.to_radians(),
+            h_rad: orbit.raan_deg()?.to_radians(),
+            big_l,
+            big_g,
+            big_h: big_g * orbit.inc_deg()?.to_radians().cos(),
+        })
+    }
+
+    /// Returns the orbit corresponding to these elements at the provided epoch in the provided frame.
+    pub fn to_orbit(&self, epoch: Epoch, frame: Frame) -> PhysicsResult<Orbit> {
+        let mu_km3_s2 = frame.mu_km3_s2()?;
+        Orbit::try_keplerian_mean_anomaly(
+            self.big_l.powi(2) / mu_km3_s2,
+            self.ecc(),
+            self.inc_deg(),
+            self.h_rad.to_degrees(),
+            self.g_rad.to_degrees(),
+            self.l_rad.to_degrees(),
+            epoch,
+            frame,
+        )
+    }
+
+    /// Returns the eccentricity
+    pub fn ecc(&self) -> f64 {
+        (1.0 - (self.big_g / self.big_l).powi(2)).max(0.0).sqrt()
+    }
+
+    /// Returns the inclination in degrees
+    pub fn inc_deg(&self) -> f64 {
+        (self.big_h / self.big_g)
+            .clamp(-1.0, 1.0)
+            .acos()
+            .to_degrees()
+    }
+
+    /// Returns the elements as a vector ordered as [l, g, h, L, G, H]
+    pub fn to_vector(&self) -> Vector6<f64> {
+        Vector6::new(
+            self.l_rad, self.g_rad, self.h_rad, self.big_l, self.big_g, self.big_h,
+        )
+    }
+}
+
+/// Poincaré elements, the canonical variables which are non-singular for circular and equatorial orbits (prograde).
+///
+/// These derive from the Delaunay elements with the mean longitude λ = l + g + h and the longitude of periapsis ϖ = g + h:
+/// - Λ = L, conjugate to λ;
+/// - ξ = sqrt(2 (L - G)) cos(ϖ) and η = -sqrt(2 (L - G)) sin(ϖ);
+/// - p = sqrt(2 (G - H)) cos(h) and q = -sqrt(2 (G - H)) sin(h).
+///
+/// They are computed from the [EquinoctialElements], such that near circular and equatorial orbits are well defined.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PoincareElements {
+    /// Mean longitude λ, in radians
+    pub lambda_rad: f64,
+    /// Λ = sqrt(μ a), in km^2/s
+    pub big_lambda: f64,
+    /// ξ, in km/s^(1/2)
+    pub xi: f64,
+    /// η, in km/s^(1/2)
+    pub eta: f64,
+    /// p, in km/s^(1/2)
+    pub p: f64,
+    /// q, in km/s^(1/2)
+    pub q: f64,
+}
+
+impl PoincareElements {
+    /// Builds the Poincaré elements of the provided orbit, which must be elliptical.
+    pub fn from_orbit(orbit: &Orbit) -> PhysicsResult<Self> {
+        let mu_km3_s2 = orbit.frame.mu_km3_s2()?;
+        Ok(Self::from_equinoctial(
+            &EquinoctialElements::from_orbit(orbit)?,
+            mu_km3_s2,
+        ))
+    }
+
+    /// Builds the Poincaré elements from the equinoctial elements.
+    pub fn from_equinoctial(eq: &EquinoctialElements, mu_km3_s2: f64) -> Self {
+        let big_lambda = (mu_km3_s2 * eq.sma_km).sqrt();
+        let beta = (1.0 - eq.ecc().powi(2)).sqrt();
+        let big_g = big_lambda * beta;
+        // sqrt(2 (L - G)) / e, and sqrt(2 (G - H)) / tan(i/2) with cos(i/2) = 1 / sqrt(1 + tan^2(i/2))
+        let ecc_scale = (2.0 * big_lambda / (1.0 + beta)).sqrt();
+        let inc_scale = 2.0 * big_g.sqrt() / (1.0 + eq.p.powi(2) + eq.q.powi(2)).sqrt();
+        Self {
+            lambda_rad: eq.lambda_rad,
+            big_lambda,
+            xi: ecc_scale * eq.k,
+            eta: -ecc_scale * eq.h,
+            p: inc_scale * eq.q,
+            q: -inc_scale * eq.p,
+        }
+    }
+
+    /// Returns the equinoctial elements of these Poincaré elements.
+    pub fn to_equinoctial(&self, mu_km3_s2: f64) -> EquinoctialElements {
+        let big_g = self.big_lambda - 0.5 * (self.xi.powi(2) + self.eta.powi(2));
+        let big_h = big_g - 0.5 * (self.p.powi(2) + self.q.powi(2));
+        let beta = big_g / self.big_lambda;
+        let ecc_scale = (2.0 * self.big_lambda / (1.0 + beta)).sqrt();
+        let cos_half_inc = (0.5 * (1.0 + big_h / big_g)).max(0.0).sqrt();
+        let inc_scale = 2.0 * big_g.sqrt() * cos_half_inc;
+        EquinoctialElements {
+            sma_km: self.big_lambda.powi(2) / mu_km3_s2,
+            h: -self.eta / ecc_scale,
+            k: self.xi / ecc_scale,
+            p: -self.q / inc_scale,
+            q: self.p / inc_scale,
+            lambda_rad: self.lambda_rad,
+        }
+    }
+
+    /// Returns the orbit corresponding to these elements at the provided epoch in the provided frame.
+    pub fn to_orbit(&self, epoch: Epoch, frame: Frame) -> PhysicsResult<Orbit> {
+        self.to_equinoctial(frame.mu_km3_s2()?)
+            .to_orbit(epoch, frame)
+    }
+
+    /// Returns the elements as a vector ordered as [λ, Λ, ξ, η, p, q]
+    pub fn to_vector(&self) -> Vector6<f64> {
+        Vector6::new(
+            self.lambda_rad,
+            self.big_lambda,
+            self.xi,
+            self.eta,
+            self.p,
+            self.q,
+        )
+    }
+}
+
+#[cfg(test)]
+mod ut_elements {
+    use super::*;
+    use crate::fixtures;
+
+    fn earth() -> Frame {
+        fixtures::eme2k()
+    }
+
+    #[test]
+    fn equinoctial_round_trip() {
+        let epoch = fixtures::epoch();
+        let orbit =
+            Orbit::try_keplerian(7_000.0, 0.01, 51.6, 30.0, 45.0, 10.0, epoch, earth()).unwrap();
+
+        let elements = EquinoctialElements::from_orbit(&orbit).unwrap();
+        assert!((elements.sma_km - 7_000.0).abs() < 1e-8);
+        assert!((elements.ecc() - 0.01).abs() < 1e-12);
+        assert!((elements.inc_deg() - 51.6).abs() < 1e-10);
+        assert!((elements.raan_deg() - 30.0).abs() < 1e-10);
+
+        let rebuilt = elements.to_orbit(epoch, orbit.frame).unwrap();
+        assert!(orbit.rss_radius_km(&rebuilt).unwrap() < 1e-8);
+        assert!(orbit.rss_velocity_km_s(&rebuilt).unwrap() < 1e-11);
+
+        // Circular and equatorial orbits are not singular.
+        let orbit =
+            Orbit::try_keplerian(7_000.0, 0.0, 0.0, 0.0, 0.0, 10.0, epoch, earth()).unwrap();
+        let rebuilt = EquinoctialElements::from_orbit(&orbit)
+            .unwrap()
+            .to_orbit(epoch, orbit.frame)
+            .unwrap();
+        assert!(orbit.rss_radius_km(&rebuilt).unwrap() < 1e-8);
+    }
+
+    #[test]
+    fn canonical_round_trips() {
+        let epoch = fixtures::epoch();
+        let orbit =
+            Orbit::try_keplerian(7_000.0, 0.05, 51.6, 30.0, 45.0, 10.0, epoch, earth()).unwrap();
+
+        let delaunay = DelaunayElements::from_orbit(&orbit).unwrap();
+        assert!((delaunay.ecc() - 0.05).abs() < 1e-12);
+        assert!((delaunay.inc_deg() - 51.6).abs() < 1e-10);
+        assert!((delaunay.big_g - orbit.hmag().unwrap()).abs() < 1e-9);
+        assert!((delaunay.big_h - orbit.hz().unwrap()).abs() < 1e-9);
+        let rebuilt = delaunay.to_orbit(epoch, orbit.frame).unwrap();
+        assert!(orbit.rss_radius_km(&rebuilt).unwrap() < 1e-6);
+        assert!(orbit.rss_velocity_km_s(&rebuilt).unwrap() < 1e-9);
+
+        let poincare = PoincareElements::from_orbit(&orbit).unwrap();
+        assert!((poincare.big_lambda - delaunay.big_l).abs() < 1e-9);
+        // Definitions from the Delaunay elements
+        let lon_peri = delaunay.g_rad + delaunay.h_rad;
+        let ecc_amp = (2.0 * (delaunay.big_l - delaunay.big_g)).sqrt();
+        let inc_amp = (2.0 * (delaunay.big_g - delaunay.big_h)).sqrt();
+        assert!((poincare.xi - ecc_amp * lon_peri.cos()).abs() < 1e-9);
+        assert!((poincare.eta + ecc_amp * lon_peri.sin()).abs() < 1e-9);
+        assert!((poincare.p - inc_amp * delaunay.h_rad.cos()).abs() < 1e-9);
+        assert!((poincare.q + inc_amp * delaunay.h_rad.sin()).abs() < 1e-9);
+        let rebuilt = poincare.to_orbit(epoch, orbit.frame).unwrap();
+        assert!(orbit.rss_radius_km(&rebuilt).unwrap() < 1e-6);
+        assert!(orbit.rss_velocity_km_s(&rebuilt).unwrap() < 1e-9);
+
+        // The Poincaré elements are not singular for circular and equatorial orbits
+        let circular =
+            Orbit::try_keplerian(7_000.0, 0.0, 0.0, 0.0, 0.0, 10.0, epoch, earth()).unwrap();
+        let poincare = PoincareElements::from_orbit(&circular).unwrap();
+        assert!(poincare.to_vector().fixed_rows::<4>(2).norm() < 1e-12);
+        let rebuilt = poincare.to_orbit(epoch, circular.frame).unwrap();
+        assert!(circular.rss_radius_km(&rebuilt).unwrap() < 1e-8);
+
+        // The elements are available as state parameters
+        use crate::cosmic::Spacecraft;
+        use crate::md::StateParameter;
+        use crate::State;
+        let sc = Spacecraft::from(orbit);
+        let mean_lon = (30.0 + 45.0 + orbit.ma_deg().unwrap()).rem_euclid(360.0);
+        assert!((sc.value(StateParameter::MeanLongitude).unwrap() - mean_lon).abs() < 1e-9);
+        assert_eq!(sc.value(StateParameter::DelaunayG).unwrap(), delaunay.big_g);
+        let eq = sc.equinoctial().unwrap();
+        assert_eq!(sc.value(StateParameter::EquinoctialK).unwrap(), eq.k);
+        assert_eq!(sc.value(StateParameter::EquinoctialQ).unwrap(), eq.q);
+
+        let hyperbolic =
+            Orbit::try_keplerian(-7_000.0, 1.5, 10.0, 0.0, 0.0, 10.0, epoch, earth()).unwrap();
+        assert!(DelaunayElements::from_orbit(&hyperbolic).is_err());
+    }
+}
@@ -0,0 +1,243 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use anise::constants::frames::SUN_J2000;
+use snafu::ResultExt;
+
+use super::{Frame, Spacecraft};
+use crate::errors::{EventAlmanacSnafu, EventError, EventPhysicsSnafu};
+use crate::linalg::Vector3;
+use crate::md::EventEvaluator;
+use crate::time::{Duration, Unit};
+use crate::State;
+use anise::almanac::Almanac;
+use std::fmt;
+use std::sync::Arc;
+
+/// Returns the elevation in degrees of the light source above the local horizontal plane of this geodetic point.
+fn local_elevation_deg(latitude_deg: f64, longitude_deg: f64, light_km: &Vector3<f64>) -> f64 {
+    let (lat, lon) = (latitude_deg.to_radians(), longitude_deg.to_radians());
+    let normal = Vector3::new(lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin());
+    (normal.dot(light_km) / light_km.norm())
+        .clamp(-1.0, 1.0)
+        .asin()
+        .to_degrees()
+}
+
+/// Returns the angle in degrees between the orbital plane and the direction of the light source, positive on the side
+/// of the orbit angular momentum.
+fn beta_angle_deg(hvec: &Vector3<f64>, light_km: &Vector3<f64>) -> f64 {
+    (hvec.dot(light_km) / (hvec.norm() * light_km.norm()))
+        .clamp(-1.0, 1.0)
+        .asin()
+        .to_degrees()
+}
+
+/// An event on the elevation of the Sun above the local horizon of the sub-spacecraft point, positive while the Sun is
+/// above the threshold.
+///
+/// The sub-spacecraft point is the geodetic nadir on the ellipsoid of the body fixed frame. The direction of the Sun is
+/// taken from the center of the body, which is within a few millidegrees of the direction from the surface for the Earth.
+/// With a zero threshold, the arcs of this event (cf. `Traj::find_arcs`) are the passes over the day side and its edges are
+/// the crossings of the terminator.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SunElevationEvent {
+    pub light_source: Frame,
+    /// Body fixed frame with a shape, e.g. as returned by `almanac.frame_from_uid(IAU_EARTH_FRAME)`
+    pub body_fixed: Frame,
+    /// in degrees
+    pub threshold_deg: f64,
+}
+
+impl SunElevationEvent {
+    /// Initializes an event on the elevation of the Sun at the sub-spacecraft point.
+    pub fn new(body_fixed: Frame, threshold_deg: f64) -> Self {
+        Self {
+            light_source: SUN_J2000,
+            body_fixed,
+            threshold_deg,
+        }
+    }
+
+    /// Event which is positive over the day side, and whose edges are the crossings of the terminator.
+    pub fn terminator(body_fixed: Frame) -> Self {
+        Self::new(body_fixed, 0.0)
+    }
+
+    /// Returns the elevation of the Sun at the sub-spacecraft point, in degrees.
+    pub fn sun_elevation_deg(
+        &self,
+        sc: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<f64, EventError> {
+        let orbit = almanac
+            .transform_to(sc.orbit, self.body_fixed, None)
+            .context(EventAlmanacSnafu)?;
+        let light_km = almanac
+            .transform(self.light_source, self.body_fixed, orbit.epoch, None)
+            .context(EventAlmanacSnafu)?
+            .radius_km;
+
+        Ok(local_elevation_deg(
+            orbit.latitude_deg().context(EventPhysicsSnafu)?,
+            orbit.longitude_deg(),
+            &light_km,
+        ))
+    }
+}
+
+impl fmt::Display for SunElevationEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.threshold_deg == 0.0 {
+            write!(f, "day side of {}", self.body_fixed)
+        } else {
+            write!(
+                f,
+                "{} elevation above {} deg on {}",
+                self.light_source, self.threshold_deg, self.body_fixed
+            )
+        }
+    }
+}
+
+impl EventEvaluator<Spacecraft> for SunElevationEvent {
+    fn eval(&self, sc: &Spacecraft, almanac: Arc<Almanac>) -> Result<f64, EventError> {
+        Ok(self.sun_elevation_deg(sc, almanac)? - self.threshold_deg)
+    }
+
+    fn eval_string(&self, sc: &Spacecraft, almanac: Arc<Almanac>) -> Result<String, EventError> {
+        Ok(format!(
+            "Elevation of {} at the sub-spacecraft point is {:.6} deg on {}",
+            self.light_source,
+            self.sun_elevation_deg(sc, almanac)?,
+            sc.epoch()
+        ))
+    }
+
+    fn epoch_precision(&self) -> Duration {
+        1 * Unit::Second
+    }
+
+    /// Angle precision of the elevation evaluator is 1 millidegree.
+    fn value_precision(&self) -> f64 {
+        1e-3
+    }
+}
+
+/// An event on the beta angle of the orbit, i.e. the angle between the orbital plane and the direction of the Sun,
+/// positive while the beta angle is above the threshold.
+///
+/// The beta angle is positive when the Sun is on the side of the orbit angular momentum. Its magnitude drives the
+/// duration of the eclipses, which vanish above a critical value (about 70 degrees in LEO).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BetaAngleEvent {
+    pub light_source: Frame,
+    /// in degrees
+    pub threshold_deg: f64,
+}
+
+impl BetaAngleEvent {
+    /// Initializes an event on the beta angle of the orbit with respect to the Sun.
+    pub fn new(threshold_deg: f64) -> Self {
+        Self {
+            light_source: SUN_J2000,
+            threshold_deg,
+        }
+    }
+
+    /// Returns the beta angle of the orbit of the spacecraft about the origin of its frame, in degrees.
+    pub fn beta_angle_deg(
+        &self,
+        sc: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<f64, EventError> {
+        let light_km = almanac
+            .transform(self.light_source, sc.orbit.frame, sc.epoch(), None)
+            .context(EventAlmanacSnafu)?
+            .radius_km;
+
+        Ok(beta_angle_deg(
+            &sc.orbit.hvec().context(EventPhysicsSnafu)?,
+            &light_km,
+        ))
+    }
+}
+
+impl fmt::Display for BetaAngleEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "beta angle above {} deg w.r.t. {}",
+            self.threshold_deg, self.light_source
+        )
+    }
+}
+
+impl EventEvaluator<Spacecraft> for BetaAngleEvent {
+    fn eval(&self, sc: &Spacecraft, almanac: Arc<Almanac>) -> Result<f64, EventError> {
+        Ok(self.beta_angle_deg(sc, almanac)? - self.threshold_deg)
+    }
+
+    fn eval_string(&self, sc: &Spacecraft, almanac: Arc<Almanac>) -> Result<String, EventError> {
+        Ok(format!(
+            "Beta angle is {:.6} deg on {}",
+            self.beta_angle_deg(sc, almanac)?,
+            sc.epoch()
+        ))
+    }
+
+    /// The beta angle drifts slowly, so a coarse time precision suffices.
+    fn epoch_precision(&self) -> Duration {
+        1 * Unit::Minute
+    }
+
+    fn value_precision(&self) -> f64 {
+        1e-3
+    }
+}
+
+#[cfg(test)]
+mod ut_lighting {
+    use super::*;
+
+    #[test]
+    fn sun_elevation() {
+        let sun_km = Vector3::new(1.5e8, 0.0, 0.0);
+        // Sub-solar point, antipode, and terminator
+        assert!((local_elevation_deg(0.0, 0.0, &sun_km) - 90.0).abs() < 1e-12);
+        assert!((local_elevation_deg(0.0, 180.0, &sun_km) + 90.0).abs() < 1e-12);
+        assert!(local_elevation_deg(0.0, 90.0, &sun_km).abs() < 1e-12);
+        assert!(local_elevation_deg(90.0, 0.0, &sun_km).abs() < 1e-12);
+        // On the meridian of the Sun, its elevation decreases with the latitude.
+        assert!((local_elevation_deg(30.0, 0.0, &sun_km) - 60.0).abs() < 1e-12);
+        assert!((local_elevation_deg(-30.0, 0.0, &sun_km) - 60.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn beta_angle() {
+        let sun_km = Vector3::new(1.5e8, 0.0, 0.0);
+        // Equatorial orbit with the Sun in the equatorial plane
+        assert!(beta_angle_deg(&Vector3::z(), &sun_km).abs() < 1e-12);
+        // Dawn-dusk orbit, with the angular momentum toward or away from the Sun
+        assert!((beta_angle_deg(&Vector3::x(), &sun_km) - 90.0).abs() < 1e-12);
+        assert!((beta_angle_deg(&-Vector3::x(), &sun_km) + 90.0).abs() < 1e-12);
+        // Angular momentum tilted by 30 degrees from the Sun direction
+        let hvec = Vector3::new(30_f64.to_radians().cos(), 0.0, 30_f64.to_radians().sin());
+        assert!((beta_angle_deg(&(hvec * 5e4), &sun_km) - 60.0).abs() < 1e-12);
+    }
+}
@@ -73,6 +73,12 @@ where
     /// Unsets the STM for this state
     fn unset_stm(&mut self);
 
+    /// Number of leading components of the propagation vector which are integrated, e.g. all of them but an unset STM.
+    /// The other components are left untouched by the integrator.
+    fn integrated_len(&self) -> usize {
+        Self::VecLength::dim()
+    }
+
     /// Set this state
     fn set(&mut self, epoch: Epoch, vector: &OVector<f64, Self::VecLength>);
 
@@ -105,6 +111,16 @@ where
         Err(StateError::Unavailable { param })
     }
 
+    /// Return the value of the parameter, including those which require the Almanac, cf. [StateParameter::requires_almanac].
+    /// By default, this only returns the value of the parameter.
+    fn value_with_almanac(
+        &self,
+        param: StateParameter,
+        _almanac: &Almanac,
+    ) -> Result<f64, StateError> {
+        self.value(param)
+    }
+
     /// Allows setting the value of the given parameter.
     /// NOTE: Most parameters where the `value` is available CANNOT be also set for that parameter (it's a much harder problem!)
     fn set_value(&mut self, param: StateParameter, _val: f64) -> Result<(), StateError> {
@@ -153,6 +169,10 @@ pub enum AstroError {
 mod orbitdual;
 pub use self::orbitdual::*;
 
+// Re-Export the equinoctial and canonical orbital elements
+mod elements;
+pub use self::elements::*;
+
 // Re-Export B Plane
 mod bplane;
 pub use self::bplane::*;
@@ -161,9 +181,24 @@ pub use self::bplane::*;
 mod spacecraft;
 pub use self::spacecraft::*;
 
+// Re-Export the attitude
+mod attitude;
+pub use self::attitude::*;
+
+// Re-Export the propellant tanks
+mod tanks;
+pub use self::tanks::*;
+
+// Re-Export the cached frame transformations
+mod transform;
+pub use self::transform::*;
+
 /// The eclipse module allows finding eclipses and (conversely) visibility between a state and another one (e.g. a planet or the Sun).
 pub mod eclipse;
 
+/// The lighting module provides events on the local day and night of the sub-spacecraft point and on the beta angle.
+pub mod lighting;
+
 /// Speed of light in meters per second
 pub const SPEED_OF_LIGHT_M_S: f64 = SPEED_OF_LIGHT_KM_S * 1e3;
 pub use anise::constants::SPEED_OF_LIGHT_KM_S;
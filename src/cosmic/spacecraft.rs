@@ -16,8 +16,9 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use anise::almanac::Almanac;
 use anise::astro::PhysicsResult;
-use anise::constants::frames::EARTH_J2000;
+use anise::constants::frames::{EARTH_J2000, MOON_J2000, SUN_J2000};
 pub use anise::prelude::Orbit;
 
 pub use anise::structure::spacecraft::{DragData, Mass, SRPData};
@@ -26,10 +27,14 @@ use serde::{Deserialize, Serialize};
 use snafu::ResultExt;
 use typed_builder::TypedBuilder;
 
-use super::{AstroPhysicsSnafu, BPlane, State};
+use super::eclipse::EclipseLocator;
+use super::{
+    AstroPhysicsSnafu, Attitude, AttitudeProfile, AxisAreas, BPlane, DelaunayElements,
+    EquinoctialElements, PoincareElements, PropTanks, State,
+};
 use crate::dynamics::guidance::Thruster;
-use crate::dynamics::DynamicsError;
-use crate::errors::{StateAstroSnafu, StateError};
+use crate::dynamics::{BatteryState, DynamicsError, ExtraStates, ExtraStm, MAX_EXTRA_STATES};
+use crate::errors::{StateAlmanacSnafu, StateAstroSnafu, StateError};
 use crate::io::ConfigRepr;
 use crate::linalg::{Const, DimName, OMatrix, OVector};
 use crate::md::StateParameter;
@@ -40,8 +45,9 @@ use std::default::Default;
 use std::fmt;
 use std::ops::Add;
 
+/// Guidance mode of a spacecraft, stored in the state vector as a real number: Coast is 0, Thrust is 1, Inhibit is -1, and
+/// the custom modes start at 2.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
-
 pub enum GuidanceMode {
     /// Guidance is turned off and Guidance Law may switch mode to Thrust for next call
     Coast,
@@ -49,6 +55,22 @@ pub enum GuidanceMode {
     Thrust,
     /// Guidance is turned off and Guidance Law may not change its mode (will need to be done externally to the guidance law).
     Inhibit,
+    /// Mode of a user guidance law, whose meaning is only known to that law. The built-in guidance laws do not thrust in
+    /// custom modes.
+    Custom(u8),
+}
+
+impl GuidanceMode {
+    /// Value of the first custom mode in the state vector
+    const CUSTOM_OFFSET: f64 = 2.0;
+
+    /// Returns the identifier of this custom mode, if it is one
+    pub fn custom_id(&self) -> Option<u8> {
+        match self {
+            Self::Custom(id) => Some(*id),
+            _ => None,
+        }
+    }
 }
 
 impl Default for GuidanceMode {
@@ -59,7 +81,9 @@ impl Default for GuidanceMode {
 
 impl From<f64> for GuidanceMode {
     fn from(value: f64) -> Self {
-        if value >= 1.0 {
+        if value >= Self::CUSTOM_OFFSET {
+            Self::Custom((value - Self::CUSTOM_OFFSET).round().min(u8::MAX as f64) as u8)
+        } else if value >= 1.0 {
             Self::Thrust
         } else if value < 0.0 {
             Self::Inhibit
@@ -75,6 +99,7 @@ impl From<GuidanceMode> for f64 {
             GuidanceMode::Coast => 0.0,
             GuidanceMode::Thrust => 1.0,
             GuidanceMode::Inhibit => -1.0,
+            GuidanceMode::Custom(id) => GuidanceMode::CUSTOM_OFFSET + f64::from(id),
         }
     }
 }
@@ -96,8 +121,28 @@ pub struct Spacecraft {
     #[builder(default)]
     #[serde(default)]
     pub drag: DragData,
+    /// Projected areas along the body axes, which replace the SRP and drag areas if set, cf. [Spacecraft::srp_area_m2]
+    #[builder(default, setter(strip_option))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub areas: Option<AxisAreas>,
     #[builder(default, setter(strip_option))]
     pub thruster: Option<Thruster>,
+    /// Propellant tanks, whose total must match the propellant mass. If empty, the propellant is a single pool.
+    #[builder(default)]
+    #[serde(default, skip_serializing_if = "PropTanks::is_empty")]
+    pub tanks: PropTanks,
+    /// Battery charge, updated during the propagation if the dynamics include a power model
+    #[builder(default, setter(strip_option))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub battery: Option<BatteryState>,
+    /// Values of the user defined extra states, named and propagated by the spacecraft dynamics
+    #[builder(default)]
+    #[serde(default, skip_serializing_if = "ExtraStates::is_empty")]
+    pub extra: ExtraStates,
+    /// Attitude of the spacecraft, updated from its commanded profile after each propagation step
+    #[builder(default, setter(strip_option))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attitude: Option<Attitude>,
     /// Any extra information or extension that is needed for specific guidance laws
     #[builder(default)]
     #[serde(default)]
@@ -107,6 +152,10 @@ pub struct Spacecraft {
     #[builder(default, setter(strip_option))]
     #[serde(skip)]
     pub stm: Option<OMatrix<f64, Const<9>, Const<9>>>,
+    /// Set to only propagate the orbital block (position and velocity) of the STM, cf. [Spacecraft::with_orbital_stm]
+    #[builder(default)]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub orbital_stm: bool,
 }
 
 impl Default for Spacecraft {
@@ -116,9 +165,15 @@ impl Default for Spacecraft {
             mass: Mass::default(),
             srp: SRPData::default(),
             drag: DragData::default(),
+            areas: None,
             thruster: None,
+            tanks: PropTanks::default(),
+            battery: None,
+            extra: ExtraStates::default(),
+            attitude: None,
             mode: GuidanceMode::default(),
             stm: None,
+            orbital_stm: false,
         }
     }
 }
@@ -130,6 +185,13 @@ impl From<Orbit> for Spacecraft {
 }
 
 impl Spacecraft {
+    /// Index of the STM in the propagation vector, after the position, velocity, Cr, Cd, and prop mass
+    pub(crate) const STM_INDEX: usize = 9;
+    /// Index of the extra states in the propagation vector, after the STM
+    pub(crate) const EXTRA_INDEX: usize = Self::STM_INDEX + 81;
+    /// Index of the STM blocks of the extra states in the propagation vector, after the extra states, cf. [ExtraStm]
+    pub(crate) const EXTRA_STM_INDEX: usize = Self::EXTRA_INDEX + MAX_EXTRA_STATES;
+
     /// Initialize a spacecraft state from all of its parameters
     pub fn new(
         orbit: Orbit,
@@ -203,12 +265,84 @@ impl Spacecraft {
         self
     }
 
-    /// Returns a copy of the state with a new prop mass
+    /// Returns a copy of the state with a new prop mass, spread across the tanks proportionally to their mass
     pub fn with_prop_mass(mut self, prop_mass_kg: f64) -> Self {
         self.mass.prop_mass_kg = prop_mass_kg;
+        self.tanks.set_total_kg(prop_mass_kg);
+        self
+    }
+
+    /// Returns a copy of the state with these propellant tanks, setting the prop mass to their total
+    pub fn with_tanks(mut self, tanks: PropTanks) -> Self {
+        self.mass.prop_mass_kg = tanks.total_kg();
+        self.tanks = tanks;
+        self
+    }
+
+    /// Returns a copy of the state commanded to this attitude profile, cf. [Attitude].
+    pub fn with_attitude(mut self, profile: AttitudeProfile) -> Self {
+        self.attitude = Some(Attitude::new(profile));
+        self
+    }
+
+    /// Returns a copy of the state with these projected areas along its body axes, cf. [Spacecraft::srp_area_m2]
+    pub fn with_areas(mut self, areas: AxisAreas) -> Self {
+        self.areas = Some(areas);
+        self
+    }
+
+    /// Returns the SRP area of the spacecraft seen from the Sun, in m^2, where the direction of the Sun (or of the spacecraft
+    /// from the Sun) is expressed in the inertial frame of the orbit.
+    ///
+    /// If the axis areas are set, this is their projection along that direction using the attitude of the spacecraft (or an
+    /// attitude aligned with the inertial frame if unset). Otherwise, this is the area of the SRP configuration.
+    pub fn srp_area_m2(&self, sun_dir: &Vector3<f64>) -> f64 {
+        self.projected_area_m2(sun_dir).unwrap_or(self.srp.area_m2)
+    }
+
+    /// Returns the drag area of the spacecraft along its velocity relative to the atmosphere, in m^2, as per
+    /// [Spacecraft::srp_area_m2]. Otherwise, this is the area of the drag configuration.
+    pub fn drag_area_m2(&self, velocity: &Vector3<f64>) -> f64 {
+        self.projected_area_m2(velocity)
+            .unwrap_or(self.drag.area_m2)
+    }
+
+    fn projected_area_m2(&self, dir_inertial: &Vector3<f64>) -> Option<f64> {
+        let areas = self.areas?;
+        if dir_inertial.norm() < f64::EPSILON {
+            return Some(0.0);
+        }
+        let dir_body = match self.attitude {
+            Some(attitude) => attitude.q_body_to_inertial.inverse() * dir_inertial,
+            None => *dir_inertial,
+        };
+        Some(areas.projected_m2(&dir_body))
+    }
+
+    /// Returns a copy of the state with this value of the extra state at this index, cf. [crate::dynamics::ExtraState].
+    /// The spacecraft then carries at least this many extra states, even if their value is zero.
+    ///
+    /// # Panics
+    /// If the index is greater than or equal to [crate::dynamics::MAX_EXTRA_STATES].
+    pub fn with_extra(mut self, index: usize, value: f64) -> Self {
+        self.extra.values[index] = value;
+        self.extra.count = self.extra.count.max(index + 1);
+        if self.stm.is_some() && !self.orbital_stm && self.extra.stm.is_none() {
+            self.extra.stm = Some(ExtraStm::identity());
+        }
         self
     }
 
+    /// Draws the propellant used since the last update (i.e. the difference between the total of the tanks and the prop mass)
+    /// from the tanks feeding the thruster, cf. [PropTanks::draw]. Returns false if these tanks do not hold enough propellant.
+    pub fn update_tanks(&mut self) -> bool {
+        if self.tanks.is_empty() {
+            return true;
+        }
+        let used_kg = self.tanks.total_kg() - self.mass.prop_mass_kg;
+        self.tanks.draw(used_kg)
+    }
+
     /// Returns a copy of the state with a new SRP area and CR
     pub fn with_srp(mut self, srp_area_m2: f64, coeff_reflectivity: f64) -> Self {
         self.srp = SRPData {
@@ -252,12 +386,33 @@ impl Spacecraft {
         self
     }
 
+    /// Returns a copy of the state with this thruster, e.g. `Thruster::from_catalog("NEXT")`
+    pub fn with_thruster(mut self, thruster: Thruster) -> Self {
+        self.thruster = Some(thruster);
+        self
+    }
+
     /// Returns a copy of the state with a new orbit
     pub fn with_orbit(mut self, orbit: Orbit) -> Self {
         self.orbit = orbit;
         self
     }
 
+    /// Returns the equinoctial elements of the orbit of this spacecraft, which must be elliptical
+    pub fn equinoctial(&self) -> PhysicsResult<EquinoctialElements> {
+        EquinoctialElements::from_orbit(&self.orbit)
+    }
+
+    /// Returns the Delaunay elements of the orbit of this spacecraft, which must be elliptical
+    pub fn delaunay(&self) -> PhysicsResult<DelaunayElements> {
+        DelaunayElements::from_orbit(&self.orbit)
+    }
+
+    /// Returns the Poincaré elements of the orbit of this spacecraft, which must be elliptical
+    pub fn poincare(&self) -> PhysicsResult<PoincareElements> {
+        PoincareElements::from_orbit(&self.orbit)
+    }
+
     /// Returns the root sum square error between this spacecraft and the other, in kilometers for the position, kilometers per second in velocity, and kilograms in prop
     pub fn rss(&self, other: &Self) -> PhysicsResult<(f64, f64, f64)> {
         let rss_p_km = self.orbit.rss_radius_km(&other.orbit)?;
@@ -272,6 +427,8 @@ impl Spacecraft {
     /// Sets the STM of this state of identity, which also enables computation of the STM for spacecraft navigation
     pub fn enable_stm(&mut self) {
         self.stm = Some(OMatrix::<f64, Const<9>, Const<9>>::identity());
+        self.extra.stm = (!self.extra.is_empty()).then(ExtraStm::identity);
+        self.orbital_stm = false;
     }
 
     /// Copies the current state but sets the STM to identity
@@ -280,6 +437,17 @@ impl Spacecraft {
         self
     }
 
+    /// Copies the current state but sets the STM to identity, of which only the 6x6 orbital block is propagated.
+    ///
+    /// This is faster when Cr, Cd, and the prop mass are not estimated: their rows and columns of the STM remain identity,
+    /// i.e. the sensitivity of the orbit to these parameters is ignored.
+    pub fn with_orbital_stm(mut self) -> Self {
+        self.enable_stm();
+        self.extra.stm = None;
+        self.orbital_stm = true;
+        self
+    }
+
     /// Returns the total mass in kilograms
     pub fn mass_kg(&self) -> f64 {
         self.mass.total_mass_kg()
@@ -372,10 +540,13 @@ impl fmt::UpperHex for Spacecraft {
 
 impl State for Spacecraft {
     type Size = Const<9>;
-    type VecLength = Const<90>;
+    type VecLength = Const<306>;
 
     fn reset_stm(&mut self) {
         self.stm = Some(OMatrix::<f64, Const<9>, Const<9>>::identity());
+        if !self.orbital_stm && !self.extra.is_empty() {
+            self.extra.stm = Some(ExtraStm::identity());
+        }
     }
 
     fn zeros() -> Self {
@@ -383,9 +554,9 @@ impl State for Spacecraft {
     }
 
     /// The vector is organized as such:
-    /// [X, Y, Z, Vx, Vy, Vz, Cr, Cd, Fuel mass, STM(9x9)]
-    fn to_vector(&self) -> OVector<f64, Const<90>> {
-        let mut vector = OVector::<f64, Const<90>>::zeros();
+    /// [X, Y, Z, Vx, Vy, Vz, Cr, Cd, Fuel mass, STM(9x9), extra states, STM blocks of the extra states]
+    fn to_vector(&self) -> OVector<f64, Const<306>> {
+        let mut vector = OVector::<f64, Const<306>>::zeros();
         // Set the orbit state info
         for (i, val) in self.orbit.radius_km.iter().enumerate() {
             // Place the orbit state first, then skip three (Cr, Cd, Fuel), then copy orbit STM
@@ -399,27 +570,62 @@ impl State for Spacecraft {
         vector[6] = self.srp.coeff_reflectivity;
         vector[7] = self.drag.coeff_drag;
         vector[8] = self.mass.prop_mass_kg;
+        vector
+            .fixed_rows_mut::<MAX_EXTRA_STATES>(Self::EXTRA_INDEX)
+            .copy_from_slice(&self.extra.values);
         // Add the STM to the vector
         if let Some(stm) = self.stm {
-            for (idx, stm_val) in stm.as_slice().iter().enumerate() {
-                vector[idx + Self::Size::dim()] = *stm_val;
+            if self.orbital_stm {
+                for (idx, stm_val) in stm.fixed_view::<6, 6>(0, 0).iter().enumerate() {
+                    vector[idx + Self::STM_INDEX] = *stm_val;
+                }
+            } else {
+                for (idx, stm_val) in stm.as_slice().iter().enumerate() {
+                    vector[idx + Self::STM_INDEX] = *stm_val;
+                }
+                if let Some(extra_stm) = self.extra.stm {
+                    for (idx, stm_val) in extra_stm
+                        .extra_wrt_sc
+                        .iter()
+                        .chain(extra_stm.sc_wrt_extra.iter())
+                        .chain(extra_stm.extra_wrt_extra.iter())
+                        .enumerate()
+                    {
+                        vector[idx + Self::EXTRA_STM_INDEX] = *stm_val;
+                    }
+                }
             }
         }
         vector
     }
 
     /// Vector is expected to be organized as such:
-    /// [X, Y, Z, Vx, Vy, Vz, Cr, Cd, Fuel mass, STM(9x9)]
-    fn set(&mut self, epoch: Epoch, vector: &OVector<f64, Const<90>>) {
+    /// [X, Y, Z, Vx, Vy, Vz, Cr, Cd, Fuel mass, STM(9x9), extra states, STM blocks of the extra states]
+    fn set(&mut self, epoch: Epoch, vector: &OVector<f64, Const<306>>) {
         let sc_state =
             OVector::<f64, Self::Size>::from_column_slice(&vector.as_slice()[..Self::Size::dim()]);
 
         if self.stm.is_some() {
-            let sc_full_stm = OMatrix::<f64, Self::Size, Self::Size>::from_column_slice(
-                &vector.as_slice()[Self::Size::dim()..],
-            );
+            let stm_slice = &vector.as_slice()[Self::STM_INDEX..Self::EXTRA_INDEX];
+            let sc_full_stm = if self.orbital_stm {
+                let mut stm = OMatrix::<f64, Self::Size, Self::Size>::identity();
+                stm.fixed_view_mut::<6, 6>(0, 0)
+                    .copy_from_slice(&stm_slice[..36]);
+                stm
+            } else {
+                OMatrix::<f64, Self::Size, Self::Size>::from_column_slice(stm_slice)
+            };
 
             self.stm = Some(sc_full_stm);
+
+            if let (Some(extra_stm), false) = (self.extra.stm.as_mut(), self.orbital_stm) {
+                let blocks = &vector.as_slice()[Self::EXTRA_STM_INDEX..];
+                let (extra_wrt_sc, blocks) = blocks.split_at(9 * MAX_EXTRA_STATES);
+                let (sc_wrt_extra, extra_wrt_extra) = blocks.split_at(9 * MAX_EXTRA_STATES);
+                extra_stm.extra_wrt_sc.copy_from_slice(extra_wrt_sc);
+                extra_stm.sc_wrt_extra.copy_from_slice(sc_wrt_extra);
+                extra_stm.extra_wrt_extra.copy_from_slice(extra_wrt_extra);
+            }
         }
 
         let radius_km = sc_state.fixed_rows::<3>(0).into_owned();
@@ -430,6 +636,9 @@ impl State for Spacecraft {
         self.srp.coeff_reflectivity = sc_state[6].clamp(0.0, 2.0);
         self.drag.coeff_drag = sc_state[7];
         self.mass.prop_mass_kg = sc_state[8];
+        self.extra.values.copy_from_slice(
+            &vector.as_slice()[Self::EXTRA_INDEX..Self::EXTRA_INDEX + MAX_EXTRA_STATES],
+        );
     }
 
     /// diag(STM) = [X,Y,Z,Vx,Vy,Vz,Cr,Cd,Fuel]
@@ -502,6 +711,34 @@ impl State for Spacecraft {
                 .context(AstroPhysicsSnafu)
                 .context(StateAstroSnafu { param }),
             StateParameter::Declination => Ok(self.orbit.declination_deg()),
+            StateParameter::DelaunayL | StateParameter::DelaunayG | StateParameter::DelaunayH => {
+                let elements = self
+                    .delaunay()
+                    .context(AstroPhysicsSnafu)
+                    .context(StateAstroSnafu { param })?;
+                Ok(match param {
+                    StateParameter::DelaunayL => elements.big_l,
+                    StateParameter::DelaunayG => elements.big_g,
+                    _ => elements.big_h,
+                })
+            }
+            StateParameter::EquinoctialH
+            | StateParameter::EquinoctialK
+            | StateParameter::EquinoctialP
+            | StateParameter::EquinoctialQ
+            | StateParameter::MeanLongitude => {
+                let elements = self
+                    .equinoctial()
+                    .context(AstroPhysicsSnafu)
+                    .context(StateAstroSnafu { param })?;
+                Ok(match param {
+                    StateParameter::EquinoctialH => elements.h,
+                    StateParameter::EquinoctialK => elements.k,
+                    StateParameter::EquinoctialP => elements.p,
+                    StateParameter::EquinoctialQ => elements.q,
+                    _ => elements.lambda_rad.to_degrees().rem_euclid(360.0),
+                })
+            }
             StateParameter::EccentricAnomaly => self
                 .orbit
                 .ea_deg()
@@ -623,11 +860,79 @@ impl State for Spacecraft {
         }
     }
 
+    fn value_with_almanac(
+        &self,
+        param: StateParameter,
+        almanac: &Almanac,
+    ) -> Result<f64, StateError> {
+        if !param.requires_almanac() {
+            return self.value(param);
+        }
+
+        let orbit = self.orbit;
+        if param == StateParameter::Illumination {
+            return EclipseLocator::default()
+                .occultation(orbit, almanac)
+                .map(|occult| (occult.factor() - 1.0).abs())
+                .context(StateAlmanacSnafu { param });
+        }
+
+        let position_of = |frame| {
+            almanac
+                .transform(frame, orbit.frame, orbit.epoch, None)
+                .map(|state| state.radius_km)
+                .context(StateAlmanacSnafu { param })
+        };
+        let sun_km = position_of(SUN_J2000)?;
+
+        match param {
+            StateParameter::SunAngle => {
+                Ok(angle_deg(&(sun_km - orbit.radius_km), &-orbit.radius_km))
+            }
+            StateParameter::SunProbeEarthAngle => {
+                let earth_km = position_of(EARTH_J2000)?;
+                Ok(angle_deg(
+                    &(sun_km - orbit.radius_km),
+                    &(earth_km - orbit.radius_km),
+                ))
+            }
+            StateParameter::MoonPhaseAngle => {
+                let moon_km = position_of(MOON_J2000)?;
+                Ok(angle_deg(&(sun_km - moon_km), &(orbit.radius_km - moon_km)))
+            }
+            StateParameter::BetaAngle => {
+                let h_hat = orbit
+                    .hvec()
+                    .context(AstroPhysicsSnafu)
+                    .context(StateAstroSnafu { param })?
+                    .normalize();
+                Ok(h_hat
+                    .dot(&sun_km.normalize())
+                    .clamp(-1.0, 1.0)
+                    .asin()
+                    .to_degrees())
+            }
+            StateParameter::LTAN => {
+                // The mean Sun is at noon in local time, and the Earth rotates 15 degrees per hour with respect to the Sun
+                let raan_deg = orbit
+                    .raan_deg()
+                    .context(AstroPhysicsSnafu)
+                    .context(StateAstroSnafu { param })?;
+                let sun_ra_deg = sun_km.y.atan2(sun_km.x).to_degrees();
+                Ok((12.0 + (raan_deg - sun_ra_deg) / 15.0).rem_euclid(24.0))
+            }
+            _ => Err(StateError::Unavailable { param }),
+        }
+    }
+
     fn set_value(&mut self, param: StateParameter, val: f64) -> Result<(), StateError> {
         match param {
             StateParameter::Cd => self.drag.coeff_drag = val,
             StateParameter::Cr => self.srp.coeff_reflectivity = val,
-            StateParameter::PropMass => self.mass.prop_mass_kg = val,
+            StateParameter::PropMass => {
+                self.mass.prop_mass_kg = val;
+                self.tanks.set_total_kg(val);
+            }
             StateParameter::DryMass => self.mass.dry_mass_kg = val,
             StateParameter::Isp => match self.thruster {
                 Some(ref mut thruster) => thruster.isp_s = val,
@@ -692,6 +997,23 @@ impl State for Spacecraft {
 
     fn unset_stm(&mut self) {
         self.stm = None;
+        self.extra.stm = None;
+    }
+
+    /// Only the state is integrated without STM, only the orbital block of the STM if it is reduced, and the extra states
+    /// and their STM blocks only if the spacecraft carries any.
+    fn integrated_len(&self) -> usize {
+        if !self.extra.is_empty() {
+            return match (self.stm, self.orbital_stm, self.extra.stm) {
+                (Some(_), false, Some(_)) => Self::VecLength::dim(),
+                _ => Self::EXTRA_STM_INDEX,
+            };
+        }
+        match (self.stm, self.orbital_stm) {
+            (None, _) => Self::STM_INDEX,
+            (Some(_), true) => Self::STM_INDEX + 36,
+            (Some(_), false) => Self::EXTRA_INDEX,
+        }
     }
 
     fn orbit(&self) -> Orbit {
@@ -738,11 +1060,145 @@ impl Add<OVector<f64, Const<9>>> for Spacecraft {
 
 impl ConfigRepr for Spacecraft {}
 
+/// Returns the angle between these two vectors, in degrees
+fn angle_deg(a: &Vector3<f64>, b: &Vector3<f64>) -> f64 {
+    (a.dot(b) / (a.norm() * b.norm()))
+        .clamp(-1.0, 1.0)
+        .acos()
+        .to_degrees()
+}
+
+#[test]
+fn test_almanac_params() {
+    let eme2k = crate::fixtures::eme2k();
+    let epoch = Epoch::from_gregorian_utc_at_midnight(2024, 3, 20);
+    let sc = Spacecraft::from(Orbit::keplerian(
+        7_000.0, 0.001, 98.0, 90.0, 0.0, 0.0, epoch, eme2k,
+    ));
+    let almanac = Almanac::default();
+
+    // These require the ephemerides of the Sun and the Moon, but the other parameters are still available
+    for param in [
+        StateParameter::SunAngle,
+        StateParameter::SunProbeEarthAngle,
+        StateParameter::BetaAngle,
+        StateParameter::LTAN,
+        StateParameter::MoonPhaseAngle,
+    ] {
+        assert!(param.requires_almanac());
+        assert!(!param.is_orbital());
+        assert_eq!(
+            sc.value(param),
+            Err(StateError::Unavailable { param }),
+            "{param}"
+        );
+        assert!(matches!(
+            sc.value_with_almanac(param, &almanac),
+            Err(StateError::StateAlmanacError { .. })
+        ));
+    }
+    assert_eq!(
+        sc.value_with_almanac(StateParameter::SMA, &almanac),
+        sc.value(StateParameter::SMA)
+    );
+    assert!((angle_deg(&Vector3::x(), &Vector3::new(1.0, 1.0, 0.0)) - 45.0).abs() < 1e-12);
+}
+
+#[test]
+fn test_custom_guidance_mode() {
+    use crate::dynamics::guidance::{GuidanceError, GuidanceLaw};
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::propagators::Propagator;
+    use hifitime::Unit;
+    use std::sync::Arc;
+
+    for mode in [
+        GuidanceMode::Coast,
+        GuidanceMode::Thrust,
+        GuidanceMode::Inhibit,
+        GuidanceMode::Custom(0),
+        GuidanceMode::Custom(u8::MAX),
+    ] {
+        assert_eq!(GuidanceMode::from(f64::from(mode)), mode);
+    }
+    assert_eq!(GuidanceMode::from(1.5), GuidanceMode::Thrust);
+    assert_eq!(GuidanceMode::from(1e6), GuidanceMode::Custom(u8::MAX));
+    assert_eq!(GuidanceMode::Custom(3).custom_id(), Some(3));
+    assert!(GuidanceMode::Thrust.custom_id().is_none());
+
+    /// Warms up the thruster in the first custom mode, and then burns along the velocity in the second one.
+    struct WarmUpThenBurn {
+        burn_start: Epoch,
+    }
+
+    impl fmt::Display for WarmUpThenBurn {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "warm up then burn at {}", self.burn_start)
+        }
+    }
+
+    impl GuidanceLaw for WarmUpThenBurn {
+        fn direction(&self, osc: &Spacecraft) -> Result<Vector3<f64>, GuidanceError> {
+            Ok(osc.orbit.velocity_km_s.normalize())
+        }
+
+        fn throttle(&self, osc: &Spacecraft) -> Result<f64, GuidanceError> {
+            Ok(if osc.mode() == GuidanceMode::Custom(1) {
+                1.0
+            } else {
+                0.0
+            })
+        }
+
+        fn next(&self, sc: &mut Spacecraft, _almanac: Arc<Almanac>) {
+            if sc.mode() == GuidanceMode::Custom(0) && sc.epoch() >= self.burn_start {
+                sc.mut_mode(GuidanceMode::Custom(1));
+            }
+        }
+    }
+
+    let eme2k = crate::fixtures::eme2k();
+    let epoch = Epoch::from_gregorian_utc_at_midnight(2024, 3, 20);
+    let orbit = Orbit::keplerian(7_000.0, 0.001, 28.5, 0.0, 0.0, 0.0, epoch, eme2k);
+    let sc = Spacecraft::from_thruster(
+        orbit,
+        500.0,
+        50.0,
+        Thruster::new(1.0, 300.0),
+        GuidanceMode::Custom(0),
+    );
+    assert_eq!(sc.value(StateParameter::GuidanceMode), Ok(2.0));
+
+    let law = Arc::new(WarmUpThenBurn {
+        burn_start: epoch + Unit::Minute * 10,
+    });
+    let setup = Propagator::default(SpacecraftDynamics::from_guidance_law(
+        OrbitalDynamics::two_body(),
+        law,
+    ));
+    let almanac = crate::fixtures::almanac();
+
+    let warm = setup
+        .with(sc, almanac.clone())
+        .for_duration(Unit::Minute * 5)
+        .unwrap();
+    assert_eq!(warm.mode(), GuidanceMode::Custom(0));
+    assert_eq!(warm.mass.prop_mass_kg, 50.0);
+
+    let burnt = setup
+        .with(sc, almanac)
+        .for_duration(Unit::Minute * 30)
+        .unwrap();
+    assert_eq!(burnt.mode(), GuidanceMode::Custom(1));
+    assert!(burnt.mass.prop_mass_kg < 50.0);
+}
+
 #[test]
 fn test_serde() {
     use serde_yml;
     use std::str::FromStr;
 
+    use crate::cosmic::PropTank;
     use anise::constants::frames::EARTH_J2000;
 
     let orbit = Orbit::new(
@@ -830,10 +1286,7 @@ thruster:
     "#;
 
     let mut sc_thruster = sc;
-    sc_thruster.thruster = Some(Thruster {
-        isp_s: 300.5,
-        thrust_N: 1e-5,
-    });
+    sc_thruster.thruster = Some(Thruster::new(1e-5, 300.5));
     let deser_sc: Spacecraft = serde_yml::from_str(s).unwrap();
     assert_eq!(sc_thruster, deser_sc);
 
@@ -864,4 +1317,12 @@ mass:
 
     let sc = Spacecraft::new(orbit, 500.0, 159.0, 0.0, 0.0, 1.8, 2.2);
     assert_eq!(sc, deser_sc);
+
+    // Check that the propellant tanks are serialized as a list
+    let tanks = PropTanks::new(&[PropTank::new(100.0, 0), PropTank::new(59.0, 1).isolated()]);
+    let sc_tanks = sc.with_tanks(tanks.unwrap());
+    let serialized_sc = serde_yml::to_string(&sc_tanks).unwrap();
+    let deser_sc: Spacecraft = serde_yml::from_str(&serialized_sc).unwrap();
+    assert_eq!(deser_sc.tanks, sc_tanks.tanks);
+    assert!(!serde_yml::to_string(&sc).unwrap().contains("tanks"));
 }
@@ -0,0 +1,322 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Maximum number of propellant tanks of a spacecraft
+pub const MAX_PROP_TANKS: usize = 8;
+
+/// Mass below which a propellant shortfall is considered to be numerical noise, in kg (one milligram)
+const TANK_MASS_TOL_KG: f64 = 1e-6;
+
+/// A propellant tank of a spacecraft.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PropTank {
+    /// Propellant mass in this tank, in kg
+    pub prop_mass_kg: f64,
+    /// Draw priority of this tank: the tanks of lowest priority are emptied first, and tanks of equal priority are drawn
+    /// proportionally to their propellant mass.
+    #[serde(default)]
+    pub priority: u8,
+    /// Set to false if this tank does not feed the thruster, e.g. the oxidizer or the reserve of another propulsion system.
+    #[serde(default = "feeds_thruster_default")]
+    pub feeds_thruster: bool,
+}
+
+fn feeds_thruster_default() -> bool {
+    true
+}
+
+impl PropTank {
+    /// Initializes a tank feeding the thruster with this propellant mass and draw priority.
+    pub fn new(prop_mass_kg: f64, priority: u8) -> Self {
+        Self {
+            prop_mass_kg,
+            priority,
+            feeds_thruster: true,
+        }
+    }
+
+    /// Returns a copy of this tank which does not feed the thruster.
+    pub fn isolated(mut self) -> Self {
+        self.feeds_thruster = false;
+        self
+    }
+}
+
+/// The propellant tanks of a spacecraft, up to [MAX_PROP_TANKS].
+///
+/// The propellant mass of the spacecraft remains the integrated quantity: the tanks only book-keep how it is split. The
+/// propellant used during a burn is drawn from the tanks feeding the thruster in order of priority, cf. [PropTanks::draw],
+/// whereas any other change of the propellant mass (e.g. a dispersion or a correction of a targeter) is spread across all
+/// tanks proportionally to their mass, cf. [PropTanks::set_total_kg]. A spacecraft without tanks has a single propellant pool.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<PropTank>", into = "Vec<PropTank>")]
+pub struct PropTanks {
+    tanks: [Option<PropTank>; MAX_PROP_TANKS],
+}
+
+impl PropTanks {
+    /// Initializes the tanks of a spacecraft, or returns None if there are more than [MAX_PROP_TANKS] tanks.
+    pub fn new(tanks: &[PropTank]) -> Option<Self> {
+        if tanks.len() > MAX_PROP_TANKS {
+            return None;
+        }
+        let mut me = Self::default();
+        for (slot, tank) in me.tanks.iter_mut().zip(tanks) {
+            *slot = Some(*tank);
+        }
+        Some(me)
+    }
+
+    /// Returns an iterator over the tanks
+    pub fn iter(&self) -> impl Iterator<Item = &PropTank> {
+        self.tanks.iter().flatten()
+    }
+
+    /// Returns the tank at this index, if any
+    pub fn get(&self, index: usize) -> Option<&PropTank> {
+        self.tanks.get(index).and_then(|tank| tank.as_ref())
+    }
+
+    /// Returns the number of tanks
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns true if there are no tanks, i.e. the spacecraft has a single propellant pool
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the total propellant mass of all tanks, in kg
+    pub fn total_kg(&self) -> f64 {
+        self.iter().map(|tank| tank.prop_mass_kg).sum()
+    }
+
+    /// Returns the propellant mass available to the thruster, in kg
+    pub fn available_kg(&self) -> f64 {
+        self.iter()
+            .filter(|tank| tank.feeds_thruster)
+            .map(|tank| tank.prop_mass_kg)
+            .sum()
+    }
+
+    /// Draws this propellant mass from the tanks feeding the thruster, by increasing priority. Returns false if these tanks
+    /// do not hold enough propellant, in which case they are all emptied.
+    ///
+    /// A negative mass is returned to the tanks as per [PropTanks::set_total_kg].
+    pub fn draw(&mut self, mass_kg: f64) -> bool {
+        if mass_kg < 0.0 {
+            self.set_total_kg(self.total_kg() - mass_kg);
+            return true;
+        }
+
+        let mut remaining_kg = mass_kg;
+        let mut priorities = self
+            .iter()
+            .filter(|tank| tank.feeds_thruster)
+            .map(|tank| tank.priority)
+            .collect::<Vec<_>>();
+        priorities.sort_unstable();
+        priorities.dedup();
+
+        for priority in priorities {
+            if remaining_kg <= 0.0 {
+                break;
+            }
+            let in_group = |tank: &PropTank| tank.feeds_thruster && tank.priority == priority;
+            let group_kg: f64 = self
+                .iter()
+                .filter(|tank| in_group(tank))
+                .map(|tank| tank.prop_mass_kg)
+                .sum();
+            if group_kg <= 0.0 {
+                continue;
+            }
+
+            let fraction = (remaining_kg / group_kg).min(1.0);
+            for tank in self
+                .tanks
+                .iter_mut()
+                .flatten()
+                .filter(|tank| in_group(tank))
+            {
+                tank.prop_mass_kg -= tank.prop_mass_kg * fraction;
+            }
+            remaining_kg -= group_kg * fraction;
+        }
+
+        remaining_kg < TANK_MASS_TOL_KG
+    }
+
+    /// Sets the total propellant mass of the tanks, scaling each tank proportionally to its current mass (or filling the
+    /// first tank if they are all empty).
+    pub fn set_total_kg(&mut self, total_kg: f64) {
+        let current_kg = self.total_kg();
+        if current_kg > 0.0 {
+            let scale = total_kg / current_kg;
+            for tank in self.tanks.iter_mut().flatten() {
+                tank.prop_mass_kg *= scale;
+            }
+        } else if let Some(tank) = self.tanks.iter_mut().flatten().next() {
+            tank.prop_mass_kg = total_kg;
+        }
+    }
+}
+
+impl TryFrom<Vec<PropTank>> for PropTanks {
+    type Error = String;
+
+    fn try_from(tanks: Vec<PropTank>) -> Result<Self, Self::Error> {
+        Self::new(&tanks).ok_or_else(|| {
+            format!(
+                "{} propellant tanks exceed the maximum of {MAX_PROP_TANKS}",
+                tanks.len()
+            )
+        })
+    }
+}
+
+impl From<PropTanks> for Vec<PropTank> {
+    fn from(tanks: PropTanks) -> Self {
+        tanks.iter().copied().collect()
+    }
+}
+
+impl fmt::Display for PropTanks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let masses = self
+            .iter()
+            .map(|tank| format!("{:.3}", tank.prop_mass_kg))
+            .collect::<Vec<_>>();
+        write!(f, "tanks = [{}] kg", masses.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod ut_tanks {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn draw_by_priority() {
+        let mut tanks = PropTanks::new(&[
+            PropTank::new(10.0, 1),
+            PropTank::new(30.0, 0),
+            PropTank::new(10.0, 0),
+            PropTank::new(50.0, 0).isolated(),
+        ])
+        .unwrap();
+        assert_eq!(tanks.len(), 4);
+        assert_eq!(tanks.total_kg(), 100.0);
+        assert_eq!(tanks.available_kg(), 50.0);
+
+        // Tanks of equal priority are drawn proportionally
+        assert!(tanks.draw(20.0));
+        assert!((tanks.get(1).unwrap().prop_mass_kg - 15.0).abs() < 1e-12);
+        assert!((tanks.get(2).unwrap().prop_mass_kg - 5.0).abs() < 1e-12);
+        assert_eq!(tanks.get(0).unwrap().prop_mass_kg, 10.0);
+
+        // Then the next priority, but never the isolated tank
+        assert!(tanks.draw(25.0));
+        assert!(tanks.get(1).unwrap().prop_mass_kg.abs() < 1e-12);
+        assert!((tanks.get(0).unwrap().prop_mass_kg - 5.0).abs() < 1e-12);
+        assert!(!tanks.draw(10.0));
+        assert!(tanks.available_kg().abs() < 1e-12);
+        assert_eq!(tanks.get(3).unwrap().prop_mass_kg, 50.0);
+
+        // Other changes are spread across all tanks
+        tanks.set_total_kg(25.0);
+        assert_eq!(tanks.get(3).unwrap().prop_mass_kg, 25.0);
+
+        let mut many = vec![PropTank::new(1.0, 0); MAX_PROP_TANKS];
+        assert!(PropTanks::try_from(many.clone()).is_ok());
+        many.push(PropTank::new(1.0, 0));
+        assert!(PropTanks::try_from(many).is_err());
+    }
+
+    #[test]
+    fn finite_burn() {
+        use crate::cosmic::{GuidanceMode, Spacecraft};
+        use crate::dynamics::guidance::{LocalFrame, Maneuver, Thruster};
+        use crate::dynamics::{DynamicsError, OrbitalDynamics, SpacecraftDynamics};
+        use crate::linalg::Vector3;
+        use crate::propagators::{PropagationError, Propagator};
+        use crate::time::Unit;
+        use std::sync::Arc;
+
+        let epoch = fixtures::epoch();
+        let orbit = fixtures::keplerian(7_000.0, 0.01, 28.5, 10.0, 20.0, 30.0);
+        let tanks = PropTanks::new(&[
+            PropTank::new(40.0, 1),
+            PropTank::new(60.0, 0),
+            PropTank::new(20.0, 0).isolated(),
+        ])
+        .unwrap();
+        let sc = Spacecraft::from_thruster(
+            orbit,
+            500.0,
+            0.0,
+            Thruster::new(10.0, 300.0),
+            GuidanceMode::Thrust,
+        )
+        .with_tanks(tanks);
+        assert_eq!(sc.mass.prop_mass_kg, 120.0);
+
+        let mnvr = Maneuver::from_time_invariant(
+            epoch,
+            epoch + Unit::Hour,
+            1.0,
+            Vector3::x(),
+            LocalFrame::VNC,
+        );
+        let dynamics =
+            SpacecraftDynamics::from_guidance_law(OrbitalDynamics::two_body(), Arc::new(mnvr));
+        let setup = Propagator::default(dynamics);
+        let almanac = fixtures::almanac();
+
+        // The burn only draws from the tank of lowest priority
+        let end = setup
+            .with(sc, almanac.clone())
+            .until_epoch(epoch + Unit::Hour)
+            .unwrap();
+        let used_kg = 120.0 - end.mass.prop_mass_kg;
+        assert!(used_kg > 10.0);
+        assert!((end.tanks.total_kg() - end.mass.prop_mass_kg).abs() < 1e-9);
+        assert!((end.tanks.get(1).unwrap().prop_mass_kg - (60.0 - used_kg)).abs() < 1e-9);
+        assert_eq!(end.tanks.get(0).unwrap().prop_mass_kg, 40.0);
+        assert_eq!(end.tanks.get(2).unwrap().prop_mass_kg, 20.0);
+
+        // The isolated tank cannot feed the burn
+        let starved = sc.with_tanks(
+            PropTanks::new(&[PropTank::new(1.0, 0), PropTank::new(50.0, 0).isolated()]).unwrap(),
+        );
+        let err = setup
+            .with(starved, almanac)
+            .until_epoch(epoch + Unit::Hour)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            PropagationError::Dynamics {
+                source: DynamicsError::FuelExhausted { .. }
+            }
+        ));
+    }
+}
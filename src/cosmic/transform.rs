@@ -0,0 +1,186 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{Frame, Orbit};
+use crate::linalg::{Matrix6, Vector6};
+use crate::time::Epoch;
+use anise::almanac::Almanac;
+use anise::constants::orientations::J2000;
+use anise::errors::{AlmanacResult, EphemerisSnafu, OrientationSnafu};
+use snafu::ResultExt;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Transformation of Cartesian states from one frame into another at a given epoch: the transformed state is
+/// `matrix * state + offset`, with positions in km and velocities in km/s.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FrameTransform {
+    pub matrix: Matrix6<f64>,
+    pub offset: Vector6<f64>,
+}
+
+impl FrameTransform {
+    /// Computes the transformation between these frames at this epoch, with the same steps as `Almanac::transform_to`:
+    /// rotation into J2000 if the orientations differ, translation to the new origin, and rotation into the new orientation.
+    pub fn compute(from: Frame, to: Frame, epoch: Epoch, almanac: &Almanac) -> AlmanacResult<Self> {
+        let (mut matrix, intermediate) = if from.orient_origin_match(to) {
+            (Matrix6::identity(), from)
+        } else {
+            let intermediate = from.with_orient(J2000);
+            let dcm = almanac
+                .rotate(from, intermediate, epoch)
+                .context(OrientationSnafu {
+                    action: "computing frame transform",
+                })?;
+            (dcm.state_dcm(), intermediate)
+        };
+
+        let mut offset = almanac
+            .translate(intermediate, to, epoch, None)
+            .context(EphemerisSnafu {
+                action: "computing frame transform",
+            })?
+            .to_cartesian_pos_vel();
+
+        if !intermediate.orient_origin_match(to) {
+            let dcm = almanac
+                .rotate(intermediate, to, epoch)
+                .context(OrientationSnafu {
+                    action: "computing frame transform",
+                })?
+                .state_dcm();
+            matrix = dcm * matrix;
+            offset = dcm * offset;
+        }
+
+        Ok(Self { matrix, offset })
+    }
+
+    /// Applies this transformation to a Cartesian state
+    pub fn apply(&self, state: &Vector6<f64>) -> Vector6<f64> {
+        self.matrix * state + self.offset
+    }
+}
+
+/// Cache of the transformations from one frame into another, keyed by epoch.
+///
+/// Transforming several trajectories sampled at the same epochs (e.g. the runs of a Monte Carlo, or the truth and the
+/// estimate of an orbit determination) only evaluates the ephemeris and orientation data once per epoch.
+/// The cache may be shared between threads.
+#[derive(Debug)]
+pub struct FrameTransformCache {
+    pub from: Frame,
+    /// Frame of the transformed states, e.g. as returned by `almanac.frame_from_uid(MOON_J2000)`
+    pub to: Frame,
+    transforms: RwLock<HashMap<Epoch, FrameTransform>>,
+}
+
+impl FrameTransformCache {
+    pub fn new(from: Frame, to: Frame) -> Self {
+        Self {
+            from,
+            to,
+            transforms: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the transformation at this epoch, computing it only if it isn't cached yet.
+    pub fn transform(&self, epoch: Epoch, almanac: &Almanac) -> AlmanacResult<FrameTransform> {
+        if let Some(transform) = self.transforms.read().unwrap().get(&epoch) {
+            return Ok(*transform);
+        }
+
+        let transform = FrameTransform::compute(self.from, self.to, epoch, almanac)?;
+        self.transforms.write().unwrap().insert(epoch, transform);
+        Ok(transform)
+    }
+
+    /// Transforms this orbit into the `to` frame. Orbits which are not in the `from` frame are transformed directly with
+    /// the almanac, without caching.
+    pub fn transform_orbit(&self, orbit: Orbit, almanac: &Almanac) -> AlmanacResult<Orbit> {
+        if !(orbit.frame.ephem_origin_match(self.from)
+            && orbit.frame.orient_origin_match(self.from))
+        {
+            return almanac.transform_to(orbit, self.to, None);
+        }
+
+        let state = self
+            .transform(orbit.epoch, almanac)?
+            .apply(&orbit.to_cartesian_pos_vel());
+        Ok(Orbit::from_cartesian_pos_vel(state, orbit.epoch, self.to))
+    }
+
+    /// Number of epochs in the cache
+    pub fn len(&self) -> usize {
+        self.transforms.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod ut_transform {
+    use super::*;
+    use crate::fixtures;
+    use crate::linalg::Vector3;
+
+    #[test]
+    fn same_frame_is_identity() {
+        let almanac = Almanac::default();
+        let eme2k = fixtures::eme2k();
+        let epoch = fixtures::epoch();
+        let cache = FrameTransformCache::new(eme2k, eme2k);
+        assert!(cache.is_empty());
+
+        let orbit = Orbit::new(7_000.0, 100.0, -50.0, 0.1, 7.5, 0.2, epoch, eme2k);
+        for _ in 0..3 {
+            let transformed = cache.transform_orbit(orbit, &almanac).unwrap();
+            assert_eq!(transformed, orbit);
+        }
+        assert_eq!(cache.len(), 1);
+        cache
+            .transform_orbit(
+                orbit
+                    .at_epoch(epoch + 60.0 * crate::time::Unit::Second)
+                    .unwrap(),
+                &almanac,
+            )
+            .unwrap();
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn apply_affine() {
+        // A rotation of 90 degrees about Z followed by a translation
+        let mut matrix = Matrix6::zeros();
+        for block in [0, 3] {
+            matrix[(block, block + 1)] = -1.0;
+            matrix[(block + 1, block)] = 1.0;
+            matrix[(block + 2, block + 2)] = 1.0;
+        }
+        let transform = FrameTransform {
+            matrix,
+            offset: Vector6::new(10.0, 0.0, 0.0, 0.0, 1.0, 0.0),
+        };
+        let state = transform.apply(&Vector6::new(1.0, 0.0, 0.0, 0.0, 0.0, 1.0));
+        assert_eq!(state.fixed_rows::<3>(0), Vector3::new(10.0, 1.0, 0.0));
+        assert_eq!(state.fixed_rows::<3>(3), Vector3::new(0.0, 1.0, 1.0));
+    }
+}
@@ -18,13 +18,17 @@
 
 use anise::almanac::Almanac;
 use anise::constants::frames::IAU_EARTH_FRAME;
+use anise::errors::OrientationSnafu;
 use snafu::ResultExt;
 
 use super::{
     DynamicsAlmanacSnafu, DynamicsAstroSnafu, DynamicsError, DynamicsPlanetarySnafu, ForceModel,
+    ForcePartials,
 };
-use crate::cosmic::{AstroError, AstroPhysicsSnafu, Frame, Spacecraft};
-use crate::linalg::{Matrix4x3, Vector3};
+use crate::cosmic::{AstroPhysicsSnafu, Frame, Spacecraft};
+use crate::linalg::{Matrix3, Vector3};
+use crate::profiling;
+use std::f64::consts::LN_10;
 use std::fmt;
 use std::sync::Arc;
 
@@ -71,31 +75,30 @@ impl ForceModel for ConstantDrag {
     }
 
     fn eom(&self, ctx: &Spacecraft, almanac: Arc<Almanac>) -> Result<Vector3<f64>, DynamicsError> {
-        let osc = almanac
-            .transform_to(ctx.orbit, self.drag_frame, None)
-            .context(DynamicsAlmanacSnafu {
-                action: "transforming into drag frame",
-            })?;
-
-        let velocity = osc.velocity_km_s;
+        let (velocity, _) = relative_velocity(ctx, self.drag_frame, almanac)?;
         // Note the 1e3 factor to convert drag units from ((kg * km^2 * s^-2) / m^1) to (kg * km * s^-2)
         Ok(-0.5
             * 1e3
             * self.rho
             * ctx.drag.coeff_drag
-            * ctx.drag.area_m2
+            * ctx.drag_area_m2(&velocity)
             * velocity.norm()
             * velocity)
     }
 
     fn dual_eom(
         &self,
-        _osc_ctx: &Spacecraft,
-        _almanac: Arc<Almanac>,
-    ) -> Result<(Vector3<f64>, Matrix4x3<f64>), DynamicsError> {
-        Err(DynamicsError::DynamicsAstro {
-            source: AstroError::PartialsUndefined,
-        })
+        ctx: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<(Vector3<f64>, ForcePartials), DynamicsError> {
+        let (velocity, omega_cross) = relative_velocity(ctx, self.drag_frame, almanac)?;
+        Ok(drag_partials(
+            ctx,
+            self.rho,
+            Vector3::zeros(),
+            velocity,
+            omega_cross,
+        ))
     }
 }
 
@@ -154,36 +157,36 @@ impl fmt::Display for Drag {
     }
 }
 
-impl ForceModel for Drag {
-    fn estimation_index(&self) -> Option<usize> {
-        if self.estimate {
-            Some(7)
-        } else {
-            None
-        }
-    }
-
-    fn eom(&self, ctx: &Spacecraft, almanac: Arc<Almanac>) -> Result<Vector3<f64>, DynamicsError> {
-        let integration_frame = ctx.orbit.frame;
+impl Drag {
+    /// Returns the atmospheric density in kg/m^3 and its gradient wrt the position of the spacecraft (in the integration frame).
+    fn density(
+        &self,
+        ctx: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<(f64, Vector3<f64>), DynamicsError> {
+        let osc_drag_frame = profiling::almanac_query("drag: transform_to", || {
+            almanac.transform_to(ctx.orbit, self.drag_frame, None)
+        })
+        .context(DynamicsAlmanacSnafu {
+            action: "transforming into drag frame",
+        })?;
 
-        let osc_drag_frame = almanac
-            .transform_to(ctx.orbit, self.drag_frame, None)
-            .context(DynamicsAlmanacSnafu {
-                action: "transforming into drag frame",
-            })?;
+        // Unit vector along which the density varies, rotated back into the integration frame.
+        let r_hat = profiling::almanac_query("drag: rotate", || {
+            almanac.rotate(self.drag_frame, ctx.orbit.frame, ctx.orbit.epoch)
+        })
+        .context(OrientationSnafu {
+            action: "rotating drag frame into integration frame",
+        })
+        .context(DynamicsAlmanacSnafu {
+            action: "computing the density gradient",
+        })?
+        .rot_mat
+            * osc_drag_frame.radius_km
+            / osc_drag_frame.rmag_km();
 
         match self.density {
-            AtmDensity::Constant(rho) => {
-                let velocity = osc_drag_frame.velocity_km_s;
-                // Note the 1e3 factor to convert drag units from ((kg * km^2 * s^-2) / m^1) to (kg * km * s^-2)
-                Ok(-0.5
-                    * 1e3
-                    * rho
-                    * ctx.drag.coeff_drag
-                    * ctx.drag.area_m2
-                    * velocity.norm()
-                    * velocity)
-            }
+            AtmDensity::Constant(rho) => Ok((rho, Vector3::zeros())),
 
             AtmDensity::Exponential {
                 rho0,
@@ -202,24 +205,7 @@ impl ForceModel for Drag {
                         / ref_alt_m)
                         .exp();
 
-                // TODO: Drag modeling will be improved in https://github.com/nyx-space/nyx/issues/317
-                // The frame will be double checked in this PR as well.
-                let velocity_integr_frame = almanac
-                    .transform_to(osc_drag_frame, integration_frame, None)
-                    .context(DynamicsAlmanacSnafu {
-                        action: "rotating into the integration frame",
-                    })?
-                    .velocity_km_s;
-
-                let velocity = velocity_integr_frame - osc_drag_frame.velocity_km_s;
-                // Note the 1e3 factor to convert drag units from ((kg * km^2 * s^-2) / m^1) to (kg * km * s^-2)
-                Ok(-0.5
-                    * 1e3
-                    * rho
-                    * ctx.drag.coeff_drag
-                    * ctx.drag.area_m2
-                    * velocity.norm()
-                    * velocity)
+                Ok((rho, -rho / ref_alt_m * r_hat))
             }
 
             AtmDensity::StdAtm { max_alt_m } => {
@@ -229,9 +215,9 @@ impl ForceModel for Drag {
                         .mean_equatorial_radius_km()
                         .context(AstroPhysicsSnafu)
                         .context(DynamicsAstroSnafu)?;
-                let rho = if altitude_km > max_alt_m / 1_000.0 {
+                let (rho, dlogrho_dalt) = if altitude_km > max_alt_m / 1_000.0 {
                     // Use a constant density
-                    10.0_f64.powf((-7e-5) * altitude_km - 14.464)
+                    (10.0_f64.powf((-7e-5) * altitude_km - 14.464), -7e-5)
                 } else {
                     // Code from AVS/Schaub's Basilisk
                     // Calculating the density based on a scaled 6th order polynomial fit to the log of density
@@ -243,37 +229,126 @@ impl ForceModel for Drag {
                             - 2.3024 * scale
                             - 12.575;
 
+                    let dlogdensity_dscale = 6.0 * 0.34047 * scale.powi(5)
+                        - 5.0 * 0.5889 * scale.powi(4)
+                        - 4.0 * 0.5269 * scale.powi(3)
+                        + 3.0 * 1.0036 * scale.powi(2)
+                        + 2.0 * 0.60713 * scale
+                        - 2.3024;
+
                     /* Calculating density by raising 10 to the log of density */
-                    10.0_f64.powf(logdensity)
+                    (10.0_f64.powf(logdensity), dlogdensity_dscale / 292.8563)
                 };
 
-                let velocity_integr_frame = almanac
-                    .transform_to(osc_drag_frame, integration_frame, None)
-                    .context(DynamicsAlmanacSnafu {
-                        action: "rotating into the integration frame",
-                    })?
-                    .velocity_km_s;
-
-                let velocity = velocity_integr_frame - osc_drag_frame.velocity_km_s;
-                // Note the 1e3 factor to convert drag units from ((kg * km^2 * s^-2) / m^1) to (kg * km * s^-2)
-                Ok(-0.5
-                    * 1e3
-                    * rho
-                    * ctx.drag.coeff_drag
-                    * ctx.drag.area_m2
-                    * velocity.norm()
-                    * velocity)
+                Ok((rho, rho * LN_10 * dlogrho_dalt * r_hat))
             }
         }
     }
+}
+
+impl ForceModel for Drag {
+    fn estimation_index(&self) -> Option<usize> {
+        if self.estimate {
+            Some(7)
+        } else {
+            None
+        }
+    }
+
+    fn eom(&self, ctx: &Spacecraft, almanac: Arc<Almanac>) -> Result<Vector3<f64>, DynamicsError> {
+        let (rho, _) = self.density(ctx, almanac.clone())?;
+        let (velocity, _) = relative_velocity(ctx, self.drag_frame, almanac)?;
+
+        // Note the 1e3 factor to convert drag units from ((kg * km^2 * s^-2) / m^1) to (kg * km * s^-2)
+        Ok(-0.5
+            * 1e3
+            * rho
+            * ctx.drag.coeff_drag
+            * ctx.drag_area_m2(&velocity)
+            * velocity.norm()
+            * velocity)
+    }
 
     fn dual_eom(
         &self,
-        _osc_ctx: &Spacecraft,
-        _almanac: Arc<Almanac>,
-    ) -> Result<(Vector3<f64>, Matrix4x3<f64>), DynamicsError> {
-        Err(DynamicsError::DynamicsAstro {
-            source: AstroError::PartialsUndefined,
-        })
+        ctx: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<(Vector3<f64>, ForcePartials), DynamicsError> {
+        let (rho, grad_rho) = self.density(ctx, almanac.clone())?;
+        let (velocity, omega_cross) = relative_velocity(ctx, self.drag_frame, almanac)?;
+
+        Ok(drag_partials(ctx, rho, grad_rho, velocity, omega_cross))
+    }
+}
+
+/// Returns the velocity of the spacecraft relative to the atmosphere, which co-rotates with the drag frame, expressed
+/// in the integration frame, along with the matrix W such that this relative velocity is v - W r (i.e. W = [ω×]).
+fn relative_velocity(
+    ctx: &Spacecraft,
+    drag_frame: Frame,
+    almanac: Arc<Almanac>,
+) -> Result<(Vector3<f64>, Matrix3<f64>), DynamicsError> {
+    let integration_frame = ctx.orbit.frame;
+    let osc_drag_frame = profiling::almanac_query("drag: transform_to", || {
+        almanac.transform_to(ctx.orbit, drag_frame, None)
+    })
+    .context(DynamicsAlmanacSnafu {
+        action: "transforming into drag frame",
+    })?;
+
+    let dcm = profiling::almanac_query("drag: rotate", || {
+        almanac.rotate(drag_frame, integration_frame, ctx.orbit.epoch)
+    })
+    .context(OrientationSnafu {
+        action: "rotating drag frame into integration frame",
+    })
+    .context(DynamicsAlmanacSnafu {
+        action: "computing the velocity relative to the atmosphere",
+    })?;
+
+    let omega_cross = match dcm.rot_mat_dt {
+        Some(rot_mat_dt) => rot_mat_dt * dcm.rot_mat.transpose(),
+        None => Matrix3::zeros(),
+    };
+
+    Ok((dcm.rot_mat * osc_drag_frame.velocity_km_s, omega_cross))
+}
+
+/// Computes the drag force F = -1/2 rho Cd A |u| u and its partials, where u = v - W r is the velocity relative to the atmosphere.
+fn drag_partials(
+    ctx: &Spacecraft,
+    rho: f64,
+    grad_rho: Vector3<f64>,
+    velocity: Vector3<f64>,
+    omega_cross: Matrix3<f64>,
+) -> (Vector3<f64>, ForcePartials) {
+    // Note the 1e3 factor to convert drag units from ((kg * km^2 * s^-2) / m^1) to (kg * km * s^-2)
+    // The partials of the projected area with respect to the velocity are neglected
+    let coeff = 0.5 * 1e3 * ctx.drag.coeff_drag * ctx.drag_area_m2(&velocity);
+    let vmag = velocity.norm();
+    let force = -coeff * rho * vmag * velocity;
+
+    let mut grad = ForcePartials::zeros();
+    if vmag > 0.0 {
+        // Partials wrt the relative velocity, where [i, j] is the partial of the i-th component wrt the j-th variable
+        let wrt_vel =
+            -coeff * rho * (vmag * Matrix3::identity() + velocity * velocity.transpose() / vmag);
+        let wrt_pos = -wrt_vel * omega_cross - coeff * vmag * velocity * grad_rho.transpose();
+
+        for i in 0..3 {
+            for j in 0..3 {
+                grad[(j, i)] = wrt_pos[(i, j)];
+                grad[(j + 3, i)] = wrt_vel[(i, j)];
+            }
+        }
     }
+
+    // Compute the partial wrt to Cd.
+    if ctx.drag.coeff_drag.abs() > 0.0 {
+        for i in 0..3 {
+            grad[(6, i)] = force[i] / ctx.drag.coeff_drag;
+        }
+    }
+
+    (force, grad)
 }
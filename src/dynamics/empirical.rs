@@ -0,0 +1,162 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::AccelModel;
+use super::DynamicsError;
+use crate::cosmic::Orbit;
+use crate::linalg::{Const, Matrix3, Vector3};
+use crate::time::{Duration, Epoch};
+use anise::almanac::Almanac;
+use hyperdual::linalg::norm;
+use hyperdual::{extract_jacobian_and_result, hyperspace_from_vector, OHyperdual};
+use std::fmt;
+use std::sync::Arc;
+
+/// Empirical accelerations, constant in the radial, in-track, and cross-track (RIC) frame over each arc.
+///
+/// These are typically used to absorb small unmodeled forces, like outgassing or thermal radiation, during orbit determination.
+/// Each arc starts at its epoch and lasts until the start of the next arc. There is no empirical acceleration prior to the first arc.
+#[derive(Clone, Debug, Default)]
+pub struct EmpiricalAccel {
+    /// Start epoch of each arc and the acceleration in the RIC frame over that arc, in km/s^2, sorted by epoch.
+    pub arcs: Vec<(Epoch, Vector3<f64>)>,
+}
+
+impl EmpiricalAccel {
+    /// Initializes a constant empirical acceleration in the RIC frame, in km/s^2, applied at all epochs.
+    pub fn constant(accel_ric_km_s2: Vector3<f64>) -> Arc<Self> {
+        Arc::new(Self {
+            arcs: vec![(Epoch::from_tai_duration(Duration::MIN), accel_ric_km_s2)],
+        })
+    }
+
+    /// Initializes piecewise constant empirical accelerations from the start epoch of each arc and its RIC acceleration in km/s^2.
+    pub fn from_arcs(mut arcs: Vec<(Epoch, Vector3<f64>)>) -> Arc<Self> {
+        arcs.sort_by_key(|(start, _)| *start);
+        Arc::new(Self { arcs })
+    }
+
+    /// Returns the RIC acceleration in km/s^2 applicable at the provided epoch.
+    pub fn accel_ric_km_s2(&self, epoch: Epoch) -> Vector3<f64> {
+        match self.arcs.partition_point(|(start, _)| *start <= epoch) {
+            0 => Vector3::zeros(),
+            idx => self.arcs[idx - 1].1,
+        }
+    }
+}
+
+impl fmt::Display for EmpiricalAccel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Empirical RIC accelerations over {} arc(s)",
+            self.arcs.len()
+        )
+    }
+}
+
+impl AccelModel for EmpiricalAccel {
+    fn eom(&self, osc: &Orbit, _almanac: Arc<Almanac>) -> Result<Vector3<f64>, DynamicsError> {
+        let accel_ric = self.accel_ric_km_s2(osc.epoch);
+
+        let r_hat = osc.radius_km / osc.rmag_km();
+        let h_vec = osc.radius_km.cross(&osc.velocity_km_s);
+        let c_hat = h_vec / h_vec.norm();
+        let i_hat = c_hat.cross(&r_hat);
+
+        Ok(accel_ric[0] * r_hat + accel_ric[1] * i_hat + accel_ric[2] * c_hat)
+    }
+
+    /// NOTE: The partials wrt the velocity, due to the rotation of the RIC frame, are not included.
+    fn dual_eom(
+        &self,
+        osc: &Orbit,
+        _almanac: Arc<Almanac>,
+    ) -> Result<(Vector3<f64>, Matrix3<f64>), DynamicsError> {
+        type HD = OHyperdual<f64, Const<7>>;
+
+        let accel_ric = self.accel_ric_km_s2(osc.epoch);
+
+        let radius: Vector3<HD> = hyperspace_from_vector(&osc.radius_km);
+        let velocity = osc.velocity_km_s.map(HD::from_real);
+
+        let cross = |a: &Vector3<HD>, b: &Vector3<HD>| -> Vector3<HD> {
+            Vector3::new(
+                a[1] * b[2] - a[2] * b[1],
+                a[2] * b[0] - a[0] * b[2],
+                a[0] * b[1] - a[1] * b[0],
+            )
+        };
+
+        let r_hat = radius / norm(&radius);
+        let h_vec = cross(&radius, &velocity);
+        let c_hat = h_vec / norm(&h_vec);
+        let i_hat = cross(&c_hat, &r_hat);
+
+        let accel = r_hat * HD::from_real(accel_ric[0])
+            + i_hat * HD::from_real(accel_ric[1])
+            + c_hat * HD::from_real(accel_ric[2]);
+
+        Ok(extract_jacobian_and_result::<_, 3, 3, 7>(&accel))
+    }
+}
+
+#[cfg(test)]
+mod ut_empirical {
+    use super::*;
+    use crate::fixtures;
+    use crate::time::Unit;
+
+    #[test]
+    fn empirical_ric_directions() {
+        let epoch = fixtures::epoch();
+        let eme2k = fixtures::eme2k();
+        let orbit = Orbit::new(7000.0, 0.0, 0.0, 0.0, 7.5, 0.0, epoch, eme2k);
+        let almanac = fixtures::almanac();
+
+        let radial = EmpiricalAccel::constant(Vector3::new(1e-9, 0.0, 0.0));
+        let in_track = EmpiricalAccel::constant(Vector3::new(0.0, 1e-9, 0.0));
+        let cross_track = EmpiricalAccel::constant(Vector3::new(0.0, 0.0, 1e-9));
+
+        let eom = |model: &EmpiricalAccel| model.eom(&orbit, almanac.clone()).unwrap();
+        assert!((eom(&radial) - Vector3::new(1e-9, 0.0, 0.0)).norm() < 1e-24);
+        assert!((eom(&in_track) - Vector3::new(0.0, 1e-9, 0.0)).norm() < 1e-24);
+        assert!((eom(&cross_track) - Vector3::new(0.0, 0.0, 1e-9)).norm() < 1e-24);
+
+        // The dual EOM must match the real EOM
+        let (accel, grad) = in_track.dual_eom(&orbit, almanac.clone()).unwrap();
+        assert!((accel - eom(&in_track)).norm() < 1e-24);
+        // Rotating the position in-track rotates the in-track direction towards the anti-radial direction.
+        assert!((grad[(0, 1)] + 1e-9 / 7000.0).abs() < 1e-24);
+
+        // Piecewise constant arcs
+        let arcs = EmpiricalAccel::from_arcs(vec![
+            (epoch + Unit::Hour * 1, Vector3::new(0.0, 2e-9, 0.0)),
+            (epoch, Vector3::new(0.0, 1e-9, 0.0)),
+        ]);
+        assert_eq!(
+            arcs.accel_ric_km_s2(epoch - Unit::Second * 1),
+            Vector3::zeros()
+        );
+        assert_eq!(arcs.accel_ric_km_s2(epoch), Vector3::new(0.0, 1e-9, 0.0));
+        assert_eq!(
+            arcs.accel_ric_km_s2(epoch + Unit::Hour * 2),
+            Vector3::new(0.0, 2e-9, 0.0)
+        );
+    }
+}
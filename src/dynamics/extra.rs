@@ -0,0 +1,377 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{DynamicsError, ForceModel, ForcePartials};
+use crate::cosmic::Spacecraft;
+use crate::linalg::{Const, OMatrix, OVector, Vector3};
+use crate::State;
+use anise::prelude::Almanac;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+/// Maximum number of extra states of a spacecraft
+pub const MAX_EXTRA_STATES: usize = 8;
+
+/// Values of the extra states of a spacecraft, e.g. an antenna boresight bias or a gas leak acceleration.
+///
+/// The values are stored in the order of the [ExtraState] models of the spacecraft dynamics, which also name them. They are
+/// integrated with the rest of the state vector, and their partials are integrated with the full STM of the spacecraft.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExtraStates {
+    /// Values of the extra states, in the order of their models
+    #[serde(default)]
+    pub values: [f64; MAX_EXTRA_STATES],
+    /// Number of extra states carried by the spacecraft, cf. [ExtraStates::len]
+    #[serde(default)]
+    pub count: usize,
+    /// Blocks of the STM involving the extra states, set with the full STM of the spacecraft if it carries extra states
+    #[serde(skip)]
+    pub stm: Option<ExtraStm>,
+}
+
+impl ExtraStates {
+    /// Returns the number of extra states carried by the spacecraft, i.e. the count, or more if a later value is set.
+    pub fn len(&self) -> usize {
+        let set = self
+            .values
+            .iter()
+            .rposition(|value| *value != 0.0)
+            .map_or(0, |index| index + 1);
+        self.count.max(set)
+    }
+
+    /// Returns true if the spacecraft carries no extra states
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Blocks of the STM of the spacecraft state augmented with its extra states, besides the STM of the spacecraft state itself.
+///
+/// The spacecraft state is the position, velocity, Cr, Cd, and prop mass, cf. [Spacecraft::stm].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ExtraStm {
+    /// Partials of the extra states wrt the initial spacecraft state
+    pub extra_wrt_sc: OMatrix<f64, Const<MAX_EXTRA_STATES>, Const<9>>,
+    /// Partials of the spacecraft state wrt the initial extra states, only non zero for extra states used by the dynamics
+    pub sc_wrt_extra: OMatrix<f64, Const<9>, Const<MAX_EXTRA_STATES>>,
+    /// Partials of the extra states wrt their initial values
+    pub extra_wrt_extra: OMatrix<f64, Const<MAX_EXTRA_STATES>, Const<MAX_EXTRA_STATES>>,
+}
+
+impl ExtraStm {
+    /// The STM blocks at the start of a propagation
+    pub fn identity() -> Self {
+        Self {
+            extra_wrt_sc: OMatrix::<f64, Const<MAX_EXTRA_STATES>, Const<9>>::zeros(),
+            sc_wrt_extra: OMatrix::<f64, Const<9>, Const<MAX_EXTRA_STATES>>::zeros(),
+            extra_wrt_extra:
+                OMatrix::<f64, Const<MAX_EXTRA_STATES>, Const<MAX_EXTRA_STATES>>::identity(),
+        }
+    }
+}
+
+/// Dynamics of an extra state.
+#[derive(Clone)]
+pub enum ExtraStateModel {
+    /// The value is constant
+    Constant,
+    /// The value changes at this constant rate, in units per second
+    Rate(f64),
+    /// The rate of the value (in units per second) is computed from the spacecraft state and the current value
+    Custom(Arc<dyn Fn(&Spacecraft, f64) -> f64 + Send + Sync>),
+}
+
+impl fmt::Debug for ExtraStateModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Constant => write!(f, "Constant"),
+            Self::Rate(rate) => write!(f, "Rate({rate})"),
+            Self::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+/// A named extra state of a spacecraft, whose value is stored in [Spacecraft::extra].
+#[derive(Clone, Debug)]
+pub struct ExtraState {
+    pub name: String,
+    pub model: ExtraStateModel,
+    /// Set if a force model reads this extra state, such that the partials of the dynamics wrt it are part of the STM
+    pub in_dynamics: bool,
+}
+
+impl ExtraState {
+    /// An extra state whose value is constant
+    pub fn constant(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            model: ExtraStateModel::Constant,
+            in_dynamics: false,
+        }
+    }
+
+    /// An extra state whose value changes at a constant rate, in units per second
+    pub fn with_rate(name: &str, rate: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            model: ExtraStateModel::Rate(rate),
+            in_dynamics: false,
+        }
+    }
+
+    /// An extra state whose rate (in units per second) is computed from the spacecraft state and the current value
+    pub fn custom<F>(name: &str, rate: F) -> Self
+    where
+        F: Fn(&Spacecraft, f64) -> f64 + Send + Sync + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            model: ExtraStateModel::Custom(Arc::new(rate)),
+            in_dynamics: false,
+        }
+    }
+
+    /// Marks this extra state as read by a force model (e.g. a gas leak acceleration), such that the partials of the
+    /// dynamics wrt it are computed by finite differencing and integrated in the STM, which allows estimating it.
+    pub fn in_dynamics(mut self) -> Self {
+        self.in_dynamics = true;
+        self
+    }
+
+    /// Returns the rate of this extra state given the spacecraft state and its current value
+    pub fn rate(&self, sc: &Spacecraft, value: f64) -> f64 {
+        match &self.model {
+            ExtraStateModel::Constant => 0.0,
+            ExtraStateModel::Rate(rate) => *rate,
+            ExtraStateModel::Custom(rate) => rate(sc, value),
+        }
+    }
+
+    /// Returns the partials of the rate of this extra state wrt the spacecraft state and wrt its value, computed by
+    /// central finite differences for a custom model.
+    pub fn rate_partials(&self, sc: &Spacecraft, value: f64) -> (OVector<f64, Const<9>>, f64) {
+        let mut wrt_sc = OVector::<f64, Const<9>>::zeros();
+        let ExtraStateModel::Custom(rate) = &self.model else {
+            return (wrt_sc, 0.0);
+        };
+
+        let sc_vec = sc.to_vector();
+        for col in 0..9 {
+            let step = f64::EPSILON.cbrt() * sc_vec[col].abs().max(1.0);
+            let mut pert = OVector::<f64, Const<9>>::zeros();
+            pert[col] = step;
+            wrt_sc[col] =
+                (rate(&(*sc + pert), value) - rate(&(*sc + (-pert)), value)) / (2.0 * step);
+        }
+
+        let step = f64::EPSILON.cbrt() * value.abs().max(1.0);
+        let wrt_value = (rate(sc, value + step) - rate(sc, value - step)) / (2.0 * step);
+
+        (wrt_sc, wrt_value)
+    }
+}
+
+impl fmt::Display for ExtraState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({:?})", self.name, self.model)
+    }
+}
+
+/// Force along a fixed inertial direction, e.g. a gas leak, whose magnitude in Newtons is the extra state at this index.
+///
+/// The extra state must be marked as [ExtraState::in_dynamics] for the sensitivity of the orbit to it to be part of the STM.
+#[derive(Copy, Clone, Debug)]
+pub struct ExtraForce {
+    /// Index of the extra state of the magnitude of the force, in Newtons
+    pub index: usize,
+    /// Unit vector of the direction of the force in the integration frame
+    pub direction: Vector3<f64>,
+}
+
+impl ExtraForce {
+    /// Initializes a force along this direction (normalized), of the magnitude of the extra state at this index
+    pub fn new(index: usize, direction: Vector3<f64>) -> Arc<Self> {
+        Arc::new(Self {
+            index,
+            direction: direction.normalize(),
+        })
+    }
+}
+
+impl fmt::Display for ExtraForce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "force of extra[{}] along [{}, {}, {}]",
+            self.index, self.direction.x, self.direction.y, self.direction.z
+        )
+    }
+}
+
+impl ForceModel for ExtraForce {
+    fn estimation_index(&self) -> Option<usize> {
+        None
+    }
+
+    fn eom(&self, ctx: &Spacecraft, _almanac: Arc<Almanac>) -> Result<Vector3<f64>, DynamicsError> {
+        // Convert from Newtons to kg km s^-2
+        Ok(self.direction * ctx.extra.values[self.index] * 1e-3)
+    }
+
+    fn dual_eom(
+        &self,
+        osc_ctx: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<(Vector3<f64>, ForcePartials), DynamicsError> {
+        Ok((self.eom(osc_ctx, almanac)?, ForcePartials::zeros()))
+    }
+}
+
+#[cfg(test)]
+mod ut_extra {
+    use super::*;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::propagators::{PropagationError, Propagator};
+    use crate::time::Unit;
+
+    #[test]
+    fn propagate_extra_states() {
+        let orbit = fixtures::keplerian(7_000.0, 0.01, 28.5, 10.0, 20.0, 30.0);
+        let sc = Spacecraft::from(orbit)
+            .with_extra(0, 0.25)
+            .with_extra(1, 1e-9)
+            .with_extra(2, 1.0);
+
+        let dynamics = SpacecraftDynamics::new(OrbitalDynamics::two_body())
+            .with_extra_states(vec![
+                ExtraState::constant("boresight_bias_deg"),
+                ExtraState::with_rate("leak_accel_km_s2", 1e-12),
+                ExtraState::custom("decay", |_, value| -value / 7200.0),
+            ])
+            .unwrap();
+        assert_eq!(dynamics.extra_index("decay"), Some(2));
+        assert!(dynamics.extra_index("unknown").is_none());
+
+        let end = Propagator::default(dynamics.clone())
+            .with(sc, fixtures::almanac())
+            .for_duration(Unit::Hour * 2)
+            .unwrap();
+
+        assert_eq!(dynamics.extra_value(&end, "boresight_bias_deg"), Some(0.25));
+        let leak = dynamics.extra_value(&end, "leak_accel_km_s2").unwrap();
+        assert!((leak - (1e-9 + 1e-12 * 7200.0)).abs() < 1e-18);
+        // The custom rate is integrated with the rest of the state
+        let decay = dynamics.extra_value(&end, "decay").unwrap();
+        assert!((decay - (-1.0_f64).exp()).abs() < 1e-9, "{decay}");
+
+        // The spacecraft must carry all of the extra states of the dynamics
+        assert!(matches!(
+            Propagator::default(dynamics.clone())
+                .with(
+                    Spacecraft::from(orbit).with_extra(1, 0.0),
+                    fixtures::almanac()
+                )
+                .for_duration(Unit::Hour * 2),
+            Err(PropagationError::Dynamics {
+                source: DynamicsError::MissingExtraStates { count: 3, len: 2 }
+            })
+        ));
+
+        assert!(matches!(
+            dynamics
+                .with_extra_states(vec![ExtraState::constant("too many"); MAX_EXTRA_STATES + 1]),
+            Err(DynamicsError::TooManyExtraStates { .. })
+        ));
+    }
+
+    #[test]
+    fn extra_states_stm() {
+        let almanac = fixtures::almanac();
+        let orbit = fixtures::keplerian(7_000.0, 0.01, 28.5, 10.0, 20.0, 30.0);
+        let sc = Spacecraft::from_srp_defaults(orbit, 100.0, 1.0)
+            .with_extra(0, 1e-3)
+            .with_extra(1, 1.0);
+
+        let dynamics = SpacecraftDynamics::from_model(
+            OrbitalDynamics::two_body(),
+            ExtraForce::new(0, Vector3::z()),
+        )
+        .with_extra_states(vec![
+            ExtraState::constant("leak_N").in_dynamics(),
+            // The decay depends on the altitude, such that the extra state depends on the orbit
+            ExtraState::custom("decay", |sc, value| {
+                -value * sc.orbit.rmag_km() / 7_000.0 / 7200.0
+            }),
+        ])
+        .unwrap();
+        let setup = Propagator::default(dynamics);
+        let duration = Unit::Hour * 1;
+
+        let end = setup
+            .with(sc.with_stm(), almanac.clone())
+            .for_duration(duration)
+            .unwrap();
+        let extra_stm = end.extra.stm.unwrap();
+
+        // Compare the STM blocks to central finite differences of the propagation.
+        let propagate = |sc: Spacecraft| {
+            setup
+                .with(sc, almanac.clone())
+                .for_duration(duration)
+                .unwrap()
+        };
+        let leak_step = 1e-3;
+        let plus = propagate(sc.with_extra(0, 1e-3 + leak_step));
+        let minus = propagate(sc.with_extra(0, 1e-3 - leak_step));
+        for i in 0..6 {
+            let fd = (plus.to_vector()[i] - minus.to_vector()[i]) / (2.0 * leak_step);
+            let stm = extra_stm.sc_wrt_extra[(i, 0)];
+            assert!(
+                (fd - stm).abs() < 1e-4 * fd.abs().max(1.0),
+                "d x[{i}] / d leak: {stm} != {fd}"
+            );
+        }
+
+        let decay_step = 1e-3;
+        let plus = propagate(sc.with_extra(1, 1.0 + decay_step));
+        let minus = propagate(sc.with_extra(1, 1.0 - decay_step));
+        let fd = (plus.extra.values[1] - minus.extra.values[1]) / (2.0 * decay_step);
+        assert!((fd - extra_stm.extra_wrt_extra[(1, 1)]).abs() < 1e-8);
+        assert!((fd - (-0.5_f64).exp()).abs() < 0.05);
+
+        let pos_step = 1e-3;
+        let mut plus = sc;
+        plus.orbit.radius_km.x += pos_step;
+        let mut minus = sc;
+        minus.orbit.radius_km.x -= pos_step;
+        let fd =
+            (propagate(plus).extra.values[1] - propagate(minus).extra.values[1]) / (2.0 * pos_step);
+        assert!(
+            (fd - extra_stm.extra_wrt_sc[(1, 0)]).abs() < 1e-4 * fd.abs(),
+            "d decay / d x: {} != {fd}",
+            extra_stm.extra_wrt_sc[(1, 0)]
+        );
+
+        // The constant leak only maps onto itself
+        assert_eq!(extra_stm.extra_wrt_extra[(0, 0)], 1.0);
+        assert_eq!(extra_stm.extra_wrt_sc.row(0).norm(), 0.0);
+    }
+}
@@ -18,7 +18,7 @@ use anise::prelude::Almanac;
 */
 use hifitime::Epoch;
 
-use super::{GuidanceError, GuidanceLaw, Maneuver};
+use super::{first_epoch_between, GuidanceError, GuidanceLaw, Maneuver};
 use crate::cosmic::{GuidanceMode, Spacecraft};
 use crate::linalg::Vector3;
 use crate::State;
@@ -98,8 +98,8 @@ impl GuidanceLaw for FiniteBurns {
     fn next(&self, sc: &mut Spacecraft, _almanac: Arc<Almanac>) {
         // Grab the last maneuver
         if let Some(last_mnvr) = self.mnvrs.last() {
-            // If the last maneuver ends before the current epoch, switch back into coast
-            if last_mnvr.end < sc.epoch() {
+            // If the last maneuver has ended by the current epoch, switch back into coast
+            if last_mnvr.end <= sc.epoch() {
                 sc.mut_mode(GuidanceMode::Coast)
             } else {
                 // Get ready for the maneuver
@@ -110,4 +110,12 @@ impl GuidanceLaw for FiniteBurns {
             sc.mut_mode(GuidanceMode::Coast)
         }
     }
+
+    fn next_discontinuity(&self, osc: &Spacecraft, end: Epoch) -> Option<Epoch> {
+        first_epoch_between(
+            self.mnvrs.iter().flat_map(|mnvr| [mnvr.start, mnvr.end]),
+            osc.epoch(),
+            end,
+        )
+    }
 }
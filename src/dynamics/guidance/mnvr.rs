@@ -17,7 +17,8 @@
 */
 
 use super::{
-    ra_dec_from_unit_vector, GuidanceError, GuidanceLaw, GuidancePhysicsSnafu, LocalFrame,
+    first_epoch_between, ra_dec_from_unit_vector, GuidanceError, GuidanceLaw, GuidancePhysicsSnafu,
+    LocalFrame,
 };
 use crate::cosmic::{GuidanceMode, Spacecraft};
 use crate::dynamics::guidance::unit_vector_from_ra_dec;
@@ -309,6 +310,10 @@ impl GuidanceLaw for Maneuver {
         };
         sc.mut_mode(next_mode);
     }
+
+    fn next_discontinuity(&self, osc: &Spacecraft, end: Epoch) -> Option<Epoch> {
+        first_epoch_between([self.start, self.end].into_iter(), osc.epoch(), end)
+    }
 }
 
 #[cfg(test)]
@@ -16,13 +16,14 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use crate::cosmic::{GuidanceMode, Orbit, Spacecraft, STD_GRAVITY};
+use crate::cosmic::{GuidanceMode, Orbit, Spacecraft};
 use crate::errors::{NyxError, StateError};
 use crate::linalg::Vector3;
 use anise::astro::PhysicsResult;
 use anise::errors::PhysicsError;
 use anise::math::rotation::DCM;
 use anise::prelude::Almanac;
+use hifitime::Epoch;
 use serde::{Deserialize, Serialize};
 
 mod finiteburns;
@@ -33,28 +34,17 @@ pub use mnvr::{Maneuver, MnvrRepr};
 
 mod ruggiero;
 pub use ruggiero::{Objective, Ruggiero, StateParameter};
+
+mod thrust_history;
 use snafu::Snafu;
+pub use thrust_history::{ThrustHistory, ThrustInterpolation, ThrustSample, ThrustSegment};
+
+mod thruster;
+pub use thruster::{Thruster, THRUSTER_CATALOG};
 
 use std::fmt;
 use std::sync::Arc;
 
-/// Defines a thruster with a maximum isp and a maximum thrust.
-#[allow(non_snake_case)]
-#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
-pub struct Thruster {
-    /// The thrust is to be provided in Newtons
-    pub thrust_N: f64,
-    /// The Isp is to be provided in seconds
-    pub isp_s: f64,
-}
-
-impl Thruster {
-    /// Returns the exhaust velocity v_e in meters per second
-    pub fn exhaust_velocity_m_s(&self) -> f64 {
-        self.isp_s * STD_GRAVITY
-    }
-}
-
 /// The `GuidanceLaw` trait handles guidance laws, optimizations, and other such methods for
 /// controlling the overall thrust direction when tied to a `BaseSpacecraft`. For delta V control,
 /// tie the DeltaVctrl to a MissionArc.
@@ -66,6 +56,12 @@ pub trait GuidanceLaw: fmt::Display + Send + Sync {
     /// For example, 0 means coasting, i.e. no thrusting, and 1 means maximum thrusting.
     fn throttle(&self, osc_state: &Spacecraft) -> Result<f64, GuidanceError>;
 
+    /// Returns the propellant mass flow rate in kg/s (as a positive number) if this guidance law prescribes it,
+    /// otherwise the mass flow rate is computed from the throttle and the Isp of the thruster.
+    fn mass_flow_kg_s(&self, _osc_state: &Spacecraft) -> Result<Option<f64>, GuidanceError> {
+        Ok(None)
+    }
+
     /// Updates the state of the BaseSpacecraft for the next maneuver, e.g. prepares the controller for the next maneuver
     fn next(&self, next_state: &mut Spacecraft, almanac: Arc<Almanac>);
 
@@ -73,6 +69,29 @@ pub trait GuidanceLaw: fmt::Display + Send + Sync {
     fn achieved(&self, _osc_state: &Spacecraft) -> Result<bool, GuidanceError> {
         Err(GuidanceError::NoGuidanceObjectiveDefined)
     }
+
+    /// Returns the first epoch strictly between the epoch of the state and `end` at which the thrust is switched on or off,
+    /// if this guidance law is scheduled in time.
+    fn next_discontinuity(&self, _osc_state: &Spacecraft, _end: Epoch) -> Option<Epoch> {
+        None
+    }
+}
+
+/// Returns the epoch closest to `start` among those strictly between `start` and `end`, in either direction.
+pub(crate) fn first_epoch_between(
+    epochs: impl Iterator<Item = Epoch>,
+    start: Epoch,
+    end: Epoch,
+) -> Option<Epoch> {
+    epochs
+        .filter(|epoch| {
+            if end >= start {
+                *epoch > start && *epoch < end
+            } else {
+                *epoch < start && *epoch > end
+            }
+        })
+        .min_by_key(|epoch| (*epoch - start).abs())
 }
 
 /// Converts the alpha (in-plane) and beta (out-of-plane) angles in the RCN frame to the unit vector in the RCN frame
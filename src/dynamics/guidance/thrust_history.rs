@@ -0,0 +1,267 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{
+    first_epoch_between, GuidanceError, GuidanceLaw, GuidancePhysicsSnafu, LocalFrame, Thruster,
+};
+use crate::cosmic::{GuidanceMode, Spacecraft, STD_GRAVITY};
+use crate::linalg::Vector3;
+use crate::time::{Duration, Epoch};
+use crate::State;
+use anise::prelude::Almanac;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::fmt;
+use std::sync::Arc;
+
+/// Interpolation of the thrust history between two samples.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ThrustInterpolation {
+    /// The value of a sample is held until the next sample
+    #[default]
+    None,
+    /// The value is linearly interpolated between two samples
+    Linear,
+}
+
+/// A sample of a thrust history.
+#[allow(non_snake_case)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ThrustSample {
+    /// Time of this sample since the start of its segment
+    pub offset: Duration,
+    /// Thrust vector in Newtons, in the frame of its segment
+    pub thrust_N: Vector3<f64>,
+    /// Propellant mass flow rate in kg/s, if tabulated
+    pub mass_flow_kg_s: Option<f64>,
+}
+
+/// A continuous segment of a thrust history, e.g. one finite burn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThrustSegment {
+    pub name: String,
+    /// Epoch of the first sample of this segment
+    pub start: Epoch,
+    /// Frame of the thrust vectors
+    pub frame: LocalFrame,
+    /// Interpolation of the thrust vector
+    pub interpolation: ThrustInterpolation,
+    /// Interpolation of the mass flow rate
+    pub mass_flow_interpolation: ThrustInterpolation,
+    /// Samples of this segment, in chronological order
+    pub samples: Vec<ThrustSample>,
+}
+
+impl ThrustSegment {
+    /// Returns the epoch of the last sample of this segment
+    pub fn end(&self) -> Epoch {
+        self.start + self.samples.last().map_or(Duration::ZERO, |s| s.offset)
+    }
+
+    /// Returns the thrust vector (in the frame of this segment) and the mass flow rate at this epoch, if within this segment.
+    /// The last sample marks the end of the segment, so there is no thrust from that epoch onward.
+    pub fn thrust_at(&self, epoch: Epoch) -> Option<(Vector3<f64>, Option<f64>)> {
+        self.sample_at(epoch, false)
+    }
+
+    /// Returns the thrust and mass flow rate at this epoch. If `hold` is set, the thrust from the end of the segment onward is
+    /// its limit from the left, which is needed by the integrator stages evaluated at or beyond the end of the last thrusting step.
+    #[allow(non_snake_case)]
+    fn sample_at(&self, epoch: Epoch, hold: bool) -> Option<(Vector3<f64>, Option<f64>)> {
+        let end = self.end();
+        if epoch < self.start || (epoch >= end && !hold) {
+            return None;
+        }
+        let offset = (epoch - self.start).min(end - self.start);
+        let next = if epoch >= end {
+            self.samples.len() - 1
+        } else {
+            self.samples.partition_point(|s| s.offset <= offset)
+        };
+        if next == 0 {
+            return None;
+        }
+        let prev = &self.samples[next - 1];
+        let after = self.samples.get(next);
+
+        // Fraction of the way to the next sample, for linear interpolation
+        let lerp = |interpolation: ThrustInterpolation| match (interpolation, after) {
+            (ThrustInterpolation::Linear, Some(after)) => Some((
+                after,
+                (offset - prev.offset).to_seconds() / (after.offset - prev.offset).to_seconds(),
+            )),
+            _ => None,
+        };
+
+        let thrust_N = match lerp(self.interpolation) {
+            Some((after, frac)) => prev.thrust_N + (after.thrust_N - prev.thrust_N) * frac,
+            None => prev.thrust_N,
+        };
+
+        let mass_flow_kg_s = match lerp(self.mass_flow_interpolation) {
+            Some((after, frac)) => prev
+                .mass_flow_kg_s
+                .zip(after.mass_flow_kg_s)
+                .map(|(prev, after)| prev + (after - prev) * frac),
+            None => prev.mass_flow_kg_s,
+        };
+
+        Some((thrust_N, mass_flow_kg_s))
+    }
+}
+
+/// A guidance law replaying a tabulated thrust history, e.g. a burn designed in another tool and read from a GMAT ThrustHistoryFile.
+///
+/// The throttle is the ratio of the tabulated thrust to the thrust of the spacecraft's thruster, which must therefore be at least
+/// the maximum thrust of the history, cf. [ThrustHistory::equivalent_thruster]. If the history tabulates the mass flow rate, it is
+/// used instead of the Isp of the thruster. Outside of the segments, the spacecraft coasts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThrustHistory {
+    /// Segments of the thrust history, in chronological order
+    pub segments: Vec<ThrustSegment>,
+}
+
+impl ThrustHistory {
+    /// Builds a thrust history from its segments, which are sorted chronologically.
+    pub fn new(mut segments: Vec<ThrustSegment>) -> Self {
+        segments.sort_by_key(|segment| segment.start);
+        Self { segments }
+    }
+
+    /// Returns the thrust vector (in the frame of its segment), the mass flow rate, and the latest segment started by this epoch.
+    /// Past the end of that segment, its final thrust is held: the guidance mode turns the thrust off from the end onward,
+    /// but some integrator stages are evaluated beyond the end of their step.
+    #[allow(clippy::type_complexity)]
+    fn thrust_at(&self, epoch: Epoch) -> Option<(Vector3<f64>, Option<f64>, &ThrustSegment)> {
+        let segment = self
+            .segments
+            .iter()
+            .rev()
+            .find(|segment| segment.start <= epoch)?;
+        segment
+            .sample_at(epoch, true)
+            .map(|(thrust, mass_flow)| (thrust, mass_flow, segment))
+    }
+
+    /// Returns a thruster which can replay this history: its thrust is the maximum thrust of the history, and its Isp is the
+    /// average Isp of the samples which tabulate a mass flow rate (or zero if none does).
+    #[allow(non_snake_case)]
+    pub fn equivalent_thruster(&self) -> Thruster {
+        let samples = self.segments.iter().flat_map(|segment| &segment.samples);
+        let thrust_N = samples
+            .clone()
+            .map(|sample| sample.thrust_N.norm())
+            .fold(0.0, f64::max);
+
+        let isps = samples
+            .filter_map(|sample| {
+                let mass_flow = sample.mass_flow_kg_s?.abs();
+                let thrust = sample.thrust_N.norm();
+                (mass_flow > 0.0 && thrust > 0.0).then(|| thrust / (mass_flow * STD_GRAVITY))
+            })
+            .collect::<Vec<_>>();
+        let isp_s = if isps.is_empty() {
+            0.0
+        } else {
+            isps.iter().sum::<f64>() / isps.len() as f64
+        };
+
+        Thruster::new(thrust_N, isp_s)
+    }
+}
+
+impl fmt::Display for ThrustHistory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ThrustHistory with {} segments", self.segments.len())?;
+        if let (Some(first), Some(last)) = (self.segments.first(), self.segments.last()) {
+            write!(f, " from {} to {}", first.start, last.end())?;
+        }
+        Ok(())
+    }
+}
+
+impl GuidanceLaw for ThrustHistory {
+    fn direction(&self, osc: &Spacecraft) -> Result<Vector3<f64>, GuidanceError> {
+        if osc.mode() != GuidanceMode::Thrust {
+            return Ok(Vector3::zeros());
+        }
+        match self.thrust_at(osc.epoch()) {
+            Some((thrust, _, segment)) if thrust.norm() > 0.0 => {
+                let dcm =
+                    segment
+                        .frame
+                        .dcm_to_inertial(osc.orbit)
+                        .context(GuidancePhysicsSnafu {
+                            action: "computing the thrust history frame",
+                        })?;
+                Ok(dcm * (thrust / thrust.norm()))
+            }
+            _ => Ok(Vector3::zeros()),
+        }
+    }
+
+    fn throttle(&self, osc: &Spacecraft) -> Result<f64, GuidanceError> {
+        if osc.mode() != GuidanceMode::Thrust {
+            return Ok(0.0);
+        }
+        match self.thrust_at(osc.epoch()) {
+            Some((thrust, _, _)) => {
+                let thruster = osc.thruster.ok_or(GuidanceError::NoThrustersDefined)?;
+                Ok(thrust.norm() / thruster.thrust_N)
+            }
+            None => Ok(0.0),
+        }
+    }
+
+    fn mass_flow_kg_s(&self, osc: &Spacecraft) -> Result<Option<f64>, GuidanceError> {
+        if osc.mode() != GuidanceMode::Thrust {
+            return Ok(None);
+        }
+        Ok(self
+            .thrust_at(osc.epoch())
+            .and_then(|(_, mass_flow, _)| mass_flow)
+            .map(f64::abs))
+    }
+
+    fn next(&self, sc: &mut Spacecraft, _almanac: Arc<Almanac>) {
+        // Only thrust during the steps within a segment, which start and end at the samples thanks to the discontinuities.
+        if self
+            .segments
+            .iter()
+            .any(|segment| segment.thrust_at(sc.epoch()).is_some())
+        {
+            sc.mut_mode(GuidanceMode::Thrust)
+        } else {
+            sc.mut_mode(GuidanceMode::Coast)
+        }
+    }
+
+    fn next_discontinuity(&self, osc: &Spacecraft, end: Epoch) -> Option<Epoch> {
+        // Each sample is a discontinuity of the thrust (or of its derivative when interpolated linearly)
+        first_epoch_between(
+            self.segments.iter().flat_map(|segment| {
+                segment
+                    .samples
+                    .iter()
+                    .map(move |sample| segment.start + sample.offset)
+            }),
+            osc.epoch(),
+            end,
+        )
+    }
+}
@@ -0,0 +1,180 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// The builder generated for the unit-suffixed fields is not snake case either
+#![allow(non_snake_case)]
+
+use crate::cosmic::STD_GRAVITY;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use typed_builder::TypedBuilder;
+
+/// Defines a thruster with a maximum isp and a maximum thrust.
+///
+/// Pulsed or thermally limited thrusters may only fire during a fraction of a thrust arc: the dynamics then apply the mean
+/// thrust, cf. [Thruster::mean_thrust_N]. Impulses smaller than the minimum impulse bit cannot be delivered.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, TypedBuilder)]
+pub struct Thruster {
+    /// The thrust is to be provided in Newtons
+    pub thrust_N: f64,
+    /// The Isp is to be provided in seconds
+    pub isp_s: f64,
+    /// Fraction of a thrust arc during which the thruster fires, between 0 and 1 (defaults to 1, i.e. continuous firing)
+    #[builder(default = 1.0)]
+    #[serde(default = "default_duty_cycle", skip_serializing_if = "is_continuous")]
+    pub duty_cycle: f64,
+    /// Smallest impulse the thruster can deliver, in Newton seconds (defaults to zero)
+    #[builder(default)]
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub min_impulse_bit_N_s: f64,
+}
+
+fn default_duty_cycle() -> f64 {
+    1.0
+}
+
+fn is_continuous(duty_cycle: &f64) -> bool {
+    *duty_cycle == 1.0
+}
+
+fn is_zero(value: &f64) -> bool {
+    *value == 0.0
+}
+
+/// Nominal performance of common thrusters, for preliminary design. Refer to the datasheet of each thruster for its
+/// operating envelope: the thrust and Isp vary with the feed pressure (chemical) or the input power (electric).
+pub const THRUSTER_CATALOG: [(&str, Thruster); 10] = [
+    // Chemical monopropellant (hydrazine)
+    ("MR-103", Thruster::with_mib(1.0, 224.0, 0.01)),
+    ("MR-106", Thruster::with_mib(22.0, 235.0, 0.2)),
+    // Chemical bipropellant
+    ("R-4D", Thruster::with_mib(490.0, 312.0, 5.0)),
+    ("LEROS-1b", Thruster::with_mib(635.0, 317.0, 10.0)),
+    ("S400", Thruster::with_mib(425.0, 318.0, 10.0)),
+    // Cold gas (nitrogen)
+    ("cold-gas-N2", Thruster::with_mib(0.1, 65.0, 1e-4)),
+    // Hall effect
+    ("SPT-100", Thruster::new(0.083, 1600.0)),
+    ("PPS-1350", Thruster::new(0.088, 1650.0)),
+    // Gridded ion
+    ("NSTAR", Thruster::new(0.092, 3100.0)),
+    ("NEXT", Thruster::new(0.236, 4190.0)),
+];
+
+impl Thruster {
+    /// Initializes a thruster which fires continuously and without minimum impulse bit
+    #[allow(non_snake_case)]
+    pub const fn new(thrust_N: f64, isp_s: f64) -> Self {
+        Self::with_mib(thrust_N, isp_s, 0.0)
+    }
+
+    /// Initializes a thruster which fires continuously with this minimum impulse bit, in Newton seconds
+    #[allow(non_snake_case)]
+    const fn with_mib(thrust_N: f64, isp_s: f64, min_impulse_bit_N_s: f64) -> Self {
+        Self {
+            thrust_N,
+            isp_s,
+            duty_cycle: 1.0,
+            min_impulse_bit_N_s,
+        }
+    }
+
+    /// Returns the thruster of this name from the [THRUSTER_CATALOG], ignoring case
+    pub fn from_catalog(name: &str) -> Option<Self> {
+        THRUSTER_CATALOG
+            .iter()
+            .find(|(model, _)| model.eq_ignore_ascii_case(name))
+            .map(|(_, thruster)| *thruster)
+    }
+
+    /// Returns the exhaust velocity v_e in meters per second
+    pub fn exhaust_velocity_m_s(&self) -> f64 {
+        self.isp_s * STD_GRAVITY
+    }
+
+    /// Returns the thrust averaged over a thrust arc given the duty cycle, in Newtons
+    #[allow(non_snake_case)]
+    pub fn mean_thrust_N(&self) -> f64 {
+        self.thrust_N * self.duty_cycle
+    }
+
+    /// Returns the mass flow rate at full throttle averaged over a thrust arc given the duty cycle, in kg/s
+    pub fn mean_mass_flow_kg_s(&self) -> f64 {
+        self.mean_thrust_N() / self.exhaust_velocity_m_s()
+    }
+
+    /// Returns whether the thruster can deliver this impulse, in Newton seconds, i.e. if it is not smaller than the minimum
+    /// impulse bit
+    #[allow(non_snake_case)]
+    pub fn can_deliver(&self, impulse_N_s: f64) -> bool {
+        impulse_N_s >= self.min_impulse_bit_N_s
+    }
+}
+
+impl fmt::Display for Thruster {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} N @ {} s", self.thrust_N, self.isp_s)?;
+        if self.duty_cycle < 1.0 {
+            write!(f, " ({:.1}% duty cycle)", self.duty_cycle * 100.0)?;
+        }
+        if self.min_impulse_bit_N_s > 0.0 {
+            write!(f, " (MIB {} N s)", self.min_impulse_bit_N_s)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod ut_thruster {
+    use super::*;
+
+    #[test]
+    fn catalog_and_builder() {
+        let nstar = Thruster::from_catalog("nstar").unwrap();
+        assert_eq!(nstar.thrust_N, 0.092);
+        assert_eq!(nstar.duty_cycle, 1.0);
+        assert!(Thruster::from_catalog("warp drive").is_none());
+        for (name, thruster) in THRUSTER_CATALOG {
+            assert!(thruster.thrust_N > 0.0 && thruster.isp_s > 0.0, "{name}");
+        }
+
+        let pulsed = Thruster::builder()
+            .thrust_N(1.0)
+            .isp_s(220.0)
+            .duty_cycle(0.25)
+            .min_impulse_bit_N_s(0.05)
+            .build();
+        assert_eq!(pulsed.mean_thrust_N(), 0.25);
+        assert!((pulsed.mean_mass_flow_kg_s() - 0.25 / (220.0 * STD_GRAVITY)).abs() < 1e-15);
+        assert!(!pulsed.can_deliver(0.01));
+        assert!(pulsed.can_deliver(0.05));
+        assert_eq!(
+            format!("{pulsed}"),
+            "1 N @ 220 s (25.0% duty cycle) (MIB 0.05 N s)"
+        );
+
+        // Continuous thrusters without minimum impulse bit serialize as before
+        let yaml = serde_yml::to_string(&Thruster::new(10.0, 300.0)).unwrap();
+        assert_eq!(yaml, "thrust_N: 10.0\nisp_s: 300.0\n");
+        let loaded: Thruster = serde_yml::from_str(&yaml).unwrap();
+        assert_eq!(loaded, Thruster::new(10.0, 300.0));
+        let loaded: Thruster =
+            serde_yml::from_str(&serde_yml::to_string(&pulsed).unwrap()).unwrap();
+        assert_eq!(loaded, pulsed);
+    }
+}
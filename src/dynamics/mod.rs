@@ -18,11 +18,13 @@
 
 use crate::cosmic::{AstroError, Orbit};
 use crate::linalg::allocator::Allocator;
-use crate::linalg::{DefaultAllocator, DimName, Matrix3, Matrix4x3, OMatrix, OVector, Vector3};
+use crate::linalg::{Const, DefaultAllocator, DimName, Matrix3, OMatrix, OVector, Vector3};
+use crate::md::trajectory::TrajError;
 use crate::State;
 use anise::almanac::planetary::PlanetaryDataError;
 use anise::almanac::Almanac;
 use anise::errors::AlmanacError;
+use hifitime::Epoch;
 use hyperdual::Owned;
 use snafu::Snafu;
 
@@ -43,12 +45,30 @@ pub use self::orbital::*;
 pub mod spacecraft;
 pub use self::spacecraft::*;
 
+/// Dynamics along an external ephemeris, where the forces are only used for the STM.
+pub mod reference;
+pub use self::reference::*;
+
+/// Selection and cross checking of the method used to compute the STM.
+pub mod stm;
+pub use self::stm::*;
+
 /// Defines a few examples of guidance laws.
 pub mod guidance;
 
 /// Defines some velocity change controllers.
 pub mod deltavctrl;
 
+/// User defined extra states of a spacecraft, e.g. biases or small accelerations, and their dynamics.
+pub mod extra;
+pub use self::extra::{
+    ExtraForce, ExtraState, ExtraStateModel, ExtraStates, ExtraStm, MAX_EXTRA_STATES,
+};
+
+/// The power module limits the electric propulsion thrust to the power generated by the solar arrays and stored in the battery.
+pub mod power;
+pub use self::power::{BatteryState, PowerBalance, PowerModel};
+
 /// Defines solar radiation pressure models
 pub mod solarpressure;
 pub use self::solarpressure::*;
@@ -57,6 +77,10 @@ pub use self::solarpressure::*;
 pub mod drag;
 pub use self::drag::*;
 
+/// Defines empirical accelerations, constant in the RIC frame over arcs.
+pub mod empirical;
+pub use self::empirical::*;
+
 /// Define the spherical harmonic models.
 /// This module allows loading gravity models from [PDS](http://pds-geosciences.wustl.edu/), [EGM2008](http://earth-info.nga.mil/GandG/wgs84/gravitymod/egm2008/) and GMAT's own COF files.
 pub mod sph_harmonics;
@@ -128,6 +152,19 @@ where
     ) -> Result<Self::StateType, DynamicsError> {
         Ok(next_state)
     }
+
+    /// Returns the first epoch strictly between the epoch of the state and `end` (in either direction) at which these dynamics are
+    /// known to be discontinuous, e.g. the start or end of a maneuver. The propagator ends its step exactly at that epoch instead
+    /// of integrating across the discontinuity.
+    fn next_discontinuity(&self, _state: &Self::StateType, _end: Epoch) -> Option<Epoch> {
+        None
+    }
+
+    /// Returns whether these dynamics switched between two consecutive states returned by `finally`, e.g. a change of guidance mode.
+    /// The propagator then locates the switch by bisection and ends its step there.
+    fn switched(&self, _prev_state: &Self::StateType, _next_state: &Self::StateType) -> bool {
+        false
+    }
 }
 
 /// The `ForceModel` trait handles immutable dynamics which return a force. Those will be divided by the mass of the spacecraft to compute the acceleration (F = ma).
@@ -142,14 +179,19 @@ pub trait ForceModel: Send + Sync + fmt::Display {
 
     /// Force models must implement their partials, although those will only be called if the propagation requires the
     /// computation of the STM. The `osc_ctx` is the osculating context, i.e. it changes for each sub-step of the integrator.
-    /// The last row corresponds to the partials of the parameter of this force model wrt the position, i.e. this only applies to conservative forces.
+    /// Refer to [ForcePartials] for the layout of the partials.
     fn dual_eom(
         &self,
         osc_ctx: &Spacecraft,
         almanac: Arc<Almanac>,
-    ) -> Result<(Vector3<f64>, Matrix4x3<f64>), DynamicsError>;
+    ) -> Result<(Vector3<f64>, ForcePartials), DynamicsError>;
 }
 
+/// Partials of a force model: each column is a component of the force, and the rows are the partials of that component
+/// wrt the position (rows 0 to 2), the velocity (rows 3 to 5), and the parameter of the force model (row 6, e.g. Cr or Cd),
+/// i.e. `partials[(j, i)]` is the partial of the i-th component of the force wrt the j-th variable.
+pub type ForcePartials = OMatrix<f64, Const<7>, Const<3>>;
+
 /// The `AccelModel` trait handles immutable dynamics which return an acceleration. Those can be added directly to Orbital Dynamics for example.
 ///
 /// Examples include spherical harmonics, i.e. accelerations which do not need to save the current state, only act on it.
@@ -190,4 +232,24 @@ pub enum DynamicsError {
         action: &'static str,
         source: PlanetaryDataError,
     },
+    #[snafu(display("{count} extra states exceed the maximum of {max}"))]
+    TooManyExtraStates { count: usize, max: usize },
+    #[snafu(display(
+        "{count} extra states are propagated but the spacecraft only carries {len}, cf. Spacecraft::with_extra"
+    ))]
+    MissingExtraStates { count: usize, len: usize },
+    #[snafu(display("dynamical model issue due to the reference trajectory: {source}"))]
+    DynamicsTrajectory { source: TrajError },
+    #[snafu(display(
+        "{primary} partial ({row}, {col}) is {primary_val:e} but {secondary} partial is {secondary_val:e} at {epoch}"
+    ))]
+    PartialsMismatch {
+        primary: StmMethod,
+        secondary: StmMethod,
+        row: usize,
+        col: usize,
+        primary_val: f64,
+        secondary_val: f64,
+        epoch: Epoch,
+    },
 }
@@ -21,6 +21,7 @@ use super::{
 };
 use crate::cosmic::{AstroPhysicsSnafu, Frame, Orbit};
 use crate::linalg::{Const, Matrix3, Matrix6, OVector, Vector3, Vector6};
+use crate::profiling::{self, Subsystem};
 
 use anise::almanac::Almanac;
 use anise::astro::Aberration;
@@ -95,6 +96,7 @@ impl OrbitalDynamics {
 
         // Apply the acceleration models
         for model in &self.accel_models {
+            let _scope = profiling::scope(Subsystem::ForceModel, || model.to_string());
             let model_acc = model.eom(osc, almanac.clone())?;
             for i in 0..3 {
                 d_x[i + 3] += model_acc[i];
@@ -152,6 +154,7 @@ impl OrbitalDynamics {
 
         // Apply the acceleration models
         for model in &self.accel_models {
+            let _scope = profiling::scope(Subsystem::ForceModel, || format!("{model} (STM)"));
             let (model_acc, model_grad) = model.dual_eom(osc, almanac.clone())?;
             for i in 0..3 {
                 dx[i + 3] += model_acc[i];
@@ -220,11 +223,12 @@ impl AccelModel for PointMasses {
                 })?;
 
             // Orbit of j-th body as seen from primary body
-            let st_ij = almanac
-                .transform(third_body_frame, osc.frame, osc.epoch, self.correction)
-                .context(DynamicsAlmanacSnafu {
-                    action: "computing third body gravitational pull",
-                })?;
+            let st_ij = profiling::almanac_query("point masses: transform", || {
+                almanac.transform(third_body_frame, osc.frame, osc.epoch, self.correction)
+            })
+            .context(DynamicsAlmanacSnafu {
+                action: "computing third body gravitational pull",
+            })?;
 
             let r_ij = st_ij.radius_km;
             let r_ij3 = st_ij.rmag_km().powi(3);
@@ -271,11 +275,12 @@ impl AccelModel for PointMasses {
             );
 
             // Orbit of j-th body as seen from primary body
-            let st_ij = almanac
-                .transform(third_body_frame, osc.frame, osc.epoch, self.correction)
-                .context(DynamicsAlmanacSnafu {
-                    action: "computing third body gravitational pull",
-                })?;
+            let st_ij = profiling::almanac_query("point masses: transform", || {
+                almanac.transform(third_body_frame, osc.frame, osc.epoch, self.correction)
+            })
+            .context(DynamicsAlmanacSnafu {
+                action: "computing third body gravitational pull",
+            })?;
 
             let r_ij: Vector3<OHyperdual<f64, Const<7>>> = hyperspace_from_vector(&st_ij.radius_km);
             let r_ij3 = norm(&r_ij).powi(3);
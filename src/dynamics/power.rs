@@ -0,0 +1,349 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{DynamicsAlmanacSnafu, DynamicsError, DynamicsPlanetarySnafu, SOLAR_FLUX_W_m2};
+use crate::cosmic::eclipse::EclipseLocator;
+use crate::cosmic::{Frame, Spacecraft, AU};
+use crate::md::trajectory::Traj;
+use crate::time::Epoch;
+use crate::State;
+use anise::almanac::Almanac;
+use anise::constants::frames::SUN_J2000;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::fmt;
+use std::sync::Arc;
+
+/// Energy stored in the battery of a spacecraft, updated after each propagation step by the [PowerModel].
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BatteryState {
+    /// Energy stored in the battery, in Wh
+    pub charge_wh: f64,
+    /// Epoch of the last update of the charge
+    pub epoch: Epoch,
+}
+
+/// Power balance of a spacecraft at a given epoch, cf. [PowerModel::balance].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PowerBalance {
+    pub epoch: Epoch,
+    /// Power generated by the solar arrays, in W
+    pub array_w: f64,
+    /// Power consumed by the bus, in W
+    pub bus_w: f64,
+    /// Power consumed by the electric propulsion, in W
+    pub thruster_w: f64,
+    /// Power charged into the battery if positive, or discharged from it if negative, in W
+    pub battery_w: f64,
+    /// Energy stored in the battery, in Wh
+    pub charge_wh: f64,
+    /// Depth of discharge of the battery, between zero (full) and one (empty)
+    pub depth_of_discharge: f64,
+    /// Ratio of the thrust allowed by the available power to the thrust commanded by the guidance law
+    pub thrust_ratio: f64,
+}
+
+/// A simple power subsystem model coupled to electric propulsion.
+///
+/// The solar arrays generate `array_area_m2 * array_efficiency * phi * illumination / r_sun_au^2`, where the illumination
+/// accounts for the eclipses by the shadow bodies. This power first feeds the bus, and the rest is available to the thruster,
+/// which consumes `thruster_power_w` at full throttle. While the depth of discharge of the battery is below its maximum,
+/// the battery may supplement the arrays by up to `battery_power_w`. The throttle of the guidance law is limited to what the
+/// available power allows, and the battery charge is updated after each propagation step with the resulting energy balance.
+#[derive(Clone)]
+pub struct PowerModel {
+    /// Area of the solar arrays, in m^2
+    pub array_area_m2: f64,
+    /// Conversion efficiency of the solar arrays, between zero and one
+    pub array_efficiency: f64,
+    /// Solar flux at 1 AU, in W/m^2
+    pub phi: f64,
+    pub e_loc: EclipseLocator,
+    /// Constant power consumed by the bus, in W
+    pub bus_power_w: f64,
+    /// Power consumed by the thruster at full throttle, in W
+    pub thruster_power_w: f64,
+    /// Capacity of the battery, in Wh (zero if there is no battery)
+    pub battery_capacity_wh: f64,
+    /// Maximum discharge power of the battery, in W
+    pub battery_power_w: f64,
+    /// Maximum depth of discharge of the battery, between zero and one
+    pub max_depth_of_discharge: f64,
+}
+
+impl PowerModel {
+    /// Initializes a power model without bus load nor battery, accounting for the eclipses by the provided shadow bodies.
+    pub fn new(
+        array_area_m2: f64,
+        array_efficiency: f64,
+        thruster_power_w: f64,
+        shadow_bodies: Vec<Frame>,
+        almanac: Arc<Almanac>,
+    ) -> Result<Self, DynamicsError> {
+        let e_loc = EclipseLocator {
+            light_source: almanac
+                .frame_from_uid(SUN_J2000)
+                .context(DynamicsPlanetarySnafu {
+                    action: "planetary data of the Sun not loaded",
+                })?,
+            shadow_bodies: shadow_bodies
+                .iter()
+                .map(|object| {
+                    almanac
+                        .frame_from_uid(object)
+                        .context(DynamicsPlanetarySnafu {
+                            action: "planetary data of a shadow body not loaded",
+                        })
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        };
+        Ok(Self {
+            array_area_m2,
+            array_efficiency,
+            phi: SOLAR_FLUX_W_m2,
+            e_loc,
+            bus_power_w: 0.0,
+            thruster_power_w,
+            battery_capacity_wh: 0.0,
+            battery_power_w: 0.0,
+            max_depth_of_discharge: 0.0,
+        })
+    }
+
+    /// Returns a copy of this model with this constant bus load, in W.
+    pub fn with_bus_power(mut self, bus_power_w: f64) -> Self {
+        self.bus_power_w = bus_power_w;
+        self
+    }
+
+    /// Returns a copy of this model with a battery of this capacity, maximum discharge power, and maximum depth of discharge.
+    pub fn with_battery(
+        mut self,
+        capacity_wh: f64,
+        max_power_w: f64,
+        max_depth_of_discharge: f64,
+    ) -> Self {
+        self.battery_capacity_wh = capacity_wh;
+        self.battery_power_w = max_power_w;
+        self.max_depth_of_discharge = max_depth_of_discharge;
+        self
+    }
+
+    /// Returns the power generated by the solar arrays, in W, given the illumination factor and the distance to the Sun in AU.
+    pub fn array_power_w(&self, illumination: f64, sun_distance_au: f64) -> f64 {
+        self.array_area_m2 * self.array_efficiency * self.phi * illumination
+            / sun_distance_au.powi(2)
+    }
+
+    /// Returns the depth of discharge of the battery at this charge, or zero if there is no battery.
+    pub fn depth_of_discharge(&self, charge_wh: f64) -> f64 {
+        if self.battery_capacity_wh > 0.0 {
+            1.0 - charge_wh / self.battery_capacity_wh
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns the ratio of the full thrust which the available power allows, given the array power and the battery charge.
+    pub fn throttle_limit(&self, array_w: f64, charge_wh: f64) -> f64 {
+        if self.thruster_power_w <= 0.0 {
+            return 1.0;
+        }
+        let battery_w = if self.depth_of_discharge(charge_wh) < self.max_depth_of_discharge {
+            self.battery_power_w
+        } else {
+            0.0
+        };
+        ((array_w - self.bus_power_w + battery_w) / self.thruster_power_w).clamp(0.0, 1.0)
+    }
+
+    /// Returns the power balance given the array power, the battery charge, and the throttle commanded by the guidance law.
+    pub fn balance_at(
+        &self,
+        epoch: Epoch,
+        array_w: f64,
+        charge_wh: f64,
+        throttle: f64,
+    ) -> PowerBalance {
+        let limit = self.throttle_limit(array_w, charge_wh);
+        let thruster_w = throttle.min(limit) * self.thruster_power_w;
+        let thrust_ratio = if throttle > 0.0 {
+            throttle.min(limit) / throttle
+        } else {
+            1.0
+        };
+
+        PowerBalance {
+            epoch,
+            array_w,
+            bus_w: self.bus_power_w,
+            thruster_w,
+            battery_w: array_w - self.bus_power_w - thruster_w,
+            charge_wh,
+            depth_of_discharge: self.depth_of_discharge(charge_wh),
+            thrust_ratio,
+        }
+    }
+
+    /// Returns the illumination factor of the solar arrays and the distance to the Sun in AU.
+    pub fn illumination(
+        &self,
+        sc: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<(f64, f64), DynamicsError> {
+        let r_sun = almanac
+            .transform_to(sc.orbit, self.e_loc.light_source, None)
+            .context(DynamicsAlmanacSnafu {
+                action: "transforming state to vector seen from Sun",
+            })?
+            .radius_km;
+
+        let k = self
+            .e_loc
+            .illumination(sc.orbit, almanac)
+            .context(DynamicsAlmanacSnafu {
+                action: "power model illumination",
+            })?;
+
+        Ok((k, r_sun.norm() / AU))
+    }
+
+    /// Returns the charge of the battery of this spacecraft, which is full if it was never set.
+    pub fn charge_wh(&self, sc: &Spacecraft) -> f64 {
+        sc.battery
+            .map_or(self.battery_capacity_wh, |battery| battery.charge_wh)
+    }
+
+    /// Returns the power balance of this spacecraft given the throttle commanded by the guidance law.
+    pub fn balance(
+        &self,
+        sc: &Spacecraft,
+        throttle: f64,
+        almanac: Arc<Almanac>,
+    ) -> Result<PowerBalance, DynamicsError> {
+        let (k, r_sun_au) = self.illumination(sc, almanac)?;
+        Ok(self.balance_at(
+            sc.epoch(),
+            self.array_power_w(k, r_sun_au),
+            self.charge_wh(sc),
+            throttle,
+        ))
+    }
+
+    /// Updates the battery charge of this spacecraft with the power balance at its epoch since the previous update.
+    pub fn update_battery(&self, sc: &mut Spacecraft, balance: &PowerBalance) {
+        let charge_wh = match sc.battery {
+            Some(battery) => {
+                let dt_h = (sc.epoch() - battery.epoch).to_seconds() / 3600.0;
+                (battery.charge_wh + balance.battery_w * dt_h).clamp(0.0, self.battery_capacity_wh)
+            }
+            None => self.battery_capacity_wh,
+        };
+        sc.battery = Some(BatteryState {
+            charge_wh,
+            epoch: sc.epoch(),
+        });
+    }
+
+    /// Returns the power balance at each state of this trajectory, given the throttle at each state.
+    pub fn energy_balance<F>(
+        &self,
+        traj: &Traj<Spacecraft>,
+        throttle: F,
+        almanac: Arc<Almanac>,
+    ) -> Result<Vec<PowerBalance>, DynamicsError>
+    where
+        F: Fn(&Spacecraft) -> f64,
+    {
+        traj.states
+            .iter()
+            .map(|sc| self.balance(sc, throttle(sc), almanac.clone()))
+            .collect()
+    }
+}
+
+impl fmt::Display for PowerModel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "power model with {} m^2 arrays ({:.1} %), {} W bus, {} W thruster, {} Wh battery; {}",
+            self.array_area_m2,
+            self.array_efficiency * 100.0,
+            self.bus_power_w,
+            self.thruster_power_w,
+            self.battery_capacity_wh,
+            self.e_loc
+        )
+    }
+}
+
+#[cfg(test)]
+mod ut_power {
+    use super::*;
+    use anise::constants::frames::EARTH_J2000;
+
+    #[test]
+    fn thrust_limited_by_power() {
+        let model = PowerModel {
+            array_area_m2: 10.0,
+            array_efficiency: 0.3,
+            phi: SOLAR_FLUX_W_m2,
+            e_loc: EclipseLocator {
+                light_source: SUN_J2000,
+                shadow_bodies: vec![EARTH_J2000],
+            },
+            bus_power_w: 500.0,
+            thruster_power_w: 5000.0,
+            battery_capacity_wh: 0.0,
+            battery_power_w: 0.0,
+            max_depth_of_discharge: 0.0,
+        }
+        .with_battery(1000.0, 1000.0, 0.5);
+
+        // Full sunlight at 1 AU: 4101 W, of which 3601 W feed the thruster, plus 1000 W from the battery
+        let array_w = model.array_power_w(1.0, 1.0);
+        assert!((array_w - 4101.0).abs() < 1e-9);
+        assert!((model.throttle_limit(array_w, 1000.0) - 4601.0 / 5000.0).abs() < 1e-12);
+        // Beyond the maximum depth of discharge, only the arrays power the thruster
+        assert!((model.throttle_limit(array_w, 400.0) - 3601.0 / 5000.0).abs() < 1e-12);
+        // In eclipse, the bus is powered by the battery and nothing is left for the thruster
+        assert_eq!(model.throttle_limit(0.0, 400.0), 0.0);
+        // Farther from the Sun, the arrays generate less power
+        assert!((model.array_power_w(1.0, 2.0) - array_w / 4.0).abs() < 1e-9);
+
+        let epoch = Epoch::from_gregorian_utc_at_midnight(2024, 1, 1);
+        let balance = model.balance_at(epoch, array_w, 1000.0, 1.0);
+        assert!((balance.thrust_ratio - 0.9202).abs() < 1e-12);
+        assert!((balance.battery_w + 1000.0).abs() < 1e-9);
+
+        // The battery discharges during the hour of thrust, and recharges once the thruster is off
+        let mut sc = Spacecraft::default();
+        sc.orbit.epoch = epoch;
+        model.update_battery(&mut sc, &balance);
+        assert_eq!(sc.battery.unwrap().charge_wh, 1000.0);
+        sc.orbit.epoch = epoch + crate::time::Unit::Hour * 0.5;
+        model.update_battery(&mut sc, &balance);
+        assert!((sc.battery.unwrap().charge_wh - 500.0).abs() < 1e-9);
+        let coast = model.balance_at(sc.epoch(), array_w, 500.0, 0.0);
+        assert_eq!(coast.thrust_ratio, 1.0);
+        assert!((coast.depth_of_discharge - 0.5).abs() < 1e-12);
+        sc.orbit.epoch += crate::time::Unit::Hour;
+        model.update_battery(&mut sc, &coast);
+        assert_eq!(sc.battery.unwrap().charge_wh, 1000.0);
+    }
+}
@@ -0,0 +1,192 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use anise::prelude::Almanac;
+use snafu::ResultExt;
+
+use super::{Dynamics, DynamicsAlmanacSnafu, DynamicsError, DynamicsTrajectorySnafu};
+use super::{Spacecraft, SpacecraftDynamics};
+use crate::linalg::{Const, OMatrix, OVector};
+use crate::md::trajectory::Traj;
+use crate::time::Epoch;
+use crate::State;
+
+use std::fmt;
+use std::sync::Arc;
+
+/// Dynamics whose nominal trajectory is read from an external ephemeris (e.g. an OEM or a BSP) instead of being integrated.
+///
+/// The orbit of the state is replaced by the reference at every evaluation of the equations of motion and after every step,
+/// such that the configured spacecraft dynamics are only used for the STM, the Cr, Cd, and propellant mass, and the measurement
+/// modeling. This allows orbit determination relative to an externally supplied reference orbit.
+#[derive(Clone)]
+pub struct ReferenceDynamics {
+    /// Dynamics used for the partials along the reference
+    pub dynamics: SpacecraftDynamics,
+    /// Reference trajectory, which must cover the whole propagation
+    pub reference: Arc<Traj<Spacecraft>>,
+}
+
+impl ReferenceDynamics {
+    /// Initializes the dynamics along the provided reference trajectory.
+    pub fn new(dynamics: SpacecraftDynamics, reference: Traj<Spacecraft>) -> Self {
+        Self {
+            dynamics,
+            reference: Arc::new(reference),
+        }
+    }
+
+    /// Returns the state with its orbit replaced by the reference at the epoch of the state, in the frame of the state.
+    fn on_reference(
+        &self,
+        state: Spacecraft,
+        almanac: &Almanac,
+    ) -> Result<Spacecraft, DynamicsError> {
+        let reference = self
+            .reference
+            .at(state.epoch())
+            .context(DynamicsTrajectorySnafu)?
+            .orbit;
+
+        let orbit = if reference.frame == state.orbit.frame {
+            reference
+        } else {
+            almanac
+                .transform_to(reference, state.orbit.frame, None)
+                .context(DynamicsAlmanacSnafu {
+                    action: "transforming the reference into the frame of the state",
+                })?
+        };
+
+        Ok(state.with_orbit(orbit))
+    }
+}
+
+impl fmt::Display for ReferenceDynamics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} along reference from {} to {}",
+            self.dynamics,
+            self.reference.first().epoch(),
+            self.reference.last().epoch()
+        )
+    }
+}
+
+impl Dynamics for ReferenceDynamics {
+    type HyperdualSize = Const<9>;
+    type StateType = Spacecraft;
+
+    fn finally(
+        &self,
+        next_state: Self::StateType,
+        almanac: Arc<Almanac>,
+    ) -> Result<Self::StateType, DynamicsError> {
+        let next_state = self.dynamics.finally(next_state, almanac.clone())?;
+        self.on_reference(next_state, &almanac)
+    }
+
+    fn eom(
+        &self,
+        delta_t_s: f64,
+        state: &OVector<f64, Const<306>>,
+        ctx: &Self::StateType,
+        almanac: Arc<Almanac>,
+    ) -> Result<OVector<f64, Const<306>>, DynamicsError> {
+        // The state itself is integrated as usual, such that the integrator error control is unaffected by the
+        // interpolation of the reference, and it is moved back onto the reference after each step.
+        let mut ctx_no_stm = *ctx;
+        ctx_no_stm.stm = None;
+        let mut d_x = self
+            .dynamics
+            .eom(delta_t_s, state, &ctx_no_stm, almanac.clone())?;
+
+        let osc_sc = ctx.set_with_delta_seconds(delta_t_s, state);
+        if let Some(stm) = osc_sc.stm {
+            // But the partials are evaluated along the reference
+            let osc_sc = self.on_reference(osc_sc, &almanac)?;
+            let (_, grad) = self.dynamics.stm_partials(delta_t_s, &osc_sc, almanac)?;
+            let stm_dt = grad * stm;
+            for (i, val) in stm_dt.iter().copied().enumerate() {
+                d_x[i + Spacecraft::STM_INDEX] = val;
+            }
+        }
+
+        Ok(d_x)
+    }
+
+    fn dual_eom(
+        &self,
+        delta_t_s: f64,
+        ctx: &Self::StateType,
+        almanac: Arc<Almanac>,
+    ) -> Result<(OVector<f64, Const<9>>, OMatrix<f64, Const<9>, Const<9>>), DynamicsError> {
+        let osc_sc = self.on_reference(*ctx, &almanac)?;
+        self.dynamics.dual_eom(delta_t_s, &osc_sc, almanac)
+    }
+
+    fn next_discontinuity(&self, state: &Self::StateType, end: Epoch) -> Option<Epoch> {
+        self.dynamics.next_discontinuity(state, end)
+    }
+
+    fn switched(&self, prev_state: &Self::StateType, next_state: &Self::StateType) -> bool {
+        self.dynamics.switched(prev_state, next_state)
+    }
+}
+
+#[cfg(test)]
+mod ut_reference {
+    use super::ReferenceDynamics;
+    use crate::cosmic::Spacecraft;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::propagators::Propagator;
+    use crate::time::TimeUnits;
+    use crate::State;
+
+    #[test]
+    fn stm_along_reference() {
+        let epoch = fixtures::epoch();
+        let orbit = fixtures::keplerian(8_000.0, 0.2, 28.5, 30.0, 45.0, 10.0);
+        let sc = Spacecraft::builder().orbit(orbit).build();
+        let almanac = fixtures::almanac();
+        let dynamics = SpacecraftDynamics::new(OrbitalDynamics::two_body());
+
+        let (_, reference) = Propagator::default(dynamics.clone())
+            .with(sc, almanac.clone())
+            .for_duration_with_traj(3.hours())
+            .unwrap();
+        let expected = Propagator::default(dynamics.clone())
+            .with(sc.with_stm(), almanac.clone())
+            .for_duration(2.hours())
+            .unwrap();
+
+        // The initial orbit is off the reference, but the propagation follows the reference
+        let mut off_ref = sc.with_stm();
+        off_ref.orbit.radius_km.x += 10.0;
+        let prop = Propagator::default(ReferenceDynamics::new(dynamics, reference.clone()));
+        let end = prop.with(off_ref, almanac).for_duration(2.hours()).unwrap();
+
+        let ref_end = reference.at(epoch + 2.hours()).unwrap();
+        assert!(ref_end.orbit.rss_radius_km(&end.orbit).unwrap() < 1e-9);
+        let stm_err =
+            (end.stm().unwrap() - expected.stm().unwrap()).norm() / expected.stm().unwrap().norm();
+        assert!(stm_err < 1e-6, "{stm_err:e}");
+    }
+}
@@ -16,12 +16,15 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use super::{DynamicsAlmanacSnafu, DynamicsError, DynamicsPlanetarySnafu, ForceModel};
+use super::{
+    DynamicsAlmanacSnafu, DynamicsError, DynamicsPlanetarySnafu, ForceModel, ForcePartials,
+};
 use crate::cosmic::eclipse::EclipseLocator;
 use crate::cosmic::{Frame, Spacecraft, AU, SPEED_OF_LIGHT_M_S};
-use crate::linalg::{Const, Matrix4x3, Vector3};
+use crate::linalg::{Const, Vector3};
+use crate::profiling;
 use anise::almanac::Almanac;
-use anise::constants::frames::SUN_J2000;
+use anise::constants::frames::{EARTH_J2000, MOON_J2000, SUN_J2000};
 use hyperdual::{hyperspace_from_vector, linalg::norm, Float, OHyperdual};
 use log::warn;
 use snafu::ResultExt;
@@ -72,6 +75,11 @@ impl SolarPressure {
         })
     }
 
+    /// Accounts for the shadowing of both the Earth and the Moon, and will set the solar flux at 1 AU to: Phi = 1367.0
+    pub fn cislunar(almanac: Arc<Almanac>) -> Result<Arc<Self>, DynamicsError> {
+        Self::new(vec![EARTH_J2000, MOON_J2000], almanac)
+    }
+
     /// Accounts for the shadowing of only one body and will set the solar flux at 1 AU to: Phi = 1367.0
     pub fn default(shadow_body: Frame, almanac: Arc<Almanac>) -> Result<Arc<Self>, DynamicsError> {
         Ok(Arc::new(Self::default_raw(vec![shadow_body], almanac)?))
@@ -119,64 +127,62 @@ impl ForceModel for SolarPressure {
     fn eom(&self, ctx: &Spacecraft, almanac: Arc<Almanac>) -> Result<Vector3<f64>, DynamicsError> {
         let osc = ctx.orbit;
         // Compute the position of the Sun as seen from the spacecraft
-        let r_sun = almanac
-            .transform_to(ctx.orbit, self.e_loc.light_source, None)
-            .context(DynamicsAlmanacSnafu {
-                action: "transforming state to vector seen from Sun",
-            })?
-            .radius_km;
+        let r_sun = profiling::almanac_query("SRP: transform_to", || {
+            almanac.transform_to(ctx.orbit, self.e_loc.light_source, None)
+        })
+        .context(DynamicsAlmanacSnafu {
+            action: "transforming state to vector seen from Sun",
+        })?
+        .radius_km;
 
         let r_sun_unit = r_sun / r_sun.norm();
 
-        // ANISE returns the occultation percentage (or factor), which is the opposite as the illumination factor.
-        let occult = self
-            .e_loc
-            .compute(osc, almanac)
-            .context(DynamicsAlmanacSnafu {
-                action: "solar radiation pressure computation",
-            })?
-            .factor();
-
-        // Compute the illumination factor.
-        let k: f64 = (occult - 1.0).abs();
+        // Compute the illumination factor, accounting for the worst obstruction of all shadow bodies.
+        let k = profiling::almanac_query("SRP: illumination", || {
+            self.e_loc.illumination(osc, almanac)
+        })
+        .context(DynamicsAlmanacSnafu {
+            action: "solar radiation pressure computation",
+        })?;
 
         let r_sun_au = r_sun.norm() / AU;
         // in N/(m^2)
         let flux_pressure = (k * self.phi / SPEED_OF_LIGHT_M_S) * (1.0 / r_sun_au).powi(2);
 
         // Note the 1e-3 is to convert the SRP from m/s^2 to km/s^2
-        Ok(1e-3 * ctx.srp.coeff_reflectivity * ctx.srp.area_m2 * flux_pressure * r_sun_unit)
+        Ok(1e-3
+            * ctx.srp.coeff_reflectivity
+            * ctx.srp_area_m2(&r_sun_unit)
+            * flux_pressure
+            * r_sun_unit)
     }
 
     fn dual_eom(
         &self,
         ctx: &Spacecraft,
         almanac: Arc<Almanac>,
-    ) -> Result<(Vector3<f64>, Matrix4x3<f64>), DynamicsError> {
+    ) -> Result<(Vector3<f64>, ForcePartials), DynamicsError> {
         let osc = ctx.orbit;
 
         // Compute the position of the Sun as seen from the spacecraft
-        let r_sun = almanac
-            .transform_to(ctx.orbit, self.e_loc.light_source, None)
-            .context(DynamicsAlmanacSnafu {
-                action: "transforming state to vector seen from Sun",
-            })?
-            .radius_km;
+        let r_sun = profiling::almanac_query("SRP: transform_to", || {
+            almanac.transform_to(ctx.orbit, self.e_loc.light_source, None)
+        })
+        .context(DynamicsAlmanacSnafu {
+            action: "transforming state to vector seen from Sun",
+        })?
+        .radius_km;
 
         let r_sun_d: Vector3<OHyperdual<f64, Const<9>>> = hyperspace_from_vector(&r_sun);
         let r_sun_unit = r_sun_d / norm(&r_sun_d);
 
-        // ANISE returns the occultation percentage (or factor), which is the opposite as the illumination factor.
-        let occult = self
-            .e_loc
-            .compute(osc, almanac.clone())
-            .context(DynamicsAlmanacSnafu {
-                action: "solar radiation pressure computation",
-            })?
-            .factor();
-
-        // Compute the illumination factor.
-        let k: f64 = (occult - 1.0).abs();
+        // Compute the illumination factor, accounting for the worst obstruction of all shadow bodies.
+        let k = profiling::almanac_query("SRP: illumination", || {
+            self.e_loc.illumination(osc, almanac)
+        })
+        .context(DynamicsAlmanacSnafu {
+            action: "solar radiation pressure computation",
+        })?;
 
         let r_sun_au = norm(&r_sun_d) / AU;
         let inv_r_sun_au = OHyperdual::<f64, Const<9>>::from_real(1.0) / (r_sun_au);
@@ -187,29 +193,29 @@ impl ForceModel for SolarPressure {
                 * inv_r_sun_au_p2;
 
         // Note the 1e-3 is to convert the SRP from m/s^2 to km/s^2
+        // The partials of the projected area with respect to the position are neglected
         let dual_force_scalar = OHyperdual::<f64, Const<9>>::from_real(
-            1e-3 * ctx.srp.coeff_reflectivity * ctx.srp.area_m2,
+            1e-3 * ctx.srp.coeff_reflectivity * ctx.srp_area_m2(&r_sun),
         );
         let mut dual_force: Vector3<OHyperdual<f64, Const<9>>> = Vector3::zeros();
         dual_force[0] = dual_force_scalar * flux_pressure * r_sun_unit[0];
         dual_force[1] = dual_force_scalar * flux_pressure * r_sun_unit[1];
         dual_force[2] = dual_force_scalar * flux_pressure * r_sun_unit[2];
 
-        // Extract result into Vector6 and Matrix6
+        // Extract result into Vector3 and the partials, which are zero wrt the velocity
         let mut dx = Vector3::zeros();
-        let mut grad = Matrix4x3::zeros();
+        let mut grad = ForcePartials::zeros();
         for i in 0..3 {
             dx[i] += dual_force[i].real();
-            // NOTE: Although the hyperdual state is of size 7, we're only setting the values up to 3 (Matrix3)
+            // NOTE: Although the hyperdual state is of size 9, we're only setting the values up to 3 (position)
             for j in 0..3 {
-                grad[(i, j)] += dual_force[i][j + 1];
+                grad[(j, i)] += dual_force[i][j + 1];
             }
         }
 
         // Compute the partial wrt to Cr.
-        let wrt_cr = self.eom(ctx, almanac)? / ctx.srp.coeff_reflectivity;
-        for j in 0..3 {
-            grad[(3, j)] = wrt_cr[j];
+        for i in 0..3 {
+            grad[(6, i)] = dx[i] / ctx.srp.coeff_reflectivity;
         }
 
         Ok((dx, grad))
@@ -21,18 +21,23 @@ use snafu::ResultExt;
 
 use super::guidance::{ra_dec_from_unit_vector, GuidanceError, GuidanceLaw};
 use super::orbital::OrbitalDynamics;
-use super::{Dynamics, DynamicsGuidanceSnafu, ForceModel};
+use super::{
+    Dynamics, DynamicsAlmanacSnafu, DynamicsAstroSnafu, DynamicsGuidanceSnafu, ExtraState,
+    ExtraStm, ForceModel, PowerModel, StmMethod, StmValidation, MAX_EXTRA_STATES,
+};
 pub use crate::cosmic::{GuidanceMode, Spacecraft, STD_GRAVITY};
 use crate::dynamics::DynamicsError;
 
-use crate::linalg::{Const, DimName, OMatrix, OVector, Vector3};
+use crate::linalg::{Const, Matrix3, OMatrix, OVector, Vector3};
 pub use crate::md::prelude::SolarPressure;
+use crate::profiling::{self, Subsystem};
 use crate::State;
+use hifitime::Epoch;
 
 use std::fmt::{self, Write};
 use std::sync::Arc;
 
-use crate::cosmic::AstroError;
+use crate::cosmic::{AstroError, AstroPhysicsSnafu};
 
 const NORM_ERR: f64 = 1e-4;
 
@@ -46,6 +51,14 @@ pub struct SpacecraftDynamics {
     pub force_models: Vec<Arc<dyn ForceModel>>,
     pub guid_law: Option<Arc<dyn GuidanceLaw>>,
     pub decrement_mass: bool,
+    /// Method used to compute the partials integrated into the STM
+    pub stm_method: StmMethod,
+    /// Optionally cross check the partials against another method
+    pub stm_validation: Option<StmValidation>,
+    /// Optional power model limiting the thrust to the available power
+    pub power: Option<Arc<PowerModel>>,
+    /// Named extra states, whose values are stored in the same order in [Spacecraft::extra]
+    pub extra_states: Vec<ExtraState>,
 }
 
 impl SpacecraftDynamics {
@@ -57,6 +70,10 @@ impl SpacecraftDynamics {
             guid_law: Some(guid_law),
             force_models: Vec::new(),
             decrement_mass: true,
+            stm_method: StmMethod::default(),
+            stm_validation: None,
+            power: None,
+            extra_states: Vec::new(),
         }
     }
 
@@ -71,6 +88,10 @@ impl SpacecraftDynamics {
             guid_law: Some(guid_law),
             force_models: Vec::new(),
             decrement_mass: false,
+            stm_method: StmMethod::default(),
+            stm_validation: None,
+            power: None,
+            extra_states: Vec::new(),
         }
     }
 
@@ -81,6 +102,10 @@ impl SpacecraftDynamics {
             guid_law: None,
             force_models: Vec::new(),
             decrement_mass: true,
+            stm_method: StmMethod::default(),
+            stm_validation: None,
+            power: None,
+            extra_states: Vec::new(),
         }
     }
 
@@ -91,6 +116,10 @@ impl SpacecraftDynamics {
             guid_law: None,
             force_models: vec![force_model],
             decrement_mass: true,
+            stm_method: StmMethod::default(),
+            stm_validation: None,
+            power: None,
+            extra_states: Vec::new(),
         }
     }
 
@@ -119,8 +148,226 @@ impl SpacecraftDynamics {
             guid_law: Some(guid_law),
             force_models: self.force_models.clone(),
             decrement_mass: self.decrement_mass,
+            stm_method: self.stm_method,
+            stm_validation: self.stm_validation,
+            power: self.power.clone(),
+            extra_states: self.extra_states.clone(),
         }
     }
+
+    /// Clone these spacecraft dynamics and compute the STM with the provided method.
+    pub fn with_stm_method(&self, stm_method: StmMethod) -> Self {
+        let mut me = self.clone();
+        me.stm_method = stm_method;
+        me
+    }
+
+    /// Clone these spacecraft dynamics and cross check the partials of the STM method against the provided validation method.
+    pub fn with_stm_validation(&self, validation: StmValidation) -> Self {
+        let mut me = self.clone();
+        me.stm_validation = Some(validation);
+        me
+    }
+
+    /// Clone these spacecraft dynamics and limit the thrust to the power available from this power model.
+    pub fn with_power(&self, power: Arc<PowerModel>) -> Self {
+        let mut me = self.clone();
+        me.power = Some(power);
+        me
+    }
+
+    /// Clone these spacecraft dynamics and propagate these extra states, up to [MAX_EXTRA_STATES].
+    pub fn with_extra_states(&self, extra_states: Vec<ExtraState>) -> Result<Self, DynamicsError> {
+        if extra_states.len() > MAX_EXTRA_STATES {
+            return Err(DynamicsError::TooManyExtraStates {
+                count: extra_states.len(),
+                max: MAX_EXTRA_STATES,
+            });
+        }
+        let mut me = self.clone();
+        me.extra_states = extra_states;
+        Ok(me)
+    }
+
+    /// Returns the index of the extra state of this name in [Spacecraft::extra], if any.
+    pub fn extra_index(&self, name: &str) -> Option<usize> {
+        self.extra_states
+            .iter()
+            .position(|extra| extra.name == name)
+    }
+
+    /// Returns the value of the extra state of this name for this spacecraft, if any.
+    pub fn extra_value(&self, sc: &Spacecraft, name: &str) -> Option<f64> {
+        self.extra_index(name).map(|index| sc.extra.values[index])
+    }
+
+    /// Returns the state derivative, excluding the guidance law, and the partials of the dynamics at the osculating state,
+    /// computed with the STM method and cross checked if a validation method is set.
+    pub(crate) fn stm_partials(
+        &self,
+        delta_t_s: f64,
+        osc_sc: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<(OVector<f64, Const<9>>, OMatrix<f64, Const<9>, Const<9>>), DynamicsError> {
+        let (d_x, grad) =
+            self.partials_with(self.stm_method, delta_t_s, osc_sc, almanac.clone())?;
+
+        if let Some(validation) = self.stm_validation {
+            let (_, other) = self.partials_with(validation.method, delta_t_s, osc_sc, almanac)?;
+            for col in 0..9 {
+                for row in 0..9 {
+                    let (primary_val, secondary_val) = (grad[(row, col)], other[(row, col)]);
+                    if (primary_val - secondary_val).abs()
+                        > validation.abs_tol + validation.rel_tol * primary_val.abs()
+                    {
+                        return Err(DynamicsError::PartialsMismatch {
+                            primary: self.stm_method,
+                            secondary: validation.method,
+                            row,
+                            col,
+                            primary_val,
+                            secondary_val,
+                            epoch: osc_sc.epoch(),
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok((d_x, grad))
+    }
+
+    fn partials_with(
+        &self,
+        method: StmMethod,
+        delta_t_s: f64,
+        osc_sc: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<(OVector<f64, Const<9>>, OMatrix<f64, Const<9>, Const<9>>), DynamicsError> {
+        match method {
+            StmMethod::Hyperdual => self.dual_eom(delta_t_s, osc_sc, almanac),
+            StmMethod::FiniteDifference => {
+                let mut ctx = *osc_sc;
+                ctx.stm = None;
+                let state = ctx.to_vector();
+                let mut grad = OMatrix::<f64, Const<9>, Const<9>>::zeros();
+                for col in 0..9 {
+                    let step = f64::EPSILON.cbrt() * state[col].abs().max(1.0);
+                    let mut state_plus = state;
+                    state_plus[col] += step;
+                    let mut state_minus = state;
+                    state_minus[col] -= step;
+                    let d_plus = self.eom(0.0, &state_plus, &ctx, almanac.clone())?;
+                    let d_minus = self.eom(0.0, &state_minus, &ctx, almanac.clone())?;
+                    for row in 0..9 {
+                        grad[(row, col)] = (d_plus[row] - d_minus[row]) / (2.0 * step);
+                    }
+                }
+                Ok((self.ballistic_eom(&ctx, almanac)?, grad))
+            }
+            StmMethod::Analytic => {
+                if !self.orbital_dyn.accel_models.is_empty()
+                    || !self.force_models.is_empty()
+                    || self.guid_law.is_some()
+                {
+                    return Err(DynamicsError::DynamicsAstro {
+                        source: AstroError::PartialsUndefined,
+                    });
+                }
+                let mu_km3_s2 = osc_sc
+                    .orbit
+                    .frame
+                    .mu_km3_s2()
+                    .context(AstroPhysicsSnafu)
+                    .context(DynamicsAstroSnafu)?;
+                let r = osc_sc.orbit.radius_km;
+                let rmag = r.norm();
+                // Gradient of the point mass gravity: mu / r^5 (3 r r^T - r^2 I)
+                let gravity_grad = (3.0 * r * r.transpose() - rmag.powi(2) * Matrix3::identity())
+                    * (mu_km3_s2 / rmag.powi(5));
+
+                let mut grad = OMatrix::<f64, Const<9>, Const<9>>::zeros();
+                for i in 0..3 {
+                    grad[(i, i + 3)] = 1.0;
+                    for j in 0..3 {
+                        grad[(i + 3, j)] = gravity_grad[(i, j)];
+                    }
+                }
+                Ok((self.ballistic_eom(osc_sc, almanac)?, grad))
+            }
+        }
+    }
+
+    /// Returns the state derivative from the orbital dynamics and the force models, excluding the guidance law.
+    fn ballistic_eom(
+        &self,
+        osc_sc: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<OVector<f64, Const<9>>, DynamicsError> {
+        let mut d_x = OVector::<f64, Const<9>>::zeros();
+        for (i, val) in self
+            .orbital_dyn
+            .eom(&osc_sc.orbit, almanac.clone())?
+            .iter()
+            .copied()
+            .take(6)
+            .enumerate()
+        {
+            d_x[i] = val;
+        }
+
+        for model in &self.force_models {
+            let _scope = profiling::scope(Subsystem::ForceModel, || model.to_string());
+            let model_frc = model.eom(osc_sc, almanac.clone())? / osc_sc.mass_kg();
+            for i in 0..3 {
+                d_x[i + 3] += model_frc[i];
+            }
+        }
+
+        Ok(d_x)
+    }
+
+    /// Returns the partials of the rates of the extra states wrt the spacecraft state, and wrt the extra states.
+    fn extra_rate_partials(
+        &self,
+        osc_sc: &Spacecraft,
+    ) -> (
+        OMatrix<f64, Const<MAX_EXTRA_STATES>, Const<9>>,
+        OMatrix<f64, Const<MAX_EXTRA_STATES>, Const<MAX_EXTRA_STATES>>,
+    ) {
+        let mut wrt_sc = OMatrix::<f64, Const<MAX_EXTRA_STATES>, Const<9>>::zeros();
+        let mut wrt_extra =
+            OMatrix::<f64, Const<MAX_EXTRA_STATES>, Const<MAX_EXTRA_STATES>>::zeros();
+        for (i, model) in self.extra_states.iter().enumerate() {
+            let (row, wrt_value) = model.rate_partials(osc_sc, osc_sc.extra.values[i]);
+            wrt_sc.set_row(i, &row.transpose());
+            wrt_extra[(i, i)] = wrt_value;
+        }
+        (wrt_sc, wrt_extra)
+    }
+
+    /// Returns the partials of the state derivative wrt the extra states read by the force models, by central finite differences.
+    fn extra_dynamics_partials(
+        &self,
+        osc_sc: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<OMatrix<f64, Const<9>, Const<MAX_EXTRA_STATES>>, DynamicsError> {
+        let mut grad = OMatrix::<f64, Const<9>, Const<MAX_EXTRA_STATES>>::zeros();
+        for (i, model) in self.extra_states.iter().enumerate() {
+            if !model.in_dynamics {
+                continue;
+            }
+            let step = f64::EPSILON.cbrt() * osc_sc.extra.values[i].abs().max(1.0);
+            let mut sc_plus = *osc_sc;
+            sc_plus.extra.values[i] += step;
+            let mut sc_minus = *osc_sc;
+            sc_minus.extra.values[i] -= step;
+            let d_plus = self.ballistic_eom(&sc_plus, almanac.clone())?;
+            let d_minus = self.ballistic_eom(&sc_minus, almanac.clone())?;
+            grad.set_column(i, &((d_plus - d_minus) / (2.0 * step)));
+        }
+        Ok(grad)
+    }
 }
 
 impl fmt::Display for SpacecraftDynamics {
@@ -161,6 +408,39 @@ impl Dynamics for SpacecraftDynamics {
             });
         }
 
+        // Draw the propellant used during this step from the tanks feeding the thruster
+        let mut next_state = next_state;
+        if !next_state.update_tanks() {
+            error!(
+                "tanks feeding the thruster exhausted at {}",
+                next_state.epoch()
+            );
+            return Err(DynamicsError::FuelExhausted {
+                sc: Box::new(next_state),
+            });
+        }
+
+        if let Some(attitude) = next_state.attitude.as_mut() {
+            attitude
+                .update(next_state.orbit, almanac.clone())
+                .context(DynamicsAlmanacSnafu {
+                    action: "updating the attitude from its profile",
+                })?;
+        }
+
+        // Update the battery charge with the power balance of this step
+        if let Some(power) = &self.power {
+            let throttle =
+                match &self.guid_law {
+                    Some(guid_law) if next_state.thruster.is_some() => guid_law
+                        .throttle(&next_state)
+                        .context(DynamicsGuidanceSnafu)?,
+                    _ => 0.0,
+                };
+            let balance = power.balance(&next_state, throttle, almanac.clone())?;
+            power.update_battery(&mut next_state, &balance);
+        }
+
         if let Some(guid_law) = &self.guid_law {
             let mut state = next_state;
             // Update the control mode
@@ -171,54 +451,95 @@ impl Dynamics for SpacecraftDynamics {
         }
     }
 
+    fn next_discontinuity(&self, state: &Self::StateType, end: Epoch) -> Option<Epoch> {
+        self.guid_law
+            .as_ref()
+            .and_then(|guid_law| guid_law.next_discontinuity(state, end))
+    }
+
+    fn switched(&self, prev_state: &Self::StateType, next_state: &Self::StateType) -> bool {
+        self.guid_law.is_some() && prev_state.mode() != next_state.mode()
+    }
+
     fn eom(
         &self,
         delta_t_s: f64,
-        state: &OVector<f64, Const<90>>,
+        state: &OVector<f64, Const<306>>,
         ctx: &Self::StateType,
         almanac: Arc<Almanac>,
-    ) -> Result<OVector<f64, Const<90>>, DynamicsError> {
+    ) -> Result<OVector<f64, Const<306>>, DynamicsError> {
+        // The extra states are only integrated if the spacecraft carries them
+        if ctx.extra.len() < self.extra_states.len() {
+            return Err(DynamicsError::MissingExtraStates {
+                count: self.extra_states.len(),
+                len: ctx.extra.len(),
+            });
+        }
         // Rebuild the osculating state for the EOM context.
         let osc_sc = ctx.set_with_delta_seconds(delta_t_s, state);
-        let mut d_x = OVector::<f64, Const<90>>::zeros();
+        let mut d_x = OVector::<f64, Const<306>>::zeros();
 
         // Maybe I use this only when estimating the orbit state from a spacecraft, but that functionality will soon disappear.
-        match ctx.stm {
+        match osc_sc.stm {
             Some(stm) => {
                 // Call the gradient (also called the dual EOM function of the force models)
-                let (state, grad) = self.dual_eom(delta_t_s, &osc_sc, almanac)?;
-
-                // Apply the gradient to the STM
-                let stm_dt = stm * grad;
+                let (state, grad) = self.stm_partials(delta_t_s, &osc_sc, almanac.clone())?;
 
                 // Rebuild the state vector
                 for (i, val) in state.iter().enumerate() {
                     d_x[i] = *val;
                 }
 
-                for (i, val) in stm_dt.iter().copied().enumerate() {
-                    d_x[i + <Spacecraft as State>::Size::dim()] = val;
+                // Apply the gradient to the STM of this stage of the integrator: dPhi/dt = A Phi
+                if osc_sc.orbital_stm {
+                    let stm_dt = grad.fixed_view::<6, 6>(0, 0) * stm.fixed_view::<6, 6>(0, 0);
+                    for (i, val) in stm_dt.iter().copied().enumerate() {
+                        d_x[i + Spacecraft::STM_INDEX] = val;
+                    }
+                } else {
+                    let mut stm_dt = grad * stm;
+                    if let (Some(extra_stm), false) =
+                        (osc_sc.extra.stm, self.extra_states.is_empty())
+                    {
+                        // Integrate the STM of the state augmented with the extra states, block by block.
+                        let (extra_grad, extra_wrt_extra) = self.extra_rate_partials(&osc_sc);
+                        let sc_wrt_extra =
+                            self.extra_dynamics_partials(&osc_sc, almanac.clone())?;
+                        stm_dt += sc_wrt_extra * extra_stm.extra_wrt_sc;
+                        let blocks_dt = ExtraStm {
+                            extra_wrt_sc: extra_grad * stm
+                                + extra_wrt_extra * extra_stm.extra_wrt_sc,
+                            sc_wrt_extra: grad * extra_stm.sc_wrt_extra
+                                + sc_wrt_extra * extra_stm.extra_wrt_extra,
+                            extra_wrt_extra: extra_grad * extra_stm.sc_wrt_extra
+                                + extra_wrt_extra * extra_stm.extra_wrt_extra,
+                        };
+                        for (i, val) in blocks_dt
+                            .extra_wrt_sc
+                            .iter()
+                            .chain(blocks_dt.sc_wrt_extra.iter())
+                            .chain(blocks_dt.extra_wrt_extra.iter())
+                            .copied()
+                            .enumerate()
+                        {
+                            d_x[i + Spacecraft::EXTRA_STM_INDEX] = val;
+                        }
+                    }
+                    for (i, val) in stm_dt.iter().copied().enumerate() {
+                        d_x[i + Spacecraft::STM_INDEX] = val;
+                    }
                 }
             }
             None => {
-                // Compute the orbital dynamics
+                // Compute the orbital dynamics and apply the force models for non STM propagation
                 for (i, val) in self
-                    .orbital_dyn
-                    .eom(&osc_sc.orbit, almanac.clone())?
+                    .ballistic_eom(&osc_sc, almanac.clone())?
                     .iter()
                     .copied()
                     .enumerate()
                 {
                     d_x[i] = val;
                 }
-
-                // Apply the force models for non STM propagation
-                for model in &self.force_models {
-                    let model_frc = model.eom(&osc_sc, almanac.clone())? / osc_sc.mass_kg();
-                    for i in 0..3 {
-                        d_x[i + 3] += model_frc[i];
-                    }
-                }
             }
         };
 
@@ -231,8 +552,10 @@ impl Dynamics for SpacecraftDynamics {
                     });
                 }
                 let thruster = osc_sc.thruster.unwrap();
-                let thrust_throttle_lvl =
+                let mut thrust_throttle_lvl =
                     guid_law.throttle(&osc_sc).context(DynamicsGuidanceSnafu)?;
+                // Ratio of the thrust allowed by the available power
+                let mut thrust_ratio = 1.0;
                 if !(0.0..=1.0).contains(&thrust_throttle_lvl) {
                     return Err(DynamicsError::DynamicsGuidance {
                         source: GuidanceError::ThrottleRatio {
@@ -240,6 +563,14 @@ impl Dynamics for SpacecraftDynamics {
                         },
                     });
                 } else if thrust_throttle_lvl > 0.0 {
+                    if let Some(power) = &self.power {
+                        thrust_ratio = power
+                            .balance(&osc_sc, thrust_throttle_lvl, almanac.clone())?
+                            .thrust_ratio;
+                        thrust_throttle_lvl *= thrust_ratio;
+                    }
+                }
+                if thrust_throttle_lvl > 0.0 {
                     // Thrust arc
                     let thrust_inertial =
                         guid_law.direction(&osc_sc).context(DynamicsGuidanceSnafu)?;
@@ -256,12 +587,17 @@ impl Dynamics for SpacecraftDynamics {
                         });
                     } else if thrust_inertial.norm().is_normal() {
                         // Compute the thrust in Newtons and Isp
-                        let total_thrust = (thrust_throttle_lvl * thruster.thrust_N) * 1e-3; // Convert m/s^-2 to km/s^-2
+                        let total_thrust = (thrust_throttle_lvl * thruster.mean_thrust_N()) * 1e-3; // Convert m/s^-2 to km/s^-2
                         (
                             thrust_inertial * total_thrust,
                             if self.decrement_mass {
-                                let prop_usage = thrust_throttle_lvl * thruster.thrust_N
-                                    / (thruster.isp_s * STD_GRAVITY);
+                                let prop_usage = match guid_law
+                                    .mass_flow_kg_s(&osc_sc)
+                                    .context(DynamicsGuidanceSnafu)?
+                                {
+                                    Some(mass_flow_kg_s) => mass_flow_kg_s * thrust_ratio,
+                                    None => thrust_throttle_lvl * thruster.mean_mass_flow_kg_s(),
+                                };
                                 -prop_usage
                             } else {
                                 0.0
@@ -284,6 +620,11 @@ impl Dynamics for SpacecraftDynamics {
             }
             d_x[8] += prop_rate;
         }
+
+        for (i, model) in self.extra_states.iter().enumerate() {
+            d_x[i + Spacecraft::EXTRA_INDEX] = model.rate(&osc_sc, osc_sc.extra.values[i]);
+        }
+
         Ok(d_x)
     }
 
@@ -322,19 +663,22 @@ impl Dynamics for SpacecraftDynamics {
         // Call the EOMs
         let total_mass = ctx.mass_kg();
         for model in &self.force_models {
+            let _scope = profiling::scope(Subsystem::ForceModel, || format!("{model} (STM)"));
             let (model_frc, model_grad) = model.dual_eom(ctx, almanac.clone())?;
             for i in 0..3 {
                 // Add the velocity changes
                 d_x[i + 3] += model_frc[i] / total_mass;
-                // Add the velocity partials
-                for j in 0..3 {
-                    grad[(i + 3, j)] += model_grad[(i, j)] / total_mass;
+                // Add the partials of the acceleration wrt the position and the velocity
+                for j in 0..6 {
+                    grad[(i + 3, j)] += model_grad[(j, i)] / total_mass;
                 }
+                // Add the partial of the acceleration wrt the propellant mass
+                grad[(i + 3, 8)] -= model_frc[i] / total_mass.powi(2);
             }
             // Add this force model's estimation if applicable.
             if let Some(idx) = model.estimation_index() {
-                for j in 0..3 {
-                    grad[(j + 3, idx)] += model_grad[(3, j)] / total_mass;
+                for i in 0..3 {
+                    grad[(i + 3, idx)] += model_grad[(6, i)] / total_mass;
                 }
             }
         }
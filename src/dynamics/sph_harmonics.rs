@@ -24,6 +24,7 @@ use crate::cosmic::{AstroPhysicsSnafu, Frame, Orbit};
 use crate::dynamics::AccelModel;
 use crate::io::gravity::HarmonicsMem;
 use crate::linalg::{DMatrix, Matrix3, Vector3, Vector4, U7};
+use crate::profiling;
 use hyperdual::linalg::norm;
 use hyperdual::{hyperspace_from_vector, Float, OHyperdual};
 use std::cmp::min;
@@ -51,6 +52,10 @@ pub struct Harmonics {
 impl Harmonics {
     /// Create a new Harmonics dynamical model from the provided gravity potential storage instance.
     pub fn from_stor(compute_frame: Frame, stor: HarmonicsMem) -> Arc<Self> {
+        if let Err(e) = stor.check_frame(compute_frame, 1e-3) {
+            warn!("gravity field inconsistent with its compute frame: {e}");
+        }
+
         let degree_np2 = stor.max_degree_n() + 2;
         let mut a_nm = DMatrix::from_element(degree_np2 + 1, degree_np2 + 1, 0.0);
         let mut b_nm = DMatrix::from_element(degree_np2, degree_np2, 0.0);
@@ -149,11 +154,12 @@ impl fmt::Display for Harmonics {
 impl AccelModel for Harmonics {
     fn eom(&self, osc: &Orbit, almanac: Arc<Almanac>) -> Result<Vector3<f64>, DynamicsError> {
         // Convert the osculating orbit to the correct frame (needed for multiple harmonic fields)
-        let state = almanac
-            .transform_to(*osc, self.compute_frame, None)
-            .context(DynamicsAlmanacSnafu {
-                action: "transforming into gravity field frame",
-            })?;
+        let state = profiling::almanac_query("harmonics: transform_to", || {
+            almanac.transform_to(*osc, self.compute_frame, None)
+        })
+        .context(DynamicsAlmanacSnafu {
+            action: "transforming into gravity field frame",
+        })?;
 
         // Using the GMAT notation, with extra character for ease of highlight
         let r_ = state.rmag_km();
@@ -244,14 +250,15 @@ impl AccelModel for Harmonics {
         // Rotate this acceleration vector back into the integration frame (no center change needed, it's just a vector)
         // As discussed with Sai, if the Earth was spinning faster, would the acceleration due to the harmonics be any different?
         // No. Therefore, we do not need to account for the transport theorem here.
-        let dcm = almanac
-            .rotate(self.compute_frame, osc.frame, osc.epoch)
-            .context(OrientationSnafu {
-                action: "transform state dcm",
-            })
-            .context(DynamicsAlmanacSnafu {
-                action: "transforming into gravity field frame",
-            })?;
+        let dcm = profiling::almanac_query("harmonics: rotate", || {
+            almanac.rotate(self.compute_frame, osc.frame, osc.epoch)
+        })
+        .context(OrientationSnafu {
+            action: "transform state dcm",
+        })
+        .context(DynamicsAlmanacSnafu {
+            action: "transforming into gravity field frame",
+        })?;
 
         Ok(dcm.rot_mat * accel)
     }
@@ -262,11 +269,12 @@ impl AccelModel for Harmonics {
         almanac: Arc<Almanac>,
     ) -> Result<(Vector3<f64>, Matrix3<f64>), DynamicsError> {
         // Convert the osculating orbit to the correct frame (needed for multiple harmonic fields)
-        let state = almanac
-            .transform_to(*osc, self.compute_frame, None)
-            .context(DynamicsAlmanacSnafu {
-                action: "transforming into gravity field frame",
-            })?;
+        let state = profiling::almanac_query("harmonics: transform_to", || {
+            almanac.transform_to(*osc, self.compute_frame, None)
+        })
+        .context(DynamicsAlmanacSnafu {
+            action: "transforming into gravity field frame",
+        })?;
 
         let radius: Vector3<OHyperdual<f64, U7>> = hyperspace_from_vector(&state.radius_km);
 
@@ -367,36 +375,24 @@ impl AccelModel for Harmonics {
             a3 -= rr * sum3;
         }
 
-        let dcm = almanac
-            .rotate(self.compute_frame, osc.frame, osc.epoch)
-            .context(OrientationSnafu {
-                action: "transform state dcm",
-            })
-            .context(DynamicsAlmanacSnafu {
-                action: "transforming into gravity field frame",
-            })?
-            .rot_mat;
-
-        // Convert DCM to OHyperdual DCMs
-        let mut dcm_d = Matrix3::<OHyperdual<f64, U7>>::zeros();
-        for i in 0..3 {
-            for j in 0..3 {
-                dcm_d[(i, j)] = OHyperdual::from_fn(|k| {
-                    if k == 0 {
-                        dcm[(i, j)]
-                    } else if i + 1 == k {
-                        1.0
-                    } else {
-                        0.0
-                    }
-                })
-            }
-        }
+        let dcm = profiling::almanac_query("harmonics: rotate", || {
+            almanac.rotate(self.compute_frame, osc.frame, osc.epoch)
+        })
+        .context(OrientationSnafu {
+            action: "transform state dcm",
+        })
+        .context(DynamicsAlmanacSnafu {
+            action: "transforming into gravity field frame",
+        })?
+        .rot_mat;
+
+        // Convert DCM to OHyperdual DCMs: the rotation does not depend on the position.
+        let dcm_d = dcm.map(OHyperdual::<f64, U7>::from);
 
         let accel = dcm_d * Vector3::new(a0 + a3 * s_, a1 + a3 * t_, a2 + a3 * u_);
         // Extract data
         let mut dx = Vector3::zeros();
-        let mut grad = Matrix3::zeros();
+        let mut grad = Matrix3::<f64>::zeros();
         for i in 0..3 {
             dx[i] += accel[i].real();
             // NOTE: Although the hyperdual state is of size 7, we're only setting the values up to 3 (Matrix3)
@@ -404,6 +400,8 @@ impl AccelModel for Harmonics {
                 grad[(i, j - 1)] += accel[i][j];
             }
         }
+        // The partials were computed wrt the position in the gravity field frame, so rotate them into the integration frame.
+        let grad = grad * dcm.transpose();
         Ok((dx, grad))
     }
 }
@@ -0,0 +1,205 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Method used to compute the partials of the dynamics, which are integrated into the STM.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StmMethod {
+    /// Automatic differentiation with hyperdual numbers of each force model (default)
+    #[default]
+    Hyperdual,
+    /// Central finite differences of the full dynamics, including the guidance law
+    FiniteDifference,
+    /// Closed form partials, only available for two body dynamics without any other model
+    Analytic,
+}
+
+impl fmt::Display for StmMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Hyperdual => write!(f, "hyperdual"),
+            Self::FiniteDifference => write!(f, "finite difference"),
+            Self::Analytic => write!(f, "analytic"),
+        }
+    }
+}
+
+/// Cross checks the partials of the STM method against another method at every evaluation of the dynamics.
+///
+/// Each partial must match within `abs_tol + rel_tol * |partial|`, else the propagation fails with the first mismatched
+/// partial. This is meant for debugging the partials of new models and is expensive.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StmValidation {
+    /// Method to compare against
+    pub method: StmMethod,
+    /// Relative tolerance on each partial
+    pub rel_tol: f64,
+    /// Absolute tolerance on each partial
+    pub abs_tol: f64,
+}
+
+impl StmValidation {
+    /// Cross check against the provided method with a relative tolerance of 1e-6 and an absolute tolerance of 1e-12.
+    pub fn new(method: StmMethod) -> Self {
+        Self {
+            method,
+            rel_tol: 1e-6,
+            abs_tol: 1e-12,
+        }
+    }
+}
+
+#[cfg(test)]
+mod ut_stm {
+    use super::{StmMethod, StmValidation};
+    use crate::cosmic::Spacecraft;
+    use crate::dynamics::{DynamicsError, OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::propagators::{PropagationError, Propagator};
+    use crate::time::TimeUnits;
+    use crate::State;
+
+    #[test]
+    fn methods_agree() {
+        let orbit = fixtures::keplerian(8_000.0, 0.2, 28.5, 30.0, 45.0, 10.0);
+        let sc = Spacecraft::builder().orbit(orbit).build().with_stm();
+        let almanac = fixtures::almanac();
+        let dynamics = SpacecraftDynamics::new(OrbitalDynamics::two_body());
+
+        let stm_with = |dynamics: SpacecraftDynamics| {
+            Propagator::default(dynamics)
+                .with(sc, almanac.clone())
+                .for_duration(1.hours())
+                .map(|end| end.stm().unwrap())
+        };
+
+        let expected = stm_with(dynamics.clone()).unwrap();
+        for method in [StmMethod::FiniteDifference, StmMethod::Analytic] {
+            let stm = stm_with(dynamics.with_stm_method(method)).unwrap();
+            let err = (stm - expected).norm() / expected.norm();
+            assert!(err < 1e-6, "{method}: {err:e}");
+        }
+
+        // Cross checking passes with the default tolerances
+        let validated = stm_with(
+            dynamics
+                .with_stm_method(StmMethod::Analytic)
+                .with_stm_validation(StmValidation::new(StmMethod::FiniteDifference)),
+        )
+        .unwrap();
+        assert!((validated - expected).norm() / expected.norm() < 1e-6);
+
+        // But not when requiring finite differences to match exactly
+        let mut exact = StmValidation::new(StmMethod::FiniteDifference);
+        exact.rel_tol = 0.0;
+        exact.abs_tol = 0.0;
+        match stm_with(dynamics.with_stm_validation(exact)) {
+            Err(PropagationError::Dynamics {
+                source:
+                    DynamicsError::PartialsMismatch {
+                        primary, secondary, ..
+                    },
+            }) => {
+                assert_eq!(primary, StmMethod::Hyperdual);
+                assert_eq!(secondary, StmMethod::FiniteDifference);
+            }
+            other => panic!("expected a partials mismatch, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn stm_matches_perturbed_propagation() {
+        let orbit = fixtures::keplerian(8_000.0, 0.05, 28.5, 10.0, 20.0, 30.0);
+        let sc = Spacecraft::builder().orbit(orbit).build();
+        let almanac = fixtures::almanac();
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+
+        let nominal = prop
+            .with(sc.with_stm(), almanac.clone())
+            .for_duration(1.hours())
+            .unwrap();
+        let stm = nominal.stm().unwrap();
+
+        // Each column of the STM is the sensitivity of the final state to a perturbation of the initial state.
+        for col in 0..6 {
+            let mut perturbed = sc;
+            let step = if col < 3 { 1e-3 } else { 1e-6 };
+            if col < 3 {
+                perturbed.orbit.radius_km[col] += step;
+            } else {
+                perturbed.orbit.velocity_km_s[col - 3] += step;
+            }
+            let end = prop
+                .with(perturbed, almanac.clone())
+                .for_duration(1.hours())
+                .unwrap();
+            let fd =
+                (end.orbit.to_cartesian_pos_vel() - nominal.orbit.to_cartesian_pos_vel()) / step;
+            let expected = stm.fixed_view::<6, 1>(0, col);
+            let err = (fd - expected).norm() / expected.norm();
+            assert!(err < 1e-5, "column {col}: {err:e}");
+        }
+    }
+
+    #[test]
+    fn reduced_orbital_stm() {
+        let orbit = fixtures::keplerian(8_000.0, 0.05, 28.5, 10.0, 20.0, 30.0);
+        let sc = Spacecraft::builder().orbit(orbit).build();
+        // The state is followed by the STM, then the extra states and their STM blocks only if the spacecraft carries any
+        assert_eq!(sc.integrated_len(), 9);
+        assert_eq!(sc.with_orbital_stm().integrated_len(), 45);
+        assert_eq!(sc.with_stm().integrated_len(), 90);
+        assert!(sc.with_stm().extra.stm.is_none());
+        let extra = sc.with_extra(1, 0.0);
+        assert_eq!(extra.integrated_len(), 98);
+        assert_eq!(extra.with_orbital_stm().integrated_len(), 98);
+        assert_eq!(extra.with_stm().integrated_len(), 306);
+        assert_eq!(sc.with_stm().with_extra(0, 1.0).integrated_len(), 306);
+
+        let almanac = fixtures::almanac();
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let full = prop
+            .with(sc.with_stm(), almanac.clone())
+            .for_duration(1.hours())
+            .unwrap();
+        let reduced = prop
+            .with(sc.with_orbital_stm(), almanac.clone())
+            .for_duration(1.hours())
+            .unwrap();
+        let none = prop.with(sc, almanac).for_duration(1.hours()).unwrap();
+
+        // The trajectory does not depend on the STM, and the orbital block matches the full STM
+        assert_eq!(reduced.orbit, full.orbit);
+        assert_eq!(none.orbit, full.orbit);
+        assert!(reduced.orbital_stm);
+        let (full_stm, reduced_stm) = (full.stm().unwrap(), reduced.stm().unwrap());
+        let err = (full_stm.fixed_view::<6, 6>(0, 0) - reduced_stm.fixed_view::<6, 6>(0, 0)).norm();
+        assert!(err < 1e-12, "{err:e}");
+        // The parameters are ignored by the reduced STM
+        assert_eq!(
+            reduced_stm.fixed_view::<3, 3>(6, 6),
+            nalgebra::Matrix3::identity()
+        );
+        assert_eq!(
+            reduced_stm.fixed_view::<6, 3>(0, 6),
+            nalgebra::Matrix6x3::zeros()
+        );
+    }
+}
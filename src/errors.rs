@@ -19,7 +19,10 @@
 use crate::md::trajectory::TrajError;
 use crate::md::StateParameter;
 pub use crate::md::TargetingError;
-use crate::{cosmic::AstroError, io::ConfigError};
+use crate::{
+    cosmic::{AstroError, Frame},
+    io::ConfigError,
+};
 use anise::errors::{AlmanacError, PhysicsError};
 use hifitime::Epoch;
 use snafu::prelude::*;
@@ -38,6 +41,14 @@ pub enum NyxError {
     LambertNotReasonablePhi,
     #[snafu(display("Use the Izzo algorithm for multi-rev transfers"))]
     LambertMultiRevNotSupported,
+    #[snafu(display(
+        "No {n_revs} revolution Lambert transfer for this time of flight (max {max_revs})"
+    ))]
+    LambertNoSolution { n_revs: u8, max_revs: u8 },
+    #[snafu(display("Lambert transfer requires the same frame at departure ({departure}) and arrival ({arrival})"))]
+    LambertFrameMismatch { departure: Frame, arrival: Frame },
+    #[snafu(display("Lambert transfer requires the GM of the frame: {source}"))]
+    LambertPhysicsError { source: PhysicsError },
     #[snafu(display("Unavailable parameter {param:?}: {msg}"))]
     StateParameterUnavailable { param: StateParameter, msg: String },
     #[snafu(display("Could not load file: {msg}"))]
@@ -67,10 +78,20 @@ pub enum NyxError {
     MonteCarlo { msg: String },
     #[snafu(display("CCSDS error: {msg}"))]
     CCSDS { msg: String },
+    #[snafu(display("JPL Horizons error: {msg}"))]
+    Horizons { msg: String },
+    #[snafu(display("Celestrak error: {msg}"))]
+    Celestrak { msg: String },
+    #[snafu(display("GMAT script error: {msg}"))]
+    Gmat { msg: String },
+    #[snafu(display("data manager error: {msg}"))]
+    DataManager { msg: String },
     #[snafu(display("Error: {msg}"))]
     CustomError { msg: String },
     #[snafu(display("Trajectory error: {source}"))]
     Trajectory { source: TrajError },
+    #[snafu(display("Event error: {source}"))]
+    Event { source: EventError },
     #[snafu(display("Math domain error: {msg}"))]
     MathDomain { msg: String },
     #[snafu(display("Guidance law config error: {msg}"))]
@@ -111,6 +132,12 @@ pub enum StateError {
     },
     #[snafu(display("No thruster attached to spacecraft"))]
     NoThrusterAvail,
+    #[snafu(display("{param} computation caused {source}"))]
+    StateAlmanacError {
+        param: StateParameter,
+        #[snafu(source(from(AlmanacError, Box::new)))]
+        source: Box<AlmanacError>,
+    },
 }
 
 #[derive(Debug, PartialEq, Snafu)]
@@ -152,4 +179,8 @@ pub enum MonteCarloError {
         action: &'static str,
         num_runs: usize,
     },
+    #[snafu(display("invalid Monte Carlo stopping criteria: {msg}"))]
+    StoppingCriteria { msg: String },
+    #[snafu(display("worst case search failed: {msg}"))]
+    WorstCaseSearch { msg: String },
 }
@@ -0,0 +1,63 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Fixtures shared by the unit tests.
+
+use crate::cosmic::Orbit;
+use crate::time::Epoch;
+use crate::GMAT_EARTH_GM;
+use anise::constants::frames::EARTH_J2000;
+use anise::prelude::{Almanac, Frame};
+use std::sync::Arc;
+
+/// Epoch of the fixtures, 2024-01-01 at midnight UTC.
+pub(crate) fn epoch() -> Epoch {
+    Epoch::from_gregorian_utc_at_midnight(2024, 1, 1)
+}
+
+/// Earth J2000 frame with the GMAT gravitational parameter.
+pub(crate) fn eme2k() -> Frame {
+    EARTH_J2000.with_mu_km3_s2(GMAT_EARTH_GM)
+}
+
+/// Almanac without any loaded data, which suffices for the inertial Earth frames.
+pub(crate) fn almanac() -> Arc<Almanac> {
+    Arc::new(Almanac::default())
+}
+
+/// Orbit of these Keplerian elements (in km and degrees) in [eme2k] at the fixture [epoch].
+pub(crate) fn keplerian(
+    sma_km: f64,
+    ecc: f64,
+    inc_deg: f64,
+    raan_deg: f64,
+    aop_deg: f64,
+    ta_deg: f64,
+) -> Orbit {
+    Orbit::try_keplerian(
+        sma_km,
+        ecc,
+        inc_deg,
+        raan_deg,
+        aop_deg,
+        ta_deg,
+        epoch(),
+        eme2k(),
+    )
+    .unwrap()
+}
@@ -0,0 +1,569 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// Every handler returns a `tonic::Status`, which is large by design.
+#![allow(clippy::result_large_err)]
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anise::prelude::{Almanac, Frame, Orbit};
+use indexmap::IndexMap;
+use tonic::{Request, Response, Status};
+
+use crate::cosmic::{DragData, Mass, SRPData};
+use crate::dynamics::{OrbitalDynamics, SolarPressure, SpacecraftDynamics};
+use crate::io::ConfigRepr;
+use crate::linalg::{SMatrix, SVector};
+use crate::md::objective::Objective;
+use crate::md::opti::targeter::Targeter;
+use crate::md::StateParameter;
+use crate::od::prelude::{
+    GroundStation, KfEstimate, Measurement, MeasurementType, ResidRejectCrit, TrackingArcSim,
+    TrackingDataArc, TrkConfig, KF,
+};
+use crate::od::SpacecraftODProcess;
+use crate::propagators::Propagator;
+use crate::time::{Epoch, Unit};
+use crate::{Spacecraft, State};
+
+/// Messages and service traits generated from `proto/nyx/v1/nyx.proto`.
+pub mod proto {
+    tonic::include_proto!("nyx.v1");
+}
+
+use proto::nyx_server::{Nyx, NyxServer};
+
+/// gRPC service exposing propagation, targeting, tracking simulation and orbit determination.
+///
+/// All of the computations run on the blocking thread pool of tokio with the almanac of the service.
+#[derive(Clone)]
+pub struct NyxService {
+    almanac: Arc<Almanac>,
+}
+
+impl NyxService {
+    /// Initializes a new service whose requests will be computed with the provided almanac.
+    pub fn new(almanac: Arc<Almanac>) -> Self {
+        Self { almanac }
+    }
+
+    /// Wraps this service into a server which can be added to a `tonic` router.
+    pub fn into_server(self) -> NyxServer<Self> {
+        NyxServer::new(self)
+    }
+
+    fn propagate(&self, req: proto::PropagateRequest) -> Result<proto::PropagateResponse, Status> {
+        let almanac = self.almanac.clone();
+        let sc = spacecraft_from_proto(req.initial_state, &almanac)?;
+        let end_epoch = epoch_from_proto(&req.end_epoch)?;
+        let dynamics = dynamics_from_proto(req.dynamics, sc.orbit.frame, almanac.clone())?;
+
+        let prop = Propagator::default(dynamics);
+        let mut instance = prop.with(sc, almanac);
+
+        if req.output_step_s > 0.0 {
+            let (final_state, traj) = instance
+                .until_epoch_with_traj(end_epoch)
+                .map_err(internal)?;
+
+            Ok(proto::PropagateResponse {
+                final_state: Some(spacecraft_to_proto(&final_state)),
+                trajectory: traj
+                    .every(req.output_step_s * Unit::Second)
+                    .map(|state| spacecraft_to_proto(&state))
+                    .collect(),
+            })
+        } else {
+            let final_state = instance.until_epoch(end_epoch).map_err(internal)?;
+
+            Ok(proto::PropagateResponse {
+                final_state: Some(spacecraft_to_proto(&final_state)),
+                trajectory: Vec::new(),
+            })
+        }
+    }
+
+    fn target(&self, req: proto::TargetRequest) -> Result<proto::TargetResponse, Status> {
+        let almanac = self.almanac.clone();
+        let sc = spacecraft_from_proto(req.initial_state, &almanac)?;
+        let achievement_epoch = epoch_from_proto(&req.achievement_epoch)?;
+        let dynamics = dynamics_from_proto(req.dynamics, sc.orbit.frame, almanac.clone())?;
+        let prop = Propagator::default(dynamics);
+
+        let objectives = req
+            .objectives
+            .iter()
+            .map(|obj| {
+                let param = StateParameter::from_str(&obj.parameter).map_err(invalid)?;
+                Ok(Objective::within_tolerance(
+                    param,
+                    obj.desired_value,
+                    obj.tolerance,
+                ))
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+
+        // The targeter is generic over the number of objectives, so dispatch on the supported counts.
+        macro_rules! solve {
+            ($objs:expr) => {{
+                let sol = Targeter::delta_v(&prop, $objs)
+                    .try_achieve_from(sc, sc.epoch(), achievement_epoch, almanac)
+                    .map_err(internal)?;
+                proto::TargetResponse {
+                    dv_x_km_s: sol.correction[0],
+                    dv_y_km_s: sol.correction[1],
+                    dv_z_km_s: sol.correction[2],
+                    corrected_state: Some(spacecraft_to_proto(&sol.corrected_state)),
+                    achieved_state: Some(spacecraft_to_proto(&sol.achieved_state)),
+                    achieved_errors: sol.achieved_errors.iter().copied().collect(),
+                    iterations: sol.iterations as u32,
+                }
+            }};
+        }
+
+        Ok(match objectives.as_slice() {
+            [a] => solve!([*a]),
+            [a, b] => solve!([*a, *b]),
+            [a, b, c] => solve!([*a, *b, *c]),
+            _ => {
+                return Err(Status::invalid_argument(format!(
+                    "between one and three objectives are supported, got {}",
+                    objectives.len()
+                )))
+            }
+        })
+    }
+
+    fn simulate_tracking(
+        &self,
+        req: proto::SimulateTrackingRequest,
+    ) -> Result<proto::SimulateTrackingResponse, Status> {
+        let almanac = self.almanac.clone();
+        let sc = spacecraft_from_proto(req.initial_state, &almanac)?;
+        let end_epoch = epoch_from_proto(&req.end_epoch)?;
+        let dynamics = dynamics_from_proto(req.dynamics, sc.orbit.frame, almanac.clone())?;
+
+        let devices = GroundStation::loads_named(&req.ground_stations_yaml).map_err(invalid)?;
+        let configs = TrkConfig::loads_named(&req.tracking_configs_yaml).map_err(invalid)?;
+
+        let (_, traj) = Propagator::default(dynamics)
+            .with(sc, almanac.clone())
+            .until_epoch_with_traj(end_epoch)
+            .map_err(internal)?;
+
+        let mut arc_sim = match req.seed {
+            Some(seed) => {
+                TrackingArcSim::<Spacecraft, GroundStation>::with_seed(devices, traj, configs, seed)
+            }
+            None => TrackingArcSim::<Spacecraft, GroundStation>::new(devices, traj, configs),
+        }
+        .map_err(invalid)?;
+
+        arc_sim.build_schedule(almanac.clone()).map_err(internal)?;
+        let arc = arc_sim.generate_measurements(almanac).map_err(internal)?;
+
+        Ok(proto::SimulateTrackingResponse {
+            measurements: arc
+                .measurements
+                .values()
+                .map(measurement_to_proto)
+                .collect(),
+        })
+    }
+
+    fn determine_orbit(
+        &self,
+        req: proto::DetermineOrbitRequest,
+    ) -> Result<proto::DetermineOrbitResponse, Status> {
+        let almanac = self.almanac.clone();
+        let sc = spacecraft_from_proto(req.initial_estimate, &almanac)?;
+        let dynamics = dynamics_from_proto(req.dynamics, sc.orbit.frame, almanac.clone())?;
+        let devices = GroundStation::loads_named(&req.ground_stations_yaml).map_err(invalid)?;
+
+        let mut arc = TrackingDataArc::default();
+        for msr in &req.measurements {
+            let msr = measurement_from_proto(msr)?;
+            arc.measurements.insert(msr.epoch, msr);
+        }
+
+        let pos_var = req.position_sigma_km.powi(2);
+        let vel_var = req.velocity_sigma_km_s.powi(2);
+        let covar = SMatrix::<f64, 9, 9>::from_diagonal(&SVector::<f64, 9>::from_iterator([
+            pos_var, pos_var, pos_var, vel_var, vel_var, vel_var, 0.0, 0.0, 0.0,
+        ]));
+
+        let initial_estimate = KfEstimate::from_covar(sc.with_stm(), covar);
+
+        let prop = Propagator::default(dynamics);
+        let mut odp = SpacecraftODProcess::ckf(
+            prop.with(sc.with_stm(), almanac.clone()),
+            KF::no_snc(initial_estimate),
+            devices,
+            Some(ResidRejectCrit::default()),
+            almanac,
+        );
+
+        odp.process_arc(&arc).map_err(internal)?;
+
+        let estimates = odp
+            .estimates
+            .iter()
+            .map(|est| {
+                let covar = est.covar.fixed_view::<6, 6>(0, 0);
+                proto::Estimate {
+                    state: Some(spacecraft_to_proto(&est.nominal_state)),
+                    covariance: covar.transpose().iter().copied().collect(),
+                }
+            })
+            .collect();
+
+        let residuals = odp.residuals.iter().flatten();
+
+        Ok(proto::DetermineOrbitResponse {
+            estimates,
+            residual_ratios: residuals.clone().map(|resid| resid.ratio).collect(),
+            rejected_measurements: residuals.filter(|resid| resid.rejected).count() as u32,
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl Nyx for NyxService {
+    async fn propagate(
+        &self,
+        request: Request<proto::PropagateRequest>,
+    ) -> Result<Response<proto::PropagateResponse>, Status> {
+        let me = self.clone();
+        blocking(move || me.propagate(request.into_inner())).await
+    }
+
+    async fn target(
+        &self,
+        request: Request<proto::TargetRequest>,
+    ) -> Result<Response<proto::TargetResponse>, Status> {
+        let me = self.clone();
+        blocking(move || me.target(request.into_inner())).await
+    }
+
+    async fn simulate_tracking(
+        &self,
+        request: Request<proto::SimulateTrackingRequest>,
+    ) -> Result<Response<proto::SimulateTrackingResponse>, Status> {
+        let me = self.clone();
+        blocking(move || me.simulate_tracking(request.into_inner())).await
+    }
+
+    async fn determine_orbit(
+        &self,
+        request: Request<proto::DetermineOrbitRequest>,
+    ) -> Result<Response<proto::DetermineOrbitResponse>, Status> {
+        let me = self.clone();
+        blocking(move || me.determine_orbit(request.into_inner())).await
+    }
+}
+
+/// Runs the computation on the blocking thread pool so that the server remains responsive.
+async fn blocking<T, F>(f: F) -> Result<Response<T>, Status>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Status> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(internal)?
+        .map(Response::new)
+}
+
+fn invalid<E: std::fmt::Display>(e: E) -> Status {
+    Status::invalid_argument(e.to_string())
+}
+
+fn internal<E: std::fmt::Display>(e: E) -> Status {
+    Status::internal(e.to_string())
+}
+
+fn epoch_from_proto(epoch: &str) -> Result<Epoch, Status> {
+    Epoch::from_str(epoch).map_err(|e| invalid(format!("{epoch}: {e}")))
+}
+
+fn orbit_from_proto(
+    state: Option<proto::CartesianState>,
+    almanac: &Almanac,
+) -> Result<Orbit, Status> {
+    let state = state.ok_or_else(|| Status::invalid_argument("missing orbit"))?;
+    let epoch = epoch_from_proto(&state.epoch)?;
+
+    let frame = Frame::new(state.center_id, state.orientation_id);
+    let frame = match state.mu_km3_s2 {
+        Some(mu_km3_s2) => frame.with_mu_km3_s2(mu_km3_s2),
+        None => almanac.frame_from_uid(frame).map_err(invalid)?,
+    };
+
+    Ok(Orbit::new(
+        state.x_km,
+        state.y_km,
+        state.z_km,
+        state.vx_km_s,
+        state.vy_km_s,
+        state.vz_km_s,
+        epoch,
+        frame,
+    ))
+}
+
+fn orbit_to_proto(orbit: &Orbit) -> proto::CartesianState {
+    proto::CartesianState {
+        epoch: orbit.epoch.to_string(),
+        center_id: orbit.frame.ephemeris_id,
+        orientation_id: orbit.frame.orientation_id,
+        x_km: orbit.radius_km.x,
+        y_km: orbit.radius_km.y,
+        z_km: orbit.radius_km.z,
+        vx_km_s: orbit.velocity_km_s.x,
+        vy_km_s: orbit.velocity_km_s.y,
+        vz_km_s: orbit.velocity_km_s.z,
+        mu_km3_s2: orbit.frame.mu_km3_s2,
+    }
+}
+
+fn spacecraft_from_proto(
+    state: Option<proto::SpacecraftState>,
+    almanac: &Almanac,
+) -> Result<Spacecraft, Status> {
+    let state = state.ok_or_else(|| Status::invalid_argument("missing spacecraft state"))?;
+
+    Ok(Spacecraft::builder()
+        .orbit(orbit_from_proto(state.orbit, almanac)?)
+        .mass(Mass::from_dry_and_prop_masses(
+            state.dry_mass_kg,
+            state.prop_mass_kg,
+        ))
+        .srp(SRPData {
+            area_m2: state.srp_area_m2,
+            coeff_reflectivity: state.coeff_reflectivity,
+        })
+        .drag(DragData {
+            area_m2: state.drag_area_m2,
+            coeff_drag: state.coeff_drag,
+        })
+        .build())
+}
+
+fn spacecraft_to_proto(sc: &Spacecraft) -> proto::SpacecraftState {
+    proto::SpacecraftState {
+        orbit: Some(orbit_to_proto(&sc.orbit)),
+        dry_mass_kg: sc.mass.dry_mass_kg,
+        prop_mass_kg: sc.mass.prop_mass_kg,
+        srp_area_m2: sc.srp.area_m2,
+        coeff_reflectivity: sc.srp.coeff_reflectivity,
+        drag_area_m2: sc.drag.area_m2,
+        coeff_drag: sc.drag.coeff_drag,
+    }
+}
+
+fn dynamics_from_proto(
+    dynamics: Option<proto::Dynamics>,
+    center: Frame,
+    almanac: Arc<Almanac>,
+) -> Result<SpacecraftDynamics, Status> {
+    let dynamics = dynamics.unwrap_or_default();
+
+    let orbital_dyn = if dynamics.point_masses.is_empty() {
+        OrbitalDynamics::two_body()
+    } else {
+        OrbitalDynamics::point_masses(dynamics.point_masses)
+    };
+
+    if dynamics.solar_radiation_pressure {
+        let srp = SolarPressure::new(vec![center], almanac).map_err(invalid)?;
+        Ok(SpacecraftDynamics::from_model(orbital_dyn, srp))
+    } else {
+        Ok(SpacecraftDynamics::new(orbital_dyn))
+    }
+}
+
+fn measurement_from_proto(msr: &proto::Measurement) -> Result<Measurement, Status> {
+    let mut data = IndexMap::new();
+    for (name, value) in &msr.data {
+        let msr_type: MeasurementType =
+            serde_json::from_value(serde_json::Value::String(name.clone()))
+                .map_err(|_| invalid(format!("unknown measurement type {name}")))?;
+        data.insert(msr_type, *value);
+    }
+
+    Ok(Measurement {
+        tracker: msr.tracker.clone(),
+        epoch: epoch_from_proto(&msr.epoch)?,
+        data,
+    })
+}
+
+fn measurement_to_proto(msr: &Measurement) -> proto::Measurement {
+    proto::Measurement {
+        tracker: msr.tracker.clone(),
+        epoch: msr.epoch.to_string(),
+        data: msr
+            .data
+            .iter()
+            .map(|(msr_type, value)| {
+                let name = serde_json::to_value(msr_type)
+                    .ok()
+                    .and_then(|v| v.as_str().map(str::to_string))
+                    .unwrap_or_else(|| format!("{msr_type:?}"));
+                (name, *value)
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod ut_grpc {
+    use super::*;
+    use crate::fixtures;
+    use crate::time::TimeUnits;
+    use crate::GMAT_EARTH_GM;
+
+    fn leo() -> proto::SpacecraftState {
+        proto::SpacecraftState {
+            orbit: Some(proto::CartesianState {
+                epoch: "2024-01-01T00:00:00 UTC".to_string(),
+                center_id: 399,
+                orientation_id: 1,
+                x_km: 7_000.0,
+                y_km: 0.0,
+                z_km: 0.0,
+                vx_km_s: 0.0,
+                vy_km_s: 7.546_049_108,
+                vz_km_s: 0.0,
+                mu_km3_s2: Some(GMAT_EARTH_GM),
+            }),
+            dry_mass_kg: 100.0,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn propagate_two_body() {
+        let service = NyxService::new(fixtures::almanac());
+        let orbit = orbit_from_proto(leo().orbit, &Almanac::default()).unwrap();
+        let period = orbit.period().unwrap();
+
+        let resp = Nyx::propagate(
+            &service,
+            Request::new(proto::PropagateRequest {
+                initial_state: Some(leo()),
+                dynamics: None,
+                end_epoch: (orbit.epoch + period).to_string(),
+                output_step_s: 60.0,
+            }),
+        )
+        .await
+        .unwrap()
+        .into_inner();
+
+        let final_state =
+            orbit_from_proto(resp.final_state.unwrap().orbit, &Almanac::default()).unwrap();
+
+        // After one period, the orbit is back where it started.
+        assert!((final_state.radius_km - orbit.radius_km).norm() < 1e-3);
+        assert!(resp.trajectory.len() as f64 >= (period.to_seconds() / 60.0).floor());
+    }
+
+    #[tokio::test]
+    async fn target_sma() {
+        let service = NyxService::new(fixtures::almanac());
+        let orbit = orbit_from_proto(leo().orbit, &Almanac::default()).unwrap();
+
+        let resp = Nyx::target(
+            &service,
+            Request::new(proto::TargetRequest {
+                initial_state: Some(leo()),
+                dynamics: None,
+                achievement_epoch: (orbit.epoch + 30.minutes()).to_string(),
+                objectives: vec![proto::Objective {
+                    parameter: "sma".to_string(),
+                    desired_value: 7_100.0,
+                    tolerance: 0.1,
+                }],
+            }),
+        )
+        .await
+        .unwrap()
+        .into_inner();
+
+        assert!(resp.achieved_errors[0].abs() < 0.1);
+        assert!(resp.dv_y_km_s.abs() > 0.0);
+    }
+
+    #[tokio::test]
+    async fn invalid_requests() {
+        let service = NyxService::new(fixtures::almanac());
+
+        let err = Nyx::propagate(
+            &service,
+            Request::new(proto::PropagateRequest {
+                initial_state: Some(leo()),
+                dynamics: None,
+                end_epoch: "not an epoch".to_string(),
+                output_step_s: 0.0,
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+
+        let err = Nyx::target(
+            &service,
+            Request::new(proto::TargetRequest {
+                initial_state: Some(leo()),
+                dynamics: None,
+                achievement_epoch: "2024-01-01T01:00:00 UTC".to_string(),
+                objectives: vec![],
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[test]
+    fn measurement_round_trip() {
+        let msr = proto::Measurement {
+            tracker: "DSS-65".to_string(),
+            epoch: "2024-01-01T00:00:00 UTC".to_string(),
+            data: [
+                ("range_km".to_string(), 1_234.5),
+                ("doppler_km_s".to_string(), -0.5),
+            ]
+            .into_iter()
+            .collect(),
+        };
+
+        let nyx_msr = measurement_from_proto(&msr).unwrap();
+        assert_eq!(nyx_msr.data[&MeasurementType::Range], 1_234.5);
+        assert_eq!(nyx_msr.data[&MeasurementType::Doppler], -0.5);
+        assert_eq!(measurement_to_proto(&nyx_msr).data, msr.data);
+
+        let bad = proto::Measurement {
+            data: [("range".to_string(), 1.0)].into_iter().collect(),
+            ..msr
+        };
+        assert!(measurement_from_proto(&bad).is_err());
+    }
+}
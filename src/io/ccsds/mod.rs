@@ -0,0 +1,115 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::errors::NyxError;
+use crate::time::{Epoch, Format, Formatter, Unit};
+use anise::constants::orientations::J2000;
+use anise::prelude::Frame;
+use std::str::FromStr;
+
+mod omm;
+pub use omm::{Omm, OmmTleParameters};
+
+mod opm;
+pub use opm::{Opm, OpmManeuver};
+
+/// Splits a line of a CCSDS message in the Keyword = Value Notation (KVN) into its keyword and value, without the units.
+/// Returns None for empty lines and comments.
+pub(crate) fn kvn_pair(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with("COMMENT") {
+        return None;
+    }
+    let (keyword, value) = line.split_once('=')?;
+    // Drop the optional units, e.g. `6655.9942 [km]`
+    let value = match value.find('[') {
+        Some(idx) => &value[..idx],
+        None => value,
+    };
+    Some((keyword.trim(), value.trim()))
+}
+
+/// Parses the value of this keyword as a float.
+pub(crate) fn kvn_f64(keyword: &str, value: &str) -> Result<f64, NyxError> {
+    value.parse::<f64>().map_err(|e| NyxError::CCSDS {
+        msg: format!("could not parse {keyword} = `{value}`: {e}"),
+    })
+}
+
+/// Parses an epoch of a CCSDS message in the provided time system, either in the calendar format (e.g. `2021-06-03T09:00:34.1`)
+/// or in the day of year format (e.g. `2007-064T10:34:41.4264`).
+pub(crate) fn kvn_epoch(value: &str, time_system: &str) -> Result<Epoch, NyxError> {
+    let err = |e: String| NyxError::CCSDS {
+        msg: format!("could not parse epoch `{value}` in {time_system}: {e}"),
+    };
+    match value.split_once('T') {
+        Some((date, time)) if date.len() == 8 && date.as_bytes()[4] == b'-' => {
+            let day_of_year = date[5..].parse::<i64>().map_err(|e| err(e.to_string()))?;
+            let first_day = Epoch::from_str(&format!("{}-01-01T{time} {time_system}", &date[..4]))
+                .map_err(|e| err(e.to_string()))?;
+            Ok(first_day + (day_of_year - 1) * Unit::Day)
+        }
+        _ => {
+            Epoch::from_str(format!("{value} {time_system}").trim()).map_err(|e| err(e.to_string()))
+        }
+    }
+}
+
+/// Formats this epoch as a CCSDS epoch, in its own time system.
+pub(crate) fn ccsds_epoch(epoch: Epoch) -> String {
+    format!(
+        "{}",
+        Formatter::new(epoch, Format::from_str("%Y-%m-%dT%H:%M:%S.%f").unwrap())
+    )
+}
+
+/// Returns the CCSDS center name and reference frame of this frame.
+pub(crate) fn ccsds_frame_names(frame: Frame) -> (String, String) {
+    let center = format!("{frame:e}").to_uppercase();
+    let ref_frame = match frame.orientation_id {
+        J2000 => "ICRF".to_string(),
+        _ => format!("{frame:o}"),
+    };
+    (center, ref_frame)
+}
+
+/// Builds the frame from its CCSDS center name and reference frame, e.g. `EARTH` and `EME2000`.
+/// The EME2000 and GCRF reference frames are treated as the ICRF, like the J2000 frame of Nyx.
+pub(crate) fn ccsds_frame(center: &str, ref_frame: &str) -> Result<Frame, NyxError> {
+    // The CCSDS names are uppercase, but the celestial names are capitalized, e.g. `Earth-Moon Barycenter`
+    let mut capitalize = true;
+    let center_name = center
+        .chars()
+        .map(|c| {
+            let c_out = if capitalize {
+                c.to_ascii_uppercase()
+            } else {
+                c.to_ascii_lowercase()
+            };
+            capitalize = c == ' ' || c == '-';
+            c_out
+        })
+        .collect::<String>();
+    let orientation = match ref_frame {
+        "EME2000" | "GCRF" => "J2000",
+        _ => ref_frame,
+    };
+    Frame::from_name(&center_name, orientation).map_err(|e| NyxError::CCSDS {
+        msg: format!("frame error `{center} {ref_frame}`: {e}"),
+    })
+}
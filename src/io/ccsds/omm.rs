@@ -0,0 +1,455 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{ccsds_epoch, kvn_epoch, kvn_f64, kvn_pair};
+use crate::errors::NyxError;
+use crate::io::watermark::prj_name_ver;
+use crate::propagators::{Tle, MU_KM3_S2};
+use crate::time::Epoch;
+use log::debug;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::{read_to_string, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// TLE related parameters of an Orbit Mean-Elements Message, required by the SGP4 theory.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OmmTleParameters {
+    /// Ephemeris type, always zero in the published element sets
+    pub ephemeris_type: u8,
+    /// Security classification, typically `U`
+    pub classification: char,
+    pub norad_cat_id: u32,
+    pub element_set_no: u32,
+    pub rev_at_epoch: u32,
+    /// B* drag term, in inverse Earth radii
+    pub bstar: f64,
+    /// First derivative of the mean motion divided by two, in revolutions per day squared
+    pub mean_motion_dot: f64,
+    /// Second derivative of the mean motion divided by six, in revolutions per day cubed
+    pub mean_motion_ddot: f64,
+}
+
+/// A CCSDS Orbit Mean-Elements Message (OMM), in the Keyword = Value Notation (KVN), as distributed by the catalog providers.
+///
+/// The mean elements are only meaningful in their mean element theory, e.g. SGP4: use [Omm::to_tle] to propagate them with [crate::propagators::Sgp4].
+/// The spacecraft parameters and the covariance of the message are ignored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Omm {
+    pub object_name: String,
+    /// International designator, e.g. `1998-067A`
+    pub object_id: String,
+    pub originator: String,
+    pub center_name: String,
+    /// Reference frame of the mean elements, e.g. `TEME` for SGP4
+    pub ref_frame: String,
+    /// Theory of the mean elements, e.g. `SGP4`
+    pub mean_element_theory: String,
+    pub epoch: Epoch,
+    /// Mean motion, in revolutions per day, used instead of the semi-major axis by the SGP/SGP4 theories
+    pub mean_motion_rev_day: Option<f64>,
+    pub sma_km: Option<f64>,
+    pub ecc: f64,
+    pub inclination_deg: f64,
+    pub raan_deg: f64,
+    pub aop_deg: f64,
+    pub mean_anomaly_deg: f64,
+    pub gm_km3_s2: Option<f64>,
+    pub tle_parameters: Option<OmmTleParameters>,
+}
+
+impl Omm {
+    /// Builds the OMM of this element set, in the SGP4 theory with the WGS-72 gravitational parameter.
+    pub fn from_tle(tle: &Tle) -> Self {
+        // The two digit launch year of the TLE follows the same convention as its epoch
+        let object_id = match tle.intl_designator.get(..2).map(|yy| yy.parse::<u32>()) {
+            Some(Ok(yy)) if tle.intl_designator.len() > 2 => {
+                let year = if yy < 57 { 2000 + yy } else { 1900 + yy };
+                format!("{year}-{}", &tle.intl_designator[2..])
+            }
+            _ => tle.intl_designator.clone(),
+        };
+
+        Self {
+            object_name: tle
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("{}", tle.catalog_number)),
+            object_id,
+            originator: "Nyx Space".to_string(),
+            center_name: "EARTH".to_string(),
+            ref_frame: "TEME".to_string(),
+            mean_element_theory: "SGP4".to_string(),
+            epoch: tle.epoch,
+            mean_motion_rev_day: Some(tle.mean_motion_rev_day),
+            sma_km: None,
+            ecc: tle.ecc,
+            inclination_deg: tle.inclination_deg,
+            raan_deg: tle.raan_deg,
+            aop_deg: tle.aop_deg,
+            mean_anomaly_deg: tle.mean_anomaly_deg,
+            gm_km3_s2: Some(MU_KM3_S2),
+            tle_parameters: Some(OmmTleParameters {
+                ephemeris_type: 0,
+                classification: tle.classification,
+                norad_cat_id: tle.catalog_number,
+                element_set_no: tle.element_set_number,
+                rev_at_epoch: tle.rev_number,
+                bstar: tle.bstar,
+                mean_motion_dot: tle.mean_motion_dot,
+                mean_motion_ddot: tle.mean_motion_ddot,
+            }),
+        }
+    }
+
+    /// Converts this OMM into an element set, which requires the SGP or SGP4 theory, the mean motion and the TLE parameters.
+    pub fn to_tle(&self) -> Result<Tle, NyxError> {
+        if !self.mean_element_theory.contains("SGP") {
+            return Err(NyxError::CCSDS {
+                msg: format!(
+                    "{} mean elements cannot be converted to a TLE",
+                    self.mean_element_theory
+                ),
+            });
+        }
+        let params = self.tle_parameters.ok_or(NyxError::CCSDS {
+            msg: "the TLE parameters are required to build a TLE".to_string(),
+        })?;
+        let mean_motion_rev_day = self.mean_motion_rev_day.ok_or(NyxError::CCSDS {
+            msg: "the mean motion is required to build a TLE".to_string(),
+        })?;
+
+        // E.g. 1998-067A is 98067A in a TLE
+        let intl_designator = match self.object_id.split_once('-') {
+            Some((year, piece)) if year.len() == 4 => format!("{}{piece}", &year[2..]),
+            _ => self.object_id.clone(),
+        };
+
+        Ok(Tle {
+            name: Some(self.object_name.clone()),
+            catalog_number: params.norad_cat_id,
+            classification: params.classification,
+            intl_designator,
+            epoch: self.epoch,
+            mean_motion_dot: params.mean_motion_dot,
+            mean_motion_ddot: params.mean_motion_ddot,
+            bstar: params.bstar,
+            element_set_number: params.element_set_no,
+            inclination_deg: self.inclination_deg,
+            raan_deg: self.raan_deg,
+            ecc: self.ecc,
+            aop_deg: self.aop_deg,
+            mean_anomaly_deg: self.mean_anomaly_deg,
+            mean_motion_rev_day,
+            rev_number: params.rev_at_epoch,
+        })
+    }
+
+    /// Reads the OMM from the provided file, cf. [Omm::from_kvn].
+    pub fn from_omm_file<P: AsRef<Path>>(path: P) -> Result<Self, NyxError> {
+        let kvn = read_to_string(path).map_err(|e| NyxError::CCSDS {
+            msg: format!("File read error: {e}"),
+        })?;
+        Self::from_kvn(&kvn)
+    }
+
+    /// Parses an OMM in the KVN format.
+    pub fn from_kvn(kvn: &str) -> Result<Self, NyxError> {
+        let values = kvn
+            .lines()
+            .filter_map(kvn_pair)
+            .collect::<HashMap<&str, &str>>();
+
+        Self::from_keywords(&values)
+    }
+
+    /// Parses a catalog of concatenated OMMs in the KVN format, each starting with its `CCSDS_OMM_VERS` keyword.
+    pub fn from_catalog(kvn: &str) -> Result<Vec<Self>, NyxError> {
+        let mut omms = Vec::new();
+        let mut values = HashMap::new();
+        for (keyword, value) in kvn.lines().filter_map(kvn_pair) {
+            if keyword == "CCSDS_OMM_VERS" && !values.is_empty() {
+                omms.push(Self::from_keywords(&values)?);
+                values.clear();
+            }
+            values.insert(keyword, value);
+        }
+        if !values.is_empty() {
+            omms.push(Self::from_keywords(&values)?);
+        }
+        Ok(omms)
+    }
+
+    /// Builds the OMM from the values of its keywords.
+    pub(crate) fn from_keywords(values: &HashMap<&str, &str>) -> Result<Self, NyxError> {
+        let text = |keyword: &str| -> Result<&str, NyxError> {
+            values.get(keyword).copied().ok_or(NyxError::CCSDS {
+                msg: format!("missing {keyword} in OMM"),
+            })
+        };
+        let float = |keyword: &str| -> Result<f64, NyxError> { kvn_f64(keyword, text(keyword)?) };
+        let optional = |keyword: &str| -> Result<Option<f64>, NyxError> {
+            values
+                .get(keyword)
+                .map(|value| kvn_f64(keyword, value))
+                .transpose()
+        };
+        let integer = |keyword: &str| -> Result<u32, NyxError> {
+            let value = text(keyword)?;
+            value.parse::<u32>().map_err(|e| NyxError::CCSDS {
+                msg: format!("could not parse {keyword} = `{value}`: {e}"),
+            })
+        };
+
+        let version = text("CCSDS_OMM_VERS")?;
+        if !["1", "2", "3"].iter().any(|v| version.starts_with(v)) {
+            return Err(NyxError::CCSDS {
+                msg: format!("unsupported OMM version {version}"),
+            });
+        }
+
+        let tle_parameters = if values.contains_key("NORAD_CAT_ID") {
+            Some(OmmTleParameters {
+                ephemeris_type: values
+                    .get("EPHEMERIS_TYPE")
+                    .and_then(|value| value.parse::<u8>().ok())
+                    .unwrap_or(0),
+                classification: values
+                    .get("CLASSIFICATION_TYPE")
+                    .and_then(|value| value.chars().next())
+                    .unwrap_or('U'),
+                norad_cat_id: integer("NORAD_CAT_ID")?,
+                element_set_no: integer("ELEMENT_SET_NO").unwrap_or(0),
+                rev_at_epoch: integer("REV_AT_EPOCH").unwrap_or(0),
+                bstar: optional("BSTAR")?.unwrap_or(0.0),
+                mean_motion_dot: optional("MEAN_MOTION_DOT")?.unwrap_or(0.0),
+                mean_motion_ddot: optional("MEAN_MOTION_DDOT")?.unwrap_or(0.0),
+            })
+        } else {
+            debug!("OMM without TLE parameters");
+            None
+        };
+
+        let omm = Self {
+            object_name: text("OBJECT_NAME")?.to_string(),
+            object_id: text("OBJECT_ID")?.to_string(),
+            originator: text("ORIGINATOR").unwrap_or("").to_string(),
+            center_name: text("CENTER_NAME")?.to_string(),
+            ref_frame: text("REF_FRAME")?.to_string(),
+            mean_element_theory: text("MEAN_ELEMENT_THEORY")?.to_string(),
+            epoch: kvn_epoch(text("EPOCH")?, text("TIME_SYSTEM")?)?,
+            mean_motion_rev_day: optional("MEAN_MOTION")?,
+            sma_km: optional("SEMI_MAJOR_AXIS")?,
+            ecc: float("ECCENTRICITY")?,
+            inclination_deg: float("INCLINATION")?,
+            raan_deg: float("RA_OF_ASC_NODE")?,
+            aop_deg: float("ARG_OF_PERICENTER")?,
+            mean_anomaly_deg: float("MEAN_ANOMALY")?,
+            gm_km3_s2: optional("GM")?,
+            tle_parameters,
+        };
+
+        if omm.mean_motion_rev_day.is_none() && omm.sma_km.is_none() {
+            return Err(NyxError::CCSDS {
+                msg: "either MEAN_MOTION or SEMI_MAJOR_AXIS is required in OMM".to_string(),
+            });
+        }
+
+        Ok(omm)
+    }
+
+    /// Formats this OMM in the KVN format.
+    pub fn to_kvn(&self) -> String {
+        // Writing to a string cannot fail
+        let mut kvn = String::new();
+        writeln!(kvn, "CCSDS_OMM_VERS = 2.0").unwrap();
+        writeln!(
+            kvn,
+            "COMMENT Built by {} -- https://nyxspace.com/",
+            prj_name_ver()
+        )
+        .unwrap();
+        writeln!(
+            kvn,
+            "CREATION_DATE = {}",
+            ccsds_epoch(Epoch::now().unwrap())
+        )
+        .unwrap();
+        writeln!(kvn, "ORIGINATOR = {}\n", self.originator).unwrap();
+
+        writeln!(kvn, "OBJECT_NAME = {}", self.object_name).unwrap();
+        writeln!(kvn, "OBJECT_ID = {}", self.object_id).unwrap();
+        writeln!(kvn, "CENTER_NAME = {}", self.center_name).unwrap();
+        writeln!(kvn, "REF_FRAME = {}", self.ref_frame).unwrap();
+        writeln!(kvn, "TIME_SYSTEM = {}", self.epoch.time_scale).unwrap();
+        writeln!(kvn, "MEAN_ELEMENT_THEORY = {}\n", self.mean_element_theory).unwrap();
+
+        writeln!(kvn, "EPOCH = {}", ccsds_epoch(self.epoch)).unwrap();
+        if let Some(sma_km) = self.sma_km {
+            writeln!(kvn, "SEMI_MAJOR_AXIS = {sma_km:e} [km]").unwrap();
+        }
+        if let Some(mean_motion) = self.mean_motion_rev_day {
+            writeln!(kvn, "MEAN_MOTION = {mean_motion:e} [rev/day]").unwrap();
+        }
+        writeln!(kvn, "ECCENTRICITY = {:e}", self.ecc).unwrap();
+        writeln!(kvn, "INCLINATION = {:e} [deg]", self.inclination_deg).unwrap();
+        writeln!(kvn, "RA_OF_ASC_NODE = {:e} [deg]", self.raan_deg).unwrap();
+        writeln!(kvn, "ARG_OF_PERICENTER = {:e} [deg]", self.aop_deg).unwrap();
+        writeln!(kvn, "MEAN_ANOMALY = {:e} [deg]", self.mean_anomaly_deg).unwrap();
+        if let Some(gm) = self.gm_km3_s2 {
+            writeln!(kvn, "GM = {gm:e} [km**3/s**2]").unwrap();
+        }
+
+        if let Some(params) = &self.tle_parameters {
+            writeln!(kvn, "\nEPHEMERIS_TYPE = {}", params.ephemeris_type).unwrap();
+            writeln!(kvn, "CLASSIFICATION_TYPE = {}", params.classification).unwrap();
+            writeln!(kvn, "NORAD_CAT_ID = {}", params.norad_cat_id).unwrap();
+            writeln!(kvn, "ELEMENT_SET_NO = {}", params.element_set_no).unwrap();
+            writeln!(kvn, "REV_AT_EPOCH = {}", params.rev_at_epoch).unwrap();
+            writeln!(kvn, "BSTAR = {:e} [1/ER]", params.bstar).unwrap();
+            writeln!(
+                kvn,
+                "MEAN_MOTION_DOT = {:e} [rev/day**2]",
+                params.mean_motion_dot
+            )
+            .unwrap();
+            writeln!(
+                kvn,
+                "MEAN_MOTION_DDOT = {:e} [rev/day**3]",
+                params.mean_motion_ddot
+            )
+            .unwrap();
+        }
+
+        kvn
+    }
+
+    /// Writes this OMM to the provided file in the KVN format.
+    pub fn to_omm_file<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, NyxError> {
+        let path_buf = path.as_ref().to_path_buf();
+        let mut file = File::create(&path_buf).map_err(|e| NyxError::CCSDS {
+            msg: format!("File creation error: {e}"),
+        })?;
+        file.write_all(self.to_kvn().as_bytes())
+            .map_err(|e| NyxError::CCSDS {
+                msg: format!("Could not write: {e}"),
+            })?;
+        Ok(path_buf)
+    }
+}
+
+#[cfg(test)]
+mod ut_omm {
+    use super::*;
+    use crate::propagators::Sgp4;
+    use crate::time::Unit;
+    use anise::constants::frames::EARTH_J2000;
+
+    #[test]
+    fn read_goes9_omm() {
+        let path: PathBuf = [
+            env!("CARGO_MANIFEST_DIR"),
+            "data",
+            "tests",
+            "ccsds",
+            "odm",
+            "OMMExample_goes9.omm",
+        ]
+        .iter()
+        .collect();
+
+        let omm = Omm::from_omm_file(path).unwrap();
+        assert_eq!(omm.object_name, "GOES 9");
+        assert_eq!(omm.mean_element_theory, "SGP/SGP4");
+        // Day 64 of 2007 is March 5th
+        assert_eq!(
+            omm.epoch,
+            Epoch::from_gregorian_utc(2007, 3, 5, 10, 34, 41, 426_400_000)
+        );
+        assert_eq!(omm.mean_motion_rev_day, Some(1.00273272));
+        assert_eq!(omm.gm_km3_s2, Some(398_600.8));
+
+        let params = omm.tle_parameters.unwrap();
+        assert_eq!(params.norad_cat_id, 23581);
+        assert_eq!(params.element_set_no, 925);
+        assert_eq!(params.rev_at_epoch, 4316);
+        assert_eq!(params.mean_motion_dot, -0.00000113);
+
+        // Propagate the mean elements with SGP4
+        let tle = omm.to_tle().unwrap();
+        assert_eq!(tle.intl_designator, "95025A");
+        let sgp4 = Sgp4::new(&tle).unwrap();
+        assert!(sgp4.is_deep_space());
+        let orbit = sgp4.at(omm.epoch + Unit::Hour * 6, EARTH_J2000).unwrap();
+        // Geostationary
+        assert!((orbit.rmag_km() - 42_164.0).abs() < 100.0);
+    }
+
+    #[test]
+    fn omm_catalog() {
+        let tle = Tle::from_lines(
+            "1 25544U 98067A   08264.51782528 -.00002182  00000-0 -11606-4 0  2927",
+            "2 25544  51.6416 247.4627 0006703 130.5360 325.0288 15.72125391563537",
+        )
+        .unwrap();
+
+        let iss = Omm::from_tle(&tle);
+        let other = Omm {
+            object_name: "OTHER".to_string(),
+            ..iss.clone()
+        };
+        let catalog = format!("{}\n{}", iss.to_kvn(), other.to_kvn());
+
+        let omms = Omm::from_catalog(&catalog).unwrap();
+        assert_eq!(omms, vec![iss, other]);
+    }
+
+    #[test]
+    fn omm_tle_round_trip() {
+        let tle = Tle::from_lines(
+            "1 25544U 98067A   08264.51782528 -.00002182  00000-0 -11606-4 0  2927",
+            "2 25544  51.6416 247.4627 0006703 130.5360 325.0288 15.72125391563537",
+        )
+        .unwrap();
+
+        let omm = Omm::from_tle(&tle);
+        assert_eq!(omm.object_id, "1998-067A");
+
+        let path: PathBuf = [
+            env!("CARGO_MANIFEST_DIR"),
+            "output_data",
+            "omm_round_trip.omm",
+        ]
+        .iter()
+        .collect();
+        let path = omm.to_omm_file(path).unwrap();
+        let read = Omm::from_omm_file(path).unwrap();
+        assert_eq!(read, omm);
+
+        let read_tle = read.to_tle().unwrap();
+        assert_eq!(read_tle.to_lines(), tle.to_lines());
+
+        // Only the SGP theories can be converted to a TLE
+        let dsst = Omm {
+            mean_element_theory: "DSST".to_string(),
+            ..omm
+        };
+        assert!(dsst.to_tle().is_err());
+    }
+}
@@ -0,0 +1,520 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{ccsds_epoch, ccsds_frame, ccsds_frame_names, kvn_epoch, kvn_f64, kvn_pair};
+use crate::cosmic::{Orbit, Spacecraft};
+use crate::dynamics::guidance::{LocalFrame, Maneuver};
+use crate::errors::NyxError;
+use crate::io::watermark::prj_name_ver;
+use crate::linalg::{Matrix6, Vector3};
+use crate::time::{Duration, Epoch, Unit};
+use log::debug;
+use std::fmt::Write as _;
+use std::fs::{read_to_string, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Names of the Cartesian components in the covariance keywords of the CCSDS messages, e.g. `CY_DOT_X`
+const COV_NAMES: [&str; 6] = ["X", "Y", "Z", "X_DOT", "Y_DOT", "Z_DOT"];
+
+/// A maneuver of an Orbit Parameter Message.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OpmManeuver {
+    pub epoch_ignition: Epoch,
+    /// Duration of the maneuver, zero for an impulsive maneuver
+    pub duration: Duration,
+    /// Change of mass of the spacecraft, negative when propellant is consumed, in kg
+    pub delta_mass_kg: f64,
+    /// Frame of the delta-v: the RTN frame (i.e. [LocalFrame::RIC]) or the inertial frame of the orbit
+    pub frame: LocalFrame,
+    pub dv_km_s: Vector3<f64>,
+}
+
+impl OpmManeuver {
+    /// Converts this maneuver into a Nyx maneuver: impulsive with this delta-v if its duration is zero, and otherwise a
+    /// finite burn at full thrust in the direction of this delta-v.
+    pub fn to_maneuver(&self) -> Maneuver {
+        if self.duration == Duration::ZERO {
+            Maneuver::from_impulsive(self.epoch_ignition, self.dv_km_s, self.frame)
+        } else {
+            Maneuver::from_time_invariant(
+                self.epoch_ignition,
+                self.epoch_ignition + self.duration,
+                1.0,
+                self.dv_km_s.normalize(),
+                self.frame,
+            )
+        }
+    }
+}
+
+/// A CCSDS Orbit Parameter Message (OPM), in the Keyword = Value Notation (KVN), holding a single spacecraft state with
+/// its optional covariance and maneuvers.
+///
+/// The Keplerian elements of the message are ignored when reading it since they duplicate the Cartesian state, apart
+/// from the gravitational parameter which is set on the frame of the orbit.
+#[derive(Clone, Debug)]
+pub struct Opm {
+    pub object_name: String,
+    pub object_id: String,
+    pub originator: String,
+    pub spacecraft: Spacecraft,
+    /// Cartesian covariance of the orbit, in km and km/s, in the frame of the orbit
+    pub covariance: Option<Matrix6<f64>>,
+    pub maneuvers: Vec<OpmManeuver>,
+}
+
+impl Opm {
+    /// Initializes an OPM of this spacecraft, without covariance nor maneuvers, using its name as the object ID.
+    pub fn new(object_name: String, spacecraft: Spacecraft) -> Self {
+        Self {
+            object_id: object_name.clone(),
+            object_name,
+            originator: "Nyx Space".to_string(),
+            spacecraft,
+            covariance: None,
+            maneuvers: Vec::new(),
+        }
+    }
+
+    /// Reads the OPM from the provided file, cf. [Opm::from_kvn].
+    pub fn from_opm_file<P: AsRef<Path>>(
+        path: P,
+        template: Option<Spacecraft>,
+    ) -> Result<Self, NyxError> {
+        let kvn = read_to_string(path).map_err(|e| NyxError::CCSDS {
+            msg: format!("File read error: {e}"),
+        })?;
+        Self::from_kvn(&kvn, template)
+    }
+
+    /// Parses an OPM in the KVN format. The spacecraft parameters missing from the message are taken from the template.
+    ///
+    /// The mass of the message is the total mass of the spacecraft: the dry mass is set such that the propellant and
+    /// extra masses of the template are preserved. A covariance in the RTN frame is rotated into the frame of the orbit.
+    pub fn from_kvn(kvn: &str, template: Option<Spacecraft>) -> Result<Self, NyxError> {
+        let mut spacecraft = template.unwrap_or_default();
+
+        let text = |keyword: &str| -> Option<String> {
+            kvn.lines()
+                .filter_map(kvn_pair)
+                .find(|(key, _)| *key == keyword)
+                .map(|(_, value)| value.to_string())
+        };
+        let missing = |keyword: &str| NyxError::CCSDS {
+            msg: format!("missing {keyword} in OPM"),
+        };
+        let required = |keyword: &str| text(keyword).ok_or_else(|| missing(keyword));
+
+        let version = required("CCSDS_OPM_VERS")?;
+        if !["1", "2", "3"].iter().any(|v| version.starts_with(v)) {
+            return Err(NyxError::CCSDS {
+                msg: format!("unsupported OPM version {version}"),
+            });
+        }
+        let time_system = required("TIME_SYSTEM")?;
+        let center = required("CENTER_NAME")?;
+        let ref_frame = required("REF_FRAME")?;
+        let mut frame = ccsds_frame(&center, &ref_frame)?;
+
+        let mut opm = Self::new(required("OBJECT_NAME")?, spacecraft);
+        opm.object_id = text("OBJECT_ID").unwrap_or_else(|| opm.object_name.clone());
+        if let Some(originator) = text("ORIGINATOR") {
+            opm.originator = originator;
+        }
+
+        let mut epoch = None;
+        let mut cartesian = [None; 6];
+        let mut cov_ref_frame = None;
+        let mut covariance = None;
+        let mut maneuver: Option<OpmManeuver> = None;
+
+        for (keyword, value) in kvn.lines().filter_map(kvn_pair) {
+            match keyword {
+                "EPOCH" => epoch = Some(kvn_epoch(value, &time_system)?),
+                "X" | "Y" | "Z" | "X_DOT" | "Y_DOT" | "Z_DOT" => {
+                    let idx = COV_NAMES.iter().position(|name| *name == keyword).unwrap();
+                    cartesian[idx] = Some(kvn_f64(keyword, value)?);
+                }
+                "GM" => frame = frame.with_mu_km3_s2(kvn_f64(keyword, value)?),
+                "MASS" => {
+                    spacecraft.mass.dry_mass_kg = kvn_f64(keyword, value)?
+                        - spacecraft.mass.prop_mass_kg
+                        - spacecraft.mass.extra_mass_kg
+                }
+                "SOLAR_RAD_AREA" => spacecraft.srp.area_m2 = kvn_f64(keyword, value)?,
+                "SOLAR_RAD_COEFF" => spacecraft.srp.coeff_reflectivity = kvn_f64(keyword, value)?,
+                "DRAG_AREA" => spacecraft.drag.area_m2 = kvn_f64(keyword, value)?,
+                "DRAG_COEFF" => spacecraft.drag.coeff_drag = kvn_f64(keyword, value)?,
+                "COV_REF_FRAME" => cov_ref_frame = Some(value.to_string()),
+                "MAN_EPOCH_IGNITION" => {
+                    opm.maneuvers.extend(maneuver.take());
+                    maneuver = Some(OpmManeuver {
+                        epoch_ignition: kvn_epoch(value, &time_system)?,
+                        duration: Duration::ZERO,
+                        delta_mass_kg: 0.0,
+                        frame: LocalFrame::Inertial,
+                        dv_km_s: Vector3::zeros(),
+                    });
+                }
+                _ if keyword.starts_with("MAN_") => {
+                    let mnvr = maneuver.as_mut().ok_or(NyxError::CCSDS {
+                        msg: format!("{keyword} before MAN_EPOCH_IGNITION"),
+                    })?;
+                    match keyword {
+                        "MAN_DURATION" => mnvr.duration = kvn_f64(keyword, value)? * Unit::Second,
+                        "MAN_DELTA_MASS" => mnvr.delta_mass_kg = kvn_f64(keyword, value)?,
+                        "MAN_REF_FRAME" => {
+                            mnvr.frame = match value {
+                                "RTN" | "RSW" | "RIC" => LocalFrame::RIC,
+                                _ if value == ref_frame => LocalFrame::Inertial,
+                                _ => {
+                                    return Err(NyxError::CCSDS {
+                                        msg: format!("unsupported maneuver frame {value}"),
+                                    })
+                                }
+                            }
+                        }
+                        "MAN_DV_1" => mnvr.dv_km_s[0] = kvn_f64(keyword, value)?,
+                        "MAN_DV_2" => mnvr.dv_km_s[1] = kvn_f64(keyword, value)?,
+                        "MAN_DV_3" => mnvr.dv_km_s[2] = kvn_f64(keyword, value)?,
+                        _ => debug!("ignoring {keyword} in OPM"),
+                    }
+                }
+                _ if keyword.starts_with('C') => {
+                    // Lower triangular elements, e.g. CX_DOT_Y
+                    match (0..6)
+                        .flat_map(|i| (0..=i).map(move |j| (i, j)))
+                        .find(|(i, j)| keyword == format!("C{}_{}", COV_NAMES[*i], COV_NAMES[*j]))
+                    {
+                        Some((i, j)) => {
+                            let cov = covariance.get_or_insert_with(Matrix6::zeros);
+                            cov[(i, j)] = kvn_f64(keyword, value)?;
+                            cov[(j, i)] = cov[(i, j)];
+                        }
+                        None => debug!("ignoring {keyword} in OPM"),
+                    }
+                }
+                _ => debug!("ignoring {keyword} in OPM"),
+            }
+        }
+        opm.maneuvers.extend(maneuver);
+
+        let epoch = epoch.ok_or_else(|| missing("EPOCH"))?;
+        let mut state = [0.0; 6];
+        for (idx, value) in cartesian.iter().enumerate() {
+            state[idx] = value.ok_or_else(|| missing(COV_NAMES[idx]))?;
+        }
+        spacecraft = spacecraft.with_orbit(Orbit::new(
+            state[0], state[1], state[2], state[3], state[4], state[5], epoch, frame,
+        ));
+
+        if let Some(cov) = covariance.as_mut() {
+            match cov_ref_frame.as_deref() {
+                None => {}
+                Some(name) if name == ref_frame => {}
+                Some("RTN" | "RSW" | "RIC") => {
+                    let dcm = spacecraft
+                        .orbit
+                        .dcm_from_ric_to_inertial()
+                        .map_err(|e| NyxError::CCSDS {
+                            msg: format!("could not rotate the RTN covariance: {e}"),
+                        })?
+                        .rot_mat;
+                    let mut rot = Matrix6::zeros();
+                    rot.fixed_view_mut::<3, 3>(0, 0).copy_from(&dcm);
+                    rot.fixed_view_mut::<3, 3>(3, 3).copy_from(&dcm);
+                    *cov = rot * *cov * rot.transpose();
+                }
+                Some(name) => {
+                    return Err(NyxError::CCSDS {
+                        msg: format!("unsupported covariance frame {name}"),
+                    })
+                }
+            }
+        }
+
+        opm.spacecraft = spacecraft;
+        opm.covariance = covariance;
+        Ok(opm)
+    }
+
+    /// Formats this OPM in the KVN format, with the covariance in the frame of the orbit.
+    pub fn to_kvn(&self) -> Result<String, NyxError> {
+        let orbit = self.spacecraft.orbit;
+        let (center, ref_frame) = ccsds_frame_names(orbit.frame);
+
+        // Writing to a string cannot fail
+        let mut kvn = String::new();
+        writeln!(kvn, "CCSDS_OPM_VERS = 2.0").unwrap();
+        writeln!(
+            kvn,
+            "COMMENT Built by {} -- https://nyxspace.com/",
+            prj_name_ver()
+        )
+        .unwrap();
+        writeln!(
+            kvn,
+            "CREATION_DATE = {}",
+            ccsds_epoch(Epoch::now().unwrap())
+        )
+        .unwrap();
+        writeln!(kvn, "ORIGINATOR = {}\n", self.originator).unwrap();
+
+        writeln!(kvn, "OBJECT_NAME = {}", self.object_name).unwrap();
+        writeln!(kvn, "OBJECT_ID = {}", self.object_id).unwrap();
+        writeln!(kvn, "CENTER_NAME = {center}").unwrap();
+        writeln!(kvn, "REF_FRAME = {ref_frame}").unwrap();
+        writeln!(kvn, "TIME_SYSTEM = {}\n", orbit.epoch.time_scale).unwrap();
+
+        writeln!(kvn, "EPOCH = {}", ccsds_epoch(orbit.epoch)).unwrap();
+        let state = orbit.to_cartesian_pos_vel();
+        for (idx, name) in COV_NAMES.iter().enumerate() {
+            let unit = if idx < 3 { "km" } else { "km/s" };
+            writeln!(kvn, "{name} = {:e} [{unit}]", state[idx]).unwrap();
+        }
+        if let Ok(mu_km3_s2) = orbit.frame.mu_km3_s2() {
+            writeln!(kvn, "GM = {mu_km3_s2:e} [km**3/s**2]").unwrap();
+        }
+
+        writeln!(kvn).unwrap();
+        writeln!(
+            kvn,
+            "MASS = {:e} [kg]",
+            self.spacecraft.mass.total_mass_kg()
+        )
+        .unwrap();
+        writeln!(
+            kvn,
+            "SOLAR_RAD_AREA = {:e} [m**2]",
+            self.spacecraft.srp.area_m2
+        )
+        .unwrap();
+        writeln!(
+            kvn,
+            "SOLAR_RAD_COEFF = {:e}",
+            self.spacecraft.srp.coeff_reflectivity
+        )
+        .unwrap();
+        writeln!(kvn, "DRAG_AREA = {:e} [m**2]", self.spacecraft.drag.area_m2).unwrap();
+        writeln!(kvn, "DRAG_COEFF = {:e}", self.spacecraft.drag.coeff_drag).unwrap();
+
+        if let Some(cov) = &self.covariance {
+            writeln!(kvn, "\nCOV_REF_FRAME = {ref_frame}").unwrap();
+            for i in 0..6 {
+                for j in 0..=i {
+                    writeln!(
+                        kvn,
+                        "C{}_{} = {:e}",
+                        COV_NAMES[i],
+                        COV_NAMES[j],
+                        cov[(i, j)]
+                    )
+                    .unwrap();
+                }
+            }
+        }
+
+        for mnvr in &self.maneuvers {
+            let frame = match mnvr.frame {
+                LocalFrame::RIC => "RTN".to_string(),
+                LocalFrame::Inertial => ref_frame.clone(),
+                frame => {
+                    return Err(NyxError::CCSDS {
+                        msg: format!(
+                            "maneuvers in the {frame:?} frame cannot be written to an OPM"
+                        ),
+                    })
+                }
+            };
+            writeln!(
+                kvn,
+                "\nMAN_EPOCH_IGNITION = {}",
+                ccsds_epoch(mnvr.epoch_ignition)
+            )
+            .unwrap();
+            writeln!(
+                kvn,
+                "MAN_DURATION = {:e} [s]",
+                mnvr.duration.to_unit(Unit::Second)
+            )
+            .unwrap();
+            writeln!(kvn, "MAN_DELTA_MASS = {:e} [kg]", mnvr.delta_mass_kg).unwrap();
+            writeln!(kvn, "MAN_REF_FRAME = {frame}").unwrap();
+            for (idx, dv) in mnvr.dv_km_s.iter().enumerate() {
+                writeln!(kvn, "MAN_DV_{} = {dv:e} [km/s]", idx + 1).unwrap();
+            }
+        }
+
+        Ok(kvn)
+    }
+
+    /// Writes this OPM to the provided file in the KVN format.
+    pub fn to_opm_file<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, NyxError> {
+        let kvn = self.to_kvn()?;
+        let path_buf = path.as_ref().to_path_buf();
+        let mut file = File::create(&path_buf).map_err(|e| NyxError::CCSDS {
+            msg: format!("File creation error: {e}"),
+        })?;
+        file.write_all(kvn.as_bytes())
+            .map_err(|e| NyxError::CCSDS {
+                msg: format!("Could not write: {e}"),
+            })?;
+        Ok(path_buf)
+    }
+}
+
+#[cfg(test)]
+mod ut_opm {
+    use super::*;
+    use crate::cosmic::Mass;
+    use crate::fixtures;
+
+    #[test]
+    fn read_opm_with_maneuvers() {
+        let path: PathBuf = [
+            env!("CARGO_MANIFEST_DIR"),
+            "data",
+            "tests",
+            "ccsds",
+            "odm",
+            "OPMExample_maneuvers.opm",
+        ]
+        .iter()
+        .collect();
+
+        let template = Spacecraft {
+            mass: Mass::from_dry_and_prop_masses(1000.0, 500.0),
+            ..Default::default()
+        };
+        let opm = Opm::from_opm_file(path, Some(template)).unwrap();
+
+        assert_eq!(opm.object_name, "EUTELSAT W4");
+        assert_eq!(opm.object_id, "2021-028A");
+        assert_eq!(opm.originator, "GSOC");
+
+        let orbit = opm.spacecraft.orbit;
+        assert_eq!(orbit.frame, fixtures::eme2k());
+        assert_eq!(
+            orbit.epoch,
+            Epoch::from_gregorian_utc_at_midnight(2021, 6, 3)
+        );
+        assert_eq!(
+            orbit.radius_km,
+            Vector3::new(6655.9942, -40218.5751, -82.9177)
+        );
+        assert_eq!(
+            orbit.velocity_km_s,
+            Vector3::new(3.11548208, 0.47042605, -0.00101495)
+        );
+        // The Keplerian elements are consistent with the Cartesian state
+        assert!((orbit.sma_km().unwrap() - 41399.5123).abs() < 1e-2);
+
+        // The propellant mass of the template is preserved
+        assert!((opm.spacecraft.mass.total_mass_kg() - 1913.0).abs() < 1e-9);
+        assert_eq!(opm.spacecraft.mass.prop_mass_kg, 500.0);
+        assert_eq!(opm.spacecraft.srp.area_m2, 10.0);
+        assert_eq!(opm.spacecraft.srp.coeff_reflectivity, 1.3);
+        assert_eq!(opm.spacecraft.drag.coeff_drag, 2.3);
+
+        // The RTN covariance is rotated into the inertial frame, which preserves its trace
+        let cov = opm.covariance.unwrap();
+        assert!((cov - cov.transpose()).norm() < 1e-18);
+        let rtn_pos_trace = 3.331349476038534e-04 + 6.782421679971363e-04 + 3.231931992380369e-04;
+        assert!((cov.fixed_view::<3, 3>(0, 0).trace() - rtn_pos_trace).abs() < 1e-15);
+        assert!(cov[(0, 1)] != 4.618927349220216e-04);
+
+        assert_eq!(opm.maneuvers.len(), 2);
+        let finite = opm.maneuvers[0];
+        assert_eq!(finite.frame, LocalFrame::Inertial);
+        assert_eq!(finite.duration, 132.6 * Unit::Second);
+        assert_eq!(finite.delta_mass_kg, -18.418);
+        let mnvr = finite.to_maneuver();
+        assert_eq!(mnvr.end - mnvr.start, finite.duration);
+        assert!((mnvr.vector(mnvr.start).norm() - 1.0).abs() < 1e-12);
+
+        let impulsive = opm.maneuvers[1];
+        assert_eq!(impulsive.frame, LocalFrame::RIC);
+        assert_eq!(impulsive.duration, Duration::ZERO);
+        assert_eq!(impulsive.dv_km_s, Vector3::new(0.001015, -0.001873, 0.0));
+        assert_eq!(
+            impulsive.epoch_ignition,
+            Epoch::from_gregorian_utc_hms(2021, 6, 5, 18, 59, 21)
+        );
+    }
+
+    #[test]
+    fn opm_round_trip() {
+        let eme2k = fixtures::eme2k();
+        let epoch = Epoch::from_gregorian_utc(2024, 3, 14, 15, 9, 26, 535_897_000);
+        let spacecraft = Spacecraft::builder()
+            .orbit(Orbit::keplerian(
+                7_000.0, 0.01, 28.5, 45.0, 60.0, 90.0, epoch, eme2k,
+            ))
+            .mass(Mass::from_dry_mass(850.0))
+            .build();
+
+        let mut opm = Opm::new("round trip".to_string(), spacecraft);
+        opm.covariance = Some(Matrix6::from_fn(|i, j| {
+            if i == j {
+                10.0_f64.powi(-(i as i32))
+            } else {
+                1e-9 * (i + j) as f64
+            }
+        }));
+        opm.maneuvers = vec![
+            OpmManeuver {
+                epoch_ignition: epoch + Unit::Hour * 2,
+                duration: Unit::Second * 90,
+                delta_mass_kg: -3.2,
+                frame: LocalFrame::Inertial,
+                dv_km_s: Vector3::new(1e-3, -2e-3, 5e-4),
+            },
+            OpmManeuver {
+                epoch_ignition: epoch + Unit::Hour * 5,
+                duration: Duration::ZERO,
+                delta_mass_kg: -0.4,
+                frame: LocalFrame::RIC,
+                dv_km_s: Vector3::new(0.0, 1e-4, 0.0),
+            },
+        ];
+
+        let path: PathBuf = [
+            env!("CARGO_MANIFEST_DIR"),
+            "output_data",
+            "opm_round_trip.opm",
+        ]
+        .iter()
+        .collect();
+        let path = opm.to_opm_file(path).unwrap();
+        let read = Opm::from_opm_file(path, None).unwrap();
+
+        assert_eq!(read.object_name, opm.object_name);
+        assert_eq!(read.object_id, opm.object_id);
+        assert_eq!(read.spacecraft.orbit, spacecraft.orbit);
+        assert_eq!(read.spacecraft.mass.total_mass_kg(), 850.0);
+        assert_eq!(read.spacecraft.srp, spacecraft.srp);
+        assert_eq!(read.spacecraft.drag, spacecraft.drag);
+        assert_eq!(read.covariance, opm.covariance);
+        assert_eq!(read.maneuvers, opm.maneuvers);
+
+        // Maneuvers in other local frames cannot be represented
+        opm.maneuvers[0].frame = LocalFrame::VNC;
+        assert!(opm.to_kvn().is_err());
+    }
+}
@@ -0,0 +1,275 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::ccsds::Omm;
+use super::percent_encode;
+use crate::cosmic::Spacecraft;
+use crate::errors::NyxError;
+use crate::md::prelude::Traj;
+use crate::propagators::{Sgp4, Tle};
+use crate::time::{Duration, Epoch};
+use anise::prelude::Frame;
+use rayon::prelude::*;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::read_to_string;
+use std::path::Path;
+
+/// Endpoint of the general perturbations (GP) element sets of Celestrak
+pub const CELESTRAK_GP_URL: &str = "https://celestrak.org/NORAD/elements/gp.php";
+
+/// A query of the Celestrak GP element sets, returned as OMMs in the KVN format.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GpQuery {
+    /// Celestrak group, e.g. `stations`, `active` or `starlink`
+    Group(String),
+    /// NORAD catalog number, e.g. 25544 for the ISS
+    CatalogNumber(u32),
+    /// Objects whose name contains this string
+    Name(String),
+    /// International designator of a launch (e.g. `2023-001`) or of an object (e.g. `1998-067A`)
+    IntlDesignator(String),
+}
+
+impl GpQuery {
+    /// Returns the URL of this query.
+    pub fn url(&self) -> String {
+        let (key, value) = match self {
+            Self::Group(group) => ("GROUP", group.clone()),
+            Self::CatalogNumber(number) => ("CATNR", format!("{number}")),
+            Self::Name(name) => ("NAME", name.clone()),
+            Self::IntlDesignator(designator) => ("INTDES", designator.clone()),
+        };
+        format!(
+            "{CELESTRAK_GP_URL}?{key}={}&FORMAT=kvn",
+            percent_encode(&value)
+        )
+    }
+
+    /// Fetches the element sets of this query from Celestrak.
+    ///
+    /// Celestrak limits the rate of the downloads: store large groups with [std::fs::write] and read them back with [read_gp_file].
+    #[cfg(feature = "celestrak")]
+    pub fn fetch(&self) -> Result<Vec<Omm>, NyxError> {
+        let url = self.url();
+        log::debug!("querying {url}");
+
+        let text = super::http::get_text(&url).map_err(|msg| NyxError::Celestrak { msg })?;
+        if !text.contains("CCSDS_OMM_VERS") {
+            // E.g. `No GP data found`
+            return Err(NyxError::Celestrak {
+                msg: format!("{self}: {}", text.trim()),
+            });
+        }
+
+        Omm::from_catalog(&text)
+    }
+}
+
+impl fmt::Display for GpQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Group(group) => write!(f, "group {group}"),
+            Self::CatalogNumber(number) => write!(f, "catalog number {number}"),
+            Self::Name(name) => write!(f, "name {name}"),
+            Self::IntlDesignator(designator) => write!(f, "international designator {designator}"),
+        }
+    }
+}
+
+/// Reads the GP element sets previously downloaded from Celestrak, cf. [read_gp].
+pub fn read_gp_file<P: AsRef<Path>>(path: P) -> Result<Vec<Omm>, NyxError> {
+    let text = read_to_string(path).map_err(|e| NyxError::Celestrak {
+        msg: format!("File read error: {e}"),
+    })?;
+    read_gp(&text)
+}
+
+/// Parses GP element sets in any of the formats of Celestrak with the full precision of the elements, i.e. the OMM in KVN or
+/// JSON formats, or the two-line and three-line element sets.
+pub fn read_gp(text: &str) -> Result<Vec<Omm>, NyxError> {
+    let trimmed = text.trim_start();
+    if trimmed.starts_with('[') || trimmed.starts_with('{') {
+        from_json(trimmed)
+    } else if trimmed.contains("CCSDS_OMM_VERS") {
+        Omm::from_catalog(trimmed)
+    } else {
+        let tles =
+            Tle::from_catalog(trimmed).map_err(|e| NyxError::Celestrak { msg: e.to_string() })?;
+        Ok(tles.iter().map(Omm::from_tle).collect())
+    }
+}
+
+/// Parses the OMMs in the JSON format, which only includes the SGP4 mean elements in TEME and in UTC.
+fn from_json(text: &str) -> Result<Vec<Omm>, NyxError> {
+    let json: Value = serde_json::from_str(text).map_err(|e| NyxError::Celestrak {
+        msg: format!("invalid JSON: {e}"),
+    })?;
+
+    let records = match json {
+        Value::Array(records) => records,
+        record => vec![record],
+    };
+
+    records
+        .iter()
+        .map(|record| {
+            let object = record.as_object().ok_or(NyxError::Celestrak {
+                msg: format!("expected an OMM object but got {record}"),
+            })?;
+
+            let mut values = object
+                .iter()
+                .filter_map(|(keyword, value)| match value {
+                    Value::String(value) => Some((keyword.as_str(), value.clone())),
+                    Value::Number(value) => Some((keyword.as_str(), value.to_string())),
+                    _ => None,
+                })
+                .collect::<HashMap<&str, String>>();
+
+            for (keyword, default) in [
+                ("CCSDS_OMM_VERS", "2.0"),
+                ("CENTER_NAME", "EARTH"),
+                ("REF_FRAME", "TEME"),
+                ("TIME_SYSTEM", "UTC"),
+                ("MEAN_ELEMENT_THEORY", "SGP4"),
+            ] {
+                values.entry(keyword).or_insert_with(|| default.to_string());
+            }
+
+            Omm::from_keywords(
+                &values
+                    .iter()
+                    .map(|(keyword, value)| (*keyword, value.as_str()))
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+/// Builds the trajectories of these element sets with SGP4, e.g. as the secondary objects of a [crate::tools::conjunction::ConjunctionScreening].
+/// The trajectories are in the provided Earth centered J2000 frame, from `start` to `end` every `step`.
+pub fn gp_trajectories(
+    omms: &[Omm],
+    start: Epoch,
+    end: Epoch,
+    step: Duration,
+    frame: Frame,
+) -> Result<Vec<Traj<Spacecraft>>, NyxError> {
+    omms.par_iter()
+        .map(|omm| {
+            let sgp4 = Sgp4::new(&omm.to_tle()?).map_err(|e| NyxError::Celestrak {
+                msg: format!("{}: {e}", omm.object_name),
+            })?;
+            sgp4.to_traj(start, end, step, frame)
+                .map_err(|e| NyxError::Celestrak {
+                    msg: format!("{}: {e}", omm.object_name),
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod ut_celestrak {
+    use super::*;
+    use crate::time::Unit;
+    use anise::constants::frames::EARTH_J2000;
+
+    const ISS_LINES: (&str, &str) = (
+        "1 25544U 98067A   08264.51782528 -.00002182  00000-0 -11606-4 0  2927",
+        "2 25544  51.6416 247.4627 0006703 130.5360 325.0288 15.72125391563537",
+    );
+
+    #[test]
+    fn query_urls() {
+        assert_eq!(
+            GpQuery::Group("stations".to_string()).url(),
+            format!("{CELESTRAK_GP_URL}?GROUP=stations&FORMAT=kvn")
+        );
+        assert_eq!(
+            GpQuery::CatalogNumber(25544).url(),
+            format!("{CELESTRAK_GP_URL}?CATNR=25544&FORMAT=kvn")
+        );
+        assert_eq!(
+            GpQuery::Name("ISS (ZARYA)".to_string()).url(),
+            format!("{CELESTRAK_GP_URL}?NAME=ISS%20%28ZARYA%29&FORMAT=kvn")
+        );
+    }
+
+    #[test]
+    fn read_all_formats() {
+        let tle = Tle::from_lines(ISS_LINES.0, ISS_LINES.1).unwrap();
+        let iss = Omm::from_tle(&tle);
+
+        // Three-line element sets
+        let three_lines = format!("ISS (ZARYA)\n{}\n{}\n", ISS_LINES.0, ISS_LINES.1);
+        let omms = read_gp(&three_lines).unwrap();
+        assert_eq!(omms.len(), 1);
+        assert_eq!(omms[0].object_name, "ISS (ZARYA)");
+        assert_eq!(omms[0].to_tle().unwrap().to_lines(), tle.to_lines());
+
+        // OMM in KVN
+        let omms = read_gp(&iss.to_kvn()).unwrap();
+        assert_eq!(omms, vec![iss.clone()]);
+
+        // OMM in JSON, as distributed by Celestrak
+        let json = r#"[{
+            "OBJECT_NAME": "ISS (ZARYA)",
+            "OBJECT_ID": "1998-067A",
+            "EPOCH": "2008-09-20T12:25:40.104192",
+            "MEAN_MOTION": 15.72125391,
+            "ECCENTRICITY": 0.0006703,
+            "INCLINATION": 51.6416,
+            "RA_OF_ASC_NODE": 247.4627,
+            "ARG_OF_PERICENTER": 130.536,
+            "MEAN_ANOMALY": 325.0288,
+            "EPHEMERIS_TYPE": 0,
+            "CLASSIFICATION_TYPE": "U",
+            "NORAD_CAT_ID": 25544,
+            "ELEMENT_SET_NO": 292,
+            "REV_AT_EPOCH": 56353,
+            "BSTAR": -1.1606e-5,
+            "MEAN_MOTION_DOT": -2.182e-5,
+            "MEAN_MOTION_DDOT": 0
+        }]"#;
+        let omms = read_gp(json).unwrap();
+        assert_eq!(omms.len(), 1);
+        assert_eq!(omms[0].ref_frame, "TEME");
+        assert_eq!(omms[0].to_tle().unwrap().to_lines(), tle.to_lines());
+    }
+
+    #[test]
+    fn catalog_trajectories() {
+        let tle = Tle::from_lines(ISS_LINES.0, ISS_LINES.1).unwrap();
+        let omms = vec![Omm::from_tle(&tle)];
+
+        let trajs = gp_trajectories(
+            &omms,
+            tle.epoch,
+            tle.epoch + Unit::Hour * 1,
+            Unit::Minute * 1,
+            EARTH_J2000,
+        )
+        .unwrap();
+
+        assert_eq!(trajs.len(), 1);
+        assert_eq!(trajs[0].states.len(), 61);
+        assert_eq!(trajs[0].name.as_deref(), Some("25544"));
+    }
+}
@@ -0,0 +1,302 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::errors::NyxError;
+use crate::runtime;
+use anise::almanac::metaload::{MetaAlmanac, MetaFile};
+use anise::prelude::Almanac;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Environment variable overriding the default cache directory of the [DataManager]
+pub const NYX_DATA_DIR: &str = "NYX_DATA_DIR";
+
+/// Downloads and caches the ANISE kernels (planetary ephemerides, planetary constants, Earth orientation, etc.) needed to build an Almanac.
+///
+/// Each file is a [MetaFile], i.e. a URL or a local path with an optional CRC32 checksum, such that the file lists of ANISE
+/// MetaAlmanac configurations can be used as is. Remote files are downloaded into the cache directory on first use, and reused
+/// afterwards as long as their checksum matches. Files without a checksum (e.g. the latest Earth orientation parameters) are
+/// downloaded on each fetch.
+///
+/// If a file cannot be downloaded, because this manager is offline, because the `download` feature is disabled, or because
+/// the download failed, then its cached copy is used with a warning, even if its checksum differs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataManager {
+    /// Files of the Almanac, loaded in order
+    pub files: Vec<MetaFile>,
+    /// Directory storing the downloaded files
+    pub cache_dir: PathBuf,
+    /// Set to true to never download any file and only use the cached copies
+    pub offline: bool,
+}
+
+impl DataManager {
+    /// Initializes a data manager of the provided files, cached in the default cache directory, cf. [DataManager::default_cache_dir].
+    pub fn new(files: Vec<MetaFile>) -> Self {
+        Self {
+            files,
+            cache_dir: Self::default_cache_dir(),
+            offline: false,
+        }
+    }
+
+    /// Initializes a data manager of the files of an ANISE MetaAlmanac, e.g. loaded from its Dhall configuration.
+    pub fn from_meta_almanac(meta: MetaAlmanac) -> Self {
+        Self::new(meta.files)
+    }
+
+    /// Sets the cache directory of this data manager.
+    pub fn with_cache_dir<P: AsRef<Path>>(mut self, cache_dir: P) -> Self {
+        self.cache_dir = cache_dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// Sets whether this data manager only uses the cached copies of the files.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Returns the default cache directory: the `NYX_DATA_DIR` environment variable if set, otherwise `nyx-space`
+    /// in the user cache directory (`XDG_CACHE_HOME` or `~/.cache`), or in the temporary directory as a last resort.
+    pub fn default_cache_dir() -> PathBuf {
+        if let Ok(dir) = env::var(NYX_DATA_DIR) {
+            return PathBuf::from(dir);
+        }
+        let cache = match (env::var("XDG_CACHE_HOME"), env::var("HOME")) {
+            (Ok(cache), _) => PathBuf::from(cache),
+            (Err(_), Ok(home)) => PathBuf::from(home).join(".cache"),
+            _ => env::temp_dir(),
+        };
+        cache.join("nyx-space")
+    }
+
+    /// Returns the local path of this file: its path if it is local, or its path in the cache directory if it is remote.
+    pub fn local_path(&self, file: &MetaFile) -> PathBuf {
+        match remote_name(&file.uri) {
+            Some(name) => self.cache_dir.join(name),
+            None => PathBuf::from(file.uri.strip_prefix("file://").unwrap_or(&file.uri)),
+        }
+    }
+
+    /// Downloads the files which are not cached yet (or whose cached copy fails the integrity check),
+    /// and returns the local path of each file, in order.
+    pub fn fetch(&self) -> Result<Vec<PathBuf>, NyxError> {
+        self.files
+            .iter()
+            .map(|file| self.fetch_file(file))
+            .collect()
+    }
+
+    /// Fetches all of the files and loads them, in order, into an Almanac.
+    pub fn load(&self) -> Result<Almanac, NyxError> {
+        let mut almanac = Almanac::default();
+        for path in self.fetch()? {
+            almanac = almanac
+                .load(&path.to_string_lossy())
+                .map_err(|e| NyxError::DataManager {
+                    msg: format!("loading {}: {e}", path.display()),
+                })?;
+        }
+        Ok(almanac)
+    }
+
+    /// Fetches all of the files and returns the Almanac loaded from them, shared with the other computations which loaded the same files, cf. [runtime::shared_almanac].
+    pub fn load_shared(&self) -> Result<Arc<Almanac>, NyxError> {
+        let paths: Vec<String> = self
+            .fetch()?
+            .iter()
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+        runtime::shared_almanac(&paths, || self.load())
+    }
+
+    /// Fetches a single file and returns its local path.
+    fn fetch_file(&self, file: &MetaFile) -> Result<PathBuf, NyxError> {
+        let path = self.local_path(file);
+
+        if remote_name(&file.uri).is_none() {
+            // Local files are only checked
+            let crc32 = checksum(&path).ok_or_else(|| NyxError::DataManager {
+                msg: format!("{} not found", path.display()),
+            })?;
+            check_crc32(file, &path, crc32)?;
+            return Ok(path);
+        }
+
+        let cached_crc32 = checksum(&path);
+        if let (Some(expected), Some(cached)) = (file.crc32, cached_crc32) {
+            if expected == cached {
+                debug!("using cached {}", path.display());
+                return Ok(path);
+            }
+            info!(
+                "cached {} differs from the expected CRC32 (got 0x{cached:x}, expected 0x{expected:x})",
+                path.display()
+            );
+        }
+
+        match self.download(file, &path) {
+            Ok(()) => Ok(path),
+            Err(e) => match cached_crc32 {
+                Some(_) => {
+                    warn!("{e} -- falling back to cached {}", path.display());
+                    Ok(path)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Downloads this file into the cache directory, checking its CRC32 before replacing the cached copy.
+    #[cfg(feature = "download")]
+    fn download(&self, file: &MetaFile, path: &Path) -> Result<(), NyxError> {
+        if self.offline {
+            return Err(NyxError::DataManager {
+                msg: format!("cannot download {} while offline", file.uri),
+            });
+        }
+
+        info!("downloading {}", file.uri);
+        let bytes = super::http::get_bytes(&file.uri).map_err(|msg| NyxError::DataManager {
+            msg: format!("downloading {}: {msg}", file.uri),
+        })?;
+        check_crc32(file, path, crc32fast::hash(&bytes))?;
+
+        fs::create_dir_all(&self.cache_dir).map_err(|e| NyxError::DataManager {
+            msg: format!("creating {}: {e}", self.cache_dir.display()),
+        })?;
+        // Write to a temporary file first so that an interrupted download never corrupts the cache
+        let partial = path.with_extension("part");
+        fs::write(&partial, &bytes)
+            .and_then(|_| fs::rename(&partial, path))
+            .map_err(|e| NyxError::DataManager {
+                msg: format!("storing {}: {e}", path.display()),
+            })?;
+        info!("saved {} to {}", file.uri, path.display());
+        Ok(())
+    }
+
+    #[cfg(not(feature = "download"))]
+    fn download(&self, file: &MetaFile, _path: &Path) -> Result<(), NyxError> {
+        let reason = if self.offline {
+            "while offline"
+        } else {
+            "without the `download` feature"
+        };
+        Err(NyxError::DataManager {
+            msg: format!("cannot download {} {reason}", file.uri),
+        })
+    }
+}
+
+impl Default for DataManager {
+    /// A data manager of the latest ANISE kernels, cf. [MetaAlmanac::default].
+    fn default() -> Self {
+        Self::from_meta_almanac(MetaAlmanac::default())
+    }
+}
+
+/// Returns the file name of this URI if it is a remote (HTTP or HTTPS) URL.
+fn remote_name(uri: &str) -> Option<&str> {
+    if uri.starts_with("http://") || uri.starts_with("https://") {
+        uri.rsplit('/').next().filter(|name| !name.is_empty())
+    } else {
+        None
+    }
+}
+
+/// Returns the CRC32 of this file, if it can be read.
+fn checksum(path: &Path) -> Option<u32> {
+    fs::read(path).ok().map(|bytes| crc32fast::hash(&bytes))
+}
+
+fn check_crc32(file: &MetaFile, path: &Path, crc32: u32) -> Result<(), NyxError> {
+    match file.crc32 {
+        Some(expected) if expected != crc32 => Err(NyxError::DataManager {
+            msg: format!(
+                "integrity check of {} failed (got CRC32 0x{crc32:x}, expected 0x{expected:x})",
+                path.display()
+            ),
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod ut_data {
+    use super::*;
+
+    #[test]
+    fn cache_and_fallback() {
+        let cache_dir = env::temp_dir().join("nyx_ut_data_cache");
+        fs::create_dir_all(&cache_dir).unwrap();
+        let bytes = b"not quite a kernel".to_vec();
+        let crc32 = crc32fast::hash(&bytes);
+
+        let local = cache_dir.join("local.bsp");
+        fs::write(&local, &bytes).unwrap();
+        fs::write(cache_dir.join("remote.pca"), &bytes).unwrap();
+
+        let files = vec![
+            MetaFile {
+                uri: format!("file://{}", local.display()),
+                crc32: Some(crc32),
+            },
+            MetaFile {
+                uri: "http://example.invalid/anise/remote.pca".to_string(),
+                crc32: Some(crc32),
+            },
+        ];
+        let mgr = DataManager::new(files)
+            .with_cache_dir(&cache_dir)
+            .with_offline(true);
+
+        // Valid cached copies are used without any download
+        assert_eq!(
+            mgr.fetch().unwrap(),
+            vec![local.clone(), cache_dir.join("remote.pca")]
+        );
+
+        // Cached copies which fail the integrity check are used as a fallback, but local files must match
+        let mut stale = mgr.clone();
+        stale.files[1].crc32 = Some(crc32 + 1);
+        assert_eq!(stale.fetch().unwrap()[1], cache_dir.join("remote.pca"));
+        stale.files[0].crc32 = Some(crc32 + 1);
+        assert!(stale.fetch().is_err());
+
+        // Files which are neither cached nor downloadable are an error
+        let mut missing = mgr.clone();
+        missing.files[1].uri = "https://example.invalid/anise/missing.bsp".to_string();
+        assert!(missing.fetch().is_err());
+
+        fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
+    #[test]
+    fn default_files() {
+        let mgr = DataManager::default().with_cache_dir("/tmp/nyx");
+        assert!(!mgr.files.is_empty());
+        assert_eq!(
+            mgr.local_path(&mgr.files[0]),
+            PathBuf::from("/tmp/nyx/de440s.bsp")
+        );
+    }
+}
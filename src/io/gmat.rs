@@ -0,0 +1,655 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::dynamics::guidance::{
+    LocalFrame, ThrustHistory, ThrustInterpolation, ThrustSample, ThrustSegment,
+};
+use crate::errors::NyxError;
+use crate::linalg::Vector3;
+use crate::propagators::{ErrorControl, IntegratorMethod, IntegratorOptions};
+use crate::time::{Epoch, Unit};
+use crate::tools::scenario::{DynamicsScenario, Scenario};
+use crate::Spacecraft;
+use anise::constants::orientations::{ECLIPJ2000, J2000};
+use std::fmt::Write as _;
+use std::fs::{read_to_string, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use typed_builder::TypedBuilder;
+
+/// Name of the spacecraft in the GMAT script
+const SC_NAME: &str = "NyxSC";
+/// Name of the propagator in the GMAT script, whose force model is suffixed with `_ForceModel`
+const PROP_NAME: &str = "NyxProp";
+/// Julian date of the reference of the GMAT modified Julian dates
+const GMAT_MJD_OFFSET: f64 = 2_430_000.0;
+
+/// A GMAT script propagating the spacecraft with the same force models and integrator as Nyx, and reporting its
+/// Cartesian state at every step in `NyxReport.txt`.
+///
+/// The script is meant for cross-validation, so only the settings with a GMAT equivalent are supported. Note that GMAT
+/// uses its own planetary constants and ephemerides, and that the gravity field file must be available (unzipped) in
+/// the GMAT data directory.
+#[derive(Clone, Debug, TypedBuilder)]
+#[builder(doc)]
+pub struct GmatScript {
+    pub spacecraft: Spacecraft,
+    #[builder(default)]
+    pub dynamics: DynamicsScenario,
+    #[builder(default = IntegratorMethod::RungeKutta89)]
+    pub method: IntegratorMethod,
+    #[builder(default)]
+    pub opts: IntegratorOptions,
+    pub end_epoch: Epoch,
+}
+
+impl GmatScript {
+    /// Builds the GMAT script of the spacecraft, dynamics and propagation span of this scenario, with the default propagator.
+    pub fn from_scenario(scenario: &Scenario) -> Self {
+        Self::builder()
+            .spacecraft(scenario.spacecraft)
+            .dynamics(scenario.dynamics.clone())
+            .end_epoch(scenario.propagation.end_epoch)
+            .build()
+    }
+
+    /// Returns the content of the GMAT script.
+    pub fn to_script(&self) -> Result<String, NyxError> {
+        let orbit = self.spacecraft.orbit;
+        let central_body = gmat_body(orbit.frame.ephemeris_id)?;
+        let axes = match orbit.frame.orientation_id {
+            J2000 => "MJ2000Eq",
+            ECLIPJ2000 => "MJ2000Ec",
+            _ => {
+                return Err(NyxError::Gmat {
+                    msg: format!("{} has no GMAT equivalent axes", orbit.frame),
+                })
+            }
+        };
+        let coord_sys = format!("{central_body}{axes}");
+
+        let mut point_masses = Vec::new();
+        for id in &self.dynamics.point_masses {
+            let body = gmat_body(*id)?;
+            if body != central_body && !point_masses.contains(&body) {
+                point_masses.push(body);
+            }
+        }
+
+        let mut script = String::new();
+        writeln!(script, "%General Mission Analysis Tool(GMAT) Script").unwrap();
+        writeln!(
+            script,
+            "%Generated by Nyx {} for the propagation of {} from {} until {}",
+            env!("CARGO_PKG_VERSION"),
+            orbit.frame,
+            orbit.epoch,
+            self.end_epoch
+        )
+        .unwrap();
+
+        // Spacecraft
+        writeln!(script, "\nCreate Spacecraft {SC_NAME};").unwrap();
+        let tai_mod_julian =
+            (orbit.epoch - Epoch::from_jde_tai(GMAT_MJD_OFFSET)).to_unit(Unit::Day);
+        let sc_fields = [
+            ("DateFormat", "TAIModJulian".to_string()),
+            ("Epoch", format!("'{tai_mod_julian}'")),
+            ("CoordinateSystem", coord_sys.clone()),
+            ("DisplayStateType", "Cartesian".to_string()),
+            ("X", format!("{}", orbit.radius_km.x)),
+            ("Y", format!("{}", orbit.radius_km.y)),
+            ("Z", format!("{}", orbit.radius_km.z)),
+            ("VX", format!("{}", orbit.velocity_km_s.x)),
+            ("VY", format!("{}", orbit.velocity_km_s.y)),
+            ("VZ", format!("{}", orbit.velocity_km_s.z)),
+            (
+                "DryMass",
+                format!(
+                    "{}",
+                    self.spacecraft.mass.dry_mass_kg + self.spacecraft.mass.extra_mass_kg
+                ),
+            ),
+            ("Cd", format!("{}", self.spacecraft.drag.coeff_drag)),
+            ("Cr", format!("{}", self.spacecraft.srp.coeff_reflectivity)),
+            ("DragArea", format!("{}", self.spacecraft.drag.area_m2)),
+            ("SRPArea", format!("{}", self.spacecraft.srp.area_m2)),
+        ];
+        for (field, value) in sc_fields {
+            writeln!(script, "GMAT {SC_NAME}.{field} = {value};").unwrap();
+        }
+
+        if self.spacecraft.mass.prop_mass_kg > 0.0 {
+            writeln!(script, "GMAT {SC_NAME}.Tanks = {{{SC_NAME}_Tank}};").unwrap();
+            writeln!(script, "\nCreate ChemicalTank {SC_NAME}_Tank;").unwrap();
+            writeln!(
+                script,
+                "GMAT {SC_NAME}_Tank.FuelMass = {};",
+                self.spacecraft.mass.prop_mass_kg
+            )
+            .unwrap();
+        }
+
+        if !coord_sys.starts_with("Earth") {
+            writeln!(script, "\nCreate CoordinateSystem {coord_sys};").unwrap();
+            writeln!(script, "GMAT {coord_sys}.Origin = {central_body};").unwrap();
+            writeln!(script, "GMAT {coord_sys}.Axes = {axes};").unwrap();
+        }
+
+        // Force model
+        let fm = format!("{PROP_NAME}_ForceModel");
+        writeln!(script, "\nCreate ForceModel {fm};").unwrap();
+        writeln!(script, "GMAT {fm}.CentralBody = {central_body};").unwrap();
+
+        if let Some(harmonics) = &self.dynamics.harmonics {
+            let body = gmat_body(harmonics.frame.ephemeris_id)?;
+            if body != central_body {
+                return Err(NyxError::Gmat {
+                    msg: format!(
+                        "GMAT only supports the gravity field of the central body {central_body}, not {body}"
+                    ),
+                });
+            }
+            let file_name = Path::new(&harmonics.path)
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or(&harmonics.path);
+            writeln!(script, "GMAT {fm}.PrimaryBodies = {{{body}}};").unwrap();
+            writeln!(
+                script,
+                "GMAT {fm}.GravityField.{body}.Degree = {};",
+                harmonics.degree
+            )
+            .unwrap();
+            writeln!(
+                script,
+                "GMAT {fm}.GravityField.{body}.Order = {};",
+                harmonics.order
+            )
+            .unwrap();
+            writeln!(
+                script,
+                "GMAT {fm}.GravityField.{body}.PotentialFile = '{}';",
+                file_name.trim_end_matches(".gz")
+            )
+            .unwrap();
+        } else {
+            // Without a gravity field, the central body is one of the point masses.
+            point_masses.insert(0, central_body);
+        }
+
+        if !point_masses.is_empty() {
+            writeln!(
+                script,
+                "GMAT {fm}.PointMasses = {{{}}};",
+                point_masses.join(", ")
+            )
+            .unwrap();
+        }
+        writeln!(script, "GMAT {fm}.Drag = None;").unwrap();
+        if self.dynamics.solar_radiation_pressure {
+            writeln!(script, "GMAT {fm}.SRP = On;").unwrap();
+            writeln!(script, "GMAT {fm}.SRP.Flux = 1367;").unwrap();
+            writeln!(script, "GMAT {fm}.SRP.SRPModel = Spherical;").unwrap();
+        } else {
+            writeln!(script, "GMAT {fm}.SRP = Off;").unwrap();
+        }
+        writeln!(script, "GMAT {fm}.RelativisticCorrection = Off;").unwrap();
+        writeln!(
+            script,
+            "GMAT {fm}.ErrorControl = {};",
+            gmat_error_ctrl(&self.opts)?
+        )
+        .unwrap();
+
+        // Propagator
+        let (init_step, min_step, max_step) = if self.opts.fixed_step {
+            (
+                self.opts.init_step,
+                self.opts.init_step,
+                self.opts.init_step,
+            )
+        } else {
+            (self.opts.init_step, self.opts.min_step, self.opts.max_step)
+        };
+        writeln!(script, "\nCreate Propagator {PROP_NAME};").unwrap();
+        let prop_fields = [
+            ("FM", fm),
+            ("Type", gmat_integrator(self.method)?.to_string()),
+            ("InitialStepSize", format!("{}", init_step.to_seconds())),
+            ("Accuracy", format!("{:e}", self.opts.tolerance)),
+            ("MinStep", format!("{}", min_step.to_seconds())),
+            ("MaxStep", format!("{}", max_step.to_seconds())),
+            ("MaxStepAttempts", format!("{}", self.opts.attempts)),
+            ("StopIfAccuracyIsViolated", "true".to_string()),
+        ];
+        for (field, value) in prop_fields {
+            writeln!(script, "GMAT {PROP_NAME}.{field} = {value};").unwrap();
+        }
+
+        // Report
+        writeln!(script, "\nCreate ReportFile NyxReport;").unwrap();
+        writeln!(script, "GMAT NyxReport.Filename = 'NyxReport.txt';").unwrap();
+        writeln!(script, "GMAT NyxReport.Precision = 16;").unwrap();
+        writeln!(
+            script,
+            "GMAT NyxReport.Add = {{{SC_NAME}.TAIModJulian, {}}};",
+            ["X", "Y", "Z", "VX", "VY", "VZ"]
+                .iter()
+                .map(|comp| format!("{SC_NAME}.{coord_sys}.{comp}"))
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+        .unwrap();
+        writeln!(script, "GMAT NyxReport.WriteHeaders = true;").unwrap();
+
+        // Mission sequence
+        writeln!(script, "\nBeginMissionSequence;").unwrap();
+        writeln!(
+            script,
+            "Propagate {PROP_NAME}({SC_NAME}) {{{SC_NAME}.ElapsedSecs = {}}};",
+            (self.end_epoch - orbit.epoch).to_seconds()
+        )
+        .unwrap();
+
+        Ok(script)
+    }
+
+    /// Writes the GMAT script to the provided file.
+    pub fn to_script_file<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, NyxError> {
+        let script = self.to_script()?;
+        let path_buf = path.as_ref().to_path_buf();
+        let mut file = File::create(&path_buf).map_err(|e| NyxError::Gmat {
+            msg: format!("File creation error: {e}"),
+        })?;
+        file.write_all(script.as_bytes())
+            .map_err(|e| NyxError::Gmat {
+                msg: format!("Could not write: {e}"),
+            })?;
+        Ok(path_buf)
+    }
+}
+
+impl ThrustHistory {
+    /// Reads a GMAT ThrustHistoryFile, where each `BeginThrust{name}` ... `EndThrust{name}` block is a segment.
+    ///
+    /// Each data row holds the time in seconds since the `Start_Epoch` of the segment, the thrust vector in Newtons and,
+    /// with `ModelThrustAndMassRate`, the mass flow rate in kg/s. The start epochs are either in the GMAT UTC Gregorian
+    /// format (e.g. `01 Jan 2000 11:59:28.000`) or in any format supported by hifitime.
+    ///
+    /// Only the `None` and `Linear` interpolation methods are supported. The thrust vectors must be expressed in
+    /// an MJ2000 equatorial coordinate system (i.e. the inertial frame of the spacecraft), or in one of the
+    /// `Inertial`, `RIC`, `VNC` and `RCN` local frames of Nyx.
+    pub fn from_gmat_str(content: &str) -> Result<Self, NyxError> {
+        let mut segments = Vec::new();
+        let mut segment: Option<ThrustSegment> = None;
+        let mut has_mass_flow = false;
+
+        for (lno, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('%') || line.starts_with('#') {
+                continue;
+            }
+            let err = |msg: String| NyxError::Gmat {
+                msg: format!("thrust history line {}: {msg}", lno + 1),
+            };
+
+            if let Some(name) = block_name(line, "BeginThrust") {
+                if segment.is_some() {
+                    return Err(err(format!(
+                        "{name} begins before the previous segment ends"
+                    )));
+                }
+                segment = Some(ThrustSegment {
+                    name: name.to_string(),
+                    start: Epoch::from_tai_seconds(0.0),
+                    frame: LocalFrame::Inertial,
+                    interpolation: ThrustInterpolation::None,
+                    mass_flow_interpolation: ThrustInterpolation::None,
+                    samples: Vec::new(),
+                });
+                has_mass_flow = false;
+                continue;
+            }
+
+            let cur = segment
+                .as_mut()
+                .ok_or_else(|| err(format!("`{line}` outside of a thrust segment")))?;
+
+            if let Some(name) = block_name(line, "EndThrust") {
+                if name != cur.name {
+                    return Err(err(format!("{name} ends segment {}", cur.name)));
+                }
+                if cur.samples.is_empty() {
+                    return Err(err(format!("segment {name} has no data")));
+                }
+                segments.push(segment.take().unwrap());
+            } else if let Some((key, value)) = line.split_once('=') {
+                let value = value.trim().trim_matches('\'').trim_end_matches(';');
+                match key.trim() {
+                    "Start_Epoch" => cur.start = gmat_epoch(value).map_err(err)?,
+                    "Thrust_Vector_Coordinate_System" => {
+                        cur.frame = gmat_thrust_frame(value).map_err(err)?
+                    }
+                    "Thrust_Vector_Interpolation_Method" => {
+                        cur.interpolation = gmat_interpolation(value).map_err(err)?
+                    }
+                    "Mass_Flow_Rate_Interpolation_Method" => {
+                        cur.mass_flow_interpolation = gmat_interpolation(value).map_err(err)?
+                    }
+                    key => warn!("ignoring `{key}` of thrust segment {}", cur.name),
+                }
+            } else if line == "ModelThrustAndMassRate" {
+                has_mass_flow = true;
+            } else if line == "ModelThrustOnly" {
+                has_mass_flow = false;
+            } else {
+                let values = line
+                    .split_whitespace()
+                    .map(|token| token.parse::<f64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| err(format!("invalid data `{line}`: {e}")))?;
+                let expected = if has_mass_flow { 5 } else { 4 };
+                if values.len() != expected {
+                    return Err(err(format!(
+                        "expected {expected} values but found {}",
+                        values.len()
+                    )));
+                }
+                let offset = Unit::Second * values[0];
+                if cur.samples.last().is_some_and(|prev| prev.offset >= offset) {
+                    return Err(err("times must be strictly increasing".to_string()));
+                }
+                cur.samples.push(ThrustSample {
+                    offset,
+                    thrust_N: Vector3::new(values[1], values[2], values[3]),
+                    mass_flow_kg_s: has_mass_flow.then(|| values[4]),
+                });
+            }
+        }
+
+        if let Some(segment) = segment {
+            return Err(NyxError::Gmat {
+                msg: format!("thrust segment {} is never ended", segment.name),
+            });
+        }
+
+        Ok(Self::new(segments))
+    }
+
+    /// Reads a GMAT ThrustHistoryFile, cf. [ThrustHistory::from_gmat_str].
+    pub fn from_gmat_file<P: AsRef<Path>>(path: P) -> Result<Self, NyxError> {
+        let content = read_to_string(&path).map_err(|e| NyxError::Gmat {
+            msg: format!("could not read {}: {e}", path.as_ref().display()),
+        })?;
+        Self::from_gmat_str(&content)
+    }
+}
+
+/// Returns the name of this block if the line is `keyword{name}`.
+fn block_name<'a>(line: &'a str, keyword: &str) -> Option<&'a str> {
+    line.strip_prefix(keyword)?
+        .trim()
+        .strip_prefix('{')?
+        .strip_suffix('}')
+        .map(str::trim)
+}
+
+/// Parses a GMAT UTC Gregorian epoch, e.g. `01 Jan 2000 11:59:28.000`, or any epoch supported by hifitime.
+fn gmat_epoch(value: &str) -> Result<Epoch, String> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let parts = value.split_whitespace().collect::<Vec<_>>();
+    if let [day, month, year, time] = parts[..] {
+        if let Some(month_idx) = MONTHS.iter().position(|m| m.eq_ignore_ascii_case(month)) {
+            return Epoch::from_str(&format!("{year}-{:02}-{day:0>2}T{time} UTC", month_idx + 1))
+                .map_err(|e| format!("invalid epoch `{value}`: {e}"));
+        }
+    }
+    Epoch::from_str(value).map_err(|e| format!("invalid epoch `{value}`: {e}"))
+}
+
+fn gmat_thrust_frame(value: &str) -> Result<LocalFrame, String> {
+    match value {
+        "Inertial" => Ok(LocalFrame::Inertial),
+        "RIC" => Ok(LocalFrame::RIC),
+        "VNC" => Ok(LocalFrame::VNC),
+        "RCN" => Ok(LocalFrame::RCN),
+        _ if value.ends_with("MJ2000Eq") => Ok(LocalFrame::Inertial),
+        _ => Err(format!("unsupported thrust coordinate system `{value}`")),
+    }
+}
+
+fn gmat_interpolation(value: &str) -> Result<ThrustInterpolation, String> {
+    match value {
+        "None" => Ok(ThrustInterpolation::None),
+        "Linear" => Ok(ThrustInterpolation::Linear),
+        _ => Err(format!("unsupported interpolation method `{value}`")),
+    }
+}
+
+/// Returns the GMAT name of the celestial body of this NAIF ID, where planet barycenters are treated as the planets.
+fn gmat_body(naif_id: i32) -> Result<&'static str, NyxError> {
+    match naif_id {
+        10 => Ok("Sun"),
+        1 | 199 => Ok("Mercury"),
+        2 | 299 => Ok("Venus"),
+        399 => Ok("Earth"),
+        301 => Ok("Luna"),
+        4 | 499 => Ok("Mars"),
+        5 | 599 => Ok("Jupiter"),
+        6 | 699 => Ok("Saturn"),
+        7 | 799 => Ok("Uranus"),
+        8 | 899 => Ok("Neptune"),
+        9 | 999 => Ok("Pluto"),
+        _ => Err(NyxError::Gmat {
+            msg: format!("NAIF ID {naif_id} is not a GMAT celestial body"),
+        }),
+    }
+}
+
+fn gmat_integrator(method: IntegratorMethod) -> Result<&'static str, NyxError> {
+    match method {
+        IntegratorMethod::RungeKutta89 => Ok("RungeKutta89"),
+        IntegratorMethod::DormandPrince78 => Ok("PrinceDormand78"),
+        IntegratorMethod::DormandPrince45 => Ok("PrinceDormand45"),
+        IntegratorMethod::RungeKutta4 => Ok("RungeKutta4"),
+        IntegratorMethod::Verner56 => Ok("RungeKutta56"),
+        _ => Err(NyxError::Gmat {
+            msg: format!("{method:?} has no GMAT equivalent integrator"),
+        }),
+    }
+}
+
+/// Returns the GMAT error control, where the Cartesian error controls of Nyx are approximated by the GMAT ones.
+fn gmat_error_ctrl(opts: &IntegratorOptions) -> Result<&'static str, NyxError> {
+    if opts.component_tolerances.is_some() {
+        return Err(NyxError::Gmat {
+            msg: "component tolerances have no GMAT equivalent error control".to_string(),
+        });
+    }
+    match opts.error_ctrl {
+        ErrorControl::RSSCartesianState | ErrorControl::RSSState => Ok("RSSState"),
+        ErrorControl::RSSCartesianStep | ErrorControl::RSSStep => Ok("RSSStep"),
+        ErrorControl::LargestState => Ok("LargestState"),
+        ErrorControl::LargestStep => Ok("LargestStep"),
+        ErrorControl::LargestError => Err(NyxError::Gmat {
+            msg: "LargestError has no GMAT equivalent error control".to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod ut_gmat {
+    use super::*;
+    use crate::cosmic::Orbit;
+    use crate::fixtures;
+    use crate::tools::scenario::{GravityFormat, HarmonicsScenario};
+    use anise::constants::frames::{EARTH_J2000, IAU_EARTH_FRAME, MOON_J2000};
+
+    #[test]
+    fn leo_script() {
+        // Same initial state as the GMAT propagator validation scripts
+        let epoch = Epoch::from_jde_tai(2_451_545.0);
+        let orbit = Orbit::cartesian(
+            -2436.45,
+            -2436.45,
+            6891.037,
+            5.088611,
+            -5.088611,
+            0.0,
+            epoch,
+            EARTH_J2000,
+        );
+        let spacecraft = Spacecraft::builder().orbit(orbit).build();
+
+        let gmat = GmatScript::builder()
+            .spacecraft(spacecraft)
+            .dynamics(DynamicsScenario {
+                point_masses: vec![10, 301],
+                harmonics: Some(HarmonicsScenario {
+                    frame: IAU_EARTH_FRAME,
+                    path: "data/JGM3.cof.gz".to_string(),
+                    format: GravityFormat::Cof,
+                    degree: 21,
+                    order: 21,
+                }),
+                solar_radiation_pressure: true,
+            })
+            .method(IntegratorMethod::DormandPrince45)
+            .end_epoch(epoch + Unit::Day * 1)
+            .build();
+
+        let script = gmat.to_script().unwrap();
+        println!("{script}");
+
+        for line in [
+            "GMAT NyxSC.Epoch = '21545';",
+            "GMAT NyxSC.CoordinateSystem = EarthMJ2000Eq;",
+            "GMAT NyxSC.X = -2436.45;",
+            "GMAT NyxProp_ForceModel.PrimaryBodies = {Earth};",
+            "GMAT NyxProp_ForceModel.GravityField.Earth.Degree = 21;",
+            "GMAT NyxProp_ForceModel.GravityField.Earth.PotentialFile = 'JGM3.cof';",
+            "GMAT NyxProp_ForceModel.PointMasses = {Sun, Luna};",
+            "GMAT NyxProp_ForceModel.SRP = On;",
+            "GMAT NyxProp_ForceModel.ErrorControl = RSSStep;",
+            "GMAT NyxProp.Type = PrinceDormand45;",
+            "GMAT NyxProp.Accuracy = 1e-12;",
+            "GMAT NyxProp.MaxStep = 2700;",
+            "Propagate NyxProp(NyxSC) {NyxSC.ElapsedSecs = 86400};",
+        ] {
+            assert!(script.contains(line), "missing `{line}`");
+        }
+        assert!(!script.contains("Create CoordinateSystem"));
+
+        // Unsupported configurations are rejected
+        let mut bad = gmat.clone();
+        bad.method = IntegratorMethod::Dop853;
+        assert!(bad.to_script().is_err());
+
+        let mut bad = gmat.clone();
+        bad.dynamics.point_masses.push(-85);
+        assert!(bad.to_script().is_err());
+
+        // Other central bodies need their own coordinate system, and the gravity field must be that of the central body.
+        let mut lunar = gmat;
+        lunar.spacecraft.orbit.frame = MOON_J2000;
+        assert!(lunar.to_script().is_err());
+        lunar.dynamics.harmonics = None;
+        let script = lunar.to_script().unwrap();
+        assert!(script.contains("Create CoordinateSystem LunaMJ2000Eq;"));
+        assert!(script.contains("GMAT NyxProp_ForceModel.PointMasses = {Luna, Sun};"));
+    }
+
+    #[test]
+    fn thrust_history() {
+        use crate::cosmic::{GuidanceMode, STD_GRAVITY};
+        use crate::dynamics::guidance::Thruster;
+        use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+        use crate::propagators::Propagator;
+        use std::sync::Arc;
+
+        let thf = r#"% Two burns designed in another tool
+BeginThrust{Burn1}
+Start_Epoch = 01 Jan 2000 12:00:00.000
+Thrust_Vector_Coordinate_System = EarthMJ2000Eq
+Thrust_Vector_Interpolation_Method = None
+Mass_Flow_Rate_Interpolation_Method = None
+ModelThrustAndMassRate
+0.0   1.0 0.0 0.0 -0.001
+60.0  1.0 0.0 0.0 -0.001
+EndThrust{Burn1}
+
+BeginThrust{Burn2}
+Start_Epoch = 01 Jan 2000 13:00:00.000
+Thrust_Vector_Coordinate_System = VNC
+Thrust_Vector_Interpolation_Method = Linear
+ModelThrustOnly
+0.0   0.0 0.0 0.0
+100.0 2.0 0.0 0.0
+EndThrust{Burn2}
+"#;
+        let history = ThrustHistory::from_gmat_str(thf).unwrap();
+        assert_eq!(history.segments.len(), 2);
+        let burn2 = &history.segments[1];
+        assert_eq!(
+            burn2.start,
+            Epoch::from_gregorian_utc_hms(2000, 1, 1, 13, 0, 0)
+        );
+        assert_eq!(burn2.frame, LocalFrame::VNC);
+        assert_eq!(
+            burn2.thrust_at(burn2.start + Unit::Second * 50),
+            Some((Vector3::new(1.0, 0.0, 0.0), None))
+        );
+        let thruster = history.equivalent_thruster();
+        assert_eq!(thruster.thrust_N, 2.0);
+        assert!((thruster.isp_s - 1.0 / (1e-3 * STD_GRAVITY)).abs() < 1e-9);
+
+        // Invalid files are rejected
+        assert!(ThrustHistory::from_gmat_str(&thf.replace("EndThrust{Burn2}", "")).is_err());
+        assert!(ThrustHistory::from_gmat_str(&thf.replace("60.0 ", "0.0 ")).is_err());
+        assert!(ThrustHistory::from_gmat_str(&thf.replace("= Linear", "= CubicSpline")).is_err());
+
+        // Replay the burns: the first uses the tabulated mass flow, the second the Isp of the thruster
+        let epoch = Epoch::from_gregorian_utc_hms(2000, 1, 1, 11, 59, 0);
+        let eme2k = fixtures::eme2k();
+        let orbit = Orbit::keplerian(7_000.0, 0.01, 28.5, 10.0, 20.0, 30.0, epoch, eme2k);
+        let sc = Spacecraft::from_thruster(
+            orbit,
+            500.0,
+            100.0,
+            Thruster::new(2.0, 300.0),
+            GuidanceMode::Thrust,
+        );
+        let dynamics =
+            SpacecraftDynamics::from_guidance_law(OrbitalDynamics::two_body(), Arc::new(history));
+        let setup = Propagator::default(dynamics);
+        let mut prop = setup.with(sc, fixtures::almanac());
+
+        let after_burn1 = prop.until_epoch(epoch + Unit::Minute * 30).unwrap();
+        assert!((after_burn1.mass.prop_mass_kg - (100.0 - 0.06)).abs() < 1e-9);
+
+        let after_burn2 = prop.until_epoch(epoch + Unit::Hour * 2).unwrap();
+        let burn2_usage = 100.0 / (300.0 * STD_GRAVITY);
+        // The ramp is held constant at the stages evaluated past the end of the step, hence a looser tolerance
+        assert!((after_burn2.mass.prop_mass_kg - (100.0 - 0.06 - burn2_usage)).abs() < 1e-6);
+        assert_eq!(after_burn2.mode(), GuidanceMode::Coast);
+    }
+}
@@ -16,6 +16,7 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use crate::cosmic::Frame;
 use crate::linalg::DMatrix;
 use crate::NyxError;
 use flate2::read::GzDecoder;
@@ -23,6 +24,38 @@ use std::fs::File;
 use std::io::prelude::*;
 use std::str::FromStr;
 
+/// Nominal degree 2 zonal Love number of the Earth used for the permanent tide conversions (IERS Conventions 2010, section 6.2.2)
+pub const EARTH_K20: f64 = 0.30190;
+
+/// Product of A0 and H0, i.e. the normalized C20 of the Earth's permanent tide potential (IERS Conventions 2010, section 6.2.2)
+const EARTH_A0_H0: f64 = 4.4228e-8 * -0.31460;
+
+/// Convention used for the permanent tide in the C20 coefficient of a gravity field.
+///
+/// Fields from different sources use different conventions (e.g. EGM2008 is tide free while the GRACE fields are
+/// often zero tide), so they must be converted to the same convention before being compared or combined with
+/// a solid tide model.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TideSystem {
+    /// The permanent tide and the associated permanent deformation of the Earth are both removed
+    TideFree,
+    /// The permanent deformation is included but not the permanent tide potential (IERS recommendation)
+    ZeroTide,
+    /// Both the permanent tide potential and the permanent deformation are included
+    MeanTide,
+}
+
+impl TideSystem {
+    /// Returns the offset to add to the normalized C20 of the Earth to convert it from tide free to this system.
+    fn c20_offset_from_tide_free(&self, k20: f64) -> f64 {
+        match self {
+            Self::TideFree => 0.0,
+            Self::ZeroTide => EARTH_A0_H0 * k20,
+            Self::MeanTide => EARTH_A0_H0 * (1.0 + k20),
+        }
+    }
+}
+
 /// `HarmonicsMem` loads the requested gravity potential files and stores them in memory (in a HashMap).
 ///
 /// WARNING: This memory backend may require a lot of RAM (e.g. EMG2008 2190x2190 requires nearly 400 MB of RAM).
@@ -32,6 +65,12 @@ pub struct HarmonicsMem {
     order: usize,
     c_nm: DMatrix<f64>,
     s_nm: DMatrix<f64>,
+    /// Gravitational parameter of the model, if specified in the file
+    gm_km3_s2: Option<f64>,
+    /// Reference radius of the model, if specified in the file
+    radius_km: Option<f64>,
+    /// Permanent tide convention of the C20 coefficient, if known
+    tide_system: Option<TideSystem>,
 }
 
 impl HarmonicsMem {
@@ -45,6 +84,9 @@ impl HarmonicsMem {
             order: 0,
             c_nm,
             s_nm: DMatrix::from_element(3, 3, 0.0),
+            gm_km3_s2: None,
+            radius_km: None,
+            tide_system: None,
         }
     }
 
@@ -71,7 +113,10 @@ impl HarmonicsMem {
         Self::from_j2(-0.484_165_143_790_815e-03)
     }
 
-    /// Initialize `HarmonicsMem` from the file path (must be a gunzipped file)
+    /// Initialize `HarmonicsMem` from a PDS SHADR/SHA file (e.g. the GRAIL lunar fields or the MRO Mars fields).
+    ///
+    /// The reference radius and gravitational parameter are read from the header and can be checked
+    /// against the compute frame with `check_frame`.
     ///
     /// Gravity models provided by `nyx`:
     /// + EMG2008 to 2190 for Earth (tide free)
@@ -90,6 +135,10 @@ impl HarmonicsMem {
         )
     }
 
+    /// Initialize `HarmonicsMem` from an EGM-formatted file (e.g. EGM2008).
+    ///
+    /// These files do not specify their permanent tide convention, so declare it with `with_tide_system`
+    /// (e.g. the provided EGM2008 file is tide free).
     pub fn from_egm(
         filepath: &str,
         degree: usize,
@@ -132,7 +181,22 @@ impl HarmonicsMem {
         let mut s_nm_mat = DMatrix::from_element(degree + 1, degree + 1, 0.0);
         let mut max_order: usize = 0;
         let mut max_degree: usize = 0;
+        let mut gm_km3_s2 = None;
+        let mut radius_km = None;
         for (lno, line) in data_as_str.split('\n').enumerate() {
+            if line.starts_with("POTFIELD") {
+                // POTFIELD degree order body GM (m^3/s^2) radius (m) normalization
+                let items: Vec<&str> = line.split_whitespace().collect();
+                gm_km3_s2 = items
+                    .get(4)
+                    .and_then(|item| f64::from_str(item).ok())
+                    .map(|gm_m3_s2| gm_m3_s2 * 1e-9);
+                radius_km = items
+                    .get(5)
+                    .and_then(|item| f64::from_str(item).ok())
+                    .map(|radius_m| radius_m * 1e-3);
+                continue;
+            }
             if line.is_empty() || !line.starts_with('R') {
                 continue; // This is either a comment, a header or "END"
             }
@@ -317,6 +381,204 @@ impl HarmonicsMem {
             order: max_order,
             c_nm: c_nm_mat,
             s_nm: s_nm_mat,
+            gm_km3_s2,
+            radius_km,
+            tide_system: None,
+        })
+    }
+
+    /// Initialize `HarmonicsMem` from an ICGEM `.gfc` file (e.g. EIGEN-6C4, GGM05C, GOCO06s).
+    ///
+    /// The `earth_gravity_constant` and `radius` header keywords are stored so they can be checked against
+    /// the compute frame with `check_frame`. Unnormalized fields are converted to fully normalized coefficients.
+    /// Only the static part of the field is loaded: the reference values of `gfct` records are used,
+    /// and the `trnd`, `acos` and `asin` time variable records are ignored.
+    pub fn from_icgem(
+        filepath: &str,
+        degree: usize,
+        order: usize,
+        gunzipped: bool,
+    ) -> Result<HarmonicsMem, NyxError> {
+        let data_as_str = Self::read_to_string(filepath, gunzipped)?;
+
+        let mut c_nm_mat = DMatrix::from_element(degree + 1, degree + 1, 0.0);
+        let mut s_nm_mat = DMatrix::from_element(degree + 1, degree + 1, 0.0);
+        let mut max_degree: usize = 0;
+        let mut max_order: usize = 0;
+        let mut gm_km3_s2 = None;
+        let mut radius_km = None;
+        let mut normalized = true;
+        let mut tide_system = None;
+        let mut in_header = true;
+        let mut ignored_time_variable = false;
+
+        for (lno, line) in data_as_str.lines().enumerate() {
+            let items: Vec<&str> = line.split_whitespace().collect();
+            if items.is_empty() {
+                continue;
+            }
+
+            if in_header {
+                match items[0] {
+                    "end_of_head" => in_header = false,
+                    "earth_gravity_constant" | "radius" if items.len() > 1 => {
+                        let value =
+                            f64::from_str(&items[1].replace(['D', 'd'], "E")).map_err(|_| {
+                                NyxError::FileUnreadable {
+                                    msg: format!(
+                                        "ICGEM file: could not parse `{}` on line {lno}",
+                                        items[0]
+                                    ),
+                                }
+                            })?;
+                        // ICGEM files use SI units
+                        if items[0] == "radius" {
+                            radius_km = Some(value * 1e-3);
+                        } else {
+                            gm_km3_s2 = Some(value * 1e-9);
+                        }
+                    }
+                    "norm" if items.len() > 1 => normalized = items[1] != "unnormalized",
+                    "tide_system" if items.len() > 1 => {
+                        tide_system = match items[1] {
+                            "tide_free" => Some(TideSystem::TideFree),
+                            "zero_tide" => Some(TideSystem::ZeroTide),
+                            "mean_tide" => Some(TideSystem::MeanTide),
+                            other => {
+                                warn!("{filepath}: unknown tide system `{other}`");
+                                None
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+
+            match items[0] {
+                "gfc" | "gfct" => {}
+                "trnd" | "acos" | "asin" | "dot" => {
+                    ignored_time_variable = true;
+                    continue;
+                }
+                _ => continue,
+            }
+
+            if items.len() < 5 {
+                return Err(NyxError::FileUnreadable {
+                    msg: format!("ICGEM file: expected at least five items on line {lno}"),
+                });
+            }
+
+            let cur_degree = usize::from_str(items[1]).map_err(|_| NyxError::FileUnreadable {
+                msg: format!(
+                    "ICGEM file: could not parse degree `{}` on line {lno}",
+                    items[1]
+                ),
+            })?;
+            let cur_order = usize::from_str(items[2]).map_err(|_| NyxError::FileUnreadable {
+                msg: format!(
+                    "ICGEM file: could not parse order `{}` on line {lno}",
+                    items[2]
+                ),
+            })?;
+            let mut c_nm = f64::from_str(&items[3].replace(['D', 'd'], "E")).map_err(|_| {
+                NyxError::FileUnreadable {
+                    msg: format!(
+                        "ICGEM file: could not parse C_nm `{}` on line {lno}",
+                        items[3]
+                    ),
+                }
+            })?;
+            let mut s_nm = f64::from_str(&items[4].replace(['D', 'd'], "E")).map_err(|_| {
+                NyxError::FileUnreadable {
+                    msg: format!(
+                        "ICGEM file: could not parse S_nm `{}` on line {lno}",
+                        items[4]
+                    ),
+                }
+            })?;
+
+            // ICGEM files are not necessarily sorted by degree, so we cannot stop reading early.
+            if cur_degree > degree || cur_order > order {
+                continue;
+            }
+
+            if !normalized {
+                let norm = Self::normalization_factor(cur_degree, cur_order);
+                c_nm /= norm;
+                s_nm /= norm;
+            }
+
+            c_nm_mat[(cur_degree, cur_order)] = c_nm;
+            s_nm_mat[(cur_degree, cur_order)] = s_nm;
+            max_degree = max_degree.max(cur_degree);
+            max_order = max_order.max(cur_order);
+        }
+
+        if in_header {
+            return Err(NyxError::FileUnreadable {
+                msg: format!("ICGEM file: {filepath} has no `end_of_head` keyword"),
+            });
+        }
+
+        if ignored_time_variable {
+            warn!(
+                "{filepath}: time variable coefficients ignored, only the static field is loaded"
+            );
+        }
+
+        if max_degree < degree || max_order < order {
+            warn!(
+                "{filepath} only contained (degree, order) of ({max_degree}, {max_order}) instead of requested ({degree}, {order})",
+            );
+        } else {
+            info!("{filepath} loaded with (degree, order) = ({degree}, {order})");
+        }
+
+        Ok(HarmonicsMem {
+            degree: max_degree,
+            order: max_order,
+            c_nm: c_nm_mat,
+            s_nm: s_nm_mat,
+            gm_km3_s2,
+            radius_km,
+            tide_system,
+        })
+    }
+
+    /// Returns the factor N_nm such that the unnormalized coefficient is N_nm times the fully normalized one.
+    fn normalization_factor(degree: usize, order: usize) -> f64 {
+        // (n-m)!/(n+m)! computed as a product to avoid overflowing
+        let mut ratio = 1.0;
+        for i in (degree - order + 1)..=(degree + order) {
+            ratio /= i as f64;
+        }
+        let delta = if order == 0 { 1.0 } else { 2.0 };
+        (delta * (2 * degree + 1) as f64 * ratio).sqrt()
+    }
+
+    /// Reads the whole file, gunzipping it if requested.
+    fn read_to_string(filepath: &str, gunzipped: bool) -> Result<String, NyxError> {
+        let mut f = File::open(filepath).map_err(|_| NyxError::FileUnreadable {
+            msg: format!("File not found: {filepath}"),
+        })?;
+        let mut buffer = vec![0; 0];
+        if gunzipped {
+            let mut d = GzDecoder::new(f);
+            d.read_to_end(&mut buffer)
+                .map_err(|_| NyxError::FileUnreadable {
+                    msg: "could not read file as gunzip".to_string(),
+                })?;
+        } else {
+            f.read_to_end(&mut buffer)
+                .map_err(|_| NyxError::FileUnreadable {
+                    msg: "could not read file to end".to_string(),
+                })?;
+        }
+
+        String::from_utf8(buffer).map_err(|_| NyxError::FileUnreadable {
+            msg: "could not decode file contents as utf8".to_string(),
         })
     }
 
@@ -354,8 +616,18 @@ impl HarmonicsMem {
 
         let mut max_degree: usize = 0;
         let mut max_order: usize = 0;
+        let mut gm_km3_s2 = None;
+        let mut radius_km = None;
         for (lno, line) in data_as_str.split('\n').enumerate() {
             if lno == 0 && skip_first_line {
+                // The PDS SHA header starts with the reference radius (km) and the GM (km^3/s^2)
+                let items: Vec<&str> = line.split(',').map(|item| item.trim()).collect();
+                radius_km = items
+                    .first()
+                    .and_then(|item| f64::from_str(&item.replace('D', "E")).ok());
+                gm_km3_s2 = items
+                    .get(1)
+                    .and_then(|item| f64::from_str(&item.replace('D', "E")).ok());
                 continue;
             }
             // These variables need to be declared as mutable because rustc does not know
@@ -449,6 +721,9 @@ impl HarmonicsMem {
             degree: max_degree,
             c_nm: c_nm_mat,
             s_nm: s_nm_mat,
+            gm_km3_s2,
+            radius_km,
+            tide_system: None,
         })
     }
 
@@ -466,6 +741,97 @@ impl HarmonicsMem {
     pub fn cs_nm(&self, degree: usize, order: usize) -> (f64, f64) {
         (self.c_nm[(degree, order)], self.s_nm[(degree, order)])
     }
+
+    /// Returns the gravitational parameter of this model, if it was specified in the file.
+    pub fn gm_km3_s2(&self) -> Option<f64> {
+        self.gm_km3_s2
+    }
+
+    /// Returns the reference radius of this model, if it was specified in the file.
+    pub fn radius_km(&self) -> Option<f64> {
+        self.radius_km
+    }
+
+    /// Checks that the gravitational parameter and the reference radius of this model match those of the frame
+    /// in which the field will be computed, to within the provided relative tolerance.
+    ///
+    /// The spherical harmonics are computed with the GM and mean equatorial radius of the frame, so a mismatch
+    /// scales every coefficient of degree n by (R_model / R_frame)^n. Metadata missing from the file is not checked.
+    pub fn check_frame(&self, frame: Frame, rel_tol: f64) -> Result<(), NyxError> {
+        if let Some(gm_km3_s2) = self.gm_km3_s2 {
+            let frame_gm = frame.mu_km3_s2().map_err(|e| NyxError::CustomError {
+                msg: format!("{frame:x} has no GM: {e}"),
+            })?;
+            if ((gm_km3_s2 - frame_gm) / frame_gm).abs() > rel_tol {
+                return Err(NyxError::CustomError {
+                    msg: format!(
+                        "gravity field GM of {gm_km3_s2} km^3/s^2 does not match {frame_gm} km^3/s^2 of {frame:x}"
+                    ),
+                });
+            }
+        }
+
+        if let Some(radius_km) = self.radius_km {
+            let frame_radius =
+                frame
+                    .mean_equatorial_radius_km()
+                    .map_err(|e| NyxError::CustomError {
+                        msg: format!("{frame:x} has no shape: {e}"),
+                    })?;
+            if ((radius_km - frame_radius) / frame_radius).abs() > rel_tol {
+                return Err(NyxError::CustomError {
+                    msg: format!(
+                        "gravity field reference radius of {radius_km} km does not match {frame_radius} km of {frame:x}"
+                    ),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the permanent tide convention of the C20 coefficient, if known.
+    pub fn tide_system(&self) -> Option<TideSystem> {
+        self.tide_system
+    }
+
+    /// Declares the permanent tide convention of this field, e.g. for formats which do not specify it.
+    /// This does _not_ modify the coefficients, use `to_tide_system` for that.
+    pub fn with_tide_system(mut self, tide_system: TideSystem) -> Self {
+        self.tide_system = Some(tide_system);
+        self
+    }
+
+    /// Converts the C20 coefficient of this field to the requested permanent tide convention,
+    /// using the nominal Love number of the Earth [EARTH_K20].
+    ///
+    /// *WARNING:* The conversion constants are those of the EARTH, and _should not_ be used for any other body.
+    pub fn to_tide_system(self, tide_system: TideSystem) -> Result<Self, NyxError> {
+        self.to_tide_system_with_love_number(tide_system, EARTH_K20)
+    }
+
+    /// Converts the C20 coefficient of this field to the requested permanent tide convention,
+    /// using the provided degree 2 zonal Love number.
+    ///
+    /// Returns an error if the current tide system of the field is unknown: declare it with `with_tide_system`.
+    pub fn to_tide_system_with_love_number(
+        mut self,
+        tide_system: TideSystem,
+        k20: f64,
+    ) -> Result<Self, NyxError> {
+        let current = self.tide_system.ok_or(NyxError::CustomError {
+            msg: "tide system of gravity field is unknown, declare it with `with_tide_system`"
+                .to_string(),
+        })?;
+
+        if self.c_nm.nrows() > 2 {
+            self.c_nm[(2, 0)] +=
+                tide_system.c20_offset_from_tide_free(k20) - current.c20_offset_from_tide_free(k20);
+        }
+        self.tide_system = Some(tide_system);
+
+        Ok(self)
+    }
 }
 
 #[test]
@@ -478,3 +844,78 @@ fn test_load_harmonic_files() {
     HarmonicsMem::from_shadr("data/Luna_jggrx_1500e_sha.tab.gz", 1500, 1500, true)
         .expect("could not load jggrx");
 }
+
+#[test]
+fn test_load_icgem() {
+    use anise::structure::planetocentric::ellipsoid::Ellipsoid;
+
+    let icgem = HarmonicsMem::from_icgem("data/tests/gravity/egm2008_excerpt.gfc", 4, 4, false)
+        .expect("could not load ICGEM file");
+    let egm = HarmonicsMem::from_egm("data/EGM2008_to2190_TideFree.gz", 4, 4, true)
+        .expect("could not load EGM2008");
+
+    assert_eq!(icgem.max_degree_n(), 4);
+    assert_eq!(icgem.max_order_m(), 4);
+    for n in 2..=4 {
+        for m in 0..=n {
+            assert_eq!(icgem.cs_nm(n, m), egm.cs_nm(n, m), "({n}, {m})");
+        }
+    }
+
+    assert_eq!(icgem.gm_km3_s2(), Some(398_600.441_5));
+    assert_eq!(icgem.radius_km(), Some(6_378.136_3));
+    assert_eq!(egm.gm_km3_s2(), None);
+
+    let mut earth = crate::fixtures::eme2k();
+    earth.shape = Some(Ellipsoid::from_sphere(6_378.136_3));
+    assert!(icgem.check_frame(earth, 1e-9).is_ok());
+
+    earth.shape = Some(Ellipsoid::from_sphere(6_378.0));
+    assert!(icgem.check_frame(earth, 1e-9).is_err());
+    assert!(icgem.check_frame(earth, 1e-3).is_ok());
+
+    // SHA and COF headers are also parsed
+    let jggrx = HarmonicsMem::from_shadr("data/Luna_jggrx_1500e_sha.tab.gz", 2, 2, true)
+        .expect("could not load jggrx");
+    assert_eq!(jggrx.radius_km(), Some(1_738.0));
+    assert_eq!(jggrx.gm_km3_s2(), Some(4_902.800_456_866));
+
+    let jgm3 = HarmonicsMem::from_cof("data/JGM3.cof.gz", 2, 2, true).expect("could not load JGM3");
+    assert!((jgm3.gm_km3_s2().unwrap() - 398_600.441_5).abs() < 1e-9);
+    assert!((jgm3.radius_km().unwrap() - 6_378.136_3).abs() < 1e-9);
+
+    // Unnormalized J2 is sqrt(5) times the normalized C20
+    assert!((HarmonicsMem::normalization_factor(2, 0) - 5.0_f64.sqrt()).abs() < 1e-15);
+    assert!((HarmonicsMem::normalization_factor(2, 2) - (5.0_f64 / 12.0).sqrt()).abs() < 1e-15);
+}
+
+#[test]
+fn test_tide_system() {
+    let tide_free = HarmonicsMem::from_icgem("data/tests/gravity/egm2008_excerpt.gfc", 4, 4, false)
+        .expect("could not load ICGEM file");
+    assert_eq!(tide_free.tide_system(), Some(TideSystem::TideFree));
+    let c20_tf = tide_free.cs_nm(2, 0).0;
+
+    let zero_tide = tide_free
+        .clone()
+        .to_tide_system(TideSystem::ZeroTide)
+        .unwrap();
+    assert_eq!(zero_tide.tide_system(), Some(TideSystem::ZeroTide));
+    // A0 H0 k20 with the nominal anelastic k20
+    assert!((zero_tide.cs_nm(2, 0).0 - c20_tf + 4.2006e-9).abs() < 1e-12);
+    // Only C20 is affected
+    assert_eq!(zero_tide.cs_nm(3, 0), tide_free.cs_nm(3, 0));
+
+    let mean_tide = zero_tide.to_tide_system(TideSystem::MeanTide).unwrap();
+    let back = mean_tide.to_tide_system(TideSystem::TideFree).unwrap();
+    assert!((back.cs_nm(2, 0).0 - c20_tf).abs() < 1e-18);
+
+    // Unknown tide systems must be declared before conversion
+    let egm = HarmonicsMem::from_egm("data/EGM2008_to2190_TideFree.gz", 2, 2, true).unwrap();
+    assert!(egm.clone().to_tide_system(TideSystem::ZeroTide).is_err());
+    let egm = egm
+        .with_tide_system(TideSystem::TideFree)
+        .to_tide_system(TideSystem::ZeroTide)
+        .unwrap();
+    assert!((egm.cs_nm(2, 0).0 - c20_tf + 4.2006e-9).abs() < 1e-12);
+}
@@ -0,0 +1,323 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::percent_encode;
+use crate::cosmic::{Orbit, Spacecraft};
+use crate::errors::NyxError;
+use crate::md::prelude::Traj;
+use crate::time::{Duration, Epoch, Unit};
+use anise::constants::frames::SUN_J2000;
+use anise::constants::orientations::J2000;
+use anise::prelude::Frame;
+use log::debug;
+use std::fs::read_to_string;
+use std::path::Path;
+use typed_builder::TypedBuilder;
+
+/// Endpoint of the JPL Horizons API
+pub const HORIZONS_API_URL: &str = "https://ssd.jpl.nasa.gov/api/horizons.api";
+
+/// A query of the state vectors of a small body or a spacecraft from [JPL Horizons](https://ssd.jpl.nasa.gov/horizons/).
+///
+/// The states are requested in the ICRF (i.e. the J2000 frame of Nyx), in kilometers and seconds, in the TDB time scale,
+/// and relative to the center of the provided frame.
+/// The query itself is always available (e.g. to download the ephemeris with another tool), but fetching it requires the `horizons` feature.
+#[derive(Clone, Debug, TypedBuilder)]
+#[builder(doc)]
+pub struct HorizonsQuery {
+    /// Horizons command of the target, e.g. `DES=2000433;` for the asteroid Eros, `DES=1P;` for Halley's comet or `-74` for MRO
+    #[builder(setter(into))]
+    pub target: String,
+    /// Center of the returned states, only the ephemeris of this frame is used
+    #[builder(default = SUN_J2000)]
+    pub center: Frame,
+    pub start: Epoch,
+    pub end: Epoch,
+    /// Requested step between the states, adjusted so that the span is split into equal intervals
+    pub step: Duration,
+}
+
+impl HorizonsQuery {
+    /// Returns the URL of this query to the Horizons API, with a plain text response.
+    pub fn url(&self) -> String {
+        let intervals = ((self.end - self.start).to_seconds() / self.step.to_seconds())
+            .ceil()
+            .max(1.0);
+        let params = [
+            ("format", "text".to_string()),
+            ("COMMAND", format!("'{}'", self.target)),
+            ("OBJ_DATA", "'NO'".to_string()),
+            ("MAKE_EPHEM", "'YES'".to_string()),
+            ("EPHEM_TYPE", "'VECTORS'".to_string()),
+            ("CENTER", format!("'500@{}'", self.center.ephemeris_id)),
+            ("REF_PLANE", "'FRAME'".to_string()),
+            ("REF_SYSTEM", "'ICRF'".to_string()),
+            ("VEC_TABLE", "'2'".to_string()),
+            ("VEC_LABELS", "'NO'".to_string()),
+            ("CSV_FORMAT", "'YES'".to_string()),
+            ("OUT_UNITS", "'KM-S'".to_string()),
+            ("TIME_TYPE", "'TDB'".to_string()),
+            (
+                "START_TIME",
+                format!("'JD {:.9}'", self.start.to_jde_tdb_days()),
+            ),
+            (
+                "STOP_TIME",
+                format!("'JD {:.9}'", self.end.to_jde_tdb_days()),
+            ),
+            ("STEP_SIZE", format!("'{intervals}'")),
+        ];
+
+        let query = params
+            .iter()
+            .map(|(key, value)| format!("{key}={}", percent_encode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        format!("{HORIZONS_API_URL}?{query}")
+    }
+
+    /// Sends this query to JPL Horizons and parses the response.
+    #[cfg(feature = "horizons")]
+    pub fn fetch(&self) -> Result<HorizonsEphemeris, NyxError> {
+        let url = self.url();
+        debug!("querying {url}");
+
+        let text = super::http::get_text(&url).map_err(|msg| NyxError::Horizons { msg })?;
+
+        HorizonsEphemeris::from_text(&text, self.center)
+    }
+}
+
+/// The state vectors of a Horizons response.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HorizonsEphemeris {
+    /// Target body name reported by Horizons, e.g. `433 Eros (A898 PA)`
+    pub target_name: Option<String>,
+    pub states: Vec<Orbit>,
+}
+
+impl HorizonsEphemeris {
+    /// Reads a Horizons vector table previously saved to a file, cf. [HorizonsEphemeris::from_text].
+    pub fn from_file<P: AsRef<Path>>(path: P, center: Frame) -> Result<Self, NyxError> {
+        let text = read_to_string(path).map_err(|e| NyxError::Horizons {
+            msg: format!("File read error: {e}"),
+        })?;
+        Self::from_text(&text, center)
+    }
+
+    /// Parses the vector table of a Horizons response, i.e. the lines between `$$SOE` and `$$EOE`, in the CSV format
+    /// and without labels. The states are expressed in the J2000 orientation of the provided center.
+    pub fn from_text(text: &str, center: Frame) -> Result<Self, NyxError> {
+        let frame = Frame::new(center.ephemeris_id, J2000);
+
+        let target_name = text
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("Target body name:"))
+            .map(|name| match name.find('{') {
+                Some(idx) => name[..idx].trim().to_string(),
+                None => name.trim().to_string(),
+            });
+
+        let table = match (text.find("$$SOE"), text.find("$$EOE")) {
+            (Some(start), Some(end)) if start < end => &text[start + 5..end],
+            _ => {
+                // Horizons reports errors, like an ambiguous target, in place of the table
+                let reason = text
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .take(5)
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                return Err(NyxError::Horizons {
+                    msg: format!("no vector table in response: {reason}"),
+                });
+            }
+        };
+
+        let mut states = Vec::new();
+        for line in table.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            // JDTDB, Calendar Date (TDB), X, Y, Z, VX, VY, VZ,
+            let parts = line.split(',').map(str::trim).collect::<Vec<_>>();
+            if parts.len() < 8 {
+                return Err(NyxError::Horizons {
+                    msg: format!("expected eight columns in `{line}`"),
+                });
+            }
+
+            let mut values = [0.0; 6];
+            for (value, part) in values.iter_mut().zip(&parts[2..8]) {
+                *value = parse_f64(part)?;
+            }
+
+            states.push(Orbit::new(
+                values[0],
+                values[1],
+                values[2],
+                values[3],
+                values[4],
+                values[5],
+                jde_tdb_epoch(parts[0])?,
+                frame,
+            ));
+        }
+
+        if states.is_empty() {
+            return Err(NyxError::Horizons {
+                msg: "empty vector table".to_string(),
+            });
+        }
+
+        debug!("read {} states of {target_name:?}", states.len());
+
+        Ok(Self {
+            target_name,
+            states,
+        })
+    }
+
+    /// Builds the trajectory of these states, e.g. to use a comet or an asteroid as a target.
+    /// Export it with [Traj::to_oem_file] to convert it to an SPK file with ANISE, and load it in an Almanac.
+    /// If no spacecraft template is provided, then a default massless spacecraft will be built.
+    pub fn to_traj(&self, tpl_option: Option<Spacecraft>) -> Traj<Spacecraft> {
+        let template = tpl_option.unwrap_or_default();
+        let mut traj = Traj::new();
+        traj.name.clone_from(&self.target_name);
+        traj.states = self
+            .states
+            .iter()
+            .map(|orbit| template.with_orbit(*orbit))
+            .collect();
+        traj.finalize();
+        traj
+    }
+}
+
+fn parse_f64(value: &str) -> Result<f64, NyxError> {
+    value.parse::<f64>().map_err(|e| NyxError::Horizons {
+        msg: format!("could not parse `{value}`: {e}"),
+    })
+}
+
+/// Parses a Julian date in TDB, keeping the integer and fractional days apart to preserve the precision.
+fn jde_tdb_epoch(value: &str) -> Result<Epoch, NyxError> {
+    let (days, fraction) = match value.split_once('.') {
+        Some((days, fraction)) => (days, format!("0.{fraction}")),
+        None => (value, "0".to_string()),
+    };
+    // J2000 is JD 2451545.0 TDB
+    let days_since_j2000 = parse_f64(days)? - 2_451_545.0;
+    Ok(Epoch::from_tdb_duration(
+        days_since_j2000 * Unit::Day + parse_f64(&fraction)? * Unit::Day,
+    ))
+}
+
+#[cfg(test)]
+mod ut_horizons {
+    use super::*;
+    use crate::time::TimeScale;
+    use anise::constants::frames::EARTH_J2000;
+
+    /// Abbreviated response of Horizons
+    const RESPONSE: &str = "API VERSION: 1.2
+*******************************************************************************
+Ephemeris / API_USER Mon Mar  4 10:24:47 2024 Pasadena, USA      / Horizons
+*******************************************************************************
+Target body name: 433 Eros (A898 PA)              {source: JPL#659}
+Center body name: Sun (10)                        {source: DE441}
+Center-site name: BODY CENTER
+*******************************************************************************
+JDTDB, Calendar Date (TDB), X, Y, Z, VX, VY, VZ,
+**************************************************************************************************************************
+$$SOE
+2460310.500000000, A.D. 2024-Jan-01 00:00:00.0000, -1.668163604437913E+08,  1.215013823339815E+08,  4.791569946099590E+07, -1.358245398093069E+01, -1.490617001346218E+01, -9.233154283624391E+00,
+2460311.000000000, A.D. 2024-Jan-01 12:00:00.0000, -1.673959180744131E+08,  1.208564047693624E+08,  4.751632017018218E+07, -1.324796012013497E+01, -1.495266466107018E+01, -9.255012071364215E+00,
+2460311.500000000, A.D. 2024-Jan-02 00:00:00.0000, -1.679609961542693E+08,  1.202096027640312E+08,  4.711594262815040E+07, -1.291279836021577E+01, -1.499786011290034E+01, -9.276277432049823E+00,
+$$EOE
+**************************************************************************************************************************
+";
+
+    #[test]
+    fn parse_vectors() {
+        let ephem = HorizonsEphemeris::from_text(RESPONSE, SUN_J2000).unwrap();
+        assert_eq!(ephem.target_name.as_deref(), Some("433 Eros (A898 PA)"));
+        assert_eq!(ephem.states.len(), 3);
+
+        let first = ephem.states[0];
+        assert_eq!(
+            first.epoch,
+            Epoch::from_gregorian(2024, 1, 1, 0, 0, 0, 0, TimeScale::TDB)
+        );
+        assert_eq!(first.frame, SUN_J2000);
+        assert_eq!(first.radius_km.x, -1.668163604437913E+08);
+        assert_eq!(first.velocity_km_s.z, -9.23315428362439E+00);
+        assert_eq!(
+            ephem.states[2].epoch,
+            Epoch::from_gregorian(2024, 1, 2, 0, 0, 0, 0, TimeScale::TDB)
+        );
+
+        let traj = ephem.to_traj(None);
+        assert_eq!(traj.name.as_deref(), Some("433 Eros (A898 PA)"));
+        let mid = traj
+            .at(Epoch::from_gregorian(
+                2024,
+                1,
+                1,
+                6,
+                0,
+                0,
+                0,
+                TimeScale::TDB,
+            ))
+            .unwrap();
+        // Between the first two states
+        assert!(mid.orbit.radius_km.x < first.radius_km.x);
+        assert!(mid.orbit.radius_km.x > ephem.states[1].radius_km.x);
+    }
+
+    #[test]
+    fn horizons_errors() {
+        let err = HorizonsEphemeris::from_text(
+            "API VERSION: 1.2\n\n  Multiple major-bodies match string \"MARS*\"",
+            SUN_J2000,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Multiple major-bodies"));
+    }
+
+    #[test]
+    fn query_url() {
+        let start = Epoch::from_gregorian(2024, 1, 1, 0, 0, 0, 0, TimeScale::TDB);
+        let query = HorizonsQuery::builder()
+            .target("DES=2000433;")
+            .center(EARTH_J2000)
+            .start(start)
+            .end(start + Unit::Day * 1)
+            .step(Unit::Hour * 7)
+            .build();
+
+        let url = query.url();
+        assert!(url.starts_with(HORIZONS_API_URL));
+        assert!(url.contains("COMMAND=%27DES%3D2000433%3B%27"));
+        assert!(url.contains("CENTER=%27500%40399%27"));
+        assert!(url.contains("START_TIME=%27JD%202460310.500000000%27"));
+        // Four equal intervals of six hours
+        assert!(url.contains("STEP_SIZE=%274%27"));
+    }
+}
@@ -0,0 +1,55 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::time::Duration;
+
+/// Downloads the content of this URL as bytes, with a five minute timeout and up to 500 MB.
+#[cfg(feature = "download")]
+pub(crate) fn get_bytes(url: &str) -> Result<Vec<u8>, String> {
+    let client: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(300)))
+        .build()
+        .into();
+
+    client
+        .get(url)
+        .call()
+        .map_err(|e| format!("request failed: {e}"))?
+        .body_mut()
+        .with_config()
+        .limit(500 * 1024 * 1024)
+        .read_to_vec()
+        .map_err(|e| format!("could not read response: {e}"))
+}
+
+/// Downloads the content of this URL as text, with a one minute timeout.
+#[cfg(any(feature = "horizons", feature = "celestrak"))]
+pub(crate) fn get_text(url: &str) -> Result<String, String> {
+    let client: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(60)))
+        .build()
+        .into();
+
+    client
+        .get(url)
+        .call()
+        .map_err(|e| format!("request failed: {e}"))?
+        .body_mut()
+        .read_to_string()
+        .map_err(|e| format!("could not read response: {e}"))
+}
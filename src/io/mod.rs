@@ -23,6 +23,8 @@ use crate::time::Epoch;
 use arrow::error::ArrowError;
 use parquet::errors::ParquetError;
 use snafu::prelude::*;
+#[cfg(any(feature = "horizons", feature = "celestrak", feature = "download"))]
+mod http;
 pub(crate) mod watermark;
 use hifitime::prelude::{Format, Formatter};
 use hifitime::Duration;
@@ -43,8 +45,105 @@ use typed_builder::TypedBuilder;
 /// Handles loading of gravity models using files of NASA PDS and GMAT COF. Several gunzipped files are provided with nyx.
 pub mod gravity;
 
+/// Reads and writes the CCSDS messages of single spacecraft states, i.e. the Orbit Parameter Message (OPM) and the Orbit Mean-Elements Message (OMM).
+pub mod ccsds;
+
+/// Queries the state vectors of small bodies and spacecraft from JPL Horizons. Fetching requires the `horizons` feature.
+pub mod horizons;
+
+/// Reads the general perturbations (GP) element sets of the Celestrak catalog, e.g. to screen conjunctions. Fetching requires the `celestrak` feature.
+pub mod celestrak;
+
+/// Downloads and caches the ANISE kernels needed to build an Almanac, with integrity checks and an offline fallback. Downloading requires the `download` feature.
+pub mod data;
+
+/// Validated spacecraft configurations with subsystem blocks (thrusters, tanks, plates, antennas).
+pub mod spacecraft;
+
+/// Writes GMAT scripts equivalent to the spacecraft, dynamics and propagator of a scenario, for cross-validation, and reads GMAT thrust history files.
+pub mod gmat;
+
 use std::io;
 
+/// Default number of rows of each record batch, and row group, of the parquet exports
+pub const EXPORT_BATCH_ROWS: usize = 65_536;
+
+/// Compression codec of the parquet exports.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParquetCompression {
+    Uncompressed,
+    Snappy,
+    Lz4,
+    /// Level from 1 (fastest) to 22 (smallest)
+    Zstd {
+        level: i32,
+    },
+    /// Level from 0 (fastest) to 9 (smallest)
+    Gzip {
+        level: u32,
+    },
+}
+
+impl Default for ParquetCompression {
+    /// Defaults to ZSTD level 10, which favors small files over the export speed.
+    fn default() -> Self {
+        Self::Zstd { level: 10 }
+    }
+}
+
+/// Metadata automatically added to the parquet exports, in addition to the metadata of the export configuration.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MetadataPolicy {
+    /// Nyx version, license, creator name and platform, and the creation epoch.
+    #[default]
+    Full,
+    /// Nyx version, license, and the creation epoch, but not who created the file.
+    Anonymous,
+    /// Only the metadata of the export configuration.
+    Minimal,
+}
+
+/// Writer properties of the parquet exports.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, TypedBuilder)]
+#[builder(doc)]
+pub struct ParquetCfg {
+    /// Compression codec and level, defaults to ZSTD level 10.
+    #[builder(default)]
+    #[serde(default)]
+    pub compression: ParquetCompression,
+    /// Maximum number of rows per row group, which is also the number of rows buffered by the streaming exports.
+    #[builder(default = EXPORT_BATCH_ROWS)]
+    #[serde(default = "default_row_group_rows")]
+    pub row_group_rows: usize,
+    /// Metadata added to the file
+    #[builder(default)]
+    #[serde(default)]
+    pub metadata: MetadataPolicy,
+}
+
+impl Default for ParquetCfg {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+fn default_row_group_rows() -> usize {
+    EXPORT_BATCH_ROWS
+}
+
+/// Percent encodes all but the unreserved characters of a URL.
+pub(crate) fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
 /// Configuration for exporting a trajectory to parquet.
 #[derive(Clone, Default, Serialize, Deserialize, TypedBuilder)]
 #[builder(doc)]
@@ -67,6 +166,10 @@ pub struct ExportCfg {
     /// Set to true to append the timestamp to the filename
     #[builder(default)]
     pub timestamp: bool,
+    /// Compression, row group size, and metadata policy of the parquet file
+    #[builder(default)]
+    #[serde(default)]
+    pub parquet: ParquetCfg,
 }
 
 impl ExportCfg {
@@ -130,6 +233,15 @@ pub enum ConfigError {
     #[snafu(display("failed to parse YAML configuration file: {source}"))]
     ParseError { source: serde_yml::Error },
 
+    #[snafu(display("failed to parse JSON configuration file: {source}"))]
+    ParseJsonError { source: serde_json::Error },
+
+    #[snafu(display("failed to write configuration file: {source}"))]
+    WriteError { source: io::Error },
+
+    #[snafu(display("failed to serialize configuration to YAML: {source}"))]
+    SerializeError { source: serde_yml::Error },
+
     #[snafu(display("of invalid configuration: {msg}"))]
     InvalidConfig { msg: String },
 }
@@ -274,18 +386,16 @@ where
     }
 }
 
+/// A deserializer from an optional Duration string, which rejects invalid durations instead of ignoring them
 pub(crate) fn maybe_duration_from_str<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    if let Ok(s) = String::deserialize(deserializer) {
-        if let Ok(duration) = Duration::from_str(&s) {
-            Ok(Some(duration))
-        } else {
-            Ok(None)
-        }
-    } else {
-        Ok(None)
+    match Option::<String>::deserialize(deserializer)? {
+        Some(s) => Duration::from_str(&s)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        None => Ok(None),
     }
 }
 
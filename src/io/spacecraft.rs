@@ -0,0 +1,592 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::ConfigRepr;
+use crate::cosmic::{
+    AttitudeProfile, AxisAreas, DragData, GuidanceMode, Mass, Orbit, PropTank, PropTanks, SRPData,
+    Spacecraft, MAX_PROP_TANKS,
+};
+use crate::dynamics::guidance::Thruster;
+use crate::linalg::Vector3;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_yml::{Mapping, Value};
+use std::collections::HashSet;
+
+/// Top level blocks of a spacecraft configuration
+const BLOCKS: [&str; 11] = [
+    "orbit",
+    "mass",
+    "srp",
+    "drag",
+    "areas",
+    "thrusters",
+    "tanks",
+    "plates",
+    "antennas",
+    "mode",
+    "attitude",
+];
+
+/// Masses of the spacecraft configuration: the propellant mass is the total of the tanks if any are defined.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MassConfig {
+    pub dry_mass_kg: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prop_mass_kg: Option<f64>,
+    #[serde(default)]
+    pub extra_mass_kg: f64,
+}
+
+/// A thruster of the spacecraft configuration.
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThrusterConfig {
+    pub name: String,
+    /// Thrust in Newtons
+    pub thrust_N: f64,
+    /// Specific impulse in seconds
+    pub isp_s: f64,
+}
+
+/// A propellant tank of the spacecraft configuration.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TankConfig {
+    pub name: String,
+    pub prop_mass_kg: f64,
+    /// Draw priority, cf. [PropTank::priority]
+    #[serde(default)]
+    pub priority: u8,
+    /// Names of the thrusters fed by this tank: all of them if unset, none of them if empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thrusters: Option<Vec<String>>,
+}
+
+/// A flat plate of the spacecraft surface model, e.g. a face of the bus or a solar array.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlateConfig {
+    pub name: String,
+    pub area_m2: f64,
+    /// Outward normal of the plate in the body frame
+    pub normal: Vector3<f64>,
+    pub coeff_reflectivity: f64,
+}
+
+/// An antenna of the spacecraft, e.g. to account for its offset from the center of mass in the tracking data.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AntennaConfig {
+    pub name: String,
+    /// Boresight of the antenna in the body frame
+    pub boresight: Vector3<f64>,
+    /// Offset of the phase center of the antenna from the center of mass in the body frame, in meters
+    #[serde(default)]
+    pub offset_m: Vector3<f64>,
+    /// Half beam width of the antenna, in degrees
+    pub half_beamwidth_deg: f64,
+}
+
+/// A validated spacecraft configuration with optional subsystem blocks, which builds the [Spacecraft] state.
+///
+/// Unlike the flat [Spacecraft] configuration, a vehicle may have several thrusters, tanks (feeding some of the thrusters),
+/// plates, and antennas. Each block is validated when the configuration is loaded, and errors point at the offending path,
+/// e.g. ``invalid spacecraft configuration at `thrusters[1].isp_s`: must be positive``.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Value")]
+pub struct SpacecraftConfig {
+    pub orbit: Orbit,
+    pub mass: MassConfig,
+    /// SRP configuration, which is computed from the plates if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub srp: Option<SRPData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drag: Option<DragData>,
+    /// Projected areas along the body axes, which replace the SRP and drag areas, cf. [Spacecraft::srp_area_m2]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub areas: Option<AxisAreas>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub thrusters: Vec<ThrusterConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tanks: Vec<TankConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plates: Vec<PlateConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub antennas: Vec<AntennaConfig>,
+    #[serde(default)]
+    pub mode: GuidanceMode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attitude: Option<AttitudeProfile>,
+}
+
+impl SpacecraftConfig {
+    /// Validates this configuration, returning the path and reason of the first invalid entry.
+    pub fn validate(&self) -> Result<(), String> {
+        check(
+            self.mass.dry_mass_kg >= 0.0,
+            "mass.dry_mass_kg",
+            "must be non-negative",
+        )?;
+        check(
+            self.mass.extra_mass_kg >= 0.0,
+            "mass.extra_mass_kg",
+            "must be non-negative",
+        )?;
+        if let Some(prop_mass_kg) = self.mass.prop_mass_kg {
+            check(
+                prop_mass_kg >= 0.0,
+                "mass.prop_mass_kg",
+                "must be non-negative",
+            )?;
+            check(
+                self.tanks.is_empty(),
+                "mass.prop_mass_kg",
+                "must not be set when tanks are defined, since it is their total",
+            )?;
+        }
+
+        if let Some(srp) = self.srp {
+            check(srp.area_m2 >= 0.0, "srp.area_m2", "must be non-negative")?;
+            check(
+                (0.0..=2.0).contains(&srp.coeff_reflectivity),
+                "srp.coeff_reflectivity",
+                "must be between 0 and 2",
+            )?;
+        }
+        if let Some(drag) = self.drag {
+            check(drag.area_m2 >= 0.0, "drag.area_m2", "must be non-negative")?;
+            check(
+                drag.coeff_drag >= 0.0,
+                "drag.coeff_drag",
+                "must be non-negative",
+            )?;
+        }
+        if let Some(areas) = self.areas {
+            for (axis, area_m2) in [("x", areas.x_m2), ("y", areas.y_m2), ("z", areas.z_m2)] {
+                check(
+                    area_m2 >= 0.0,
+                    &format!("areas.{axis}_m2"),
+                    "must be non-negative",
+                )?;
+            }
+        }
+
+        let thruster_names = unique_names(self.thrusters.iter().map(|t| &t.name), "thrusters")?;
+        for (i, thruster) in self.thrusters.iter().enumerate() {
+            let path = format!("thrusters[{i}]");
+            check(
+                thruster.thrust_N > 0.0,
+                &format!("{path}.thrust_N"),
+                "must be positive",
+            )?;
+            check(
+                thruster.isp_s > 0.0,
+                &format!("{path}.isp_s"),
+                "must be positive",
+            )?;
+        }
+
+        unique_names(self.tanks.iter().map(|t| &t.name), "tanks")?;
+        check(
+            self.tanks.len() <= MAX_PROP_TANKS,
+            "tanks",
+            &format!("at most {MAX_PROP_TANKS} tanks are supported"),
+        )?;
+        for (i, tank) in self.tanks.iter().enumerate() {
+            let path = format!("tanks[{i}]");
+            check(
+                tank.prop_mass_kg >= 0.0,
+                &format!("{path}.prop_mass_kg"),
+                "must be non-negative",
+            )?;
+            for (j, name) in tank.thrusters.iter().flatten().enumerate() {
+                check(
+                    thruster_names.contains(name.as_str()),
+                    &format!("{path}.thrusters[{j}]"),
+                    &format!("unknown thruster `{name}`"),
+                )?;
+            }
+        }
+
+        unique_names(self.plates.iter().map(|p| &p.name), "plates")?;
+        for (i, plate) in self.plates.iter().enumerate() {
+            let path = format!("plates[{i}]");
+            check(
+                plate.area_m2 > 0.0,
+                &format!("{path}.area_m2"),
+                "must be positive",
+            )?;
+            check(
+                plate.normal.norm() > f64::EPSILON,
+                &format!("{path}.normal"),
+                "must not be zero",
+            )?;
+            check(
+                (0.0..=2.0).contains(&plate.coeff_reflectivity),
+                &format!("{path}.coeff_reflectivity"),
+                "must be between 0 and 2",
+            )?;
+        }
+
+        unique_names(self.antennas.iter().map(|a| &a.name), "antennas")?;
+        for (i, antenna) in self.antennas.iter().enumerate() {
+            let path = format!("antennas[{i}]");
+            check(
+                antenna.boresight.norm() > f64::EPSILON,
+                &format!("{path}.boresight"),
+                "must not be zero",
+            )?;
+            check(
+                antenna.half_beamwidth_deg > 0.0 && antenna.half_beamwidth_deg <= 180.0,
+                &format!("{path}.half_beamwidth_deg"),
+                "must be in (0, 180]",
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the thruster equivalent to all of the thrusters firing together: the thrusts add up, and the Isp is the
+    /// total thrust divided by the total weight flow rate.
+    #[allow(non_snake_case)]
+    pub fn equivalent_thruster(&self) -> Option<Thruster> {
+        if self.thrusters.is_empty() {
+            return None;
+        }
+        let thrust_N: f64 = self.thrusters.iter().map(|t| t.thrust_N).sum();
+        let weight_flow: f64 = self.thrusters.iter().map(|t| t.thrust_N / t.isp_s).sum();
+        Some(Thruster::new(thrust_N, thrust_N / weight_flow))
+    }
+
+    /// Returns the SRP configuration, or the one of the plates if unset: their total area and area weighted reflectivity.
+    pub fn srp_data(&self) -> SRPData {
+        match self.srp {
+            Some(srp) => srp,
+            None if !self.plates.is_empty() => {
+                let area_m2: f64 = self.plates.iter().map(|p| p.area_m2).sum();
+                let weighted: f64 = self
+                    .plates
+                    .iter()
+                    .map(|p| p.area_m2 * p.coeff_reflectivity)
+                    .sum();
+                SRPData {
+                    area_m2,
+                    coeff_reflectivity: weighted / area_m2,
+                }
+            }
+            None => SRPData::default(),
+        }
+    }
+
+    /// Builds the spacecraft state of this configuration.
+    pub fn to_spacecraft(&self) -> Spacecraft {
+        let tanks = self
+            .tanks
+            .iter()
+            .map(|tank| PropTank {
+                prop_mass_kg: tank.prop_mass_kg,
+                priority: tank.priority,
+                feeds_thruster: tank.thrusters.as_ref().is_none_or(|t| !t.is_empty()),
+            })
+            .collect::<Vec<_>>();
+
+        let mut sc = Spacecraft {
+            orbit: self.orbit,
+            mass: Mass {
+                dry_mass_kg: self.mass.dry_mass_kg,
+                prop_mass_kg: self.mass.prop_mass_kg.unwrap_or(0.0),
+                extra_mass_kg: self.mass.extra_mass_kg,
+            },
+            srp: self.srp_data(),
+            drag: self.drag.unwrap_or_default(),
+            areas: self.areas,
+            thruster: self.equivalent_thruster(),
+            mode: self.mode,
+            ..Default::default()
+        };
+        if !tanks.is_empty() {
+            sc = sc.with_tanks(PropTanks::new(&tanks).expect("number of tanks validated"));
+        }
+        if let Some(profile) = self.attitude {
+            sc = sc.with_attitude(profile);
+        }
+        sc
+    }
+}
+
+impl From<&Spacecraft> for SpacecraftConfig {
+    /// Builds the configuration of a spacecraft state, with a single thruster named `main`.
+    fn from(sc: &Spacecraft) -> Self {
+        Self {
+            orbit: sc.orbit,
+            mass: MassConfig {
+                dry_mass_kg: sc.mass.dry_mass_kg,
+                prop_mass_kg: sc.tanks.is_empty().then_some(sc.mass.prop_mass_kg),
+                extra_mass_kg: sc.mass.extra_mass_kg,
+            },
+            srp: Some(sc.srp),
+            drag: Some(sc.drag),
+            areas: sc.areas,
+            thrusters: sc
+                .thruster
+                .iter()
+                .map(|thruster| ThrusterConfig {
+                    name: "main".to_string(),
+                    thrust_N: thruster.thrust_N,
+                    isp_s: thruster.isp_s,
+                })
+                .collect(),
+            tanks: sc
+                .tanks
+                .iter()
+                .enumerate()
+                .map(|(i, tank)| TankConfig {
+                    name: format!("tank{i}"),
+                    prop_mass_kg: tank.prop_mass_kg,
+                    priority: tank.priority,
+                    thrusters: (!tank.feeds_thruster).then(Vec::new),
+                })
+                .collect(),
+            plates: Vec::new(),
+            antennas: Vec::new(),
+            mode: sc.mode,
+            attitude: sc.attitude.map(|attitude| attitude.profile),
+        }
+    }
+}
+
+impl TryFrom<Value> for SpacecraftConfig {
+    type Error = String;
+
+    /// Deserializes each block on its own such that errors point at the offending path, and validates the configuration.
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let map = match value {
+            Value::Mapping(map) => map,
+            _ => return Err(invalid("", "expected a mapping of the spacecraft blocks")),
+        };
+        for key in map.keys() {
+            let key = key.as_str().unwrap_or_default();
+            if !BLOCKS.contains(&key) {
+                return Err(invalid(
+                    key,
+                    &format!("unknown block, expected one of {}", BLOCKS.join(", ")),
+                ));
+            }
+        }
+
+        let me = Self {
+            orbit: required(&map, "orbit")?,
+            mass: required(&map, "mass")?,
+            srp: optional(&map, "srp")?,
+            drag: optional(&map, "drag")?,
+            areas: optional(&map, "areas")?,
+            thrusters: list(&map, "thrusters")?,
+            tanks: list(&map, "tanks")?,
+            plates: list(&map, "plates")?,
+            antennas: list(&map, "antennas")?,
+            mode: optional(&map, "mode")?.unwrap_or_default(),
+            attitude: optional(&map, "attitude")?,
+        };
+        me.validate()
+            .map_err(|e| format!("invalid spacecraft configuration {e}"))?;
+        Ok(me)
+    }
+}
+
+impl ConfigRepr for SpacecraftConfig {}
+
+fn invalid(path: &str, msg: &str) -> String {
+    format!("invalid spacecraft configuration at `{path}`: {msg}")
+}
+
+fn check(valid: bool, path: &str, msg: &str) -> Result<(), String> {
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("at `{path}`: {msg}"))
+    }
+}
+
+fn unique_names<'a, I>(names: I, block: &str) -> Result<HashSet<&'a str>, String>
+where
+    I: Iterator<Item = &'a String>,
+{
+    let mut seen = HashSet::new();
+    for (i, name) in names.enumerate() {
+        check(
+            seen.insert(name.as_str()),
+            &format!("{block}[{i}].name"),
+            &format!("duplicate name `{name}`"),
+        )?;
+    }
+    Ok(seen)
+}
+
+fn optional<T: DeserializeOwned>(map: &Mapping, key: &str) -> Result<Option<T>, String> {
+    match map.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => serde_yml::from_value(value.clone())
+            .map(Some)
+            .map_err(|e| invalid(key, &e.to_string())),
+    }
+}
+
+fn required<T: DeserializeOwned>(map: &Mapping, key: &str) -> Result<T, String> {
+    optional(map, key)?.ok_or_else(|| invalid(key, "missing block"))
+}
+
+fn list<T: DeserializeOwned>(map: &Mapping, key: &str) -> Result<Vec<T>, String> {
+    match map.get(key) {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::Sequence(items)) => items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                serde_yml::from_value(item.clone())
+                    .map_err(|e| invalid(&format!("{key}[{i}]"), &e.to_string()))
+            })
+            .collect(),
+        Some(_) => Err(invalid(key, "expected a list")),
+    }
+}
+
+#[cfg(test)]
+mod ut_spacecraft_config {
+    use super::*;
+
+    const ORBIT: &str = r#"
+orbit:
+    radius_km: [-9042.862234, 18536.333069, 6999.957069]
+    velocity_km_s: [-3.288789, -2.226285, 1.646738]
+    epoch: 2018-09-15T00:15:53.098000000 UTC
+    frame:
+        ephemeris_id: 399
+        orientation_id: 1
+        mu_km3_s2: 398600.4415
+        shape: null
+"#;
+
+    #[test]
+    fn subsystem_blocks() {
+        let yaml = format!(
+            "{ORBIT}{}",
+            r#"
+mass:
+    dry_mass_kg: 500.0
+thrusters:
+    - name: main
+      thrust_N: 10.0
+      isp_s: 300.0
+    - name: backup
+      thrust_N: 10.0
+      isp_s: 200.0
+tanks:
+    - name: fuel
+      prop_mass_kg: 80.0
+    - name: reserve
+      prop_mass_kg: 20.0
+      priority: 1
+      thrusters: []
+plates:
+    - name: bus
+      area_m2: 2.0
+      normal: [1.0, 0.0, 0.0]
+      coeff_reflectivity: 1.2
+    - name: array
+      area_m2: 6.0
+      normal: [0.0, 0.0, 1.0]
+      coeff_reflectivity: 1.6
+antennas:
+    - name: hga
+      boresight: [0.0, 0.0, -1.0]
+      offset_m: [0.5, 0.0, 0.0]
+      half_beamwidth_deg: 2.5
+attitude: Nadir
+"#
+        );
+        let cfg = serde_yml::from_str::<SpacecraftConfig>(&yaml).unwrap();
+        let sc = cfg.to_spacecraft();
+        assert_eq!(sc.mass.prop_mass_kg, 100.0);
+        assert_eq!(sc.tanks.available_kg(), 80.0);
+        let thruster = sc.thruster.unwrap();
+        assert_eq!(thruster.thrust_N, 20.0);
+        assert!((thruster.isp_s - 240.0).abs() < 1e-9);
+        assert_eq!(sc.srp.area_m2, 8.0);
+        assert!((sc.srp.coeff_reflectivity - 1.5).abs() < 1e-12);
+        assert_eq!(sc.attitude.unwrap().profile, AttitudeProfile::Nadir);
+        assert_eq!(cfg.antennas[0].offset_m, Vector3::new(0.5, 0.0, 0.0));
+
+        // Round trip through YAML
+        let reloaded =
+            serde_yml::from_str::<SpacecraftConfig>(&serde_yml::to_string(&cfg).unwrap()).unwrap();
+        assert_eq!(reloaded, cfg);
+        // And from the flat spacecraft state
+        let from_sc = SpacecraftConfig::from(&sc);
+        assert_eq!(from_sc.to_spacecraft().tanks, sc.tanks);
+    }
+
+    #[test]
+    fn error_paths() {
+        let with = |blocks: &str| {
+            serde_yml::from_str::<SpacecraftConfig>(&format!(
+                "{ORBIT}mass:\n    dry_mass_kg: 500.0\n{blocks}"
+            ))
+            .unwrap_err()
+            .to_string()
+        };
+
+        let err = with("areas:\n    x_m2: 1.0\n    y_m2: -2.0\n    z_m2: 3.0\n");
+        assert!(err.contains("`areas.y_m2`: must be non-negative"), "{err}");
+
+        let err = with("thrusters:\n    - name: a\n      thrust_N: 1.0\n      isp_s: 300.0\n    - name: b\n      thrust_N: 1.0\n      isp_s: -1.0\n");
+        assert!(
+            err.contains("`thrusters[1].isp_s`: must be positive"),
+            "{err}"
+        );
+
+        let err = with("thrusters:\n    - name: a\n      thrust_N: 1.0\n");
+        assert!(
+            err.contains("`thrusters[0]`") && err.contains("isp_s"),
+            "{err}"
+        );
+
+        let err = with("tanks:\n    - name: t\n      prop_mass_kg: 1.0\n      thrusters: [nope]\n");
+        assert!(
+            err.contains("`tanks[0].thrusters[0]`: unknown thruster `nope`"),
+            "{err}"
+        );
+
+        let err = with("plates:\n    - name: p\n      area_m2: 1.0\n      normal: [0.0, 0.0, 0.0]\n      coeff_reflectivity: 1.0\n");
+        assert!(
+            err.contains("`plates[0].normal`: must not be zero"),
+            "{err}"
+        );
+
+        let err = with("antenas: []\n");
+        assert!(err.contains("`antenas`: unknown block"), "{err}");
+
+        let err = with("antennas:\n    - name: a\n      boresight: [0.0, 0.0, 1.0]\n      half_beamwidth_deg: 1.0\n    - name: a\n      boresight: [0.0, 0.0, 1.0]\n      half_beamwidth_deg: 1.0\n");
+        assert!(
+            err.contains("`antennas[1].name`: duplicate name `a`"),
+            "{err}"
+        );
+    }
+}
@@ -20,35 +20,72 @@ use std::collections::HashMap;
 
 use hifitime::Epoch;
 use parquet::{
-    basic::{Compression, ZstdLevel},
+    basic::{Compression, GzipLevel, ZstdLevel},
+    errors::ParquetError,
     file::properties::WriterProperties,
     format::KeyValue,
 };
 use shadow_rs::shadow;
 use whoami::{platform, realname, username};
 
+use super::{MetadataPolicy, ParquetCfg, ParquetCompression};
+use crate::mc::RunConfig;
+
 shadow!(build);
 
 /// The parquet writer properties
 pub(crate) fn pq_writer(metadata: Option<HashMap<String, String>>) -> Option<WriterProperties> {
+    pq_writer_with(metadata, &ParquetCfg::default()).ok()
+}
+
+/// The parquet writer properties with the provided compression, row group size, and metadata policy
+pub(crate) fn pq_writer_with(
+    metadata: Option<HashMap<String, String>>,
+    cfg: &ParquetCfg,
+) -> Result<WriterProperties, ParquetError> {
+    let compression = match cfg.compression {
+        ParquetCompression::Uncompressed => Compression::UNCOMPRESSED,
+        ParquetCompression::Snappy => Compression::SNAPPY,
+        ParquetCompression::Lz4 => Compression::LZ4_RAW,
+        ParquetCompression::Zstd { level } => Compression::ZSTD(ZstdLevel::try_new(level)?),
+        ParquetCompression::Gzip { level } => Compression::GZIP(GzipLevel::try_new(level)?),
+    };
+
     let bldr = WriterProperties::builder()
-        .set_compression(Compression::ZSTD(ZstdLevel::try_new(10).unwrap()));
+        .set_compression(compression)
+        .set_max_row_group_size(cfg.row_group_rows.max(1));
+
+    let mut file_metadata = Vec::new();
 
-    let mut file_metadata = vec![
-        KeyValue::new("Generated by".to_string(), prj_name_ver()),
-        KeyValue::new(
+    if cfg.metadata != MetadataPolicy::Minimal {
+        file_metadata.push(KeyValue::new("Generated by".to_string(), prj_name_ver()));
+        file_metadata.push(KeyValue::new(
             format!("{} License", build::PROJECT_NAME),
             "AGPL 3.0".to_string(),
-        ),
-        KeyValue::new(
+        ));
+    }
+
+    if cfg.metadata == MetadataPolicy::Full {
+        file_metadata.push(KeyValue::new(
             "Created by".to_string(),
             format!("{} ({}) on {}", realname(), username(), platform()),
-        ),
-        KeyValue::new(
+        ));
+    }
+
+    if cfg.metadata != MetadataPolicy::Minimal {
+        file_metadata.push(KeyValue::new(
             "Created on".to_string(),
             format!("{}", Epoch::now().unwrap()),
-        ),
-    ];
+        ));
+    }
+
+    // Always record the master seed, which is required to reproduce the run
+    if let Some(master_seed) = RunConfig::current().master_seed {
+        file_metadata.push(KeyValue::new(
+            "Master seed".to_string(),
+            format!("{master_seed}"),
+        ));
+    }
 
     if let Some(custom_md) = metadata {
         for (k, v) in custom_md {
@@ -56,7 +93,7 @@ pub(crate) fn pq_writer(metadata: Option<HashMap<String, String>>) -> Option<Wri
         }
     }
 
-    Some(bldr.set_key_value_metadata(Some(file_metadata)).build())
+    Ok(bldr.set_key_value_metadata(Some(file_metadata)).build())
 }
 
 pub(crate) fn prj_name_ver() -> String {
@@ -63,6 +63,23 @@ pub mod mc;
 /// Polynomial and fitting module
 pub mod polyfit;
 
+/// Progress reporting of long running computations, e.g. propagations, orbit determination arcs and Monte Carlo runs
+pub mod progress;
+
+/// Opt-in profiling of the wall time spent per force model, integrator stage, measurement update and Almanac query
+pub mod profiling;
+
+/// Process-wide runtime configuration: thread pool size, thread affinity and shared Almanacs
+pub mod runtime;
+
+/// gRPC service for propagation, targeting, tracking simulation and orbit determination
+#[cfg(feature = "grpc")]
+pub mod grpc;
+
+/// REST/JSON API server for propagation, targeting, tracking simulation and orbit determination
+#[cfg(feature = "rest")]
+pub mod rest;
+
 /// Re-export of hifitime
 pub mod time {
     pub use hifitime::prelude::*;
@@ -79,3 +96,6 @@ pub use self::cosmic::{Orbit, Spacecraft, State, TimeTagged};
 /// The GMAT Earth gravitation parameter, used only for testing.
 #[cfg(test)]
 pub(crate) const GMAT_EARTH_GM: f64 = 398_600.441_5;
+
+#[cfg(test)]
+pub(crate) mod fixtures;
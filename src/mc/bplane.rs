@@ -0,0 +1,465 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::results::{PropResult, Results};
+use crate::cosmic::{BPlane, BPlaneTarget};
+use crate::errors::MonteCarloError;
+use crate::io::watermark::pq_writer;
+use crate::linalg::{Matrix2, Vector2};
+use crate::time::{Duration, Epoch};
+use crate::{Spacecraft, State};
+use anise::almanac::Almanac;
+use anise::prelude::Frame;
+use arrow::array::{Array, Float64Builder, StringBuilder, UInt64Builder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use hifitime::TimeScale;
+use parquet::arrow::ArrowWriter;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// B-Plane coordinates of a single run of a Monte Carlo.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BPlaneSample {
+    pub index: usize,
+    pub epoch: Epoch,
+    pub b_dot_t_km: f64,
+    pub b_dot_r_km: f64,
+    pub ltof: Duration,
+}
+
+/// Dispersion ellipse in the B-Plane, centered on the mean of the samples.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DispersionEllipse {
+    /// Number of standard deviations of this ellipse
+    pub sigma: f64,
+    pub center_b_dot_t_km: f64,
+    pub center_b_dot_r_km: f64,
+    pub semi_major_km: f64,
+    pub semi_minor_km: f64,
+    /// Angle of the semi-major axis from the T axis toward the R axis, in degrees between -90 and 90
+    pub angle_deg: f64,
+}
+
+impl DispersionEllipse {
+    /// Builds the ellipse of this (B∙T, B∙R) covariance at the requested number of standard deviations.
+    pub fn from_covariance(center: Vector2<f64>, covar: &Matrix2<f64>, sigma: f64) -> Self {
+        let (a, b, c) = (covar[(0, 0)], covar[(0, 1)], covar[(1, 1)]);
+        let half_trace = 0.5 * (a + c);
+        let delta = (0.25 * (a - c).powi(2) + b.powi(2)).sqrt();
+
+        Self {
+            sigma,
+            center_b_dot_t_km: center[0],
+            center_b_dot_r_km: center[1],
+            semi_major_km: sigma * (half_trace + delta).max(0.0).sqrt(),
+            semi_minor_km: sigma * (half_trace - delta).max(0.0).sqrt(),
+            angle_deg: 0.5 * (2.0 * b).atan2(a - c).to_degrees(),
+        }
+    }
+
+    /// Returns whether this B-Plane point is within this ellipse
+    pub fn contains(&self, b_dot_t_km: f64, b_dot_r_km: f64) -> bool {
+        let (sin, cos) = self.angle_deg.to_radians().sin_cos();
+        let dt = b_dot_t_km - self.center_b_dot_t_km;
+        let dr = b_dot_r_km - self.center_b_dot_r_km;
+        let along = dt * cos + dr * sin;
+        let across = -dt * sin + dr * cos;
+        (along / self.semi_major_km).powi(2) + (across / self.semi_minor_km).powi(2) <= 1.0
+    }
+
+    /// Returns `num_points` points of the outline of this ellipse, as (B∙T, B∙R) in km
+    pub fn outline(&self, num_points: usize) -> Vec<(f64, f64)> {
+        let (sin, cos) = self.angle_deg.to_radians().sin_cos();
+        (0..num_points)
+            .map(|i| {
+                let theta = 2.0 * std::f64::consts::PI * i as f64 / num_points as f64;
+                let along = self.semi_major_km * theta.cos();
+                let across = self.semi_minor_km * theta.sin();
+                (
+                    self.center_b_dot_t_km + along * cos - across * sin,
+                    self.center_b_dot_r_km + along * sin + across * cos,
+                )
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for DispersionEllipse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-sigma ellipse centered on B∙T = {:.3} km, B∙R = {:.3} km: semi-major = {:.3} km, semi-minor = {:.3} km, angle = {:.3} deg",
+            self.sigma,
+            self.center_b_dot_t_km,
+            self.center_b_dot_r_km,
+            self.semi_major_km,
+            self.semi_minor_km,
+            self.angle_deg
+        )
+    }
+}
+
+/// B-Plane scatter of the runs of a Monte Carlo at the target body.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BPlaneScatter {
+    /// Frame of the target body, in which the B-Planes are computed
+    pub frame: Frame,
+    pub samples: Vec<BPlaneSample>,
+    /// Mean of the (B∙T, B∙R) coordinates, in km
+    pub mean: Vector2<f64>,
+    /// Sample covariance of the (B∙T, B∙R) coordinates, in km^2
+    pub covariance: Matrix2<f64>,
+}
+
+impl BPlaneScatter {
+    /// Builds the scatter from the states of each run, skipping those whose orbit about the target body is not hyperbolic.
+    pub fn from_states(
+        states: &[(usize, Spacecraft)],
+        frame: Frame,
+        almanac: Arc<Almanac>,
+    ) -> Result<Self, MonteCarloError> {
+        let mut samples = Vec::with_capacity(states.len());
+        for (index, state) in states {
+            let orbit = match almanac.transform_to(state.orbit, frame, None) {
+                Ok(orbit) => orbit,
+                Err(e) => {
+                    warn!("run #{index}: {e}, skipping in B-Plane scatter");
+                    continue;
+                }
+            };
+            match BPlane::new(orbit) {
+                Ok(bplane) => samples.push(BPlaneSample {
+                    index: *index,
+                    epoch: state.epoch(),
+                    b_dot_t_km: bplane.b_dot_t(),
+                    b_dot_r_km: bplane.b_dot_r(),
+                    ltof: bplane.ltof(),
+                }),
+                Err(e) => warn!("run #{index}: {e}, skipping in B-Plane scatter"),
+            }
+        }
+
+        if samples.is_empty() {
+            return Err(MonteCarloError::NoSuccessfulRuns {
+                action: "compute the B-Plane scatter",
+                num_runs: states.len(),
+            });
+        }
+
+        let n = samples.len() as f64;
+        let mean = samples
+            .iter()
+            .map(|sample| Vector2::new(sample.b_dot_t_km, sample.b_dot_r_km))
+            .sum::<Vector2<f64>>()
+            / n;
+        let covariance = samples
+            .iter()
+            .map(|sample| {
+                let delta = Vector2::new(sample.b_dot_t_km, sample.b_dot_r_km) - mean;
+                delta * delta.transpose()
+            })
+            .sum::<Matrix2<f64>>()
+            / (n - 1.0).max(1.0);
+
+        Ok(Self {
+            frame,
+            samples,
+            mean,
+            covariance,
+        })
+    }
+
+    /// Returns the dispersion ellipse of this scatter at the requested number of standard deviations.
+    pub fn ellipse(&self, sigma: f64) -> DispersionEllipse {
+        DispersionEllipse::from_covariance(self.mean, &self.covariance, sigma)
+    }
+
+    /// Returns the dispersion ellipse containing this probability of a bivariate normal distribution, e.g. 0.99.
+    pub fn ellipse_for_probability(&self, probability: f64) -> DispersionEllipse {
+        self.ellipse((-2.0 * (1.0 - probability).ln()).sqrt())
+    }
+
+    /// Fraction of the samples whose B-Plane coordinates are within the tolerances of this target.
+    pub fn fraction_within(&self, target: &BPlaneTarget) -> f64 {
+        self.samples
+            .iter()
+            .filter(|sample| {
+                (sample.b_dot_t_km - target.b_t_km).abs() <= target.tol_b_t_km
+                    && (sample.b_dot_r_km - target.b_r_km).abs() <= target.tol_b_r_km
+            })
+            .count() as f64
+            / self.samples.len() as f64
+    }
+
+    /// Stores the scatter to a parquet file, with the mean, covariance and 3-sigma dispersion ellipse in the metadata.
+    pub fn to_parquet<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Box<dyn Error>> {
+        let path_buf = path.as_ref().to_path_buf();
+
+        let hdrs = vec![
+            Field::new("Run", DataType::UInt64, false),
+            Field::new("Epoch (UTC)", DataType::Utf8, false),
+            Field::new("B∙T (km)", DataType::Float64, false),
+            Field::new("B∙R (km)", DataType::Float64, false),
+            Field::new("LTOF (s)", DataType::Float64, false),
+        ];
+
+        let mut index = UInt64Builder::new();
+        let mut epoch = StringBuilder::new();
+        let mut b_dot_t = Float64Builder::new();
+        let mut b_dot_r = Float64Builder::new();
+        let mut ltof = Float64Builder::new();
+        for sample in &self.samples {
+            index.append_value(sample.index as u64);
+            epoch.append_value(sample.epoch.to_time_scale(TimeScale::UTC).to_isoformat());
+            b_dot_t.append_value(sample.b_dot_t_km);
+            b_dot_r.append_value(sample.b_dot_r_km);
+            ltof.append_value(sample.ltof.to_seconds());
+        }
+
+        let record: Vec<Arc<dyn Array>> = vec![
+            Arc::new(index.finish()),
+            Arc::new(epoch.finish()),
+            Arc::new(b_dot_t.finish()),
+            Arc::new(b_dot_r.finish()),
+            Arc::new(ltof.finish()),
+        ];
+
+        let ellipse = self.ellipse(3.0);
+        let mut metadata = HashMap::new();
+        metadata.insert("Purpose".to_string(), "B-Plane scatter".to_string());
+        metadata.insert("Frame".to_string(), format!("{}", self.frame));
+        metadata.insert(
+            "Mean (km)".to_string(),
+            format!("[{}, {}]", self.mean[0], self.mean[1]),
+        );
+        metadata.insert(
+            "Covariance (km^2)".to_string(),
+            format!(
+                "[[{}, {}], [{}, {}]]",
+                self.covariance[(0, 0)],
+                self.covariance[(0, 1)],
+                self.covariance[(1, 0)],
+                self.covariance[(1, 1)]
+            ),
+        );
+        metadata.insert(
+            "3-sigma semi-major (km)".to_string(),
+            format!("{}", ellipse.semi_major_km),
+        );
+        metadata.insert(
+            "3-sigma semi-minor (km)".to_string(),
+            format!("{}", ellipse.semi_minor_km),
+        );
+        metadata.insert(
+            "Ellipse angle (deg)".to_string(),
+            format!("{}", ellipse.angle_deg),
+        );
+
+        let schema = Arc::new(Schema::new(hdrs));
+        let file = File::create(&path_buf)?;
+        let mut writer = ArrowWriter::try_new(file, schema.clone(), pq_writer(Some(metadata)))?;
+        writer.write(&RecordBatch::try_new(schema, record)?)?;
+        writer.close()?;
+
+        info!(
+            "B-Plane scatter of {} samples written to {}",
+            self.samples.len(),
+            path_buf.display()
+        );
+
+        Ok(path_buf)
+    }
+}
+
+impl fmt::Display for BPlaneScatter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] B-Plane scatter of {} samples: {}",
+            self.frame,
+            self.samples.len(),
+            self.ellipse(3.0)
+        )
+    }
+}
+
+impl Results<Spacecraft, PropResult<Spacecraft>> {
+    /// Computes the B-Plane scatter about the target body at the provided epoch, over all of the successful runs whose
+    /// trajectory includes this epoch.
+    pub fn bplane_scatter_at(
+        &self,
+        epoch: Epoch,
+        frame: Frame,
+        almanac: Arc<Almanac>,
+    ) -> Result<BPlaneScatter, MonteCarloError> {
+        let mut states = Vec::with_capacity(self.runs.len());
+        for run in &self.runs {
+            match &run.result {
+                Ok(r) => match r.traj.at(epoch) {
+                    Ok(state) => states.push((run.index, state)),
+                    Err(e) => warn!("run #{}: {e}, skipping in B-Plane scatter", run.index),
+                },
+                Err(e) => warn!(
+                    "run #{} failed with {e}, skipping in B-Plane scatter",
+                    run.index
+                ),
+            }
+        }
+
+        BPlaneScatter::from_states(&states, frame, almanac)
+    }
+
+    /// Computes the B-Plane scatter about the target body at the final state of each successful run, e.g. at the
+    /// sphere of influence or at an encounter event.
+    pub fn bplane_scatter_at_final(
+        &self,
+        frame: Frame,
+        almanac: Arc<Almanac>,
+    ) -> Result<BPlaneScatter, MonteCarloError> {
+        let states = self
+            .runs
+            .iter()
+            .filter_map(|run| match &run.result {
+                Ok(r) => Some((run.index, r.state)),
+                Err(e) => {
+                    warn!(
+                        "run #{} failed with {e}, skipping in B-Plane scatter",
+                        run.index
+                    );
+                    None
+                }
+            })
+            .collect::<Vec<(usize, Spacecraft)>>();
+
+        BPlaneScatter::from_states(&states, frame, almanac)
+    }
+}
+
+#[cfg(test)]
+mod ut_bplane_scatter {
+    use super::*;
+    use crate::cosmic::Orbit;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::mc::{MonteCarlo, MvnSpacecraft, StateDispersion};
+    use crate::md::StateParameter;
+    use crate::propagators::Propagator;
+    use crate::time::Unit;
+    use anise::constants::frames::EARTH_J2000;
+
+    #[test]
+    fn dispersion_ellipse() {
+        let scatter = BPlaneScatter {
+            frame: EARTH_J2000,
+            samples: vec![],
+            mean: Vector2::new(1_000.0, -500.0),
+            // Principal axes of 2 and 1 km rotated by 30 degrees
+            covariance: {
+                let (sin, cos) = 30.0_f64.to_radians().sin_cos();
+                let rot = Matrix2::new(cos, -sin, sin, cos);
+                rot * Matrix2::new(4.0, 0.0, 0.0, 1.0) * rot.transpose()
+            },
+        };
+
+        let ellipse = scatter.ellipse(3.0);
+        println!("{ellipse}");
+        assert!((ellipse.semi_major_km - 6.0).abs() < 1e-9);
+        assert!((ellipse.semi_minor_km - 3.0).abs() < 1e-9);
+        assert!((ellipse.angle_deg - 30.0).abs() < 1e-9);
+        assert!(ellipse.contains(1_000.0, -500.0));
+        assert!(ellipse.contains(1_000.0 + 5.9 * 0.866_025, -500.0 + 5.9 * 0.5));
+        assert!(!ellipse.contains(1_000.0 + 6.1 * 0.866_025, -500.0 + 6.1 * 0.5));
+        assert!(!ellipse.contains(1_000.0 - 3.1 * 0.5, -500.0 + 3.1 * 0.866_025));
+        for (b_t, b_r) in ellipse.outline(36) {
+            let mut inflated = ellipse;
+            inflated.semi_major_km += 1e-6;
+            inflated.semi_minor_km += 1e-6;
+            assert!(inflated.contains(b_t, b_r));
+            assert!(!ellipse.contains(b_t + (b_t - 1_000.0) * 1e-3, b_r + (b_r + 500.0) * 1e-3));
+        }
+
+        // The 1-sigma ellipse of a bivariate normal contains 39.35% of the samples
+        let prob = scatter.ellipse_for_probability(1.0 - (-0.5_f64).exp());
+        assert!((prob.sigma - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn bplane_scatter_of_monte_carlo() {
+        let eme2k = fixtures::eme2k();
+        let epoch = fixtures::epoch();
+        // Incoming hyperbola, before periapsis
+        let nominal = Spacecraft::from(Orbit::cartesian(
+            -50_000.0, 10_000.0, 0.0, 6.0, 0.0, 1.0, epoch, eme2k,
+        ));
+        let generator = MvnSpacecraft::new(
+            nominal,
+            vec![
+                StateDispersion::zero_mean(StateParameter::VX, 1e-3),
+                StateDispersion::zero_mean(StateParameter::VY, 1e-3),
+            ],
+        )
+        .unwrap();
+
+        let almanac = fixtures::almanac();
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let results = MonteCarlo::new(nominal, generator, "bplane".to_string(), Some(0))
+            .run_until_epoch(prop, almanac.clone(), epoch + Unit::Hour * 1, 100);
+
+        let nominal_bplane = BPlane::new(nominal.orbit).unwrap();
+        let initial = results
+            .bplane_scatter_at(epoch, eme2k, almanac.clone())
+            .unwrap();
+        let last = results.bplane_scatter_at_final(eme2k, almanac).unwrap();
+        println!("{initial}\n{last}");
+
+        assert_eq!(initial.samples.len(), 100);
+        assert_eq!(last.samples.len(), 100);
+        // The B-Plane is invariant in two body dynamics
+        assert!((initial.mean - last.mean).norm() < 1e-3);
+        assert!((initial.covariance - last.covariance).norm() < 1e-3);
+        assert!((initial.mean[0] - nominal_bplane.b_dot_t()).abs() < 10.0);
+        assert!((initial.mean[1] - nominal_bplane.b_dot_r()).abs() < 10.0);
+
+        let ellipse = last.ellipse(3.0);
+        assert!(ellipse.semi_major_km > 0.0 && ellipse.semi_minor_km <= ellipse.semi_major_km);
+        let within = last
+            .samples
+            .iter()
+            .filter(|sample| ellipse.contains(sample.b_dot_t_km, sample.b_dot_r_km))
+            .count();
+        assert!(within >= 95);
+
+        let target = BPlaneTarget {
+            b_t_km: nominal_bplane.b_dot_t(),
+            b_r_km: nominal_bplane.b_dot_r(),
+            ltof_s: 0.0,
+            tol_b_t_km: 1e6,
+            tol_b_r_km: 1e6,
+            tol_ltof_s: 0.0,
+        };
+        assert!((last.fraction_within(&target) - 1.0).abs() < f64::EPSILON);
+
+        let path = std::env::temp_dir().join("nyx_ut_bplane_scatter.parquet");
+        assert_eq!(last.to_parquet(&path).unwrap(), path);
+    }
+}
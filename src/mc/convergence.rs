@@ -0,0 +1,429 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::results::{PropResult, Results};
+use super::{std_normal_quantile, stream_seed, MonteCarlo, StateGenerator};
+use crate::dynamics::Dynamics;
+use crate::errors::MonteCarloError;
+use crate::linalg::allocator::Allocator;
+use crate::linalg::DefaultAllocator;
+use crate::md::trajectory::Interpolatable;
+use crate::md::StateParameter;
+use crate::propagators::Propagator;
+use crate::time::Epoch;
+use crate::State;
+use anise::almanac::Almanac;
+use log::{info, warn};
+use std::fmt;
+use std::sync::Arc;
+use typed_builder::TypedBuilder;
+
+/// Statistic of a state parameter monitored for the convergence of a Monte Carlo.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MonitoredStatistic {
+    Mean,
+    /// Percentile between 0.0 and 100.0, e.g. 99.0 for the 99th percentile
+    Percentile(f64),
+}
+
+impl fmt::Display for MonitoredStatistic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mean => write!(f, "mean"),
+            Self::Percentile(prct) => write!(f, "P{prct}"),
+        }
+    }
+}
+
+/// Criteria to stop a Monte Carlo once a statistic of a parameter of its final states has converged, i.e. once the
+/// confidence interval of that statistic is narrower than the tolerance.
+///
+/// The confidence interval of the mean uses the normal approximation. The confidence interval of a percentile is
+/// distribution free: it spans the order statistics whose ranks bound the binomial count of samples below that percentile.
+/// That interval is unbounded until there are enough samples in the tail, e.g. about 400 samples for the 99th percentile at a 95% confidence.
+#[derive(Clone, Debug, TypedBuilder)]
+#[builder(doc)]
+pub struct StoppingCriteria {
+    /// Parameter of the final state of each run whose statistic is monitored
+    pub param: StateParameter,
+    pub statistic: MonitoredStatistic,
+    /// Half width of the confidence interval of the statistic below which it has converged, in the unit of the parameter
+    pub tolerance: f64,
+    /// Confidence level of the interval, between 0.0 and 1.0
+    #[builder(default = 0.95)]
+    pub confidence: f64,
+    /// Number of runs propagated between each check of the convergence
+    #[builder(default = 100)]
+    pub batch_size: usize,
+    /// Minimum number of runs, even if the statistic has converged before
+    #[builder(default = 100)]
+    pub min_runs: usize,
+    /// Maximum number of runs, even if the statistic has not converged
+    #[builder(default = 10_000)]
+    pub max_runs: usize,
+}
+
+impl StoppingCriteria {
+    fn check(&self) -> Result<(), MonteCarloError> {
+        let msg = if !(self.confidence > 0.0 && self.confidence < 1.0) {
+            format!("confidence must be in (0, 1) but got {}", self.confidence)
+        } else if self.tolerance <= 0.0 {
+            format!("tolerance must be positive but got {}", self.tolerance)
+        } else if self.batch_size == 0 {
+            "batch size must be positive".to_string()
+        } else if self.min_runs > self.max_runs {
+            format!(
+                "minimum number of runs {} exceeds the maximum of {}",
+                self.min_runs, self.max_runs
+            )
+        } else if let MonitoredStatistic::Percentile(prct) = self.statistic {
+            if !(0.0..=100.0).contains(&prct) {
+                format!("percentile must be between 0 and 100 but got {prct}")
+            } else {
+                return Ok(());
+            }
+        } else {
+            return Ok(());
+        };
+        Err(MonteCarloError::StoppingCriteria { msg })
+    }
+
+    /// Estimates the monitored statistic over these values and its confidence interval.
+    pub fn estimate(&self, values: &[f64]) -> Result<ConvergenceStep, MonteCarloError> {
+        self.check()?;
+        let num_samples = values.len();
+        if num_samples < 2 {
+            return Err(MonteCarloError::StoppingCriteria {
+                msg: format!("at least two samples are needed but got {num_samples}"),
+            });
+        }
+
+        let n = num_samples as f64;
+        let z = std_normal_quantile(0.5 + self.confidence / 2.0);
+
+        let (estimate, lower, upper) = match self.statistic {
+            MonitoredStatistic::Mean => {
+                let mean = values.iter().sum::<f64>() / n;
+                let variance = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+                let half_width = z * (variance / n).sqrt();
+                (mean, mean - half_width, mean + half_width)
+            }
+            MonitoredStatistic::Percentile(prct) => {
+                let mut values = values.to_vec();
+                values.sort_by(|a, b| a.total_cmp(b));
+                let p = prct / 100.0;
+                // Same interpolation as the ensemble statistics
+                let rank = p * (n - 1.0);
+                let (below, above) = (rank.floor() as usize, rank.ceil() as usize);
+                let estimate =
+                    values[below] + (rank - below as f64) * (values[above] - values[below]);
+                // One-based ranks of the order statistics bounding the interval
+                let spread = z * (n * p * (1.0 - p)).sqrt();
+                let lower_rank = (n * p - spread).floor();
+                let upper_rank = (n * p + spread).ceil();
+                let lower = if lower_rank >= 1.0 {
+                    values[lower_rank as usize - 1]
+                } else {
+                    f64::NEG_INFINITY
+                };
+                let upper = if upper_rank <= n {
+                    values[upper_rank as usize - 1]
+                } else {
+                    f64::INFINITY
+                };
+                (estimate, lower, upper)
+            }
+        };
+
+        Ok(ConvergenceStep {
+            num_samples,
+            estimate,
+            lower,
+            upper,
+        })
+    }
+}
+
+/// Estimate of the monitored statistic and its confidence interval after a batch of runs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ConvergenceStep {
+    /// Number of successful runs in this estimate
+    pub num_samples: usize,
+    pub estimate: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl ConvergenceStep {
+    /// Half width of the confidence interval, infinite if there are not enough samples to bound it
+    pub fn half_width(&self) -> f64 {
+        (self.upper - self.lower) / 2.0
+    }
+}
+
+/// History of the convergence of the monitored statistic of a Monte Carlo, one step per batch of runs.
+#[derive(Clone, Debug)]
+pub struct Convergence {
+    pub criteria: StoppingCriteria,
+    pub history: Vec<ConvergenceStep>,
+    /// Number of runs propagated, including the failed ones
+    pub num_runs: usize,
+    /// Whether the statistic has converged, or the runs stopped at the maximum number of runs or upon cancellation
+    pub converged: bool,
+}
+
+impl Convergence {
+    /// Returns the last estimate of the statistic, if any
+    pub fn last(&self) -> Option<&ConvergenceStep> {
+        self.history.last()
+    }
+}
+
+impl fmt::Display for Convergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = if self.converged {
+            "converged"
+        } else {
+            "did not converge"
+        };
+        write!(
+            f,
+            "{} of {} {status} after {} runs",
+            self.criteria.statistic, self.criteria.param, self.num_runs
+        )?;
+        if let Some(step) = self.last() {
+            write!(
+                f,
+                ": {:.6} {unit} in [{:.6}, {:.6}] at {}% confidence (tolerance {} {unit})",
+                step.estimate,
+                step.lower,
+                step.upper,
+                self.criteria.confidence * 100.0,
+                self.criteria.tolerance,
+                unit = self.criteria.param.unit()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: Interpolatable, Distr: StateGenerator<S>> MonteCarlo<S, Distr>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    /// Propagates batches of runs until the specified epoch, until the monitored statistic of the final states converges
+    /// per the stopping criteria, or until the maximum number of runs.
+    ///
+    /// The runs are the same as the first runs of [MonteCarlo::run_until_epoch] with the same seed and random sampling.
+    /// Latin hypercube sampling is not nested, so each batch is drawn from a different stratification.
+    #[must_use = "Monte Carlo result must be used"]
+    pub fn run_until_epoch_converged<D>(
+        &self,
+        prop: Propagator<D>,
+        almanac: Arc<Almanac>,
+        end_epoch: Epoch,
+        criteria: &StoppingCriteria,
+    ) -> Result<(Results<S, PropResult<S>>, Convergence), MonteCarloError>
+    where
+        D: Dynamics<StateType = S>,
+        DefaultAllocator: Allocator<<D::StateType as State>::Size>
+            + Allocator<<D::StateType as State>::Size, <D::StateType as State>::Size>
+            + Allocator<<D::StateType as State>::VecLength>,
+        <DefaultAllocator as Allocator<<D::StateType as State>::VecLength>>::Buffer<f64>: Send,
+    {
+        criteria.check()?;
+        // The batches must continue the same sequence of samples
+        let seed = self
+            .seed
+            .unwrap_or_else(|| stream_seed(&format!("montecarlo/{}", self.scenario)));
+
+        let mut runs = Vec::new();
+        let mut values = Vec::new();
+        let mut convergence = Convergence {
+            criteria: criteria.clone(),
+            history: Vec::new(),
+            num_runs: 0,
+            converged: false,
+        };
+
+        while convergence.num_runs < criteria.max_runs {
+            let skip = convergence.num_runs;
+            let batch_size = criteria.batch_size.min(criteria.max_runs - skip);
+            let init_states = self
+                .generate_states(skip, batch_size, Some(seed))
+                .into_iter()
+                .map(|(index, state)| (skip + index, state))
+                .collect();
+
+            let batch = self.propagate_states(prop.clone(), init_states, None, |prop, state| {
+                prop.with(state, almanac.clone())
+                    .quiet()
+                    .until_epoch_with_traj(end_epoch)
+            });
+
+            for run in batch.runs {
+                if let Ok(rslt) = &run.result {
+                    values.push(
+                        rslt.state
+                            .value(criteria.param)
+                            .map_err(|source| MonteCarloError::StateError { source })?,
+                    );
+                }
+                runs.push(run);
+            }
+            convergence.num_runs = runs.len();
+
+            if values.len() >= 2 {
+                let step = criteria.estimate(&values)?;
+                info!(
+                    "{} of {} after {} runs: {:.6} with a half width of {:.6}",
+                    criteria.statistic,
+                    criteria.param,
+                    convergence.num_runs,
+                    step.estimate,
+                    step.half_width()
+                );
+                convergence.history.push(step);
+                if convergence.num_runs >= criteria.min_runs
+                    && step.half_width() <= criteria.tolerance
+                {
+                    convergence.converged = true;
+                    break;
+                }
+            }
+
+            if convergence.num_runs < skip + batch_size {
+                // Cancelled
+                break;
+            }
+        }
+
+        if !convergence.converged {
+            warn!("{convergence}");
+        }
+
+        Ok((
+            Results {
+                runs,
+                scenario: self.scenario.clone(),
+            },
+            convergence,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod ut_convergence {
+    use super::*;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::mc::{MvnSpacecraft, StateDispersion};
+    use crate::time::Unit;
+    use crate::Spacecraft;
+
+    #[test]
+    fn percentile_interval() {
+        let criteria = StoppingCriteria::builder()
+            .param(StateParameter::SMA)
+            .statistic(MonitoredStatistic::Percentile(99.0))
+            .tolerance(1.0)
+            .build();
+
+        // Too few samples to bound the 99th percentile
+        let values = (0..100).map(f64::from).collect::<Vec<f64>>();
+        let step = criteria.estimate(&values).unwrap();
+        assert!((step.estimate - 98.01).abs() < 1e-9);
+        assert!(step.half_width().is_infinite());
+
+        let values = (0..1000).map(f64::from).collect::<Vec<f64>>();
+        let step = criteria.estimate(&values).unwrap();
+        assert!(step.lower < step.estimate && step.estimate < step.upper);
+        assert!(step.half_width().is_finite());
+
+        assert!(StoppingCriteria::builder()
+            .param(StateParameter::SMA)
+            .statistic(MonitoredStatistic::Mean)
+            .tolerance(1.0)
+            .confidence(1.0)
+            .build()
+            .estimate(&values)
+            .is_err());
+    }
+
+    #[test]
+    fn stop_on_converged_mean() {
+        let epoch = fixtures::epoch();
+        let nominal = Spacecraft::from(fixtures::keplerian(7_000.0, 0.01, 28.5, 0.0, 0.0, 0.0));
+        let generator = MvnSpacecraft::new(
+            nominal,
+            vec![StateDispersion::zero_mean(StateParameter::SMA, 5.0)],
+        )
+        .unwrap();
+        let almanac = fixtures::almanac();
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let mc = MonteCarlo::new(nominal, generator, "converged".to_string(), Some(0));
+
+        // About (1.96 * 5 / 0.5)^2 = 384 runs are needed
+        let criteria = StoppingCriteria::builder()
+            .param(StateParameter::SMA)
+            .statistic(MonitoredStatistic::Mean)
+            .tolerance(0.5)
+            .batch_size(50)
+            .max_runs(2_000)
+            .build();
+
+        let (results, convergence) = mc
+            .run_until_epoch_converged(
+                prop.clone(),
+                almanac.clone(),
+                epoch + Unit::Minute * 10,
+                &criteria,
+            )
+            .unwrap();
+        println!("{convergence}");
+
+        assert!(convergence.converged);
+        assert_eq!(results.runs.len(), convergence.num_runs);
+        assert!((300..=500).contains(&convergence.num_runs));
+        assert!(convergence.num_runs.is_multiple_of(50));
+        let last = convergence.last().unwrap();
+        assert!(last.half_width() <= 0.5);
+        assert!((last.estimate - 7_000.0).abs() < 1.0);
+        // Each run has a unique index
+        assert!(results
+            .runs
+            .iter()
+            .enumerate()
+            .all(|(i, run)| run.index == i));
+
+        // An unreachable tolerance stops at the maximum number of runs
+        let criteria = StoppingCriteria::builder()
+            .param(StateParameter::SMA)
+            .statistic(MonitoredStatistic::Percentile(99.0))
+            .tolerance(1e-6)
+            .batch_size(50)
+            .max_runs(120)
+            .build();
+        let (results, convergence) = mc
+            .run_until_epoch_converged(prop, almanac, epoch + Unit::Minute * 10, &criteria)
+            .unwrap();
+        assert!(!convergence.converged);
+        assert_eq!(results.runs.len(), 120);
+        assert_eq!(convergence.history.len(), 3);
+    }
+}
@@ -17,15 +17,18 @@
 */
 
 use crate::md::StateParameter;
+use serde::{Deserialize, Serialize};
 use typed_builder::TypedBuilder;
 
 /// A dispersions configuration, allows specifying min/max bounds (by default, they are not set)
-#[derive(Copy, Clone, TypedBuilder)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, TypedBuilder)]
 pub struct StateDispersion {
     pub param: StateParameter,
     #[builder(default, setter(strip_option))]
+    #[serde(default)]
     pub mean: Option<f64>,
     #[builder(default, setter(strip_option))]
+    #[serde(default)]
     pub std_dev: Option<f64>,
 }
 
@@ -34,5 +34,45 @@ pub use generator::{DispersedState, Dispersion};
 mod multivariate;
 pub use multivariate::MvnSpacecraft;
 
+mod sampling;
+pub use sampling::{std_normal_quantile, Sampling, StateGenerator, SOBOL_MAX_DIMENSION};
+
+mod parameters;
+pub use parameters::{
+    ParameterDispersion, ParameterDistribution, SpacecraftDispersions, SpacecraftGenerator,
+    DISPERSABLE_PARAMETERS,
+};
+
+mod bplane;
+pub use bplane::{BPlaneSample, BPlaneScatter, DispersionEllipse};
+
+mod progress;
+pub use progress::{CancellationToken, McProgress, ProgressCallback};
+
+mod registry;
+pub use registry::{SampleRecord, SampleStatus, SeedRegistry};
+
+mod seed;
+pub use seed::{stream_rng, stream_seed, RunConfig};
+
+mod realism;
+pub use realism::{
+    CovarianceRealism, RealismEpoch, RealismError, RealismResults, RealismSample, CHI2_6DOF_95,
+};
+
 mod results;
 pub use results::{Results, Stats};
+
+mod sweep;
+pub use sweep::{EpochEnvelope, EpochSweep, EpochSweepResults, RetargetFn};
+
+mod convergence;
+pub use convergence::{Convergence, ConvergenceStep, MonitoredStatistic, StoppingCriteria};
+
+mod worst_case;
+pub use worst_case::{WorstCase, WorstCaseSearch};
+
+mod statistics;
+pub use statistics::{
+    statistics_to_parquet, EnsembleStatistics, Histogram, ParameterStatistics, StatisticsCfg,
+};
@@ -16,35 +16,47 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use super::Pcg64Mcg;
+use super::{stream_rng, stream_seed, Pcg64Mcg};
 use crate::dynamics::Dynamics;
 use crate::linalg::allocator::Allocator;
 use crate::linalg::DefaultAllocator;
 use crate::mc::results::{PropResult, Results, Run};
-use crate::mc::DispersedState;
-use crate::md::trajectory::Interpolatable;
+use crate::mc::{
+    CancellationToken, DispersedState, McProgress, Sampling, SeedRegistry, StateGenerator,
+};
+use crate::md::trajectory::{Interpolatable, Traj, TrajTolerance};
 use crate::md::EventEvaluator;
-use crate::propagators::Propagator;
+use crate::progress::{default_reporter, ProgressReporter};
+use crate::propagators::{PropagationError, Propagator};
 #[cfg(not(target_arch = "wasm32"))]
 use crate::time::Unit;
 use crate::time::{Duration, Epoch};
 use crate::State;
 use anise::almanac::Almanac;
-use indicatif::{ParallelProgressIterator, ProgressBar, ProgressStyle};
-use log::info;
-use rand::SeedableRng;
+use log::{info, warn};
 use rand_distr::Distribution;
 use rayon::prelude::ParallelIterator;
 use rayon::prelude::*;
 use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc::channel;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 #[cfg(not(target_arch = "wasm32"))]
 use std::time::Instant as StdInstant;
 
 /// A Monte Carlo framework, automatically running on all threads via a thread pool. This framework is targeted toward analysis of time-continuous variables.
 /// One caveat of the design is that the trajectory is used for post processing, not each individual state. This may prevent some event switching from being shown in GNC simulations.
-pub struct MonteCarlo<S: Interpolatable, Distr: Distribution<DispersedState<S>>>
+///
+/// # Memory
+/// The propagator is shared by reference by all of the workers, and the almanac, the force models and the gravity fields of its dynamics are shared `Arc`s:
+/// none of them are copied for each run. The memory of large campaigns is dominated by the trajectory of each run instead, which
+/// can be compressed as soon as the run completes with [MonteCarlo::with_traj_compression]. For example, a one day propagation of a
+/// low Earth orbit with the default propagator stores about 1200 spacecraft states per run, and about 150 states once compressed
+/// with the default tolerance of one meter and one millimeter per second. The size of each state depends on the tanks, battery,
+/// attitude and extra states of the spacecraft, cf. `std::mem::size_of::<Spacecraft>()`.
+/// In that example, the compression took about four times longer than the two body propagation itself, so it should only be used
+/// when memory is the limiting factor.
+pub struct MonteCarlo<S: Interpolatable, Distr: StateGenerator<S>>
 where
     DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
 {
@@ -55,9 +67,17 @@ where
     /// Name of this run, will be reflected in the progress bar and in the output structure
     pub scenario: String,
     pub nominal_state: S,
+    /// Sampling strategy of the dispersed states, random by default
+    pub sampling: Sampling,
+    /// Reports the completed runs, a progress bar by default if the `progress-bar` feature is enabled
+    pub reporter: Option<Arc<dyn ProgressReporter>>,
+    /// Token to gracefully cancel the runs, if any
+    pub cancellation: Option<CancellationToken>,
+    /// Tolerance to compress the trajectory of each run with, if any (all of the states are kept by default)
+    pub traj_tolerance: Option<TrajTolerance>,
 }
 
-impl<S: Interpolatable, Distr: Distribution<DispersedState<S>>> MonteCarlo<S, Distr>
+impl<S: Interpolatable, Distr: StateGenerator<S>> MonteCarlo<S, Distr>
 where
     DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
 {
@@ -72,19 +92,38 @@ where
             seed,
             scenario,
             nominal_state,
+            sampling: Sampling::default(),
+            reporter: default_reporter(),
+            cancellation: None,
+            traj_tolerance: None,
         }
     }
-    // Just the template for the progress bar
-    fn progress_bar(&self, num_runs: usize) -> ProgressBar {
-        let pb = ProgressBar::new(num_runs.try_into().unwrap());
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("[{elapsed_precise}] {bar:100.cyan/blue} {pos:>7}/{len:7} {msg}")
-                .unwrap()
-                .progress_chars("##-"),
-        );
-        pb.set_message(format!("{self}"));
-        pb
+
+    /// Sets the sampling strategy of the dispersed states of this Monte Carlo run.
+    pub fn with_sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Reports the completed runs to this reporter instead of the default one, e.g. to a [crate::mc::ProgressCallback], called from the
+    /// worker threads every time a run completes.
+    pub fn with_progress_reporter(mut self, reporter: Arc<dyn ProgressReporter>) -> Self {
+        self.reporter = Some(reporter);
+        self
+    }
+
+    /// Allows the runs to be gracefully cancelled with this token: the runs in progress complete, no other run starts,
+    /// and the results of the completed runs are returned.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Compresses the trajectory of each run as soon as it completes, such that its interpolation stays within this tolerance.
+    /// Refer to [Traj::compress]: this bounds the memory of large Monte Carlo campaigns, where the trajectories dominate.
+    pub fn with_traj_compression(mut self, tolerance: TrajTolerance) -> Self {
+        self.traj_tolerance = Some(tolerance);
+        self
     }
 
     /// Generate states and propagate each independently until a specific event is found `trigger` times.
@@ -132,57 +171,11 @@ where
             + Allocator<<D::StateType as State>::VecLength>,
         <DefaultAllocator as Allocator<<D::StateType as State>::VecLength>>::Buffer<f64>: Send,
     {
-        // Generate the initial states
         let init_states = self.generate_states(skip, num_runs, self.seed);
-        // Setup the progress bar
-        let pb = self.progress_bar(num_runs);
-        // Setup the thread friendly communication
-        let (tx, rx) = channel();
-
-        // Generate all states (must be done separately because the rng is not thread safe)
-        #[cfg(not(target_arch = "wasm32"))]
-        let start = StdInstant::now();
-
-        init_states.par_iter().progress_with(pb).for_each_with(
-            (prop, tx),
-            |(prop, tx), (index, dispersed_state)| {
-                let result = prop
-                    .with(dispersed_state.state, almanac.clone())
-                    .until_nth_event(max_duration, event, trigger);
-
-                // Build a single run result
-                let run = Run {
-                    index: *index,
-                    dispersed_state: dispersed_state.clone(),
-                    result: result.map(|r| PropResult {
-                        state: r.0,
-                        traj: r.1,
-                    }),
-                };
-                tx.send(run).unwrap();
-            },
-        );
-
-        #[cfg(not(target_arch = "wasm32"))]
-        {
-            let clock_time = StdInstant::now() - start;
-            info!(
-                "Propagated {} states in {}",
-                num_runs,
-                clock_time.as_secs_f64() * Unit::Second
-            );
-        }
-
-        // Collect all of the results and sort them by run index
-        let mut runs = rx
-            .iter()
-            .collect::<Vec<Run<D::StateType, PropResult<D::StateType>>>>();
-        runs.par_sort_by_key(|run| run.index);
-
-        Results {
-            runs,
-            scenario: self.scenario.clone(),
-        }
+        self.propagate_states(prop, init_states, None, |prop, state| {
+            prop.with(state, almanac.clone())
+                .until_nth_event(max_duration, event, trigger)
+        })
     }
 
     /// Generate states and propagate each independently until a specific event is found `trigger` times.
@@ -225,23 +218,203 @@ where
             + Allocator<<D::StateType as State>::VecLength>,
         <DefaultAllocator as Allocator<<D::StateType as State>::VecLength>>::Buffer<f64>: Send,
     {
-        // Generate the initial states
         let init_states = self.generate_states(skip, num_runs, self.seed);
-        // Setup the progress bar
-        let pb = self.progress_bar(num_runs);
+        self.propagate_states(prop, init_states, None, |prop, state| {
+            prop.with(state, almanac.clone())
+                .quiet()
+                .until_epoch_with_traj(end_epoch)
+        })
+    }
+
+    /// Builds the seed registry of a campaign of `num_runs` samples of this Monte Carlo, using its sampling strategy.
+    /// If this Monte Carlo has no seed, the campaign seed is derived from the master seed of the [RunConfig] (or drawn from entropy)
+    /// and stored in the registry.
+    pub fn seed_registry(&self, num_runs: usize) -> SeedRegistry {
+        let seed = self
+            .seed
+            .unwrap_or_else(|| stream_seed(&format!("montecarlo/{}", self.scenario)));
+        SeedRegistry::new(self.scenario.clone(), seed, self.sampling, num_runs)
+    }
+
+    /// Generates the dispersed states of the requested samples of this registry. These are identical regardless of
+    /// which other samples are generated, such that any sample can be re-run in isolation.
+    #[must_use = "Generated states for a Monte Carlo run must be used"]
+    pub fn generate_registered_states(
+        &self,
+        registry: &SeedRegistry,
+        indexes: &[usize],
+    ) -> Vec<(usize, DispersedState<S>)> {
+        let mut points = None;
+        if registry.sampling != Sampling::Random {
+            match self.random_state.unit_hypercube_dimension() {
+                Some(dimension) => {
+                    points = Some(registry.sampling.unit_hypercube(
+                        dimension,
+                        registry.len(),
+                        &mut Pcg64Mcg::new(registry.seed),
+                    ))
+                }
+                None => warn!(
+                    "{:?} sampling is not supported by this generator, using random sampling instead",
+                    registry.sampling
+                ),
+            }
+        }
+
+        indexes
+            .iter()
+            .filter_map(|index| match registry.sample(*index) {
+                Some(sample) => {
+                    let dispersed = points
+                        .as_ref()
+                        .and_then(|points| self.random_state.sample_unit_hypercube(&points[*index]))
+                        .unwrap_or_else(|| {
+                            self.random_state.sample(&mut Pcg64Mcg::new(sample.seed))
+                        });
+                    Some((*index, dispersed))
+                }
+                None => {
+                    warn!(
+                        "sample #{index} is not in the {} samples of the registry",
+                        registry.len()
+                    );
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Propagates the requested samples of this registry until the specified epoch, recording their completion in the registry.
+    ///
+    /// An interrupted campaign is resumed exactly by running the [SeedRegistry::pending] samples.
+    #[must_use = "Monte Carlo result must be used"]
+    pub fn run_samples_until_epoch<D>(
+        &self,
+        prop: Propagator<D>,
+        almanac: Arc<Almanac>,
+        end_epoch: Epoch,
+        registry: &mut SeedRegistry,
+        indexes: &[usize],
+    ) -> Results<S, PropResult<S>>
+    where
+        D: Dynamics<StateType = S>,
+
+        DefaultAllocator: Allocator<<D::StateType as State>::Size>
+            + Allocator<<D::StateType as State>::Size, <D::StateType as State>::Size>
+            + Allocator<<D::StateType as State>::VecLength>,
+        <DefaultAllocator as Allocator<<D::StateType as State>::VecLength>>::Buffer<f64>: Send,
+    {
+        let init_states = self.generate_registered_states(registry, indexes);
+        self.propagate_states(prop, init_states, Some(registry), |prop, state| {
+            prop.with(state, almanac.clone())
+                .quiet()
+                .until_epoch_with_traj(end_epoch)
+        })
+    }
+
+    /// Propagates the requested samples of this registry until a specific event is found `trigger` times, recording
+    /// their completion in the registry.
+    #[must_use = "Monte Carlo result must be used"]
+    #[allow(clippy::too_many_arguments)]
+    pub fn run_samples_until_nth_event<D, F>(
+        &self,
+        prop: Propagator<D>,
+        almanac: Arc<Almanac>,
+        max_duration: Duration,
+        event: &F,
+        trigger: usize,
+        registry: &mut SeedRegistry,
+        indexes: &[usize],
+    ) -> Results<S, PropResult<S>>
+    where
+        D: Dynamics<StateType = S>,
+
+        F: EventEvaluator<S>,
+        DefaultAllocator: Allocator<<D::StateType as State>::Size>
+            + Allocator<<D::StateType as State>::Size, <D::StateType as State>::Size>
+            + Allocator<<D::StateType as State>::VecLength>,
+        <DefaultAllocator as Allocator<<D::StateType as State>::VecLength>>::Buffer<f64>: Send,
+    {
+        let init_states = self.generate_registered_states(registry, indexes);
+        self.propagate_states(prop, init_states, Some(registry), |prop, state| {
+            prop.with(state, almanac.clone())
+                .until_nth_event(max_duration, event, trigger)
+        })
+    }
+
+    /// Propagates each of the initial states on the thread pool, recording their completion in the registry, if any.
+    pub(super) fn propagate_states<D, P>(
+        &self,
+        prop: Propagator<D>,
+        init_states: Vec<(usize, DispersedState<S>)>,
+        registry: Option<&mut SeedRegistry>,
+        propagate: P,
+    ) -> Results<S, PropResult<S>>
+    where
+        D: Dynamics<StateType = S>,
+        P: Fn(&Propagator<D>, S) -> Result<(S, Traj<S>), PropagationError> + Send + Sync,
+        DefaultAllocator: Allocator<<D::StateType as State>::Size>
+            + Allocator<<D::StateType as State>::Size, <D::StateType as State>::Size>
+            + Allocator<<D::StateType as State>::VecLength>,
+        <DefaultAllocator as Allocator<<D::StateType as State>::VecLength>>::Buffer<f64>: Send,
+    {
+        let num_runs = init_states.len();
+        let reporter = self.reporter.as_deref();
+        if let Some(reporter) = reporter {
+            reporter.start(&format!("{self}"));
+        }
         // Setup the thread friendly communication
         let (tx, rx) = channel();
+        let registry = registry.map(Mutex::new);
+        let completed = AtomicUsize::new(0);
+        let failed = AtomicUsize::new(0);
+        let cancellation = self.cancellation.as_ref();
 
         // And propagate on the thread pool
         #[cfg(not(target_arch = "wasm32"))]
         let start = StdInstant::now();
-        init_states.par_iter().progress_with(pb).for_each_with(
-            (prop, tx),
-            |(arc_prop, tx), (index, dispersed_state)| {
-                let result = arc_prop
-                    .with(dispersed_state.state, almanac.clone())
-                    .quiet()
-                    .until_epoch_with_traj(end_epoch);
+        // The propagator, and therefore its dynamics and force models, is shared by reference across all of the workers
+        let prop = &prop;
+        let traj_tolerance = self.traj_tolerance;
+        init_states
+            .par_iter()
+            .for_each_with(tx, |tx, (index, dispersed_state)| {
+                if let Some(token) = cancellation {
+                    if token.is_cancelled() {
+                        return;
+                    }
+                }
+
+                let result = propagate(prop, dispersed_state.state);
+
+                let num_completed = completed.fetch_add(1, Ordering::Relaxed) + 1;
+                let num_failed = if result.is_err() {
+                    failed.fetch_add(1, Ordering::Relaxed) + 1
+                } else {
+                    failed.load(Ordering::Relaxed)
+                };
+
+                if let Some(registry) = &registry {
+                    let mut registry = registry.lock().unwrap();
+                    registry.record(*index, result.as_ref().map(|_| ()));
+                    if num_completed.is_multiple_of(registry.checkpoint_every) {
+                        registry.save_checkpoint();
+                    }
+                }
+
+                if let Some(reporter) = reporter {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    let elapsed = Some(start.elapsed().as_secs_f64() * Unit::Second);
+                    #[cfg(target_arch = "wasm32")]
+                    let elapsed = None;
+
+                    reporter.report_runs(McProgress {
+                        completed: num_completed,
+                        failed: num_failed,
+                        total: num_runs,
+                        elapsed,
+                    });
+                }
 
                 // Build a single run result
                 let run = Run {
@@ -249,24 +422,43 @@ where
                     dispersed_state: dispersed_state.clone(),
                     result: result.map(|r| PropResult {
                         state: r.0,
-                        traj: r.1,
+                        traj: match traj_tolerance {
+                            Some(tolerance) => r.1.compress(tolerance).unwrap_or(r.1),
+                            None => r.1,
+                        },
                     }),
                 };
 
                 tx.send(run).unwrap();
-            },
-        );
+            });
+
+        let num_completed = completed.into_inner();
+        if let Some(reporter) = reporter {
+            reporter.finish();
+        }
 
         #[cfg(not(target_arch = "wasm32"))]
         {
             let clock_time = StdInstant::now() - start;
             info!(
                 "Propagated {} states in {}",
-                num_runs,
+                num_completed,
                 clock_time.as_secs_f64() * Unit::Second
             );
         }
 
+        if num_completed < num_runs {
+            warn!(
+                "{self} cancelled after {num_completed} of {num_runs} runs, the other runs were not started"
+            );
+        }
+
+        if let Some(registry) = registry {
+            let registry = registry.into_inner().unwrap();
+            registry.save_checkpoint();
+            info!("{registry}");
+        }
+
         // Collect all of the results and sort them by run index
         let mut runs = rx.iter().collect::<Vec<Run<S, PropResult<S>>>>();
         runs.par_sort_by_key(|run| run.index);
@@ -278,6 +470,9 @@ where
     }
 
     /// Set up the seed and generate the states. This is useful for checking the generated states before running a large scale Monte Carlo.
+    ///
+    /// With the Latin Hypercube and Sobol sampling strategies, the sample points are generated for the `skip + num_runs` runs,
+    /// such that resuming a run generates the same states as a single run.
     #[must_use = "Generated states for a Monte Carlo run must be used"]
     pub fn generate_states(
         &self,
@@ -286,11 +481,37 @@ where
         seed: Option<u128>,
     ) -> Vec<(usize, DispersedState<S>)> {
         // Setup the RNG
-        let rng = match seed {
+        let mut rng = match seed {
             Some(seed) => Pcg64Mcg::new(seed),
-            None => Pcg64Mcg::from_entropy(),
+            None => stream_rng(&format!("montecarlo/{}", self.scenario)),
         };
 
+        if self.sampling != Sampling::Random {
+            match self.random_state.unit_hypercube_dimension() {
+                Some(dimension) => {
+                    let points = self
+                        .sampling
+                        .unit_hypercube(dimension, skip + num_runs, &mut rng);
+                    let states = points[skip..]
+                        .iter()
+                        .map_while(|point| self.random_state.sample_unit_hypercube(point))
+                        .enumerate()
+                        .collect::<Vec<(usize, DispersedState<S>)>>();
+                    if states.len() == num_runs {
+                        return states;
+                    }
+                    warn!(
+                        "{:?} sampling failed, using random sampling instead",
+                        self.sampling
+                    );
+                }
+                None => warn!(
+                    "{:?} sampling is not supported by this generator, using random sampling instead",
+                    self.sampling
+                ),
+            }
+        }
+
         // Generate the states, forcing the borrow as specified in the `sample_iter` docs.
         (&self.random_state)
             .sample_iter(rng)
@@ -301,8 +522,7 @@ where
     }
 }
 
-impl<S: Interpolatable, Distr: Distribution<DispersedState<S>>> fmt::Display
-    for MonteCarlo<S, Distr>
+impl<S: Interpolatable, Distr: StateGenerator<S>> fmt::Display for MonteCarlo<S, Distr>
 where
     DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
 {
@@ -315,8 +535,7 @@ where
     }
 }
 
-impl<S: Interpolatable, Distr: Distribution<DispersedState<S>>> fmt::LowerHex
-    for MonteCarlo<S, Distr>
+impl<S: Interpolatable, Distr: StateGenerator<S>> fmt::LowerHex for MonteCarlo<S, Distr>
 where
     DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
 {
@@ -330,3 +549,68 @@ where
         )
     }
 }
+
+#[cfg(test)]
+mod ut_montecarlo {
+    use super::*;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::mc::{MvnSpacecraft, StateDispersion};
+    use crate::md::StateParameter;
+    use crate::Spacecraft;
+
+    #[test]
+    fn compressed_run_trajectories() {
+        let epoch = fixtures::epoch();
+        let nominal = Spacecraft::from(fixtures::keplerian(7_000.0, 0.01, 28.5, 0.0, 0.0, 0.0));
+        let generator = MvnSpacecraft::new(
+            nominal,
+            vec![StateDispersion::zero_mean(StateParameter::SMA, 5.0)],
+        )
+        .unwrap();
+        let almanac = fixtures::almanac();
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let end_epoch = epoch + Unit::Day;
+
+        let mc = MonteCarlo::new(nominal, generator.clone(), "full".to_string(), Some(0));
+        let full = mc.run_until_epoch(prop.clone(), almanac.clone(), end_epoch, 10);
+
+        let mc = MonteCarlo::new(nominal, generator, "compressed".to_string(), Some(0))
+            .with_traj_compression(TrajTolerance::default());
+        let compressed = mc.run_until_epoch(prop, almanac, end_epoch, 10);
+
+        let num_states = |rslts: &Results<Spacecraft, PropResult<Spacecraft>>| {
+            rslts
+                .runs
+                .iter()
+                .map(|run| run.result.as_ref().unwrap().traj.states.len())
+                .sum::<usize>()
+        };
+        println!(
+            "{} states without compression, {} with compression, each of {} bytes",
+            num_states(&full),
+            num_states(&compressed),
+            std::mem::size_of::<Spacecraft>()
+        );
+        assert!(num_states(&compressed) < num_states(&full));
+
+        // Same seed, so the same final states, and the compressed trajectories remain within tolerance
+        for (full_run, compressed_run) in full.runs.iter().zip(compressed.runs.iter()) {
+            let (full_rslt, compressed_rslt) = (
+                full_run.result.as_ref().unwrap(),
+                compressed_run.result.as_ref().unwrap(),
+            );
+            assert_eq!(full_rslt.state, compressed_rslt.state);
+            let check_epoch = epoch + Unit::Hour * 7.3;
+            let err_km = (full_rslt.traj.at(check_epoch).unwrap().orbit.radius_km
+                - compressed_rslt
+                    .traj
+                    .at(check_epoch)
+                    .unwrap()
+                    .orbit
+                    .radius_km)
+                .norm();
+            assert!(err_km < 1e-3, "{err_km} km");
+        }
+    }
+}
@@ -18,7 +18,8 @@
 
 use std::error::Error;
 
-use super::{DispersedState, StateDispersion};
+use super::sampling::std_normal_quantile;
+use super::{DispersedState, StateDispersion, StateGenerator};
 use crate::errors::StateError;
 use crate::md::prelude::{BPlane, OrbitDual};
 use crate::md::{AstroSnafu, StateParameter};
@@ -28,6 +29,7 @@ use rand_distr::{Distribution, Normal};
 use snafu::ResultExt;
 
 /// A multivariate spacecraft state generator for Monte Carlo analyses. Ensures that the covariance is properly applied on all provided state variables.
+#[derive(Clone)]
 pub struct MvnSpacecraft {
     /// The template state
     pub template: Spacecraft,
@@ -136,13 +138,16 @@ impl MvnSpacecraft {
                 } else {
                     match disp.param {
                         StateParameter::Cr => {
-                            cov[(7, 7)] = disp.mean.unwrap_or(0.0).powi(2);
+                            cov[(6, 6)] = disp.std_dev.unwrap_or(0.0).powi(2);
+                            mean[6] = disp.mean.unwrap_or(0.0);
                         }
                         StateParameter::Cd => {
-                            cov[(8, 8)] = disp.mean.unwrap_or(0.0).powi(2);
+                            cov[(7, 7)] = disp.std_dev.unwrap_or(0.0).powi(2);
+                            mean[7] = disp.mean.unwrap_or(0.0);
                         }
-                        StateParameter::DryMass | StateParameter::PropMass => {
-                            cov[(9, 9)] = disp.mean.unwrap_or(0.0).powi(2);
+                        StateParameter::PropMass => {
+                            cov[(8, 8)] = disp.std_dev.unwrap_or(0.0).powi(2);
+                            mean[8] = disp.mean.unwrap_or(0.0);
                         }
                         _ => return Err(Box::new(StateError::ReadOnly { param: disp.param })),
                     }
@@ -264,10 +269,9 @@ impl MvnSpacecraft {
     }
 }
 
-impl Distribution<DispersedState<Spacecraft>> for MvnSpacecraft {
-    fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> DispersedState<Spacecraft> {
-        // Generate the vector representing the state
-        let x_rng = SVector::<f64, 9>::from_fn(|_, _| self.std_norm_distr.sample(rng));
+impl MvnSpacecraft {
+    /// Builds the dispersed state from a sample of the standard multivariate normal distribution.
+    fn disperse(&self, x_rng: SVector<f64, 9>) -> DispersedState<Spacecraft> {
         let x = self.sqrt_s_v * x_rng + self.mean;
         let mut state = self.template;
 
@@ -285,6 +289,8 @@ impl Distribution<DispersedState<Spacecraft>> for MvnSpacecraft {
                 state.mass.prop_mass_kg += val;
             }
         }
+        // Spread the dispersion of the prop mass across the tanks
+        state.tanks.set_total_kg(state.mass.prop_mass_kg);
 
         let mut actual_dispersions = Vec::new();
         for disp in &self.dispersions {
@@ -300,15 +306,33 @@ impl Distribution<DispersedState<Spacecraft>> for MvnSpacecraft {
     }
 }
 
+impl Distribution<DispersedState<Spacecraft>> for MvnSpacecraft {
+    fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> DispersedState<Spacecraft> {
+        // Generate the vector representing the state
+        let x_rng = SVector::<f64, 9>::from_fn(|_, _| self.std_norm_distr.sample(rng));
+        self.disperse(x_rng)
+    }
+}
+
+impl StateGenerator<Spacecraft> for MvnSpacecraft {
+    fn unit_hypercube_dimension(&self) -> Option<usize> {
+        Some(9)
+    }
+
+    fn sample_unit_hypercube(&self, point: &[f64]) -> Option<DispersedState<Spacecraft>> {
+        let x_rng = SVector::<f64, 9>::from_fn(|i, _| std_normal_quantile(point[i]));
+        Some(self.disperse(x_rng))
+    }
+}
+
 #[cfg(test)]
 mod multivariate_ut {
     use super::*;
+    use crate::fixtures;
     use crate::Spacecraft;
-    use crate::GMAT_EARTH_GM;
 
     #[test]
     fn disperse_r_mag() {
-        use anise::constants::frames::EARTH_J2000;
         use anise::prelude::Orbit;
 
         use crate::time::Epoch;
@@ -318,7 +342,7 @@ mod multivariate_ut {
         // Create a reproducible fast seed
         let seed = 0;
 
-        let eme2k = EARTH_J2000.with_mu_km3_s2(GMAT_EARTH_GM);
+        let eme2k = fixtures::eme2k();
 
         let dt = Epoch::from_gregorian_utc_at_midnight(2021, 1, 31);
         let state = Spacecraft::builder()
@@ -362,16 +386,14 @@ mod multivariate_ut {
 
     #[test]
     fn disperse_full_cartesian() {
-        use anise::constants::frames::EARTH_J2000;
         use anise::prelude::Orbit;
 
         use crate::time::Epoch;
         use crate::Spacecraft;
-        use crate::GMAT_EARTH_GM;
 
         use rand_pcg::Pcg64Mcg;
 
-        let eme2k = EARTH_J2000.with_mu_km3_s2(GMAT_EARTH_GM);
+        let eme2k = fixtures::eme2k();
 
         let dt = Epoch::from_gregorian_utc_at_midnight(2021, 1, 31);
         let state = Orbit::keplerian(8_191.93, 1e-6, 12.85, 306.614, 314.19, 99.887_7, dt, eme2k);
@@ -443,13 +465,12 @@ mod multivariate_ut {
 
     #[test]
     fn disperse_raan_only() {
-        use anise::constants::frames::EARTH_J2000;
         use anise::prelude::Orbit;
 
         use crate::time::Epoch;
         use rand_pcg::Pcg64Mcg;
 
-        let eme2k = EARTH_J2000.with_mu_km3_s2(GMAT_EARTH_GM);
+        let eme2k = fixtures::eme2k();
 
         let dt = Epoch::from_gregorian_utc_at_midnight(2021, 1, 31);
         let state = Spacecraft::builder()
@@ -509,13 +530,12 @@ mod multivariate_ut {
     #[ignore = "https://github.com/nyx-space/nyx/issues/339"]
     #[test]
     fn disperse_keplerian() {
-        use anise::constants::frames::EARTH_J2000;
         use anise::prelude::Orbit;
 
         use crate::time::Epoch;
         use rand_pcg::Pcg64Mcg;
 
-        let eme2k = EARTH_J2000.with_mu_km3_s2(GMAT_EARTH_GM);
+        let eme2k = fixtures::eme2k();
 
         let dt = Epoch::from_gregorian_utc_at_midnight(2021, 1, 31);
         let state = Spacecraft::builder()
@@ -0,0 +1,508 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::sampling::std_normal_quantile;
+use super::{DispersedState, MvnSpacecraft, StateDispersion, StateGenerator};
+use crate::errors::StateError;
+use crate::io::ConfigRepr;
+use crate::md::StateParameter;
+use crate::{NyxError, Spacecraft, State};
+use rand_distr::{Distribution, StandardNormal};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use typed_builder::TypedBuilder;
+
+/// Spacecraft parameters which may be dispersed independently of the orbital state.
+pub const DISPERSABLE_PARAMETERS: [StateParameter; 6] = [
+    StateParameter::Cr,
+    StateParameter::Cd,
+    StateParameter::DryMass,
+    StateParameter::PropMass,
+    StateParameter::Thrust,
+    StateParameter::Isp,
+];
+
+/// Distribution of the dispersion of a spacecraft parameter, i.e. of the offset applied to the template value.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ParameterDistribution {
+    Normal {
+        #[serde(default)]
+        mean: f64,
+        std_dev: f64,
+    },
+    Uniform {
+        min: f64,
+        max: f64,
+    },
+}
+
+impl ParameterDistribution {
+    /// Checks that this distribution can be sampled.
+    pub fn validate(&self) -> Result<(), NyxError> {
+        let valid = match self {
+            Self::Normal { mean, std_dev } => {
+                mean.is_finite() && std_dev.is_finite() && *std_dev >= 0.0
+            }
+            Self::Uniform { min, max } => min.is_finite() && max.is_finite() && min <= max,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(NyxError::CustomError {
+                msg: format!("invalid parameter distribution {self:?}"),
+            })
+        }
+    }
+}
+
+impl ParameterDistribution {
+    /// Returns the value of the dispersion at this probability, i.e. the inverse of the cumulative distribution function.
+    pub fn quantile(&self, p: f64) -> f64 {
+        match *self {
+            Self::Normal { mean, std_dev } => mean + std_dev * std_normal_quantile(p),
+            Self::Uniform { min, max } => min + (max - min) * p,
+        }
+    }
+}
+
+// Sampling never panics: a distribution which does not validate yields non-finite or out of range samples instead.
+impl Distribution<f64> for ParameterDistribution {
+    fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        match *self {
+            Self::Normal { mean, std_dev } => {
+                let z: f64 = rng.sample(StandardNormal);
+                mean + std_dev * z
+            }
+            Self::Uniform { min, max } => min + (max - min) * rng.gen::<f64>(),
+        }
+    }
+}
+
+/// Dispersion of a spacecraft parameter (Cr, Cd, dry or propellant mass, thrust or Isp). The dispersed value may be
+/// clamped between optional bounds, e.g. to prevent a negative propellant mass.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, TypedBuilder)]
+#[builder(doc)]
+pub struct ParameterDispersion {
+    pub param: StateParameter,
+    pub distribution: ParameterDistribution,
+    /// Minimum of the dispersed value (not of the dispersion)
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub bound_min: Option<f64>,
+    /// Maximum of the dispersed value (not of the dispersion)
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub bound_max: Option<f64>,
+}
+
+impl ParameterDispersion {
+    /// Initializes a new normal dispersion of zero mean from the 1σ
+    pub fn from_std_dev(param: StateParameter, std_dev: f64) -> Self {
+        Self::builder()
+            .param(param)
+            .distribution(ParameterDistribution::Normal { mean: 0.0, std_dev })
+            .build()
+    }
+
+    /// Applies this dispersion to the parameter of the spacecraft, and returns the dispersion after the bounds are applied.
+    fn disperse(&self, spacecraft: &mut Spacecraft, dispersion: f64) -> Result<f64, StateError> {
+        let nominal = spacecraft.value(self.param)?;
+        let mut value = nominal + dispersion;
+        if let Some(min) = self.bound_min {
+            value = value.max(min);
+        }
+        if let Some(max) = self.bound_max {
+            value = value.min(max);
+        }
+        spacecraft.set_value(self.param, value)?;
+        Ok(value - nominal)
+    }
+}
+
+/// Dispersions of a spacecraft for Monte Carlo analyses, which may be loaded from a YAML file.
+///
+/// The orbital dispersions are applied with a multivariate normal distribution (cf. [MvnSpacecraft]), and the spacecraft
+/// parameters (Cr, Cd, dry mass, propellant mass, thrust and Isp) are dispersed independently with their own distribution.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpacecraftDispersions {
+    #[serde(default)]
+    pub orbit: Vec<StateDispersion>,
+    #[serde(default)]
+    pub parameters: Vec<ParameterDispersion>,
+}
+
+impl ConfigRepr for SpacecraftDispersions {}
+
+impl SpacecraftDispersions {
+    /// Builds the generator of dispersed states around this template spacecraft.
+    ///
+    /// # Errors
+    /// + The orbital dispersions must only disperse orbital parameters;
+    /// + The spacecraft parameters must be dispersable and available on the template, e.g. dispersing the thrust requires a thruster;
+    /// + Each spacecraft parameter may only be dispersed once.
+    pub fn generator(&self, template: Spacecraft) -> Result<SpacecraftGenerator, Box<dyn Error>> {
+        if let Some(disp) = self.orbit.iter().find(|disp| !disp.param.is_orbital()) {
+            return Err(Box::new(NyxError::CustomError {
+                msg: format!(
+                    "{} is not an orbital parameter, use a parameter dispersion instead",
+                    disp.param
+                ),
+            }));
+        }
+
+        for (idx, disp) in self.parameters.iter().enumerate() {
+            if !DISPERSABLE_PARAMETERS.contains(&disp.param) {
+                return Err(Box::new(StateError::ReadOnly { param: disp.param }));
+            }
+            template.value(disp.param).map_err(Box::new)?;
+            disp.distribution.validate().map_err(Box::new)?;
+            if self.parameters[..idx]
+                .iter()
+                .any(|other| other.param == disp.param)
+            {
+                return Err(Box::new(NyxError::CustomError {
+                    msg: format!("{} is dispersed more than once", disp.param),
+                }));
+            }
+        }
+
+        let orbit = if self.orbit.is_empty() {
+            None
+        } else {
+            Some(MvnSpacecraft::new(template, self.orbit.clone())?)
+        };
+
+        Ok(SpacecraftGenerator {
+            template,
+            orbit,
+            parameters: self.parameters.clone(),
+        })
+    }
+}
+
+/// A generator of spacecraft states with dispersed orbital states and spacecraft parameters, built from [SpacecraftDispersions].
+///
+/// The generator may only be built with [SpacecraftDispersions::generator], which checks that every parameter dispersion
+/// can be applied to the template.
+#[derive(Clone)]
+pub struct SpacecraftGenerator {
+    /// The template state
+    template: Spacecraft,
+    /// Generator of the orbital dispersions, if any
+    orbit: Option<MvnSpacecraft>,
+    parameters: Vec<ParameterDispersion>,
+}
+
+impl SpacecraftGenerator {
+    /// Returns the template state
+    pub fn template(&self) -> Spacecraft {
+        self.template
+    }
+
+    /// Returns the generator of the orbital dispersions, if any
+    pub fn orbit(&self) -> Option<&MvnSpacecraft> {
+        self.orbit.as_ref()
+    }
+
+    /// Returns the dispersions of the spacecraft parameters
+    pub fn parameters(&self) -> &[ParameterDispersion] {
+        &self.parameters
+    }
+}
+
+impl Distribution<DispersedState<Spacecraft>> for SpacecraftGenerator {
+    fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> DispersedState<Spacecraft> {
+        let mut dispersed = match &self.orbit {
+            Some(mvn) => mvn.sample(rng),
+            None => DispersedState {
+                state: self.template,
+                actual_dispersions: Vec::new(),
+            },
+        };
+
+        for disp in &self.parameters {
+            let delta = disp
+                .disperse(&mut dispersed.state, disp.distribution.sample(rng))
+                .expect("parameters are checked by SpacecraftDispersions::generator");
+            dispersed.actual_dispersions.push((disp.param, delta));
+        }
+
+        dispersed
+    }
+}
+
+impl StateGenerator<Spacecraft> for SpacecraftGenerator {
+    fn unit_hypercube_dimension(&self) -> Option<usize> {
+        let orbit_dim = match &self.orbit {
+            Some(mvn) => mvn.unit_hypercube_dimension()?,
+            None => 0,
+        };
+        Some(orbit_dim + self.parameters.len())
+    }
+
+    fn sample_unit_hypercube(&self, point: &[f64]) -> Option<DispersedState<Spacecraft>> {
+        let (mut dispersed, params_point) = match &self.orbit {
+            Some(mvn) => {
+                let orbit_dim = mvn.unit_hypercube_dimension()?;
+                (
+                    mvn.sample_unit_hypercube(&point[..orbit_dim])?,
+                    &point[orbit_dim..],
+                )
+            }
+            None => (
+                DispersedState {
+                    state: self.template,
+                    actual_dispersions: Vec::new(),
+                },
+                point,
+            ),
+        };
+
+        for (disp, p) in self.parameters.iter().zip(params_point) {
+            let delta = disp
+                .disperse(&mut dispersed.state, disp.distribution.quantile(*p))
+                .expect("parameters are checked by SpacecraftDispersions::generator");
+            dispersed.actual_dispersions.push((disp.param, delta));
+        }
+
+        Some(dispersed)
+    }
+}
+
+#[cfg(test)]
+mod ut_parameters {
+    use super::*;
+    use crate::dynamics::guidance::Thruster;
+    use crate::fixtures;
+    use crate::time::Epoch;
+    use anise::prelude::Orbit;
+    use anise::structure::spacecraft::Mass;
+    use rand_pcg::Pcg64Mcg;
+
+    fn template() -> Spacecraft {
+        let eme2k = fixtures::eme2k();
+        let dt = Epoch::from_gregorian_utc_at_midnight(2021, 1, 31);
+        Spacecraft::builder()
+            .orbit(Orbit::keplerian(
+                8_191.93, 1e-6, 12.85, 306.614, 314.19, 99.887_7, dt, eme2k,
+            ))
+            .mass(Mass::from_dry_and_prop_masses(500.0, 50.0))
+            .thruster(Thruster::new(1.0, 300.0))
+            .build()
+    }
+
+    #[test]
+    fn disperse_spacecraft_parameters() {
+        let yaml = r#"
+orbit:
+  - param: SMA
+    std_dev: 1.0
+parameters:
+  - param: Cr
+    distribution: !Normal
+      std_dev: 0.1
+  - param: PropMass
+    distribution: !Uniform
+      min: -10.0
+      max: 10.0
+    bound_max: 55.0
+  - param: Thrust
+    distribution: !Normal
+      mean: -0.05
+      std_dev: 0.01
+  - param: Isp
+    distribution: !Uniform
+      min: -5.0
+      max: 0.0
+"#;
+        let dispersions: SpacecraftDispersions = serde_yml::from_str(yaml).unwrap();
+        assert_eq!(dispersions.orbit.len(), 1);
+        assert_eq!(dispersions.parameters.len(), 4);
+        let reloaded: SpacecraftDispersions =
+            serde_yml::from_str(&serde_yml::to_string(&dispersions).unwrap()).unwrap();
+        assert_eq!(reloaded, dispersions);
+
+        let template = template();
+        let generator = dispersions.generator(template).unwrap();
+
+        let num_runs = 1000;
+        let mut rng = Pcg64Mcg::new(0);
+        let mut cr = Vec::with_capacity(num_runs);
+        let mut thrust = Vec::with_capacity(num_runs);
+        for _ in 0..num_runs {
+            let dispersed = generator.sample(&mut rng);
+            assert_eq!(dispersed.actual_dispersions.len(), 5);
+            let state = dispersed.state;
+
+            for (param, delta) in &dispersed.actual_dispersions[1..] {
+                assert!(
+                    (state.value(*param).unwrap() - template.value(*param).unwrap() - delta).abs()
+                        < 1e-9
+                );
+            }
+            assert!((40.0..=55.0).contains(&state.mass.prop_mass_kg));
+            let isp = state.thruster.unwrap().isp_s;
+            assert!((295.0..=300.0).contains(&isp));
+            assert_eq!(state.mass.dry_mass_kg, 500.0);
+            assert_eq!(state.drag.coeff_drag, template.drag.coeff_drag);
+            cr.push(state.srp.coeff_reflectivity);
+            thrust.push(state.thruster.unwrap().thrust_N);
+        }
+
+        let mean = |v: &[f64]| v.iter().sum::<f64>() / v.len() as f64;
+        let std_dev = |v: &[f64]| {
+            let m = mean(v);
+            (v.iter().map(|x| (x - m).powi(2)).sum::<f64>() / v.len() as f64).sqrt()
+        };
+        assert!((mean(&cr) - template.srp.coeff_reflectivity).abs() < 0.01);
+        assert!((std_dev(&cr) - 0.1).abs() < 0.01);
+        assert!((mean(&thrust) - 0.95).abs() < 1e-3);
+        assert!((std_dev(&thrust) - 0.01).abs() < 1e-3);
+    }
+
+    #[test]
+    fn stratified_sampling() {
+        use crate::mc::{MonteCarlo, Sampling};
+
+        let template = template();
+        let dispersions = SpacecraftDispersions {
+            orbit: vec![StateDispersion::zero_mean(StateParameter::SMA, 1.0)],
+            parameters: vec![
+                ParameterDispersion::from_std_dev(StateParameter::Cr, 0.1),
+                ParameterDispersion::builder()
+                    .param(StateParameter::Isp)
+                    .distribution(ParameterDistribution::Uniform {
+                        min: -10.0,
+                        max: 10.0,
+                    })
+                    .build(),
+            ],
+        };
+        let generator = dispersions.generator(template).unwrap();
+        assert_eq!(generator.unit_hypercube_dimension(), Some(11));
+
+        for sampling in [Sampling::LatinHypercube, Sampling::Sobol] {
+            let mc = MonteCarlo::new(
+                template,
+                generator.clone(),
+                "stratified".to_string(),
+                Some(0),
+            )
+            .with_sampling(sampling);
+            let num_runs = 64;
+            let states = mc.generate_states(0, num_runs, mc.seed);
+            assert_eq!(states.len(), num_runs);
+
+            // Each of the equiprobable intervals of the Isp and Cr dispersions has exactly one sample.
+            let mut isp_strata = states
+                .iter()
+                .map(|(_, disp)| {
+                    ((disp.state.thruster.unwrap().isp_s - 290.0) / 20.0 * num_runs as f64).floor()
+                        as usize
+                })
+                .collect::<Vec<usize>>();
+            isp_strata.sort();
+            assert_eq!(isp_strata, (0..num_runs).collect::<Vec<usize>>());
+
+            let cr_quantiles = (1..num_runs)
+                .map(|k| {
+                    template.srp.coeff_reflectivity
+                        + 0.1 * std_normal_quantile(k as f64 / num_runs as f64)
+                })
+                .collect::<Vec<f64>>();
+            let mut cr_strata = states
+                .iter()
+                .map(|(_, disp)| {
+                    cr_quantiles
+                        .iter()
+                        .filter(|q| **q < disp.state.srp.coeff_reflectivity)
+                        .count()
+                })
+                .collect::<Vec<usize>>();
+            cr_strata.sort();
+            assert_eq!(cr_strata, (0..num_runs).collect::<Vec<usize>>());
+
+            // Resuming the run generates the same states
+            let resumed = mc.generate_states(16, num_runs - 16, mc.seed);
+            for ((_, expected), (_, state)) in states[16..].iter().zip(&resumed) {
+                assert_eq!(expected.state, state.state);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_dispersions() {
+        let template = template();
+
+        // Orbital parameters are dispersed with the multivariate distribution
+        let orbital = SpacecraftDispersions {
+            parameters: vec![ParameterDispersion::from_std_dev(StateParameter::SMA, 1.0)],
+            ..Default::default()
+        };
+        assert!(orbital.generator(template).is_err());
+
+        let not_orbital = SpacecraftDispersions {
+            orbit: vec![StateDispersion::zero_mean(StateParameter::Cr, 0.1)],
+            ..Default::default()
+        };
+        assert!(not_orbital.generator(template).is_err());
+
+        // The thrust requires a thruster
+        let thrust = SpacecraftDispersions {
+            parameters: vec![ParameterDispersion::from_std_dev(
+                StateParameter::Thrust,
+                0.1,
+            )],
+            ..Default::default()
+        };
+        assert!(thrust.generator(template).is_ok());
+        let mut no_thruster = template;
+        no_thruster.thruster = None;
+        assert!(thrust.generator(no_thruster).is_err());
+
+        let twice = SpacecraftDispersions {
+            parameters: vec![
+                ParameterDispersion::from_std_dev(StateParameter::Cd, 0.1),
+                ParameterDispersion::from_std_dev(StateParameter::Cd, 0.2),
+            ],
+            ..Default::default()
+        };
+        assert!(twice.generator(template).is_err());
+
+        let negative_std_dev = SpacecraftDispersions {
+            parameters: vec![ParameterDispersion::from_std_dev(StateParameter::Cd, -0.1)],
+            ..Default::default()
+        };
+        assert!(negative_std_dev.generator(template).is_err());
+
+        // Invalid distributions are rejected by the generator, but sampling them directly does not panic.
+        let mut rng = Pcg64Mcg::new(0);
+        let nan_std_dev = ParameterDistribution::Normal {
+            mean: 0.0,
+            std_dev: f64::NAN,
+        };
+        assert!(nan_std_dev.validate().is_err());
+        assert!(nan_std_dev.sample(&mut rng).is_nan());
+        let reversed = ParameterDistribution::Uniform {
+            min: 1.0,
+            max: -1.0,
+        };
+        assert!(reversed.validate().is_err());
+        assert!(reversed.sample(&mut rng).abs() <= 1.0);
+    }
+}
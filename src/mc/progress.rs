@@ -0,0 +1,188 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::progress::{Progress, ProgressReporter};
+use crate::time::Duration;
+use std::fmt;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+/// Progress of a Monte Carlo run, reported every time a run completes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct McProgress {
+    /// Number of completed runs, including the failed ones
+    pub completed: usize,
+    /// Number of failed runs
+    pub failed: usize,
+    /// Total number of runs to complete
+    pub total: usize,
+    /// Wall clock time since the start of the Monte Carlo, unavailable in WebAssembly
+    pub elapsed: Option<Duration>,
+}
+
+impl McProgress {
+    /// Estimated wall clock time until all runs complete, assuming that the remaining runs take as long as the completed ones.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.completed == 0 {
+            return None;
+        }
+        self.elapsed
+            .map(|elapsed| elapsed * ((self.total - self.completed) as f64 / self.completed as f64))
+    }
+
+    /// Fraction of the completed runs, between 0 and 1
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.completed as f64 / self.total as f64
+        }
+    }
+}
+
+impl fmt::Display for McProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} runs ({} failed)",
+            self.completed, self.total, self.failed
+        )?;
+        if let Some(eta) = self.eta() {
+            write!(f, " - ETA {eta}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Callback called from the worker threads every time a run of a Monte Carlo completes, to be used as the progress reporter
+/// of a Monte Carlo, cf. [crate::mc::MonteCarlo::with_progress_reporter].
+///
+/// Only the Monte Carlo runs are reported to this callback: the progress of the other computations is ignored.
+#[derive(Clone)]
+pub struct ProgressCallback(Arc<dyn Fn(McProgress) + Send + Sync>);
+
+impl ProgressCallback {
+    pub fn new<F: Fn(McProgress) + Send + Sync + 'static>(callback: F) -> Self {
+        Self(Arc::new(callback))
+    }
+
+    /// Sends the progress reports to this channel, ignoring disconnections of the receiver.
+    pub fn channel(tx: Sender<McProgress>) -> Self {
+        let tx = Mutex::new(tx);
+        Self::new(move |progress| {
+            let _ = tx.lock().unwrap().send(progress);
+        })
+    }
+}
+
+impl ProgressReporter for ProgressCallback {
+    fn report(&self, _progress: Progress) {}
+
+    fn report_runs(&self, progress: McProgress) {
+        (self.0)(progress)
+    }
+}
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ProgressCallback")
+    }
+}
+
+/// Upon cancellation of a Monte Carlo run, the runs in progress complete but no other run starts, and the results of the completed runs are returned.
+pub use crate::progress::CancellationToken;
+
+#[cfg(test)]
+mod ut_progress {
+    use super::*;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::mc::{MonteCarlo, MvnSpacecraft, StateDispersion};
+    use crate::md::StateParameter;
+    use crate::propagators::Propagator;
+    use crate::time::Unit;
+    use crate::Spacecraft;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn progress_eta() {
+        let progress = McProgress {
+            completed: 25,
+            failed: 1,
+            total: 100,
+            elapsed: Some(Unit::Second * 10),
+        };
+        assert_eq!(progress.eta(), Some(Unit::Second * 30));
+        assert!((progress.fraction() - 0.25).abs() < f64::EPSILON);
+        assert_eq!(format!("{progress}"), "25/100 runs (1 failed) - ETA 30 s");
+
+        let started = McProgress {
+            completed: 0,
+            ..progress
+        };
+        assert_eq!(started.eta(), None);
+    }
+
+    #[test]
+    fn report_and_cancel() {
+        let epoch = fixtures::epoch();
+        let nominal = Spacecraft::from(fixtures::keplerian(7_000.0, 0.01, 28.5, 0.0, 0.0, 0.0));
+        let generator = MvnSpacecraft::new(
+            nominal,
+            vec![StateDispersion::zero_mean(StateParameter::SMA, 5.0)],
+        )
+        .unwrap();
+        let almanac = fixtures::almanac();
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let num_runs = 500;
+
+        // Reports are sent through a channel
+        let (tx, rx) = channel();
+        let results = MonteCarlo::new(nominal, generator.clone(), "progress".to_string(), Some(0))
+            .with_progress_reporter(Arc::new(ProgressCallback::channel(tx)))
+            .run_until_epoch(prop.clone(), almanac.clone(), epoch + Unit::Minute * 10, 20);
+        let reports = rx.iter().collect::<Vec<McProgress>>();
+        assert_eq!(results.runs.len(), 20);
+        assert_eq!(reports.len(), 20);
+        assert!(reports
+            .iter()
+            .all(|report| report.total == 20 && report.failed == 0 && report.elapsed.is_some()));
+        assert_eq!(
+            reports.iter().map(|report| report.completed).max(),
+            Some(20)
+        );
+
+        // Cancel from the callback once ten runs have completed
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        let results = MonteCarlo::new(nominal, generator, "cancel".to_string(), Some(0))
+            .with_cancellation(token.clone())
+            .with_progress_reporter(Arc::new(ProgressCallback::new(move |progress| {
+                if progress.completed >= 10 {
+                    canceller.cancel();
+                }
+            })))
+            .run_until_epoch(prop, almanac, epoch + Unit::Hour * 1, num_runs);
+
+        assert!(token.is_cancelled());
+        // The runs in progress complete, and their results are preserved
+        assert!(results.runs.len() >= 10);
+        assert!(results.runs.len() < num_runs);
+        assert!(results.runs.iter().all(|run| run.result.is_ok()));
+    }
+}
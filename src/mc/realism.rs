@@ -0,0 +1,519 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{stream_seed, Pcg64Mcg, Sampling, SeedRegistry};
+use crate::dynamics::SpacecraftDynamics;
+use crate::errors::MonteCarloError;
+use crate::io::watermark::pq_writer;
+use crate::linalg::{Const, Matrix6, Vector6};
+use crate::od::prelude::*;
+use crate::progress::{default_reporter, Progress};
+use crate::propagators::Propagator;
+use crate::time::{Duration, Epoch};
+use crate::Spacecraft;
+use anise::almanac::Almanac;
+use arrow::array::{Array, BooleanBuilder, Float64Builder, StringBuilder, UInt64Builder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use hifitime::TimeScale;
+use parquet::arrow::ArrowWriter;
+use rand_distr::Distribution;
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use typed_builder::TypedBuilder;
+
+/// 95% quantile of the chi-squared distribution with six degrees of freedom
+pub const CHI2_6DOF_95: f64 = 12.591_587_243_743_977;
+
+/// A covariance realism assessment, which runs a truth simulation, a tracking simulation and an orbit determination for
+/// each sample of a Monte Carlo, and compares the actual estimation errors to the formal covariance of the filter.
+///
+/// The truth initial state of each sample is dispersed from the covariance of the initial estimate, from which every
+/// orbit determination starts. A realistic covariance leads to a mean squared Mahalanobis distance of the position and
+/// velocity errors of six (the number of degrees of freedom), and to 95% of these distances below [CHI2_6DOF_95].
+#[derive(Clone, TypedBuilder)]
+#[builder(doc)]
+pub struct CovarianceRealism {
+    /// Name of this assessment
+    #[builder(default = "covariance realism".to_string())]
+    pub scenario: String,
+    /// Propagator of the truth trajectories
+    pub truth_prop: Propagator<SpacecraftDynamics>,
+    /// Propagator of the orbit determination, which may use different dynamics than the truth
+    pub estimation_prop: Propagator<SpacecraftDynamics>,
+    /// Initial estimate of every orbit determination, whose covariance disperses the truth initial states
+    pub initial_estimate: KfEstimate<Spacecraft>,
+    /// Tracking devices simulating the measurements
+    pub devices: BTreeMap<String, GroundStation>,
+    /// Tracking devices processing the measurements, defaults to the simulation devices
+    #[builder(default, setter(strip_option))]
+    pub proc_devices: Option<BTreeMap<String, GroundStation>>,
+    /// Tracking configuration of each simulation device
+    pub configs: BTreeMap<String, TrkConfig>,
+    /// Process noise of the filter, if any
+    #[builder(default, setter(strip_option))]
+    pub process_noise: Option<SNC3>,
+    /// Trigger to switch from a classical to an extended Kalman filter, if any
+    #[builder(default, setter(strip_option))]
+    pub ekf_trigger: Option<EkfTrigger>,
+    /// Residual rejection criteria of the filter, if any
+    #[builder(default, setter(strip_option))]
+    pub resid_crit: Option<ResidRejectCrit>,
+    /// End of the tracking arcs
+    pub end_epoch: Epoch,
+    /// Step and end epoch of the prediction after the last measurement, if any
+    #[builder(default, setter(strip_option))]
+    pub prediction: Option<(Duration, Epoch)>,
+    /// Seed of the campaign, from which the seed of each sample is drawn
+    #[builder(default, setter(strip_option))]
+    pub seed: Option<u128>,
+}
+
+/// Estimation error of a single estimate, compared to the truth.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RealismError {
+    pub epoch: Epoch,
+    /// Position and velocity error of the estimate, in km and km/s
+    pub error: Vector6<f64>,
+    /// Position and velocity standard deviations of the estimate, in km and km/s
+    pub sigmas: Vector6<f64>,
+    /// Squared Mahalanobis distance of the position and velocity error
+    pub mahalanobis_sq: f64,
+    /// Whether this estimate is a prediction, i.e. not a measurement update
+    pub predicted: bool,
+}
+
+/// Estimation errors of a single sample of the covariance realism assessment.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RealismSample {
+    pub index: usize,
+    /// Seed of this sample, from which the truth initial state and the measurement noises are drawn
+    pub seed: u128,
+    /// Number of simulated measurements
+    pub num_msr: usize,
+    pub errors: Vec<RealismError>,
+}
+
+/// Statistics of the estimation errors of all of the samples at a given epoch.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RealismEpoch {
+    pub epoch: Epoch,
+    pub num_samples: usize,
+    /// Whether any of the estimates at this epoch is a prediction
+    pub predicted: bool,
+    /// Mean squared Mahalanobis distance, which should be six for a realistic covariance
+    pub mean_mahalanobis_sq: f64,
+    /// Fraction of the squared Mahalanobis distances within [CHI2_6DOF_95], which should be 0.95 for a realistic covariance
+    pub within_95: f64,
+    /// Root mean square of the errors normalized by their standard deviation, which should be one for a realistic covariance
+    pub normalized_rms: Vector6<f64>,
+}
+
+/// Results of a covariance realism assessment.
+#[derive(Clone, Debug)]
+pub struct RealismResults {
+    pub scenario: String,
+    /// Seed registry of the campaign, recording the status of each sample
+    pub registry: SeedRegistry,
+    /// Samples whose truth simulation, tracking simulation and orbit determination succeeded
+    pub samples: Vec<RealismSample>,
+    /// Statistics of all of the samples at each epoch with an estimate, in chronological order
+    pub epochs: Vec<RealismEpoch>,
+}
+
+impl CovarianceRealism {
+    /// Runs the assessment over `num_runs` samples on all threads.
+    pub fn run(
+        &self,
+        almanac: Arc<Almanac>,
+        num_runs: usize,
+    ) -> Result<RealismResults, Box<dyn Error>> {
+        let seed = self
+            .seed
+            .unwrap_or_else(|| stream_seed(&format!("realism/{}", self.scenario)));
+        let mut registry =
+            SeedRegistry::new(self.scenario.clone(), seed, Sampling::Random, num_runs);
+
+        let dispersions = self.initial_estimate.to_random_variable()?;
+
+        let reporter = default_reporter();
+        if let Some(reporter) = &reporter {
+            reporter.start(&format!("{self}"));
+        }
+        let completed = AtomicUsize::new(0);
+
+        let outcomes = registry
+            .samples
+            .par_iter()
+            .map(|sample| {
+                let mut rng = Pcg64Mcg::new(sample.seed);
+                let truth = dispersions.sample(&mut rng).state;
+                let outcome = self.run_sample(truth, sample.seed, almanac.clone()).map(
+                    |(num_msr, errors)| RealismSample {
+                        index: sample.index,
+                        seed: sample.seed,
+                        num_msr,
+                        errors,
+                    },
+                );
+                if let Some(reporter) = &reporter {
+                    let num_completed = completed.fetch_add(1, Ordering::Relaxed) + 1;
+                    reporter.report(Progress::new(num_completed as f64 / num_runs as f64, None));
+                }
+                outcome
+            })
+            .collect::<Vec<Result<RealismSample, Box<dyn Error + Send + Sync>>>>();
+        if let Some(reporter) = &reporter {
+            reporter.finish();
+        }
+
+        let mut samples = Vec::with_capacity(num_runs);
+        for (index, outcome) in outcomes.into_iter().enumerate() {
+            match outcome {
+                Ok(sample) => {
+                    registry.record::<String>(index, Ok(()));
+                    samples.push(sample);
+                }
+                Err(e) => {
+                    warn!("covariance realism sample #{index} failed: {e}");
+                    registry.record(index, Err(e));
+                }
+            }
+        }
+
+        if samples.is_empty() {
+            return Err(Box::new(MonteCarloError::NoSuccessfulRuns {
+                action: "assess the covariance realism",
+                num_runs,
+            }));
+        }
+
+        let epochs = RealismEpoch::from_samples(&samples);
+        info!("{registry}");
+
+        Ok(RealismResults {
+            scenario: self.scenario.clone(),
+            registry,
+            samples,
+            epochs,
+        })
+    }
+
+    /// Runs the truth simulation, tracking simulation and orbit determination of a single sample, returning the number
+    /// of measurements and the estimation errors.
+    pub fn run_sample(
+        &self,
+        truth: Spacecraft,
+        seed: u128,
+        almanac: Arc<Almanac>,
+    ) -> Result<(usize, Vec<RealismError>), Box<dyn Error + Send + Sync>> {
+        let truth_end = match self.prediction {
+            Some((_, end)) if end > self.end_epoch => end,
+            _ => self.end_epoch,
+        };
+
+        let (_, truth_traj) = self
+            .truth_prop
+            .with(truth, almanac.clone())
+            .quiet()
+            .until_epoch_with_traj(truth_end)?;
+
+        let mut arc_sim = TrackingArcSim::<Spacecraft, GroundStation>::with_rng(
+            self.devices.clone(),
+            truth_traj.clone(),
+            self.configs.clone(),
+            Pcg64Mcg::new(seed),
+        )?;
+        arc_sim.build_schedule(almanac.clone())?;
+        let arc = arc_sim
+            .generate_measurements(almanac.clone())?
+            .filter_by_epoch(..=self.end_epoch);
+
+        let estimate = self.initial_estimate;
+        let prop_est = self
+            .estimation_prop
+            .with(estimate.nominal_state().with_stm(), almanac.clone());
+        let kf = match &self.process_noise {
+            Some(snc) => KF::new(estimate, snc.clone()),
+            None => KF::no_snc(estimate),
+        };
+
+        let mut odp = ODProcess::<_, Const<2>, Const<3>, _, _>::new(
+            prop_est,
+            kf,
+            self.proc_devices
+                .clone()
+                .unwrap_or_else(|| self.devices.clone()),
+            self.ekf_trigger,
+            self.resid_crit,
+            almanac,
+        );
+        odp.process_arc(&arc)?;
+        if let Some((step, end)) = self.prediction {
+            odp.predict_until(step, end)?;
+        }
+
+        let mut errors = Vec::with_capacity(odp.estimates.len());
+        for estimate in &odp.estimates {
+            let epoch = estimate.epoch();
+            let truth = truth_traj.at(epoch)?;
+            let error =
+                truth.orbit.to_cartesian_pos_vel() - estimate.state().orbit.to_cartesian_pos_vel();
+            let covar: Matrix6<f64> = estimate.covar().fixed_view::<6, 6>(0, 0).into_owned();
+            match covar.try_inverse() {
+                Some(info) => errors.push(RealismError {
+                    epoch,
+                    error,
+                    sigmas: covar.diagonal().map(|var| var.sqrt()),
+                    mahalanobis_sq: (error.transpose() * info * error)[(0, 0)],
+                    predicted: estimate.predicted(),
+                }),
+                None => warn!("singular covariance at {epoch}, skipping in covariance realism"),
+            }
+        }
+
+        Ok((arc.len(), errors))
+    }
+}
+
+impl fmt::Display for CovarianceRealism {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - Nyx covariance realism - seed: {:?}",
+            self.scenario, self.seed
+        )
+    }
+}
+
+impl RealismEpoch {
+    /// Groups the estimation errors of all samples by epoch and computes their statistics.
+    pub fn from_samples(samples: &[RealismSample]) -> Vec<Self> {
+        let mut by_epoch = BTreeMap::<Epoch, Vec<&RealismError>>::new();
+        for error in samples.iter().flat_map(|sample| &sample.errors) {
+            by_epoch.entry(error.epoch).or_default().push(error);
+        }
+
+        by_epoch
+            .into_iter()
+            .map(|(epoch, errors)| {
+                let n = errors.len() as f64;
+                let mut normalized_sq = Vector6::zeros();
+                for error in &errors {
+                    normalized_sq += error
+                        .error
+                        .component_div(&error.sigmas)
+                        .map(|normalized| normalized.powi(2));
+                }
+                Self {
+                    epoch,
+                    num_samples: errors.len(),
+                    predicted: errors.iter().any(|error| error.predicted),
+                    mean_mahalanobis_sq: errors
+                        .iter()
+                        .map(|error| error.mahalanobis_sq)
+                        .sum::<f64>()
+                        / n,
+                    within_95: errors
+                        .iter()
+                        .filter(|error| error.mahalanobis_sq <= CHI2_6DOF_95)
+                        .count() as f64
+                        / n,
+                    normalized_rms: (normalized_sq / n).map(|ms| ms.sqrt()),
+                }
+            })
+            .collect()
+    }
+}
+
+impl RealismResults {
+    /// Mean squared Mahalanobis distance over all of the estimates of all of the samples, which should be six for a realistic covariance
+    pub fn mean_mahalanobis_sq(&self) -> f64 {
+        let (sum, count) = self
+            .samples
+            .iter()
+            .flat_map(|sample| &sample.errors)
+            .fold((0.0, 0), |(sum, count), error| {
+                (sum + error.mahalanobis_sq, count + 1)
+            });
+        sum / count as f64
+    }
+
+    /// Fraction of all of the estimates of all of the samples whose squared Mahalanobis distance is within [CHI2_6DOF_95]
+    pub fn within_95(&self) -> f64 {
+        let errors = self
+            .samples
+            .iter()
+            .flat_map(|sample| &sample.errors)
+            .collect::<Vec<&RealismError>>();
+        errors
+            .iter()
+            .filter(|error| error.mahalanobis_sq <= CHI2_6DOF_95)
+            .count() as f64
+            / errors.len() as f64
+    }
+
+    /// Stores the statistics at each epoch to a parquet file.
+    pub fn to_parquet<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Box<dyn Error>> {
+        let path_buf = path.as_ref().to_path_buf();
+
+        let components = ["X", "Y", "Z", "VX", "VY", "VZ"];
+        let mut hdrs = vec![
+            Field::new("Epoch (UTC)", DataType::Utf8, false),
+            Field::new("Samples", DataType::UInt64, false),
+            Field::new("Predicted", DataType::Boolean, false),
+            Field::new(
+                "Mean squared Mahalanobis distance",
+                DataType::Float64,
+                false,
+            ),
+            Field::new("Fraction within 95% bound", DataType::Float64, false),
+        ];
+        for component in components {
+            hdrs.push(Field::new(
+                format!("Normalized {component} error RMS"),
+                DataType::Float64,
+                false,
+            ));
+        }
+
+        let mut epoch = StringBuilder::new();
+        let mut num_samples = UInt64Builder::new();
+        let mut predicted = BooleanBuilder::new();
+        let mut mahalanobis = Float64Builder::new();
+        let mut within_95 = Float64Builder::new();
+        let mut normalized = components
+            .iter()
+            .map(|_| Float64Builder::new())
+            .collect::<Vec<_>>();
+
+        for stats in &self.epochs {
+            epoch.append_value(stats.epoch.to_time_scale(TimeScale::UTC).to_isoformat());
+            num_samples.append_value(stats.num_samples as u64);
+            predicted.append_value(stats.predicted);
+            mahalanobis.append_value(stats.mean_mahalanobis_sq);
+            within_95.append_value(stats.within_95);
+            for (i, col) in normalized.iter_mut().enumerate() {
+                col.append_value(stats.normalized_rms[i]);
+            }
+        }
+
+        let mut record: Vec<Arc<dyn Array>> = vec![
+            Arc::new(epoch.finish()),
+            Arc::new(num_samples.finish()),
+            Arc::new(predicted.finish()),
+            Arc::new(mahalanobis.finish()),
+            Arc::new(within_95.finish()),
+        ];
+        for mut col in normalized {
+            record.push(Arc::new(col.finish()));
+        }
+
+        let mut metadata = HashMap::new();
+        metadata.insert("Purpose".to_string(), "Covariance realism".to_string());
+        metadata.insert("Scenario".to_string(), self.scenario.clone());
+        metadata.insert("Seed".to_string(), format!("{}", self.registry.seed));
+
+        let schema = Arc::new(Schema::new(hdrs));
+        let file = File::create(&path_buf)?;
+        let mut writer = ArrowWriter::try_new(file, schema.clone(), pq_writer(Some(metadata)))?;
+        writer.write(&RecordBatch::try_new(schema, record)?)?;
+        writer.close()?;
+
+        info!(
+            "Covariance realism of {} samples written to {}",
+            self.samples.len(),
+            path_buf.display()
+        );
+
+        Ok(path_buf)
+    }
+}
+
+impl fmt::Display for RealismResults {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} samples, mean squared Mahalanobis distance = {:.3} (expected 6), {:.1}% within the 95% bound",
+            self.scenario,
+            self.samples.len(),
+            self.mean_mahalanobis_sq(),
+            self.within_95() * 100.0
+        )
+    }
+}
+
+#[cfg(test)]
+mod ut_realism {
+    use super::*;
+    use crate::time::Unit;
+
+    #[test]
+    fn realism_statistics() {
+        let epoch = Epoch::from_gregorian_utc_at_midnight(2024, 1, 1);
+        let sigmas = Vector6::new(1.0, 1.0, 1.0, 1e-3, 1e-3, 1e-3);
+        let error = |scale: f64, epoch: Epoch| {
+            let error = sigmas * scale;
+            RealismError {
+                epoch,
+                error,
+                sigmas,
+                mahalanobis_sq: error
+                    .component_div(&sigmas)
+                    .map(|normalized| normalized.powi(2))
+                    .sum(),
+                predicted: false,
+            }
+        };
+
+        let samples = vec![
+            RealismSample {
+                index: 0,
+                seed: 1,
+                num_msr: 2,
+                errors: vec![error(1.0, epoch), error(0.5, epoch + Unit::Minute * 1)],
+            },
+            RealismSample {
+                index: 1,
+                seed: 2,
+                num_msr: 1,
+                errors: vec![error(2.0, epoch)],
+            },
+        ];
+
+        let epochs = RealismEpoch::from_samples(&samples);
+        assert_eq!(epochs.len(), 2);
+        assert_eq!(epochs[0].epoch, epoch);
+        assert_eq!(epochs[0].num_samples, 2);
+        // Squared distances of 6 and 24
+        assert!((epochs[0].mean_mahalanobis_sq - 15.0).abs() < 1e-12);
+        assert!((epochs[0].within_95 - 0.5).abs() < f64::EPSILON);
+        for rms in epochs[0].normalized_rms.iter() {
+            assert!((rms - 2.5_f64.sqrt()).abs() < 1e-12);
+        }
+        assert_eq!(epochs[1].num_samples, 1);
+        assert!((epochs[1].mean_mahalanobis_sq - 1.5).abs() < 1e-12);
+        assert!((epochs[1].within_95 - 1.0).abs() < f64::EPSILON);
+    }
+}
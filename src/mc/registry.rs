@@ -0,0 +1,297 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{Pcg64Mcg, Sampling};
+use crate::io::{ConfigError, ConfigRepr, SerializeSnafu, WriteSnafu};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// Completion status of a sample of a Monte Carlo campaign.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SampleStatus {
+    #[default]
+    Pending,
+    Succeeded,
+    Failed,
+}
+
+/// Seed and completion status of a single sample of a Monte Carlo campaign.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SampleRecord {
+    pub index: usize,
+    /// Seed of the random number generator of this sample only
+    pub seed: u128,
+    #[serde(default)]
+    pub status: SampleStatus,
+    /// Error of the last run of this sample, if it failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A registry of the seeds and completion status of every sample of a Monte Carlo campaign.
+///
+/// Each sample is dispersed from its own seed, such that an interrupted campaign can be resumed exactly by running its
+/// pending samples (cf. [super::MonteCarlo::run_samples_until_epoch]), and any sample can be re-run in isolation.
+/// With the Latin Hypercube and Sobol sampling strategies, the sample points are instead drawn from the campaign seed
+/// for all samples at once, which remains deterministic.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SeedRegistry {
+    pub scenario: String,
+    /// Seed of the campaign, from which the sample seeds are drawn
+    pub seed: u128,
+    #[serde(default)]
+    pub sampling: Sampling,
+    pub samples: Vec<SampleRecord>,
+    /// Path where this registry is saved while samples complete, if any
+    #[serde(skip)]
+    pub checkpoint: Option<PathBuf>,
+    /// Number of completed samples between two saves of the checkpoint
+    #[serde(skip, default = "checkpoint_every_default")]
+    pub checkpoint_every: usize,
+}
+
+fn checkpoint_every_default() -> usize {
+    1
+}
+
+impl SeedRegistry {
+    /// Builds a registry of `num_runs` pending samples, whose seeds are drawn from the campaign seed.
+    pub fn new(scenario: String, seed: u128, sampling: Sampling, num_runs: usize) -> Self {
+        let mut rng = Pcg64Mcg::new(seed);
+        let samples = (0..num_runs)
+            .map(|index| SampleRecord {
+                index,
+                seed: rng.gen(),
+                status: SampleStatus::Pending,
+                error: None,
+            })
+            .collect();
+
+        Self {
+            scenario,
+            seed,
+            sampling,
+            samples,
+            checkpoint: None,
+            checkpoint_every: checkpoint_every_default(),
+        }
+    }
+
+    /// Saves this registry to the provided path every `every` completed samples and at the end of each run.
+    pub fn with_checkpoint<P: AsRef<Path>>(mut self, path: P, every: usize) -> Self {
+        self.checkpoint = Some(path.as_ref().to_path_buf());
+        self.checkpoint_every = every.max(1);
+        self
+    }
+
+    /// Number of samples in this campaign
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Returns whether this campaign has no samples
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Returns the record of the requested sample, if it exists
+    pub fn sample(&self, index: usize) -> Option<&SampleRecord> {
+        self.samples.get(index)
+    }
+
+    /// Indexes of the samples which have not succeeded yet, including the failed ones
+    pub fn pending(&self) -> Vec<usize> {
+        self.samples
+            .iter()
+            .filter(|sample| sample.status != SampleStatus::Succeeded)
+            .map(|sample| sample.index)
+            .collect()
+    }
+
+    /// Indexes of the samples with the requested status
+    pub fn with_status(&self, status: SampleStatus) -> Vec<usize> {
+        self.samples
+            .iter()
+            .filter(|sample| sample.status == status)
+            .map(|sample| sample.index)
+            .collect()
+    }
+
+    /// Returns whether all of the samples of this campaign have succeeded
+    pub fn is_complete(&self) -> bool {
+        self.samples
+            .iter()
+            .all(|sample| sample.status == SampleStatus::Succeeded)
+    }
+
+    /// Records the outcome of a run of this sample.
+    pub fn record<E: fmt::Display>(&mut self, index: usize, outcome: Result<(), E>) {
+        if let Some(sample) = self.samples.get_mut(index) {
+            match outcome {
+                Ok(()) => {
+                    sample.status = SampleStatus::Succeeded;
+                    sample.error = None;
+                }
+                Err(e) => {
+                    sample.status = SampleStatus::Failed;
+                    sample.error = Some(e.to_string());
+                }
+            }
+        }
+    }
+
+    /// Saves this registry to the provided path as YAML, which can be loaded with [ConfigRepr::load].
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
+        let file = File::create(path).context(WriteSnafu)?;
+        serde_yml::to_writer(BufWriter::new(file), self).context(SerializeSnafu)
+    }
+
+    /// Saves this registry to its checkpoint, if set, logging any error.
+    pub(crate) fn save_checkpoint(&self) {
+        if let Some(path) = &self.checkpoint {
+            if let Err(e) = self.save(path) {
+                warn!("could not save seed registry to {}: {e}", path.display());
+            }
+        }
+    }
+}
+
+impl ConfigRepr for SeedRegistry {}
+
+impl fmt::Display for SeedRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - seed: {} - {:?} sampling - {} samples: {} succeeded, {} failed, {} pending",
+            self.scenario,
+            self.seed,
+            self.sampling,
+            self.len(),
+            self.with_status(SampleStatus::Succeeded).len(),
+            self.with_status(SampleStatus::Failed).len(),
+            self.with_status(SampleStatus::Pending).len(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod ut_registry {
+    use super::*;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::mc::{MonteCarlo, MvnSpacecraft, StateDispersion};
+    use crate::md::StateParameter;
+    use crate::propagators::Propagator;
+    use crate::time::Unit;
+    use crate::Spacecraft;
+
+    #[test]
+    fn resume_and_rerun_samples() {
+        let epoch = fixtures::epoch();
+        let nominal = Spacecraft::from(fixtures::keplerian(7_000.0, 0.01, 28.5, 0.0, 0.0, 0.0));
+        let generator = MvnSpacecraft::new(
+            nominal,
+            vec![
+                StateDispersion::zero_mean(StateParameter::SMA, 5.0),
+                StateDispersion::zero_mean(StateParameter::Inclination, 0.1),
+            ],
+        )
+        .unwrap();
+        let almanac = fixtures::almanac();
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let end_epoch = epoch + Unit::Minute * 30;
+
+        // The campaign seed is drawn if the Monte Carlo has none
+        let mc = MonteCarlo::new(nominal, generator.clone(), "registry".to_string(), None);
+        let registry = mc.seed_registry(10);
+        assert_eq!(registry.len(), 10);
+        assert_eq!(registry.pending().len(), 10);
+        assert_ne!(registry.samples[0].seed, registry.samples[1].seed);
+
+        let mc = MonteCarlo::new(nominal, generator, "registry".to_string(), Some(42));
+        let full = mc.run_samples_until_epoch(
+            prop.clone(),
+            almanac.clone(),
+            end_epoch,
+            &mut mc.seed_registry(10),
+            &(0..10).collect::<Vec<usize>>(),
+        );
+
+        // Interrupt the campaign after the first four samples
+        let path = std::env::temp_dir().join("nyx_ut_seed_registry.yaml");
+        let mut registry = mc.seed_registry(10).with_checkpoint(&path, 2);
+        let first = mc.run_samples_until_epoch(
+            prop.clone(),
+            almanac.clone(),
+            end_epoch,
+            &mut registry,
+            &[0, 1, 2, 3],
+        );
+        assert_eq!(first.runs.len(), 4);
+        println!("{registry}");
+
+        // Resume from the checkpoint
+        let mut loaded = SeedRegistry::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.samples, registry.samples);
+        assert_eq!(
+            loaded.with_status(SampleStatus::Succeeded),
+            vec![0, 1, 2, 3]
+        );
+        assert!(!loaded.is_complete());
+
+        let pending = loaded.pending();
+        assert_eq!(pending, (4..10).collect::<Vec<usize>>());
+        let resumed = mc.run_samples_until_epoch(
+            prop.clone(),
+            almanac.clone(),
+            end_epoch,
+            &mut loaded,
+            &pending,
+        );
+        assert!(loaded.is_complete());
+
+        for run in first.runs.iter().chain(&resumed.runs) {
+            let expected = &full.runs[run.index];
+            assert_eq!(run.dispersed_state.state, expected.dispersed_state.state);
+            assert_eq!(
+                run.result.as_ref().unwrap().state,
+                expected.result.as_ref().unwrap().state
+            );
+        }
+
+        // A single sample is re-run in isolation
+        let isolated = mc.generate_registered_states(&loaded, &[7, 12]);
+        assert_eq!(isolated.len(), 1);
+        assert_eq!(isolated[0].0, 7);
+        assert_eq!(isolated[0].1.state, full.runs[7].dispersed_state.state);
+
+        // Stratified samples are also reproducible in isolation
+        let mc = mc.with_sampling(Sampling::Sobol);
+        let registry = mc.seed_registry(8);
+        assert_eq!(registry.sampling, Sampling::Sobol);
+        let all = mc.generate_registered_states(&registry, &(0..8).collect::<Vec<usize>>());
+        let isolated = mc.generate_registered_states(&registry, &[5]);
+        assert_eq!(isolated[0].1.state, all[5].1.state);
+    }
+}
@@ -23,7 +23,7 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use crate::errors::{MonteCarloError, NoSuccessfulRunsSnafu, StateError};
-use crate::io::watermark::pq_writer;
+use crate::io::watermark::pq_writer_with;
 use crate::io::{ExportCfg, InputOutputError};
 use crate::linalg::allocator::Allocator;
 use crate::linalg::DefaultAllocator;
@@ -403,7 +403,7 @@ where
             }
         }
 
-        let props = pq_writer(Some(metadata));
+        let props = Some(pq_writer_with(Some(metadata), &cfg.parquet)?);
 
         let file = File::create(&path_buf)?;
         let mut writer = ArrowWriter::try_new(file, schema.clone(), props).unwrap();
@@ -0,0 +1,324 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::DispersedState;
+use crate::linalg::allocator::Allocator;
+use crate::linalg::DefaultAllocator;
+use crate::State;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_distr::Distribution;
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of dimensions of the Sobol sequence, i.e. of dispersed variables.
+pub const SOBOL_MAX_DIMENSION: usize = 21;
+
+/// Number of bits of the Sobol sequence
+const SOBOL_BITS: usize = 32;
+
+/// Degree, coefficients, and initial direction numbers of the primitive polynomials of dimensions 2 to 21 of the
+/// Sobol sequence, from the `new-joe-kuo-6.21201` table of S. Joe and F. Y. Kuo.
+const SOBOL_POLYNOMIALS: [(usize, u32, &[u32]); SOBOL_MAX_DIMENSION - 1] = [
+    (1, 0, &[1]),
+    (2, 1, &[1, 3]),
+    (3, 1, &[1, 3, 1]),
+    (3, 2, &[1, 1, 1]),
+    (4, 1, &[1, 1, 3, 3]),
+    (4, 4, &[1, 3, 5, 13]),
+    (5, 2, &[1, 1, 5, 5, 17]),
+    (5, 4, &[1, 1, 5, 5, 5]),
+    (5, 7, &[1, 1, 7, 11, 19]),
+    (5, 11, &[1, 1, 5, 1, 1]),
+    (5, 13, &[1, 1, 1, 3, 11]),
+    (5, 14, &[1, 3, 5, 5, 31]),
+    (6, 1, &[1, 3, 3, 9, 7, 49]),
+    (6, 13, &[1, 1, 1, 15, 21, 21]),
+    (6, 16, &[1, 3, 1, 13, 27, 49]),
+    (6, 19, &[1, 1, 1, 15, 7, 5]),
+    (6, 22, &[1, 3, 1, 15, 13, 25]),
+    (6, 25, &[1, 1, 5, 5, 19, 61]),
+    (7, 1, &[1, 3, 7, 11, 23, 15, 103]),
+    (7, 4, &[1, 3, 7, 13, 13, 15, 69]),
+];
+
+/// Sampling strategy of the dispersed states of a Monte Carlo run.
+///
+/// The Latin Hypercube and Sobol strategies provide a better coverage of the dispersions than random sampling for small
+/// sample counts. They are only available for the generators which can map the unit hypercube to dispersed states
+/// (cf. [StateGenerator]), and default to random sampling otherwise.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Sampling {
+    /// Independent pseudo random samples
+    #[default]
+    Random,
+    /// Latin Hypercube sampling: each dispersed variable is stratified in as many equiprobable intervals as there are
+    /// samples, and each interval is sampled exactly once.
+    LatinHypercube,
+    /// Quasi random Sobol sequence, with a random digital shift seeded by the Monte Carlo seed.
+    Sobol,
+}
+
+impl Sampling {
+    /// Generates the points of the unit hypercube of this sampling strategy, in the open interval (0, 1) on each dimension.
+    pub fn unit_hypercube<R: Rng + ?Sized>(
+        &self,
+        dimension: usize,
+        num_samples: usize,
+        rng: &mut R,
+    ) -> Vec<Vec<f64>> {
+        match self {
+            Self::Random => (0..num_samples)
+                .map(|_| {
+                    (0..dimension)
+                        .map(|_| rng.gen_range(f64::EPSILON..1.0))
+                        .collect()
+                })
+                .collect(),
+            Self::LatinHypercube => {
+                let mut points = vec![vec![0.0; dimension]; num_samples];
+                let mut strata = (0..num_samples).collect::<Vec<usize>>();
+                for dim in 0..dimension {
+                    strata.shuffle(rng);
+                    for (point, stratum) in points.iter_mut().zip(&strata) {
+                        let offset: f64 = rng.gen_range(f64::EPSILON..1.0);
+                        point[dim] = (*stratum as f64 + offset) / num_samples as f64;
+                    }
+                }
+                points
+            }
+            Self::Sobol => {
+                let shifts = (0..dimension).map(|_| rng.gen()).collect::<Vec<u32>>();
+                sobol(dimension, num_samples, &shifts)
+            }
+        }
+    }
+}
+
+/// Returns the first points of the Sobol sequence, digitally shifted, and offset by half of the resolution of the
+/// sequence to exclude zero.
+///
+/// # Panics
+/// If the dimension is greater than [SOBOL_MAX_DIMENSION].
+fn sobol(dimension: usize, num_samples: usize, shifts: &[u32]) -> Vec<Vec<f64>> {
+    assert!(
+        dimension <= SOBOL_MAX_DIMENSION,
+        "Sobol sampling supports up to {SOBOL_MAX_DIMENSION} dimensions, requested {dimension}"
+    );
+
+    // Direction numbers of each dimension, scaled to the number of bits.
+    let directions = (0..dimension)
+        .map(|dim| {
+            let mut v = [0_u32; SOBOL_BITS];
+            if dim == 0 {
+                for (k, vk) in v.iter_mut().enumerate() {
+                    *vk = 1 << (SOBOL_BITS - 1 - k);
+                }
+            } else {
+                let (degree, coeffs, m) = SOBOL_POLYNOMIALS[dim - 1];
+                for k in 0..degree.min(SOBOL_BITS) {
+                    v[k] = m[k] << (SOBOL_BITS - 1 - k);
+                }
+                for k in degree..SOBOL_BITS {
+                    v[k] = v[k - degree] ^ (v[k - degree] >> degree);
+                    for j in 1..degree {
+                        if (coeffs >> (degree - 1 - j)) & 1 == 1 {
+                            v[k] ^= v[k - j];
+                        }
+                    }
+                }
+            }
+            v
+        })
+        .collect::<Vec<[u32; SOBOL_BITS]>>();
+
+    // Gray code construction, starting from the zero point.
+    let mut state = vec![0_u32; dimension];
+    let mut points = Vec::with_capacity(num_samples);
+    let scale = 2.0_f64.powi(SOBOL_BITS as i32);
+    for idx in 0..num_samples {
+        if idx > 0 {
+            let bit = (idx - 1).trailing_ones() as usize;
+            for (dim, x) in state.iter_mut().enumerate() {
+                *x ^= directions[dim][bit];
+            }
+        }
+        points.push(
+            state
+                .iter()
+                .zip(shifts)
+                .map(|(x, shift)| ((x ^ shift) as f64 + 0.5) / scale)
+                .collect(),
+        );
+    }
+    points
+}
+
+/// Inverse of the cumulative distribution function of the standard normal distribution, with a relative error below
+/// 1.2e-9 (P. J. Acklam's algorithm).
+pub fn std_normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969_683_028_665_376e1,
+        2.209_460_984_245_205e2,
+        -2.759_285_104_469_687e2,
+        1.383_577_518_672_69e2,
+        -3.066_479_806_614_716e1,
+        2.506_628_277_459_239,
+    ];
+    const B: [f64; 5] = [
+        -5.447_609_879_822_406e1,
+        1.615_858_368_580_409e2,
+        -1.556_989_798_598_866e2,
+        6.680_131_188_771_972e1,
+        -1.328_068_155_288_572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784_894_002_430_293e-3,
+        -3.223_964_580_411_365e-1,
+        -2.400_758_277_161_838,
+        -2.549_732_539_343_734,
+        4.374_664_141_464_968,
+        2.938_163_982_698_783,
+    ];
+    const D: [f64; 4] = [
+        7.784_695_709_041_462e-3,
+        3.224_671_290_700_398e-1,
+        2.445_134_137_142_996,
+        3.754_408_661_907_416,
+    ];
+    const P_LOW: f64 = 0.024_25;
+
+    if p <= 0.0 {
+        return f64::NEG_INFINITY;
+    } else if p >= 1.0 {
+        return f64::INFINITY;
+    }
+
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+
+    if p < P_LOW {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - P_LOW {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+/// A generator of dispersed states for Monte Carlo runs.
+///
+/// Generators which can build a dispersed state from a point of the unit hypercube, e.g. by applying the inverse of the
+/// cumulative distribution function of each dispersed variable, support the Latin Hypercube and Sobol sampling strategies.
+pub trait StateGenerator<S: State>: Distribution<DispersedState<S>>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    /// Number of independent variables of this generator, if it supports sampling from the unit hypercube.
+    fn unit_hypercube_dimension(&self) -> Option<usize> {
+        None
+    }
+
+    /// Builds the dispersed state from this point of the unit hypercube, whose length is the dimension of this generator.
+    fn sample_unit_hypercube(&self, _point: &[f64]) -> Option<DispersedState<S>> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod ut_sampling {
+    use super::*;
+    use rand_pcg::Pcg64Mcg;
+
+    #[test]
+    fn normal_quantile() {
+        assert_eq!(std_normal_quantile(0.5), 0.0);
+        assert!((std_normal_quantile(0.975) - 1.959_963_984_540_054).abs() < 1e-8);
+        assert!((std_normal_quantile(0.001) + 3.090_232_306_167_813).abs() < 1e-8);
+        assert!((std_normal_quantile(0.9) - 1.281_551_565_544_601).abs() < 1e-8);
+        assert!((std_normal_quantile(1e-9) + 5.997_807_015_007_686).abs() < 1e-7);
+    }
+
+    /// Checks that each of the projections of the points on each dimension has exactly one point per stratum.
+    fn assert_stratified(points: &[Vec<f64>], dimension: usize) {
+        let num = points.len();
+        for dim in 0..dimension {
+            let mut strata = points
+                .iter()
+                .map(|point| {
+                    assert!(point[dim] > 0.0 && point[dim] < 1.0);
+                    (point[dim] * num as f64).floor() as usize
+                })
+                .collect::<Vec<usize>>();
+            strata.sort();
+            assert_eq!(strata, (0..num).collect::<Vec<usize>>(), "dimension {dim}");
+        }
+    }
+
+    #[test]
+    fn latin_hypercube() {
+        let mut rng = Pcg64Mcg::new(0);
+        let points = Sampling::LatinHypercube.unit_hypercube(5, 100, &mut rng);
+        assert_eq!(points.len(), 100);
+        assert_stratified(&points, 5);
+    }
+
+    #[test]
+    fn sobol_sequence() {
+        // The first points of the unshifted sequence in two dimensions
+        let points = sobol(2, 4, &[0, 0]);
+        let half = 0.5 / 2.0_f64.powi(32);
+        for (point, expected) in
+            points
+                .iter()
+                .zip([[0.0, 0.0], [0.5, 0.5], [0.75, 0.25], [0.25, 0.75]])
+        {
+            assert!((point[0] - expected[0] - half).abs() < 1e-15);
+            assert!((point[1] - expected[1] - half).abs() < 1e-15);
+        }
+
+        // Every power of two of points is stratified on each dimension, even with the digital shift.
+        let mut rng = Pcg64Mcg::new(0);
+        let points = Sampling::Sobol.unit_hypercube(SOBOL_MAX_DIMENSION, 256, &mut rng);
+        assert_stratified(&points, SOBOL_MAX_DIMENSION);
+        assert_stratified(&points[..64], SOBOL_MAX_DIMENSION);
+
+        // The first two dimensions form a (0, 8, 2)-net: each elementary box of the 16 by 16 grid has exactly one point.
+        let mut boxes = points
+            .iter()
+            .map(|point| ((point[0] * 16.0) as usize, (point[1] * 16.0) as usize))
+            .collect::<Vec<_>>();
+        boxes.sort();
+        boxes.dedup();
+        assert_eq!(boxes.len(), 256);
+    }
+
+    #[test]
+    fn random() {
+        let mut rng = Pcg64Mcg::new(0);
+        let points = Sampling::Random.unit_hypercube(3, 10, &mut rng);
+        assert_eq!(points.len(), 10);
+        assert!(points
+            .iter()
+            .all(|point| point.len() == 3 && point.iter().all(|u| *u > 0.0 && *u < 1.0)));
+    }
+}
@@ -0,0 +1,159 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::Pcg64Mcg;
+use rand::{Rng, SeedableRng};
+use std::fmt;
+use std::sync::RwLock;
+
+static MASTER_SEED: RwLock<Option<u128>> = RwLock::new(None);
+
+/// Run configuration shared by the whole process.
+///
+/// In reproducible mode, every random number generator which is not explicitly seeded (tracking noise, Monte Carlo
+/// dispersions, parameter sweeps, initial estimates, etc.) is seeded from a single master seed, and the master seed is
+/// stored in the metadata of all of the parquet files. Rerunning the same scenario with the same master seed therefore
+/// yields bit-identical results. Otherwise, these generators are seeded from the system entropy.
+///
+/// Each generator draws from its own named stream, such that adding random draws to one stream does not change the others.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RunConfig {
+    /// Seed from which all of the random streams are derived, if any
+    pub master_seed: Option<u128>,
+}
+
+impl RunConfig {
+    /// A reproducible run configuration using the provided master seed.
+    pub fn reproducible(master_seed: u128) -> Self {
+        Self {
+            master_seed: Some(master_seed),
+        }
+    }
+
+    /// Returns the run configuration currently applied to this process.
+    pub fn current() -> Self {
+        Self {
+            master_seed: *MASTER_SEED.read().unwrap(),
+        }
+    }
+
+    /// Applies this run configuration to the whole process, including the computations already set up.
+    pub fn apply(&self) {
+        *MASTER_SEED.write().unwrap() = self.master_seed;
+    }
+
+    /// Returns whether this configuration guarantees reproducible runs.
+    pub fn is_reproducible(&self) -> bool {
+        self.master_seed.is_some()
+    }
+
+    /// Seed of the random stream named `stream`, derived from the master seed if set, else drawn from the system entropy.
+    pub fn stream_seed(&self, stream: &str) -> u128 {
+        match self.master_seed {
+            Some(master_seed) => {
+                // The PCG state ignores its lowest bit, so mix all of the bits of the master seed first
+                let hash = fnv1a(stream);
+                let lo = splitmix64(master_seed as u64 ^ hash);
+                let hi = splitmix64((master_seed >> 64) as u64 ^ lo);
+                Pcg64Mcg::new(u128::from(hi) << 64 | u128::from(lo)).gen()
+            }
+            None => Pcg64Mcg::from_entropy().gen(),
+        }
+    }
+}
+
+impl fmt::Display for RunConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.master_seed {
+            Some(seed) => write!(f, "reproducible run with master seed {seed}"),
+            None => write!(f, "run seeded from entropy"),
+        }
+    }
+}
+
+/// Seed of the random stream named `stream` using the current run configuration.
+pub fn stream_seed(stream: &str) -> u128 {
+    RunConfig::current().stream_seed(stream)
+}
+
+/// Random number generator of the stream named `stream` using the current run configuration.
+pub fn stream_rng(stream: &str) -> Pcg64Mcg {
+    Pcg64Mcg::new(stream_seed(stream))
+}
+
+/// 64-bit FNV-1a hash, which unlike the hasher of the standard library is stable across platforms and releases.
+fn fnv1a(stream: &str) -> u64 {
+    stream.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Finalizer of the SplitMix64 generator, a bijection which mixes all of the bits of its input.
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod ut_seed {
+    use super::*;
+
+    #[test]
+    fn streams() {
+        let cfg = RunConfig::reproducible(42);
+        assert_eq!(
+            cfg.stream_seed("noise/DSS-65"),
+            cfg.stream_seed("noise/DSS-65")
+        );
+        assert_ne!(
+            cfg.stream_seed("noise/DSS-65"),
+            cfg.stream_seed("noise/DSS-34")
+        );
+        assert_ne!(
+            cfg.stream_seed("noise/DSS-65"),
+            RunConfig::reproducible(43).stream_seed("noise/DSS-65")
+        );
+        // The derivation must not change across releases, else previous runs could not be reproduced
+        assert_eq!(fnv1a("a"), 0xaf63_dc4c_8601_ec8c);
+
+        // Without a master seed, the streams are drawn from entropy
+        let entropy = RunConfig::default();
+        assert_ne!(entropy.stream_seed("noise"), entropy.stream_seed("noise"));
+    }
+
+    #[test]
+    fn master_seed() {
+        RunConfig::reproducible(7).apply();
+        assert!(RunConfig::current().is_reproducible());
+
+        let draw = || stream_rng("ut_seed").gen::<u64>();
+        assert_eq!(draw(), draw());
+
+        // The master seed is recorded in the parquet metadata
+        let props = crate::io::watermark::pq_writer(None).unwrap();
+        let metadata = props.key_value_metadata().unwrap();
+        assert!(metadata
+            .iter()
+            .any(|kv| kv.key == "Master seed" && kv.value.as_deref() == Some("7")));
+
+        RunConfig::default().apply();
+        assert_ne!(draw(), draw());
+    }
+}
@@ -0,0 +1,636 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::results::{PropResult, Results};
+use crate::errors::{MonteCarloError, NoSuccessfulRunsSnafu, StateError};
+use crate::io::watermark::pq_writer;
+use crate::linalg::allocator::Allocator;
+use crate::linalg::{DMatrix, DefaultAllocator};
+use crate::md::trajectory::Interpolatable;
+use crate::md::{EventEvaluator, StateParameter};
+use crate::time::{Duration, Epoch, TimeSeries};
+use anise::almanac::Almanac;
+use arrow::array::{Array, Float64Builder, ListBuilder, StringBuilder, UInt64Builder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use hifitime::TimeScale;
+use parquet::arrow::ArrowWriter;
+use snafu::ensure;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use typed_builder::TypedBuilder;
+
+/// Configuration of the statistics computed over the runs of a Monte Carlo.
+#[derive(Clone, Debug, TypedBuilder)]
+#[builder(doc)]
+pub struct StatisticsCfg {
+    /// State parameters whose statistics are computed
+    pub params: Vec<StateParameter>,
+    /// Percentiles to compute, between 0.0 and 100.0
+    #[builder(default = vec![1.0, 5.0, 25.0, 50.0, 75.0, 95.0, 99.0])]
+    pub percentiles: Vec<f64>,
+    /// Number of bins of the histograms, spanning from the minimum to the maximum value
+    #[builder(default = 20)]
+    pub histogram_bins: usize,
+}
+
+/// Histogram of the values of a parameter: `counts[i]` values are in `[edges[i], edges[i + 1])`, the last bin including the maximum.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    pub edges: Vec<f64>,
+    pub counts: Vec<u64>,
+}
+
+impl Histogram {
+    fn new(values: &[f64], min: f64, max: f64, bins: usize) -> Self {
+        let bins = bins.max(1);
+        let width = (max - min) / bins as f64;
+        let edges = (0..=bins).map(|i| min + width * i as f64).collect();
+        let mut counts = vec![0; bins];
+        for value in values {
+            let bin = if width > 0.0 {
+                (((value - min) / width).floor() as usize).min(bins - 1)
+            } else {
+                0
+            };
+            counts[bin] += 1;
+        }
+        Self { edges, counts }
+    }
+}
+
+/// Statistics of a single state parameter over the runs of a Monte Carlo.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParameterStatistics {
+    pub param: StateParameter,
+    pub mean: f64,
+    /// Sample standard deviation
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Percentiles (between 0 and 100) and their values, linearly interpolated between the closest ranks
+    pub percentiles: Vec<(f64, f64)>,
+    pub histogram: Histogram,
+}
+
+impl ParameterStatistics {
+    /// Returns the value of the requested percentile if it was computed
+    pub fn percentile(&self, percentile: f64) -> Option<f64> {
+        self.percentiles
+            .iter()
+            .find(|(p, _)| *p == percentile)
+            .map(|(_, value)| *value)
+    }
+}
+
+/// Statistics of several state parameters over the runs of a Monte Carlo at a given snapshot, e.g. at an epoch or an event.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnsembleStatistics {
+    /// Name of this snapshot
+    pub snapshot: String,
+    /// Earliest epoch of the states of this snapshot
+    pub first_epoch: Epoch,
+    /// Latest epoch of the states of this snapshot, which differs from the first epoch if the snapshot is an event
+    pub last_epoch: Epoch,
+    /// Number of runs in these statistics
+    pub num_samples: usize,
+    /// Statistics of each parameter, in the order of the configuration
+    pub params: Vec<ParameterStatistics>,
+    /// Sample covariance of the parameters, in the order of the configuration
+    pub covariance: DMatrix<f64>,
+}
+
+impl EnsembleStatistics {
+    /// Computes the statistics of the requested parameters over these states.
+    pub fn from_states<S: Interpolatable>(
+        snapshot: String,
+        states: &[S],
+        cfg: &StatisticsCfg,
+    ) -> Result<Self, MonteCarloError>
+    where
+        DefaultAllocator:
+            Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+    {
+        ensure!(
+            !states.is_empty(),
+            NoSuccessfulRunsSnafu {
+                action: "compute statistics",
+                num_runs: 0_usize
+            }
+        );
+        for prct in &cfg.percentiles {
+            if !(0.0..=100.0).contains(prct) {
+                return Err(MonteCarloError::ParamPercentage {
+                    param: cfg.params.first().copied().unwrap_or(StateParameter::Epoch),
+                    prct: *prct,
+                });
+            }
+        }
+
+        let num_samples = states.len();
+        let mut columns = Vec::with_capacity(cfg.params.len());
+        for param in &cfg.params {
+            columns.push(
+                states
+                    .iter()
+                    .map(|state| state.value(*param))
+                    .collect::<Result<Vec<f64>, StateError>>()
+                    .map_err(|source| MonteCarloError::StateError { source })?,
+            );
+        }
+
+        let means = columns
+            .iter()
+            .map(|values| values.iter().sum::<f64>() / num_samples as f64)
+            .collect::<Vec<f64>>();
+
+        let dof = (num_samples.max(2) - 1) as f64;
+        let covariance = DMatrix::from_fn(cfg.params.len(), cfg.params.len(), |i, j| {
+            columns[i]
+                .iter()
+                .zip(&columns[j])
+                .map(|(xi, xj)| (xi - means[i]) * (xj - means[j]))
+                .sum::<f64>()
+                / dof
+        });
+
+        let params = cfg
+            .params
+            .iter()
+            .zip(columns)
+            .enumerate()
+            .map(|(i, (param, mut values))| {
+                values.sort_by(|a, b| a.total_cmp(b));
+                let min = values[0];
+                let max = values[num_samples - 1];
+                let percentiles = cfg
+                    .percentiles
+                    .iter()
+                    .map(|prct| {
+                        let rank = prct / 100.0 * (num_samples - 1) as f64;
+                        let lower = rank.floor() as usize;
+                        let upper = rank.ceil() as usize;
+                        let value =
+                            values[lower] + (rank - lower as f64) * (values[upper] - values[lower]);
+                        (*prct, value)
+                    })
+                    .collect();
+
+                ParameterStatistics {
+                    param: *param,
+                    mean: means[i],
+                    std_dev: covariance[(i, i)].sqrt(),
+                    min,
+                    max,
+                    percentiles,
+                    histogram: Histogram::new(&values, min, max, cfg.histogram_bins),
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            snapshot,
+            first_epoch: states.iter().map(|state| state.epoch()).min().unwrap(),
+            last_epoch: states.iter().map(|state| state.epoch()).max().unwrap(),
+            num_samples,
+            params,
+            covariance,
+        })
+    }
+
+    /// Returns the statistics of this parameter, if computed
+    pub fn param(&self, param: StateParameter) -> Option<&ParameterStatistics> {
+        self.params.iter().find(|stats| stats.param == param)
+    }
+
+    /// Returns the sample covariance between these two parameters, if computed
+    pub fn covariance_of(&self, param1: StateParameter, param2: StateParameter) -> Option<f64> {
+        let i = self.params.iter().position(|stats| stats.param == param1)?;
+        let j = self.params.iter().position(|stats| stats.param == param2)?;
+        Some(self.covariance[(i, j)])
+    }
+}
+
+impl fmt::Display for EnsembleStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} ({} samples from {} to {})",
+            self.snapshot, self.num_samples, self.first_epoch, self.last_epoch
+        )?;
+        for stats in &self.params {
+            write!(
+                f,
+                "\t{}: mean = {:.6} {unit}, std dev = {:.6} {unit}, range = [{:.6}, {:.6}]",
+                stats.param,
+                stats.mean,
+                stats.std_dev,
+                stats.min,
+                stats.max,
+                unit = stats.param.unit()
+            )?;
+            for (prct, value) in &stats.percentiles {
+                write!(f, ", P{prct} = {value:.6}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Stores these statistics to a parquet file, with one row per snapshot and parameter, including the percentiles, the
+/// covariance with the other parameters of the snapshot, and the histogram.
+///
+/// All of the snapshots must have been computed with the same configuration.
+pub fn statistics_to_parquet<P: AsRef<Path>>(
+    stats: &[EnsembleStatistics],
+    path: P,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let path_buf = path.as_ref().to_path_buf();
+
+    let first = stats.first().ok_or_else(|| {
+        Box::new(crate::io::InputOutputError::EmptyDataset {
+            action: "exporting Monte Carlo statistics",
+        })
+    })?;
+    let params = first
+        .params
+        .iter()
+        .map(|stats| stats.param)
+        .collect::<Vec<StateParameter>>();
+    let percentiles = first
+        .params
+        .first()
+        .map(|stats| stats.percentiles.iter().map(|(p, _)| *p).collect())
+        .unwrap_or_else(Vec::new);
+
+    let mut hdrs = vec![
+        Field::new("Snapshot", DataType::Utf8, false),
+        Field::new("First epoch (UTC)", DataType::Utf8, false),
+        Field::new("Last epoch (UTC)", DataType::Utf8, false),
+        Field::new("Parameter", DataType::Utf8, false),
+        Field::new("Unit", DataType::Utf8, false),
+        Field::new("Samples", DataType::UInt64, false),
+        Field::new("Mean", DataType::Float64, false),
+        Field::new("Std dev", DataType::Float64, false),
+        Field::new("Min", DataType::Float64, false),
+        Field::new("Max", DataType::Float64, false),
+    ];
+    for prct in &percentiles {
+        hdrs.push(Field::new(format!("P{prct}"), DataType::Float64, false));
+    }
+    for param in &params {
+        hdrs.push(Field::new(format!("Cov {param}"), DataType::Float64, false));
+    }
+    hdrs.push(Field::new(
+        "Histogram edges",
+        DataType::List(Arc::new(Field::new("item", DataType::Float64, true))),
+        false,
+    ));
+    hdrs.push(Field::new(
+        "Histogram counts",
+        DataType::List(Arc::new(Field::new("item", DataType::UInt64, true))),
+        false,
+    ));
+
+    let mut snapshot = StringBuilder::new();
+    let mut first_epoch = StringBuilder::new();
+    let mut last_epoch = StringBuilder::new();
+    let mut param_col = StringBuilder::new();
+    let mut unit = StringBuilder::new();
+    let mut samples = UInt64Builder::new();
+    let mut mean = Float64Builder::new();
+    let mut std_dev = Float64Builder::new();
+    let mut min = Float64Builder::new();
+    let mut max = Float64Builder::new();
+    let mut prct_cols = percentiles
+        .iter()
+        .map(|_| Float64Builder::new())
+        .collect::<Vec<_>>();
+    let mut cov_cols = params
+        .iter()
+        .map(|_| Float64Builder::new())
+        .collect::<Vec<_>>();
+    let mut edges = ListBuilder::new(Float64Builder::new());
+    let mut counts = ListBuilder::new(UInt64Builder::new());
+
+    for ensemble in stats {
+        if ensemble.params.len() != params.len()
+            || ensemble
+                .params
+                .iter()
+                .zip(&params)
+                .any(|(stats, param)| stats.param != *param)
+        {
+            return Err(Box::new(crate::io::InputOutputError::Inconsistency {
+                msg: format!(
+                    "snapshot `{}` has different parameters than `{}`",
+                    ensemble.snapshot, first.snapshot
+                ),
+            }));
+        }
+
+        for (i, stats) in ensemble.params.iter().enumerate() {
+            snapshot.append_value(&ensemble.snapshot);
+            first_epoch.append_value(
+                ensemble
+                    .first_epoch
+                    .to_time_scale(TimeScale::UTC)
+                    .to_isoformat(),
+            );
+            last_epoch.append_value(
+                ensemble
+                    .last_epoch
+                    .to_time_scale(TimeScale::UTC)
+                    .to_isoformat(),
+            );
+            param_col.append_value(format!("{}", stats.param));
+            unit.append_value(stats.param.unit());
+            samples.append_value(ensemble.num_samples as u64);
+            mean.append_value(stats.mean);
+            std_dev.append_value(stats.std_dev);
+            min.append_value(stats.min);
+            max.append_value(stats.max);
+            for (col, prct) in prct_cols.iter_mut().zip(&percentiles) {
+                col.append_option(stats.percentile(*prct));
+            }
+            for (j, col) in cov_cols.iter_mut().enumerate() {
+                col.append_value(ensemble.covariance[(i, j)]);
+            }
+            edges.values().append_slice(&stats.histogram.edges);
+            edges.append(true);
+            counts.values().append_slice(&stats.histogram.counts);
+            counts.append(true);
+        }
+    }
+
+    let mut record: Vec<Arc<dyn Array>> = vec![
+        Arc::new(snapshot.finish()),
+        Arc::new(first_epoch.finish()),
+        Arc::new(last_epoch.finish()),
+        Arc::new(param_col.finish()),
+        Arc::new(unit.finish()),
+        Arc::new(samples.finish()),
+        Arc::new(mean.finish()),
+        Arc::new(std_dev.finish()),
+        Arc::new(min.finish()),
+        Arc::new(max.finish()),
+    ];
+    for mut col in prct_cols.into_iter().chain(cov_cols) {
+        record.push(Arc::new(col.finish()));
+    }
+    record.push(Arc::new(edges.finish()));
+    record.push(Arc::new(counts.finish()));
+
+    let mut metadata = HashMap::new();
+    metadata.insert("Purpose".to_string(), "Monte Carlo statistics".to_string());
+
+    let schema = Arc::new(Schema::new(hdrs));
+    let file = File::create(&path_buf)?;
+    let mut writer = ArrowWriter::try_new(file, schema.clone(), pq_writer(Some(metadata)))?;
+    writer.write(&RecordBatch::try_new(schema, record)?)?;
+    writer.close()?;
+
+    info!(
+        "Monte Carlo statistics of {} snapshots written to {}",
+        stats.len(),
+        path_buf.display()
+    );
+
+    Ok(path_buf)
+}
+
+impl<S: Interpolatable> Results<S, PropResult<S>>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+    <DefaultAllocator as Allocator<S::VecLength>>::Buffer<f64>: Send,
+{
+    /// Computes the statistics of the requested parameters at the provided epoch, over all of the successful runs whose
+    /// trajectory includes this epoch.
+    pub fn statistics_at(
+        &self,
+        epoch: Epoch,
+        cfg: &StatisticsCfg,
+    ) -> Result<EnsembleStatistics, MonteCarloError> {
+        let mut states = Vec::with_capacity(self.runs.len());
+        for run in &self.runs {
+            match &run.result {
+                Ok(r) => match r.traj.at(epoch) {
+                    Ok(state) => states.push(state),
+                    Err(e) => warn!("run #{}: {e}, skipping in statistics", run.index),
+                },
+                Err(e) => warn!("run #{} failed with {e}, skipping in statistics", run.index),
+            }
+        }
+
+        ensure!(
+            !states.is_empty(),
+            NoSuccessfulRunsSnafu {
+                action: "compute statistics",
+                num_runs: self.runs.len()
+            }
+        );
+
+        EnsembleStatistics::from_states(format!("{epoch}"), &states, cfg)
+    }
+
+    /// Computes the statistics of the requested parameters every step from the start to the end of the first successful run.
+    pub fn statistics_every(
+        &self,
+        step: Duration,
+        cfg: &StatisticsCfg,
+    ) -> Result<Vec<EnsembleStatistics>, MonteCarloError> {
+        let traj = self
+            .runs
+            .iter()
+            .find_map(|run| run.result.as_ref().ok().map(|r| &r.traj))
+            .ok_or(MonteCarloError::NoSuccessfulRuns {
+                action: "compute statistics",
+                num_runs: self.runs.len(),
+            })?;
+
+        TimeSeries::inclusive(traj.first().epoch(), traj.last().epoch(), step)
+            .map(|epoch| self.statistics_at(epoch, cfg))
+            .collect()
+    }
+
+    /// Computes the statistics of the requested parameters at the nth (zero indexed) occurrence of the event in each
+    /// successful run, skipping the runs where this occurrence is not found.
+    pub fn statistics_at_event<E: EventEvaluator<S>>(
+        &self,
+        event: &E,
+        occurrence: usize,
+        cfg: &StatisticsCfg,
+        almanac: Arc<Almanac>,
+    ) -> Result<EnsembleStatistics, MonteCarloError> {
+        let mut states = Vec::with_capacity(self.runs.len());
+        for run in &self.runs {
+            match &run.result {
+                Ok(r) => match r.traj.find(event, almanac.clone()) {
+                    Ok(events) => match events.get(occurrence) {
+                        Some(details) => states.push(details.state),
+                        None => warn!(
+                            "run #{}: only {} occurrences of {event}, skipping in statistics",
+                            run.index,
+                            events.len()
+                        ),
+                    },
+                    Err(e) => warn!("run #{}: {e}, skipping in statistics", run.index),
+                },
+                Err(e) => warn!("run #{} failed with {e}, skipping in statistics", run.index),
+            }
+        }
+
+        ensure!(
+            !states.is_empty(),
+            NoSuccessfulRunsSnafu {
+                action: "compute statistics",
+                num_runs: self.runs.len()
+            }
+        );
+
+        EnsembleStatistics::from_states(format!("{event} #{occurrence}"), &states, cfg)
+    }
+}
+
+#[cfg(test)]
+mod ut_statistics {
+    use super::*;
+    use crate::cosmic::Orbit;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::mc::{MonteCarlo, MvnSpacecraft, StateDispersion};
+    use crate::md::Event;
+    use crate::propagators::Propagator;
+    use crate::time::Unit;
+    use crate::Spacecraft;
+
+    #[test]
+    fn statistics_of_states() {
+        let eme2k = fixtures::eme2k();
+        let epoch = fixtures::epoch();
+        // SMA from 7000 to 7100 km
+        let states = (0..=100)
+            .map(|i| {
+                Spacecraft::from(Orbit::keplerian(
+                    7_000.0 + i as f64,
+                    0.01,
+                    28.5,
+                    0.0,
+                    0.0,
+                    0.0,
+                    epoch + Unit::Second * i,
+                    eme2k,
+                ))
+            })
+            .collect::<Vec<Spacecraft>>();
+
+        let cfg = StatisticsCfg::builder()
+            .params(vec![StateParameter::SMA, StateParameter::Eccentricity])
+            .histogram_bins(10)
+            .build();
+        let stats = EnsembleStatistics::from_states("test".to_string(), &states, &cfg).unwrap();
+        println!("{stats}");
+
+        assert_eq!(stats.num_samples, 101);
+        assert_eq!(stats.first_epoch, epoch);
+        assert_eq!(stats.last_epoch, epoch + Unit::Second * 100);
+
+        let sma = stats.param(StateParameter::SMA).unwrap();
+        assert!((sma.mean - 7_050.0).abs() < 1e-9);
+        assert!((sma.min - 7_000.0).abs() < 1e-9);
+        assert!((sma.max - 7_100.0).abs() < 1e-9);
+        // Variance of 0..=100 with n - 1 degrees of freedom
+        assert!((sma.std_dev - (101.0 * 102.0 / 12.0_f64).sqrt()).abs() < 1e-6);
+        assert!((sma.percentile(50.0).unwrap() - 7_050.0).abs() < 1e-9);
+        assert!((sma.percentile(5.0).unwrap() - 7_005.0).abs() < 1e-9);
+        assert_eq!(sma.percentile(42.0), None);
+        assert_eq!(sma.histogram.counts.len(), 10);
+        assert_eq!(sma.histogram.counts.iter().sum::<u64>(), 101);
+        assert_eq!(sma.histogram.counts[9], 11);
+
+        // The eccentricity is constant, down to numerical noise
+        let ecc = stats.param(StateParameter::Eccentricity).unwrap();
+        assert!(ecc.std_dev < 1e-12);
+        assert_eq!(ecc.histogram.counts.iter().sum::<u64>(), 101);
+        assert!(
+            stats
+                .covariance_of(StateParameter::SMA, StateParameter::Eccentricity)
+                .unwrap()
+                .abs()
+                < 1e-9
+        );
+
+        let invalid = StatisticsCfg::builder()
+            .params(vec![StateParameter::SMA])
+            .percentiles(vec![101.0])
+            .build();
+        assert!(EnsembleStatistics::from_states("test".to_string(), &states, &invalid).is_err());
+        assert!(
+            EnsembleStatistics::from_states::<Spacecraft>("test".to_string(), &[], &cfg).is_err()
+        );
+    }
+
+    #[test]
+    fn statistics_of_monte_carlo() {
+        let epoch = fixtures::epoch();
+        let nominal = Spacecraft::from(fixtures::keplerian(7_000.0, 0.01, 28.5, 0.0, 0.0, 0.0));
+        let generator = MvnSpacecraft::new(
+            nominal,
+            vec![StateDispersion::zero_mean(StateParameter::SMA, 5.0)],
+        )
+        .unwrap();
+
+        let almanac = fixtures::almanac();
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let results = MonteCarlo::new(nominal, generator, "stats".to_string(), Some(0))
+            .run_until_epoch(prop, almanac.clone(), epoch + Unit::Hour * 3, 50);
+
+        let cfg = StatisticsCfg::builder()
+            .params(vec![StateParameter::SMA, StateParameter::Rmag])
+            .build();
+
+        let mut every = results.statistics_every(Unit::Hour * 1, &cfg).unwrap();
+        assert_eq!(every.len(), 4);
+        // The SMA is constant in two body dynamics
+        let initial = every[0].param(StateParameter::SMA).unwrap();
+        let last = every[3].param(StateParameter::SMA).unwrap();
+        assert!((initial.mean - 7_000.0).abs() < 2.0);
+        assert!((initial.std_dev - 5.0).abs() < 1.5);
+        assert!((initial.mean - last.mean).abs() < 1e-6);
+        assert!((initial.std_dev - last.std_dev).abs() < 1e-6);
+
+        // The orbital periods differ, so the apoapses occur at different epochs
+        let apoapses = results
+            .statistics_at_event(&Event::apoapsis(), 0, &cfg, almanac)
+            .unwrap();
+        println!("{apoapses}");
+        assert_eq!(apoapses.num_samples, 50);
+        assert!(apoapses.first_epoch < apoapses.last_epoch);
+        let rmag = apoapses.param(StateParameter::Rmag).unwrap();
+        assert!((rmag.mean - 7_000.0 * 1.01).abs() < 5.0);
+
+        let path = std::env::temp_dir().join("nyx_ut_mc_statistics.parquet");
+        every.push(apoapses);
+        assert_eq!(statistics_to_parquet(&every, &path).unwrap(), path);
+    }
+}
@@ -0,0 +1,464 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::results::{PropResult, Results};
+use super::{
+    stream_rng, DispersedState, MonteCarlo, ParameterDistribution, Pcg64Mcg, StateGenerator,
+};
+use crate::dynamics::Dynamics;
+use crate::io::watermark::pq_writer;
+use crate::linalg::allocator::Allocator;
+use crate::linalg::DefaultAllocator;
+use crate::md::trajectory::Interpolatable;
+use crate::md::StateParameter;
+use crate::propagators::Propagator;
+use crate::time::{Duration, Epoch, TimeSeries, Unit};
+use crate::{NyxError, State};
+use anise::almanac::Almanac;
+use arrow::array::{Array, Float64Builder, StringBuilder, UInt64Builder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use rand::Rng;
+use rand_distr::Distribution;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Retargeting hook of an epoch sweep: called with the nominal state moved to each swept initial epoch, it returns the
+/// initial state for that epoch, e.g. the injection state of a transfer re-solved for this launch date.
+pub type RetargetFn<S> = dyn Fn(S) -> Result<S, NyxError> + Send + Sync;
+
+/// Initial epochs of an epoch sweep, e.g. the launch dates of a launch period.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum EpochSweep {
+    /// Evenly spaced epochs from the start to the end epoch, both included
+    Grid {
+        start: Epoch,
+        end: Epoch,
+        step: Duration,
+    },
+    /// Random slips of the nominal epoch, in seconds
+    Slip {
+        distribution: ParameterDistribution,
+        num_epochs: usize,
+    },
+}
+
+impl EpochSweep {
+    /// Returns the initial epochs of this sweep, drawing the slips from the provided random number generator.
+    pub fn epochs<R: Rng>(&self, nominal: Epoch, rng: &mut R) -> Result<Vec<Epoch>, NyxError> {
+        match *self {
+            Self::Grid { start, end, step } => {
+                if end < start || step <= Duration::ZERO {
+                    return Err(NyxError::CustomError {
+                        msg: format!("invalid epoch sweep grid from {start} to {end} by {step}"),
+                    });
+                }
+                Ok(TimeSeries::inclusive(start, end, step).collect())
+            }
+            Self::Slip {
+                distribution,
+                num_epochs,
+            } => {
+                distribution.validate()?;
+                Ok((0..num_epochs)
+                    .map(|_| nominal + distribution.sample(rng) * Unit::Second)
+                    .collect())
+            }
+        }
+    }
+}
+
+impl<S: Interpolatable, Distr: StateGenerator<S>> MonteCarlo<S, Distr>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+    <DefaultAllocator as Allocator<S::VecLength>>::Buffer<f64>: Send,
+{
+    /// Sweeps the initial epoch of this Monte Carlo, and propagates each run for the provided duration.
+    ///
+    /// At each initial epoch, the nominal state is moved to that epoch and retargeted with the hook, if any. Then,
+    /// `samples_per_epoch` runs are dispersed around the retargeted state by applying the dispersions of this Monte
+    /// Carlo's generator, or only the retargeted state is propagated if `samples_per_epoch` is zero. The slip of each
+    /// run is recorded in its dispersions as the [StateParameter::Epoch], in seconds.
+    #[must_use = "Epoch sweep result must be used"]
+    pub fn run_epoch_sweep_for<D>(
+        &self,
+        prop: Propagator<D>,
+        almanac: Arc<Almanac>,
+        sweep: &EpochSweep,
+        retarget: Option<&RetargetFn<S>>,
+        samples_per_epoch: usize,
+        duration: Duration,
+    ) -> Result<EpochSweepResults<S>, NyxError>
+    where
+        D: Dynamics<StateType = S>,
+        DefaultAllocator: Allocator<<D::StateType as State>::Size>
+            + Allocator<<D::StateType as State>::Size, <D::StateType as State>::Size>
+            + Allocator<<D::StateType as State>::VecLength>,
+    {
+        let nominal_epoch = self.nominal_state.epoch();
+        // Draw the slips from a seed of their own, so they are independent of the dispersed states.
+        let mut rng = match self.seed {
+            Some(seed) => Pcg64Mcg::new(Pcg64Mcg::new(seed).gen()),
+            None => stream_rng(&format!("sweep/{}", self.scenario)),
+        };
+        let epochs = sweep.epochs(nominal_epoch, &mut rng)?;
+
+        let runs_per_epoch = samples_per_epoch.max(1);
+        let samples = if samples_per_epoch > 0 {
+            self.generate_states(0, epochs.len() * samples_per_epoch, self.seed)
+        } else {
+            Vec::new()
+        };
+
+        let mut retarget_failures = Vec::new();
+        let mut init_states = Vec::with_capacity(epochs.len() * runs_per_epoch);
+        for (epoch_idx, epoch) in epochs.iter().copied().enumerate() {
+            let mut nominal = self.nominal_state;
+            nominal.set_epoch(epoch);
+            let nominal = match retarget {
+                Some(retarget) => match retarget(nominal) {
+                    Ok(state) => state,
+                    Err(e) => {
+                        warn!("retargeting failed @ {epoch}: {e}");
+                        retarget_failures.push((epoch, e.to_string()));
+                        continue;
+                    }
+                },
+                None => nominal,
+            };
+            let slip = (StateParameter::Epoch, (epoch - nominal_epoch).to_seconds());
+
+            if samples_per_epoch == 0 {
+                init_states.push((
+                    epoch_idx,
+                    DispersedState {
+                        state: nominal,
+                        actual_dispersions: vec![slip],
+                    },
+                ));
+                continue;
+            }
+
+            for (index, sample) in &samples[epoch_idx * samples_per_epoch..][..samples_per_epoch] {
+                let mut state = nominal;
+                let applied = sample
+                    .actual_dispersions
+                    .iter()
+                    .try_for_each(|(param, delta)| {
+                        let value = state.value(*param)?;
+                        state.set_value(*param, value + delta)
+                    });
+                match applied {
+                    Ok(()) => {
+                        let mut actual_dispersions = vec![slip];
+                        actual_dispersions.extend(sample.actual_dispersions.iter().copied());
+                        init_states.push((
+                            *index,
+                            DispersedState {
+                                state,
+                                actual_dispersions,
+                            },
+                        ));
+                    }
+                    Err(e) => warn!("could not disperse run #{index} @ {epoch}: {e}"),
+                }
+            }
+        }
+
+        let results = self.propagate_states(prop, init_states, None, |prop, state| {
+            prop.with(state, almanac.clone())
+                .quiet()
+                .for_duration_with_traj(duration)
+        });
+
+        Ok(EpochSweepResults {
+            epochs,
+            runs_per_epoch,
+            retarget_failures,
+            results,
+        })
+    }
+}
+
+/// Envelope of a parameter of the final states of the runs of an initial epoch of a sweep.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EpochEnvelope {
+    pub epoch: Epoch,
+    pub param: StateParameter,
+    /// Number of runs of this epoch whose final state provides this parameter
+    pub num_samples: usize,
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+}
+
+/// Results of an epoch sweep, e.g. over the launch dates of a launch period.
+pub struct EpochSweepResults<S: Interpolatable>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+    <DefaultAllocator as Allocator<S::VecLength>>::Buffer<f64>: Send,
+{
+    /// Swept initial epochs, in the order of the runs
+    pub epochs: Vec<Epoch>,
+    /// Number of runs of each initial epoch, such that the runs of the i-th epoch have indexes from `i * runs_per_epoch`
+    pub runs_per_epoch: usize,
+    /// Initial epochs where the retargeting failed, with the error, whose runs were not propagated
+    pub retarget_failures: Vec<(Epoch, String)>,
+    pub results: Results<S, PropResult<S>>,
+}
+
+impl<S: Interpolatable> EpochSweepResults<S>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+    <DefaultAllocator as Allocator<S::VecLength>>::Buffer<f64>: Send,
+{
+    /// Returns the final states of the successful runs of the i-th initial epoch
+    pub fn final_states(&self, epoch_idx: usize) -> Vec<S> {
+        let indexes = epoch_idx * self.runs_per_epoch..(epoch_idx + 1) * self.runs_per_epoch;
+        self.results
+            .runs
+            .iter()
+            .filter(|run| indexes.contains(&run.index))
+            .filter_map(|run| run.result.as_ref().ok().map(|r| r.state))
+            .collect()
+    }
+
+    /// Returns the envelope of this parameter of the final states for each initial epoch where at least one run succeeded,
+    /// i.e. the performance envelope over the launch period.
+    pub fn envelope(&self, param: StateParameter) -> Vec<EpochEnvelope> {
+        self.epochs
+            .iter()
+            .enumerate()
+            .filter_map(|(epoch_idx, epoch)| {
+                let values = self
+                    .final_states(epoch_idx)
+                    .iter()
+                    .filter_map(|state| state.value(param).ok())
+                    .collect::<Vec<f64>>();
+                if values.is_empty() {
+                    return None;
+                }
+                Some(EpochEnvelope {
+                    epoch: *epoch,
+                    param,
+                    num_samples: values.len(),
+                    min: values.iter().copied().fold(f64::INFINITY, f64::min),
+                    mean: values.iter().sum::<f64>() / values.len() as f64,
+                    max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                })
+            })
+            .collect()
+    }
+
+    /// Exports the envelopes of the provided parameters to a parquet file, with one row per initial epoch and parameter.
+    pub fn envelope_to_parquet<P: AsRef<Path>>(
+        &self,
+        path: P,
+        params: &[StateParameter],
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let path_buf = path.as_ref().to_path_buf();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("Epoch (UTC)", DataType::Utf8, false),
+            Field::new("Parameter", DataType::Utf8, false),
+            Field::new("Unit", DataType::Utf8, false),
+            Field::new("Samples", DataType::UInt64, false),
+            Field::new("Min", DataType::Float64, false),
+            Field::new("Mean", DataType::Float64, false),
+            Field::new("Max", DataType::Float64, false),
+        ]));
+
+        let mut epoch = StringBuilder::new();
+        let mut param_col = StringBuilder::new();
+        let mut unit = StringBuilder::new();
+        let mut samples = UInt64Builder::new();
+        let mut min = Float64Builder::new();
+        let mut mean = Float64Builder::new();
+        let mut max = Float64Builder::new();
+        for param in params {
+            for env in self.envelope(*param) {
+                epoch.append_value(format!("{}", env.epoch));
+                param_col.append_value(format!("{param}"));
+                unit.append_value(param.unit());
+                samples.append_value(env.num_samples as u64);
+                min.append_value(env.min);
+                mean.append_value(env.mean);
+                max.append_value(env.max);
+            }
+        }
+
+        let record: Vec<Arc<dyn Array>> = vec![
+            Arc::new(epoch.finish()),
+            Arc::new(param_col.finish()),
+            Arc::new(unit.finish()),
+            Arc::new(samples.finish()),
+            Arc::new(min.finish()),
+            Arc::new(mean.finish()),
+            Arc::new(max.finish()),
+        ];
+
+        let mut metadata = HashMap::new();
+        metadata.insert("Purpose".to_string(), "Epoch sweep envelope".to_string());
+        metadata.insert("Scenario".to_string(), self.results.scenario.clone());
+
+        let file = File::create(&path_buf)?;
+        let mut writer = ArrowWriter::try_new(file, schema.clone(), pq_writer(Some(metadata)))?;
+        writer.write(&RecordBatch::try_new(schema, record)?)?;
+        writer.close()?;
+
+        info!("Epoch sweep envelope written to {}", path_buf.display());
+
+        Ok(path_buf)
+    }
+}
+
+impl<S: Interpolatable> fmt::Display for EpochSweepResults<S>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+    <DefaultAllocator as Allocator<S::VecLength>>::Buffer<f64>: Send,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - {} epochs ({} retargeting failures) - {} runs, {} succeeded",
+            self.results.scenario,
+            self.epochs.len(),
+            self.retarget_failures.len(),
+            self.results.runs.len(),
+            self.results
+                .runs
+                .iter()
+                .filter(|run| run.result.is_ok())
+                .count()
+        )
+    }
+}
+
+#[cfg(test)]
+mod ut_sweep {
+    use super::*;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::mc::{MvnSpacecraft, StateDispersion};
+    use crate::Spacecraft;
+
+    #[test]
+    fn launch_period_sweep() {
+        let epoch = fixtures::epoch();
+        let nominal = Spacecraft::from(fixtures::keplerian(7_000.0, 0.01, 28.5, 0.0, 0.0, 0.0));
+        let generator = MvnSpacecraft::new(
+            nominal,
+            vec![StateDispersion::zero_mean(StateParameter::SMA, 5.0)],
+        )
+        .unwrap();
+        let almanac = fixtures::almanac();
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let mc = MonteCarlo::new(nominal, generator, "launch period".to_string(), Some(0));
+
+        // Retarget the SMA with the launch slip, and fail past two days of slip
+        let retarget = move |mut state: Spacecraft| -> Result<Spacecraft, NyxError> {
+            let slip_days = (state.epoch() - epoch).to_unit(Unit::Day);
+            if slip_days > 2.0 {
+                return Err(NyxError::CustomError {
+                    msg: "launch period closed".to_string(),
+                });
+            }
+            state
+                .set_value(StateParameter::SMA, 7_000.0 + 100.0 * slip_days)
+                .map_err(|e| NyxError::CustomError { msg: e.to_string() })?;
+            Ok(state)
+        };
+
+        let sweep = EpochSweep::Grid {
+            start: epoch,
+            end: epoch + Unit::Day * 3,
+            step: Unit::Day * 1,
+        };
+        let results = mc
+            .run_epoch_sweep_for(
+                prop.clone(),
+                almanac.clone(),
+                &sweep,
+                Some(&retarget),
+                10,
+                Unit::Minute * 30,
+            )
+            .unwrap();
+        println!("{results}");
+        assert_eq!(results.epochs.len(), 4);
+        assert_eq!(results.retarget_failures.len(), 1);
+        assert_eq!(results.retarget_failures[0].0, epoch + Unit::Day * 3);
+        assert_eq!(results.results.runs.len(), 30);
+
+        // Each run starts at its epoch, and records its slip
+        for run in &results.results.runs {
+            let epoch_idx = run.index / results.runs_per_epoch;
+            assert_eq!(run.dispersed_state.state.epoch(), results.epochs[epoch_idx]);
+            assert_eq!(
+                run.dispersed_state.actual_dispersions[0],
+                (StateParameter::Epoch, epoch_idx as f64 * 86_400.0)
+            );
+        }
+
+        // The envelope follows the retargeted SMA, dispersed around it
+        let envelope = results.envelope(StateParameter::SMA);
+        assert_eq!(envelope.len(), 3);
+        for (epoch_idx, env) in envelope.iter().enumerate() {
+            println!("{env:?}");
+            assert_eq!(env.num_samples, 10);
+            let sma = 7_000.0 + 100.0 * epoch_idx as f64;
+            assert!(env.min < env.mean && env.mean < env.max);
+            assert!((env.mean - sma).abs() < 10.0);
+            assert!(env.max - env.min < 50.0);
+        }
+
+        let path = std::env::temp_dir().join("nyx_ut_epoch_sweep.parquet");
+        assert_eq!(
+            results
+                .envelope_to_parquet(&path, &[StateParameter::SMA, StateParameter::Rmag])
+                .unwrap(),
+            path
+        );
+
+        // Random launch slips without dispersions propagate the nominal state at each epoch
+        let sweep = EpochSweep::Slip {
+            distribution: ParameterDistribution::Uniform {
+                min: 0.0,
+                max: 3_600.0,
+            },
+            num_epochs: 5,
+        };
+        let slips = mc
+            .run_epoch_sweep_for(prop, almanac, &sweep, None, 0, Unit::Minute * 30)
+            .unwrap();
+        assert_eq!(slips.results.runs.len(), 5);
+        assert_eq!(slips.runs_per_epoch, 1);
+        for (epoch_idx, slipped) in slips.epochs.iter().enumerate() {
+            assert!(*slipped >= epoch && *slipped <= epoch + Unit::Hour * 1);
+            let finals = slips.final_states(epoch_idx);
+            assert_eq!(finals.len(), 1);
+            assert_eq!(finals[0].epoch(), *slipped + Unit::Minute * 30);
+        }
+    }
+}
@@ -0,0 +1,377 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{stream_rng, DispersedState, MonteCarlo, Pcg64Mcg, StateGenerator};
+use crate::dynamics::Dynamics;
+use crate::errors::{MonteCarloError, NyxError};
+use crate::linalg::allocator::Allocator;
+use crate::linalg::DefaultAllocator;
+use crate::md::trajectory::Interpolatable;
+use crate::propagators::Propagator;
+use crate::time::Epoch;
+use crate::State;
+use anise::almanac::Almanac;
+use log::{info, warn};
+use rayon::prelude::*;
+use std::cell::Cell;
+use std::fmt;
+use std::sync::Arc;
+use typed_builder::TypedBuilder;
+
+/// Configuration of the search of the worst case dispersions of a Monte Carlo.
+///
+/// The search space is the box of the unit hypercube of the generator of dispersed states where each independent variable is
+/// within the confidence bounds, e.g. within 3σ on each independent variable of a multivariate normal generator with the default confidence.
+/// The search starts from the worst of the initial samples of the Monte Carlo sampling strategy, and refines it with the
+/// Nelder-Mead simplex algorithm.
+#[derive(Clone, Debug, TypedBuilder)]
+#[builder(doc)]
+pub struct WorstCaseSearch {
+    /// Probability of each independent variable to be within the bounds of the search, between 0.0 and 1.0
+    #[builder(default = 0.9973)]
+    pub confidence: f64,
+    /// Number of samples propagated to initialize the search
+    #[builder(default = 50)]
+    pub initial_samples: usize,
+    /// Maximum number of propagations of the Nelder-Mead search, excluding the initial samples
+    #[builder(default = 300)]
+    pub max_evaluations: usize,
+    /// The search stops once the metric varies by less than this tolerance over the simplex, in the unit of the metric
+    #[builder(default = 1e-6)]
+    pub tolerance: f64,
+    /// Size of the initial simplex, as a fraction of the searched box
+    #[builder(default = 0.25)]
+    pub initial_step: f64,
+}
+
+/// The worst case dispersions found by a [WorstCaseSearch].
+#[derive(Clone)]
+pub struct WorstCase<S: Interpolatable>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    /// Point of the unit hypercube of the generator of the worst case
+    pub point: Vec<f64>,
+    pub dispersed_state: DispersedState<S>,
+    /// Propagated state of the worst case
+    pub final_state: S,
+    /// Value of the metric of the worst case
+    pub metric: f64,
+    /// Worst value of the metric over the initial samples, i.e. from the random sampling alone
+    pub sampled_metric: f64,
+    /// Number of propagations, including the initial samples
+    pub evaluations: usize,
+    /// Worst value of the metric after each iteration of the Nelder-Mead search
+    pub history: Vec<f64>,
+}
+
+impl<S: Interpolatable> fmt::Display for WorstCase<S>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "worst case metric of {:.6} (sampled {:.6}) after {} evaluations with dispersions",
+            self.metric, self.sampled_metric, self.evaluations
+        )?;
+        for (param, value) in &self.dispersed_state.actual_dispersions {
+            write!(f, " {param} = {value:.6}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Propagation of one point of the searched box
+struct Evaluation<S: Interpolatable>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    point: Vec<f64>,
+    dispersed_state: DispersedState<S>,
+    final_state: S,
+    metric: f64,
+}
+
+impl<S: Interpolatable, Distr: StateGenerator<S>> MonteCarlo<S, Distr>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    /// Searches the dispersions which maximize the metric of the state propagated until the specified epoch, complementing
+    /// the random sampling of the Monte Carlo for requirement verification. Propagation failures are never the worst case.
+    ///
+    /// This requires a generator which maps the unit hypercube to dispersed states (cf. [StateGenerator]).
+    pub fn find_worst_case_until_epoch<D, M>(
+        &self,
+        prop: Propagator<D>,
+        almanac: Arc<Almanac>,
+        end_epoch: Epoch,
+        metric: M,
+        search: &WorstCaseSearch,
+    ) -> Result<WorstCase<S>, MonteCarloError>
+    where
+        D: Dynamics<StateType = S>,
+        M: Fn(&S) -> Result<f64, NyxError> + Sync,
+        DefaultAllocator: Allocator<<D::StateType as State>::Size>
+            + Allocator<<D::StateType as State>::Size, <D::StateType as State>::Size>
+            + Allocator<<D::StateType as State>::VecLength>,
+        <DefaultAllocator as Allocator<<D::StateType as State>::VecLength>>::Buffer<f64>: Send,
+    {
+        let dimension = self.random_state.unit_hypercube_dimension().ok_or(
+            MonteCarloError::WorstCaseSearch {
+                msg: "the generator does not support sampling from the unit hypercube".to_string(),
+            },
+        )?;
+        if !(search.confidence > 0.0 && search.confidence < 1.0) {
+            return Err(MonteCarloError::WorstCaseSearch {
+                msg: format!("confidence must be in (0, 1) but got {}", search.confidence),
+            });
+        }
+
+        // Maps the searched box, normalized to [0, 1] on each dimension, to the unit hypercube of the generator
+        let low = (1.0 - search.confidence) / 2.0;
+        let disperse = |x: &[f64]| -> Option<(Vec<f64>, DispersedState<S>)> {
+            let point = x
+                .iter()
+                .map(|xi| low + xi.clamp(0.0, 1.0) * search.confidence)
+                .collect::<Vec<f64>>();
+            let dispersed_state = self.random_state.sample_unit_hypercube(&point)?;
+            Some((point, dispersed_state))
+        };
+        let propagate =
+            |(point, dispersed_state): (Vec<f64>, DispersedState<S>)| -> Option<Evaluation<S>> {
+                let final_state = match prop
+                    .with(dispersed_state.state, almanac.clone())
+                    .quiet()
+                    .until_epoch(end_epoch)
+                {
+                    Ok(state) => state,
+                    Err(e) => {
+                        warn!("worst case search: propagation failed: {e}");
+                        return None;
+                    }
+                };
+                match metric(&final_state) {
+                    Ok(metric) if metric.is_finite() => Some(Evaluation {
+                        point,
+                        dispersed_state,
+                        final_state,
+                        metric,
+                    }),
+                    Ok(metric) => {
+                        warn!("worst case search: ignoring metric of {metric}");
+                        None
+                    }
+                    Err(e) => {
+                        warn!("worst case search: metric failed: {e}");
+                        None
+                    }
+                }
+            };
+
+        // Initialize the search from the worst of the samples
+        let mut rng = match self.seed {
+            Some(seed) => Pcg64Mcg::new(seed),
+            None => stream_rng(&format!("worst_case/{}", self.scenario)),
+        };
+        let samples =
+            self.sampling
+                .unit_hypercube(dimension, search.initial_samples.max(1), &mut rng);
+        let evaluations = Cell::new(samples.len());
+        let mut worst = samples
+            .iter()
+            .filter_map(|x| disperse(x).map(|dispersed| (x.clone(), dispersed)))
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .filter_map(|(x, dispersed)| propagate(dispersed).map(|eval| (x, eval)))
+            .max_by(|(_, a), (_, b)| a.metric.total_cmp(&b.metric))
+            .ok_or(MonteCarloError::WorstCaseSearch {
+                msg: format!(
+                    "none of the {} initial samples could be evaluated",
+                    samples.len()
+                ),
+            })?;
+        let sampled_metric = worst.1.metric;
+        info!("worst case search: worst sampled metric of {sampled_metric:.6}");
+
+        // Nelder-Mead simplex minimizing the opposite of the metric, with the standard coefficients
+        let max_evaluations = evaluations.get() + search.max_evaluations;
+        let simplex_eval = |x: Vec<f64>, worst: &mut (Vec<f64>, Evaluation<S>)| {
+            let x = x
+                .into_iter()
+                .map(|xi| xi.clamp(0.0, 1.0))
+                .collect::<Vec<f64>>();
+            evaluations.set(evaluations.get() + 1);
+            match disperse(&x).and_then(propagate) {
+                Some(eval) => {
+                    let cost = -eval.metric;
+                    if eval.metric > worst.1.metric {
+                        *worst = (x.clone(), eval);
+                    }
+                    (x, cost)
+                }
+                None => (x, f64::INFINITY),
+            }
+        };
+
+        let start = worst.0.clone();
+        let mut simplex = vec![(start.clone(), -sampled_metric)];
+        for i in 0..dimension {
+            let mut vertex = start.clone();
+            vertex[i] += if vertex[i] + search.initial_step <= 1.0 {
+                search.initial_step
+            } else {
+                -search.initial_step
+            };
+            simplex.push(simplex_eval(vertex, &mut worst));
+        }
+
+        let mut history = Vec::new();
+        while evaluations.get() < max_evaluations {
+            simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+            history.push(worst.1.metric);
+            let (best_cost, worst_cost) = (simplex[0].1, simplex[dimension].1);
+            if (worst_cost - best_cost).abs() <= search.tolerance {
+                break;
+            }
+
+            let centroid = (0..dimension)
+                .map(|j| {
+                    simplex[..dimension].iter().map(|v| v.0[j]).sum::<f64>() / dimension as f64
+                })
+                .collect::<Vec<f64>>();
+            let along = |coeff: f64, vertex: &[f64]| -> Vec<f64> {
+                centroid
+                    .iter()
+                    .zip(vertex)
+                    .map(|(c, v)| c + coeff * (v - c))
+                    .collect()
+            };
+
+            let reflected = simplex_eval(along(-1.0, &simplex[dimension].0), &mut worst);
+            if reflected.1 < best_cost {
+                let expanded = simplex_eval(along(-2.0, &simplex[dimension].0), &mut worst);
+                simplex[dimension] = if expanded.1 < reflected.1 {
+                    expanded
+                } else {
+                    reflected
+                };
+            } else if reflected.1 < simplex[dimension - 1].1 {
+                simplex[dimension] = reflected;
+            } else {
+                let contracted = if reflected.1 < worst_cost {
+                    simplex_eval(along(-0.5, &simplex[dimension].0), &mut worst)
+                } else {
+                    simplex_eval(along(0.5, &simplex[dimension].0), &mut worst)
+                };
+                if contracted.1 < reflected.1.min(worst_cost) {
+                    simplex[dimension] = contracted;
+                } else {
+                    // Shrink toward the best vertex
+                    let best = simplex[0].0.clone();
+                    for vertex in simplex.iter_mut().skip(1) {
+                        let shrunk = best
+                            .iter()
+                            .zip(&vertex.0)
+                            .map(|(b, v)| b + 0.5 * (v - b))
+                            .collect();
+                        *vertex = simplex_eval(shrunk, &mut worst);
+                    }
+                }
+            }
+        }
+
+        let (_, eval) = worst;
+        info!(
+            "worst case search: metric of {:.6} after {} evaluations",
+            eval.metric,
+            evaluations.get()
+        );
+
+        Ok(WorstCase {
+            point: eval.point,
+            dispersed_state: eval.dispersed_state,
+            final_state: eval.final_state,
+            metric: eval.metric,
+            sampled_metric,
+            evaluations: evaluations.get(),
+            history,
+        })
+    }
+}
+
+#[cfg(test)]
+mod ut_worst_case {
+    use super::*;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::mc::{MvnSpacecraft, StateDispersion};
+    use crate::md::StateParameter;
+    use crate::time::Unit;
+    use crate::Spacecraft;
+
+    #[test]
+    fn worst_case_sma() {
+        let epoch = fixtures::epoch();
+        let nominal = Spacecraft::from(fixtures::keplerian(7_000.0, 0.01, 28.5, 0.0, 0.0, 0.0));
+        let generator = MvnSpacecraft::new(
+            nominal,
+            vec![
+                StateDispersion::zero_mean(StateParameter::SMA, 5.0),
+                StateDispersion::zero_mean(StateParameter::Inclination, 0.1),
+            ],
+        )
+        .unwrap();
+        let almanac = fixtures::almanac();
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let mc = MonteCarlo::new(nominal, generator, "worst case".to_string(), Some(0));
+
+        // The SMA is constant in two body dynamics, so its worst case is at a corner of the searched box, which is at least
+        // the 3σ bound of its dispersion since the SMA combines several of the independent Cartesian variables.
+        let worst = mc
+            .find_worst_case_until_epoch(
+                prop,
+                almanac,
+                epoch + Unit::Minute * 10,
+                |state: &Spacecraft| {
+                    state
+                        .value(StateParameter::SMA)
+                        .map_err(|e| NyxError::CustomError { msg: e.to_string() })
+                },
+                &WorstCaseSearch::builder().build(),
+            )
+            .unwrap();
+        println!("{worst}");
+
+        let three_sigma = 5.0 * crate::mc::std_normal_quantile(0.5 + 0.9973 / 2.0);
+        assert!(worst.metric >= worst.sampled_metric);
+        assert!(
+            worst.metric >= 7_000.0 + three_sigma,
+            "{} < {}",
+            worst.metric,
+            7_000.0 + three_sigma
+        );
+        assert!(worst.metric < 7_000.0 + 2.0 * three_sigma);
+        assert!(worst
+            .point
+            .iter()
+            .all(|u| (0.00135 - 1e-9..=0.99865 + 1e-9).contains(u)));
+        assert!(worst.evaluations <= 50 + 300 + 9);
+        assert!(worst.history.windows(2).all(|w| w[1] >= w[0]));
+    }
+}
@@ -0,0 +1,262 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::EventEvaluator;
+use crate::errors::EventError;
+use crate::linalg::allocator::Allocator;
+use crate::linalg::DefaultAllocator;
+use crate::time::Duration;
+use crate::State;
+use anise::almanac::Almanac;
+use std::fmt;
+use std::ops::Not;
+use std::sync::Arc;
+
+/// A boolean combination of events, where each event is true while its evaluation is positive.
+///
+/// A conjunction evaluates to the smallest of its events and a disjunction to the largest, while a negation flips the
+/// sign of its event. The composite is therefore continuous and positive exactly while the condition holds, so that its
+/// arcs (cf. `Traj::find_arcs`) are the periods where the condition holds, e.g. "in view of the station AND NOT in eclipse
+/// AND NOT above 1000 km" is
+///
+/// `CompositeEvent::event(station).and(!CompositeEvent::event(umbra)).and(!CompositeEvent::event(Event::new(StateParameter::Altitude, 1000.0)))`.
+///
+/// The events may have different units: only the sign of the composite matters, but its epoch and value precisions are
+/// the finest of its events.
+#[derive(Clone)]
+pub enum CompositeEvent<S: State>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    /// True while this event is positive
+    Event(Arc<dyn EventEvaluator<S>>),
+    /// True while all of these conditions are true
+    All(Vec<CompositeEvent<S>>),
+    /// True while any of these conditions is true
+    Any(Vec<CompositeEvent<S>>),
+    /// True while this condition is false
+    Not(Box<CompositeEvent<S>>),
+}
+
+impl<S: State> CompositeEvent<S>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    /// Condition which is true while the provided event is positive
+    pub fn event<E: EventEvaluator<S> + 'static>(event: E) -> Self {
+        Self::Event(Arc::new(event))
+    }
+
+    /// Condition which is true while both this condition and the other one are true
+    pub fn and(self, other: Self) -> Self {
+        match self {
+            Self::All(mut conditions) => {
+                conditions.push(other);
+                Self::All(conditions)
+            }
+            _ => Self::All(vec![self, other]),
+        }
+    }
+
+    /// Condition which is true while either this condition or the other one is true
+    pub fn or(self, other: Self) -> Self {
+        match self {
+            Self::Any(mut conditions) => {
+                conditions.push(other);
+                Self::Any(conditions)
+            }
+            _ => Self::Any(vec![self, other]),
+        }
+    }
+
+    /// Returns all of the events of this condition
+    fn leaves(&self) -> Vec<&Arc<dyn EventEvaluator<S>>> {
+        match self {
+            Self::Event(event) => vec![event],
+            Self::All(conditions) | Self::Any(conditions) => {
+                conditions.iter().flat_map(|c| c.leaves()).collect()
+            }
+            Self::Not(condition) => condition.leaves(),
+        }
+    }
+}
+
+impl<S: State> Not for CompositeEvent<S>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    type Output = Self;
+
+    /// Condition which is true while this condition is false
+    fn not(self) -> Self {
+        match self {
+            Self::Not(condition) => *condition,
+            _ => Self::Not(Box::new(self)),
+        }
+    }
+}
+
+impl<S: State> fmt::Display for CompositeEvent<S>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let join = |conditions: &[CompositeEvent<S>], op: &str| {
+            conditions
+                .iter()
+                .map(|c| format!("{c}"))
+                .collect::<Vec<String>>()
+                .join(op)
+        };
+        match self {
+            Self::Event(event) => write!(f, "{event}"),
+            Self::All(conditions) => write!(f, "({})", join(conditions, " AND ")),
+            Self::Any(conditions) => write!(f, "({})", join(conditions, " OR ")),
+            Self::Not(condition) => write!(f, "NOT {condition}"),
+        }
+    }
+}
+
+impl<S: State> EventEvaluator<S> for CompositeEvent<S>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    fn eval(&self, state: &S, almanac: Arc<Almanac>) -> Result<f64, EventError> {
+        match self {
+            Self::Event(event) => event.eval(state, almanac),
+            Self::All(conditions) => conditions.iter().try_fold(f64::INFINITY, |min, c| {
+                Ok(min.min(c.eval(state, almanac.clone())?))
+            }),
+            Self::Any(conditions) => conditions.iter().try_fold(f64::NEG_INFINITY, |max, c| {
+                Ok(max.max(c.eval(state, almanac.clone())?))
+            }),
+            Self::Not(condition) => Ok(-condition.eval(state, almanac)?),
+        }
+    }
+
+    fn eval_string(&self, state: &S, almanac: Arc<Almanac>) -> Result<String, EventError> {
+        let join = |conditions: &[CompositeEvent<S>], op: &str| {
+            conditions
+                .iter()
+                .map(|c| c.eval_string(state, almanac.clone()))
+                .collect::<Result<Vec<String>, EventError>>()
+                .map(|strings| strings.join(op))
+        };
+        match self {
+            Self::Event(event) => event.eval_string(state, almanac),
+            Self::All(conditions) => Ok(format!("({})", join(conditions, " AND ")?)),
+            Self::Any(conditions) => Ok(format!("({})", join(conditions, " OR ")?)),
+            Self::Not(condition) => Ok(format!(
+                "NOT {}",
+                condition.eval_string(state, almanac.clone())?
+            )),
+        }
+    }
+
+    fn epoch_precision(&self) -> Duration {
+        self.leaves()
+            .iter()
+            .map(|event| event.epoch_precision())
+            .min()
+            .unwrap_or(Duration::MAX)
+    }
+
+    fn value_precision(&self) -> f64 {
+        self.leaves()
+            .iter()
+            .map(|event| event.value_precision())
+            .fold(f64::INFINITY, f64::min)
+    }
+}
+
+#[cfg(test)]
+mod ut_composite {
+    use super::*;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::md::prelude::Traj;
+    use crate::md::{Event, StateParameter};
+    use crate::propagators::Propagator;
+    use crate::time::Unit;
+    use crate::Spacecraft;
+
+    fn traj() -> Traj<Spacecraft> {
+        let orbit = fixtures::keplerian(8_000.0, 0.2, 30.0, 0.0, 40.0, 0.0);
+        let (_, traj) = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()))
+            .with(
+                Spacecraft::builder().orbit(orbit).build(),
+                fixtures::almanac(),
+            )
+            .for_duration_with_traj(orbit.period().unwrap() * 3.0)
+            .unwrap();
+        traj
+    }
+
+    #[test]
+    fn boolean_algebra() {
+        let almanac = fixtures::almanac();
+        let traj = traj();
+
+        let north = CompositeEvent::event(Event::new(StateParameter::Z, 0.0));
+        let low = !CompositeEvent::event(Event::new(StateParameter::Rmag, 8_000.0));
+        // Double negation cancels out
+        assert!(matches!(!!low.clone(), CompositeEvent::Not(_)));
+        assert!(matches!(!!north.clone(), CompositeEvent::Event(_)));
+
+        let both = north.clone().and(low.clone());
+        let either = north.clone().or(low.clone());
+        println!("{both}\n{either}");
+        assert_eq!(both.epoch_precision(), Unit::Millisecond * 1);
+
+        for state in traj.every(Unit::Minute * 1) {
+            let is_north = state.orbit.radius_km.z > 0.0;
+            let is_low = state.orbit.rmag_km() < 8_000.0;
+            let both_val = both.eval(&state, almanac.clone()).unwrap();
+            let either_val = either.eval(&state, almanac.clone()).unwrap();
+            if both_val.abs() > 1e-6 {
+                assert_eq!(both_val > 0.0, is_north && is_low);
+            }
+            if either_val.abs() > 1e-6 {
+                assert_eq!(either_val > 0.0, is_north || is_low);
+            }
+        }
+
+        // Each arc of the conjunction starts and ends on one of the two conditions, or at the bounds of the trajectory.
+        let arcs = traj.find_arcs(&both, almanac.clone()).unwrap();
+        assert!(!arcs.is_empty());
+        for arc in &arcs {
+            println!("{arc}");
+            for edge in [&arc.rise.state, &arc.fall.state] {
+                if edge.epoch() == traj.first().epoch() || edge.epoch() == traj.last().epoch() {
+                    continue;
+                }
+                let z = edge.orbit.radius_km.z;
+                let r = edge.orbit.rmag_km();
+                assert!(
+                    z.abs() < 1e-2 || (r - 8_000.0).abs() < 1e-2,
+                    "z = {z} km, r = {r} km"
+                );
+            }
+            let middle = traj
+                .at(arc.rise.state.epoch()
+                    + (arc.fall.state.epoch() - arc.rise.state.epoch()) * 0.5)
+                .unwrap();
+            assert!(middle.orbit.radius_km.z > 0.0 && middle.orbit.rmag_km() < 8_000.0);
+        }
+    }
+}
@@ -36,6 +36,8 @@ pub enum EventEdge {
     Rising,
     /// Represents a falling edge of the event. This is the opposite of the rising edge, indicating a transition from a higher to a lower value of the event evaluator. For example, if tracking the elevation of an object, a falling edge would signify a
     Falling,
+    /// The event function touches zero without changing sign, e.g. a grazing eclipse or an elevation which peaks exactly at the mask.
+    Touching,
     /// If the edge cannot be clearly defined, it will be marked as unclear. This happens if the event is at a saddle point and the epoch precision is too large to find the exact slope.
     Unclear,
 }
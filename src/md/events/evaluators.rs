@@ -62,9 +62,14 @@ impl EventEvaluator<Spacecraft> for Event {
                 180.0,
             )),
             StateParameter::PropMass => Ok(state.mass.prop_mass_kg - self.desired_value),
-            _ => Ok(state.value(self.parameter).context(EventStateSnafu {
-                param: self.parameter,
-            })? - self.desired_value),
+            _ => {
+                Ok(state
+                    .value_with_almanac(self.parameter, &almanac)
+                    .context(EventStateSnafu {
+                        param: self.parameter,
+                    })?
+                    - self.desired_value)
+            }
         }
     }
 
@@ -77,11 +82,7 @@ impl EventEvaluator<Spacecraft> for Event {
         self.value_precision
     }
 
-    fn eval_string(
-        &self,
-        state: &Spacecraft,
-        _almanac: Arc<Almanac>,
-    ) -> Result<String, EventError> {
+    fn eval_string(&self, state: &Spacecraft, almanac: Arc<Almanac>) -> Result<String, EventError> {
         match self.parameter {
             StateParameter::Apoapsis | StateParameter::Periapsis => {
                 Ok(format!("{}", self.parameter))
@@ -92,9 +93,11 @@ impl EventEvaluator<Spacecraft> for Event {
                 } else {
                     format!(" ({})", self.parameter.unit())
                 };
-                let val = state.value(self.parameter).context(EventStateSnafu {
-                    param: self.parameter,
-                })?;
+                let val = state.value_with_almanac(self.parameter, &almanac).context(
+                    EventStateSnafu {
+                        param: self.parameter,
+                    },
+                )?;
 
                 Ok(format!("{}{} = {:.3}{}", self.parameter, unit, val, unit))
             }
@@ -16,6 +16,7 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+pub mod composite;
 pub mod details;
 pub mod evaluators;
 pub mod search;
@@ -27,6 +28,7 @@ use crate::time::{Duration, Unit};
 use crate::State;
 use anise::prelude::{Almanac, Frame};
 use anise::structure::planetocentric::ellipsoid::Ellipsoid;
+pub use composite::CompositeEvent;
 
 use std::default::Default;
 use std::fmt;
@@ -30,6 +30,11 @@ use std::iter::Iterator;
 use std::sync::mpsc::channel;
 use std::sync::Arc;
 
+/// Minimum number of subdivisions of each step of the trajectory when searching for events.
+pub const EVENT_STEP_SUBDIVISIONS: usize = 3;
+/// Maximum number of samples of the event function when searching for events.
+pub const MAX_EVENT_SAMPLES: usize = 50_000;
+
 impl<S: Interpolatable> Traj<S>
 where
     DefaultAllocator: Allocator<S::VecLength> + Allocator<S::Size> + Allocator<S::Size, S::Size>,
@@ -184,16 +189,19 @@ where
 
     /// Find all of the states where the event happens
     ///
-    /// # Limitations
-    /// This method uses a Brent solver. If the function that defines the event is not unimodal, the event finder may not converge correctly.
+    /// # Algorithm
+    /// The event function is sampled at every state of the trajectory, and every step between two states is further subdivided
+    /// such that there are at least [EVENT_STEP_SUBDIVISIONS] samples per step and that the samples are at most 1% of the
+    /// trajectory duration apart. If the trajectory was built with dense output, these samples are evaluated on the continuous
+    /// extension of the integrator. Trajectories with more than [MAX_EVENT_SAMPLES] states are sampled uniformly instead.
     ///
-    /// # Heuristic detail
-    /// The initial search step is 1% of the duration of the trajectory duration.
-    /// For example, if the trajectory is 100 days long, then we split the trajectory into 100 chunks of 1 day and see whether
-    /// the event is in there. If the event happens twice or more times within 1% of the trajectory duration, only the _one_ of
-    /// such events will be found.
+    /// Every sign change between two samples is then polished with a Brent solver, so several events within the same step
+    /// are all found. Around every local minimum of the absolute value of the event function which does not change sign,
+    /// the extremum is located with a golden section search: if it changes sign there, the event is brief (e.g. a short eclipse)
+    /// and both of its edges are polished, and if it touches zero (within the value precision), it is reported as a
+    /// [EventEdge::Touching] event.
     ///
-    /// If this heuristic fails to find any such events, then `find_minmax` is called on the event with a time precision of `Unit::Second`.
+    /// If this fails to find any such events, then `find_minmax` is called on the event with a time precision of `Unit::Second`.
     /// Then we search only within the min and max bounds of the provided event.
     #[allow(clippy::identity_op)]
     pub fn find<E>(
@@ -213,22 +221,142 @@ where
                 event: format!("{event}"),
             });
         }
-        let heuristic = (end_epoch - start_epoch) / 100;
-        info!("Searching for {event} with initial heuristic of {heuristic}");
 
-        let (sender, receiver) = channel();
+        let epochs = self.event_sample_epochs();
+        info!("Searching for {event} with {} samples", epochs.len());
+
+        let samples: Vec<(Epoch, f64)> = epochs
+            .into_par_iter()
+            .filter_map(|epoch| {
+                let state = self.at(epoch).ok()?;
+                let value = event.eval(&state, almanac.clone()).ok()?;
+                Some((epoch, value))
+            })
+            .collect();
+
+        let precision = event.value_precision().abs();
+        let is_zero = |value: f64| value.abs() <= precision;
+
+        let mut on_sample = Vec::new();
+        let mut brackets = Vec::new();
+        let mut touching = Vec::new();
+
+        // Searches for the extremum of the event between the provided epochs, which either reveals a brief event
+        // (i.e. two sign changes), or a touching event.
+        let search_extremum = |brackets: &mut Vec<(Epoch, Epoch)>,
+                               touching: &mut Vec<Epoch>,
+                               start: Epoch,
+                               end: Epoch,
+                               sign: f64|
+         -> bool {
+            match self.find_extremum(start, end, sign, event, almanac.clone()) {
+                Some((extremum, value)) if value.signum() != sign && !is_zero(value) => {
+                    brackets.push((start, extremum));
+                    brackets.push((extremum, end));
+                    true
+                }
+                Some((extremum, value)) if is_zero(value) => {
+                    touching.push(extremum);
+                    true
+                }
+                _ => false,
+            }
+        };
 
-        let epochs: Vec<Epoch> = TimeSeries::inclusive(start_epoch, end_epoch, heuristic).collect();
-        epochs.into_par_iter().for_each_with(sender, |s, epoch| {
-            if let Ok(event_state) =
-                self.find_bracketed(epoch, epoch + heuristic, event, almanac.clone())
+        // Sign changes between samples, and runs of samples on the event
+        let mut idx = 0;
+        while idx < samples.len() {
+            let (epoch, value) = samples[idx];
+            if !is_zero(value) {
+                if let Some((next_epoch, next_value)) = samples.get(idx + 1).copied() {
+                    if !is_zero(next_value) && value.signum() != next_value.signum() {
+                        brackets.push((epoch, next_epoch));
+                    }
+                }
+                idx += 1;
+                continue;
+            }
+
+            let run_start = idx;
+            while idx < samples.len() && is_zero(samples[idx].1) {
+                idx += 1;
+            }
+
+            if run_start == 0 || idx == samples.len() {
+                // At the bounds of the trajectory
+                on_sample.push(epoch);
+            } else {
+                let (prev_epoch, prev_value) = samples[run_start - 1];
+                let (next_epoch, next_value) = samples[idx];
+                if prev_value.signum() != next_value.signum() {
+                    brackets.push((prev_epoch, next_epoch));
+                } else if !search_extremum(
+                    &mut brackets,
+                    &mut touching,
+                    prev_epoch,
+                    next_epoch,
+                    prev_value.signum(),
+                ) {
+                    on_sample.push(epoch);
+                }
+            }
+        }
+
+        // Brief and touching events around the local minima of the absolute value of the event function
+        for window in samples.windows(3) {
+            let (prev_epoch, prev_value) = window[0];
+            let (_, value) = window[1];
+            let (next_epoch, next_value) = window[2];
+            if !is_zero(prev_value)
+                && !is_zero(value)
+                && !is_zero(next_value)
+                && value.signum() == prev_value.signum()
+                && value.signum() == next_value.signum()
+                && value.abs() < prev_value.abs()
+                && value.abs() <= next_value.abs()
             {
-                s.send(event_state).unwrap()
-            };
-        });
+                search_extremum(
+                    &mut brackets,
+                    &mut touching,
+                    prev_epoch,
+                    next_epoch,
+                    value.signum(),
+                );
+            }
+        }
+
+        let (sender, receiver) = channel();
+
+        brackets
+            .into_par_iter()
+            .for_each_with(sender, |s, (start, end)| {
+                if let Ok(event_state) = self.find_bracketed(start, end, event, almanac.clone()) {
+                    s.send(event_state).unwrap()
+                };
+            });
 
         let mut states: Vec<_> = receiver.iter().collect();
 
+        for epoch in on_sample {
+            let state = self.at(epoch).context(EventTrajSnafu {})?;
+            let value = event.eval(&state, almanac.clone())?;
+            states.push(EventDetails::new(
+                state,
+                value,
+                event,
+                self,
+                almanac.clone(),
+            )?);
+        }
+
+        for epoch in touching {
+            let state = self.at(epoch).context(EventTrajSnafu {})?;
+            let value = event.eval(&state, almanac.clone())?;
+            let mut details = EventDetails::new(state, value, event, self, almanac.clone())?;
+            details.edge = EventEdge::Touching;
+            states.push(details);
+        }
+
         if states.is_empty() {
             warn!("Heuristic failed to find any {event} event, using slower approach");
             // Crap, we didn't find the event.
@@ -301,9 +429,11 @@ where
                 }
             };
         }
-        // Remove duplicates and reorder
+        // Remove duplicates (e.g. an event on a sample which was also the end of a bracket) and reorder
         states.sort_by(|s1, s2| s1.state.epoch().partial_cmp(&s2.state.epoch()).unwrap());
-        states.dedup();
+        states.dedup_by(|s1, s2| {
+            (s1.state.epoch() - s2.state.epoch()).abs() <= event.epoch_precision()
+        });
 
         match states.len() {
             0 => info!("Event {event} not found"),
@@ -321,6 +451,86 @@ where
         Ok(states)
     }
 
+    /// Returns the epochs at which the event function is sampled by `find`.
+    fn event_sample_epochs(&self) -> Vec<Epoch> {
+        let start_epoch = self.first().epoch();
+        let end_epoch = self.last().epoch();
+
+        if self.states.len() * EVENT_STEP_SUBDIVISIONS > MAX_EVENT_SAMPLES {
+            let step = (end_epoch - start_epoch) / (MAX_EVENT_SAMPLES as f64);
+            return TimeSeries::inclusive(start_epoch, end_epoch, step).collect();
+        }
+
+        let max_spacing_s = ((end_epoch - start_epoch) / 100).to_seconds();
+
+        let mut epochs = Vec::with_capacity(self.states.len() * EVENT_STEP_SUBDIVISIONS);
+        epochs.push(start_epoch);
+        for pair in self.states.windows(2) {
+            let step = pair[1].epoch() - pair[0].epoch();
+            let subdivisions =
+                EVENT_STEP_SUBDIVISIONS.max((step.to_seconds() / max_spacing_s).ceil() as usize);
+            for i in 1..subdivisions {
+                epochs.push(pair[0].epoch() + step * (i as f64 / subdivisions as f64));
+            }
+            epochs.push(pair[1].epoch());
+        }
+
+        epochs
+    }
+
+    /// Locates the minimum of `sign * event` between the provided epochs with a golden section search,
+    /// and returns its epoch and the value of the event there.
+    fn find_extremum<E>(
+        &self,
+        start: Epoch,
+        end: Epoch,
+        sign: f64,
+        event: &E,
+        almanac: Arc<Almanac>,
+    ) -> Option<(Epoch, f64)>
+    where
+        E: EventEvaluator<S>,
+    {
+        let inv_phi = (5.0_f64.sqrt() - 1.0) / 2.0;
+        let eval = |epoch: Epoch| -> Option<f64> {
+            let state = self.at(epoch).ok()?;
+            event.eval(&state, almanac.clone()).ok()
+        };
+
+        let (mut a, mut b) = (start, end);
+        let mut c = b - (b - a) * inv_phi;
+        let mut d = a + (b - a) * inv_phi;
+        let mut yc = eval(c)?;
+        let mut yd = eval(d)?;
+
+        while b - a > event.epoch_precision() {
+            // Stop as soon as the event changes sign
+            if yc.signum() != sign {
+                return Some((c, yc));
+            } else if yd.signum() != sign {
+                return Some((d, yd));
+            }
+
+            if sign * yc < sign * yd {
+                b = d;
+                (d, yd) = (c, yc);
+                c = b - (b - a) * inv_phi;
+                yc = eval(c)?;
+            } else {
+                a = c;
+                (c, yc) = (d, yd);
+                d = a + (b - a) * inv_phi;
+                yd = eval(d)?;
+            }
+        }
+
+        if sign * yc < sign * yd {
+            Some((c, yc))
+        } else {
+            Some((d, yd))
+        }
+    }
+
     /// Find the minimum and maximum of the provided event through the trajectory
     #[allow(clippy::identity_op)]
     pub fn find_minmax<E>(
@@ -422,6 +632,8 @@ where
                 }
             }
         };
+        // Touching events neither start nor end an arc
+        events.retain(|event| event.edge != EventEdge::Touching);
         events.sort_by_key(|event| event.state.epoch());
 
         // Now, let's pair the events.
@@ -503,3 +715,61 @@ where
         Ok(arcs)
     }
 }
+
+#[cfg(test)]
+mod ut_search {
+    use crate::cosmic::Spacecraft;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::md::events::details::EventEdge;
+    use crate::md::prelude::{Event, StateParameter};
+    use crate::propagators::{ErrorControl, IntegratorMethod, IntegratorOptions, Propagator};
+    use crate::time::Unit;
+
+    #[test]
+    fn brief_and_touching_events() {
+        let epoch = fixtures::epoch();
+        let orbit = fixtures::keplerian(7_000.0, 0.01, 28.5, 30.0, 45.0, 0.0);
+        let period = orbit.period().unwrap();
+        let apoapsis_km = orbit.apoapsis_km().unwrap();
+        let almanac = fixtures::almanac();
+
+        // Large steps such that the brief events happen within a single step
+        let (_, traj) = Propagator::new(
+            SpacecraftDynamics::new(OrbitalDynamics::two_body()),
+            IntegratorMethod::Dop853,
+            IntegratorOptions::with_adaptive_step(
+                Unit::Second * 1,
+                Unit::Hour * 1,
+                1e-12,
+                ErrorControl::RSSCartesianStep,
+            ),
+        )
+        .with(Spacecraft::builder().orbit(orbit).build(), almanac.clone())
+        .for_duration_with_dense_traj(period * 2)
+        .unwrap();
+
+        // The radius is above this value for about 70 seconds around each apoapsis
+        let brief = Event::new(StateParameter::Rmag, apoapsis_km - 0.05);
+        let events = traj.find(&brief, almanac.clone()).unwrap();
+        assert_eq!(events.len(), 4, "{events:?}");
+        for pair in events.chunks(2) {
+            assert_eq!(pair[0].edge, EventEdge::Rising);
+            assert_eq!(pair[1].edge, EventEdge::Falling);
+            let duration = pair[1].state.orbit.epoch - pair[0].state.orbit.epoch;
+            assert!(duration < Unit::Second * 90 && duration > Unit::Second * 50);
+        }
+        assert_eq!(traj.find_arcs(&brief, almanac.clone()).unwrap().len(), 2);
+
+        // The maximum radius grazes this value
+        let touching = Event::new(StateParameter::Rmag, apoapsis_km + 5e-4);
+        let events = traj.find(&touching, almanac.clone()).unwrap();
+        assert_eq!(events.len(), 2, "{events:?}");
+        for (idx, event) in events.iter().enumerate() {
+            assert_eq!(event.edge, EventEdge::Touching);
+            let expected = epoch + period * (0.5 + idx as f64);
+            assert!((event.state.orbit.epoch - expected).abs() < Unit::Second * 1);
+        }
+        assert!(traj.find_arcs(&touching, almanac).unwrap().is_empty());
+    }
+}
@@ -29,13 +29,15 @@ pub mod prelude {
         trajectory::{ExportCfg, Interpolatable, Traj},
         Event, StateParameter, Trajectory,
     };
-    pub use crate::cosmic::{try_achieve_b_plane, BPlane, BPlaneTarget, GuidanceMode, OrbitDual};
+    pub use crate::cosmic::{
+        try_achieve_b_plane, BPlane, BPlaneCovariance, BPlaneTarget, GuidanceMode, OrbitDual,
+    };
     pub use crate::dynamics::{
         Drag, Harmonics, OrbitalDynamics, PointMasses, SolarPressure, SpacecraftDynamics,
     };
     pub use crate::dynamics::{Dynamics, NyxError};
-    pub use crate::io::gravity::HarmonicsMem;
-    pub use crate::md::objective::Objective;
+    pub use crate::io::gravity::{HarmonicsMem, TideSystem};
+    pub use crate::md::objective::{Constraint, Objective};
     pub use crate::propagators::{IntegratorOptions, Propagator};
     pub use crate::time::{Duration, Epoch, TimeUnits, Unit};
     pub use crate::Spacecraft;
@@ -48,10 +50,12 @@ pub mod prelude {
 pub mod trajectory;
 
 pub(crate) mod events;
-pub use events::{Event, EventEvaluator};
+pub use events::{CompositeEvent, Event, EventEvaluator};
 
 pub mod objective;
 pub mod opti;
+pub mod plan;
+pub mod stationkeeping;
 pub use opti::targeter;
 pub type Trajectory = trajectory::Traj<Spacecraft>;
 
@@ -83,8 +87,16 @@ pub enum TargetingError {
     Verification { msg: String },
     #[snafu(display("astro error during targeting: {source}"))]
     Astro { source: AstroError },
-    #[snafu(display("targeting aborted, too many iterations"))]
-    TooManyIterations,
+    #[snafu(display("targeting aborted after {iterations} iterations"))]
+    TooManyIterations { iterations: usize },
+    /// Raised when the propagator of the targeter is cancelled, with the total correction of the last iterate
+    #[snafu(display(
+        "targeting cancelled after {iterations} iterations, last correction: {correction:?}"
+    ))]
+    Cancelled {
+        iterations: usize,
+        correction: Vec<f64>,
+    },
     #[snafu(display("correction is ineffective at {action}: value at previous iteration {prev_val}, current value: {cur_val}"))]
     CorrectionIneffective {
         prev_val: f64,
@@ -104,3 +116,19 @@ pub enum TargetingError {
     #[snafu(display("during an optimization targets are too close"))]
     TargetsTooClose,
 }
+
+impl TargetingError {
+    /// Reports the cancellation of a propagation as the cancellation of the targeter, with the total correction of its last iterate.
+    pub(crate) fn with_iterate(self, iterations: usize, correction: &[f64]) -> Self {
+        match self {
+            Self::PropError {
+                source: PropagationError::Cancelled { .. },
+            }
+            | Self::Cancelled { .. } => Self::Cancelled {
+                iterations,
+                correction: correction.to_vec(),
+            },
+            e => e,
+        }
+    }
+}
@@ -17,9 +17,47 @@
 */
 
 use super::StateParameter;
+use crate::cosmic::{AstroAlmanacSnafu, AstroError, BPlane, Frame, Orbit, OrbitDual};
+use crate::linalg::{Matrix6, Vector3, Vector6};
 use crate::{errors::StateError, Spacecraft, State};
+use anise::almanac::Almanac;
+use anise::constants::frames::SUN_J2000;
+use anise::errors::OrientationSnafu;
+use snafu::ResultExt;
 use std::fmt;
 
+/// Defines how the achieved value of an objective is compared to its desired value
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Constraint {
+    /// The achieved value must match the desired value within the tolerance
+    #[default]
+    Equal,
+    /// The achieved value must be greater than or equal to the desired value
+    AtLeast,
+    /// The achieved value must be less than or equal to the desired value
+    AtMost,
+}
+
+impl Constraint {
+    /// Prefix of the desired value when displaying an objective: nothing for equalities, else the inequality sign.
+    pub(crate) fn prefix(&self) -> String {
+        match self {
+            Self::Equal => String::new(),
+            _ => format!("{self} "),
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Equal => write!(f, "="),
+            Self::AtLeast => write!(f, "≥"),
+            Self::AtMost => write!(f, "≤"),
+        }
+    }
+}
+
 /// Defines a state parameter event finder
 #[derive(Copy, Clone, Debug)]
 pub struct Objective {
@@ -33,6 +71,13 @@ pub struct Objective {
     pub multiplicative_factor: f64,
     /// An additive factor to this parameters's error in the targeting (defaults to 0.0)
     pub additive_factor: f64,
+    /// Whether the parameter must match the desired value or is only bounded by it (defaults to an equality).
+    /// Inequalities are handled with an active set in the Newton Raphson correctors of the [Targeter](crate::md::opti::targeter::Targeter).
+    pub constraint: Constraint,
+    /// An optional frame in which to evaluate this objective instead of the objective frame of the targeter, requires the Almanac.
+    /// For example, the geodetic longitude and latitude in a body fixed frame place the spacecraft over a ground site,
+    /// and the norm of the radius in the frame of another body is the range to that body.
+    pub obs_frame: Option<Frame>,
 }
 
 impl Objective {
@@ -55,9 +100,104 @@ impl Objective {
             tolerance,
             multiplicative_factor: 1.0,
             additive_factor: 0.0,
+            constraint: Constraint::Equal,
+            obs_frame: None,
+        }
+    }
+
+    /// Match a specific value for the parameter evaluated in the provided frame, using the default precision of the parameter.
+    pub fn in_frame(parameter: StateParameter, desired_value: f64, obs_frame: Frame) -> Self {
+        Self {
+            obs_frame: Some(obs_frame),
+            ..Self::new(parameter, desired_value)
         }
     }
 
+    /// Requires the parameter to be greater than or equal to the provided value, e.g. a minimum periapsis radius.
+    /// The tolerance is the default precision of the parameter, as in [Objective::new].
+    pub fn at_least(parameter: StateParameter, min_value: f64) -> Self {
+        Self {
+            constraint: Constraint::AtLeast,
+            ..Self::new(parameter, min_value)
+        }
+    }
+
+    /// Requires the parameter to be less than or equal to the provided value, e.g. a maximum eclipse duration.
+    /// The tolerance is the default precision of the parameter, as in [Objective::new].
+    pub fn at_most(parameter: StateParameter, max_value: f64) -> Self {
+        Self {
+            constraint: Constraint::AtMost,
+            ..Self::new(parameter, max_value)
+        }
+    }
+
+    /// Returns whether this objective must be evaluated with the Almanac, i.e. in its own frame or from the position of other celestial objects.
+    pub fn requires_almanac(&self) -> bool {
+        self.obs_frame.is_some() || self.parameter.requires_almanac()
+    }
+
+    /// Evaluates the parameter of this objective on the provided orbit, and returns it with its partial derivatives with respect to the Cartesian state of that orbit.
+    ///
+    /// The orbit is first transformed into the observation frame of this objective, if any. The Sun angle is the angle between
+    /// the directions of the Sun and of the center of that frame as seen from the spacecraft.
+    pub fn evaluate(
+        &self,
+        orbit: Orbit,
+        almanac: &Almanac,
+    ) -> Result<(f64, Vector6<f64>), AstroError> {
+        let (orbit_obs, to_obs) = match self.obs_frame {
+            Some(frame) if frame != orbit.frame => {
+                let orbit_obs = almanac
+                    .transform_to(orbit, frame, None)
+                    .context(AstroAlmanacSnafu)?;
+                let dcm = almanac
+                    .rotate(orbit.frame, frame, orbit.epoch)
+                    .context(OrientationSnafu {
+                        action: "rotating a state into the frame of an objective",
+                    })
+                    .context(AstroAlmanacSnafu)?;
+
+                (orbit_obs, dcm.state_dcm())
+            }
+            _ => (orbit, Matrix6::identity()),
+        };
+
+        let (value, partial_obs) = if self.parameter == StateParameter::SunAngle {
+            let sun = almanac
+                .transform(SUN_J2000, orbit_obs.frame, orbit_obs.epoch, None)
+                .context(AstroAlmanacSnafu)?;
+            let (angle, grad) = sun_angle_deg(&orbit_obs.radius_km, &sun.radius_km);
+
+            (angle, Vector6::new(grad.x, grad.y, grad.z, 0.0, 0.0, 0.0))
+        } else {
+            let dual = OrbitDual::from(orbit_obs);
+            let partial = if self.parameter.is_b_plane() {
+                let b_plane = BPlane::from_dual(dual)?;
+                match self.parameter {
+                    StateParameter::BdotR => b_plane.b_r,
+                    StateParameter::BdotT => b_plane.b_t,
+                    _ => b_plane.ltof_s,
+                }
+            } else {
+                dual.partial_for(self.parameter)?
+            };
+
+            (
+                partial.real(),
+                Vector6::new(
+                    partial.wtr_x(),
+                    partial.wtr_y(),
+                    partial.wtr_z(),
+                    partial.wtr_vx(),
+                    partial.wtr_vy(),
+                    partial.wtr_vz(),
+                ),
+            )
+        };
+
+        Ok((value, to_obs.transpose() * partial_obs))
+    }
+
     /// Returns whether this objective has been achieved, and the associated parameter error.
     pub fn assess(&self, achieved: &Spacecraft) -> Result<(bool, f64), StateError> {
         Ok(self.assess_value(achieved.value(self.parameter)?))
@@ -65,11 +205,30 @@ impl Objective {
 
     /// Returns whether this objective has been achieved, and the associated parameter error.
     /// Warning: the parameter `achieved` must be in the same unit as the objective.
+    /// Inequality constraints are achieved when the bound is respected, or violated by less than the tolerance.
     pub fn assess_value(&self, achieved: f64) -> (bool, f64) {
         let param_err =
             self.multiplicative_factor * (self.desired_value - achieved) + self.additive_factor;
 
-        (param_err.abs() <= self.tolerance, param_err)
+        let ok = match self.constraint {
+            Constraint::Equal => param_err.abs() <= self.tolerance,
+            Constraint::AtLeast => achieved >= self.desired_value - self.tolerance,
+            Constraint::AtMost => achieved <= self.desired_value + self.tolerance,
+        };
+
+        (ok, param_err)
+    }
+
+    /// Returns whether this objective constrains the correction of a targeter given the achieved value of its parameter.
+    ///
+    /// Equalities are always active. Inequalities are only active when the achieved value is within the tolerance of the
+    /// bound or violates it: the targeter then seeks the bound. Otherwise, the inequality is ignored by the targeter.
+    pub fn is_active(&self, achieved: f64) -> bool {
+        match self.constraint {
+            Constraint::Equal => true,
+            Constraint::AtLeast => achieved < self.desired_value + self.tolerance,
+            Constraint::AtMost => achieved > self.desired_value - self.tolerance,
+        }
     }
 }
 
@@ -83,10 +242,15 @@ impl fmt::LowerHex for Objective {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
         let max_obj_tol = self.tolerance.log10().abs().ceil() as usize;
 
+        write!(f, "{:?}", self.parameter)?;
+        if let Some(frame) = self.obs_frame {
+            write!(f, " in {frame}")?;
+        }
+
         write!(
             f,
-            "{:?} → {:.prec$} {}",
-            self.parameter,
+            " → {}{:.prec$} {}",
+            self.constraint.prefix(),
             self.desired_value,
             self.parameter.unit(),
             prec = max_obj_tol,
@@ -99,3 +263,83 @@ impl fmt::LowerHex for Objective {
         }
     }
 }
+
+/// Returns the angle (deg) between the Sun and the frame center as seen from a spacecraft at `radius_km`, and its gradient with respect to that radius.
+fn sun_angle_deg(radius_km: &Vector3<f64>, sun_radius_km: &Vector3<f64>) -> (f64, Vector3<f64>) {
+    let to_sun = sun_radius_km - radius_km;
+    let to_center = -radius_km;
+    let (sun_dist, center_dist) = (to_sun.norm(), to_center.norm());
+    let cos_angle = (to_sun.dot(&to_center) / (sun_dist * center_dist)).clamp(-1.0, 1.0);
+
+    let dcos_dsun = to_center / (sun_dist * center_dist) - cos_angle * to_sun / sun_dist.powi(2);
+    let dcos_dcenter =
+        to_sun / (sun_dist * center_dist) - cos_angle * to_center / center_dist.powi(2);
+    // Both vectors decrease when the spacecraft moves
+    let dcos_dr = -(dcos_dsun + dcos_dcenter);
+    let sin_angle = (1.0 - cos_angle.powi(2)).sqrt().max(f64::EPSILON);
+
+    (
+        cos_angle.acos().to_degrees(),
+        -dcos_dr / sin_angle * 1.0_f64.to_degrees(),
+    )
+}
+
+#[cfg(test)]
+mod ut_objective {
+    use super::{sun_angle_deg, Constraint, Objective, StateParameter};
+    use crate::linalg::Vector3;
+
+    #[test]
+    fn inequalities() {
+        let rp = Objective::at_least(StateParameter::Periapsis, 7_000.0);
+        assert_eq!(rp.constraint, Constraint::AtLeast);
+        // Respected by a margin: achieved and ignored by the targeter
+        assert!(rp.assess_value(7_100.0).0);
+        assert!(!rp.is_active(7_100.0));
+        // At the bound: achieved but the targeter keeps it there
+        assert!(rp.assess_value(7_000.0).0);
+        assert!(rp.is_active(7_000.0));
+        // Violated
+        let (ok, err) = rp.assess_value(6_900.0);
+        assert!(!ok);
+        assert_eq!(err, 100.0);
+        assert!(rp.is_active(6_900.0));
+
+        let ecc = Objective::at_most(StateParameter::Eccentricity, 0.1);
+        assert!(ecc.assess_value(0.05).0);
+        assert!(!ecc.is_active(0.05));
+        assert!(!ecc.assess_value(0.2).0);
+        assert!(ecc.is_active(0.2));
+
+        assert!(format!("{rp:x}").contains('≥'));
+        assert!(format!("{ecc:x}").contains('≤'));
+        assert!(Objective::new(StateParameter::SMA, 7_000.0).is_active(8_000.0));
+    }
+
+    #[test]
+    fn sun_angle() {
+        let sun = Vector3::new(149_597_870.7, 0.0, 0.0);
+        let radius = Vector3::new(7_000.0, 3_000.0, 1_000.0);
+        let (angle, grad) = sun_angle_deg(&radius, &sun);
+        // The Sun is nearly along +X, and the center of the frame along -r
+        let expected = (-radius.x / radius.norm()).acos().to_degrees();
+        assert!((angle - expected).abs() < 1e-2, "{angle} != {expected}");
+
+        for i in 0..3 {
+            let mut pert = Vector3::zeros();
+            pert[i] = 1e-3;
+            let fd = (sun_angle_deg(&(radius + pert), &sun).0
+                - sun_angle_deg(&(radius - pert), &sun).0)
+                / 2e-3;
+            assert!(
+                (fd - grad[i]).abs() < 1e-8,
+                "component {i}: {fd} != {}",
+                grad[i]
+            );
+        }
+
+        assert!(StateParameter::SunAngle.requires_almanac());
+        assert!(Objective::new(StateParameter::SunAngle, 90.0).requires_almanac());
+        assert!(!Objective::new(StateParameter::SMA, 7_000.0).requires_almanac());
+    }
+}
@@ -0,0 +1,678 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use anise::prelude::Almanac;
+use snafu::ResultExt;
+
+use super::nlp::{NlpProblem, NlpSolution, NlpSolver};
+use crate::cosmic::AstroPhysicsSnafu;
+use crate::dynamics::guidance::GuidanceError;
+use crate::dynamics::Dynamics;
+use crate::errors::TargetingError;
+use crate::linalg::{DVector, Vector3};
+use crate::md::objective::Objective;
+use crate::md::StateParameter;
+use crate::md::{prelude::*, AstroSnafu, PropSnafu, TargetingTrajSnafu};
+
+use std::fmt;
+use std::sync::Arc;
+
+/// Cost function of the collocation problem
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CollocationObjective {
+    /// Minimize the integral of the square of the throttle, a smooth problem which is a good initial guess for the others
+    MinimumEnergy,
+    /// Maximize the final mass of the spacecraft
+    MassOptimal,
+    /// Minimize the time of flight, which is then a decision variable
+    TimeOptimal,
+}
+
+/// Bounds on a state parameter which must hold at every node of the trajectory, e.g. a minimum radius.
+#[derive(Copy, Clone, Debug)]
+pub struct PathConstraint {
+    /// The constrained state parameter
+    pub parameter: StateParameter,
+    /// Lower bound, in the unit of the parameter (may be negative infinity)
+    pub min: f64,
+    /// Upper bound, in the unit of the parameter (may be infinity)
+    pub max: f64,
+}
+
+impl PathConstraint {
+    /// Constrains the parameter between the provided bounds
+    pub fn new(parameter: StateParameter, min: f64, max: f64) -> Self {
+        Self {
+            parameter,
+            min,
+            max,
+        }
+    }
+}
+
+/// A low thrust optimal control problem transcribed with Hermite-Simpson direct collocation.
+///
+/// The trajectory is split into segments of equal duration. The decision variables are the position, velocity, and mass
+/// at each node, the throttle vector (direction times throttle level) at each node and at the midpoint of each segment,
+/// and the time of flight for time optimal problems. The Hermite-Simpson defects of each segment, the initial state, and the final
+/// objectives are the equality constraints; the throttle level and the path constraints are the inequality constraints.
+/// The resulting nonlinear program is solved by any [NlpSolver], e.g. the built-in [Sqp](super::nlp::Sqp).
+///
+/// The ballistic dynamics are those of the provided spacecraft dynamics (its guidance law is ignored) and the thrust is
+/// provided by the thruster of the initial spacecraft. All variables are scaled by the initial radius, the associated
+/// circular orbit period, and the initial mass.
+#[derive(Clone)]
+pub struct HermiteSimpson {
+    /// Ballistic dynamics of the spacecraft
+    pub dynamics: SpacecraftDynamics,
+    /// Initial state of the spacecraft, which must have a thruster
+    pub initial: Spacecraft,
+    /// Objectives which must be achieved at the end of the trajectory
+    pub objectives: Vec<Objective>,
+    /// Constraints which must hold at every node
+    pub path_constraints: Vec<PathConstraint>,
+    /// Time of flight, or its initial guess for time optimal problems
+    pub tof: Duration,
+    /// Number of segments of the transcription
+    pub segments: usize,
+    /// Cost function to minimize
+    pub cost: CollocationObjective,
+}
+
+#[allow(clippy::result_large_err)]
+impl HermiteSimpson {
+    /// Initializes a new collocation problem.
+    pub fn new(
+        dynamics: SpacecraftDynamics,
+        initial: Spacecraft,
+        objectives: Vec<Objective>,
+        tof: Duration,
+        segments: usize,
+        cost: CollocationObjective,
+    ) -> Result<Self, TargetingError> {
+        if initial.thruster.is_none() {
+            return Err(TargetingError::GuidanceError {
+                source: GuidanceError::NoThrustersDefined,
+            });
+        }
+
+        if segments == 0 {
+            return Err(TargetingError::VariableError {
+                msg: "collocation requires at least one segment".to_string(),
+            });
+        }
+
+        let mut dynamics = dynamics;
+        dynamics.guid_law = None;
+        let mut initial = initial;
+        initial.stm = None;
+
+        Ok(Self {
+            dynamics,
+            initial,
+            objectives,
+            path_constraints: Vec::new(),
+            tof,
+            segments,
+            cost,
+        })
+    }
+
+    /// Adds a path constraint to this problem
+    pub fn with_path_constraint(mut self, constraint: PathConstraint) -> Self {
+        self.path_constraints.push(constraint);
+        self
+    }
+
+    /// Solves the problem from a ballistic propagation of the initial state.
+    ///
+    /// Mass and time optimal problems are first solved as a minimum energy problem, whose solution is the initial guess of the actual problem.
+    pub fn solve(
+        &self,
+        solver: &dyn NlpSolver,
+        almanac: Arc<Almanac>,
+    ) -> Result<CollocationSolution, TargetingError> {
+        let (_, traj) = Propagator::default(self.dynamics.clone())
+            .with(self.initial, almanac.clone())
+            .for_duration_with_traj(self.tof)
+            .context(PropSnafu)?;
+
+        if self.cost == CollocationObjective::MinimumEnergy {
+            return self.solve_from(solver, &traj, almanac);
+        }
+
+        let mut min_energy = self.clone();
+        min_energy.cost = CollocationObjective::MinimumEnergy;
+        let guess = min_energy.solve_from(solver, &traj, almanac.clone())?;
+        info!("minimum energy initial guess: {guess}");
+
+        self.solve_from_solution(solver, &guess, almanac)
+    }
+
+    /// Solves the problem using the states of the provided trajectory at each node as the initial guess, with a zero throttle.
+    pub fn solve_from(
+        &self,
+        solver: &dyn NlpSolver,
+        guess: &Traj<Spacecraft>,
+        almanac: Arc<Almanac>,
+    ) -> Result<CollocationSolution, TargetingError> {
+        let transcription = Transcription::new(self, almanac)?;
+
+        let mut x0 = DVector::zeros(transcription.num_variables());
+        for k in 0..=self.segments {
+            let epoch = self.initial.epoch() + self.tof * (k as f64 / self.segments as f64);
+            let sc = guess.at(epoch).context(TargetingTrajSnafu)?;
+            x0.rows_mut(transcription.state_idx(k), 7)
+                .copy_from(&transcription.scale_state(&sc));
+        }
+        if self.cost == CollocationObjective::TimeOptimal {
+            x0[transcription.tof_idx()] = self.tof.to_seconds() / transcription.time_unit_s;
+        }
+
+        transcription.solve(solver, x0)
+    }
+
+    /// Solves the problem using the states, controls, and time of flight of a previous solution with the same number of segments as the initial guess.
+    pub fn solve_from_solution(
+        &self,
+        solver: &dyn NlpSolver,
+        guess: &CollocationSolution,
+        almanac: Arc<Almanac>,
+    ) -> Result<CollocationSolution, TargetingError> {
+        if guess.states.len() != self.segments + 1 {
+            return Err(TargetingError::VariableError {
+                msg: format!(
+                    "initial guess has {} segments but problem has {}",
+                    guess.states.len() - 1,
+                    self.segments
+                ),
+            });
+        }
+
+        let transcription = Transcription::new(self, almanac)?;
+
+        let mut x0 = DVector::zeros(transcription.num_variables());
+        for k in 0..=self.segments {
+            x0.rows_mut(transcription.state_idx(k), 7)
+                .copy_from(&transcription.scale_state(&guess.states[k]));
+            x0.rows_mut(transcription.control_idx(k), 3)
+                .copy_from(&guess.controls[k]);
+            if k < self.segments {
+                x0.rows_mut(transcription.midpoint_idx(k), 3)
+                    .copy_from(&guess.midpoint_controls[k]);
+            }
+        }
+        if self.cost == CollocationObjective::TimeOptimal {
+            x0[transcription.tof_idx()] = guess.tof.to_seconds() / transcription.time_unit_s;
+        }
+
+        transcription.solve(solver, x0)
+    }
+}
+
+/// Solution of a collocation problem
+#[derive(Clone, Debug)]
+pub struct CollocationSolution {
+    /// State of the spacecraft at each node
+    pub states: Vec<Spacecraft>,
+    /// Throttle vector at each node, whose norm is the throttle level
+    pub controls: Vec<Vector3<f64>>,
+    /// Throttle vector at the midpoint of each segment
+    pub midpoint_controls: Vec<Vector3<f64>>,
+    /// Optimal time of flight
+    pub tof: Duration,
+    /// Solution of the underlying nonlinear program
+    pub nlp: NlpSolution,
+}
+
+impl CollocationSolution {
+    /// Propellant consumed over the trajectory, in kg
+    pub fn prop_usage_kg(&self) -> f64 {
+        self.states[0].mass.prop_mass_kg - self.states[self.states.len() - 1].mass.prop_mass_kg
+    }
+
+    /// Largest throttle level of the solution
+    pub fn max_throttle(&self) -> f64 {
+        self.controls
+            .iter()
+            .chain(self.midpoint_controls.iter())
+            .map(|u| u.norm())
+            .fold(0.0, f64::max)
+    }
+
+    /// Builds a trajectory from the states at the nodes
+    pub fn to_traj(&self) -> Traj<Spacecraft> {
+        let mut traj = Traj::new();
+        traj.states = self.states.clone();
+        traj.finalize();
+        traj
+    }
+}
+
+impl fmt::Display for CollocationSolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Collocation solution with {} segments, TOF = {}, propellant usage = {:.6} kg, max throttle = {:.6}",
+            self.states.len() - 1,
+            self.tof,
+            self.prop_usage_kg(),
+            self.max_throttle()
+        )?;
+        write!(f, "{}", self.nlp)
+    }
+}
+
+/// Nonlinear program of a Hermite-Simpson transcription, in scaled units.
+struct Transcription<'a> {
+    problem: &'a HermiteSimpson,
+    almanac: Arc<Almanac>,
+    length_unit_km: f64,
+    time_unit_s: f64,
+    mass_unit_kg: f64,
+    /// Initial state, in scaled units
+    initial: DVector<f64>,
+}
+
+impl<'a> Transcription<'a> {
+    const STATE_SIZE: usize = 7;
+    const NODE_SIZE: usize = 10;
+    const THROTTLE_SMOOTHING: f64 = 1e-3;
+
+    #[allow(clippy::result_large_err)]
+    fn new(problem: &'a HermiteSimpson, almanac: Arc<Almanac>) -> Result<Self, TargetingError> {
+        let mu_km3_s2 = problem
+            .initial
+            .orbit
+            .frame
+            .mu_km3_s2()
+            .context(AstroPhysicsSnafu)
+            .context(AstroSnafu)?;
+
+        let length_unit_km = problem.initial.orbit.rmag_km();
+        let mut me = Self {
+            problem,
+            almanac,
+            length_unit_km,
+            time_unit_s: (length_unit_km.powi(3) / mu_km3_s2).sqrt(),
+            mass_unit_kg: problem.initial.mass_kg(),
+            initial: DVector::zeros(Self::STATE_SIZE),
+        };
+        me.initial = me.scale_state(&problem.initial);
+        Ok(me)
+    }
+
+    fn segments(&self) -> usize {
+        self.problem.segments
+    }
+
+    fn state_idx(&self, node: usize) -> usize {
+        node * Self::NODE_SIZE
+    }
+
+    fn control_idx(&self, node: usize) -> usize {
+        node * Self::NODE_SIZE + Self::STATE_SIZE
+    }
+
+    fn midpoint_idx(&self, segment: usize) -> usize {
+        (self.segments() + 1) * Self::NODE_SIZE + 3 * segment
+    }
+
+    fn tof_idx(&self) -> usize {
+        self.midpoint_idx(self.segments())
+    }
+
+    fn tof_s(&self, x: &DVector<f64>) -> f64 {
+        if self.problem.cost == CollocationObjective::TimeOptimal {
+            x[self.tof_idx()] * self.time_unit_s
+        } else {
+            self.problem.tof.to_seconds()
+        }
+    }
+
+    fn velocity_unit_km_s(&self) -> f64 {
+        self.length_unit_km / self.time_unit_s
+    }
+
+    fn scale_state(&self, sc: &Spacecraft) -> DVector<f64> {
+        let vu = self.velocity_unit_km_s();
+        DVector::from_vec(vec![
+            sc.orbit.radius_km.x / self.length_unit_km,
+            sc.orbit.radius_km.y / self.length_unit_km,
+            sc.orbit.radius_km.z / self.length_unit_km,
+            sc.orbit.velocity_km_s.x / vu,
+            sc.orbit.velocity_km_s.y / vu,
+            sc.orbit.velocity_km_s.z / vu,
+            sc.mass_kg() / self.mass_unit_kg,
+        ])
+    }
+
+    /// Rebuilds the spacecraft from the scaled state vector
+    fn unscale_state(&self, state: &[f64], epoch: Epoch) -> Spacecraft {
+        let vu = self.velocity_unit_km_s();
+        let mut sc = self.problem.initial;
+        sc.orbit.epoch = epoch;
+        sc.orbit.radius_km = Vector3::new(state[0], state[1], state[2]) * self.length_unit_km;
+        sc.orbit.velocity_km_s = Vector3::new(state[3], state[4], state[5]) * vu;
+        sc.mass.prop_mass_kg = state[6] * self.mass_unit_kg
+            - self.problem.initial.mass.dry_mass_kg
+            - self.problem.initial.mass.extra_mass_kg;
+        sc
+    }
+
+    fn spacecraft(&self, x: &DVector<f64>, node: usize, tof_s: f64) -> Spacecraft {
+        let epoch = self.problem.initial.epoch()
+            + (tof_s * node as f64 / self.segments() as f64) * Unit::Second;
+        let idx = self.state_idx(node);
+        self.unscale_state(&x.as_slice()[idx..idx + Self::STATE_SIZE], epoch)
+    }
+
+    fn control(&self, x: &DVector<f64>, idx: usize) -> Vector3<f64> {
+        Vector3::new(x[idx], x[idx + 1], x[idx + 2])
+    }
+
+    /// Solves the nonlinear program from the provided decision variables and rebuilds the solution.
+    #[allow(clippy::result_large_err)]
+    fn solve(
+        &self,
+        solver: &dyn NlpSolver,
+        x0: DVector<f64>,
+    ) -> Result<CollocationSolution, TargetingError> {
+        let nlp = solver.solve(self, x0)?;
+
+        let tof_s = self.tof_s(&nlp.x);
+        let mut states = Vec::with_capacity(self.segments() + 1);
+        let mut controls = Vec::with_capacity(self.segments() + 1);
+        let mut midpoint_controls = Vec::with_capacity(self.segments());
+        for k in 0..=self.segments() {
+            states.push(self.spacecraft(&nlp.x, k, tof_s));
+            controls.push(self.control(&nlp.x, self.control_idx(k)));
+            if k < self.segments() {
+                midpoint_controls.push(self.control(&nlp.x, self.midpoint_idx(k)));
+            }
+        }
+
+        Ok(CollocationSolution {
+            states,
+            controls,
+            midpoint_controls,
+            tof: tof_s * Unit::Second,
+            nlp,
+        })
+    }
+
+    /// Scaled state derivative, or NaN if the dynamics cannot be evaluated.
+    fn derivative(&self, state: &[f64], control: &Vector3<f64>, epoch: Epoch) -> DVector<f64> {
+        let sc = self.unscale_state(state, epoch);
+        let ballistic =
+            match self
+                .problem
+                .dynamics
+                .eom(0.0, &sc.to_vector(), &sc, self.almanac.clone())
+            {
+                Ok(d_x) => d_x,
+                Err(e) => {
+                    warn!("collocation dynamics failed at {epoch}: {e}");
+                    return DVector::from_element(Self::STATE_SIZE, f64::NAN);
+                }
+            };
+
+        let thruster = self.problem.initial.thruster.unwrap();
+        let mass_kg = state[6] * self.mass_unit_kg;
+        // Smoothed throttle level for the mass flow to be differentiable at zero thrust, which is where
+        // the coast arcs of a mass optimal solution lie. It is exact to within the smoothing at full throttle.
+        let throttle = (control.norm_squared() + Self::THROTTLE_SMOOTHING.powi(2)).sqrt()
+            - Self::THROTTLE_SMOOTHING;
+        let thrust_acc_km_s2 = control * thruster.mean_thrust_N() * 1e-3 / mass_kg;
+        let mass_rate_kg_s = if self.problem.dynamics.decrement_mass {
+            -throttle * thruster.mean_mass_flow_kg_s()
+        } else {
+            0.0
+        };
+
+        let vu = self.velocity_unit_km_s();
+        let mut d_x = DVector::zeros(Self::STATE_SIZE);
+        for i in 0..3 {
+            d_x[i] = ballistic[i] * self.time_unit_s / self.length_unit_km;
+            d_x[i + 3] = (ballistic[i + 3] + thrust_acc_km_s2[i]) * self.time_unit_s / vu;
+        }
+        d_x[6] = mass_rate_kg_s * self.time_unit_s / self.mass_unit_kg;
+        d_x
+    }
+}
+
+impl NlpProblem for Transcription<'_> {
+    fn num_variables(&self) -> usize {
+        let n = self.tof_idx();
+        if self.problem.cost == CollocationObjective::TimeOptimal {
+            n + 1
+        } else {
+            n
+        }
+    }
+
+    fn objective(&self, x: &DVector<f64>) -> f64 {
+        match self.problem.cost {
+            CollocationObjective::MinimumEnergy => {
+                let h = self.tof_s(x) / self.time_unit_s / self.segments() as f64;
+                (0..self.segments())
+                    .map(|k| {
+                        h / 6.0
+                            * (self.control(x, self.control_idx(k)).norm_squared()
+                                + 4.0 * self.control(x, self.midpoint_idx(k)).norm_squared()
+                                + self.control(x, self.control_idx(k + 1)).norm_squared())
+                    })
+                    .sum()
+            }
+            CollocationObjective::MassOptimal => -x[self.state_idx(self.segments()) + 6],
+            CollocationObjective::TimeOptimal => x[self.tof_idx()],
+        }
+    }
+
+    fn equalities(&self, x: &DVector<f64>) -> DVector<f64> {
+        let n = self.segments();
+        let ns = Self::STATE_SIZE;
+        let tof_s = self.tof_s(x);
+        let h = tof_s / self.time_unit_s / n as f64;
+        let epoch_at =
+            |tau: f64| self.problem.initial.epoch() + (tof_s * tau / n as f64) * Unit::Second;
+
+        let mut eq = DVector::zeros(ns * (n + 1) + self.problem.objectives.len());
+
+        // Initial state
+        let x_init = x.rows(self.state_idx(0), ns);
+        eq.rows_mut(0, ns).copy_from(&(x_init - &self.initial));
+
+        // Hermite-Simpson defects
+        let derivatives: Vec<DVector<f64>> = (0..=n)
+            .map(|k| {
+                let idx = self.state_idx(k);
+                self.derivative(
+                    &x.as_slice()[idx..idx + ns],
+                    &self.control(x, self.control_idx(k)),
+                    epoch_at(k as f64),
+                )
+            })
+            .collect();
+
+        for k in 0..n {
+            let x_k = x.rows(self.state_idx(k), ns);
+            let x_kp1 = x.rows(self.state_idx(k + 1), ns);
+            let x_mid = (x_k + x_kp1) * 0.5 + (&derivatives[k] - &derivatives[k + 1]) * (h / 8.0);
+            let f_mid = self.derivative(
+                x_mid.as_slice(),
+                &self.control(x, self.midpoint_idx(k)),
+                epoch_at(k as f64 + 0.5),
+            );
+            let defect =
+                x_kp1 - x_k - (&derivatives[k] + f_mid * 4.0 + &derivatives[k + 1]) * (h / 6.0);
+            eq.rows_mut(ns * (k + 1), ns).copy_from(&defect);
+        }
+
+        // Final objectives
+        let final_sc = self.spacecraft(x, n, tof_s);
+        for (i, obj) in self.problem.objectives.iter().enumerate() {
+            eq[ns * (n + 1) + i] = match final_sc.value(obj.parameter) {
+                Ok(achieved) => obj.assess_value(achieved).1 / obj.desired_value.abs().max(1.0),
+                Err(e) => {
+                    warn!("collocation cannot evaluate {obj}: {e}");
+                    f64::NAN
+                }
+            };
+        }
+
+        eq
+    }
+
+    fn inequalities(&self, x: &DVector<f64>) -> DVector<f64> {
+        let n = self.segments();
+        let tof_s = self.tof_s(x);
+        let mut ineq = Vec::new();
+
+        // Throttle level
+        for k in 0..=n {
+            ineq.push(self.control(x, self.control_idx(k)).norm_squared() - 1.0);
+            if k < n {
+                ineq.push(self.control(x, self.midpoint_idx(k)).norm_squared() - 1.0);
+            }
+        }
+
+        // Path constraints at each node
+        for k in 0..=n {
+            let sc = self.spacecraft(x, k, tof_s);
+            for constraint in &self.problem.path_constraints {
+                let value = sc.value(constraint.parameter).unwrap_or(f64::NAN);
+                let scale = [constraint.min, constraint.max]
+                    .iter()
+                    .filter(|bound| bound.is_finite())
+                    .fold(1.0_f64, |scale, bound| scale.max(bound.abs()));
+                if constraint.min.is_finite() {
+                    ineq.push((constraint.min - value) / scale);
+                }
+                if constraint.max.is_finite() {
+                    ineq.push((value - constraint.max) / scale);
+                }
+            }
+        }
+
+        // Strictly positive time of flight
+        if self.problem.cost == CollocationObjective::TimeOptimal {
+            ineq.push(1e-3 - x[self.tof_idx()]);
+        }
+
+        DVector::from_vec(ineq)
+    }
+}
+
+#[cfg(test)]
+mod ut_collocation {
+    use super::*;
+    use crate::cosmic::Mass;
+    use crate::dynamics::guidance::Thruster;
+    use crate::dynamics::OrbitalDynamics;
+    use crate::fixtures;
+    use crate::md::opti::nlp::Sqp;
+
+    fn setup() -> (HermiteSimpson, Spacecraft, Arc<Almanac>) {
+        let almanac = fixtures::almanac();
+        let orbit = fixtures::keplerian(7_000.0, 1e-4, 28.5, 30.0, 45.0, 10.0);
+
+        let sc = Spacecraft::builder()
+            .orbit(orbit)
+            .mass(Mass::from_dry_and_prop_masses(400.0, 100.0))
+            .thruster(Thruster::new(5.0, 1500.0))
+            .build();
+
+        let dynamics = SpacecraftDynamics::new(OrbitalDynamics::two_body());
+        let tof = 30 * Unit::Minute;
+
+        // The target is reached by an along track impulse of 1.5 m/s, which the low thrust engine can replicate.
+        let mut kicked = sc;
+        let vhat = kicked.orbit.velocity_km_s.normalize();
+        kicked.orbit.velocity_km_s += vhat * 1.5e-3;
+        let target = Propagator::default(dynamics.clone())
+            .with(kicked, almanac.clone())
+            .for_duration(tof)
+            .unwrap();
+
+        let objectives = vec![
+            Objective::within_tolerance(StateParameter::X, target.orbit.radius_km.x, 1e-3),
+            Objective::within_tolerance(StateParameter::Y, target.orbit.radius_km.y, 1e-3),
+            Objective::within_tolerance(StateParameter::Z, target.orbit.radius_km.z, 1e-3),
+            Objective::within_tolerance(StateParameter::VX, target.orbit.velocity_km_s.x, 1e-6),
+            Objective::within_tolerance(StateParameter::VY, target.orbit.velocity_km_s.y, 1e-6),
+            Objective::within_tolerance(StateParameter::VZ, target.orbit.velocity_km_s.z, 1e-6),
+        ];
+
+        let problem = HermiteSimpson::new(
+            dynamics,
+            sc,
+            objectives,
+            tof,
+            6,
+            CollocationObjective::MinimumEnergy,
+        )
+        .unwrap();
+
+        (problem, target, almanac)
+    }
+
+    #[test]
+    fn minimum_energy_transfer() {
+        let (problem, target, almanac) = setup();
+
+        let sol = problem.solve(&Sqp::default(), almanac).unwrap();
+        println!("{sol}");
+
+        let final_sc = sol.states.last().unwrap();
+        assert!((final_sc.orbit.radius_km - target.orbit.radius_km).norm() < 1e-3);
+        assert!((final_sc.orbit.velocity_km_s - target.orbit.velocity_km_s).norm() < 1e-6);
+        assert!(sol.max_throttle() <= 1.0 + 1e-6);
+        assert!(sol.prop_usage_kg() > 0.0);
+        assert!(sol.nlp.max_violation < 1e-8);
+    }
+
+    #[test]
+    fn mass_optimal_with_path_constraint() {
+        let (mut problem, target, almanac) = setup();
+        problem.cost = CollocationObjective::MassOptimal;
+        let problem = problem.with_path_constraint(PathConstraint::new(
+            StateParameter::Rmag,
+            6_990.0,
+            f64::INFINITY,
+        ));
+
+        let sol = problem
+            .solve(
+                &Sqp {
+                    max_iterations: 1000,
+                    ..Default::default()
+                },
+                almanac,
+            )
+            .unwrap();
+        println!("{sol}");
+
+        let final_sc = sol.states.last().unwrap();
+        assert!((final_sc.orbit.radius_km - target.orbit.radius_km).norm() < 1e-3);
+        for sc in &sol.states {
+            assert!(sc.orbit.rmag_km() >= 6_990.0 - 1e-6);
+        }
+        assert!(sol.max_throttle() <= 1.0 + 1e-6);
+    }
+}
@@ -17,418 +17,311 @@
 */
 
 use rayon::prelude::*;
-use snafu::ResultExt;
+use snafu::{ensure, ResultExt};
 
-use crate::dynamics::guidance::{ra_dec_from_unit_vector, GuidanceError, LocalFrame, Mnvr};
+use crate::dynamics::guidance::{
+    ra_dec_from_unit_vector, GuidanceError, LocalFrame, Maneuver, MnvrRepr,
+};
 use crate::linalg::{SMatrix, SVector, Vector3};
-use crate::md::objective::Objective;
-use crate::md::{prelude::*, PropSnafu, TargetingError};
-use crate::md::{StateParameter, TargetingTrajSnafu};
+use crate::md::{prelude::*, NotFiniteSnafu, PropSnafu, TargetingError};
 pub use crate::md::{Variable, Vary};
 use crate::polyfit::CommonPolynomial;
-use crate::propagators::error_ctrl::ErrorCtrl;
 use crate::pseudo_inverse;
 use crate::time::TimeUnits;
-use core::f64::consts::TAU;
 
-impl<'a, E: ErrorCtrl> Optimizer<'a, E, 3, 6> {
-    /// Create a new Targeter which will apply an impulsive delta-v correction.
-    /// The `spacecraft` _must_ be the spacecraft BEFORE the Δv is applied
+/// Tolerance on the position error at the end of the finite burn, in km
+const POSITION_TOL_KM: f64 = 1e-3;
+/// Tolerance on the velocity error at the end of the finite burn, in km/s
+const VELOCITY_TOL_KM_S: f64 = 1e-6;
+
+impl Targeter<'_, 6, 6> {
+    /// Converts an impulsive maneuver into an equivalent finite burn of the thruster of the spacecraft.
+    ///
+    /// The initial guess of the finite burn is centered on the epoch of the impulsive maneuver, along its direction, and lasts as long
+    /// as needed for the rocket equation to provide the same delta-v. The start epoch, the duration, and the in-plane and out-of-plane
+    /// angles (and their rates) of the burn are then corrected with a Newton Raphson iteration, whose Jacobian is computed via finite
+    /// differencing, until the state after the burn matches the state after the impulsive maneuver.
+    ///
+    /// The `spacecraft` _must_ be the spacecraft BEFORE the Δv is applied, and `dv_km_s` is expressed in its inertial frame.
+    /// The dynamics of the propagator should decrement the mass of the spacecraft so that the mass flow of the burn is accounted for.
+    #[allow(clippy::result_large_err)]
     pub fn convert_impulsive_mnvr(
         spacecraft: Spacecraft,
-        dv: Vector3<f64>,
-        prop: &'a Propagator<'a, SpacecraftDynamics, E>,
-    ) -> Result<Mnvr, TargetingError> {
-        if spacecraft.thruster.is_none() {
-            return Err(TargetingError::GuidanceError {
-                source: GuidanceError::NoThrustersDefined,
-            });
-        }
+        dv_km_s: Vector3<f64>,
+        prop: &Propagator<SpacecraftDynamics>,
+        almanac: Arc<Almanac>,
+    ) -> Result<Maneuver, TargetingError> {
+        let thruster = match spacecraft.thruster {
+            Some(thruster) => thruster,
+            None => {
+                return Err(TargetingError::GuidanceError {
+                    source: GuidanceError::NoThrustersDefined,
+                })
+            }
+        };
+        ensure!(dv_km_s.norm() > f64::EPSILON, NotFiniteSnafu);
 
         /* ************************* */
         /* Compute the initial guess */
         /* ************************* */
-        // Calculate the u, dot u (=0) and ddot u from this state
-        let u = dv / dv.norm();
-        let r = spacecraft.orbit.radius_km;
-        let rmag = spacecraft.orbit.rmag_km();
-        let u_ddot = (3.0 * spacecraft.orbit.frame.gm() / rmag.powi(5))
-            * (r.dot(&u) * r - (r.dot(&u).powi(2) * u));
-        // Compute the control rates at the time of the impulsive maneuver (tdv)
-        let (alpha_tdv, delta_tdv) = ra_dec_from_unit_vector(u);
-        let (alpha_ddot_tdv, delta_ddot_tdv) = ra_dec_from_unit_vector(u_ddot);
-        // Build the maneuver polynomial angles from these
-        let alpha_inplane_radians = CommonPolynomial::Quadratic(0.0, 0.0, alpha_tdv);
-        let beta_outofplane_radians = CommonPolynomial::Quadratic(0.0, 0.0, 0.0);
-
-        // Compute a few thruster parameters
-        let thruster = spacecraft.thruster.as_ref().unwrap();
         let v_exhaust_m_s = thruster.exhaust_velocity_m_s();
-
-        let delta_tfb = ((v_exhaust_m_s * spacecraft.mass_kg()) / thruster.thrust_N)
-            * (1.0 - (-dv.norm() * 1e3 / v_exhaust_m_s).exp());
+        let burn_duration_s = ((v_exhaust_m_s * spacecraft.mass_kg()) / thruster.mean_thrust_N())
+            * (1.0 - (-dv_km_s.norm() * 1e3 / v_exhaust_m_s).exp());
+        let (alpha, delta) = ra_dec_from_unit_vector(dv_km_s / dv_km_s.norm());
 
         let impulse_epoch = spacecraft.epoch();
-        // Build the estimated maneuver
-        let mut mnvr = Mnvr {
-            start: impulse_epoch - 0.5 * delta_tfb * Unit::Second,
-            end: impulse_epoch + 0.5 * delta_tfb * Unit::Second,
+        let mut mnvr = Maneuver {
+            start: impulse_epoch - 0.5 * burn_duration_s.seconds(),
+            end: impulse_epoch + 0.5 * burn_duration_s.seconds(),
             thrust_prct: 1.0,
-            alpha_inplane_radians,
-            delta_outofplane_radians: beta_outofplane_radians,
+            representation: MnvrRepr::Angles {
+                azimuth: CommonPolynomial::Quadratic(0.0, 0.0, alpha),
+                elevation: CommonPolynomial::Quadratic(0.0, 0.0, delta),
+            },
             frame: LocalFrame::Inertial,
         };
 
-        println!("INITIAL GUESS\n{mnvr}\n\n");
+        info!("Initial guess of the finite burn: {mnvr}");
 
         /* ************************ */
         /* Compute the nominal traj */
         /* ************************ */
-        // Pre-traj is the trajectory _before_ the impulsive maneuver
-        let pre_sc = prop
-            .with(spacecraft)
-            .for_duration(-60.0 * Unit::Minute)
-            .context(PropSnafu)?;
-        let (_, pre_traj) = prop
-            .with(pre_sc)
-            .until_epoch_with_traj(impulse_epoch)
+        // The burn is simulated between two fixed epochs, one burn duration before and after the impulsive maneuver, so that the
+        // objectives do not depend on the start and end epochs of the burn.
+        let correction_epoch = impulse_epoch - burn_duration_s.seconds();
+        let achievement_epoch = impulse_epoch + burn_duration_s.seconds();
+        let xi = prop
+            .with(spacecraft, almanac.clone())
+            .until_epoch(correction_epoch)
             .context(PropSnafu)?;
-        // Post-traj is the trajectory _after_ the impulsive maneuver
-        let (_, post_traj) = prop
-            .with(spacecraft.with_dv(dv))
-            .for_duration_with_traj(60.0 * Unit::Minute)
+        let xf_desired = prop
+            .with(spacecraft.with_dv_km_s(dv_km_s), almanac.clone())
+            .until_epoch(achievement_epoch)
             .context(PropSnafu)?;
 
-        println!("{pre_traj}");
-        println!("{post_traj}");
+        // The velocity errors are scaled by the burn duration to be commensurate with the position errors.
+        let velocity_scale_s = burn_duration_s;
+        let tolerance = POSITION_TOL_KM.max(VELOCITY_TOL_KM_S * velocity_scale_s);
 
-        // Now let's setup the optimizer.
         let variables = [
-            Variable::from(Vary::MnvrAlpha).with_initial_guess(alpha_tdv),
-            Variable::from(Vary::MnvrAlphaDot),
-            Variable::from(Vary::MnvrAlphaDDot).with_initial_guess(alpha_ddot_tdv),
-            Variable::from(Vary::MnvrDelta).with_initial_guess(delta_tdv),
-            Variable::from(Vary::MnvrDeltaDot),
-            Variable::from(Vary::MnvrDeltaDDot).with_initial_guess(delta_ddot_tdv),
-            // Variable::from(Vary::StartEpoch),
-            // Variable::from(Vary::Duration),
+            Variable::from(Vary::MnvrAlpha).with_pert(1e-6),
+            Variable::from(Vary::MnvrAlphaDot).with_pert(1e-6 / burn_duration_s),
+            Variable::from(Vary::MnvrDelta).with_pert(1e-6),
+            Variable::from(Vary::MnvrDeltaDot).with_pert(1e-6 / burn_duration_s),
+            Variable::from(Vary::StartEpoch).with_pert(1e-2),
+            Variable::from(Vary::Duration).with_pert(1e-2),
         ];
 
-        const NUM_VARIABLES: usize = 6;
+        let error = |mnvr: &Maneuver| -> Result<SVector<f64, 6>, TargetingError> {
+            let xf = finite_burn(xi, mnvr, achievement_epoch, prop, almanac.clone())?;
+            let mut err = SVector::<f64, 6>::zeros();
+            err.fixed_rows_mut::<3>(0)
+                .copy_from(&(xf_desired.orbit.radius_km - xf.orbit.radius_km));
+            err.fixed_rows_mut::<3>(3).copy_from(
+                &((xf_desired.orbit.velocity_km_s - xf.orbit.velocity_km_s) * velocity_scale_s),
+            );
+            Ok(err)
+        };
 
-        // The correction stores, in order, alpha_0, \dot{alpha_0}, \ddot{alpha_0}, beta_0, \dot{beta_0}, \ddot{beta_0}
+        let max_iter = 50;
         let mut prev_err_norm = f64::INFINITY;
-        // The objectives will be updated if the duration of the maneuver is changed
-        let mut sc_x0 = pre_traj.at(mnvr.start).context(TargetingTrajSnafu)?;
-        let mut sc_xf_desired = post_traj.at(mnvr.end).context(TargetingTrajSnafu)?;
-        let mut objectives = [
-            Objective {
-                parameter: StateParameter::X,
-                desired_value: sc_xf_desired.orbit.x_km,
-                tolerance: 1e-3,
-                additive_factor: 0.0,
-                multiplicative_factor: 1.0,
-            },
-            Objective {
-                parameter: StateParameter::Y,
-                desired_value: sc_xf_desired.orbit.y_km,
-                tolerance: 1e-3,
-                additive_factor: 0.0,
-                multiplicative_factor: 1.0,
-            },
-            Objective {
-                parameter: StateParameter::Z,
-                desired_value: sc_xf_desired.orbit.z_km,
-                tolerance: 1e-3,
-                additive_factor: 0.0,
-                multiplicative_factor: 1.0,
-            },
-            Objective {
-                parameter: StateParameter::VX,
-                desired_value: sc_xf_desired.orbit.vx_km_s,
-                tolerance: 1e-3,
-                additive_factor: 0.0,
-                multiplicative_factor: 1e-3,
-            },
-            Objective {
-                parameter: StateParameter::VY,
-                desired_value: sc_xf_desired.orbit.vy_km_s,
-                tolerance: 1e-3,
-                additive_factor: 0.0,
-                multiplicative_factor: 1e-3,
-            },
-            Objective {
-                parameter: StateParameter::VZ,
-                desired_value: sc_xf_desired.orbit.vz_km_s,
-                tolerance: 1e-3,
-                additive_factor: 0.0,
-                multiplicative_factor: 1e-3,
-            },
-        ];
-        const NUM_OBJ: usize = 6;
-
-        // Determine padding in debugging info
-        // For the width, we find the largest desired values and multiply it by the order of magnitude of its tolerance
-        let max_obj_val = objectives
-            .iter()
-            .map(|obj| {
-                obj.desired_value.abs().ceil() as i32
-                    * 10_i32.pow(obj.tolerance.abs().log10().ceil() as u32)
-            })
-            .max()
-            .unwrap();
-
-        let max_obj_tol = objectives
-            .iter()
-            .map(|obj| obj.tolerance.log10().abs().ceil() as usize)
-            .max()
-            .unwrap();
-
-        let width = f64::from(max_obj_val).log10() as usize + 2 + max_obj_tol;
-
-        let max_iter = 10;
 
         for it in 0..=max_iter {
-            dbg!(it);
-            // Propagate with the estimated maneuver until the end of the maneuver
-            let mut prop = prop.clone();
-            prop.set_tolerance(1e-3);
-            prop.dynamics = prop.dynamics.with_guidance_law(Arc::new(mnvr));
-            let sc_xf_achieved = prop
-                .with(sc_x0.with_guidance_mode(GuidanceMode::Thrust))
-                .until_epoch(mnvr.end)
-                .context(PropSnafu)?;
-
-            println!("#{it} INIT: {sc_x0}\nAchieved: {sc_xf_achieved}\nDesired: {sc_xf_desired}");
-
-            // Build the error vector
-            let mut err_vector = SVector::<f64, NUM_OBJ>::zeros();
-            let mut converged = true;
-            // Build debugging information
-            let mut objmsg = Vec::with_capacity(objectives.len());
-
-            // The Jacobian includes the sensitivity of each objective with respect to each variable for the whole trajectory.
-            // As such, it includes the STM of that variable for the whole propagation arc.
-            let mut jac = SMatrix::<f64, NUM_OBJ, NUM_VARIABLES>::zeros();
-
-            // For each objective, we'll perturb the variables to compute the Jacobian with finite differencing.
-            for (i, obj) in objectives.iter().enumerate() {
-                let achieved = sc_xf_achieved.value(obj.parameter).unwrap();
-                // Check if this objective has been achieved
-                let (ok, param_err) = obj.assess_raw(achieved);
-                if !ok {
-                    converged = false;
-                }
-                err_vector[i] = param_err;
-
-                objmsg.push(format!(
-                    "\t{:?}: achieved = {:>width$.prec$}\t desired = {:>width$.prec$}\t scaled error = {:>width$.prec$}",
-                    obj.parameter,
-                    achieved,
-                    obj.desired_value,
-                    param_err, width=width, prec=max_obj_tol
-                ));
-
-                let mut pert_calc: Vec<_> = variables
-                    .iter()
-                    .enumerate()
-                    .map(|(j, var)| (j, var, 0.0_f64))
-                    .collect();
-
-                pert_calc.par_iter_mut().for_each(|(_, var, jac_val)| {
-                    let mut this_prop = prop.clone();
-                    let mut this_mnvr = mnvr;
-
-                    // Modify the burn itself
-                    let pert = var.perturbation;
-                    // Modify the maneuver, but do not change the epochs of the maneuver unless the change is greater than one millisecond
-                    match var.component {
-                        Vary::Duration => this_mnvr.end = mnvr.start + pert.seconds(),
-                        Vary::EndEpoch => this_mnvr.end = mnvr.end + pert.seconds(),
-                        Vary::StartEpoch => this_mnvr.start = mnvr.start + pert.seconds(),
-                        Vary::MnvrAlpha | Vary::MnvrAlphaDot | Vary::MnvrAlphaDDot => {
-                            this_mnvr.alpha_inplane_radians = mnvr
-                                .alpha_inplane_radians
-                                .add_val_in_order(pert, var.component.vec_index())
-                                .unwrap();
-                        }
-                        Vary::MnvrDelta | Vary::MnvrDeltaDot | Vary::MnvrDeltaDDot => {
-                            this_mnvr.delta_outofplane_radians = mnvr
-                                .delta_outofplane_radians
-                                .add_val_in_order(pert, var.component.vec_index())
-                                .unwrap();
-                        }
-                        _ => unreachable!(),
-                    }
-
-                    // Grab the nominal start time from the pre_dv trajectory
-                    let this_sc_x0 = pre_traj.at(this_mnvr.start).unwrap();
-
-                    this_prop.dynamics = this_prop.dynamics.with_guidance_law(Arc::new(this_mnvr));
-                    let this_sc_xf_achieved = this_prop
-                        .with(this_sc_x0.with_guidance_mode(GuidanceMode::Thrust))
-                        .until_epoch(this_mnvr.end)
-                        .unwrap();
+            let err = error(&mnvr)?;
 
-                    let this_achieved = this_sc_xf_achieved.value(obj.parameter).unwrap();
-                    *jac_val = (this_achieved - achieved) / var.perturbation;
-                });
-
-                for (j, _, jac_val) in &pert_calc {
-                    jac[(i, *j)] = *jac_val;
-                }
-            }
+            debug!("#{it} error (norm = {:.3e}): {err}", err.norm());
 
-            if converged {
-                print!("\n\nI can't believe we converged\n\n");
+            if err.norm() < tolerance {
+                let xf = finite_burn(xi, &mnvr, mnvr.end, prop, almanac.clone())?;
+                info!(
+                    "Impulsive to finite burn conversion -- CONVERGED in {it} iterations: {mnvr}"
+                );
+                info!(
+                    "Finite burn consumed {:.3} kg of propellant",
+                    xi.mass_kg() - xf.mass_kg()
+                );
                 return Ok(mnvr);
-                // let conv_dur = Instant::now() - start_instant;
-                // let mut corrected_state = xi_start;
-
-                // let mut state_correction = Vector6::<f64>::zeros();
-                // for (i, var) in self.variables.iter().enumerate() {
-                //     state_correction[var.component.vec_index()] += total_correction[i];
-                // }
-                // // Now, let's apply the correction to the initial state
-                // if let Some(frame) = self.correction_frame {
-                //     let dcm_vnc2inertial = corrected_state
-                //         .orbit
-                //         .dcm_from_traj_frame(frame)
-                //         .unwrap()
-                //         .transpose();
-                //     let velocity_correction =
-                //         dcm_vnc2inertial * state_correction.fixed_rows::<3>(3);
-                //     corrected_state.orbit.apply_dv_km_s(velocity_correction);
-                // } else {
-                //     corrected_state.orbit = corrected_state.orbit + state_correction;
-                // }
-
-                // let sol = TargeterSolution {
-                //     corrected_state,
-                //     achieved_state: xi_start.with_orbit(xf),
-                //     correction: total_correction,
-                //     computation_dur: conv_dur,
-                //     variables: self.variables.clone(),
-                //     achieved_errors: err_vector,
-                //     achieved_objectives: self.objectives.clone(),
-                //     iterations: it,
-                // };
-                // // Log success as info
-                // if it == 1 {
-                //     info!("Targeter -- CONVERGED in 1 iteration");
-                // } else {
-                //     info!("Targeter -- CONVERGED in {} iterations", it);
-                // }
-                // for obj in &objmsg {
-                //     info!("{}", obj);
-                // }
-                // return Ok(sol);
             }
 
-            // We haven't converged yet, so let's build t
-            if (err_vector.norm() - prev_err_norm).abs() < 1e-10 {
+            if (err.norm() - prev_err_norm).abs() < 1e-10 {
                 return Err(TargetingError::CorrectionIneffective {
-                    action: "this is not expected to work yet",
-                    cur_val: err_vector.norm(),
                     prev_val: prev_err_norm,
+                    cur_val: err.norm(),
+                    action: "impulsive to finite burn conversion",
                 });
             }
-            prev_err_norm = err_vector.norm();
-            dbg!(prev_err_norm);
+            prev_err_norm = err.norm();
 
-            debug!("Jacobian {}", jac);
-
-            // Perform the pseudo-inverse if needed, else just inverse
-            let jac_inv = pseudo_inverse!(&jac)?;
+            // Compute the Jacobian by perturbing each variable of the maneuver.
+            let columns = variables
+                .par_iter()
+                .map(|var| -> Result<SVector<f64, 6>, TargetingError> {
+                    let this_mnvr = perturbed(&mnvr, var.component, var.perturbation);
+                    Ok((error(&this_mnvr)? - err) / var.perturbation)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
 
-            debug!("Inverse Jacobian {}", jac_inv);
+            // The errors are the desired minus the achieved values, so the Jacobian of the achieved values is the opposite.
+            let jac = -SMatrix::<f64, 6, 6>::from_columns(&columns);
 
-            let mut delta = jac_inv * err_vector;
+            debug!("Jacobian {jac}");
 
-            debug!("Error vector: {}\nRaw correction: {}", err_vector, delta);
+            let jac_inv = pseudo_inverse!(&jac)?;
+            let delta = jac_inv * err;
 
-            // And finally apply it to the maneuver
-            let mut update_obj = false;
             for (i, var) in variables.iter().enumerate() {
-                // Choose the minimum step between the provided max step and the correction.
-                if delta[i].abs() > var.max_step.abs() {
-                    delta[i] = var.max_step.abs() * delta[i].signum();
-                } else if delta[i] > var.max_value {
-                    delta[i] = var.max_value;
-                } else if delta[i] < var.min_value {
-                    delta[i] = var.min_value;
-                }
-
-                println!(
-                    "Correction {:?} (element {}): {}",
-                    var.component, i, delta[i]
-                );
-
-                let corr = delta[i];
-
-                // Modify the maneuver, but do not change the epochs of the maneuver unless the change is greater than one millisecond
-                match var.component {
-                    Vary::Duration => {
-                        mnvr.end = mnvr.start + corr.seconds();
-                        update_obj = true;
-                    }
-                    Vary::EndEpoch => {
-                        mnvr.end += corr.seconds();
-                        update_obj = true;
-                    }
-                    Vary::StartEpoch => {
-                        mnvr.start += corr.seconds();
-                        update_obj = true;
+                // Limit the change of the epochs to keep the linearization valid.
+                let corr = match var.component {
+                    Vary::StartEpoch | Vary::Duration => {
+                        delta[i].clamp(-0.1 * burn_duration_s, 0.1 * burn_duration_s)
                     }
-                    Vary::MnvrAlpha | Vary::MnvrAlphaDot | Vary::MnvrAlphaDDot => {
-                        mnvr.alpha_inplane_radians = mnvr
-                            .alpha_inplane_radians
-                            .add_val_in_order(corr % TAU, var.component.vec_index())
-                            .unwrap();
-                    }
-                    Vary::MnvrDelta | Vary::MnvrDeltaDot | Vary::MnvrDeltaDDot => {
-                        mnvr.delta_outofplane_radians = mnvr
-                            .delta_outofplane_radians
-                            .add_val_in_order(corr % TAU, var.component.vec_index())
-                            .unwrap();
-                    }
-                    _ => unreachable!(),
-                }
+                    _ => delta[i],
+                };
+                mnvr = perturbed(&mnvr, var.component, corr);
             }
+        }
 
-            // Log progress to debug
-            info!("Targeter -- Iteration #{it}");
-            for obj in &objmsg {
-                println!("{obj}");
-            }
+        Err(TargetingError::TooManyIterations {
+            iterations: max_iter,
+        })
+    }
+}
 
-            println!("New mnvr {mnvr}");
-            if update_obj {
-                sc_x0 = pre_traj.at(mnvr.start).context(TargetingTrajSnafu)?;
-                sc_xf_desired = post_traj.at(mnvr.end).context(TargetingTrajSnafu)?;
-                objectives = [
-                    Objective::within_tolerance(StateParameter::X, sc_xf_desired.orbit.x_km, 1e-3),
-                    Objective::within_tolerance(StateParameter::Y, sc_xf_desired.orbit.y_km, 1e-3),
-                    Objective::within_tolerance(StateParameter::Z, sc_xf_desired.orbit.z_km, 1e-3),
-                    Objective::within_tolerance(
-                        StateParameter::VX,
-                        sc_xf_desired.orbit.vx_km_s,
-                        1e-3,
-                    ),
-                    Objective::within_tolerance(
-                        StateParameter::VY,
-                        sc_xf_desired.orbit.vy_km_s,
-                        1e-3,
-                    ),
-                    Objective::within_tolerance(
-                        StateParameter::VZ,
-                        sc_xf_desired.orbit.vz_km_s,
-                        1e-3,
-                    ),
-                ];
+/// Propagates from the initial state until the provided epoch, executing the finite burn on the way.
+#[allow(clippy::result_large_err)]
+fn finite_burn(
+    xi: Spacecraft,
+    mnvr: &Maneuver,
+    epoch: Epoch,
+    prop: &Propagator<SpacecraftDynamics>,
+    almanac: Arc<Almanac>,
+) -> Result<Spacecraft, TargetingError> {
+    let pre_mnvr = prop
+        .with(xi, almanac.clone())
+        .until_epoch(mnvr.start)
+        .context(PropSnafu)?;
+
+    // Add this maneuver to the dynamics, making sure that we don't over-step it
+    let mut mnvr_prop = prop.clone();
+    mnvr_prop.dynamics = mnvr_prop.dynamics.with_guidance_law(Arc::new(*mnvr));
+    mnvr_prop.set_max_step(mnvr.duration());
+    let post_mnvr = mnvr_prop
+        .with(
+            pre_mnvr.with_guidance_mode(GuidanceMode::Thrust),
+            almanac.clone(),
+        )
+        .until_epoch(mnvr.end)
+        .context(PropSnafu)?;
+
+    prop.with(post_mnvr.with_guidance_mode(GuidanceMode::Coast), almanac)
+        .until_epoch(epoch)
+        .context(PropSnafu)
+}
+
+/// Returns a copy of the maneuver where the provided component is changed by the provided value.
+fn perturbed(mnvr: &Maneuver, component: Vary, value: f64) -> Maneuver {
+    let mut this_mnvr = *mnvr;
+    if let MnvrRepr::Angles { azimuth, elevation } = mnvr.representation {
+        match component {
+            Vary::StartEpoch => {
+                this_mnvr.start += value.seconds();
+                this_mnvr.end += value.seconds();
+            }
+            Vary::Duration => this_mnvr.end += value.seconds(),
+            Vary::MnvrAlpha | Vary::MnvrAlphaDot | Vary::MnvrAlphaDDot => {
+                this_mnvr.representation = MnvrRepr::Angles {
+                    azimuth: azimuth
+                        .add_val_in_order(value, component.vec_index())
+                        .unwrap(),
+                    elevation,
+                };
             }
+            Vary::MnvrDelta | Vary::MnvrDeltaDot | Vary::MnvrDeltaDDot => {
+                this_mnvr.representation = MnvrRepr::Angles {
+                    azimuth,
+                    elevation: elevation
+                        .add_val_in_order(value, component.vec_index())
+                        .unwrap(),
+                };
+            }
+            _ => unreachable!(),
         }
+    }
+    this_mnvr
+}
 
-        unreachable!();
+#[cfg(test)]
+mod ut_convert_impulsive {
+    use super::*;
+    use crate::cosmic::Orbit;
+    use crate::dynamics::guidance::Thruster;
+    use crate::fixtures;
+    use anise::structure::spacecraft::Mass;
+
+    #[test]
+    fn tcm_to_finite_burn() {
+        let almanac = fixtures::almanac();
+        let eme2k = fixtures::eme2k();
+        let epoch = Epoch::from_gregorian_utc_at_midnight(2023, 3, 1);
+        let orbit =
+            Orbit::try_keplerian(24_396.0, 0.1, 7.0, 45.0, 30.0, 60.0, epoch, eme2k).unwrap();
+
+        let sc = Spacecraft::builder()
+            .orbit(orbit)
+            .mass(Mass::from_dry_and_prop_masses(400.0, 100.0))
+            .thruster(Thruster::new(1.0, 300.0))
+            .build();
+
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+
+        // A 5 m/s maneuver mostly along the velocity, with some out-of-plane component
+        let dv_km_s =
+            orbit.velocity_km_s.normalize() * 4e-3 + orbit.hvec().unwrap().normalize() * 3e-3;
+
+        let mnvr = Targeter::convert_impulsive_mnvr(sc, dv_km_s, &prop, almanac.clone()).unwrap();
+        println!("{mnvr}");
+
+        // The burn is centered on the impulsive maneuver and lasts about as long as the rocket equation predicts.
+        let rocket_s =
+            300.0 * 9.806_65 * 500.0 / 1.0 * (1.0 - (-5.0_f64 / (300.0 * 9.806_65)).exp());
+        assert!((mnvr.duration().to_seconds() - rocket_s).abs() < 0.01 * rocket_s);
+        assert!((mnvr.start + mnvr.duration() * 0.5 - epoch).abs() < 0.1 * mnvr.duration());
+
+        // Check that the finite burn leads to the same state as the impulsive maneuver.
+        let later = epoch + Unit::Hour * 2;
+        let xf_impulsive = prop
+            .with(sc.with_dv_km_s(dv_km_s), almanac.clone())
+            .until_epoch(later)
+            .unwrap();
+
+        let xi = prop
+            .with(sc, almanac.clone())
+            .until_epoch(mnvr.start)
+            .unwrap();
+        let xf_finite = finite_burn(xi, &mnvr, later, &prop, almanac).unwrap();
+
+        let dr_km = (xf_finite.orbit.radius_km - xf_impulsive.orbit.radius_km).norm();
+        let dv_m_s =
+            (xf_finite.orbit.velocity_km_s - xf_impulsive.orbit.velocity_km_s).norm() * 1e3;
+        println!("position error: {dr_km:.3e} km\tvelocity error: {dv_m_s:.3e} m/s");
+        assert!(dr_km < 1e-2);
+        assert!(dv_m_s < 1e-2);
+
+        // The mass flow of the burn is accounted for.
+        let prop_used_kg = sc.mass_kg() - xf_finite.mass_kg();
+        let rocket_kg = 500.0 * (1.0 - (-5.0_f64 / (300.0 * 9.806_65)).exp());
+        assert!(
+            (prop_used_kg - rocket_kg).abs() < 0.01 * rocket_kg,
+            "{prop_used_kg}"
+        );
+
+        // A spacecraft without thruster cannot convert an impulsive maneuver.
+        assert!(Targeter::convert_impulsive_mnvr(
+            Spacecraft::from(orbit),
+            dv_km_s,
+            &prop,
+            fixtures::almanac()
+        )
+        .is_err());
     }
 }
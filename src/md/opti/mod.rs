@@ -16,16 +16,41 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+/// Hermite-Simpson direct collocation of low thrust optimal control problems.
+pub mod collocation;
+/// Conversion of impulsive maneuvers into equivalent finite burns.
+pub mod convert_impulsive;
+/// Targeting of impulsive corrections at several epochs in a single problem.
+pub mod multi_maneuver;
 pub mod multipleshooting;
-pub use multipleshooting::{ctrlnodes, multishoot};
+pub use multipleshooting::{corrector, ctrlnodes, multishoot};
+/// Nonlinear programming interface and a built-in sequential quadratic programming solver.
+pub mod nlp;
 /// Uses a [Newton Raphson](https://en.wikipedia.org/wiki/Newton%27s_method_in_optimization) method where the Jacobian is computed via finite differencing.
 pub mod raphson_finite_diff;
 /// Uses a [Newton Raphson](https://en.wikipedia.org/wiki/Newton%27s_method_in_optimization) method where the Jacobian is computed via hyperdual numbers.
 pub mod raphson_hyperdual;
+/// Uses a [Newton Raphson](https://en.wikipedia.org/wiki/Newton%27s_method_in_optimization) method where the Jacobian is computed from the state transition matrix.
+pub mod raphson_stm;
 pub mod solution;
 pub mod target_variable;
 pub mod targeter;
 
+use crate::md::TargetingError;
+use crate::propagators::PropagationError;
+
+/// Returns the result of the propagation of a perturbed state, or None if the propagator was cancelled, in which case
+/// the targeter reports the cancellation once all of its perturbations have returned.
+pub(crate) fn unless_cancelled<T>(result: Result<T, TargetingError>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(TargetingError::PropError {
+            source: PropagationError::Cancelled { .. },
+        }) => None,
+        Err(e) => panic!("perturbed propagation failed: {e:?}"),
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum DiffMethod {
     /// Slower, but more commonly used
@@ -0,0 +1,414 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use snafu::{ensure, ResultExt};
+
+use super::raphson_stm::ObjectivesAssessment;
+use crate::active_pseudo_inverse;
+use crate::errors::TargetingError;
+use crate::linalg::{DMatrix, SVector, Vector3};
+use crate::md::objective::Objective;
+use crate::md::{prelude::*, PropSnafu, UnderdeterminedProblemSnafu};
+pub use crate::md::{Variable, Vary};
+use std::fmt;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+/// Solution of a targeting problem where the same kind of impulsive correction is applied at several epochs
+#[derive(Clone, Debug)]
+pub struct MultiManeuverSolution<const V: usize, const O: usize> {
+    /// The corrected spacecraft state at each correction epoch, i.e. right after each correction
+    pub corrected_states: Vec<Spacecraft>,
+    /// The state at which the objectives are achieved
+    pub achieved_state: Spacecraft,
+    /// The correction applied at each correction epoch, in the correction frame of the state right before that correction
+    pub corrections: Vec<SVector<f64, V>>,
+    /// The kind of correction (position or velocity)
+    pub variables: [Variable; V],
+    /// The errors achieved
+    pub achieved_errors: SVector<f64, O>,
+    /// The objectives set in the targeter
+    pub achieved_objectives: [Objective; O],
+    /// The number of iterations required
+    pub iterations: usize,
+    /// Computation duration
+    pub computation_dur: std::time::Duration,
+}
+
+impl<const V: usize, const O: usize> MultiManeuverSolution<V, O> {
+    /// Returns the epochs of the corrections
+    pub fn epochs(&self) -> Vec<Epoch> {
+        self.corrected_states.iter().map(|sc| sc.epoch()).collect()
+    }
+
+    /// Returns the total magnitude of all of the corrections, e.g. the total delta-v in km/s
+    pub fn total_correction(&self) -> f64 {
+        self.corrections.iter().map(|corr| corr.norm()).sum()
+    }
+}
+
+impl<const V: usize, const O: usize> fmt::Display for MultiManeuverSolution<V, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Multiple maneuver targeter solution correcting {:?} at {} epochs (converged in {:.3} seconds, {} iterations):",
+            self.variables
+                .iter()
+                .map(|v| format!("{:?}", v.component))
+                .collect::<Vec<String>>(),
+            self.corrections.len(),
+            self.computation_dur.as_secs_f64(),
+            self.iterations
+        )?;
+
+        for (state, correction) in self.corrected_states.iter().zip(&self.corrections) {
+            write!(f, "\tCorrection @ {}:", state.epoch())?;
+            for (i, var) in self.variables.iter().enumerate() {
+                write!(f, " {:?} = {:.6}", var.component, correction[i])?;
+            }
+            writeln!(f, " (norm = {:.6})", correction.norm())?;
+        }
+
+        writeln!(f, "\tAchieved @ {}:", self.achieved_state.epoch())?;
+        for (i, obj) in self.achieved_objectives.iter().enumerate() {
+            writeln!(
+                f,
+                "\t\t{:?} = {:.3} (wanted {}{:.3} ± {:.1e})",
+                obj.parameter,
+                obj.desired_value - self.achieved_errors[i],
+                obj.constraint.prefix(),
+                obj.desired_value,
+                obj.tolerance
+            )?;
+        }
+        write!(
+            f,
+            "\tAchieved state:\n\t\t{}\n\t\t{:x}",
+            self.achieved_state, self.achieved_state
+        )
+    }
+}
+
+impl<const V: usize, const O: usize> Targeter<'_, V, O> {
+    /// Differential correction of impulsive corrections applied at each of the provided epochs, e.g. two trajectory correction
+    /// maneuvers, such that the objectives are achieved at the achievement epoch.
+    ///
+    /// The variables of this targeter are applied at each correction epoch, so the problem has V times the number of epochs
+    /// unknowns, all solved for simultaneously. The Jacobian is assembled from the state transition matrix of a single
+    /// propagation per iteration. When the problem is underdetermined, the minimum norm correction is sought.
+    ///
+    /// Only position and velocity variables are supported.
+    #[allow(clippy::result_large_err)]
+    pub fn try_achieve_multiple(
+        &self,
+        initial_state: Spacecraft,
+        correction_epochs: &[Epoch],
+        achievement_epoch: Epoch,
+        almanac: Arc<Almanac>,
+    ) -> Result<MultiManeuverSolution<V, O>, TargetingError> {
+        ensure!(!self.objectives.is_empty(), UnderdeterminedProblemSnafu);
+
+        if correction_epochs.is_empty()
+            || correction_epochs.windows(2).any(|w| w[0] >= w[1])
+            || correction_epochs[0] < initial_state.epoch()
+            || correction_epochs[correction_epochs.len() - 1] >= achievement_epoch
+        {
+            let msg = format!(
+                "correction epochs must be increasing, after the initial state and before the achievement epoch: {correction_epochs:?}"
+            );
+            error!("{}", msg);
+            return Err(TargetingError::VariableError { msg });
+        }
+
+        for var in &self.variables {
+            var.valid()?;
+            match var.component {
+                Vary::PositionX | Vary::PositionY | Vary::PositionZ => {
+                    if let Some(frame) = self.correction_frame {
+                        let msg = format!(
+                            "Variable is in frame {frame:?} but that frame cannot be used for a {:?} correction",
+                            var.component
+                        );
+                        error!("{}", msg);
+                        return Err(TargetingError::FrameError { msg });
+                    }
+                }
+                Vary::VelocityX | Vary::VelocityY | Vary::VelocityZ => {}
+                _ => {
+                    return Err(TargetingError::UnsupportedVariable {
+                        var: var.to_string(),
+                    })
+                }
+            }
+        }
+
+        let num_mnvrs = correction_epochs.len();
+
+        let xi_start = self
+            .prop
+            .with(initial_state, almanac.clone())
+            .until_epoch(correction_epochs[0])
+            .context(PropSnafu)?;
+
+        let mut corrections = vec![SVector::<f64, V>::zeros(); num_mnvrs];
+        for correction in corrections.iter_mut() {
+            for (i, var) in self.variables.iter().enumerate() {
+                correction[i] = var.init_guess;
+            }
+        }
+
+        let mut prev_err_norm = f64::INFINITY;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let start_instant = Instant::now();
+
+        for it in 0..=self.iterations {
+            // Propagate through all of the corrections, with the STM from the first correction epoch.
+            let mut state = xi_start.with_stm();
+            let mut corrected_states = Vec::with_capacity(num_mnvrs);
+            let mut correction_dcms = Vec::with_capacity(num_mnvrs);
+            for (epoch, correction) in correction_epochs.iter().zip(&corrections) {
+                state = self
+                    .prop
+                    .with(state, almanac.clone())
+                    .until_epoch(*epoch)
+                    .context(PropSnafu)?;
+                let dcm = self.correction_dcm(&state)?;
+                state
+                    .orbit
+                    .apply_dv_km_s(dcm * self.velocity_of(correction));
+                state.orbit.radius_km += self.position_of(correction);
+                corrected_states.push(state);
+                correction_dcms.push(dcm);
+            }
+
+            let xf = self
+                .prop
+                .with(state, almanac.clone())
+                .until_epoch(achievement_epoch)
+                .context(PropSnafu)?;
+
+            // An impulsive correction does not change the STM, so the STM from each correction epoch to the achievement epoch
+            // is computed from the STMs since the first correction epoch.
+            let phi_f = xf.stm().unwrap().fixed_view::<6, 6>(0, 0).into_owned();
+            let mut dxf_dvar = DMatrix::<f64>::zeros(6, num_mnvrs * V);
+            for (k, (corrected, dcm)) in corrected_states.iter().zip(&correction_dcms).enumerate() {
+                let phi_k = corrected
+                    .stm()
+                    .unwrap()
+                    .fixed_view::<6, 6>(0, 0)
+                    .into_owned();
+                let phi_f_k = phi_f
+                    * phi_k
+                        .try_inverse()
+                        .ok_or(TargetingError::SingularJacobian)?;
+                for (j, var) in self.variables.iter().enumerate() {
+                    let idx = var.component.vec_index();
+                    let column = if idx < 3 {
+                        phi_f_k.column(idx).into_owned()
+                    } else {
+                        phi_f_k.fixed_columns::<3>(3) * dcm.column(idx - 3)
+                    };
+                    dxf_dvar.set_column(k * V + j, &column);
+                }
+            }
+
+            let ObjectivesAssessment {
+                err_vector,
+                partials,
+                active,
+                converged,
+                objmsg,
+            } = self.assess_objectives(&xf, &almanac)?;
+
+            if converged {
+                #[cfg(not(target_arch = "wasm32"))]
+                let conv_dur = Instant::now() - start_instant;
+                #[cfg(target_arch = "wasm32")]
+                let conv_dur = Duration::ZERO.into();
+
+                info!("Multiple maneuver targeter -- CONVERGED in {it} iterations");
+                for obj in &objmsg {
+                    info!("{}", obj);
+                }
+
+                return Ok(MultiManeuverSolution {
+                    corrected_states: corrected_states
+                        .into_iter()
+                        .map(|mut sc| {
+                            sc.stm = None;
+                            sc
+                        })
+                        .collect(),
+                    achieved_state: {
+                        let mut xf = xf;
+                        xf.stm = None;
+                        xf
+                    },
+                    corrections,
+                    variables: self.variables,
+                    achieved_errors: err_vector,
+                    achieved_objectives: self.objectives,
+                    iterations: it,
+                    computation_dur: conv_dur,
+                });
+            }
+
+            if (err_vector.norm() - prev_err_norm).abs() < 1e-10 {
+                return Err(TargetingError::CorrectionIneffective {
+                    prev_val: prev_err_norm,
+                    cur_val: err_vector.norm(),
+                    action: "multiple maneuver targeter",
+                });
+            }
+            prev_err_norm = err_vector.norm();
+
+            let jac = partials * dxf_dvar;
+            debug!("Jacobian {}", jac);
+
+            let jac_inv = active_pseudo_inverse!(&jac, &active)?;
+            let delta = jac_inv * err_vector;
+
+            debug!(
+                "Error vector (norm = {}): {}\nRaw correction: {}",
+                err_vector.norm(),
+                err_vector,
+                delta
+            );
+
+            for (k, correction) in corrections.iter_mut().enumerate() {
+                for (j, var) in self.variables.iter().enumerate() {
+                    let mut step = delta[k * V + j];
+                    // Choose the minimum step between the provided max step and the correction.
+                    if step.abs() > var.max_step.abs() {
+                        step = var.max_step.abs() * step.signum();
+                    }
+                    correction[j] += step;
+                }
+            }
+
+            info!("Multiple maneuver targeter -- Iteration #{it}");
+            for obj in &objmsg {
+                info!("{}", obj);
+            }
+        }
+
+        Err(TargetingError::TooManyIterations {
+            iterations: self.iterations,
+        })
+    }
+
+    /// Returns the position part of the provided correction
+    fn position_of(&self, correction: &SVector<f64, V>) -> Vector3<f64> {
+        let mut position = Vector3::zeros();
+        for (i, var) in self.variables.iter().enumerate() {
+            if let Vary::PositionX | Vary::PositionY | Vary::PositionZ = var.component {
+                position[var.component.vec_index()] += correction[i];
+            }
+        }
+        position
+    }
+
+    /// Returns the velocity part of the provided correction, in the correction frame
+    fn velocity_of(&self, correction: &SVector<f64, V>) -> Vector3<f64> {
+        let mut velocity = Vector3::zeros();
+        for (i, var) in self.variables.iter().enumerate() {
+            if let Vary::VelocityX | Vary::VelocityY | Vary::VelocityZ = var.component {
+                velocity[var.component.vec_index() - 3] += correction[i];
+            }
+        }
+        velocity
+    }
+}
+
+#[cfg(test)]
+mod ut_multi_maneuver {
+    use super::*;
+    use crate::cosmic::Orbit;
+    use crate::fixtures;
+    use crate::md::StateParameter;
+
+    #[test]
+    fn two_tcms() {
+        let almanac = fixtures::almanac();
+        let eme2k = fixtures::eme2k();
+        let epoch = Epoch::from_gregorian_utc_at_midnight(2023, 3, 1);
+        let orbit =
+            Orbit::try_keplerian(8_000.0, 0.05, 28.5, 10.0, 20.0, 30.0, epoch, eme2k).unwrap();
+        let sc = Spacecraft::from(orbit);
+
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let tgt = Targeter::vnc(
+            &prop,
+            [
+                Objective::within_tolerance(StateParameter::SMA, 8_100.0, 1e-3),
+                Objective::within_tolerance(StateParameter::Eccentricity, 0.06, 1e-6),
+                Objective::within_tolerance(StateParameter::Inclination, 28.6, 1e-5),
+            ],
+        );
+
+        let tcm1 = epoch + Unit::Minute * 10;
+        let tcm2 = epoch + Unit::Minute * 40;
+        let achievement = epoch + Unit::Hour * 2;
+
+        let sol = tgt
+            .try_achieve_multiple(sc, &[tcm1, tcm2], achievement, almanac.clone())
+            .unwrap();
+        println!("{sol}");
+
+        assert_eq!(sol.epochs(), vec![tcm1, tcm2]);
+        let achieved = sol.achieved_state.orbit;
+        assert!((achieved.sma_km().unwrap() - 8_100.0).abs() < 1e-3);
+        assert!((achieved.ecc().unwrap() - 0.06).abs() < 1e-6);
+        assert!((achieved.inc_deg().unwrap() - 28.6).abs() < 1e-5);
+
+        // The achieved state follows from the second corrected state
+        let xf = prop
+            .with(sol.corrected_states[1], almanac.clone())
+            .until_epoch(achievement)
+            .unwrap();
+        assert!(xf.orbit.rss_radius_km(&achieved).unwrap() < 1e-6);
+
+        // Splitting the correction over both maneuvers costs less than the minimum norm single maneuver at either epoch
+        let sum_sq = |sol: &MultiManeuverSolution<3, 3>| {
+            sol.corrections
+                .iter()
+                .map(|corr| corr.norm_squared())
+                .sum::<f64>()
+        };
+        for tcm in [tcm1, tcm2] {
+            let single = tgt
+                .try_achieve_multiple(sc, &[tcm], achievement, almanac.clone())
+                .unwrap();
+            assert!(sum_sq(&sol) < sum_sq(&single));
+        }
+
+        // And a single correction matches the single maneuver targeter
+        let single = tgt
+            .try_achieve_multiple(sc, &[tcm1], achievement, almanac.clone())
+            .unwrap();
+        let reference = tgt
+            .try_achieve_stm(sc, tcm1, achievement, almanac.clone())
+            .unwrap();
+        assert!((single.corrections[0] - reference.correction).norm() < 1e-9);
+
+        assert!(tgt
+            .try_achieve_multiple(sc, &[tcm2, tcm1], achievement, almanac)
+            .is_err());
+    }
+}
@@ -0,0 +1,342 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use rayon::prelude::*;
+use snafu::ResultExt;
+
+use super::{MultiShootTrajSnafu, MultipleShootingError, TargetingSnafu};
+use crate::errors::TargetingError;
+use crate::linalg::{DMatrix, DVector};
+use crate::md::{prelude::*, PropSnafu};
+
+use std::fmt;
+
+/// A multiple shooting differential corrector, which enforces the continuity of the trajectory between patch points.
+///
+/// The trajectory is split into segments, each propagated from one patch point to the epoch of the next one, and the
+/// position and velocity of all of the patch points are corrected, with a minimum norm Newton update using the STM of
+/// each segment, until the end of each segment matches the start of the next one. The epochs of the patch points are fixed.
+/// This converges for long and sensitive transfers (cislunar, low energy) where a single shooting diverges.
+///
+/// Components of the first and last patch points may be fixed, e.g. the initial and final positions.
+/// The partials are computed by central differences of the propagation of each segment, in parallel, such that any
+/// dynamics are supported, including guidance laws.
+pub struct MultiShootCorrector<'a> {
+    /// The propagator setup (kind, stages, etc.)
+    pub prop: &'a Propagator<SpacecraftDynamics>,
+    /// Initial guess of the state at each patch point, in chronological order
+    pub patch_points: Vec<Spacecraft>,
+    /// Indexes of the Cartesian components (0 to 5) of the first patch point which are not corrected
+    pub fixed_initial: Vec<usize>,
+    /// Indexes of the Cartesian components (0 to 5) of the last patch point which are not corrected
+    pub fixed_final: Vec<usize>,
+    /// Continuity tolerance on the position, in km
+    pub tol_position_km: f64,
+    /// Continuity tolerance on the velocity, in km/s
+    pub tol_velocity_km_s: f64,
+    /// The maximum number of iterations allowed
+    pub max_iterations: usize,
+}
+
+#[allow(clippy::result_large_err)]
+impl<'a> MultiShootCorrector<'a> {
+    /// Initializes a corrector from the provided patch points, with the initial and final positions fixed,
+    /// a continuity tolerance of 1 meter and 1 mm/s, and up to 25 iterations.
+    pub fn new(
+        prop: &'a Propagator<SpacecraftDynamics>,
+        patch_points: Vec<Spacecraft>,
+    ) -> Result<Self, MultipleShootingError> {
+        if patch_points.len() < 2 {
+            error!("At least two patch points are needed for a multiple shooting correction");
+            return Err(MultipleShootingError::TargetingError {
+                segment: 0_usize,
+                source: Box::new(TargetingError::UnderdeterminedProblem),
+            });
+        }
+
+        Ok(Self {
+            prop,
+            patch_points,
+            fixed_initial: vec![0, 1, 2],
+            fixed_final: vec![0, 1, 2],
+            tol_position_km: 1e-3,
+            tol_velocity_km_s: 1e-6,
+            max_iterations: 25,
+        })
+    }
+
+    /// Initializes a corrector by splitting the initial guess trajectory into the provided number of segments of equal duration.
+    pub fn from_trajectory(
+        prop: &'a Propagator<SpacecraftDynamics>,
+        traj: &Trajectory,
+        segments: usize,
+    ) -> Result<Self, MultipleShootingError> {
+        let step = (traj.last().epoch() - traj.first().epoch()) / (segments.max(1) as f64);
+        let patch_points = (0..=segments)
+            .map(|i| {
+                if i == segments {
+                    Ok(*traj.last())
+                } else {
+                    traj.at(traj.first().epoch() + step * (i as f64))
+                }
+            })
+            .collect::<Result<Vec<Spacecraft>, _>>()
+            .context(MultiShootTrajSnafu)?;
+
+        Self::new(prop, patch_points)
+    }
+
+    /// Corrects the patch points until the trajectory is continuous.
+    pub fn correct(
+        &self,
+        almanac: Arc<Almanac>,
+    ) -> Result<MultiShootCorrection, MultipleShootingError> {
+        let segments = self.patch_points.len() - 1;
+        let mut patch_points = self.patch_points.clone();
+
+        // Columns of the Jacobian which are corrected
+        let free: Vec<usize> = (0..6 * (segments + 1))
+            .filter(|col| {
+                !((*col < 6 && self.fixed_initial.contains(col))
+                    || (*col >= 6 * segments && self.fixed_final.contains(&(col - 6 * segments))))
+            })
+            .collect();
+
+        for iteration in 0..=self.max_iterations {
+            // Propagate all of the segments, along with the perturbations of the start of each segment
+            let tasks: Vec<(usize, Option<(usize, f64)>)> = (0..segments)
+                .flat_map(|i| {
+                    std::iter::once((i, None)).chain(
+                        (0..6).flat_map(move |col| [(i, Some((col, 1.0))), (i, Some((col, -1.0)))]),
+                    )
+                })
+                .collect();
+
+            let finals = tasks
+                .par_iter()
+                .map(|(i, pert)| {
+                    let mut start = patch_points[*i];
+                    if let Some((col, sign)) = pert {
+                        let step = sign * self.perturbation(&start, *col);
+                        if *col < 3 {
+                            start.orbit.radius_km[*col] += step;
+                        } else {
+                            start.orbit.velocity_km_s[col - 3] += step;
+                        }
+                    }
+                    self.prop
+                        .with(start, almanac.clone())
+                        .until_epoch(patch_points[i + 1].epoch())
+                        .map(|end| end.orbit.to_cartesian_pos_vel())
+                        .context(PropSnafu)
+                        .context(TargetingSnafu { segment: *i })
+                })
+                .collect::<Result<Vec<_>, MultipleShootingError>>()?;
+
+            let ends: Vec<Spacecraft> = (0..segments)
+                .map(|i| {
+                    let mut end = patch_points[i + 1];
+                    let nominal = finals[13 * i];
+                    end.orbit.radius_km = nominal.fixed_rows::<3>(0).into_owned();
+                    end.orbit.velocity_km_s = nominal.fixed_rows::<3>(3).into_owned();
+                    end
+                })
+                .collect();
+
+            // Continuity constraints
+            let mut constraints = DVector::<f64>::zeros(6 * segments);
+            let mut max_position_km = 0.0_f64;
+            let mut max_velocity_km_s = 0.0_f64;
+            for (i, end) in ends.iter().enumerate() {
+                let dr = end.orbit.radius_km - patch_points[i + 1].orbit.radius_km;
+                let dv = end.orbit.velocity_km_s - patch_points[i + 1].orbit.velocity_km_s;
+                max_position_km = max_position_km.max(dr.amax());
+                max_velocity_km_s = max_velocity_km_s.max(dv.amax());
+                constraints.fixed_rows_mut::<3>(6 * i).copy_from(&dr);
+                constraints.fixed_rows_mut::<3>(6 * i + 3).copy_from(&dv);
+            }
+
+            info!(
+                "Multiple shooting correction #{iteration}\tmax discontinuity = {:.3e} km, {:.3e} km/s",
+                max_position_km, max_velocity_km_s
+            );
+
+            if max_position_km <= self.tol_position_km
+                && max_velocity_km_s <= self.tol_velocity_km_s
+            {
+                return Ok(MultiShootCorrection {
+                    patch_points,
+                    iterations: iteration,
+                    max_position_error_km: max_position_km,
+                    max_velocity_error_km_s: max_velocity_km_s,
+                });
+            }
+
+            if iteration == self.max_iterations {
+                break;
+            }
+
+            // Jacobian of the continuity constraints with respect to the free components of the patch points,
+            // where the partials of each segment are computed by central differences.
+            let mut full_jac = DMatrix::<f64>::zeros(6 * segments, 6 * (segments + 1));
+            for i in 0..segments {
+                for col in 0..6 {
+                    let step = self.perturbation(&patch_points[i], col);
+                    let partial = (finals[13 * i + 1 + 2 * col] - finals[13 * i + 2 + 2 * col])
+                        / (2.0 * step);
+                    for row in 0..6 {
+                        full_jac[(6 * i + row, 6 * i + col)] = partial[row];
+                    }
+                    full_jac[(6 * i + col, 6 * (i + 1) + col)] = -1.0;
+                }
+            }
+            let jac = full_jac.select_columns(free.iter());
+
+            // Minimum norm correction, with an SVD because of the different scales of positions and velocities
+            let correction = jac
+                .svd(true, true)
+                .solve(&constraints, f64::EPSILON)
+                .map_err(|msg| TargetingError::Verification {
+                    msg: msg.to_string(),
+                })
+                .context(TargetingSnafu { segment: 0_usize })?;
+
+            for (k, col) in free.iter().enumerate() {
+                let orbit = &mut patch_points[col / 6].orbit;
+                let component = col % 6;
+                if component < 3 {
+                    orbit.radius_km[component] -= correction[k];
+                } else {
+                    orbit.velocity_km_s[component - 3] -= correction[k];
+                }
+            }
+        }
+
+        Err(MultipleShootingError::TargetingError {
+            segment: 0_usize,
+            source: Box::new(TargetingError::TooManyIterations {
+                iterations: self.max_iterations,
+            }),
+        })
+    }
+
+    /// Returns the finite difference perturbation of the provided Cartesian component
+    fn perturbation(&self, state: &Spacecraft, component: usize) -> f64 {
+        if component < 3 {
+            1e-7 * state.orbit.rmag_km()
+        } else {
+            1e-7 * state.orbit.vmag_km_s()
+        }
+    }
+}
+
+/// The result of a multiple shooting correction.
+#[derive(Clone, Debug)]
+pub struct MultiShootCorrection {
+    /// Corrected patch points
+    pub patch_points: Vec<Spacecraft>,
+    /// Number of iterations of the correction
+    pub iterations: usize,
+    /// Maximum position discontinuity between segments, in km
+    pub max_position_error_km: f64,
+    /// Maximum velocity discontinuity between segments, in km/s
+    pub max_velocity_error_km_s: f64,
+}
+
+#[allow(clippy::result_large_err)]
+impl MultiShootCorrection {
+    /// Builds the trajectory of each segment between the corrected patch points.
+    pub fn build_trajectories(
+        &self,
+        prop: &Propagator<SpacecraftDynamics>,
+        almanac: Arc<Almanac>,
+    ) -> Result<Vec<Trajectory>, MultipleShootingError> {
+        self.patch_points
+            .windows(2)
+            .enumerate()
+            .map(|(i, pair)| {
+                prop.with(pair[0], almanac.clone())
+                    .until_epoch_with_traj(pair[1].epoch())
+                    .map(|(_, traj)| traj)
+                    .context(PropSnafu)
+                    .context(TargetingSnafu { segment: i })
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for MultiShootCorrection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Multiple shooting of {} segments converged in {} iterations: max discontinuity = {:.3e} km, {:.3e} km/s",
+            self.patch_points.len() - 1,
+            self.iterations,
+            self.max_position_error_km,
+            self.max_velocity_error_km_s
+        )
+    }
+}
+
+#[cfg(test)]
+mod ut_corrector {
+    use super::MultiShootCorrector;
+    use crate::fixtures;
+    use crate::linalg::Vector3;
+    use crate::md::prelude::*;
+
+    #[test]
+    fn continuity_two_body() {
+        let orbit = fixtures::keplerian(8_000.0, 0.2, 28.5, 30.0, 45.0, 10.0);
+        let sc = Spacecraft::builder().orbit(orbit).build();
+        let almanac = fixtures::almanac();
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+
+        let (truth_end, truth) = prop
+            .with(sc, almanac.clone())
+            .for_duration_with_traj(4.5.hours())
+            .unwrap();
+
+        // Perturb the interior patch points and the initial velocity, with a transfer angle far from 180 degrees
+        let mut corrector = MultiShootCorrector::from_trajectory(&prop, &truth, 5).unwrap();
+        assert_eq!(corrector.patch_points.len(), 6);
+        corrector.patch_points[0].orbit.velocity_km_s += Vector3::new(5e-3, -2e-3, 1e-3);
+        for (i, patch) in corrector.patch_points[1..5].iter_mut().enumerate() {
+            patch.orbit.radius_km += Vector3::new(10.0, -5.0, 2.0) * (i as f64 + 1.0);
+            patch.orbit.velocity_km_s += Vector3::new(-1e-2, 5e-3, 2e-3);
+        }
+
+        let correction = corrector.correct(almanac.clone()).unwrap();
+        println!("{correction}");
+        assert!(correction.iterations > 0);
+
+        // The fixed positions are unchanged and the corrected trajectory matches the truth
+        assert_eq!(correction.patch_points[0].orbit.radius_km, orbit.radius_km);
+        assert_eq!(
+            correction.patch_points[5].orbit.radius_km,
+            truth_end.orbit.radius_km
+        );
+        for patch in &correction.patch_points {
+            let expected = truth.at(patch.epoch()).unwrap();
+            assert!(expected.orbit.rss_radius_km(&patch.orbit).unwrap() < 1e-2);
+        }
+
+        let trajs = correction.build_trajectories(&prop, almanac).unwrap();
+        assert_eq!(trajs.len(), 5);
+    }
+}
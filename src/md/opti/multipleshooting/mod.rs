@@ -22,6 +22,7 @@ use snafu::Snafu;
 use crate::md::{trajectory::TrajError, TargetingError};
 
 pub mod altitude_heuristic;
+pub mod corrector;
 pub mod ctrlnodes;
 pub mod equidistant_heuristic;
 pub mod multishoot;
@@ -40,7 +41,8 @@ pub enum MultipleShootingError {
     #[snafu(display("segment #{segment} encountered {source}"))]
     TargetingError {
         segment: usize,
-        source: TargetingError,
+        #[snafu(source(from(TargetingError, Box::new)))]
+        source: Box<TargetingError>,
     },
     #[snafu(display("during a multiple shooting, encountered {source}"))]
     MultiShootTrajError { source: TrajError },
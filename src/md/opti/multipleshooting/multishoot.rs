@@ -273,7 +273,9 @@ impl<T: MultishootNode<OT>, const VT: usize, const OT: usize> MultipleShooting<'
         }
         Err(MultipleShootingError::TargetingError {
             segment: 0_usize,
-            source: TargetingError::TooManyIterations,
+            source: Box::new(TargetingError::TooManyIterations {
+                iterations: self.max_iterations,
+            }),
         })
     }
 }
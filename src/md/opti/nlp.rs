@@ -0,0 +1,474 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use rayon::prelude::*;
+
+use crate::errors::TargetingError;
+use crate::linalg::{DMatrix, DVector};
+
+use std::fmt;
+
+/// A nonlinear program: minimize the objective subject to equality constraints `c(x) = 0` and inequality constraints `g(x) <= 0`.
+///
+/// The derivatives default to central finite differences computed in parallel, and may be overwritten when analytical partials are available.
+pub trait NlpProblem: Sync {
+    /// Number of decision variables
+    fn num_variables(&self) -> usize;
+
+    /// Value of the objective function, to be minimized
+    fn objective(&self, x: &DVector<f64>) -> f64;
+
+    /// Value of the equality constraints, which must be zero at the solution
+    fn equalities(&self, x: &DVector<f64>) -> DVector<f64>;
+
+    /// Value of the inequality constraints, which must be negative or zero at the solution
+    fn inequalities(&self, x: &DVector<f64>) -> DVector<f64>;
+
+    /// Gradient of the objective function
+    fn objective_gradient(&self, x: &DVector<f64>) -> DVector<f64> {
+        let jac = fd_jacobian(x, |x| DVector::from_element(1, self.objective(x)));
+        jac.row(0).transpose()
+    }
+
+    /// Jacobian of the equality constraints
+    fn equalities_jacobian(&self, x: &DVector<f64>) -> DMatrix<f64> {
+        fd_jacobian(x, |x| self.equalities(x))
+    }
+
+    /// Jacobian of the inequality constraints
+    fn inequalities_jacobian(&self, x: &DVector<f64>) -> DMatrix<f64> {
+        fd_jacobian(x, |x| self.inequalities(x))
+    }
+}
+
+/// Interface to a nonlinear programming solver, allowing an external solver to be used in place of the built-in [Sqp].
+pub trait NlpSolver {
+    /// Solves the problem from the provided initial guess of the decision variables
+    #[allow(clippy::result_large_err)]
+    fn solve(
+        &self,
+        problem: &dyn NlpProblem,
+        initial_guess: DVector<f64>,
+    ) -> Result<NlpSolution, TargetingError>;
+}
+
+/// Solution of a nonlinear program
+#[derive(Clone, Debug)]
+pub struct NlpSolution {
+    /// Decision variables at the solution
+    pub x: DVector<f64>,
+    /// Objective value at the solution
+    pub objective: f64,
+    /// Largest violation of the constraints at the solution
+    pub max_violation: f64,
+    /// Number of iterations of the solver
+    pub iterations: usize,
+}
+
+impl fmt::Display for NlpSolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "NLP solution after {} iterations: objective = {:.9e}, max violation = {:.3e}",
+            self.iterations, self.objective, self.max_violation
+        )
+    }
+}
+
+/// A dense sequential quadratic programming solver.
+///
+/// Each quadratic subproblem is solved with an active set of the inequality constraints, the Hessian of the Lagrangian
+/// is approximated with a damped BFGS update, and the step is globalized with a backtracking filter line search.
+/// This is meant for problems of up to a few hundred variables; larger transcriptions should use an external sparse solver.
+#[derive(Copy, Clone, Debug)]
+pub struct Sqp {
+    /// The maximum number of major iterations allowed
+    pub max_iterations: usize,
+    /// The maximum number of active set iterations per quadratic subproblem
+    pub max_qp_iterations: usize,
+    /// Tolerance on the violation of the constraints
+    pub feasibility_tol: f64,
+    /// Tolerance on the infinity norm of the step
+    pub step_tol: f64,
+}
+
+impl Default for Sqp {
+    fn default() -> Self {
+        Self {
+            max_iterations: 200,
+            max_qp_iterations: 50,
+            feasibility_tol: 1e-9,
+            step_tol: 1e-7,
+        }
+    }
+}
+
+impl Sqp {
+    /// Solves the quadratic subproblem, returning the step and the multipliers of the equality and inequality constraints.
+    #[allow(clippy::result_large_err)]
+    fn solve_qp(
+        &self,
+        hessian: &DMatrix<f64>,
+        grad: &DVector<f64>,
+        eq: &DVector<f64>,
+        eq_jac: &DMatrix<f64>,
+        ineq: &DVector<f64>,
+        ineq_jac: &DMatrix<f64>,
+    ) -> Result<(DVector<f64>, DVector<f64>, DVector<f64>), TargetingError> {
+        let n = grad.len();
+        let n_eq = eq.len();
+        let mut active: Vec<usize> = (0..ineq.len())
+            .filter(|&i| ineq[i] >= -self.feasibility_tol)
+            .collect();
+
+        let mut result = None;
+        for _ in 0..self.max_qp_iterations {
+            let m = n_eq + active.len();
+            let mut kkt = DMatrix::zeros(n + m, n + m);
+            let mut rhs = DVector::zeros(n + m);
+            kkt.view_mut((0, 0), (n, n)).copy_from(hessian);
+            rhs.rows_mut(0, n).copy_from(&(-grad));
+            for i in 0..n_eq {
+                for j in 0..n {
+                    kkt[(n + i, j)] = eq_jac[(i, j)];
+                    kkt[(j, n + i)] = eq_jac[(i, j)];
+                }
+                rhs[n + i] = -eq[i];
+            }
+            for (k, &i) in active.iter().enumerate() {
+                for j in 0..n {
+                    kkt[(n + n_eq + k, j)] = ineq_jac[(i, j)];
+                    kkt[(j, n + n_eq + k)] = ineq_jac[(i, j)];
+                }
+                rhs[n + n_eq + k] = -ineq[i];
+            }
+
+            // Fall back to the minimum norm solution of the SVD if the active constraints are linearly dependent.
+            let sol = match kkt.clone().lu().solve(&rhs) {
+                Some(sol) if sol.iter().all(|v| v.is_finite()) => sol,
+                _ => kkt
+                    .svd(true, true)
+                    .solve(&rhs, f64::EPSILON)
+                    .map_err(|msg| TargetingError::Verification {
+                        msg: msg.to_string(),
+                    })?,
+            };
+
+            let step = sol.rows(0, n).into_owned();
+            let eq_mult = sol.rows(n, n_eq).into_owned();
+            let mut ineq_mult = DVector::zeros(ineq.len());
+            for (k, &i) in active.iter().enumerate() {
+                ineq_mult[i] = sol[n + n_eq + k];
+            }
+
+            // Release the active constraint with the most negative multiplier, if any.
+            let mut release = None;
+            let mut min_mult = -self.feasibility_tol;
+            for (k, &i) in active.iter().enumerate() {
+                if ineq_mult[i] < min_mult {
+                    min_mult = ineq_mult[i];
+                    release = Some(k);
+                }
+            }
+
+            // Otherwise, activate the most violated inactive constraint, if any.
+            let mut activate = None;
+            if release.is_none() {
+                let predicted = ineq + ineq_jac * &step;
+                let mut max_viol = self.feasibility_tol;
+                for i in (0..ineq.len()).filter(|i| !active.contains(i)) {
+                    if predicted[i] > max_viol {
+                        max_viol = predicted[i];
+                        activate = Some(i);
+                    }
+                }
+            }
+
+            result = Some((step, eq_mult, ineq_mult));
+
+            if let Some(k) = release {
+                active.remove(k);
+            } else if let Some(i) = activate {
+                active.push(i);
+            } else {
+                break;
+            }
+        }
+
+        result.ok_or(TargetingError::TooManyIterations {
+            iterations: self.max_qp_iterations,
+        })
+    }
+}
+
+impl NlpSolver for Sqp {
+    fn solve(
+        &self,
+        problem: &dyn NlpProblem,
+        initial_guess: DVector<f64>,
+    ) -> Result<NlpSolution, TargetingError> {
+        let n = problem.num_variables();
+        if initial_guess.len() != n {
+            return Err(TargetingError::VariableError {
+                msg: format!(
+                    "initial guess has {} variables but problem has {n}",
+                    initial_guess.len()
+                ),
+            });
+        }
+
+        let violation = |eq: &DVector<f64>, ineq: &DVector<f64>| -> f64 {
+            eq.iter().map(|c| c.abs()).sum::<f64>() + ineq.iter().map(|g| g.max(0.0)).sum::<f64>()
+        };
+
+        let max_violation = |eq: &DVector<f64>, ineq: &DVector<f64>| -> f64 {
+            eq.iter()
+                .map(|c| c.abs())
+                .chain(ineq.iter().map(|g| g.max(0.0)))
+                .fold(0.0, f64::max)
+        };
+
+        let mut x = initial_guess;
+        let mut obj = problem.objective(&x);
+        let mut grad = problem.objective_gradient(&x);
+        let mut eq = problem.equalities(&x);
+        let mut eq_jac = problem.equalities_jacobian(&x);
+        let mut ineq = problem.inequalities(&x);
+        let mut ineq_jac = problem.inequalities_jacobian(&x);
+
+        let mut hessian = DMatrix::<f64>::identity(n, n);
+        // Filter of pairs of constraint violation and objective which the iterates may not return to.
+        let mut filter: Vec<(f64, f64)> = Vec::new();
+        let max_viol = 1e4 * violation(&eq, &ineq).max(1.0);
+
+        for iteration in 0..self.max_iterations {
+            let (step, eq_mult, ineq_mult) =
+                self.solve_qp(&hessian, &grad, &eq, &eq_jac, &ineq, &ineq_jac)?;
+
+            let viol = max_violation(&eq, &ineq);
+            debug!(
+                "SQP #{iteration}: objective = {obj:.9e}\tmax violation = {viol:.3e}\t|step| = {:.3e}",
+                step.amax()
+            );
+
+            if viol < self.feasibility_tol && step.amax() < self.step_tol {
+                info!("SQP converged after {iteration} iterations");
+                return Ok(NlpSolution {
+                    x,
+                    objective: obj,
+                    max_violation: viol,
+                    iterations: iteration,
+                });
+            }
+
+            // Backtracking filter line search, which unlike a merit function does not suffer from the Maratos effect.
+            let theta = violation(&eq, &ineq);
+            let slope = grad.dot(&step);
+            // Returns whether the trial point is accepted, and whether the filter must be augmented with the current iterate.
+            let assess = |alpha: f64, obj_new: f64, theta_new: f64, filter: &[(f64, f64)]| {
+                let acceptable = theta_new.is_finite()
+                    && obj_new.is_finite()
+                    && theta_new <= max_viol
+                    && filter.iter().all(|&(theta_f, obj_f)| {
+                        theta_new < (1.0 - 1e-5) * theta_f || obj_new < obj_f - 1e-5 * theta_f
+                    });
+                if !acceptable {
+                    return (false, false);
+                }
+                // Switch to an Armijo condition on the objective when the step is mostly an optimality step.
+                if slope < 0.0 && -alpha * slope > theta.powf(1.1) {
+                    (obj_new <= obj + 1e-4 * alpha * slope, false)
+                } else {
+                    let accepted =
+                        theta_new <= (1.0 - 1e-5) * theta || obj_new <= obj - 1e-5 * theta;
+                    (accepted, accepted)
+                }
+            };
+
+            let mut alpha = 1.0;
+            let mut x_new;
+            let mut obj_new;
+            let mut eq_new;
+            let mut ineq_new;
+            let mut stalled = false;
+            loop {
+                x_new = &x + alpha * &step;
+                obj_new = problem.objective(&x_new);
+                eq_new = problem.equalities(&x_new);
+                ineq_new = problem.inequalities(&x_new);
+                let theta_new = violation(&eq_new, &ineq_new);
+
+                let (accepted, augment) = assess(alpha, obj_new, theta_new, &filter);
+                if accepted {
+                    if augment {
+                        filter.push(((1.0 - 1e-5) * theta, obj - 1e-5 * theta));
+                    }
+                    break;
+                }
+
+                if alpha == 1.0 {
+                    // Second order correction: project the full step back onto the constraints
+                    // which are active in the quadratic subproblem or violated at the trial point.
+                    let rows: Vec<usize> = (0..ineq.len())
+                        .filter(|&i| ineq_mult[i] > 0.0 || ineq_new[i] > 0.0)
+                        .collect();
+                    let mut jac = DMatrix::zeros(eq.len() + rows.len(), n);
+                    let mut resid = DVector::zeros(eq.len() + rows.len());
+                    jac.rows_mut(0, eq.len()).copy_from(&eq_jac);
+                    resid.rows_mut(0, eq.len()).copy_from(&(-&eq_new));
+                    for (k, &i) in rows.iter().enumerate() {
+                        jac.row_mut(eq.len() + k).copy_from(&ineq_jac.row(i));
+                        resid[eq.len() + k] = -ineq_new[i];
+                    }
+
+                    if let Ok(correction) = jac.svd(true, true).solve(&resid, 1e-12) {
+                        let x_soc = &x_new + correction;
+                        let obj_soc = problem.objective(&x_soc);
+                        let eq_soc = problem.equalities(&x_soc);
+                        let ineq_soc = problem.inequalities(&x_soc);
+                        let (accepted, augment) =
+                            assess(alpha, obj_soc, violation(&eq_soc, &ineq_soc), &filter);
+                        if accepted {
+                            if augment {
+                                filter.push(((1.0 - 1e-5) * theta, obj - 1e-5 * theta));
+                            }
+                            x_new = x_soc;
+                            obj_new = obj_soc;
+                            eq_new = eq_soc;
+                            ineq_new = ineq_soc;
+                            break;
+                        }
+                    }
+                }
+
+                if alpha < 1e-8 {
+                    if !theta_new.is_finite() || !obj_new.is_finite() {
+                        return Err(TargetingError::Verification {
+                            msg: format!("SQP iteration {iteration} leads to a non finite merit"),
+                        });
+                    }
+                    stalled = true;
+                    break;
+                }
+                alpha *= 0.5;
+            }
+
+            let grad_new = problem.objective_gradient(&x_new);
+            let eq_jac_new = problem.equalities_jacobian(&x_new);
+            let ineq_jac_new = problem.inequalities_jacobian(&x_new);
+
+            // Damped BFGS update of the Hessian of the Lagrangian
+            let lagrangian_grad =
+                |grad: &DVector<f64>, eq_jac: &DMatrix<f64>, ineq_jac: &DMatrix<f64>| {
+                    grad + eq_jac.tr_mul(&eq_mult) + ineq_jac.tr_mul(&ineq_mult)
+                };
+            let s = &x_new - &x;
+            let mut y = lagrangian_grad(&grad_new, &eq_jac_new, &ineq_jac_new)
+                - lagrangian_grad(&grad, &eq_jac, &ineq_jac);
+            let hs = &hessian * &s;
+            let shs = s.dot(&hs);
+            if stalled {
+                // The quasi-Newton model is poor if no step is acceptable, so restart it.
+                hessian = DMatrix::identity(n, n);
+                filter.clear();
+            } else if shs > f64::EPSILON {
+                let sy = s.dot(&y);
+                if sy < 0.2 * shs {
+                    let theta = 0.8 * shs / (shs - sy);
+                    y = theta * y + (1.0 - theta) * &hs;
+                }
+                hessian += &y * y.transpose() / s.dot(&y) - &hs * hs.transpose() / shs;
+            }
+
+            x = x_new;
+            obj = obj_new;
+            grad = grad_new;
+            eq = eq_new;
+            eq_jac = eq_jac_new;
+            ineq = ineq_new;
+            ineq_jac = ineq_jac_new;
+        }
+
+        error!(
+            "SQP failed to converge after {} iterations: max violation = {:.3e}",
+            self.max_iterations,
+            max_violation(&eq, &ineq)
+        );
+        Err(TargetingError::TooManyIterations {
+            iterations: self.max_iterations,
+        })
+    }
+}
+
+/// Computes the Jacobian of the provided function by central differences, one column per thread.
+pub fn fd_jacobian<F>(x: &DVector<f64>, func: F) -> DMatrix<f64>
+where
+    F: Fn(&DVector<f64>) -> DVector<f64> + Sync,
+{
+    let columns: Vec<DVector<f64>> = (0..x.len())
+        .into_par_iter()
+        .map(|j| {
+            let step = 1e-6 * x[j].abs().max(1.0);
+            let mut x_plus = x.clone();
+            x_plus[j] += step;
+            let mut x_minus = x.clone();
+            x_minus[j] -= step;
+            (func(&x_plus) - func(&x_minus)) / (2.0 * step)
+        })
+        .collect();
+
+    let rows = columns.first().map_or(0, |col| col.len());
+    DMatrix::from_fn(rows, x.len(), |i, j| columns[j][i])
+}
+
+#[cfg(test)]
+mod ut_nlp {
+    use super::*;
+
+    /// Minimize (x0 - 1)^2 + (x1 - 2)^2 subject to x0 + x1 = 2 and x0 <= 0.25
+    struct Quadratic;
+
+    impl NlpProblem for Quadratic {
+        fn num_variables(&self) -> usize {
+            2
+        }
+
+        fn objective(&self, x: &DVector<f64>) -> f64 {
+            (x[0] - 1.0).powi(2) + (x[1] - 2.0).powi(2)
+        }
+
+        fn equalities(&self, x: &DVector<f64>) -> DVector<f64> {
+            DVector::from_element(1, x[0] + x[1] - 2.0)
+        }
+
+        fn inequalities(&self, x: &DVector<f64>) -> DVector<f64> {
+            DVector::from_element(1, x[0] - 0.25)
+        }
+    }
+
+    #[test]
+    fn sqp_active_inequality() {
+        let sol = Sqp::default()
+            .solve(&Quadratic, DVector::from_vec(vec![3.0, -4.0]))
+            .unwrap();
+        // Without the inequality, the solution would be (0.5, 1.5)
+        assert!((sol.x[0] - 0.25).abs() < 1e-6, "{}", sol.x);
+        assert!((sol.x[1] - 1.75).abs() < 1e-6, "{}", sol.x);
+        assert!(sol.max_violation < 1e-9);
+    }
+}
@@ -18,20 +18,22 @@
 
 use super::solution::TargeterSolution;
 use super::targeter::Targeter;
+use super::unless_cancelled;
+use crate::active_pseudo_inverse;
 use crate::cosmic::{AstroAlmanacSnafu, AstroPhysicsSnafu};
 use crate::dynamics::guidance::{GuidanceError, LocalFrame, Maneuver, MnvrRepr};
 use crate::errors::TargetingError;
 use crate::linalg::{SMatrix, SVector, Vector6};
 use crate::md::{prelude::*, AstroSnafu, GuidanceSnafu, UnderdeterminedProblemSnafu};
-use crate::md::{PropSnafu, StateParameter};
+use crate::md::{CancelledSnafu, PropSnafu, StateParameter};
 pub use crate::md::{Variable, Vary};
 use crate::polyfit::CommonPolynomial;
-use crate::pseudo_inverse;
 use hifitime::TimeUnits;
 use rayon::prelude::*;
 use snafu::{ensure, ResultExt};
 #[cfg(not(target_arch = "wasm32"))]
 use std::time::Instant;
+use tracing::{debug, debug_span, error, info, info_span};
 
 impl<const V: usize, const O: usize> Targeter<'_, V, O> {
     /// Differential correction using finite differencing
@@ -53,13 +55,40 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
             }
         }
 
+        // The correction and achievement epochs are variables of free time of flight problems.
+        let mut correction_epoch = correction_epoch;
+        let mut achievement_epoch = achievement_epoch;
+        let mut departure_target = false;
+        for var in &self.variables {
+            match var.component {
+                Vary::CorrectionEpoch => {
+                    correction_epoch += var.init_guess.seconds();
+                    departure_target = true;
+                }
+                Vary::AchievementEpoch => achievement_epoch += var.init_guess.seconds(),
+                _ => {}
+            }
+        }
+
+        if departure_target
+            && self
+                .variables
+                .iter()
+                .any(|var| var.component.is_finite_burn())
+        {
+            let msg = "the correction epoch cannot be varied with a finite burn, vary the start epoch of the burn instead".to_string();
+            error!("{}", msg);
+            return Err(TargetingError::VariableError { msg });
+        }
+
         // Now we know that the problem is correctly defined, so let's propagate as is to the epoch
         // where the correction should be applied.
-        let xi_start = self
+        let mut xi_start = self
             .prop
             .with(initial_state, almanac.clone())
             .until_epoch(correction_epoch)
-            .context(PropSnafu)?;
+            .context(PropSnafu)
+            .map_err(|e| e.with_iterate(0, &[0.0; V]))?;
 
         debug!("initial_state = {}", initial_state);
         debug!("xi_start = {}", xi_start);
@@ -89,11 +118,12 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
             // Check the validity (this function will report to log and raise an error)
             var.valid()?;
             // Check that there is no attempt to target a position in a local frame
-            if self.correction_frame.is_some() && var.component.vec_index() < 3 {
+            if let (Some(frame), Vary::PositionX | Vary::PositionY | Vary::PositionZ) =
+                (self.correction_frame, var.component)
+            {
                 // Then this is a position correction, which is not allowed if a frame is provided!
                 let msg = format!(
-                    "Variable is in frame {:?} but that frame cannot be used for a {:?} correction",
-                    self.correction_frame.unwrap(),
+                    "Variable is in frame {frame:?} but that frame cannot be used for a {:?} correction",
                     var.component
                 );
                 error!("{}", msg);
@@ -158,7 +188,9 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
                     _ => unreachable!(),
                 }
                 info!("Initial maneuver guess: {}", mnvr);
-            } else {
+            } else if var.component.is_spacecraft_param() {
+                var.component.apply_to_spacecraft(&mut xi, var.init_guess)?;
+            } else if !var.component.is_time_of_flight() {
                 state_correction[var.component.vec_index()] += var.init_guess;
                 // Now, let's apply the correction to the initial state
                 if let Some(frame) = self.correction_frame {
@@ -207,7 +239,15 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
         #[cfg(not(target_arch = "wasm32"))]
         let start_instant = Instant::now();
 
+        let _span = info_span!(
+            "targeter",
+            method = "finite_diff",
+            variables = V,
+            objectives = O
+        )
+        .entered();
         for it in 0..=self.iterations {
+            let _iteration = debug_span!("iteration", it).entered();
             // Modify each variable by the desired perturbation, propagate, compute the final parameter, and store how modifying that variable affects the final parameter
             let cur_xi = xi;
 
@@ -219,7 +259,8 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
                 let pre_mnvr = prop
                     .with(cur_xi, almanac.clone())
                     .until_epoch(mnvr.start)
-                    .context(PropSnafu)?;
+                    .context(PropSnafu)
+                    .map_err(|e| e.with_iterate(it, total_correction.as_slice()))?;
                 prop.dynamics = prop.dynamics.with_guidance_law(Arc::new(mnvr));
                 prop.set_max_step(mnvr.duration());
                 let post_mnvr = prop
@@ -228,19 +269,22 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
                         almanac.clone(),
                     )
                     .until_epoch(mnvr.end)
-                    .context(PropSnafu)?;
+                    .context(PropSnafu)
+                    .map_err(|e| e.with_iterate(it, total_correction.as_slice()))?;
                 // Reset the propagator options to their previous configuration
                 prop.opts = prop_opts;
                 // And propagate until the achievement epoch
                 prop.with(post_mnvr, almanac.clone())
                     .until_epoch(achievement_epoch)
-                    .context(PropSnafu)?
+                    .context(PropSnafu)
+                    .map_err(|e| e.with_iterate(it, total_correction.as_slice()))?
                     .orbit
             } else {
                 self.prop
                     .with(cur_xi, almanac.clone())
                     .until_epoch(achievement_epoch)
-                    .context(PropSnafu)?
+                    .context(PropSnafu)
+                    .map_err(|e| e.with_iterate(it, total_correction.as_slice()))?
                     .orbit
             };
 
@@ -259,6 +303,7 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
             // Build the error vector
             let mut err_vector = SVector::<f64, O>::zeros();
             let mut converged = true;
+            let mut active = [true; O];
 
             // Build the B-Plane once, if needed, and always in the objective frame
             let b_plane = if is_bplane_tgt {
@@ -275,21 +320,23 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
             let mut jac = SMatrix::<f64, O, V>::zeros();
 
             for (i, obj) in self.objectives.iter().enumerate() {
-                let partial = if obj.parameter.is_b_plane() {
+                let achieved = if obj.requires_almanac() {
+                    obj.evaluate(xf, &almanac).context(AstroSnafu)?.0
+                } else if obj.parameter.is_b_plane() {
                     match obj.parameter {
                         StateParameter::BdotR => b_plane.unwrap().b_r,
                         StateParameter::BdotT => b_plane.unwrap().b_t,
                         StateParameter::BLTOF => b_plane.unwrap().ltof_s,
                         _ => unreachable!(),
                     }
+                    .real()
                 } else {
                     xf_dual_obj_frame
                         .partial_for(obj.parameter)
                         .context(AstroSnafu)?
+                        .real()
                 };
 
-                let achieved = partial.real();
-
                 let (ok, param_err) = obj.assess_value(achieved);
                 if !ok {
                     converged = false;
@@ -304,6 +351,12 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
                     param_err, width=width, prec=max_obj_tol
                 ));
 
+                // Inactive inequality constraints do not restrict the correction, so their partials are not needed.
+                active[i] = obj.is_active(achieved);
+                if !active[i] {
+                    continue;
+                }
+
                 let mut pert_calc: Vec<_> = self
                     .variables
                     .iter()
@@ -313,6 +366,7 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
 
                 pert_calc.par_iter_mut().for_each(|(_, var, jac_val)| {
                     let mut this_xi = xi;
+                    let mut this_achievement_epoch = achievement_epoch;
 
                     let mut this_prop = self.prop.clone();
                     let mut this_mnvr = mnvr;
@@ -407,6 +461,22 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
                             }
                             _ => unreachable!(),
                         }
+                    } else if var.component.is_spacecraft_param() {
+                        var.component
+                            .apply_to_spacecraft(&mut this_xi, var.perturbation)
+                            .unwrap();
+                    } else if var.component == Vary::AchievementEpoch {
+                        this_achievement_epoch += var.perturbation.seconds();
+                    } else if var.component == Vary::CorrectionEpoch {
+                        let Some(corrected) = unless_cancelled(self.corrected_at(
+                            xi_start,
+                            correction_epoch + var.perturbation.seconds(),
+                            &total_correction,
+                            almanac.clone(),
+                        )) else {
+                            return;
+                        };
+                        this_xi = corrected;
                     } else {
                         let mut state_correction = Vector6::<f64>::zeros();
                         state_correction[var.component.vec_index()] += var.perturbation;
@@ -430,36 +500,49 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
 
                     let this_xf = if finite_burn_target {
                         // Propagate normally until start of maneuver
-                        let pre_mnvr = this_prop
-                            .with(cur_xi, almanac.clone())
-                            .until_epoch(this_mnvr.start)
-                            .unwrap();
+                        let Some(pre_mnvr) = unless_cancelled(
+                            this_prop
+                                .with(this_xi, almanac.clone())
+                                .until_epoch(this_mnvr.start)
+                                .context(PropSnafu),
+                        ) else {
+                            return;
+                        };
                         // Add this maneuver to the dynamics, make sure that we don't over-step this maneuver
                         let prop_opts = this_prop.opts;
                         this_prop.set_max_step(this_mnvr.duration());
                         this_prop.dynamics =
                             this_prop.dynamics.with_guidance_law(Arc::new(this_mnvr));
-                        let post_mnvr = this_prop
-                            .with(
-                                pre_mnvr.with_guidance_mode(GuidanceMode::Thrust),
-                                almanac.clone(),
-                            )
-                            .until_epoch(this_mnvr.end)
-                            .unwrap();
+                        let Some(post_mnvr) = unless_cancelled(
+                            this_prop
+                                .with(
+                                    pre_mnvr.with_guidance_mode(GuidanceMode::Thrust),
+                                    almanac.clone(),
+                                )
+                                .until_epoch(this_mnvr.end)
+                                .context(PropSnafu),
+                        ) else {
+                            return;
+                        };
                         // Reset the propagator options to their previous configuration
                         this_prop.opts = prop_opts;
                         // And propagate until the achievement epoch
-                        this_prop
-                            .with(post_mnvr, almanac.clone())
-                            .until_epoch(achievement_epoch)
-                            .unwrap()
-                            .orbit
+                        unless_cancelled(
+                            this_prop
+                                .with(post_mnvr, almanac.clone())
+                                .until_epoch(this_achievement_epoch)
+                                .context(PropSnafu),
+                        )
                     } else {
-                        this_prop
-                            .with(this_xi, almanac.clone())
-                            .until_epoch(achievement_epoch)
-                            .unwrap()
-                            .orbit
+                        unless_cancelled(
+                            this_prop
+                                .with(this_xi, almanac.clone())
+                                .until_epoch(this_achievement_epoch)
+                                .context(PropSnafu),
+                        )
+                    };
+                    let Some(this_xf) = this_xf.map(|sc| sc.orbit) else {
+                        return;
                     };
 
                     let xf_dual_obj_frame = match &self.objective_frame {
@@ -481,18 +564,19 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
                         None
                     };
 
-                    let partial = if obj.parameter.is_b_plane() {
+                    let this_achieved = if obj.requires_almanac() {
+                        obj.evaluate(this_xf, &almanac).unwrap().0
+                    } else if obj.parameter.is_b_plane() {
                         match obj.parameter {
                             StateParameter::BdotR => b_plane.unwrap().b_r,
                             StateParameter::BdotT => b_plane.unwrap().b_t,
                             StateParameter::BLTOF => b_plane.unwrap().ltof_s,
                             _ => unreachable!(),
                         }
+                        .real()
                     } else {
-                        xf_dual_obj_frame.partial_for(obj.parameter).unwrap()
+                        xf_dual_obj_frame.partial_for(obj.parameter).unwrap().real()
                     };
-
-                    let this_achieved = partial.real();
                     *jac_val = (this_achieved - achieved) / var.perturbation;
                     if opposed_pert {
                         // We opposed the perturbation to ensure we don't over step a min/max bound
@@ -510,6 +594,15 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
                 }
             }
 
+            // The perturbations are skipped when the propagator is cancelled, so the Jacobian must not be used
+            ensure!(
+                !self.prop.is_cancelled(),
+                CancelledSnafu {
+                    iterations: it,
+                    correction: total_correction.as_slice().to_vec(),
+                }
+            );
+
             if converged {
                 #[cfg(not(target_arch = "wasm32"))]
                 let conv_dur = Instant::now() - start_instant;
@@ -518,8 +611,11 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
                 let mut corrected_state = xi_start;
 
                 let mut state_correction = Vector6::<f64>::zeros();
-                if !finite_burn_target {
-                    for (i, var) in self.variables.iter().enumerate() {
+                for (i, var) in self.variables.iter().enumerate() {
+                    if var.component.is_spacecraft_param() {
+                        var.component
+                            .apply_to_spacecraft(&mut corrected_state, total_correction[i])?;
+                    } else if !finite_burn_target && !var.component.is_time_of_flight() {
                         state_correction[var.component.vec_index()] += total_correction[i];
                     }
                 }
@@ -529,8 +625,7 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
                         .dcm_to_inertial(corrected_state.orbit)
                         .context(AstroPhysicsSnafu)
                         .context(AstroSnafu)?
-                        .rot_mat
-                        .transpose();
+                        .rot_mat;
 
                     let velocity_correction =
                         dcm_vnc2inertial * state_correction.fixed_rows::<3>(3);
@@ -544,7 +639,7 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
 
                 let sol = TargeterSolution {
                     corrected_state,
-                    achieved_state: xi_start.with_orbit(xf),
+                    achieved_state: xi.with_orbit(xf),
                     correction: total_correction,
                     computation_dur: conv_dur,
                     variables: self.variables,
@@ -554,9 +649,18 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
                 };
                 // Log success as info
                 if it == 1 {
-                    info!("Targeter -- CONVERGED in 1 iteration");
+                    info!(
+                        iterations = it,
+                        err_norm = err_vector.norm(),
+                        "Targeter -- CONVERGED in 1 iteration"
+                    );
                 } else {
-                    info!("Targeter -- CONVERGED in {} iterations", it);
+                    info!(
+                        iterations = it,
+                        err_norm = err_vector.norm(),
+                        "Targeter -- CONVERGED in {} iterations",
+                        it
+                    );
                 }
                 for obj in &objmsg {
                     info!("{}", obj);
@@ -577,7 +681,7 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
             debug!("Jacobian {}", jac);
 
             // Perform the pseudo-inverse if needed, else just inverse
-            let jac_inv = pseudo_inverse!(&jac)?;
+            let jac_inv = active_pseudo_inverse!(&jac, &active)?;
 
             debug!("Inverse Jacobian {}", jac_inv);
 
@@ -592,6 +696,7 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
 
             // And finally apply it to the xi
             let mut state_correction = Vector6::<f64>::zeros();
+            let mut departure_moved = false;
             for (i, var) in self.variables.iter().enumerate() {
                 debug!(
                     "Correction {:?}{} (element {}): {}",
@@ -695,7 +800,21 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
                     } else if delta[i] < var.min_value {
                         delta[i] = var.min_value;
                     }
-                    state_correction[var.component.vec_index()] += delta[i];
+                    if var.component.is_spacecraft_param() {
+                        var.component.apply_to_spacecraft(&mut xi, delta[i])?;
+                    } else if var.component.is_time_of_flight() {
+                        // Keep the total change of epoch within the bounds of the variable
+                        delta[i] =
+                            var.apply_bounds(total_correction[i] + delta[i]) - total_correction[i];
+                        if var.component == Vary::CorrectionEpoch {
+                            correction_epoch += delta[i].seconds();
+                            departure_moved = true;
+                        } else {
+                            achievement_epoch += delta[i].seconds();
+                        }
+                    } else {
+                        state_correction[var.component.vec_index()] += delta[i];
+                    }
                 }
             }
 
@@ -715,13 +834,244 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
             total_correction += delta;
             debug!("Total correction: {:e}", total_correction);
 
+            if departure_moved {
+                // The correction is applied at another epoch, so rebuild the corrected state from the uncorrected one
+                xi_start = self
+                    .prop
+                    .with(xi_start, almanac.clone())
+                    .until_epoch(correction_epoch)
+                    .context(PropSnafu)
+                    .map_err(|e| e.with_iterate(it, total_correction.as_slice()))?;
+                xi = self
+                    .corrected_at(
+                        xi_start,
+                        correction_epoch,
+                        &total_correction,
+                        almanac.clone(),
+                    )
+                    .map_err(|e| e.with_iterate(it, total_correction.as_slice()))?;
+            }
+
             // Log progress to debug
-            info!("Targeter -- Iteration #{} -- {}", it, achievement_epoch);
+            info!(
+                iteration = it,
+                err_norm = prev_err_norm,
+                correction_norm = delta.norm(),
+                "Targeter -- Iteration #{} -- {}",
+                it,
+                achievement_epoch
+            );
             for obj in &objmsg {
                 info!("{}", obj);
             }
         }
 
-        Err(TargetingError::TooManyIterations)
+        Err(TargetingError::TooManyIterations {
+            iterations: self.iterations,
+        })
+    }
+
+    /// Returns the state at the provided correction epoch with the total correction of the orbital and spacecraft
+    /// variables applied, which is needed when the correction epoch is itself a variable.
+    #[allow(clippy::result_large_err)]
+    fn corrected_at(
+        &self,
+        xi_start: Spacecraft,
+        epoch: Epoch,
+        total_correction: &SVector<f64, V>,
+        almanac: Arc<Almanac>,
+    ) -> Result<Spacecraft, TargetingError> {
+        let mut xi = self
+            .prop
+            .with(xi_start, almanac)
+            .until_epoch(epoch)
+            .context(PropSnafu)?;
+
+        let mut state_correction = Vector6::<f64>::zeros();
+        for (i, var) in self.variables.iter().enumerate() {
+            if var.component.is_spacecraft_param() {
+                var.component
+                    .apply_to_spacecraft(&mut xi, total_correction[i])?;
+            } else if !var.component.is_time_of_flight() {
+                state_correction[var.component.vec_index()] += total_correction[i];
+            }
+        }
+
+        if let Some(frame) = self.correction_frame {
+            let dcm_vnc2inertial = frame
+                .dcm_to_inertial(xi.orbit)
+                .context(AstroPhysicsSnafu)
+                .context(AstroSnafu)?
+                .rot_mat;
+
+            xi.orbit
+                .apply_dv_km_s(dcm_vnc2inertial * state_correction.fixed_rows::<3>(3));
+        } else {
+            xi = xi + state_correction;
+        }
+
+        Ok(xi)
+    }
+}
+
+#[cfg(test)]
+mod ut_raphson_fd {
+    use super::*;
+    use crate::cosmic::Orbit;
+    use crate::dynamics::guidance::Thruster;
+    use crate::fixtures;
+    use crate::linalg::Vector3;
+    use crate::md::objective::Objective;
+    use anise::structure::spacecraft::Mass;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn spacecraft_params() {
+        let almanac = fixtures::almanac();
+        let eme2k = fixtures::eme2k();
+        let epoch = Epoch::from_gregorian_utc_at_midnight(2023, 3, 1);
+        let orbit =
+            Orbit::try_keplerian(8_000.0, 0.01, 28.5, 10.0, 20.0, 30.0, epoch, eme2k).unwrap();
+        let sc = Spacecraft::builder()
+            .orbit(orbit)
+            .mass(Mass::from_dry_and_prop_masses(400.0, 100.0))
+            .thruster(Thruster::new(10.0, 300.0))
+            .mode(GuidanceMode::Thrust)
+            .build();
+
+        // Thrust along the velocity for ten minutes: only the thrust level or the fuel load can change the final orbit.
+        let burn = Maneuver::from_time_invariant(
+            epoch,
+            epoch + Unit::Minute * 10,
+            1.0,
+            Vector3::x(),
+            LocalFrame::VNC,
+        );
+        let mut prop = Propagator::default(SpacecraftDynamics::from_guidance_law(
+            OrbitalDynamics::two_body(),
+            Arc::new(burn),
+        ));
+        prop.set_max_step(Unit::Minute * 1);
+        let achievement = epoch + Unit::Minute * 20;
+        let sma = Objective::within_tolerance(StateParameter::SMA, 8_050.0, 1e-3);
+
+        let thrust_tgt = Targeter::new(&prop, [Vary::Thrust.into()], [sma]);
+        let sol = thrust_tgt
+            .try_achieve_fd(sc, epoch, achievement, almanac.clone())
+            .unwrap();
+        println!("{sol}");
+
+        let thrust_n = sol.corrected_state.thruster.unwrap().thrust_N;
+        assert!((thrust_n - 10.0 - sol.correction[0]).abs() < f64::EPSILON);
+        assert!((sol.achieved_state.orbit.sma_km().unwrap() - 8_050.0).abs() < 1e-3);
+        // The corrected state achieves the objective when propagated again.
+        let xf = prop
+            .with(sol.corrected_state, almanac.clone())
+            .until_epoch(achievement)
+            .unwrap();
+        assert!((xf.orbit.sma_km().unwrap() - 8_050.0).abs() < 1e-3);
+
+        // A slightly larger SMA is achieved with less fuel on board, i.e. a larger acceleration.
+        let sma = Objective::within_tolerance(StateParameter::SMA, 8_030.0, 1e-3);
+        let mass_tgt = Targeter::new(&prop, [Vary::PropMass.into()], [sma]);
+        let sol = mass_tgt
+            .try_achieve_fd(sc, epoch, achievement, almanac.clone())
+            .unwrap();
+        println!("{sol}");
+        assert!(sol.correction[0] < 0.0);
+        assert!(
+            (sol.corrected_state.mass.prop_mass_kg - 100.0 - sol.correction[0]).abs()
+                < f64::EPSILON
+        );
+        assert!((sol.achieved_state.orbit.sma_km().unwrap() - 8_030.0).abs() < 1e-3);
+
+        // The STM does not include the spacecraft parameters
+        assert!(matches!(
+            thrust_tgt.try_achieve_stm(sc, epoch, achievement, almanac),
+            Err(TargetingError::UnsupportedVariable { .. })
+        ));
+    }
+
+    #[test]
+    fn free_time_of_flight() {
+        let almanac = fixtures::almanac();
+        let eme2k = fixtures::eme2k();
+        let epoch = Epoch::from_gregorian_utc_at_midnight(2023, 3, 1);
+        let orbit =
+            Orbit::try_keplerian(7_000.0, 0.0, 28.5, 10.0, 20.0, 0.0, epoch, eme2k).unwrap();
+        let sc = Spacecraft::from(orbit);
+
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+
+        // Hohmann transfer to 8000 km: the apoapsis of the transfer orbit is reached after half of its period.
+        let transfer_sma_km: f64 = 7_500.0;
+        let half_period_s = PI * (transfer_sma_km.powi(3) / eme2k.mu_km3_s2().unwrap()).sqrt();
+        let hohmann_dv_km_s = orbit.vmag_km_s() * ((8_000.0 / transfer_sma_km).sqrt() - 1.0);
+        let objectives = [
+            Objective::within_tolerance(StateParameter::Rmag, 8_000.0, 1e-3),
+            Objective::within_tolerance(StateParameter::FlightPathAngle, 0.0, 1e-4),
+        ];
+
+        // Free arrival epoch
+        let tgt = Targeter {
+            prop: &prop,
+            objectives,
+            objective_frame: None,
+            variables: [
+                Variable::from(Vary::VelocityX).with_initial_guess(0.2),
+                Vary::AchievementEpoch.into(),
+            ],
+            correction_frame: Some(LocalFrame::VNC),
+            iterations: 50,
+        };
+
+        let sol = tgt
+            .try_achieve_fd(sc, epoch, epoch + Unit::Minute * 50, almanac.clone())
+            .unwrap();
+        println!("{sol}");
+
+        assert!((sol.correction[0] - hohmann_dv_km_s).abs() < 1e-5);
+        let tof_s = (sol.achieved_state.epoch() - sol.corrected_state.epoch()).to_seconds();
+        assert!(
+            (tof_s - half_period_s).abs() < 1e-1,
+            "{tof_s} != {half_period_s}"
+        );
+
+        // Free departure epoch: the apoapsis must be reached at a fixed epoch
+        let arrival = epoch + Unit::Minute * 60;
+        let tgt = Targeter {
+            prop: &prop,
+            objectives,
+            objective_frame: None,
+            variables: [
+                Variable::from(Vary::VelocityX).with_initial_guess(0.2),
+                Vary::CorrectionEpoch.into(),
+            ],
+            correction_frame: Some(LocalFrame::VNC),
+            iterations: 50,
+        };
+
+        let sol = tgt
+            .try_achieve_fd(sc, epoch + Unit::Minute * 5, arrival, almanac.clone())
+            .unwrap();
+        println!("{sol}");
+
+        assert!((sol.correction[0] - hohmann_dv_km_s).abs() < 1e-5);
+        assert_eq!(sol.achieved_state.epoch(), arrival);
+        let tof_s = (arrival - sol.corrected_state.epoch()).to_seconds();
+        assert!(
+            (tof_s - half_period_s).abs() < 1e-1,
+            "{tof_s} != {half_period_s}"
+        );
+
+        // The corrected state achieves the objectives when propagated again.
+        let (xf, _) = tgt.apply_with_traj(&sol, almanac.clone()).unwrap();
+        assert!((xf.orbit.rmag_km() - 8_000.0).abs() < 1e-3);
+
+        // The epochs cannot be solved with the STM
+        assert!(matches!(
+            tgt.try_achieve_stm(sc, epoch + Unit::Minute * 5, arrival, almanac),
+            Err(TargetingError::UnsupportedVariable { .. })
+        ));
     }
 }
@@ -19,16 +19,17 @@
 use snafu::{ensure, ResultExt};
 
 use super::solution::TargeterSolution;
+use crate::active_pseudo_inverse;
 use crate::cosmic::AstroAlmanacSnafu;
 use crate::errors::TargetingError;
-use crate::linalg::{DMatrix, SVector};
+use crate::linalg::{DMatrix, SVector, Vector6};
 use crate::md::{prelude::*, PropSnafu, UnderdeterminedProblemSnafu};
 use crate::md::{AstroSnafu, StateParameter};
 pub use crate::md::{Variable, Vary};
-use crate::pseudo_inverse;
 use crate::utils::are_eigenvalues_stable;
 #[cfg(not(target_arch = "wasm32"))]
 use std::time::Instant;
+use tracing::{debug, debug_span, info, info_span, warn};
 
 impl<const V: usize, const O: usize> Targeter<'_, V, O> {
     /// Differential correction using hyperdual numbers for the objectives
@@ -56,7 +57,8 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
             .prop
             .with(initial_state, almanac.clone())
             .until_epoch(correction_epoch)
-            .context(PropSnafu)?;
+            .context(PropSnafu)
+            .map_err(|e| e.with_iterate(0, &[0.0; V]))?;
 
         debug!("initial_state = {initial_state:?}");
         debug!("xi_start = {xi_start:?}");
@@ -122,7 +124,15 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
         #[cfg(not(target_arch = "wasm32"))]
         let start_instant = Instant::now();
 
+        let _span = info_span!(
+            "targeter",
+            method = "hyperdual",
+            variables = V,
+            objectives = O
+        )
+        .entered();
         for it in 0..=self.iterations {
+            let _iteration = debug_span!("iteration", it).entered();
             // Now, enable the trajectory STM for this state so we can apply the correction
             xi.enable_stm();
 
@@ -131,7 +141,8 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
                 .prop
                 .with(xi, almanac.clone())
                 .until_epoch(achievement_epoch)
-                .context(PropSnafu)?;
+                .context(PropSnafu)
+                .map_err(|e| e.with_iterate(it, total_correction.as_slice()))?;
 
             // Check linearization
             if !are_eigenvalues_stable(xf.stm().unwrap().complex_eigenvalues()) {
@@ -156,6 +167,7 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
             // Build the error vector
             let mut err_vector = SVector::<f64, O>::zeros();
             let mut converged = true;
+            let mut active = [true; O];
 
             // Build the B-Plane once, if needed, and always in the objective frame
             let b_plane = if is_bplane_tgt {
@@ -172,21 +184,35 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
             let mut jac = DMatrix::from_element(self.objectives.len(), self.variables.len(), 0.0);
 
             for (i, obj) in self.objectives.iter().enumerate() {
-                let xf_partial = if obj.parameter.is_b_plane() {
-                    match obj.parameter {
-                        StateParameter::BdotR => b_plane.unwrap().b_r,
-                        StateParameter::BdotT => b_plane.unwrap().b_t,
-                        StateParameter::BLTOF => b_plane.unwrap().ltof_s,
-                        _ => unreachable!(),
-                    }
+                let (achieved, partial) = if obj.requires_almanac() {
+                    obj.evaluate(xf.orbit, &almanac).context(AstroSnafu)?
                 } else {
-                    xf_dual_obj_frame
-                        .partial_for(obj.parameter)
-                        .context(AstroSnafu)?
+                    let xf_partial = if obj.parameter.is_b_plane() {
+                        match obj.parameter {
+                            StateParameter::BdotR => b_plane.unwrap().b_r,
+                            StateParameter::BdotT => b_plane.unwrap().b_t,
+                            StateParameter::BLTOF => b_plane.unwrap().ltof_s,
+                            _ => unreachable!(),
+                        }
+                    } else {
+                        xf_dual_obj_frame
+                            .partial_for(obj.parameter)
+                            .context(AstroSnafu)?
+                    };
+
+                    (
+                        xf_partial.real(),
+                        Vector6::new(
+                            xf_partial.wtr_x(),
+                            xf_partial.wtr_y(),
+                            xf_partial.wtr_z(),
+                            xf_partial.wtr_vx(),
+                            xf_partial.wtr_vy(),
+                            xf_partial.wtr_vz(),
+                        ),
+                    )
                 };
 
-                let achieved = xf_partial.real();
-
                 let (ok, param_err) = obj.assess_value(achieved);
                 if !ok {
                     converged = false;
@@ -201,23 +227,16 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
                     param_err, width=width, prec=max_obj_tol
                 ));
 
+                // Inactive inequality constraints do not restrict the correction, so their partials are not needed.
+                active[i] = obj.is_active(achieved);
+                if !active[i] {
+                    continue;
+                }
+
                 // Build the Jacobian with the partials of the objectives with respect to all of the final state parameters
                 // We localize the problem in the STM.
                 // TODO: VNC (how?!)
-                let mut partial_vec = DMatrix::from_element(1, 6, 0.0);
-                for (i, val) in [
-                    xf_partial.wtr_x(),
-                    xf_partial.wtr_y(),
-                    xf_partial.wtr_z(),
-                    xf_partial.wtr_vx(),
-                    xf_partial.wtr_vy(),
-                    xf_partial.wtr_vz(),
-                ]
-                .iter()
-                .enumerate()
-                {
-                    partial_vec[(0, i)] = *val;
-                }
+                let partial_vec = partial.transpose();
 
                 for (j, var) in self.variables.iter().enumerate() {
                     // Grab the STM first.
@@ -225,7 +244,7 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
                     let stm = sc_stm.fixed_view::<6, 6>(0, 0);
                     let idx = var.component.vec_index();
                     // Compute the partial of the objective over all components wrt to all of the components in the STM of the control variable.
-                    let rslt = &partial_vec * stm.fixed_columns::<1>(idx);
+                    let rslt = partial_vec * stm.fixed_columns::<1>(idx);
                     jac[(i, j)] = rslt[(0, 0)];
                 }
             }
@@ -263,7 +282,12 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
                     achieved_objectives: self.objectives,
                     iterations: it,
                 };
-                info!("Targeter -- CONVERGED in {} iterations", it);
+                info!(
+                    iterations = it,
+                    err_norm = err_vector.norm(),
+                    "Targeter -- CONVERGED in {} iterations",
+                    it
+                );
                 for obj in &objmsg {
                     info!("{}", obj);
                 }
@@ -283,7 +307,7 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
             debug!("Jacobian {}", jac);
 
             // Perform the pseudo-inverse if needed, else just inverse
-            let jac_inv = pseudo_inverse!(&jac)?;
+            let jac_inv = active_pseudo_inverse!(&jac, &active)?;
 
             debug!("Inverse Jacobian {}", jac_inv);
 
@@ -333,16 +357,25 @@ impl<const V: usize, const O: usize> Targeter<'_, V, O> {
                     }
                 }
             }
-            total_correction += delta;
+            total_correction += &delta;
             debug!("Total correction: {:e}", total_correction);
 
             // Log progress
-            info!("Targeter -- Iteration #{} -- {}", it, achievement_epoch);
+            info!(
+                iteration = it,
+                err_norm = prev_err_norm,
+                correction_norm = delta.norm(),
+                "Targeter -- Iteration #{} -- {}",
+                it,
+                achievement_epoch
+            );
             for obj in &objmsg {
                 info!("{}", obj);
             }
         }
 
-        Err(TargetingError::TooManyIterations)
+        Err(TargetingError::TooManyIterations {
+            iterations: self.iterations,
+        })
     }
 }
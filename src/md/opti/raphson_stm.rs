@@ -0,0 +1,842 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use anise::errors::OrientationSnafu;
+use snafu::{ensure, ResultExt};
+
+use super::solution::TargeterSolution;
+use crate::active_pseudo_inverse;
+use crate::cosmic::{AstroAlmanacSnafu, AstroPhysicsSnafu};
+use crate::dynamics::guidance::{GuidanceError, LocalFrame, Maneuver, MnvrRepr};
+use crate::dynamics::StmMethod;
+use crate::errors::TargetingError;
+use crate::linalg::{Matrix3, SMatrix, SVector, Vector3, Vector6};
+use crate::md::{prelude::*, GuidanceSnafu, PropSnafu, UnderdeterminedProblemSnafu};
+use crate::md::{AstroSnafu, StateParameter};
+pub use crate::md::{Variable, Vary};
+use crate::polyfit::CommonPolynomial;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+use tracing::{debug, debug_span, error, info, info_span};
+
+/// Minimum number of steps of the burn arc used to integrate the maneuver partials
+const BURN_STEPS: f64 = 50.0;
+
+/// Objectives of a targeter evaluated at the achievement epoch
+pub(super) struct ObjectivesAssessment<const O: usize> {
+    /// Error of each objective
+    pub err_vector: SVector<f64, O>,
+    /// Partials of each objective with respect to the achieved state in the integration frame, zero for inactive objectives
+    pub partials: SMatrix<f64, O, 6>,
+    /// Whether each objective constrains the correction
+    pub active: [bool; O],
+    /// Whether all objectives are achieved
+    pub converged: bool,
+    /// Debugging information on each objective
+    pub objmsg: Vec<String>,
+}
+
+impl<const V: usize, const O: usize> Targeter<'_, V, O> {
+    /// Differential correction where the Jacobian is computed from the state transition matrix (STM).
+    ///
+    /// A single propagation with the STM is needed per iteration, instead of one propagation per variable with finite
+    /// differencing, and no perturbation size needs to be tuned. The partials of impulsive corrections are read from the STM
+    /// between the correction and achievement epochs, rotated into the correction frame if one is set. The partials of finite
+    /// burn variables are integrated along the burn arc from the STM and the partials of the thrust acceleration and mass flow.
+    ///
+    /// The thrust direction rate and acceleration variables are not supported because of their nonlinear representation, and the
+    /// spacecraft parameters (e.g. Cr) and the epochs are not supported because the STM only includes the orbital state: use finite differencing.
+    #[allow(clippy::result_large_err)]
+    pub fn try_achieve_stm(
+        &self,
+        initial_state: Spacecraft,
+        correction_epoch: Epoch,
+        achievement_epoch: Epoch,
+        almanac: Arc<Almanac>,
+    ) -> Result<TargeterSolution<V, O>, TargetingError> {
+        ensure!(!self.objectives.is_empty(), UnderdeterminedProblemSnafu);
+
+        let mut finite_burn_target = false;
+        for var in &self.variables {
+            var.valid()?;
+            if let (Some(frame), Vary::PositionX | Vary::PositionY | Vary::PositionZ) =
+                (self.correction_frame, var.component)
+            {
+                let msg = format!(
+                    "Variable is in frame {frame:?} but that frame cannot be used for a {:?} correction",
+                    var.component
+                );
+                error!("{}", msg);
+                return Err(TargetingError::FrameError { msg });
+            }
+            match var.component {
+                Vary::ThrustRateX
+                | Vary::ThrustRateY
+                | Vary::ThrustRateZ
+                | Vary::ThrustAccelX
+                | Vary::ThrustAccelY
+                | Vary::ThrustAccelZ
+                | Vary::Cr
+                | Vary::Cd
+                | Vary::PropMass
+                | Vary::Thrust
+                | Vary::CorrectionEpoch
+                | Vary::AchievementEpoch => {
+                    return Err(TargetingError::UnsupportedVariable {
+                        var: var.to_string(),
+                    })
+                }
+                _ => finite_burn_target |= var.component.is_finite_burn(),
+            }
+        }
+
+        if finite_burn_target && initial_state.thruster.is_none() {
+            return Err(TargetingError::GuidanceError {
+                source: GuidanceError::NoThrustersDefined,
+            });
+        }
+
+        let xi_start = self
+            .prop
+            .with(initial_state, almanac.clone())
+            .until_epoch(correction_epoch)
+            .context(PropSnafu)
+            .map_err(|e| e.with_iterate(0, &[0.0; V]))?;
+
+        debug!("initial_state = {initial_state}");
+        debug!("xi_start = {xi_start}");
+
+        let mut xi = xi_start;
+        // The correction frame is that of the uncorrected state, such that the total correction is expressed in that frame.
+        let corr_dcm = self.correction_dcm(&xi_start)?;
+        let mut mnvr = Maneuver {
+            start: correction_epoch,
+            end: achievement_epoch,
+            thrust_prct: 1.0,
+            representation: MnvrRepr::Angles {
+                azimuth: CommonPolynomial::Quadratic(0.0, 0.0, 0.0),
+                elevation: CommonPolynomial::Quadratic(0.0, 0.0, 0.0),
+            },
+            frame: LocalFrame::RCN,
+        };
+
+        // Store the total correction in a static vector
+        let mut total_correction = SVector::<f64, V>::zeros();
+
+        // Apply the initial guess
+        for (i, var) in self.variables.iter().enumerate() {
+            self.apply_stm_correction(var, var.init_guess, &corr_dcm, &mut xi, &mut mnvr)?;
+            total_correction[i] += var.init_guess;
+        }
+
+        if finite_burn_target {
+            info!("Initial maneuver guess: {}", mnvr);
+        }
+
+        let mut prev_err_norm = f64::INFINITY;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let start_instant = Instant::now();
+
+        let _span = info_span!("targeter", method = "stm", variables = V, objectives = O).entered();
+        for it in 0..=self.iterations {
+            let _iteration = debug_span!("iteration", it).entered();
+            let (xf, dxf_dvar) = self
+                .propagate_with_partials(
+                    xi,
+                    &corr_dcm,
+                    finite_burn_target.then_some(mnvr),
+                    achievement_epoch,
+                    almanac.clone(),
+                )
+                .map_err(|e| e.with_iterate(it, total_correction.as_slice()))?;
+
+            let ObjectivesAssessment {
+                err_vector,
+                partials,
+                active,
+                converged,
+                objmsg,
+            } = self.assess_objectives(&xf, &almanac)?;
+
+            let jac = partials * dxf_dvar;
+
+            if converged {
+                #[cfg(not(target_arch = "wasm32"))]
+                let conv_dur = Instant::now() - start_instant;
+                #[cfg(target_arch = "wasm32")]
+                let conv_dur = Duration::ZERO.into();
+
+                let sol = TargeterSolution {
+                    corrected_state: xi,
+                    achieved_state: xf,
+                    correction: total_correction,
+                    computation_dur: conv_dur,
+                    variables: self.variables,
+                    achieved_errors: err_vector,
+                    achieved_objectives: self.objectives,
+                    iterations: it,
+                };
+                info!(
+                    iterations = it,
+                    err_norm = err_vector.norm(),
+                    "Targeter -- CONVERGED in {} iterations",
+                    it
+                );
+                for obj in &objmsg {
+                    info!("{}", obj);
+                }
+                return Ok(sol);
+            }
+
+            if (err_vector.norm() - prev_err_norm).abs() < 1e-10 {
+                return Err(TargetingError::CorrectionIneffective {
+                    prev_val: prev_err_norm,
+                    cur_val: err_vector.norm(),
+                    action: "STM targeter",
+                });
+            }
+            prev_err_norm = err_vector.norm();
+
+            debug!("Jacobian {}", jac);
+
+            let jac_inv = active_pseudo_inverse!(&jac, &active)?;
+            let mut delta = jac_inv * err_vector;
+
+            debug!(
+                "Error vector (norm = {}): {}\nRaw correction: {}",
+                err_vector.norm(),
+                err_vector,
+                delta
+            );
+
+            for (i, var) in self.variables.iter().enumerate() {
+                // Choose the minimum step between the provided max step and the correction.
+                if delta[i].abs() > var.max_step.abs() {
+                    delta[i] = var.max_step.abs() * delta[i].signum();
+                }
+                self.apply_stm_correction(var, delta[i], &corr_dcm, &mut xi, &mut mnvr)?;
+                total_correction[i] += delta[i];
+            }
+
+            info!(
+                iteration = it,
+                err_norm = prev_err_norm,
+                correction_norm = delta.norm(),
+                "Targeter -- Iteration #{it}"
+            );
+            for obj in &objmsg {
+                info!("{}", obj);
+            }
+        }
+
+        Err(TargetingError::TooManyIterations {
+            iterations: self.iterations,
+        })
+    }
+
+    /// Evaluates the objectives at the achievement state, and their partials with respect to that state in the integration frame.
+    /// Objectives which require the Almanac are evaluated in their own frame with [Objective::evaluate].
+    #[allow(clippy::result_large_err)]
+    pub(super) fn assess_objectives(
+        &self,
+        xf: &Spacecraft,
+        almanac: &Arc<Almanac>,
+    ) -> Result<ObjectivesAssessment<O>, TargetingError> {
+        let (xf_dual_obj_frame, obj_to_integration) = match &self.objective_frame {
+            Some(frame) => {
+                let orbit_obj_frame = almanac
+                    .transform_to(xf.orbit, *frame, None)
+                    .context(AstroAlmanacSnafu)
+                    .context(AstroSnafu)?;
+                let dcm = almanac
+                    .rotate(xf.orbit.frame, *frame, xf.epoch())
+                    .context(OrientationSnafu {
+                        action: "rotating the final state into the objective frame",
+                    })
+                    .context(AstroAlmanacSnafu)
+                    .context(AstroSnafu)?;
+
+                (OrbitDual::from(orbit_obj_frame), dcm.state_dcm())
+            }
+            None => (OrbitDual::from(xf.orbit), SMatrix::<f64, 6, 6>::identity()),
+        };
+
+        let b_plane = if self.objectives.iter().any(|obj| obj.parameter.is_b_plane()) {
+            Some(BPlane::from_dual(xf_dual_obj_frame).context(AstroSnafu)?)
+        } else {
+            None
+        };
+
+        let mut assessment = ObjectivesAssessment {
+            err_vector: SVector::<f64, O>::zeros(),
+            partials: SMatrix::<f64, O, 6>::zeros(),
+            active: [true; O],
+            converged: true,
+            objmsg: Vec::with_capacity(O),
+        };
+
+        for (i, obj) in self.objectives.iter().enumerate() {
+            let (achieved, partial) = if obj.requires_almanac() {
+                obj.evaluate(xf.orbit, almanac).context(AstroSnafu)?
+            } else {
+                let xf_partial = if obj.parameter.is_b_plane() {
+                    match obj.parameter {
+                        StateParameter::BdotR => b_plane.unwrap().b_r,
+                        StateParameter::BdotT => b_plane.unwrap().b_t,
+                        StateParameter::BLTOF => b_plane.unwrap().ltof_s,
+                        _ => unreachable!(),
+                    }
+                } else {
+                    xf_dual_obj_frame
+                        .partial_for(obj.parameter)
+                        .context(AstroSnafu)?
+                };
+
+                let partial_obj_frame = Vector6::new(
+                    xf_partial.wtr_x(),
+                    xf_partial.wtr_y(),
+                    xf_partial.wtr_z(),
+                    xf_partial.wtr_vx(),
+                    xf_partial.wtr_vy(),
+                    xf_partial.wtr_vz(),
+                );
+
+                (
+                    xf_partial.real(),
+                    obj_to_integration.transpose() * partial_obj_frame,
+                )
+            };
+
+            let (ok, param_err) = obj.assess_value(achieved);
+            if !ok {
+                assessment.converged = false;
+            }
+            assessment.err_vector[i] = param_err;
+            assessment.active[i] = obj.is_active(achieved);
+
+            assessment.objmsg.push(format!(
+                "\t{:?}: achieved = {:.6}\t desired = {:.6}\t scaled error = {:.6}",
+                obj.parameter, achieved, obj.desired_value, param_err
+            ));
+
+            // Inactive inequality constraints do not restrict the correction.
+            if assessment.active[i] {
+                assessment.partials.set_row(i, &partial.transpose());
+            }
+        }
+
+        Ok(assessment)
+    }
+
+    /// Applies a correction of the provided variable to the state at the correction epoch, or to the maneuver.
+    #[allow(clippy::result_large_err)]
+    fn apply_stm_correction(
+        &self,
+        var: &Variable,
+        corr: f64,
+        corr_dcm: &Matrix3<f64>,
+        xi: &mut Spacecraft,
+        mnvr: &mut Maneuver,
+    ) -> Result<(), TargetingError> {
+        match var.component {
+            Vary::PositionX | Vary::PositionY | Vary::PositionZ => {
+                xi.orbit.radius_km[var.component.vec_index()] += corr;
+            }
+            Vary::VelocityX | Vary::VelocityY | Vary::VelocityZ => {
+                let mut dv_km_s = Vector3::zeros();
+                dv_km_s[var.component.vec_index() - 3] = corr;
+                xi.orbit.apply_dv_km_s(corr_dcm * dv_km_s);
+            }
+            Vary::StartEpoch => mnvr.start += corr.seconds(),
+            Vary::Duration | Vary::EndEpoch => mnvr.end += corr.seconds(),
+            Vary::MnvrAlpha | Vary::MnvrAlphaDot | Vary::MnvrAlphaDDot => {
+                if let MnvrRepr::Angles { azimuth, elevation } = mnvr.representation {
+                    let azimuth = azimuth
+                        .add_val_in_order(corr, var.component.vec_index())
+                        .unwrap();
+                    mnvr.representation = MnvrRepr::Angles { azimuth, elevation };
+                }
+            }
+            Vary::MnvrDelta | Vary::MnvrDeltaDot | Vary::MnvrDeltaDDot => {
+                if let MnvrRepr::Angles { azimuth, elevation } = mnvr.representation {
+                    let elevation = elevation
+                        .add_val_in_order(corr, var.component.vec_index())
+                        .unwrap();
+                    mnvr.representation = MnvrRepr::Angles { azimuth, elevation };
+                }
+            }
+            Vary::ThrustX | Vary::ThrustY | Vary::ThrustZ => {
+                let mut vector = mnvr.direction();
+                vector[var.component.vec_index()] += corr;
+                var.ensure_bounds(&mut vector[var.component.vec_index()]);
+                mnvr.set_direction(vector).context(GuidanceSnafu)?;
+            }
+            Vary::ThrustLevel => {
+                mnvr.thrust_prct += corr;
+                var.ensure_bounds(&mut mnvr.thrust_prct);
+                mnvr.thrust_prct = mnvr.thrust_prct.clamp(0.0, 1.0);
+            }
+            _ => {
+                return Err(TargetingError::UnsupportedVariable {
+                    var: var.to_string(),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the rotation from the correction frame to the integration frame at the provided state.
+    #[allow(clippy::result_large_err)]
+    pub(super) fn correction_dcm(
+        &self,
+        state: &Spacecraft,
+    ) -> Result<Matrix3<f64>, TargetingError> {
+        match self.correction_frame {
+            Some(frame) => Ok(frame
+                .dcm_to_inertial(state.orbit)
+                .context(AstroPhysicsSnafu)
+                .context(AstroSnafu)?
+                .rot_mat),
+            None => Ok(Matrix3::identity()),
+        }
+    }
+
+    /// Propagates the state at the correction epoch until the achievement epoch, executing the maneuver if any, and returns the
+    /// final state and the partials of the final position and velocity with respect to each variable.
+    #[allow(clippy::result_large_err)]
+    fn propagate_with_partials(
+        &self,
+        xi: Spacecraft,
+        corr_dcm: &Matrix3<f64>,
+        mnvr: Option<Maneuver>,
+        achievement_epoch: Epoch,
+        almanac: Arc<Almanac>,
+    ) -> Result<(Spacecraft, SMatrix<f64, 6, V>), TargetingError> {
+        // All of the STMs are computed from the correction epoch.
+        let xi_stm = xi.with_stm();
+        let mut dxf_dvar = SMatrix::<f64, 6, V>::zeros();
+
+        let (xf, burn_states) = match mnvr {
+            None => {
+                let xf = self
+                    .prop
+                    .with(xi_stm, almanac)
+                    .until_epoch(achievement_epoch)
+                    .context(PropSnafu)?;
+                (xf, Vec::new())
+            }
+            Some(mnvr) => {
+                let pre_mnvr = self
+                    .prop
+                    .with(xi_stm, almanac.clone())
+                    .until_epoch(mnvr.start)
+                    .context(PropSnafu)?;
+                // The partials of the thrust are only available by finite differencing the dynamics, and the burn arc is
+                // sampled finely enough to integrate the partials of the maneuver.
+                let mut prop = self.prop.clone();
+                prop.dynamics = prop
+                    .dynamics
+                    .with_guidance_law(Arc::new(mnvr))
+                    .with_stm_method(StmMethod::FiniteDifference);
+                prop.set_max_step(mnvr.duration() / BURN_STEPS);
+                let (post_mnvr, burn_traj) = prop
+                    .with(
+                        pre_mnvr.with_guidance_mode(GuidanceMode::Thrust),
+                        almanac.clone(),
+                    )
+                    .until_epoch_with_traj(mnvr.end)
+                    .context(PropSnafu)?;
+                let xf = self
+                    .prop
+                    .with(post_mnvr, almanac)
+                    .until_epoch(achievement_epoch)
+                    .context(PropSnafu)?;
+                (xf, burn_traj.states)
+            }
+        };
+
+        let phi_f = xf.stm().unwrap();
+        let decrement_mass = self.prop.dynamics.decrement_mass;
+
+        // State transition matrix from the provided state of the burn arc to the achievement epoch
+        let phi_f_from = |state: &Spacecraft| -> Result<SMatrix<f64, 9, 9>, TargetingError> {
+            match state.stm().unwrap().try_inverse() {
+                Some(phi_inv) => Ok(phi_f * phi_inv),
+                None => Err(TargetingError::SingularJacobian),
+            }
+        };
+
+        for (j, var) in self.variables.iter().enumerate() {
+            let column: SVector<f64, 9> = match var.component {
+                Vary::PositionX | Vary::PositionY | Vary::PositionZ => {
+                    phi_f.column(var.component.vec_index()).into_owned()
+                }
+                Vary::VelocityX | Vary::VelocityY | Vary::VelocityZ => {
+                    let dir = corr_dcm.column(var.component.vec_index() - 3).into_owned();
+                    phi_f.fixed_columns::<3>(3) * dir
+                }
+                Vary::StartEpoch | Vary::Duration | Vary::EndEpoch => {
+                    let mnvr = mnvr.unwrap();
+                    let (state, sign) = if var.component == Vary::StartEpoch {
+                        (burn_states.first().unwrap(), -1.0)
+                    } else {
+                        (burn_states.last().unwrap(), 1.0)
+                    };
+                    // Moving the start or end of the burn adds or removes the thrust at that edge of the burn arc.
+                    let mut col =
+                        sign * phi_f_from(state)? * thrust_rates(state, &mnvr, decrement_mass)?;
+                    if var.component == Vary::StartEpoch {
+                        // The direction of the burn is defined with respect to its start, so it is also delayed.
+                        col -= integrate_burn(&burn_states, &phi_f_from, |state| {
+                            let dt = (state.epoch() - mnvr.start).to_seconds();
+                            let mut rates = SVector::<f64, 9>::zeros();
+                            rates.fixed_rows_mut::<3>(3).copy_from(
+                                &(thrust_accel(state, &mnvr)? * direction_rate(&mnvr, dt)),
+                            );
+                            Ok(rates)
+                        })?;
+                    }
+                    col
+                }
+                Vary::MnvrAlpha
+                | Vary::MnvrAlphaDot
+                | Vary::MnvrAlphaDDot
+                | Vary::MnvrDelta
+                | Vary::MnvrDeltaDot
+                | Vary::MnvrDeltaDDot => {
+                    let mnvr = mnvr.unwrap();
+                    integrate_burn(&burn_states, &phi_f_from, |state| {
+                        let dt = (state.epoch() - mnvr.start).to_seconds();
+                        let mut rates = SVector::<f64, 9>::zeros();
+                        rates.fixed_rows_mut::<3>(3).copy_from(
+                            &(thrust_accel(state, &mnvr)?
+                                * angle_partial(&mnvr, var.component, dt)),
+                        );
+                        Ok(rates)
+                    })?
+                }
+                Vary::ThrustX | Vary::ThrustY | Vary::ThrustZ => {
+                    let mnvr = mnvr.unwrap();
+                    let mut unit = Vector3::zeros();
+                    unit[var.component.vec_index()] = 1.0;
+                    integrate_burn(&burn_states, &phi_f_from, |state| {
+                        let mut rates = SVector::<f64, 9>::zeros();
+                        rates
+                            .fixed_rows_mut::<3>(3)
+                            .copy_from(&(thrust_accel(state, &mnvr)? * unit));
+                        Ok(rates)
+                    })?
+                }
+                Vary::ThrustLevel => {
+                    let mnvr = mnvr.unwrap();
+                    // The thrust and mass flow are proportional to the thrust level.
+                    integrate_burn(&burn_states, &phi_f_from, |state| {
+                        Ok(thrust_rates(state, &mnvr, decrement_mass)?
+                            / mnvr.thrust_prct.max(f64::EPSILON))
+                    })?
+                }
+                _ => {
+                    return Err(TargetingError::UnsupportedVariable {
+                        var: var.to_string(),
+                    })
+                }
+            };
+            dxf_dvar.set_column(j, &column.fixed_rows::<6>(0));
+        }
+
+        Ok((xf, dxf_dvar))
+    }
+}
+
+/// Integrates the effect on the final state of the provided state rates along the burn arc with the trapezoidal rule.
+#[allow(clippy::result_large_err)]
+fn integrate_burn<P, F>(
+    burn_states: &[Spacecraft],
+    phi_f_from: &P,
+    rates: F,
+) -> Result<SVector<f64, 9>, TargetingError>
+where
+    P: Fn(&Spacecraft) -> Result<SMatrix<f64, 9, 9>, TargetingError>,
+    F: Fn(&Spacecraft) -> Result<SVector<f64, 9>, TargetingError>,
+{
+    let mut integral = SVector::<f64, 9>::zeros();
+    let mut prev: Option<(Epoch, SVector<f64, 9>)> = None;
+    for state in burn_states {
+        let this = phi_f_from(state)? * rates(state)?;
+        if let Some((prev_epoch, prev_val)) = prev {
+            let dt = (state.epoch() - prev_epoch).to_seconds();
+            integral += 0.5 * dt * (prev_val + this);
+        }
+        prev = Some((state.epoch(), this));
+    }
+    Ok(integral)
+}
+
+/// Returns the matrix mapping a direction in the frame of the maneuver to the thrust acceleration in the integration frame, in km/s^2.
+#[allow(clippy::result_large_err)]
+fn thrust_accel(state: &Spacecraft, mnvr: &Maneuver) -> Result<Matrix3<f64>, TargetingError> {
+    let thruster = state.thruster.unwrap();
+    let accel_km_s2 = mnvr.thrust_prct * thruster.mean_thrust_N() * 1e-3 / state.mass_kg();
+    let dcm = match mnvr.frame {
+        LocalFrame::Inertial => Matrix3::identity(),
+        frame => {
+            frame
+                .dcm_to_inertial(state.orbit)
+                .context(AstroPhysicsSnafu)
+                .context(AstroSnafu)?
+                .rot_mat
+        }
+    };
+    Ok(accel_km_s2 * dcm)
+}
+
+/// Returns the rates of the state due to the thrust of the maneuver, i.e. the thrust acceleration and the mass flow.
+#[allow(clippy::result_large_err)]
+fn thrust_rates(
+    state: &Spacecraft,
+    mnvr: &Maneuver,
+    decrement_mass: bool,
+) -> Result<SVector<f64, 9>, TargetingError> {
+    let thruster = state.thruster.unwrap();
+    let mut rates = SVector::<f64, 9>::zeros();
+    rates
+        .fixed_rows_mut::<3>(3)
+        .copy_from(&(thrust_accel(state, mnvr)? * mnvr.vector(state.epoch())));
+    if decrement_mass {
+        rates[8] = -mnvr.thrust_prct * thruster.mean_mass_flow_kg_s();
+    }
+    Ok(rates)
+}
+
+/// Returns the partial of the thrust direction with respect to the provided angle coefficient, `dt` seconds after the start of the maneuver.
+fn angle_partial(mnvr: &Maneuver, component: Vary, dt: f64) -> Vector3<f64> {
+    let (alpha, delta) = angles(mnvr, dt);
+    // The coefficient of the constant term has index two, that of the rate index one, and that of the acceleration index zero.
+    let factor = dt.powi(2 - component.vec_index() as i32);
+    match component {
+        Vary::MnvrAlpha | Vary::MnvrAlphaDot | Vary::MnvrAlphaDDot => {
+            factor * Vector3::new(-delta.cos() * alpha.sin(), delta.cos() * alpha.cos(), 0.0)
+        }
+        _ => {
+            factor
+                * Vector3::new(
+                    -delta.sin() * alpha.cos(),
+                    -delta.sin() * alpha.sin(),
+                    delta.cos(),
+                )
+        }
+    }
+}
+
+/// Returns the time derivative of the thrust direction, `dt` seconds after the start of the maneuver.
+fn direction_rate(mnvr: &Maneuver, dt: f64) -> Vector3<f64> {
+    match mnvr.representation {
+        MnvrRepr::Vector(_) => Vector3::zeros(),
+        MnvrRepr::Angles { azimuth, elevation } => {
+            angle_partial(mnvr, Vary::MnvrAlpha, dt) * azimuth.deriv(dt)
+                + angle_partial(mnvr, Vary::MnvrDelta, dt) * elevation.deriv(dt)
+        }
+    }
+}
+
+/// Returns the in-plane and out-of-plane angles of the maneuver, `dt` seconds after its start.
+fn angles(mnvr: &Maneuver, dt: f64) -> (f64, f64) {
+    match mnvr.representation {
+        MnvrRepr::Angles { azimuth, elevation } => (azimuth.eval(dt), elevation.eval(dt)),
+        MnvrRepr::Vector(vector) => {
+            crate::dynamics::guidance::ra_dec_from_unit_vector(vector / vector.norm())
+        }
+    }
+}
+
+#[cfg(test)]
+mod ut_raphson_stm {
+    use super::*;
+    use crate::cosmic::Orbit;
+    use crate::dynamics::guidance::Thruster;
+    use crate::fixtures;
+    use crate::md::objective::Objective;
+    use crate::progress::CancellationToken;
+    use anise::structure::spacecraft::Mass;
+
+    #[test]
+    fn vnc_stm_vs_fd() {
+        let almanac = fixtures::almanac();
+        let eme2k = fixtures::eme2k();
+        let epoch = Epoch::from_gregorian_utc_at_midnight(2023, 3, 1);
+        let orbit =
+            Orbit::try_keplerian(8_000.0, 0.05, 28.5, 10.0, 20.0, 30.0, epoch, eme2k).unwrap();
+        let sc = Spacecraft::from(orbit);
+
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let tgt = Targeter::vnc(
+            &prop,
+            [
+                Objective::within_tolerance(StateParameter::SMA, 8_100.0, 1e-3),
+                Objective::within_tolerance(StateParameter::Eccentricity, 0.06, 1e-6),
+                Objective::within_tolerance(StateParameter::Inclination, 28.6, 1e-5),
+            ],
+        );
+
+        let achievement = epoch + Unit::Hour * 1;
+        let sol_stm = tgt
+            .try_achieve_stm(sc, epoch, achievement, almanac.clone())
+            .unwrap();
+        let sol_fd = tgt.try_achieve_fd(sc, epoch, achievement, almanac).unwrap();
+        println!("{sol_stm}\n{sol_fd}");
+
+        assert!((sol_stm.correction - sol_fd.correction).norm() < 1e-5);
+        assert!(sol_stm.iterations <= sol_fd.iterations);
+
+        // The corrected state of the STM targeter includes the VNC correction.
+        let dv_vnc = LocalFrame::VNC
+            .dcm_to_inertial(orbit)
+            .unwrap()
+            .rot_mat
+            .transpose()
+            * (sol_stm.corrected_state.orbit.velocity_km_s - orbit.velocity_km_s);
+        assert!((dv_vnc - sol_stm.correction).norm() < 1e-12);
+    }
+
+    #[test]
+    fn cancelled() {
+        let almanac = fixtures::almanac();
+        let eme2k = fixtures::eme2k();
+        let epoch = Epoch::from_gregorian_utc_at_midnight(2023, 3, 1);
+        let orbit =
+            Orbit::try_keplerian(8_000.0, 0.05, 28.5, 10.0, 20.0, 30.0, epoch, eme2k).unwrap();
+        let sc = Spacecraft::from(orbit);
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()))
+            .with_cancellation(token);
+        let tgt = Targeter::vnc(
+            &prop,
+            [Objective::within_tolerance(
+                StateParameter::SMA,
+                8_100.0,
+                1e-3,
+            )],
+        );
+
+        let achievement = epoch + Unit::Hour * 1;
+        assert!(matches!(
+            tgt.try_achieve_stm(sc, epoch, achievement, almanac.clone()),
+            Err(TargetingError::Cancelled { iterations: 0, correction }) if correction == vec![0.0; 3]
+        ));
+        assert!(matches!(
+            tgt.try_achieve_fd(sc, epoch, achievement, almanac),
+            Err(TargetingError::Cancelled { iterations: 0, correction }) if correction == vec![0.0; 3]
+        ));
+    }
+
+    #[test]
+    fn finite_burn_stm() {
+        let almanac = fixtures::almanac();
+        let eme2k = fixtures::eme2k();
+        let epoch = Epoch::from_gregorian_utc_at_midnight(2023, 3, 1);
+        let orbit =
+            Orbit::try_keplerian(8_000.0, 0.01, 28.5, 10.0, 20.0, 30.0, epoch, eme2k).unwrap();
+        let sc = Spacecraft::builder()
+            .orbit(orbit)
+            .mass(Mass::from_dry_and_prop_masses(400.0, 100.0))
+            .thruster(Thruster::new(50.0, 300.0))
+            .build();
+
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        // Thrust in the RCN frame for ten minutes, correcting the direction and the duration of the burn.
+        let tgt = Targeter {
+            prop: &prop,
+            objectives: [
+                Objective::within_tolerance(StateParameter::SMA, 8_100.0, 1e-2),
+                Objective::within_tolerance(StateParameter::Inclination, 28.55, 1e-4),
+            ],
+            objective_frame: None,
+            variables: [
+                Variable::from(Vary::MnvrAlpha).with_initial_guess(1.5),
+                Variable::from(Vary::MnvrDelta),
+                Variable::from(Vary::EndEpoch),
+            ],
+            correction_frame: None,
+            iterations: 50,
+        };
+
+        let sol = tgt
+            .try_achieve_stm(sc, epoch, epoch + Unit::Minute * 10, almanac)
+            .unwrap();
+        println!("{sol}");
+
+        let achieved = sol.achieved_state.orbit;
+        assert!((achieved.sma_km().unwrap() - 8_100.0).abs() < 1e-2);
+        assert!((achieved.inc_deg().unwrap() - 28.55).abs() < 1e-4);
+        // The mass flow is accounted for.
+        assert!(sol.achieved_state.mass_kg() < sc.mass_kg());
+    }
+
+    #[test]
+    fn inequality_constraints() {
+        let almanac = fixtures::almanac();
+        let eme2k = fixtures::eme2k();
+        let epoch = Epoch::from_gregorian_utc_at_midnight(2023, 3, 1);
+        // Periapsis radius of 7600 km
+        let orbit =
+            Orbit::try_keplerian(8_000.0, 0.05, 28.5, 10.0, 20.0, 30.0, epoch, eme2k).unwrap();
+        let sc = Spacecraft::from(orbit);
+        let achievement = epoch + Unit::Hour * 1;
+
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let sma = Objective::within_tolerance(StateParameter::SMA, 8_100.0, 1e-3);
+
+        let sma_only = Targeter::vnc(&prop, [sma])
+            .try_achieve_stm(sc, epoch, achievement, almanac.clone())
+            .unwrap();
+
+        // This bound is respected throughout, so it does not change the solution
+        let rp_low = Objective {
+            tolerance: 1e-3,
+            ..Objective::at_least(StateParameter::Periapsis, 7_500.0)
+        };
+        let sol = Targeter::vnc(&prop, [sma, rp_low])
+            .try_achieve_stm(sc, epoch, achievement, almanac.clone())
+            .unwrap();
+        println!("{sol}");
+        assert!((sol.correction - sma_only.correction).norm() < 1e-9);
+
+        // But this one is violated by the SMA only solution, so the targeter seeks it
+        let rp_high = Objective {
+            tolerance: 1e-3,
+            ..Objective::at_least(StateParameter::Periapsis, 7_620.0)
+        };
+        assert!(sma_only.achieved_state.orbit.periapsis_km().unwrap() < 7_620.0);
+        for sol in [
+            Targeter::vnc(&prop, [sma, rp_high])
+                .try_achieve_stm(sc, epoch, achievement, almanac.clone())
+                .unwrap(),
+            Targeter::vnc(&prop, [sma, rp_high])
+                .try_achieve_fd(sc, epoch, achievement, almanac.clone())
+                .unwrap(),
+        ] {
+            println!("{sol}");
+            let achieved = sol.achieved_state.orbit;
+            assert!((achieved.sma_km().unwrap() - 8_100.0).abs() < 1e-3);
+            assert!((achieved.periapsis_km().unwrap() - 7_620.0).abs() < 1e-3);
+        }
+    }
+}
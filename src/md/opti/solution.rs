@@ -156,6 +156,13 @@ impl<const V: usize, const O: usize> TargeterSolution<V, O> {
                     mnvr.thrust_prct += corr;
                     var.ensure_bounds(&mut mnvr.thrust_prct);
                 }
+                // Spacecraft parameters and epochs are already applied to the corrected and achieved states
+                Vary::Cr
+                | Vary::Cd
+                | Vary::PropMass
+                | Vary::Thrust
+                | Vary::CorrectionEpoch
+                | Vary::AchievementEpoch => {}
                 _ => unreachable!(),
             }
         }
@@ -169,9 +176,10 @@ impl<const V: usize, const O: usize> fmt::Display for TargeterSolution<V, O> {
         let mut objmsg = String::from("");
         for (i, obj) in self.achieved_objectives.iter().enumerate() {
             objmsg.push_str(&format!(
-                "\n\t\t{:?} = {:.3} (wanted {:.3} ± {:.1e})",
+                "\n\t\t{:?} = {:.3} (wanted {}{:.3} ± {:.1e})",
                 obj.parameter,
-                obj.desired_value + self.achieved_errors[i],
+                obj.desired_value - self.achieved_errors[i],
+                obj.constraint.prefix(),
                 obj.desired_value,
                 obj.tolerance
             ));
@@ -190,6 +198,21 @@ impl<const V: usize, const O: usize> fmt::Display for TargeterSolution<V, O> {
                     is_only_position = false;
                     "m/s"
                 }
+                Vary::PropMass => {
+                    is_only_position = false;
+                    is_only_velocity = false;
+                    "kg"
+                }
+                Vary::Thrust => {
+                    is_only_position = false;
+                    is_only_velocity = false;
+                    "N"
+                }
+                Vary::CorrectionEpoch | Vary::AchievementEpoch => {
+                    is_only_position = false;
+                    is_only_velocity = false;
+                    "s"
+                }
                 _ => {
                     is_only_position = false;
                     is_only_velocity = false;
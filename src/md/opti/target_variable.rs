@@ -16,7 +16,8 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use crate::cosmic::Frame;
+use crate::cosmic::{Frame, Spacecraft};
+use crate::dynamics::guidance::GuidanceError;
 use crate::errors::TargetingError;
 use std::default::Default;
 use std::f64::consts::{FRAC_PI_2, FRAC_PI_8, PI};
@@ -75,6 +76,19 @@ pub enum Vary {
     ThrustAccelY,
     /// Thrust direction acceleration in Z
     ThrustAccelZ,
+    /// Coefficient of reflectivity of the spacecraft
+    Cr,
+    /// Coefficient of drag of the spacecraft
+    Cd,
+    /// Propellant mass of the spacecraft (kg)
+    PropMass,
+    /// Thrust of the spacecraft thruster (N), e.g. to find the thrust of a guidance law that achieves a rendezvous.
+    /// Unlike [Vary::ThrustLevel], this is not a finite burn variable: it applies to any thrusting arc of the propagation.
+    Thrust,
+    /// Correction (or departure) epoch difference in seconds, i.e. when the impulsive correction is applied
+    CorrectionEpoch,
+    /// Achievement (or arrival) epoch difference in seconds, i.e. when the objectives are evaluated
+    AchievementEpoch,
 }
 
 impl Vary {
@@ -101,6 +115,46 @@ impl Vary {
             || *self == Self::ThrustAccelZ
     }
 
+    /// Returns whether this variable is a parameter of the spacecraft itself, instead of its orbit or of the finite burn.
+    pub fn is_spacecraft_param(&self) -> bool {
+        matches!(self, Self::Cr | Self::Cd | Self::PropMass | Self::Thrust)
+    }
+
+    /// Returns whether this variable changes the time of flight, i.e. the correction or the achievement epoch.
+    pub fn is_time_of_flight(&self) -> bool {
+        matches!(self, Self::CorrectionEpoch | Self::AchievementEpoch)
+    }
+
+    /// Adds the provided correction to this spacecraft parameter of the state.
+    #[allow(clippy::result_large_err)]
+    pub fn apply_to_spacecraft(
+        &self,
+        state: &mut Spacecraft,
+        correction: f64,
+    ) -> Result<(), TargetingError> {
+        match self {
+            Self::Cr => state.srp.coeff_reflectivity += correction,
+            Self::Cd => state.drag.coeff_drag += correction,
+            Self::PropMass => {
+                state.mass.prop_mass_kg = (state.mass.prop_mass_kg + correction).max(0.0)
+            }
+            Self::Thrust => match state.thruster {
+                Some(ref mut thruster) => thruster.thrust_N += correction,
+                None => {
+                    return Err(TargetingError::GuidanceError {
+                        source: GuidanceError::NoThrustersDefined,
+                    })
+                }
+            },
+            _ => {
+                return Err(TargetingError::UnsupportedVariable {
+                    var: format!("{self:?}"),
+                })
+            }
+        }
+        Ok(())
+    }
+
     #[allow(clippy::nonminimal_bool)]
     pub fn vec_index(&self) -> usize {
         match self {
@@ -301,6 +355,38 @@ impl From<Vary> for Variable {
                 init_guess: 1.0,
                 ..Default::default()
             },
+            Vary::Cr | Vary::Cd => Self {
+                component: vary,
+                perturbation: 0.01,
+                max_step: 0.1,
+                max_value: 1.0,
+                min_value: -1.0,
+                ..Default::default()
+            },
+            Vary::PropMass => Self {
+                component: vary,
+                perturbation: 0.1,
+                max_step: 10.0,
+                max_value: 100.0,
+                min_value: -100.0,
+                ..Default::default()
+            },
+            Vary::Thrust => Self {
+                component: vary,
+                perturbation: 0.01,
+                max_step: 1.0,
+                max_value: 10.0,
+                min_value: -10.0,
+                ..Default::default()
+            },
+            Vary::CorrectionEpoch | Vary::AchievementEpoch => Self {
+                component: vary,
+                perturbation: 1.0,
+                max_step: 600.0,
+                max_value: 86_400.0,
+                min_value: -86_400.0,
+                ..Default::default()
+            },
         }
     }
 }
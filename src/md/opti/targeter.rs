@@ -20,7 +20,7 @@ use snafu::ResultExt;
 
 use crate::dynamics::guidance::LocalFrame;
 use crate::errors::TargetingError;
-use crate::md::objective::Objective;
+use crate::md::objective::{Constraint, Objective};
 use crate::md::prelude::*;
 use crate::md::AstroSnafu;
 use crate::md::PropSnafu;
@@ -273,7 +273,7 @@ impl<'a, const V: usize, const O: usize> Targeter<'a, V, O> {
                 println!("{mnvr}");
                 let mut prop = self.prop.clone();
                 prop.dynamics = prop.dynamics.with_guidance_law(Arc::new(mnvr));
-                prop.with(solution.corrected_state, almanac)
+                prop.with(solution.corrected_state, almanac.clone())
                     .until_epoch_with_traj(solution.achieved_state.epoch())
                     .context(PropSnafu)?
             }
@@ -281,7 +281,7 @@ impl<'a, const V: usize, const O: usize> Targeter<'a, V, O> {
                 // This isn't a finite burn maneuver, let's just apply the correction
                 // Propagate until achievement epoch
                 self.prop
-                    .with(solution.corrected_state, almanac)
+                    .with(solution.corrected_state, almanac.clone())
                     .until_epoch_with_traj(solution.achieved_state.epoch())
                     .context(PropSnafu)?
             }
@@ -307,20 +307,30 @@ impl<'a, const V: usize, const O: usize> Targeter<'a, V, O> {
         let mut converged = true;
         let mut param_errors = Vec::new();
         for obj in &self.objectives {
-            let partial = if obj.parameter.is_b_plane() {
+            let value = if obj.requires_almanac() {
+                obj.evaluate(xf.orbit, &almanac).context(AstroSnafu)?.0
+            } else if obj.parameter.is_b_plane() {
                 match obj.parameter {
                     StateParameter::BdotR => b_plane.unwrap().b_r,
                     StateParameter::BdotT => b_plane.unwrap().b_t,
                     StateParameter::BLTOF => b_plane.unwrap().ltof_s,
                     _ => unreachable!(),
                 }
+                .real()
             } else {
-                xf_dual.partial_for(obj.parameter).context(AstroSnafu)?
+                xf_dual
+                    .partial_for(obj.parameter)
+                    .context(AstroSnafu)?
+                    .real()
             };
 
-            let param_err = obj.desired_value - partial.real();
+            let param_err = obj.desired_value - value;
 
-            if param_err.abs() > obj.tolerance {
+            let achieved = match obj.constraint {
+                Constraint::Equal => param_err.abs() <= obj.tolerance,
+                _ => obj.assess_value(value).0,
+            };
+            if !achieved {
                 converged = false;
             }
             param_errors.push(param_err);
@@ -331,9 +341,10 @@ impl<'a, const V: usize, const O: usize> Targeter<'a, V, O> {
             let mut objmsg = String::from("");
             for (i, obj) in self.objectives.iter().enumerate() {
                 objmsg.push_str(&format!(
-                    "{:?} = {:.3} BUT should be {:.3} (± {:.1e}) (error = {:.3})",
+                    "{:?} = {:.3} BUT should be {}{:.3} (± {:.1e}) (error = {:.3})",
                     obj.parameter,
-                    obj.desired_value + param_errors[i],
+                    obj.desired_value - param_errors[i],
+                    obj.constraint.prefix(),
                     obj.desired_value,
                     obj.tolerance,
                     param_errors[i]
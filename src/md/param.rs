@@ -51,6 +51,12 @@ pub enum StateParameter {
     Cr,
     /// Declination (deg) (also called elevation if in a body fixed frame)
     Declination,
+    /// Delaunay L = sqrt(μ a), conjugate to the mean anomaly (km^2/s)
+    DelaunayL,
+    /// Delaunay G = L sqrt(1 - e^2), conjugate to the argument of periapsis (km^2/s)
+    DelaunayG,
+    /// Delaunay H = G cos(i), conjugate to the right ascension of the ascending node (km^2/s)
+    DelaunayH,
     /// Dry mass (kg)
     DryMass,
     /// The epoch of the state
@@ -61,6 +67,14 @@ pub enum StateParameter {
     Eccentricity,
     /// Specific energy
     Energy,
+    /// Equinoctial h = e sin(ω + Ω)
+    EquinoctialH,
+    /// Equinoctial k = e cos(ω + Ω)
+    EquinoctialK,
+    /// Equinoctial p = tan(i/2) sin(Ω)
+    EquinoctialP,
+    /// Equinoctial q = tan(i/2) cos(Ω)
+    EquinoctialQ,
     /// Flight path angle (deg)
     FlightPathAngle,
     /// Geodetic height (km)
@@ -81,12 +95,17 @@ pub enum StateParameter {
     HZ,
     /// Hyperbolic anomaly (deg), only valid for hyperbolic orbits
     HyperbolicAnomaly,
+    /// Illumination factor of the spacecraft by the Sun, from 0.0 (umbra) to 1.0 (full sun), with the Earth and the Moon as
+    /// shadow bodies, requires the Almanac
+    Illumination,
     /// Inclination (deg)
     Inclination,
     /// Specific impulse (isp) in seconds
     Isp,
     /// Mean anomaly (deg)
     MeanAnomaly,
+    /// Mean longitude λ = M + ω + Ω (deg)
+    MeanLongitude,
     /// Periapsis, shortcut for TA == 0.0
     Periapsis,
     /// Radius of periapse (km)
@@ -107,6 +126,16 @@ pub enum StateParameter {
     SMA,
     /// Semi minor axis (km)
     SemiMinorAxis,
+    /// Angle between the Sun and the center of the frame as seen from the spacecraft (deg), requires the Almanac
+    SunAngle,
+    /// Angle between the Sun and the Earth as seen from the spacecraft (deg), requires the Almanac
+    SunProbeEarthAngle,
+    /// Angle between the orbit plane and the direction of the Sun (deg), requires the Almanac
+    BetaAngle,
+    /// Local solar time at the ascending node (hours), requires the Almanac
+    LTAN,
+    /// Phase angle of the Moon as seen from the spacecraft, i.e. the Sun-Moon-spacecraft angle (deg), requires the Almanac
+    MoonPhaseAngle,
     /// Thrust (Newtons)
     Thrust,
     /// Total mass
@@ -137,7 +166,11 @@ impl StateParameter {
     /// Returns the default event finding precision in the unit of that parameter
     pub fn default_event_precision(&self) -> f64 {
         match self {
-            Self::Eccentricity => 1e-5,
+            Self::Eccentricity
+            | Self::EquinoctialH
+            | Self::EquinoctialK
+            | Self::EquinoctialP
+            | Self::EquinoctialQ => 1e-5,
             // Non anomaly angles
             Self::AoL
             | Self::AoP
@@ -148,6 +181,10 @@ impl StateParameter {
             | Self::Inclination
             | Self::RightAscension
             | Self::RAAN
+            | Self::SunAngle
+            | Self::SunProbeEarthAngle
+            | Self::BetaAngle
+            | Self::MoonPhaseAngle
             | Self::TrueLongitude
             | Self::VelocityDeclination => 1e-1,
 
@@ -155,6 +192,7 @@ impl StateParameter {
             Self::Apoapsis
             | Self::Periapsis
             | Self::MeanAnomaly
+            | Self::MeanLongitude
             | Self::EccentricAnomaly
             | Self::HyperbolicAnomaly
             | Self::TrueAnomaly => 1e-3,
@@ -182,8 +220,11 @@ impl StateParameter {
 
             // Special
             Self::Energy => 1e-3,
+            Self::DelaunayL | Self::DelaunayG | Self::DelaunayH => 1e-3,
             Self::DryMass | Self::PropMass => 1e-3,
             Self::Period => 1e-1,
+            Self::LTAN => 1e-3,
+            Self::Illumination => 1e-3,
             _ => unimplemented!("{self} cannot be used for event finding"),
         }
     }
@@ -193,9 +234,24 @@ impl StateParameter {
         matches!(&self, Self::BdotR | Self::BdotT | Self::BLTOF)
     }
 
+    /// Returns whether this parameter depends on other celestial objects, and can therefore only be computed with the Almanac
+    pub const fn requires_almanac(&self) -> bool {
+        matches!(
+            &self,
+            Self::SunAngle
+                | Self::SunProbeEarthAngle
+                | Self::BetaAngle
+                | Self::LTAN
+                | Self::MoonPhaseAngle
+                | Self::Illumination
+        )
+    }
+
     /// Returns whether this is an orbital parameter
     pub const fn is_orbital(&self) -> bool {
-        !self.is_for_spacecraft() && !matches!(self, Self::Apoapsis | Self::Periapsis | Self::Epoch)
+        !self.is_for_spacecraft()
+            && !self.requires_almanac()
+            && !matches!(self, Self::Apoapsis | Self::Periapsis | Self::Epoch)
     }
 
     /// Returns whether this parameter is only applicable to a spacecraft state
@@ -224,11 +280,16 @@ impl StateParameter {
             | Self::Inclination
             | Self::RightAscension
             | Self::RAAN
+            | Self::SunAngle
+            | Self::SunProbeEarthAngle
+            | Self::BetaAngle
+            | Self::MoonPhaseAngle
             | Self::TrueLongitude
             | Self::VelocityDeclination
             | Self::Apoapsis
             | Self::Periapsis
             | Self::MeanAnomaly
+            | Self::MeanLongitude
             | Self::EccentricAnomaly
             | Self::HyperbolicAnomaly
             | Self::TrueAnomaly => "deg",
@@ -256,8 +317,11 @@ impl StateParameter {
 
             Self::C3 | Self::Energy => "km^2/s^2",
 
+            Self::DelaunayL | Self::DelaunayG | Self::DelaunayH => "km^2/s",
+
             Self::DryMass | Self::PropMass => "kg",
             Self::Isp => "isp",
+            Self::LTAN => "h",
             Self::Thrust => "N",
             _ => "",
         }
@@ -314,6 +378,7 @@ impl FromStr for StateParameter {
             "periapsis" => Ok(Self::Periapsis),
             "aol" => Ok(Self::AoL),
             "aop" => Ok(Self::AoP),
+            "beta_angle" => Ok(Self::BetaAngle),
             "bltof" => Ok(Self::BLTOF),
             "bdotr" => Ok(Self::BdotR),
             "bdott" => Ok(Self::BdotT),
@@ -321,11 +386,18 @@ impl FromStr for StateParameter {
             "cd" => Ok(Self::Cd),
             "cr" => Ok(Self::Cr),
             "declin" => Ok(Self::Declination),
+            "delaunay_l" => Ok(Self::DelaunayL),
+            "delaunay_g" => Ok(Self::DelaunayG),
+            "delaunay_h" => Ok(Self::DelaunayH),
             "dry_mass" => Ok(Self::DryMass),
             "apoapsis_radius" => Ok(Self::ApoapsisRadius),
             "ea" => Ok(Self::EccentricAnomaly),
             "ecc" => Ok(Self::Eccentricity),
             "energy" => Ok(Self::Energy),
+            "equinoctial_h" => Ok(Self::EquinoctialH),
+            "equinoctial_k" => Ok(Self::EquinoctialK),
+            "equinoctial_p" => Ok(Self::EquinoctialP),
+            "equinoctial_q" => Ok(Self::EquinoctialQ),
             "fpa" => Ok(Self::FlightPathAngle),
             "guidance_mode" | "mode" => Ok(Self::GuidanceMode),
             "geodetic_height" => Ok(Self::Height),
@@ -336,9 +408,13 @@ impl FromStr for StateParameter {
             "hx" => Ok(Self::HX),
             "hy" => Ok(Self::HY),
             "hz" => Ok(Self::HZ),
+            "illumination" => Ok(Self::Illumination),
             "inc" => Ok(Self::Inclination),
             "isp" => Ok(Self::Isp),
+            "ltan" => Ok(Self::LTAN),
             "ma" => Ok(Self::MeanAnomaly),
+            "mean_lon" => Ok(Self::MeanLongitude),
+            "moon_phase_angle" => Ok(Self::MoonPhaseAngle),
             "periapsis_radius" => Ok(Self::PeriapsisRadius),
             "period" => Ok(Self::Period),
             "prop_mass" => Ok(Self::PropMass),
@@ -348,6 +424,8 @@ impl FromStr for StateParameter {
             "semi_parameter" => Ok(Self::SemiParameter),
             "semi_minor" => Ok(Self::SemiMinorAxis),
             "sma" => Ok(Self::SMA),
+            "sun_angle" => Ok(Self::SunAngle),
+            "spe_angle" => Ok(Self::SunProbeEarthAngle),
             "ta" => Ok(Self::TrueAnomaly),
             "tlong" => Ok(Self::TrueLongitude),
             "thrust" => Ok(Self::Thrust),
@@ -374,6 +452,7 @@ impl fmt::Display for StateParameter {
             Self::Periapsis => "periapsis",
             Self::AoL => "aol",
             Self::AoP => "aop",
+            Self::BetaAngle => "beta_angle",
             Self::BLTOF => "BLToF",
             Self::BdotR => "BdotR",
             Self::BdotT => "BdotT",
@@ -381,12 +460,19 @@ impl fmt::Display for StateParameter {
             Self::Cd => "cd",
             Self::Cr => "cr",
             Self::Declination => "declin",
+            Self::DelaunayL => "delaunay_l",
+            Self::DelaunayG => "delaunay_g",
+            Self::DelaunayH => "delaunay_h",
             Self::DryMass => "dry_mass",
             Self::Epoch => "epoch",
             Self::ApoapsisRadius => "apoapsis_radius",
             Self::EccentricAnomaly => "ea",
             Self::Eccentricity => "ecc",
             Self::Energy => "energy",
+            Self::EquinoctialH => "equinoctial_h",
+            Self::EquinoctialK => "equinoctial_k",
+            Self::EquinoctialP => "equinoctial_p",
+            Self::EquinoctialQ => "equinoctial_q",
             Self::FlightPathAngle => "fpa",
             Self::GuidanceMode => "guidance_mode",
             Self::Height => "geodetic_height",
@@ -397,9 +483,13 @@ impl fmt::Display for StateParameter {
             Self::HX => "hx",
             Self::HY => "hy",
             Self::HZ => "hz",
+            Self::Illumination => "illumination",
             Self::Inclination => "inc",
             Self::Isp => "isp",
+            Self::LTAN => "ltan",
             Self::MeanAnomaly => "ma",
+            Self::MeanLongitude => "mean_lon",
+            Self::MoonPhaseAngle => "moon_phase_angle",
             Self::PeriapsisRadius => "periapsis_radius",
             Self::Period => "period",
             Self::PropMass => "prop_mass",
@@ -409,6 +499,8 @@ impl fmt::Display for StateParameter {
             Self::SemiParameter => "semi_parameter",
             Self::SemiMinorAxis => "semi_minor",
             Self::SMA => "sma",
+            Self::SunAngle => "sun_angle",
+            Self::SunProbeEarthAngle => "spe_angle",
             Self::Thrust => "thrust",
             Self::TotalMass => "total_mass",
             Self::TrueAnomaly => "ta",
@@ -449,11 +541,18 @@ mod ut_state_param {
             StateParameter::Cd,
             StateParameter::Cr,
             StateParameter::Declination,
+            StateParameter::DelaunayL,
+            StateParameter::DelaunayG,
+            StateParameter::DelaunayH,
             StateParameter::DryMass,
             StateParameter::ApoapsisRadius,
             StateParameter::EccentricAnomaly,
             StateParameter::Eccentricity,
             StateParameter::Energy,
+            StateParameter::EquinoctialH,
+            StateParameter::EquinoctialK,
+            StateParameter::EquinoctialP,
+            StateParameter::EquinoctialQ,
             StateParameter::FlightPathAngle,
             StateParameter::GuidanceMode,
             StateParameter::Height,
@@ -464,9 +563,11 @@ mod ut_state_param {
             StateParameter::HX,
             StateParameter::HY,
             StateParameter::HZ,
+            StateParameter::Illumination,
             StateParameter::Inclination,
             StateParameter::Isp,
             StateParameter::MeanAnomaly,
+            StateParameter::MeanLongitude,
             StateParameter::PeriapsisRadius,
             StateParameter::Period,
             StateParameter::PropMass,
@@ -476,6 +577,11 @@ mod ut_state_param {
             StateParameter::SemiParameter,
             StateParameter::SemiMinorAxis,
             StateParameter::SMA,
+            StateParameter::SunAngle,
+            StateParameter::SunProbeEarthAngle,
+            StateParameter::BetaAngle,
+            StateParameter::LTAN,
+            StateParameter::MoonPhaseAngle,
             StateParameter::Thrust,
             StateParameter::TotalMass,
             StateParameter::TrueAnomaly,
@@ -0,0 +1,425 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::dynamics::guidance::{FiniteBurns, LocalFrame, Maneuver};
+use crate::dynamics::SpacecraftDynamics;
+use crate::io::{ConfigError, ConfigRepr, ParseJsonSnafu, ParseSnafu, ReadSnafu};
+use crate::linalg::Vector3;
+use crate::md::trajectory::{StitchedTraj, TrajError};
+use crate::propagators::{PropagationError, Propagator};
+use crate::time::Epoch;
+use crate::{Spacecraft, State};
+use anise::almanac::Almanac;
+use anise::errors::PhysicsError;
+use serde::{Deserialize, Serialize};
+use snafu::prelude::*;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug, PartialEq, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum ManeuverPlanError {
+    #[snafu(display("invalid maneuver plan: {msg}"))]
+    InvalidPlan { msg: String },
+    #[snafu(display("maneuver plan execution encountered {source}"))]
+    PlanPropagation { source: PropagationError },
+    #[snafu(display("computing the local frame of the maneuver at {epoch} encountered {source}"))]
+    PlanLocalFrame { epoch: Epoch, source: PhysicsError },
+    #[snafu(display("building the trajectory of the maneuver plan encountered {source}"))]
+    PlanTrajectory { source: TrajError },
+}
+
+/// A maneuver of a maneuver plan.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PlannedManeuver {
+    /// Instantaneous change of velocity, in km/s in the local frame (i.e. the attitude of the burn)
+    Impulsive {
+        epoch: Epoch,
+        dv_km_s: Vector3<f64>,
+        frame: LocalFrame,
+    },
+    /// Finite burn executed with the thruster of the spacecraft, whose attitude is set by the representation and frame of
+    /// the maneuver
+    Finite(Maneuver),
+}
+
+impl PlannedManeuver {
+    /// Epoch at which this maneuver starts
+    pub fn start(&self) -> Epoch {
+        match self {
+            Self::Impulsive { epoch, .. } => *epoch,
+            Self::Finite(mnvr) => mnvr.start,
+        }
+    }
+
+    /// Epoch at which this maneuver ends, i.e. its start for an impulsive maneuver
+    pub fn end(&self) -> Epoch {
+        match self {
+            Self::Impulsive { epoch, .. } => *epoch,
+            Self::Finite(mnvr) => mnvr.end,
+        }
+    }
+}
+
+impl fmt::Display for PlannedManeuver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Impulsive {
+                epoch,
+                dv_km_s,
+                frame,
+            } => write!(
+                f,
+                "Impulsive maneuver @ {epoch}: {:.3} m/s in {frame:?} [{:.6}, {:.6}, {:.6}] km/s",
+                dv_km_s.norm() * 1e3,
+                dv_km_s.x,
+                dv_km_s.y,
+                dv_km_s.z
+            ),
+            Self::Finite(mnvr) => write!(f, "{mnvr}"),
+        }
+    }
+}
+
+/// A maneuver plan is a list of impulsive and finite maneuvers, which may be loaded from a YAML (or JSON) file, e.g.
+///
+/// ```yaml
+/// maneuvers:
+///   - !Impulsive
+///     epoch: 2024-01-01T01:00:00 UTC
+///     dv_km_s: [0.01, 0.0, 0.0]
+///     frame: VNC
+/// ```
+///
+/// Plans may also be stored as JSON files, e.g. `{"maneuvers": [{"Impulsive": {"epoch": ..., "dv_km_s": [...], "frame": "VNC"}}]}`,
+/// which are loaded as such if their extension is `json`. The maneuvers must be in chronological order and must not overlap.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ManeuverPlan {
+    pub maneuvers: Vec<PlannedManeuver>,
+}
+
+impl ConfigRepr for ManeuverPlan {
+    /// Loads a maneuver plan from a YAML file, or from a JSON file if the extension is `json`.
+    fn load<P>(path: P) -> Result<Self, ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let is_json = path
+            .as_ref()
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        let reader = BufReader::new(File::open(path).context(ReadSnafu)?);
+
+        if is_json {
+            serde_json::from_reader(reader).context(ParseJsonSnafu)
+        } else {
+            serde_yml::from_reader(reader).context(ParseSnafu)
+        }
+    }
+}
+
+impl ManeuverPlan {
+    /// Builds a plan from the provided maneuvers, sorted chronologically.
+    pub fn new(mut maneuvers: Vec<PlannedManeuver>) -> Result<Self, ManeuverPlanError> {
+        maneuvers.sort_by_key(|mnvr| mnvr.start());
+        let me = Self { maneuvers };
+        me.validate()?;
+        Ok(me)
+    }
+
+    /// Checks that the maneuvers are chronological, that they do not overlap, and that the finite burns end after they start.
+    pub fn validate(&self) -> Result<(), ManeuverPlanError> {
+        for (idx, mnvr) in self.maneuvers.iter().enumerate() {
+            ensure!(
+                mnvr.end() >= mnvr.start(),
+                InvalidPlanSnafu {
+                    msg: format!("maneuver #{idx} ends before it starts")
+                }
+            );
+            if let Some(next) = self.maneuvers.get(idx + 1) {
+                ensure!(
+                    next.start() >= mnvr.end(),
+                    InvalidPlanSnafu {
+                        msg: format!(
+                            "maneuver #{} starting {} overlaps maneuver #{idx} ending {}",
+                            idx + 1,
+                            next.start(),
+                            mnvr.end()
+                        )
+                    }
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the finite burns of this plan
+    pub fn finite_burns(&self) -> Vec<Maneuver> {
+        self.maneuvers
+            .iter()
+            .filter_map(|mnvr| match mnvr {
+                PlannedManeuver::Finite(mnvr) => Some(*mnvr),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Total delta-v of the impulsive maneuvers, in m/s
+    pub fn impulsive_dv_m_s(&self) -> f64 {
+        self.maneuvers
+            .iter()
+            .map(|mnvr| match mnvr {
+                PlannedManeuver::Impulsive { dv_km_s, .. } => dv_km_s.norm() * 1e3,
+                _ => 0.0,
+            })
+            .sum()
+    }
+
+    /// Propagates the spacecraft until the end epoch while executing this plan, and returns the final state and the
+    /// trajectory, with one segment between the boundaries of each maneuver.
+    ///
+    /// The finite burns are flown with the thruster of the spacecraft, by adding their guidance law to the dynamics of
+    /// the propagator (replacing any guidance law it may have). If the spacecraft has a thruster, the impulsive maneuvers
+    /// consume propellant per the rocket equation. Maneuvers outside of the propagation time span are ignored.
+    pub fn execute(
+        &self,
+        prop: &Propagator<SpacecraftDynamics>,
+        initial: Spacecraft,
+        end: Epoch,
+        almanac: Arc<Almanac>,
+    ) -> Result<(Spacecraft, StitchedTraj<Spacecraft>), ManeuverPlanError> {
+        self.validate()?;
+        ensure!(
+            end > initial.epoch(),
+            InvalidPlanSnafu {
+                msg: format!(
+                    "end epoch {end} is not after the initial epoch {}",
+                    initial.epoch()
+                )
+            }
+        );
+
+        let finite = self.finite_burns();
+        let prop = if finite.is_empty() {
+            prop.clone()
+        } else {
+            Propagator::new(
+                prop.dynamics
+                    .with_guidance_law(FiniteBurns::from_mnvrs(finite)),
+                prop.method,
+                prop.opts,
+            )
+        };
+
+        let mut state = initial;
+        let mut traj = StitchedTraj::new();
+
+        for mnvr in &self.maneuvers {
+            if mnvr.start() < initial.epoch() || mnvr.start() >= end {
+                warn!(
+                    "Ignoring maneuver starting outside of [{}; {end}): {mnvr}",
+                    initial.epoch()
+                );
+                continue;
+            }
+
+            // Stop at the start of each maneuver to avoid the integrator stepping over its boundaries.
+            if mnvr.start() > state.epoch() {
+                let (pre_mnvr, segment) = prop
+                    .with(state, almanac.clone())
+                    .until_epoch_with_traj(mnvr.start())
+                    .context(PlanPropagationSnafu)?;
+                traj.push(segment).context(PlanTrajectorySnafu)?;
+                state = pre_mnvr;
+            }
+
+            match mnvr {
+                PlannedManeuver::Impulsive {
+                    epoch,
+                    dv_km_s,
+                    frame,
+                } => {
+                    let dv_inertial_km_s = frame
+                        .dcm_to_inertial(state.orbit)
+                        .context(PlanLocalFrameSnafu { epoch: *epoch })?
+                        .rot_mat
+                        * dv_km_s;
+                    if let Some(thruster) = state.thruster {
+                        let impulse_n_s = state.mass.total_mass_kg() * dv_km_s.norm() * 1e3;
+                        if !thruster.can_deliver(impulse_n_s) {
+                            warn!("{mnvr} is below the minimum impulse bit of the thruster ({thruster}): skipped");
+                            continue;
+                        }
+                    }
+                    state.orbit.apply_dv_km_s(dv_inertial_km_s);
+
+                    if let Some(thruster) = state.thruster {
+                        let total_mass_kg = state.mass.total_mass_kg();
+                        let used_kg = total_mass_kg
+                            * (1.0
+                                - (-dv_km_s.norm() * 1e3 / thruster.exhaust_velocity_m_s()).exp());
+                        state.mass.prop_mass_kg -= used_kg;
+                        if !state.update_tanks() || state.mass.prop_mass_kg < 0.0 {
+                            warn!("Propellant exhausted by {mnvr}");
+                        }
+                    }
+                }
+                PlannedManeuver::Finite(burn) => {
+                    let burn_end = burn.end.min(end);
+                    if burn_end > state.epoch() {
+                        let (post_burn, segment) = prop
+                            .with(state, almanac.clone())
+                            .until_epoch_with_traj(burn_end)
+                            .context(PlanPropagationSnafu)?;
+                        traj.push(segment).context(PlanTrajectorySnafu)?;
+                        state = post_burn;
+                    }
+                }
+            }
+            info!("Executed {mnvr}");
+        }
+
+        let (final_state, segment) = prop
+            .with(state, almanac)
+            .until_epoch_with_traj(end)
+            .context(PlanPropagationSnafu)?;
+        traj.push(segment).context(PlanTrajectorySnafu)?;
+
+        Ok((final_state, traj))
+    }
+}
+
+impl fmt::Display for ManeuverPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Maneuver plan of {} maneuvers", self.maneuvers.len())?;
+        for mnvr in &self.maneuvers {
+            write!(f, "\n\t{mnvr}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod ut_plan {
+    use super::*;
+    use crate::dynamics::guidance::Thruster;
+    use crate::dynamics::OrbitalDynamics;
+    use crate::fixtures;
+    use crate::time::Unit;
+    use anise::structure::spacecraft::Mass;
+
+    #[test]
+    fn plan_serde_and_execution() {
+        let yaml = r#"
+maneuvers:
+  - !Finite
+    start: 2024-01-01T03:00:00 UTC
+    end: 2024-01-01T03:10:00 UTC
+    thrust_prct: 1.0
+    representation: !Vector [1.0, 0.0, 0.0]
+    frame: VNC
+  - !Impulsive
+    epoch: 2024-01-01T01:00:00 UTC
+    dv_km_s: [0.1, 0.0, 0.0]
+    frame: VNC
+"#;
+        let loaded: ManeuverPlan = serde_yml::from_str(yaml).unwrap();
+        // The plan is sorted on creation
+        let plan = ManeuverPlan::new(loaded.maneuvers.clone()).unwrap();
+        assert_ne!(plan, loaded);
+        assert!(plan.maneuvers[0].start() < plan.maneuvers[1].start());
+        assert!((plan.impulsive_dv_m_s() - 100.0).abs() < 1e-9);
+        println!("{plan}");
+
+        let reloaded: ManeuverPlan =
+            serde_yml::from_str(&serde_yml::to_string(&plan).unwrap()).unwrap();
+        assert_eq!(reloaded, plan);
+
+        let json_path: std::path::PathBuf =
+            [env!("CARGO_MANIFEST_DIR"), "output_data", "mnvr_plan.json"]
+                .iter()
+                .collect();
+        std::fs::write(&json_path, serde_json::to_string_pretty(&plan).unwrap()).unwrap();
+        assert_eq!(ManeuverPlan::load(&json_path).unwrap(), plan);
+
+        // Overlapping maneuvers are rejected
+        let mut overlapping = plan.maneuvers.clone();
+        overlapping.push(PlannedManeuver::Impulsive {
+            epoch: plan.maneuvers[1].start() + Unit::Minute * 5,
+            dv_km_s: Vector3::zeros(),
+            frame: LocalFrame::Inertial,
+        });
+        assert!(ManeuverPlan::new(overlapping).is_err());
+
+        let epoch = fixtures::epoch();
+        let orbit = fixtures::keplerian(7_000.0, 0.001, 28.5, 0.0, 0.0, 0.0);
+        let sc = Spacecraft::builder()
+            .orbit(orbit)
+            .mass(Mass::from_dry_and_prop_masses(500.0, 100.0))
+            .thruster(Thruster::new(10.0, 300.0))
+            .build();
+
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let almanac = fixtures::almanac();
+        let (final_state, traj) = plan
+            .execute(&prop, sc, epoch + Unit::Hour * 5, almanac.clone())
+            .unwrap();
+        println!("{traj}");
+        assert_eq!(traj.segments.len(), 4);
+        assert!(traj.gaps().is_empty());
+        assert_eq!(final_state.epoch(), epoch + Unit::Hour * 5);
+
+        // The impulsive maneuver changes the velocity along the velocity vector
+        let burn_epoch = epoch + Unit::Hour * 1;
+        let pre_burn = traj.segments[0].last();
+        let (_, post_burn) = traj.at(burn_epoch).unwrap();
+        let dv = post_burn.orbit.velocity_km_s - pre_burn.orbit.velocity_km_s;
+        assert!((dv.norm() - 0.1).abs() < 1e-12);
+        assert!(
+            dv.normalize()
+                .dot(&pre_burn.orbit.velocity_km_s.normalize())
+                > 1.0 - 1e-12
+        );
+
+        // Impulsive propellant usage per the rocket equation
+        let expected_kg = 600.0 * (1.0 - (-100.0 / (300.0 * crate::cosmic::STD_GRAVITY)).exp());
+        assert!((100.0 - post_burn.mass.prop_mass_kg - expected_kg).abs() < 1e-9);
+
+        // The finite burn raises the orbit further and consumes 10 minutes of propellant
+        let after_finite = traj.at(epoch + Unit::Minute * 190).unwrap().1;
+        let before_finite = traj.at(epoch + Unit::Minute * 179).unwrap().1;
+        assert!(after_finite.orbit.sma_km().unwrap() > before_finite.orbit.sma_km().unwrap() + 1.0);
+        let finite_kg = 10.0 * 600.0 / (300.0 * crate::cosmic::STD_GRAVITY);
+        assert!(
+            (before_finite.mass.prop_mass_kg - final_state.mass.prop_mass_kg - finite_kg).abs()
+                < 1e-3,
+            "{} kg",
+            before_finite.mass.prop_mass_kg - final_state.mass.prop_mass_kg
+        );
+
+        // Without a maneuver, the plan is a simple propagation.
+        let (coast, coast_traj) = ManeuverPlan::default()
+            .execute(&prop, sc, epoch + Unit::Hour * 1, almanac)
+            .unwrap();
+        assert_eq!(coast_traj.segments.len(), 1);
+        assert!((coast.orbit.sma_km().unwrap() - 7_000.0).abs() < 1e-6);
+    }
+}
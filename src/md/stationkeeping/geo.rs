@@ -0,0 +1,471 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{vnc_to_inertial, SkManeuver, SkManeuverKind, StationKeepingReport};
+use crate::cosmic::{AstroAlmanacSnafu, AstroPhysicsSnafu};
+use crate::linalg::{Matrix3, Vector3};
+use crate::md::objective::Objective;
+use crate::md::prelude::*;
+use crate::md::{AstroSnafu, PropSnafu, TargetingError};
+use crate::utils::between_pm_180;
+
+use snafu::ResultExt;
+use std::fmt;
+
+/// Rotation rate of the Earth, in rad/s
+const EARTH_ROTATION_RAD_S: f64 = 7.292_115_146_706_979e-5;
+/// Duration of a sidereal day, in seconds
+const DAY_S: f64 = 86_164.090_5;
+
+/// Station keeping of a geostationary orbit within a longitude and inclination box.
+///
+/// The trajectory is propagated and checked every `check_step`. When the longitude is about to leave the box, an East-West
+/// maneuver sets the longitude drift rate for the longitude to cross the box and come back, following the parabola defined by
+/// the longitude acceleration estimated from the history since the previous East-West maneuver. When the inclination exceeds
+/// its bound, a North-South maneuver is executed at the next node. Each maneuver is computed with the VNC targeter, which
+/// re-targets the desired semi-major axis or inclination through the full dynamics of the propagator.
+#[derive(Clone)]
+pub struct GeoStationKeeping {
+    /// Propagator of the station keeping loop, also used by the targeter
+    pub prop: Propagator<SpacecraftDynamics>,
+    /// Body fixed frame in which the longitude is computed, e.g. ITRF93. If unset, the longitude is computed from the
+    /// Greenwich mean sidereal time, ignoring precession and nutation, which is only suitable for preliminary analyses.
+    pub body_fixed_frame: Option<Frame>,
+    /// Center of the longitude box, in degrees
+    pub center_longitude_deg: f64,
+    /// Half width of the longitude box, in degrees
+    pub longitude_half_width_deg: f64,
+    /// Maximum inclination, in degrees
+    pub max_inclination_deg: f64,
+    /// Inclination targeted by the North-South maneuvers, in degrees
+    pub target_inclination_deg: f64,
+    /// Fraction of the box bounds at which a maneuver is planned, leaving the rest as margin for the execution
+    pub trigger_fraction: f64,
+    /// Time between two checks of the box constraints
+    pub check_step: Duration,
+}
+
+impl GeoStationKeeping {
+    /// Initializes a new station keeping loop, planning maneuvers at 90% of the bounds and checking them every six hours.
+    pub fn new(
+        prop: Propagator<SpacecraftDynamics>,
+        body_fixed_frame: Option<Frame>,
+        center_longitude_deg: f64,
+        longitude_half_width_deg: f64,
+        max_inclination_deg: f64,
+    ) -> Self {
+        Self {
+            prop,
+            body_fixed_frame,
+            center_longitude_deg,
+            longitude_half_width_deg,
+            max_inclination_deg,
+            target_inclination_deg: 0.0,
+            trigger_fraction: 0.9,
+            check_step: Unit::Hour * 6,
+        }
+    }
+
+    /// Returns the offset of the longitude of the spacecraft from the center of the box, in degrees
+    #[allow(clippy::result_large_err)]
+    pub fn longitude_offset_deg(
+        &self,
+        sc: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<f64, TargetingError> {
+        let longitude_deg = match self.body_fixed_frame {
+            Some(frame) => almanac
+                .transform_to(sc.orbit, frame, None)
+                .context(AstroAlmanacSnafu)
+                .context(AstroSnafu)?
+                .longitude_deg(),
+            None => {
+                let right_ascension_deg = sc
+                    .orbit
+                    .radius_km
+                    .y
+                    .atan2(sc.orbit.radius_km.x)
+                    .to_degrees();
+                right_ascension_deg - gmst_deg(sc.epoch())
+            }
+        };
+
+        Ok(between_pm_180(longitude_deg - self.center_longitude_deg))
+    }
+
+    /// Propagates the initial state for the provided duration, executing the station keeping maneuvers needed to remain in the box.
+    #[allow(clippy::result_large_err)]
+    pub fn run(
+        &self,
+        initial: Spacecraft,
+        duration: Duration,
+        almanac: Arc<Almanac>,
+    ) -> Result<StationKeepingReport, TargetingError> {
+        let end_epoch = initial.epoch() + duration;
+        let ew_trigger_deg = self.trigger_fraction * self.longitude_half_width_deg;
+        let ns_trigger_deg = self.trigger_fraction * self.max_inclination_deg;
+
+        let mut state = initial;
+        let mut states = vec![initial];
+        let mut maneuvers = Vec::new();
+        // Longitude offsets since the last East-West maneuver, in seconds past the first sample and degrees
+        let mut history = vec![(0.0, self.longitude_offset_deg(&state, almanac.clone())?)];
+        let mut history_start = state.epoch();
+
+        while state.epoch() < end_epoch {
+            let (next, traj) = self
+                .prop
+                .with(state, almanac.clone())
+                .until_epoch_with_traj((state.epoch() + self.check_step).min(end_epoch))
+                .context(PropSnafu)?;
+            states.extend(traj.states);
+            state = next;
+
+            let offset_deg = self.longitude_offset_deg(&state, almanac.clone())?;
+            history.push(((state.epoch() - history_start).to_seconds(), offset_deg));
+
+            let drift_deg_s = if offset_deg.abs() >= ew_trigger_deg {
+                self.measure_drift_deg_s(state, almanac.clone())?
+            } else {
+                0.0
+            };
+
+            if offset_deg * drift_deg_s > 0.0 {
+                // Enter the box with the drift rate for which the longitude parabola spans the whole box. The longitude
+                // acceleration is only estimated once the history averages out the daily librations. If the acceleration
+                // does not bring the spacecraft back, only reverse the drift.
+                let accel_deg_s2 = if history[history.len() - 1].0 >= 2.0 * DAY_S {
+                    fit_drift(&history).1
+                } else {
+                    0.0
+                };
+                let side = offset_deg.signum();
+                let parabola_rate_deg_s = if side * accel_deg_s2 > 0.0 {
+                    2.0 * (accel_deg_s2.abs() * 2.0 * ew_trigger_deg).sqrt()
+                } else {
+                    0.0
+                };
+                let target_drift_deg_s = -side * parabola_rate_deg_s.max(drift_deg_s.abs());
+
+                let (corrected, mnvr) =
+                    self.east_west(state, target_drift_deg_s - drift_deg_s, almanac.clone())?;
+                info!("{mnvr}");
+                maneuvers.push(mnvr);
+                state = corrected;
+                // Replace the state before the maneuver, which has the same epoch.
+                states.pop();
+                states.push(state);
+
+                history = vec![(0.0, offset_deg)];
+                history_start = state.epoch();
+            }
+
+            let inc_deg = state
+                .orbit
+                .inc_deg()
+                .context(AstroPhysicsSnafu)
+                .context(AstroSnafu)?;
+            if inc_deg >= ns_trigger_deg && state.epoch() < end_epoch {
+                // Maneuver at the next node, where a cross track maneuver only changes the inclination.
+                let period = state
+                    .orbit
+                    .period()
+                    .context(AstroPhysicsSnafu)
+                    .context(AstroSnafu)?;
+                let (node, traj) = self
+                    .prop
+                    .with(state, almanac.clone())
+                    .until_event(period, &Event::new(StateParameter::Z, 0.0))
+                    .context(PropSnafu)?;
+
+                if node.epoch() < end_epoch {
+                    states.extend(traj.states.into_iter().filter(|s| s.epoch() < node.epoch()));
+                    let (corrected, mnvr) = self.north_south(node, almanac.clone())?;
+                    info!("{mnvr}");
+                    maneuvers.push(mnvr);
+                    state = corrected;
+                    states.push(state);
+                }
+            }
+        }
+
+        let mut traj = Traj::new();
+        traj.states = states;
+        traj.finalize();
+
+        Ok(StationKeepingReport { maneuvers, traj })
+    }
+
+    /// Measures the mean longitude drift rate over one orbital period of ballistic propagation, in degrees per second.
+    ///
+    /// The librations due to the eccentricity have the period of the orbit, so they cancel out over one revolution.
+    #[allow(clippy::result_large_err)]
+    fn measure_drift_deg_s(
+        &self,
+        state: Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<f64, TargetingError> {
+        let period = state
+            .orbit
+            .period()
+            .context(AstroPhysicsSnafu)
+            .context(AstroSnafu)?;
+        let next = self
+            .prop
+            .with(state, almanac.clone())
+            .for_duration(period)
+            .context(PropSnafu)?;
+
+        let delta_deg = between_pm_180(
+            self.longitude_offset_deg(&next, almanac.clone())?
+                - self.longitude_offset_deg(&state, almanac)?,
+        );
+
+        Ok(delta_deg / period.to_seconds())
+    }
+
+    /// Changes the semi-major axis to change the longitude drift rate by the provided amount.
+    #[allow(clippy::result_large_err)]
+    fn east_west(
+        &self,
+        state: Spacecraft,
+        delta_drift_deg_s: f64,
+        almanac: Arc<Almanac>,
+    ) -> Result<(Spacecraft, SkManeuver), TargetingError> {
+        // The longitude drift is the difference between the mean motion and the rotation of the Earth: d(lambda)/dt = n - w,
+        // so a change of drift rate is obtained by a change of mean motion of the same amount.
+        let sma_km = state
+            .orbit
+            .sma_km()
+            .context(AstroPhysicsSnafu)
+            .context(AstroSnafu)?;
+        let n_rad_s = EARTH_ROTATION_RAD_S.max(
+            (state
+                .orbit
+                .frame
+                .mu_km3_s2()
+                .context(AstroPhysicsSnafu)
+                .context(AstroSnafu)?
+                / sma_km.powi(3))
+            .sqrt(),
+        );
+        let delta_sma_km = -2.0 / 3.0 * sma_km * delta_drift_deg_s.to_radians() / n_rad_s;
+
+        self.correct(
+            state,
+            Vary::VelocityX,
+            StateParameter::SMA,
+            delta_sma_km,
+            1e-3,
+            SkManeuverKind::EastWest,
+            almanac,
+        )
+    }
+
+    /// Targets the inclination of the station keeping box with a cross track maneuver.
+    #[allow(clippy::result_large_err)]
+    fn north_south(
+        &self,
+        state: Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<(Spacecraft, SkManeuver), TargetingError> {
+        let inc_deg = state
+            .orbit
+            .inc_deg()
+            .context(AstroPhysicsSnafu)
+            .context(AstroSnafu)?;
+
+        self.correct(
+            state,
+            Vary::VelocityY,
+            StateParameter::Inclination,
+            self.target_inclination_deg - inc_deg,
+            1e-4,
+            SkManeuverKind::NorthSouth,
+            almanac,
+        )
+    }
+
+    /// Targets a change of the provided parameter, shortly after the maneuver, with a single VNC component.
+    #[allow(clippy::result_large_err, clippy::too_many_arguments)]
+    fn correct(
+        &self,
+        state: Spacecraft,
+        component: Vary,
+        parameter: StateParameter,
+        delta: f64,
+        tolerance: f64,
+        kind: SkManeuverKind,
+        almanac: Arc<Almanac>,
+    ) -> Result<(Spacecraft, SkManeuver), TargetingError> {
+        // Compare with the ballistic value at the achievement epoch to remove the short periodic variations.
+        let achievement_epoch = state.epoch() + Unit::Minute * 10;
+        let ballistic = self
+            .prop
+            .with(state, almanac.clone())
+            .until_epoch(achievement_epoch)
+            .context(PropSnafu)?;
+        let desired = OrbitDual::from(ballistic.orbit)
+            .partial_for(parameter)
+            .context(AstroSnafu)?
+            .real()
+            + delta;
+
+        let targeter = Targeter::vnc_with_components(
+            &self.prop,
+            [component.into()],
+            [Objective::within_tolerance(parameter, desired, tolerance)],
+        );
+        let sol = targeter.try_achieve_from(state, state.epoch(), achievement_epoch, almanac)?;
+
+        // Apply the VNC correction of the solution to the state at the maneuver epoch.
+        let mut vnc_dv_km_s = Vector3::zeros();
+        vnc_dv_km_s[component.vec_index() - 3] = sol.correction[0];
+        let dv_km_s = vnc_to_inertial(&state, vnc_dv_km_s)?;
+
+        let mut corrected = state;
+        corrected.orbit.apply_dv_km_s(dv_km_s);
+
+        let mnvr = SkManeuver {
+            epoch: state.epoch(),
+            kind,
+            dv_km_s,
+        };
+
+        Ok((corrected, mnvr))
+    }
+}
+
+impl fmt::Display for GeoStationKeeping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "GEO station keeping at {:.3} deg ± {:.3} deg, inclination ≤ {:.3} deg (checked every {})",
+            self.center_longitude_deg,
+            self.longitude_half_width_deg,
+            self.max_inclination_deg,
+            self.check_step
+        )
+    }
+}
+
+/// Greenwich mean sidereal time (IAU 1982), in degrees
+pub(crate) fn gmst_deg(epoch: Epoch) -> f64 {
+    let days = (epoch - Epoch::from_gregorian_utc_hms(2000, 1, 1, 12, 0, 0)).to_unit(Unit::Day);
+    let centuries = days / 36_525.0;
+    280.460_618_37 + 360.985_647_366_29 * days + 0.000_387_933 * centuries.powi(2)
+        - centuries.powi(3) / 38_710_000.0
+}
+
+/// Least squares fit of the longitude history, returning the drift rate at the latest sample and the longitude acceleration.
+fn fit_drift(history: &[(f64, f64)]) -> (f64, f64) {
+    match history.len() {
+        0 | 1 => (0.0, 0.0),
+        2 => {
+            let (t0, l0) = history[0];
+            let (t1, l1) = history[1];
+            ((l1 - l0) / (t1 - t0), 0.0)
+        }
+        _ => {
+            // Fit lambda = c0 + c1 t + c2 t^2, with the time normalized by the last sample for conditioning.
+            let t_max = history[history.len() - 1].0;
+            let mut normal = Matrix3::<f64>::zeros();
+            let mut rhs = Vector3::<f64>::zeros();
+            for &(t, l) in history {
+                let tau = t / t_max;
+                let row = Vector3::new(1.0, tau, tau * tau);
+                normal += row * row.transpose();
+                rhs += row * l;
+            }
+            match normal.try_inverse() {
+                Some(inv) => {
+                    let c = inv * rhs;
+                    ((c[1] + 2.0 * c[2]) / t_max, 2.0 * c[2] / t_max.powi(2))
+                }
+                None => (0.0, 0.0),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod ut_geo_sk {
+    use super::*;
+    use crate::cosmic::Orbit;
+    use crate::fixtures;
+
+    #[test]
+    fn drift_fit() {
+        // Parabola of 1e-12 deg/s^2 starting at 0.01 deg with a drift of -1e-7 deg/s
+        let history: Vec<(f64, f64)> = (0..10)
+            .map(|i| {
+                let t = i as f64 * 21_600.0;
+                (t, 0.01 - 1e-7 * t + 0.5e-12 * t * t)
+            })
+            .collect();
+        let (drift, accel) = fit_drift(&history);
+        let t_last = history[9].0;
+        assert!((drift - (-1e-7 + 1e-12 * t_last)).abs() < 1e-15);
+        assert!((accel - 1e-12).abs() < 1e-18);
+    }
+
+    #[test]
+    fn two_body_box() {
+        let almanac = fixtures::almanac();
+        let eme2k = fixtures::eme2k();
+        let epoch = Epoch::from_gregorian_utc_at_midnight(2023, 3, 1);
+        // Slightly above the geosynchronous altitude, so the spacecraft drifts West, and with an inclination above the box
+        let orbit =
+            Orbit::try_keplerian(42_166.2, 1e-5, 0.06, 0.0, 0.0, 75.0, epoch, eme2k).unwrap();
+        let sc = Spacecraft::from(orbit);
+
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let mut sk = GeoStationKeeping::new(prop, None, 0.0, 0.1, 0.05);
+        // Leave enough margin for the librations due to the eccentricity of the East-West maneuvers.
+        sk.trigger_fraction = 0.7;
+        // Start close to the West edge of the box
+        sk.center_longitude_deg = sk.longitude_offset_deg(&sc, almanac.clone()).unwrap() + 0.07;
+        println!("{sk}");
+
+        let rpt = sk.run(sc, Unit::Day * 10, almanac.clone()).unwrap();
+        println!("{rpt}");
+
+        // The inclination is corrected once, and the drift is reversed at both edges of the box.
+        assert_eq!(
+            rpt.maneuvers
+                .iter()
+                .filter(|m| m.kind == SkManeuverKind::NorthSouth)
+                .count(),
+            1
+        );
+        assert!(
+            rpt.maneuvers
+                .iter()
+                .filter(|m| m.kind == SkManeuverKind::EastWest)
+                .count()
+                >= 2
+        );
+        assert!(rpt.dv_m_s_of(SkManeuverKind::NorthSouth) > 3.0);
+
+        for state in &rpt.traj.states {
+            let offset = sk.longitude_offset_deg(state, almanac.clone()).unwrap();
+            assert!(offset.abs() <= sk.longitude_half_width_deg, "{offset}");
+        }
+        assert!(rpt.traj.last().orbit.inc_deg().unwrap() < 0.05);
+    }
+}
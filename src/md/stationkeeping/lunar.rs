@@ -0,0 +1,479 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{vnc_to_inertial, SkManeuver, SkManeuverKind, StationKeepingReport};
+use crate::cosmic::AstroPhysicsSnafu;
+use crate::linalg::Vector3;
+use crate::md::objective::Objective;
+use crate::md::prelude::*;
+use crate::md::{AstroSnafu, PropSnafu, TargetingError};
+use crate::propagators::PropagationError;
+use crate::utils::between_pm_180;
+
+use snafu::ResultExt;
+use std::fmt;
+
+/// Strategy used to maintain a lunar orbit
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug)]
+pub enum LunarControl {
+    /// Frozen orbit maintenance: when the eccentricity or the argument of periapsis leaves its tolerance, an in-plane maneuver
+    /// at the next apoapsis restores both of them.
+    Frozen {
+        /// Eccentricity of the frozen orbit
+        ecc: f64,
+        /// Argument of periapsis of the frozen orbit, in degrees
+        aop_deg: f64,
+        /// Allowed deviation of the eccentricity before a maneuver is planned
+        ecc_tolerance: f64,
+        /// Allowed deviation of the argument of periapsis before a maneuver is planned, in degrees
+        aop_tolerance_deg: f64,
+    },
+    /// Crossing control, e.g. the x-axis crossing control of a near rectilinear halo orbit (NRHO): at every maneuver event,
+    /// an impulsive maneuver targets the objective at the `horizon`-th target event downstream of the maneuver.
+    Crossing {
+        /// Event at which the maneuvers are executed, e.g. the apoapsis
+        maneuver_event: Event,
+        /// Event at which the objective is evaluated, e.g. the periapsis
+        target_event: Event,
+        /// Number of target events after the maneuver at which the objective is evaluated, at least one
+        horizon: usize,
+        /// Objective to achieve at the target event
+        objective: Objective,
+        /// Frame in which the objective is computed, if different from the propagation frame, e.g. a rotating frame
+        objective_frame: Option<Frame>,
+        /// Maneuvers smaller than this are not executed, in m/s
+        min_dv_m_s: f64,
+    },
+}
+
+/// Orbit maintenance of a lunar orbit, following the provided control strategy.
+///
+/// Each maneuver is computed with the targeter through the full dynamics of the propagator, which should include the
+/// perturbations driving the orbit away from its reference (lunar harmonics, Earth and Sun third body, etc.).
+#[derive(Clone)]
+pub struct LunarStationKeeping {
+    /// Propagator of the station keeping loop, also used by the targeter
+    pub prop: Propagator<SpacecraftDynamics>,
+    /// Control strategy
+    pub control: LunarControl,
+    /// Time between two checks of the frozen orbit tolerances, unused by the crossing control
+    pub check_step: Duration,
+    /// Total delta-v available for station keeping, in m/s: once a maneuver would exceed it, no more maneuvers are executed
+    pub dv_budget_m_s: Option<f64>,
+}
+
+impl LunarStationKeeping {
+    /// Initializes a new station keeping loop without any delta-v budget, checking the frozen orbit tolerances every six hours.
+    pub fn new(prop: Propagator<SpacecraftDynamics>, control: LunarControl) -> Self {
+        Self {
+            prop,
+            control,
+            check_step: Unit::Hour * 6,
+            dv_budget_m_s: None,
+        }
+    }
+
+    /// Propagates the initial state for the provided duration, executing the maneuvers of the control strategy.
+    #[allow(clippy::result_large_err)]
+    pub fn run(
+        &self,
+        initial: Spacecraft,
+        duration: Duration,
+        almanac: Arc<Almanac>,
+    ) -> Result<StationKeepingReport, TargetingError> {
+        let end_epoch = initial.epoch() + duration;
+
+        let mut state = initial;
+        let mut states = vec![initial];
+        let mut maneuvers: Vec<SkManeuver> = Vec::new();
+        let mut budget_exhausted = false;
+        let mut at_maneuver_event = false;
+
+        while state.epoch() < end_epoch {
+            // Find the next maneuver opportunity, and the state at which it is executed.
+            let period = state
+                .orbit
+                .period()
+                .context(AstroPhysicsSnafu)
+                .context(AstroSnafu)?;
+
+            let mnvr_state = match &self.control {
+                LunarControl::Frozen {
+                    ecc,
+                    aop_deg,
+                    ecc_tolerance,
+                    aop_tolerance_deg,
+                } => {
+                    let (next, traj) = self
+                        .prop
+                        .with(state, almanac.clone())
+                        .until_epoch_with_traj((state.epoch() + self.check_step).min(end_epoch))
+                        .context(PropSnafu)?;
+                    states.extend(traj.states);
+                    state = next;
+
+                    let ecc_err = state
+                        .orbit
+                        .ecc()
+                        .context(AstroPhysicsSnafu)
+                        .context(AstroSnafu)?
+                        - ecc;
+                    let aop_err_deg = between_pm_180(
+                        state
+                            .orbit
+                            .aop_deg()
+                            .context(AstroPhysicsSnafu)
+                            .context(AstroSnafu)?
+                            - aop_deg,
+                    );
+
+                    if budget_exhausted
+                        || state.epoch() >= end_epoch
+                        || (ecc_err.abs() <= *ecc_tolerance
+                            && aop_err_deg.abs() <= *aop_tolerance_deg)
+                    {
+                        continue;
+                    }
+
+                    // Maneuver at the next apoapsis, where the in-plane maneuvers are the most efficient.
+                    let (apoapsis, traj) = self
+                        .prop
+                        .with(state, almanac.clone())
+                        .until_event(period, &Event::apoapsis())
+                        .context(PropSnafu)?;
+                    if apoapsis.epoch() >= end_epoch {
+                        continue;
+                    }
+                    states.extend(
+                        traj.states
+                            .into_iter()
+                            .filter(|s| s.epoch() < apoapsis.epoch()),
+                    );
+                    state = apoapsis;
+                    apoapsis
+                }
+                LunarControl::Crossing { maneuver_event, .. } => {
+                    if at_maneuver_event {
+                        // Move past the event of the previous maneuver before searching for the next one.
+                        let (next, traj) = self
+                            .prop
+                            .with(state, almanac.clone())
+                            .until_epoch_with_traj((state.epoch() + period * 0.5).min(end_epoch))
+                            .context(PropSnafu)?;
+                        states.extend(traj.states);
+                        state = next;
+                        if state.epoch() >= end_epoch {
+                            continue;
+                        }
+                    }
+
+                    let (event_state, traj) = self
+                        .prop
+                        .with(state, almanac.clone())
+                        .until_event(period, maneuver_event)
+                        .context(PropSnafu)?;
+                    if event_state.epoch() >= end_epoch {
+                        let (next, traj) = self
+                            .prop
+                            .with(state, almanac.clone())
+                            .until_epoch_with_traj(end_epoch)
+                            .context(PropSnafu)?;
+                        states.extend(traj.states);
+                        state = next;
+                        continue;
+                    }
+                    states.extend(
+                        traj.states
+                            .into_iter()
+                            .filter(|s| s.epoch() < event_state.epoch()),
+                    );
+                    state = event_state;
+                    at_maneuver_event = true;
+                    if budget_exhausted {
+                        states.push(state);
+                        continue;
+                    }
+                    event_state
+                }
+            };
+
+            let mnvr = match self.plan(mnvr_state, almanac.clone())? {
+                Some(mnvr) => mnvr,
+                None => {
+                    states.push(state);
+                    continue;
+                }
+            };
+
+            if let Some(budget_m_s) = self.dv_budget_m_s {
+                let spent_m_s: f64 = maneuvers.iter().map(|mnvr| mnvr.dv_m_s()).sum();
+                if spent_m_s + mnvr.dv_m_s() > budget_m_s {
+                    warn!(
+                        "{mnvr} exceeds the remaining budget of {:.3} m/s: no more maneuvers are executed",
+                        budget_m_s - spent_m_s
+                    );
+                    budget_exhausted = true;
+                    states.push(state);
+                    continue;
+                }
+            }
+
+            info!("{mnvr}");
+            state.orbit.apply_dv_km_s(mnvr.dv_km_s);
+            maneuvers.push(mnvr);
+            states.push(state);
+        }
+
+        let mut traj = Traj::new();
+        traj.states = states;
+        traj.finalize();
+
+        Ok(StationKeepingReport { maneuvers, traj })
+    }
+
+    /// Computes the maneuver of the control strategy at the provided state, if it is large enough to be executed.
+    #[allow(clippy::result_large_err)]
+    fn plan(
+        &self,
+        state: Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<Option<SkManeuver>, TargetingError> {
+        match &self.control {
+            LunarControl::Frozen {
+                ecc,
+                aop_deg,
+                ecc_tolerance,
+                aop_tolerance_deg,
+            } => {
+                // Restore the frozen elements shortly after the maneuver, with the velocity and co-normal components which
+                // respectively mostly change the eccentricity and the argument of periapsis at the apoapsis.
+                let targeter = Targeter::vnc_with_components(
+                    &self.prop,
+                    [Vary::VelocityX.into(), Vary::VelocityZ.into()],
+                    [
+                        Objective::within_tolerance(
+                            StateParameter::Eccentricity,
+                            *ecc,
+                            ecc_tolerance * 0.1,
+                        ),
+                        Objective::within_tolerance(
+                            StateParameter::AoP,
+                            *aop_deg,
+                            aop_tolerance_deg * 0.1,
+                        ),
+                    ],
+                );
+                let sol = targeter.try_achieve_from(
+                    state,
+                    state.epoch(),
+                    state.epoch() + Unit::Minute * 10,
+                    almanac,
+                )?;
+
+                Ok(Some(SkManeuver {
+                    epoch: state.epoch(),
+                    kind: SkManeuverKind::Eccentricity,
+                    dv_km_s: vnc_to_inertial(
+                        &state,
+                        Vector3::new(sol.correction[0], 0.0, sol.correction[1]),
+                    )?,
+                }))
+            }
+            LunarControl::Crossing {
+                target_event,
+                horizon,
+                objective,
+                objective_frame,
+                min_dv_m_s,
+                ..
+            } => {
+                // The objective is evaluated at the epoch of the ballistic crossing. The crossings found at the maneuver itself
+                // are ignored, e.g. when both events are the same.
+                let period = state
+                    .orbit
+                    .period()
+                    .context(AstroPhysicsSnafu)
+                    .context(AstroSnafu)?;
+                let (_, traj) = self
+                    .prop
+                    .with(state, almanac.clone())
+                    .for_duration_with_traj(period * (*horizon as f64 + 0.5))
+                    .context(PropSnafu)?;
+                let crossings: Vec<_> = traj
+                    .find(target_event, almanac.clone())
+                    .map_err(|source| TargetingError::PropError {
+                        source: PropagationError::TrajectoryEventError { source },
+                    })?
+                    .into_iter()
+                    .filter(|crossing| crossing.state.epoch() - state.epoch() > period * 0.01)
+                    .collect();
+                let nth = horizon.saturating_sub(1);
+                let crossing = match crossings.get(nth) {
+                    Some(crossing) => crossing.state,
+                    None => {
+                        return Err(TargetingError::PropError {
+                            source: PropagationError::NthEventError {
+                                nth,
+                                found: crossings.len(),
+                            },
+                        })
+                    }
+                };
+
+                let mut targeter = Targeter::vnc(&self.prop, [*objective]);
+                targeter.objective_frame = *objective_frame;
+                let sol =
+                    targeter.try_achieve_from(state, state.epoch(), crossing.epoch(), almanac)?;
+
+                let mnvr = SkManeuver {
+                    epoch: state.epoch(),
+                    kind: SkManeuverKind::Crossing,
+                    dv_km_s: vnc_to_inertial(&state, sol.correction)?,
+                };
+
+                if mnvr.dv_m_s() < *min_dv_m_s {
+                    debug!("skipping {mnvr}");
+                    Ok(None)
+                } else {
+                    Ok(Some(mnvr))
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for LunarStationKeeping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.control {
+            LunarControl::Frozen {
+                ecc,
+                aop_deg,
+                ecc_tolerance,
+                aop_tolerance_deg,
+            } => write!(
+                f,
+                "Frozen orbit station keeping at ecc = {ecc:.4} ± {ecc_tolerance:.4} and AoP = {aop_deg:.3} deg ± {aop_tolerance_deg:.3} deg (checked every {})",
+                self.check_step
+            )?,
+            LunarControl::Crossing {
+                target_event,
+                horizon,
+                objective,
+                ..
+            } => write!(
+                f,
+                "Crossing control targeting {objective:x} at {target_event} #{horizon}"
+            )?,
+        }
+        if let Some(budget_m_s) = self.dv_budget_m_s {
+            write!(f, " with a budget of {budget_m_s:.3} m/s")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod ut_lunar_sk {
+    use super::*;
+    use crate::cosmic::Orbit;
+    use anise::constants::frames::MOON_J2000;
+
+    #[test]
+    fn frozen_orbit() {
+        let almanac = Arc::new(Almanac::default());
+        let moon_j2k = MOON_J2000.with_mu_km3_s2(4_902.800_066);
+        let epoch = Epoch::from_gregorian_utc_at_midnight(2023, 3, 1);
+        // Low lunar orbit whose eccentricity vector has drifted away from its frozen value
+        let orbit =
+            Orbit::try_keplerian(1_837.4, 0.02, 27.0, 10.0, 80.0, 0.0, epoch, moon_j2k).unwrap();
+        let sc = Spacecraft::from(orbit);
+
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let control = LunarControl::Frozen {
+            ecc: 0.03,
+            aop_deg: 90.0,
+            ecc_tolerance: 2e-3,
+            aop_tolerance_deg: 2.0,
+        };
+        let mut sk = LunarStationKeeping::new(prop, control);
+        println!("{sk}");
+
+        let rpt = sk.run(sc, Unit::Day * 2, almanac.clone()).unwrap();
+        println!("{rpt}");
+
+        assert_eq!(rpt.maneuvers.len(), 1);
+        assert_eq!(rpt.maneuvers[0].kind, SkManeuverKind::Eccentricity);
+        let last = rpt.traj.last().orbit;
+        assert!((last.ecc().unwrap() - 0.03).abs() < 2e-4);
+        assert!((last.aop_deg().unwrap() - 90.0).abs() < 0.2);
+
+        // Without enough budget, the maneuver is not executed.
+        sk.dv_budget_m_s = Some(0.5 * rpt.total_dv_m_s());
+        let rpt = sk.run(sc, Unit::Day * 2, almanac).unwrap();
+        assert!(rpt.maneuvers.is_empty());
+        assert!((rpt.traj.last().orbit.ecc().unwrap() - 0.02).abs() < 1e-6);
+    }
+
+    #[test]
+    fn nrho_crossing() {
+        let almanac = Arc::new(Almanac::default());
+        let moon_j2k = MOON_J2000.with_mu_km3_s2(4_902.800_066);
+        let epoch = Epoch::from_gregorian_utc_at_midnight(2023, 3, 1);
+        // Highly eccentric polar orbit, similar to a southern NRHO, whose perilune is 100 km too high
+        let rp_km = 5_100.0;
+        let ra_km = 70_000.0;
+        let orbit = Orbit::try_keplerian(
+            (rp_km + ra_km) / 2.0,
+            (ra_km - rp_km) / (ra_km + rp_km),
+            90.0,
+            0.0,
+            90.0,
+            90.0,
+            epoch,
+            moon_j2k,
+        )
+        .unwrap();
+        let sc = Spacecraft::from(orbit);
+
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let control = LunarControl::Crossing {
+            maneuver_event: Event::apoapsis(),
+            target_event: Event::periapsis(),
+            horizon: 1,
+            objective: Objective::within_tolerance(StateParameter::Rmag, 5_000.0, 0.1),
+            objective_frame: None,
+            min_dv_m_s: 0.05,
+        };
+        let sk = LunarStationKeeping::new(prop, control);
+        println!("{sk}");
+
+        let rpt = sk.run(sc, Unit::Day * 20, almanac).unwrap();
+        println!("{rpt}");
+
+        assert!(!rpt.maneuvers.is_empty());
+        assert!(rpt
+            .maneuvers
+            .iter()
+            .all(|mnvr| mnvr.kind == SkManeuverKind::Crossing));
+        // Lowering the perilune by 100 km only requires a small maneuver at the apolune.
+        assert!(rpt.total_dv_m_s() < 10.0);
+        let rp_final = rpt.traj.last().orbit.periapsis_km().unwrap();
+        assert!((rp_final - 5_000.0).abs() < 1.0, "{rp_final}");
+    }
+}
@@ -0,0 +1,166 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::cosmic::AstroPhysicsSnafu;
+use crate::dynamics::guidance::LocalFrame;
+use crate::linalg::Vector3;
+use crate::md::trajectory::Traj;
+use crate::md::{AstroSnafu, TargetingError};
+use crate::time::{Duration, Epoch, Unit};
+use crate::{Spacecraft, State};
+
+use snafu::ResultExt;
+use std::fmt;
+
+mod geo;
+mod lunar;
+pub(crate) use geo::gmst_deg;
+pub use geo::GeoStationKeeping;
+pub use lunar::{LunarControl, LunarStationKeeping};
+
+/// The purpose of a station keeping maneuver
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SkManeuverKind {
+    /// Along track maneuver controlling the longitude drift of a geostationary orbit
+    EastWest,
+    /// Cross track maneuver controlling the inclination of a geostationary orbit
+    NorthSouth,
+    /// In-plane maneuver restoring the eccentricity and argument of periapsis of a frozen orbit
+    Eccentricity,
+    /// Maneuver targeting the state at a downstream crossing, e.g. the x-axis crossing of a near rectilinear halo orbit
+    Crossing,
+}
+
+impl fmt::Display for SkManeuverKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EastWest => write!(f, "East-West"),
+            Self::NorthSouth => write!(f, "North-South"),
+            Self::Eccentricity => write!(f, "Eccentricity"),
+            Self::Crossing => write!(f, "Crossing"),
+        }
+    }
+}
+
+/// An impulsive maneuver executed by a station keeping loop
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SkManeuver {
+    /// Epoch of the maneuver
+    pub epoch: Epoch,
+    /// Purpose of the maneuver
+    pub kind: SkManeuverKind,
+    /// Delta-v in the inertial frame of the trajectory, in km/s
+    pub dv_km_s: Vector3<f64>,
+}
+
+impl SkManeuver {
+    /// Magnitude of this maneuver, in m/s
+    pub fn dv_m_s(&self) -> f64 {
+        self.dv_km_s.norm() * 1e3
+    }
+}
+
+impl fmt::Display for SkManeuver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} maneuver @ {}: {:.3} m/s",
+            self.kind,
+            self.epoch,
+            self.dv_m_s()
+        )
+    }
+}
+
+/// Result of a station keeping simulation: the maneuvers and the trajectory including them.
+#[derive(Clone, Debug)]
+pub struct StationKeepingReport {
+    /// All of the maneuvers executed, in chronological order
+    pub maneuvers: Vec<SkManeuver>,
+    /// Trajectory of the spacecraft over the whole simulation
+    pub traj: Traj<Spacecraft>,
+}
+
+impl StationKeepingReport {
+    /// Duration of the simulation
+    pub fn duration(&self) -> Duration {
+        self.traj.last().epoch() - self.traj.first().epoch()
+    }
+
+    /// Total delta-v of all maneuvers, in m/s
+    pub fn total_dv_m_s(&self) -> f64 {
+        self.maneuvers.iter().map(|mnvr| mnvr.dv_m_s()).sum()
+    }
+
+    /// Total delta-v of the maneuvers of the provided kind, in m/s
+    pub fn dv_m_s_of(&self, kind: SkManeuverKind) -> f64 {
+        self.maneuvers
+            .iter()
+            .filter(|mnvr| mnvr.kind == kind)
+            .map(|mnvr| mnvr.dv_m_s())
+            .sum()
+    }
+
+    /// Total delta-v scaled to one Julian year of operations, in m/s per year
+    pub fn annual_dv_m_s(&self) -> f64 {
+        self.total_dv_m_s() * self.years_factor()
+    }
+
+    /// Delta-v of the maneuvers of the provided kind scaled to one Julian year of operations, in m/s per year
+    pub fn annual_dv_m_s_of(&self, kind: SkManeuverKind) -> f64 {
+        self.dv_m_s_of(kind) * self.years_factor()
+    }
+
+    fn years_factor(&self) -> f64 {
+        (365.25 * Unit::Day).to_seconds() / self.duration().to_seconds()
+    }
+}
+
+impl fmt::Display for StationKeepingReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Station keeping over {}: {} maneuvers, {:.3} m/s total ({:.3} m/s per year)",
+            self.duration(),
+            self.maneuvers.len(),
+            self.total_dv_m_s(),
+            self.annual_dv_m_s()
+        )?;
+        for mnvr in &self.maneuvers {
+            writeln!(f, "\t{mnvr}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Rotates a delta-v from the VNC frame of the provided state to its inertial frame.
+///
+/// The targeter solution is applied this way instead of through its corrected state, whose local frame correction is only
+/// valid for inertial corrections.
+#[allow(clippy::result_large_err)]
+fn vnc_to_inertial(
+    state: &Spacecraft,
+    vnc_dv_km_s: Vector3<f64>,
+) -> Result<Vector3<f64>, TargetingError> {
+    Ok(LocalFrame::VNC
+        .dcm_to_inertial(state.orbit)
+        .context(AstroPhysicsSnafu)
+        .context(AstroSnafu)?
+        .rot_mat
+        * vnc_dv_km_s)
+}
@@ -0,0 +1,235 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{Interpolatable, Traj, TrajError};
+use crate::errors::NyxError;
+use crate::linalg::allocator::Allocator;
+use crate::linalg::DefaultAllocator;
+use rayon::prelude::*;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Bounds on the interpolation error of a compressed trajectory with respect to the original one.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TrajTolerance {
+    /// Maximum position error, in km
+    pub position_km: f64,
+    /// Maximum velocity error, in km/s
+    pub velocity_km_s: f64,
+}
+
+impl TrajTolerance {
+    pub fn new(position_km: f64, velocity_km_s: f64) -> Self {
+        Self {
+            position_km,
+            velocity_km_s,
+        }
+    }
+}
+
+impl Default for TrajTolerance {
+    /// One meter and one millimeter per second
+    fn default() -> Self {
+        Self::new(1e-3, 1e-6)
+    }
+}
+
+impl fmt::Display for TrajTolerance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} km and {} km/s", self.position_km, self.velocity_km_s)
+    }
+}
+
+impl<S: Interpolatable> Traj<S>
+where
+    DefaultAllocator: Allocator<S::VecLength> + Allocator<S::Size> + Allocator<S::Size, S::Size>,
+{
+    /// Resamples this trajectory with the fewest of its states such that its interpolation stays within the tolerance of
+    /// this trajectory, in position and velocity.
+    ///
+    /// The error is checked at the epoch of each state of this trajectory and halfway between each of them, against this
+    /// trajectory itself (i.e. its continuous extension if it was built with dense output). Starting from the first and
+    /// last states, the gaps where the tolerance is exceeded are bisected until it is met everywhere.
+    pub fn compress(&self, tolerance: TrajTolerance) -> Result<Self, NyxError> {
+        if self.states.len() < 2 {
+            return Err(NyxError::Trajectory {
+                source: TrajError::CreationError {
+                    msg: "At least two states are needed to compress a trajectory".to_string(),
+                },
+            });
+        }
+
+        let n = self.states.len();
+        // Check epochs, each with the index of the state after which it lies and its reference state
+        let mut checks = Vec::with_capacity(2 * n);
+        for (idx, pair) in self.states.windows(2).enumerate() {
+            let (start, end) = (pair[0].epoch(), pair[1].epoch());
+            checks.push((idx, start, pair[0]));
+            let middle = start + (end - start) * 0.5;
+            checks.push((idx, middle, self.at(middle)?));
+        }
+
+        let mut kept = BTreeSet::from([0, n - 1]);
+        loop {
+            let traj = self.with_states(&kept);
+            let kept_idx = kept.iter().copied().collect::<Vec<usize>>();
+
+            // Check whether the tolerance is exceeded in each gap between consecutive kept states.
+            let failing = kept_idx
+                .par_windows(2)
+                .map(|gap| -> Result<bool, TrajError> {
+                    let first = checks.partition_point(|(idx, _, _)| *idx < gap[0]);
+                    let last = checks.partition_point(|(idx, _, _)| *idx < gap[1]);
+                    for (_, epoch, reference) in &checks[first..last] {
+                        if Self::error(&traj.at(*epoch)?, reference, tolerance) > 1.0 {
+                            return Ok(true);
+                        }
+                    }
+                    Ok(false)
+                })
+                .collect::<Result<Vec<bool>, TrajError>>()?;
+
+            // Bisect the failing gaps. A failing gap without any state left to keep is caused by the interpolation
+            // window reaching into sparser neighbors, so the closest neighbors with states left are bisected instead.
+            let splittable = |gap: usize| kept_idx[gap + 1] > kept_idx[gap] + 1;
+            let mut split = BTreeSet::new();
+            for gap in (0..failing.len()).filter(|gap| failing[*gap]) {
+                if splittable(gap) {
+                    split.insert(gap);
+                } else {
+                    split.extend((0..gap).rev().find(|g| splittable(*g)));
+                    split.extend((gap + 1..failing.len()).find(|g| splittable(*g)));
+                }
+            }
+
+            if split.is_empty() {
+                info!(
+                    "Compressed {} states into {} within {tolerance}",
+                    self.states.len(),
+                    kept.len()
+                );
+                return Ok(traj);
+            }
+
+            kept.extend(
+                split
+                    .into_iter()
+                    .map(|gap| (kept_idx[gap] + kept_idx[gap + 1]) / 2),
+            );
+        }
+    }
+
+    /// Keeps every `step`-th state of this trajectory, as well as its last state, and drops its dense output.
+    pub fn decimate(&self, step: usize) -> Result<Self, NyxError> {
+        if self.states.is_empty() || step == 0 {
+            return Err(NyxError::Trajectory {
+                source: TrajError::CreationError {
+                    msg: "Decimation requires a non empty trajectory and a non zero step"
+                        .to_string(),
+                },
+            });
+        }
+
+        let mut kept = (0..self.states.len())
+            .step_by(step)
+            .collect::<BTreeSet<usize>>();
+        kept.insert(self.states.len() - 1);
+
+        Ok(self.with_states(&kept))
+    }
+
+    /// Returns a copy of this trajectory with only the provided states and no dense output.
+    fn with_states(&self, kept: &BTreeSet<usize>) -> Self {
+        Self {
+            name: self.name.clone(),
+            states: kept.iter().map(|idx| self.states[*idx]).collect(),
+            dense: Vec::new(),
+            interpolation_samples: self.interpolation_samples,
+        }
+    }
+
+    /// Returns the largest of the position and velocity errors, relative to their tolerances.
+    fn error(state: &S, reference: &S, tolerance: TrajTolerance) -> f64 {
+        let (orbit, reference) = (state.orbit(), reference.orbit());
+        let position = (orbit.radius_km - reference.radius_km).norm() / tolerance.position_km;
+        let velocity =
+            (orbit.velocity_km_s - reference.velocity_km_s).norm() / tolerance.velocity_km_s;
+        position.max(velocity)
+    }
+}
+
+#[cfg(test)]
+mod ut_compress {
+    use super::*;
+    use crate::cosmic::Spacecraft;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::propagators::{IntegratorOptions, Propagator};
+    use crate::time::{TimeUnits, Unit};
+    use crate::State;
+
+    #[test]
+    fn compress_and_decimate() {
+        let orbit = fixtures::keplerian(7_500.0, 0.05, 51.6, 30.0, 45.0, 10.0);
+
+        let (_, traj) = Propagator::rk89(
+            SpacecraftDynamics::new(OrbitalDynamics::two_body()),
+            IntegratorOptions::with_fixed_step_s(10.0),
+        )
+        .with(
+            Spacecraft::builder().orbit(orbit).build(),
+            fixtures::almanac(),
+        )
+        .for_duration_with_traj(1.days())
+        .unwrap();
+
+        let tolerance = TrajTolerance::default();
+        let compressed = traj.compress(tolerance).unwrap();
+        println!(
+            "{} -> {} states",
+            traj.states.len(),
+            compressed.states.len()
+        );
+        assert!(compressed.states.len() * 10 < traj.states.len());
+        assert_eq!(compressed.first(), traj.first());
+        assert_eq!(compressed.last(), traj.last());
+
+        // The checks are halfway between states, so allow some slack at arbitrary epochs.
+        for state in traj.every(Unit::Second * 7) {
+            let error = Traj::<Spacecraft>::error(
+                &compressed.at(state.epoch()).unwrap(),
+                &state,
+                tolerance,
+            );
+            assert!(error < 2.0, "{error} at {}", state.epoch());
+        }
+
+        // A looser tolerance needs fewer states.
+        let loose = traj.compress(TrajTolerance::new(1.0, 1e-3)).unwrap();
+        assert!(loose.states.len() < compressed.states.len());
+
+        let decimated = traj.decimate(10).unwrap();
+        assert_eq!(
+            decimated.states.len(),
+            (traj.states.len() - 1).div_ceil(10) + 1
+        );
+        assert_eq!(decimated.last(), traj.last());
+        assert_eq!(decimated.states[1], traj.states[10]);
+        assert!(traj.decimate(0).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::TrajError;
+use crate::linalg::allocator::Allocator;
+use crate::linalg::{DefaultAllocator, DimName, OVector};
+use crate::time::{Duration, Epoch};
+use crate::State;
+
+/// The continuous extension (dense output) of a single integration step.
+///
+/// The state vector (without the STM) at `start + θ step` is `y + \sum_j θ^j c_j`, where the polynomial coefficients
+/// `c_j` are built by the integrator from its stages. This interpolant has the order of the continuous extension of
+/// the integrator (e.g. 7 for `Dop853`), which is more accurate than refitting the states of the trajectory.
+/// Parameters which are not part of the state vector, including the STM, are those of the start of the step.
+#[derive(Clone, PartialEq)]
+pub struct DenseStep<S: State>
+where
+    DefaultAllocator: Allocator<S::VecLength> + Allocator<S::Size> + Allocator<S::Size, S::Size>,
+{
+    /// State at the start of the step
+    pub start: S,
+    /// Signed duration of the step
+    pub step: Duration,
+    /// Coefficients of θ, θ², etc. of each component of the state vector, flattened by power of θ
+    pub(crate) coeffs: Vec<f64>,
+}
+
+impl<S: State> DenseStep<S>
+where
+    DefaultAllocator: Allocator<S::VecLength> + Allocator<S::Size> + Allocator<S::Size, S::Size>,
+{
+    /// Builds the continuous extension of a step from the polynomial coefficients of the full state vector.
+    pub(crate) fn new(start: S, step: Duration, coeffs: Vec<OVector<f64, S::VecLength>>) -> Self {
+        let coeffs = coeffs
+            .iter()
+            .flat_map(|coeff| coeff.iter().copied().take(S::Size::dim()))
+            .collect();
+        Self {
+            start,
+            step,
+            coeffs,
+        }
+    }
+
+    /// Returns the earliest epoch of this step, i.e. its end epoch for a backward step.
+    pub fn earliest(&self) -> Epoch {
+        self.start.epoch().min(self.start.epoch() + self.step)
+    }
+
+    /// Returns the latest epoch of this step, i.e. its start epoch for a backward step.
+    pub fn latest(&self) -> Epoch {
+        self.start.epoch().max(self.start.epoch() + self.step)
+    }
+
+    /// Returns whether the provided epoch is within this step.
+    pub fn contains(&self, epoch: Epoch) -> bool {
+        self.earliest() <= epoch && epoch <= self.latest()
+    }
+
+    /// Evaluates the continuous extension at the provided epoch.
+    pub fn at(&self, epoch: Epoch) -> Result<S, TrajError> {
+        if !self.contains(epoch) {
+            return Err(TrajError::NoInterpolationData { epoch });
+        }
+
+        let theta = (epoch - self.start.epoch()).to_seconds() / self.step.to_seconds();
+
+        let mut vector = self.start.to_vector();
+        for i in 0..S::Size::dim() {
+            vector[i] += self
+                .coeffs
+                .iter()
+                .skip(i)
+                .step_by(S::Size::dim())
+                .rev()
+                .fold(0.0, |acc, coeff| (acc + coeff) * theta);
+        }
+
+        let mut state = self.start;
+        state.set(epoch, &vector);
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod ut_dense {
+    use crate::cosmic::Spacecraft;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::propagators::{ErrorControl, IntegratorMethod, IntegratorOptions, Propagator};
+    use crate::time::{TimeUnits, Unit};
+    use crate::State;
+
+    #[test]
+    fn dense_vs_kepler() {
+        let orbit = fixtures::keplerian(24_000.0, 0.6, 28.5, 30.0, 45.0, 10.0);
+        let sc = Spacecraft::builder().orbit(orbit).build();
+        let almanac = fixtures::almanac();
+
+        for method in [IntegratorMethod::Dop853, IntegratorMethod::DormandPrince45] {
+            let prop = Propagator::new(
+                SpacecraftDynamics::new(OrbitalDynamics::two_body()),
+                method,
+                IntegratorOptions::with_adaptive_step(
+                    1.seconds(),
+                    Unit::Hour * 2,
+                    1e-10,
+                    ErrorControl::RSSCartesianStep,
+                ),
+            );
+
+            let (_, dense_traj) = prop
+                .with(sc, almanac.clone())
+                .for_duration_with_dense_traj(1.days())
+                .unwrap();
+            let (_, traj) = prop
+                .with(sc, almanac.clone())
+                .for_duration_with_traj(1.days())
+                .unwrap();
+
+            assert_eq!(dense_traj.states.len(), traj.states.len());
+            assert_eq!(dense_traj.dense.len(), traj.states.len() - 1);
+
+            let mut max_dense_err = 0.0_f64;
+            let mut max_err = 0.0_f64;
+            for state in dense_traj.every(7.minutes()) {
+                let truth = orbit.at_epoch(state.epoch()).unwrap();
+                max_dense_err = max_dense_err.max(truth.rss_radius_km(&state.orbit).unwrap());
+                let state = traj.at(state.epoch()).unwrap();
+                max_err = max_err.max(truth.rss_radius_km(&state.orbit).unwrap());
+            }
+            // The continuous extension is at least as good as the interpolation of the states.
+            assert!(
+                max_dense_err < 1.1 * max_err,
+                "{method:?}: dense {max_dense_err:.3e} km -- interpolated {max_err:.3e} km"
+            );
+            if method == IntegratorMethod::Dop853 {
+                assert!(max_dense_err < 1e-6, "{max_dense_err:.3e} km");
+            }
+        }
+
+        // The other methods do not have a dense output
+        let prop = Propagator::rk89(
+            SpacecraftDynamics::new(OrbitalDynamics::two_body()),
+            IntegratorOptions::default(),
+        );
+        assert!(prop
+            .with(sc, almanac)
+            .for_duration_with_dense_traj(1.days())
+            .is_err());
+    }
+}
@@ -0,0 +1,352 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{Interpolatable, Traj, TrajError};
+use crate::errors::NyxError;
+use crate::io::watermark::pq_writer;
+use crate::linalg::allocator::Allocator;
+use crate::linalg::{DefaultAllocator, Vector6};
+use crate::time::Epoch;
+use arrow::array::{Array, Float64Builder, StringBuilder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use hifitime::TimeScale;
+use parquet::arrow::ArrowWriter;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Difference between a reference trajectory and another one, in the RIC frame of the reference.
+///
+/// Each difference is the reference minus the other trajectory, as computed by `Orbit::ric_difference`, i.e.
+/// accounting for the transport theorem in the velocity.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrajDiff {
+    pub epochs: Vec<Epoch>,
+    /// Radial, in-track, cross-track position (km) and velocity (km/s) differences
+    pub ric: Vec<Vector6<f64>>,
+}
+
+/// Summary statistics of a trajectory difference.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TrajDiffStats {
+    /// Mean of each RIC component, in km and km/s
+    pub mean: Vector6<f64>,
+    /// Root mean square of each RIC component, in km and km/s
+    pub rms: Vector6<f64>,
+    /// Maximum absolute value of each RIC component, in km and km/s
+    pub max_abs: Vector6<f64>,
+    /// Maximum position error (root sum square), in km
+    pub max_pos_km: f64,
+    /// Epoch of the maximum position error
+    pub max_pos_epoch: Epoch,
+    /// Maximum velocity error (root sum square), in km/s
+    pub max_vel_km_s: f64,
+    /// Root mean square of the position errors, in km
+    pub rms_pos_km: f64,
+    /// Root mean square of the velocity errors, in km/s
+    pub rms_vel_km_s: f64,
+}
+
+impl TrajDiff {
+    /// Returns the position error (root sum square) at each epoch, in km
+    pub fn pos_errors_km(&self) -> Vec<f64> {
+        self.ric
+            .iter()
+            .map(|delta| delta.fixed_rows::<3>(0).norm())
+            .collect()
+    }
+
+    /// Returns the velocity error (root sum square) at each epoch, in km/s
+    pub fn vel_errors_km_s(&self) -> Vec<f64> {
+        self.ric
+            .iter()
+            .map(|delta| delta.fixed_rows::<3>(3).norm())
+            .collect()
+    }
+
+    /// Computes the summary statistics of this difference, or None if there are no common epochs.
+    pub fn stats(&self) -> Option<TrajDiffStats> {
+        if self.ric.is_empty() {
+            return None;
+        }
+
+        let n = self.ric.len() as f64;
+        let mean = self.ric.iter().sum::<Vector6<f64>>() / n;
+        let rms = (self
+            .ric
+            .iter()
+            .map(|delta| delta.component_mul(delta))
+            .sum::<Vector6<f64>>()
+            / n)
+            .map(f64::sqrt);
+        let max_abs = self
+            .ric
+            .iter()
+            .fold(Vector6::zeros(), |max, delta| max.sup(&delta.abs()));
+
+        let pos_errors = self.pos_errors_km();
+        let vel_errors = self.vel_errors_km_s();
+
+        let (max_idx, max_pos_km) = pos_errors
+            .iter()
+            .copied()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap();
+
+        Some(TrajDiffStats {
+            mean,
+            rms,
+            max_abs,
+            max_pos_km,
+            max_pos_epoch: self.epochs[max_idx],
+            max_vel_km_s: vel_errors.iter().copied().fold(0.0, f64::max),
+            rms_pos_km: (pos_errors.iter().map(|err| err.powi(2)).sum::<f64>() / n).sqrt(),
+            rms_vel_km_s: (vel_errors.iter().map(|err| err.powi(2)).sum::<f64>() / n).sqrt(),
+        })
+    }
+
+    /// Exports the per-epoch RIC errors to a parquet file, with the summary statistics in the metadata.
+    pub fn to_parquet<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Box<dyn Error>> {
+        let path_buf = path.as_ref().to_path_buf();
+
+        let mut hdrs = vec![Field::new("Epoch (UTC)", DataType::Utf8, false)];
+        for coord in ["R", "I", "C"] {
+            hdrs.push(Field::new(
+                format!("Delta {coord} (km)"),
+                DataType::Float64,
+                false,
+            ));
+        }
+        for coord in ["R", "I", "C"] {
+            hdrs.push(Field::new(
+                format!("Delta V{coord} (km/s)"),
+                DataType::Float64,
+                false,
+            ));
+        }
+        hdrs.push(Field::new("Position error (km)", DataType::Float64, false));
+        hdrs.push(Field::new(
+            "Velocity error (km/s)",
+            DataType::Float64,
+            false,
+        ));
+
+        let schema = Arc::new(Schema::new(hdrs));
+
+        let mut record: Vec<Arc<dyn Array>> = Vec::new();
+
+        let mut utc_epoch = StringBuilder::new();
+        for epoch in &self.epochs {
+            utc_epoch.append_value(epoch.to_time_scale(TimeScale::UTC).to_isoformat());
+        }
+        record.push(Arc::new(utc_epoch.finish()));
+
+        for coord_no in 0..6 {
+            let mut data = Float64Builder::new();
+            for delta in &self.ric {
+                data.append_value(delta[coord_no]);
+            }
+            record.push(Arc::new(data.finish()));
+        }
+
+        for errors in [self.pos_errors_km(), self.vel_errors_km_s()] {
+            let mut data = Float64Builder::new();
+            data.append_slice(&errors);
+            record.push(Arc::new(data.finish()));
+        }
+
+        let mut metadata = HashMap::new();
+        metadata.insert(
+            "Purpose".to_string(),
+            "Trajectory difference data".to_string(),
+        );
+        if let Some(stats) = self.stats() {
+            metadata.insert(
+                "Max position error (km)".to_string(),
+                stats.max_pos_km.to_string(),
+            );
+            metadata.insert(
+                "Max position error epoch (UTC)".to_string(),
+                stats
+                    .max_pos_epoch
+                    .to_time_scale(TimeScale::UTC)
+                    .to_isoformat(),
+            );
+            metadata.insert(
+                "Max velocity error (km/s)".to_string(),
+                stats.max_vel_km_s.to_string(),
+            );
+            metadata.insert(
+                "RMS position error (km)".to_string(),
+                stats.rms_pos_km.to_string(),
+            );
+            metadata.insert(
+                "RMS velocity error (km/s)".to_string(),
+                stats.rms_vel_km_s.to_string(),
+            );
+        }
+
+        let file = File::create(&path_buf)?;
+        let mut writer = ArrowWriter::try_new(file, schema.clone(), pq_writer(Some(metadata)))?;
+        writer.write(&RecordBatch::try_new(schema, record)?)?;
+        writer.close()?;
+
+        info!(
+            "Trajectory difference of {} epochs written to {}",
+            self.epochs.len(),
+            path_buf.display()
+        );
+
+        Ok(path_buf)
+    }
+}
+
+impl fmt::Display for TrajDiffStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "max position error of {:.6} km on {}, RMS of {:.6} km",
+            self.max_pos_km, self.max_pos_epoch, self.rms_pos_km
+        )?;
+        writeln!(
+            f,
+            "max velocity error of {:.6e} km/s, RMS of {:.6e} km/s",
+            self.max_vel_km_s, self.rms_vel_km_s
+        )?;
+        write!(
+            f,
+            "RMS (R, I, C) = ({:.6}, {:.6}, {:.6}) km",
+            self.rms[0], self.rms[1], self.rms[2]
+        )
+    }
+}
+
+impl<S: Interpolatable> Traj<S>
+where
+    DefaultAllocator: Allocator<S::VecLength> + Allocator<S::Size> + Allocator<S::Size, S::Size>,
+{
+    /// Computes the difference between this reference trajectory and the other one, in the RIC frame of this trajectory.
+    ///
+    /// The epochs of the difference are the epochs of the states of this trajectory within the span of the other trajectory,
+    /// which is interpolated at these epochs. Both trajectories must be in the same frame.
+    pub fn diff(&self, other: &Self) -> Result<TrajDiff, NyxError> {
+        if self.states.is_empty() || other.states.is_empty() {
+            return Err(NyxError::Trajectory {
+                source: TrajError::CreationError {
+                    msg: "No trajectory to difference".to_string(),
+                },
+            });
+        }
+
+        if self.first().frame() != other.first().frame() {
+            return Err(NyxError::Trajectory {
+                source: TrajError::CreationError {
+                    msg: format!(
+                        "cannot difference trajectories in {} and {}, convert them to the same frame first",
+                        self.first().frame(),
+                        other.first().frame()
+                    ),
+                },
+            });
+        }
+
+        let start = other.first().epoch();
+        let end = other.last().epoch();
+
+        let mut diff = TrajDiff::default();
+        for state in self
+            .states
+            .iter()
+            .filter(|state| state.epoch() >= start && state.epoch() <= end)
+        {
+            let other_state = other.at(state.epoch())?;
+            let delta = state
+                .orbit()
+                .ric_difference(&other_state.orbit())
+                .map_err(|e| NyxError::MathDomain { msg: e.to_string() })?;
+
+            diff.epochs.push(state.epoch());
+            diff.ric.push(delta.to_cartesian_pos_vel());
+        }
+
+        Ok(diff)
+    }
+}
+
+#[cfg(test)]
+mod ut_traj_diff {
+    use crate::cosmic::Orbit;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::propagators::Propagator;
+    use crate::time::TimeUnits;
+    use crate::Spacecraft;
+
+    #[test]
+    fn sma_offset() {
+        let almanac = fixtures::almanac();
+        let epoch = fixtures::epoch();
+
+        let reference = fixtures::keplerian(7_000.0, 0.0, 30.0, 0.0, 0.0, 0.0);
+        let other = fixtures::keplerian(7_000.1, 0.0, 30.0, 0.0, 0.0, 0.0);
+
+        let setup = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let prop = |orbit: Orbit| {
+            setup
+                .with(Spacecraft::builder().orbit(orbit).build(), almanac.clone())
+                .for_duration_with_traj(1.days())
+                .unwrap()
+                .1
+        };
+
+        let ref_traj = prop(reference);
+        let other_traj = prop(other);
+
+        let diff = ref_traj.diff(&other_traj).unwrap();
+        assert_eq!(diff.epochs.len(), ref_traj.states.len());
+
+        // Initially, the only difference is radial
+        assert!((diff.ric[0][0] + 0.1).abs() < 1e-9, "{}", diff.ric[0]);
+        assert!(diff.ric[0][2].abs() < 1e-9);
+
+        let stats = diff.stats().unwrap();
+        println!("{stats}");
+
+        // The other orbit is slower, so it lags in-track by 3*pi*da/a per orbit, and no cross-track error appears.
+        assert!(stats.max_abs[2] < 1e-6);
+        assert!(stats.max_abs[1] > 3.0 * std::f64::consts::PI * 0.1 / 7_000.0 * 7_000.0 * 10.0);
+        assert!(
+            (stats.max_pos_km - diff.pos_errors_km().iter().copied().fold(0.0, f64::max)).abs()
+                < 1e-12
+        );
+        assert!(stats.rms_pos_km <= stats.max_pos_km);
+        assert!(stats.max_pos_epoch > epoch + 23.hours());
+
+        // The difference of a trajectory with itself is zero.
+        let zero = ref_traj.diff(&ref_traj).unwrap().stats().unwrap();
+        assert!(zero.max_pos_km < 1e-9 && zero.max_vel_km_s < 1e-12);
+
+        let path = std::env::temp_dir().join("nyx_ut_traj_diff.parquet");
+        assert_eq!(diff.to_parquet(&path).unwrap(), path);
+    }
+}
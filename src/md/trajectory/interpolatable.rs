@@ -101,6 +101,8 @@ impl Interpolatable for Spacecraft {
             / (last.epoch() - first.epoch()).to_seconds();
 
         self.mass.prop_mass_kg += prop_kg_dt * (epoch - first.epoch()).to_seconds();
+        // The tanks were already checked during the propagation
+        self.update_tanks();
 
         Ok(self)
     }
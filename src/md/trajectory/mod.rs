@@ -19,14 +19,28 @@
 use anise::math::interpolation::InterpolationError;
 use snafu::prelude::*;
 
+mod compress;
+mod dense;
+mod diff;
 mod interpolatable;
 mod sc_traj;
+mod spk;
+mod stitch;
+mod stk;
 mod traj;
 mod traj_it;
+mod writer;
 
+pub use compress::TrajTolerance;
+pub use dense::DenseStep;
+pub use diff::{TrajDiff, TrajDiffStats};
 pub use interpolatable::Interpolatable;
 pub(crate) use interpolatable::INTERPOLATION_SAMPLES;
+pub use spk::SpkType;
+pub use stitch::{StitchedTraj, TrajGap};
 pub use traj::Traj;
+pub use writer::TrajWriter;
+use writer::{traj_metadata, TrajBatchBuilder};
 
 pub use crate::io::ExportCfg;
 
@@ -43,6 +57,18 @@ pub enum TrajError {
     },
     #[snafu(display("No interpolation data at {epoch}"))]
     NoInterpolationData { epoch: Epoch },
+    #[snafu(display("{epoch} is outside of the trajectory, which spans from {start} to {end}"))]
+    OutOfBounds {
+        epoch: Epoch,
+        start: Epoch,
+        end: Epoch,
+    },
+    #[snafu(display("{epoch} is in the gap from {start} to {end} between two segments"))]
+    InGap {
+        epoch: Epoch,
+        start: Epoch,
+        end: Epoch,
+    },
     #[snafu(display("Failed to create trajectory: {msg}"))]
     CreationError { msg: String },
     #[snafu(display("Probable bug: Requested epoch {req_epoch}, corresponding to an offset of {req_dur} in a spline of duration {spline_dur}"))]
@@ -24,14 +24,16 @@ use arrow::array::RecordBatchReader;
 use arrow::array::{Float64Array, StringArray};
 use hifitime::TimeSeries;
 use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use rayon::prelude::*;
 use snafu::{ensure, ResultExt};
 
-use super::TrajError;
 use super::{ExportCfg, Traj};
-use crate::cosmic::Spacecraft;
+use super::{TrajError, INTERPOLATION_SAMPLES};
+use crate::cosmic::{FrameTransformCache, Spacecraft};
 use crate::errors::{FromAlmanacSnafu, NyxError};
 use crate::io::watermark::prj_name_ver;
 use crate::io::{InputOutputError, MissingDataSnafu, ParquetSnafu, StdIOSnafu};
+use crate::linalg::Matrix6;
 use crate::md::prelude::{Interpolatable, StateParameter};
 use crate::md::EventEvaluator;
 use crate::time::{Duration, Epoch, Format, Formatter, TimeUnits};
@@ -80,10 +82,15 @@ impl Traj<Spacecraft> {
             states.push(sc_template.with_orbit(orbit));
         }
 
-        Ok(Self { name, states })
+        Ok(Self {
+            name,
+            states,
+            ..Default::default()
+        })
     }
-    /// Allows converting the source trajectory into the (almost) equivalent trajectory in another frame
-    #[allow(clippy::map_clone)]
+    /// Allows converting the source trajectory into the (almost) equivalent trajectory in another frame.
+    ///
+    /// The transformation is computed once per epoch and applied to the states in parallel.
     pub fn to_frame(&self, new_frame: Frame, almanac: Arc<Almanac>) -> Result<Self, NyxError> {
         if self.states.is_empty() {
             return Err(NyxError::Trajectory {
@@ -93,25 +100,49 @@ impl Traj<Spacecraft> {
             });
         }
 
+        // Use the frame data from the almanac, as `Almanac::transform_to` does.
+        let new_frame = almanac.frame_from_uid(new_frame).unwrap_or(new_frame);
+        let cache = FrameTransformCache::new(self.first().orbit.frame, new_frame);
+        self.to_frame_with_cache(&cache, almanac)
+    }
+
+    /// Converts this trajectory into the `to` frame of the cache, reusing (and filling) the transformations it holds, e.g.
+    /// to convert several trajectories sampled at the same epochs.
+    pub fn to_frame_with_cache(
+        &self,
+        cache: &FrameTransformCache,
+        almanac: Arc<Almanac>,
+    ) -> Result<Self, NyxError> {
+        if self.states.is_empty() {
+            return Err(NyxError::Trajectory {
+                source: TrajError::CreationError {
+                    msg: "No trajectory to convert".to_string(),
+                },
+            });
+        }
+
         #[cfg(not(target_arch = "wasm32"))]
         let start_instant = Instant::now();
-        let mut traj = Self::new();
-        for state in &self.states {
-            let new_orbit =
-                almanac
-                    .transform_to(state.orbit, new_frame, None)
-                    .context(FromAlmanacSnafu {
-                        action: "transforming trajectory into new frame",
-                    })?;
-            traj.states.push(state.with_orbit(new_orbit));
-        }
+        let mut traj = Self::new().with_interpolation_samples(self.interpolation_samples);
+        traj.states = self
+            .states
+            .par_iter()
+            .map(|state| {
+                cache
+                    .transform_orbit(state.orbit, &almanac)
+                    .map(|orbit| state.with_orbit(orbit))
+            })
+            .collect::<Result<Vec<Spacecraft>, AlmanacError>>()
+            .context(FromAlmanacSnafu {
+                action: "transforming trajectory into new frame",
+            })?;
         traj.finalize();
 
         #[cfg(not(target_arch = "wasm32"))]
         info!(
             "Converted trajectory from {} to {} in {} ms: {traj}",
             self.first().orbit.frame,
-            new_frame,
+            cache.to,
             (Instant::now() - start_instant).as_millis()
         );
 
@@ -119,7 +150,7 @@ impl Traj<Spacecraft> {
         info!(
             "Converted trajectory from {} to {}: {traj}",
             self.first().orbit.frame,
-            new_frame,
+            cache.to,
         );
 
         Ok(traj)
@@ -156,6 +187,10 @@ impl Traj<Spacecraft> {
     ///
     /// CCSDS OEM only contains the orbit information but Nyx builds spacecraft trajectories.
     /// If not spacecraft template is provided, then a default massless spacecraft will be built.
+    ///
+    /// The number of interpolation samples is set from the `INTERPOLATION` and `INTERPOLATION_DEGREE` metadata of the
+    /// file if any (e.g. a degree 5 Lagrange interpolation uses six states), and can be changed with
+    /// `with_interpolation_samples`. Nyx always uses a Hermite interpolation of the states.
     pub fn from_oem_file<P: AsRef<Path>>(
         path: P,
         tpl_option: Option<Spacecraft>,
@@ -172,6 +207,7 @@ impl Traj<Spacecraft> {
         let mut time_system = String::new();
 
         let ignored_tokens: HashSet<_> = [
+            "CCSDS_OEM_VERS".to_string(),
             "CCSDS_OMM_VERS".to_string(),
             "CREATION_DATE".to_string(),
             "ORIGINATOR".to_string(),
@@ -184,6 +220,8 @@ impl Traj<Spacecraft> {
 
         let mut center_name = None;
         let mut orient_name = None;
+        let mut interpolation = None;
+        let mut interpolation_degree = None;
 
         'lines: for (lno, line) in reader.lines().enumerate() {
             let line = line.map_err(|e| NyxError::CCSDS {
@@ -209,6 +247,12 @@ impl Traj<Spacecraft> {
             } else if line.starts_with("REF_FRAME") {
                 let parts: Vec<&str> = line.split('=').collect();
                 orient_name = Some(parts[1].trim().to_owned());
+            } else if line.starts_with("INTERPOLATION_DEGREE") {
+                let parts: Vec<&str> = line.split('=').collect();
+                interpolation_degree = parts[1].trim().parse::<usize>().ok();
+            } else if line.starts_with("INTERPOLATION") {
+                let parts: Vec<&str> = line.split('=').collect();
+                interpolation = Some(parts[1].trim().to_uppercase());
             } else if line.starts_with("TIME_SYSTEM") {
                 let parts: Vec<&str> = line.split('=').collect();
                 time_system = parts[1].trim().to_string();
@@ -271,13 +315,41 @@ impl Traj<Spacecraft> {
 
         traj.finalize();
 
+        if let Some(degree) = interpolation_degree {
+            // Hermite interpolation uses the velocities, so it needs half as many states for the same degree.
+            let samples = match interpolation.as_deref() {
+                Some("HERMITE") => degree.div_ceil(2),
+                _ => degree + 1,
+            };
+            debug!(
+                "Using {samples} interpolation samples for {interpolation:?} of degree {degree}"
+            );
+            traj = traj.with_interpolation_samples(samples);
+        }
+
         Ok(traj)
     }
 
+    /// Exports this trajectory to the provided filename in the CCSDS OEM v2 format, without covariance.
     pub fn to_oem_file<P: AsRef<Path>>(
         &self,
         path: P,
         cfg: ExportCfg,
+    ) -> Result<PathBuf, NyxError> {
+        self.to_oem(path, cfg, &[])
+    }
+
+    /// Exports this trajectory to the provided filename in the CCSDS OEM v2 format, with the provided covariances
+    /// (e.g. from an orbit determination) in the covariance section.
+    ///
+    /// Each covariance is the 6x6 Cartesian covariance in km and km/s of the orbit at its epoch, expressed in the frame of
+    /// the trajectory. Only the covariances within the exported time span are written. The interpolation metadata
+    /// describes the Hermite interpolation used by Nyx, so that the ephemeris is interpolated identically by other tools.
+    pub fn to_oem<P: AsRef<Path>>(
+        &self,
+        path: P,
+        cfg: ExportCfg,
+        covariances: &[(Epoch, Matrix6<f64>)],
     ) -> Result<PathBuf, NyxError> {
         if self.states.is_empty() {
             return Err(NyxError::CCSDS {
@@ -316,7 +388,7 @@ impl Traj<Spacecraft> {
         let iso8601_no_ts = Format::from_str("%Y-%m-%dT%H:%M:%S.%f").unwrap();
 
         // Write mandatory metadata
-        writeln!(writer, "CCSDS_OMM_VERS = 2.0").map_err(err_hdlr)?;
+        writeln!(writer, "CCSDS_OEM_VERS = 2.0").map_err(err_hdlr)?;
 
         writeln!(
             writer,
@@ -404,6 +476,15 @@ impl Traj<Spacecraft> {
         )
         .map_err(err_hdlr)?;
 
+        writeln!(writer, "\tINTERPOLATION = HERMITE").map_err(err_hdlr)?;
+        // Hermite interpolation on positions and velocities of N samples is of degree 2N - 1.
+        writeln!(
+            writer,
+            "\tINTERPOLATION_DEGREE = {}",
+            2 * self.interpolation_samples.clamp(2, INTERPOLATION_SAMPLES) - 1
+        )
+        .map_err(err_hdlr)?;
+
         writeln!(writer, "META_STOP\n").map_err(err_hdlr)?;
 
         for sc_state in &states {
@@ -425,6 +506,32 @@ impl Traj<Spacecraft> {
         #[allow(clippy::writeln_empty_string)]
         writeln!(writer, "").map_err(err_hdlr)?;
 
+        let (start, end) = (states[0].epoch(), states[states.len() - 1].epoch());
+        let covariances = covariances
+            .iter()
+            .filter(|(epoch, _)| (start..=end).contains(epoch))
+            .collect::<Vec<_>>();
+        if !covariances.is_empty() {
+            writeln!(writer, "COVARIANCE_START").map_err(err_hdlr)?;
+            for (epoch, covar) in covariances {
+                writeln!(
+                    writer,
+                    "\tEPOCH = {}",
+                    Formatter::new(*epoch, iso8601_no_ts)
+                )
+                .map_err(err_hdlr)?;
+                // Lower triangular part, row by row
+                for i in 0..6 {
+                    let row = (0..=i)
+                        .map(|j| format!("{:E}", covar[(i, j)]))
+                        .collect::<Vec<String>>()
+                        .join(" ");
+                    writeln!(writer, "\t{row}").map_err(err_hdlr)?;
+                }
+            }
+            writeln!(writer, "COVARIANCE_STOP\n").map_err(err_hdlr)?;
+        }
+
         // Return the path this was written to
         let tock_time = Epoch::now().unwrap() - tick;
         info!(
@@ -626,10 +733,11 @@ impl Traj<Spacecraft> {
 #[cfg(test)]
 mod ut_ccsds_oem {
 
+    use crate::linalg::Matrix6;
     use crate::md::prelude::{OrbitalDynamics, Propagator, SpacecraftDynamics};
     use crate::time::{Epoch, TimeUnits};
-    use crate::Spacecraft;
     use crate::{io::ExportCfg, md::prelude::Traj, Orbit};
+    use crate::{Spacecraft, State};
     use anise::almanac::Almanac;
     use anise::constants::frames::MOON_J2000;
     use pretty_env_logger;
@@ -658,7 +766,24 @@ mod ut_ccsds_oem {
 
         // This trajectory has two duplicate epochs, which should be removed by the call to finalize()
         assert_eq!(traj.states.len(), 361);
-        assert_eq!(traj.name.unwrap(), "TEST_OBJ".to_string());
+        assert_eq!(traj.name.as_ref().unwrap(), &"TEST_OBJ".to_string());
+        // Lagrange interpolation of degree 7
+        assert_eq!(traj.interpolation_samples, 8);
+
+        // The interpolation of a 10 second ephemeris barely depends on the number of samples (within 10 cm).
+        let epoch = traj.first().epoch() + 30.minutes() + 5.seconds();
+        let default = traj
+            .clone()
+            .with_interpolation_samples(13)
+            .at(epoch)
+            .unwrap();
+        let linear = traj.clone().with_interpolation_samples(0);
+        assert_eq!(linear.interpolation_samples, 2);
+        let from_file = traj.at(epoch).unwrap();
+        assert!((from_file.orbit.radius_km - default.orbit.radius_km).norm() < 1e-4);
+        assert!(
+            (linear.at(epoch).unwrap().orbit.radius_km - default.orbit.radius_km).norm() < 1e-4
+        );
     }
 
     #[test]
@@ -760,6 +885,60 @@ mod ut_ccsds_oem {
         );
     }
 
+    #[test]
+    fn test_oem_covariance() {
+        let path: PathBuf = [
+            env!("CARGO_MANIFEST_DIR"),
+            "data",
+            "tests",
+            "ccsds",
+            "oem",
+            "GEO_20s.oem",
+        ]
+        .iter()
+        .collect();
+
+        let traj: Traj<Spacecraft> = Traj::from_oem_file(path, None).unwrap();
+
+        // One covariance every ten states, and one outside of the exported time span.
+        let mut covariances = traj
+            .states
+            .iter()
+            .step_by(10)
+            .map(|state| {
+                let mut covar = Matrix6::from_diagonal_element(1e-2);
+                covar[(3, 0)] = 1e-5;
+                (state.epoch(), covar)
+            })
+            .collect::<Vec<_>>();
+        let num_covar = covariances.len();
+        covariances.push((traj.last().epoch() + 1.days(), Matrix6::identity()));
+
+        let path: PathBuf = [
+            env!("CARGO_MANIFEST_DIR"),
+            "output_data",
+            "GEO_20s_covar.oem",
+        ]
+        .iter()
+        .collect();
+
+        let out_path = traj
+            .to_oem(path, ExportCfg::default(), &covariances)
+            .unwrap();
+
+        let oem = std::fs::read_to_string(&out_path).unwrap();
+        assert!(oem.starts_with("CCSDS_OEM_VERS = 2.0"));
+        assert!(oem.contains("INTERPOLATION = HERMITE"));
+        // The degree 5 Lagrange interpolation of the source file is exported as a Hermite interpolation on six states.
+        assert!(oem.contains("INTERPOLATION_DEGREE = 11"));
+        assert_eq!(oem.matches("EPOCH =").count(), num_covar);
+        assert!(oem.contains("\t1E-5 0E0 0E0 1E-2\n"));
+
+        // The covariance section is skipped when reloading.
+        let traj_reloaded: Traj<Spacecraft> = Traj::from_oem_file(out_path, None).unwrap();
+        assert_eq!(traj_reloaded, traj);
+    }
+
     #[test]
     fn test_moon_frame_long_prop() {
         use std::path::PathBuf;
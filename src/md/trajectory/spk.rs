@@ -0,0 +1,272 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{Interpolatable, Traj, INTERPOLATION_SAMPLES};
+use crate::io::{InputOutputError, StdIOSnafu};
+use crate::linalg::allocator::Allocator;
+use crate::linalg::DefaultAllocator;
+use anise::naif::daf::{DafDataType, FileRecord};
+use snafu::ResultExt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Number of doubles in a DAF record
+const RCRD_DBL: usize = 128;
+/// Number of bytes in a DAF record
+const RCRD_LEN: usize = 8 * RCRD_DBL;
+/// Number of characters of a segment name in an SPK file, i.e. eight times the size of a summary in doubles
+const SEGMENT_NAME_LEN: usize = 40;
+/// Spacing of the epoch directory of the unequal step SPK types
+const EPOCH_DIR_STEP: usize = 100;
+/// FTP validation string of DAF files, used to detect corruption by ASCII transfers
+const FTP_STR: &[u8; 28] = b"FTPSTR:\r:\n:\r\n:\r\x00:\x81:\x10\xce:ENDFTP";
+
+/// SPK segment types which can be written from a trajectory. Both are interpolated from unequally spaced states.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SpkType {
+    /// Lagrange interpolation of the positions and velocities (SPK type 9)
+    Lagrange9,
+    /// Hermite interpolation of the positions using the velocities (SPK type 13), as used by Nyx to interpolate trajectories
+    #[default]
+    Hermite13,
+}
+
+impl SpkType {
+    /// Returns the window size for the requested number of samples and states: SPICE requires an even window for the
+    /// Hermite type, i.e. a degree equal to 3 mod 4.
+    fn window_size(&self, samples: usize, num_states: usize) -> usize {
+        let window = samples.clamp(2, INTERPOLATION_SAMPLES).min(num_states);
+        match self {
+            Self::Lagrange9 => window,
+            Self::Hermite13 => window - window % 2,
+        }
+    }
+
+    fn data_type(&self) -> DafDataType {
+        match self {
+            Self::Lagrange9 => DafDataType::Type9LagrangeUnequalStep,
+            Self::Hermite13 => DafDataType::Type13HermiteUnequalStep,
+        }
+    }
+}
+
+impl<S: Interpolatable> Traj<S>
+where
+    DefaultAllocator: Allocator<S::VecLength> + Allocator<S::Size> + Allocator<S::Size, S::Size>,
+{
+    /// Writes this trajectory as the single segment of a new SPICE SPK (BSP) kernel, for the provided NAIF ID of the
+    /// spacecraft (typically negative), e.g. to load it in an Almanac or in any SPICE based tool.
+    ///
+    /// The segment is relative to the ephemeris center and in the orientation of the frame of the trajectory, and stores
+    /// every state of the trajectory, interpolated with the number of samples of this trajectory (rounded down to an even
+    /// number for the Hermite type). The segment name is the name of the trajectory, truncated to 40 characters.
+    pub fn to_spk<P: AsRef<Path>>(
+        &self,
+        path: P,
+        naif_id: i32,
+        spk_type: SpkType,
+    ) -> Result<PathBuf, InputOutputError> {
+        if self.states.len() < 2 {
+            return Err(InputOutputError::EmptyDataset {
+                action: "exporting trajectory to SPK, which requires two states",
+            });
+        }
+
+        let frame = self.first().frame();
+        let num_states = self.states.len();
+        let window = spk_type.window_size(self.interpolation_samples, num_states);
+
+        // Data of the segment: states, epochs, epoch directory, window size minus one (i.e. the degree of the Lagrange
+        // type), and the number of states.
+        let mut data = Vec::with_capacity(7 * num_states + num_states / EPOCH_DIR_STEP + 2);
+        for state in &self.states {
+            let orbit = state.orbit();
+            data.extend(orbit.radius_km.iter());
+            data.extend(orbit.velocity_km_s.iter());
+        }
+        let epochs = self
+            .states
+            .iter()
+            .map(|state| state.epoch().to_et_seconds())
+            .collect::<Vec<f64>>();
+        data.extend(epochs.iter());
+        data.extend(
+            epochs
+                .iter()
+                .skip(EPOCH_DIR_STEP - 1)
+                .step_by(EPOCH_DIR_STEP)
+                .take((num_states - 1) / EPOCH_DIR_STEP),
+        );
+        data.push((window - 1) as f64);
+        data.push(num_states as f64);
+
+        // The file record is followed by the summary record, the name record and then the data.
+        let start_addr = 3 * RCRD_DBL + 1;
+        let end_addr = start_addr + data.len() - 1;
+
+        let mut file_record = FileRecord::default();
+        file_record.id_str.copy_from_slice(b"DAF/SPK ");
+        file_record.nd = 2;
+        file_record.ni = 6;
+        file_record.internal_filename = [b' '; 60];
+        file_record.internal_filename[..13].copy_from_slice(b"NYX SPACE SPK");
+        file_record.forward = 2;
+        file_record.backward = 2;
+        file_record.free_addr = (end_addr + 1) as u32;
+        file_record.endian_str.copy_from_slice(b"LTL-IEEE");
+        file_record.ftp_str.copy_from_slice(FTP_STR);
+
+        let mut bytes = Vec::with_capacity(3 * RCRD_LEN + data.len().div_ceil(RCRD_DBL) * RCRD_LEN);
+        bytes.extend(file_record.id_str);
+        bytes.extend(file_record.nd.to_le_bytes());
+        bytes.extend(file_record.ni.to_le_bytes());
+        bytes.extend(file_record.internal_filename);
+        bytes.extend(file_record.forward.to_le_bytes());
+        bytes.extend(file_record.backward.to_le_bytes());
+        bytes.extend(file_record.free_addr.to_le_bytes());
+        bytes.extend(file_record.endian_str);
+        bytes.extend(file_record.pre_null);
+        bytes.extend(file_record.ftp_str);
+        bytes.extend(file_record.pst_null);
+
+        // Summary record: next and previous summary records, number of summaries, and the summary of the segment.
+        for value in [0.0, 0.0, 1.0, epochs[0], epochs[num_states - 1]] {
+            bytes.extend(value.to_le_bytes());
+        }
+        for value in [
+            naif_id,
+            frame.ephemeris_id,
+            frame.orientation_id,
+            spk_type.data_type() as i32,
+            start_addr as i32,
+            end_addr as i32,
+        ] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.resize(2 * RCRD_LEN, 0);
+
+        // Name record
+        let name = self.name.clone().unwrap_or_else(|| "Nyx Space".to_string());
+        bytes.extend(
+            name.bytes()
+                .chain(std::iter::repeat(b' '))
+                .take(SEGMENT_NAME_LEN),
+        );
+        bytes.resize(3 * RCRD_LEN, b' ');
+
+        for value in &data {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.resize(bytes.len().div_ceil(RCRD_LEN) * RCRD_LEN, 0);
+
+        let path_buf = path.as_ref().to_path_buf();
+        let file = File::create(&path_buf).context(StdIOSnafu {
+            action: "creating SPK file",
+        })?;
+        BufWriter::new(file).write_all(&bytes).context(StdIOSnafu {
+            action: "writing SPK file",
+        })?;
+
+        info!(
+            "Trajectory of {naif_id} written to {} as {:?} segment with window size {window}",
+            path_buf.display(),
+            spk_type.data_type()
+        );
+
+        Ok(path_buf)
+    }
+}
+
+#[cfg(test)]
+mod ut_spk {
+    use super::*;
+    use crate::cosmic::Spacecraft;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::propagators::Propagator;
+    use crate::time::Unit;
+    use crate::State;
+    use anise::almanac::Almanac;
+    use anise::constants::frames::EARTH_J2000;
+    use anise::prelude::Frame;
+
+    #[test]
+    fn window_size() {
+        assert_eq!(SpkType::Hermite13.window_size(13, 1000), 12);
+        assert_eq!(SpkType::Lagrange9.window_size(13, 1000), 13);
+        assert_eq!(SpkType::Hermite13.window_size(13, 5), 4);
+        assert_eq!(SpkType::Lagrange9.window_size(0, 5), 2);
+    }
+
+    #[test]
+    fn spk_round_trip() {
+        let _epoch = fixtures::epoch();
+        let orbit = fixtures::keplerian(7_000.0, 0.01, 28.5, 10.0, 20.0, 30.0);
+
+        let (_, mut traj) =
+            Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()))
+                .with(
+                    Spacecraft::builder().orbit(orbit).build(),
+                    fixtures::almanac(),
+                )
+                .for_duration_with_traj(Unit::Day * 1)
+                .unwrap();
+        // Ignore the continuous extension, and use the interpolation of the states as the SPK readers do.
+        traj.dense.clear();
+        traj.name = Some("Nyx round trip".to_string());
+        // Ensure that the epoch directory is used
+        assert!(traj.states.len() > EPOCH_DIR_STEP);
+
+        for (spk_type, naif_id) in [(SpkType::Hermite13, -10_013), (SpkType::Lagrange9, -10_009)] {
+            let path: PathBuf = [
+                env!("CARGO_MANIFEST_DIR"),
+                "output_data",
+                &format!("spk_round_trip_{naif_id}.bsp"),
+            ]
+            .iter()
+            .collect();
+
+            let out_path = traj.to_spk(path, naif_id, spk_type).unwrap();
+            let almanac = Almanac::default().load(out_path.to_str().unwrap()).unwrap();
+
+            let sc_frame = Frame::new(naif_id, EARTH_J2000.orientation_id);
+            let (start, end) = almanac.spk_domain(naif_id).unwrap();
+            // The epochs are stored as ET seconds.
+            assert!((start - traj.first().epoch()).abs() < Unit::Microsecond * 1);
+            assert!((end - traj.last().epoch()).abs() < Unit::Microsecond * 1);
+
+            for state in traj.every(Unit::Minute * 17) {
+                let spk_state = almanac
+                    .translate(sc_frame, EARTH_J2000, state.epoch(), None)
+                    .unwrap();
+                let pos_err = (spk_state.radius_km - state.orbit.radius_km).norm();
+                let vel_err = (spk_state.velocity_km_s - state.orbit.velocity_km_s).norm();
+                assert!(
+                    pos_err < 1e-3 && vel_err < 1e-6,
+                    "{spk_type:?} @ {}: {pos_err} km, {vel_err} km/s",
+                    state.epoch()
+                );
+            }
+        }
+
+        assert!(Traj::<Spacecraft>::new()
+            .to_spk("unused.bsp", -10_000, SpkType::Hermite13)
+            .is_err());
+    }
+}
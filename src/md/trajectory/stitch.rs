@@ -0,0 +1,333 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{Interpolatable, Traj, TrajError};
+use crate::linalg::allocator::Allocator;
+use crate::linalg::DefaultAllocator;
+use crate::time::{Duration, Epoch};
+use std::fmt;
+
+/// A gap between two consecutive segments of a stitched trajectory, where no state is available.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TrajGap {
+    /// Index of the segment which ends at the start of this gap
+    pub after: usize,
+    pub start: Epoch,
+    pub end: Epoch,
+}
+
+impl TrajGap {
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+}
+
+impl fmt::Display for TrajGap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "gap after segment #{} from {} to {} ({})",
+            self.after,
+            self.start,
+            self.end,
+            self.duration()
+        )
+    }
+}
+
+/// A trajectory made of consecutive segments, e.g. the propagation arcs of a multi-phase mission separated by maneuvers.
+///
+/// Each segment is interpolated on its own, so discontinuities between segments (e.g. an impulsive maneuver, where a
+/// segment ends with the pre-maneuver state and the next one starts with the post-maneuver state at the same epoch) are
+/// preserved. Segments may also be separated by gaps, where the trajectory is not defined.
+#[derive(Clone, PartialEq)]
+pub struct StitchedTraj<S: Interpolatable>
+where
+    DefaultAllocator: Allocator<S::VecLength> + Allocator<S::Size> + Allocator<S::Size, S::Size>,
+{
+    /// Segments in chronological order, which may share their boundary epochs but never overlap otherwise
+    pub segments: Vec<Traj<S>>,
+}
+
+impl<S: Interpolatable> StitchedTraj<S>
+where
+    DefaultAllocator: Allocator<S::VecLength> + Allocator<S::Size> + Allocator<S::Size, S::Size>,
+{
+    pub fn new() -> Self {
+        Self {
+            segments: Vec::new(),
+        }
+    }
+
+    /// Stitches the provided segments, in chronological order.
+    pub fn from_segments(segments: Vec<Traj<S>>) -> Result<Self, TrajError> {
+        let mut me = Self::new();
+        for segment in segments {
+            me.push(segment)?;
+        }
+        Ok(me)
+    }
+
+    /// Appends a segment after the last one.
+    ///
+    /// The segment must be in the same frame as the previous segments and must not start before the end of the last
+    /// segment. If it starts after the end of the last segment, the trajectory has a gap in between.
+    pub fn push(&mut self, segment: Traj<S>) -> Result<(), TrajError> {
+        if segment.states.is_empty() {
+            return Err(TrajError::CreationError {
+                msg: "cannot stitch an empty segment".to_string(),
+            });
+        }
+
+        if let Some(last) = self.segments.last() {
+            if last.first().frame() != segment.first().frame() {
+                return Err(TrajError::CreationError {
+                    msg: format!(
+                        "frame mismatch in segment #{}: {} != {}",
+                        self.segments.len(),
+                        segment.first().frame(),
+                        last.first().frame()
+                    ),
+                });
+            }
+            if segment.first().epoch() < last.last().epoch() {
+                return Err(TrajError::CreationError {
+                    msg: format!(
+                        "segment #{} starts at {} before the end of the previous segment at {}",
+                        self.segments.len(),
+                        segment.first().epoch(),
+                        last.last().epoch()
+                    ),
+                });
+            }
+            if segment.first().epoch() > last.last().epoch() {
+                info!(
+                    "Stitched trajectory has a gap of {} before segment #{}",
+                    segment.first().epoch() - last.last().epoch(),
+                    self.segments.len()
+                );
+            }
+        }
+
+        self.segments.push(segment);
+        Ok(())
+    }
+
+    /// Returns the index of the segment which defines the trajectory at this epoch, if any.
+    ///
+    /// At the boundary between two segments, the later segment is used, e.g. the post-maneuver state.
+    pub fn segment_of(&self, epoch: Epoch) -> Option<usize> {
+        let idx = self
+            .segments
+            .partition_point(|segment| segment.first().epoch() <= epoch);
+        (idx > 0 && epoch <= self.segments[idx - 1].last().epoch()).then(|| idx - 1)
+    }
+
+    /// Evaluates the trajectory at this epoch, and returns the index of the segment which served the request along with
+    /// the state. At the boundary between two segments, the later segment is used.
+    pub fn at(&self, epoch: Epoch) -> Result<(usize, S), TrajError> {
+        match self.segment_of(epoch) {
+            Some(idx) => Ok((idx, self.segments[idx].at(epoch)?)),
+            None => match self
+                .gaps()
+                .into_iter()
+                .find(|gap| gap.start < epoch && epoch < gap.end)
+            {
+                Some(gap) => Err(TrajError::InGap {
+                    epoch,
+                    start: gap.start,
+                    end: gap.end,
+                }),
+                None => match (self.first(), self.last()) {
+                    (Some(first), Some(last)) => Err(TrajError::OutOfBounds {
+                        epoch,
+                        start: first.epoch(),
+                        end: last.epoch(),
+                    }),
+                    _ => Err(TrajError::NoInterpolationData { epoch }),
+                },
+            },
+        }
+    }
+
+    /// Returns the gaps between consecutive segments.
+    pub fn gaps(&self) -> Vec<TrajGap> {
+        self.segments
+            .windows(2)
+            .enumerate()
+            .filter(|(_, pair)| pair[1].first().epoch() > pair[0].last().epoch())
+            .map(|(after, pair)| TrajGap {
+                after,
+                start: pair[0].last().epoch(),
+                end: pair[1].first().epoch(),
+            })
+            .collect()
+    }
+
+    /// Returns the first state of the first segment
+    pub fn first(&self) -> Option<&S> {
+        self.segments.first().map(|segment| segment.first())
+    }
+
+    /// Returns the last state of the last segment
+    pub fn last(&self) -> Option<&S> {
+        self.segments.last().map(|segment| segment.last())
+    }
+
+    /// Concatenates all of the segments into a single trajectory, e.g. to export it.
+    ///
+    /// The resulting trajectory interpolates across the gaps and keeps only the later state at the boundary between two
+    /// segments, so it should not be evaluated near discontinuities.
+    pub fn to_traj(&self) -> Traj<S> {
+        let mut traj = Traj::new();
+        // Keep the later state on boundaries, since finalizing keeps the first of the duplicate epochs.
+        for segment in self.segments.iter().rev() {
+            traj.states.extend(segment.states.iter().rev().copied());
+        }
+        traj.finalize();
+        traj
+    }
+}
+
+impl<S: Interpolatable> Default for StitchedTraj<S>
+where
+    DefaultAllocator: Allocator<S::VecLength> + Allocator<S::Size> + Allocator<S::Size, S::Size>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Interpolatable> fmt::Display for StitchedTraj<S>
+where
+    DefaultAllocator: Allocator<S::VecLength> + Allocator<S::Size> + Allocator<S::Size, S::Size>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let gaps = self.gaps();
+        write!(
+            f,
+            "Stitched trajectory of {} segments with {} gaps",
+            self.segments.len(),
+            gaps.len()
+        )?;
+        for (idx, segment) in self.segments.iter().enumerate() {
+            write!(
+                f,
+                "\n\t#{idx}{} from {} to {}",
+                segment
+                    .name
+                    .as_ref()
+                    .map(|name| format!(" ({name})"))
+                    .unwrap_or_default(),
+                segment.first().epoch(),
+                segment.last().epoch()
+            )?;
+        }
+        for gap in gaps {
+            write!(f, "\n\t{gap}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod ut_stitch {
+    use super::*;
+    use crate::cosmic::{Orbit, Spacecraft};
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::linalg::Vector3;
+    use crate::propagators::Propagator;
+    use crate::time::Unit;
+    use crate::State;
+    use anise::constants::frames::MOON_J2000;
+
+    #[test]
+    fn maneuver_and_gap() {
+        let almanac = fixtures::almanac();
+        let eme2k = fixtures::eme2k();
+        let epoch = fixtures::epoch();
+        let orbit = Orbit::keplerian(7_000.0, 0.01, 28.5, 0.0, 0.0, 0.0, epoch, eme2k);
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+
+        let (pre_burn, arc0) = prop
+            .with(Spacecraft::builder().orbit(orbit).build(), almanac.clone())
+            .for_duration_with_traj(Unit::Hour * 1)
+            .unwrap();
+
+        // Impulsive maneuver, then a coast with a gap in the trajectory
+        let mut post_burn = pre_burn;
+        post_burn.orbit.velocity_km_s += Vector3::new(0.0, 0.1, 0.0);
+        let (end_arc1, arc1) = prop
+            .with(post_burn, almanac.clone())
+            .for_duration_with_traj(Unit::Hour * 1)
+            .unwrap();
+        let after_gap = prop
+            .with(end_arc1, almanac.clone())
+            .for_duration(Unit::Minute * 30)
+            .unwrap();
+        let (_, arc2) = prop
+            .with(after_gap, almanac.clone())
+            .for_duration_with_traj(Unit::Hour * 1)
+            .unwrap();
+
+        let stitched = StitchedTraj::from_segments(vec![arc0, arc1.clone(), arc2]).unwrap();
+        println!("{stitched}");
+
+        let burn_epoch = pre_burn.epoch();
+        let (idx, state) = stitched.at(burn_epoch - Unit::Second * 1).unwrap();
+        assert_eq!(idx, 0);
+        assert!((state.orbit.velocity_km_s - pre_burn.orbit.velocity_km_s).norm() < 1e-2);
+        // The boundary is served by the post-maneuver segment.
+        let (idx, state) = stitched.at(burn_epoch).unwrap();
+        assert_eq!(idx, 1);
+        assert_eq!(state, post_burn);
+        let (idx, _) = stitched.at(burn_epoch + Unit::Minute * 30).unwrap();
+        assert_eq!(idx, 1);
+
+        let gaps = stitched.gaps();
+        assert_eq!(gaps.len(), 1);
+        assert_eq!(gaps[0].after, 1);
+        assert_eq!(gaps[0].duration(), Unit::Minute * 30);
+        assert!(matches!(
+            stitched.at(end_arc1.epoch() + Unit::Minute * 10),
+            Err(TrajError::InGap { .. })
+        ));
+        match stitched.at(epoch - Unit::Minute * 10) {
+            Err(TrajError::OutOfBounds { start, .. }) => assert_eq!(start, epoch),
+            other => panic!("expected an out of bounds error, got {other:?}"),
+        }
+        let (idx, _) = stitched.at(after_gap.epoch() + Unit::Minute * 10).unwrap();
+        assert_eq!(idx, 2);
+
+        // The flattened trajectory keeps the post-maneuver state.
+        let traj = stitched.to_traj();
+        assert_eq!(traj.at(burn_epoch).unwrap(), post_burn);
+
+        // Overlapping segments and frame mismatches are rejected.
+        let mut bad = stitched.clone();
+        assert!(bad.push(arc1.clone()).is_err());
+        let mut moon_arc = arc1;
+        for state in moon_arc.states.iter_mut() {
+            state.orbit.frame = MOON_J2000;
+            state.orbit.epoch += Unit::Day * 1;
+        }
+        assert!(bad.push(moon_arc).is_err());
+        assert!(bad.push(Traj::new()).is_err());
+    }
+}
@@ -0,0 +1,242 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{ExportCfg, Traj, INTERPOLATION_SAMPLES};
+use crate::cosmic::{Frame, Spacecraft};
+use crate::io::watermark::prj_name_ver;
+use crate::io::{InputOutputError, StdIOSnafu};
+use crate::time::{Epoch, Format, Formatter, TimeScale, TimeUnits};
+use crate::State;
+use anise::constants::celestial_objects::*;
+use anise::constants::orientations::{ITRF93, J2000, MOON_ME};
+use snafu::ResultExt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Returns the STK central body of this frame, if supported.
+fn stk_central_body(frame: Frame) -> Option<&'static str> {
+    match frame.ephemeris_id {
+        SUN => Some("Sun"),
+        MERCURY | 199 => Some("Mercury"),
+        VENUS | 299 => Some("Venus"),
+        EARTH => Some("Earth"),
+        MOON => Some("Moon"),
+        MARS => Some("Mars"),
+        JUPITER => Some("Jupiter"),
+        SATURN => Some("Saturn"),
+        URANUS => Some("Uranus"),
+        NEPTUNE => Some("Neptune"),
+        PLUTO => Some("Pluto"),
+        _ => None,
+    }
+}
+
+/// Returns the STK coordinate system of this frame, if supported: the inertial J2000 orientation is the ICRF, and the
+/// IAU body fixed frames, the ITRF93 and the Moon mean Earth frame are the body fixed frames of their central body.
+fn stk_coordinate_system(frame: Frame) -> Option<&'static str> {
+    match frame.orientation_id {
+        J2000 => Some("ICRF"),
+        ITRF93 | MOON_ME => Some("Fixed"),
+        id if id == frame.ephemeris_id => Some("Fixed"),
+        _ => None,
+    }
+}
+
+impl Traj<Spacecraft> {
+    /// Exports this trajectory to the provided filename in the STK ephemeris format (`.e`), with the position and
+    /// velocity in kilometers and kilometers per second.
+    ///
+    /// The scenario epoch is the first exported state, in UTC, and the time of each state is the elapsed time in seconds
+    /// since that epoch. The trajectory must be in the J2000 orientation or in the body fixed frame of one of the planets,
+    /// the Sun or the Moon. Only the `start_epoch`, `end_epoch`, `step` and `timestamp` fields of the configuration are used.
+    pub fn to_stk_e_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        cfg: ExportCfg,
+    ) -> Result<PathBuf, InputOutputError> {
+        if self.states.is_empty() {
+            return Err(InputOutputError::EmptyDataset {
+                action: "exporting trajectory to STK ephemeris",
+            });
+        }
+
+        let frame = self.first().orbit.frame;
+        let central_body =
+            stk_central_body(frame).ok_or_else(|| InputOutputError::UnsupportedData {
+                which: format!("STK central body of {frame}"),
+            })?;
+        let coordinate_system =
+            stk_coordinate_system(frame).ok_or_else(|| InputOutputError::UnsupportedData {
+                which: format!("STK coordinate system of {frame}"),
+            })?;
+
+        let tick = Epoch::now().unwrap();
+        info!("Exporting trajectory to STK ephemeris file...");
+
+        let path_buf = cfg.actual_path(path);
+
+        let states = if cfg.start_epoch.is_some() || cfg.end_epoch.is_some() || cfg.step.is_some() {
+            // Must interpolate the data!
+            let start = cfg.start_epoch.unwrap_or_else(|| self.first().epoch());
+            let end = cfg.end_epoch.unwrap_or_else(|| self.last().epoch());
+            let step = cfg.step.unwrap_or_else(|| 1.minutes());
+            self.every_between(step, start, end).collect()
+        } else {
+            self.states.to_vec()
+        };
+
+        let file = File::create(&path_buf).context(StdIOSnafu {
+            action: "creating STK ephemeris file",
+        })?;
+        let mut writer = BufWriter::new(file);
+
+        let scenario_epoch = states[0].epoch();
+        let stk_epoch_fmt = Format::from_str("%d %b %Y %H:%M:%S.%f").unwrap();
+
+        let mut lines = vec![
+            "stk.v.11.0".to_string(),
+            String::new(),
+            format!("# Built by {} -- https://nyxspace.com/", prj_name_ver()),
+            String::new(),
+            "BEGIN Ephemeris".to_string(),
+            String::new(),
+            format!("NumberOfEphemerisPoints {}", states.len()),
+            format!(
+                "ScenarioEpoch {}",
+                Formatter::new(scenario_epoch.to_time_scale(TimeScale::UTC), stk_epoch_fmt)
+            ),
+            "InterpolationMethod Hermite".to_string(),
+            format!(
+                "InterpolationSamplesM1 {}",
+                self.interpolation_samples.clamp(2, INTERPOLATION_SAMPLES) - 1
+            ),
+            format!("CentralBody {central_body}"),
+            format!("CoordinateSystem {coordinate_system}"),
+            "DistanceUnit Kilometers".to_string(),
+            String::new(),
+            "EphemerisTimePosVel".to_string(),
+            String::new(),
+        ];
+
+        for state in &states {
+            let orbit = state.orbit;
+            lines.push(format!(
+                "{:.9E} {:.16E} {:.16E} {:.16E} {:.16E} {:.16E} {:.16E}",
+                (orbit.epoch - scenario_epoch).to_seconds(),
+                orbit.radius_km.x,
+                orbit.radius_km.y,
+                orbit.radius_km.z,
+                orbit.velocity_km_s.x,
+                orbit.velocity_km_s.y,
+                orbit.velocity_km_s.z
+            ));
+        }
+
+        lines.push(String::new());
+        lines.push("END Ephemeris".to_string());
+
+        for line in lines {
+            writeln!(writer, "{line}").context(StdIOSnafu {
+                action: "writing STK ephemeris file",
+            })?;
+        }
+
+        let tock_time = Epoch::now().unwrap() - tick;
+        info!(
+            "Trajectory written to {} in {tock_time}",
+            path_buf.display()
+        );
+        Ok(path_buf)
+    }
+}
+
+#[cfg(test)]
+mod ut_stk {
+    use super::*;
+    use crate::cosmic::Orbit;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::propagators::Propagator;
+    use crate::time::Unit;
+    use anise::constants::frames::{EARTH_J2000, EARTH_MOON_BARYCENTER_J2000, IAU_MOON_FRAME};
+
+    #[test]
+    fn stk_frames() {
+        assert_eq!(stk_central_body(EARTH_J2000), Some("Earth"));
+        assert_eq!(stk_coordinate_system(EARTH_J2000), Some("ICRF"));
+        assert_eq!(stk_central_body(IAU_MOON_FRAME), Some("Moon"));
+        assert_eq!(stk_coordinate_system(IAU_MOON_FRAME), Some("Fixed"));
+        assert_eq!(stk_central_body(EARTH_MOON_BARYCENTER_J2000), None);
+    }
+
+    #[test]
+    fn stk_ephemeris() {
+        let eme2k = fixtures::eme2k();
+        let epoch = Epoch::from_gregorian_utc(2024, 6, 1, 12, 30, 0, 0);
+        let orbit = Orbit::keplerian(7_000.0, 0.01, 28.5, 0.0, 0.0, 0.0, epoch, eme2k);
+
+        let (_, traj) = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()))
+            .with(
+                Spacecraft::builder().orbit(orbit).build(),
+                fixtures::almanac(),
+            )
+            .for_duration_with_traj(Unit::Hour * 2)
+            .unwrap();
+
+        let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "output_data", "stk_leo.e"]
+            .iter()
+            .collect();
+
+        let cfg = ExportCfg::builder().step(Unit::Minute * 1).build();
+        let out_path = traj.to_stk_e_file(path, cfg).unwrap();
+
+        let ephem = std::fs::read_to_string(out_path).unwrap();
+        println!("{}", &ephem[..800]);
+        assert!(ephem.starts_with("stk.v.11.0"));
+        assert!(ephem.contains("ScenarioEpoch 01 Jun 2024 12:30:00.000000000\n"));
+        assert!(ephem.contains("NumberOfEphemerisPoints 121\n"));
+        assert!(ephem.contains("InterpolationSamplesM1 12\n"));
+        assert!(ephem.contains("CentralBody Earth\nCoordinateSystem ICRF\n"));
+        assert!(ephem.trim_end().ends_with("END Ephemeris"));
+
+        // Check the last state against the trajectory.
+        let last = ephem
+            .lines()
+            .rev()
+            .find(|line| line.starts_with(char::is_numeric))
+            .unwrap()
+            .split_whitespace()
+            .map(|value| value.parse::<f64>().unwrap())
+            .collect::<Vec<f64>>();
+        assert_eq!(last[0], 7200.0);
+        let state = traj.at(epoch + Unit::Hour * 2).unwrap();
+        assert!((last[1] - state.orbit.radius_km.x).abs() < 1e-9);
+        assert!((last[6] - state.orbit.velocity_km_s.z).abs() < 1e-12);
+
+        // Barycenters are not supported.
+        let mut bary = traj.clone();
+        for state in bary.states.iter_mut() {
+            state.orbit.frame = EARTH_MOON_BARYCENTER_J2000;
+        }
+        assert!(bary
+            .to_stk_e_file("unused.e", ExportCfg::default())
+            .is_err());
+    }
+}
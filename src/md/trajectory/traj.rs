@@ -17,14 +17,13 @@
 */
 
 use super::traj_it::TrajIterator;
+use super::{traj_metadata, DenseStep, Interpolatable, TrajBatchBuilder, TrajError, TrajWriter};
 use super::{ExportCfg, InterpolationSnafu, INTERPOLATION_SAMPLES};
-use super::{Interpolatable, TrajError};
 use crate::errors::NyxError;
-use crate::io::watermark::pq_writer;
-use crate::io::InputOutputError;
+use crate::io::watermark::pq_writer_with;
 use crate::linalg::allocator::Allocator;
 use crate::linalg::DefaultAllocator;
-use crate::md::prelude::{GuidanceMode, StateParameter};
+use crate::md::prelude::StateParameter;
 use crate::md::EventEvaluator;
 use crate::time::{Duration, Epoch, TimeSeries, TimeUnits};
 use anise::almanac::Almanac;
@@ -53,6 +52,11 @@ where
     pub name: Option<String>,
     /// We use a vector because we know that the states are produced in a chronological manner (the direction does not matter).
     pub states: Vec<S>,
+    /// Continuous extension of the integration steps, if the trajectory was built with dense output.
+    /// When available, it is used instead of the interpolation of the states.
+    pub dense: Vec<DenseStep<S>>,
+    /// Number of states used in the Hermite interpolation of the states, between 2 and 13 (the default).
+    pub interpolation_samples: usize,
 }
 
 impl<S: Interpolatable> Traj<S>
@@ -63,21 +67,48 @@ where
         Self {
             name: None,
             states: Vec::new(),
+            dense: Vec::new(),
+            interpolation_samples: INTERPOLATION_SAMPLES,
         }
     }
+
+    /// Sets the number of states used in the interpolation of this trajectory, clamped between 2 and 13.
+    ///
+    /// Fewer samples match the lower degree interpolation of some external ephemerides (e.g. a degree 7 Hermite
+    /// interpolation uses four states), while more samples smooth out sparse trajectories.
+    pub fn with_interpolation_samples(mut self, samples: usize) -> Self {
+        self.interpolation_samples = samples.clamp(2, INTERPOLATION_SAMPLES);
+        self
+    }
+
     /// Orders the states, can be used to store the states out of order
     pub fn finalize(&mut self) {
         // Remove duplicate epochs
         self.states.dedup_by(|a, b| a.epoch().eq(&b.epoch()));
         // And sort
         self.states.sort_by_key(|a| a.epoch());
+        self.dense.sort_by_key(|step| step.earliest());
     }
 
     /// Evaluate the trajectory at this specific epoch.
     pub fn at(&self, epoch: Epoch) -> Result<S, TrajError> {
-        if self.states.is_empty() || self.first().epoch() > epoch || self.last().epoch() < epoch {
+        if self.states.is_empty() {
             return Err(TrajError::NoInterpolationData { epoch });
         }
+        if self.first().epoch() > epoch || self.last().epoch() < epoch {
+            return Err(TrajError::OutOfBounds {
+                epoch,
+                start: self.first().epoch(),
+                end: self.last().epoch(),
+            });
+        }
+        // Use the continuous extension of the integrator if available
+        let step_idx = self.dense.partition_point(|step| step.latest() < epoch);
+        if let Some(step) = self.dense.get(step_idx) {
+            if step.contains(epoch) {
+                return step.at(epoch);
+            }
+        }
         match self
             .states
             .binary_search_by(|state| state.epoch().cmp(&epoch))
@@ -96,11 +127,12 @@ where
                 // NOTE: This is essentially the same code as in ANISE for the Hermite SPK type 13
 
                 // We didn't find it, so let's build an interpolation here.
-                let num_left = INTERPOLATION_SAMPLES / 2;
+                let samples = self.interpolation_samples.clamp(2, INTERPOLATION_SAMPLES);
+                let num_left = samples / 2;
 
                 // Ensure that we aren't fetching out of the window
                 let mut first_idx = idx.saturating_sub(num_left);
-                let last_idx = self.states.len().min(first_idx + INTERPOLATION_SAMPLES);
+                let last_idx = self.states.len().min(first_idx + samples);
 
                 // Check that we have enough samples
                 if last_idx == self.states.len() {
@@ -186,6 +218,9 @@ where
     }
 
     /// Store this trajectory arc to a parquet file with the provided configuration and event evaluators
+    ///
+    /// The states are written in batches by a [TrajWriter], so interpolating a long trajectory at a fine step does not require
+    /// storing all of the interpolated states in memory.
     pub fn to_parquet<P: AsRef<Path>>(
         &self,
         path: P,
@@ -193,131 +228,56 @@ where
         cfg: ExportCfg,
         almanac: Arc<Almanac>,
     ) -> Result<PathBuf, Box<dyn Error>> {
-        let tick = Epoch::now().unwrap();
-        info!("Exporting trajectory to parquet file...");
-
-        // Grab the path here before we move stuff.
-        let path_buf = cfg.actual_path(path);
-
-        // Build the schema
-        let mut hdrs = vec![Field::new("Epoch (UTC)", DataType::Utf8, false)];
-
-        let frame = self.states[0].frame();
-        let more_meta = Some(vec![(
-            "Frame".to_string(),
-            serde_dhall::serialize(&frame).to_string().map_err(|e| {
-                Box::new(InputOutputError::SerializeDhall {
-                    what: format!("frame `{frame}`"),
-                    err: e.to_string(),
-                })
-            })?,
-        )]);
-
-        let mut fields = match cfg.fields {
-            Some(fields) => fields,
-            None => S::export_params(),
-        };
-
-        // Check that we can retrieve this information
-        fields.retain(|param| self.first().value(*param).is_ok());
+        let interpolate =
+            cfg.start_epoch.is_some() || cfg.end_epoch.is_some() || cfg.step.is_some();
+        let start = cfg.start_epoch.unwrap_or_else(|| self.first().epoch());
+        let end = cfg.end_epoch.unwrap_or_else(|| self.last().epoch());
+        let step = cfg.step.unwrap_or_else(|| 1.minutes());
 
-        for field in &fields {
-            hdrs.push(field.to_field(more_meta.clone()));
-        }
+        let mut writer = TrajWriter::new(path, self.first(), events, cfg, almanac)?;
 
-        if let Some(events) = events.as_ref() {
-            for event in events {
-                let field = Field::new(format!("{event}"), DataType::Float64, false);
-                hdrs.push(field);
+        if interpolate {
+            // Must interpolate the data!
+            for state in self.every_between(step, start, end) {
+                writer.push(state)?;
+            }
+        } else {
+            for state in &self.states {
+                writer.push(*state)?;
             }
         }
 
-        // Build the schema
-        let schema = Arc::new(Schema::new(hdrs));
-        let mut record: Vec<Arc<dyn Array>> = Vec::new();
+        writer.close()
+    }
 
-        // Build the states iterator -- this does require copying the current states but I can't either get a reference or a copy of all the states.
-        let states = if cfg.start_epoch.is_some() || cfg.end_epoch.is_some() || cfg.step.is_some() {
-            // Must interpolate the data!
+    /// Builds an in-memory Arrow record batch of this trajectory, with the same columns and schema metadata as [Self::to_parquet].
+    ///
+    /// This allows handing the trajectory to other Arrow consumers (e.g. pandas or polars) without a file round trip.
+    /// If the configuration has neither start, end, nor step, the states are read in place without being copied.
+    pub fn to_record_batch(
+        &self,
+        events: Option<Vec<&dyn EventEvaluator<S>>>,
+        cfg: ExportCfg,
+        almanac: Arc<Almanac>,
+    ) -> Result<RecordBatch, Box<dyn Error>> {
+        let batcher = TrajBatchBuilder::new(
+            self.first(),
+            events,
+            cfg.fields,
+            traj_metadata(cfg.metadata),
+            almanac,
+        )?;
+
+        if cfg.start_epoch.is_some() || cfg.end_epoch.is_some() || cfg.step.is_some() {
             let start = cfg.start_epoch.unwrap_or_else(|| self.first().epoch());
             let end = cfg.end_epoch.unwrap_or_else(|| self.last().epoch());
             let step = cfg.step.unwrap_or_else(|| 1.minutes());
-            self.every_between(step, start, end).collect::<Vec<S>>()
-        } else {
-            self.states.to_vec()
-        };
-
-        // Build all of the records
 
-        // Epochs
-        let mut utc_epoch = StringBuilder::new();
-        for s in &states {
-            utc_epoch.append_value(s.epoch().to_time_scale(TimeScale::UTC).to_isoformat());
-        }
-        record.push(Arc::new(utc_epoch.finish()));
-
-        // Add all of the fields
-        for field in fields {
-            if field == StateParameter::GuidanceMode {
-                let mut guid_mode = StringBuilder::new();
-                for s in &states {
-                    guid_mode
-                        .append_value(format!("{:?}", GuidanceMode::from(s.value(field).unwrap())));
-                }
-                record.push(Arc::new(guid_mode.finish()));
-            } else {
-                let mut data = Float64Builder::new();
-                for s in &states {
-                    data.append_value(s.value(field).unwrap());
-                }
-                record.push(Arc::new(data.finish()));
-            }
-        }
-
-        info!(
-            "Serialized {} states from {} to {}",
-            states.len(),
-            states.first().unwrap().epoch(),
-            states.last().unwrap().epoch()
-        );
-
-        // Add all of the evaluated events
-        if let Some(events) = events {
-            info!("Evaluating {} event(s)", events.len());
-            for event in events {
-                let mut data = Float64Builder::new();
-                for s in &states {
-                    data.append_value(event.eval(s, almanac.clone()).map_err(Box::new)?);
-                }
-                record.push(Arc::new(data.finish()));
-            }
-        }
-
-        // Serialize all of the devices and add that to the parquet file too.
-        let mut metadata = HashMap::new();
-        metadata.insert("Purpose".to_string(), "Trajectory data".to_string());
-        if let Some(add_meta) = cfg.metadata {
-            for (k, v) in add_meta {
-                metadata.insert(k, v);
-            }
+            let states = self.every_between(step, start, end).collect::<Vec<S>>();
+            batcher.build(&states)
+        } else {
+            batcher.build(&self.states)
         }
-
-        let props = pq_writer(Some(metadata));
-
-        let file = File::create(&path_buf)?;
-        let mut writer = ArrowWriter::try_new(file, schema.clone(), props).unwrap();
-
-        let batch = RecordBatch::try_new(schema, record)?;
-        writer.write(&batch)?;
-        writer.close()?;
-
-        // Return the path this was written to
-        let tock_time = Epoch::now().unwrap() - tick;
-        info!(
-            "Trajectory written to {} in {tock_time}",
-            path_buf.display()
-        );
-        Ok(path_buf)
     }
 
     /// Allows resampling this trajectory at a fixed interval instead of using the propagator step size.
@@ -515,7 +475,7 @@ where
             }
         }
 
-        let props = pq_writer(Some(metadata));
+        let props = Some(pq_writer_with(Some(metadata), &cfg.parquet)?);
 
         let file = File::create(&path_buf)?;
         let mut writer = ArrowWriter::try_new(file, schema.clone(), props).unwrap();
@@ -585,6 +545,14 @@ where
             {
                 me.states.push(*state);
             }
+            // And the continuous extension of these segments
+            for step in other
+                .dense
+                .iter()
+                .filter(|step| step.earliest() >= self.last().epoch())
+            {
+                me.dense.push(step.clone());
+            }
             me.finalize();
 
             Ok(me)
@@ -0,0 +1,498 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{ExportCfg, Interpolatable};
+use crate::io::watermark::pq_writer_with;
+use crate::io::InputOutputError;
+use crate::linalg::allocator::Allocator;
+use crate::linalg::DefaultAllocator;
+use crate::md::prelude::{GuidanceMode, StateParameter};
+use crate::md::EventEvaluator;
+use crate::time::Epoch;
+use anise::almanac::Almanac;
+use arrow::array::{Array, Float64Builder, StringBuilder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use hifitime::TimeScale;
+use parquet::arrow::ArrowWriter;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Builds the Arrow record batches of the states of a trajectory, with the columns of the export configuration and the event evaluators.
+pub(crate) struct TrajBatchBuilder<'a, S: Interpolatable>
+where
+    DefaultAllocator: Allocator<S::VecLength> + Allocator<S::Size> + Allocator<S::Size, S::Size>,
+{
+    pub(crate) schema: Arc<Schema>,
+    fields: Vec<StateParameter>,
+    events: Vec<&'a dyn EventEvaluator<S>>,
+    almanac: Arc<Almanac>,
+}
+
+impl<'a, S: Interpolatable> TrajBatchBuilder<'a, S>
+where
+    DefaultAllocator: Allocator<S::VecLength> + Allocator<S::Size> + Allocator<S::Size, S::Size>,
+{
+    /// Builds the schema from the first state, the exported fields and the events. The metadata is stored in the schema.
+    pub(crate) fn new(
+        first_state: &S,
+        events: Option<Vec<&'a dyn EventEvaluator<S>>>,
+        fields: Option<Vec<StateParameter>>,
+        metadata: HashMap<String, String>,
+        almanac: Arc<Almanac>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut hdrs = vec![Field::new("Epoch (UTC)", DataType::Utf8, false)];
+
+        let frame = first_state.frame();
+        let more_meta = Some(vec![(
+            "Frame".to_string(),
+            serde_dhall::serialize(&frame).to_string().map_err(|e| {
+                Box::new(InputOutputError::SerializeDhall {
+                    what: format!("frame `{frame}`"),
+                    err: e.to_string(),
+                })
+            })?,
+        )]);
+
+        let mut fields = match fields {
+            Some(fields) => fields,
+            None => S::export_params(),
+        };
+
+        // Check that we can retrieve this information
+        fields.retain(|param| first_state.value_with_almanac(*param, &almanac).is_ok());
+
+        for field in &fields {
+            hdrs.push(field.to_field(more_meta.clone()));
+        }
+
+        let events = events.unwrap_or_default();
+        for event in &events {
+            let field = Field::new(format!("{event}"), DataType::Float64, false);
+            hdrs.push(field);
+        }
+
+        Ok(Self {
+            schema: Arc::new(Schema::new_with_metadata(hdrs, metadata)),
+            fields,
+            events,
+            almanac,
+        })
+    }
+
+    /// Builds the record batch of these states.
+    pub(crate) fn build(&self, states: &[S]) -> Result<RecordBatch, Box<dyn Error>> {
+        let mut record: Vec<Arc<dyn Array>> = Vec::new();
+
+        // Epochs
+        let mut utc_epoch = StringBuilder::new();
+        for s in states {
+            utc_epoch.append_value(s.epoch().to_time_scale(TimeScale::UTC).to_isoformat());
+        }
+        record.push(Arc::new(utc_epoch.finish()));
+
+        // Add all of the fields
+        for field in &self.fields {
+            if *field == StateParameter::GuidanceMode {
+                let mut guid_mode = StringBuilder::new();
+                for s in states {
+                    guid_mode.append_value(format!(
+                        "{:?}",
+                        GuidanceMode::from(s.value(*field).unwrap())
+                    ));
+                }
+                record.push(Arc::new(guid_mode.finish()));
+            } else {
+                let mut data = Float64Builder::with_capacity(states.len());
+                for s in states {
+                    data.append_value(s.value_with_almanac(*field, &self.almanac).unwrap());
+                }
+                record.push(Arc::new(data.finish()));
+            }
+        }
+
+        // Add all of the evaluated events
+        for event in &self.events {
+            let mut data = Float64Builder::with_capacity(states.len());
+            for s in states {
+                data.append_value(event.eval(s, self.almanac.clone()).map_err(Box::new)?);
+            }
+            record.push(Arc::new(data.finish()));
+        }
+
+        Ok(RecordBatch::try_new(self.schema.clone(), record)?)
+    }
+
+    /// Number of evaluated events
+    pub(crate) fn num_events(&self) -> usize {
+        self.events.len()
+    }
+}
+
+/// Writes the states of a trajectory to a parquet file as they are produced, one record batch (and row group) at a time,
+/// so that long trajectories never have to be stored in memory.
+///
+/// The number of states of each batch is set by the parquet configuration, [crate::io::EXPORT_BATCH_ROWS] by default.
+/// The schema is built from the first state. States outside of the start and end epochs of the configuration are skipped,
+/// but its step is ignored: the states are written as they are pushed.
+pub struct TrajWriter<'a, S: Interpolatable>
+where
+    DefaultAllocator: Allocator<S::VecLength> + Allocator<S::Size> + Allocator<S::Size, S::Size>,
+{
+    writer: ArrowWriter<File>,
+    batcher: TrajBatchBuilder<'a, S>,
+    start_epoch: Option<Epoch>,
+    end_epoch: Option<Epoch>,
+    path_buf: PathBuf,
+    buffer: Vec<S>,
+    batch_rows: usize,
+    num_states: usize,
+    first_epoch: Option<Epoch>,
+    last_epoch: Option<Epoch>,
+    tick: Epoch,
+}
+
+impl<'a, S: Interpolatable> TrajWriter<'a, S>
+where
+    DefaultAllocator: Allocator<S::VecLength> + Allocator<S::Size> + Allocator<S::Size, S::Size>,
+{
+    /// Creates the parquet file with the schema of the first state (which is not written), the configuration and the optional event evaluators.
+    pub fn new<P: AsRef<Path>>(
+        path: P,
+        first_state: &S,
+        events: Option<Vec<&'a dyn EventEvaluator<S>>>,
+        cfg: ExportCfg,
+        almanac: Arc<Almanac>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let tick = Epoch::now().unwrap();
+        info!("Exporting trajectory to parquet file...");
+
+        // Grab the path here before we move stuff.
+        let path_buf = cfg.actual_path(path);
+
+        let metadata = traj_metadata(cfg.metadata);
+        let props = pq_writer_with(Some(metadata.clone()), &cfg.parquet)?;
+        let batch_rows = cfg.parquet.row_group_rows.max(1);
+
+        let batcher = TrajBatchBuilder::new(first_state, events, cfg.fields, metadata, almanac)?;
+
+        let file = File::create(&path_buf)?;
+        let writer = ArrowWriter::try_new(file, batcher.schema.clone(), Some(props))?;
+
+        Ok(Self {
+            writer,
+            batcher,
+            start_epoch: cfg.start_epoch,
+            end_epoch: cfg.end_epoch,
+            path_buf,
+            buffer: Vec::with_capacity(batch_rows),
+            batch_rows,
+            num_states: 0,
+            first_epoch: None,
+            last_epoch: None,
+            tick,
+        })
+    }
+
+    /// Adds this state to the file, which is written once a full batch of states is buffered.
+    pub fn push(&mut self, state: S) -> Result<(), Box<dyn Error>> {
+        let epoch = state.epoch();
+        if self.start_epoch.is_some_and(|start| epoch < start)
+            || self.end_epoch.is_some_and(|end| epoch > end)
+        {
+            return Ok(());
+        }
+
+        self.first_epoch.get_or_insert(epoch);
+        self.last_epoch = Some(epoch);
+        self.num_states += 1;
+
+        self.buffer.push(state);
+        if self.buffer.len() >= self.batch_rows {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Number of states written or buffered so far
+    pub fn len(&self) -> usize {
+        self.num_states
+    }
+
+    /// Returns true if no state was pushed to this writer
+    pub fn is_empty(&self) -> bool {
+        self.num_states == 0
+    }
+
+    /// Writes the buffered states as a record batch.
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let batch = self.batcher.build(&self.buffer)?;
+        self.writer.write(&batch)?;
+        // Each batch is its own row group, so that its memory is released
+        self.writer.flush()?;
+        self.buffer.clear();
+
+        Ok(())
+    }
+
+    /// Writes the remaining states and closes the file. Returns the path it was written to.
+    pub fn close(mut self) -> Result<PathBuf, Box<dyn Error>> {
+        self.flush()?;
+        self.writer.close()?;
+
+        if self.batcher.num_events() > 0 {
+            info!("Evaluated {} event(s)", self.batcher.num_events());
+        }
+        if let (Some(first), Some(last)) = (self.first_epoch, self.last_epoch) {
+            info!(
+                "Serialized {} states from {first} to {last}",
+                self.num_states
+            );
+        }
+
+        let tock_time = Epoch::now().unwrap() - self.tick;
+        info!(
+            "Trajectory written to {} in {tock_time}",
+            self.path_buf.display()
+        );
+        Ok(self.path_buf)
+    }
+}
+
+/// Metadata of a trajectory export, with the additional metadata of the configuration.
+pub(crate) fn traj_metadata(add_meta: Option<HashMap<String, String>>) -> HashMap<String, String> {
+    let mut metadata = HashMap::new();
+    metadata.insert("Purpose".to_string(), "Trajectory data".to_string());
+    if let Some(add_meta) = add_meta {
+        for (k, v) in add_meta {
+            metadata.insert(k, v);
+        }
+    }
+    metadata
+}
+
+#[cfg(test)]
+mod ut_writer {
+    use super::TrajWriter;
+    use crate::cosmic::{Orbit, Spacecraft};
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::io::{ExportCfg, MetadataPolicy, ParquetCfg, ParquetCompression, EXPORT_BATCH_ROWS};
+    use crate::md::StateParameter;
+    use crate::propagators::{IntegratorOptions, Propagator};
+    use crate::time::TimeUnits;
+    use anise::structure::planetocentric::ellipsoid::Ellipsoid;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use parquet::basic::Compression;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use std::fs::File;
+    use std::path::PathBuf;
+
+    fn earth_orbit() -> Orbit {
+        // The frame is serialized in the metadata, so it needs its shape
+        let eme2k = fixtures::eme2k().with_ellipsoid(Ellipsoid::from_sphere(6_378.136_3));
+        let epoch = fixtures::epoch();
+        Orbit::keplerian(7_500.0, 0.05, 51.6, 30.0, 45.0, 10.0, epoch, eme2k)
+    }
+
+    fn output_path(name: &str) -> PathBuf {
+        [env!("CARGO_MANIFEST_DIR"), "output_data", name]
+            .iter()
+            .collect()
+    }
+
+    #[test]
+    fn write_in_batches() {
+        let orbit = earth_orbit();
+        let state = Spacecraft::builder().orbit(orbit).build();
+        let start = orbit.epoch + 1.minutes();
+        let cfg = ExportCfg::builder()
+            .fields(vec![
+                StateParameter::X,
+                StateParameter::Y,
+                StateParameter::Z,
+            ])
+            .start_epoch(start)
+            .build();
+
+        let mut writer = TrajWriter::new(
+            output_path("batched_traj.parquet"),
+            &state,
+            None,
+            cfg,
+            fixtures::almanac(),
+        )
+        .unwrap();
+
+        // The first minute is before the start epoch of the export
+        for i in 0..(EXPORT_BATCH_ROWS + 60 + 10) {
+            let mut state = state;
+            state.orbit.epoch += (i as i64).seconds();
+            writer.push(state).unwrap();
+        }
+        assert_eq!(writer.len(), EXPORT_BATCH_ROWS + 10);
+
+        let path = writer.close().unwrap();
+        let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+        let metadata = reader.metadata();
+        assert_eq!(
+            metadata.file_metadata().num_rows() as usize,
+            EXPORT_BATCH_ROWS + 10
+        );
+        assert_eq!(metadata.num_row_groups(), 2);
+    }
+
+    #[test]
+    fn stream_propagation_to_parquet() {
+        let orbit = earth_orbit();
+
+        let prop = Propagator::rk89(
+            SpacecraftDynamics::new(OrbitalDynamics::two_body()),
+            IntegratorOptions::with_fixed_step_s(10.0),
+        );
+
+        let (end_state, path) = prop
+            .with(
+                Spacecraft::builder().orbit(orbit).build(),
+                fixtures::almanac(),
+            )
+            .for_duration_with_parquet(
+                1.hours(),
+                output_path("streamed_traj.parquet"),
+                ExportCfg::builder().step(1.minutes()).build(),
+            )
+            .unwrap();
+
+        assert_eq!(end_state.orbit.epoch, orbit.epoch + 1.hours());
+
+        // The initial state and one state per minute
+        let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 61);
+    }
+
+    #[test]
+    fn record_batch_matches_parquet() {
+        let orbit = earth_orbit();
+        let almanac = fixtures::almanac();
+
+        let prop = Propagator::rk89(
+            SpacecraftDynamics::new(OrbitalDynamics::two_body()),
+            IntegratorOptions::with_fixed_step_s(60.0),
+        );
+
+        let (_, traj) = prop
+            .with(Spacecraft::builder().orbit(orbit).build(), almanac.clone())
+            .for_duration_with_traj(1.hours())
+            .unwrap();
+
+        let cfg = ExportCfg::from_metadata(vec![("Mission".to_string(), "LEO".to_string())]);
+
+        let batch = traj
+            .to_record_batch(None, cfg.clone(), almanac.clone())
+            .unwrap();
+        assert_eq!(batch.num_rows(), traj.states.len());
+        assert_eq!(batch.schema().metadata()["Purpose"], "Trajectory data");
+        assert_eq!(batch.schema().metadata()["Mission"], "LEO");
+
+        let path = traj
+            .to_parquet(output_path("record_batch_traj.parquet"), None, cfg, almanac)
+            .unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let from_file = reader.map(|batch| batch.unwrap()).collect::<Vec<_>>();
+        assert_eq!(from_file.len(), 1);
+        assert_eq!(from_file[0].columns(), batch.columns());
+    }
+
+    #[test]
+    fn configurable_writer() {
+        let orbit = earth_orbit();
+        let state = Spacecraft::builder().orbit(orbit).build();
+        let cfg = ExportCfg::builder()
+            .fields(vec![StateParameter::X])
+            .parquet(
+                ParquetCfg::builder()
+                    .compression(ParquetCompression::Snappy)
+                    .row_group_rows(100)
+                    .metadata(MetadataPolicy::Minimal)
+                    .build(),
+            )
+            .build();
+
+        let mut writer = TrajWriter::new(
+            output_path("configured_traj.parquet"),
+            &state,
+            None,
+            cfg,
+            fixtures::almanac(),
+        )
+        .unwrap();
+
+        for i in 0..250 {
+            let mut state = state;
+            state.orbit.epoch += (i as i64).seconds();
+            writer.push(state).unwrap();
+        }
+
+        let path = writer.close().unwrap();
+        let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+        let metadata = reader.metadata();
+        assert_eq!(metadata.num_row_groups(), 3);
+        assert_eq!(
+            metadata.row_group(0).column(1).compression(),
+            Compression::SNAPPY
+        );
+
+        let keys = metadata
+            .file_metadata()
+            .key_value_metadata()
+            .unwrap()
+            .iter()
+            .map(|kv| kv.key.as_str())
+            .collect::<Vec<_>>();
+        assert!(keys.contains(&"Purpose"));
+        assert!(!keys.contains(&"Created by"));
+
+        // Invalid compression levels are rejected
+        let cfg = ExportCfg::builder()
+            .parquet(
+                ParquetCfg::builder()
+                    .compression(ParquetCompression::Zstd { level: 99 })
+                    .build(),
+            )
+            .build();
+        assert!(TrajWriter::new(
+            output_path("invalid_traj.parquet"),
+            &state,
+            None,
+            cfg,
+            fixtures::almanac(),
+        )
+        .is_err());
+    }
+}
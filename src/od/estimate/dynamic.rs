@@ -0,0 +1,511 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::KfEstimate;
+use crate::dynamics::{ExtraStm, MAX_EXTRA_STATES};
+use crate::linalg::{Const, DMatrix, DVector, DimName, OMatrix, OVector};
+use crate::od::{ODDynamicsSnafu, ODError};
+use crate::time::Epoch;
+use crate::{Spacecraft, State};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::fmt;
+
+/// Size of the state of the spacecraft which the propagator integrates the STM of
+type ScSize = Const<9>;
+/// Index of the extra states in the state of the spacecraft augmented with them
+const AUG_EXTRA_INDEX: usize = 9;
+/// Size of the state of the spacecraft augmented with its extra states
+type AugSize = Const<{ AUG_EXTRA_INDEX + MAX_EXTRA_STATES }>;
+
+/// A solve-for parameter of a [DynamicEstimate], estimated in addition to the position and velocity.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SolveFor {
+    /// Coefficient of reflectivity
+    Cr,
+    /// Coefficient of drag
+    Cd,
+    /// Propellant mass, in kg
+    PropMass,
+    /// Extra state of the spacecraft at this index, cf. [crate::dynamics::ExtraState]
+    Extra(usize),
+    /// Parameter only known to the filter (e.g. a measurement bias or a clock offset), whose nominal value is zero
+    Bias(String),
+}
+
+impl SolveFor {
+    /// Returns the index of this parameter in the state of the spacecraft, if it is part of it
+    fn spacecraft_index(&self) -> Option<usize> {
+        match self {
+            Self::Cr => Some(6),
+            Self::Cd => Some(7),
+            Self::PropMass => Some(8),
+            Self::Extra(_) | Self::Bias(_) => None,
+        }
+    }
+
+    /// Returns the index of this parameter in the state of the spacecraft augmented with its extra states, if it is part of it
+    fn augmented_index(&self) -> Option<usize> {
+        match self {
+            Self::Extra(index) => Some(AUG_EXTRA_INDEX + index),
+            _ => self.spacecraft_index(),
+        }
+    }
+
+    /// Returns the nominal value of this parameter for this spacecraft
+    fn nominal_value(&self, sc: &Spacecraft) -> f64 {
+        match self {
+            Self::Cr => sc.srp.coeff_reflectivity,
+            Self::Cd => sc.drag.coeff_drag,
+            Self::PropMass => sc.mass.prop_mass_kg,
+            Self::Extra(index) => sc.extra.values[*index],
+            Self::Bias(_) => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for SolveFor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cr => write!(f, "Cr"),
+            Self::Cd => write!(f, "Cd"),
+            Self::PropMass => write!(f, "prop mass (kg)"),
+            Self::Extra(index) => write!(f, "extra[{index}]"),
+            Self::Bias(name) => write!(f, "bias `{name}`"),
+        }
+    }
+}
+
+/// Kalman filter estimate whose size is chosen at runtime: the position and velocity, followed by the solve-for parameters.
+///
+/// The STM of the extra states is integrated with the full STM of the spacecraft (cf. [ExtraStm]), whereas the biases are
+/// constant in the STM of this estimate, and their uncertainty only grows with process noise.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DynamicEstimate {
+    /// The estimated state
+    pub nominal_state: Spacecraft,
+    /// The solve-for parameters, after the position and velocity
+    pub solve_for: Vec<SolveFor>,
+    /// The state deviation
+    pub state_deviation: DVector<f64>,
+    /// The Covariance of this estimate
+    pub covar: DMatrix<f64>,
+    /// The predicted covariance of this estimate
+    pub covar_bar: DMatrix<f64>,
+    /// Whether or not this is a predicted estimate from a time update, or an estimate from a measurement
+    pub predicted: bool,
+    /// The STM used to compute this Estimate
+    pub stm: DMatrix<f64>,
+}
+
+impl DynamicEstimate {
+    /// Initializes a new filter estimate from the nominal state (not dispersed), the solve-for parameters, and the full covariance
+    pub fn from_covar(
+        nominal_state: Spacecraft,
+        solve_for: Vec<SolveFor>,
+        covar: DMatrix<f64>,
+    ) -> Result<Self, ODError> {
+        for (i, param) in solve_for.iter().enumerate() {
+            if matches!(param, SolveFor::Extra(index) if *index >= MAX_EXTRA_STATES)
+                || solve_for[..i].contains(param)
+            {
+                return Err(ODError::InvalidSolveFor {
+                    param: param.to_string(),
+                });
+            }
+        }
+
+        let size = 6 + solve_for.len();
+        if covar.shape() != (size, size) {
+            return Err(ODError::SolveForMismatch {
+                expected: size,
+                got: covar.nrows(),
+                action: "initializing the covariance",
+            });
+        }
+
+        Ok(Self {
+            nominal_state,
+            solve_for,
+            state_deviation: DVector::zeros(size),
+            covar_bar: covar.clone(),
+            covar,
+            predicted: true,
+            stm: DMatrix::identity(size, size),
+        })
+    }
+
+    /// Initializes a new filter estimate from the nominal state (not dispersed), the solve-for parameters, and the diagonal of the covariance
+    pub fn from_diag(
+        nominal_state: Spacecraft,
+        solve_for: Vec<SolveFor>,
+        diag: &[f64],
+    ) -> Result<Self, ODError> {
+        Self::from_covar(
+            nominal_state,
+            solve_for,
+            DMatrix::from_diagonal(&DVector::from_column_slice(diag)),
+        )
+    }
+
+    /// Number of estimated values, i.e. six plus the number of solve-for parameters
+    pub fn size(&self) -> usize {
+        6 + self.solve_for.len()
+    }
+
+    /// Epoch of this estimate
+    pub fn epoch(&self) -> Epoch {
+        self.nominal_state.epoch()
+    }
+
+    /// Returns the index of this parameter in the estimate, if it is solved for
+    pub fn index_of(&self, param: &SolveFor) -> Option<usize> {
+        self.solve_for
+            .iter()
+            .position(|p| p == param)
+            .map(|pos| pos + 6)
+    }
+
+    /// Returns the index in the state of the spacecraft of each value of the estimate, if it is part of it
+    fn spacecraft_indices(&self) -> Vec<Option<usize>> {
+        (0..6)
+            .map(Some)
+            .chain(self.solve_for.iter().map(SolveFor::spacecraft_index))
+            .collect()
+    }
+
+    /// Maps this matrix whose columns follow the state of the spacecraft (e.g. the sensitivity matrix of a measurement) to
+    /// the columns of this estimate. The columns of the parameters which are not part of it (e.g. biases) are zero.
+    pub fn map_columns(&self, sc_matrix: &DMatrix<f64>) -> Result<DMatrix<f64>, ODError> {
+        if sc_matrix.ncols() != ScSize::USIZE {
+            return Err(ODError::SolveForMismatch {
+                expected: ScSize::USIZE,
+                got: sc_matrix.ncols(),
+                action: "mapping the spacecraft columns",
+            });
+        }
+        let mut mapped = DMatrix::zeros(sc_matrix.nrows(), self.size());
+        for (i, sc_i) in self.spacecraft_indices().into_iter().enumerate() {
+            if let Some(sc_i) = sc_i {
+                mapped.set_column(i, &sc_matrix.column(sc_i));
+            }
+        }
+        Ok(mapped)
+    }
+
+    /// Maps the STM of this spacecraft, including the blocks of its extra states if they were propagated, to this
+    /// estimate. The biases are constant.
+    pub fn map_stm(&self, sc: &Spacecraft) -> Result<DMatrix<f64>, ODError> {
+        let sc_stm = sc.stm().context(ODDynamicsSnafu)?;
+        let extra_stm = sc.extra.stm.unwrap_or_else(ExtraStm::identity);
+        // STM of the spacecraft state augmented with the extra states
+        let mut aug_stm = OMatrix::<f64, AugSize, AugSize>::zeros();
+        aug_stm.fixed_view_mut::<9, 9>(0, 0).copy_from(&sc_stm);
+        aug_stm
+            .fixed_view_mut::<MAX_EXTRA_STATES, 9>(AUG_EXTRA_INDEX, 0)
+            .copy_from(&extra_stm.extra_wrt_sc);
+        aug_stm
+            .fixed_view_mut::<9, MAX_EXTRA_STATES>(0, AUG_EXTRA_INDEX)
+            .copy_from(&extra_stm.sc_wrt_extra);
+        aug_stm
+            .fixed_view_mut::<MAX_EXTRA_STATES, MAX_EXTRA_STATES>(AUG_EXTRA_INDEX, AUG_EXTRA_INDEX)
+            .copy_from(&extra_stm.extra_wrt_extra);
+
+        let indices: Vec<Option<usize>> = (0..6)
+            .map(Some)
+            .chain(self.solve_for.iter().map(SolveFor::augmented_index))
+            .collect();
+        let mut stm = DMatrix::identity(self.size(), self.size());
+        for (i, aug_i) in indices.iter().enumerate() {
+            for (j, aug_j) in indices.iter().enumerate() {
+                if let (Some(aug_i), Some(aug_j)) = (aug_i, aug_j) {
+                    stm[(i, j)] = aug_stm[(*aug_i, *aug_j)];
+                }
+            }
+        }
+        Ok(stm)
+    }
+
+    /// Computes a time update/prediction to the epoch of the nominal state, whose STM must span from the epoch of this
+    /// estimate. The process noise, if any, must have the size of this estimate.
+    pub fn time_update(
+        &mut self,
+        nominal_state: Spacecraft,
+        process_noise: Option<&DMatrix<f64>>,
+    ) -> Result<(), ODError> {
+        let stm = self.map_stm(&nominal_state)?;
+        let mut covar_bar = &stm * &self.covar * stm.transpose();
+        if let Some(q) = process_noise {
+            if q.shape() != covar_bar.shape() {
+                return Err(ODError::SolveForMismatch {
+                    expected: self.size(),
+                    got: q.nrows(),
+                    action: "adding the process noise",
+                });
+            }
+            covar_bar += q;
+        }
+
+        self.state_deviation = &stm * &self.state_deviation;
+        self.nominal_state = nominal_state;
+        self.covar = covar_bar.clone();
+        self.covar_bar = covar_bar;
+        self.stm = stm;
+        self.predicted = true;
+        Ok(())
+    }
+
+    /// Computes the measurement update (with a Joseph update of the covariance) at the epoch of this estimate, given the
+    /// sensitivity matrix (with the columns of this estimate, cf. [DynamicEstimate::map_columns]), the prefit residual,
+    /// and the measurement noise. Returns the postfit residual.
+    pub fn measurement_update(
+        &mut self,
+        h_tilde: &DMatrix<f64>,
+        prefit: &DVector<f64>,
+        r_k: &DMatrix<f64>,
+    ) -> Result<DVector<f64>, ODError> {
+        if h_tilde.ncols() != self.size() {
+            return Err(ODError::SolveForMismatch {
+                expected: self.size(),
+                got: h_tilde.ncols(),
+                action: "applying the sensitivity matrix",
+            });
+        }
+
+        let h_tilde_t = h_tilde.transpose();
+        let mut s_k_inv = h_tilde * &self.covar_bar * &h_tilde_t + r_k;
+        if !s_k_inv.try_inverse_mut() {
+            return Err(ODError::SingularKalmanGain {
+                epoch: self.epoch(),
+            });
+        }
+        let gain = &self.covar_bar * &h_tilde_t * s_k_inv;
+
+        let postfit = prefit - h_tilde * &self.state_deviation;
+        self.state_deviation += &gain * &postfit;
+
+        let first_term = DMatrix::identity(self.size(), self.size()) - &gain * h_tilde;
+        self.covar =
+            &first_term * &self.covar_bar * first_term.transpose() + &gain * r_k * gain.transpose();
+        self.predicted = false;
+        Ok(postfit)
+    }
+
+    /// The estimated state, i.e. the nominal state plus the deviation of the position, velocity, and of the parameters
+    /// which are part of the spacecraft.
+    pub fn state(&self) -> Spacecraft {
+        let mut deviation = OVector::<f64, ScSize>::zeros();
+        for (i, sc_i) in self.spacecraft_indices().into_iter().enumerate() {
+            if let Some(sc_i) = sc_i {
+                deviation[sc_i] = self.state_deviation[i];
+            }
+        }
+        let mut state = self.nominal_state.add(deviation);
+        for (i, param) in self.solve_for.iter().enumerate() {
+            if let SolveFor::Extra(index) = param {
+                state.extra.values[*index] += self.state_deviation[i + 6];
+            }
+        }
+        state
+    }
+
+    /// Returns the estimated value of this parameter, if it is solved for
+    pub fn param_value(&self, param: &SolveFor) -> Option<f64> {
+        self.index_of(param)
+            .map(|i| param.nominal_value(&self.nominal_state) + self.state_deviation[i])
+    }
+
+    /// Returns the standard deviation of this parameter, if it is solved for
+    pub fn param_sigma(&self, param: &SolveFor) -> Option<f64> {
+        self.index_of(param).map(|i| self.covar[(i, i)].sqrt())
+    }
+
+    /// Replaces the spacecraft part of this estimate (position, velocity and the spacecraft parameters which are solved
+    /// for) by the provided fixed size estimate, e.g. a smoothed estimate. The other parameters keep their deviation and
+    /// variance, but their correlation with the spacecraft part is dropped.
+    pub fn set_spacecraft_estimate(&mut self, estimate: &KfEstimate<Spacecraft>) {
+        let indices = self.spacecraft_indices();
+        self.nominal_state = estimate.nominal_state;
+        for (i, sc_i) in indices.iter().enumerate() {
+            match sc_i {
+                Some(sc_i) => {
+                    self.state_deviation[i] = estimate.state_deviation[*sc_i];
+                    for (j, sc_j) in indices.iter().enumerate() {
+                        let (covar, covar_bar) = match sc_j {
+                            Some(sc_j) => (
+                                estimate.covar[(*sc_i, *sc_j)],
+                                estimate.covar_bar[(*sc_i, *sc_j)],
+                            ),
+                            None => (0.0, 0.0),
+                        };
+                        self.covar[(i, j)] = covar;
+                        self.covar[(j, i)] = covar;
+                        self.covar_bar[(i, j)] = covar_bar;
+                        self.covar_bar[(j, i)] = covar_bar;
+                    }
+                }
+                None => continue,
+            }
+        }
+        self.stm = DMatrix::identity(self.size(), self.size());
+        self.predicted = estimate.predicted;
+    }
+
+    /// Zeroes the deviation of the spacecraft part of this estimate, once it is applied to the nominal state (EKF).
+    pub(crate) fn reset_spacecraft_deviation(&mut self) {
+        for (i, sc_i) in self.spacecraft_indices().into_iter().enumerate() {
+            if sc_i.is_some() {
+                self.state_deviation[i] = 0.0;
+            }
+        }
+    }
+
+    /// Returns the fixed size estimate of the spacecraft state, where the parameters which are not solved for have no
+    /// uncertainty. The extra states and the biases are dropped.
+    pub fn to_estimate(&self) -> KfEstimate<Spacecraft> {
+        let indices = self.spacecraft_indices();
+        let mut estimate =
+            KfEstimate::from_diag(self.nominal_state, OVector::<f64, ScSize>::zeros());
+        estimate.predicted = self.predicted;
+        for (i, sc_i) in indices.iter().enumerate() {
+            let Some(sc_i) = *sc_i else { continue };
+            estimate.state_deviation[sc_i] = self.state_deviation[i];
+            for (j, sc_j) in indices.iter().enumerate() {
+                let Some(sc_j) = *sc_j else { continue };
+                estimate.covar[(sc_i, sc_j)] = self.covar[(i, j)];
+                estimate.covar_bar[(sc_i, sc_j)] = self.covar_bar[(i, j)];
+                estimate.stm[(sc_i, sc_j)] = self.stm[(i, j)];
+            }
+        }
+        estimate
+    }
+}
+
+impl fmt::Display for DynamicEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let word = if self.predicted {
+            "Prediction"
+        } else {
+            "Estimate"
+        };
+        let mut fmt_cov = Vec::with_capacity(self.size());
+        for i in 0..6 {
+            let unit = if i < 3 { "km" } else { "km/s" };
+            fmt_cov.push(format!("{:.6} {unit}", self.covar[(i, i)].sqrt()));
+        }
+        for (i, param) in self.solve_for.iter().enumerate() {
+            fmt_cov.push(format!("{param}: {:.6}", self.covar[(i + 6, i + 6)].sqrt()));
+        }
+        write!(
+            f,
+            "=== {} @ {} ===\nstate {}\nsigmas [{}]\n",
+            word,
+            self.epoch(),
+            self.state(),
+            fmt_cov.join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod ut_dynamic_estimate {
+    use super::*;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::propagators::Propagator;
+    use crate::time::Unit;
+
+    #[test]
+    fn runtime_solve_for() {
+        let epoch = fixtures::epoch();
+        let orbit = fixtures::keplerian(7_000.0, 0.01, 28.5, 10.0, 20.0, 30.0);
+        let sc = Spacecraft::from(orbit)
+            .with_drag(2.0, 2.2)
+            .with_extra(1, 0.5);
+
+        let solve_for = vec![
+            SolveFor::Cd,
+            SolveFor::Extra(1),
+            SolveFor::Bias("range".to_string()),
+        ];
+        let diag = [1.0, 1.0, 1.0, 1e-6, 1e-6, 1e-6, 0.04, 0.01, 1e-2];
+        let mut estimate = DynamicEstimate::from_diag(sc, solve_for.clone(), &diag).unwrap();
+        assert_eq!(estimate.size(), 9);
+        assert_eq!(estimate.index_of(&SolveFor::Extra(1)), Some(7));
+        assert_eq!(estimate.param_value(&SolveFor::Cd), Some(2.2));
+        assert!(estimate.param_value(&SolveFor::Cr).is_none());
+
+        assert!(matches!(
+            DynamicEstimate::from_diag(sc, solve_for.clone(), &diag[..8]),
+            Err(ODError::SolveForMismatch { .. })
+        ));
+        assert!(matches!(
+            DynamicEstimate::from_diag(sc, vec![SolveFor::Cd, SolveFor::Cd], &diag[..8]),
+            Err(ODError::InvalidSolveFor { .. })
+        ));
+
+        // Time update with the STM of the dynamics, where the extra state and the bias are constant
+        let nominal = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()))
+            .with(sc.with_stm(), fixtures::almanac())
+            .for_duration(Unit::Minute * 10)
+            .unwrap();
+        estimate.time_update(nominal, None).unwrap();
+        assert_eq!(estimate.epoch(), epoch + Unit::Minute * 10);
+        assert!(estimate.covar[(0, 0)] > 1.0);
+        assert_eq!(estimate.covar[(8, 8)], 1e-2);
+
+        // A range measurement with an unknown bias
+        let r_hat = nominal.orbit.radius_km.normalize();
+        let mut h_sc = DMatrix::zeros(1, 9);
+        for i in 0..3 {
+            h_sc[(0, i)] = r_hat[i];
+        }
+        let mut h_tilde = estimate.map_columns(&h_sc).unwrap();
+        h_tilde[(0, 8)] = 1.0;
+        let prior_range_var = estimate.covar[(8, 8)];
+        let postfit = estimate
+            .measurement_update(
+                &h_tilde,
+                &DVector::from_element(1, 0.3),
+                &DMatrix::from_element(1, 1, 1e-4),
+            )
+            .unwrap();
+        assert!(!estimate.predicted);
+        assert_eq!(postfit[0], 0.3);
+        assert!(estimate.covar[(8, 8)] < prior_range_var);
+        assert!((&estimate.covar - estimate.covar.transpose()).norm() < 1e-12);
+        // The residual is split between the radial position and the bias
+        let fitted = (&h_tilde * &estimate.state_deviation)[0];
+        assert!(fitted > 0.29 && fitted < 0.3);
+        assert!(
+            estimate
+                .param_value(&SolveFor::Bias("range".to_string()))
+                .unwrap()
+                > 0.0
+        );
+
+        // The extra state is part of the estimated spacecraft, and the fixed size estimate keeps the spacecraft parameters
+        let dev_extra = estimate.state_deviation[7];
+        assert_eq!(estimate.state().extra.values[1], 0.5 + dev_extra);
+        let fixed = estimate.to_estimate();
+        assert_eq!(fixed.covar[(7, 7)], estimate.covar[(6, 6)]);
+        assert_eq!(fixed.covar[(6, 6)], 0.0);
+        assert_eq!(fixed.state_deviation[0], estimate.state_deviation[0]);
+        println!("{estimate}");
+    }
+}
@@ -20,13 +20,12 @@ use super::{Estimate, State};
 use crate::cosmic::AstroError;
 use crate::linalg::allocator::Allocator;
 use crate::linalg::{DefaultAllocator, DimName, Matrix, OMatrix, OVector};
-use crate::mc::{MvnSpacecraft, StateDispersion};
+use crate::mc::{stream_rng, MvnSpacecraft, StateDispersion};
 use crate::md::prelude::OrbitDual;
 use crate::md::StateParameter;
 use crate::Spacecraft;
 use nalgebra::Const;
 use nalgebra::SMatrix;
-use rand::SeedableRng;
 use rand_distr::Distribution;
 use rand_pcg::Pcg64Mcg;
 use std::cmp::PartialEq;
@@ -112,7 +111,7 @@ impl KfEstimate<Spacecraft> {
 
         let mut rng = match seed {
             Some(seed) => Pcg64Mcg::new(seed),
-            None => Pcg64Mcg::from_entropy(),
+            None => stream_rng("initial_estimate"),
         };
         let dispersed_state = generator.sample(&mut rng);
 
@@ -351,16 +350,14 @@ where
 
 #[cfg(test)]
 mod ut_kfest {
-    use crate::{
-        mc::StateDispersion, md::StateParameter, od::estimate::KfEstimate, Spacecraft,
-        GMAT_EARTH_GM,
-    };
-    use anise::{constants::frames::EARTH_J2000, prelude::Orbit};
+    use crate::fixtures;
+    use crate::{mc::StateDispersion, md::StateParameter, od::estimate::KfEstimate, Spacecraft};
+    use anise::prelude::Orbit;
     use hifitime::Epoch;
 
     #[test]
     fn test_estimate_from_disp() {
-        let eme2k = EARTH_J2000.with_mu_km3_s2(GMAT_EARTH_GM);
+        let eme2k = fixtures::eme2k();
         let dt = Epoch::from_gregorian_tai_at_midnight(2020, 1, 1);
         let initial_state = Spacecraft::builder()
             .orbit(Orbit::keplerian(
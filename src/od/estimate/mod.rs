@@ -29,6 +29,8 @@ pub mod residual;
 pub use residual::Residual;
 pub mod kfestimate;
 pub use kfestimate::KfEstimate;
+pub mod dynamic;
+pub use dynamic::{DynamicEstimate, SolveFor};
 mod sc_uncertainty;
 pub use sc_uncertainty::SpacecraftUncertainty;
 
@@ -0,0 +1,406 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::linalg::allocator::Allocator;
+use crate::linalg::{Const, DMatrix, DVector, DefaultAllocator, DimName, OMatrix, OVector, U3};
+use crate::od::estimate::{DynamicEstimate, KfEstimate, Residual, SolveFor};
+use crate::od::process::ResidRejectCrit;
+use crate::od::snc::SNC3;
+use crate::od::{Filter, ODError};
+use crate::profiling::{self, Subsystem};
+use crate::{Spacecraft, State};
+use std::collections::BTreeMap;
+use tracing::debug;
+
+/// Size of the state of the spacecraft which the propagator integrates the STM of
+type ScSize = Const<9>;
+
+/// Defines both a Classical and an Extended Kalman filter (CKF and EKF) whose estimate is a [DynamicEstimate], i.e.
+/// whose solve-for parameters are chosen at runtime.
+///
+/// It implements [Filter] on the state of the spacecraft, so it is used by an orbit determination process like the
+/// [crate::od::filter::kalman::KF]: the estimates of the process are the spacecraft part of the estimates of this filter
+/// (cf. [DynamicEstimate::to_estimate]), and the full estimates are stored in [DynamicKF::history].
+///
+/// In EKF mode, the process applies the deviation of the spacecraft part to its reference trajectory, whereas the other
+/// parameters (extra states and biases) remain deviations estimated by this filter.
+/// M: Measurement size (used for the sensitivity matrix)
+#[derive(Debug, Clone)]
+pub struct DynamicKF<M: DimName>
+where
+    DefaultAllocator: Allocator<M> + Allocator<M, ScSize>,
+{
+    /// The previous estimate used in the KF computations.
+    pub prev_estimate: DynamicEstimate,
+    /// A sets of process noise of the position and velocity, must be ordered chronologically
+    pub process_noise: Vec<SNC3>,
+    /// Determines whether this KF should operate as a Conventional/Classical Kalman filter or an Extended Kalman Filter.
+    pub ekf: bool,
+    /// Row of the measurement on which each solve-for bias applies, for all trackers, e.g. 0 for the range of a range
+    /// and Doppler process. The biases which are not listed here are not observed.
+    pub bias_rows: BTreeMap<String, usize>,
+    /// Every estimate computed by this filter, in the order of the estimates of the process
+    pub history: Vec<DynamicEstimate>,
+    prev_projection: KfEstimate<Spacecraft>,
+    h_tilde: OMatrix<f64, M, ScSize>,
+    h_tilde_updated: bool,
+}
+
+impl<M: DimName> DynamicKF<M>
+where
+    DefaultAllocator: Allocator<M> + Allocator<M, ScSize>,
+{
+    /// Initializes this KF with an initial estimate and several process noises, which must be ordered chronologically.
+    pub fn new(initial_estimate: DynamicEstimate, process_noises: Vec<SNC3>) -> Self {
+        let mut process_noises = process_noises;
+        // Set the initial epoch of the SNC
+        for snc in &mut process_noises {
+            snc.init_epoch = Some(initial_estimate.epoch());
+        }
+
+        Self {
+            prev_projection: initial_estimate.to_estimate(),
+            prev_estimate: initial_estimate,
+            process_noise: process_noises,
+            ekf: false,
+            bias_rows: BTreeMap::new(),
+            history: Vec::new(),
+            h_tilde: OMatrix::<f64, M, ScSize>::zeros(),
+            h_tilde_updated: false,
+        }
+    }
+
+    /// Applies the solve-for bias with this name to the provided row of the measurements.
+    pub fn with_bias_row(mut self, name: &str, row: usize) -> Self {
+        self.bias_rows.insert(name.to_string(), row);
+        self
+    }
+
+    /// Returns the process noise of the estimate at the epoch of the nominal state, if any SNC applies.
+    fn process_noise_at(&self, nominal_state: &Spacecraft) -> Option<DMatrix<f64>> {
+        // Walk the SNCs backward until one can be applied, as the KF does
+        let snc_matrix = self
+            .process_noise
+            .iter()
+            .rev()
+            .find_map(|snc| snc.to_matrix(nominal_state.epoch()))?;
+
+        // Gamma matrix, which assumes that the acceleration is constant between these two epochs
+        let delta_t = (nominal_state.epoch() - self.prev_estimate.epoch()).to_seconds();
+        let mut gamma = OMatrix::<f64, Const<6>, U3>::zeros();
+        for i in 0..3 {
+            gamma[(i, i)] = delta_t.powi(2) / 2.0;
+            gamma[(i + 3, i)] = delta_t;
+        }
+
+        let size = self.prev_estimate.size();
+        let mut q = DMatrix::zeros(size, size);
+        q.view_mut((0, 0), (6, 6))
+            .copy_from(&(gamma * snc_matrix * gamma.transpose()));
+        Some(q)
+    }
+
+    /// Stores the estimate in the history and returns its spacecraft part.
+    fn record(&mut self) -> KfEstimate<Spacecraft> {
+        for snc in &mut self.process_noise {
+            snc.prev_epoch = Some(self.prev_estimate.epoch());
+        }
+        self.prev_projection = self.prev_estimate.to_estimate();
+        self.history.push(self.prev_estimate.clone());
+        self.prev_projection
+    }
+}
+
+impl<M: DimName> Filter<Spacecraft, U3, M> for DynamicKF<M>
+where
+    DefaultAllocator: Allocator<M>
+        + Allocator<M, M>
+        + Allocator<M, ScSize>
+        + Allocator<ScSize, M>
+        + Allocator<Const<1>, M>,
+{
+    type Estimate = KfEstimate<Spacecraft>;
+
+    /// Returns the spacecraft part of the previous estimate
+    fn previous_estimate(&self) -> &Self::Estimate {
+        &self.prev_projection
+    }
+
+    /// Replaces the spacecraft part of the previous estimate, cf. [DynamicEstimate::set_spacecraft_estimate]
+    fn set_previous_estimate(&mut self, est: &Self::Estimate) {
+        self.prev_estimate.set_spacecraft_estimate(est);
+        self.prev_projection = self.prev_estimate.to_estimate();
+    }
+
+    fn update_h_tilde(&mut self, h_tilde: OMatrix<f64, M, ScSize>) {
+        self.h_tilde = h_tilde;
+        self.h_tilde_updated = true;
+    }
+
+    fn time_update(&mut self, nominal_state: Spacecraft) -> Result<Self::Estimate, ODError> {
+        let _scope = profiling::scope(Subsystem::MeasurementUpdate, || "time update".to_string());
+        if self.ekf {
+            self.prev_estimate.reset_spacecraft_deviation();
+        }
+        let process_noise = self.process_noise_at(&nominal_state);
+        self.prev_estimate
+            .time_update(nominal_state, process_noise.as_ref())?;
+        Ok(self.record())
+    }
+
+    fn measurement_update(
+        &mut self,
+        nominal_state: Spacecraft,
+        real_obs: &OVector<f64, M>,
+        computed_obs: &OVector<f64, M>,
+        r_k: OMatrix<f64, M, M>,
+        resid_rejection: Option<ResidRejectCrit>,
+    ) -> Result<(Self::Estimate, Residual<M>), ODError> {
+        if !self.h_tilde_updated {
+            return Err(ODError::SensitivityNotUpdated);
+        }
+        self.h_tilde_updated = false;
+
+        let epoch = nominal_state.epoch();
+        if self.ekf {
+            self.prev_estimate.reset_spacecraft_deviation();
+        }
+        // Predict the estimate to the epoch of the measurement, without process noise like the KF
+        self.prev_estimate.time_update(nominal_state, None)?;
+
+        // Map the sensitivity to the estimate, and add the sensitivity to the biases
+        let h_sc = DMatrix::from_column_slice(M::USIZE, ScSize::USIZE, self.h_tilde.as_slice());
+        let mut h_tilde = self.prev_estimate.map_columns(&h_sc)?;
+        for (name, row) in &self.bias_rows {
+            if let Some(col) = self.prev_estimate.index_of(&SolveFor::Bias(name.clone())) {
+                if *row < M::USIZE {
+                    h_tilde[(*row, col)] = 1.0;
+                }
+            }
+        }
+
+        let prefit = real_obs - computed_obs;
+        let r_k_dyn = DMatrix::from_column_slice(M::USIZE, M::USIZE, r_k.as_slice());
+        let s_k = &h_tilde * &self.prev_estimate.covar_bar * h_tilde.transpose() + &r_k_dyn;
+        let r_k_chol = s_k
+            .clone()
+            .cholesky()
+            .ok_or(ODError::SingularNoiseRk)?
+            .l()
+            .diagonal();
+        let r_k_chol = OVector::<f64, M>::from_column_slice(r_k_chol.as_slice());
+
+        // Average of each component of the prefit over the square root of the innovation covariance, as in the KF
+        let ratio = s_k
+            .diagonal()
+            .iter()
+            .enumerate()
+            .map(|(idx, r)| prefit[idx] / r.sqrt())
+            .sum::<f64>()
+            / (M::USIZE as f64);
+
+        if let Some(resid_reject) = resid_rejection {
+            if ratio.abs() > resid_reject.num_sigmas {
+                debug!(
+                    ratio,
+                    num_sigmas = resid_reject.num_sigmas,
+                    "measurement rejected"
+                );
+                return Ok((
+                    self.record(),
+                    Residual::rejected(epoch, prefit, ratio, r_k_chol),
+                ));
+            }
+        }
+
+        let postfit = self.prev_estimate.measurement_update(
+            &h_tilde,
+            &DVector::from_column_slice(prefit.as_slice()),
+            &r_k_dyn,
+        )?;
+        let postfit = OVector::<f64, M>::from_column_slice(postfit.as_slice());
+        debug!(ratio, ekf = self.ekf, "measurement accepted");
+
+        Ok((
+            self.record(),
+            Residual::accepted(epoch, prefit, postfit, ratio, r_k_chol),
+        ))
+    }
+
+    fn is_extended(&self) -> bool {
+        self.ekf
+    }
+
+    fn set_extended(&mut self, status: bool) {
+        self.ekf = status;
+    }
+
+    /// Overwrites all of the process noises to the one provided
+    fn set_process_noise(&mut self, snc: SNC3) {
+        self.process_noise = vec![snc];
+    }
+}
+
+#[cfg(test)]
+mod ut_dynamic_kf {
+    use super::*;
+    use crate::dynamics::{ExtraForce, ExtraState, OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::linalg::{Vector1, Vector3};
+    use crate::propagators::Propagator;
+    use crate::time::TimeUnits;
+
+    #[test]
+    fn range_bias() {
+        let orbit = fixtures::keplerian(7_000.0, 0.01, 51.6, 30.0, 45.0, 10.0);
+        let sc = Spacecraft::from(orbit).with_stm();
+        let setup = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let almanac = fixtures::almanac();
+        let mut truth = setup.with(sc, almanac.clone());
+        let mut prop = setup.with(sc, almanac);
+
+        let bias = SolveFor::Bias("range".to_string());
+        let initial_estimate = DynamicEstimate::from_diag(
+            sc,
+            vec![bias.clone()],
+            &[1e-2, 1e-2, 1e-2, 1e-8, 1e-8, 1e-8, 1e-2],
+        )
+        .unwrap();
+        let mut kf = DynamicKF::<Const<1>>::new(initial_estimate, vec![]).with_bias_row("range", 0);
+
+        // Biased ranges of the true trajectory from a fixed inertial point
+        let station_km = Vector3::new(6_378.0, 0.0, 0.0);
+        let range_km = |sc: &Spacecraft| (sc.orbit.radius_km - station_km).norm();
+        let range_bias_km = 0.05;
+        for i in 0..90 {
+            let real =
+                Vector1::new(range_km(&truth.for_duration(1.minutes()).unwrap()) + range_bias_km);
+            let nominal = prop.for_duration(1.minutes()).unwrap();
+            if i == 60 {
+                kf.set_extended(true);
+            }
+            let rho_km = nominal.orbit.radius_km - station_km;
+            let mut h_tilde = OMatrix::<f64, Const<1>, ScSize>::zeros();
+            for j in 0..3 {
+                h_tilde[(0, j)] = rho_km[j] / rho_km.norm();
+            }
+            kf.update_h_tilde(h_tilde);
+            let (estimate, residual) = kf
+                .measurement_update(
+                    nominal,
+                    &real,
+                    &Vector1::new(range_km(&nominal)),
+                    OMatrix::<f64, Const<1>, Const<1>>::new(1e-8),
+                    None,
+                )
+                .unwrap();
+            assert!(!residual.rejected);
+            assert_eq!(&estimate, kf.previous_estimate());
+            if kf.is_extended() {
+                // As in the OD process, the spacecraft deviation is applied to the nominal state, but the bias
+                // remains estimated by the filter
+                prop.state = prop.state + estimate.state_deviation;
+            }
+            prop.state.reset_stm();
+        }
+
+        assert_eq!(kf.history.len(), 90);
+        let last = kf.history.last().unwrap();
+        println!("{last}");
+        let bias_km = last.param_value(&bias).unwrap();
+        assert!(
+            (bias_km - range_bias_km).abs() < 1e-3,
+            "range bias estimated at {bias_km} km"
+        );
+        assert!(last.param_sigma(&bias).unwrap() < 1e-2);
+        assert!((prop.state.orbit.radius_km - truth.state.orbit.radius_km).norm() < 1e-3);
+
+        // The sensitivity must be updated before each measurement update
+        let nominal = prop.for_duration(1.minutes()).unwrap();
+        assert!(kf
+            .measurement_update(
+                nominal,
+                &Vector1::zeros(),
+                &Vector1::zeros(),
+                OMatrix::<f64, Const<1>, Const<1>>::new(1e-8),
+                None,
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn leak_force() {
+        let orbit = fixtures::keplerian(7_000.0, 0.01, 51.6, 30.0, 45.0, 10.0);
+        // The true leak is unknown to the filter, whose nominal state has none
+        let leak_n = 2e-3;
+        let sc = Spacecraft::from_srp_defaults(orbit, 100.0, 1.0)
+            .with_extra(0, 0.0)
+            .with_stm();
+        let dynamics = SpacecraftDynamics::from_model(
+            OrbitalDynamics::two_body(),
+            ExtraForce::new(0, Vector3::new(0.0, 0.0, 1.0)),
+        )
+        .with_extra_states(vec![ExtraState::constant("leak_N").in_dynamics()])
+        .unwrap();
+        let setup = Propagator::default(dynamics);
+        let almanac = fixtures::almanac();
+        let mut truth = setup.with(sc.with_extra(0, leak_n), almanac.clone());
+        let mut prop = setup.with(sc, almanac);
+
+        let leak = SolveFor::Extra(0);
+        let initial_estimate = DynamicEstimate::from_diag(
+            sc,
+            vec![leak.clone()],
+            &[1e-2, 1e-2, 1e-2, 1e-8, 1e-8, 1e-8, 1e-4],
+        )
+        .unwrap();
+        let mut kf = DynamicKF::<Const<1>>::new(initial_estimate, vec![]);
+
+        let station_km = Vector3::new(6_378.0, 0.0, 0.0);
+        let range_km = |sc: &Spacecraft| (sc.orbit.radius_km - station_km).norm();
+        for _ in 0..90 {
+            let real = Vector1::new(range_km(&truth.for_duration(1.minutes()).unwrap()));
+            let nominal = prop.for_duration(1.minutes()).unwrap();
+            let rho_km = nominal.orbit.radius_km - station_km;
+            let mut h_tilde = OMatrix::<f64, Const<1>, ScSize>::zeros();
+            for j in 0..3 {
+                h_tilde[(0, j)] = rho_km[j] / rho_km.norm();
+            }
+            kf.update_h_tilde(h_tilde);
+            kf.measurement_update(
+                nominal,
+                &real,
+                &Vector1::new(range_km(&nominal)),
+                OMatrix::<f64, Const<1>, Const<1>>::new(1e-8),
+                None,
+            )
+            .unwrap();
+            prop.state.reset_stm();
+        }
+
+        // The leak is only observable through its effect on the orbit, i.e. through the STM of the extra state
+        let last = kf.history.last().unwrap();
+        println!("{last}");
+        let leak_est = last.param_value(&leak).unwrap();
+        assert!(
+            (leak_est - leak_n).abs() < 1e-4,
+            "leak estimated at {leak_est} N"
+        );
+        assert!((last.state().orbit.radius_km - truth.state.orbit.radius_km).norm() < 1e-2);
+    }
+}
@@ -23,8 +23,10 @@ pub use crate::od::estimate::{Estimate, KfEstimate, Residual};
 use crate::od::process::ResidRejectCrit;
 pub use crate::od::snc::SNC;
 use crate::od::{Filter, ODDynamicsSnafu, ODError, State};
+use crate::profiling::{self, Subsystem};
 pub use crate::time::{Epoch, Unit};
 use snafu::prelude::*;
+use tracing::{debug, debug_span, info};
 
 /// Defines both a Classical and an Extended Kalman filter (CKF and EKF)
 /// T: Type of state
@@ -59,7 +61,7 @@ where
     pub ekf: bool,
     h_tilde: OMatrix<f64, M, <T as State>::Size>,
     h_tilde_updated: bool,
-    prev_used_snc: usize,
+    pub(crate) prev_used_snc: usize,
 }
 
 impl<T, A, M> KF<T, A, M>
@@ -201,6 +203,7 @@ where
     ///
     /// May return a FilterError if the STM was not updated.
     fn time_update(&mut self, nominal_state: T) -> Result<Self::Estimate, ODError> {
+        let _scope = profiling::scope(Subsystem::MeasurementUpdate, || "time update".to_string());
         let stm = nominal_state.stm().context(ODDynamicsSnafu)?;
         let mut covar_bar = stm * self.prev_estimate.covar * stm.transpose();
 
@@ -286,6 +289,7 @@ where
         let stm = nominal_state.stm().context(ODDynamicsSnafu)?;
 
         let epoch = nominal_state.epoch();
+        let _span = debug_span!("measurement_update", %epoch).entered();
 
         let covar_bar = stm * self.prev_estimate.covar * stm.transpose();
 
@@ -315,6 +319,11 @@ where
 
         if let Some(resid_reject) = resid_rejection {
             if ratio.abs() > resid_reject.num_sigmas {
+                debug!(
+                    ratio,
+                    num_sigmas = resid_reject.num_sigmas,
+                    "measurement rejected"
+                );
                 // Reject this whole measurement and perform only a time update
                 let pred_est = self.time_update(nominal_state)?;
                 return Ok((
@@ -331,7 +340,7 @@ where
         // no difference in the truth and sim.
         let mut innovation_covar = h_p_ht + &s_k;
         if !innovation_covar.try_inverse_mut() {
-            return Err(ODError::SingularKalmanGain);
+            return Err(ODError::SingularKalmanGain { epoch });
         }
 
         let gain = covar_bar * h_tilde_t * &innovation_covar;
@@ -370,6 +379,14 @@ where
             predicted: false,
         };
 
+        debug!(
+            ratio,
+            prefit_norm = res.prefit.norm(),
+            postfit_norm = res.postfit.norm(),
+            ekf = self.ekf,
+            "measurement accepted"
+        );
+
         self.h_tilde_updated = false;
         self.prev_estimate = estimate;
         // Update the prev epoch for all SNCs
@@ -73,3 +73,25 @@ where
         1e-3
     }
 }
+
+/// Owned ground stations evaluate like their references, e.g. to use them in a [crate::md::CompositeEvent].
+impl<S: Interpolatable> EventEvaluator<S> for GroundStation
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    fn eval(&self, rx_gs_frame: &S, almanac: Arc<Almanac>) -> Result<f64, EventError> {
+        <&GroundStation as EventEvaluator<S>>::eval(&self, rx_gs_frame, almanac)
+    }
+
+    fn eval_string(&self, state: &S, almanac: Arc<Almanac>) -> Result<String, EventError> {
+        <&GroundStation as EventEvaluator<S>>::eval_string(&self, state, almanac)
+    }
+
+    fn epoch_precision(&self) -> Duration {
+        <&GroundStation as EventEvaluator<S>>::epoch_precision(&self)
+    }
+
+    fn value_precision(&self) -> f64 {
+        <&GroundStation as EventEvaluator<S>>::value_precision(&self)
+    }
+}
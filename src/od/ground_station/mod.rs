@@ -26,7 +26,7 @@ use snafu::ensure;
 use super::msr::MeasurementType;
 use super::noise::StochasticNoise;
 use super::{ODAlmanacSnafu, ODError, ODTrajSnafu, TrackingDevice};
-use crate::io::ConfigRepr;
+use crate::io::{maybe_duration_from_str, maybe_duration_to_str, ConfigRepr};
 use crate::od::NoiseNotConfiguredSnafu;
 use crate::time::Epoch;
 use hifitime::Duration;
@@ -53,11 +53,19 @@ pub struct GroundStation {
     pub frame: Frame,
     pub measurement_types: IndexSet<MeasurementType>,
     /// Duration needed to generate a measurement (if unset, it is assumed to be instantaneous)
+    #[serde(
+        default,
+        serialize_with = "maybe_duration_to_str",
+        deserialize_with = "maybe_duration_from_str"
+    )]
     pub integration_time: Option<Duration>,
     /// Whether to correct for light travel time
+    #[serde(default)]
     pub light_time_correction: bool,
     /// Noise on the timestamp of the measurement
+    #[serde(default)]
     pub timestamp_noise_s: Option<StochasticNoise>,
+    #[serde(default)]
     pub stochastic_noises: Option<IndexMap<MeasurementType, StochasticNoise>>,
 }
 
@@ -370,4 +378,80 @@ mod gs_ut {
         let reser = serde_yml::to_string(&expected).unwrap();
         dbg!(reser);
     }
+
+    #[test]
+    fn serde_round_trip() {
+        use crate::od::noise::WhiteNoise;
+        use hifitime::TimeUnits;
+
+        let mut gs = GroundStation::from_point(
+            "Round trip".to_string(),
+            -35.398333,
+            148.981944,
+            0.691750,
+            IAU_EARTH_FRAME,
+        )
+        .with_msr_type(
+            MeasurementType::Range,
+            StochasticNoise {
+                white_noise: Some(WhiteNoise {
+                    mean: 1e-3,
+                    sigma: 5e-3,
+                }),
+                bias: Some(GaussMarkov::new(1.5.hours(), 5e-3).unwrap()),
+            },
+        )
+        .with_msr_type(
+            MeasurementType::Doppler,
+            StochasticNoise {
+                white_noise: Some(WhiteNoise {
+                    mean: 0.0,
+                    sigma: 5e-7,
+                }),
+                bias: None,
+            },
+        )
+        .with_integration_time(Some(1.minutes() + 3.5.seconds()));
+        gs.elevation_mask_deg = 7.5;
+        gs.light_time_correction = true;
+        gs.timestamp_noise_s = Some(StochasticNoise {
+            white_noise: Some(WhiteNoise {
+                mean: 0.0,
+                sigma: 1e-6,
+            }),
+            bias: None,
+        });
+
+        let yaml = serde_yml::to_string(&gs).unwrap();
+        println!("{yaml}");
+        assert_eq!(serde_yml::from_str::<GroundStation>(&yaml).unwrap(), gs);
+
+        let json = serde_json::to_string(&gs).unwrap();
+        assert_eq!(serde_json::from_str::<GroundStation>(&json).unwrap(), gs);
+
+        // The optional settings may be omitted, but invalid durations are rejected.
+        let minimal = r#"
+name: Minimal
+elevation_mask_deg: 5.0
+latitude_deg: 2.3522
+longitude_deg: 48.8566
+height_km: 0.4
+frame:
+  ephemeris_id: 399
+  orientation_id: 399
+  mu_km3_s2: null
+  shape: null
+measurement_types:
+  - range_km
+"#;
+        let gs = serde_yml::from_str::<GroundStation>(minimal).unwrap();
+        assert_eq!(gs.integration_time, None);
+        assert!(!gs.light_time_correction);
+        assert!(gs.stochastic_noises.is_none());
+
+        assert!(serde_yml::from_str::<GroundStation>(&format!(
+            "{minimal}integration_time: one minute\n"
+        ))
+        .is_err());
+    }
 }
@@ -0,0 +1,487 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::estimate::KfEstimate;
+use super::filter::kalman::KF;
+use super::msr::sensitivity::TrackerSensitivity;
+use super::simulator::{TrackingArcSim, TrkConfig};
+use super::snc::SNC3;
+use super::{Filter, GroundStation, ODConfigSnafu, ODError, ODPropSnafu, TrackingDevice};
+use crate::cosmic::AstroError;
+use crate::dynamics::SpacecraftDynamics;
+use crate::io::watermark::pq_writer;
+use crate::linalg::{Const, Matrix3, Vector3};
+use crate::md::prelude::Traj;
+use crate::md::StateParameter;
+use crate::propagators::Propagator;
+use crate::time::{Duration, Epoch, TimeSeries};
+use crate::{Spacecraft, State};
+use anise::almanac::Almanac;
+use arrow::array::{Array, BooleanBuilder, Float64Builder, StringBuilder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use indexmap::IndexSet;
+use parquet::arrow::ArrowWriter;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use typed_builder::TypedBuilder;
+
+/// Impulsive maneuver of a linear covariance analysis, with its execution errors.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LinCovManeuver {
+    pub epoch: Epoch,
+    /// Delta-v vector in the inertial frame of the spacecraft, in km/s
+    pub dv_km_s: Vector3<f64>,
+    /// 1-sigma magnitude error as a fraction of the delta-v magnitude (e.g. 0.01 for 1%)
+    pub magnitude_sigma: f64,
+    /// 1-sigma pointing error, in degrees
+    pub pointing_sigma_deg: f64,
+}
+
+impl LinCovManeuver {
+    /// Returns the covariance of the executed delta-v, in km^2/s^2: the magnitude error is along the delta-v direction,
+    /// and the pointing error is perpendicular to it.
+    pub fn execution_covar(&self) -> Matrix3<f64> {
+        let dv_mag = self.dv_km_s.norm();
+        if dv_mag < f64::EPSILON {
+            return Matrix3::zeros();
+        }
+        let dv_hat = self.dv_km_s / dv_mag;
+        let along = dv_hat * dv_hat.transpose();
+
+        (self.magnitude_sigma * dv_mag).powi(2) * along
+            + (self.pointing_sigma_deg.to_radians() * dv_mag).powi(2)
+                * (Matrix3::identity() - along)
+    }
+}
+
+/// Linear covariance analysis: maps the covariance of the initial estimate through the state transition matrix of the
+/// nominal trajectory, with measurement updates from the tracking devices, process noise, and maneuver execution errors.
+///
+/// No state is sampled, so the dispersions are computed with a single propagation instead of the many of a Monte Carlo.
+/// The measurement updates use the same Kalman filter as the orbit determination process, with zero residuals.
+#[derive(Clone, Debug, TypedBuilder)]
+#[builder(doc)]
+pub struct LinCov {
+    /// Nominal initial state and its covariance
+    pub initial_estimate: KfEstimate<Spacecraft>,
+    /// Tracking devices providing the measurement updates, if any
+    #[builder(default)]
+    pub devices: BTreeMap<String, GroundStation>,
+    /// Tracking configuration of each device
+    #[builder(default)]
+    pub configs: BTreeMap<String, TrkConfig>,
+    /// State noise compensations, ordered chronologically
+    #[builder(default)]
+    pub process_noise: Vec<SNC3>,
+    #[builder(default)]
+    pub maneuvers: Vec<LinCovManeuver>,
+    /// Time step of the covariance timeline between measurement updates
+    pub step: Duration,
+}
+
+impl LinCov {
+    /// Runs the linear covariance analysis until the end epoch.
+    pub fn run(
+        &self,
+        prop: &Propagator<SpacecraftDynamics>,
+        end_epoch: Epoch,
+        almanac: Arc<Almanac>,
+    ) -> Result<LinCovResults, ODError> {
+        let start_epoch = self.initial_estimate.nominal_state.epoch();
+        let mut maneuvers = self
+            .maneuvers
+            .iter()
+            .filter(|mnvr| mnvr.epoch >= start_epoch && mnvr.epoch <= end_epoch)
+            .copied()
+            .collect::<Vec<LinCovManeuver>>();
+        maneuvers.sort_by_key(|mnvr| mnvr.epoch);
+
+        // Schedule the measurements from the nominal trajectory.
+        let mut schedule = BTreeMap::new();
+        if !self.devices.is_empty() {
+            let traj = self.nominal_traj(prop, &maneuvers, end_epoch, almanac.clone())?;
+            let mut arc_sim = TrackingArcSim::<Spacecraft, GroundStation>::with_seed(
+                self.devices.clone(),
+                traj,
+                self.configs.clone(),
+                0,
+            )
+            .context(ODConfigSnafu)?;
+            let arc = arc_sim
+                .build_schedule(almanac.clone())
+                .and_then(|_| arc_sim.generate_measurements(almanac.clone()))
+                .map_err(|e| ODError::MeasurementSimError {
+                    details: e.to_string(),
+                })?;
+            for (epoch, msr) in arc.measurements {
+                schedule.insert(epoch, msr.tracker);
+            }
+        }
+
+        let mut epochs = TimeSeries::inclusive(start_epoch, end_epoch, self.step)
+            .chain(schedule.keys().copied())
+            .chain(maneuvers.iter().map(|mnvr| mnvr.epoch))
+            .chain([end_epoch])
+            .filter(|epoch| *epoch > start_epoch && *epoch <= end_epoch)
+            .collect::<Vec<Epoch>>();
+        epochs.sort();
+        epochs.dedup();
+
+        let mut kf = if self.process_noise.is_empty() {
+            KF::<Spacecraft, Const<3>, Const<2>>::no_snc(self.initial_estimate)
+        } else {
+            KF::<Spacecraft, Const<3>, Const<2>>::with_sncs(
+                self.initial_estimate,
+                self.process_noise.clone(),
+            )
+        };
+
+        let mut devices = self.devices.clone();
+        let mut state = self.initial_estimate.nominal_state.with_stm();
+        let mut estimates = vec![self.initial_estimate];
+        let mut updates = Vec::new();
+        let mut next_mnvr = 0;
+
+        info!(
+            "LinCov from {start_epoch} to {end_epoch}: {} measurements and {} maneuvers",
+            schedule.len(),
+            maneuvers.len()
+        );
+
+        for epoch in epochs {
+            state = prop
+                .with(state, almanac.clone())
+                .quiet()
+                .until_epoch(epoch)
+                .context(ODPropSnafu)?;
+
+            let mut estimate = None;
+            if let Some(tracker) = schedule.get(&epoch) {
+                if let Some(device) = devices.get_mut(tracker) {
+                    if let Some(msr) = device.measure_instantaneous(state, None, almanac.clone())? {
+                        let msr_types = device.measurement_types().clone();
+                        // Process the measurement types two by two, like the orbit determination process.
+                        for window in msr_types.iter().copied().collect::<Vec<_>>().chunks(2) {
+                            let cur_msr_types = window.iter().copied().collect::<IndexSet<_>>();
+                            let h_tilde = device.h_tilde::<Const<2>>(
+                                &msr,
+                                &cur_msr_types,
+                                &state,
+                                almanac.clone(),
+                            )?;
+                            kf.update_h_tilde(h_tilde);
+                            // The covariance update does not depend on the residual.
+                            let obs = msr.observation::<Const<2>>(&cur_msr_types);
+                            let (est, _) = kf.measurement_update(
+                                state,
+                                &obs,
+                                &obs,
+                                device.measurement_covar_matrix(&cur_msr_types, epoch)?,
+                                None,
+                            )?;
+                            estimate = Some(est);
+                        }
+                        updates.push((epoch, tracker.clone()));
+                    }
+                }
+            }
+
+            let mut estimate = match estimate {
+                Some(est) => est,
+                None => kf.time_update(state)?,
+            };
+
+            while next_mnvr < maneuvers.len() && maneuvers[next_mnvr].epoch == epoch {
+                let mnvr = maneuvers[next_mnvr];
+                debug!("LinCov maneuver of {} km/s @ {epoch}", mnvr.dv_km_s);
+                state.orbit.velocity_km_s += mnvr.dv_km_s;
+                estimate.nominal_state = state;
+                let mut vel_covar = estimate.covar.fixed_view_mut::<3, 3>(3, 3);
+                vel_covar += mnvr.execution_covar();
+                kf.set_previous_estimate(&estimate);
+                next_mnvr += 1;
+            }
+
+            state.reset_stm();
+            estimates.push(estimate);
+        }
+
+        Ok(LinCovResults { estimates, updates })
+    }
+
+    /// Propagates the nominal trajectory, with the maneuvers, to schedule the measurements.
+    fn nominal_traj(
+        &self,
+        prop: &Propagator<SpacecraftDynamics>,
+        maneuvers: &[LinCovManeuver],
+        end_epoch: Epoch,
+        almanac: Arc<Almanac>,
+    ) -> Result<Traj<Spacecraft>, ODError> {
+        let mut state = self.initial_estimate.nominal_state;
+        let mut traj = Traj::new();
+        for epoch in maneuvers.iter().map(|mnvr| mnvr.epoch).chain([end_epoch]) {
+            let (end_state, segment) = prop
+                .with(state, almanac.clone())
+                .quiet()
+                .until_epoch_with_traj(epoch)
+                .context(ODPropSnafu)?;
+            traj.states.extend(segment.states);
+            state = end_state;
+            for mnvr in maneuvers.iter().filter(|mnvr| mnvr.epoch == epoch) {
+                state.orbit.velocity_km_s += mnvr.dv_km_s;
+            }
+        }
+        traj.finalize();
+        Ok(traj)
+    }
+}
+
+/// Covariance timeline of a linear covariance analysis.
+#[derive(Clone, Debug)]
+pub struct LinCovResults {
+    /// Estimates from the initial epoch, at each time step, measurement update, and maneuver
+    pub estimates: Vec<KfEstimate<Spacecraft>>,
+    /// Epochs of the measurement updates, with the name of their tracker
+    pub updates: Vec<(Epoch, String)>,
+}
+
+impl LinCovResults {
+    /// Returns the latest estimate at or before the provided epoch
+    pub fn at(&self, epoch: Epoch) -> Option<&KfEstimate<Spacecraft>> {
+        self.estimates
+            .iter()
+            .rev()
+            .find(|est| est.nominal_state.epoch() <= epoch)
+    }
+
+    /// Returns the final estimate
+    pub fn last(&self) -> &KfEstimate<Spacecraft> {
+        self.estimates.last().unwrap()
+    }
+
+    /// Returns the 1-sigma dispersion of the provided parameter at each epoch of the timeline
+    pub fn sigma_timeline(&self, param: StateParameter) -> Result<Vec<(Epoch, f64)>, AstroError> {
+        self.estimates
+            .iter()
+            .map(|est| Ok((est.nominal_state.epoch(), est.sigma_for(param)?)))
+            .collect()
+    }
+
+    /// Exports the 1-sigma dispersions of the provided parameters at each epoch of the timeline to a parquet file.
+    pub fn to_parquet<P: AsRef<Path>>(
+        &self,
+        path: P,
+        params: &[StateParameter],
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let path_buf = path.as_ref().to_path_buf();
+
+        let mut fields = vec![
+            Field::new("Epoch (UTC)", DataType::Utf8, false),
+            Field::new("Measurement update", DataType::Boolean, false),
+        ];
+        for param in params {
+            fields.push(Field::new(
+                format!("Sigma {param} ({})", param.unit()),
+                DataType::Float64,
+                false,
+            ));
+        }
+        let schema = Arc::new(Schema::new(fields));
+
+        let mut epochs = StringBuilder::new();
+        let mut updated = BooleanBuilder::new();
+        for est in &self.estimates {
+            epochs.append_value(format!("{}", est.nominal_state.epoch()));
+            updated.append_value(!est.predicted);
+        }
+        let mut record: Vec<Arc<dyn Array>> =
+            vec![Arc::new(epochs.finish()), Arc::new(updated.finish())];
+        for param in params {
+            let mut sigmas = Float64Builder::new();
+            for (_, sigma) in self.sigma_timeline(*param)? {
+                sigmas.append_value(sigma);
+            }
+            record.push(Arc::new(sigmas.finish()));
+        }
+
+        let mut metadata = HashMap::new();
+        metadata.insert(
+            "Purpose".to_string(),
+            "Linear covariance analysis".to_string(),
+        );
+        metadata.insert(
+            "Measurement updates".to_string(),
+            format!("{}", self.updates.len()),
+        );
+
+        let file = File::create(&path_buf)?;
+        let mut writer = ArrowWriter::try_new(file, schema.clone(), pq_writer(Some(metadata)))?;
+        writer.write(&RecordBatch::try_new(schema, record)?)?;
+        writer.close()?;
+
+        info!("LinCov results written to {}", path_buf.display());
+
+        Ok(path_buf)
+    }
+}
+
+impl fmt::Display for LinCovResults {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let first = self.estimates.first().unwrap();
+        let last = self.last();
+        write!(
+            f,
+            "LinCov from {} to {} with {} measurement updates - final 1-sigma: {:.3} km, {:.3} m/s",
+            first.nominal_state.epoch(),
+            last.nominal_state.epoch(),
+            self.updates.len(),
+            last.covar.fixed_view::<3, 3>(0, 0).trace().sqrt(),
+            last.covar.fixed_view::<3, 3>(3, 3).trace().sqrt() * 1e3,
+        )
+    }
+}
+
+#[cfg(test)]
+mod ut_lincov {
+    use super::*;
+    use crate::dynamics::OrbitalDynamics;
+    use crate::fixtures;
+    use crate::linalg::SVector;
+    use crate::mc::MonteCarlo;
+    use crate::time::Unit;
+
+    #[test]
+    fn lincov_matches_monte_carlo() {
+        let epoch = fixtures::epoch();
+        let nominal = Spacecraft::from(fixtures::keplerian(7_000.0, 0.01, 28.5, 10.0, 20.0, 30.0));
+        let almanac = fixtures::almanac();
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let end_epoch = epoch + Unit::Hour * 2;
+
+        let initial_estimate = KfEstimate::from_diag(
+            nominal,
+            SVector::<f64, 9>::from_iterator([
+                0.1_f64.powi(2),
+                0.1_f64.powi(2),
+                0.1_f64.powi(2),
+                1e-4_f64.powi(2),
+                1e-4_f64.powi(2),
+                1e-4_f64.powi(2),
+                0.0,
+                0.0,
+                0.0,
+            ]),
+        );
+
+        let lincov = LinCov::builder()
+            .initial_estimate(initial_estimate)
+            .step(Unit::Minute * 10)
+            .build();
+        let results = lincov.run(&prop, end_epoch, almanac.clone()).unwrap();
+        println!("{results}");
+        assert!(results.updates.is_empty());
+        assert_eq!(results.estimates.len(), 13);
+        assert_eq!(results.last().nominal_state.epoch(), end_epoch);
+
+        // Compare with the dispersions of a Monte Carlo
+        let mc = MonteCarlo::new(
+            nominal,
+            initial_estimate.to_random_variable().unwrap(),
+            "lincov".to_string(),
+            Some(0),
+        );
+        let runs = mc.run_until_epoch(prop.clone(), almanac.clone(), end_epoch, 250);
+        let final_x = runs
+            .runs
+            .iter()
+            .map(|run| run.result.as_ref().unwrap().state.orbit.radius_km.x)
+            .collect::<Vec<f64>>();
+        let mean = final_x.iter().sum::<f64>() / final_x.len() as f64;
+        let mc_sigma = (final_x.iter().map(|x| (x - mean).powi(2)).sum::<f64>()
+            / (final_x.len() - 1) as f64)
+            .sqrt();
+        let lincov_sigma = results.last().sigma_for(StateParameter::X).unwrap();
+        println!("sigma X: LinCov {lincov_sigma:.6} km - MC {mc_sigma:.6} km");
+        assert!((lincov_sigma - mc_sigma).abs() / mc_sigma < 0.1);
+
+        // A maneuver with execution errors increases the dispersions along its direction
+        let mnvr = LinCovManeuver {
+            epoch: epoch + Unit::Hour * 1,
+            dv_km_s: nominal.orbit.velocity_km_s.normalize() * 0.1,
+            magnitude_sigma: 0.01,
+            pointing_sigma_deg: 1.0,
+        };
+        let exec = mnvr.execution_covar();
+        let dv_hat = mnvr.dv_km_s.normalize();
+        assert!(((dv_hat.transpose() * exec * dv_hat)[0].sqrt() - 1e-3).abs() < 1e-12);
+        assert!(
+            (exec.trace() - (1e-3_f64.powi(2) + 2.0 * (0.1 * 1.0_f64.to_radians()).powi(2))).abs()
+                < 1e-15
+        );
+
+        let with_mnvr = LinCov::builder()
+            .initial_estimate(initial_estimate)
+            .maneuvers(vec![mnvr])
+            .step(Unit::Minute * 10)
+            .build()
+            .run(&prop, end_epoch, almanac)
+            .unwrap();
+        // Maneuver epoch is on the time step grid, so the timeline has the same epochs
+        assert_eq!(with_mnvr.estimates.len(), results.estimates.len());
+        let before = results.at(mnvr.epoch).unwrap();
+        let after = with_mnvr.at(mnvr.epoch).unwrap();
+        assert!(
+            (after.covar.fixed_view::<3, 3>(3, 3) - before.covar.fixed_view::<3, 3>(3, 3) - exec)
+                .norm()
+                < 1e-15
+        );
+        assert!(
+            with_mnvr.last().sigma_for(StateParameter::Rmag).unwrap()
+                > results.last().sigma_for(StateParameter::Rmag).unwrap()
+        );
+        // The nominal trajectory includes the maneuver
+        assert!(
+            (with_mnvr.last().nominal_state.orbit.velocity_km_s
+                - results.last().nominal_state.orbit.velocity_km_s)
+                .norm()
+                > 0.01
+        );
+
+        let path = std::env::temp_dir().join("nyx_ut_lincov.parquet");
+        assert_eq!(
+            with_mnvr
+                .to_parquet(&path, &[StateParameter::X, StateParameter::VX])
+                .unwrap(),
+            path
+        );
+        assert_eq!(
+            with_mnvr
+                .sigma_timeline(StateParameter::Rmag)
+                .unwrap()
+                .len(),
+            13
+        );
+    }
+}
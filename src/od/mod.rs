@@ -52,6 +52,8 @@ pub mod simulator;
 /// Provides the interfaces to the orbit determination process
 pub mod process;
 
+pub mod lincov;
+
 pub use simulator::TrackingDevice;
 
 /// Provides all state noise compensation functionality
@@ -77,11 +79,23 @@ pub type SpacecraftODProcessSeq<'a> = self::process::ODProcess<
     GroundStation,
 >;
 
+/// A helper type for spacecraft orbit determination whose solve-for parameters are chosen at runtime, cf. [filter::dynamic::DynamicKF]
+pub type DynamicODProcess<'a> = self::process::ODProcess<
+    'a,
+    crate::md::prelude::SpacecraftDynamics,
+    nalgebra::Const<2>,
+    nalgebra::Const<3>,
+    filter::dynamic::DynamicKF<nalgebra::Const<2>>,
+    GroundStation,
+>;
+
 #[allow(unused_imports)]
 pub mod prelude {
     pub use super::estimate::*;
+    pub use super::filter::dynamic::DynamicKF;
     pub use super::filter::kalman::*;
     pub use super::ground_station::*;
+    pub use super::lincov::*;
     pub use super::msr::*;
     pub use super::noise::{GaussMarkov, StochasticNoise, WhiteNoise};
     pub use super::process::*;
@@ -104,16 +118,30 @@ pub enum ODError {
     TooFewMeasurements { need: usize, action: &'static str },
     #[snafu(display("invalid step size: {step}"))]
     StepSizeError { step: Duration },
-    #[snafu(display("filter iteration did not converge in {loops} iterations"))]
-    Diverged { loops: usize },
+    #[snafu(display(
+        "filter iterations diverged {loops} times in a row: residual ratio RMS {residual_rms} (best {best_rms})"
+    ))]
+    Diverged {
+        loops: usize,
+        residual_rms: f64,
+        best_rms: f64,
+    },
+    #[snafu(display(
+        "filter iterations did not converge in {iterations} iterations: residual ratio RMS {residual_rms} (best {best_rms})"
+    ))]
+    NotConverged {
+        iterations: usize,
+        residual_rms: f64,
+        best_rms: f64,
+    },
     #[snafu(display("STM is singular"))]
     SingularStateTransitionMatrix,
     #[snafu(display("invalid measurement @ {epoch} = {val}"))]
     InvalidMeasurement { epoch: Epoch, val: f64 },
     #[snafu(display("sensitivity matrix must be updated before this call"))]
     SensitivityNotUpdated,
-    #[snafu(display("Kalman gain is singular"))]
-    SingularKalmanGain,
+    #[snafu(display("Kalman gain is singular at {epoch}"))]
+    SingularKalmanGain { epoch: Epoch },
     #[snafu(display("noise matrix is singular"))]
     SingularNoiseRk,
     #[snafu(display("{kind} noise not configured"))]
@@ -140,4 +168,12 @@ pub enum ODError {
     },
     #[snafu(display("not enough residuals to {action}"))]
     ODNoResiduals { action: &'static str },
+    #[snafu(display("invalid solve-for parameter {param}"))]
+    InvalidSolveFor { param: String },
+    #[snafu(display("{action} expected {expected} solve-for values but got {got}"))]
+    SolveForMismatch {
+        expected: usize,
+        got: usize,
+        action: &'static str,
+    },
 }
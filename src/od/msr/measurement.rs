@@ -20,10 +20,11 @@ use super::MeasurementType;
 use hifitime::Epoch;
 use indexmap::{IndexMap, IndexSet};
 use nalgebra::{allocator::Allocator, DefaultAllocator, DimName, OVector};
+use serde_derive::{Deserialize, Serialize};
 use std::fmt;
 
 /// A type-agnostic simultaneous measurement storage structure. Allows storing any number of simultaneous measurement of a given taker.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Measurement {
     /// Tracker alias which made this measurement
     pub tracker: String,
@@ -15,7 +15,7 @@
     You should have received a copy of the GNU Affero General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
-use crate::io::watermark::pq_writer;
+use crate::io::watermark::pq_writer_with;
 use crate::io::{ArrowSnafu, InputOutputError, MissingDataSnafu, ParquetSnafu, StdIOSnafu};
 use crate::io::{EmptyDatasetSnafu, ExportCfg};
 use crate::od::msr::{Measurement, MeasurementType};
@@ -219,21 +219,17 @@ impl TrackingDataArc {
         self.to_parquet(path, ExportCfg::default())
     }
 
-    /// Store this tracking arc to a parquet file, with optional metadata and a timestamp appended to the filename.
-    pub fn to_parquet<P: AsRef<Path>>(
-        &self,
-        path: P,
-        cfg: ExportCfg,
-    ) -> Result<PathBuf, Box<dyn Error>> {
+    /// Builds an in-memory Arrow record batch of this tracking arc, with the same columns and schema metadata as [Self::to_parquet].
+    ///
+    /// This allows handing the measurements to other Arrow consumers (e.g. pandas or polars) without a file round trip.
+    pub fn to_record_batch(&self, cfg: ExportCfg) -> Result<RecordBatch, Box<dyn Error>> {
         ensure!(
             !self.is_empty(),
             EmptyDatasetSnafu {
-                action: "tracking data arc to parquet"
+                action: "tracking data arc to record batch"
             }
         );
 
-        let path_buf = cfg.actual_path(path);
-
         if cfg.step.is_some() {
             warn!("The `step` parameter in the export is not supported for tracking arcs.");
         }
@@ -256,50 +252,56 @@ impl TrackingDataArc {
 
         hdrs.append(&mut msr_fields);
 
+        let mut metadata = HashMap::new();
+        metadata.insert("Purpose".to_string(), "Tracking Arc Data".to_string());
+        if let Some(add_meta) = cfg.metadata {
+            for (k, v) in add_meta {
+                metadata.insert(k, v);
+            }
+        }
+
         // Build the schema
-        let schema = Arc::new(Schema::new(hdrs));
+        let schema = Arc::new(Schema::new_with_metadata(hdrs, metadata));
         let mut record: Vec<Arc<dyn Array>> = Vec::new();
 
-        // Build the measurement iterator
+        // Select the measurements without copying them
+        let measurements = if cfg.start_epoch.is_some() || cfg.end_epoch.is_some() {
+            let start = cfg
+                .start_epoch
+                .unwrap_or_else(|| self.start_epoch().unwrap());
+            let end = cfg.end_epoch.unwrap_or_else(|| self.end_epoch().unwrap());
 
-        let measurements =
-            if cfg.start_epoch.is_some() || cfg.end_epoch.is_some() || cfg.step.is_some() {
-                let start = cfg
-                    .start_epoch
-                    .unwrap_or_else(|| self.start_epoch().unwrap());
-                let end = cfg.end_epoch.unwrap_or_else(|| self.end_epoch().unwrap());
+            info!("Exporting measurements from {start} to {end}.");
 
-                info!("Exporting measurements from {start} to {end}.");
-
-                self.measurements
-                    .range(start..end)
-                    .map(|(k, v)| (*k, v.clone()))
-                    .collect()
-            } else {
-                self.measurements.clone()
-            };
+            self.measurements
+                .range(start..end)
+                .map(|(_, msr)| msr)
+                .collect::<Vec<_>>()
+        } else {
+            self.measurements.values().collect::<Vec<_>>()
+        };
 
         // Build all of the records
 
         // Epochs
         let mut utc_epoch = StringBuilder::new();
-        for epoch in measurements.keys() {
-            utc_epoch.append_value(epoch.to_time_scale(TimeScale::UTC).to_isoformat());
+        for m in &measurements {
+            utc_epoch.append_value(m.epoch.to_time_scale(TimeScale::UTC).to_isoformat());
         }
         record.push(Arc::new(utc_epoch.finish()));
 
         // Device names
         let mut device_names = StringBuilder::new();
-        for m in measurements.values() {
-            device_names.append_value(m.tracker.clone());
+        for m in &measurements {
+            device_names.append_value(&m.tracker);
         }
         record.push(Arc::new(device_names.finish()));
 
         // Measurement data, column by column
         for msr_type in msr_types {
-            let mut data_builder = Float64Builder::new();
+            let mut data_builder = Float64Builder::with_capacity(measurements.len());
 
-            for m in measurements.values() {
+            for m in &measurements {
                 match m.data.get(&msr_type) {
                     Some(value) => data_builder.append_value(*value),
                     None => data_builder.append_null(),
@@ -308,22 +310,30 @@ impl TrackingDataArc {
             record.push(Arc::new(data_builder.finish()));
         }
 
-        // Serialize all of the devices and add that to the parquet file too.
-        let mut metadata = HashMap::new();
-        metadata.insert("Purpose".to_string(), "Tracking Arc Data".to_string());
-        if let Some(add_meta) = cfg.metadata {
-            for (k, v) in add_meta {
-                metadata.insert(k, v);
-            }
-        }
+        Ok(RecordBatch::try_new(schema, record)?)
+    }
+
+    /// Store this tracking arc to a parquet file, with optional metadata and a timestamp appended to the filename.
+    pub fn to_parquet<P: AsRef<Path>>(
+        &self,
+        path: P,
+        cfg: ExportCfg,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let path_buf = cfg.actual_path(path);
+        let pq_cfg = cfg.parquet;
+
+        let batch = self.to_record_batch(cfg)?;
 
-        let props = pq_writer(Some(metadata));
+        // The schema metadata includes the purpose and the additional metadata.
+        let props = Some(pq_writer_with(
+            Some(batch.schema().metadata().clone()),
+            &pq_cfg,
+        )?);
 
         let file = File::create(&path_buf)?;
 
-        let mut writer = ArrowWriter::try_new(file, schema.clone(), props).unwrap();
+        let mut writer = ArrowWriter::try_new(file, batch.schema(), props)?;
 
-        let batch = RecordBatch::try_new(schema, record)?;
         writer.write(&batch)?;
         writer.close()?;
 
@@ -142,6 +142,103 @@ impl TrackingDataArc {
         self
     }
 
+    /// Returns a new tracking arc that only contains the provided measurement types.
+    /// Measurements which have none of these types are removed.
+    pub fn filter_by_types(mut self, types: &IndexSet<MeasurementType>) -> Self {
+        self.measurements = self
+            .measurements
+            .into_iter()
+            .filter_map(|(epoch, mut msr)| {
+                msr.data.retain(|msr_type, _| types.contains(msr_type));
+                if msr.data.is_empty() {
+                    None
+                } else {
+                    Some((epoch, msr))
+                }
+            })
+            .collect::<BTreeMap<Epoch, Measurement>>();
+        self
+    }
+
+    /// Merges the measurements of another tracking arc into this one, e.g. to assemble tracking data from several providers.
+    ///
+    /// Measurements of the same tracker at the same epoch are combined. As this structure does not support concurrent
+    /// measurements from several trackers, a measurement of another tracker at an epoch already in this arc is ignored.
+    pub fn merge(mut self, other: Self) -> Self {
+        for (epoch, msr) in other.measurements {
+            match self.measurements.get_mut(&epoch) {
+                Some(existing) if existing.tracker == msr.tracker => {
+                    existing.data.extend(msr.data);
+                }
+                Some(existing) => {
+                    warn!(
+                        "ignoring measurement of {} @ {epoch}: {} already measured at that epoch",
+                        msr.tracker, existing.tracker
+                    );
+                }
+                None => {
+                    self.measurements.insert(epoch, msr);
+                }
+            }
+        }
+
+        self.source = match (self.source, other.source) {
+            (Some(src), Some(other_src)) if src != other_src => Some(format!("{src}, {other_src}")),
+            (src, other_src) => src.or(other_src),
+        };
+
+        self
+    }
+
+    /// Merges all of the provided tracking arcs into a single one, in order, cf. [TrackingDataArc::merge].
+    pub fn merge_all<I: IntoIterator<Item = Self>>(arcs: I) -> Self {
+        arcs.into_iter()
+            .reduce(|merged, arc| merged.merge(arc))
+            .unwrap_or_default()
+    }
+
+    /// Splits this tracking arc into consecutive arcs spanning at most the provided duration each, starting at the first measurement.
+    /// Spans without any measurement are skipped.
+    pub fn split_by_time_span(&self, span: Duration) -> Vec<Self> {
+        let mut arcs: Vec<Self> = Vec::new();
+        if self.is_empty() || span <= Duration::ZERO {
+            return arcs;
+        }
+
+        let start = self.start_epoch().unwrap();
+        let mut cur_span = None;
+        for (epoch, msr) in &self.measurements {
+            let span_num = ((*epoch - start).to_seconds() / span.to_seconds()).floor() as i64;
+            if cur_span != Some(span_num) {
+                cur_span = Some(span_num);
+                arcs.push(TrackingDataArc {
+                    source: self.source.clone(),
+                    ..Default::default()
+                });
+            }
+            arcs.last_mut()
+                .unwrap()
+                .measurements
+                .insert(*epoch, msr.clone());
+        }
+        arcs
+    }
+
+    /// Splits this tracking arc into one arc per tracker, keyed by the tracker alias.
+    pub fn split_by_tracker(&self) -> IndexMap<String, Self> {
+        let mut arcs = IndexMap::new();
+        for (epoch, msr) in &self.measurements {
+            arcs.entry(msr.tracker.clone())
+                .or_insert_with(|| TrackingDataArc {
+                    source: self.source.clone(),
+                    ..Default::default()
+                })
+                .measurements
+                .insert(*epoch, msr.clone());
+        }
+        arcs
+    }
+
     /// Downsamples the tracking data to a lower frequency using a simple moving average low-pass filter followed by decimation,
     /// returning new `TrackingDataArc` with downsampled measurements.
     ///
@@ -263,3 +360,69 @@ impl PartialEq for TrackingDataArc {
         self.measurements == other.measurements
     }
 }
+
+#[cfg(test)]
+mod ut_arc {
+    use super::{Measurement, MeasurementType, TrackingDataArc};
+    use hifitime::{Epoch, TimeUnits};
+    use indexmap::IndexSet;
+
+    fn arc(tracker: &str, source: &str, start: Epoch, count: i64) -> TrackingDataArc {
+        let mut arc = TrackingDataArc {
+            source: Some(source.to_string()),
+            ..Default::default()
+        };
+        for i in 0..count {
+            let epoch = start + (i * 10).minutes();
+            let msr = Measurement::new(tracker.to_string(), epoch)
+                .with(MeasurementType::Range, 1_000.0 + i as f64)
+                .with(MeasurementType::Doppler, 0.1 * i as f64);
+            arc.measurements.insert(epoch, msr);
+        }
+        arc
+    }
+
+    #[test]
+    fn merge_split_filter() {
+        let start = Epoch::from_gregorian_utc_at_midnight(2024, 1, 1);
+        let dss65 = arc("DSS-65", "dss65.parquet", start, 6);
+        let dss34 = arc("DSS-34", "dss34.tdm", start + 2.hours(), 6);
+
+        let merged = TrackingDataArc::merge_all([dss65.clone(), dss34.clone()]);
+        assert_eq!(merged.len(), 12);
+        assert_eq!(merged.source.as_deref(), Some("dss65.parquet, dss34.tdm"));
+        assert_eq!(merged.unique_aliases().len(), 2);
+
+        // Conflicting epochs of another tracker are ignored, and data of the same tracker is combined
+        let mut azel = arc("DSS-65", "dss65.parquet", start, 1);
+        azel.measurements.values_mut().for_each(|msr| {
+            msr.data.clear();
+            msr.push(MeasurementType::Azimuth, 12.0);
+        });
+        let merged = merged
+            .merge(arc("DSS-13", "dss13.parquet", start, 1))
+            .merge(azel);
+        assert_eq!(merged.len(), 12);
+        assert_eq!(merged.measurements[&start].tracker, "DSS-65");
+        assert_eq!(merged.measurements[&start].data.len(), 3);
+
+        // Splitting by tracker restores the original arcs
+        let by_tracker = merged.split_by_tracker();
+        assert_eq!(by_tracker.len(), 2);
+        assert_eq!(by_tracker["DSS-34"], dss34);
+        assert_eq!(by_tracker["DSS-34"].source, merged.source);
+
+        // The second hour has no measurements, so it is skipped
+        let by_span = merged.split_by_time_span(1.hours());
+        assert_eq!(by_span.len(), 2);
+        assert_eq!(by_span[0].unique_aliases().len(), 1);
+        assert_eq!(by_span[0].len(), 6);
+        assert_eq!(by_span[1], dss34);
+
+        let mut types = IndexSet::new();
+        types.insert(MeasurementType::Azimuth);
+        let azimuths = merged.filter_by_types(&types);
+        assert_eq!(azimuths.len(), 1);
+        assert_eq!(azimuths.unique_types().len(), 1);
+    }
+}
@@ -17,13 +17,13 @@
 */
 
 use crate::io::watermark::pq_writer;
+use crate::mc::stream_rng;
 use arrow::array::{ArrayRef, Float64Array, UInt32Array};
 use arrow::datatypes::{DataType, Field, Schema};
 use arrow::record_batch::RecordBatch;
 use hifitime::{Epoch, TimeSeries, TimeUnits};
 use parquet::arrow::ArrowWriter;
-use rand::{Rng, SeedableRng};
-use rand_pcg::Pcg64Mcg;
+use rand::Rng;
 use serde_derive::{Deserialize, Serialize};
 use std::error::Error;
 use std::fs::File;
@@ -151,7 +151,7 @@ impl StochasticNoise {
         let mut samples = Vec::with_capacity(capacity);
 
         for run in 0..num_runs {
-            let mut rng = Pcg64Mcg::from_entropy();
+            let mut rng = stream_rng(&format!("noise/run{run}"));
 
             let mut mdl = self;
             for epoch in TimeSeries::inclusive(start, end, step) {
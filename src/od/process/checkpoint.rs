@@ -0,0 +1,479 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{EkfTrigger, ODProcess};
+use crate::dynamics::SpacecraftDynamics;
+use crate::io::{ConfigError, ConfigRepr, SerializeSnafu, WriteSnafu};
+use crate::linalg::allocator::Allocator;
+use crate::linalg::{DefaultAllocator, DimName, OMatrix, OVector};
+use crate::od::estimate::{KfEstimate, Residual};
+use crate::od::filter::kalman::KF;
+use crate::od::msr::sensitivity::TrackerSensitivity;
+use crate::od::msr::MeasurementType;
+use crate::od::{Filter, ODConfigSnafu, ODError};
+use crate::propagators::PropCheckpoint;
+use crate::time::Epoch;
+use crate::{Spacecraft, State};
+use indexmap::IndexSet;
+use nalgebra::Const;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// A checkpoint of an orbit determination process, from which the processing of measurements may be resumed later
+/// with [ODProcess::resume], e.g. for a daily OD which picks up where the previous run ended.
+///
+/// It stores the navigation propagation, the state of the filter, of its process noise and of its EKF trigger, and the
+/// estimate and residual history.
+/// The OD process does not draw any random numbers, so there is no random generator state to store: the seeds of
+/// the tracking simulation are part of its own configuration.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(serialize = "S: Serialize", deserialize = "S: DeserializeOwned"))]
+pub struct ODCheckpoint<S: State>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    /// Checkpoint of the navigation propagator, whose state is the reference trajectory of the filter
+    pub propagation: PropCheckpoint<S>,
+    /// Previous estimate of the filter, from which the next time or measurement update is computed
+    pub estimate: EstimateRecord<S>,
+    /// Whether the filter was operating as an Extended Kalman Filter
+    pub extended: bool,
+    /// Epochs of each process noise of the filter, in the order of the process noises of the filter
+    pub process_noise: Vec<SncRecord>,
+    /// Index of the process noise used in the last time update of the filter
+    pub prev_used_snc: usize,
+    /// State of the trigger switching between the CKF and the EKF, if any
+    pub ekf_trigger: Option<EkfTrigger>,
+    /// Estimate history of the process
+    pub estimates: Vec<EstimateRecord<S>>,
+    /// Residual history of the process, aligned with the estimates (time updates have no residual)
+    pub residuals: Vec<Option<ResidualRecord>>,
+}
+
+/// A serializable Kalman filter estimate, whose vectors and matrices are stored in column-major order.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(serialize = "S: Serialize", deserialize = "S: DeserializeOwned"))]
+pub struct EstimateRecord<S: State>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    /// The estimated state
+    pub nominal_state: S,
+    /// The state deviation
+    pub state_deviation: Vec<f64>,
+    /// The covariance of this estimate
+    pub covar: Vec<f64>,
+    /// The predicted covariance of this estimate
+    pub covar_bar: Vec<f64>,
+    /// Whether or not this is a predicted estimate from a time update
+    pub predicted: bool,
+    /// The STM used to compute this estimate
+    pub stm: Vec<f64>,
+}
+
+/// The epochs tracked by a state noise compensation, which determine its decay and whether it is disabled.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SncRecord {
+    /// Initial epoch of the SNC, from which its decay is computed
+    pub init_epoch: Option<Epoch>,
+    /// Epoch of the previous SNC request, from which its disable time is computed
+    pub prev_epoch: Option<Epoch>,
+}
+
+/// A serializable residual, whose vectors hold one component per measurement type.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ResidualRecord {
+    /// Date time of this residual
+    pub epoch: Epoch,
+    /// The prefit residual in the units of the measurement type
+    pub prefit: Vec<f64>,
+    /// The postfit residual in the units of the measurement type
+    pub postfit: Vec<f64>,
+    /// The prefit residual ratio
+    pub ratio: f64,
+    /// The tracker measurement noise (variance)
+    pub tracker_msr_noise: Vec<f64>,
+    /// Whether or not this was rejected
+    pub rejected: bool,
+    /// Name of the tracker that caused this residual
+    pub tracker: Option<String>,
+    /// Measurement types used to compute this residual (in order)
+    pub msr_types: IndexSet<MeasurementType>,
+}
+
+impl<S: State> EstimateRecord<S>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+    <DefaultAllocator as Allocator<S::Size>>::Buffer<f64>: Copy,
+    <DefaultAllocator as Allocator<S::Size, S::Size>>::Buffer<f64>: Copy,
+{
+    /// Rebuilds the Kalman filter estimate of this record.
+    pub fn to_estimate(&self) -> Result<KfEstimate<S>, ConfigError> {
+        let size = S::Size::dim();
+        for (name, data, expected) in [
+            ("state deviation", &self.state_deviation, size),
+            ("covariance", &self.covar, size * size),
+            ("predicted covariance", &self.covar_bar, size * size),
+            ("STM", &self.stm, size * size),
+        ] {
+            if data.len() != expected {
+                return Err(ConfigError::InvalidConfig {
+                    msg: format!(
+                        "estimate {name} has {} components but the state requires {expected}",
+                        data.len()
+                    ),
+                });
+            }
+        }
+
+        Ok(KfEstimate {
+            nominal_state: self.nominal_state,
+            state_deviation: OVector::<f64, S::Size>::from_column_slice(&self.state_deviation),
+            covar: OMatrix::<f64, S::Size, S::Size>::from_column_slice(&self.covar),
+            covar_bar: OMatrix::<f64, S::Size, S::Size>::from_column_slice(&self.covar_bar),
+            predicted: self.predicted,
+            stm: OMatrix::<f64, S::Size, S::Size>::from_column_slice(&self.stm),
+        })
+    }
+}
+
+impl<S: State> From<&KfEstimate<S>> for EstimateRecord<S>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+    <DefaultAllocator as Allocator<S::Size>>::Buffer<f64>: Copy,
+    <DefaultAllocator as Allocator<S::Size, S::Size>>::Buffer<f64>: Copy,
+{
+    fn from(estimate: &KfEstimate<S>) -> Self {
+        Self {
+            nominal_state: estimate.nominal_state,
+            state_deviation: estimate.state_deviation.as_slice().to_vec(),
+            covar: estimate.covar.as_slice().to_vec(),
+            covar_bar: estimate.covar_bar.as_slice().to_vec(),
+            predicted: estimate.predicted,
+            stm: estimate.stm.as_slice().to_vec(),
+        }
+    }
+}
+
+impl ResidualRecord {
+    /// Rebuilds the residual of this record, which must have as many components as the measurement size.
+    pub fn to_residual<M: DimName>(&self) -> Result<Residual<M>, ConfigError>
+    where
+        DefaultAllocator: Allocator<M>,
+    {
+        for (name, data) in [
+            ("prefit", &self.prefit),
+            ("postfit", &self.postfit),
+            ("measurement noise", &self.tracker_msr_noise),
+        ] {
+            if data.len() != M::dim() {
+                return Err(ConfigError::InvalidConfig {
+                    msg: format!(
+                        "residual {name} has {} components but the filter processes {} measurements",
+                        data.len(),
+                        M::dim()
+                    ),
+                });
+            }
+        }
+
+        Ok(Residual {
+            epoch: self.epoch,
+            prefit: OVector::<f64, M>::from_column_slice(&self.prefit),
+            postfit: OVector::<f64, M>::from_column_slice(&self.postfit),
+            ratio: self.ratio,
+            tracker_msr_noise: OVector::<f64, M>::from_column_slice(&self.tracker_msr_noise),
+            rejected: self.rejected,
+            tracker: self.tracker.clone(),
+            msr_types: self.msr_types.clone(),
+        })
+    }
+}
+
+impl<M: DimName> From<&Residual<M>> for ResidualRecord
+where
+    DefaultAllocator: Allocator<M>,
+{
+    fn from(residual: &Residual<M>) -> Self {
+        Self {
+            epoch: residual.epoch,
+            prefit: residual.prefit.as_slice().to_vec(),
+            postfit: residual.postfit.as_slice().to_vec(),
+            ratio: residual.ratio,
+            tracker_msr_noise: residual.tracker_msr_noise.as_slice().to_vec(),
+            rejected: residual.rejected,
+            tracker: residual.tracker.clone(),
+            msr_types: residual.msr_types.clone(),
+        }
+    }
+}
+
+impl<S: State + Serialize + DeserializeOwned> ODCheckpoint<S>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    /// Saves this checkpoint to the provided path as YAML, which can be loaded with [ConfigRepr::load].
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
+        let file = File::create(path).context(WriteSnafu)?;
+        serde_yml::to_writer(BufWriter::new(file), self).context(SerializeSnafu)
+    }
+}
+
+impl<S: State + Serialize + DeserializeOwned + Debug> ConfigRepr for ODCheckpoint<S> where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>
+{
+}
+
+impl<MsrSize: DimName, Accel: DimName, Trk: TrackerSensitivity<Spacecraft, Spacecraft>>
+    ODProcess<'_, SpacecraftDynamics, MsrSize, Accel, KF<Spacecraft, Accel, MsrSize>, Trk>
+where
+    DefaultAllocator: Allocator<MsrSize>
+        + Allocator<MsrSize, <Spacecraft as State>::Size>
+        + Allocator<Const<1>, MsrSize>
+        + Allocator<MsrSize, MsrSize>
+        + Allocator<<Spacecraft as State>::Size, MsrSize>
+        + Allocator<Accel>
+        + Allocator<Accel, Accel>
+        + Allocator<<Spacecraft as State>::Size>
+        + Allocator<<Spacecraft as State>::VecLength>
+        + Allocator<<Spacecraft as State>::Size, <Spacecraft as State>::Size>
+        + Allocator<<Spacecraft as State>::Size, Accel>
+        + Allocator<Accel, <Spacecraft as State>::Size>,
+{
+    /// Returns a checkpoint of this OD process, which may be saved to disk to resume the processing later.
+    pub fn checkpoint(&self) -> ODCheckpoint<Spacecraft> {
+        ODCheckpoint {
+            propagation: self.prop.checkpoint(),
+            estimate: self.kf.previous_estimate().into(),
+            extended: self.kf.is_extended(),
+            process_noise: self
+                .kf
+                .process_noise
+                .iter()
+                .map(|snc| SncRecord {
+                    init_epoch: snc.init_epoch,
+                    prev_epoch: snc.prev_epoch,
+                })
+                .collect(),
+            prev_used_snc: self.kf.prev_used_snc,
+            ekf_trigger: self.ekf_trigger,
+            estimates: self.estimates.iter().map(EstimateRecord::from).collect(),
+            residuals: self
+                .residuals
+                .iter()
+                .map(|resid| resid.as_ref().map(ResidualRecord::from))
+                .collect(),
+        }
+    }
+
+    /// Restores the state of this OD process from the provided checkpoint, such that the next call to
+    /// [ODProcess::process_arc] continues from where the checkpointed process stopped.
+    ///
+    /// The devices, process noise and residual rejection criteria of this process are kept, and the checkpointed
+    /// state becomes the initial state used when iterating. The epochs of the process noise are restored, so the
+    /// process noise must be the one of the checkpointed process.
+    pub fn resume(&mut self, checkpoint: &ODCheckpoint<Spacecraft>) -> Result<(), ODError> {
+        let state = checkpoint
+            .propagation
+            .restored_state()
+            .context(ODConfigSnafu)?;
+        let estimate = checkpoint.estimate.to_estimate().context(ODConfigSnafu)?;
+
+        let estimates = checkpoint
+            .estimates
+            .iter()
+            .map(EstimateRecord::to_estimate)
+            .collect::<Result<Vec<_>, _>>()
+            .context(ODConfigSnafu)?;
+        let residuals = checkpoint
+            .residuals
+            .iter()
+            .map(|resid| resid.as_ref().map(ResidualRecord::to_residual).transpose())
+            .collect::<Result<Vec<_>, _>>()
+            .context(ODConfigSnafu)?;
+        if checkpoint.process_noise.len() != self.kf.process_noise.len() {
+            return Err(ConfigError::InvalidConfig {
+                msg: format!(
+                    "checkpoint has {} process noises but the filter has {}",
+                    checkpoint.process_noise.len(),
+                    self.kf.process_noise.len()
+                ),
+            })
+            .context(ODConfigSnafu);
+        }
+
+        self.prop.state = state;
+        self.prop.set_step(
+            checkpoint.propagation.step_size,
+            checkpoint.propagation.fixed_step,
+        );
+        self.prop.details = checkpoint.propagation.details;
+        self.init_state = state;
+
+        self.kf.set_previous_estimate(&estimate);
+        self.kf.set_extended(checkpoint.extended);
+        for (snc, record) in self
+            .kf
+            .process_noise
+            .iter_mut()
+            .zip(&checkpoint.process_noise)
+        {
+            snc.init_epoch = record.init_epoch;
+            snc.prev_epoch = record.prev_epoch;
+        }
+        self.kf.prev_used_snc = checkpoint.prev_used_snc;
+        if checkpoint.ekf_trigger.is_some() {
+            self.ekf_trigger = checkpoint.ekf_trigger;
+        }
+
+        self.estimates = estimates;
+        self.residuals = residuals;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod ut_checkpoint {
+    use super::ODCheckpoint;
+    use crate::cosmic::{Orbit, Spacecraft};
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::io::ConfigRepr;
+    use crate::linalg::{SVector, Vector2};
+    use crate::od::estimate::{KfEstimate, Residual};
+    use crate::od::filter::kalman::KF;
+    use crate::od::msr::MeasurementType;
+    use crate::od::prelude::{EkfTrigger, GroundStation};
+    use crate::od::snc::SNC3;
+    use crate::od::{Filter, SpacecraftODProcess};
+    use crate::propagators::Propagator;
+    use crate::time::TimeUnits;
+    use crate::State;
+    use indexmap::IndexSet;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn save_and_resume() {
+        let eme2k = fixtures::eme2k();
+        let epoch = fixtures::epoch();
+        let orbit =
+            Orbit::try_keplerian(7_000.0, 0.01, 51.6, 30.0, 45.0, 10.0, epoch, eme2k).unwrap();
+        let sc = Spacecraft::builder().orbit(orbit).build().with_stm();
+        let almanac = fixtures::almanac();
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+
+        let new_process = |sc: Spacecraft| -> SpacecraftODProcess {
+            let initial_estimate = KfEstimate::from_diag(
+                sc,
+                SVector::<f64, 9>::from_column_slice(&[
+                    0.25, 0.25, 0.25, 1e-6, 1e-6, 1e-6, 0.0, 0.0, 0.0,
+                ]),
+            );
+            // A decaying SNC, followed by another one which starts later, both with a disable time
+            let process_noise = vec![
+                SNC3::with_decay(2.minutes(), &[1e-8; 3], &[1e-3; 3]),
+                SNC3::with_start_time(2.minutes(), &[1e-9; 3], epoch + 90.seconds()),
+            ];
+            SpacecraftODProcess::ekf(
+                prop.with(sc, almanac.clone()),
+                KF::with_sncs(initial_estimate, process_noise),
+                BTreeMap::<String, GroundStation>::new(),
+                EkfTrigger::new(10, 2.hours()),
+                None,
+                almanac.clone(),
+            )
+        };
+
+        // Advance the filter with a couple of time updates, and record a residual as a measurement update would
+        let mut process = new_process(sc);
+        for _ in 0..2 {
+            process.prop.for_duration(1.minutes()).unwrap();
+            let estimate = process.kf.time_update(process.prop.state).unwrap();
+            process.estimates.push(estimate);
+            process.residuals.push(None);
+            process.prop.state.reset_stm();
+        }
+        let mut msr_types = IndexSet::new();
+        msr_types.insert(MeasurementType::Range);
+        msr_types.insert(MeasurementType::Doppler);
+        process.residuals[1] = Some(Residual {
+            epoch: process.prop.state.epoch(),
+            prefit: Vector2::new(1.5e-3, -2.0e-6),
+            postfit: Vector2::new(1.0e-4, 3.0e-7),
+            ratio: 0.75,
+            tracker_msr_noise: Vector2::new(1e-6, 1e-10),
+            rejected: false,
+            tracker: Some("DSS-65".to_string()),
+            msr_types,
+        });
+        process.kf.set_extended(true);
+        assert_eq!(process.kf.prev_used_snc, 1);
+
+        let path = std::env::temp_dir().join("nyx_ut_od_checkpoint.yaml");
+        process.checkpoint().save(&path).unwrap();
+        let loaded = ODCheckpoint::<Spacecraft>::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Resume a process which started from another state, at another epoch
+        let mut other = sc.with_dry_mass(150.0);
+        other.orbit.epoch = epoch + 1.hours();
+        let mut resumed = new_process(other);
+        resumed.resume(&loaded).unwrap();
+        assert_eq!(resumed.prop.state, process.prop.state);
+        assert_eq!(
+            resumed.kf.previous_estimate(),
+            process.kf.previous_estimate()
+        );
+        assert!(resumed.kf.is_extended());
+        for (resumed_snc, snc) in resumed
+            .kf
+            .process_noise
+            .iter()
+            .zip(&process.kf.process_noise)
+        {
+            assert_eq!(resumed_snc.init_epoch, snc.init_epoch);
+            assert_eq!(resumed_snc.prev_epoch, snc.prev_epoch);
+        }
+        assert_eq!(resumed.kf.prev_used_snc, process.kf.prev_used_snc);
+        assert_eq!(resumed.ekf_trigger, process.ekf_trigger);
+        assert_eq!(resumed.estimates, process.estimates);
+        assert_eq!(resumed.residuals, process.residuals);
+
+        // The resumed process continues exactly like the original one, whose SNCs are disabled after this long a step
+        for od in [&mut process, &mut resumed] {
+            od.prop.for_duration(3.minutes()).unwrap();
+            let estimate = od.kf.time_update(od.prop.state).unwrap();
+            od.estimates.push(estimate);
+        }
+        assert_eq!(resumed.estimates.last(), process.estimates.last());
+
+        // A checkpoint of another filter size or with other process noises is rejected
+        let mut invalid = loaded.clone();
+        invalid.estimate.covar.truncate(36);
+        assert!(resumed.resume(&invalid).is_err());
+        let mut invalid = loaded;
+        invalid.process_noise.pop();
+        assert!(resumed.resume(&invalid).is_err());
+    }
+}
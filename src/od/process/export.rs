@@ -17,7 +17,8 @@
 */
 
 use crate::dynamics::SpacecraftDynamics;
-use crate::io::watermark::pq_writer;
+use crate::errors::NyxError;
+use crate::io::watermark::pq_writer_with;
 use crate::io::{ArrowSnafu, ExportCfg, ParquetSnafu, StdIOSnafu};
 use crate::linalg::allocator::Allocator;
 use crate::linalg::{DefaultAllocator, DimName};
@@ -29,7 +30,6 @@ use crate::{od::*, Spacecraft};
 use arrow::array::{Array, BooleanBuilder, Float64Builder, StringBuilder};
 use arrow::datatypes::{DataType, Field, Schema};
 use arrow::record_batch::RecordBatch;
-use filter::kalman::KF;
 use hifitime::TimeScale;
 use msr::sensitivity::TrackerSensitivity;
 use msr::TrackingDataArc;
@@ -38,12 +38,41 @@ use parquet::arrow::ArrowWriter;
 use snafu::prelude::*;
 use std::collections::HashMap;
 use std::fs::File;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 
 use super::ODProcess;
 
-impl<MsrSize: DimName, Accel: DimName, Trk: TrackerSensitivity<Spacecraft, Spacecraft>>
-    ODProcess<'_, SpacecraftDynamics, MsrSize, Accel, KF<Spacecraft, Accel, MsrSize>, Trk>
+/// Schema and epoch bounds of an orbit determination export.
+struct ExportLayout {
+    schema: Arc<Schema>,
+    fields: Vec<StateParameter>,
+    sigma_fields: Vec<StateParameter>,
+    start_epoch: Option<Epoch>,
+    end_epoch: Option<Epoch>,
+}
+
+impl ExportLayout {
+    /// Selects the estimates to export, which are chronological, without copying them
+    fn selection(&self, estimates: &[KfEstimate<Spacecraft>]) -> Range<usize> {
+        let first = match self.start_epoch {
+            Some(start) => estimates.partition_point(|estimate| estimate.epoch() < start),
+            None => 0,
+        };
+        let last = match self.end_epoch {
+            Some(end) => estimates.partition_point(|estimate| estimate.epoch() <= end),
+            None => estimates.len(),
+        };
+        first..last.max(first)
+    }
+}
+
+impl<
+        MsrSize: DimName,
+        Accel: DimName,
+        K: Filter<Spacecraft, Accel, MsrSize, Estimate = KfEstimate<Spacecraft>>,
+        Trk: TrackerSensitivity<Spacecraft, Spacecraft>,
+    > ODProcess<'_, SpacecraftDynamics, MsrSize, Accel, K, Trk>
 where
     DefaultAllocator: Allocator<MsrSize>
         + Allocator<MsrSize, <Spacecraft as State>::Size>
@@ -68,6 +97,158 @@ where
         path: P,
         cfg: ExportCfg,
     ) -> Result<PathBuf, ODError> {
+        let tick = Epoch::now().unwrap();
+        info!("Exporting orbit determination result to parquet file...");
+
+        // Grab the path here before we move stuff.
+        let path_buf = cfg.actual_path(path);
+
+        self.check_results()?;
+        let batch_rows = cfg.parquet.row_group_rows.max(1);
+        let layout = self.export_layout(&self.estimates[0], arc, cfg.clone())?;
+        let mut writer = Self::export_writer(&layout, &path_buf, &cfg)?;
+
+        let selected = layout.selection(&self.estimates);
+        for first in selected.clone().step_by(batch_rows) {
+            let last = (first + batch_rows).min(selected.end);
+            Self::write_batch(&mut writer, self.export_batch(arc, &layout, first..last)?)?;
+        }
+
+        info!("Serialized {} estimates and residuals", selected.len());
+
+        writer
+            .close()
+            .context(ParquetSnafu {
+                action: "closing OD results file",
+            })
+            .context(ODIOSnafu)?;
+
+        // Return the path this was written to
+        let tock_time = Epoch::now().unwrap() - tick;
+        info!(
+            "Orbit determination results written to {} in {tock_time}",
+            path_buf.display()
+        );
+        Ok(path_buf)
+    }
+
+    /// Builds an in-memory Arrow record batch of the estimates and residuals, with the same columns and schema metadata as [Self::to_parquet].
+    ///
+    /// This allows handing the OD results to other Arrow consumers (e.g. pandas or polars) without a file round trip.
+    pub fn to_record_batch(
+        &self,
+        arc: &TrackingDataArc,
+        cfg: ExportCfg,
+    ) -> Result<RecordBatch, ODError> {
+        self.check_results()?;
+        let layout = self.export_layout(&self.estimates[0], arc, cfg)?;
+        self.export_batch(arc, &layout, layout.selection(&self.estimates))
+    }
+
+    /// Processes the arc like [ODProcess::process_arc], writing the estimates and residuals to a parquet file as they are produced.
+    ///
+    /// Every `cfg.parquet.row_group_rows` estimates are written as a row group, with the same columns as [Self::to_parquet],
+    /// and are then dropped from memory. Hence, the estimates and residuals are _not_ available in this structure once this
+    /// returns (e.g. for smoothing): read them back from the returned file instead. Estimates from previous arcs which are
+    /// still in memory are written first.
+    pub fn process_arc_with_parquet<P: AsRef<Path>>(
+        &mut self,
+        arc: &TrackingDataArc,
+        path: P,
+        cfg: ExportCfg,
+    ) -> Result<PathBuf, ODError> {
+        let path_buf = cfg.actual_path(path);
+        let batch_rows = cfg.parquet.row_group_rows.max(1);
+
+        let template = self
+            .estimates
+            .first()
+            .unwrap_or(self.kf.previous_estimate());
+        let layout = self.export_layout(template, arc, cfg.clone())?;
+        let mut writer = Self::export_writer(&layout, &path_buf, &cfg)?;
+        let mut num_rows = 0;
+
+        let mut drain = |od: &mut Self, min_rows: usize| -> Result<(), ODError> {
+            if od.estimates.len() < min_rows {
+                return Ok(());
+            }
+            let selected = layout.selection(&od.estimates);
+            if !selected.is_empty() {
+                Self::write_batch(
+                    &mut writer,
+                    od.export_batch(arc, &layout, selected.clone())?,
+                )?;
+                num_rows += selected.len();
+            }
+            od.estimates.clear();
+            od.residuals.clear();
+            Ok(())
+        };
+
+        self.process_arc_with_sink(arc, &mut |od| drain(od, batch_rows))?;
+        // Write whatever remains once the arc is processed.
+        drain(self, 1)?;
+
+        info!("Serialized {num_rows} estimates and residuals");
+
+        writer
+            .close()
+            .context(ParquetSnafu {
+                action: "closing OD results file",
+            })
+            .context(ODIOSnafu)?;
+
+        info!(
+            "Orbit determination results written to {}",
+            path_buf.display()
+        );
+        Ok(path_buf)
+    }
+
+    /// Opens the parquet writer of an export.
+    fn export_writer(
+        layout: &ExportLayout,
+        path: &Path,
+        cfg: &ExportCfg,
+    ) -> Result<ArrowWriter<File>, ODError> {
+        let props = pq_writer_with(Some(layout.schema.metadata().clone()), &cfg.parquet)
+            .context(ParquetSnafu {
+                action: "configuring the OD results file",
+            })
+            .context(ODIOSnafu)?;
+
+        let file = File::create(path)
+            .context(StdIOSnafu {
+                action: "creating OD results file",
+            })
+            .context(ODIOSnafu)?;
+
+        ArrowWriter::try_new(file, layout.schema.clone(), Some(props))
+            .context(ParquetSnafu {
+                action: "exporting OD results",
+            })
+            .context(ODIOSnafu)
+    }
+
+    /// Writes this batch as its own row group, so that its memory is released.
+    fn write_batch(writer: &mut ArrowWriter<File>, batch: RecordBatch) -> Result<(), ODError> {
+        writer
+            .write(&batch)
+            .context(ParquetSnafu {
+                action: "writing OD results",
+            })
+            .context(ODIOSnafu)?;
+
+        writer
+            .flush()
+            .context(ParquetSnafu {
+                action: "writing OD results",
+            })
+            .context(ODIOSnafu)
+    }
+
+    /// Ensures that there are estimates to export and that they are aligned with the residuals.
+    fn check_results(&self) -> Result<(), ODError> {
         ensure!(
             !self.estimates.is_empty(),
             TooFewMeasurementsSnafu {
@@ -84,20 +265,24 @@ where
             });
         }
 
-        let tick = Epoch::now().unwrap();
-        info!("Exporting orbit determination result to parquet file...");
+        Ok(())
+    }
 
+    /// Builds the schema of the export, whose fields are those available in the template estimate.
+    fn export_layout(
+        &self,
+        template: &KfEstimate<Spacecraft>,
+        arc: &TrackingDataArc,
+        cfg: ExportCfg,
+    ) -> Result<ExportLayout, ODError> {
         if cfg.step.is_some() {
             warn!("The `step` parameter in the export is not supported for orbit determination exports.");
         }
 
-        // Grab the path here before we move stuff.
-        let path_buf = cfg.actual_path(path);
-
         // Build the schema
         let mut hdrs = vec![Field::new("Epoch (UTC)", DataType::Utf8, false)];
 
-        let frame = self.estimates[0].state().frame();
+        let frame = template.state().frame();
 
         let more_meta = Some(vec![(
             "Frame".to_string(),
@@ -117,7 +302,7 @@ where
         };
 
         // Check that we can retrieve this information
-        fields.retain(|param| match self.estimates[0].state().value(*param) {
+        fields.retain(|param| match template.state().value(*param) {
             Ok(_) => param != &StateParameter::GuidanceMode,
             Err(_) => false,
         });
@@ -137,7 +322,7 @@ where
                     | &StateParameter::VX
                     | &StateParameter::VY
                     | &StateParameter::VZ
-            ) && self.estimates[0].sigma_for(*param).is_ok()
+            ) && template.sigma_for(*param).is_ok()
         });
 
         for field in &sigma_fields {
@@ -180,8 +365,6 @@ where
             }
         }
 
-        let est_size = <Spacecraft as State>::Size::dim();
-
         let mut idx = 0;
         for i in 0..state_items.len() {
             for j in i..state_items.len() {
@@ -245,60 +428,62 @@ where
 
         hdrs.append(&mut msr_fields);
 
-        // Build the schema
-        let schema = Arc::new(Schema::new(hdrs));
-        let mut record: Vec<Arc<dyn Array>> = Vec::new();
+        // Serialize all of the devices and add that to the parquet file too.
+        let mut metadata = HashMap::new();
+        metadata.insert(
+            "Purpose".to_string(),
+            "Orbit determination results".to_string(),
+        );
+        if let Some(add_meta) = cfg.metadata {
+            for (k, v) in add_meta {
+                metadata.insert(k, v);
+            }
+        }
 
-        // Build the states iterator -- this does require copying the current states but I can't either get a reference or a copy of all the states.
-        let (estimates, residuals) =
-            if cfg.start_epoch.is_some() || cfg.end_epoch.is_some() || cfg.step.is_some() {
-                // Must interpolate the data!
-                let start = cfg
-                    .start_epoch
-                    .unwrap_or_else(|| self.estimates.first().unwrap().state().epoch());
-                let end = cfg
-                    .end_epoch
-                    .unwrap_or_else(|| self.estimates.last().unwrap().state().epoch());
-
-                let mut residuals: Vec<Option<Residual<MsrSize>>> =
-                    Vec::with_capacity(self.residuals.len());
-                let mut estimates = Vec::with_capacity(self.estimates.len());
-
-                for (estimate, residual) in self.estimates.iter().zip(self.residuals.iter()) {
-                    if estimate.epoch() >= start && estimate.epoch() <= end {
-                        estimates.push(*estimate);
-                        residuals.push(residual.clone());
-                    }
-                }
+        Ok(ExportLayout {
+            schema: Arc::new(Schema::new_with_metadata(hdrs, metadata)),
+            fields,
+            sigma_fields,
+            start_epoch: cfg.start_epoch,
+            end_epoch: cfg.end_epoch,
+        })
+    }
 
-                (estimates, residuals)
-            } else {
-                (self.estimates.to_vec(), self.residuals.to_vec())
-            };
+    /// Builds the record batch of the estimates and residuals in this range.
+    fn export_batch(
+        &self,
+        arc: &TrackingDataArc,
+        layout: &ExportLayout,
+        range: Range<usize>,
+    ) -> Result<RecordBatch, ODError> {
+        let estimates = &self.estimates[range.clone()];
+        let residuals = &self.residuals[range];
+        let est_size = <Spacecraft as State>::Size::dim();
 
-        // Build all of the records
+        // Build all of the records of this batch
+        let mut record: Vec<Arc<dyn Array>> = Vec::new();
 
         // Epochs
         let mut utc_epoch = StringBuilder::new();
-        for s in &estimates {
+        for s in estimates {
             utc_epoch.append_value(s.epoch().to_time_scale(TimeScale::UTC).to_isoformat());
         }
         record.push(Arc::new(utc_epoch.finish()));
 
         // Add all of the fields
-        for field in fields {
+        for field in &layout.fields {
             let mut data = Float64Builder::new();
-            for s in &estimates {
-                data.append_value(s.state().value(field).unwrap());
+            for s in estimates {
+                data.append_value(s.state().value(*field).unwrap());
             }
             record.push(Arc::new(data.finish()));
         }
 
         // Add all of the 1-sigma uncertainties
-        for field in sigma_fields {
+        for field in &layout.sigma_fields {
             let mut data = Float64Builder::new();
-            for s in &estimates {
-                data.append_value(s.sigma_for(field).unwrap());
+            for s in estimates {
+                data.append_value(s.sigma_for(*field).unwrap());
             }
             record.push(Arc::new(data.finish()));
         }
@@ -307,7 +492,7 @@ where
         for i in 0..est_size {
             for j in i..est_size {
                 let mut data = Float64Builder::new();
-                for s in &estimates {
+                for s in estimates {
                     data.append_value(s.covar()[(i, j)]);
                 }
                 record.push(Arc::new(data.finish()));
@@ -317,7 +502,7 @@ where
         // Add the sigma/uncertainty in the integration frame
         for i in 0..est_size {
             let mut data = Float64Builder::new();
-            for s in &estimates {
+            for s in estimates {
                 data.append_value(s.covar()[(i, i)].sqrt());
             }
             record.push(Arc::new(data.finish()));
@@ -326,7 +511,7 @@ where
         // Add the sigma/uncertainty covariance in the RIC frame
         let mut ric_covariances = Vec::new();
 
-        for s in &estimates {
+        for s in estimates {
             let dcm_ric2inertial = s
                 .state()
                 .orbit()
@@ -356,7 +541,7 @@ where
         // Prefits
         for msr_type in arc.unique_types() {
             let mut data = Float64Builder::new();
-            for resid_opt in &residuals {
+            for resid_opt in residuals {
                 if let Some(resid) = resid_opt {
                     match resid.prefit(msr_type) {
                         Some(prefit) => data.append_value(prefit),
@@ -371,7 +556,7 @@ where
         // Postfit
         for msr_type in arc.unique_types() {
             let mut data = Float64Builder::new();
-            for resid_opt in &residuals {
+            for resid_opt in residuals {
                 if let Some(resid) = resid_opt {
                     match resid.postfit(msr_type) {
                         Some(postfit) => data.append_value(postfit),
@@ -386,7 +571,7 @@ where
         // Measurement noise
         for msr_type in arc.unique_types() {
             let mut data = Float64Builder::new();
-            for resid_opt in &residuals {
+            for resid_opt in residuals {
                 if let Some(resid) = resid_opt {
                     match resid.trk_noise(msr_type) {
                         Some(noise) => data.append_value(noise),
@@ -400,7 +585,7 @@ where
         }
         // Residual ratio (unique entry regardless of the size)
         let mut data = Float64Builder::new();
-        for resid_opt in &residuals {
+        for resid_opt in residuals {
             if let Some(resid) = resid_opt {
                 data.append_value(resid.ratio);
             } else {
@@ -411,7 +596,7 @@ where
 
         // Residual acceptance (unique entry regardless of the size)
         let mut data = BooleanBuilder::new();
-        for resid_opt in &residuals {
+        for resid_opt in residuals {
             if let Some(resid) = resid_opt {
                 data.append_value(resid.rejected);
             } else {
@@ -422,7 +607,7 @@ where
 
         // Residual tracker (unique entry regardless of the size)
         let mut data = StringBuilder::new();
-        for resid_opt in &residuals {
+        for resid_opt in residuals {
             if let Some(resid) = resid_opt {
                 data.append_value(
                     resid
@@ -436,60 +621,467 @@ where
         }
         record.push(Arc::new(data.finish()));
 
-        info!("Serialized {} estimates and residuals", estimates.len());
+        RecordBatch::try_new(layout.schema.clone(), record)
+            .context(ArrowSnafu {
+                action: "writing OD results (building batch record)",
+            })
+            .context(ODIOSnafu)
+    }
 
-        // Serialize all of the devices and add that to the parquet file too.
-        let mut metadata = HashMap::new();
-        metadata.insert(
-            "Purpose".to_string(),
-            "Orbit determination results".to_string(),
-        );
-        if let Some(add_meta) = cfg.metadata {
-            for (k, v) in add_meta {
-                metadata.insert(k, v);
-            }
-        }
+    /// Store the estimated trajectory and its covariance in a CCSDS OEM v2 file.
+    pub fn to_oem<P: AsRef<Path>>(&self, path: P, cfg: ExportCfg) -> Result<PathBuf, NyxError> {
+        let covariances = self
+            .estimates
+            .iter()
+            .map(|est| (est.epoch(), est.covar.fixed_view::<6, 6>(0, 0).into_owned()))
+            .collect::<Vec<_>>();
 
-        let props = pq_writer(Some(metadata));
+        self.to_traj()?.to_oem(path, cfg, &covariances)
+    }
+}
 
-        let file = File::create(&path_buf)
-            .context(StdIOSnafu {
-                action: "creating OD results file",
+impl<
+        MsrSize: DimName,
+        Accel: DimName,
+        K: Filter<Spacecraft, Accel, MsrSize, Estimate = KfEstimate<Spacecraft>>,
+    > ODProcess<'_, SpacecraftDynamics, MsrSize, Accel, K, GroundStation>
+where
+    DefaultAllocator: Allocator<MsrSize>
+        + Allocator<MsrSize, <Spacecraft as State>::Size>
+        + Allocator<Const<1>, MsrSize>
+        + Allocator<<Spacecraft as State>::Size>
+        + Allocator<<Spacecraft as State>::Size, <Spacecraft as State>::Size>
+        + Allocator<MsrSize, MsrSize>
+        + Allocator<MsrSize, <Spacecraft as State>::Size>
+        + Allocator<<Spacecraft as State>::Size, MsrSize>
+        + Allocator<Accel>
+        + Allocator<Accel, Accel>
+        + Allocator<<Spacecraft as State>::Size>
+        + Allocator<<Spacecraft as State>::VecLength>
+        + Allocator<<Spacecraft as State>::Size, <Spacecraft as State>::Size>
+        + Allocator<<Spacecraft as State>::Size, Accel>
+        + Allocator<Accel, <Spacecraft as State>::Size>,
+{
+    /// Store the residuals in a dedicated parquet file, with one row per measurement type of each residual.
+    ///
+    /// Each row includes the tracker, the prefit and postfit residuals, the measurement noise, the residual ratio and
+    /// whether the measurement was rejected, and the azimuth and elevation of the estimate as seen from the tracker.
+    /// The azimuth and elevation are null if they cannot be computed, e.g. if the almanac lacks the station's body.
+    /// The file metadata includes the filter diagnostics: the residual ratio RMS and the number of accepted and rejected residuals.
+    pub fn residuals_to_parquet<P: AsRef<Path>>(
+        &self,
+        path: P,
+        cfg: ExportCfg,
+    ) -> Result<PathBuf, ODError> {
+        let tick = Epoch::now().unwrap();
+        info!("Exporting orbit determination residuals to parquet file...");
+
+        let path_buf = cfg.actual_path(path);
+        let batch = self.residuals_to_record_batch(cfg.clone())?;
+        let schema = batch.schema();
+
+        let props = pq_writer_with(Some(schema.metadata().clone()), &cfg.parquet)
+            .context(ParquetSnafu {
+                action: "configuring the OD residuals file",
             })
             .context(ODIOSnafu)?;
 
-        let mut writer = ArrowWriter::try_new(file, schema.clone(), props)
-            .context(ParquetSnafu {
-                action: "exporting OD results",
+        let file = File::create(&path_buf)
+            .context(StdIOSnafu {
+                action: "creating OD residuals file",
             })
             .context(ODIOSnafu)?;
 
-        let batch = RecordBatch::try_new(schema, record)
-            .context(ArrowSnafu {
-                action: "writing OD results (building batch record)",
+        let mut writer = ArrowWriter::try_new(file, schema, Some(props))
+            .context(ParquetSnafu {
+                action: "exporting OD residuals",
             })
             .context(ODIOSnafu)?;
 
         writer
             .write(&batch)
             .context(ParquetSnafu {
-                action: "writing OD results",
+                action: "writing OD residuals",
             })
             .context(ODIOSnafu)?;
 
         writer
             .close()
             .context(ParquetSnafu {
-                action: "closing OD results file",
+                action: "closing OD residuals file",
             })
             .context(ODIOSnafu)?;
 
-        // Return the path this was written to
         let tock_time = Epoch::now().unwrap() - tick;
         info!(
-            "Orbit determination results written to {} in {tock_time}",
+            "{} rows of residuals written to {} in {tock_time}",
+            batch.num_rows(),
             path_buf.display()
         );
         Ok(path_buf)
     }
+
+    /// Builds an in-memory Arrow record batch of the residuals, with the same columns and schema metadata as
+    /// [Self::residuals_to_parquet], e.g. to hand them to pandas or polars without a file round trip.
+    pub fn residuals_to_record_batch(&self, cfg: ExportCfg) -> Result<RecordBatch, ODError> {
+        if self.estimates.len() != self.residuals.len() {
+            return Err(ODError::ODConfigError {
+                source: ConfigError::InvalidConfig {
+                    msg: "Estimates and residuals are not aligned.".to_string(),
+                },
+            });
+        }
+
+        let mut epochs = StringBuilder::new();
+        let mut trackers = StringBuilder::new();
+        let mut msr_types = StringBuilder::new();
+        let mut units = StringBuilder::new();
+        let mut prefits = Float64Builder::new();
+        let mut postfits = Float64Builder::new();
+        let mut noises = Float64Builder::new();
+        let mut ratios = Float64Builder::new();
+        let mut rejections = BooleanBuilder::new();
+        let mut azimuths = Float64Builder::new();
+        let mut elevations = Float64Builder::new();
+
+        let (mut num_accepted, mut num_rejected) = (0, 0);
+        let mut ratio_sq_sum = 0.0;
+
+        for (estimate, resid) in self.estimates.iter().zip(self.residuals.iter()) {
+            let resid = match resid {
+                Some(resid) => resid,
+                None => continue,
+            };
+
+            if cfg.start_epoch.is_some_and(|start| resid.epoch < start)
+                || cfg.end_epoch.is_some_and(|end| resid.epoch > end)
+            {
+                continue;
+            }
+
+            if resid.rejected {
+                num_rejected += 1;
+            } else {
+                num_accepted += 1;
+            }
+            ratio_sq_sum += resid.ratio.powi(2);
+
+            let tracker = resid
+                .tracker
+                .clone()
+                .unwrap_or("Undefined tracker".to_string());
+
+            // The station frame must be loaded in the almanac to compute its azimuth and elevation
+            let az_el = self
+                .devices
+                .get(&tracker)
+                .filter(|device| self.almanac.frame_from_uid(device.frame).is_ok())
+                .and_then(|device| {
+                    device
+                        .azimuth_elevation_of(estimate.state().orbit, None, &self.almanac)
+                        .ok()
+                });
+
+            for msr_type in &resid.msr_types {
+                epochs.append_value(resid.epoch.to_time_scale(TimeScale::UTC).to_isoformat());
+                trackers.append_value(&tracker);
+                msr_types.append_value(format!("{msr_type:?}"));
+                units.append_value(msr_type.unit());
+                prefits.append_option(resid.prefit(*msr_type));
+                postfits.append_option(resid.postfit(*msr_type));
+                noises.append_option(resid.trk_noise(*msr_type));
+                ratios.append_value(resid.ratio);
+                rejections.append_value(resid.rejected);
+                azimuths.append_option(az_el.map(|az_el| az_el.azimuth_deg));
+                elevations.append_option(az_el.map(|az_el| az_el.elevation_deg));
+            }
+        }
+
+        let hdrs = vec![
+            Field::new("Epoch (UTC)", DataType::Utf8, false),
+            Field::new("Tracker", DataType::Utf8, false),
+            Field::new("Measurement type", DataType::Utf8, false),
+            Field::new("Unit", DataType::Utf8, false),
+            Field::new("Prefit residual", DataType::Float64, true),
+            Field::new("Postfit residual", DataType::Float64, true),
+            Field::new("Measurement noise", DataType::Float64, true),
+            Field::new("Residual ratio", DataType::Float64, false),
+            Field::new("Residual Rejected", DataType::Boolean, false),
+            Field::new("Azimuth (deg)", DataType::Float64, true),
+            Field::new("Elevation (deg)", DataType::Float64, true),
+        ];
+
+        let num_residuals = num_accepted + num_rejected;
+        let mut metadata = HashMap::new();
+        metadata.insert(
+            "Purpose".to_string(),
+            "Orbit determination residuals".to_string(),
+        );
+        metadata.insert("Accepted residuals".to_string(), num_accepted.to_string());
+        metadata.insert("Rejected residuals".to_string(), num_rejected.to_string());
+        if num_residuals > 0 {
+            metadata.insert(
+                "Residual ratio RMS".to_string(),
+                format!("{}", (ratio_sq_sum / num_residuals as f64).sqrt()),
+            );
+        }
+        if let Some(add_meta) = cfg.metadata {
+            for (k, v) in add_meta {
+                metadata.insert(k, v);
+            }
+        }
+
+        let schema = Arc::new(Schema::new_with_metadata(hdrs, metadata));
+
+        let record: Vec<Arc<dyn Array>> = vec![
+            Arc::new(epochs.finish()),
+            Arc::new(trackers.finish()),
+            Arc::new(msr_types.finish()),
+            Arc::new(units.finish()),
+            Arc::new(prefits.finish()),
+            Arc::new(postfits.finish()),
+            Arc::new(noises.finish()),
+            Arc::new(ratios.finish()),
+            Arc::new(rejections.finish()),
+            Arc::new(azimuths.finish()),
+            Arc::new(elevations.finish()),
+        ];
+
+        RecordBatch::try_new(schema, record)
+            .context(ArrowSnafu {
+                action: "writing OD residuals (building batch record)",
+            })
+            .context(ODIOSnafu)
+    }
+}
+
+#[cfg(test)]
+mod ut_export {
+    use crate::cosmic::{Orbit, Spacecraft};
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::io::{ExportCfg, ParquetCfg};
+    use crate::linalg::{SVector, Vector2};
+    use crate::od::estimate::{KfEstimate, Residual};
+    use crate::od::filter::kalman::KF;
+    use crate::od::msr::{Measurement, MeasurementType, TrackingDataArc};
+    use crate::od::noise::StochasticNoise;
+    use crate::od::{Filter, GroundStation, SpacecraftODProcess};
+    use crate::propagators::{IntegratorOptions, Propagator};
+    use crate::time::TimeUnits;
+    use crate::State;
+    use anise::constants::frames::IAU_EARTH_FRAME;
+    use anise::structure::planetocentric::ellipsoid::Ellipsoid;
+    use arrow::array::{Array, BooleanArray, Float64Array, StringArray};
+    use indexmap::IndexSet;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use std::collections::BTreeMap;
+    use std::fs::File;
+
+    #[test]
+    fn residuals_parquet() {
+        let orbit = fixtures::keplerian(7_000.0, 0.01, 51.6, 30.0, 45.0, 10.0);
+        let sc = Spacecraft::builder().orbit(orbit).build().with_stm();
+        let almanac = fixtures::almanac();
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+
+        let dss65 = GroundStation::dss65_madrid(
+            0.0,
+            StochasticNoise::default_range_km(),
+            StochasticNoise::default_doppler_km_s(),
+            IAU_EARTH_FRAME,
+        );
+        let mut devices = BTreeMap::new();
+        devices.insert(dss65.name.clone(), dss65);
+
+        let initial_estimate = KfEstimate::from_diag(
+            sc,
+            SVector::<f64, 9>::from_column_slice(&[
+                0.25, 0.25, 0.25, 1e-6, 1e-6, 1e-6, 0.0, 0.0, 0.0,
+            ]),
+        );
+        let mut process: SpacecraftODProcess = SpacecraftODProcess::ckf(
+            prop.with(sc, almanac.clone()),
+            KF::no_snc(initial_estimate),
+            devices,
+            None,
+            almanac,
+        );
+
+        let mut msr_types = IndexSet::new();
+        msr_types.insert(MeasurementType::Range);
+        msr_types.insert(MeasurementType::Doppler);
+
+        for i in 0..3 {
+            process.prop.for_duration(1.minutes()).unwrap();
+            let estimate = process.kf.time_update(process.prop.state).unwrap();
+            process.estimates.push(estimate);
+            process.residuals.push(if i == 0 {
+                None
+            } else {
+                Some(Residual {
+                    epoch: process.prop.state.epoch(),
+                    prefit: Vector2::new(1.5e-3, -2.0e-6),
+                    postfit: Vector2::new(1.0e-4, 3.0e-7),
+                    ratio: 3.0 * i as f64,
+                    tracker_msr_noise: Vector2::new(1e-6, 1e-10),
+                    rejected: i == 2,
+                    tracker: Some("Madrid".to_string()),
+                    msr_types: msr_types.clone(),
+                })
+            });
+            process.prop.state.reset_stm();
+        }
+
+        let path = process
+            .residuals_to_parquet(
+                std::env::temp_dir().join("nyx_ut_od_residuals.parquet"),
+                ExportCfg::default(),
+            )
+            .unwrap();
+
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap();
+        let metadata = builder
+            .metadata()
+            .file_metadata()
+            .key_value_metadata()
+            .unwrap()
+            .iter()
+            .map(|kv| (kv.key.clone(), kv.value.clone().unwrap_or_default()))
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(metadata["Accepted residuals"], "1");
+        assert_eq!(metadata["Rejected residuals"], "1");
+        assert!(metadata.contains_key("Generated by"));
+
+        let batch = builder.build().unwrap().next().unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // The in-memory batch holds the same residuals
+        let in_memory = process
+            .residuals_to_record_batch(ExportCfg::default())
+            .unwrap();
+        assert_eq!(in_memory.columns(), batch.columns());
+        assert_eq!(in_memory.schema().metadata()["Accepted residuals"], "1");
+
+        // One row per measurement type of each residual
+        assert_eq!(batch.num_rows(), 4);
+        let types = batch
+            .column_by_name("Measurement type")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(types.value(0), "Range");
+        assert_eq!(types.value(1), "Doppler");
+        let prefits = batch
+            .column_by_name("Prefit residual")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(prefits.value(3), -2.0e-6);
+        let rejected = batch
+            .column_by_name("Residual Rejected")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<BooleanArray>()
+            .unwrap();
+        assert!(!rejected.value(0) && rejected.value(2));
+        // The default almanac cannot compute the elevation from Madrid
+        assert_eq!(
+            batch
+                .column_by_name("Elevation (deg)")
+                .unwrap()
+                .null_count(),
+            4
+        );
+    }
+
+    #[test]
+    fn stream_od_to_parquet() {
+        // The frame is serialized in the metadata, so it needs its shape
+        let eme2k = fixtures::eme2k().with_ellipsoid(Ellipsoid::from_sphere(6_378.136_3));
+        let epoch = fixtures::epoch();
+        let orbit =
+            Orbit::try_keplerian(7_000.0, 0.01, 51.6, 30.0, 45.0, 10.0, epoch, eme2k).unwrap();
+        let sc = Spacecraft::builder().orbit(orbit).build().with_stm();
+        let almanac = fixtures::almanac();
+        let prop = Propagator::rk89(
+            SpacecraftDynamics::new(OrbitalDynamics::two_body()),
+            IntegratorOptions::with_fixed_step_s(10.0),
+        );
+
+        // The tracker is not configured, so the filter only performs the time updates between the measurements
+        let mut arc = TrackingDataArc {
+            measurements: BTreeMap::new(),
+            source: None,
+        };
+        for i in 1..=20 {
+            let msr_epoch = epoch + i.minutes();
+            arc.measurements.insert(
+                msr_epoch,
+                Measurement::new("Unconfigured".to_string(), msr_epoch)
+                    .with(MeasurementType::Range, 7_000.0),
+            );
+        }
+
+        let new_process = || -> SpacecraftODProcess {
+            let initial_estimate = KfEstimate::from_diag(
+                sc,
+                SVector::<f64, 9>::from_column_slice(&[
+                    0.25, 0.25, 0.25, 1e-6, 1e-6, 1e-6, 0.0, 0.0, 0.0,
+                ]),
+            );
+            SpacecraftODProcess::ckf(
+                prop.with(sc, almanac.clone()),
+                KF::no_snc(initial_estimate),
+                BTreeMap::<String, GroundStation>::new(),
+                None,
+                almanac.clone(),
+            )
+        };
+
+        let cfg = ExportCfg::builder()
+            .parquet(ParquetCfg::builder().row_group_rows(25).build())
+            .build();
+
+        let mut buffered = new_process();
+        buffered.process_arc(&arc).unwrap();
+        let num_estimates = buffered.estimates.len();
+        assert!(num_estimates > 25);
+
+        let mut streamed = new_process();
+        let path = streamed
+            .process_arc_with_parquet(
+                &arc,
+                std::env::temp_dir().join("nyx_ut_od_streamed.parquet"),
+                cfg.clone(),
+            )
+            .unwrap();
+        // The estimates were written as they were produced
+        assert!(streamed.estimates.is_empty());
+        assert!(streamed.residuals.is_empty());
+        assert_eq!(
+            streamed.kf.previous_estimate(),
+            buffered.kf.previous_estimate()
+        );
+
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        let metadata = reader.metadata();
+        assert_eq!(metadata.file_metadata().num_rows() as usize, num_estimates);
+        assert!(metadata.num_row_groups() > 1);
+        for row_group in metadata.row_groups() {
+            assert!(row_group.num_rows() <= 25);
+        }
+
+        std::fs::remove_file(&path).unwrap();
+
+        // Same columns as the export of the buffered estimates
+        let batch = buffered.to_record_batch(&arc, cfg).unwrap();
+        assert_eq!(
+            metadata.file_metadata().schema_descr().num_columns(),
+            batch.num_columns()
+        );
+    }
 }
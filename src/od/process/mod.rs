@@ -23,12 +23,15 @@ pub use crate::od::estimate::*;
 pub use crate::od::ground_station::*;
 pub use crate::od::snc::*;
 pub use crate::od::*;
-use crate::propagators::PropInstance;
+use crate::profiling::{self, Subsystem};
+use crate::progress::{Progress, ProgressReporter};
+use crate::propagators::{PropInstance, PropagationError};
 pub use crate::time::{Duration, Unit};
 use anise::prelude::Almanac;
 use indexmap::IndexSet;
 use msr::sensitivity::TrackerSensitivity;
 use snafu::prelude::*;
+use tracing::{debug, error, info, info_span, warn};
 mod conf;
 pub use conf::{IterationConf, SmoothingArc};
 mod trigger;
@@ -39,6 +42,8 @@ pub use self::rejectcrit::ResidRejectCrit;
 use std::collections::BTreeMap;
 use std::marker::PhantomData;
 use std::ops::Add;
+mod checkpoint;
+pub use checkpoint::{EstimateRecord, ODCheckpoint, ResidualRecord};
 mod export;
 
 /// An orbit determination process. Note that everything passed to this structure is moved.
@@ -79,6 +84,8 @@ pub struct ODProcess<
     /// Residual rejection criteria allows preventing bad measurements from affecting the estimation.
     pub resid_crit: Option<ResidRejectCrit>,
     pub almanac: Arc<Almanac>,
+    /// Reports the progress of the processing of each arc, if any
+    pub progress: Option<Arc<dyn ProgressReporter>>,
     init_state: D::StateType,
     _marker: PhantomData<Accel>,
 }
@@ -125,6 +132,7 @@ where
             ekf_trigger,
             resid_crit,
             almanac,
+            progress: None,
             init_state,
             _marker: PhantomData::<Accel>,
         }
@@ -149,11 +157,18 @@ where
             ekf_trigger: Some(trigger),
             resid_crit,
             almanac,
+            progress: None,
             init_state,
             _marker: PhantomData::<Accel>,
         }
     }
 
+    /// Reports the progress of the processing of each arc to this reporter.
+    pub fn with_progress(mut self, reporter: Arc<dyn ProgressReporter>) -> Self {
+        self.progress = Some(reporter);
+        self
+    }
+
     /// Allows to smooth the provided estimates. Returns the smoothed estimates or an error.
     ///
     /// Estimates must be ordered in chronological order. This function will smooth the
@@ -294,6 +309,7 @@ where
                 trigger.reset();
             }
 
+            let _span = info_span!("od_iteration", iteration = iter_cnt).entered();
             info!("***************************");
             info!("*** Iteration number {iter_cnt:02} ***");
             info!("***************************");
@@ -309,9 +325,20 @@ where
             self.kf.set_previous_estimate(&smoothed[0]);
             // And re-run the filter
             self.process_arc(arc)?;
+            if self.prop.prop.is_cancelled() {
+                warn!("Filter iterations cancelled during iteration {iter_cnt}");
+                break;
+            }
 
             // Compute the new RMS
             let new_rms = self.rms_residual_ratios();
+            info!(
+                iteration = iter_cnt,
+                residual_rms = new_rms,
+                previous_rms,
+                best_rms,
+                "OD iteration complete"
+            );
             let cur_rms_num = (new_rms - previous_rms).abs();
             let cur_rel_rms = cur_rms_num / previous_rms;
             if cur_rel_rms < config.relative_tol || cur_rms_num < config.absolute_tol * best_rms {
@@ -352,6 +379,8 @@ where
                     if config.force_failure {
                         return Err(ODError::Diverged {
                             loops: config.max_divergences,
+                            residual_rms: new_rms,
+                            best_rms,
                         });
                     } else {
                         error!("{}", msg);
@@ -379,8 +408,10 @@ where
                     config.max_iterations, config
                 );
                 if config.force_failure {
-                    return Err(ODError::Diverged {
-                        loops: config.max_divergences,
+                    return Err(ODError::NotConverged {
+                        iterations: iter_cnt,
+                        residual_rms: new_rms,
+                        best_rms,
                     });
                 } else {
                     error!("{}", msg);
@@ -398,8 +429,40 @@ where
     /// + The measurements must be a list mapping the name of the measurement device to the measurement itself.
     /// + The name of all measurement devices must be present in the provided devices, i.e. the key set of `devices` must be a superset of the measurement device names present in the list.
     /// + The maximum step size to ensure we don't skip any measurements.
-    #[allow(clippy::erasing_op)]
+    ///
+    /// If the propagator has a cancellation token, the processing stops cleanly once it is cancelled, and the estimates
+    /// and residuals processed until then are kept.
     pub fn process_arc(&mut self, arc: &TrackingDataArc) -> Result<(), ODError> {
+        self.process_arc_with_sink(arc, &mut |_| Ok(()))
+    }
+
+    /// Processes the arc, calling the sink before each propagation step so that it may drain the estimates and residuals.
+    fn process_arc_with_sink(
+        &mut self,
+        arc: &TrackingDataArc,
+        sink: &mut dyn FnMut(&mut Self) -> Result<(), ODError>,
+    ) -> Result<(), ODError> {
+        let reporter = self.progress.clone();
+        if let Some(reporter) = &reporter {
+            reporter.start(&format!(
+                "Processing {} measurements",
+                arc.measurements.len()
+            ));
+        }
+        let result = self.process_measurements(arc, reporter.as_deref(), sink);
+        if let Some(reporter) = &reporter {
+            reporter.finish();
+        }
+        result
+    }
+
+    #[allow(clippy::erasing_op)]
+    fn process_measurements(
+        &mut self,
+        arc: &TrackingDataArc,
+        reporter: Option<&dyn ProgressReporter>,
+        sink: &mut dyn FnMut(&mut Self) -> Result<(), ODError>,
+    ) -> Result<(), ODError> {
         let measurements = &arc.measurements;
         ensure!(
             measurements.len() >= 2,
@@ -445,6 +508,7 @@ where
         }
 
         let prop_time = arc.end_epoch().unwrap() - self.kf.previous_estimate().epoch();
+        let _span = info_span!("process_arc", num_msrs, %prop_time).entered();
         info!("Navigation propagating for a total of {prop_time} with step size {max_step}");
 
         let mut epoch = self.prop.state.epoch();
@@ -459,11 +523,21 @@ where
         let mut msr_accepted_cnt: usize = 0;
         let tick = Epoch::now().unwrap();
 
-        for (msr_cnt, (epoch_ref, msr)) in measurements.iter().enumerate() {
+        let mut cancelled = false;
+        'msrs: for (msr_cnt, (epoch_ref, msr)) in measurements.iter().enumerate() {
             let next_msr_epoch = *epoch_ref;
 
             // Advance the propagator
             loop {
+                if self.prop.prop.is_cancelled() {
+                    // Keep the estimates and residuals processed so far
+                    warn!("Orbit determination cancelled at {epoch} after {msr_cnt} of {num_msrs} measurements");
+                    cancelled = true;
+                    break 'msrs;
+                }
+
+                sink(self)?;
+
                 let delta_t = next_msr_epoch - epoch;
 
                 // Propagator for the minimum time between the maximum step size, the next step size, and the duration to the next measurement.
@@ -481,10 +555,15 @@ where
                 traj.states.truncate(index);
 
                 debug!("propagate for {next_step_size} (Δt to next msr: {delta_t})");
-                let (_, traj_covar) = self
-                    .prop
-                    .for_duration_with_traj(next_step_size)
-                    .context(ODPropSnafu)?;
+                let (_, traj_covar) = match self.prop.for_duration_with_traj(next_step_size) {
+                    Err(PropagationError::Cancelled { epoch }) => {
+                        // Keep the estimates and residuals processed so far
+                        warn!("Orbit determination cancelled at {epoch} after {msr_cnt} of {num_msrs} measurements");
+                        cancelled = true;
+                        break 'msrs;
+                    }
+                    result => result.context(ODPropSnafu)?,
+                };
 
                 for state in traj_covar.states {
                     // NOTE: At the time being, only spacecraft estimation is possible, and the trajectory will always be the exact state
@@ -504,6 +583,8 @@ where
                     // Get the computed observations
                     match self.devices.get_mut(&msr.tracker) {
                         Some(device) => {
+                            let _scope =
+                                profiling::scope(Subsystem::MeasurementUpdate, || device.name());
                             if let Some(computed_meas) =
                                 device.measure(epoch, &traj, None, self.almanac.clone())?
                             {
@@ -568,7 +649,17 @@ where
                                         self.resid_crit,
                                     ) {
                                         Ok((estimate, mut residual)) => {
-                                            debug!("processed measurement #{msr_cnt} for {cur_msr_types:?} @ {epoch} from {}", device.name());
+                                            debug!(
+                                                msr = msr_cnt,
+                                                tracker = %device.name(),
+                                                %epoch,
+                                                ratio = residual.ratio,
+                                                prefit_norm = residual.prefit.norm(),
+                                                postfit_norm = residual.postfit.norm(),
+                                                rejected = residual.rejected,
+                                                "processed measurement #{msr_cnt} for {cur_msr_types:?} @ {epoch} from {}",
+                                                device.name()
+                                            );
 
                                             residual.tracker = Some(device.name());
                                             residual.msr_types = cur_msr_types;
@@ -622,6 +713,13 @@ where
                         }
                     }
 
+                    if let Some(reporter) = reporter {
+                        reporter.report(Progress::new(
+                            (msr_cnt + 1) as f64 / (num_msrs as f64),
+                            Some(epoch),
+                        ));
+                    }
+
                     let msr_prct = (10.0 * (msr_cnt as f64) / (num_msrs as f64)) as usize;
                     if !reported[msr_prct] {
                         let num_rejected = msr_cnt - msr_accepted_cnt.saturating_sub(1);
@@ -657,7 +755,7 @@ where
         }
 
         // Always report the 100% mark
-        if !reported[10] {
+        if !reported[10] && !cancelled {
             let tock_time = Epoch::now().unwrap() - tick;
             info!(
                 "100% done - {msr_accepted_cnt:.0} measurements accepted, {:.0} rejected (done in {tock_time})",
@@ -673,9 +771,13 @@ where
         let prop_time = end_epoch - self.kf.previous_estimate().epoch();
         info!("Mapping covariance for {prop_time} with {step} step");
 
+        // Step in the direction of the end epoch, which may be in the past.
+        let backward = end_epoch < self.prop.state.epoch();
+        let step = if backward { -step.abs() } else { step.abs() };
+
         loop {
             let mut epoch = self.prop.state.epoch();
-            if epoch + self.prop.details.step > end_epoch {
+            if (!backward && epoch + step > end_epoch) || (backward && epoch + step < end_epoch) {
                 self.prop.until_epoch(end_epoch).context(ODPropSnafu)?;
             } else {
                 self.prop.for_duration(step).context(ODPropSnafu)?;
@@ -729,7 +831,7 @@ where
                     .iter()
                     .map(|est| est.nominal_state())
                     .collect(),
-                name: None,
+                ..Default::default()
             })
         }
     }
@@ -776,6 +878,7 @@ where
             ekf_trigger: None,
             init_state,
             almanac,
+            progress: None,
             _marker: PhantomData::<Accel>,
         }
     }
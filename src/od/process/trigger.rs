@@ -23,8 +23,9 @@ use crate::linalg::allocator::Allocator;
 use crate::linalg::DefaultAllocator;
 use crate::time::Duration;
 use crate::State;
+use serde_derive::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
 /// An EkfTrigger on the number of measurements processed and a time between measurements.
 pub struct EkfTrigger {
     pub num_msrs: usize,
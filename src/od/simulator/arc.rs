@@ -19,16 +19,17 @@
 use anise::almanac::Almanac;
 use hifitime::{Duration, Epoch, TimeSeries, TimeUnits};
 use num::integer::gcd;
-use rand::SeedableRng;
 use rand_pcg::Pcg64Mcg;
 
 use crate::dynamics::NyxError;
 use crate::io::ConfigError;
+use crate::mc::stream_rng;
 use crate::md::trajectory::Interpolatable;
 use crate::od::msr::TrackingDataArc;
 use crate::od::prelude::Strand;
 use crate::od::simulator::Cadence;
 use crate::od::GroundStation;
+use crate::progress::{Progress, ProgressReporter};
 use crate::Spacecraft;
 use crate::State;
 use crate::{linalg::allocator::Allocator, od::TrackingDevice};
@@ -56,6 +57,8 @@ where
     pub trajectory: Traj<MsrIn>,
     /// Configuration of each device
     pub configs: BTreeMap<String, TrkConfig>,
+    /// Reports the progress of the simulation of the measurements, if any
+    pub progress: Option<Arc<dyn ProgressReporter>>,
     /// Random number generator used for this tracking arc, ensures repeatability
     rng: Pcg64Mcg,
     /// Greatest common denominator time series that allows this arc to meet all of the conditions.
@@ -117,6 +120,7 @@ where
             devices,
             trajectory,
             configs,
+            progress: None,
             rng,
             time_series,
             _msr_in: PhantomData,
@@ -139,17 +143,23 @@ where
         Self::with_rng(devices, trajectory, configs, rng)
     }
 
-    /// Build a new tracking arc simulator using the system entropy to seed the random number generator.
+    /// Build a new tracking arc simulator seeded from the master seed of the [RunConfig], or from the system entropy if none is set.
     pub fn new(
         devices: BTreeMap<String, D>,
         trajectory: Traj<MsrIn>,
         configs: BTreeMap<String, TrkConfig>,
     ) -> Result<Self, ConfigError> {
-        let rng = Pcg64Mcg::from_entropy();
+        let rng = stream_rng("tracking_arc");
 
         Self::with_rng(devices, trajectory, configs, rng)
     }
 
+    /// Reports the progress of the simulation of the measurements to this reporter.
+    pub fn with_progress(mut self, reporter: Arc<dyn ProgressReporter>) -> Self {
+        self.progress = Some(reporter);
+        self
+    }
+
     /// Generates measurements for the tracking arc using the defined strands
     ///
     /// # Warning
@@ -170,7 +180,16 @@ where
     ) -> Result<TrackingDataArc, NyxError> {
         let mut measurements = BTreeMap::new();
 
-        for (name, device) in self.devices.iter_mut() {
+        let reporter = self.progress.clone();
+        if let Some(reporter) = &reporter {
+            reporter.start(&format!(
+                "Simulating measurements of {} devices",
+                self.devices.len()
+            ));
+        }
+        let num_devices = self.devices.len() as f64;
+
+        for (dev_idx, (name, device)) in self.devices.iter_mut().enumerate() {
             if let Some(cfg) = self.configs.get(name) {
                 if cfg.scheduler.is_some() {
                     if cfg.strands.is_none() {
@@ -195,6 +214,16 @@ where
                             for epoch in
                                 TimeSeries::inclusive(strand.start, strand.end, cfg.sampling)
                             {
+                                if let Some(reporter) = &reporter {
+                                    let strand_fraction =
+                                        Progress::between(strand.start, strand.end, epoch).fraction;
+                                    reporter.report(Progress::new(
+                                        (dev_idx as f64
+                                            + (ii as f64 + strand_fraction) / strands.len() as f64)
+                                            / num_devices,
+                                        Some(epoch),
+                                    ));
+                                }
                                 match device.measure(
                                     epoch,
                                     &self.trajectory,
@@ -233,6 +262,10 @@ where
             }
         }
 
+        if let Some(reporter) = &reporter {
+            reporter.finish();
+        }
+
         // Build the tracking arc.
         let trk_data = TrackingDataArc {
             measurements,
@@ -60,12 +60,17 @@ pub struct Scheduler {
     /// is set to 01 seconds, then this will cause the tracking to start at 01:02:03 as it is rounded to the nearest second.
     #[builder(default = Some(Unit::Second * 1.0), setter(strip_option))]
     #[serde(
+        default = "default_sample_alignment",
         serialize_with = "maybe_duration_to_str",
         deserialize_with = "maybe_duration_from_str"
     )]
     pub sample_alignment: Option<Duration>,
 }
 
+fn default_sample_alignment() -> Option<Duration> {
+    Some(Unit::Second * 1.0)
+}
+
 /// Determines whether tracking is continuous or intermittent.
 #[derive(Copy, Clone, Deserialize, PartialEq, Serialize)]
 pub enum Cadence {
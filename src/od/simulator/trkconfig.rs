@@ -270,4 +270,66 @@ mod trkconfig_ut {
 
         assert_eq!(cfg.sampling, 60.seconds());
     }
+
+    #[test]
+    fn serde_round_trip() {
+        let cfgs = [
+            TrkConfig::builder()
+                .sampling(10.5.seconds())
+                .scheduler(
+                    Scheduler::builder()
+                        .handoff(Handoff::Greedy)
+                        .cadence(Cadence::Intermittent {
+                            on: 2.hours() + 30.seconds(),
+                            off: 21.5.hours(),
+                        })
+                        .min_samples(42)
+                        .sample_alignment(2.5.seconds())
+                        .build(),
+                )
+                .build(),
+            TrkConfig {
+                scheduler: Some(Scheduler {
+                    sample_alignment: None,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            TrkConfig {
+                scheduler: None,
+                sampling: 10.seconds(),
+                strands: Some(vec![Strand {
+                    start: Epoch::from_gregorian_utc_hms(2024, 1, 1, 0, 0, 0),
+                    end: Epoch::from_gregorian_utc_hms(2024, 1, 1, 1, 0, 0),
+                }]),
+            },
+        ];
+
+        for cfg in cfgs {
+            let yaml = serde_yml::to_string(&cfg).unwrap();
+            println!("{yaml}");
+            assert_eq!(serde_yml::from_str::<TrkConfig>(&yaml).unwrap(), cfg);
+
+            let json = serde_json::to_string(&cfg).unwrap();
+            assert_eq!(serde_json::from_str::<TrkConfig>(&json).unwrap(), cfg);
+        }
+
+        // An omitted alignment matches the builder default, an explicit null disables it, and an invalid one is rejected.
+        let yaml = "sampling: 1 min\nscheduler:\n  handoff: Eager\n  cadence: Continuous\n  min_samples: 10\n";
+        let cfg = serde_yml::from_str::<TrkConfig>(yaml).unwrap();
+        assert_eq!(
+            cfg.scheduler.unwrap().sample_alignment,
+            Scheduler::builder().build().sample_alignment
+        );
+        assert_eq!(cfg.scheduler, Some(Scheduler::builder().build()));
+
+        let cfg =
+            serde_yml::from_str::<TrkConfig>(&format!("{yaml}  sample_alignment: null\n")).unwrap();
+        assert_eq!(cfg.scheduler.unwrap().sample_alignment, None);
+
+        assert!(
+            serde_yml::from_str::<TrkConfig>(&format!("{yaml}  sample_alignment: often\n"))
+                .is_err()
+        );
+    }
 }
@@ -0,0 +1,287 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::time::Duration;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+static PROFILER: Profiler = Profiler::new();
+
+/// Subsystem to which the profiled wall time is attributed.
+///
+/// The Almanac queries are also accounted for in the force models which perform them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Subsystem {
+    /// Evaluation of an acceleration or force model, e.g. the spherical harmonics or the solar radiation pressure
+    ForceModel,
+    /// Evaluation of the equations of motion at a stage of the Runge Kutta integrator
+    IntegratorStage,
+    /// Time and measurement updates of the orbit determination filter
+    MeasurementUpdate,
+    /// Ephemeris and orientation queries to the Almanac
+    AlmanacQuery,
+}
+
+impl fmt::Display for Subsystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ForceModel => write!(f, "force model"),
+            Self::IntegratorStage => write!(f, "integrator stage"),
+            Self::MeasurementUpdate => write!(f, "measurement update"),
+            Self::AlmanacQuery => write!(f, "almanac query"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct Record {
+    calls: u64,
+    total: std::time::Duration,
+    max: std::time::Duration,
+}
+
+/// Accumulator of the profiled wall times. The process uses a single profiler, cf. [enable] and [report].
+struct Profiler {
+    enabled: AtomicBool,
+    records: Mutex<BTreeMap<(Subsystem, String), Record>>,
+}
+
+impl Profiler {
+    const fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            records: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn scope<F: FnOnce() -> String>(&self, subsystem: Subsystem, name: F) -> Option<Scope<'_>> {
+        if self.enabled.load(Ordering::Relaxed) {
+            Some(Scope {
+                profiler: self,
+                subsystem,
+                name: name(),
+                start: Instant::now(),
+            })
+        } else {
+            None
+        }
+    }
+
+    fn report(&self) -> ProfileReport {
+        let entries = self
+            .records
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((subsystem, name), record)| ProfileEntry {
+                subsystem: *subsystem,
+                name: name.clone(),
+                calls: record.calls,
+                total: record.total.into(),
+                max: record.max.into(),
+            })
+            .collect();
+        ProfileReport { entries }
+    }
+}
+
+/// Enables the accumulation of the wall time spent in each profiled subsystem, for all of the computations of this process.
+///
+/// Profiling is disabled by default, and costs a single atomic load per profiled call when disabled.
+pub fn enable() {
+    PROFILER.enabled.store(true, Ordering::Relaxed);
+}
+
+/// Disables profiling, keeping the wall times accumulated so far.
+pub fn disable() {
+    PROFILER.enabled.store(false, Ordering::Relaxed);
+}
+
+/// Returns whether the wall times are currently accumulated, cf. [enable].
+pub fn is_enabled() -> bool {
+    PROFILER.enabled.load(Ordering::Relaxed)
+}
+
+/// Clears the wall times accumulated so far.
+pub fn reset() {
+    PROFILER.records.lock().unwrap().clear();
+}
+
+/// Returns the wall times accumulated since profiling was enabled or last reset.
+pub fn report() -> ProfileReport {
+    PROFILER.report()
+}
+
+/// Starts timing a call to `subsystem`, and records its wall time when the returned guard is dropped.
+///
+/// The name is only built if profiling is enabled.
+pub(crate) fn scope<F: FnOnce() -> String>(
+    subsystem: Subsystem,
+    name: F,
+) -> Option<Scope<'static>> {
+    PROFILER.scope(subsystem, name)
+}
+
+/// Times the Almanac query named `query`, e.g. the transformation of the spacecraft state into the frame of a force model.
+pub(crate) fn almanac_query<T, F: FnOnce() -> T>(query: &'static str, f: F) -> T {
+    let _scope = scope(Subsystem::AlmanacQuery, || query.to_string());
+    f()
+}
+
+pub(crate) struct Scope<'a> {
+    profiler: &'a Profiler,
+    subsystem: Subsystem,
+    name: String,
+    start: Instant,
+}
+
+impl Drop for Scope<'_> {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        let mut records = self.profiler.records.lock().unwrap();
+        let record = records
+            .entry((self.subsystem, std::mem::take(&mut self.name)))
+            .or_default();
+        record.calls += 1;
+        record.total += elapsed;
+        record.max = record.max.max(elapsed);
+    }
+}
+
+/// Wall time accumulated by a profiled model or operation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProfileEntry {
+    /// Subsystem to which this wall time is attributed
+    pub subsystem: Subsystem,
+    /// Name of the model or operation, e.g. the display of a force model
+    pub name: String,
+    /// Number of profiled calls
+    pub calls: u64,
+    /// Wall time accumulated over all of the calls
+    pub total: Duration,
+    /// Longest single call
+    pub max: Duration,
+}
+
+impl ProfileEntry {
+    /// Mean wall time of a single call
+    pub fn mean(&self) -> Duration {
+        if self.calls == 0 {
+            Duration::ZERO
+        } else {
+            self.total / self.calls as f64
+        }
+    }
+}
+
+/// Summary of the wall time spent in each profiled subsystem, displayed as a table.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProfileReport {
+    /// Accumulated wall times, sorted by subsystem and name
+    pub entries: Vec<ProfileEntry>,
+}
+
+impl ProfileReport {
+    /// Total wall time spent in the provided subsystem
+    pub fn total(&self, subsystem: Subsystem) -> Duration {
+        self.entries
+            .iter()
+            .filter(|entry| entry.subsystem == subsystem)
+            .fold(Duration::ZERO, |total, entry| total + entry.total)
+    }
+
+    /// Returns the wall time accumulated by the model or operation `name` of the provided subsystem, if it was profiled
+    pub fn get(&self, subsystem: Subsystem, name: &str) -> Option<&ProfileEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.subsystem == subsystem && entry.name == name)
+    }
+}
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .entries
+            .iter()
+            .map(|entry| entry.name.len())
+            .max()
+            .unwrap_or(0)
+            .max(4);
+        writeln!(
+            f,
+            "{:<18} {:<width$} {:>10} {:>12} {:>12} {:>12} {:>7}",
+            "Subsystem", "Name", "Calls", "Total (ms)", "Mean (us)", "Max (us)", "Share"
+        )?;
+        for entry in &self.entries {
+            // The share is relative to the subsystem because the Almanac queries are nested in the force models
+            let subsystem_total = self.total(entry.subsystem).to_seconds();
+            let share = if subsystem_total > 0.0 {
+                100.0 * entry.total.to_seconds() / subsystem_total
+            } else {
+                0.0
+            };
+            writeln!(
+                f,
+                "{:<18} {:<width$} {:>10} {:>12.3} {:>12.3} {:>12.3} {:>6.1}%",
+                entry.subsystem.to_string(),
+                entry.name,
+                entry.calls,
+                entry.total.to_seconds() * 1e3,
+                entry.mean().to_seconds() * 1e6,
+                entry.max.to_seconds() * 1e6,
+                share
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod ut_profiling {
+    use super::*;
+
+    #[test]
+    fn accumulate() {
+        // A dedicated profiler, independent of the profiler of the process
+        let profiler = Profiler::new();
+
+        // Disabled by default: nothing is recorded and the name is not built
+        assert!(profiler
+            .scope(Subsystem::ForceModel, || unreachable!())
+            .is_none());
+
+        profiler.enabled.store(true, Ordering::Relaxed);
+        for _ in 0..3 {
+            let _scope = profiler.scope(Subsystem::AlmanacQuery, || "ut_profiling".to_string());
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        let report = profiler.report();
+        assert_eq!(report.entries.len(), 1);
+        let entry = report.get(Subsystem::AlmanacQuery, "ut_profiling").unwrap();
+        assert_eq!(entry.calls, 3);
+        assert!(entry.total >= Duration::from_milliseconds(3.0));
+        assert!(entry.max <= entry.total);
+        assert!(entry.mean() >= Duration::from_milliseconds(1.0));
+        assert_eq!(report.total(Subsystem::AlmanacQuery), entry.total);
+        assert!(format!("{report}").contains("ut_profiling"));
+    }
+}
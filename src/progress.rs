@@ -0,0 +1,226 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::mc::McProgress;
+use crate::time::Epoch;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Progress of a long running computation, e.g. a propagation or an orbit determination arc.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Progress {
+    /// Fraction of the computation completed, between 0 and 1
+    pub fraction: f64,
+    /// Current epoch of the simulation, if the computation advances in time
+    pub epoch: Option<Epoch>,
+}
+
+impl Progress {
+    pub fn new(fraction: f64, epoch: Option<Epoch>) -> Self {
+        Self {
+            fraction: fraction.clamp(0.0, 1.0),
+            epoch,
+        }
+    }
+
+    /// Progress of a computation advancing from `start` to `end`, currently at `epoch`.
+    pub fn between(start: Epoch, end: Epoch, epoch: Epoch) -> Self {
+        let total = (end - start).to_seconds();
+        let fraction = if total.abs() > 0.0 {
+            (epoch - start).to_seconds() / total
+        } else {
+            1.0
+        };
+        Self::new(fraction, Some(epoch))
+    }
+
+    /// Percentage of the computation completed, between 0 and 100
+    pub fn percent(&self) -> f64 {
+        100.0 * self.fraction
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>5.1}%", self.percent())?;
+        if let Some(epoch) = self.epoch {
+            write!(f, " @ {epoch}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Receives the progress of long running computations: propagations, tracking arc simulations, orbit determination arcs, and Monte Carlo runs.
+///
+/// Reporters are shared as `Arc<dyn ProgressReporter>` and may be called from the worker threads of the thread pool.
+pub trait ProgressReporter: Send + Sync + fmt::Debug {
+    /// Called when a computation starts, with a short description of that computation.
+    fn start(&self, _task: &str) {}
+
+    /// Called every time the computation advances.
+    fn report(&self, progress: Progress);
+
+    /// Called every time a run of a Monte Carlo completes, with the number of completed and failed runs.
+    ///
+    /// By default, this reports the fraction of the completed runs.
+    fn report_runs(&self, progress: McProgress) {
+        self.report(Progress::new(progress.fraction(), None));
+    }
+
+    /// Called when the computation completes.
+    fn finish(&self) {}
+}
+
+/// Reports the progress to the `log` facade at the `info` level, every time another tenth of the computation completes.
+#[derive(Debug, Default)]
+pub struct LogReporter {
+    last_tenth: AtomicUsize,
+}
+
+impl ProgressReporter for LogReporter {
+    fn start(&self, task: &str) {
+        self.last_tenth.store(0, Ordering::Relaxed);
+        info!("{task}");
+    }
+
+    fn report(&self, progress: Progress) {
+        let tenth = (progress.fraction * 10.0).floor() as usize;
+        let prev = self.last_tenth.fetch_max(tenth, Ordering::Relaxed);
+        if tenth > prev {
+            info!("{progress}");
+        }
+    }
+}
+
+/// Terminal progress bar of the [indicatif](https://docs.rs/indicatif) crate.
+#[cfg(feature = "progress-bar")]
+#[derive(Debug)]
+pub struct ProgressBarReporter {
+    bar: indicatif::ProgressBar,
+}
+
+#[cfg(feature = "progress-bar")]
+impl ProgressBarReporter {
+    /// Resolution of the progress bar
+    const STEPS: u64 = 1000;
+
+    pub fn new() -> Self {
+        let bar = indicatif::ProgressBar::new(Self::STEPS);
+        bar.set_style(
+            indicatif::ProgressStyle::default_bar()
+                .template("[{elapsed_precise}] {prefix} {bar:80.cyan/blue} {percent:>3}% {msg}")
+                .unwrap()
+                .progress_chars("##-"),
+        );
+        Self { bar }
+    }
+}
+
+#[cfg(feature = "progress-bar")]
+impl Default for ProgressBarReporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "progress-bar")]
+impl ProgressReporter for ProgressBarReporter {
+    fn start(&self, task: &str) {
+        self.bar.reset();
+        self.bar.set_prefix(task.to_string());
+    }
+
+    fn report(&self, progress: Progress) {
+        self.bar
+            .set_position((progress.fraction * Self::STEPS as f64).round() as u64);
+        if let Some(epoch) = progress.epoch {
+            self.bar.set_message(format!("{epoch}"));
+        }
+    }
+
+    fn finish(&self) {
+        self.bar.finish();
+    }
+}
+
+/// A token to cooperatively cancel long running computations, which may be cloned and shared with other threads, e.g. with a GUI or a service.
+///
+/// The computations check the token between their steps, and stop cleanly, returning their partial results where possible.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests the cancellation of the computations sharing this token
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Builds the default reporter: a progress bar if the `progress-bar` feature is enabled, else nothing.
+pub(crate) fn default_reporter() -> Option<Arc<dyn ProgressReporter>> {
+    #[cfg(feature = "progress-bar")]
+    {
+        Some(Arc::new(ProgressBarReporter::new()))
+    }
+    #[cfg(not(feature = "progress-bar"))]
+    {
+        None
+    }
+}
+
+#[cfg(test)]
+mod ut_progress {
+    use super::*;
+    use crate::time::Unit;
+
+    #[test]
+    fn progress_between() {
+        let start = Epoch::from_gregorian_utc_at_midnight(2024, 1, 1);
+        let end = start + Unit::Hour * 4;
+
+        let progress = Progress::between(start, end, start + Unit::Hour);
+        assert!((progress.percent() - 25.0).abs() < 1e-12);
+        assert_eq!(progress.epoch, Some(start + Unit::Hour));
+
+        // Backward propagation still advances from zero to one
+        let progress = Progress::between(end, start, end - Unit::Hour * 3);
+        assert!((progress.fraction - 0.75).abs() < 1e-12);
+
+        assert_eq!(Progress::between(start, start, start).fraction, 1.0);
+        assert_eq!(Progress::new(1.5, None).fraction, 1.0);
+    }
+
+    #[test]
+    fn log_reporter_tenths() {
+        let reporter = LogReporter::default();
+        reporter.start("test");
+        for i in 0..=100 {
+            reporter.report(Progress::new(i as f64 / 100.0, None));
+        }
+        assert_eq!(reporter.last_tenth.load(Ordering::Relaxed), 10);
+    }
+}
@@ -0,0 +1,145 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::IntegrationDetails;
+use crate::io::{ConfigError, ConfigRepr, SerializeSnafu, WriteSnafu};
+use crate::linalg::allocator::Allocator;
+use crate::linalg::{DefaultAllocator, DimName, OVector};
+use crate::time::Duration;
+use crate::State;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// A checkpoint of a propagation, from which the propagation may be resumed later or on another machine
+/// with [super::Propagator::resume].
+///
+/// It stores the state (including its guidance mode), its full propagation vector (including the STM, if set),
+/// and the integrator step size and details, such that the resumed propagation takes the same steps.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(serialize = "S: Serialize", deserialize = "S: DeserializeOwned"))]
+pub struct PropCheckpoint<S: State>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    /// State of the propagation at the checkpoint
+    pub state: S,
+    /// Propagation vector of the state, which also includes the STM if it is set
+    pub vector: Vec<f64>,
+    /// Whether the STM of the state is set
+    pub stm: bool,
+    /// Step size the integrator will use for its next step, as a magnitude
+    pub step_size: Duration,
+    /// Whether the integrator uses a fixed step size
+    pub fixed_step: bool,
+    /// Details of the last integration step
+    pub details: IntegrationDetails,
+}
+
+impl<S: State> PropCheckpoint<S>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    /// Rebuilds the state of this checkpoint, including its STM.
+    pub fn restored_state(&self) -> Result<S, ConfigError> {
+        if self.vector.len() != S::VecLength::dim() {
+            return Err(ConfigError::InvalidConfig {
+                msg: format!(
+                    "checkpoint vector has {} components but the state requires {}",
+                    self.vector.len(),
+                    S::VecLength::dim()
+                ),
+            });
+        }
+
+        let mut state = self.state;
+        if self.stm {
+            state.reset_stm();
+        }
+        state.set(
+            self.state.epoch(),
+            &OVector::<f64, S::VecLength>::from_column_slice(&self.vector),
+        );
+        Ok(state)
+    }
+}
+
+impl<S: State + Serialize + DeserializeOwned> PropCheckpoint<S>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    /// Saves this checkpoint to the provided path as YAML, which can be loaded with [ConfigRepr::load].
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
+        let file = File::create(path).context(WriteSnafu)?;
+        serde_yml::to_writer(BufWriter::new(file), self).context(SerializeSnafu)
+    }
+}
+
+impl<S: State + Serialize + DeserializeOwned + Debug> ConfigRepr for PropCheckpoint<S> where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>
+{
+}
+
+#[cfg(test)]
+mod ut_checkpoint {
+    use super::PropCheckpoint;
+    use crate::cosmic::{GuidanceMode, Spacecraft};
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::io::ConfigRepr;
+    use crate::propagators::Propagator;
+    use crate::time::TimeUnits;
+    use crate::State;
+
+    #[test]
+    fn save_and_resume() {
+        let orbit = fixtures::keplerian(8_000.0, 0.2, 28.5, 30.0, 45.0, 10.0);
+        let mut sc = Spacecraft::builder().orbit(orbit).build().with_stm();
+        sc.mut_mode(GuidanceMode::Inhibit);
+        let almanac = fixtures::almanac();
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+
+        let mut instance = prop.with(sc, almanac.clone());
+        instance.for_duration(1.hours()).unwrap();
+        let checkpoint = instance.checkpoint();
+        assert!(checkpoint.stm);
+
+        let path = std::env::temp_dir().join("nyx_ut_checkpoint.yaml");
+        checkpoint.save(&path).unwrap();
+        let loaded = PropCheckpoint::<Spacecraft>::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // The resumed propagation is identical to the uninterrupted one, including the STM and guidance mode
+        let expected = instance.for_duration(1.hours()).unwrap();
+        let mut resumed = prop.resume(&loaded, almanac).unwrap();
+        assert_eq!(resumed.step_size, checkpoint.step_size);
+        let end = resumed.for_duration(1.hours()).unwrap();
+        assert_eq!(end, expected);
+        assert_eq!(end.stm().unwrap(), expected.stm().unwrap());
+        assert_eq!(end.mode(), GuidanceMode::Inhibit);
+
+        // A checkpoint of another kind of state is rejected
+        let mut invalid = loaded;
+        invalid.vector.truncate(9);
+        assert!(prop.resume(&invalid, fixtures::almanac()).is_err());
+    }
+}
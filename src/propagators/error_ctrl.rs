@@ -192,6 +192,73 @@ impl Default for ErrorControl {
     }
 }
 
+/// Number of leading state components which may have their own tolerances, e.g. the position, velocity, Cr, Cd, and propellant mass of a spacecraft.
+pub const MAX_TOLERANCE_COMPONENTS: usize = 9;
+
+/// Absolute and relative tolerances for each of the leading components of the state, e.g. tight on the position and loose on the propellant mass.
+///
+/// The error of component `i` is scaled by `abs_tol[i] + rel_tol[i] * max(|y_i|, |y_new_i|)`, and the step is accepted if the RMS
+/// of the scaled errors is at most one. A component with an infinite absolute tolerance does not contribute to the error, and
+/// neither do the components beyond [MAX_TOLERANCE_COMPONENTS], such as the STM.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ComponentTolerances {
+    pub abs_tol: [f64; MAX_TOLERANCE_COMPONENTS],
+    pub rel_tol: [f64; MAX_TOLERANCE_COMPONENTS],
+}
+
+impl ComponentTolerances {
+    /// Initializes the same absolute and relative tolerances for all components.
+    pub fn new(abs_tol: f64, rel_tol: f64) -> Self {
+        Self {
+            abs_tol: [abs_tol; MAX_TOLERANCE_COMPONENTS],
+            rel_tol: [rel_tol; MAX_TOLERANCE_COMPONENTS],
+        }
+    }
+
+    /// Initializes the tolerances of a spacecraft state: position (km), velocity (km/s), and propellant mass (kg),
+    /// all with the same relative tolerance. The Cr and Cd do not contribute to the error.
+    pub fn spacecraft(pos_km: f64, vel_km_s: f64, prop_mass_kg: f64, rel_tol: f64) -> Self {
+        let mut me = Self::new(pos_km, rel_tol);
+        for i in 3..6 {
+            me.abs_tol[i] = vel_km_s;
+        }
+        me.abs_tol[6] = f64::INFINITY;
+        me.abs_tol[7] = f64::INFINITY;
+        me.abs_tol[8] = prop_mass_kg;
+        me
+    }
+
+    /// Sets the tolerances of the component at the provided index of the state vector.
+    ///
+    /// # Panics
+    /// If the index is not less than [MAX_TOLERANCE_COMPONENTS].
+    pub fn with_component(mut self, index: usize, abs_tol: f64, rel_tol: f64) -> Self {
+        self.abs_tol[index] = abs_tol;
+        self.rel_tol[index] = rel_tol;
+        self
+    }
+
+    /// Computes the RMS of the scaled errors of the step, which is acceptable if it is at most one.
+    pub fn estimate<N: DimName>(
+        &self,
+        error_est: &OVector<f64, N>,
+        candidate: &OVector<f64, N>,
+        cur_state: &OVector<f64, N>,
+    ) -> f64
+    where
+        DefaultAllocator: Allocator<N>,
+    {
+        let n = N::dim().min(MAX_TOLERANCE_COMPONENTS);
+        let mut sum = 0.0;
+        for i in 0..n {
+            let scale =
+                self.abs_tol[i] + self.rel_tol[i] * candidate[i].abs().max(cur_state[i].abs());
+            sum += (error_est[i] / scale).powi(2);
+        }
+        (sum / n as f64).sqrt()
+    }
+}
+
 /// An RSS step error control which effectively computes the L2 norm of the provided Vector of size 3
 ///
 /// Note that this error controller should be preferably be used only with slices of a state with the same units.
@@ -16,25 +16,39 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use super::{DynamicsSnafu, IntegrationDetails, PropagationError, Propagator};
+use super::{
+    DynamicsSnafu, IntegrationDetails, PropCheckpoint, PropConfigSnafu, PropagationError,
+    Propagator, StopCondition, PREDICATE_EPOCH_PRECISION,
+};
 use crate::dynamics::{Dynamics, DynamicsAlmanacSnafu};
+use crate::io::ConfigError;
 use crate::linalg::allocator::Allocator;
 use crate::linalg::{DefaultAllocator, OVector};
-use crate::md::trajectory::{Interpolatable, Traj};
+use crate::md::trajectory::{DenseStep, ExportCfg, Interpolatable, Traj, TrajWriter};
 use crate::md::EventEvaluator;
+use crate::profiling::{self, Subsystem};
+use crate::progress::{Progress, ProgressReporter};
 use crate::propagators::TrajectoryEventSnafu;
 use crate::time::{Duration, Epoch, Unit};
 use crate::State;
 use anise::almanac::Almanac;
 use anise::errors::MathError;
+use anise::prelude::Frame;
 use rayon::iter::ParallelBridge;
 use rayon::prelude::ParallelIterator;
 use snafu::ResultExt;
+use std::error::Error;
 use std::f64;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{channel, Sender};
 use std::sync::Arc;
+use std::thread;
 #[cfg(not(target_arch = "wasm32"))]
 use std::time::Instant;
+use tracing::{debug, info, info_span, trace, warn};
+
+/// Epoch precision to which a switch of the dynamics, e.g. of guidance mode, is located within a step.
+pub const SWITCH_EPOCH_PRECISION: Duration = Duration::from_milliseconds(1.0);
 
 /// A Propagator allows propagating a set of dynamics forward or backward in time.
 /// It is an EventTracker, without any event tracking. It includes the options, the integrator
@@ -58,6 +72,8 @@ where
     pub(crate) fixed_step: bool,
     // Allows us to do pre-allocation of the ki vectors
     pub(crate) k: Vec<OVector<f64, <D::StateType as State>::VecLength>>,
+    // Continuous extension of each step, only built when requested
+    pub(crate) dense_steps: Option<Vec<DenseStep<D::StateType>>>,
 }
 
 impl<D: Dynamics> PropInstance<'_, D>
@@ -84,16 +100,40 @@ where
         self.fixed_step = fixed;
     }
 
-    #[allow(clippy::erasing_op)]
+    /// Returns the progress reporter of the propagator, unless this instance is quiet.
+    fn reporter(&self) -> Option<Arc<dyn ProgressReporter>> {
+        self.prop.progress.clone().filter(|_| self.log_progress)
+    }
+
     fn for_duration_channel_option(
         &mut self,
         duration: Duration,
         maybe_tx_chan: Option<Sender<D::StateType>>,
+    ) -> Result<D::StateType, PropagationError> {
+        let reporter = self.reporter();
+        if let Some(reporter) = &reporter {
+            reporter.start(&format!("Propagating for {duration}"));
+        }
+        let result = self.propagate_for(duration, maybe_tx_chan, reporter.as_deref());
+        if let Some(reporter) = &reporter {
+            reporter.finish();
+        }
+        result
+    }
+
+    #[allow(clippy::erasing_op)]
+    fn propagate_for(
+        &mut self,
+        duration: Duration,
+        maybe_tx_chan: Option<Sender<D::StateType>>,
+        reporter: Option<&dyn ProgressReporter>,
     ) -> Result<D::StateType, PropagationError> {
         if duration == 0 * Unit::Second {
             return Ok(self.state);
         }
-        let stop_time = self.state.epoch() + duration;
+        let start_time = self.state.epoch();
+        let stop_time = start_time + duration;
+        let _span = info_span!("propagate", start = %self.state.epoch(), %stop_time).entered();
 
         if self.log_progress {
             // Prevent the print spam for orbit determination cases
@@ -107,9 +147,12 @@ where
             .context(DynamicsSnafu)?;
 
         let backprop = duration.is_negative();
-        if backprop {
-            self.step_size = -self.step_size; // Invert the step size
-        }
+        // The step size is stored as a magnitude between calls, and is negative while propagating backward.
+        self.step_size = if backprop {
+            -self.step_size.abs()
+        } else {
+            self.step_size.abs()
+        };
 
         // Transform the state if needed
         let mut original_frame = None;
@@ -145,11 +188,19 @@ where
 
         loop {
             let epoch = self.state.epoch();
+            if self.prop.is_cancelled() {
+                // The partial state remains available as the state of this instance
+                warn!("Propagation cancelled at {epoch}, before reaching {stop_time}");
+                self.step_size = self.step_size.abs();
+                self.rotate_back(original_frame)?;
+                return Err(PropagationError::Cancelled { epoch });
+            }
             if (!backprop && epoch + self.step_size > stop_time)
                 || (backprop && epoch + self.step_size <= stop_time)
             {
                 if stop_time == epoch {
                     // No propagation necessary
+                    self.step_size = self.step_size.abs();
                     #[cfg(not(target_arch = "wasm32"))]
                     {
                         if self.log_progress {
@@ -159,38 +210,31 @@ where
                     }
 
                     // Rotate back if needed
-                    if let Some(original_frame) = original_frame {
-                        let new_orbit = self
-                            .almanac
-                            .transform_to(self.state.orbit(), original_frame, None)
-                            .context(DynamicsAlmanacSnafu {
-                                action: "transforming state from desired integration frame",
-                            })
-                            .context(DynamicsSnafu)?;
-                        self.state.set_orbit(new_orbit);
-                    }
+                    self.rotate_back(original_frame)?;
 
                     return Ok(self.state);
                 }
-                // Take one final step of exactly the needed duration until the stop time
+                // Take one final step of exactly the needed duration until the stop time,
+                // or several if the dynamics are discontinuous before the stop time.
                 let prev_step_size = self.step_size;
                 let prev_step_kind = self.fixed_step;
-                self.set_step(stop_time - epoch, true);
+                while self.state.epoch() != stop_time {
+                    self.set_step(stop_time - self.state.epoch(), true);
 
-                self.single_step()?;
+                    self.single_step()?;
 
-                // Publish to channel if provided
-                if let Some(ref chan) = maybe_tx_chan {
-                    if let Err(e) = chan.send(self.state) {
-                        warn!("{} when sending on channel", e)
+                    // Publish to channel if provided
+                    if let Some(ref chan) = maybe_tx_chan {
+                        if let Err(e) = chan.send(self.state) {
+                            warn!("{} when sending on channel", e)
+                        }
                     }
                 }
 
-                // Restore the step size for subsequent calls
-                self.set_step(prev_step_size, prev_step_kind);
-
-                if backprop {
-                    self.step_size = -self.step_size; // Restore to a positive step size
+                // Restore the step size for subsequent calls, as a positive step size
+                self.set_step(prev_step_size.abs(), prev_step_kind);
+                if let Some(reporter) = reporter {
+                    reporter.report(Progress::new(1.0, Some(stop_time)));
                 }
 
                 #[cfg(not(target_arch = "wasm32"))]
@@ -202,16 +246,7 @@ where
                 }
 
                 // Rotate back if needed
-                if let Some(original_frame) = original_frame {
-                    let new_orbit = self
-                        .almanac
-                        .transform_to(self.state.orbit(), original_frame, None)
-                        .context(DynamicsAlmanacSnafu {
-                            action: "transforming state from desired integration frame",
-                        })
-                        .context(DynamicsSnafu)?;
-                    self.state.set_orbit(new_orbit);
-                }
+                self.rotate_back(original_frame)?;
 
                 return Ok(self.state);
             } else {
@@ -232,6 +267,9 @@ where
                     }
                 }
                 self.single_step()?;
+                if let Some(reporter) = reporter {
+                    reporter.report(Progress::between(start_time, stop_time, self.state.epoch()));
+                }
                 // Publish to channel if provided
                 if let Some(ref chan) = maybe_tx_chan {
                     if let Err(e) = chan.send(self.state) {
@@ -242,6 +280,21 @@ where
         }
     }
 
+    /// Rotates the state back into its original frame, if it was transformed into the integration frame.
+    fn rotate_back(&mut self, original_frame: Option<Frame>) -> Result<(), PropagationError> {
+        if let Some(original_frame) = original_frame {
+            let new_orbit = self
+                .almanac
+                .transform_to(self.state.orbit(), original_frame, None)
+                .context(DynamicsAlmanacSnafu {
+                    action: "transforming state from desired integration frame",
+                })
+                .context(DynamicsSnafu)?;
+            self.state.set_orbit(new_orbit);
+        }
+        Ok(())
+    }
+
     /// This method propagates the provided Dynamics for the provided duration.
     pub fn for_duration(&mut self, duration: Duration) -> Result<D::StateType, PropagationError> {
         self.for_duration_channel_option(duration, None)
@@ -272,6 +325,79 @@ where
         self.for_duration_with_channel(duration, tx_chan)
     }
 
+    /// Propagates the provided Dynamics for the provided duration and publishes states on the channel at a fixed `cadence` of
+    /// simulation time, independently of the integrator step size. The steps are shortened to end exactly on each output epoch,
+    /// and the end state is always published. Returns the end state.
+    pub fn for_duration_with_decimated_channel(
+        &mut self,
+        duration: Duration,
+        tx_chan: Sender<D::StateType>,
+        cadence: Duration,
+    ) -> Result<D::StateType, PropagationError> {
+        if cadence <= Duration::ZERO {
+            return Err(ConfigError::InvalidConfig {
+                msg: format!("output cadence must be strictly positive, got {cadence}"),
+            })
+            .context(PropConfigSnafu);
+        }
+
+        let stop_time = self.state.epoch() + duration;
+        if self.log_progress {
+            info!(
+                "Propagating for {} until {} with outputs every {}",
+                duration, stop_time, cadence
+            );
+        }
+        let cadence = if duration.is_negative() {
+            -cadence
+        } else {
+            cadence
+        };
+
+        // Each segment is a propagation on its own, so only log the overall progress
+        let log_progress = self.log_progress;
+        self.log_progress = false;
+        let mut result = Ok(self.state);
+        let mut output_epoch = self.state.epoch() + cadence;
+        while (cadence.is_negative() && output_epoch > stop_time)
+            || (!cadence.is_negative() && output_epoch < stop_time)
+        {
+            result = self.until_epoch(output_epoch);
+            match result {
+                Ok(state) => {
+                    if let Err(e) = tx_chan.send(state) {
+                        warn!("{} when sending on channel", e)
+                    }
+                }
+                Err(_) => break,
+            }
+            output_epoch += cadence;
+        }
+        if result.is_ok() {
+            result = self.until_epoch(stop_time);
+            if let Ok(state) = result {
+                if let Err(e) = tx_chan.send(state) {
+                    warn!("{} when sending on channel", e)
+                }
+            }
+        }
+        self.log_progress = log_progress;
+
+        result
+    }
+
+    /// Propagates the provided Dynamics until the provided epoch and publishes states on the channel at a fixed `cadence` of
+    /// simulation time, see [Self::for_duration_with_decimated_channel]. Returns the end state.
+    pub fn until_epoch_with_decimated_channel(
+        &mut self,
+        end_time: Epoch,
+        tx_chan: Sender<D::StateType>,
+        cadence: Duration,
+    ) -> Result<D::StateType, PropagationError> {
+        let duration: Duration = end_time - self.state.epoch();
+        self.for_duration_with_decimated_channel(duration, tx_chan, cadence)
+    }
+
     /// Propagates the provided Dynamics for the provided duration and generate the trajectory of these dynamics on its own thread.
     /// Returns the end state and the trajectory.
     #[allow(clippy::map_clone)]
@@ -306,9 +432,64 @@ where
         Ok((end_state, traj))
     }
 
+    /// Propagates the provided Dynamics for the provided duration and generate the trajectory of these dynamics, including
+    /// the continuous extension (dense output) of every integration step, which is then used to evaluate the trajectory
+    /// between its states instead of interpolating them. This requires an integrator with dense output, e.g. `Dop853`.
+    /// Returns the end state and the trajectory.
+    pub fn for_duration_with_dense_traj(
+        &mut self,
+        duration: Duration,
+    ) -> Result<(D::StateType, Traj<D::StateType>), PropagationError>
+    where
+        <DefaultAllocator as Allocator<<D::StateType as State>::VecLength>>::Buffer<f64>: Send,
+        D::StateType: Interpolatable,
+    {
+        match &self.prop.stepper {
+            Some(stepper) if !stepper.has_dense_output() => {
+                return Err(ConfigError::InvalidConfig {
+                    msg: format!("{stepper:?} does not provide a dense output"),
+                })
+                .context(PropConfigSnafu);
+            }
+            None if !self.prop.method.has_dense_output() => {
+                return Err(ConfigError::InvalidConfig {
+                    msg: format!(
+                        "{:?} does not provide a dense output, use DormandPrince45 or Dop853",
+                        self.prop.method
+                    ),
+                })
+                .context(PropConfigSnafu);
+            }
+            _ => {}
+        }
+
+        self.dense_steps = Some(Vec::new());
+        let result = self.for_duration_with_traj(duration);
+        let dense_steps = self.dense_steps.take().unwrap_or_default();
+
+        let (end_state, mut traj) = result?;
+        traj.dense = dense_steps;
+        traj.finalize();
+
+        Ok((end_state, traj))
+    }
+
+    /// Propagates the provided Dynamics until the provided epoch and generate the trajectory of these dynamics, including
+    /// the continuous extension of every integration step. Returns the end state and the trajectory.
+    pub fn until_epoch_with_dense_traj(
+        &mut self,
+        end_time: Epoch,
+    ) -> Result<(D::StateType, Traj<D::StateType>), PropagationError>
+    where
+        <DefaultAllocator as Allocator<<D::StateType as State>::VecLength>>::Buffer<f64>: Send,
+        D::StateType: Interpolatable,
+    {
+        let duration: Duration = end_time - self.state.epoch();
+        self.for_duration_with_dense_traj(duration)
+    }
+
     /// Propagates the provided Dynamics until the provided epoch and generate the trajectory of these dynamics on its own thread.
     /// Returns the end state and the trajectory.
-    /// Known bug #190: Cannot generate a valid trajectory when propagating backward
     pub fn until_epoch_with_traj(
         &mut self,
         end_time: Epoch,
@@ -321,6 +502,66 @@ where
         self.for_duration_with_traj(duration)
     }
 
+    /// Propagates the provided Dynamics for the provided duration and writes the states to a parquet file as they are produced,
+    /// on their own thread, so that long propagations never store their trajectory in memory (cf. [TrajWriter]).
+    /// If the configuration has a step, the states are written at that cadence of simulation time,
+    /// cf. [Self::for_duration_with_decimated_channel]. Returns the end state and the path of the file.
+    pub fn for_duration_with_parquet<P: AsRef<Path>>(
+        &mut self,
+        duration: Duration,
+        path: P,
+        cfg: ExportCfg,
+    ) -> Result<(D::StateType, PathBuf), Box<dyn Error>>
+    where
+        D::StateType: Interpolatable,
+    {
+        let start_state = self.state;
+        let almanac = self.almanac.clone();
+        let path_buf = path.as_ref().to_path_buf();
+        let cadence = cfg.step;
+
+        let (tx, rx) = channel();
+        let (end_state, written) = thread::scope(|scope| {
+            let writer = scope.spawn(move || -> Result<PathBuf, String> {
+                let mut writer = TrajWriter::new(path_buf, &start_state, None, cfg, almanac)
+                    .map_err(|e| e.to_string())?;
+                writer.push(start_state).map_err(|e| e.to_string())?;
+                for state in rx {
+                    writer.push(state).map_err(|e| e.to_string())?;
+                }
+                writer.close().map_err(|e| e.to_string())
+            });
+
+            // The channel is dropped at the end of the propagation, which ends the writer thread
+            let end_state = match cadence {
+                Some(cadence) => self.for_duration_with_decimated_channel(duration, tx, cadence),
+                None => self.for_duration_with_channel(duration, tx),
+            };
+
+            (end_state, writer.join())
+        });
+
+        let end_state = end_state?;
+        let path_buf = written.map_err(|_| "parquet writer thread panicked")??;
+
+        Ok((end_state, path_buf))
+    }
+
+    /// Propagates the provided Dynamics until the provided epoch and writes the states to a parquet file as they are produced,
+    /// cf. [Self::for_duration_with_parquet]. Returns the end state and the path of the file.
+    pub fn until_epoch_with_parquet<P: AsRef<Path>>(
+        &mut self,
+        end_time: Epoch,
+        path: P,
+        cfg: ExportCfg,
+    ) -> Result<(D::StateType, PathBuf), Box<dyn Error>>
+    where
+        D::StateType: Interpolatable,
+    {
+        let duration: Duration = end_time - self.state.epoch();
+        self.for_duration_with_parquet(duration, path, cfg)
+    }
+
     /// Propagate until a specific event is found once.
     /// Returns the state found and the trajectory until `max_duration`
     pub fn until_event<F: EventEvaluator<D::StateType>>(
@@ -336,7 +577,8 @@ where
     }
 
     /// Propagate until a specific event is found `trigger` times.
-    /// Returns the state found and the trajectory until `max_duration`
+    /// Returns the state found and the trajectory until `max_duration`.
+    /// If `max_duration` is negative, the events are counted backward from the initial state.
     pub fn until_nth_event<F: EventEvaluator<D::StateType>>(
         &mut self,
         max_duration: Duration,
@@ -351,9 +593,13 @@ where
 
         let (_, traj) = self.for_duration_with_traj(max_duration)?;
         // Now, find the requested event
-        let events = traj
+        let mut events = traj
             .find(event, self.almanac.clone())
             .context(TrajectoryEventSnafu)?;
+        if max_duration.is_negative() {
+            // Count the events in the direction of the propagation
+            events.reverse();
+        }
         match events.get(trigger) {
             Some(event_state) => Ok((event_state.state, traj)),
             None => Err(PropagationError::NthEventError {
@@ -363,9 +609,276 @@ where
         }
     }
 
+    /// Propagate until any of the stop conditions is met, e.g. until impact OR 30 days OR fuel exhausted.
+    /// Returns the state at which the propagation stopped and the condition which stopped it.
+    ///
+    /// Events and predicates are checked after every step: when one switches, it is located within that step by
+    /// propagating again from the start of the step. A predicate which holds on the initial state stops immediately.
+    pub fn until(
+        &mut self,
+        condition: &StopCondition<D::StateType>,
+    ) -> Result<(D::StateType, StopCondition<D::StateType>), PropagationError> {
+        let start = self.state.epoch();
+        let stop_time = match condition.bound(start) {
+            Some(stop_time) => stop_time,
+            None => {
+                return Err(ConfigError::InvalidConfig {
+                    msg: format!(
+                        "{condition} requires an epoch or a duration to stop the propagation"
+                    ),
+                })
+                .context(PropConfigSnafu)
+            }
+        };
+
+        let leaves = condition.leaves();
+        for leaf in &leaves {
+            if let StopCondition::Predicate(predicate) = leaf {
+                if predicate(&self.state) {
+                    return Ok((self.state, (*leaf).clone()));
+                }
+            }
+        }
+
+        let _span = info_span!("propagate_until", start = %start, %stop_time).entered();
+        if self.log_progress {
+            info!("Propagating until {condition}");
+        }
+        // Each step is a propagation on its own, so only log the overall progress
+        let log_progress = self.log_progress;
+        self.log_progress = false;
+        let result = self.until_leaves(&leaves, stop_time);
+        self.log_progress = log_progress;
+
+        let (state, leaf) = result?;
+        Ok((state, leaf.clone()))
+    }
+
+    fn until_leaves<'c>(
+        &mut self,
+        leaves: &[&'c StopCondition<D::StateType>],
+        stop_time: Epoch,
+    ) -> Result<(D::StateType, &'c StopCondition<D::StateType>), PropagationError> {
+        let start = self.state.epoch();
+        let backprop = stop_time < start;
+
+        loop {
+            let prev_state = self.state;
+            let remaining = stop_time - prev_state.epoch();
+            if remaining == Duration::ZERO {
+                // The bound of the propagation is the first of the time conditions reached
+                let leaf = leaves
+                    .iter()
+                    .find(|leaf| match leaf {
+                        StopCondition::Epoch(epoch) => *epoch == stop_time,
+                        StopCondition::Duration(duration) => start + *duration == stop_time,
+                        _ => false,
+                    })
+                    .expect("the stop time is the bound of one of the conditions");
+                return Ok((self.state, *leaf));
+            }
+
+            let step = if self.step_size.abs() < remaining.abs() {
+                if backprop {
+                    -self.step_size.abs()
+                } else {
+                    self.step_size.abs()
+                }
+            } else {
+                remaining
+            };
+            self.for_duration(step)?;
+
+            // Find the first of the events and predicates which switched during this step
+            let mut found: Option<(D::StateType, &'c StopCondition<D::StateType>)> = None;
+            for leaf in leaves {
+                let located = match leaf {
+                    StopCondition::Event(event) => {
+                        let prev_value = event
+                            .eval(&prev_state, self.almanac.clone())
+                            .context(TrajectoryEventSnafu)?;
+                        if event
+                            .eval_crossing(&prev_state, &self.state, self.almanac.clone())
+                            .context(TrajectoryEventSnafu)?
+                        {
+                            Some(self.locate_switch(
+                                prev_state,
+                                self.state,
+                                event.epoch_precision(),
+                                |state| {
+                                    Ok(event
+                                        .eval(state, self.almanac.clone())
+                                        .context(TrajectoryEventSnafu)?
+                                        * prev_value
+                                        <= 0.0)
+                                },
+                            )?)
+                        } else {
+                            None
+                        }
+                    }
+                    StopCondition::Predicate(predicate) => {
+                        if predicate(&self.state) {
+                            Some(self.locate_switch(
+                                prev_state,
+                                self.state,
+                                PREDICATE_EPOCH_PRECISION,
+                                |state| Ok(predicate(state)),
+                            )?)
+                        } else {
+                            None
+                        }
+                    }
+                    _ => None,
+                };
+
+                if let Some(state) = located {
+                    let is_first = match found {
+                        Some((first, _)) => {
+                            (state.epoch() - prev_state.epoch()).abs()
+                                < (first.epoch() - prev_state.epoch()).abs()
+                        }
+                        None => true,
+                    };
+                    if is_first {
+                        found = Some((state, *leaf));
+                    }
+                }
+            }
+
+            if let Some((state, leaf)) = found {
+                self.state = state;
+                return Ok((state, leaf));
+            }
+        }
+    }
+
+    /// Locates the epoch at which `switched` becomes true between two consecutive states, to the provided precision.
+    /// The bisection propagates from `prev_state` and returns the first state found for which `switched` is true.
+    fn locate_switch<F>(
+        &self,
+        prev_state: D::StateType,
+        next_state: D::StateType,
+        precision: Duration,
+        switched: F,
+    ) -> Result<D::StateType, PropagationError>
+    where
+        F: Fn(&D::StateType) -> Result<bool, PropagationError>,
+    {
+        let mut lo = Duration::ZERO;
+        let mut hi = next_state.epoch() - prev_state.epoch();
+        let mut hi_state = next_state;
+
+        while (hi - lo).abs() > precision {
+            let mid = lo + (hi - lo) * 0.5;
+            let mid_state = self
+                .prop
+                .with(prev_state, self.almanac.clone())
+                .quiet()
+                .for_duration(mid)?;
+            if switched(&mid_state)? {
+                hi = mid;
+                hi_state = mid_state;
+            } else {
+                lo = mid;
+            }
+        }
+
+        Ok(hi_state)
+    }
+
+    /// Returns a checkpoint of this propagation, from which it may be resumed with [Propagator::resume].
+    pub fn checkpoint(&self) -> PropCheckpoint<D::StateType> {
+        PropCheckpoint {
+            state: self.state,
+            vector: self.state.to_vector().as_slice().to_vec(),
+            stm: self.state.stm().is_ok(),
+            step_size: self.step_size.abs(),
+            fixed_step: self.fixed_step,
+            details: self.details,
+        }
+    }
+
     /// Take a single propagator step and emit the result on the TX channel (if enabled)
+    ///
+    /// The step ends exactly at the next discontinuity of the dynamics, if any, such that the integrator never steps across
+    /// a thrust on/off transition or a guidance mode switch.
     pub fn single_step(&mut self) -> Result<(), PropagationError> {
+        let prev_state = self.state;
+        let step_size = self.step_size;
+        let fixed_step = self.fixed_step;
+
+        if let Some(epoch) = self
+            .prop
+            .dynamics
+            .next_discontinuity(&prev_state, prev_state.epoch() + step_size)
+        {
+            self.set_step(epoch - prev_state.epoch(), true);
+            self.integrate_step()?;
+            // The next step starts with the step size the integrator would have used
+            self.set_step(step_size, fixed_step);
+            return Ok(());
+        }
+
+        self.integrate_step()?;
+
+        if self.prop.dynamics.switched(&prev_state, &self.state) {
+            let next_step_size = self.step_size;
+            let switch_step = self.locate_dynamics_switch(prev_state)?;
+            // Integrate again until the switch, which also rebuilds the dense output of that step
+            if let Some(dense_steps) = self.dense_steps.as_mut() {
+                dense_steps.pop();
+            }
+            self.state = prev_state;
+            self.set_step(switch_step, true);
+            self.integrate_step()?;
+            self.set_step(next_step_size, fixed_step);
+        }
+
+        Ok(())
+    }
+
+    /// Locates the switch of the dynamics within the step which was just taken from `prev_state`, to within
+    /// [SWITCH_EPOCH_PRECISION]. Returns the duration from the previous state until the first state which switched.
+    fn locate_dynamics_switch(
+        &mut self,
+        prev_state: D::StateType,
+    ) -> Result<Duration, PropagationError> {
+        let mut lo = Duration::ZERO;
+        let mut hi = self.state.epoch() - prev_state.epoch();
+
+        while (hi - lo).abs() > SWITCH_EPOCH_PRECISION {
+            let mid = lo + (hi - lo) * 0.5;
+            self.state = prev_state;
+            self.set_step(mid, true);
+            let (t, state_vec) = self.derive()?;
+            let mut mid_state = prev_state;
+            mid_state.set(prev_state.epoch() + t, &state_vec);
+            let mid_state = self
+                .prop
+                .dynamics
+                .finally(mid_state, self.almanac.clone())
+                .context(DynamicsSnafu)?;
+
+            if self.prop.dynamics.switched(&prev_state, &mid_state) {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
+        }
+
+        Ok(hi)
+    }
+
+    /// Integrates a single step from the current state and applies the `finally` of the dynamics.
+    fn integrate_step(&mut self) -> Result<(), PropagationError> {
         let (t, state_vec) = self.derive()?;
+        if self.dense_steps.is_some() {
+            let dense_step = self.dense_step(t)?;
+            if let Some(dense_steps) = self.dense_steps.as_mut() {
+                dense_steps.push(dense_step);
+            }
+        }
         self.state.set(self.state.epoch() + t, &state_vec);
         self.state = self
             .prop
@@ -373,6 +886,14 @@ where
             .finally(self.state, self.almanac.clone())
             .context(DynamicsSnafu)?;
 
+        trace!(
+            epoch = %self.state.epoch(),
+            step_s = t.to_seconds(),
+            error = self.details.error,
+            attempts = self.details.attempts,
+            "integration step"
+        );
+
         Ok(())
     }
 
@@ -384,18 +905,37 @@ where
         &mut self,
     ) -> Result<(Duration, OVector<f64, <D::StateType as State>::VecLength>), PropagationError>
     {
+        let prop = self.prop;
+        if let Some(stepper) = &prop.stepper {
+            self.details.attempts = 1;
+            let (step, state_vec) = stepper.step(
+                prop,
+                &self.state,
+                &mut self.step_size,
+                self.fixed_step,
+                &mut self.details,
+                self.almanac.clone(),
+            )?;
+            self.details.step = step;
+            return Ok((step, state_vec));
+        }
+
         let state_vec = &self.state.to_vector();
         let state_ctx = &self.state;
+        // Only integrate the leading components which are set, e.g. skip an unset or reduced STM
+        let n = state_ctx.integrated_len();
         // Reset the number of attempts used (we don't reset the error because it's set before it's read)
         self.details.attempts = 1;
         // Convert the step size to seconds -- it's mutable because we may change it below
         let mut step_size_s = self.step_size.to_seconds();
         loop {
+            let stage = profiling::scope(Subsystem::IntegratorStage, || "stage 1".to_string());
             let ki = self
                 .prop
                 .dynamics
                 .eom(0.0, state_vec, state_ctx, self.almanac.clone())
                 .context(DynamicsSnafu)?;
+            drop(stage);
             self.k[0] = ki;
             let mut a_idx: usize = 0;
             for i in 0..(self.prop.method.stages() - 1) {
@@ -407,16 +947,22 @@ where
                 for kj in &self.k[0..i + 1] {
                     let a_ij = self.prop.method.a_coeffs()[a_idx];
                     ci += a_ij;
-                    wi += a_ij * kj;
+                    wi.rows_mut(0, n).axpy(a_ij, &kj.rows(0, n), 1.0);
                     a_idx += 1;
                 }
 
+                let mut stage_state = state_vec.clone();
+                stage_state
+                    .rows_mut(0, n)
+                    .axpy(step_size_s, &wi.rows(0, n), 1.0);
+                let _stage =
+                    profiling::scope(Subsystem::IntegratorStage, || format!("stage {}", i + 2));
                 let ki = self
                     .prop
                     .dynamics
                     .eom(
                         ci * step_size_s,
-                        &(state_vec + step_size_s * wi),
+                        &stage_state,
                         state_ctx,
                         self.almanac.clone(),
                     )
@@ -433,9 +979,15 @@ where
                 let b_i = self.prop.method.b_coeffs()[i];
                 if !self.fixed_step {
                     let b_i_star = self.prop.method.b_coeffs()[i + self.prop.method.stages()];
-                    error_est += step_size_s * (b_i - b_i_star) * ki;
+                    error_est.rows_mut(0, n).axpy(
+                        step_size_s * (b_i - b_i_star),
+                        &ki.rows(0, n),
+                        1.0,
+                    );
                 }
-                next_state += step_size_s * b_i * ki;
+                next_state
+                    .rows_mut(0, n)
+                    .axpy(step_size_s * b_i, &ki.rows(0, n), 1.0);
             }
 
             if self.fixed_step {
@@ -444,14 +996,13 @@ where
                 return Ok(((self.details.step), next_state));
             } else {
                 // Compute the error estimate.
-                self.details.error =
-                    self.prop
-                        .opts
-                        .error_ctrl
-                        .estimate(&error_est, &next_state, state_vec);
+                self.details.error = self
+                    .prop
+                    .opts
+                    .step_error(&error_est, &next_state, state_vec);
 
                 if self.details.error <= self.prop.opts.tolerance
-                    || step_size_s <= self.prop.opts.min_step.to_seconds()
+                    || step_size_s.abs() <= self.prop.opts.min_step.to_seconds()
                     || self.details.attempts >= self.prop.opts.attempts
                 {
                     if next_state.iter().any(|x| x.is_nan()) {
@@ -478,8 +1029,10 @@ where
                             * (self.prop.opts.tolerance / self.details.error)
                                 .powf(1.0 / f64::from(self.prop.method.order()));
 
-                        step_size_s = if proposed_step > self.prop.opts.max_step.to_seconds() {
-                            self.prop.opts.max_step.to_seconds()
+                        // Bound the magnitude of the step, which is negative when propagating backward.
+                        step_size_s = if proposed_step.abs() > self.prop.opts.max_step.to_seconds()
+                        {
+                            self.prop.opts.max_step.to_seconds().copysign(step_size_s)
                         } else {
                             proposed_step
                         };
@@ -505,8 +1058,8 @@ where
                         * (self.prop.opts.tolerance / self.details.error)
                             .powf(1.0 / f64::from(self.prop.method.order() - 1));
 
-                    step_size_s = if proposed_step_s < self.prop.opts.min_step.to_seconds() {
-                        self.prop.opts.min_step.to_seconds()
+                    step_size_s = if proposed_step_s.abs() < self.prop.opts.min_step.to_seconds() {
+                        self.prop.opts.min_step.to_seconds().copysign(step_size_s)
                     } else {
                         proposed_step_s
                     };
@@ -516,8 +1069,472 @@ where
         }
     }
 
+    /// Builds the continuous extension of the step of the provided duration which was just computed from the current state.
+    fn dense_step(&self, step: Duration) -> Result<DenseStep<D::StateType>, PropagationError> {
+        if let Some(stepper) = &self.prop.stepper {
+            let coeffs =
+                stepper.dense_coeffs(self.prop, &self.state, step, self.almanac.clone())?;
+            return Ok(DenseStep::new(self.state, step, coeffs));
+        }
+
+        let method = self.prop.method;
+        let degree = method.dense_degree();
+        let dense_coeffs = method.dense_coeffs();
+        let step_s = step.to_seconds();
+        let state_vec = self.state.to_vector();
+
+        // Evaluate the additional stages of the continuous extension.
+        let mut k = self.k.clone();
+        let mut a_idx: usize = 0;
+        for i in method.stages()..(dense_coeffs.len() / degree) {
+            let mut ci: f64 = 0.0;
+            let mut wi = OVector::<f64, <D::StateType as State>::VecLength>::from_element(0.0);
+            for kj in &k[0..i] {
+                let a_ij = method.dense_a_coeffs()[a_idx];
+                ci += a_ij;
+                wi += a_ij * kj;
+                a_idx += 1;
+            }
+            k.push(
+                self.prop
+                    .dynamics
+                    .eom(
+                        ci * step_s,
+                        &(&state_vec + step_s * wi),
+                        &self.state,
+                        self.almanac.clone(),
+                    )
+                    .context(DynamicsSnafu)?,
+            );
+        }
+
+        let coeffs = (0..degree)
+            .map(|power| {
+                k.iter().enumerate().fold(
+                    OVector::<f64, <D::StateType as State>::VecLength>::from_element(0.0),
+                    |acc, (i, ki)| acc + step_s * dense_coeffs[i * degree + power] * ki,
+                )
+            })
+            .collect();
+
+        Ok(DenseStep::new(self.state, step, coeffs))
+    }
+
     /// Copy the details of the latest integration step.
     pub fn latest_details(&self) -> IntegrationDetails {
         self.details
     }
 }
+
+#[cfg(test)]
+mod ut_backprop {
+    use crate::cosmic::{Orbit, Spacecraft};
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::md::prelude::Event;
+    use crate::propagators::{ErrorControl, IntegratorMethod, IntegratorOptions, Propagator};
+    use crate::time::{Duration, TimeUnits, Unit};
+    use crate::State;
+
+    fn setup() -> (Orbit, Propagator<SpacecraftDynamics>) {
+        let orbit = fixtures::keplerian(8_000.0, 0.2, 28.5, 30.0, 45.0, 10.0);
+        let prop = Propagator::new(
+            SpacecraftDynamics::new(OrbitalDynamics::two_body()),
+            IntegratorMethod::Dop853,
+            IntegratorOptions::with_adaptive_step(
+                Unit::Second * 1,
+                Unit::Minute * 5,
+                1e-12,
+                ErrorControl::RSSCartesianStep,
+            ),
+        );
+        (orbit, prop)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (orbit, prop) = setup();
+        let almanac = fixtures::almanac();
+        let sc = Spacecraft::builder().orbit(orbit).build();
+
+        let mut instance = prop.with(sc, almanac);
+        let forward = instance.for_duration(1.days()).unwrap();
+        assert!(instance.step_size > Duration::ZERO);
+
+        let backward = instance.until_epoch(orbit.epoch).unwrap();
+        assert_eq!(backward.epoch(), orbit.epoch);
+        assert!(instance.step_size > Duration::ZERO);
+        assert!(orbit.rss_radius_km(&backward.orbit).unwrap() < 1e-5);
+        assert!(orbit.rss_velocity_km_s(&backward.orbit).unwrap() < 1e-8);
+
+        // And the instance can propagate forward again
+        let again = instance.for_duration(1.days()).unwrap();
+        assert!(forward.orbit.rss_radius_km(&again.orbit).unwrap() < 1e-5);
+
+        // Propagating to the current epoch is a no-op in both directions
+        assert_eq!(instance.until_epoch(again.epoch()).unwrap(), again);
+        assert!(instance.step_size > Duration::ZERO);
+    }
+
+    #[test]
+    fn backward_traj_and_events() {
+        let (orbit, prop) = setup();
+        let almanac = fixtures::almanac();
+        let sc = Spacecraft::builder().orbit(orbit).build();
+        let period = orbit.period().unwrap();
+
+        for dense in [false, true] {
+            let mut instance = prop.with(sc, almanac.clone());
+            let (end, traj) = if dense {
+                instance.for_duration_with_dense_traj(-1.days()).unwrap()
+            } else {
+                instance
+                    .until_epoch_with_traj(orbit.epoch - 1.days())
+                    .unwrap()
+            };
+
+            assert_eq!(end.epoch(), orbit.epoch - 1.days());
+            assert_eq!(traj.first().epoch(), end.epoch());
+            assert_eq!(traj.last().epoch(), orbit.epoch);
+            for pair in traj.states.windows(2) {
+                let step = pair[1].epoch() - pair[0].epoch();
+                // The maximum step is also enforced when propagating backward
+                assert!(step > Duration::ZERO && step <= 5.minutes(), "{step}");
+            }
+
+            for state in traj.every(17.minutes()) {
+                let truth = orbit.at_epoch(state.epoch()).unwrap();
+                assert!(truth.rss_radius_km(&state.orbit).unwrap() < 1e-4);
+            }
+        }
+
+        // The events are counted backward from the initial state
+        let (apoapsis, _) = prop
+            .with(sc, almanac.clone())
+            .until_event(-1.days(), &Event::apoapsis())
+            .unwrap();
+        assert!(apoapsis.epoch() < orbit.epoch);
+        assert!(apoapsis.epoch() > orbit.epoch - period);
+        assert!((apoapsis.orbit.ta_deg().unwrap() - 180.0).abs() < 1e-3);
+
+        let (second, _) = prop
+            .with(sc, almanac)
+            .until_nth_event(-1.days(), &Event::apoapsis(), 1)
+            .unwrap();
+        assert!(((apoapsis.epoch() - second.epoch()) - period).abs() < 1.seconds());
+    }
+}
+
+#[cfg(test)]
+mod ut_discontinuity {
+    use crate::cosmic::{GuidanceMode, Orbit, Spacecraft, STD_GRAVITY};
+    use crate::dynamics::guidance::{GuidanceError, GuidanceLaw, LocalFrame, Maneuver, Thruster};
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::linalg::Vector3;
+    use crate::propagators::{IntegratorOptions, Propagator};
+    use crate::time::{Epoch, TimeUnits};
+    use crate::State;
+    use anise::almanac::Almanac;
+    use anise::structure::spacecraft::Mass;
+    use std::fmt;
+    use std::sync::Arc;
+
+    const THRUSTER: Thruster = Thruster::new(10.0, 300.0);
+
+    fn spacecraft(epoch: Epoch) -> Spacecraft {
+        let eme2k = fixtures::eme2k();
+        let orbit =
+            Orbit::try_keplerian(7_000.0, 0.01, 28.5, 30.0, 45.0, 10.0, epoch, eme2k).unwrap();
+        Spacecraft::builder()
+            .orbit(orbit)
+            .mass(Mass::from_dry_and_prop_masses(500.0, 100.0))
+            .thruster(THRUSTER)
+            .build()
+    }
+
+    /// Thrusts along the velocity until the radius reaches the target.
+    struct RaiseRadius {
+        rmag_km: f64,
+    }
+
+    impl fmt::Display for RaiseRadius {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "raise radius to {} km", self.rmag_km)
+        }
+    }
+
+    impl GuidanceLaw for RaiseRadius {
+        fn direction(&self, osc: &Spacecraft) -> Result<Vector3<f64>, GuidanceError> {
+            Ok(osc.orbit.velocity_km_s / osc.orbit.vmag_km_s())
+        }
+
+        fn throttle(&self, osc: &Spacecraft) -> Result<f64, GuidanceError> {
+            match osc.mode() {
+                GuidanceMode::Thrust => Ok(1.0),
+                _ => Ok(0.0),
+            }
+        }
+
+        fn next(&self, sc: &mut Spacecraft, _almanac: Arc<Almanac>) {
+            if sc.orbit.rmag_km() < self.rmag_km {
+                sc.mut_mode(GuidanceMode::Thrust)
+            } else {
+                sc.mut_mode(GuidanceMode::Coast)
+            }
+        }
+    }
+
+    #[test]
+    fn scheduled_burn() {
+        let epoch = fixtures::epoch();
+        let sc = spacecraft(epoch);
+        // Neither end of the burn is on a step boundary
+        let mnvr = Maneuver::from_time_invariant(
+            epoch + 123.456.seconds(),
+            epoch + 700.0.seconds(),
+            1.0,
+            Vector3::new(1.0, 0.0, 0.0),
+            LocalFrame::VNC,
+        );
+        let dynamics =
+            SpacecraftDynamics::from_guidance_law(OrbitalDynamics::two_body(), Arc::new(mnvr));
+        let prop = Propagator::rk89(dynamics, IntegratorOptions::with_fixed_step(60.seconds()));
+
+        let (end, traj) = prop
+            .with(sc, fixtures::almanac())
+            .for_duration_with_traj(20.minutes())
+            .unwrap();
+
+        // Steps end at the start and end of the burn, and the fixed step size is then used again
+        assert!(traj.states.iter().any(|s| s.epoch() == mnvr.start));
+        assert!(traj.states.iter().any(|s| s.epoch() == mnvr.end));
+        assert!(traj
+            .states
+            .iter()
+            .any(|s| s.epoch() == mnvr.start + 60.seconds()));
+
+        // The propellant is only used during the burn
+        let mdot = THRUSTER.thrust_N / (THRUSTER.isp_s * STD_GRAVITY);
+        let expected = mdot * (mnvr.end - mnvr.start).to_seconds();
+        let used = sc.mass.prop_mass_kg - end.mass.prop_mass_kg;
+        assert!((used - expected).abs() < 1e-9, "{used} != {expected}");
+    }
+
+    #[test]
+    fn guidance_mode_switch() {
+        let epoch = fixtures::epoch();
+        let sc = spacecraft(epoch);
+        let target = sc.orbit.rmag_km() + 5.0;
+        let dynamics = SpacecraftDynamics::from_guidance_law(
+            OrbitalDynamics::two_body(),
+            Arc::new(RaiseRadius { rmag_km: target }),
+        );
+        let prop = Propagator::default(dynamics);
+
+        let (_, traj) = prop
+            .with(sc, fixtures::almanac())
+            .for_duration_with_traj(30.minutes())
+            .unwrap();
+
+        // A step ends where the guidance switched to coasting
+        let switch = traj
+            .states
+            .windows(2)
+            .find(|pair| {
+                pair[0].mode() == GuidanceMode::Thrust && pair[1].mode() == GuidanceMode::Coast
+            })
+            .map(|pair| pair[1])
+            .unwrap();
+        assert!(switch.orbit.rmag_km() >= target);
+        assert!(switch.orbit.rmag_km() - target < 1e-2);
+    }
+}
+
+#[cfg(test)]
+mod ut_decimated {
+    use crate::cosmic::Spacecraft;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::propagators::{IntegratorOptions, Propagator};
+    use crate::time::TimeUnits;
+    use crate::State;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn fixed_cadence() {
+        let epoch = fixtures::epoch();
+        let orbit = fixtures::keplerian(8_000.0, 0.2, 28.5, 30.0, 45.0, 10.0);
+        let sc = Spacecraft::builder().orbit(orbit).build();
+        let almanac = fixtures::almanac();
+        // Small steps compared to the output cadence
+        let prop = Propagator::rk89(
+            SpacecraftDynamics::new(OrbitalDynamics::two_body()),
+            IntegratorOptions::with_max_step(7.seconds()),
+        );
+
+        for duration in [10.5.minutes(), -10.5.minutes()] {
+            let (tx, rx) = channel();
+            let end = prop
+                .with(sc, almanac.clone())
+                .for_duration_with_decimated_channel(duration, tx, 1.minutes())
+                .unwrap();
+            let states = rx.iter().collect::<Vec<_>>();
+
+            assert_eq!(states.len(), 11);
+            let cadence = if duration.is_negative() {
+                -1.minutes()
+            } else {
+                1.minutes()
+            };
+            for (i, state) in states[..10].iter().enumerate() {
+                let expected = epoch + cadence * (i as i64 + 1);
+                assert_eq!(state.epoch(), expected);
+                let truth = orbit.at_epoch(expected).unwrap();
+                assert!(truth.rss_radius_km(&state.orbit).unwrap() < 1e-6);
+            }
+            assert_eq!(states[10], end);
+            assert_eq!(end.epoch(), epoch + duration);
+        }
+
+        let (tx, _rx) = channel();
+        assert!(prop
+            .with(sc, almanac)
+            .for_duration_with_decimated_channel(1.hours(), tx, 0.seconds())
+            .is_err());
+    }
+}
+
+#[cfg(test)]
+mod ut_progress {
+    use crate::cosmic::Spacecraft;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::progress::{Progress, ProgressReporter};
+    use crate::propagators::{IntegratorOptions, Propagator};
+    use crate::time::TimeUnits;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Default)]
+    struct Recorder {
+        reports: Mutex<Vec<Progress>>,
+        finished: Mutex<usize>,
+    }
+
+    impl ProgressReporter for Recorder {
+        fn report(&self, progress: Progress) {
+            self.reports.lock().unwrap().push(progress);
+        }
+
+        fn finish(&self) {
+            *self.finished.lock().unwrap() += 1;
+        }
+    }
+
+    #[test]
+    fn reports_progress() {
+        let epoch = fixtures::epoch();
+        let orbit = fixtures::keplerian(8_000.0, 0.2, 28.5, 30.0, 45.0, 10.0);
+        let sc = Spacecraft::builder().orbit(orbit).build();
+        let almanac = fixtures::almanac();
+
+        let recorder = Arc::new(Recorder::default());
+        let prop = Propagator::rk89(
+            SpacecraftDynamics::new(OrbitalDynamics::two_body()),
+            IntegratorOptions::with_fixed_step(1.minutes()),
+        )
+        .with_progress(recorder.clone());
+
+        prop.with(sc, almanac.clone())
+            .for_duration(90.5.minutes())
+            .unwrap();
+
+        {
+            let reports = recorder.reports.lock().unwrap();
+            // One report per full step and one for the final partial step
+            assert_eq!(reports.len(), 91);
+            assert!(reports
+                .windows(2)
+                .all(|pair| pair[0].fraction < pair[1].fraction));
+            assert_eq!(reports[0].epoch, Some(epoch + 1.minutes()));
+            let last = reports.last().unwrap();
+            assert_eq!(last.fraction, 1.0);
+            assert_eq!(last.epoch, Some(epoch + 90.5.minutes()));
+        }
+        assert_eq!(*recorder.finished.lock().unwrap(), 1);
+
+        // Quiet instances, e.g. those of orbit determination, do not report
+        prop.with(sc, almanac)
+            .quiet()
+            .for_duration(10.minutes())
+            .unwrap();
+        assert_eq!(recorder.reports.lock().unwrap().len(), 91);
+        assert_eq!(*recorder.finished.lock().unwrap(), 1);
+    }
+}
+
+#[cfg(test)]
+mod ut_cancellation {
+    use crate::cosmic::Spacecraft;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::progress::{CancellationToken, Progress, ProgressReporter};
+    use crate::propagators::{IntegratorOptions, PropagationError, Propagator, StopCondition};
+    use crate::time::TimeUnits;
+    use crate::State;
+    use std::sync::Arc;
+
+    /// Cancels the propagation once half of it has completed, as a GUI would.
+    #[derive(Debug)]
+    struct CancelHalfway(CancellationToken);
+
+    impl ProgressReporter for CancelHalfway {
+        fn report(&self, progress: Progress) {
+            if progress.fraction >= 0.5 {
+                self.0.cancel();
+            }
+        }
+    }
+
+    #[test]
+    fn partial_results() {
+        let epoch = fixtures::epoch();
+        let orbit = fixtures::keplerian(8_000.0, 0.2, 28.5, 30.0, 45.0, 10.0);
+        let sc = Spacecraft::builder().orbit(orbit).build();
+        let almanac = fixtures::almanac();
+
+        let token = CancellationToken::new();
+        let prop = Propagator::rk89(
+            SpacecraftDynamics::new(OrbitalDynamics::two_body()),
+            IntegratorOptions::with_fixed_step(1.minutes()),
+        )
+        .with_progress(Arc::new(CancelHalfway(token.clone())))
+        .with_cancellation(token.clone());
+
+        // The propagation stops at the end of the step where it was cancelled, and the partial state remains available
+        let mut instance = prop.with(sc, almanac.clone());
+        let err = instance.for_duration(100.minutes()).unwrap_err();
+        assert!(token.is_cancelled());
+        assert_eq!(
+            err,
+            PropagationError::Cancelled {
+                epoch: epoch + 50.minutes()
+            }
+        );
+        assert_eq!(instance.state.epoch(), epoch + 50.minutes());
+
+        // All of the entry points report the cancellation
+        assert!(matches!(
+            prop.with(sc, almanac.clone())
+                .for_duration_with_traj(100.minutes()),
+            Err(PropagationError::Cancelled { .. })
+        ));
+
+        let mut instance = prop.with(sc, almanac);
+        let err = instance
+            .until(&StopCondition::Duration(100.minutes()))
+            .unwrap_err();
+        assert!(matches!(err, PropagationError::Cancelled { epoch: e } if e == epoch));
+        assert_eq!(instance.state.epoch(), epoch);
+    }
+}
@@ -17,6 +17,7 @@
 */
 
 use anise::errors::MathError;
+use serde::{Deserialize, Serialize};
 use snafu::prelude::*;
 use std::fmt;
 
@@ -27,17 +28,54 @@ pub use self::error_ctrl::*;
 // Re-Export
 mod instance;
 pub use instance::*;
+/// Checkpoints to resume a propagation later.
+mod checkpoint;
+pub use checkpoint::*;
+/// Stream based propagation for async services.
+#[cfg(feature = "async")]
+mod stream;
+#[cfg(feature = "async")]
+pub use stream::*;
 mod propagator;
 pub use propagator::*;
 mod rk_methods;
 pub use rk_methods::*;
+/// Composable conditions to stop the propagation.
+mod stop;
+pub use stop::*;
 mod options;
 pub use options::*;
+/// Semi-analytic propagation of the mean orbital elements.
+mod semi_analytic;
+pub use semi_analytic::*;
+/// Integration schemes other than the Runge Kutta methods.
+mod stepper;
+pub use stepper::*;
+/// Taylor series integration of the point mass and J2 problem.
+mod taylor;
+pub use taylor::*;
+/// Symplectic integration for long term studies of nearly conservative dynamics.
+mod symplectic;
+pub use symplectic::*;
+/// Sundman regularized integration for highly eccentric orbits.
+mod sundman;
+pub use sundman::*;
+/// Implicit Radau IIA integration for stiff dynamics.
+mod radau;
+pub use radau::*;
+/// SGP4/SDP4 propagation of two-line element sets.
+mod sgp4;
+pub use sgp4::*;
 
-use crate::{dynamics::DynamicsError, errors::EventError, io::ConfigError, time::Duration};
+use crate::{
+    dynamics::DynamicsError,
+    errors::EventError,
+    io::ConfigError,
+    time::{Duration, Epoch},
+};
 
 /// Stores the details of the previous integration step of a given propagator. Access as `my_prop.clone().latest_details()`.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct IntegrationDetails {
     /// step size used
     pub step: Duration,
@@ -69,4 +107,8 @@ pub enum PropagationError {
     PropConfigError { source: ConfigError },
     #[snafu(display("propagation encountered a math error {source}"))]
     PropMathError { source: MathError },
+    /// Raised when the propagation is cancelled, cf. [Propagator::with_cancellation]. The state of the propagator instance is
+    /// the partial state at the epoch of the cancellation.
+    #[snafu(display("propagation cancelled at {epoch}"))]
+    Cancelled { epoch: Epoch },
 }
@@ -18,9 +18,11 @@
 
 use std::fmt;
 
+use crate::linalg::allocator::Allocator;
+use crate::linalg::{DefaultAllocator, DimName, OVector};
 use crate::time::{Duration, Unit};
 
-use super::ErrorControl;
+use super::{ComponentTolerances, ErrorControl};
 use anise::frames::Frame;
 use serde::{Deserialize, Serialize};
 use typed_builder::TypedBuilder;
@@ -48,6 +50,10 @@ pub struct IntegratorOptions {
     pub fixed_step: bool,
     #[builder(default)]
     pub error_ctrl: ErrorControl,
+    /// If specified, each of the leading state components has its own tolerances, which replaces the error control and the tolerance.
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub component_tolerances: Option<ComponentTolerances>,
     /// If a frame is specified and the propagator state is in a different frame, it it changed to this frame prior to integration.
     /// Note, when setting this, it's recommended to call `strip` on the Frame.
     #[builder(default, setter(strip_option))]
@@ -71,6 +77,7 @@ impl IntegratorOptions {
             attempts: 50,
             fixed_step: false,
             error_ctrl,
+            component_tolerances: None,
             integration_frame: None,
         }
     }
@@ -100,6 +107,7 @@ impl IntegratorOptions {
             fixed_step: true,
             attempts: 0,
             error_ctrl: ErrorControl::RSSCartesianStep,
+            component_tolerances: None,
             integration_frame: None,
         }
     }
@@ -124,6 +132,33 @@ impl IntegratorOptions {
         opts
     }
 
+    /// Returns the default options where each of the leading state components has its own tolerances.
+    #[allow(clippy::field_reassign_with_default)]
+    pub fn with_component_tolerances(tolerances: ComponentTolerances) -> Self {
+        let mut opts = Self::default();
+        opts.component_tolerances = Some(tolerances);
+        opts
+    }
+
+    /// Computes the error of a step, to be compared to the tolerance.
+    /// With component tolerances, the scaled error is multiplied by the tolerance such that the step is accepted if the scaled error is at most one.
+    pub(crate) fn step_error<N: DimName>(
+        &self,
+        error_est: &OVector<f64, N>,
+        candidate: &OVector<f64, N>,
+        cur_state: &OVector<f64, N>,
+    ) -> f64
+    where
+        DefaultAllocator: Allocator<N>,
+    {
+        match &self.component_tolerances {
+            Some(tolerances) => {
+                tolerances.estimate(error_est, candidate, cur_state) * self.tolerance
+            }
+            None => self.error_ctrl.estimate(error_est, candidate, cur_state),
+        }
+    }
+
     /// Returns a string with the information about these options
     pub fn info(&self) -> String {
         format!("{self}")
@@ -171,6 +206,7 @@ impl Default for IntegratorOptions {
             attempts: 50,
             fixed_step: false,
             error_ctrl: ErrorControl::RSSCartesianStep,
+            component_tolerances: None,
             integration_frame: None,
         }
     }
@@ -178,6 +214,7 @@ impl Default for IntegratorOptions {
 
 #[cfg(test)]
 mod ut_integr_opts {
+    use crate::fixtures;
     use hifitime::Unit;
 
     use crate::propagators::{ErrorControl, IntegratorOptions};
@@ -214,6 +251,51 @@ mod ut_integr_opts {
         assert!(!opts.fixed_step);
     }
 
+    #[test]
+    fn component_tolerances() {
+        use crate::cosmic::Spacecraft;
+        use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+        use crate::linalg::{Const, OVector};
+        use crate::propagators::{ComponentTolerances, IntegratorMethod, Propagator};
+
+        // Components with an infinite absolute tolerance do not contribute to the error
+        let tols = ComponentTolerances::spacecraft(1e-3, 1e-6, 1e-1, 0.0);
+        let mut error = OVector::<f64, Const<9>>::zeros();
+        error[7] = 1e3;
+        let state = OVector::<f64, Const<9>>::zeros();
+        assert_eq!(tols.estimate(&error, &state, &state), 0.0);
+        error[0] = 3e-3;
+        assert!((tols.estimate(&error, &state, &state) - 1.0).abs() < 1e-12);
+
+        let epoch = fixtures::epoch();
+        let orbit = fixtures::keplerian(8_000.0, 0.2, 28.5, 30.0, 45.0, 10.0);
+        let sc = Spacecraft::builder().orbit(orbit).build();
+        let almanac = fixtures::almanac();
+        let period = orbit.period().unwrap();
+        let truth = orbit.at_epoch(epoch + period).unwrap();
+
+        let mut steps = Vec::new();
+        for pos_km in [1e-9, 1e-5] {
+            let opts = IntegratorOptions::with_component_tolerances(
+                ComponentTolerances::spacecraft(pos_km, pos_km * 1e-3, 1.0, 0.0),
+            );
+            let prop = Propagator::new(
+                SpacecraftDynamics::new(OrbitalDynamics::two_body()),
+                IntegratorMethod::DormandPrince45,
+                opts,
+            );
+            let (end, traj) = prop
+                .with(sc, almanac.clone())
+                .for_duration_with_traj(period)
+                .unwrap();
+            let err_km = truth.rss_radius_km(&end.orbit).unwrap();
+            assert!(err_km < pos_km * 1e3, "{err_km} km with tol {pos_km}");
+            steps.push(traj.states.len());
+        }
+        // Looser tolerances take fewer steps
+        assert!(steps[1] < steps[0], "{steps:?}");
+    }
+
     #[test]
     fn test_serde() {
         let opts = IntegratorOptions::default();
@@ -20,12 +20,22 @@ use std::sync::Arc;
 
 use anise::almanac::Almanac;
 
-use super::{IntegrationDetails, IntegratorMethod, IntegratorOptions, PropInstance};
+use rayon::prelude::*;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant as StdInstant;
+
+use super::{
+    IntegrationDetails, IntegratorMethod, IntegratorOptions, PropCheckpoint, PropConfigSnafu,
+    PropInstance, PropagationError, Stepper,
+};
 use crate::dynamics::Dynamics;
 use crate::linalg::allocator::Allocator;
 use crate::linalg::{DefaultAllocator, OVector};
-use crate::time::Duration;
+use crate::md::trajectory::{Interpolatable, Traj};
+use crate::progress::{CancellationToken, ProgressReporter};
+use crate::time::{Duration, Unit};
 use crate::State;
+use snafu::ResultExt;
 
 /// A Propagator allows propagating a set of dynamics forward or backward in time.
 /// It is an EventTracker, without any event tracking. It includes the options, the integrator
@@ -41,6 +51,12 @@ where
     pub dynamics: D, // Stores the dynamics used. *Must* use this to get the latest values
     pub opts: IntegratorOptions, // Stores the integration options (tolerance, min/max step, init step, etc.)
     pub method: IntegratorMethod,
+    /// Reports the progress of the propagations which are not quiet, if any
+    pub progress: Option<Arc<dyn ProgressReporter>>,
+    /// Token to cooperatively cancel the propagations, and the targeting and orbit determination relying on them, if any
+    pub cancellation: Option<CancellationToken>,
+    /// Integration scheme used instead of the Runge Kutta method, if any
+    pub stepper: Option<Arc<dyn Stepper<D>>>,
 }
 
 /// The `Propagator` trait defines the functions of a propagator and of an event tracker.
@@ -57,9 +73,41 @@ where
             dynamics,
             opts,
             method,
+            progress: None,
+            cancellation: None,
+            stepper: None,
         }
     }
 
+    /// Integrates the steps with this stepper instead of the Runge Kutta method, e.g. a [super::TaylorStepper].
+    /// The integrator options still apply, as documented by each stepper.
+    pub fn with_stepper(mut self, stepper: Arc<dyn Stepper<D>>) -> Self {
+        self.stepper = Some(stepper);
+        self
+    }
+
+    /// Reports the progress of each propagation to this reporter, unless the propagation instance is quiet.
+    pub fn with_progress(mut self, reporter: Arc<dyn ProgressReporter>) -> Self {
+        self.progress = Some(reporter);
+        self
+    }
+
+    /// Allows cancelling the propagations with this token: a cancelled propagation stops at the end of its current step
+    /// and returns [PropagationError::Cancelled], the state reached so far remaining available on the propagator instance.
+    /// Targeters return their last iterate in [crate::md::TargetingError::Cancelled], and orbit determination processes
+    /// keep the estimates processed until the cancellation.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Returns whether the cancellation of the propagations was requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .is_some_and(|token| token.is_cancelled())
+    }
+
     /// Set the tolerance for the propagator
     pub fn set_tolerance(&mut self, tol: f64) {
         self.opts.tolerance = tol;
@@ -104,9 +152,62 @@ where
             step_size: self.opts.init_step,
             fixed_step: self.opts.fixed_step,
             k,
+            dense_steps: None,
         }
     }
 
+    /// Resumes a propagation from the provided checkpoint, e.g. after a restart or on another machine.
+    /// The almanac is not part of the checkpoint and must be provided again.
+    pub fn resume(
+        &self,
+        checkpoint: &PropCheckpoint<D::StateType>,
+        almanac: Arc<Almanac>,
+    ) -> Result<PropInstance<'_, D>, PropagationError> {
+        let state = checkpoint.restored_state().context(PropConfigSnafu)?;
+        let mut instance = self.with(state, almanac);
+        instance.set_step(checkpoint.step_size, checkpoint.fixed_step);
+        instance.details = checkpoint.details;
+        Ok(instance)
+    }
+
+    /// Propagates each of the initial states for the provided duration on the rayon thread pool, sharing these dynamics and the almanac.
+    /// Returns the end state and trajectory of each run, in the order of the initial states.
+    /// A failed run is reported as its error and does not abort the other runs of the ensemble.
+    pub fn propagate_ensemble(
+        &self,
+        states: &[D::StateType],
+        duration: Duration,
+        almanac: Arc<Almanac>,
+    ) -> Vec<Result<(D::StateType, Traj<D::StateType>), PropagationError>>
+    where
+        <DefaultAllocator as Allocator<<D::StateType as State>::VecLength>>::Buffer<f64>: Send,
+        D::StateType: Interpolatable,
+    {
+        #[cfg(not(target_arch = "wasm32"))]
+        let start = StdInstant::now();
+
+        let results = states
+            .par_iter()
+            .map(|state| {
+                self.with(*state, almanac.clone())
+                    .quiet()
+                    .for_duration_with_traj(duration)
+            })
+            .collect::<Vec<_>>();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let clock_time = StdInstant::now() - start;
+            info!(
+                "Propagated {} states in {}",
+                states.len(),
+                clock_time.as_secs_f64() * Unit::Second
+            );
+        }
+
+        results
+    }
+
     /// Default propagator is an RK89 with the default PropOpts.
     pub fn default(dynamics: D) -> Self {
         Self::rk89(dynamics, IntegratorOptions::default())
@@ -119,3 +220,60 @@ where
         Self::dp78(dynamics, IntegratorOptions::default())
     }
 }
+
+#[cfg(test)]
+mod ut_ensemble {
+    use crate::cosmic::{Orbit, Spacecraft};
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::propagators::Propagator;
+    use crate::time::TimeUnits;
+    use crate::State;
+    use anise::constants::frames::EARTH_J2000;
+
+    #[test]
+    fn ensemble_with_failed_run() {
+        let eme2k = fixtures::eme2k();
+        let epoch = fixtures::epoch();
+        let almanac = fixtures::almanac();
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+
+        let mut states = (0..4)
+            .map(|i| {
+                let orbit = Orbit::try_keplerian(
+                    7_000.0 + 500.0 * i as f64,
+                    0.01,
+                    28.5,
+                    30.0,
+                    45.0,
+                    10.0,
+                    epoch,
+                    eme2k,
+                )
+                .unwrap();
+                Spacecraft::builder().orbit(orbit).build()
+            })
+            .collect::<Vec<_>>();
+        // Without a gravitational parameter, the dynamics cannot be evaluated
+        states[2].orbit.frame = EARTH_J2000;
+
+        let results = prop.propagate_ensemble(&states, 2.hours(), almanac.clone());
+        assert_eq!(results.len(), states.len());
+
+        for (i, (state, result)) in states.iter().zip(results.iter()).enumerate() {
+            if i == 2 {
+                assert!(result.is_err());
+                continue;
+            }
+            let (end, traj) = result.as_ref().unwrap();
+            assert_eq!(end.epoch(), epoch + 2.hours());
+            assert_eq!(traj.first().epoch(), epoch);
+            // Each run matches its sequential propagation
+            let truth = prop
+                .with(*state, almanac.clone())
+                .for_duration(2.hours())
+                .unwrap();
+            assert_eq!(*end, truth);
+        }
+    }
+}
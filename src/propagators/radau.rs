@@ -0,0 +1,429 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{
+    check_no_stm, DynamicsSnafu, IntegrationDetails, PropagationError, Propagator, Stepper,
+};
+use crate::cosmic::Spacecraft;
+use crate::dynamics::{Dynamics, SpacecraftDynamics, MAX_EXTRA_STATES};
+use crate::linalg::{Const, DMatrix, DVector, OMatrix, OVector};
+use crate::time::{Duration, Epoch, Unit};
+use crate::State;
+use anise::almanac::Almanac;
+use anise::errors::MathError;
+use snafu::ResultExt;
+use std::sync::Arc;
+
+/// Position, velocity, Cr, Cd, propellant mass, and the extra states.
+type RadauSize = Const<{ RADAU_EXTRA_INDEX + MAX_EXTRA_STATES }>;
+type RadauVector = OVector<f64, RadauSize>;
+
+/// Index of the extra states in the integrated vector, after the spacecraft state.
+const RADAU_EXTRA_INDEX: usize = 9;
+
+const SQRT_6: f64 = 2.449_489_742_783_178;
+/// Nodes of the three stage Radau IIA method.
+const RADAU_C: [f64; 3] = [(4.0 - SQRT_6) / 10.0, (4.0 + SQRT_6) / 10.0, 1.0];
+/// Coefficients of the three stage Radau IIA method: the last row is also the weights, since the method is stiffly accurate.
+const RADAU_A: [[f64; 3]; 3] = [
+    [
+        (88.0 - 7.0 * SQRT_6) / 360.0,
+        (296.0 - 169.0 * SQRT_6) / 1800.0,
+        (-2.0 + 3.0 * SQRT_6) / 225.0,
+    ],
+    [
+        (296.0 + 169.0 * SQRT_6) / 1800.0,
+        (88.0 + 7.0 * SQRT_6) / 360.0,
+        (-2.0 - 3.0 * SQRT_6) / 225.0,
+    ],
+    [(16.0 - SQRT_6) / 36.0, (16.0 + SQRT_6) / 36.0, 1.0 / 9.0],
+];
+/// Order of the three stage Radau IIA method.
+const RADAU_ORDER: i32 = 5;
+/// Maximum number of simplified Newton iterations to solve for the stages, after which the step is halved.
+const MAX_NEWTON_ITERATIONS: usize = 10;
+
+/// A stepper using the implicit three stage Radau IIA method of order 5, which is L-stable.
+///
+/// Explicit methods need prohibitively many steps when the dynamics are stiff, e.g. the many revolution low thrust spirals
+/// with averaged dynamics. The stages are solved by simplified Newton iterations, with a finite difference Jacobian of the
+/// dynamics computed once per step. The local error is estimated by step doubling and is controlled like the explicit
+/// methods, using the integrator options. The STM is not propagated.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RadauStepper;
+
+impl RadauStepper {
+    /// Performs one adaptive step and returns the new state, the step used, and the proposed next step.
+    fn adaptive_step(
+        &self,
+        prop: &Propagator<SpacecraftDynamics>,
+        sc: &Spacecraft,
+        mut step_s: f64,
+        fixed_step: bool,
+        details: &mut IntegrationDetails,
+        almanac: Arc<Almanac>,
+    ) -> Result<(RadauVector, f64, f64), PropagationError> {
+        let min_step_s = prop.opts.min_step.abs().to_seconds();
+        let max_step_s = prop.opts.max_step.abs().to_seconds();
+        let sign = step_s.signum();
+        let y = Self::to_vector(sc);
+
+        loop {
+            let jacobian = self.jacobian(&prop.dynamics, sc, &y, almanac.clone())?;
+            let full = self.radau_step(prop, sc, &y, step_s, &jacobian, almanac.clone())?;
+
+            if fixed_step {
+                return match full {
+                    Some(next_y) => Ok((next_y, step_s, step_s)),
+                    None => Err(PropagationError::PropMathError {
+                        source: MathError::MaxIterationsReached {
+                            iter: MAX_NEWTON_ITERATIONS,
+                            action: "solving for the Radau stages with a fixed step",
+                        },
+                    }),
+                };
+            }
+
+            // Step doubling: the difference between one step and two half steps estimates the local error.
+            let doubled = match full {
+                Some(full) => {
+                    match self.radau_step(prop, sc, &y, 0.5 * step_s, &jacobian, almanac.clone())? {
+                        Some(half) => {
+                            let mid = Self::to_spacecraft(
+                                &half,
+                                sc.epoch() + Duration::from_seconds(0.5 * step_s),
+                                sc,
+                            );
+                            let mid_jacobian =
+                                self.jacobian(&prop.dynamics, &mid, &half, almanac.clone())?;
+                            self.radau_step(
+                                prop,
+                                &mid,
+                                &half,
+                                0.5 * step_s,
+                                &mid_jacobian,
+                                almanac.clone(),
+                            )?
+                            .map(|next_y| (full, next_y))
+                        }
+                        None => None,
+                    }
+                }
+                None => None,
+            };
+
+            let error = match doubled {
+                Some((full, next_y)) => {
+                    let error_est = (next_y - full) / f64::from(2_i32.pow(RADAU_ORDER as u32) - 1);
+                    let error = prop.opts.step_error(&error_est, &next_y, &y);
+                    details.error = error;
+
+                    if error <= prop.opts.tolerance
+                        || step_s.abs() <= min_step_s
+                        || details.attempts >= prop.opts.attempts
+                    {
+                        if next_y.iter().any(|x| x.is_nan()) {
+                            return Err(PropagationError::PropMathError {
+                                source: MathError::DomainError {
+                                    value: f64::NAN,
+                                    msg: "try decreasing the step size; part of state vector is",
+                                },
+                            });
+                        }
+                        if details.attempts >= prop.opts.attempts {
+                            warn!(
+                                "Could not further decrease step size: maximum number of attempts reached ({})",
+                                details.attempts
+                            );
+                        }
+
+                        let next_step_s = if error < prop.opts.tolerance {
+                            (0.9 * step_s.abs()
+                                * (prop.opts.tolerance / error)
+                                    .powf(1.0 / f64::from(RADAU_ORDER + 1)))
+                            .min(max_step_s)
+                        } else {
+                            step_s.abs()
+                        };
+
+                        return Ok((next_y, step_s, sign * next_step_s.max(min_step_s)));
+                    }
+                    Some(error)
+                }
+                None => {
+                    if step_s.abs() <= min_step_s || details.attempts >= prop.opts.attempts {
+                        return Err(PropagationError::PropMathError {
+                            source: MathError::MaxIterationsReached {
+                                iter: MAX_NEWTON_ITERATIONS,
+                                action: "solving for the Radau stages at the minimum step",
+                            },
+                        });
+                    }
+                    None
+                }
+            };
+
+            details.attempts += 1;
+            step_s = sign
+                * match error {
+                    Some(error) => (0.9
+                        * step_s.abs()
+                        * (prop.opts.tolerance / error).powf(1.0 / f64::from(RADAU_ORDER + 1)))
+                    .max(0.1 * step_s.abs()),
+                    // The Newton iterations did not converge
+                    None => 0.5 * step_s.abs(),
+                }
+                .max(min_step_s);
+        }
+    }
+
+    /// Performs a single Radau IIA step, or returns None if the Newton iterations did not converge.
+    fn radau_step(
+        &self,
+        prop: &Propagator<SpacecraftDynamics>,
+        sc: &Spacecraft,
+        y: &RadauVector,
+        step_s: f64,
+        jacobian: &OMatrix<f64, RadauSize, RadauSize>,
+        almanac: Arc<Almanac>,
+    ) -> Result<Option<RadauVector>, PropagationError> {
+        const N: usize = RADAU_EXTRA_INDEX + MAX_EXTRA_STATES;
+
+        // Iteration matrix of the simplified Newton method: I - h (A ⊗ J)
+        let mut iter_mat = DMatrix::<f64>::identity(3 * N, 3 * N);
+        for i in 0..3 {
+            for j in 0..3 {
+                for r in 0..N {
+                    for c in 0..N {
+                        iter_mat[(i * N + r, j * N + c)] -=
+                            step_s * RADAU_A[i][j] * jacobian[(r, c)];
+                    }
+                }
+            }
+        }
+        let lu = iter_mat.lu();
+
+        let tolerance = prop.opts.tolerance.max(1e-14) * (1.0 + y.amax());
+        let mut z = [RadauVector::zeros(); 3];
+
+        for _ in 0..MAX_NEWTON_ITERATIONS {
+            let mut f = [RadauVector::zeros(); 3];
+            for (i, fi) in f.iter_mut().enumerate() {
+                *fi = self.derivative(
+                    &prop.dynamics,
+                    sc,
+                    RADAU_C[i] * step_s,
+                    &(y + z[i]),
+                    almanac.clone(),
+                )?;
+            }
+
+            let mut residual = DVector::<f64>::zeros(3 * N);
+            for i in 0..3 {
+                let mut gi = -z[i];
+                for j in 0..3 {
+                    gi += step_s * RADAU_A[i][j] * f[j];
+                }
+                residual.rows_mut(i * N, N).copy_from(&gi);
+            }
+
+            let delta = match lu.solve(&residual) {
+                Some(delta) => delta,
+                None => return Ok(None),
+            };
+            for (i, zi) in z.iter_mut().enumerate() {
+                *zi += delta.rows(i * N, N);
+            }
+
+            if delta.amax() <= tolerance {
+                // The method is stiffly accurate, so the last stage is the next state.
+                return Ok(Some(y + z[2]));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the finite difference Jacobian of the dynamics at the provided state.
+    fn jacobian(
+        &self,
+        dynamics: &SpacecraftDynamics,
+        sc: &Spacecraft,
+        y: &RadauVector,
+        almanac: Arc<Almanac>,
+    ) -> Result<OMatrix<f64, RadauSize, RadauSize>, PropagationError> {
+        let f0 = self.derivative(dynamics, sc, 0.0, y, almanac.clone())?;
+        let mut jacobian = OMatrix::<f64, RadauSize, RadauSize>::zeros();
+        // The columns of the unused extra states are zero
+        for c in 0..RADAU_EXTRA_INDEX + dynamics.extra_states.len() {
+            let pert = f64::EPSILON.sqrt() * y[c].abs().max(1e-5);
+            let mut y_pert = *y;
+            y_pert[c] += pert;
+            let f_pert = self.derivative(dynamics, sc, 0.0, &y_pert, almanac.clone())?;
+            jacobian.set_column(c, &((f_pert - f0) / pert));
+        }
+        Ok(jacobian)
+    }
+
+    /// Returns the derivative of the state at the provided time past the epoch of the context.
+    fn derivative(
+        &self,
+        dynamics: &SpacecraftDynamics,
+        ctx: &Spacecraft,
+        delta_t_s: f64,
+        y: &RadauVector,
+        almanac: Arc<Almanac>,
+    ) -> Result<RadauVector, PropagationError> {
+        let mut state_vec = ctx.to_vector();
+        Self::scatter(y, &mut state_vec);
+        let d_x = dynamics
+            .eom(delta_t_s, &state_vec, ctx, almanac)
+            .context(DynamicsSnafu)?;
+        Ok(Self::gather(&d_x))
+    }
+
+    /// Gathers the spacecraft state and the extra states from the propagation vector.
+    fn gather(state_vec: &OVector<f64, Const<306>>) -> RadauVector {
+        let mut y = RadauVector::zeros();
+        y.fixed_rows_mut::<RADAU_EXTRA_INDEX>(0)
+            .copy_from(&state_vec.fixed_rows::<RADAU_EXTRA_INDEX>(0));
+        y.fixed_rows_mut::<MAX_EXTRA_STATES>(RADAU_EXTRA_INDEX)
+            .copy_from(&state_vec.fixed_rows::<MAX_EXTRA_STATES>(Spacecraft::EXTRA_INDEX));
+        y
+    }
+
+    /// Scatters the spacecraft state and the extra states into the propagation vector.
+    fn scatter(y: &RadauVector, state_vec: &mut OVector<f64, Const<306>>) {
+        state_vec
+            .fixed_rows_mut::<RADAU_EXTRA_INDEX>(0)
+            .copy_from(&y.fixed_rows::<RADAU_EXTRA_INDEX>(0));
+        state_vec
+            .fixed_rows_mut::<MAX_EXTRA_STATES>(Spacecraft::EXTRA_INDEX)
+            .copy_from(&y.fixed_rows::<MAX_EXTRA_STATES>(RADAU_EXTRA_INDEX));
+    }
+
+    fn to_vector(sc: &Spacecraft) -> RadauVector {
+        Self::gather(&sc.to_vector())
+    }
+
+    fn to_spacecraft(y: &RadauVector, epoch: Epoch, template: &Spacecraft) -> Spacecraft {
+        let mut state_vec = template.to_vector();
+        Self::scatter(y, &mut state_vec);
+        let mut sc = *template;
+        sc.set(epoch, &state_vec);
+        sc
+    }
+}
+
+impl Stepper<SpacecraftDynamics> for RadauStepper {
+    fn step(
+        &self,
+        prop: &Propagator<SpacecraftDynamics>,
+        state: &Spacecraft,
+        step_size: &mut Duration,
+        fixed_step: bool,
+        details: &mut IntegrationDetails,
+        almanac: Arc<Almanac>,
+    ) -> Result<(Duration, OVector<f64, <Spacecraft as State>::VecLength>), PropagationError> {
+        check_no_stm(state, "Radau")?;
+
+        let requested_s = step_size.to_seconds();
+        let (next_y, used_s, next_s) =
+            self.adaptive_step(prop, state, requested_s, fixed_step, details, almanac)?;
+
+        let step = if used_s == requested_s {
+            *step_size
+        } else {
+            Unit::Second * used_s
+        };
+        if !fixed_step {
+            *step_size = Unit::Second * next_s;
+        }
+
+        Ok((
+            step,
+            Self::to_spacecraft(&next_y, state.epoch() + step, state).to_vector(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod ut_radau {
+    use super::*;
+    use crate::cosmic::GuidanceMode;
+    use crate::dynamics::guidance::{LocalFrame, Maneuver, Thruster};
+    use crate::dynamics::OrbitalDynamics;
+    use crate::fixtures;
+    use crate::linalg::Vector3;
+    use crate::propagators::{IntegratorMethod, IntegratorOptions};
+    use crate::time::TimeUnits;
+    use anise::structure::spacecraft::Mass;
+
+    #[test]
+    fn low_thrust_vs_explicit() {
+        let epoch = fixtures::epoch();
+        let orbit = fixtures::keplerian(7_000.0, 0.01, 28.5, 30.0, 45.0, 10.0);
+        let mut sc = Spacecraft::builder()
+            .orbit(orbit)
+            .mass(Mass::from_dry_and_prop_masses(500.0, 100.0))
+            .thruster(Thruster::new(0.5, 1_500.0))
+            .build();
+        sc.mut_mode(GuidanceMode::Thrust);
+        // Continuous tangential thrust
+        let mnvr = Maneuver::from_time_invariant(
+            epoch,
+            epoch + 1.days(),
+            1.0,
+            Vector3::new(1.0, 0.0, 0.0),
+            LocalFrame::VNC,
+        );
+        let dynamics =
+            SpacecraftDynamics::from_guidance_law(OrbitalDynamics::two_body(), Arc::new(mnvr));
+
+        let almanac = fixtures::almanac();
+        let duration = 6.hours();
+        let expected = Propagator::default(dynamics.clone())
+            .with(sc, almanac.clone())
+            .for_duration(duration)
+            .unwrap();
+
+        let prop = Propagator::new(
+            dynamics,
+            IntegratorMethod::RungeKutta89,
+            IntegratorOptions::with_tolerance(1e-10),
+        )
+        .with_stepper(Arc::new(RadauStepper));
+        let (end, traj) = prop
+            .with(sc, almanac.clone())
+            .for_duration_with_traj(duration)
+            .unwrap();
+
+        assert_eq!(end.epoch(), epoch + duration);
+        assert!(traj.states.len() > 2);
+        // The spiral raised the orbit
+        assert!(end.orbit.sma_km().unwrap() > orbit.sma_km().unwrap() + 10.0);
+        let err_km = expected.orbit.rss_radius_km(&end.orbit).unwrap();
+        assert!(err_km < 1e-2, "{err_km} km");
+        assert!((expected.mass.prop_mass_kg - end.mass.prop_mass_kg).abs() < 1e-8);
+
+        // And propagating backward returns to the initial state
+        let back = prop.with(end, almanac).for_duration(-duration).unwrap();
+        assert_eq!(back.epoch(), epoch);
+        assert!(orbit.rss_radius_km(&back.orbit).unwrap() < 1e-2);
+    }
+}
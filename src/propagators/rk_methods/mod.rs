@@ -53,6 +53,15 @@ where
     /// Returns a pointer to a list of f64 corresponding to the b_i and b^*_i coefficients of the
     /// Butcher table for that RK. `Self.a_coeffs().len()` must be of size (order+1)*2.
     const B_COEFFS: &'static [f64];
+
+    /// Returns the degree of the polynomials of the continuous extension (dense output) of this RK, or zero if it has none.
+    const DENSE_DEGREE: usize = 0;
+    /// Returns the A coefficients of the additional stages needed by the continuous extension, flattened like `A_COEFFS`
+    /// and starting with the row of stage `STAGES + 1`.
+    const DENSE_A_COEFFS: &'static [f64] = &[];
+    /// Returns the coefficients of θ, θ², ..., θ^DENSE_DEGREE of the continuous extension weight b_i(θ) of each stage,
+    /// including the additional ones, such that y(t + θh) = y + h \sum_i b_i(θ) k_i.
+    const DENSE_COEFFS: &'static [f64] = &[];
 }
 
 /// Enum of supported integration methods, all of which are part of the Runge Kutta family of ordinary differential equation (ODE) solvers.
@@ -71,6 +80,8 @@ pub enum IntegratorMethod {
     CashKarp45,
     /// Verner56 is an RK Verner integrator of order 5-6. Coefficients taken from [here (PDF)](http://people.math.sfu.ca/~jverner/classify.1992.ps).
     Verner56,
+    /// `Dop853` is the 8th order Dormand-Prince integrator of Hairer with a 5th order error estimate, recommended for very tight tolerances.
+    Dop853,
 }
 
 impl IntegratorMethod {
@@ -84,6 +95,7 @@ impl IntegratorMethod {
             Self::RungeKutta4 => RK4Fixed::ORDER,
             Self::CashKarp45 => CashKarp45::ORDER,
             Self::Verner56 => Verner56::ORDER,
+            Self::Dop853 => Dop853::ORDER,
         }
     }
 
@@ -96,6 +108,7 @@ impl IntegratorMethod {
             Self::RungeKutta4 => RK4Fixed::STAGES,
             Self::CashKarp45 => CashKarp45::STAGES,
             Self::Verner56 => Verner56::STAGES,
+            Self::Dop853 => Dop853::STAGES,
         }
     }
 
@@ -111,6 +124,7 @@ impl IntegratorMethod {
             Self::RungeKutta4 => RK4Fixed::A_COEFFS,
             Self::CashKarp45 => CashKarp45::A_COEFFS,
             Self::Verner56 => Verner56::A_COEFFS,
+            Self::Dop853 => Dop853::A_COEFFS,
         }
     }
     /// Returns a pointer to a list of f64 corresponding to the b_i and b^*_i coefficients of the
@@ -123,6 +137,53 @@ impl IntegratorMethod {
             Self::RungeKutta4 => RK4Fixed::B_COEFFS,
             Self::CashKarp45 => CashKarp45::B_COEFFS,
             Self::Verner56 => Verner56::B_COEFFS,
+            Self::Dop853 => Dop853::B_COEFFS,
+        }
+    }
+
+    /// Returns whether this integrator provides a continuous extension (dense output) of its steps.
+    pub const fn has_dense_output(self) -> bool {
+        self.dense_degree() > 0
+    }
+
+    /// Returns the degree of the polynomials of the continuous extension of this integrator, or zero if it has none.
+    pub const fn dense_degree(self) -> usize {
+        match self {
+            Self::RungeKutta89 => RK89::DENSE_DEGREE,
+            Self::DormandPrince78 => Dormand78::DENSE_DEGREE,
+            Self::DormandPrince45 => Dormand45::DENSE_DEGREE,
+            Self::RungeKutta4 => RK4Fixed::DENSE_DEGREE,
+            Self::CashKarp45 => CashKarp45::DENSE_DEGREE,
+            Self::Verner56 => Verner56::DENSE_DEGREE,
+            Self::Dop853 => Dop853::DENSE_DEGREE,
+        }
+    }
+
+    /// Returns the A coefficients of the additional stages needed by the continuous extension, flattened like the
+    /// A coefficients and starting with the row of the first additional stage.
+    pub const fn dense_a_coeffs(self) -> &'static [f64] {
+        match self {
+            Self::RungeKutta89 => RK89::DENSE_A_COEFFS,
+            Self::DormandPrince78 => Dormand78::DENSE_A_COEFFS,
+            Self::DormandPrince45 => Dormand45::DENSE_A_COEFFS,
+            Self::RungeKutta4 => RK4Fixed::DENSE_A_COEFFS,
+            Self::CashKarp45 => CashKarp45::DENSE_A_COEFFS,
+            Self::Verner56 => Verner56::DENSE_A_COEFFS,
+            Self::Dop853 => Dop853::DENSE_A_COEFFS,
+        }
+    }
+
+    /// Returns the polynomial coefficients of the continuous extension weights of each stage, flattened by stage,
+    /// i.e. `dense_degree()` coefficients (of θ to θ^dense_degree) per stage, including the additional stages.
+    pub const fn dense_coeffs(self) -> &'static [f64] {
+        match self {
+            Self::RungeKutta89 => RK89::DENSE_COEFFS,
+            Self::DormandPrince78 => Dormand78::DENSE_COEFFS,
+            Self::DormandPrince45 => Dormand45::DENSE_COEFFS,
+            Self::RungeKutta4 => RK4Fixed::DENSE_COEFFS,
+            Self::CashKarp45 => CashKarp45::DENSE_COEFFS,
+            Self::Verner56 => Verner56::DENSE_COEFFS,
+            Self::Dop853 => Dop853::DENSE_COEFFS,
         }
     }
 }
@@ -144,6 +205,7 @@ impl FromStr for IntegratorMethod {
             "rungekutta4" => Ok(Self::RungeKutta4),
             "cashkarp45" => Ok(Self::CashKarp45),
             "verner56" => Ok(Self::Verner56),
+            "dop853" => Ok(Self::Dop853),
             _ => {
                 let valid = [
                     "RungeKutta89",
@@ -152,6 +214,7 @@ impl FromStr for IntegratorMethod {
                     "RungeKutta4",
                     "CashKarp45",
                     "Verner56",
+                    "Dop853",
                 ];
                 let valid_msg = valid.join(",");
                 Err(PropagationError::PropConfigError {
@@ -166,6 +229,7 @@ impl FromStr for IntegratorMethod {
 
 #[cfg(test)]
 mod ut_propagator {
+    use crate::fixtures;
     use std::str::FromStr;
 
     use super::IntegratorMethod;
@@ -179,10 +243,44 @@ mod ut_propagator {
             "RungeKutta4",
             "CashKarp45",
             "Verner56",
+            "Dop853",
         ];
         for method in valid {
             assert!(IntegratorMethod::from_str(method.to_uppercase().as_str()).is_ok());
         }
         assert!(IntegratorMethod::from_str("blah").is_err());
     }
+
+    #[test]
+    fn dop853_two_body_period() {
+        use crate::cosmic::{Orbit, Spacecraft};
+        use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+        use crate::propagators::{ErrorControl, IntegratorOptions, Propagator};
+        use crate::time::{Epoch, Unit};
+
+        let eme2k = fixtures::eme2k();
+        let epoch = Epoch::from_gregorian_tai_at_midnight(2020, 1, 1);
+        let orbit = Orbit::keplerian(26_600.0, 0.7, 63.4, 10.0, 270.0, 0.0, epoch, eme2k);
+        let period = orbit.period().unwrap();
+
+        let prop = Propagator::new(
+            SpacecraftDynamics::new(OrbitalDynamics::two_body()),
+            IntegratorMethod::Dop853,
+            IntegratorOptions::with_adaptive_step(
+                0.01 * Unit::Second,
+                Unit::Hour * 1,
+                1e-12,
+                ErrorControl::RSSCartesianStep,
+            ),
+        );
+
+        let final_state = prop
+            .with(Spacecraft::from(orbit), fixtures::almanac())
+            .for_duration(period)
+            .unwrap();
+
+        // After one period, the spacecraft is back at periapsis.
+        assert!(orbit.rss_radius_km(&final_state.orbit).unwrap() < 1e-5);
+        assert!(orbit.rss_velocity_km_s(&final_state.orbit).unwrap() < 1e-8);
+    }
 }
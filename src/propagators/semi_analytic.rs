@@ -0,0 +1,596 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{DynamicsSnafu, PropConfigSnafu, PropagationError};
+pub use crate::cosmic::EquinoctialElements;
+use crate::cosmic::{AstroPhysicsSnafu, Spacecraft};
+use crate::dynamics::{Dynamics, DynamicsAstroSnafu, SpacecraftDynamics};
+use crate::io::ConfigError;
+use crate::linalg::{Vector3, Vector6};
+use crate::md::trajectory::Traj;
+use crate::time::{Duration, Epoch};
+use crate::State;
+use anise::almanac::Almanac;
+use anise::errors::MathError;
+use snafu::ResultExt;
+use std::f64::consts::{PI, TAU};
+use std::sync::Arc;
+
+/// Velocity perturbation used to compute the Gauss-form directional derivative of the elements, in km/s.
+const VELOCITY_FD_STEP_KM_S: f64 = 1e-6;
+/// Number of fixed point iterations to compute the mean elements of osculating elements: each reduces the error by a
+/// factor of the order of the perturbations, e.g. J2.
+const MEAN_ELEMENTS_ITERATIONS: usize = 4;
+
+/// A semi-analytic propagator of the mean equinoctial elements, in the spirit of DSST.
+///
+/// The secular and long-period rates of the mean elements are computed by averaging the Gauss variational
+/// equations over the mean anomaly, using the perturbing accelerations of the provided [SpacecraftDynamics]
+/// (e.g. J2-J4 from the spherical harmonics, drag, solar radiation pressure, and lunisolar point masses).
+/// The mean elements are then integrated with a fixed step RK4, typically of several hours to a day,
+/// which makes this propagator orders of magnitude faster than the numerical propagators for long term studies.
+///
+/// The initial osculating state is converted to mean elements by removing the first order short-periodic terms of the
+/// averaged perturbations (e.g. the first order J2 transformation with the J2 harmonics), which are computed from the
+/// same quadrature. These terms are added back to the output states, unless the mean trajectory is requested with
+/// [SemiAnalyticPropagator::with_mean_output]. The averaging assumes that the perturbations are constant in time
+/// over one revolution (the epoch is frozen during the quadrature), and the mass of the spacecraft is constant.
+#[derive(Clone)]
+pub struct SemiAnalyticPropagator {
+    /// The dynamics whose perturbing accelerations are averaged; guidance laws are not supported.
+    pub dynamics: SpacecraftDynamics,
+    /// The fixed integration step of the mean elements
+    pub step: Duration,
+    /// Number of uniformly spaced mean anomaly nodes used for the averaging quadrature
+    pub quadrature_points: usize,
+    /// Set to output the mean states instead of the osculating states
+    pub mean_output: bool,
+}
+
+impl SemiAnalyticPropagator {
+    /// Initializes a new semi-analytic propagator with 32 quadrature points.
+    pub fn new(dynamics: SpacecraftDynamics, step: Duration) -> Self {
+        Self {
+            dynamics,
+            step,
+            quadrature_points: 32,
+            mean_output: false,
+        }
+    }
+
+    /// Sets the number of quadrature points used for the averaging.
+    pub fn with_quadrature_points(mut self, quadrature_points: usize) -> Self {
+        self.quadrature_points = quadrature_points;
+        self
+    }
+
+    /// Outputs the mean states, i.e. without the short-periodic terms.
+    pub fn with_mean_output(mut self) -> Self {
+        self.mean_output = true;
+        self
+    }
+
+    /// Returns the mean state of the provided osculating state, i.e. without the short-periodic terms.
+    pub fn mean_state(
+        &self,
+        osculating: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<Spacecraft, PropagationError> {
+        self.check_config()?;
+        let template = Self::template(osculating);
+        let y = self.to_mean(
+            &Self::elements_of(osculating)?.to_vector(),
+            &template,
+            almanac,
+        )?;
+        Self::to_state(&y, osculating.epoch(), osculating)
+    }
+
+    /// Returns the osculating state of the provided mean state, i.e. with the short-periodic terms.
+    pub fn osculating_state(
+        &self,
+        mean: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<Spacecraft, PropagationError> {
+        self.check_config()?;
+        let elements = Self::elements_of(mean)?;
+        let y = elements.to_vector()
+            + self.short_periodics(&elements, &Self::template(mean), almanac)?;
+        Self::to_state(&y, mean.epoch(), mean)
+    }
+
+    /// Returns the rates of the mean equinoctial elements of the provided mean spacecraft state,
+    /// ordered as [a, h, k, p, q, λ] (in km/s and rad/s).
+    pub fn mean_rates(
+        &self,
+        state: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<Vector6<f64>, PropagationError> {
+        self.check_config()?;
+        self.averaged_rates(&Self::elements_of(state)?, &Self::template(state), almanac)
+    }
+
+    /// Propagates the provided state for the provided duration and returns the state at the end.
+    pub fn for_duration(
+        &self,
+        state: Spacecraft,
+        duration: Duration,
+        almanac: Arc<Almanac>,
+    ) -> Result<Spacecraft, PropagationError> {
+        Ok(self.propagate(state, duration, almanac)?.0)
+    }
+
+    /// Propagates the provided state until the provided epoch and returns the state at that epoch.
+    pub fn until_epoch(
+        &self,
+        state: Spacecraft,
+        end_time: Epoch,
+        almanac: Arc<Almanac>,
+    ) -> Result<Spacecraft, PropagationError> {
+        self.for_duration(state, end_time - state.epoch(), almanac)
+    }
+
+    /// Propagates the provided state for the provided duration, and also returns the trajectory sampled
+    /// every `output_step`. The samples between integration nodes are built by cubic Hermite interpolation of the
+    /// mean elements, so the trajectory can be used wherever a numerically propagated trajectory is expected.
+    pub fn for_duration_with_traj(
+        &self,
+        state: Spacecraft,
+        duration: Duration,
+        output_step: Duration,
+        almanac: Arc<Almanac>,
+    ) -> Result<(Spacecraft, Traj<Spacecraft>), PropagationError> {
+        if output_step <= Duration::ZERO {
+            return Err(PropagationError::PropConfigError {
+                source: ConfigError::InvalidConfig {
+                    msg: format!("output step must be strictly positive but got {output_step}"),
+                },
+            });
+        }
+
+        let (final_state, nodes) = self.propagate(state, duration, almanac.clone())?;
+
+        let start = state.epoch();
+        let end = start + duration;
+        let sign = if duration < Duration::ZERO { -1 } else { 1 };
+
+        let mut traj = Traj::new();
+        let mut seg = 0;
+        let mut i = 0_i64;
+        loop {
+            let epoch = start + (i * sign) * output_step;
+            if (sign > 0 && epoch >= end) || (sign < 0 && epoch <= end) {
+                break;
+            }
+            // Find the segment which brackets this epoch.
+            while seg + 2 < nodes.len()
+                && sign as f64 * (epoch - nodes[seg + 1].0).to_seconds() > 0.0
+            {
+                seg += 1;
+            }
+            let (t0, y0, ydot0) = &nodes[seg];
+            let (t1, y1, ydot1) = &nodes[seg + 1];
+            let h_s = (*t1 - *t0).to_seconds();
+            let s = (epoch - *t0).to_seconds() / h_s;
+            let y = y0 * (2.0 * s.powi(3) - 3.0 * s.powi(2) + 1.0)
+                + ydot0 * (h_s * (s.powi(3) - 2.0 * s.powi(2) + s))
+                + y1 * (-2.0 * s.powi(3) + 3.0 * s.powi(2))
+                + ydot1 * (h_s * (s.powi(3) - s.powi(2)));
+
+            traj.states
+                .push(self.to_spacecraft(&y, epoch, &state, almanac.clone())?);
+            i += 1;
+        }
+        traj.states.push(final_state);
+        traj.finalize();
+
+        Ok((final_state, traj))
+    }
+
+    /// Integrates the mean elements of the provided osculating state and returns the final state along with the
+    /// integration nodes as (epoch, mean elements, mean element rates).
+    #[allow(clippy::type_complexity)]
+    fn propagate(
+        &self,
+        state: Spacecraft,
+        duration: Duration,
+        almanac: Arc<Almanac>,
+    ) -> Result<(Spacecraft, Vec<(Epoch, Vector6<f64>, Vector6<f64>)>), PropagationError> {
+        self.check_config()?;
+
+        let elements = Self::elements_of(&state)?;
+
+        if elements.sma_km <= 0.0 || elements.ecc() >= 1.0 {
+            return Err(PropagationError::PropMathError {
+                source: MathError::DomainError {
+                    value: elements.ecc(),
+                    msg: "semi-analytic propagation requires an elliptical orbit",
+                },
+            });
+        }
+
+        let template = Self::template(&state);
+
+        let stop = state.epoch() + duration;
+        let step_s =
+            self.step.abs().to_seconds() * if duration < Duration::ZERO { -1.0 } else { 1.0 };
+
+        let mut epoch = state.epoch();
+        let mut y = self.to_mean(&elements.to_vector(), &template, almanac.clone())?;
+        let mut ydot = self.rates_at(&y, epoch, &template, almanac.clone())?;
+        let mut nodes = vec![(epoch, y, ydot)];
+
+        while epoch != stop {
+            let remaining_s = (stop - epoch).to_seconds();
+            let (dt_s, next_epoch) = if remaining_s.abs() <= step_s.abs() {
+                (remaining_s, stop)
+            } else {
+                (step_s, epoch + Duration::from_seconds(step_s))
+            };
+
+            let half = epoch + Duration::from_seconds(0.5 * dt_s);
+            let k1 = ydot;
+            let k2 = self.rates_at(&(y + k1 * (0.5 * dt_s)), half, &template, almanac.clone())?;
+            let k3 = self.rates_at(&(y + k2 * (0.5 * dt_s)), half, &template, almanac.clone())?;
+            let k4 = self.rates_at(&(y + k3 * dt_s), next_epoch, &template, almanac.clone())?;
+
+            y += (k1 + 2.0 * k2 + 2.0 * k3 + k4) * (dt_s / 6.0);
+            epoch = next_epoch;
+            ydot = self.rates_at(&y, epoch, &template, almanac.clone())?;
+            nodes.push((epoch, y, ydot));
+        }
+
+        Ok((self.to_spacecraft(&y, epoch, &state, almanac)?, nodes))
+    }
+
+    /// Computes the averaged rates of the provided element vector at the provided epoch.
+    fn rates_at(
+        &self,
+        y: &Vector6<f64>,
+        epoch: Epoch,
+        template: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<Vector6<f64>, PropagationError> {
+        let mut ctx = *template;
+        ctx.orbit.epoch = epoch;
+        self.averaged_rates(&EquinoctialElements::from_vector(y), &ctx, almanac)
+    }
+
+    /// Averages the Gauss variational equations over the mean anomaly.
+    fn averaged_rates(
+        &self,
+        elements: &EquinoctialElements,
+        template: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<Vector6<f64>, PropagationError> {
+        let (node_rates, mu_km3_s2) = self.node_rates(elements, template, almanac)?;
+
+        let mut rates = node_rates.iter().sum::<Vector6<f64>>() / node_rates.len() as f64;
+        rates[5] += (mu_km3_s2 / elements.sma_km.powi(3)).sqrt();
+
+        Ok(rates)
+    }
+
+    /// Returns the rates of the elements due to the perturbations at each quadrature node, uniformly spaced in mean
+    /// longitude from that of the provided elements, and the gravitational parameter of the frame.
+    fn node_rates(
+        &self,
+        elements: &EquinoctialElements,
+        template: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<(Vec<Vector6<f64>>, f64), PropagationError> {
+        let frame = template.orbit.frame;
+        let mu_km3_s2 = frame
+            .mu_km3_s2()
+            .context(AstroPhysicsSnafu)
+            .context(DynamicsAstroSnafu)
+            .context(DynamicsSnafu)?;
+
+        let mut node_rates = Vec::with_capacity(self.quadrature_points);
+        for j in 0..self.quadrature_points {
+            let mut node = *elements;
+            node.lambda_rad += TAU * (j as f64) / (self.quadrature_points as f64);
+            let (r, v) = node.to_cartesian(mu_km3_s2);
+
+            let mut osc = *template;
+            osc.orbit.radius_km = r;
+            osc.orbit.velocity_km_s = v;
+
+            let d_x = self
+                .dynamics
+                .eom(0.0, &osc.to_vector(), &osc, almanac.clone())
+                .context(DynamicsSnafu)?;
+
+            // Remove the two-body acceleration to only keep the perturbations.
+            let accel = Vector3::new(d_x[3], d_x[4], d_x[5]) + mu_km3_s2 / r.norm().powi(3) * r;
+            let accel_norm = accel.norm();
+            if accel_norm < f64::EPSILON {
+                node_rates.push(Vector6::zeros());
+                continue;
+            }
+            let dir = accel / accel_norm;
+
+            let plus = EquinoctialElements::from_cartesian(
+                &r,
+                &(v + VELOCITY_FD_STEP_KM_S * dir),
+                mu_km3_s2,
+            )
+            .to_vector();
+            let minus = EquinoctialElements::from_cartesian(
+                &r,
+                &(v - VELOCITY_FD_STEP_KM_S * dir),
+                mu_km3_s2,
+            )
+            .to_vector();
+
+            let mut delta = plus - minus;
+            // The mean longitude is wrapped by the conversion, so unwrap the difference.
+            delta[5] = (delta[5] + PI).rem_euclid(TAU) - PI;
+
+            node_rates.push(delta * (accel_norm / (2.0 * VELOCITY_FD_STEP_KM_S)));
+        }
+
+        Ok((node_rates, mu_km3_s2))
+    }
+
+    /// Returns the first order short-periodic terms of the provided mean elements, i.e. the osculating minus the mean
+    /// elements.
+    ///
+    /// The rates at the quadrature nodes are expanded in a Fourier series of the mean longitude, whose harmonics are
+    /// integrated analytically, including the short-periodic mean motion due to that of the semi-major axis.
+    fn short_periodics(
+        &self,
+        elements: &EquinoctialElements,
+        template: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<Vector6<f64>, PropagationError> {
+        let (mut node_rates, mu_km3_s2) = self.node_rates(elements, template, almanac)?;
+        let mean_motion = (mu_km3_s2 / elements.sma_km.powi(3)).sqrt();
+        let nodes = node_rates.len();
+        // The highest harmonic resolved by the quadrature, excluding the Nyquist frequency
+        let harmonics = (nodes - 1) / 2;
+        let phase = |j: usize| TAU * (j as f64) / (nodes as f64);
+
+        // Real and imaginary parts of the Fourier coefficients c_k = 1/N Σ f_j exp(-i k θ_j), for k ≥ 1
+        let fourier = |node_rates: &[Vector6<f64>]| -> Vec<(Vector6<f64>, Vector6<f64>)> {
+            (1..=harmonics)
+                .map(|k| {
+                    let (re, im) = node_rates.iter().enumerate().fold(
+                        (Vector6::zeros(), Vector6::zeros()),
+                        |(re, im), (j, rates)| {
+                            let angle = (k as f64) * phase(j);
+                            (re + rates * angle.cos(), im - rates * angle.sin())
+                        },
+                    );
+                    (re / nodes as f64, im / nodes as f64)
+                })
+                .collect()
+        };
+        // Periodic part of the integral of the rates over time at this phase, i.e. Σ c_k exp(i k θ) / (i k n) + c.c.
+        let integrate = |coeffs: &[(Vector6<f64>, Vector6<f64>)], theta: f64| -> Vector6<f64> {
+            coeffs
+                .iter()
+                .enumerate()
+                .fold(Vector6::zeros(), |acc, (i, (re, im))| {
+                    let k = (i + 1) as f64;
+                    acc + (im * (k * theta).cos() + re * (k * theta).sin())
+                        * (2.0 / (k * mean_motion))
+                })
+        };
+
+        // The short-periodic semi-major axis changes the mean motion, and therefore the rate of the mean longitude.
+        let coeffs = fourier(&node_rates);
+        for (j, rates) in node_rates.iter_mut().enumerate() {
+            rates[5] -= 1.5 * mean_motion / elements.sma_km * integrate(&coeffs, phase(j))[0];
+        }
+
+        Ok(integrate(&fourier(&node_rates), 0.0))
+    }
+
+    /// Returns the mean elements of the provided osculating elements, by fixed point iterations on the short-periodic terms.
+    fn to_mean(
+        &self,
+        osculating: &Vector6<f64>,
+        template: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<Vector6<f64>, PropagationError> {
+        let mut mean = *osculating;
+        for _ in 0..MEAN_ELEMENTS_ITERATIONS {
+            mean = osculating
+                - self.short_periodics(
+                    &EquinoctialElements::from_vector(&mean),
+                    template,
+                    almanac.clone(),
+                )?;
+        }
+        Ok(mean)
+    }
+
+    /// Builds the output state from the provided mean elements, adding the short-periodic terms unless the mean
+    /// trajectory is requested.
+    fn to_spacecraft(
+        &self,
+        y: &Vector6<f64>,
+        epoch: Epoch,
+        template: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<Spacecraft, PropagationError> {
+        if self.mean_output {
+            return Self::to_state(y, epoch, template);
+        }
+        let mut ctx = Self::template(template);
+        ctx.orbit.epoch = epoch;
+        let short_periodics =
+            self.short_periodics(&EquinoctialElements::from_vector(y), &ctx, almanac)?;
+        Self::to_state(&(y + short_periodics), epoch, template)
+    }
+
+    fn to_state(
+        y: &Vector6<f64>,
+        epoch: Epoch,
+        template: &Spacecraft,
+    ) -> Result<Spacecraft, PropagationError> {
+        let orbit = EquinoctialElements::from_vector(y)
+            .to_orbit(epoch, template.orbit.frame)
+            .context(AstroPhysicsSnafu)
+            .context(DynamicsAstroSnafu)
+            .context(DynamicsSnafu)?;
+
+        Ok(template.with_orbit(orbit))
+    }
+
+    fn elements_of(state: &Spacecraft) -> Result<EquinoctialElements, PropagationError> {
+        EquinoctialElements::from_orbit(&state.orbit)
+            .context(AstroPhysicsSnafu)
+            .context(DynamicsAstroSnafu)
+            .context(DynamicsSnafu)
+    }
+
+    /// The context of the evaluation of the dynamics, without STM.
+    fn template(state: &Spacecraft) -> Spacecraft {
+        let mut template = *state;
+        template.unset_stm();
+        template
+    }
+
+    fn check_config(&self) -> Result<(), PropagationError> {
+        if self.dynamics.guid_law.is_some() {
+            Err(ConfigError::InvalidConfig {
+                msg: "semi-analytic propagation does not support guidance laws".to_string(),
+            })
+            .context(PropConfigSnafu)
+        } else if self.quadrature_points == 0 {
+            Err(ConfigError::InvalidConfig {
+                msg: "semi-analytic propagation requires at least one quadrature point".to_string(),
+            })
+            .context(PropConfigSnafu)
+        } else if self.step == Duration::ZERO {
+            Err(ConfigError::InvalidConfig {
+                msg: "semi-analytic propagation requires a non-zero step".to_string(),
+            })
+            .context(PropConfigSnafu)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod ut_semi_analytic {
+    use super::*;
+    use crate::cosmic::{Frame, Orbit};
+    use crate::dynamics::{Harmonics, OrbitalDynamics};
+    use crate::fixtures;
+    use crate::io::gravity::HarmonicsMem;
+    use crate::propagators::Propagator;
+    use crate::time::Unit;
+    use crate::GMAT_EARTH_GM;
+    use anise::structure::planetocentric::ellipsoid::Ellipsoid;
+
+    fn earth() -> Frame {
+        let mut frame = fixtures::eme2k();
+        frame.shape = Some(Ellipsoid::from_sphere(6_378.136_3));
+        frame
+    }
+
+    #[test]
+    fn j2_secular_rates() {
+        let epoch = fixtures::epoch();
+        let frame = earth();
+        let orbit =
+            Orbit::try_keplerian(7_000.0, 0.001, 51.6, 30.0, 45.0, 10.0, epoch, frame).unwrap();
+        let sc = Spacecraft::builder().orbit(orbit).build();
+
+        let dynamics = SpacecraftDynamics::new(OrbitalDynamics::new(vec![Harmonics::from_stor(
+            frame,
+            HarmonicsMem::j2_jgm3(),
+        )]));
+        let prop = SemiAnalyticPropagator::new(dynamics, Unit::Hour * 12);
+        let almanac = fixtures::almanac();
+
+        // Analytical nodal regression due to J2.
+        let j2 = 4.841_653_748_864_70e-04 * 5.0_f64.sqrt();
+        let n = (GMAT_EARTH_GM / 7_000.0_f64.powi(3)).sqrt();
+        let semi_latus = 7_000.0 * (1.0 - 0.001_f64.powi(2));
+        let raan_rate =
+            -1.5 * n * j2 * (6_378.136_3 / semi_latus).powi(2) * 51.6_f64.to_radians().cos();
+
+        let rates = prop.mean_rates(&sc, almanac.clone()).unwrap();
+        let elements = EquinoctialElements::from_orbit(&orbit).unwrap();
+        // dΩ/dt from dp/dt and dq/dt
+        let tan_sq = elements.p.powi(2) + elements.q.powi(2);
+        let computed = (elements.q * rates[3] - elements.p * rates[4]) / tan_sq;
+        assert!(
+            ((computed - raan_rate) / raan_rate).abs() < 1e-2,
+            "RAAN rate {computed} != {raan_rate}"
+        );
+        // No secular change in the semi-major axis
+        assert!(rates[0].abs() < 1e-9);
+
+        let (final_sc, traj) = prop
+            .for_duration_with_traj(sc, Unit::Day * 10, Unit::Hour * 1, almanac)
+            .unwrap();
+        assert_eq!(final_sc.epoch(), epoch + Unit::Day * 10);
+        assert_eq!(traj.states.len(), 10 * 24 + 1);
+        let final_elements = EquinoctialElements::from_orbit(&final_sc.orbit).unwrap();
+        let expected_raan = (30.0 + (raan_rate * 10.0 * 86_400.0).to_degrees()).rem_euclid(360.0);
+        assert!((final_elements.raan_deg() - expected_raan).abs() < 0.05);
+    }
+
+    #[test]
+    fn j2_osculating_vs_numerical() {
+        let epoch = fixtures::epoch();
+        let frame = earth();
+        let orbit =
+            Orbit::try_keplerian(7_000.0, 0.001, 51.6, 30.0, 45.0, 10.0, epoch, frame).unwrap();
+        let sc = Spacecraft::builder().orbit(orbit).build();
+
+        let dynamics = SpacecraftDynamics::new(OrbitalDynamics::new(vec![Harmonics::from_stor(
+            frame,
+            HarmonicsMem::j2_jgm3(),
+        )]));
+        let prop = SemiAnalyticPropagator::new(dynamics.clone(), Unit::Hour * 12);
+        let almanac = fixtures::almanac();
+
+        // The mean semi-major axis differs from the osculating one by the J2 short-periodic terms
+        let mean = prop.mean_state(&sc, almanac.clone()).unwrap();
+        let sma_diff_km = mean.orbit.sma_km().unwrap() - orbit.sma_km().unwrap();
+        assert!(sma_diff_km.abs() > 1.0 && sma_diff_km.abs() < 10.0);
+        let osc = prop.osculating_state(&mean, almanac.clone()).unwrap();
+        assert!((osc.orbit.radius_km - sc.orbit.radius_km).norm() < 1e-6);
+        assert!((osc.orbit.velocity_km_s - sc.orbit.velocity_km_s).norm() < 1e-9);
+
+        // Over many revolutions, the error is driven by the second order J2 terms
+        let duration = Unit::Day * 5;
+        let numerical = Propagator::default(dynamics)
+            .with(sc, almanac.clone())
+            .for_duration(duration)
+            .unwrap();
+        let semi = prop.for_duration(sc, duration, almanac.clone()).unwrap();
+        let err_km = (semi.orbit.radius_km - numerical.orbit.radius_km).norm();
+        assert!(err_km < 5.0, "error after {duration}: {err_km} km");
+
+        // Taking the osculating state as mean (i.e. starting from the state whose mean state is the osculating one)
+        // leads to a drift in mean motion of hundreds of kilometers
+        let mean_prop = prop.with_mean_output();
+        let start = mean_prop.osculating_state(&sc, almanac.clone()).unwrap();
+        let as_mean = mean_prop.for_duration(start, duration, almanac).unwrap();
+        let as_mean_err_km = (as_mean.orbit.radius_km - numerical.orbit.radius_km).norm();
+        assert!(as_mean_err_km > 100.0, "{as_mean_err_km} km");
+    }
+}
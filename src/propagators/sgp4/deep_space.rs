@@ -0,0 +1,598 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Deep space (SDP4) lunar-solar and resonance terms, following the `dscom`, `dsinit`, `dspace` and `dpper`
+//! routines of Vallado, Crawford, Hujsak and Kelso, "Revisiting Spacetrack Report #3", AIAA 2006-6753.
+
+use super::MeanElements;
+use std::f64::consts::{PI, TAU};
+
+const ZES: f64 = 0.01675;
+const ZEL: f64 = 0.05490;
+const ZNS: f64 = 1.19459e-5;
+const ZNL: f64 = 1.5835218e-4;
+/// Earth rotation rate, in radians per minute
+const RPTIM: f64 = 4.375_269_088_011_3e-3;
+
+/// Geopotential resonance class of the orbit.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Resonance {
+    None,
+    /// One day period (geosynchronous)
+    Synchronous,
+    /// Half day period with a high eccentricity (Molniya)
+    HalfDay,
+}
+
+/// Initialized deep space coefficients of an element set with a period of at least 225 minutes.
+#[derive(Clone, Debug)]
+pub(super) struct DeepSpace {
+    // Lunar-solar periodic coefficients
+    e3: f64,
+    ee2: f64,
+    se2: f64,
+    se3: f64,
+    sgh2: f64,
+    sgh3: f64,
+    sgh4: f64,
+    sh2: f64,
+    sh3: f64,
+    si2: f64,
+    si3: f64,
+    sl2: f64,
+    sl3: f64,
+    sl4: f64,
+    xgh2: f64,
+    xgh3: f64,
+    xgh4: f64,
+    xh2: f64,
+    xh3: f64,
+    xi2: f64,
+    xi3: f64,
+    xl2: f64,
+    xl3: f64,
+    xl4: f64,
+    zmol: f64,
+    zmos: f64,
+    // Lunar-solar secular rates
+    dedt: f64,
+    didt: f64,
+    dmdt: f64,
+    dnodt: f64,
+    domdt: f64,
+    // Resonance terms
+    resonance: Resonance,
+    d2201: f64,
+    d2211: f64,
+    d3210: f64,
+    d3222: f64,
+    d4410: f64,
+    d4422: f64,
+    d5220: f64,
+    d5232: f64,
+    d5421: f64,
+    d5433: f64,
+    del1: f64,
+    del2: f64,
+    del3: f64,
+    xfact: f64,
+    xlamo: f64,
+    gsto: f64,
+}
+
+/// Near-Earth quantities needed to initialize the deep space terms.
+pub(super) struct DeepSpaceInit {
+    /// Epoch in days since 1949 December 31 00:00 UT
+    pub epoch_days: f64,
+    pub ecco: f64,
+    pub inclo: f64,
+    pub nodeo: f64,
+    pub argpo: f64,
+    pub mo: f64,
+    /// Un-Kozai'd mean motion, in radians per minute
+    pub no: f64,
+    pub mdot: f64,
+    pub nodedot: f64,
+    pub xpidot: f64,
+    pub gsto: f64,
+    pub xke: f64,
+}
+
+impl DeepSpace {
+    /// Computes the lunar-solar coefficients (`dscom`) and the secular and resonance terms (`dsinit`).
+    pub(super) fn new(init: DeepSpaceInit) -> Self {
+        let DeepSpaceInit {
+            epoch_days,
+            ecco: em,
+            inclo,
+            nodeo,
+            argpo,
+            mo,
+            no,
+            mdot,
+            nodedot,
+            xpidot,
+            gsto,
+            xke,
+        } = init;
+
+        const C1SS: f64 = 2.9864797e-6;
+        const C1L: f64 = 4.7968065e-7;
+        const ZSINIS: f64 = 0.39785416;
+        const ZCOSIS: f64 = 0.91744867;
+        const ZCOSGS: f64 = 0.1945905;
+        const ZSINGS: f64 = -0.98088458;
+
+        let nm = no;
+        let (snodm, cnodm) = nodeo.sin_cos();
+        let (sinomm, cosomm) = argpo.sin_cos();
+        let (sinim, cosim) = inclo.sin_cos();
+        let emsq = em * em;
+        let betasq = 1.0 - emsq;
+        let rtemsq = betasq.sqrt();
+
+        // Lunar and solar geometry at epoch
+        let day = epoch_days + 18261.5;
+        let xnodce = (4.5236020 - 9.2422029e-4 * day) % TAU;
+        let (stem, ctem) = xnodce.sin_cos();
+        let zcosil = 0.91375164 - 0.03568096 * ctem;
+        let zsinil = (1.0 - zcosil * zcosil).sqrt();
+        let zsinhl = 0.089683511 * stem / zsinil;
+        let zcoshl = (1.0 - zsinhl * zsinhl).sqrt();
+        let gam = 5.8351514 + 0.0019443680 * day;
+        let zx = 0.39785416 * stem / zsinil;
+        let zy = zcoshl * ctem + 0.91744867 * zsinhl * stem;
+        let zx = gam + zx.atan2(zy) - xnodce;
+        let (zsingl, zcosgl) = zx.sin_cos();
+
+        // First pass is the solar terms, second pass the lunar terms.
+        let mut zcosg = ZCOSGS;
+        let mut zsing = ZSINGS;
+        let mut zcosi = ZCOSIS;
+        let mut zsini = ZSINIS;
+        let mut zcosh = cnodm;
+        let mut zsinh = snodm;
+        let mut cc = C1SS;
+        let xnoi = 1.0 / nm;
+
+        let mut solar = [0.0; 19];
+        let mut lunar = [0.0; 19];
+        for pass in 0..2 {
+            let a1 = zcosg * zcosh + zsing * zcosi * zsinh;
+            let a3 = -zsing * zcosh + zcosg * zcosi * zsinh;
+            let a7 = -zcosg * zsinh + zsing * zcosi * zcosh;
+            let a8 = zsing * zsini;
+            let a9 = zsing * zsinh + zcosg * zcosi * zcosh;
+            let a10 = zcosg * zsini;
+            let a2 = cosim * a7 + sinim * a8;
+            let a4 = cosim * a9 + sinim * a10;
+            let a5 = -sinim * a7 + cosim * a8;
+            let a6 = -sinim * a9 + cosim * a10;
+
+            let x1 = a1 * cosomm + a2 * sinomm;
+            let x2 = a3 * cosomm + a4 * sinomm;
+            let x3 = -a1 * sinomm + a2 * cosomm;
+            let x4 = -a3 * sinomm + a4 * cosomm;
+            let x5 = a5 * sinomm;
+            let x6 = a6 * sinomm;
+            let x7 = a5 * cosomm;
+            let x8 = a6 * cosomm;
+
+            let z31 = 12.0 * x1 * x1 - 3.0 * x3 * x3;
+            let z32 = 24.0 * x1 * x2 - 6.0 * x3 * x4;
+            let z33 = 12.0 * x2 * x2 - 3.0 * x4 * x4;
+            let mut z1 = 3.0 * (a1 * a1 + a2 * a2) + z31 * emsq;
+            let mut z2 = 6.0 * (a1 * a3 + a2 * a4) + z32 * emsq;
+            let mut z3 = 3.0 * (a3 * a3 + a4 * a4) + z33 * emsq;
+            let z11 = -6.0 * a1 * a5 + emsq * (-24.0 * x1 * x7 - 6.0 * x3 * x5);
+            let z12 = -6.0 * (a1 * a6 + a3 * a5)
+                + emsq * (-24.0 * (x2 * x7 + x1 * x8) - 6.0 * (x3 * x6 + x4 * x5));
+            let z13 = -6.0 * a3 * a6 + emsq * (-24.0 * x2 * x8 - 6.0 * x4 * x6);
+            let z21 = 6.0 * a2 * a5 + emsq * (24.0 * x1 * x5 - 6.0 * x3 * x7);
+            let z22 = 6.0 * (a4 * a5 + a2 * a6)
+                + emsq * (24.0 * (x2 * x5 + x1 * x6) - 6.0 * (x4 * x7 + x3 * x8));
+            let z23 = 6.0 * a4 * a6 + emsq * (24.0 * x2 * x6 - 6.0 * x4 * x8);
+            z1 = z1 + z1 + betasq * z31;
+            z2 = z2 + z2 + betasq * z32;
+            z3 = z3 + z3 + betasq * z33;
+
+            let s3 = cc * xnoi;
+            let s2 = -0.5 * s3 / rtemsq;
+            let s4 = s3 * rtemsq;
+            let s1 = -15.0 * em * s4;
+            let s5 = x1 * x3 + x2 * x4;
+            let s6 = x2 * x3 + x1 * x4;
+            let s7 = x2 * x4 - x1 * x3;
+
+            let terms = [
+                s1, s2, s3, s4, s5, s6, s7, z1, z2, z3, z11, z12, z13, z21, z22, z23, z31, z32, z33,
+            ];
+            if pass == 0 {
+                solar = terms;
+                zcosg = zcosgl;
+                zsing = zsingl;
+                zcosi = zcosil;
+                zsini = zsinil;
+                zcosh = zcoshl * cnodm + zsinhl * snodm;
+                zsinh = snodm * zcoshl - cnodm * zsinhl;
+                cc = C1L;
+            } else {
+                lunar = terms;
+            }
+        }
+
+        let [ss1, ss2, ss3, ss4, ss5, ss6, ss7, sz1, sz2, sz3, sz11, sz12, sz13, sz21, sz22, sz23, sz31, sz32, sz33] =
+            solar;
+        let [s1, s2, s3, s4, s5, s6, s7, z1, z2, z3, z11, z12, z13, z21, z22, z23, z31, z32, z33] =
+            lunar;
+
+        let zmol = (4.7199672 + 0.22997150 * day - gam).rem_euclid(TAU);
+        let zmos = (6.2565837 + 0.017201977 * day).rem_euclid(TAU);
+
+        // `dsinit`: secular lunar-solar rates
+        let ses = ss1 * ZNS * ss5;
+        let sis = ss2 * ZNS * (sz11 + sz13);
+        let sls = -ZNS * ss3 * (sz1 + sz3 - 14.0 - 6.0 * emsq);
+        let sghs = ss4 * ZNS * (sz31 + sz33 - 6.0);
+        let near_equatorial = !(5.2359877e-2..=PI - 5.2359877e-2).contains(&inclo);
+        let mut shs = if near_equatorial {
+            0.0
+        } else {
+            -ZNS * ss2 * (sz21 + sz23)
+        };
+        if sinim != 0.0 {
+            shs /= sinim;
+        }
+        let sgs = sghs - cosim * shs;
+
+        let dedt = ses + s1 * ZNL * s5;
+        let didt = sis + s2 * ZNL * (z11 + z13);
+        let dmdt = sls - ZNL * s3 * (z1 + z3 - 14.0 - 6.0 * emsq);
+        let sghl = s4 * ZNL * (z31 + z33 - 6.0);
+        let shll = if near_equatorial {
+            0.0
+        } else {
+            -ZNL * s2 * (z21 + z23)
+        };
+        let mut domdt = sgs + sghl;
+        let mut dnodt = shs;
+        if sinim != 0.0 {
+            domdt -= cosim / sinim * shll;
+            dnodt += shll / sinim;
+        }
+
+        let mut me = Self {
+            e3: 2.0 * s1 * s7,
+            ee2: 2.0 * s1 * s6,
+            se2: 2.0 * ss1 * ss6,
+            se3: 2.0 * ss1 * ss7,
+            sgh2: 2.0 * ss4 * sz32,
+            sgh3: 2.0 * ss4 * (sz33 - sz31),
+            sgh4: -18.0 * ss4 * ZES,
+            sh2: -2.0 * ss2 * sz22,
+            sh3: -2.0 * ss2 * (sz23 - sz21),
+            si2: 2.0 * ss2 * sz12,
+            si3: 2.0 * ss2 * (sz13 - sz11),
+            sl2: -2.0 * ss3 * sz2,
+            sl3: -2.0 * ss3 * (sz3 - sz1),
+            sl4: -2.0 * ss3 * (-21.0 - 9.0 * emsq) * ZES,
+            xgh2: 2.0 * s4 * z32,
+            xgh3: 2.0 * s4 * (z33 - z31),
+            xgh4: -18.0 * s4 * ZEL,
+            xh2: -2.0 * s2 * z22,
+            xh3: -2.0 * s2 * (z23 - z21),
+            xi2: 2.0 * s2 * z12,
+            xi3: 2.0 * s2 * (z13 - z11),
+            xl2: -2.0 * s3 * z2,
+            xl3: -2.0 * s3 * (z3 - z1),
+            xl4: -2.0 * s3 * (-21.0 - 9.0 * emsq) * ZEL,
+            zmol,
+            zmos,
+            dedt,
+            didt,
+            dmdt,
+            dnodt,
+            domdt,
+            resonance: Resonance::None,
+            d2201: 0.0,
+            d2211: 0.0,
+            d3210: 0.0,
+            d3222: 0.0,
+            d4410: 0.0,
+            d4422: 0.0,
+            d5220: 0.0,
+            d5232: 0.0,
+            d5421: 0.0,
+            d5433: 0.0,
+            del1: 0.0,
+            del2: 0.0,
+            del3: 0.0,
+            xfact: 0.0,
+            xlamo: 0.0,
+            gsto,
+        };
+
+        if nm > 0.0034906585 && nm < 0.0052359877 {
+            me.resonance = Resonance::Synchronous;
+        }
+        if (8.26e-3..=9.24e-3).contains(&nm) && em >= 0.5 {
+            me.resonance = Resonance::HalfDay;
+        }
+
+        let theta = gsto % TAU;
+        let aonv = (nm / xke).powf(2.0 / 3.0);
+        match me.resonance {
+            Resonance::None => {}
+            Resonance::HalfDay => {
+                const ROOT22: f64 = 1.7891679e-6;
+                const ROOT44: f64 = 7.3636953e-9;
+                const ROOT54: f64 = 2.1765803e-9;
+                const ROOT32: f64 = 3.7393792e-7;
+                const ROOT52: f64 = 1.1428639e-7;
+
+                let cosisq = cosim * cosim;
+                let eoc = em * emsq;
+                let g201 = -0.306 - (em - 0.64) * 0.440;
+                let (g211, g310, g322, g410, g422, g520);
+                if em <= 0.65 {
+                    g211 = 3.616 - 13.2470 * em + 16.2900 * emsq;
+                    g310 = -19.302 + 117.3900 * em - 228.4190 * emsq + 156.5910 * eoc;
+                    g322 = -18.9068 + 109.7927 * em - 214.6334 * emsq + 146.5816 * eoc;
+                    g410 = -41.122 + 242.6940 * em - 471.0940 * emsq + 313.9530 * eoc;
+                    g422 = -146.407 + 841.8800 * em - 1629.014 * emsq + 1083.4350 * eoc;
+                    g520 = -532.114 + 3017.977 * em - 5740.032 * emsq + 3708.2760 * eoc;
+                } else {
+                    g211 = -72.099 + 331.819 * em - 508.738 * emsq + 266.724 * eoc;
+                    g310 = -346.844 + 1582.851 * em - 2415.925 * emsq + 1246.113 * eoc;
+                    g322 = -342.585 + 1554.908 * em - 2366.899 * emsq + 1215.972 * eoc;
+                    g410 = -1052.797 + 4758.686 * em - 7193.992 * emsq + 3651.957 * eoc;
+                    g422 = -3581.690 + 16178.110 * em - 24462.770 * emsq + 12422.520 * eoc;
+                    g520 = if em > 0.715 {
+                        -5149.66 + 29936.92 * em - 54087.36 * emsq + 31324.56 * eoc
+                    } else {
+                        1464.74 - 4664.75 * em + 3763.64 * emsq
+                    };
+                }
+                let (g533, g521, g532);
+                if em < 0.7 {
+                    g533 = -919.22770 + 4988.6100 * em - 9064.7700 * emsq + 5542.21 * eoc;
+                    g521 = -822.71072 + 4568.6173 * em - 8491.4146 * emsq + 5337.524 * eoc;
+                    g532 = -853.66600 + 4690.2500 * em - 8624.7700 * emsq + 5341.4 * eoc;
+                } else {
+                    g533 = -37995.780 + 161616.52 * em - 229838.20 * emsq + 109377.94 * eoc;
+                    g521 = -51752.104 + 218913.95 * em - 309468.16 * emsq + 146349.42 * eoc;
+                    g532 = -40023.880 + 170470.89 * em - 242699.48 * emsq + 115605.82 * eoc;
+                }
+
+                let sini2 = sinim * sinim;
+                let f220 = 0.75 * (1.0 + 2.0 * cosim + cosisq);
+                let f221 = 1.5 * sini2;
+                let f321 = 1.875 * sinim * (1.0 - 2.0 * cosim - 3.0 * cosisq);
+                let f322 = -1.875 * sinim * (1.0 + 2.0 * cosim - 3.0 * cosisq);
+                let f441 = 35.0 * sini2 * f220;
+                let f442 = 39.3750 * sini2 * sini2;
+                let f522 = 9.84375
+                    * sinim
+                    * (sini2 * (1.0 - 2.0 * cosim - 5.0 * cosisq)
+                        + 0.33333333 * (-2.0 + 4.0 * cosim + 6.0 * cosisq));
+                let f523 = sinim
+                    * (4.92187512 * sini2 * (-2.0 - 4.0 * cosim + 10.0 * cosisq)
+                        + 6.56250012 * (1.0 + 2.0 * cosim - 3.0 * cosisq));
+                let f542 = 29.53125
+                    * sinim
+                    * (2.0 - 8.0 * cosim + cosisq * (-12.0 + 8.0 * cosim + 10.0 * cosisq));
+                let f543 = 29.53125
+                    * sinim
+                    * (-2.0 - 8.0 * cosim + cosisq * (12.0 + 8.0 * cosim - 10.0 * cosisq));
+
+                let xno2 = nm * nm;
+                let ainv2 = aonv * aonv;
+                let mut temp1 = 3.0 * xno2 * ainv2;
+                let mut temp = temp1 * ROOT22;
+                me.d2201 = temp * f220 * g201;
+                me.d2211 = temp * f221 * g211;
+                temp1 *= aonv;
+                temp = temp1 * ROOT32;
+                me.d3210 = temp * f321 * g310;
+                me.d3222 = temp * f322 * g322;
+                temp1 *= aonv;
+                temp = 2.0 * temp1 * ROOT44;
+                me.d4410 = temp * f441 * g410;
+                me.d4422 = temp * f442 * g422;
+                temp1 *= aonv;
+                temp = temp1 * ROOT52;
+                me.d5220 = temp * f522 * g520;
+                me.d5232 = temp * f523 * g532;
+                temp = 2.0 * temp1 * ROOT54;
+                me.d5421 = temp * f542 * g521;
+                me.d5433 = temp * f543 * g533;
+                me.xlamo = (mo + nodeo + nodeo - theta - theta) % TAU;
+                me.xfact = mdot + dmdt + 2.0 * (nodedot + dnodt - RPTIM) - no;
+            }
+            Resonance::Synchronous => {
+                const Q22: f64 = 1.7891679e-6;
+                const Q31: f64 = 2.1460748e-6;
+                const Q33: f64 = 2.2123015e-7;
+
+                let g200 = 1.0 + emsq * (-2.5 + 0.8125 * emsq);
+                let g310 = 1.0 + 2.0 * emsq;
+                let g300 = 1.0 + emsq * (-6.0 + 6.60937 * emsq);
+                let f220 = 0.75 * (1.0 + cosim) * (1.0 + cosim);
+                let f311 = 0.9375 * sinim * sinim * (1.0 + 3.0 * cosim) - 0.75 * (1.0 + cosim);
+                let f330 = 1.875 * (1.0 + cosim).powi(3);
+                let del1 = 3.0 * nm * nm * aonv * aonv;
+                me.del2 = 2.0 * del1 * f220 * g200 * Q22;
+                me.del3 = 3.0 * del1 * f330 * g300 * Q33 * aonv;
+                me.del1 = del1 * f311 * g310 * Q31 * aonv;
+                me.xlamo = (mo + nodeo + argpo - theta) % TAU;
+                me.xfact = mdot + xpidot - RPTIM + dmdt + domdt + dnodt - no;
+            }
+        }
+
+        me
+    }
+
+    /// Applies the lunar-solar secular rates and integrates the resonance terms (`dspace`), `t` minutes after epoch.
+    ///
+    /// On entry, `el` holds the near-Earth secular elements; on return, it holds the deep space secular elements.
+    pub(super) fn secular(&self, t: f64, no: f64, argpo: f64, argpdot: f64, el: &mut MeanElements) {
+        const FASX2: f64 = 0.13130908;
+        const FASX4: f64 = 2.8843198;
+        const FASX6: f64 = 0.37448087;
+        const G22: f64 = 5.7686396;
+        const G32: f64 = 0.95240898;
+        const G44: f64 = 1.8014998;
+        const G52: f64 = 1.0508330;
+        const G54: f64 = 4.4108898;
+        const STEPP: f64 = 720.0;
+        const STEP2: f64 = 259_200.0;
+
+        let theta = (self.gsto + t * RPTIM) % TAU;
+        el.ecc += self.dedt * t;
+        el.inc += self.didt * t;
+        el.aop += self.domdt * t;
+        el.raan += self.dnodt * t;
+        el.ma += self.dmdt * t;
+
+        if self.resonance == Resonance::None {
+            return;
+        }
+
+        // Numerically integrate the resonance from epoch with fixed twelve hour steps.
+        let delt = if t > 0.0 { STEPP } else { -STEPP };
+        let mut atime = 0.0;
+        let mut xni = no;
+        let mut xli = self.xlamo;
+        let (xndt, xldot, xnddt, ft) = loop {
+            let (xndt, xnddt);
+            let xldot = xni + self.xfact;
+            if self.resonance == Resonance::Synchronous {
+                xndt = self.del1 * (xli - FASX2).sin()
+                    + self.del2 * (2.0 * (xli - FASX4)).sin()
+                    + self.del3 * (3.0 * (xli - FASX6)).sin();
+                xnddt = (self.del1 * (xli - FASX2).cos()
+                    + 2.0 * self.del2 * (2.0 * (xli - FASX4)).cos()
+                    + 3.0 * self.del3 * (3.0 * (xli - FASX6)).cos())
+                    * xldot;
+            } else {
+                let xomi = argpo + argpdot * atime;
+                let x2omi = xomi + xomi;
+                let x2li = xli + xli;
+                xndt = self.d2201 * (x2omi + xli - G22).sin()
+                    + self.d2211 * (xli - G22).sin()
+                    + self.d3210 * (xomi + xli - G32).sin()
+                    + self.d3222 * (-xomi + xli - G32).sin()
+                    + self.d4410 * (x2omi + x2li - G44).sin()
+                    + self.d4422 * (x2li - G44).sin()
+                    + self.d5220 * (xomi + xli - G52).sin()
+                    + self.d5232 * (-xomi + xli - G52).sin()
+                    + self.d5421 * (xomi + x2li - G54).sin()
+                    + self.d5433 * (-xomi + x2li - G54).sin();
+                xnddt = (self.d2201 * (x2omi + xli - G22).cos()
+                    + self.d2211 * (xli - G22).cos()
+                    + self.d3210 * (xomi + xli - G32).cos()
+                    + self.d3222 * (-xomi + xli - G32).cos()
+                    + self.d5220 * (xomi + xli - G52).cos()
+                    + self.d5232 * (-xomi + xli - G52).cos()
+                    + 2.0
+                        * (self.d4410 * (x2omi + x2li - G44).cos()
+                            + self.d4422 * (x2li - G44).cos()
+                            + self.d5421 * (xomi + x2li - G54).cos()
+                            + self.d5433 * (-xomi + x2li - G54).cos()))
+                    * xldot;
+            }
+
+            if (t - atime).abs() < STEPP {
+                break (xndt, xldot, xnddt, t - atime);
+            }
+            xli += xldot * delt + xndt * STEP2;
+            xni += xndt * delt + xnddt * STEP2;
+            atime += delt;
+        };
+
+        el.n = xni + xndt * ft + xnddt * ft * ft * 0.5;
+        let xl = xli + xldot * ft + xndt * ft * ft * 0.5;
+        el.ma = match self.resonance {
+            Resonance::Synchronous => xl - el.raan - el.aop + theta,
+            _ => xl - 2.0 * el.raan + 2.0 * theta,
+        };
+    }
+
+    /// Adds the lunar-solar periodic terms (`dpper`) to the provided elements, `t` minutes after epoch.
+    pub(super) fn periodic(&self, t: f64, el: &mut MeanElements) {
+        // Solar terms
+        let zm = self.zmos + ZNS * t;
+        let zf = zm + 2.0 * ZES * zm.sin();
+        let (sinzf, coszf) = zf.sin_cos();
+        let f2 = 0.5 * sinzf * sinzf - 0.25;
+        let f3 = -0.5 * sinzf * coszf;
+        let ses = self.se2 * f2 + self.se3 * f3;
+        let sis = self.si2 * f2 + self.si3 * f3;
+        let sls = self.sl2 * f2 + self.sl3 * f3 + self.sl4 * sinzf;
+        let sghs = self.sgh2 * f2 + self.sgh3 * f3 + self.sgh4 * sinzf;
+        let shs = self.sh2 * f2 + self.sh3 * f3;
+
+        // Lunar terms
+        let zm = self.zmol + ZNL * t;
+        let zf = zm + 2.0 * ZEL * zm.sin();
+        let (sinzf, coszf) = zf.sin_cos();
+        let f2 = 0.5 * sinzf * sinzf - 0.25;
+        let f3 = -0.5 * sinzf * coszf;
+        let sel = self.ee2 * f2 + self.e3 * f3;
+        let sil = self.xi2 * f2 + self.xi3 * f3;
+        let sll = self.xl2 * f2 + self.xl3 * f3 + self.xl4 * sinzf;
+        let sghl = self.xgh2 * f2 + self.xgh3 * f3 + self.xgh4 * sinzf;
+        let shll = self.xh2 * f2 + self.xh3 * f3;
+
+        let pe = ses + sel;
+        let pinc = sis + sil;
+        let pl = sls + sll;
+        let mut pgh = sghs + sghl;
+        let mut ph = shs + shll;
+
+        el.inc += pinc;
+        el.ecc += pe;
+        let (sinip, cosip) = el.inc.sin_cos();
+
+        if el.inc >= 0.2 {
+            ph /= sinip;
+            pgh -= cosip * ph;
+            el.aop += pgh;
+            el.raan += ph;
+            el.ma += pl;
+        } else {
+            // Lyddane modification for low inclinations
+            let (sinop, cosop) = el.raan.sin_cos();
+            let alfdp = sinip * sinop + ph * cosop + pinc * cosip * sinop;
+            let betdp = sinip * cosop - ph * sinop + pinc * cosip * cosop;
+            el.raan %= TAU;
+            let xls = el.ma + el.aop + cosip * el.raan + pl + pgh - pinc * el.raan * sinip;
+            let xnoh = el.raan;
+            el.raan = alfdp.atan2(betdp);
+            if (xnoh - el.raan).abs() > PI {
+                if el.raan < xnoh {
+                    el.raan += TAU;
+                } else {
+                    el.raan -= TAU;
+                }
+            }
+            el.ma += pl;
+            el.aop = xls - el.ma - cosip * el.raan;
+        }
+    }
+}
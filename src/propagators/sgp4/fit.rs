@@ -0,0 +1,380 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{teme_to_j2000_dcm, Sgp4, Sgp4Error, Tle, TleFitSnafu, MU_KM3_S2};
+use crate::cosmic::{Orbit, Spacecraft};
+use crate::linalg::{DMatrix, DVector, Vector3};
+use crate::md::trajectory::Traj;
+use crate::time::{Duration, Epoch, Unit};
+use crate::State;
+use anise::constants::frames::EARTH_J2000;
+use snafu::ensure;
+use std::f64::consts::TAU;
+use std::fmt;
+use typed_builder::TypedBuilder;
+
+/// Number of fitted parameters when B* is estimated.
+const NUM_PARAMS: usize = 7;
+
+/// Configuration of the least-squares fit of a TLE to a trajectory, cf. [Tle::fit].
+#[derive(Clone, Debug, TypedBuilder)]
+#[builder(doc)]
+pub struct TleFitConfig {
+    /// Start of the fit span, defaults to the start of the trajectory
+    #[builder(default, setter(strip_option))]
+    pub start: Option<Epoch>,
+    /// End of the fit span, defaults to the end of the trajectory
+    #[builder(default, setter(strip_option))]
+    pub end: Option<Epoch>,
+    /// Epoch of the fitted element set, defaults to the start of the fit span
+    #[builder(default, setter(strip_option))]
+    pub epoch: Option<Epoch>,
+    /// Sampling step of the trajectory in the fit span
+    #[builder(default_code = "10.0 * Unit::Minute")]
+    pub step: Duration,
+    /// Whether to estimate B*, which is otherwise fixed to its initial value
+    #[builder(default = false)]
+    pub fit_bstar: bool,
+    /// Initial (or fixed) value of B*, in inverse Earth radii
+    #[builder(default = 0.0)]
+    pub bstar: f64,
+    #[builder(default = 25)]
+    pub max_iterations: usize,
+    /// The fit has converged when the relative change of the RMS of the position residuals is below this tolerance
+    #[builder(default = 1e-6)]
+    pub tolerance: f64,
+    /// Catalog number of the fitted element set
+    #[builder(default = 99_999)]
+    pub catalog_number: u32,
+    /// International designator of the fitted element set
+    #[builder(default)]
+    pub intl_designator: String,
+}
+
+/// Result of a least-squares fit of a TLE to a trajectory.
+#[derive(Clone, Debug)]
+pub struct TleFit {
+    /// Fitted element set
+    pub tle: Tle,
+    /// Root mean square of the position residuals, in km
+    pub rms_km: f64,
+    /// Maximum position residual, in km
+    pub max_residual_km: f64,
+    /// Number of sampled states of the trajectory
+    pub num_samples: usize,
+    /// Number of iterations of the differential correction
+    pub iterations: usize,
+    /// Whether the RMS converged within the tolerance before the maximum number of iterations
+    pub converged: bool,
+}
+
+impl fmt::Display for TleFit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} samples fitted in {} iterations ({}converged): RMS of {:.3} m, max residual of {:.3} m",
+            self.num_samples,
+            self.iterations,
+            if self.converged { "" } else { "not " },
+            self.rms_km * 1e3,
+            self.max_residual_km * 1e3
+        )
+    }
+}
+
+impl Tle {
+    /// Fits the mean elements of a TLE to the positions of the trajectory with a Gauss-Newton differential correction.
+    ///
+    /// The trajectory must be in an Earth centered J2000 frame. Its states are sampled every `step` of the fit span and
+    /// rotated into TEME, then the element set is initialized from the osculating elements at its epoch. The elements
+    /// are corrected in their equinoctial form, so near circular and near equatorial orbits are supported, but
+    /// retrograde equatorial orbits are not.
+    pub fn fit(traj: &Traj<Spacecraft>, cfg: &TleFitConfig) -> Result<TleFit, Sgp4Error> {
+        ensure!(
+            !traj.states.is_empty(),
+            TleFitSnafu {
+                msg: "trajectory is empty"
+            }
+        );
+        let frame = traj.first().orbit.frame;
+        ensure!(
+            frame.ephem_origin_match(EARTH_J2000) && frame.orient_origin_match(EARTH_J2000),
+            TleFitSnafu {
+                msg: format!("trajectory must be in an Earth J2000 frame, not {frame}")
+            }
+        );
+        ensure!(
+            cfg.step > Duration::ZERO,
+            TleFitSnafu {
+                msg: format!(
+                    "sampling step must be strictly positive but got {}",
+                    cfg.step
+                )
+            }
+        );
+
+        let start = cfg.start.unwrap_or(traj.first().epoch());
+        let end = cfg.end.unwrap_or(traj.last().epoch());
+        let epoch = cfg.epoch.unwrap_or(start);
+
+        // Sample the trajectory in TEME
+        let mut samples = Vec::new();
+        for state in traj.every_between(cfg.step, start, end) {
+            let dcm = teme_to_j2000_dcm(state.epoch()).transpose();
+            samples.push((
+                (state.epoch() - epoch).to_unit(Unit::Minute),
+                dcm * state.orbit.radius_km,
+            ));
+        }
+        let num_params = if cfg.fit_bstar {
+            NUM_PARAMS
+        } else {
+            NUM_PARAMS - 1
+        };
+        ensure!(
+            3 * samples.len() > num_params,
+            TleFitSnafu {
+                msg: format!("only {} samples in the fit span", samples.len())
+            }
+        );
+
+        // Initial guess from the osculating elements at the epoch
+        let state = traj.at(epoch).map_err(|e| Sgp4Error::TleFit {
+            msg: format!("cannot initialize the fit at {epoch}: {e}"),
+        })?;
+        let dcm = teme_to_j2000_dcm(epoch).transpose();
+        let r = dcm * state.orbit.radius_km;
+        let v = dcm * state.orbit.velocity_km_s;
+        let osc = Orbit::new(
+            r.x,
+            r.y,
+            r.z,
+            v.x,
+            v.y,
+            v.z,
+            epoch,
+            EARTH_J2000.with_mu_km3_s2(MU_KM3_S2),
+        );
+        let osc_err = |e| Sgp4Error::TleFit {
+            msg: format!("cannot compute the osculating elements: {e}"),
+        };
+
+        let mut tle = Tle {
+            name: traj.name.clone(),
+            catalog_number: cfg.catalog_number,
+            classification: 'U',
+            intl_designator: cfg.intl_designator.clone(),
+            epoch,
+            mean_motion_dot: 0.0,
+            mean_motion_ddot: 0.0,
+            bstar: cfg.bstar,
+            element_set_number: 0,
+            inclination_deg: osc.inc_deg().map_err(osc_err)?,
+            raan_deg: osc.raan_deg().map_err(osc_err)?,
+            ecc: osc.ecc().map_err(osc_err)?,
+            aop_deg: osc.aop_deg().map_err(osc_err)?,
+            mean_anomaly_deg: osc.ma_deg().map_err(osc_err)?,
+            mean_motion_rev_day: 0.0,
+            rev_number: 0,
+        };
+        tle.mean_motion_rev_day =
+            (MU_KM3_S2 / osc.sma_km().map_err(osc_err)?.powi(3)).sqrt() * 86_400.0 / TAU;
+
+        let mut params = to_equinoctial(&tle);
+        let mut residuals = position_residuals(&from_equinoctial(&tle, &params)?, &samples)?;
+        let mut rms = rms_km(&residuals);
+
+        // Finite difference steps of the equinoctial elements
+        let steps = [params[0] * 1e-7, 1e-7, 1e-7, 1e-7, 1e-7, 1e-7, 1e-7];
+
+        let mut iterations = 0;
+        let mut converged = false;
+        while iterations < cfg.max_iterations {
+            iterations += 1;
+
+            let mut jac = DMatrix::zeros(residuals.len(), num_params);
+            for j in 0..num_params {
+                let mut plus = params;
+                plus[j] += steps[j];
+                let mut minus = params;
+                minus[j] -= steps[j];
+                let partial = (position_residuals(&from_equinoctial(&tle, &plus)?, &samples)?
+                    - position_residuals(&from_equinoctial(&tle, &minus)?, &samples)?)
+                    / (2.0 * steps[j]);
+                jac.set_column(j, &partial);
+            }
+
+            // The residuals are the observed minus the computed positions, so the correction solves J dx = -r.
+            let correction = -jac
+                .svd(true, true)
+                .solve(&residuals, 1e-12)
+                .map_err(|msg| Sgp4Error::TleFit {
+                    msg: msg.to_string(),
+                })?;
+
+            // Halve the correction until it reduces the residuals
+            let mut scale = 1.0;
+            let (next_params, next_residuals) = loop {
+                let mut candidate = params;
+                for j in 0..num_params {
+                    candidate[j] += scale * correction[j];
+                }
+                let candidate_residuals = from_equinoctial(&tle, &candidate)
+                    .and_then(|candidate_tle| position_residuals(&candidate_tle, &samples));
+                match candidate_residuals {
+                    Ok(res) if rms_km(&res) <= rms => break (candidate, res),
+                    _ if scale > 1e-3 => scale *= 0.5,
+                    _ => break (params, residuals.clone()),
+                }
+            };
+
+            let next_rms = rms_km(&next_residuals);
+            let change = (rms - next_rms) / rms.max(f64::EPSILON);
+            params = next_params;
+            residuals = next_residuals;
+            rms = next_rms;
+            debug!("TLE fit iteration #{iterations}: RMS = {:.6} m", rms * 1e3);
+
+            if change < cfg.tolerance {
+                converged = true;
+                break;
+            }
+        }
+
+        let tle = from_equinoctial(&tle, &params)?;
+        let max_residual_km = residuals
+            .as_slice()
+            .chunks(3)
+            .map(|res| Vector3::from_column_slice(res).norm())
+            .fold(0.0, f64::max);
+
+        Ok(TleFit {
+            tle,
+            rms_km: rms,
+            max_residual_km,
+            num_samples: samples.len(),
+            iterations,
+            converged,
+        })
+    }
+}
+
+/// Returns the mean motion (rad/min), the equinoctial elements (af, ag, chi, psi, mean longitude) and B* of the element set.
+fn to_equinoctial(tle: &Tle) -> [f64; NUM_PARAMS] {
+    let raan = tle.raan_deg.to_radians();
+    let lon_peri = raan + tle.aop_deg.to_radians();
+    let tan_half_inc = (0.5 * tle.inclination_deg.to_radians()).tan();
+    [
+        tle.mean_motion_rad_min(),
+        tle.ecc * lon_peri.cos(),
+        tle.ecc * lon_peri.sin(),
+        tan_half_inc * raan.sin(),
+        tan_half_inc * raan.cos(),
+        lon_peri + tle.mean_anomaly_deg.to_radians(),
+        tle.bstar,
+    ]
+}
+
+/// Returns a copy of the template element set with the provided equinoctial elements, cf. [to_equinoctial].
+fn from_equinoctial(template: &Tle, params: &[f64; NUM_PARAMS]) -> Result<Tle, Sgp4Error> {
+    let [n, af, ag, chi, psi, mean_lon, bstar] = *params;
+    let ecc = af.hypot(ag);
+    ensure!(
+        n > 0.0 && ecc < 1.0,
+        TleFitSnafu {
+            msg: format!("diverged to n = {n} rad/min and ecc = {ecc}")
+        }
+    );
+    let raan = chi.atan2(psi);
+    let lon_peri = ag.atan2(af);
+    let mut tle = template.clone();
+    tle.mean_motion_rev_day = n * 1440.0 / TAU;
+    tle.ecc = ecc;
+    tle.inclination_deg = (2.0 * chi.hypot(psi).atan()).to_degrees();
+    tle.raan_deg = raan.to_degrees().rem_euclid(360.0);
+    tle.aop_deg = (lon_peri - raan).to_degrees().rem_euclid(360.0);
+    tle.mean_anomaly_deg = (mean_lon - lon_peri).to_degrees().rem_euclid(360.0);
+    tle.bstar = bstar;
+    Ok(tle)
+}
+
+/// Returns the observed minus computed TEME positions, stacked, in km.
+fn position_residuals(
+    tle: &Tle,
+    samples: &[(f64, Vector3<f64>)],
+) -> Result<DVector<f64>, Sgp4Error> {
+    let sgp4 = Sgp4::new(tle)?;
+    let mut residuals = DVector::zeros(3 * samples.len());
+    for (i, (minutes, r_obs)) in samples.iter().enumerate() {
+        let (r_teme, _) = sgp4.propagate_teme(*minutes)?;
+        residuals
+            .fixed_rows_mut::<3>(3 * i)
+            .copy_from(&(r_obs - r_teme));
+    }
+    Ok(residuals)
+}
+
+fn rms_km(residuals: &DVector<f64>) -> f64 {
+    (residuals.norm_squared() / (residuals.len() / 3) as f64).sqrt()
+}
+
+#[cfg(test)]
+mod ut_tle_fit {
+    use super::*;
+
+    #[test]
+    fn refit_sgp4_trajectory() {
+        let tle = Tle::from_lines(
+            "1 25544U 98067A   08264.51782528 -.00002182  00000-0 -11606-4 0  2927",
+            "2 25544  51.6416 247.4627 0006703 130.5360 325.0288 15.72125391563537",
+        )
+        .unwrap();
+        let truth = Sgp4::new(&tle).unwrap();
+        let frame = EARTH_J2000.with_mu_km3_s2(MU_KM3_S2);
+        let traj = truth
+            .to_traj(
+                tle.epoch,
+                tle.epoch + Unit::Day * 1,
+                Unit::Minute * 5,
+                frame,
+            )
+            .unwrap();
+
+        let cfg = TleFitConfig::builder()
+            .bstar(tle.bstar)
+            .catalog_number(25544)
+            .build();
+        let fit = Tle::fit(&traj, &cfg).unwrap();
+        println!("{fit}\n{}", fit.tle);
+
+        assert!(fit.converged);
+        assert_eq!(fit.num_samples, 145);
+        // The trajectory was generated by SGP4, so the original element set is recovered.
+        assert!(fit.rms_km < 1e-6, "{fit}");
+        assert!((fit.tle.mean_motion_rev_day - tle.mean_motion_rev_day).abs() < 1e-6);
+        assert!((fit.tle.ecc - tle.ecc).abs() < 1e-6);
+        assert!((fit.tle.inclination_deg - tle.inclination_deg).abs() < 1e-4);
+        assert!((fit.tle.raan_deg - tle.raan_deg).abs() < 1e-4);
+
+        // The published lines can be parsed back.
+        let (line1, line2) = fit.tle.to_lines();
+        let published = Tle::from_lines(&line1, &line2).unwrap();
+        assert_eq!(published.catalog_number, 25544);
+        assert!((published.mean_motion_rev_day - fit.tle.mean_motion_rev_day).abs() < 1e-8);
+    }
+}
@@ -0,0 +1,682 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::cosmic::{Frame, Orbit, Spacecraft};
+use crate::linalg::Vector3;
+use crate::md::trajectory::Traj;
+use crate::time::{Duration, Epoch, Unit};
+use anise::constants::frames::EARTH_J2000;
+use snafu::prelude::*;
+use std::f64::consts::{PI, TAU};
+
+mod deep_space;
+/// Two-line element set parsing.
+mod tle;
+pub use tle::*;
+/// Conversion from the TEME frame of SGP4 to EME2000.
+mod teme;
+pub use teme::*;
+/// Least-squares fit of a TLE to a trajectory.
+mod fit;
+pub use fit::*;
+
+use deep_space::{DeepSpace, DeepSpaceInit};
+
+/// WGS-72 gravitational parameter, in km^3/s^2, which all published element sets assume.
+pub(crate) const MU_KM3_S2: f64 = 398_600.8;
+/// WGS-72 equatorial radius, in km
+const RADIUS_KM: f64 = 6378.135;
+const J2: f64 = 0.001_082_616;
+const J3: f64 = -0.000_002_538_81;
+const J4: f64 = -0.000_001_655_97;
+
+#[derive(Debug, PartialEq, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum Sgp4Error {
+    #[snafu(display("TLE line {line} is invalid: {msg}"))]
+    TleFormat { line: u8, msg: String },
+    #[snafu(display("TLE line {line} checksum is {found} but expected {expected}"))]
+    TleChecksum { line: u8, expected: u32, found: u32 },
+    #[snafu(display("SGP4 failed {minutes} min after epoch: {msg}"))]
+    Sgp4Propagation { minutes: f64, msg: String },
+    #[snafu(display("SGP4 states are only available in an Earth J2000 frame, not {frame}"))]
+    Sgp4Frame { frame: Frame },
+    #[snafu(display("TLE fit failed: {msg}"))]
+    TleFit { msg: String },
+}
+
+/// Mean orbital elements as they are perturbed through SGP4 (angles in radians, mean motion in radians per minute).
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct MeanElements {
+    pub ecc: f64,
+    pub inc: f64,
+    pub raan: f64,
+    pub aop: f64,
+    pub ma: f64,
+    pub n: f64,
+}
+
+/// SGP4/SDP4 propagator of a two-line element set.
+///
+/// This is a port of the reference implementation of Vallado, Crawford, Hujsak and Kelso,
+/// "Revisiting Spacetrack Report #3" (AIAA 2006-6753), in its "improved" operation mode and with the WGS-72 constants.
+/// Element sets with a period of 225 minutes or more use the deep space (SDP4) lunar-solar and resonance terms.
+///
+/// SGP4 natively outputs states in the True Equator Mean Equinox (TEME) frame, which are rotated into EME2000 by
+/// [`Sgp4::at`] using [`teme_to_j2000_dcm`].
+#[derive(Clone, Debug)]
+pub struct Sgp4 {
+    /// Element set used to initialize this propagator
+    pub tle: Tle,
+    xke: f64,
+    j3oj2: f64,
+    ecco: f64,
+    inclo: f64,
+    nodeo: f64,
+    argpo: f64,
+    mo: f64,
+    bstar: f64,
+    no_unkozai: f64,
+    /// Whether the perigee is so low that the higher order drag terms are dropped
+    isimp: bool,
+    aycof: f64,
+    con41: f64,
+    cc1: f64,
+    cc4: f64,
+    cc5: f64,
+    d2: f64,
+    d3: f64,
+    d4: f64,
+    delmo: f64,
+    eta: f64,
+    argpdot: f64,
+    omgcof: f64,
+    sinmao: f64,
+    t2cof: f64,
+    t3cof: f64,
+    t4cof: f64,
+    t5cof: f64,
+    x1mth2: f64,
+    x7thm1: f64,
+    mdot: f64,
+    nodedot: f64,
+    xlcof: f64,
+    xmcof: f64,
+    nodecf: f64,
+    deep: Option<DeepSpace>,
+}
+
+impl Sgp4 {
+    /// Initializes the propagator from the provided element set (`sgp4init`).
+    pub fn new(tle: &Tle) -> Result<Self, Sgp4Error> {
+        let xke = 60.0 / (RADIUS_KM.powi(3) / MU_KM3_S2).sqrt();
+        let j3oj2 = J3 / J2;
+        let x2o3 = 2.0 / 3.0;
+
+        let ecco = tle.ecc;
+        let inclo = tle.inclination_deg.to_radians();
+        let nodeo = tle.raan_deg.to_radians();
+        let argpo = tle.aop_deg.to_radians();
+        let mo = tle.mean_anomaly_deg.to_radians();
+        let no_kozai = tle.mean_motion_rad_min();
+        let bstar = tle.bstar;
+        // Days since 1949 December 31 00:00 UT
+        let epoch_days = tle.epoch.to_jde_utc_days() - 2_433_281.5;
+
+        ensure!(
+            (0.0..1.0).contains(&ecco) && no_kozai > 0.0,
+            Sgp4PropagationSnafu {
+                minutes: 0.0,
+                msg: format!("invalid mean elements: ecc = {ecco}, n = {no_kozai} rad/min"),
+            }
+        );
+
+        // `initl`: recover the original mean motion and semi-major axis.
+        let eccsq = ecco * ecco;
+        let omeosq = 1.0 - eccsq;
+        let rteosq = omeosq.sqrt();
+        let (sinio, cosio) = inclo.sin_cos();
+        let cosio2 = cosio * cosio;
+        let ak = (xke / no_kozai).powf(x2o3);
+        let d1 = 0.75 * J2 * (3.0 * cosio2 - 1.0) / (rteosq * omeosq);
+        let mut del = d1 / (ak * ak);
+        let adel = ak * (1.0 - del * del - del * (1.0 / 3.0 + 134.0 * del * del / 81.0));
+        del = d1 / (adel * adel);
+        let no_unkozai = no_kozai / (1.0 + del);
+        let ao = (xke / no_unkozai).powf(x2o3);
+        let po = ao * omeosq;
+        let con42 = 1.0 - 5.0 * cosio2;
+        let con41 = -con42 - cosio2 - cosio2;
+        let posq = po * po;
+        let rp = ao * (1.0 - ecco);
+        let gsto = gstime(epoch_days + 2_433_281.5);
+
+        let mut isimp = rp < 220.0 / RADIUS_KM + 1.0;
+
+        // Atmospheric density parameters, adjusted for low perigees
+        let ss = 78.0 / RADIUS_KM + 1.0;
+        let mut sfour = ss;
+        let mut qzms24 = ((120.0 - 78.0) / RADIUS_KM).powi(4);
+        let perige = (rp - 1.0) * RADIUS_KM;
+        if perige < 156.0 {
+            sfour = if perige < 98.0 { 20.0 } else { perige - 78.0 };
+            qzms24 = ((120.0 - sfour) / RADIUS_KM).powi(4);
+            sfour = sfour / RADIUS_KM + 1.0;
+        }
+
+        let pinvsq = 1.0 / posq;
+        let tsi = 1.0 / (ao - sfour);
+        let eta = ao * ecco * tsi;
+        let etasq = eta * eta;
+        let eeta = ecco * eta;
+        let psisq = (1.0 - etasq).abs();
+        let coef = qzms24 * tsi.powi(4);
+        let coef1 = coef / psisq.powf(3.5);
+        let cc2 = coef1
+            * no_unkozai
+            * (ao * (1.0 + 1.5 * etasq + eeta * (4.0 + etasq))
+                + 0.375 * J2 * tsi / psisq * con41 * (8.0 + 3.0 * etasq * (8.0 + etasq)));
+        let cc1 = bstar * cc2;
+        let cc3 = if ecco > 1.0e-4 {
+            -2.0 * coef * tsi * j3oj2 * no_unkozai * sinio / ecco
+        } else {
+            0.0
+        };
+        let x1mth2 = 1.0 - cosio2;
+        let cc4 = 2.0
+            * no_unkozai
+            * coef1
+            * ao
+            * omeosq
+            * (eta * (2.0 + 0.5 * etasq) + ecco * (0.5 + 2.0 * etasq)
+                - J2 * tsi / (ao * psisq)
+                    * (-3.0 * con41 * (1.0 - 2.0 * eeta + etasq * (1.5 - 0.5 * eeta))
+                        + 0.75
+                            * x1mth2
+                            * (2.0 * etasq - eeta * (1.0 + etasq))
+                            * (2.0 * argpo).cos()));
+        let cc5 = 2.0 * coef1 * ao * omeosq * (1.0 + 2.75 * (etasq + eeta) + eeta * etasq);
+
+        let cosio4 = cosio2 * cosio2;
+        let temp1 = 1.5 * J2 * pinvsq * no_unkozai;
+        let temp2 = 0.5 * temp1 * J2 * pinvsq;
+        let temp3 = -0.46875 * J4 * pinvsq * pinvsq * no_unkozai;
+        let mdot = no_unkozai
+            + 0.5 * temp1 * rteosq * con41
+            + 0.0625 * temp2 * rteosq * (13.0 - 78.0 * cosio2 + 137.0 * cosio4);
+        let argpdot = -0.5 * temp1 * con42
+            + 0.0625 * temp2 * (7.0 - 114.0 * cosio2 + 395.0 * cosio4)
+            + temp3 * (3.0 - 36.0 * cosio2 + 49.0 * cosio4);
+        let xhdot1 = -temp1 * cosio;
+        let nodedot = xhdot1
+            + (0.5 * temp2 * (4.0 - 19.0 * cosio2) + 2.0 * temp3 * (3.0 - 7.0 * cosio2)) * cosio;
+        let xpidot = argpdot + nodedot;
+        let omgcof = bstar * cc3 * argpo.cos();
+        let xmcof = if ecco > 1.0e-4 {
+            -x2o3 * coef * bstar / eeta
+        } else {
+            0.0
+        };
+        let nodecf = 3.5 * omeosq * xhdot1 * cc1;
+        let t2cof = 1.5 * cc1;
+        let xlcof = xlcof(j3oj2, sinio, cosio);
+        let aycof = -0.5 * j3oj2 * sinio;
+        let delmo = (1.0 + eta * mo.cos()).powi(3);
+        let sinmao = mo.sin();
+        let x7thm1 = 7.0 * cosio2 - 1.0;
+
+        let deep = if TAU / no_unkozai >= 225.0 {
+            isimp = true;
+            Some(DeepSpace::new(DeepSpaceInit {
+                epoch_days,
+                ecco,
+                inclo,
+                nodeo,
+                argpo,
+                mo,
+                no: no_unkozai,
+                mdot,
+                nodedot,
+                xpidot,
+                gsto,
+                xke,
+            }))
+        } else {
+            None
+        };
+
+        let (mut d2, mut d3, mut d4) = (0.0, 0.0, 0.0);
+        let (mut t3cof, mut t4cof, mut t5cof) = (0.0, 0.0, 0.0);
+        if !isimp {
+            let cc1sq = cc1 * cc1;
+            d2 = 4.0 * ao * tsi * cc1sq;
+            let temp = d2 * tsi * cc1 / 3.0;
+            d3 = (17.0 * ao + sfour) * temp;
+            d4 = 0.5 * temp * ao * tsi * (221.0 * ao + 31.0 * sfour) * cc1;
+            t3cof = d2 + 2.0 * cc1sq;
+            t4cof = 0.25 * (3.0 * d3 + cc1 * (12.0 * d2 + 10.0 * cc1sq));
+            t5cof = 0.2
+                * (3.0 * d4 + 12.0 * cc1 * d3 + 6.0 * d2 * d2 + 15.0 * cc1sq * (2.0 * d2 + cc1sq));
+        }
+
+        let me = Self {
+            tle: tle.clone(),
+            xke,
+            j3oj2,
+            ecco,
+            inclo,
+            nodeo,
+            argpo,
+            mo,
+            bstar,
+            no_unkozai,
+            isimp,
+            aycof,
+            con41,
+            cc1,
+            cc4,
+            cc5,
+            d2,
+            d3,
+            d4,
+            delmo,
+            eta,
+            argpdot,
+            omgcof,
+            sinmao,
+            t2cof,
+            t3cof,
+            t4cof,
+            t5cof,
+            x1mth2,
+            x7thm1,
+            mdot,
+            nodedot,
+            xlcof,
+            xmcof,
+            nodecf,
+            deep,
+        };
+
+        // Like the reference implementation, reject element sets which cannot be evaluated at their own epoch.
+        me.propagate_teme(0.0)?;
+
+        Ok(me)
+    }
+
+    /// Returns whether this element set is propagated with the deep space (SDP4) terms.
+    pub fn is_deep_space(&self) -> bool {
+        self.deep.is_some()
+    }
+
+    /// Returns the TEME position (km) and velocity (km/s) `minutes` after the epoch of the element set.
+    pub fn propagate_teme(&self, minutes: f64) -> Result<(Vector3<f64>, Vector3<f64>), Sgp4Error> {
+        let t = minutes;
+        let x2o3 = 2.0 / 3.0;
+        let vkmpersec = RADIUS_KM * self.xke / 60.0;
+
+        // Secular gravity and atmospheric drag
+        let xmdf = self.mo + self.mdot * t;
+        let argpdf = self.argpo + self.argpdot * t;
+        let nodedf = self.nodeo + self.nodedot * t;
+        let t2 = t * t;
+        let mut el = MeanElements {
+            ecc: self.ecco,
+            inc: self.inclo,
+            raan: nodedf + self.nodecf * t2,
+            aop: argpdf,
+            ma: xmdf,
+            n: self.no_unkozai,
+        };
+        let mut tempa = 1.0 - self.cc1 * t;
+        let mut tempe = self.bstar * self.cc4 * t;
+        let mut templ = self.t2cof * t2;
+
+        if !self.isimp {
+            let delomg = self.omgcof * t;
+            let delm = self.xmcof * ((1.0 + self.eta * xmdf.cos()).powi(3) - self.delmo);
+            let temp = delomg + delm;
+            el.ma = xmdf + temp;
+            el.aop = argpdf - temp;
+            let t3 = t2 * t;
+            let t4 = t3 * t;
+            tempa -= self.d2 * t2 + self.d3 * t3 + self.d4 * t4;
+            tempe += self.bstar * self.cc5 * (el.ma.sin() - self.sinmao);
+            templ += self.t3cof * t3 + t4 * (self.t4cof + t * self.t5cof);
+        }
+
+        if let Some(deep) = &self.deep {
+            deep.secular(t, self.no_unkozai, self.argpo, self.argpdot, &mut el);
+        }
+
+        let fail = |msg: &str| Sgp4Error::Sgp4Propagation {
+            minutes,
+            msg: msg.to_string(),
+        };
+
+        if el.n <= 0.0 {
+            return Err(fail("mean motion is not positive"));
+        }
+        let am = (self.xke / el.n).powf(x2o3) * tempa * tempa;
+        let nm = self.xke / am.powf(1.5);
+        el.ecc -= tempe;
+        if !(-0.001..1.0).contains(&el.ecc) {
+            return Err(fail("mean eccentricity is out of range"));
+        }
+        el.ecc = el.ecc.max(1.0e-6);
+        el.ma += self.no_unkozai * templ;
+        let xlm = (el.ma + el.aop + el.raan) % TAU;
+        el.raan %= TAU;
+        el.aop %= TAU;
+        el.ma = (xlm - el.aop - el.raan) % TAU;
+
+        // Lunar-solar periodics
+        let (mut aycof, mut xlcof_p) = (self.aycof, self.xlcof);
+        let (mut con41, mut x1mth2, mut x7thm1) = (self.con41, self.x1mth2, self.x7thm1);
+        if let Some(deep) = &self.deep {
+            deep.periodic(t, &mut el);
+            if el.inc < 0.0 {
+                el.inc = -el.inc;
+                el.raan += PI;
+                el.aop -= PI;
+            }
+            if !(0.0..=1.0).contains(&el.ecc) {
+                return Err(fail("perturbed eccentricity is out of range"));
+            }
+            let (sinip, cosip) = el.inc.sin_cos();
+            aycof = -0.5 * self.j3oj2 * sinip;
+            xlcof_p = xlcof(self.j3oj2, sinip, cosip);
+            let cosisq = cosip * cosip;
+            con41 = 3.0 * cosisq - 1.0;
+            x1mth2 = 1.0 - cosisq;
+            x7thm1 = 7.0 * cosisq - 1.0;
+        }
+
+        // Long period periodics
+        let axnl = el.ecc * el.aop.cos();
+        let temp = 1.0 / (am * (1.0 - el.ecc * el.ecc));
+        let aynl = el.ecc * el.aop.sin() + temp * aycof;
+        let xl = el.ma + el.aop + el.raan + temp * xlcof_p * axnl;
+
+        // Solve Kepler's equation
+        let u = (xl - el.raan) % TAU;
+        let mut eo1 = u;
+        let (mut sineo1, mut coseo1) = eo1.sin_cos();
+        for _ in 0..10 {
+            (sineo1, coseo1) = eo1.sin_cos();
+            let mut tem5 =
+                (u - aynl * coseo1 + axnl * sineo1 - eo1) / (1.0 - coseo1 * axnl - sineo1 * aynl);
+            if tem5.abs() >= 0.95 {
+                tem5 = 0.95_f64.copysign(tem5);
+            }
+            eo1 += tem5;
+            if tem5.abs() < 1.0e-12 {
+                break;
+            }
+        }
+
+        // Short period periodics
+        let ecose = axnl * coseo1 + aynl * sineo1;
+        let esine = axnl * sineo1 - aynl * coseo1;
+        let el2 = axnl * axnl + aynl * aynl;
+        let pl = am * (1.0 - el2);
+        if pl < 0.0 {
+            return Err(fail("semi-latus rectum is negative"));
+        }
+
+        let rl = am * (1.0 - ecose);
+        let rdotl = am.sqrt() * esine / rl;
+        let rvdotl = pl.sqrt() / rl;
+        let betal = (1.0 - el2).sqrt();
+        let temp = esine / (1.0 + betal);
+        let sinu = am / rl * (sineo1 - aynl - axnl * temp);
+        let cosu = am / rl * (coseo1 - axnl + aynl * temp);
+        let su = sinu.atan2(cosu);
+        let sin2u = (cosu + cosu) * sinu;
+        let cos2u = 1.0 - 2.0 * sinu * sinu;
+        let temp = 1.0 / pl;
+        let temp1 = 0.5 * J2 * temp;
+        let temp2 = temp1 * temp;
+
+        let (sinip, cosip) = el.inc.sin_cos();
+        let mrt = rl * (1.0 - 1.5 * temp2 * betal * con41) + 0.5 * temp1 * x1mth2 * cos2u;
+        let su = su - 0.25 * temp2 * x7thm1 * sin2u;
+        let xnode = el.raan + 1.5 * temp2 * cosip * sin2u;
+        let xinc = el.inc + 1.5 * temp2 * cosip * sinip * cos2u;
+        let mvt = rdotl - nm * temp1 * x1mth2 * sin2u / self.xke;
+        let rvdot = rvdotl + nm * temp1 * (x1mth2 * cos2u + 1.5 * con41) / self.xke;
+
+        // Orientation vectors
+        let (sinsu, cossu) = su.sin_cos();
+        let (snod, cnod) = xnode.sin_cos();
+        let (sini, cosi) = xinc.sin_cos();
+        let xmx = -snod * cosi;
+        let xmy = cnod * cosi;
+        let u_hat = Vector3::new(
+            xmx * sinsu + cnod * cossu,
+            xmy * sinsu + snod * cossu,
+            sini * sinsu,
+        );
+        let v_hat = Vector3::new(
+            xmx * cossu - cnod * sinsu,
+            xmy * cossu - snod * sinsu,
+            sini * cossu,
+        );
+
+        if mrt < 1.0 {
+            return Err(fail("the satellite has decayed"));
+        }
+
+        Ok((
+            mrt * RADIUS_KM * u_hat,
+            (mvt * u_hat + rvdot * v_hat) * vkmpersec,
+        ))
+    }
+
+    /// Returns the state at the provided epoch in the provided frame, which must be an Earth centered J2000 frame.
+    pub fn at(&self, epoch: Epoch, frame: Frame) -> Result<Orbit, Sgp4Error> {
+        ensure!(
+            frame.ephem_origin_match(EARTH_J2000) && frame.orient_origin_match(EARTH_J2000),
+            Sgp4FrameSnafu { frame }
+        );
+        let minutes = (epoch - self.tle.epoch).to_unit(Unit::Minute);
+        let (r_teme, v_teme) = self.propagate_teme(minutes)?;
+        let dcm = teme_to_j2000_dcm(epoch);
+        let r = dcm * r_teme;
+        let v = dcm * v_teme;
+        Ok(Orbit::new(r.x, r.y, r.z, v.x, v.y, v.z, epoch, frame))
+    }
+
+    /// Builds a trajectory of spacecraft with default properties from `start` to `end` (inclusive) every `step`,
+    /// e.g. to use a catalog object as a conjunction partner.
+    pub fn to_traj(
+        &self,
+        start: Epoch,
+        end: Epoch,
+        step: Duration,
+        frame: Frame,
+    ) -> Result<Traj<Spacecraft>, Sgp4Error> {
+        ensure!(
+            step > Duration::ZERO,
+            Sgp4PropagationSnafu {
+                minutes: 0.0,
+                msg: format!("output step must be strictly positive but got {step}"),
+            }
+        );
+        let mut traj = Traj::new();
+        traj.name = Some(match &self.tle.name {
+            Some(name) => name.clone(),
+            None => format!("{}", self.tle.catalog_number),
+        });
+        let mut epoch = start;
+        loop {
+            let orbit = self.at(epoch, frame)?;
+            traj.states.push(Spacecraft::builder().orbit(orbit).build());
+            if epoch >= end {
+                break;
+            }
+            epoch = (epoch + step).min(end);
+        }
+        traj.finalize();
+        Ok(traj)
+    }
+}
+
+fn xlcof(j3oj2: f64, sinio: f64, cosio: f64) -> f64 {
+    let denom = if (cosio + 1.0).abs() > 1.5e-12 {
+        1.0 + cosio
+    } else {
+        1.5e-12
+    };
+    -0.25 * j3oj2 * sinio * (3.0 + 5.0 * cosio) / denom
+}
+
+/// Greenwich mean sidereal time (IAU 1982), in radians, of the provided UT1 Julian date.
+fn gstime(jdut1: f64) -> f64 {
+    let tut1 = (jdut1 - 2_451_545.0) / 36_525.0;
+    let temp = -6.2e-6 * tut1.powi(3)
+        + 0.093104 * tut1 * tut1
+        + (876_600.0 * 3600.0 + 8_640_184.812866) * tut1
+        + 67_310.548_41;
+    (temp.to_radians() / 240.0).rem_euclid(TAU)
+}
+
+#[cfg(test)]
+mod ut_sgp4 {
+    use super::*;
+
+    fn assert_state(sgp4: &Sgp4, minutes: f64, r: [f64; 3], v: [f64; 3], r_tol: f64, v_tol: f64) {
+        let (r_teme, v_teme) = sgp4.propagate_teme(minutes).unwrap();
+        let r_err = (r_teme - Vector3::from(r)).norm();
+        let v_err = (v_teme - Vector3::from(v)).norm();
+        println!("t = {minutes} min\tr err = {r_err:.3e} km\tv err = {v_err:.3e} km/s");
+        assert!(r_err < r_tol, "r err = {r_err:.3e} km");
+        assert!(v_err < v_tol, "v err = {v_err:.3e} km/s");
+    }
+
+    #[test]
+    fn near_earth_vallado_verification() {
+        // Test case from the verification set of "Revisiting Spacetrack Report #3".
+        let tle = Tle::from_lines(
+            "1 00005U 58002B   00179.78495062  .00000023  00000-0  28098-4 0  4753",
+            "2 00005  34.2682 348.7242 1859667 331.7664  19.3264 10.82419157413667",
+        )
+        .unwrap();
+        assert_eq!(tle.catalog_number, 5);
+        assert_eq!(tle.intl_designator, "58002B");
+        assert!((tle.bstar - 2.8098e-5).abs() < 1e-15);
+        assert!((tle.ecc - 0.1859667).abs() < 1e-12);
+
+        let (line1, line2) = tle.to_lines();
+        assert_eq!(
+            line1,
+            "1 00005U 58002B   00179.78495062  .00000023  00000-0  28098-4 0  4753"
+        );
+        assert_eq!(
+            line2,
+            "2 00005  34.2682 348.7242 1859667 331.7664  19.3264 10.82419157413667"
+        );
+
+        let sgp4 = Sgp4::new(&tle).unwrap();
+        assert!(!sgp4.is_deep_space());
+
+        assert_state(
+            &sgp4,
+            0.0,
+            [7022.46529266, -1400.08296755, 0.03995155],
+            [1.893841015, 6.405893759, 4.534807250],
+            1e-6,
+            1e-9,
+        );
+        assert_state(
+            &sgp4,
+            360.0,
+            [-7154.03120202, -3783.17682504, -3536.19412294],
+            [4.741887409, -4.151817765, -2.093935425],
+            1e-6,
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn deep_space_vallado_verification() {
+        // Molniya orbit, in twelve hour resonance
+        let tle = Tle::from_lines(
+            "1 08195U 75081A   06176.33215444  .00000099  00000-0  11873-3 0   813",
+            "2 08195  64.1586 279.0717 6877146 264.7651  20.2257  2.00491383225656",
+        )
+        .unwrap();
+        let sgp4 = Sgp4::new(&tle).unwrap();
+        assert!(sgp4.is_deep_space());
+
+        assert_state(
+            &sgp4,
+            0.0,
+            [2349.89483350, -14785.93811562, 0.02119378],
+            [2.721488096, -3.256811655, 4.498416672],
+            1e-6,
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn catalog_and_checksum() {
+        let catalog = "ISS (ZARYA)
+1 25544U 98067A   08264.51782528 -.00002182  00000-0 -11606-4 0  2927
+2 25544  51.6416 247.4627 0006703 130.5360 325.0288 15.72125391563537
+";
+        let tle: Tle = catalog.parse().unwrap();
+        assert_eq!(tle.name.as_deref(), Some("ISS (ZARYA)"));
+        assert_eq!(tle.catalog_number, 25544);
+        assert!((tle.mean_motion_dot + 2.182e-5).abs() < 1e-15);
+        assert!((tle.bstar + 1.1606e-5).abs() < 1e-15);
+
+        let bad = catalog.replace("2927", "2928");
+        assert_eq!(
+            bad.parse::<Tle>(),
+            Err(Sgp4Error::TleChecksum {
+                line: 1,
+                expected: 7,
+                found: 8
+            })
+        );
+
+        // The ISS states at epoch are LEO states in EME2000.
+        let sgp4 = Sgp4::new(&tle).unwrap();
+        let orbit = sgp4
+            .at(tle.epoch, EARTH_J2000.with_mu_km3_s2(MU_KM3_S2))
+            .unwrap();
+        let rmag = orbit.rmag_km();
+        assert!((6650.0..6800.0).contains(&rmag), "{rmag}");
+        assert!((orbit.inc_deg().unwrap() - 51.64).abs() < 0.2);
+
+        // Formatting the parsed element set reproduces the original lines.
+        let (line1, line2) = tle.to_lines();
+        assert_eq!(
+            line1,
+            "1 25544U 98067A   08264.51782528 -.00002182  00000-0 -11606-4 0  2927"
+        );
+        assert_eq!(
+            line2,
+            "2 25544  51.6416 247.4627 0006703 130.5360 325.0288 15.72125391563537"
+        );
+
+        let moon_frame = Frame::from_ephem_j2000(301);
+        assert!(sgp4.at(tle.epoch, moon_frame).is_err());
+    }
+}
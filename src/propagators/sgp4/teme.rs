@@ -0,0 +1,165 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::linalg::Matrix3;
+use crate::time::Epoch;
+
+const ARCSEC_TO_RAD: f64 = std::f64::consts::PI / (180.0 * 3600.0);
+
+/// Largest terms of the IAU 1980 nutation series: multipliers of (l, l', F, D, Ω), then the
+/// longitude coefficients (A + B T) and the obliquity coefficients (C + D T), in units of 0.1 mas.
+/// The truncated terms sum to less than a milliarcsecond.
+#[rustfmt::skip]
+const NUTATION_1980: [([i8; 5], [f64; 4]); 30] = [
+    ([ 0,  0, 0,  0, 1], [-171_996.0, -174.2, 92_025.0,  8.9]),
+    ([ 0,  0, 2, -2, 2], [ -13_187.0,   -1.6,  5_736.0, -3.1]),
+    ([ 0,  0, 2,  0, 2], [  -2_274.0,   -0.2,    977.0, -0.5]),
+    ([ 0,  0, 0,  0, 2], [   2_062.0,    0.2,   -895.0,  0.5]),
+    ([ 0,  1, 0,  0, 0], [   1_426.0,   -3.4,     54.0, -0.1]),
+    ([ 1,  0, 0,  0, 0], [     712.0,    0.1,     -7.0,  0.0]),
+    ([ 0,  1, 2, -2, 2], [    -517.0,    1.2,    224.0, -0.6]),
+    ([ 0,  0, 2,  0, 1], [    -386.0,   -0.4,    200.0,  0.0]),
+    ([ 1,  0, 2,  0, 2], [    -301.0,    0.0,    129.0, -0.1]),
+    ([ 0, -1, 2, -2, 2], [     217.0,   -0.5,    -95.0,  0.3]),
+    ([ 1,  0, 0, -2, 0], [    -158.0,    0.0,     -1.0,  0.0]),
+    ([ 0,  0, 2, -2, 1], [     129.0,    0.1,    -70.0,  0.0]),
+    ([-1,  0, 2,  0, 2], [     123.0,    0.0,    -53.0,  0.0]),
+    ([ 1,  0, 0,  0, 1], [      63.0,    0.1,    -33.0,  0.0]),
+    ([ 0,  0, 0,  2, 0], [      63.0,    0.0,     -2.0,  0.0]),
+    ([-1,  0, 2,  2, 2], [     -59.0,    0.0,     26.0,  0.0]),
+    ([-1,  0, 0,  0, 1], [     -58.0,   -0.1,     32.0,  0.0]),
+    ([ 1,  0, 2,  0, 1], [     -51.0,    0.0,     27.0,  0.0]),
+    ([ 2,  0, 0, -2, 0], [      48.0,    0.0,      1.0,  0.0]),
+    ([-2,  0, 2,  0, 1], [      46.0,    0.0,    -24.0,  0.0]),
+    ([ 0,  0, 2,  2, 2], [     -38.0,    0.0,     16.0,  0.0]),
+    ([ 2,  0, 2,  0, 2], [     -31.0,    0.0,     13.0,  0.0]),
+    ([ 2,  0, 0,  0, 0], [      29.0,    0.0,     -1.0,  0.0]),
+    ([ 1,  0, 2, -2, 2], [      29.0,    0.0,    -12.0,  0.0]),
+    ([ 0,  0, 2,  0, 0], [      26.0,    0.0,     -1.0,  0.0]),
+    ([ 0,  0, 2, -2, 0], [     -22.0,    0.0,      0.0,  0.0]),
+    ([-1,  0, 2,  0, 1], [      21.0,    0.0,    -10.0,  0.0]),
+    ([ 0,  2, 0,  0, 0], [      17.0,   -0.1,      0.0,  0.0]),
+    ([ 0,  2, 2, -2, 2], [     -16.0,    0.1,      7.0,  0.0]),
+    ([-1,  0, 0,  2, 1], [      16.0,    0.0,     -8.0,  0.0]),
+];
+
+/// Returns the rotation from the True Equator Mean Equinox frame of SGP4 to the EME2000 frame at the provided epoch.
+///
+/// This follows Vallado's `teme2eci`: the TEME vector is first rotated by the equation of the equinoxes
+/// into the true of date frame, then the IAU 1980 nutation and IAU 1976 precession bring it to J2000.
+/// The rate of change of this rotation is negligible, so the same matrix applies to the velocity.
+pub fn teme_to_j2000_dcm(epoch: Epoch) -> Matrix3<f64> {
+    let t = epoch.to_tt_centuries_j2k();
+    let (t2, t3) = (t * t, t * t * t);
+
+    // IAU 1976 precession angles
+    let zeta = (2306.2181 * t + 0.30188 * t2 + 0.017998 * t3) * ARCSEC_TO_RAD;
+    let theta = (2004.3109 * t - 0.42665 * t2 - 0.041833 * t3) * ARCSEC_TO_RAD;
+    let z = (2306.2181 * t + 1.09468 * t2 + 0.018203 * t3) * ARCSEC_TO_RAD;
+
+    let (delta_psi, delta_eps, mean_eps) = nutation_1980(t);
+    let eps = mean_eps + delta_eps;
+    let eq_equinox = delta_psi * mean_eps.cos();
+
+    let precession = rot3(zeta) * rot2(-theta) * rot3(z);
+    let nutation = rot1(-mean_eps) * rot3(delta_psi) * rot1(eps);
+
+    precession * nutation * rot3(-eq_equinox)
+}
+
+/// Returns the nutation in longitude, the nutation in obliquity and the mean obliquity, all in radians.
+fn nutation_1980(t: f64) -> (f64, f64, f64) {
+    let (t2, t3) = (t * t, t * t * t);
+    let rev = 1_296_000.0;
+
+    // Delaunay arguments, in arcseconds
+    let fundamental = [
+        485_866.733 + (1325.0 * rev + 715_922.633) * t + 31.310 * t2 + 0.064 * t3,
+        1_287_099.804 + (99.0 * rev + 1_292_581.224) * t - 0.577 * t2 - 0.012 * t3,
+        335_778.877 + (1342.0 * rev + 295_263.137) * t - 13.257 * t2 + 0.011 * t3,
+        1_072_261.307 + (1236.0 * rev + 1_105_601.328) * t - 6.891 * t2 + 0.019 * t3,
+        450_160.280 - (5.0 * rev + 482_890.539) * t + 7.455 * t2 + 0.008 * t3,
+    ]
+    .map(|arcsec| (arcsec % rev) * ARCSEC_TO_RAD);
+
+    let mut delta_psi = 0.0;
+    let mut delta_eps = 0.0;
+    for (mult, [a, b, c, d]) in NUTATION_1980.iter() {
+        let arg: f64 = mult
+            .iter()
+            .zip(fundamental.iter())
+            .map(|(m, f)| f64::from(*m) * f)
+            .sum();
+        delta_psi += (a + b * t) * arg.sin();
+        delta_eps += (c + d * t) * arg.cos();
+    }
+
+    let mean_eps = (84_381.448 - 46.8150 * t - 0.00059 * t2 + 0.001813 * t3) * ARCSEC_TO_RAD;
+
+    (
+        delta_psi * 1e-4 * ARCSEC_TO_RAD,
+        delta_eps * 1e-4 * ARCSEC_TO_RAD,
+        mean_eps,
+    )
+}
+
+fn rot1(angle: f64) -> Matrix3<f64> {
+    let (s, c) = angle.sin_cos();
+    Matrix3::new(1.0, 0.0, 0.0, 0.0, c, s, 0.0, -s, c)
+}
+
+fn rot2(angle: f64) -> Matrix3<f64> {
+    let (s, c) = angle.sin_cos();
+    Matrix3::new(c, 0.0, -s, 0.0, 1.0, 0.0, s, 0.0, c)
+}
+
+fn rot3(angle: f64) -> Matrix3<f64> {
+    let (s, c) = angle.sin_cos();
+    Matrix3::new(c, s, 0.0, -s, c, 0.0, 0.0, 0.0, 1.0)
+}
+
+#[cfg(test)]
+mod ut_teme {
+    use super::*;
+    use crate::linalg::Vector3;
+    use crate::time::TimeScale;
+
+    #[test]
+    fn vallado_example_3_15() {
+        // Vallado, Fundamentals of Astrodynamics and Applications, 4th ed., example 3-15.
+        let epoch = Epoch::from_gregorian(2004, 4, 6, 7, 51, 28, 386_009_000, TimeScale::UTC);
+        let r_teme = Vector3::new(5094.18016210, 6127.64465950, 6380.34453270);
+        let v_teme = Vector3::new(-4.746131487, 0.785818041, 5.531931288);
+
+        let dcm = teme_to_j2000_dcm(epoch);
+        assert!((dcm * dcm.transpose() - Matrix3::identity()).norm() < 1e-14);
+
+        let r_j2k = dcm * r_teme;
+        let v_j2k = dcm * v_teme;
+        // Reference values omit the EOP nutation corrections, which are not modeled.
+        let r_expected = Vector3::new(5102.5096, 6123.01152, 6378.1363);
+        let v_expected = Vector3::new(-4.743219600, 0.790536600, 5.533756190);
+        println!(
+            "r err = {:.3e} km\tv err = {:.3e} km/s",
+            (r_j2k - r_expected).norm(),
+            (v_j2k - v_expected).norm()
+        );
+        assert!((r_j2k - r_expected).norm() < 2e-3);
+        assert!((v_j2k - v_expected).norm() < 2e-6);
+    }
+}
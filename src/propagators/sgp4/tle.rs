@@ -0,0 +1,335 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{Sgp4Error, TleChecksumSnafu, TleFormatSnafu};
+use crate::time::{Epoch, Unit};
+use snafu::ensure;
+use std::fmt;
+use std::str::FromStr;
+
+/// A two-line element set, as published in the public catalogs.
+///
+/// The mean elements are in the TEME frame and are only meaningful when propagated with SGP4/SDP4.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tle {
+    /// Optional name of the object, from the line preceding the element set (three-line format)
+    pub name: Option<String>,
+    /// Catalog number of the object
+    pub catalog_number: u32,
+    /// Security classification, typically `U`
+    pub classification: char,
+    /// International designator (launch year, launch number and piece)
+    pub intl_designator: String,
+    /// Epoch of the element set (UTC)
+    pub epoch: Epoch,
+    /// First derivative of the mean motion divided by two, in revolutions per day squared
+    pub mean_motion_dot: f64,
+    /// Second derivative of the mean motion divided by six, in revolutions per day cubed
+    pub mean_motion_ddot: f64,
+    /// B* drag term, in inverse Earth radii
+    pub bstar: f64,
+    /// Element set number
+    pub element_set_number: u32,
+    /// Inclination, in degrees
+    pub inclination_deg: f64,
+    /// Right ascension of the ascending node, in degrees
+    pub raan_deg: f64,
+    /// Eccentricity
+    pub ecc: f64,
+    /// Argument of perigee, in degrees
+    pub aop_deg: f64,
+    /// Mean anomaly, in degrees
+    pub mean_anomaly_deg: f64,
+    /// Mean motion (Kozai), in revolutions per day
+    pub mean_motion_rev_day: f64,
+    /// Revolution number at epoch
+    pub rev_number: u32,
+}
+
+impl Tle {
+    /// Parses an element set from its two lines, verifying their checksums.
+    pub fn from_lines(line1: &str, line2: &str) -> Result<Self, Sgp4Error> {
+        let l1 = line1.trim_end();
+        let l2 = line2.trim_end();
+        ensure!(
+            l1.len() >= 68 && l1.is_ascii() && l1.starts_with('1'),
+            TleFormatSnafu {
+                line: 1_u8,
+                msg: format!("expected at least 68 ASCII characters starting with `1`: `{l1}`"),
+            }
+        );
+        ensure!(
+            l2.len() >= 68 && l2.is_ascii() && l2.starts_with('2'),
+            TleFormatSnafu {
+                line: 2_u8,
+                msg: format!("expected at least 68 ASCII characters starting with `2`: `{l2}`"),
+            }
+        );
+        verify_checksum(l1, 1)?;
+        verify_checksum(l2, 2)?;
+
+        let catalog_number: u32 = parse_field(l1, 2..7, 1, "catalog number")?;
+        let catalog_number_2: u32 = parse_field(l2, 2..7, 2, "catalog number")?;
+        ensure!(
+            catalog_number == catalog_number_2,
+            TleFormatSnafu {
+                line: 2_u8,
+                msg: format!(
+                    "catalog number {catalog_number_2} does not match that of line 1 ({catalog_number})"
+                ),
+            }
+        );
+
+        let year: i32 = parse_field(l1, 18..20, 1, "epoch year")?;
+        let year = if year < 57 { 2000 + year } else { 1900 + year };
+        let day_of_year: f64 = parse_field(l1, 20..32, 1, "epoch day")?;
+        let epoch =
+            Epoch::from_gregorian_utc_at_midnight(year, 1, 1) + (day_of_year - 1.0) * Unit::Day;
+
+        Ok(Self {
+            name: None,
+            catalog_number,
+            classification: l1.as_bytes()[7] as char,
+            intl_designator: l1[9..17].trim().to_string(),
+            epoch,
+            mean_motion_dot: parse_field(l1, 33..43, 1, "first derivative of mean motion")?,
+            mean_motion_ddot: parse_exponent_field(
+                l1,
+                44..52,
+                1,
+                "second derivative of mean motion",
+            )?,
+            bstar: parse_exponent_field(l1, 53..61, 1, "B*")?,
+            element_set_number: parse_field(l1, 64..68, 1, "element set number").unwrap_or(0),
+            inclination_deg: parse_field(l2, 8..16, 2, "inclination")?,
+            raan_deg: parse_field(l2, 17..25, 2, "right ascension of the ascending node")?,
+            ecc: parse_field::<f64>(l2, 26..33, 2, "eccentricity")? * 1e-7,
+            aop_deg: parse_field(l2, 34..42, 2, "argument of perigee")?,
+            mean_anomaly_deg: parse_field(l2, 43..51, 2, "mean anomaly")?,
+            mean_motion_rev_day: parse_field(l2, 52..63, 2, "mean motion")?,
+            rev_number: parse_field(l2, 63..68, 2, "revolution number").unwrap_or(0),
+        })
+    }
+
+    /// Parses every element set of a catalog, in either the two-line or the three-line (named) format.
+    pub fn from_catalog(catalog: &str) -> Result<Vec<Self>, Sgp4Error> {
+        let lines: Vec<&str> = catalog
+            .lines()
+            .map(|line| line.trim_end())
+            .filter(|line| !line.is_empty())
+            .collect();
+
+        let mut tles = Vec::new();
+        let mut name = None;
+        let mut i = 0;
+        while i < lines.len() {
+            if lines[i].starts_with("1 ") && i + 1 < lines.len() && lines[i + 1].starts_with("2 ") {
+                let mut tle = Self::from_lines(lines[i], lines[i + 1])?;
+                tle.name = name.take();
+                tles.push(tle);
+                i += 2;
+            } else {
+                name = Some(lines[i].trim_start_matches("0 ").trim().to_string());
+                i += 1;
+            }
+        }
+
+        Ok(tles)
+    }
+
+    /// Formats this element set as its two lines, with their checksums.
+    ///
+    /// Fields are rounded to the precision of the format, e.g. 1e-4 degrees for the angles and 1e-7 for the eccentricity.
+    pub fn to_lines(&self) -> (String, String) {
+        let year = self.epoch.to_gregorian_utc().0;
+        let day_of_year = (self.epoch - Epoch::from_gregorian_utc_at_midnight(year, 1, 1))
+            .to_unit(Unit::Day)
+            + 1.0;
+
+        let line1 = format!(
+            "1 {:05}{} {:<8} {:02}{:012.8} {} {} {} 0 {:>4}",
+            self.catalog_number % 100_000,
+            self.classification,
+            self.intl_designator,
+            year.rem_euclid(100),
+            day_of_year,
+            format_decimal_field(self.mean_motion_dot),
+            format_exponent_field(self.mean_motion_ddot),
+            format_exponent_field(self.bstar),
+            self.element_set_number % 10_000,
+        );
+        let line2 = format!(
+            "2 {:05} {:8.4} {:8.4} {:07} {:8.4} {:8.4} {:11.8}{:>5}",
+            self.catalog_number % 100_000,
+            self.inclination_deg,
+            self.raan_deg.rem_euclid(360.0),
+            ((self.ecc * 1e7).round() as u32).min(9_999_999),
+            self.aop_deg.rem_euclid(360.0),
+            self.mean_anomaly_deg.rem_euclid(360.0),
+            self.mean_motion_rev_day,
+            self.rev_number % 100_000,
+        );
+
+        (
+            format!("{line1}{}", checksum(&line1)),
+            format!("{line2}{}", checksum(&line2)),
+        )
+    }
+
+    /// Returns the mean motion in radians per minute, as used by SGP4.
+    pub fn mean_motion_rad_min(&self) -> f64 {
+        self.mean_motion_rev_day * std::f64::consts::TAU / 1440.0
+    }
+}
+
+impl FromStr for Tle {
+    type Err = Sgp4Error;
+
+    /// Parses a single element set, optionally preceded by its name line.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tles = Self::from_catalog(s)?;
+        ensure!(
+            tles.len() == 1,
+            TleFormatSnafu {
+                line: 1_u8,
+                msg: format!("expected exactly one element set but found {}", tles.len()),
+            }
+        );
+        Ok(tles.remove(0))
+    }
+}
+
+impl fmt::Display for Tle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "TLE #{} {}@{}: inc = {:.4} deg\traan = {:.4} deg\tecc = {:.7}\taop = {:.4} deg\tma = {:.4} deg\tn = {:.8} rev/day\tB* = {:e}",
+            self.catalog_number,
+            self.name.as_deref().unwrap_or(""),
+            self.epoch,
+            self.inclination_deg,
+            self.raan_deg,
+            self.ecc,
+            self.aop_deg,
+            self.mean_anomaly_deg,
+            self.mean_motion_rev_day,
+            self.bstar
+        )
+    }
+}
+
+/// Computes the modulo 10 checksum of the first 68 characters of a line, where minus signs count as one.
+pub(crate) fn checksum(line: &str) -> u32 {
+    line.chars()
+        .take(68)
+        .map(|c| match c {
+            '-' => 1,
+            c => c.to_digit(10).unwrap_or(0),
+        })
+        .sum::<u32>()
+        % 10
+}
+
+fn verify_checksum(line: &str, line_no: u8) -> Result<(), Sgp4Error> {
+    let expected = checksum(line);
+    let found = line
+        .chars()
+        .nth(68)
+        .and_then(|c| c.to_digit(10))
+        .ok_or(Sgp4Error::TleFormat {
+            line: line_no,
+            msg: "missing checksum".to_string(),
+        })?;
+    ensure!(
+        expected == found,
+        TleChecksumSnafu {
+            line: line_no,
+            expected,
+            found
+        }
+    );
+    Ok(())
+}
+
+fn parse_field<T: FromStr>(
+    line: &str,
+    cols: std::ops::Range<usize>,
+    line_no: u8,
+    field: &str,
+) -> Result<T, Sgp4Error> {
+    let raw = line[cols].trim();
+    raw.parse::<T>().map_err(|_| Sgp4Error::TleFormat {
+        line: line_no,
+        msg: format!("could not parse {field} from `{raw}`"),
+    })
+}
+
+/// Parses a field with an implied leading decimal point and a trailing power of ten, e.g. ` 28098-4` is 0.28098e-4.
+fn parse_exponent_field(
+    line: &str,
+    cols: std::ops::Range<usize>,
+    line_no: u8,
+    field: &str,
+) -> Result<f64, Sgp4Error> {
+    let raw = line[cols].trim();
+    if raw.is_empty() {
+        return Ok(0.0);
+    }
+    let err = || Sgp4Error::TleFormat {
+        line: line_no,
+        msg: format!("could not parse {field} from `{raw}`"),
+    };
+    let (mantissa, exponent) = match raw.rfind(['-', '+']) {
+        Some(idx) if idx > 0 => raw.split_at(idx),
+        _ => (raw, "0"),
+    };
+    let (sign, digits) = match mantissa.strip_prefix('-') {
+        Some(digits) => (-1.0, digits),
+        None => (1.0, mantissa.trim_start_matches('+')),
+    };
+    let mantissa: f64 = format!("0.{digits}").parse().map_err(|_| err())?;
+    let exponent: i32 = exponent.parse().map_err(|_| err())?;
+    Ok(sign * mantissa * 10_f64.powi(exponent))
+}
+
+/// Formats a value with an implied leading zero and a sign column, e.g. -0.00002182 is `-.00002182`.
+fn format_decimal_field(value: f64) -> String {
+    let sign = if value < 0.0 { '-' } else { ' ' };
+    let digits = format!("{:.8}", value.abs().min(0.999_999_99));
+    format!("{sign}{}", digits.trim_start_matches('0'))
+}
+
+/// Formats a value with an implied leading decimal point and a trailing power of ten, e.g. -0.11606e-4 is `-11606-4`.
+fn format_exponent_field(value: f64) -> String {
+    if value == 0.0 {
+        return " 00000-0".to_string();
+    }
+    let sign = if value < 0.0 { '-' } else { ' ' };
+    let mut exponent = value.abs().log10().floor() as i32 + 1;
+    let mut mantissa = (value.abs() / 10_f64.powi(exponent) * 1e5).round();
+    if mantissa >= 1e5 {
+        mantissa /= 10.0;
+        exponent += 1;
+    }
+    let exp_sign = if exponent < 0 { '-' } else { '+' };
+    format!(
+        "{sign}{:05}{exp_sign}{}",
+        mantissa as u32,
+        exponent.unsigned_abs().min(9)
+    )
+}
@@ -0,0 +1,91 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{IntegrationDetails, PropConfigSnafu, PropagationError, Propagator};
+use crate::cosmic::Spacecraft;
+use crate::dynamics::Dynamics;
+use crate::io::ConfigError;
+use crate::linalg::allocator::Allocator;
+use crate::linalg::{DefaultAllocator, OVector};
+use crate::time::Duration;
+use crate::State;
+use anise::almanac::Almanac;
+use snafu::ResultExt;
+use std::fmt;
+use std::sync::Arc;
+
+/// A stepper integrates the steps of a [PropInstance](super::PropInstance) with another scheme than the Runge Kutta
+/// method of its propagator, cf. [Propagator::with_stepper].
+///
+/// The propagator instance drives the propagation, so the trajectories, events, stop conditions, backward propagation,
+/// progress reports and cancellation are the same whichever the scheme.
+pub trait Stepper<D: Dynamics>: Send + Sync + fmt::Debug
+where
+    DefaultAllocator: Allocator<<D::StateType as State>::Size>
+        + Allocator<<D::StateType as State>::Size, <D::StateType as State>::Size>
+        + Allocator<<D::StateType as State>::VecLength>,
+{
+    /// Integrates a single step of the provided (signed) step size from the provided state, and returns the step taken
+    /// and the state vector at the end of that step.
+    ///
+    /// A fixed step must be taken exactly, whereas an adaptive step may be shorter but never longer than requested.
+    /// The step size is then updated to the step proposed for the next call, and the details to those of this step.
+    fn step(
+        &self,
+        prop: &Propagator<D>,
+        state: &D::StateType,
+        step_size: &mut Duration,
+        fixed_step: bool,
+        details: &mut IntegrationDetails,
+        almanac: Arc<Almanac>,
+    ) -> Result<(Duration, OVector<f64, <D::StateType as State>::VecLength>), PropagationError>;
+
+    /// Returns whether this stepper provides the continuous extension of its steps.
+    fn has_dense_output(&self) -> bool {
+        false
+    }
+
+    /// Returns the coefficients of θ, θ², etc. of the continuous extension of the step of the provided duration from the
+    /// provided state, cf. [DenseStep](crate::md::trajectory::DenseStep).
+    fn dense_coeffs(
+        &self,
+        _prop: &Propagator<D>,
+        _state: &D::StateType,
+        _step: Duration,
+        _almanac: Arc<Almanac>,
+    ) -> Result<Vec<OVector<f64, <D::StateType as State>::VecLength>>, PropagationError> {
+        Err(ConfigError::InvalidConfig {
+            msg: format!("{self:?} does not provide a dense output"),
+        })
+        .context(PropConfigSnafu)
+    }
+}
+
+/// Rejects a spacecraft with an STM, which the steppers of the spacecraft dynamics do not propagate.
+pub(crate) fn check_no_stm(state: &Spacecraft, scheme: &str) -> Result<(), PropagationError> {
+    if state.stm.is_some() {
+        Err(ConfigError::InvalidConfig {
+            msg: format!(
+                "{scheme} propagation does not propagate the STM, use a Runge Kutta method"
+            ),
+        })
+        .context(PropConfigSnafu)
+    } else {
+        Ok(())
+    }
+}
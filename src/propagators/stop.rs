@@ -0,0 +1,204 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::linalg::allocator::Allocator;
+use crate::linalg::DefaultAllocator;
+use crate::md::EventEvaluator;
+use crate::time::{Duration, Epoch};
+use crate::State;
+use std::fmt;
+use std::sync::Arc;
+
+/// Epoch precision to which the switch of a state predicate is located.
+pub const PREDICATE_EPOCH_PRECISION: Duration = Duration::from_milliseconds(1.0);
+
+/// A condition which stops the propagation, used with [super::PropInstance::until].
+///
+/// Conditions are combined with `Any`, and the propagation stops on whichever condition is met first, e.g.
+/// "propagate until impact OR 30 days OR fuel exhausted". At least one of the conditions must be an epoch or a duration
+/// such that the propagation is bounded. If that bound is in the past, the propagation is done backward.
+#[derive(Clone)]
+pub enum StopCondition<S: State>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    /// Stop at this epoch
+    Epoch(Epoch),
+    /// Stop after this duration from the initial state
+    Duration(Duration),
+    /// Stop at the first crossing of this event, located to its epoch precision
+    Event(Arc<dyn EventEvaluator<S>>),
+    /// Stop as soon as this predicate on the state is true
+    Predicate(Arc<dyn Fn(&S) -> bool + Send + Sync>),
+    /// Stop on whichever of these conditions is met first
+    Any(Vec<StopCondition<S>>),
+}
+
+impl<S: State> StopCondition<S>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    /// Stop at the first crossing of the provided event
+    pub fn event<E: EventEvaluator<S> + 'static>(event: E) -> Self {
+        Self::Event(Arc::new(event))
+    }
+
+    /// Stop as soon as the provided predicate is true
+    pub fn predicate<F: Fn(&S) -> bool + Send + Sync + 'static>(predicate: F) -> Self {
+        Self::Predicate(Arc::new(predicate))
+    }
+
+    /// Stop on either this condition or the other one, whichever is met first
+    pub fn or(self, other: Self) -> Self {
+        match self {
+            Self::Any(mut conditions) => {
+                conditions.push(other);
+                Self::Any(conditions)
+            }
+            _ => Self::Any(vec![self, other]),
+        }
+    }
+
+    /// Returns all of the conditions which are not combinations of other conditions
+    pub(crate) fn leaves(&self) -> Vec<&Self> {
+        match self {
+            Self::Any(conditions) => conditions.iter().flat_map(|c| c.leaves()).collect(),
+            _ => vec![self],
+        }
+    }
+
+    /// Returns the epoch at which the propagation must stop at the latest, if bounded.
+    /// The earliest bound in the direction of propagation is used, such that bounds are only mixed in a single direction.
+    pub(crate) fn bound(&self, start: Epoch) -> Option<Epoch> {
+        let mut bound: Option<Epoch> = None;
+        for leaf in self.leaves() {
+            let epoch = match leaf {
+                Self::Epoch(epoch) => *epoch,
+                Self::Duration(duration) => start + *duration,
+                _ => continue,
+            };
+            bound = match bound {
+                None => Some(epoch),
+                // Keep the bound closest to the start
+                Some(prev) if (epoch - start).abs() < (prev - start).abs() => Some(epoch),
+                Some(prev) => Some(prev),
+            };
+        }
+        bound
+    }
+}
+
+impl<S: State> fmt::Display for StopCondition<S>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Epoch(epoch) => write!(f, "epoch {epoch}"),
+            Self::Duration(duration) => write!(f, "duration {duration}"),
+            Self::Event(event) => write!(f, "event {event}"),
+            Self::Predicate(_) => write!(f, "state predicate"),
+            Self::Any(conditions) => {
+                let conditions = conditions
+                    .iter()
+                    .map(|c| format!("{c}"))
+                    .collect::<Vec<String>>();
+                write!(f, "any of [{}]", conditions.join(", "))
+            }
+        }
+    }
+}
+
+impl<S: State> fmt::Debug for StopCondition<S>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+#[cfg(test)]
+mod ut_stop {
+    use super::StopCondition;
+    use crate::cosmic::Spacecraft;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::md::prelude::Event;
+    use crate::propagators::{PropagationError, Propagator};
+    use crate::time::TimeUnits;
+    use crate::State;
+
+    #[test]
+    fn any_condition() {
+        let epoch = fixtures::epoch();
+        let orbit = fixtures::keplerian(8_000.0, 0.2, 28.5, 30.0, 45.0, 10.0);
+        let sc = Spacecraft::builder().orbit(orbit).build();
+        let almanac = fixtures::almanac();
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+
+        // The apoapsis is reached before the time bound
+        let cond = StopCondition::event(Event::apoapsis()).or(StopCondition::Duration(1.days()));
+        let (apo, trigger) = prop.with(sc, almanac.clone()).until(&cond).unwrap();
+        assert!(matches!(trigger, StopCondition::Event(_)), "{trigger}");
+        assert!((apo.orbit.ta_deg().unwrap() - 180.0).abs() < 1e-3);
+
+        // The time bound is reached before the apoapsis
+        let cond = StopCondition::Any(vec![
+            StopCondition::event(Event::apoapsis()),
+            StopCondition::Epoch(epoch + 10.minutes()),
+        ]);
+        let (state, trigger) = prop.with(sc, almanac.clone()).until(&cond).unwrap();
+        assert!(matches!(trigger, StopCondition::Epoch(_)), "{trigger}");
+        assert_eq!(state.epoch(), epoch + 10.minutes());
+
+        // The predicate switches before the apoapsis, and is located within a step
+        let cond = StopCondition::event(Event::apoapsis())
+            .or(StopCondition::predicate(|sc: &Spacecraft| {
+                sc.orbit.rmag_km() > 9_000.0
+            }))
+            .or(StopCondition::Duration(1.days()));
+        let mut instance = prop.with(sc, almanac.clone());
+        let (state, trigger) = instance.until(&cond).unwrap();
+        assert!(matches!(trigger, StopCondition::Predicate(_)), "{trigger}");
+        assert!(state.orbit.rmag_km() > 9_000.0);
+        assert!(state.orbit.rmag_km() < 9_000.01);
+        assert_eq!(instance.state, state);
+        // A predicate which already holds stops immediately
+        let (again, _) = instance.until(&cond).unwrap();
+        assert_eq!(again, state);
+
+        // Propagating backward to the previous periapsis
+        let cond = StopCondition::event(Event::periapsis()).or(StopCondition::Duration(-1.days()));
+        let (peri, trigger) = prop.with(sc, almanac.clone()).until(&cond).unwrap();
+        assert!(matches!(trigger, StopCondition::Event(_)), "{trigger}");
+        assert!(peri.epoch() < epoch);
+        assert!(peri.epoch() > epoch - 10.minutes());
+        assert!(
+            peri.orbit.ta_deg().unwrap().abs() < 1e-3
+                || peri.orbit.ta_deg().unwrap() > 360.0 - 1e-3
+        );
+
+        // An unbounded propagation is rejected
+        let cond = StopCondition::event(Event::apoapsis());
+        assert!(matches!(
+            prop.with(sc, almanac).until(&cond),
+            Err(PropagationError::PropConfigError { .. })
+        ));
+    }
+}
@@ -0,0 +1,164 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{PropagationError, Propagator};
+use crate::dynamics::Dynamics;
+use crate::linalg::allocator::Allocator;
+use crate::linalg::DefaultAllocator;
+use crate::time::{Duration, Epoch};
+use crate::State;
+use anise::almanac::Almanac;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread;
+
+/// An intermediate state of a propagation published on a [PropStream].
+#[derive(Copy, Clone, Debug)]
+pub struct PropProgress<S: State>
+where
+    DefaultAllocator: Allocator<S::Size> + Allocator<S::Size, S::Size> + Allocator<S::VecLength>,
+{
+    /// State after an integration step
+    pub state: S,
+    /// Fraction of the requested duration propagated so far, between 0 and 1
+    pub progress: f64,
+}
+
+/// A stream of the intermediate states of a propagation running on its own thread.
+///
+/// Each item is an intermediate state, the last one being the end state. If the propagation fails, the error is the
+/// last item of the stream. Dropping the stream does not stop the propagation, but its states are discarded.
+pub type PropStream<S> = UnboundedReceiver<Result<PropProgress<S>, PropagationError>>;
+
+impl<D: Dynamics + 'static> Propagator<D>
+where
+    DefaultAllocator: Allocator<<D::StateType as State>::Size>
+        + Allocator<<D::StateType as State>::Size, <D::StateType as State>::Size>
+        + Allocator<<D::StateType as State>::VecLength>,
+    D::StateType: Send + 'static,
+{
+    /// Propagates the state for the provided duration on a new thread, and returns the stream of its intermediate states,
+    /// usable from an async runtime without blocking it.
+    pub fn stream_for_duration(
+        &self,
+        state: D::StateType,
+        duration: Duration,
+        almanac: Arc<Almanac>,
+    ) -> PropStream<D::StateType> {
+        let prop = self.clone();
+        let (tx_stream, rx_stream) = unbounded();
+        let start = state.epoch();
+
+        thread::spawn(move || {
+            let (tx, rx) = channel();
+            let result = thread::scope(|scope| {
+                let propagation = scope.spawn(|| {
+                    prop.with(state, almanac)
+                        .quiet()
+                        .for_duration_with_channel(duration, tx)
+                });
+
+                for state in rx {
+                    let progress = if duration == Duration::ZERO {
+                        1.0
+                    } else {
+                        ((state.epoch() - start).to_seconds() / duration.to_seconds())
+                            .clamp(0.0, 1.0)
+                    };
+                    // The receiver may have been dropped, in which case the states are discarded
+                    let _ = tx_stream.unbounded_send(Ok(PropProgress { state, progress }));
+                }
+
+                propagation.join().expect("propagation thread panicked")
+            });
+
+            match result {
+                Ok(end_state) => {
+                    // A null duration does not publish any state, so the end state is published here
+                    if duration == Duration::ZERO {
+                        let _ = tx_stream.unbounded_send(Ok(PropProgress {
+                            state: end_state,
+                            progress: 1.0,
+                        }));
+                    }
+                }
+                Err(e) => {
+                    let _ = tx_stream.unbounded_send(Err(e));
+                }
+            }
+        });
+
+        rx_stream
+    }
+
+    /// Propagates the state until the provided epoch on a new thread, and returns the stream of its intermediate states.
+    pub fn stream_until_epoch(
+        &self,
+        state: D::StateType,
+        end_time: Epoch,
+        almanac: Arc<Almanac>,
+    ) -> PropStream<D::StateType> {
+        self.stream_for_duration(state, end_time - state.epoch(), almanac)
+    }
+}
+
+#[cfg(test)]
+mod ut_stream {
+    use crate::cosmic::{Orbit, Spacecraft};
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::propagators::Propagator;
+    use crate::time::{Epoch, TimeUnits};
+    use anise::almanac::Almanac;
+    use anise::constants::frames::EARTH_J2000;
+    use futures::executor::block_on_stream;
+    use std::sync::Arc;
+
+    #[test]
+    fn stream_states() {
+        let epoch = fixtures::epoch();
+        let orbit = fixtures::keplerian(8_000.0, 0.2, 28.5, 30.0, 45.0, 10.0);
+        let sc = Spacecraft::builder().orbit(orbit).build();
+        let almanac = fixtures::almanac();
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+
+        let expected = prop
+            .with(sc, almanac.clone())
+            .for_duration(2.hours())
+            .unwrap();
+
+        let updates = block_on_stream(prop.stream_for_duration(sc, 2.hours(), almanac.clone()))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(updates.len() > 2);
+        for pair in updates.windows(2) {
+            assert!(pair[0].progress < pair[1].progress);
+        }
+        let last = updates.last().unwrap();
+        assert_eq!(last.progress, 1.0);
+        assert_eq!(last.state, expected);
+
+        // Errors are the last item of the stream
+        let mut no_mu = sc;
+        no_mu.orbit.frame = EARTH_J2000;
+        let updates = block_on_stream(prop.stream_until_epoch(no_mu, epoch + 1.hours(), almanac))
+            .collect::<Vec<_>>();
+        assert!(updates.last().unwrap().is_err());
+    }
+}
@@ -0,0 +1,304 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{
+    check_no_stm, DynamicsSnafu, IntegrationDetails, PropConfigSnafu, PropagationError, Propagator,
+    Stepper,
+};
+use crate::cosmic::Spacecraft;
+use crate::dynamics::{Dynamics, SpacecraftDynamics};
+use crate::io::ConfigError;
+use crate::linalg::{OVector, Vector3};
+use crate::time::Duration;
+use crate::State;
+use anise::almanac::Almanac;
+use snafu::ResultExt;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// The symplectic schemes available in the [SymplecticStepper], all built as symmetric compositions of
+/// the kick-drift-kick leapfrog.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SymplecticMethod {
+    /// Second order Störmer-Verlet leapfrog
+    Leapfrog,
+    /// Fourth order composition of Yoshida (1990), three leapfrog stages per step
+    Yoshida4,
+    /// Sixth order composition of Yoshida (1990, solution A), seven leapfrog stages per step
+    Yoshida6,
+}
+
+impl SymplecticMethod {
+    /// Returns the order of this method.
+    pub const fn order(self) -> u8 {
+        match self {
+            Self::Leapfrog => 2,
+            Self::Yoshida4 => 4,
+            Self::Yoshida6 => 6,
+        }
+    }
+
+    /// Returns the fractions of the step taken by each leapfrog stage.
+    fn weights(self) -> Vec<f64> {
+        match self {
+            Self::Leapfrog => vec![1.0],
+            Self::Yoshida4 => {
+                let cbrt2 = 2.0_f64.cbrt();
+                let w1 = 1.0 / (2.0 - cbrt2);
+                let w0 = -cbrt2 / (2.0 - cbrt2);
+                vec![w1, w0, w1]
+            }
+            Self::Yoshida6 => {
+                let w1 = -1.177_679_984_178_87;
+                let w2 = 0.235_573_213_359_357;
+                let w3 = 0.784_513_610_477_560;
+                let w0 = 1.0 - 2.0 * (w1 + w2 + w3);
+                vec![w3, w2, w1, w0, w1, w2, w3]
+            }
+        }
+    }
+}
+
+impl fmt::Display for SymplecticMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl FromStr for SymplecticMethod {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "leapfrog" => Ok(Self::Leapfrog),
+            "yoshida4" => Ok(Self::Yoshida4),
+            "yoshida6" => Ok(Self::Yoshida6),
+            _ => Err(ConfigError::InvalidConfig {
+                msg: format!(
+                    "unknown symplectic method `{s}`, valid options are: Leapfrog, Yoshida4, Yoshida6"
+                ),
+            }),
+        }
+    }
+}
+
+/// A fixed step symplectic stepper for long term studies of nearly conservative dynamics, e.g. decades long
+/// disposal orbit studies. The integrator options must have a fixed step, cf. [super::IntegratorOptions::with_fixed_step].
+///
+/// The accelerations are those of the spacecraft dynamics of the propagator. The integration is symplectic, and the
+/// energy error is therefore bounded instead of drifting, as long as these accelerations only depend on the
+/// position (point masses and spherical harmonics). Velocity dependent forces such as drag are evaluated with the
+/// velocity at the time of the kick, which is fine if they are small perturbations, but the scheme is then only
+/// approximately symplectic. Time dependent forces (third bodies, rotating gravity fields) are evaluated at the
+/// epoch of the kick. The mass is constant, and guidance laws are not supported.
+#[derive(Copy, Clone, Debug)]
+pub struct SymplecticStepper {
+    /// The symplectic scheme
+    pub method: SymplecticMethod,
+}
+
+impl SymplecticStepper {
+    /// Initializes a new symplectic stepper using the provided scheme.
+    pub fn new(method: SymplecticMethod) -> Self {
+        Self { method }
+    }
+
+    /// Returns the acceleration of the provided state.
+    fn acceleration(
+        &self,
+        dynamics: &SpacecraftDynamics,
+        sc: &Spacecraft,
+        almanac: Arc<Almanac>,
+    ) -> Result<Vector3<f64>, PropagationError> {
+        let d_x = dynamics
+            .eom(0.0, &sc.to_vector(), sc, almanac)
+            .context(DynamicsSnafu)?;
+        Ok(Vector3::new(d_x[3], d_x[4], d_x[5]))
+    }
+
+    fn check_config(
+        &self,
+        prop: &Propagator<SpacecraftDynamics>,
+        state: &Spacecraft,
+    ) -> Result<(), PropagationError> {
+        if prop.dynamics.guid_law.is_some() {
+            Err(ConfigError::InvalidConfig {
+                msg: "symplectic propagation does not support guidance laws".to_string(),
+            })
+            .context(PropConfigSnafu)
+        } else if !prop.opts.fixed_step {
+            Err(ConfigError::InvalidConfig {
+                msg: "symplectic propagation requires a fixed step".to_string(),
+            })
+            .context(PropConfigSnafu)
+        } else {
+            check_no_stm(state, "symplectic")
+        }
+    }
+}
+
+impl Default for SymplecticStepper {
+    /// The sixth order Yoshida composition
+    fn default() -> Self {
+        Self::new(SymplecticMethod::Yoshida6)
+    }
+}
+
+impl Stepper<SpacecraftDynamics> for SymplecticStepper {
+    fn step(
+        &self,
+        prop: &Propagator<SpacecraftDynamics>,
+        state: &Spacecraft,
+        step_size: &mut Duration,
+        _fixed_step: bool,
+        details: &mut IntegrationDetails,
+        almanac: Arc<Almanac>,
+    ) -> Result<(Duration, OVector<f64, <Spacecraft as State>::VecLength>), PropagationError> {
+        self.check_config(prop, state)?;
+
+        let weights = self.method.weights();
+        let step = *step_size;
+        let dt_s = step.to_seconds();
+        let start_epoch = state.epoch();
+
+        let mut sc = *state;
+        let mut accel = self.acceleration(&prop.dynamics, &sc, almanac.clone())?;
+        let mut elapsed_s = 0.0;
+        for (stage, weight) in weights.iter().enumerate() {
+            let h_s = weight * dt_s;
+            // Kick, drift, kick
+            sc.orbit.velocity_km_s += accel * (0.5 * h_s);
+            sc.orbit.radius_km += sc.orbit.velocity_km_s * h_s;
+            elapsed_s += h_s;
+            sc.orbit.epoch = if stage == weights.len() - 1 {
+                start_epoch + step
+            } else {
+                start_epoch + Duration::from_seconds(elapsed_s)
+            };
+            accel = self.acceleration(&prop.dynamics, &sc, almanac.clone())?;
+            sc.orbit.velocity_km_s += accel * (0.5 * h_s);
+        }
+        details.error = 0.0;
+
+        Ok((step, sc.to_vector()))
+    }
+}
+
+#[cfg(test)]
+mod ut_symplectic {
+    use super::*;
+    use crate::dynamics::OrbitalDynamics;
+    use crate::fixtures;
+    use crate::propagators::{IntegratorMethod, IntegratorOptions};
+    use crate::time::Unit;
+
+    #[test]
+    fn from_str_ok() {
+        for (name, method) in [
+            ("leapfrog", SymplecticMethod::Leapfrog),
+            ("Yoshida4", SymplecticMethod::Yoshida4),
+            ("YOSHIDA6", SymplecticMethod::Yoshida6),
+        ] {
+            assert_eq!(SymplecticMethod::from_str(name).unwrap(), method);
+            assert_eq!(
+                SymplecticMethod::from_str(&format!("{method}")).unwrap(),
+                method
+            );
+        }
+        assert!(SymplecticMethod::from_str("verlet").is_err());
+
+        for method in [SymplecticMethod::Yoshida4, SymplecticMethod::Yoshida6] {
+            assert!((method.weights().iter().sum::<f64>() - 1.0).abs() < 1e-15);
+        }
+    }
+
+    #[test]
+    fn bounded_energy_error() {
+        let epoch = fixtures::epoch();
+        let orbit = fixtures::keplerian(7_000.0, 0.1, 51.6, 30.0, 45.0, 10.0);
+        let sc = Spacecraft::builder().orbit(orbit).build();
+        let period = orbit.period().unwrap();
+        let energy = orbit.energy_km2_s2().unwrap();
+        let almanac = fixtures::almanac();
+
+        let symplectic = |method: SymplecticMethod| {
+            Propagator::new(
+                SpacecraftDynamics::new(OrbitalDynamics::two_body()),
+                IntegratorMethod::RungeKutta4,
+                IntegratorOptions::with_fixed_step(Unit::Second * 30),
+            )
+            .with_stepper(Arc::new(SymplecticStepper::new(method)))
+        };
+
+        let mut errors = Vec::new();
+        for method in [SymplecticMethod::Yoshida4, SymplecticMethod::Yoshida6] {
+            let prop = symplectic(method);
+
+            let (final_sc, traj) = prop
+                .with(sc, almanac.clone())
+                .for_duration_with_traj(period * 100)
+                .unwrap();
+            assert_eq!(final_sc.epoch(), epoch + period * 100);
+
+            // The energy error does not drift.
+            let max_err_first = traj
+                .states
+                .iter()
+                .filter(|state| state.epoch() <= epoch + period * 10)
+                .map(|state| (state.orbit.energy_km2_s2().unwrap() - energy).abs())
+                .fold(0.0, f64::max);
+            let max_err_all = traj
+                .states
+                .iter()
+                .map(|state| (state.orbit.energy_km2_s2().unwrap() - energy).abs())
+                .fold(0.0, f64::max);
+            assert!(max_err_all < 2.0 * max_err_first + 1e-12);
+            assert!(max_err_all / energy.abs() < 1e-6);
+
+            errors.push(
+                orbit
+                    .rss_radius_km(
+                        &prop
+                            .with(sc, almanac.clone())
+                            .for_duration(period)
+                            .unwrap()
+                            .orbit,
+                    )
+                    .unwrap(),
+            );
+        }
+
+        // The higher order composition is more accurate.
+        assert!(errors[1] < errors[0]);
+
+        // Back propagation returns to the initial state
+        let prop = symplectic(SymplecticMethod::Yoshida6);
+        let final_sc = prop.with(sc, almanac.clone()).for_duration(period).unwrap();
+        let initial_sc = prop
+            .with(final_sc, almanac.clone())
+            .until_epoch(epoch)
+            .unwrap();
+        assert!(orbit.rss_radius_km(&initial_sc.orbit).unwrap() < 1e-8);
+
+        // The step must be fixed
+        let mut adaptive = prop;
+        adaptive.opts = IntegratorOptions::default();
+        assert!(adaptive.with(sc, almanac).for_duration(period).is_err());
+    }
+}
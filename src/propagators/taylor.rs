@@ -0,0 +1,438 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{
+    check_no_stm, DynamicsSnafu, IntegrationDetails, PropConfigSnafu, PropagationError, Propagator,
+    Stepper,
+};
+use crate::cosmic::{AstroPhysicsSnafu, Spacecraft};
+use crate::dynamics::{DynamicsAstroSnafu, SpacecraftDynamics};
+use crate::io::gravity::HarmonicsMem;
+use crate::io::ConfigError;
+use crate::linalg::{OVector, Vector3};
+use crate::time::{Duration, Unit};
+use crate::State;
+use anise::almanac::Almanac;
+use snafu::ResultExt;
+use std::sync::Arc;
+
+/// A Taylor series stepper of the point mass and J2 problem, with the Taylor coefficients computed by automatic
+/// differentiation (i.e. the recurrence relations of the arithmetic operations on truncated power series).
+///
+/// The gravitational parameter and the J2 reference radius are those of the frame of the propagated state, and the
+/// J2 term assumes that the Z axis of that frame is aligned with the pole of the central body. The step size is
+/// chosen from the last two Taylor coefficients following Jorba & Zou (2005), which allows for very large steps with
+/// a high order: a few steps per revolution are typical at an accuracy close to machine precision. The steps are
+/// bounded by the maximum step of the integrator options, and the series also provide the dense output of each step.
+///
+/// The force models of the spacecraft dynamics are not evaluated, the mass is constant, and guidance laws are not supported.
+#[derive(Copy, Clone, Debug)]
+pub struct TaylorStepper {
+    /// Unnormalized J2 coefficient of the central body, zero for the two body problem
+    pub j2: f64,
+    /// Order of the Taylor series
+    pub order: usize,
+    /// Relative tolerance on the last terms of the series
+    pub tolerance: f64,
+}
+
+impl TaylorStepper {
+    /// Initializes a new Taylor stepper of order 20 with a tolerance of 1e-16.
+    pub fn new(j2: f64) -> Self {
+        Self {
+            j2,
+            order: 20,
+            tolerance: 1e-16,
+        }
+    }
+
+    /// Initializes a new Taylor stepper of the two body problem.
+    pub fn two_body() -> Self {
+        Self::new(0.0)
+    }
+
+    /// Initializes a new Taylor stepper using the J2 coefficient of the provided (normalized) gravity field.
+    pub fn from_harmonics(harmonics: &HarmonicsMem) -> Self {
+        Self::new(-harmonics.cs_nm(2, 0).0 * 5.0_f64.sqrt())
+    }
+
+    /// Sets the order of the Taylor series.
+    pub fn with_order(mut self, order: usize) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Sets the relative tolerance.
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Computes the Taylor series about the provided state.
+    fn series(&self, state: &Spacecraft) -> Result<TaylorSeries, PropagationError> {
+        let frame = state.orbit.frame;
+        let mu_km3_s2 = frame
+            .mu_km3_s2()
+            .context(AstroPhysicsSnafu)
+            .context(DynamicsAstroSnafu)
+            .context(DynamicsSnafu)?;
+        let j2_factor = if self.j2 == 0.0 {
+            0.0
+        } else {
+            let radius_km = frame
+                .mean_equatorial_radius_km()
+                .context(AstroPhysicsSnafu)
+                .context(DynamicsAstroSnafu)
+                .context(DynamicsSnafu)?;
+            1.5 * self.j2 * mu_km3_s2 * radius_km.powi(2)
+        };
+
+        Ok(TaylorSeries::new(
+            state.orbit.radius_km,
+            state.orbit.velocity_km_s,
+            mu_km3_s2,
+            j2_factor,
+            self.order,
+        ))
+    }
+
+    fn check_config(
+        &self,
+        dynamics: &SpacecraftDynamics,
+        state: &Spacecraft,
+    ) -> Result<(), PropagationError> {
+        if self.order < 2 {
+            Err(ConfigError::InvalidConfig {
+                msg: format!(
+                    "Taylor propagation requires an order of at least 2 but got {}",
+                    self.order
+                ),
+            })
+            .context(PropConfigSnafu)
+        } else if self.tolerance <= 0.0 {
+            Err(ConfigError::InvalidConfig {
+                msg: format!(
+                    "Taylor propagation requires a strictly positive tolerance but got {}",
+                    self.tolerance
+                ),
+            })
+            .context(PropConfigSnafu)
+        } else if dynamics.guid_law.is_some() {
+            Err(ConfigError::InvalidConfig {
+                msg: "Taylor propagation does not support guidance laws".to_string(),
+            })
+            .context(PropConfigSnafu)
+        } else {
+            check_no_stm(state, "Taylor")
+        }
+    }
+}
+
+impl Stepper<SpacecraftDynamics> for TaylorStepper {
+    fn step(
+        &self,
+        prop: &Propagator<SpacecraftDynamics>,
+        state: &Spacecraft,
+        step_size: &mut Duration,
+        fixed_step: bool,
+        details: &mut IntegrationDetails,
+        _almanac: Arc<Almanac>,
+    ) -> Result<(Duration, OVector<f64, <Spacecraft as State>::VecLength>), PropagationError> {
+        self.check_config(&prop.dynamics, state)?;
+
+        let series = self.series(state)?;
+        let series_step_s = series
+            .step_size(self.tolerance)
+            .min(prop.opts.max_step.abs().to_seconds());
+
+        let step = if fixed_step || series_step_s >= step_size.abs().to_seconds() {
+            *step_size
+        } else {
+            Unit::Second * series_step_s.copysign(step_size.to_seconds())
+        };
+
+        if !fixed_step {
+            *step_size = Unit::Second * series_step_s.copysign(step_size.to_seconds());
+        }
+        details.error = 0.0;
+
+        let (radius_km, velocity_km_s) = series.evaluate(step.to_seconds());
+        let mut state_vec = state.to_vector();
+        for i in 0..3 {
+            state_vec[i] = radius_km[i];
+            state_vec[i + 3] = velocity_km_s[i];
+        }
+
+        Ok((step, state_vec))
+    }
+
+    fn has_dense_output(&self) -> bool {
+        true
+    }
+
+    fn dense_coeffs(
+        &self,
+        _prop: &Propagator<SpacecraftDynamics>,
+        state: &Spacecraft,
+        step: Duration,
+        _almanac: Arc<Almanac>,
+    ) -> Result<Vec<OVector<f64, <Spacecraft as State>::VecLength>>, PropagationError> {
+        let series = self.series(state)?;
+        let step_s = step.to_seconds();
+
+        Ok((1..=self.order)
+            .map(|k| {
+                let scale = step_s.powi(k as i32);
+                let mut coeff = OVector::<f64, <Spacecraft as State>::VecLength>::zeros();
+                for i in 0..3 {
+                    coeff[i] = series.radius[k][i] * scale;
+                    coeff[i + 3] = series.velocity[k][i] * scale;
+                }
+                coeff
+            })
+            .collect())
+    }
+}
+
+/// The normalized Taylor coefficients of the position and velocity about a state, i.e. the k-th
+/// coefficient is the k-th derivative divided by k!.
+struct TaylorSeries {
+    radius: Vec<Vector3<f64>>,
+    velocity: Vec<Vector3<f64>>,
+}
+
+impl TaylorSeries {
+    /// Computes the Taylor coefficients of the point mass and J2 problem up to the provided order, where `j2_factor`
+    /// is 3/2 J2 μ R².
+    fn new(
+        radius_km: Vector3<f64>,
+        velocity_km_s: Vector3<f64>,
+        mu_km3_s2: f64,
+        j2_factor: f64,
+        order: usize,
+    ) -> Self {
+        let mut radius = vec![Vector3::zeros(); order + 1];
+        let mut velocity = vec![Vector3::zeros(); order + 1];
+        radius[0] = radius_km;
+        velocity[0] = velocity_km_s;
+
+        // Auxiliary series: s = |r|², w = s^(-3/2), q = s^(-5/2), p = s^(-7/2), z² and t = q - 5 z² p
+        let mut s = vec![0.0; order];
+        let mut w = vec![0.0; order];
+        let mut q = vec![0.0; order];
+        let mut p = vec![0.0; order];
+        let mut z2 = vec![0.0; order];
+        let mut t = vec![0.0; order];
+
+        let mut x = Vec::with_capacity(order);
+        let mut y = Vec::with_capacity(order);
+        let mut z = Vec::with_capacity(order);
+
+        for k in 0..order {
+            x.push(radius[k].x);
+            y.push(radius[k].y);
+            z.push(radius[k].z);
+
+            s[k] = cauchy(&x, &x, k) + cauchy(&y, &y, k) + cauchy(&z, &z, k);
+            w[k] = power(&s, &w, -1.5, k);
+
+            let mut accel =
+                -mu_km3_s2 * Vector3::new(cauchy(&x, &w, k), cauchy(&y, &w, k), cauchy(&z, &w, k));
+
+            if j2_factor != 0.0 {
+                q[k] = power(&s, &q, -2.5, k);
+                p[k] = power(&s, &p, -3.5, k);
+                z2[k] = cauchy(&z, &z, k);
+                t[k] = q[k] - 5.0 * cauchy(&z2, &p, k);
+
+                accel -= j2_factor
+                    * Vector3::new(
+                        cauchy(&x, &t, k),
+                        cauchy(&y, &t, k),
+                        cauchy(&z, &t, k) + 2.0 * cauchy(&z, &q, k),
+                    );
+            }
+
+            radius[k + 1] = velocity[k] / (k + 1) as f64;
+            velocity[k + 1] = accel / (k + 1) as f64;
+        }
+
+        Self { radius, velocity }
+    }
+
+    /// Returns the step size in seconds such that the last two terms of the series are below the relative tolerance
+    /// of the position and of the velocity, as per Jorba & Zou (2005).
+    fn step_size(&self, tolerance: f64) -> f64 {
+        let order = self.radius.len() - 1;
+        let mut step_s = f64::INFINITY;
+        for coeffs in [&self.radius, &self.velocity] {
+            let scale = tolerance * coeffs[0].amax().max(1.0);
+            for k in [order - 1, order] {
+                let norm = coeffs[k].amax();
+                if norm > 0.0 {
+                    step_s = step_s.min((scale / norm).powf(1.0 / k as f64));
+                }
+            }
+        }
+        // Safety factor of Jorba & Zou
+        step_s * (-0.7 / (order - 1) as f64).exp()
+    }
+
+    /// Evaluates the position and velocity at the provided time in seconds from the epoch of the series.
+    fn evaluate(&self, dt_s: f64) -> (Vector3<f64>, Vector3<f64>) {
+        let horner = |coeffs: &[Vector3<f64>]| {
+            coeffs
+                .iter()
+                .rev()
+                .fold(Vector3::zeros(), |acc, coeff| acc * dt_s + coeff)
+        };
+        (horner(&self.radius), horner(&self.velocity))
+    }
+}
+
+/// Returns the k-th coefficient of the product of the series a and b.
+fn cauchy(a: &[f64], b: &[f64], k: usize) -> f64 {
+    (0..=k).map(|j| a[j] * b[k - j]).sum()
+}
+
+/// Returns the k-th coefficient of u = s^alpha, provided the first k coefficients of u.
+fn power(s: &[f64], u: &[f64], alpha: f64, k: usize) -> f64 {
+    if k == 0 {
+        s[0].powf(alpha)
+    } else {
+        (0..k)
+            .map(|j| (alpha * (k - j) as f64 - j as f64) * s[k - j] * u[j])
+            .sum::<f64>()
+            / (k as f64 * s[0])
+    }
+}
+
+#[cfg(test)]
+mod ut_taylor {
+    use super::*;
+    use crate::cosmic::{Frame, Orbit};
+    use crate::dynamics::{Harmonics, OrbitalDynamics};
+    use crate::fixtures;
+    use crate::md::prelude::Event;
+    use crate::propagators::{ErrorControl, IntegratorMethod, IntegratorOptions};
+    use anise::structure::planetocentric::ellipsoid::Ellipsoid;
+
+    fn earth() -> Frame {
+        let mut frame = fixtures::eme2k();
+        frame.shape = Some(Ellipsoid::from_sphere(6_378.136_3));
+        frame
+    }
+
+    fn taylor(stepper: TaylorStepper) -> Propagator<SpacecraftDynamics> {
+        Propagator::new(
+            SpacecraftDynamics::new(OrbitalDynamics::two_body()),
+            IntegratorMethod::RungeKutta89,
+            IntegratorOptions::with_max_step(Unit::Day * 1),
+        )
+        .with_stepper(Arc::new(stepper))
+    }
+
+    #[test]
+    fn two_body_period() {
+        let epoch = fixtures::epoch();
+        let orbit =
+            Orbit::try_keplerian(26_600.0, 0.7, 63.4, 10.0, 270.0, 0.0, epoch, earth()).unwrap();
+        let sc = Spacecraft::builder().orbit(orbit).build();
+        let period = orbit.period().unwrap();
+        let almanac = fixtures::almanac();
+
+        let prop = taylor(TaylorStepper::two_body());
+        let (final_sc, traj) = prop
+            .with(sc, almanac.clone())
+            .for_duration_with_traj(period)
+            .unwrap();
+        assert_eq!(final_sc.epoch(), epoch + period);
+        assert!(orbit.rss_radius_km(&final_sc.orbit).unwrap() < 1e-6);
+        assert!(orbit.rss_velocity_km_s(&final_sc.orbit).unwrap() < 1e-9);
+        // A few tens of steps per revolution
+        assert!(traj.states.len() < 100, "{} steps", traj.states.len());
+
+        // And back
+        let initial_sc = prop
+            .with(final_sc, almanac.clone())
+            .until_epoch(epoch)
+            .unwrap();
+        assert!(orbit.rss_radius_km(&initial_sc.orbit).unwrap() < 1e-6);
+
+        // Events are supported like with any other integrator
+        let (apoapsis, _) = prop
+            .with(sc, almanac.clone())
+            .until_event(period, &Event::apoapsis())
+            .unwrap();
+        assert!((apoapsis.orbit.ta_deg().unwrap() - 180.0).abs() < 1e-3);
+
+        // The STM is not propagated
+        assert!(prop
+            .with(sc.with_stm(), almanac)
+            .for_duration(period)
+            .is_err());
+    }
+
+    #[test]
+    fn j2_vs_numerical() {
+        let epoch = fixtures::epoch();
+        let frame = earth();
+        let orbit =
+            Orbit::try_keplerian(7_000.0, 0.01, 51.6, 30.0, 45.0, 10.0, epoch, frame).unwrap();
+        let sc = Spacecraft::builder().orbit(orbit).build();
+        let almanac = fixtures::almanac();
+
+        let (final_sc, traj) = taylor(TaylorStepper::from_harmonics(&HarmonicsMem::j2_jgm3()))
+            .with(sc, almanac.clone())
+            .for_duration_with_dense_traj(Unit::Day * 1)
+            .unwrap();
+        assert_eq!(traj.dense.len(), traj.states.len() - 1);
+
+        let dynamics = SpacecraftDynamics::new(OrbitalDynamics::new(vec![Harmonics::from_stor(
+            frame,
+            HarmonicsMem::j2_jgm3(),
+        )]));
+        let (num_sc, num_traj) = Propagator::new(
+            dynamics,
+            IntegratorMethod::RungeKutta89,
+            IntegratorOptions::with_adaptive_step(
+                Unit::Second * 0.1,
+                Unit::Minute * 10,
+                1e-14,
+                ErrorControl::RSSCartesianStep,
+            ),
+        )
+        .with(sc, almanac)
+        .for_duration_with_traj(Unit::Day * 1)
+        .unwrap();
+
+        assert!(
+            num_sc.orbit.rss_radius_km(&final_sc.orbit).unwrap() < 1e-4,
+            "{}",
+            num_sc.orbit.rss_radius_km(&final_sc.orbit).unwrap()
+        );
+
+        // The dense output matches the numerical trajectory.
+        for hour in 0..24 {
+            let epoch = epoch + Unit::Hour * hour + Unit::Minute * 7;
+            let state = traj.at(epoch).unwrap();
+            let num_state = num_traj.at(epoch).unwrap();
+            assert!(num_state.orbit.rss_radius_km(&state.orbit).unwrap() < 1e-4);
+        }
+    }
+}
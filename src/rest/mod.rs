@@ -0,0 +1,686 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anise::prelude::Almanac;
+use axum::extract::{Query, State as AxumState};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::dynamics::{OrbitalDynamics, SolarPressure, SpacecraftDynamics};
+use crate::io::ExportCfg;
+use crate::linalg::{SMatrix, SVector};
+use crate::md::objective::Objective;
+use crate::md::opti::targeter::Targeter;
+use crate::md::StateParameter;
+use crate::od::prelude::{
+    GroundStation, KfEstimate, Measurement, ResidRejectCrit, TrackingArcSim, TrackingDataArc,
+    TrkConfig, KF,
+};
+use crate::od::SpacecraftODProcess;
+use crate::propagators::Propagator;
+use crate::time::{Duration, Epoch};
+use crate::tools::scenario::{Scenario, ScenarioError};
+use crate::{Spacecraft, State};
+
+/// Media type of the Apache Parquet responses.
+pub const PARQUET_MEDIA_TYPE: &str = "application/vnd.apache.parquet";
+
+/// Builds the router of the REST API, computing all requests with the provided almanac.
+///
+/// The routes are:
+/// + `GET /v1/version`
+/// + `POST /v1/propagate`
+/// + `POST /v1/target`
+/// + `POST /v1/simulate_tracking`
+/// + `POST /v1/determine_orbit`
+/// + `POST /v1/scenario`
+///
+/// The POST routes accept `?format=parquet` to return their tabular output as a Parquet file instead of JSON.
+pub fn router(almanac: Arc<Almanac>) -> Router {
+    Router::new()
+        .route("/v1/version", get(version))
+        .route("/v1/propagate", post(propagate))
+        .route("/v1/target", post(target))
+        .route("/v1/simulate_tracking", post(simulate_tracking))
+        .route("/v1/determine_orbit", post(determine_orbit))
+        .route("/v1/scenario", post(scenario))
+        .with_state(almanac)
+}
+
+/// Serves the REST API on the provided address until the server fails.
+pub async fn serve(addr: SocketAddr, almanac: Arc<Almanac>) -> std::io::Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("Nyx REST API listening on {}", listener.local_addr()?);
+    axum::serve(listener, router(almanac)).await
+}
+
+/// Error returned by the API, serialized as `{"error": "..."}`.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub msg: String,
+}
+
+impl ApiError {
+    fn bad_request<E: ToString>(e: E) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            msg: e.to_string(),
+        }
+    }
+
+    fn internal<E: ToString>(e: E) -> Self {
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            msg: e.to_string(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        #[derive(Serialize)]
+        struct Body {
+            error: String,
+        }
+
+        (self.status, Json(Body { error: self.msg })).into_response()
+    }
+}
+
+/// Format of the response of a computation.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Json,
+    Parquet,
+}
+
+#[derive(Copy, Clone, Debug, Default, Deserialize)]
+pub struct FormatQuery {
+    #[serde(default)]
+    pub format: OutputFormat,
+}
+
+/// Force models applied to the spacecraft, two body dynamics by default.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DynamicsRequest {
+    /// NAIF IDs of the third bodies, e.g. 10 for the Sun and 301 for the Moon
+    #[serde(default)]
+    pub point_masses: Vec<i32>,
+    /// Solar radiation pressure, eclipsed by the center of the state
+    #[serde(default)]
+    pub solar_radiation_pressure: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PropagateRequest {
+    pub initial_state: Spacecraft,
+    #[serde(default)]
+    pub dynamics: DynamicsRequest,
+    pub end_epoch: Epoch,
+    /// Step of the returned trajectory, none is returned if unset
+    pub output_step: Option<Duration>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PropagateResponse {
+    pub final_state: Spacecraft,
+    pub trajectory: Vec<Spacecraft>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ObjectiveRequest {
+    /// State parameter, e.g. `sma` or `inc`
+    pub parameter: String,
+    pub desired_value: f64,
+    pub tolerance: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TargetRequest {
+    /// State at the epoch of the maneuver
+    pub initial_state: Spacecraft,
+    #[serde(default)]
+    pub dynamics: DynamicsRequest,
+    pub achievement_epoch: Epoch,
+    pub objectives: Vec<ObjectiveRequest>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TargetResponse {
+    /// Impulsive maneuver in the inertial frame
+    pub dv_km_s: [f64; 3],
+    pub corrected_state: Spacecraft,
+    pub achieved_state: Spacecraft,
+    pub achieved_errors: Vec<f64>,
+    pub iterations: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SimulateTrackingRequest {
+    pub initial_state: Spacecraft,
+    #[serde(default)]
+    pub dynamics: DynamicsRequest,
+    pub end_epoch: Epoch,
+    pub ground_stations: BTreeMap<String, GroundStation>,
+    /// Tracking configurations by ground station name
+    pub tracking_configs: BTreeMap<String, TrkConfig>,
+    /// Seed of the measurement noises, random if unset
+    pub seed: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SimulateTrackingResponse {
+    pub measurements: Vec<Measurement>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DetermineOrbitRequest {
+    pub initial_estimate: Spacecraft,
+    /// Uncertainty of the initial estimate in the inertial frame
+    pub position_sigma_km: f64,
+    pub velocity_sigma_km_s: f64,
+    #[serde(default)]
+    pub dynamics: DynamicsRequest,
+    pub ground_stations: BTreeMap<String, GroundStation>,
+    pub measurements: Vec<Measurement>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EstimateResponse {
+    pub state: Spacecraft,
+    /// Covariance of the orbit, row major 6x6 in km and km/s
+    pub covariance: Vec<f64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DetermineOrbitResponse {
+    pub estimates: Vec<EstimateResponse>,
+    /// Ratio of each residual, absent for the predicted estimates
+    pub residual_ratios: Vec<f64>,
+    pub rejected_measurements: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScenarioResponse {
+    pub final_state: Spacecraft,
+}
+
+async fn version() -> Json<BTreeMap<&'static str, &'static str>> {
+    Json(BTreeMap::from([("version", env!("CARGO_PKG_VERSION"))]))
+}
+
+async fn propagate(
+    AxumState(almanac): AxumState<Arc<Almanac>>,
+    Query(query): Query<FormatQuery>,
+    Json(req): Json<PropagateRequest>,
+) -> Result<Response, ApiError> {
+    blocking(move || {
+        let sc = with_frame_data(req.initial_state, &almanac)?;
+        let dynamics = build_dynamics(&req.dynamics, &sc, almanac.clone())?;
+
+        let prop = Propagator::default(dynamics);
+        let (final_state, traj) = prop
+            .with(sc, almanac.clone())
+            .until_epoch_with_traj(req.end_epoch)
+            .map_err(ApiError::internal)?;
+
+        match query.format {
+            OutputFormat::Json => Ok(Json(PropagateResponse {
+                final_state,
+                trajectory: match req.output_step {
+                    Some(step) => traj.every(step).collect(),
+                    None => Vec::new(),
+                },
+            })
+            .into_response()),
+            OutputFormat::Parquet => parquet_response(|path| match req.output_step {
+                Some(step) => {
+                    traj.to_parquet_with_cfg(path, ExportCfg::builder().step(step).build(), almanac)
+                }
+                None => traj.to_parquet_simple(path, almanac),
+            }),
+        }
+    })
+    .await
+}
+
+async fn target(
+    AxumState(almanac): AxumState<Arc<Almanac>>,
+    Json(req): Json<TargetRequest>,
+) -> Result<Response, ApiError> {
+    blocking(move || {
+        let sc = with_frame_data(req.initial_state, &almanac)?;
+        let dynamics = build_dynamics(&req.dynamics, &sc, almanac.clone())?;
+        let prop = Propagator::default(dynamics);
+
+        let objectives = req
+            .objectives
+            .iter()
+            .map(|obj| {
+                let param =
+                    StateParameter::from_str(&obj.parameter).map_err(ApiError::bad_request)?;
+                Ok(Objective::within_tolerance(
+                    param,
+                    obj.desired_value,
+                    obj.tolerance,
+                ))
+            })
+            .collect::<Result<Vec<_>, ApiError>>()?;
+
+        // The targeter is generic over the number of objectives, so dispatch on the supported counts.
+        macro_rules! solve {
+            ($objs:expr) => {{
+                let sol = Targeter::delta_v(&prop, $objs)
+                    .try_achieve_from(sc, sc.epoch(), req.achievement_epoch, almanac)
+                    .map_err(ApiError::internal)?;
+                TargetResponse {
+                    dv_km_s: sol.correction.into(),
+                    corrected_state: sol.corrected_state,
+                    achieved_state: sol.achieved_state,
+                    achieved_errors: sol.achieved_errors.iter().copied().collect(),
+                    iterations: sol.iterations,
+                }
+            }};
+        }
+
+        let resp = match objectives.as_slice() {
+            [a] => solve!([*a]),
+            [a, b] => solve!([*a, *b]),
+            [a, b, c] => solve!([*a, *b, *c]),
+            _ => {
+                return Err(ApiError::bad_request(format!(
+                    "between one and three objectives are supported, got {}",
+                    objectives.len()
+                )))
+            }
+        };
+
+        Ok(Json(resp).into_response())
+    })
+    .await
+}
+
+async fn simulate_tracking(
+    AxumState(almanac): AxumState<Arc<Almanac>>,
+    Query(query): Query<FormatQuery>,
+    Json(req): Json<SimulateTrackingRequest>,
+) -> Result<Response, ApiError> {
+    blocking(move || {
+        let sc = with_frame_data(req.initial_state, &almanac)?;
+        let dynamics = build_dynamics(&req.dynamics, &sc, almanac.clone())?;
+
+        let prop = Propagator::default(dynamics);
+        let (_, traj) = prop
+            .with(sc, almanac.clone())
+            .until_epoch_with_traj(req.end_epoch)
+            .map_err(ApiError::internal)?;
+
+        let mut arc_sim = match req.seed {
+            Some(seed) => TrackingArcSim::<Spacecraft, GroundStation>::with_seed(
+                req.ground_stations,
+                traj,
+                req.tracking_configs,
+                seed,
+            ),
+            None => TrackingArcSim::<Spacecraft, GroundStation>::new(
+                req.ground_stations,
+                traj,
+                req.tracking_configs,
+            ),
+        }
+        .map_err(ApiError::bad_request)?;
+
+        arc_sim
+            .build_schedule(almanac.clone())
+            .map_err(ApiError::internal)?;
+        let arc = arc_sim
+            .generate_measurements(almanac)
+            .map_err(ApiError::internal)?;
+
+        match query.format {
+            OutputFormat::Json => Ok(Json(SimulateTrackingResponse {
+                measurements: arc.measurements.into_values().collect(),
+            })
+            .into_response()),
+            OutputFormat::Parquet => parquet_response(|path| arc.to_parquet_simple(path)),
+        }
+    })
+    .await
+}
+
+async fn determine_orbit(
+    AxumState(almanac): AxumState<Arc<Almanac>>,
+    Query(query): Query<FormatQuery>,
+    Json(req): Json<DetermineOrbitRequest>,
+) -> Result<Response, ApiError> {
+    blocking(move || {
+        let sc = with_frame_data(req.initial_estimate, &almanac)?;
+        let dynamics = build_dynamics(&req.dynamics, &sc, almanac.clone())?;
+
+        let mut arc = TrackingDataArc::default();
+        for msr in req.measurements {
+            arc.measurements.insert(msr.epoch, msr);
+        }
+
+        let pos_var = req.position_sigma_km.powi(2);
+        let vel_var = req.velocity_sigma_km_s.powi(2);
+        let covar = SMatrix::<f64, 9, 9>::from_diagonal(&SVector::<f64, 9>::from_iterator([
+            pos_var, pos_var, pos_var, vel_var, vel_var, vel_var, 0.0, 0.0, 0.0,
+        ]));
+
+        let prop = Propagator::default(dynamics);
+        let mut odp = SpacecraftODProcess::ckf(
+            prop.with(sc.with_stm(), almanac.clone()),
+            KF::no_snc(KfEstimate::from_covar(sc.with_stm(), covar)),
+            req.ground_stations,
+            Some(ResidRejectCrit::default()),
+            almanac,
+        );
+
+        odp.process_arc(&arc).map_err(ApiError::bad_request)?;
+
+        match query.format {
+            OutputFormat::Json => {
+                let residuals = odp.residuals.iter().flatten();
+
+                Ok(Json(DetermineOrbitResponse {
+                    estimates: odp
+                        .estimates
+                        .iter()
+                        .map(|est| EstimateResponse {
+                            state: est.nominal_state,
+                            covariance: est
+                                .covar
+                                .fixed_view::<6, 6>(0, 0)
+                                .transpose()
+                                .iter()
+                                .copied()
+                                .collect(),
+                        })
+                        .collect(),
+                    residual_ratios: residuals.clone().map(|resid| resid.ratio).collect(),
+                    rejected_measurements: residuals.filter(|resid| resid.rejected).count(),
+                })
+                .into_response())
+            }
+            OutputFormat::Parquet => {
+                parquet_response(|path| Ok(odp.to_parquet(&arc, path, ExportCfg::default())?))
+            }
+        }
+    })
+    .await
+}
+
+/// Runs a mission scenario with the almanac of the server, ignoring the almanac files and output directory of the request.
+///
+/// The Parquet format returns the last product of the scenario: the orbit determination results if any, else the tracking
+/// arc if any, else the trajectory.
+async fn scenario(
+    AxumState(almanac): AxumState<Arc<Almanac>>,
+    Query(query): Query<FormatQuery>,
+    Json(mut req): Json<Scenario>,
+) -> Result<Response, ApiError> {
+    blocking(move || {
+        static RUN_COUNT: AtomicU64 = AtomicU64::new(0);
+
+        req.output_dir = std::env::temp_dir().join(format!(
+            "nyx-rest-scenario-{}-{}",
+            std::process::id(),
+            RUN_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let output_dir = req.output_dir.clone();
+
+        let resp = req
+            .run(almanac)
+            .map_err(|e| match e {
+                ScenarioError::InvalidScenario { .. } => ApiError::bad_request(e),
+                _ => ApiError::internal(e),
+            })
+            .and_then(|products| match query.format {
+                OutputFormat::Json => Ok(Json(ScenarioResponse {
+                    final_state: products.final_state,
+                })
+                .into_response()),
+                OutputFormat::Parquet => {
+                    let path = products
+                        .od_results
+                        .or(products.tracking_arc)
+                        .unwrap_or(products.trajectory);
+                    let bytes = std::fs::read(path).map_err(ApiError::internal)?;
+                    Ok(([(header::CONTENT_TYPE, PARQUET_MEDIA_TYPE)], bytes).into_response())
+                }
+            });
+
+        let _ = std::fs::remove_dir_all(&output_dir);
+        resp
+    })
+    .await
+}
+
+/// Runs the computation on the blocking thread pool so that the server remains responsive.
+async fn blocking<F>(f: F) -> Result<Response, ApiError>
+where
+    F: FnOnce() -> Result<Response, ApiError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(ApiError::internal)?
+}
+
+/// Exports to a temporary Parquet file and returns its content.
+fn parquet_response<F>(export: F) -> Result<Response, ApiError>
+where
+    F: FnOnce(&Path) -> Result<PathBuf, Box<dyn Error>>,
+{
+    static EXPORT_COUNT: AtomicU64 = AtomicU64::new(0);
+
+    let path = std::env::temp_dir().join(format!(
+        "nyx-rest-{}-{}.parquet",
+        std::process::id(),
+        EXPORT_COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let path = export(&path).map_err(ApiError::internal)?;
+    let bytes = std::fs::read(&path).map_err(ApiError::internal);
+    let _ = std::fs::remove_file(&path);
+
+    Ok(([(header::CONTENT_TYPE, PARQUET_MEDIA_TYPE)], bytes?).into_response())
+}
+
+/// Fills the gravitational parameter and shape of the frame of the spacecraft from the almanac if they are missing.
+fn with_frame_data(mut sc: Spacecraft, almanac: &Almanac) -> Result<Spacecraft, ApiError> {
+    if sc.orbit.frame.mu_km3_s2.is_none() {
+        sc.orbit.frame = almanac
+            .frame_from_uid(sc.orbit.frame)
+            .map_err(ApiError::bad_request)?;
+    }
+    Ok(sc)
+}
+
+fn build_dynamics(
+    dynamics: &DynamicsRequest,
+    sc: &Spacecraft,
+    almanac: Arc<Almanac>,
+) -> Result<SpacecraftDynamics, ApiError> {
+    let orbital_dyn = if dynamics.point_masses.is_empty() {
+        OrbitalDynamics::two_body()
+    } else {
+        OrbitalDynamics::point_masses(dynamics.point_masses.clone())
+    };
+
+    if dynamics.solar_radiation_pressure {
+        let srp =
+            SolarPressure::new(vec![sc.orbit.frame], almanac).map_err(ApiError::bad_request)?;
+        Ok(SpacecraftDynamics::from_model(orbital_dyn, srp))
+    } else {
+        Ok(SpacecraftDynamics::new(orbital_dyn))
+    }
+}
+
+#[cfg(test)]
+mod ut_rest {
+    use super::*;
+    use crate::fixtures;
+    use crate::time::TimeUnits;
+    use crate::Orbit;
+    use anise::constants::frames::EARTH_J2000;
+    use anise::structure::planetocentric::ellipsoid::Ellipsoid;
+
+    fn leo() -> Spacecraft {
+        let orbit = fixtures::keplerian(7_000.0, 1e-3, 30.0, 0.0, 0.0, 0.0);
+        Spacecraft::builder().orbit(orbit).build()
+    }
+
+    #[tokio::test]
+    async fn propagate_json() {
+        let almanac = fixtures::almanac();
+        let sc = leo();
+        let period = sc.orbit.period().unwrap();
+
+        // Requests are deserialized from JSON, so make sure that the state round trips.
+        let req: PropagateRequest = serde_json::from_value(serde_json::json!({
+            "initial_state": sc,
+            "end_epoch": sc.epoch() + period,
+            "output_step": 1.minutes(),
+        }))
+        .unwrap();
+
+        let resp = propagate(AxumState(almanac), Query(FormatQuery::default()), Json(req))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let resp: PropagateResponse = serde_json::from_slice(&body).unwrap();
+
+        // After one period, the orbit is back where it started.
+        assert!((resp.final_state.orbit.radius_km - sc.orbit.radius_km).norm() < 1e-3);
+        assert!(resp.trajectory.len() as f64 >= (period.to_seconds() / 60.0).floor());
+    }
+
+    #[tokio::test]
+    async fn target_sma() {
+        let almanac = fixtures::almanac();
+        let sc = leo();
+
+        let req = TargetRequest {
+            initial_state: sc,
+            dynamics: DynamicsRequest::default(),
+            achievement_epoch: sc.epoch() + 30.minutes(),
+            objectives: vec![ObjectiveRequest {
+                parameter: "sma".to_string(),
+                desired_value: 7_100.0,
+                tolerance: 0.1,
+            }],
+        };
+
+        let resp = target(AxumState(almanac), Json(req)).await.unwrap();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let resp: TargetResponse = serde_json::from_slice(&body).unwrap();
+
+        assert!(resp.achieved_errors[0].abs() < 0.1);
+        assert!((resp.achieved_state.orbit.sma_km().unwrap() - 7_100.0).abs() < 0.1);
+    }
+
+    #[tokio::test]
+    async fn run_scenario() {
+        let almanac = fixtures::almanac();
+        let mut sc = leo();
+        // The frame of the exported trajectory must be complete
+        sc.orbit.frame.shape = Some(Ellipsoid::from_sphere(6_378.136_3));
+
+        let req: Scenario = serde_json::from_value(serde_json::json!({
+            "spacecraft": sc,
+            "propagation": {
+                "end_epoch": sc.epoch() + 1.hours(),
+                "output_step": 1.minutes(),
+            },
+            "output_dir": "ignored",
+        }))
+        .unwrap();
+
+        let resp = scenario(
+            AxumState(almanac.clone()),
+            Query(FormatQuery::default()),
+            Json(req.clone()),
+        )
+        .await
+        .unwrap();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let resp: ScenarioResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(resp.final_state.epoch(), sc.epoch() + 1.hours());
+
+        let resp = scenario(
+            AxumState(almanac),
+            Query(FormatQuery {
+                format: OutputFormat::Parquet,
+            }),
+            Json(req),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], PARQUET_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn bad_requests() {
+        let almanac = fixtures::almanac();
+        let sc = leo();
+
+        let req = TargetRequest {
+            initial_state: sc,
+            dynamics: DynamicsRequest::default(),
+            achievement_epoch: sc.epoch() + 30.minutes(),
+            objectives: vec![ObjectiveRequest {
+                parameter: "not_a_param".to_string(),
+                desired_value: 7_100.0,
+                tolerance: 0.1,
+            }],
+        };
+
+        let err = target(AxumState(almanac), Json(req)).await.unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+
+        let resp = err.into_response();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(body["error"].as_str().unwrap().contains("not_a_param"));
+    }
+}
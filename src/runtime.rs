@@ -0,0 +1,240 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use anise::almanac::Almanac;
+use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+use snafu::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex, RwLock, Weak};
+
+static ALMANAC: RwLock<Option<Arc<Almanac>>> = RwLock::new(None);
+static LOADED_ALMANACS: Mutex<Option<HashMap<Vec<String>, Weak<Almanac>>>> = Mutex::new(None);
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum RuntimeError {
+    #[snafu(display("could not build the thread pool: {source}"))]
+    ThreadPool { source: ThreadPoolBuildError },
+}
+
+/// Runtime configuration of Nyx, for applications which embed Nyx alongside their own computations.
+///
+/// The parallel computations of Nyx (ensemble propagation, Monte Carlo runs, event searches, targeting, etc.) run on a
+/// [rayon](https://docs.rs/rayon) thread pool. By default, this pool has one thread per logical core, which oversubscribes the
+/// cores if the host application also runs its own threads. This configuration may be installed once for the whole process
+/// with [RuntimeConfig::install], or used for a single run with [RuntimeConfig::run].
+#[derive(Clone, Default)]
+pub struct RuntimeConfig {
+    /// Number of worker threads, defaults to the number of logical cores (or to the `RAYON_NUM_THREADS` environment variable)
+    pub num_threads: Option<usize>,
+    /// Cores to which the worker threads are pinned, in a round robin fashion (only supported on Linux)
+    pub core_ids: Option<Vec<usize>>,
+    /// Almanac shared by all of the computations of the process, cf. [almanac]
+    pub almanac: Option<Arc<Almanac>>,
+}
+
+impl RuntimeConfig {
+    /// Sets the number of worker threads.
+    pub fn with_num_threads(mut self, num_threads: usize) -> Self {
+        self.num_threads = Some(num_threads);
+        self
+    }
+
+    /// Pins the worker threads to the provided cores.
+    pub fn with_core_ids(mut self, core_ids: Vec<usize>) -> Self {
+        self.core_ids = Some(core_ids);
+        self
+    }
+
+    /// Sets the Almanac shared by all of the computations of the process.
+    pub fn with_almanac(mut self, almanac: Arc<Almanac>) -> Self {
+        self.almanac = Some(almanac);
+        self
+    }
+
+    /// Installs this configuration for the whole process.
+    ///
+    /// The global thread pool can only be configured once, and before any parallel computation: this returns an error otherwise,
+    /// and leaves the shared Almanac unchanged.
+    pub fn install(&self) -> Result<(), RuntimeError> {
+        self.pool_builder()
+            .build_global()
+            .context(ThreadPoolSnafu)?;
+        if let Some(almanac) = &self.almanac {
+            *ALMANAC.write().unwrap() = Some(almanac.clone());
+        }
+        Ok(())
+    }
+
+    /// Builds a dedicated thread pool with this configuration, e.g. to run several scenarios on disjoint sets of cores.
+    pub fn thread_pool(&self) -> Result<ThreadPool, RuntimeError> {
+        self.pool_builder().build().context(ThreadPoolSnafu)
+    }
+
+    /// Runs the provided computation on a dedicated thread pool with this configuration, such that all of its parallel
+    /// computations (e.g. the runs of a Monte Carlo or an ensemble propagation) only use the configured threads.
+    pub fn run<R, F>(&self, op: F) -> Result<R, RuntimeError>
+    where
+        R: Send,
+        F: FnOnce() -> R + Send,
+    {
+        Ok(self.thread_pool()?.install(op))
+    }
+
+    fn pool_builder(&self) -> ThreadPoolBuilder {
+        let mut builder = ThreadPoolBuilder::new().thread_name(|idx| format!("nyx-worker-{idx}"));
+        if let Some(num_threads) = self.num_threads {
+            builder = builder.num_threads(num_threads);
+        }
+        if let Some(core_ids) = self.core_ids.clone().filter(|ids| !ids.is_empty()) {
+            builder = builder.start_handler(move |idx| {
+                let core = core_ids[idx % core_ids.len()];
+                if let Err(e) = pin_current_thread(core) {
+                    warn!("could not pin worker thread {idx} to core {core}: {e}");
+                }
+            });
+        }
+        builder
+    }
+}
+
+impl fmt::Debug for RuntimeConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The Almanac does not implement Debug
+        f.debug_struct("RuntimeConfig")
+            .field("num_threads", &self.num_threads)
+            .field("core_ids", &self.core_ids)
+            .field("almanac", &self.almanac.as_ref().map(|_| "shared"))
+            .finish()
+    }
+}
+
+/// Returns the Almanac shared by all of the computations of the process, if one was installed with [RuntimeConfig::install].
+pub fn almanac() -> Option<Arc<Almanac>> {
+    ALMANAC.read().unwrap().clone()
+}
+
+/// Returns the Almanac loaded from the provided files, loading it only if no other computation holds it.
+///
+/// Loading the same files from several scenarios, orbit determination processes or Monte Carlo runs therefore shares a single
+/// copy of the ephemerides in memory. The Almanac is released once the last computation drops it.
+pub fn shared_almanac<E, F>(files: &[String], load: F) -> Result<Arc<Almanac>, E>
+where
+    F: FnOnce() -> Result<Almanac, E>,
+{
+    // Loads are serialized such that concurrent loads of the same files only read them once
+    let mut loaded = LOADED_ALMANACS.lock().unwrap();
+    let loaded = loaded.get_or_insert_with(HashMap::new);
+    if let Some(almanac) = loaded.get(files).and_then(Weak::upgrade) {
+        return Ok(almanac);
+    }
+    let almanac = Arc::new(load()?);
+    loaded.retain(|_, almanac| almanac.strong_count() > 0);
+    loaded.insert(files.to_vec(), Arc::downgrade(&almanac));
+    Ok(almanac)
+}
+
+#[cfg(target_os = "linux")]
+fn pin_current_thread(core: usize) -> io::Result<()> {
+    if core >= libc::CPU_SETSIZE as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "core beyond the CPU set size",
+        ));
+    }
+    // SAFETY: the CPU set is a plain bit mask, initialized to zero, and the core is within its size.
+    // A PID of zero sets the affinity of the calling thread.
+    let rslt = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+    if rslt == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_current_thread(_core: usize) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "thread affinity is only supported on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod ut_runtime {
+    use super::*;
+
+    #[test]
+    fn dedicated_pool() {
+        let cfg = RuntimeConfig::default()
+            .with_num_threads(2)
+            .with_core_ids(vec![0]);
+        let threads = cfg.run(rayon::current_num_threads).unwrap();
+        assert_eq!(threads, 2);
+
+        let name = cfg
+            .run(|| rayon::join(|| std::thread::current().name().map(String::from), || ()).0)
+            .unwrap();
+        assert!(name.unwrap().starts_with("nyx-worker-"));
+    }
+
+    #[test]
+    fn install_after_global_pool() {
+        // Initializes the global thread pool, which cannot be configured afterward
+        rayon::current_num_threads();
+        let cfg = RuntimeConfig::default()
+            .with_num_threads(1)
+            .with_almanac(Arc::new(Almanac::default()));
+        assert!(cfg.install().is_err());
+        assert!(almanac().is_none());
+    }
+
+    #[test]
+    fn shared_almanac_loaded_once() {
+        let files = vec!["ut_runtime.bsp".to_string()];
+        let mut loads = 0;
+        let first = shared_almanac::<(), _>(&files, || {
+            loads += 1;
+            Ok(Almanac::default())
+        })
+        .unwrap();
+        let second = shared_almanac::<(), _>(&files, || {
+            loads += 1;
+            Ok(Almanac::default())
+        })
+        .unwrap();
+        assert_eq!(loads, 1);
+        assert!(Arc::ptr_eq(&first, &second));
+
+        // Once released, the Almanac is loaded again
+        drop(first);
+        drop(second);
+        shared_almanac::<(), _>(&files, || {
+            loads += 1;
+            Ok(Almanac::default())
+        })
+        .unwrap();
+        assert_eq!(loads, 2);
+    }
+}
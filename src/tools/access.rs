@@ -0,0 +1,473 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::cosmic::Frame;
+use crate::errors::{EventAlmanacSnafu, EventError, EventPhysicsSnafu, EventSnafu, NyxError};
+use crate::io::watermark::pq_writer;
+use crate::md::prelude::Traj;
+use crate::md::EventEvaluator;
+use crate::od::GroundStation;
+use crate::time::{Duration, Epoch, Unit};
+use crate::{Spacecraft, State};
+use anise::almanac::Almanac;
+use arrow::array::{Array, Float64Builder, StringBuilder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use hifitime::TimeScale;
+use parquet::arrow::ArrowWriter;
+use snafu::ResultExt;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Number of samples used to bracket the maximum elevation of an access window before refining it.
+const MAX_ELEVATION_SAMPLES: usize = 50;
+
+/// A celestial target (e.g. a planet or the Sun) seen from the spacecraft, which may be hidden behind an obstructing body.
+///
+/// Its elevation is the angle between the target and the limb of the obstructing body, as seen from the spacecraft.
+/// This is the analog of the elevation of the spacecraft as seen from a ground station.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CelestialTarget {
+    pub target: Frame,
+    /// Body which may hide the target, its shape must be defined, e.g. as returned by `almanac.frame_from_uid(MOON_J2000)`
+    pub obstructing_body: Frame,
+    /// in degrees
+    pub elevation_mask_deg: f64,
+}
+
+impl CelestialTarget {
+    /// Initializes a celestial target with a zero degree elevation mask
+    pub fn new(target: Frame, obstructing_body: Frame) -> Self {
+        Self {
+            target,
+            obstructing_body,
+            elevation_mask_deg: 0.0,
+        }
+    }
+
+    /// Returns the angle in degrees between the target and the limb of the obstructing body, as seen from the spacecraft.
+    pub fn elevation_deg(&self, sc: &Spacecraft, almanac: Arc<Almanac>) -> Result<f64, EventError> {
+        let observer = sc.orbit;
+        let to_target = almanac
+            .transform(self.target, observer.frame, observer.epoch, None)
+            .context(EventAlmanacSnafu)?
+            .radius_km
+            - observer.radius_km;
+        let to_body = almanac
+            .transform(self.obstructing_body, observer.frame, observer.epoch, None)
+            .context(EventAlmanacSnafu)?
+            .radius_km
+            - observer.radius_km;
+
+        let body_radius_km = self
+            .obstructing_body
+            .mean_equatorial_radius_km()
+            .context(EventPhysicsSnafu)?;
+
+        let separation_deg = to_target.angle(&to_body).to_degrees();
+        let angular_radius_deg = (body_radius_km / to_body.norm())
+            .min(1.0)
+            .asin()
+            .to_degrees();
+
+        Ok(separation_deg - angular_radius_deg)
+    }
+}
+
+impl fmt::Display for CelestialTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} above the limb of {} (mask {} deg)",
+            self.target, self.obstructing_body, self.elevation_mask_deg
+        )
+    }
+}
+
+impl EventEvaluator<Spacecraft> for CelestialTarget {
+    fn eval(&self, sc: &Spacecraft, almanac: Arc<Almanac>) -> Result<f64, EventError> {
+        Ok(self.elevation_deg(sc, almanac)? - self.elevation_mask_deg)
+    }
+
+    fn eval_string(&self, sc: &Spacecraft, almanac: Arc<Almanac>) -> Result<String, EventError> {
+        Ok(format!(
+            "Elevation of {} is {:.6} deg on {}",
+            self.target,
+            self.elevation_deg(sc, almanac)?,
+            sc.epoch()
+        ))
+    }
+
+    fn epoch_precision(&self) -> Duration {
+        1 * Unit::Second
+    }
+
+    /// Angle precision of the elevation evaluator is 1 millidegree.
+    fn value_precision(&self) -> f64 {
+        1e-3
+    }
+}
+
+/// A window of access between the spacecraft and a target, i.e. while its elevation is above the mask.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccessWindow {
+    /// Name of the ground station or description of the celestial target
+    pub target: String,
+    pub rise: Epoch,
+    pub set: Epoch,
+    /// Maximum elevation during this window, in degrees
+    pub max_elevation_deg: f64,
+    pub max_elevation_epoch: Epoch,
+}
+
+impl AccessWindow {
+    /// Returns the duration of this access window
+    pub fn duration(&self) -> Duration {
+        self.set - self.rise
+    }
+}
+
+impl fmt::Display for AccessWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} from {} to {} ({}), max elevation of {:.3} deg on {}",
+            self.target,
+            self.rise,
+            self.set,
+            self.duration(),
+            self.max_elevation_deg,
+            self.max_elevation_epoch
+        )
+    }
+}
+
+/// Access report between a trajectory and a set of targets.
+#[derive(Clone, Debug, Default)]
+pub struct AccessReport {
+    /// Access windows of all targets, sorted by rise epoch
+    pub windows: Vec<AccessWindow>,
+}
+
+impl AccessReport {
+    /// Computes the access windows of this trajectory with all of the ground stations and celestial targets.
+    pub fn compute(
+        traj: &Traj<Spacecraft>,
+        stations: &[GroundStation],
+        targets: &[CelestialTarget],
+        almanac: Arc<Almanac>,
+    ) -> Result<Self, NyxError> {
+        let mut report = Self::default();
+
+        for station in stations {
+            // The elevation of a ground station requires the trajectory in the station frame.
+            let traj = traj.to_frame(station.frame, almanac.clone())?;
+            report
+                .add_windows(
+                    station.name.clone(),
+                    &traj,
+                    &station,
+                    station.elevation_mask_deg,
+                    almanac.clone(),
+                )
+                .context(EventSnafu)?;
+        }
+
+        for target in targets {
+            report
+                .add_windows(
+                    format!("{}", target.target),
+                    traj,
+                    target,
+                    target.elevation_mask_deg,
+                    almanac.clone(),
+                )
+                .context(EventSnafu)?;
+        }
+
+        Ok(report)
+    }
+
+    /// Adds the access windows of an event which evaluates to the elevation of the target minus its elevation mask, in degrees.
+    pub fn add_windows<E: EventEvaluator<Spacecraft>>(
+        &mut self,
+        target: String,
+        traj: &Traj<Spacecraft>,
+        event: &E,
+        elevation_mask_deg: f64,
+        almanac: Arc<Almanac>,
+    ) -> Result<(), EventError> {
+        let arcs = match traj.find_arcs(event, almanac.clone()) {
+            Ok(arcs) => arcs,
+            Err(EventError::NotFound { .. }) => {
+                info!("No access to {target}");
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+
+        for arc in arcs {
+            let rise = arc.rise.state.epoch();
+            let set = arc.fall.state.epoch();
+            let (max_elevation_epoch, max_value) =
+                max_within(traj, event, rise, set, almanac.clone())?;
+
+            self.windows.push(AccessWindow {
+                target: target.clone(),
+                rise,
+                set,
+                max_elevation_deg: max_value + elevation_mask_deg,
+                max_elevation_epoch,
+            });
+        }
+
+        self.windows.sort_by_key(|window| window.rise);
+
+        Ok(())
+    }
+
+    /// Returns the access windows of the provided target
+    pub fn windows_of<'a>(&'a self, target: &'a str) -> impl Iterator<Item = &'a AccessWindow> {
+        self.windows
+            .iter()
+            .filter(move |window| window.target == target)
+    }
+
+    /// Returns the cumulative access duration with the provided target
+    pub fn total_duration(&self, target: &str) -> Duration {
+        self.windows_of(target)
+            .fold(Duration::ZERO, |total, window| total + window.duration())
+    }
+
+    /// Exports this report to a parquet file, with one row per access window.
+    pub fn to_parquet<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Box<dyn Error>> {
+        let path_buf = path.as_ref().to_path_buf();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("Target", DataType::Utf8, false),
+            Field::new("Rise (UTC)", DataType::Utf8, false),
+            Field::new("Set (UTC)", DataType::Utf8, false),
+            Field::new("Duration (s)", DataType::Float64, false),
+            Field::new("Max elevation (deg)", DataType::Float64, false),
+            Field::new("Max elevation epoch (UTC)", DataType::Utf8, false),
+        ]));
+
+        let utc = |epoch: Epoch| epoch.to_time_scale(TimeScale::UTC).to_isoformat();
+
+        let mut targets = StringBuilder::new();
+        let mut rises = StringBuilder::new();
+        let mut sets = StringBuilder::new();
+        let mut durations = Float64Builder::new();
+        let mut max_elevations = Float64Builder::new();
+        let mut max_elevation_epochs = StringBuilder::new();
+        for window in &self.windows {
+            targets.append_value(&window.target);
+            rises.append_value(utc(window.rise));
+            sets.append_value(utc(window.set));
+            durations.append_value(window.duration().to_seconds());
+            max_elevations.append_value(window.max_elevation_deg);
+            max_elevation_epochs.append_value(utc(window.max_elevation_epoch));
+        }
+
+        let record: Vec<Arc<dyn Array>> = vec![
+            Arc::new(targets.finish()),
+            Arc::new(rises.finish()),
+            Arc::new(sets.finish()),
+            Arc::new(durations.finish()),
+            Arc::new(max_elevations.finish()),
+            Arc::new(max_elevation_epochs.finish()),
+        ];
+
+        let mut metadata = HashMap::new();
+        metadata.insert("Purpose".to_string(), "Access report".to_string());
+
+        let file = File::create(&path_buf)?;
+        let mut writer = ArrowWriter::try_new(file, schema.clone(), pq_writer(Some(metadata)))?;
+        writer.write(&RecordBatch::try_new(schema, record)?)?;
+        writer.close()?;
+
+        info!(
+            "Access report of {} windows written to {}",
+            self.windows.len(),
+            path_buf.display()
+        );
+
+        Ok(path_buf)
+    }
+}
+
+impl fmt::Display for AccessReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} access windows", self.windows.len())?;
+        for window in &self.windows {
+            writeln!(f, "\t{window}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the epoch and value of the maximum of the event between both epochs.
+///
+/// The maximum is bracketed by sampling, and then refined with a golden section search to the epoch precision of the event.
+fn max_within<E: EventEvaluator<Spacecraft>>(
+    traj: &Traj<Spacecraft>,
+    event: &E,
+    start: Epoch,
+    end: Epoch,
+    almanac: Arc<Almanac>,
+) -> Result<(Epoch, f64), EventError> {
+    let eval = |epoch: Epoch| -> Result<f64, EventError> {
+        let state = traj.at(epoch).context(crate::errors::EventTrajSnafu)?;
+        event.eval(&state, almanac.clone())
+    };
+
+    let step = (end - start) / (MAX_ELEVATION_SAMPLES as f64);
+    if step <= Duration::ZERO {
+        return Ok((start, eval(start)?));
+    }
+
+    let mut best = (start, eval(start)?);
+    for i in 1..=MAX_ELEVATION_SAMPLES {
+        let epoch = start + step * (i as f64);
+        let value = eval(epoch)?;
+        if value > best.1 {
+            best = (epoch, value);
+        }
+    }
+
+    // Golden section search in the neighborhood of the best sample
+    let inv_phi = (5.0_f64.sqrt() - 1.0) / 2.0;
+    let mut lo = (best.0 - step).max(start);
+    let mut hi = (best.0 + step).min(end);
+    while hi - lo > event.epoch_precision() {
+        let c = hi - (hi - lo) * inv_phi;
+        let d = lo + (hi - lo) * inv_phi;
+        if eval(c)? > eval(d)? {
+            hi = d;
+        } else {
+            lo = c;
+        }
+    }
+
+    let mid = lo + (hi - lo) * 0.5;
+    let value = eval(mid)?;
+    if value > best.1 {
+        best = (mid, value);
+    }
+
+    Ok(best)
+}
+
+#[cfg(test)]
+mod ut_access {
+    use super::*;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::propagators::Propagator;
+    use crate::time::TimeUnits;
+
+    /// Elevation of the spacecraft above the local horizon of a point on the X axis of the inertial frame.
+    struct InertialSite {
+        radius_km: f64,
+        mask_deg: f64,
+    }
+
+    impl fmt::Display for InertialSite {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "inertial site")
+        }
+    }
+
+    impl EventEvaluator<Spacecraft> for InertialSite {
+        fn eval(&self, sc: &Spacecraft, _almanac: Arc<Almanac>) -> Result<f64, EventError> {
+            let rho = sc.orbit.radius_km - nalgebra::Vector3::x() * self.radius_km;
+            Ok((rho.x / rho.norm()).asin().to_degrees() - self.mask_deg)
+        }
+
+        fn eval_string(
+            &self,
+            sc: &Spacecraft,
+            almanac: Arc<Almanac>,
+        ) -> Result<String, EventError> {
+            Ok(format!("{:.3} deg", self.eval(sc, almanac)?))
+        }
+
+        fn epoch_precision(&self) -> Duration {
+            1 * Unit::Second
+        }
+
+        fn value_precision(&self) -> f64 {
+            1e-3
+        }
+    }
+
+    #[test]
+    fn windows_and_max_elevation() {
+        let almanac = fixtures::almanac();
+        let epoch = fixtures::epoch();
+
+        // Start on the opposite side of the site, such that the first pass is complete.
+        let orbit = fixtures::keplerian(7_000.0, 0.0, 0.0, 0.0, 0.0, 180.0);
+        let period = orbit.period().unwrap();
+
+        let (_, traj) = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()))
+            .with(Spacecraft::builder().orbit(orbit).build(), almanac.clone())
+            .for_duration_with_traj(period * 2.0)
+            .unwrap();
+
+        let mask_deg = 10.0;
+        let mut report = AccessReport::default();
+        report
+            .add_windows(
+                "site".to_string(),
+                &traj,
+                &InertialSite {
+                    radius_km: 6_378.0,
+                    mask_deg,
+                },
+                mask_deg,
+                almanac,
+            )
+            .unwrap();
+
+        println!("{report}");
+        assert_eq!(report.windows.len(), 2);
+
+        for (i, window) in report.windows.iter().enumerate() {
+            // Zenith pass, half an orbit after the start
+            assert!((window.max_elevation_deg - 90.0).abs() < 1e-2, "{window}");
+            let zenith = epoch + period * (i as f64 + 0.5);
+            assert!((window.max_elevation_epoch - zenith).abs() < 2.seconds());
+            let mid = window.rise + window.duration() * 0.5;
+            assert!((mid - zenith).abs() < 2.seconds());
+        }
+
+        assert_eq!(report.windows_of("site").count(), 2);
+        assert_eq!(report.windows_of("other").count(), 0);
+        assert!(
+            (report.total_duration("site") - report.windows[0].duration() * 2).abs() < 2.seconds()
+        );
+
+        let path = std::env::temp_dir().join("nyx_ut_access.parquet");
+        assert_eq!(report.to_parquet(&path).unwrap(), path);
+    }
+}
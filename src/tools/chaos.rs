@@ -0,0 +1,338 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::dynamics::SpacecraftDynamics;
+use crate::errors::NyxError;
+use crate::io::watermark::pq_writer;
+use crate::linalg::{Matrix6, Vector6};
+use crate::propagators::Propagator;
+use crate::time::{Duration, Epoch, Unit};
+use crate::{Spacecraft, State};
+use anise::almanac::Almanac;
+use arrow::array::{Array, Float64Builder, StringBuilder, UInt64Builder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use hifitime::TimeScale;
+use parquet::arrow::ArrowWriter;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use typed_builder::TypedBuilder;
+
+/// Configuration of the computation of the chaos indicators.
+///
+/// The state transition matrix mixes positions and velocities, so it is nondimensionalized with a characteristic length
+/// and time before computing its singular values. By default, these are the initial radius and the inverse of the
+/// corresponding circular mean motion.
+#[derive(Copy, Clone, Debug, TypedBuilder)]
+#[builder(doc)]
+pub struct ChaosConfig {
+    pub duration: Duration,
+    /// Step between two evaluations of the indicators
+    #[builder(default = Unit::Hour * 1)]
+    pub step: Duration,
+    /// Characteristic length, in km
+    #[builder(default, setter(strip_option))]
+    pub length_km: Option<f64>,
+    /// Characteristic time, in seconds
+    #[builder(default, setter(strip_option))]
+    pub time_s: Option<f64>,
+}
+
+/// Chaos indicators of a trajectory at a given epoch, computed from the largest singular value σ of the
+/// (nondimensional) orbital state transition matrix since the initial epoch.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ChaosIndicators {
+    pub epoch: Epoch,
+    /// Time elapsed since the initial epoch
+    pub elapsed: Duration,
+    /// Fast Lyapunov Indicator: largest value of ln σ up to this epoch
+    pub fli: f64,
+    /// Finite time Lyapunov exponent ln σ / elapsed, in 1/s
+    pub ftle_s: f64,
+}
+
+impl ChaosIndicators {
+    /// Computes the FTLE of the provided nondimensional STM, and its FLI given the FLI at the previous epoch.
+    fn new(epoch: Epoch, elapsed: Duration, stm: &Matrix6<f64>, prev_fli: f64) -> Self {
+        let log_stretch = stm.singular_values().max().ln();
+        Self {
+            epoch,
+            elapsed,
+            fli: prev_fli.max(log_stretch),
+            ftle_s: log_stretch / elapsed.to_seconds().abs(),
+        }
+    }
+}
+
+impl fmt::Display for ChaosIndicators {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} (+{}): FLI = {:.6}\tFTLE = {:.6e} 1/s",
+            self.epoch, self.elapsed, self.fli, self.ftle_s
+        )
+    }
+}
+
+/// History of the chaos indicators of a single trajectory.
+#[derive(Clone, Debug)]
+pub struct ChaosHistory {
+    pub initial_state: Spacecraft,
+    pub indicators: Vec<ChaosIndicators>,
+}
+
+impl ChaosHistory {
+    /// Propagates the state and its STM with the provided propagator, evaluating the indicators at every step.
+    pub fn compute(
+        state: Spacecraft,
+        prop: &Propagator<SpacecraftDynamics>,
+        config: ChaosConfig,
+        almanac: Arc<Almanac>,
+    ) -> Result<Self, NyxError> {
+        if config.step.to_seconds() <= 0.0 || config.duration.to_seconds() <= 0.0 {
+            return Err(NyxError::CustomError {
+                msg: "the duration and step of the chaos indicators must be positive".to_string(),
+            });
+        }
+
+        let length_km = config.length_km.unwrap_or_else(|| state.orbit.rmag_km());
+        let time_s = match config.time_s {
+            Some(time_s) => time_s,
+            None => {
+                let mu_km3_s2 =
+                    state
+                        .orbit
+                        .frame
+                        .mu_km3_s2()
+                        .map_err(|e| NyxError::CustomError {
+                            msg: format!("cannot compute the characteristic time: {e}"),
+                        })?;
+                (length_km.powi(3) / mu_km3_s2).sqrt()
+            }
+        };
+        let scale = Vector6::new(
+            1.0 / length_km,
+            1.0 / length_km,
+            1.0 / length_km,
+            time_s / length_km,
+            time_s / length_km,
+            time_s / length_km,
+        );
+        let (scaling, inv_scaling) = (
+            Matrix6::from_diagonal(&scale),
+            Matrix6::from_diagonal(&scale.map(|s| 1.0 / s)),
+        );
+
+        let mut instance = prop.with(state.with_stm(), almanac);
+        let mut indicators: Vec<ChaosIndicators> = Vec::new();
+        // Ignore a remainder of the duration which is negligible compared to the step
+        let num_steps =
+            (config.duration.to_seconds() / config.step.to_seconds() - 1e-9).ceil() as i64;
+        let mut elapsed = Duration::ZERO;
+        for k in 1..=num_steps {
+            let target = if k == num_steps {
+                config.duration
+            } else {
+                config.step * k
+            };
+            let current =
+                instance
+                    .for_duration(target - elapsed)
+                    .map_err(|e| NyxError::CustomError {
+                        msg: format!("propagating the chaos indicators: {e}"),
+                    })?;
+            elapsed = target;
+
+            let stm = current
+                .stm()
+                .map_err(|e| NyxError::CustomError { msg: e.to_string() })?
+                .fixed_view::<6, 6>(0, 0)
+                .into_owned();
+            let prev_fli = indicators.last().map_or(f64::MIN, |prev| prev.fli);
+            indicators.push(ChaosIndicators::new(
+                current.epoch(),
+                elapsed,
+                &(scaling * stm * inv_scaling),
+                prev_fli,
+            ));
+        }
+
+        Ok(Self {
+            initial_state: state,
+            indicators,
+        })
+    }
+
+    /// Returns the indicators at the end of the propagation.
+    pub fn last(&self) -> ChaosIndicators {
+        self.indicators[self.indicators.len() - 1]
+    }
+}
+
+/// Stability map: the final chaos indicators of a set of initial states, e.g. a grid of disposal orbits.
+#[derive(Clone, Debug)]
+pub struct ChaosMap {
+    /// Initial states and the indicators at the end of their propagation
+    pub entries: Vec<(Spacecraft, ChaosIndicators)>,
+}
+
+impl ChaosMap {
+    /// Computes the chaos indicators of each initial state in parallel.
+    pub fn compute(
+        initial_states: &[Spacecraft],
+        prop: &Propagator<SpacecraftDynamics>,
+        config: ChaosConfig,
+        almanac: Arc<Almanac>,
+    ) -> Result<Self, NyxError> {
+        let entries = initial_states
+            .par_iter()
+            .map(|state| {
+                let history = ChaosHistory::compute(*state, prop, config, almanac.clone())?;
+                Ok((*state, history.last()))
+            })
+            .collect::<Result<Vec<(Spacecraft, ChaosIndicators)>, NyxError>>()?;
+
+        Ok(Self { entries })
+    }
+
+    /// Exports this map to a parquet file, with one row per initial state.
+    pub fn to_parquet<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Box<dyn Error>> {
+        let path_buf = path.as_ref().to_path_buf();
+
+        let mut fields = vec![
+            Field::new("Index", DataType::UInt64, false),
+            Field::new("Epoch (UTC)", DataType::Utf8, false),
+        ];
+        for name in [
+            "x (km)",
+            "y (km)",
+            "z (km)",
+            "vx (km/s)",
+            "vy (km/s)",
+            "vz (km/s)",
+        ] {
+            fields.push(Field::new(name, DataType::Float64, false));
+        }
+        fields.push(Field::new("Duration (s)", DataType::Float64, false));
+        fields.push(Field::new("FLI", DataType::Float64, false));
+        fields.push(Field::new("FTLE (1/s)", DataType::Float64, false));
+        let schema = Arc::new(Schema::new(fields));
+
+        let mut indexes = UInt64Builder::new();
+        let mut epochs = StringBuilder::new();
+        let mut components = (0..6)
+            .map(|_| Float64Builder::new())
+            .collect::<Vec<Float64Builder>>();
+        let mut durations = Float64Builder::new();
+        let mut flis = Float64Builder::new();
+        let mut ftles = Float64Builder::new();
+        for (idx, (state, indicators)) in self.entries.iter().enumerate() {
+            indexes.append_value(idx as u64);
+            epochs.append_value(state.epoch().to_time_scale(TimeScale::UTC).to_isoformat());
+            for (builder, value) in components
+                .iter_mut()
+                .zip(state.orbit.to_cartesian_pos_vel().iter())
+            {
+                builder.append_value(*value);
+            }
+            durations.append_value(indicators.elapsed.to_seconds());
+            flis.append_value(indicators.fli);
+            ftles.append_value(indicators.ftle_s);
+        }
+
+        let mut record: Vec<Arc<dyn Array>> =
+            vec![Arc::new(indexes.finish()), Arc::new(epochs.finish())];
+        for mut builder in components {
+            record.push(Arc::new(builder.finish()));
+        }
+        record.push(Arc::new(durations.finish()));
+        record.push(Arc::new(flis.finish()));
+        record.push(Arc::new(ftles.finish()));
+
+        let mut metadata = HashMap::new();
+        metadata.insert("Purpose".to_string(), "Chaos indicators".to_string());
+        if let Some((state, _)) = self.entries.first() {
+            metadata.insert("Frame".to_string(), format!("{}", state.orbit.frame));
+        }
+
+        let file = File::create(&path_buf)?;
+        let mut writer = ArrowWriter::try_new(file, schema.clone(), pq_writer(Some(metadata)))?;
+        writer.write(&RecordBatch::try_new(schema, record)?)?;
+        writer.close()?;
+
+        info!("Chaos indicators written to {}", path_buf.display());
+
+        Ok(path_buf)
+    }
+}
+
+#[cfg(test)]
+mod ut_chaos {
+    use super::*;
+    use crate::dynamics::OrbitalDynamics;
+    use crate::fixtures;
+    use std::f64::consts::TAU;
+
+    #[test]
+    fn two_body_indicators() {
+        let circular = Spacecraft::builder()
+            .orbit(fixtures::keplerian(7_000.0, 0.0, 30.0, 45.0, 0.0, 0.0))
+            .build();
+        let period = circular.orbit.period().unwrap();
+
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let config = ChaosConfig::builder()
+            .duration(period * 10)
+            .step(period / 4)
+            .build();
+        let history = ChaosHistory::compute(circular, &prop, config, fixtures::almanac()).unwrap();
+        assert_eq!(history.indicators.len(), 40);
+
+        for pair in history.indicators.windows(2) {
+            assert!(pair[1].fli >= pair[0].fli);
+        }
+
+        // Keplerian motion only drifts linearly in phase, by -3τ (δr + δvt) in nondimensional units where τ is the
+        // elapsed mean anomaly. This phase drift rotates both the position and the velocity, so the largest singular
+        // value of the nondimensional STM is 6τ after whole revolutions.
+        let last = history.last();
+        println!("{last}");
+        let tau = 10.0 * TAU;
+        assert!((last.fli - (6.0 * tau).ln()).abs() < 1e-4, "{last}");
+        assert!((last.elapsed - period * 10).abs() < Unit::Microsecond * 1);
+        // Regular motion has a vanishing FTLE.
+        assert!(last.ftle_s < history.indicators[3].ftle_s);
+
+        let eccentric = Spacecraft::builder()
+            .orbit(fixtures::keplerian(12_000.0, 0.3, 60.0, 10.0, 20.0, 30.0))
+            .build();
+        let map =
+            ChaosMap::compute(&[circular, eccentric], &prop, config, fixtures::almanac()).unwrap();
+        assert_eq!(map.entries.len(), 2);
+        assert_eq!(map.entries[0].1, last);
+        let path = map
+            .to_parquet(std::env::temp_dir().join("nyx_ut_chaos.parquet"))
+            .unwrap();
+        assert!(path.exists());
+    }
+}
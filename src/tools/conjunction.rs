@@ -0,0 +1,540 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::errors::NyxError;
+use crate::linalg::{Matrix2, Matrix3, Vector2, Vector3};
+use crate::mc::stream_rng;
+use crate::md::prelude::Traj;
+use crate::time::{Duration, Epoch, TimeSeries, Unit};
+use crate::Spacecraft;
+use anise::almanac::Almanac;
+use anise::astro::PhysicsResult;
+use rand_distr::{Distribution, Normal};
+use rand_pcg::Pcg64Mcg;
+use std::f64::consts::PI;
+use std::fmt;
+use std::sync::Arc;
+
+/// A close approach between two trajectories, at the time of closest approach (TCA).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CloseApproach {
+    /// Index of the primary trajectory in the screened set
+    pub primary: usize,
+    /// Index of the secondary trajectory in the screened set
+    pub secondary: usize,
+    /// Time of closest approach
+    pub tca: Epoch,
+    /// State of the primary at TCA
+    pub primary_state: Spacecraft,
+    /// State of the secondary at TCA, in the frame of the primary
+    pub secondary_state: Spacecraft,
+}
+
+impl CloseApproach {
+    /// Position of the secondary with respect to the primary, in km
+    pub fn relative_position_km(&self) -> Vector3<f64> {
+        self.secondary_state.orbit.radius_km - self.primary_state.orbit.radius_km
+    }
+
+    /// Velocity of the secondary with respect to the primary, in km/s
+    pub fn relative_velocity_km_s(&self) -> Vector3<f64> {
+        self.secondary_state.orbit.velocity_km_s - self.primary_state.orbit.velocity_km_s
+    }
+
+    /// Miss distance at TCA, in km
+    pub fn miss_distance_km(&self) -> f64 {
+        self.relative_position_km().norm()
+    }
+
+    /// Relative speed at TCA, in km/s
+    pub fn relative_speed_km_s(&self) -> f64 {
+        self.relative_velocity_km_s().norm()
+    }
+
+    /// Miss vector in the radial, in-track, cross-track (RIC) frame of the primary, in km
+    pub fn miss_ric_km(&self) -> PhysicsResult<Vector3<f64>> {
+        let dcm = self.primary_state.orbit.dcm3x3_from_ric_to_inertial()?;
+        Ok(dcm.rot_mat.transpose() * self.relative_position_km())
+    }
+
+    /// Returns the encounter plane of this close approach, cf. [EncounterPlane::new].
+    pub fn encounter_plane(
+        &self,
+        primary_covar_km2: &Matrix3<f64>,
+        secondary_covar_km2: &Matrix3<f64>,
+    ) -> Result<EncounterPlane, NyxError> {
+        EncounterPlane::new(self, primary_covar_km2, secondary_covar_km2)
+    }
+}
+
+impl fmt::Display for CloseApproach {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "#{} and #{} at {}: miss distance of {:.3} km at {:.6} km/s",
+            self.primary,
+            self.secondary,
+            self.tca,
+            self.miss_distance_km(),
+            self.relative_speed_km_s()
+        )
+    }
+}
+
+/// Minimum number of radial and angular steps of the numerical integration of the probability of collision.
+const PC_RADIAL_STEPS: usize = 64;
+const PC_ANGULAR_STEPS: usize = 128;
+/// Number of integration steps per smallest standard deviation of the combined covariance.
+const PC_STEPS_PER_SIGMA: f64 = 8.0;
+/// Maximum number of radial and angular steps, which bound the cost of the integration when the covariance is tiny.
+const PC_MAX_RADIAL_STEPS: usize = 4096;
+const PC_MAX_ANGULAR_STEPS: usize = 16384;
+
+/// Encounter plane of a close approach, i.e. the plane perpendicular to the relative velocity at TCA.
+///
+/// The X axis is along the miss vector, the Z axis along the relative velocity, and the Y axis completes the frame.
+/// The covariance is the combined position covariance of both objects, projected into the encounter plane.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EncounterPlane {
+    /// Miss distance, along the X axis of the encounter plane, in km
+    pub miss_distance_km: f64,
+    /// Relative speed, along the Z axis of the encounter plane, in km/s
+    pub relative_speed_km_s: f64,
+    /// Combined position covariance in the encounter plane, in km^2
+    pub covar_km2: Matrix2<f64>,
+    /// Rotation from the frame of the close approach to the encounter plane (rows are the X, Y, and Z axes)
+    pub dcm: Matrix3<f64>,
+}
+
+impl EncounterPlane {
+    /// Builds the encounter plane of this close approach from the position covariances of both objects,
+    /// expressed in the inertial frame of the close approach, in km^2.
+    pub fn new(
+        approach: &CloseApproach,
+        primary_covar_km2: &Matrix3<f64>,
+        secondary_covar_km2: &Matrix3<f64>,
+    ) -> Result<Self, NyxError> {
+        let rel_r = approach.relative_position_km();
+        let rel_v = approach.relative_velocity_km_s();
+
+        let z_hat = rel_v.normalize();
+        // At TCA, the miss vector is perpendicular to the relative velocity, but remove any residual for robustness.
+        let miss = rel_r - z_hat * rel_r.dot(&z_hat);
+        let x_hat = if miss.norm() > f64::EPSILON {
+            miss.normalize()
+        } else {
+            // Direct hit: the orientation of the X axis in the encounter plane is arbitrary.
+            let axis = if z_hat.x.abs() < 0.9 {
+                Vector3::x()
+            } else {
+                Vector3::y()
+            };
+            z_hat.cross(&axis).normalize()
+        };
+        let y_hat = z_hat.cross(&x_hat);
+
+        let dcm = Matrix3::from_rows(&[x_hat.transpose(), y_hat.transpose(), z_hat.transpose()]);
+
+        let combined = dcm * (primary_covar_km2 + secondary_covar_km2) * dcm.transpose();
+        let covar_km2 = combined.fixed_view::<2, 2>(0, 0).into_owned();
+
+        if covar_km2.determinant() <= 0.0 || !covar_km2.iter().all(|x| x.is_finite()) {
+            return Err(NyxError::CovarianceMatrixNotPsd);
+        }
+
+        Ok(Self {
+            miss_distance_km: miss.norm(),
+            relative_speed_km_s: rel_v.norm(),
+            covar_km2,
+            dcm,
+        })
+    }
+
+    /// Computes the probability of collision by integrating the combined covariance over the hard body circle (Foster's method).
+    ///
+    /// The hard body radius is the sum of the radii of the spheres enclosing both objects.
+    ///
+    /// The integration grid is refined to resolve the smallest standard deviation of the combined covariance, up to
+    /// 4096 radial and 16384 angular steps. Beyond a hard body radius of about 300 times that standard deviation, the
+    /// grid no longer resolves the density and [Self::collision_probability_monte_carlo] should be used instead.
+    pub fn collision_probability(&self, hard_body_radius_km: f64) -> f64 {
+        let inv = self
+            .covar_km2
+            .try_inverse()
+            .expect("covariance checked on initialization");
+        let norm = 1.0 / (2.0 * PI * self.covar_km2.determinant().sqrt());
+
+        let density = |x: f64, y: f64| {
+            let d = Vector2::new(x - self.miss_distance_km, y);
+            norm * (-0.5 * d.dot(&(inv * d))).exp()
+        };
+
+        // Midpoint rule in polar coordinates centered on the hard body, which is exact enough for smooth densities
+        // provided that the steps are small compared to the smallest standard deviation.
+        let sigma_km = self.covar_km2.symmetric_eigenvalues().min().sqrt();
+        let steps = |span_km: f64, min: usize, max: usize| {
+            ((PC_STEPS_PER_SIGMA * span_km / sigma_km).ceil() as usize).clamp(min, max)
+        };
+        let radial_steps = steps(hard_body_radius_km, PC_RADIAL_STEPS, PC_MAX_RADIAL_STEPS);
+        let angular_steps = steps(
+            2.0 * PI * hard_body_radius_km,
+            PC_ANGULAR_STEPS,
+            PC_MAX_ANGULAR_STEPS,
+        );
+
+        let dr = hard_body_radius_km / radial_steps as f64;
+        let dtheta = 2.0 * PI / angular_steps as f64;
+        let mut pc = 0.0;
+        for i in 0..radial_steps {
+            let r = (i as f64 + 0.5) * dr;
+            for j in 0..angular_steps {
+                let theta = (j as f64 + 0.5) * dtheta;
+                pc += density(r * theta.cos(), r * theta.sin()) * r * dr * dtheta;
+            }
+        }
+
+        pc
+    }
+
+    /// Estimates the probability of collision by sampling the relative position in the encounter plane.
+    /// This is meant as a cross-check of [Self::collision_probability].
+    pub fn collision_probability_monte_carlo(
+        &self,
+        hard_body_radius_km: f64,
+        num_samples: usize,
+        seed: Option<u128>,
+    ) -> Result<f64, NyxError> {
+        let sqrt_covar = self
+            .covar_km2
+            .cholesky()
+            .ok_or(NyxError::CovarianceMatrixNotPsd)?
+            .l();
+
+        let mut rng = match seed {
+            Some(seed) => Pcg64Mcg::new(seed),
+            None => stream_rng("conjunction"),
+        };
+        let std_norm = Normal::new(0.0, 1.0).unwrap();
+
+        let mean = Vector2::new(self.miss_distance_km, 0.0);
+        let hits = (0..num_samples)
+            .filter(|_| {
+                let sample = Vector2::new(std_norm.sample(&mut rng), std_norm.sample(&mut rng));
+                (mean + sqrt_covar * sample).norm() <= hard_body_radius_km
+            })
+            .count();
+
+        Ok(hits as f64 / num_samples as f64)
+    }
+}
+
+impl fmt::Display for EncounterPlane {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "miss distance of {:.3} km at {:.6} km/s, combined 1-sigma of {:.3} km and {:.3} km",
+            self.miss_distance_km,
+            self.relative_speed_km_s,
+            self.covar_km2[(0, 0)].sqrt(),
+            self.covar_km2[(1, 1)].sqrt()
+        )
+    }
+}
+
+/// Close approach screening between trajectories.
+///
+/// The range rate between each pair of trajectories is sampled with the provided step over their common time span.
+/// Each change of sign from negative to positive brackets a time of closest approach, which is then refined
+/// by bisection to the provided tolerance. Only approaches closer than the threshold are reported.
+///
+/// The step must be small compared to the relative motion: at most one close approach may happen within a step.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ConjunctionScreening {
+    /// Miss distance below which a close approach is reported, in km
+    pub threshold_km: f64,
+    /// Sampling step of the range rate
+    pub step: Duration,
+    /// Precision of the time of closest approach
+    pub tolerance: Duration,
+}
+
+impl ConjunctionScreening {
+    /// Initializes a new screening with a one millisecond precision on the time of closest approach.
+    pub fn new(threshold_km: f64, step: Duration) -> Self {
+        Self {
+            threshold_km,
+            step,
+            tolerance: 1 * Unit::Millisecond,
+        }
+    }
+
+    /// Screens all pairs of trajectories and returns the close approaches sorted by TCA.
+    /// The indexes of the close approaches are those of the provided trajectories.
+    pub fn screen(
+        &self,
+        trajs: &[Traj<Spacecraft>],
+        almanac: Arc<Almanac>,
+    ) -> Result<Vec<CloseApproach>, NyxError> {
+        let mut approaches = Vec::new();
+        for (i, primary) in trajs.iter().enumerate() {
+            for (j, secondary) in trajs.iter().enumerate().skip(i + 1) {
+                for mut approach in self.screen_pair(primary, secondary, almanac.clone())? {
+                    approach.primary = i;
+                    approach.secondary = j;
+                    approaches.push(approach);
+                }
+            }
+        }
+
+        approaches.sort_by_key(|approach| approach.tca);
+        Ok(approaches)
+    }
+
+    /// Screens the secondary trajectory against the primary one, which are both numbered zero and one respectively.
+    ///
+    /// If the trajectories are in different frames, the secondary is transformed into the frame of the primary.
+    pub fn screen_pair(
+        &self,
+        primary: &Traj<Spacecraft>,
+        secondary: &Traj<Spacecraft>,
+        almanac: Arc<Almanac>,
+    ) -> Result<Vec<CloseApproach>, NyxError> {
+        let start = primary
+            .first()
+            .orbit
+            .epoch
+            .max(secondary.first().orbit.epoch);
+        let end = primary.last().orbit.epoch.min(secondary.last().orbit.epoch);
+        if end <= start {
+            return Ok(Vec::new());
+        }
+
+        let states_at = |epoch: Epoch| -> Result<(Spacecraft, Spacecraft), NyxError> {
+            let prim = primary.at(epoch)?;
+            let mut sec = secondary.at(epoch)?;
+            if sec.orbit.frame != prim.orbit.frame {
+                sec.orbit = almanac
+                    .transform_to(sec.orbit, prim.orbit.frame, None)
+                    .map_err(|e| NyxError::FromAlmanacError {
+                        source: Box::new(e),
+                        action: "transforming secondary trajectory for conjunction screening",
+                    })?;
+            }
+            Ok((prim, sec))
+        };
+
+        let range_rate = |epoch: Epoch| -> Result<f64, NyxError> {
+            let (prim, sec) = states_at(epoch)?;
+            let rel_r = sec.orbit.radius_km - prim.orbit.radius_km;
+            let rel_v = sec.orbit.velocity_km_s - prim.orbit.velocity_km_s;
+            Ok(rel_r.dot(&rel_v))
+        };
+
+        let mut epochs: Vec<Epoch> = TimeSeries::inclusive(start, end, self.step).collect();
+        if epochs.last() != Some(&end) {
+            epochs.push(end);
+        }
+
+        let mut approaches = Vec::new();
+        let mut prev = (epochs[0], range_rate(epochs[0])?);
+        for epoch in epochs.into_iter().skip(1) {
+            let next = (epoch, range_rate(epoch)?);
+            if prev.1 < 0.0 && next.1 >= 0.0 {
+                // Refine the zero of the range rate by bisection
+                let (mut lo, mut hi) = (prev.0, next.0);
+                while hi - lo > self.tolerance {
+                    let mid = lo + (hi - lo) * 0.5;
+                    if range_rate(mid)? < 0.0 {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+
+                let tca = lo + (hi - lo) * 0.5;
+                let (primary_state, secondary_state) = states_at(tca)?;
+                let approach = CloseApproach {
+                    primary: 0,
+                    secondary: 1,
+                    tca,
+                    primary_state,
+                    secondary_state,
+                };
+
+                if approach.miss_distance_km() < self.threshold_km {
+                    debug!("{approach}");
+                    approaches.push(approach);
+                }
+            }
+            prev = next;
+        }
+
+        Ok(approaches)
+    }
+}
+
+#[cfg(test)]
+mod ut_conjunction {
+    use super::*;
+    use crate::cosmic::Orbit;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::propagators::Propagator;
+    use crate::time::TimeUnits;
+
+    #[test]
+    fn crossing_orbits() {
+        let almanac = fixtures::almanac();
+        let eme2k = fixtures::eme2k();
+        let epoch = fixtures::epoch();
+
+        // Both orbits cross at their nodes, but the secondary is trailing by one degree.
+        let primary = Orbit::keplerian(7_000.0, 0.0, 0.0, 0.0, 0.0, 0.0, epoch, eme2k);
+        let secondary = Orbit::keplerian(7_000.0, 0.0, 10.0, 0.0, 0.0, -1.0, epoch, eme2k);
+        let period = primary.period().unwrap();
+
+        let setup = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let trajs = [primary, secondary]
+            .iter()
+            .map(|orbit| {
+                setup
+                    .with(Spacecraft::builder().orbit(*orbit).build(), almanac.clone())
+                    .for_duration_with_traj(period * 2.0)
+                    .unwrap()
+                    .1
+            })
+            .collect::<Vec<_>>();
+
+        let screening = ConjunctionScreening::new(500.0, 1.minutes());
+        let approaches = screening.screen(&trajs, almanac.clone()).unwrap();
+
+        for approach in &approaches {
+            println!("{approach}");
+        }
+
+        // One close approach per node crossing
+        assert_eq!(approaches.len(), 4);
+
+        for approach in &approaches {
+            assert_eq!((approach.primary, approach.secondary), (0, 1));
+
+            // Brute force the closest approach around the TCA
+            let brute_min_km = (-600..=600)
+                .filter_map(|i| {
+                    let epoch = approach.tca + (i as f64) * 0.1.seconds();
+                    let prim = trajs[0].at(epoch).ok()?;
+                    let sec = trajs[1].at(epoch).ok()?;
+                    Some((sec.orbit.radius_km - prim.orbit.radius_km).norm())
+                })
+                .fold(f64::INFINITY, f64::min);
+
+            assert!(approach.miss_distance_km() <= brute_min_km + 1e-6);
+            assert!(approach.relative_speed_km_s() > 1.0);
+
+            // The miss vector is perpendicular to the relative velocity
+            let cos_angle = approach
+                .relative_position_km()
+                .normalize()
+                .dot(&approach.relative_velocity_km_s().normalize());
+            assert!(cos_angle.abs() < 1e-5, "{cos_angle}");
+
+            let ric = approach.miss_ric_km().unwrap();
+            assert!((ric.norm() - approach.miss_distance_km()).abs() < 1e-9);
+        }
+
+        // Nothing closer than a kilometer
+        let tight = ConjunctionScreening::new(1.0, 1.minutes());
+        assert!(tight.screen(&trajs, almanac).unwrap().is_empty());
+    }
+
+    #[test]
+    fn probability_of_collision() {
+        let eme2k = fixtures::eme2k();
+        let epoch = fixtures::epoch();
+
+        let approach_with_miss = |miss_km: f64| {
+            let primary = Orbit::cartesian(7_000.0, 0.0, 0.0, 0.0, 7.5, 0.0, epoch, eme2k);
+            let secondary = Orbit::cartesian(7_000.0, miss_km, 0.0, 0.0, 7.5, 10.0, epoch, eme2k);
+            CloseApproach {
+                primary: 0,
+                secondary: 1,
+                tca: epoch,
+                primary_state: Spacecraft::builder().orbit(primary).build(),
+                secondary_state: Spacecraft::builder().orbit(secondary).build(),
+            }
+        };
+
+        // Isotropic covariance of 100 m on each object
+        let covar_km2 = Matrix3::identity() * 0.1_f64.powi(2);
+        let sigma2_km2 = 2.0 * 0.1_f64.powi(2);
+        let hbr_km: f64 = 0.02;
+
+        // Direct hit: analytical probability of a circle centered on an isotropic distribution
+        let plane = EncounterPlane::new(&approach_with_miss(0.0), &covar_km2, &covar_km2).unwrap();
+        println!("{plane}");
+        let expected = 1.0 - (-hbr_km.powi(2) / (2.0 * sigma2_km2)).exp();
+        let pc = plane.collision_probability(hbr_km);
+        assert!(
+            (pc - expected).abs() / expected < 1e-4,
+            "{pc} != {expected}"
+        );
+
+        // Small hard body radius compared to the miss distance and the covariance
+        let miss_km: f64 = 0.2;
+        let plane =
+            EncounterPlane::new(&approach_with_miss(miss_km), &covar_km2, &covar_km2).unwrap();
+        assert!((plane.miss_distance_km - miss_km).abs() < 1e-12);
+        assert!((plane.relative_speed_km_s - 10.0).abs() < 1e-12);
+        let expected =
+            hbr_km.powi(2) / (2.0 * sigma2_km2) * (-miss_km.powi(2) / (2.0 * sigma2_km2)).exp();
+        let pc = plane.collision_probability(hbr_km);
+        assert!(
+            (pc - expected).abs() / expected < 1e-2,
+            "{pc} != {expected}"
+        );
+
+        let pc_mc = plane
+            .collision_probability_monte_carlo(hbr_km, 1_000_000, Some(0))
+            .unwrap();
+        assert!((pc_mc - pc).abs() / pc < 0.1, "{pc_mc} != {pc}");
+
+        // Probability drops with the miss distance
+        let far = EncounterPlane::new(&approach_with_miss(1.0), &covar_km2, &covar_km2).unwrap();
+        assert!(far.collision_probability(hbr_km) < 1e-10);
+
+        // Off TCA, the miss distance is in the encounter plane only and excludes the along track separation
+        let mut approach = approach_with_miss(miss_km);
+        approach.secondary_state.orbit.radius_km.z += 0.5;
+        let plane = EncounterPlane::new(&approach, &covar_km2, &covar_km2).unwrap();
+        assert!((plane.miss_distance_km - miss_km).abs() < 1e-12);
+        assert!((plane.collision_probability(hbr_km) - pc).abs() < 1e-12);
+
+        // Covariance of 20 cm, much smaller than a hard body radius of 30 m grazed by the miss vector:
+        // half of the distribution lies within the hard body.
+        let covar_km2 = Matrix3::identity() * 2e-4_f64.powi(2);
+        let hbr_km = 0.03;
+        let plane =
+            EncounterPlane::new(&approach_with_miss(hbr_km), &covar_km2, &covar_km2).unwrap();
+        let pc = plane.collision_probability(hbr_km);
+        assert!((pc - 0.5).abs() < 1e-2, "{pc} != 0.5");
+        let pc_mc = plane
+            .collision_probability_monte_carlo(hbr_km, 1_000_000, Some(0))
+            .unwrap();
+        assert!((pc_mc - pc).abs() < 5e-3, "{pc_mc} != {pc}");
+    }
+}
@@ -0,0 +1,326 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{Cr3bp, PeriodicOrbit};
+use crate::cosmic::Frame;
+use crate::dynamics::SpacecraftDynamics;
+use crate::errors::NyxError;
+use crate::linalg::{Matrix6, Vector6};
+use crate::md::prelude::Traj;
+use crate::propagators::Propagator;
+use crate::time::{Duration, Epoch, Unit};
+use crate::Spacecraft;
+use anise::almanac::Almanac;
+use std::fmt;
+use std::sync::Arc;
+use typed_builder::TypedBuilder;
+
+/// Stability of an invariant manifold of a periodic orbit.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ManifoldStability {
+    /// Trajectories which asymptotically arrive onto the periodic orbit, computed by propagating backward in time
+    Stable,
+    /// Trajectories which asymptotically depart from the periodic orbit, computed by propagating forward in time
+    Unstable,
+}
+
+/// Branch of an invariant manifold, i.e. the side of the periodic orbit along the eigenvector.
+///
+/// The eigenvector of the monodromy matrix is oriented such that its X component is positive at the initial state of the
+/// periodic orbit: about L1 of the Earth-Moon system, the positive branch departs toward the Moon.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ManifoldBranch {
+    Positive,
+    Negative,
+}
+
+/// Configuration of the computation of an invariant manifold.
+#[derive(Copy, Clone, Debug, TypedBuilder)]
+#[builder(doc)]
+pub struct ManifoldConfig {
+    pub stability: ManifoldStability,
+    #[builder(default = ManifoldBranch::Positive)]
+    pub branch: ManifoldBranch,
+    /// Number of arcs, equally spaced in time along the periodic orbit
+    #[builder(default = 50)]
+    pub num_arcs: usize,
+    /// Magnitude of the position perturbation along the eigenvector, in km
+    #[builder(default = 50.0)]
+    pub perturbation_km: f64,
+    /// Propagation duration of each arc (backward for stable manifolds)
+    #[builder(default = Unit::Day * 30)]
+    pub duration: Duration,
+}
+
+/// A trajectory of an invariant manifold, in the CR3BP.
+#[derive(Clone, Debug)]
+pub struct ManifoldArc {
+    pub stability: ManifoldStability,
+    /// Fraction of the period of the departure point on the periodic orbit, in [0, 1)
+    pub phase: f64,
+    /// Nondimensional state on the periodic orbit from which this arc departs
+    pub orbit_state: Vector6<f64>,
+    /// Nondimensional time and state of each integration step, starting with the perturbed state (time decreases for
+    /// stable manifolds)
+    pub steps: Vec<(f64, Vector6<f64>)>,
+}
+
+impl PeriodicOrbit {
+    /// Computes the invariant manifold of this periodic orbit with the provided configuration.
+    ///
+    /// Each arc starts at a point of the orbit, perturbed along the stable or unstable eigenvector of the monodromy
+    /// matrix mapped to that point with the state transition matrix. Arcs which fail to propagate (e.g. because they
+    /// impact a primary) are skipped with a warning.
+    pub fn manifold(
+        &self,
+        system: &Cr3bp,
+        config: ManifoldConfig,
+    ) -> Result<Vec<ManifoldArc>, NyxError> {
+        if config.num_arcs == 0 {
+            return Err(NyxError::CustomError {
+                msg: "an invariant manifold requires at least one arc".to_string(),
+            });
+        }
+
+        let eigenvector = self.eigenvector(config.stability)?;
+        let sign = match config.branch {
+            ManifoldBranch::Positive => 1.0,
+            ManifoldBranch::Negative => -1.0,
+        };
+        let perturbation = sign * config.perturbation_km / system.length_km;
+        let time = match config.stability {
+            ManifoldStability::Stable => -1.0,
+            ManifoldStability::Unstable => 1.0,
+        } * config.duration.to_seconds()
+            / system.time_s;
+
+        let step = self.period / config.num_arcs as f64;
+        let mut state = self.state;
+        let mut stm = Matrix6::identity();
+        let mut arcs = Vec::with_capacity(config.num_arcs);
+
+        for i in 0..config.num_arcs {
+            if i > 0 {
+                let (next, step_stm) = system.propagate_with_stm(&state, step)?;
+                state = next;
+                stm = step_stm * stm;
+            }
+
+            let direction = stm * eigenvector;
+            let direction = direction / direction.fixed_rows::<3>(0).norm();
+            let phase = i as f64 / config.num_arcs as f64;
+
+            match system.propagate_with_steps(&(state + perturbation * direction), time) {
+                Ok(steps) => arcs.push(ManifoldArc {
+                    stability: config.stability,
+                    phase,
+                    orbit_state: state,
+                    steps,
+                }),
+                Err(e) => warn!("skipping the manifold arc at phase {phase:.3}: {e}"),
+            }
+        }
+
+        Ok(arcs)
+    }
+
+    /// Returns the real eigenvector of the monodromy matrix of the provided stability, oriented with a positive X
+    /// component and normalized in position.
+    fn eigenvector(&self, stability: ManifoldStability) -> Result<Vector6<f64>, NyxError> {
+        let real_eigenvalues = self
+            .eigenvalues()
+            .into_iter()
+            .filter(|eigenvalue| eigenvalue.im.abs() <= 1e-9 * eigenvalue.norm())
+            .map(|eigenvalue| eigenvalue.re);
+
+        let eigenvalue = match stability {
+            ManifoldStability::Unstable => {
+                real_eigenvalues.max_by(|a, b| a.abs().total_cmp(&b.abs()))
+            }
+            ManifoldStability::Stable => {
+                real_eigenvalues.min_by(|a, b| a.abs().total_cmp(&b.abs()))
+            }
+        }
+        .filter(|eigenvalue| (eigenvalue.abs() - 1.0).abs() > 1e-6)
+        .ok_or_else(|| NyxError::MathDomain {
+            msg: format!("periodic orbit is linearly stable and has no {stability:?} manifold"),
+        })?;
+
+        // The eigenvector spans the null space of M - λI, i.e. it is the right singular vector of its smallest singular value.
+        let svd = (self.monodromy - Matrix6::identity() * eigenvalue).svd(false, true);
+        let v_t = svd.v_t.ok_or_else(|| NyxError::MathDomain {
+            msg: "SVD of the monodromy matrix failed".to_string(),
+        })?;
+        let (smallest, _) = svd
+            .singular_values
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap();
+
+        let eigenvector = v_t.row(smallest).transpose();
+        let scale = eigenvector.fixed_rows::<3>(0).norm();
+        Ok(eigenvector * eigenvector[0].signum() / scale)
+    }
+}
+
+impl ManifoldArc {
+    /// Returns the nondimensional perturbed state from which this arc is propagated.
+    pub fn initial_state(&self) -> Vector6<f64> {
+        self.steps[0].1
+    }
+
+    /// Returns the nondimensional state at the end of this arc.
+    pub fn final_state(&self) -> Vector6<f64> {
+        self.steps[self.steps.len() - 1].1
+    }
+
+    /// Returns the nondimensional propagation time of this arc, negative for stable manifolds.
+    pub fn time(&self) -> f64 {
+        self.steps[self.steps.len() - 1].0
+    }
+
+    /// Globalizes this arc into the ephemeris model: the initial state is converted into an inertial state about the
+    /// primary at the provided epoch (cf. [Cr3bp::to_inertial]), and propagated with the provided dynamics for the
+    /// duration of the arc, backward for stable manifolds.
+    ///
+    /// The propagator should model the gravity of the secondary (and likely of the Sun), e.g. with point masses.
+    pub fn globalize(
+        &self,
+        system: &Cr3bp,
+        epoch: Epoch,
+        primary: Frame,
+        secondary: Frame,
+        prop: &Propagator<SpacecraftDynamics>,
+        almanac: Arc<Almanac>,
+    ) -> Result<Traj<Spacecraft>, NyxError> {
+        let orbit =
+            system.to_inertial(&self.initial_state(), epoch, primary, secondary, &almanac)?;
+        let (_, traj) = prop
+            .with(Spacecraft::builder().orbit(orbit).build(), almanac)
+            .for_duration_with_traj(system.to_duration(self.time()))
+            .map_err(|e| NyxError::CustomError {
+                msg: format!(
+                    "globalizing the manifold arc at phase {:.3}: {e}",
+                    self.phase
+                ),
+            })?;
+        Ok(traj)
+    }
+}
+
+impl fmt::Display for ManifoldArc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let end = self.final_state();
+        write!(
+            f,
+            "{:?} manifold arc at phase {:.3}: t = {:.6}\tx = {:.6}\ty = {:.6}\tz = {:.6}",
+            self.stability,
+            self.phase,
+            self.time(),
+            end[0],
+            end[1],
+            end[2]
+        )
+    }
+}
+
+#[cfg(test)]
+mod ut_manifold {
+    use super::*;
+    use crate::tools::cr3bp::LibrationPoint;
+
+    #[test]
+    fn lyapunov_manifolds() {
+        let system = Cr3bp::earth_moon();
+        let orbit = PeriodicOrbit::lyapunov(&system, LibrationPoint::L1, 0.01).unwrap();
+        let lambda = orbit
+            .eigenvalues()
+            .iter()
+            .map(|eigenvalue| eigenvalue.norm())
+            .fold(0.0, f64::max);
+
+        for stability in [ManifoldStability::Unstable, ManifoldStability::Stable] {
+            // A small perturbation grows by the largest eigenvalue after one period, forward along the unstable
+            // eigenvector and backward along the stable one.
+            let eigenvector = orbit.eigenvector(stability).unwrap();
+            let time = match stability {
+                ManifoldStability::Stable => -orbit.period,
+                ManifoldStability::Unstable => orbit.period,
+            };
+            let delta = 1e-9 * eigenvector;
+            let growth = (system.propagate(&(orbit.state + delta), time).unwrap()
+                - system.propagate(&orbit.state, time).unwrap())
+            .norm()
+                / delta.norm();
+            assert!(
+                (growth / lambda - 1.0).abs() < 1e-2,
+                "{stability:?}: {growth} vs {lambda}"
+            );
+
+            for branch in [ManifoldBranch::Positive, ManifoldBranch::Negative] {
+                let config = ManifoldConfig::builder()
+                    .stability(stability)
+                    .branch(branch)
+                    .num_arcs(4)
+                    .duration(Unit::Day * 15)
+                    .build();
+                let arcs = orbit.manifold(&system, config).unwrap();
+                assert_eq!(arcs.len(), 4);
+                for arc in &arcs {
+                    println!("{arc}");
+                    assert!(
+                        (arc.initial_state() - arc.orbit_state)
+                            .fixed_rows::<3>(0)
+                            .norm()
+                            * system.length_km
+                            - 50.0
+                            < 1e-9
+                    );
+                    assert!(
+                        (system.jacobi_constant(&arc.initial_state()) - orbit.jacobi).abs() < 1e-3
+                    );
+                    assert!(
+                        (system.jacobi_constant(&arc.final_state())
+                            - system.jacobi_constant(&arc.initial_state()))
+                        .abs()
+                            < 1e-10
+                    );
+                    assert!(
+                        (system.to_duration(arc.time().abs()) - Unit::Day * 15).abs()
+                            < Unit::Second * 1
+                    );
+                }
+                // About L1, the positive branch heads toward the Moon and the negative one toward the Earth.
+                let x_l1 = system.libration_point(LibrationPoint::L1).x;
+                for arc in &arcs {
+                    let (min_x, max_x) = arc
+                        .steps
+                        .iter()
+                        .fold((f64::MAX, f64::MIN), |(lo, hi), (_, x)| {
+                            (lo.min(x[0]), hi.max(x[0]))
+                        });
+                    match branch {
+                        ManifoldBranch::Positive => assert!(max_x > x_l1 + 0.1, "{arc}"),
+                        ManifoldBranch::Negative => assert!(min_x < x_l1 - 0.5, "{arc}"),
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,581 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::cosmic::{Frame, Orbit};
+use crate::errors::NyxError;
+use crate::linalg::{Matrix3, Matrix6, SVector, Vector3, Vector6};
+use crate::propagators::IntegratorMethod;
+use crate::time::{Duration, Epoch, Unit};
+use anise::almanac::Almanac;
+use std::fmt;
+
+/// Stable and unstable invariant manifolds of periodic orbits.
+mod manifold;
+/// Periodic orbits of the CR3BP and their continuation in families.
+mod periodic;
+pub use manifold::*;
+pub use periodic::*;
+
+/// Integrator of the CR3BP equations of motion
+const METHOD: IntegratorMethod = IntegratorMethod::Dop853;
+/// Relative tolerance of the integration of the CR3BP equations of motion
+const TOLERANCE: f64 = 1e-13;
+/// Maximum number of integration steps of a single propagation
+const MAX_STEPS: usize = 1_000_000;
+
+/// Libration (Lagrange) points of the CR3BP.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LibrationPoint {
+    /// Collinear point between the primaries
+    L1,
+    /// Collinear point beyond the secondary
+    L2,
+    /// Collinear point beyond the primary
+    L3,
+    /// Equilateral point leading the secondary
+    L4,
+    /// Equilateral point trailing the secondary
+    L5,
+}
+
+impl fmt::Display for LibrationPoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// Circular restricted three body problem (CR3BP) between a primary and a secondary on circular orbits about their barycenter.
+///
+/// States are nondimensional and expressed in the synodic (rotating) frame centered on the barycenter, whose X axis points
+/// from the primary to the secondary and whose Z axis is along the angular momentum of the primaries. The primary is at
+/// (-μ, 0, 0) and the secondary at (1 - μ, 0, 0). The characteristic length is the distance between the primaries and the
+/// characteristic time is the inverse of their mean motion.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Cr3bp {
+    /// Mass ratio μ = m2 / (m1 + m2)
+    pub mu: f64,
+    /// Characteristic length, in km
+    pub length_km: f64,
+    /// Characteristic time, in seconds
+    pub time_s: f64,
+}
+
+impl Cr3bp {
+    /// Initializes the CR3BP from the gravitational parameters of the primary and the secondary, and their distance.
+    pub fn from_gm(primary_gm_km3_s2: f64, secondary_gm_km3_s2: f64, distance_km: f64) -> Self {
+        let total_gm_km3_s2 = primary_gm_km3_s2 + secondary_gm_km3_s2;
+        Self {
+            mu: secondary_gm_km3_s2 / total_gm_km3_s2,
+            length_km: distance_km,
+            time_s: (distance_km.powi(3) / total_gm_km3_s2).sqrt(),
+        }
+    }
+
+    /// Earth-Moon system, with the DE440 gravitational parameters and the mean distance of the Moon.
+    pub fn earth_moon() -> Self {
+        Self::from_gm(398_600.435_436, 4_902.800_066, 384_400.0)
+    }
+
+    /// Sun-Earth/Moon system, with the DE440 gravitational parameters and one astronomical unit.
+    pub fn sun_earth() -> Self {
+        Self::from_gm(
+            132_712_440_041.939_4,
+            398_600.435_436 + 4_902.800_066,
+            149_597_870.7,
+        )
+    }
+
+    /// Returns the characteristic velocity, in km/s
+    pub fn velocity_km_s(&self) -> f64 {
+        self.length_km / self.time_s
+    }
+
+    /// Converts a nondimensional time into a duration.
+    pub fn to_duration(&self, time: f64) -> Duration {
+        Unit::Second * (time * self.time_s)
+    }
+
+    /// Converts a nondimensional state into km and km/s, still in the synodic frame.
+    pub fn to_dimensional(&self, state: &Vector6<f64>) -> Vector6<f64> {
+        let mut dim = *state;
+        dim.fixed_rows_mut::<3>(0).scale_mut(self.length_km);
+        dim.fixed_rows_mut::<3>(3).scale_mut(self.velocity_km_s());
+        dim
+    }
+
+    /// Converts a state in km and km/s in the synodic frame into a nondimensional state.
+    pub fn to_nondimensional(&self, state_km: &Vector6<f64>) -> Vector6<f64> {
+        let mut nondim = *state_km;
+        nondim
+            .fixed_rows_mut::<3>(0)
+            .scale_mut(1.0 / self.length_km);
+        nondim
+            .fixed_rows_mut::<3>(3)
+            .scale_mut(1.0 / self.velocity_km_s());
+        nondim
+    }
+
+    /// Distances from the primary and the secondary
+    fn distances(&self, r: &Vector3<f64>) -> (f64, f64) {
+        let r1 = Vector3::new(r.x + self.mu, r.y, r.z).norm();
+        let r2 = Vector3::new(r.x - 1.0 + self.mu, r.y, r.z).norm();
+        (r1, r2)
+    }
+
+    /// Returns the pseudo-potential U = (x² + y²) / 2 + (1 - μ) / r1 + μ / r2.
+    pub fn pseudo_potential(&self, state: &Vector6<f64>) -> f64 {
+        let r = state.fixed_rows::<3>(0).into_owned();
+        let (r1, r2) = self.distances(&r);
+        0.5 * (r.x * r.x + r.y * r.y) + (1.0 - self.mu) / r1 + self.mu / r2
+    }
+
+    /// Returns the gradient of the pseudo-potential with respect to the position.
+    pub fn pseudo_potential_gradient(&self, r: &Vector3<f64>) -> Vector3<f64> {
+        let (r1, r2) = self.distances(r);
+        let (k1, k2) = ((1.0 - self.mu) / r1.powi(3), self.mu / r2.powi(3));
+        Vector3::new(
+            r.x - k1 * (r.x + self.mu) - k2 * (r.x - 1.0 + self.mu),
+            r.y - k1 * r.y - k2 * r.y,
+            -k1 * r.z - k2 * r.z,
+        )
+    }
+
+    /// Returns the Hessian of the pseudo-potential with respect to the position.
+    pub fn pseudo_potential_hessian(&self, r: &Vector3<f64>) -> Matrix3<f64> {
+        let (r1, r2) = self.distances(r);
+        let (k1, k2) = ((1.0 - self.mu) / r1.powi(3), self.mu / r2.powi(3));
+        let (l1, l2) = (
+            3.0 * (1.0 - self.mu) / r1.powi(5),
+            3.0 * self.mu / r2.powi(5),
+        );
+        let d1 = Vector3::new(r.x + self.mu, r.y, r.z);
+        let d2 = Vector3::new(r.x - 1.0 + self.mu, r.y, r.z);
+        let mut hessian =
+            l1 * d1 * d1.transpose() + l2 * d2 * d2.transpose() - (k1 + k2) * Matrix3::identity();
+        hessian[(0, 0)] += 1.0;
+        hessian[(1, 1)] += 1.0;
+        hessian
+    }
+
+    /// Returns the Jacobi constant C = 2U - v², which is conserved along CR3BP trajectories.
+    pub fn jacobi_constant(&self, state: &Vector6<f64>) -> f64 {
+        2.0 * self.pseudo_potential(state) - state.fixed_rows::<3>(3).norm_squared()
+    }
+
+    /// Returns the time derivative of the state.
+    pub fn eom(&self, state: &Vector6<f64>) -> Vector6<f64> {
+        let grad = self.pseudo_potential_gradient(&state.fixed_rows::<3>(0).into_owned());
+        Vector6::new(
+            state[3],
+            state[4],
+            state[5],
+            2.0 * state[4] + grad.x,
+            -2.0 * state[3] + grad.y,
+            grad.z,
+        )
+    }
+
+    /// Returns the Jacobian of the equations of motion, i.e. the linearized dynamics about the provided state.
+    pub fn jacobian(&self, state: &Vector6<f64>) -> Matrix6<f64> {
+        let hessian = self.pseudo_potential_hessian(&state.fixed_rows::<3>(0).into_owned());
+        let mut a = Matrix6::zeros();
+        a.fixed_view_mut::<3, 3>(0, 3)
+            .copy_from(&Matrix3::identity());
+        a.fixed_view_mut::<3, 3>(3, 0).copy_from(&hessian);
+        a[(3, 4)] = 2.0;
+        a[(4, 3)] = -2.0;
+        a
+    }
+
+    /// Returns the nondimensional position of the provided libration point.
+    pub fn libration_point(&self, point: LibrationPoint) -> Vector3<f64> {
+        let mu = self.mu;
+        let hill = (mu / 3.0).cbrt();
+        let x0 = match point {
+            LibrationPoint::L1 => 1.0 - mu - hill,
+            LibrationPoint::L2 => 1.0 - mu + hill,
+            LibrationPoint::L3 => -1.0 - 5.0 * mu / 12.0,
+            LibrationPoint::L4 => return Vector3::new(0.5 - mu, 3.0_f64.sqrt() / 2.0, 0.0),
+            LibrationPoint::L5 => return Vector3::new(0.5 - mu, -(3.0_f64.sqrt()) / 2.0, 0.0),
+        };
+
+        // Newton iterations on the X component of the gradient of the pseudo-potential along the X axis
+        let mut x = x0;
+        for _ in 0..50 {
+            let r = Vector3::new(x, 0.0, 0.0);
+            let step =
+                self.pseudo_potential_gradient(&r).x / self.pseudo_potential_hessian(&r)[(0, 0)];
+            x -= step;
+            if step.abs() < 1e-15 {
+                break;
+            }
+        }
+        Vector3::new(x, 0.0, 0.0)
+    }
+
+    /// Propagates the nondimensional state for the provided nondimensional time, which may be negative.
+    pub fn propagate(&self, state: &Vector6<f64>, time: f64) -> Result<Vector6<f64>, NyxError> {
+        let eom = |x: &Vector6<f64>| self.eom(x);
+        let (_, final_state) = integrate(&eom, state, time, |_, _| false)?;
+        Ok(final_state)
+    }
+
+    /// Propagates the nondimensional state and its state transition matrix for the provided nondimensional time.
+    pub fn propagate_with_stm(
+        &self,
+        state: &Vector6<f64>,
+        time: f64,
+    ) -> Result<(Vector6<f64>, Matrix6<f64>), NyxError> {
+        let (_, augmented) = integrate(
+            &|x: &SVector<f64, 42>| self.eom_with_stm(x),
+            &augment(state),
+            time,
+            |_, _| false,
+        )?;
+        Ok(split(&augmented))
+    }
+
+    /// Propagates the nondimensional state, returning every integration step as the nondimensional time and state.
+    pub fn propagate_with_steps(
+        &self,
+        state: &Vector6<f64>,
+        time: f64,
+    ) -> Result<Vec<(f64, Vector6<f64>)>, NyxError> {
+        let mut steps = vec![(0.0, *state)];
+        integrate(&|x: &Vector6<f64>| self.eom(x), state, time, |t, x| {
+            steps.push((t, *x));
+            false
+        })?;
+        Ok(steps)
+    }
+
+    /// Converts a nondimensional synodic state into an inertial orbit about the primary at the provided epoch.
+    ///
+    /// The synodic frame is built from the ephemerides of the primaries in the almanac: its X axis points from the primary
+    /// to the secondary and its Z axis is along their instantaneous angular momentum. Positions are scaled by the
+    /// instantaneous distance between the primaries, and velocities by that distance and their instantaneous angular rate,
+    /// which is how CR3BP solutions are transitioned into the ephemeris model.
+    pub fn to_inertial(
+        &self,
+        state: &Vector6<f64>,
+        epoch: Epoch,
+        primary: Frame,
+        secondary: Frame,
+        almanac: &Almanac,
+    ) -> Result<Orbit, NyxError> {
+        let axes = SynodicAxes::new(epoch, primary, secondary, almanac)?;
+        let pos_vel = axes.to_inertial(self, state);
+        Ok(Orbit::from_cartesian_pos_vel(pos_vel, epoch, primary))
+    }
+
+    /// Converts an inertial orbit about the primary into a nondimensional synodic state, the inverse of [Self::to_inertial].
+    pub fn from_inertial(
+        &self,
+        orbit: Orbit,
+        secondary: Frame,
+        almanac: &Almanac,
+    ) -> Result<Vector6<f64>, NyxError> {
+        let axes = SynodicAxes::new(orbit.epoch, orbit.frame, secondary, almanac)?;
+        Ok(axes.to_synodic(self, &orbit.to_cartesian_pos_vel()))
+    }
+
+    /// Equations of motion of the state augmented with its state transition matrix (column major).
+    fn eom_with_stm(&self, augmented: &SVector<f64, 42>) -> SVector<f64, 42> {
+        let (state, stm) = split(augmented);
+        let mut deriv = SVector::<f64, 42>::zeros();
+        deriv.fixed_rows_mut::<6>(0).copy_from(&self.eom(&state));
+        deriv
+            .fixed_rows_mut::<36>(6)
+            .copy_from_slice((self.jacobian(&state) * stm).as_slice());
+        deriv
+    }
+}
+
+impl fmt::Display for Cr3bp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "CR3BP with μ = {:.12}, L* = {:.3} km, T* = {:.3} s",
+            self.mu, self.length_km, self.time_s
+        )
+    }
+}
+
+/// Instantaneous synodic frame of the primaries, from their ephemerides.
+struct SynodicAxes {
+    /// Rotation from the synodic frame into the inertial frame of the primary
+    dcm: Matrix3<f64>,
+    /// Position of the secondary with respect to the primary, in km
+    radius_km: Vector3<f64>,
+    /// Velocity of the secondary with respect to the primary, in km/s
+    velocity_km_s: Vector3<f64>,
+    /// Distance between the primaries, in km
+    distance_km: f64,
+    /// Rate of change of the distance between the primaries, in km/s
+    distance_rate_km_s: f64,
+    /// Angular velocity of the synodic frame, in rad/s
+    omega_rad_s: Vector3<f64>,
+}
+
+impl SynodicAxes {
+    fn new(
+        epoch: Epoch,
+        primary: Frame,
+        secondary: Frame,
+        almanac: &Almanac,
+    ) -> Result<Self, NyxError> {
+        let secondary_state = almanac
+            .transform(secondary, primary, epoch, None)
+            .map_err(|e| NyxError::FromAlmanacError {
+                source: Box::new(e),
+                action: "computing the synodic frame of the primaries",
+            })?;
+
+        Ok(Self::from_secondary(
+            secondary_state.radius_km,
+            secondary_state.velocity_km_s,
+        ))
+    }
+
+    /// Builds the synodic frame from the position and velocity of the secondary with respect to the primary.
+    fn from_secondary(radius_km: Vector3<f64>, velocity_km_s: Vector3<f64>) -> Self {
+        let distance_km = radius_km.norm();
+        let omega_rad_s = radius_km.cross(&velocity_km_s) / distance_km.powi(2);
+
+        let x_hat = radius_km / distance_km;
+        let z_hat = omega_rad_s.normalize();
+        let y_hat = z_hat.cross(&x_hat);
+
+        Self {
+            dcm: Matrix3::from_columns(&[x_hat, y_hat, z_hat]),
+            radius_km,
+            velocity_km_s,
+            distance_km,
+            distance_rate_km_s: radius_km.dot(&velocity_km_s) / distance_km,
+            omega_rad_s,
+        }
+    }
+
+    /// Converts a nondimensional synodic state into an inertial position and velocity about the primary.
+    fn to_inertial(&self, system: &Cr3bp, state: &Vector6<f64>) -> Vector6<f64> {
+        let rho_km = self.dcm * (self.distance_km * state.fixed_rows::<3>(0));
+        let rho_dot_km_s = self.dcm
+            * (self.distance_rate_km_s * state.fixed_rows::<3>(0)
+                + self.distance_km * self.omega_rad_s.norm() * state.fixed_rows::<3>(3));
+
+        let mut pos_vel = Vector6::zeros();
+        pos_vel
+            .fixed_rows_mut::<3>(0)
+            .copy_from(&(system.mu * self.radius_km + rho_km));
+        pos_vel.fixed_rows_mut::<3>(3).copy_from(
+            &(system.mu * self.velocity_km_s + rho_dot_km_s + self.omega_rad_s.cross(&rho_km)),
+        );
+        pos_vel
+    }
+
+    /// Converts an inertial position and velocity about the primary into a nondimensional synodic state.
+    fn to_synodic(&self, system: &Cr3bp, pos_vel: &Vector6<f64>) -> Vector6<f64> {
+        let rho_km = pos_vel.fixed_rows::<3>(0) - system.mu * self.radius_km;
+        let rho_dot_km_s = pos_vel.fixed_rows::<3>(3)
+            - system.mu * self.velocity_km_s
+            - self.omega_rad_s.cross(&rho_km);
+
+        let position = self.dcm.transpose() * rho_km / self.distance_km;
+        let velocity = (self.dcm.transpose() * rho_dot_km_s - self.distance_rate_km_s * position)
+            / (self.distance_km * self.omega_rad_s.norm());
+
+        let mut state = Vector6::zeros();
+        state.fixed_rows_mut::<3>(0).copy_from(&position);
+        state.fixed_rows_mut::<3>(3).copy_from(&velocity);
+        state
+    }
+}
+
+/// Returns the state augmented with an identity state transition matrix.
+fn augment(state: &Vector6<f64>) -> SVector<f64, 42> {
+    let mut augmented = SVector::<f64, 42>::zeros();
+    augmented.fixed_rows_mut::<6>(0).copy_from(state);
+    augmented
+        .fixed_rows_mut::<36>(6)
+        .copy_from_slice(Matrix6::<f64>::identity().as_slice());
+    augmented
+}
+
+/// Splits the augmented state into the state and its state transition matrix.
+fn split(augmented: &SVector<f64, 42>) -> (Vector6<f64>, Matrix6<f64>) {
+    (
+        augmented.fixed_rows::<6>(0).into_owned(),
+        Matrix6::from_column_slice(&augmented.as_slice()[6..]),
+    )
+}
+
+/// Integrates the equations of motion for the provided time with an adaptive Runge Kutta, calling `stop` after every
+/// accepted step with the time and state. Integration stops early when `stop` returns true.
+///
+/// Returns the final time and state.
+fn integrate<const N: usize, F, S>(
+    eom: &F,
+    state: &SVector<f64, N>,
+    time: f64,
+    mut stop: S,
+) -> Result<(f64, SVector<f64, N>), NyxError>
+where
+    F: Fn(&SVector<f64, N>) -> SVector<f64, N>,
+    S: FnMut(f64, &SVector<f64, N>) -> bool,
+{
+    let stages = METHOD.stages();
+    let (a_coeffs, b_coeffs) = (METHOD.a_coeffs(), METHOD.b_coeffs());
+    let order = f64::from(METHOD.order());
+
+    let mut t = 0.0;
+    let mut x = *state;
+    let mut h = time.signum() * time.abs().min(1e-2);
+    let mut k = vec![SVector::<f64, N>::zeros(); stages];
+
+    for _ in 0..MAX_STEPS {
+        if (time - t).abs() <= f64::EPSILON * time.abs().max(1.0) {
+            return Ok((t, x));
+        }
+        // Do not step past the requested time
+        if (t + h - time) * time.signum() > 0.0 {
+            h = time - t;
+        }
+
+        k[0] = eom(&x);
+        let mut a_idx = 0;
+        for i in 1..stages {
+            let mut wi = SVector::<f64, N>::zeros();
+            for kj in &k[0..i] {
+                wi += a_coeffs[a_idx] * kj;
+                a_idx += 1;
+            }
+            k[i] = eom(&(x + h * wi));
+        }
+
+        let mut next = x;
+        let mut error = SVector::<f64, N>::zeros();
+        for (i, ki) in k.iter().enumerate() {
+            next += h * b_coeffs[i] * ki;
+            error += h * (b_coeffs[i] - b_coeffs[i + stages]) * ki;
+        }
+
+        let error_norm = error
+            .iter()
+            .zip(next.iter())
+            .map(|(err, xi)| err.abs() / (TOLERANCE * (1.0 + xi.abs())))
+            .fold(0.0, f64::max);
+
+        if !error_norm.is_finite() {
+            return Err(NyxError::MathDomain {
+                msg: format!("CR3BP integration diverged at t = {t}"),
+            });
+        }
+
+        let factor = if error_norm > 0.0 {
+            (0.9 * error_norm.powf(-1.0 / order)).clamp(0.2, 5.0)
+        } else {
+            5.0
+        };
+
+        if error_norm <= 1.0 {
+            t += h;
+            x = next;
+            if stop(t, &x) {
+                return Ok((t, x));
+            }
+        }
+        h *= factor;
+    }
+
+    Err(NyxError::MaxIterReached {
+        msg: format!("{MAX_STEPS} CR3BP integration steps"),
+    })
+}
+
+#[cfg(test)]
+mod ut_cr3bp {
+    use super::*;
+
+    #[test]
+    fn earth_moon_system() {
+        let sys = Cr3bp::earth_moon();
+        println!("{sys}");
+        assert!((sys.mu - 0.012_150_585).abs() < 1e-8);
+        // About 4.34 days per nondimensional time unit
+        assert!((sys.time_s / 86_400.0 - 4.342).abs() < 1e-3);
+
+        let l1 = sys.libration_point(LibrationPoint::L1);
+        let l2 = sys.libration_point(LibrationPoint::L2);
+        assert!((l1.x - 0.836_915).abs() < 1e-5, "{l1}");
+        assert!((l2.x - 1.155_682).abs() < 1e-5, "{l2}");
+        for point in [
+            LibrationPoint::L1,
+            LibrationPoint::L2,
+            LibrationPoint::L3,
+            LibrationPoint::L4,
+            LibrationPoint::L5,
+        ] {
+            let r = sys.libration_point(point);
+            assert!(sys.pseudo_potential_gradient(&r).norm() < 1e-12, "{point}");
+        }
+
+        // The Jacobi constant is conserved and the STM matches finite differences.
+        let state = Vector6::new(0.85, 0.0, 0.05, 0.0, 0.2, 0.0);
+        let (final_state, stm) = sys.propagate_with_stm(&state, 2.0).unwrap();
+        assert!((sys.jacobi_constant(&state) - sys.jacobi_constant(&final_state)).abs() < 1e-11);
+        assert!((stm.determinant() - 1.0).abs() < 1e-8);
+        for j in 0..6 {
+            let mut plus = state;
+            plus[j] += 1e-5;
+            let mut minus = state;
+            minus[j] -= 1e-5;
+            let fd =
+                (sys.propagate(&plus, 2.0).unwrap() - sys.propagate(&minus, 2.0).unwrap()) / 2e-5;
+            assert!((fd - stm.column(j)).norm() < 1e-5 * (1.0 + fd.norm()));
+        }
+
+        // Propagating back returns to the initial state.
+        let back = sys.propagate(&final_state, -2.0).unwrap();
+        assert!((back - state).norm() < 1e-10);
+
+        let dim = sys.to_dimensional(&state);
+        assert!((sys.to_nondimensional(&dim) - state).norm() < 1e-14);
+    }
+
+    #[test]
+    fn synodic_axes() {
+        let sys = Cr3bp::earth_moon();
+        // Eccentric and inclined secondary
+        let axes = SynodicAxes::from_secondary(
+            Vector3::new(350_000.0, 120_000.0, 40_000.0),
+            Vector3::new(-0.3, 0.95, 0.12),
+        );
+
+        // The primaries are at rest at their CR3BP positions.
+        let secondary = Vector6::new(1.0 - sys.mu, 0.0, 0.0, 0.0, 0.0, 0.0);
+        let pos_vel = axes.to_inertial(&sys, &secondary);
+        assert!((pos_vel.fixed_rows::<3>(0) - axes.radius_km).norm() < 1e-9);
+        assert!((pos_vel.fixed_rows::<3>(3) - axes.velocity_km_s).norm() < 1e-12);
+        let primary = Vector6::new(-sys.mu, 0.0, 0.0, 0.0, 0.0, 0.0);
+        assert!(axes.to_inertial(&sys, &primary).norm() < 1e-9);
+
+        let state = Vector6::new(0.84, 0.01, 0.02, 0.001, 0.1, -0.02);
+        let back = axes.to_synodic(&sys, &axes.to_inertial(&sys, &state));
+        assert!((back - state).norm() < 1e-13, "{}", back - state);
+    }
+}
@@ -0,0 +1,533 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{augment, integrate, split, Cr3bp, LibrationPoint};
+use crate::errors::NyxError;
+use crate::linalg::{DMatrix, DVector, Matrix6, SVector, Vector6};
+use crate::time::Duration;
+use num::Complex;
+use std::fmt;
+
+/// Maximum number of iterations of the differential corrector
+const MAX_ITERATIONS: usize = 50;
+/// Tolerance on the norm of the constraints of the differential corrector
+const CONSTRAINT_TOLERANCE: f64 = 1e-11;
+/// Largest nondimensional change of any free variable in a single correction, to keep the corrector on the same crossing
+const MAX_CORRECTION: f64 = 0.02;
+/// Tolerance on the Y coordinate of the crossing of the XZ plane
+const CROSSING_TOLERANCE: f64 = 1e-14;
+
+/// Parameter held fixed by the differential corrector, and stepped by the continuation of a family.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FamilyParameter {
+    /// X coordinate of the crossing of the XZ plane, i.e. the amplitude of the orbit in X
+    X,
+    /// Z coordinate of the crossing of the XZ plane, i.e. the out-of-plane amplitude (spatial orbits only)
+    Z,
+    /// Jacobi constant, i.e. the energy of the orbit
+    Jacobi,
+}
+
+/// Class of a halo orbit, depending on the direction of its maximum out-of-plane excursion.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HaloClass {
+    Northern,
+    Southern,
+}
+
+/// A periodic orbit of the CR3BP which is symmetric with respect to the XZ plane, e.g. a Lyapunov, halo or NRHO orbit.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PeriodicOrbit {
+    /// Nondimensional state at the crossing of the XZ plane, where Y, VX and VZ are zero
+    pub state: Vector6<f64>,
+    /// Nondimensional period
+    pub period: f64,
+    /// State transition matrix over one period
+    pub monodromy: Matrix6<f64>,
+    /// Jacobi constant
+    pub jacobi: f64,
+}
+
+impl PeriodicOrbit {
+    /// Corrects the initial guess into a periodic orbit with a differential corrector on the half period, holding the
+    /// provided parameter fixed. The initial guess must be at a crossing of the XZ plane (Y is set to zero).
+    ///
+    /// At the next crossing of the XZ plane, the corrector zeros VX (and VZ for spatial orbits) by adjusting VY and
+    /// the other coordinate of the initial state. A guess with zero Z and VZ is corrected as a planar orbit.
+    pub fn correct(
+        system: &Cr3bp,
+        guess: Vector6<f64>,
+        fixed: FamilyParameter,
+    ) -> Result<Self, NyxError> {
+        Self::correct_to(system, guess, fixed, system.jacobi_constant(&guess))
+    }
+
+    /// Corrects the guess, targeting the provided Jacobi constant if the Jacobi constant is the fixed parameter.
+    fn correct_to(
+        system: &Cr3bp,
+        mut guess: Vector6<f64>,
+        fixed: FamilyParameter,
+        jacobi_target: f64,
+    ) -> Result<Self, NyxError> {
+        guess[1] = 0.0;
+        guess[3] = 0.0;
+        let planar = guess[2] == 0.0 && guess[5] == 0.0;
+        guess[5] = 0.0;
+
+        let free: &[usize] = match (planar, fixed) {
+            (true, FamilyParameter::X) => &[4],
+            (true, FamilyParameter::Jacobi) => &[0, 4],
+            (true, FamilyParameter::Z) => {
+                return Err(NyxError::MathDomain {
+                    msg: "cannot hold Z fixed to correct a planar periodic orbit".to_string(),
+                })
+            }
+            (false, FamilyParameter::X) => &[2, 4],
+            (false, FamilyParameter::Z) => &[0, 4],
+            (false, FamilyParameter::Jacobi) => &[0, 2, 4],
+        };
+        let targets: &[usize] = if planar { &[3] } else { &[3, 5] };
+        let with_jacobi = fixed == FamilyParameter::Jacobi;
+        let rows = targets.len() + usize::from(with_jacobi);
+
+        for _ in 0..MAX_ITERATIONS {
+            let (half_period, half_state, stm) = half_period(system, &guess)?;
+            let deriv = system.eom(&half_state);
+
+            let mut constraints = DVector::zeros(rows);
+            let mut jac = DMatrix::zeros(rows, free.len());
+            for (row, &i) in targets.iter().enumerate() {
+                constraints[row] = half_state[i];
+                for (col, &j) in free.iter().enumerate() {
+                    // Account for the change of the crossing time: the Y coordinate remains zero.
+                    jac[(row, col)] = stm[(i, j)] - deriv[i] / deriv[1] * stm[(1, j)];
+                }
+            }
+            if with_jacobi {
+                let row = rows - 1;
+                constraints[row] = system.jacobi_constant(&guess) - jacobi_target;
+                let grad = system.pseudo_potential_gradient(&guess.fixed_rows::<3>(0).into_owned());
+                for (col, &j) in free.iter().enumerate() {
+                    jac[(row, col)] = if j < 3 {
+                        2.0 * grad[j]
+                    } else {
+                        -2.0 * guess[j]
+                    };
+                }
+            }
+
+            if constraints.norm() < CONSTRAINT_TOLERANCE {
+                let period = 2.0 * half_period;
+                let (_, monodromy) = system.propagate_with_stm(&guess, period)?;
+                return Ok(Self {
+                    state: guess,
+                    period,
+                    monodromy,
+                    jacobi: system.jacobi_constant(&guess),
+                });
+            }
+
+            let mut correction = jac
+                .svd(true, true)
+                .solve(&constraints, 1e-14)
+                .map_err(|msg| NyxError::MathDomain {
+                    msg: msg.to_string(),
+                })?;
+            let largest = correction.amax();
+            if largest > MAX_CORRECTION {
+                correction *= MAX_CORRECTION / largest;
+            }
+            for (col, &j) in free.iter().enumerate() {
+                guess[j] -= correction[col];
+            }
+        }
+
+        Err(NyxError::MaxIterReached {
+            msg: format!("{MAX_ITERATIONS} differential corrections of a periodic orbit"),
+        })
+    }
+
+    /// Computes the planar Lyapunov orbit about L1, L2 or L3 with the provided nondimensional amplitude in X, from the
+    /// linearized dynamics about the libration point.
+    pub fn lyapunov(
+        system: &Cr3bp,
+        point: LibrationPoint,
+        amplitude: f64,
+    ) -> Result<Self, NyxError> {
+        let x_l = collinear_point(system, point)?;
+        let c2 = (1.0 - system.mu) / (x_l + system.mu).abs().powi(3)
+            + system.mu / (x_l - 1.0 + system.mu).abs().powi(3);
+        let freq = ((2.0 - c2 + (9.0 * c2 * c2 - 8.0 * c2).sqrt()) / 2.0).sqrt();
+        let k = (freq * freq + 1.0 + 2.0 * c2) / (2.0 * freq);
+
+        let guess = Vector6::new(x_l - amplitude, 0.0, 0.0, 0.0, k * freq * amplitude, 0.0);
+        Self::correct(system, guess, FamilyParameter::X)
+    }
+
+    /// Computes the halo orbit about L1 or L2 with the provided nondimensional out-of-plane amplitude, from the third
+    /// order approximation of Richardson ("Analytic construction of periodic orbits about the collinear points", 1980).
+    pub fn halo(
+        system: &Cr3bp,
+        point: LibrationPoint,
+        amplitude_z: f64,
+        class: HaloClass,
+    ) -> Result<Self, NyxError> {
+        let guess = richardson_halo(system, point, amplitude_z, class)?;
+        Self::correct(system, guess, FamilyParameter::Z)
+    }
+
+    /// Computes a family of periodic orbits by natural parameter continuation from this orbit, stepping the provided
+    /// parameter by `step` (nondimensional) up to `count` times. The guess of each member is extrapolated from the
+    /// previous two members.
+    ///
+    /// The family starts with this orbit, and stops early if the corrector fails, e.g. at a turning point of the parameter.
+    pub fn continuation(
+        &self,
+        system: &Cr3bp,
+        parameter: FamilyParameter,
+        step: f64,
+        count: usize,
+    ) -> Vec<Self> {
+        let mut family = vec![*self];
+        for _ in 0..count {
+            let last = family[family.len() - 1];
+            let mut guess = match family.len() {
+                1 => last.state,
+                n => 2.0 * last.state - family[n - 2].state,
+            };
+            let mut jacobi_target = last.jacobi;
+            match parameter {
+                FamilyParameter::X => guess[0] = last.state[0] + step,
+                FamilyParameter::Z => guess[2] = last.state[2] + step,
+                FamilyParameter::Jacobi => jacobi_target += step,
+            }
+
+            match Self::correct_to(system, guess, parameter, jacobi_target) {
+                Ok(orbit) => family.push(orbit),
+                Err(e) => {
+                    warn!(
+                        "continuation in {parameter:?} stopped after {} orbits: {e}",
+                        family.len()
+                    );
+                    break;
+                }
+            }
+        }
+        family
+    }
+
+    /// Returns the eigenvalues of the monodromy matrix.
+    pub fn eigenvalues(&self) -> Vec<Complex<f64>> {
+        self.monodromy
+            .complex_eigenvalues()
+            .iter()
+            .copied()
+            .collect()
+    }
+
+    /// Returns the stability index ν = (|λ| + 1/|λ|) / 2 of the largest eigenvalue of the monodromy matrix.
+    ///
+    /// The orbit is linearly stable if the index is one, and the larger the index, the faster the departure along its
+    /// unstable manifold.
+    pub fn stability_index(&self) -> f64 {
+        let max_eigenvalue = self
+            .eigenvalues()
+            .iter()
+            .map(|eigenvalue| eigenvalue.norm())
+            .fold(0.0, f64::max);
+        0.5 * (max_eigenvalue + 1.0 / max_eigenvalue)
+    }
+
+    /// Returns the period of this orbit in the provided system.
+    pub fn period_duration(&self, system: &Cr3bp) -> Duration {
+        system.to_duration(self.period)
+    }
+
+    /// Returns the state at the crossing of the XZ plane in km and km/s, in the synodic frame.
+    pub fn state_km(&self, system: &Cr3bp) -> Vector6<f64> {
+        system.to_dimensional(&self.state)
+    }
+
+    /// Propagates this orbit over one period, returning every integration step as the nondimensional time and state.
+    pub fn steps(&self, system: &Cr3bp) -> Result<Vec<(f64, Vector6<f64>)>, NyxError> {
+        system.propagate_with_steps(&self.state, self.period)
+    }
+}
+
+impl fmt::Display for PeriodicOrbit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "x = {:.10}\tz = {:.10}\tvy = {:.10}\tperiod = {:.10}\tC = {:.10}\tν = {:.4}",
+            self.state[0],
+            self.state[2],
+            self.state[4],
+            self.period,
+            self.jacobi,
+            self.stability_index()
+        )
+    }
+}
+
+/// Returns the X coordinate of a collinear libration point.
+fn collinear_point(system: &Cr3bp, point: LibrationPoint) -> Result<f64, NyxError> {
+    match point {
+        LibrationPoint::L1 | LibrationPoint::L2 | LibrationPoint::L3 => {
+            Ok(system.libration_point(point).x)
+        }
+        _ => Err(NyxError::MathDomain {
+            msg: format!("{point} is not a collinear libration point"),
+        }),
+    }
+}
+
+/// Propagates the state and its STM until the next crossing of the XZ plane, returning the nondimensional time,
+/// the state and the STM at the crossing.
+fn half_period(
+    system: &Cr3bp,
+    state: &Vector6<f64>,
+) -> Result<(f64, Vector6<f64>, Matrix6<f64>), NyxError> {
+    let eom = |x: &SVector<f64, 42>| system.eom_with_stm(x);
+    let direction = state[4].signum();
+    // Upper bound of the half period, far beyond that of any orbit about the libration points
+    let max_time = 50.0;
+
+    let mut crossed = false;
+    let (mut t, mut augmented) = integrate(&eom, &augment(state), max_time, |_, x| {
+        crossed = x[1] * direction < 0.0;
+        crossed
+    })?;
+    if !crossed {
+        return Err(NyxError::MathDomain {
+            msg: "no crossing of the XZ plane".to_string(),
+        });
+    }
+
+    // Newton iterations on the crossing time
+    for _ in 0..MAX_ITERATIONS {
+        let dt = -augmented[1] / augmented[4];
+        let (step, next) = integrate(&eom, &augmented, dt, |_, _| false)?;
+        t += step;
+        augmented = next;
+        if augmented[1].abs() < CROSSING_TOLERANCE {
+            let (state, stm) = split(&augmented);
+            return Ok((t, state, stm));
+        }
+    }
+
+    Err(NyxError::MaxIterReached {
+        msg: "location of the crossing of the XZ plane".to_string(),
+    })
+}
+
+/// Third order Richardson approximation of a halo orbit, at its crossing of the XZ plane.
+fn richardson_halo(
+    system: &Cr3bp,
+    point: LibrationPoint,
+    amplitude_z: f64,
+    class: HaloClass,
+) -> Result<Vector6<f64>, NyxError> {
+    let mu = system.mu;
+    let x_l = collinear_point(system, point)?;
+    let (gamma, sign) = match point {
+        LibrationPoint::L1 => (1.0 - mu - x_l, 1.0),
+        LibrationPoint::L2 => (x_l - 1.0 + mu, -1.0),
+        _ => {
+            return Err(NyxError::MathDomain {
+                msg: format!("halo orbits are only approximated about L1 and L2, not {point}"),
+            })
+        }
+    };
+
+    // Legendre coefficients of the potential about the libration point
+    let c_n = |n: i32| -> f64 {
+        let nf = f64::from(n);
+        let base = (1.0 - mu) * gamma.powf(nf + 1.0) / (1.0 - sign * gamma).powf(nf + 1.0);
+        if sign > 0.0 {
+            (mu + (-1.0_f64).powi(n) * base) / gamma.powi(3)
+        } else {
+            (-1.0_f64).powi(n) * (mu + base) / gamma.powi(3)
+        }
+    };
+    let (c2, c3, c4) = (c_n(2), c_n(3), c_n(4));
+
+    let lambda = ((2.0 - c2 + (9.0 * c2 * c2 - 8.0 * c2).sqrt()) / 2.0).sqrt();
+    let l2 = lambda * lambda;
+    let k = (l2 + 1.0 + 2.0 * c2) / (2.0 * lambda);
+    let k2 = k * k;
+
+    let d1 = 3.0 * l2 / k * (k * (6.0 * l2 - 1.0) - 2.0 * lambda);
+    let d2 = 8.0 * l2 / k * (k * (11.0 * l2 - 1.0) - 2.0 * lambda);
+
+    let a21 = 3.0 * c3 * (k2 - 2.0) / (4.0 * (1.0 + 2.0 * c2));
+    let a22 = 3.0 * c3 / (4.0 * (1.0 + 2.0 * c2));
+    let a23 = -3.0 * c3 * lambda / (4.0 * k * d1)
+        * (3.0 * k2 * k * lambda - 6.0 * k * (k - lambda) + 4.0);
+    let a24 = -3.0 * c3 * lambda / (4.0 * k * d1) * (2.0 + 3.0 * k * lambda);
+    let b21 = -3.0 * c3 * lambda / (2.0 * d1) * (3.0 * k * lambda - 4.0);
+    let b22 = 3.0 * c3 * lambda / d1;
+    let d21 = -c3 / (2.0 * l2);
+
+    let a31 = -9.0 * lambda / (4.0 * d2) * (4.0 * c3 * (k * a23 - b21) + k * c4 * (4.0 + k2))
+        + (9.0 * l2 + 1.0 - c2) / (2.0 * d2)
+            * (3.0 * c3 * (2.0 * a23 - k * b21) + c4 * (2.0 + 3.0 * k2));
+    let a32 = -1.0 / d2
+        * (9.0 * lambda / 4.0 * (4.0 * c3 * (k * a24 - b22) + k * c4)
+            + 1.5 * (9.0 * l2 + 1.0 - c2) * (c3 * (k * b22 + d21 - 2.0 * a24) - c4));
+    let b31 = 3.0 / (8.0 * d2)
+        * (8.0 * lambda * (3.0 * c3 * (k * b21 - 2.0 * a23) - c4 * (2.0 + 3.0 * k2))
+            + (9.0 * l2 + 1.0 + 2.0 * c2) * (4.0 * c3 * (k * a23 - b21) + k * c4 * (4.0 + k2)));
+    let b32 = 1.0 / d2
+        * (9.0 * lambda * (c3 * (k * b22 + d21 - 2.0 * a24) - c4)
+            + 3.0 / 8.0 * (9.0 * l2 + 1.0 + 2.0 * c2) * (4.0 * c3 * (k * a24 - b22) + k * c4));
+    let d31 = 3.0 / (64.0 * l2) * (4.0 * c3 * a24 + c4);
+    let d32 = 3.0 / (64.0 * l2) * (4.0 * c3 * (a23 - d21) + c4 * (4.0 + k2));
+
+    let s_den = 2.0 * lambda * (lambda * (1.0 + k2) - 2.0 * k);
+    let s1 = (1.5 * c3 * (2.0 * a21 * (k2 - 2.0) - a23 * (k2 + 2.0) - 2.0 * k * b21)
+        - 3.0 / 8.0 * c4 * (3.0 * k2 * k2 - 8.0 * k2 + 8.0))
+        / s_den;
+    let s2 = (1.5 * c3 * (2.0 * a22 * (k2 - 2.0) + a24 * (k2 + 2.0) + 2.0 * k * b22 + 5.0 * d21)
+        + 3.0 / 8.0 * c4 * (12.0 - k2))
+        / s_den;
+    let a1 = -1.5 * c3 * (2.0 * a21 + a23 + 5.0 * d21) - 3.0 / 8.0 * c4 * (12.0 - k2);
+    let a2 = 1.5 * c3 * (a24 - 2.0 * a22) + 9.0 / 8.0 * c4;
+    let l1_coeff = a1 + 2.0 * l2 * s1;
+    let l2_coeff = a2 + 2.0 * l2 * s2;
+    let delta = l2 - c2;
+
+    // Amplitudes in units of the distance between the libration point and the secondary
+    let az = amplitude_z / gamma;
+    let ax_sq = (-delta - l2_coeff * az * az) / l1_coeff;
+    if ax_sq <= 0.0 {
+        return Err(NyxError::MathDomain {
+            msg: format!("no halo orbit with an out-of-plane amplitude of {amplitude_z}"),
+        });
+    }
+    let ax = ax_sq.sqrt();
+    let freq = 1.0 + s1 * ax_sq + s2 * az * az;
+    let delta_m = match class {
+        HaloClass::Northern => 1.0,
+        HaloClass::Southern => -1.0,
+    };
+
+    // State at τ = 0, i.e. at the crossing of the XZ plane
+    let x = a21 * ax_sq + a22 * az * az - ax
+        + (a23 * ax_sq - a24 * az * az)
+        + (a31 * ax_sq * ax - a32 * ax * az * az);
+    let z = delta_m * az - 2.0 * delta_m * d21 * ax * az
+        + delta_m * (d32 * az * ax_sq - d31 * az.powi(3));
+    let vy = lambda
+        * freq
+        * (k * ax
+            + 2.0 * (b21 * ax_sq - b22 * az * az)
+            + 3.0 * (b31 * ax_sq * ax - b32 * ax * az * az));
+
+    // Richardson's X axis points from the libration point away from the secondary for L1 and toward it for L2.
+    Ok(Vector6::new(
+        x_l - sign * gamma * x,
+        0.0,
+        gamma * z,
+        0.0,
+        -sign * gamma * vy,
+        0.0,
+    ))
+}
+
+#[cfg(test)]
+mod ut_periodic {
+    use super::*;
+
+    fn check_periodic(system: &Cr3bp, orbit: &PeriodicOrbit) {
+        let final_state = system.propagate(&orbit.state, orbit.period).unwrap();
+        assert!(
+            (final_state - orbit.state).norm() < 1e-8,
+            "{orbit}: {final_state}"
+        );
+        // The monodromy matrix of a periodic orbit is symplectic and has a pair of unit eigenvalues.
+        assert!((orbit.monodromy.determinant() - 1.0).abs() < 1e-6);
+        let unit_eigenvalues = orbit
+            .eigenvalues()
+            .iter()
+            .filter(|eigenvalue| (*eigenvalue - Complex::new(1.0, 0.0)).norm() < 1e-3)
+            .count();
+        assert!(unit_eigenvalues >= 2, "{:?}", orbit.eigenvalues());
+    }
+
+    #[test]
+    fn lyapunov_family() {
+        let system = Cr3bp::earth_moon();
+        let orbit = PeriodicOrbit::lyapunov(&system, LibrationPoint::L1, 0.01).unwrap();
+        println!("{orbit}");
+        check_periodic(&system, &orbit);
+        assert_eq!(orbit.state[2], 0.0);
+        // Close to the linear period of about 2.69 (11.7 days)
+        assert!((orbit.period - 2.69).abs() < 0.05, "{orbit}");
+        assert!(orbit.stability_index() > 100.0);
+
+        let family = orbit.continuation(&system, FamilyParameter::X, -0.001, 4);
+        assert_eq!(family.len(), 5);
+        for pair in family.windows(2) {
+            assert!((pair[1].state[0] - pair[0].state[0] + 0.001).abs() < 1e-12);
+            // Larger orbits have a lower energy, i.e. a lower Jacobi constant.
+            assert!(pair[1].jacobi < pair[0].jacobi);
+        }
+
+        // Continuation in energy
+        let family = orbit.continuation(&system, FamilyParameter::Jacobi, -0.01, 2);
+        assert_eq!(family.len(), 3);
+        assert!((family[2].jacobi - orbit.jacobi + 0.02).abs() < 1e-10);
+        check_periodic(&system, &family[2]);
+    }
+
+    #[test]
+    fn halo_and_nrho() {
+        let system = Cr3bp::earth_moon();
+        for point in [LibrationPoint::L1, LibrationPoint::L2] {
+            let orbit = PeriodicOrbit::halo(&system, point, 0.02, HaloClass::Northern).unwrap();
+            println!("{point} {orbit}");
+            check_periodic(&system, &orbit);
+            assert!((orbit.state[2] - 0.02).abs() < 1e-2, "{orbit}");
+            // About two weeks
+            let days = orbit
+                .period_duration(&system)
+                .to_unit(crate::time::Unit::Day);
+            assert!((10.0..16.0).contains(&days), "{days}");
+        }
+
+        let southern =
+            PeriodicOrbit::halo(&system, LibrationPoint::L2, 0.02, HaloClass::Southern).unwrap();
+        assert!(southern.state[2] < 0.0);
+
+        // L2 southern 9:2 synodic resonant NRHO, at apolune
+        let nrho = PeriodicOrbit::correct(
+            &system,
+            Vector6::new(1.0221, 0.0, -0.1821, 0.0, -0.1033, 0.0),
+            FamilyParameter::X,
+        )
+        .unwrap();
+        println!("NRHO {nrho}");
+        check_periodic(&system, &nrho);
+        let days = nrho
+            .period_duration(&system)
+            .to_unit(crate::time::Unit::Day);
+        assert!((days - 6.56).abs() < 0.1, "{days}");
+        // NRHOs are nearly stable.
+        assert!(nrho.stability_index() < 2.0, "{nrho}");
+    }
+}
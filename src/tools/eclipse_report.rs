@@ -0,0 +1,575 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::cosmic::eclipse::EclipseLocator;
+use crate::errors::{EventAlmanacSnafu, EventError};
+use crate::io::watermark::pq_writer;
+use crate::md::events::details::EventArc;
+use crate::md::prelude::Traj;
+use crate::md::EventEvaluator;
+use crate::time::{Duration, Epoch, Unit};
+use crate::{Spacecraft, State};
+use anise::almanac::Almanac;
+use arrow::array::{Array, Float64Builder, StringBuilder, UInt64Builder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use hifitime::TimeScale;
+use parquet::arrow::ArrowWriter;
+use snafu::ResultExt;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Fraction of the light source which must be hidden for the observer to be considered in penumbra.
+pub const PENUMBRA_THRESHOLD: f64 = 0.02;
+/// Fraction of the light source which must be hidden for the observer to be considered in umbra.
+pub const UMBRA_THRESHOLD: f64 = 0.98;
+
+/// An event which is positive while more than `threshold` of the light source is hidden from the spacecraft.
+///
+/// Its arcs (cf. [Traj::find_arcs]) are the periods spent in shadow.
+#[derive(Clone)]
+pub struct ShadowEvent {
+    pub e_loc: EclipseLocator,
+    /// Occulted fraction of the light source, between 0.0 and 1.0
+    pub threshold: f64,
+}
+
+impl ShadowEvent {
+    /// Event which is positive in penumbra and in umbra
+    pub fn penumbra(e_loc: EclipseLocator) -> Self {
+        Self {
+            e_loc,
+            threshold: PENUMBRA_THRESHOLD,
+        }
+    }
+
+    /// Event which is positive in umbra only
+    pub fn umbra(e_loc: EclipseLocator) -> Self {
+        Self {
+            e_loc,
+            threshold: UMBRA_THRESHOLD,
+        }
+    }
+}
+
+impl fmt::Display for ShadowEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "shadow above {:.0}% {}",
+            self.threshold * 100.0,
+            self.e_loc
+        )
+    }
+}
+
+impl EventEvaluator<Spacecraft> for ShadowEvent {
+    fn eval(&self, sc: &Spacecraft, almanac: Arc<Almanac>) -> Result<f64, EventError> {
+        let occult = self
+            .e_loc
+            .compute(sc.orbit, almanac)
+            .context(EventAlmanacSnafu)?
+            .factor();
+
+        Ok(occult - self.threshold)
+    }
+
+    /// Stop searching when the time has converged to less than 0.1 seconds
+    fn epoch_precision(&self) -> Duration {
+        0.1 * Unit::Second
+    }
+
+    fn value_precision(&self) -> f64 {
+        1e-3
+    }
+
+    fn eval_string(&self, state: &Spacecraft, almanac: Arc<Almanac>) -> Result<String, EventError> {
+        Ok(format!(
+            "{}",
+            self.e_loc
+                .compute(state.orbit, almanac)
+                .context(EventAlmanacSnafu)?
+        ))
+    }
+}
+
+/// A single eclipse, from the entry into the penumbra until the exit of the penumbra.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Eclipse {
+    /// Revolution number, counted in osculating periods of the first state of the trajectory
+    pub orbit: u64,
+    pub penumbra_entry: Epoch,
+    /// Entry into the umbra, if the eclipse is ever total
+    pub umbra_entry: Option<Epoch>,
+    /// Exit of the umbra, if the eclipse is ever total
+    pub umbra_exit: Option<Epoch>,
+    pub penumbra_exit: Epoch,
+}
+
+impl Eclipse {
+    /// Returns the duration of this eclipse, including the penumbra
+    pub fn duration(&self) -> Duration {
+        self.penumbra_exit - self.penumbra_entry
+    }
+
+    /// Returns the time spent in umbra, zero if the eclipse is only partial
+    pub fn umbra_duration(&self) -> Duration {
+        match (self.umbra_entry, self.umbra_exit) {
+            (Some(entry), Some(exit)) => exit - entry,
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+impl fmt::Display for Eclipse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "eclipse on orbit #{} from {} to {} ({}, umbra {})",
+            self.orbit,
+            self.penumbra_entry,
+            self.penumbra_exit,
+            self.duration(),
+            self.umbra_duration()
+        )
+    }
+}
+
+/// A season of consecutive eclipses, with its worst case statistics.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EclipseSeason {
+    /// Penumbra entry of the first eclipse of the season
+    pub start: Epoch,
+    /// Penumbra exit of the last eclipse of the season
+    pub end: Epoch,
+    pub num_eclipses: usize,
+    /// Cumulative time in shadow during this season
+    pub total_duration: Duration,
+    /// Longest eclipse of the season
+    pub max_duration: Duration,
+    /// Penumbra entry of the longest eclipse of the season
+    pub max_duration_epoch: Epoch,
+    /// Longest time in umbra during this season
+    pub max_umbra_duration: Duration,
+}
+
+impl EclipseSeason {
+    /// Returns the mean duration of the eclipses of this season
+    pub fn mean_duration(&self) -> Duration {
+        self.total_duration / (self.num_eclipses as f64)
+    }
+}
+
+impl fmt::Display for EclipseSeason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} eclipses from {} to {}: longest of {} on {} (umbra {}), total of {}",
+            self.num_eclipses,
+            self.start,
+            self.end,
+            self.max_duration,
+            self.max_duration_epoch,
+            self.max_umbra_duration,
+            self.total_duration
+        )
+    }
+}
+
+/// Eclipse report of a trajectory: umbra and penumbra entry and exit epochs of each eclipse.
+#[derive(Clone, Debug)]
+pub struct EclipseReport {
+    /// Eclipses sorted chronologically
+    pub eclipses: Vec<Eclipse>,
+    /// Epoch of the first state of the trajectory
+    pub start: Epoch,
+    /// Epoch of the last state of the trajectory
+    pub end: Epoch,
+    /// Osculating period of the first state of the trajectory, used to number the revolutions
+    pub period: Option<Duration>,
+    /// Description of the shadow model, used in the exported metadata
+    pub description: String,
+}
+
+impl EclipseReport {
+    /// Computes all of the eclipses of this trajectory, as seen by this eclipse locator.
+    pub fn compute(
+        traj: &Traj<Spacecraft>,
+        e_loc: &EclipseLocator,
+        almanac: Arc<Almanac>,
+    ) -> Result<Self, EventError> {
+        Self::from_events(
+            traj,
+            &ShadowEvent::penumbra(e_loc.clone()),
+            &ShadowEvent::umbra(e_loc.clone()),
+            almanac,
+        )
+    }
+
+    /// Computes the eclipses of this trajectory from any pair of events which are positive while in penumbra and while in umbra respectively.
+    ///
+    /// Each penumbra arc is an eclipse, and the umbra arcs within it set its umbra entry and exit.
+    pub fn from_events<P, U>(
+        traj: &Traj<Spacecraft>,
+        penumbra: &P,
+        umbra: &U,
+        almanac: Arc<Almanac>,
+    ) -> Result<Self, EventError>
+    where
+        P: EventEvaluator<Spacecraft>,
+        U: EventEvaluator<Spacecraft>,
+    {
+        let penumbra_arcs = shadow_arcs(traj, penumbra, almanac.clone())?;
+        let umbra_arcs = if penumbra_arcs.is_empty() {
+            Vec::new()
+        } else {
+            shadow_arcs(traj, umbra, almanac)?
+        };
+
+        let start = traj.first().epoch();
+        let period = traj.first().orbit.period().ok();
+
+        let eclipses = penumbra_arcs
+            .iter()
+            .map(|arc| {
+                let penumbra_entry = arc.rise.state.epoch();
+                let penumbra_exit = arc.fall.state.epoch();
+                let mut within = umbra_arcs.iter().filter(|umbra_arc| {
+                    let entry = umbra_arc.rise.state.epoch();
+                    entry >= penumbra_entry && entry <= penumbra_exit
+                });
+
+                let first = within.next();
+                let last = within.next_back().or(first);
+
+                Eclipse {
+                    orbit: orbit_number(start, period, penumbra_entry),
+                    penumbra_entry,
+                    umbra_entry: first.map(|umbra_arc| umbra_arc.rise.state.epoch()),
+                    umbra_exit: last.map(|umbra_arc| umbra_arc.fall.state.epoch()),
+                    penumbra_exit,
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            eclipses,
+            start,
+            end: traj.last().epoch(),
+            period,
+            description: format!("{penumbra} and {umbra}"),
+        })
+    }
+
+    /// Returns the cumulative time in shadow
+    pub fn total_duration(&self) -> Duration {
+        self.eclipses
+            .iter()
+            .fold(Duration::ZERO, |total, eclipse| total + eclipse.duration())
+    }
+
+    /// Returns the longest eclipse, if any
+    pub fn longest(&self) -> Option<&Eclipse> {
+        self.eclipses
+            .iter()
+            .max_by_key(|eclipse| eclipse.duration())
+    }
+
+    /// Returns the cumulative eclipse duration of each revolution with at least one eclipse, as (orbit number, duration) pairs.
+    pub fn per_orbit(&self) -> Vec<(u64, Duration)> {
+        let mut durations: Vec<(u64, Duration)> = Vec::new();
+        for eclipse in &self.eclipses {
+            match durations.last_mut() {
+                Some((orbit, duration)) if *orbit == eclipse.orbit => {
+                    *duration += eclipse.duration()
+                }
+                _ => durations.push((eclipse.orbit, eclipse.duration())),
+            }
+        }
+        durations
+    }
+
+    /// Groups the eclipses into seasons: an eclipse starting more than `max_gap` after the end of the previous one starts a new season.
+    pub fn seasons(&self, max_gap: Duration) -> Vec<EclipseSeason> {
+        let mut seasons: Vec<EclipseSeason> = Vec::new();
+        for eclipse in &self.eclipses {
+            match seasons.last_mut() {
+                Some(season) if eclipse.penumbra_entry - season.end <= max_gap => {
+                    season.end = eclipse.penumbra_exit;
+                    season.num_eclipses += 1;
+                    season.total_duration += eclipse.duration();
+                    if eclipse.duration() > season.max_duration {
+                        season.max_duration = eclipse.duration();
+                        season.max_duration_epoch = eclipse.penumbra_entry;
+                    }
+                    if eclipse.umbra_duration() > season.max_umbra_duration {
+                        season.max_umbra_duration = eclipse.umbra_duration();
+                    }
+                }
+                _ => seasons.push(EclipseSeason {
+                    start: eclipse.penumbra_entry,
+                    end: eclipse.penumbra_exit,
+                    num_eclipses: 1,
+                    total_duration: eclipse.duration(),
+                    max_duration: eclipse.duration(),
+                    max_duration_epoch: eclipse.penumbra_entry,
+                    max_umbra_duration: eclipse.umbra_duration(),
+                }),
+            }
+        }
+        seasons
+    }
+
+    /// Exports this report to a parquet file, with one row per eclipse.
+    ///
+    /// Seasons are computed with the provided maximum gap between eclipses and stored as the season index of each eclipse.
+    pub fn to_parquet<P: AsRef<Path>>(
+        &self,
+        path: P,
+        season_max_gap: Duration,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let path_buf = path.as_ref().to_path_buf();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("Season", DataType::UInt64, false),
+            Field::new("Orbit", DataType::UInt64, false),
+            Field::new("Penumbra entry (UTC)", DataType::Utf8, false),
+            Field::new("Umbra entry (UTC)", DataType::Utf8, true),
+            Field::new("Umbra exit (UTC)", DataType::Utf8, true),
+            Field::new("Penumbra exit (UTC)", DataType::Utf8, false),
+            Field::new("Duration (s)", DataType::Float64, false),
+            Field::new("Umbra duration (s)", DataType::Float64, false),
+        ]));
+
+        let seasons = self.seasons(season_max_gap);
+        let utc = |epoch: Epoch| epoch.to_time_scale(TimeScale::UTC).to_isoformat();
+
+        let mut season_idx = UInt64Builder::new();
+        let mut orbits = UInt64Builder::new();
+        let mut penumbra_entries = StringBuilder::new();
+        let mut umbra_entries = StringBuilder::new();
+        let mut umbra_exits = StringBuilder::new();
+        let mut penumbra_exits = StringBuilder::new();
+        let mut durations = Float64Builder::new();
+        let mut umbra_durations = Float64Builder::new();
+        for eclipse in &self.eclipses {
+            let season = seasons
+                .iter()
+                .position(|season| eclipse.penumbra_entry <= season.end)
+                .unwrap_or_default();
+            season_idx.append_value(season as u64);
+            orbits.append_value(eclipse.orbit);
+            penumbra_entries.append_value(utc(eclipse.penumbra_entry));
+            umbra_entries.append_option(eclipse.umbra_entry.map(utc));
+            umbra_exits.append_option(eclipse.umbra_exit.map(utc));
+            penumbra_exits.append_value(utc(eclipse.penumbra_exit));
+            durations.append_value(eclipse.duration().to_seconds());
+            umbra_durations.append_value(eclipse.umbra_duration().to_seconds());
+        }
+
+        let record: Vec<Arc<dyn Array>> = vec![
+            Arc::new(season_idx.finish()),
+            Arc::new(orbits.finish()),
+            Arc::new(penumbra_entries.finish()),
+            Arc::new(umbra_entries.finish()),
+            Arc::new(umbra_exits.finish()),
+            Arc::new(penumbra_exits.finish()),
+            Arc::new(durations.finish()),
+            Arc::new(umbra_durations.finish()),
+        ];
+
+        let mut metadata = HashMap::new();
+        metadata.insert("Purpose".to_string(), "Eclipse report".to_string());
+        metadata.insert("Shadow model".to_string(), self.description.clone());
+        metadata.insert("Start (UTC)".to_string(), utc(self.start));
+        metadata.insert("End (UTC)".to_string(), utc(self.end));
+        metadata.insert(
+            "Total duration (s)".to_string(),
+            format!("{}", self.total_duration().to_seconds()),
+        );
+        if let Some(longest) = self.longest() {
+            metadata.insert(
+                "Longest eclipse (s)".to_string(),
+                format!("{}", longest.duration().to_seconds()),
+            );
+            metadata.insert(
+                "Longest eclipse entry (UTC)".to_string(),
+                utc(longest.penumbra_entry),
+            );
+        }
+
+        let file = File::create(&path_buf)?;
+        let mut writer = ArrowWriter::try_new(file, schema.clone(), pq_writer(Some(metadata)))?;
+        writer.write(&RecordBatch::try_new(schema, record)?)?;
+        writer.close()?;
+
+        info!(
+            "Eclipse report of {} eclipses written to {}",
+            self.eclipses.len(),
+            path_buf.display()
+        );
+
+        Ok(path_buf)
+    }
+}
+
+impl fmt::Display for EclipseReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} eclipses from {} to {} (total of {})",
+            self.eclipses.len(),
+            self.start,
+            self.end,
+            self.total_duration()
+        )?;
+        for eclipse in &self.eclipses {
+            writeln!(f, "\t{eclipse}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the arcs of this shadow event, or none if the trajectory is never in shadow.
+fn shadow_arcs<E: EventEvaluator<Spacecraft>>(
+    traj: &Traj<Spacecraft>,
+    event: &E,
+    almanac: Arc<Almanac>,
+) -> Result<Vec<EventArc<Spacecraft>>, EventError> {
+    match traj.find_arcs(event, almanac) {
+        Ok(arcs) => Ok(arcs),
+        Err(EventError::NotFound { .. }) => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn orbit_number(start: Epoch, period: Option<Duration>, epoch: Epoch) -> u64 {
+    match period {
+        Some(period) if period > Duration::ZERO => {
+            ((epoch - start).to_seconds() / period.to_seconds()).floor() as u64
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod ut_eclipse_report {
+    use super::*;
+    use crate::cosmic::Orbit;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::propagators::Propagator;
+    use crate::time::TimeUnits;
+
+    /// Cylindrical shadow of a body of the provided radius, with the light source infinitely far along +X.
+    struct CylindricalShadow {
+        radius_km: f64,
+    }
+
+    impl fmt::Display for CylindricalShadow {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "cylindrical shadow of {} km", self.radius_km)
+        }
+    }
+
+    impl EventEvaluator<Spacecraft> for CylindricalShadow {
+        fn eval(&self, sc: &Spacecraft, _almanac: Arc<Almanac>) -> Result<f64, EventError> {
+            let r = sc.orbit.radius_km;
+            let dist_km = if r.x < 0.0 {
+                (r.y.powi(2) + r.z.powi(2)).sqrt()
+            } else {
+                r.norm()
+            };
+            Ok(self.radius_km - dist_km)
+        }
+
+        fn epoch_precision(&self) -> Duration {
+            0.1 * Unit::Second
+        }
+
+        fn value_precision(&self) -> f64 {
+            1e-3
+        }
+
+        fn eval_string(
+            &self,
+            sc: &Spacecraft,
+            almanac: Arc<Almanac>,
+        ) -> Result<String, EventError> {
+            Ok(format!("{:.3} km", self.eval(sc, almanac)?))
+        }
+    }
+
+    #[test]
+    fn circular_leo() {
+        let almanac = fixtures::almanac();
+        let eme2k = fixtures::eme2k();
+        let epoch = Epoch::from_gregorian_utc_at_midnight(2024, 3, 20);
+
+        let orbit = Orbit::keplerian(7_000.0, 0.0, 0.0, 0.0, 0.0, 0.0, epoch, eme2k);
+        let period = orbit.period().unwrap();
+
+        let (_, traj) = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()))
+            .with(Spacecraft::builder().orbit(orbit).build(), almanac.clone())
+            .for_duration_with_traj(period * 3.2)
+            .unwrap();
+
+        let report = EclipseReport::from_events(
+            &traj,
+            &CylindricalShadow { radius_km: 6_400.0 },
+            &CylindricalShadow { radius_km: 6_300.0 },
+            almanac,
+        )
+        .unwrap();
+
+        println!("{report}");
+
+        // Analytical duration in the cylindrical shadow of a circular orbit
+        let expected =
+            |radius_km: f64| period * ((radius_km / 7_000.0_f64).asin() / std::f64::consts::PI);
+
+        assert_eq!(report.eclipses.len(), 3);
+        for (orbit_num, eclipse) in report.eclipses.iter().enumerate() {
+            assert_eq!(eclipse.orbit, orbit_num as u64);
+            assert!((eclipse.duration() - expected(6_400.0)).abs() < 1.seconds());
+            assert!((eclipse.umbra_duration() - expected(6_300.0)).abs() < 1.seconds());
+            // Eclipse is centered on the anti-Sun direction, i.e. half an orbit after the ascending node
+            let mid = eclipse.penumbra_entry + eclipse.duration() * 0.5;
+            let expected_mid = epoch + period * (orbit_num as f64 + 0.5);
+            assert!((mid - expected_mid).abs() < 1.seconds());
+        }
+
+        assert_eq!(report.per_orbit().len(), 3);
+        assert!((report.total_duration() - expected(6_400.0) * 3).abs() < 3.seconds());
+
+        let seasons = report.seasons(1.days());
+        assert_eq!(seasons.len(), 1);
+        assert_eq!(seasons[0].num_eclipses, 3);
+        assert!((seasons[0].mean_duration() - expected(6_400.0)).abs() < 1.seconds());
+
+        // With a gap shorter than an orbit, each eclipse is its own season
+        assert_eq!(report.seasons(10.minutes()).len(), 3);
+
+        let path = std::env::temp_dir().join("nyx_ut_eclipse_report.parquet");
+        assert_eq!(report.to_parquet(&path, 1.days()).unwrap(), path);
+    }
+}
@@ -0,0 +1,349 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::lambert::{transfer, LambertTransfer, TransferKind};
+use super::patched_conic::HyperbolicLeg;
+use crate::cosmic::{BPlaneTarget, Frame, Orbit};
+use crate::dynamics::SpacecraftDynamics;
+use crate::errors::NyxError;
+use crate::linalg::Vector3;
+use crate::md::opti::solution::TargeterSolution;
+use crate::md::prelude::Targeter;
+use crate::md::{PropSnafu, TargetingError};
+use crate::propagators::Propagator;
+use crate::time::{Duration, Epoch};
+use crate::{Spacecraft, State};
+use anise::almanac::Almanac;
+use snafu::ResultExt;
+use std::fmt;
+use std::sync::Arc;
+
+/// The design of a gravity assist, from the incoming and the desired outgoing hyperbolic excess velocities.
+///
+/// The flyby is designed as unpowered, using the magnitude of the incoming excess velocity: any difference in
+/// magnitude with the outgoing excess velocity must be provided by a maneuver, see `v_inf_mismatch_km_s`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FlybyDesign {
+    /// Epoch of the flyby
+    pub epoch: Epoch,
+    /// Incoming hyperbolic excess velocity, in km/s
+    pub v_inf_in_km_s: Vector3<f64>,
+    /// Desired outgoing hyperbolic excess velocity, in km/s
+    pub v_inf_out_km_s: Vector3<f64>,
+    /// Gravitational parameter of the flyby body, in km^3/s^2
+    pub gm_km3_s2: f64,
+    /// Minimum periapsis radius of the flyby, e.g. the body radius plus a safety altitude, in km
+    pub min_periapsis_km: f64,
+    /// Turn angle between the incoming and outgoing asymptotes, in degrees
+    pub turn_angle_deg: f64,
+    /// Periapsis radius needed for this turn angle, in km
+    pub periapsis_km: f64,
+    /// B plane angle of the aim point, measured from T towards R, in degrees
+    pub b_angle_deg: f64,
+}
+
+impl FlybyDesign {
+    /// Designs the flyby from the incoming and desired outgoing hyperbolic excess velocities, both in the same frame.
+    pub fn new(
+        epoch: Epoch,
+        v_inf_in_km_s: Vector3<f64>,
+        v_inf_out_km_s: Vector3<f64>,
+        gm_km3_s2: f64,
+        min_periapsis_km: f64,
+    ) -> Result<Self, NyxError> {
+        let s_hat = v_inf_in_km_s / v_inf_in_km_s.norm();
+        let out_hat = v_inf_out_km_s / v_inf_out_km_s.norm();
+        let turn_angle = s_hat.dot(&out_hat).clamp(-1.0, 1.0).acos();
+        if turn_angle < f64::EPSILON.sqrt() || !turn_angle.is_finite() {
+            return Err(NyxError::MathDomain {
+                msg: format!(
+                    "flyby turn angle of {turn_angle} rad does not define a B plane aim point"
+                ),
+            });
+        }
+
+        let c3 = v_inf_in_km_s.norm_squared();
+        let periapsis_km = gm_km3_s2 / c3 * (1.0 / (turn_angle / 2.0).sin() - 1.0);
+
+        // The outgoing asymptote is bent away from the B vector
+        let bend = out_hat - out_hat.dot(&s_hat) * s_hat;
+        let b_hat = -bend / bend.norm();
+        let t_hat = s_hat.cross(&Vector3::z()).normalize();
+        let r_hat = s_hat.cross(&t_hat);
+        let b_angle_deg = b_hat.dot(&r_hat).atan2(b_hat.dot(&t_hat)).to_degrees();
+
+        Ok(Self {
+            epoch,
+            v_inf_in_km_s,
+            v_inf_out_km_s,
+            gm_km3_s2,
+            min_periapsis_km,
+            turn_angle_deg: turn_angle.to_degrees(),
+            periapsis_km,
+            b_angle_deg,
+        })
+    }
+
+    /// Returns the maximum turn angle, at the minimum periapsis radius, in degrees
+    pub fn max_turn_angle_deg(&self) -> f64 {
+        let ecc = 1.0 + self.min_periapsis_km * self.v_inf_in_km_s.norm_squared() / self.gm_km3_s2;
+        (2.0 * (1.0 / ecc).asin()).to_degrees()
+    }
+
+    /// Returns true if the turn angle is achievable above the minimum periapsis radius
+    pub fn is_feasible(&self) -> bool {
+        self.periapsis_km >= self.min_periapsis_km
+    }
+
+    /// Returns the difference between the magnitudes of the outgoing and incoming hyperbolic excess velocities, in km/s
+    pub fn v_inf_mismatch_km_s(&self) -> f64 {
+        self.v_inf_out_km_s.norm() - self.v_inf_in_km_s.norm()
+    }
+
+    /// Returns the incoming hyperbola of this flyby
+    pub fn approach(&self) -> HyperbolicLeg {
+        HyperbolicLeg {
+            v_inf_km_s: self.v_inf_in_km_s,
+            periapsis_km: self.periapsis_km,
+            gm_km3_s2: self.gm_km3_s2,
+        }
+    }
+
+    /// Returns the B plane aim point of this flyby
+    pub fn b_plane_target(&self) -> BPlaneTarget {
+        self.approach().b_plane_target(self.b_angle_deg)
+    }
+
+    /// Returns the periapsis state of this flyby, at the epoch of the flyby, in the provided body centered frame
+    pub fn periapsis_state(&self, frame: Frame) -> Orbit {
+        self.approach()
+            .periapsis_state(self.b_angle_deg, self.epoch, frame)
+    }
+}
+
+impl fmt::Display for FlybyDesign {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "flyby at {}: turn {:.3} deg (max {:.3} deg), periapsis {:.3} km (min {:.3} km), B angle {:.3} deg, v_inf mismatch {:.3} km/s",
+            self.epoch,
+            self.turn_angle_deg,
+            self.max_turn_angle_deg(),
+            self.periapsis_km,
+            self.min_periapsis_km,
+            self.b_angle_deg,
+            self.v_inf_mismatch_km_s()
+        )
+    }
+}
+
+/// An encounter of a flyby sequence: the heliocentric state of the body at the encounter, its GM, and the minimum periapsis radius.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Encounter {
+    pub body: Orbit,
+    pub gm_km3_s2: f64,
+    pub min_periapsis_km: f64,
+}
+
+/// A sequence of gravity assists, connecting each encounter with a single revolution Lambert transfer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlybySequence {
+    /// Heliocentric transfers between consecutive encounters
+    pub legs: Vec<LambertTransfer>,
+    /// Flyby at each intermediate encounter
+    pub flybys: Vec<FlybyDesign>,
+}
+
+impl FlybySequence {
+    /// Designs the sequence through the encounters, which must be in chronological order, from departure to arrival.
+    pub fn new(encounters: &[Encounter]) -> Result<Self, NyxError> {
+        if encounters.len() < 3 {
+            return Err(NyxError::CustomError {
+                msg: format!(
+                    "a flyby sequence requires at least three encounters, got {}",
+                    encounters.len()
+                ),
+            });
+        }
+
+        let legs = encounters
+            .windows(2)
+            .map(|pair| {
+                transfer(pair[0].body, pair[1].body, TransferKind::NRevs(0)).map(|xfers| xfers[0])
+            })
+            .collect::<Result<Vec<LambertTransfer>, NyxError>>()?;
+
+        let flybys = legs
+            .windows(2)
+            .zip(&encounters[1..])
+            .map(|(pair, encounter)| {
+                FlybyDesign::new(
+                    encounter.body.epoch,
+                    -pair[0].arrival_dv_km_s(),
+                    pair[1].departure_dv_km_s(),
+                    encounter.gm_km3_s2,
+                    encounter.min_periapsis_km,
+                )
+            })
+            .collect::<Result<Vec<FlybyDesign>, NyxError>>()?;
+
+        Ok(Self { legs, flybys })
+    }
+
+    /// Returns true if all of the flybys are feasible
+    pub fn is_feasible(&self) -> bool {
+        self.flybys.iter().all(|flyby| flyby.is_feasible())
+    }
+
+    /// Returns the sum of the magnitudes of the hyperbolic excess velocity mismatches at each flyby, in km/s
+    pub fn total_v_inf_mismatch_km_s(&self) -> f64 {
+        self.flybys
+            .iter()
+            .map(|flyby| flyby.v_inf_mismatch_km_s().abs())
+            .sum()
+    }
+
+    /// Returns the nominal approach state of the provided flyby, by propagating its periapsis state backward for the
+    /// approach duration. The frame must be centered on the flyby body and include its GM.
+    pub fn approach_state(
+        &self,
+        flyby: usize,
+        template: Spacecraft,
+        frame: Frame,
+        approach: Duration,
+        prop: &Propagator<SpacecraftDynamics>,
+        almanac: Arc<Almanac>,
+    ) -> Result<Spacecraft, TargetingError> {
+        prop.with(
+            template.with_orbit(self.flybys[flyby].periapsis_state(frame)),
+            almanac,
+        )
+        .for_duration(-approach.abs())
+        .context(PropSnafu)
+    }
+
+    /// Refines the approach of the provided flyby with an impulsive correction at the approach state such that the
+    /// B plane aim point is achieved at the epoch of the flyby, with the provided propagator.
+    pub fn refine_flyby(
+        &self,
+        flyby: usize,
+        approach: Spacecraft,
+        prop: &Propagator<SpacecraftDynamics>,
+        almanac: Arc<Almanac>,
+    ) -> Result<TargeterSolution<3, 2>, TargetingError> {
+        let design = &self.flybys[flyby];
+        let targeter = Targeter::delta_v(prop, design.b_plane_target().to_objectives());
+        targeter.try_achieve_from(approach, approach.epoch(), design.epoch, almanac)
+    }
+}
+
+#[cfg(test)]
+mod ut_flyby {
+    use super::{Encounter, FlybyDesign, FlybySequence};
+    use crate::cosmic::{BPlane, Orbit, Spacecraft};
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::linalg::Vector3;
+    use crate::propagators::Propagator;
+    use crate::time::{Epoch, TimeUnits};
+    use crate::State;
+    use anise::almanac::Almanac;
+    use anise::constants::frames::{EARTH_J2000, SUN_J2000};
+    use std::sync::Arc;
+
+    #[test]
+    fn flyby_aim_point() {
+        let epoch = Epoch::from_gregorian_utc_at_midnight(2026, 1, 1);
+        let gm = 398_600.435_436;
+        let v_in = Vector3::new(3.0, 4.0, 1.0);
+        let v_out = Vector3::new(-1.0, 5.0, 0.5).normalize() * v_in.norm();
+        let flyby = FlybyDesign::new(epoch, v_in, v_out, gm, 6_678.0).unwrap();
+        println!("{flyby}");
+
+        assert!(flyby.is_feasible());
+        assert!(flyby.turn_angle_deg < flyby.max_turn_angle_deg());
+        assert!(flyby.v_inf_mismatch_km_s().abs() < 1e-12);
+
+        // The periapsis state achieves the B plane aim point and turns the asymptote as designed
+        let eme2k = EARTH_J2000.with_mu_km3_s2(gm);
+        let periapsis = flyby.periapsis_state(eme2k);
+        let b_plane = BPlane::new(periapsis).unwrap();
+        let target = flyby.b_plane_target();
+        assert!((b_plane.b_dot_t() - target.b_t_km).abs() < 1e-6);
+        assert!((b_plane.b_dot_r() - target.b_r_km).abs() < 1e-6);
+
+        let ecc = periapsis.ecc().unwrap();
+        let e_hat = periapsis.evec().unwrap() / ecc;
+        let n_hat = periapsis.hvec().unwrap().normalize().cross(&e_hat);
+        let out_hat = -e_hat / ecc + (1.0 - ecc.powi(-2)).sqrt() * n_hat;
+        assert!((out_hat - v_out.normalize()).norm() < 1e-9);
+
+        // A turn beyond the maximum is not feasible
+        let too_close = FlybyDesign::new(epoch, v_in, -v_in + Vector3::x(), gm, 6_678.0).unwrap();
+        assert!(!too_close.is_feasible());
+    }
+
+    #[test]
+    fn refine_sequence() {
+        let sun = SUN_J2000.with_mu_km3_s2(132_712_440_041.939_38);
+        let epoch = Epoch::from_gregorian_utc_at_midnight(2026, 1, 1);
+        let planet = |sma_km: f64, ta_deg: f64| {
+            Orbit::try_keplerian(sma_km, 1e-4, 0.5, 0.0, 0.0, ta_deg, epoch, sun).unwrap()
+        };
+        // Earth, Venus, Earth like sequence
+        let encounters = [
+            Encounter {
+                body: planet(149.6e6, 0.0),
+                gm_km3_s2: 398_600.435_436,
+                min_periapsis_km: 6_678.0,
+            },
+            Encounter {
+                body: planet(108.2e6, 0.0).at_epoch(epoch + 150.days()).unwrap(),
+                gm_km3_s2: 324_858.592,
+                min_periapsis_km: 6_351.0,
+            },
+            Encounter {
+                body: planet(149.6e6, 0.0).at_epoch(epoch + 450.days()).unwrap(),
+                gm_km3_s2: 398_600.435_436,
+                min_periapsis_km: 6_678.0,
+            },
+        ];
+
+        let sequence = FlybySequence::new(&encounters).unwrap();
+        assert_eq!(sequence.legs.len(), 2);
+        assert_eq!(sequence.flybys.len(), 1);
+        println!("{}", sequence.flybys[0]);
+        assert!(sequence.total_v_inf_mismatch_km_s().is_finite());
+
+        // Refine the flyby with a numerical propagation about the flyby body, from a perturbed approach
+        let venus = EARTH_J2000.with_mu_km3_s2(324_858.592);
+        let almanac = Arc::new(Almanac::default());
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+        let template = Spacecraft::builder().orbit(encounters[1].body).build();
+        let mut approach = sequence
+            .approach_state(0, template, venus, 1.days(), &prop, almanac.clone())
+            .unwrap();
+        assert_eq!(approach.epoch(), sequence.flybys[0].epoch - 1.days());
+        approach.orbit.velocity_km_s += Vector3::new(1e-3, -1e-3, 5e-4);
+
+        let solution = sequence.refine_flyby(0, approach, &prop, almanac).unwrap();
+        let target = sequence.flybys[0].b_plane_target();
+        let b_plane = BPlane::new(solution.achieved_state.orbit).unwrap();
+        assert!((b_plane.b_dot_t() - target.b_t_km).abs() < 1.0);
+        assert!((b_plane.b_dot_r() - target.b_r_km).abs() < 1.0);
+    }
+}
@@ -0,0 +1,368 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::cosmic::{Frame, Orbit};
+use crate::errors::NyxError;
+use crate::io::watermark::pq_writer;
+use crate::md::prelude::Traj;
+use crate::md::StateParameter;
+use crate::time::{Duration, Epoch};
+use crate::Spacecraft;
+use anise::almanac::Almanac;
+use anise::math::angles::between_pm_180;
+use arrow::array::{Array, Float64Builder, StringBuilder, UInt64Builder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use hifitime::TimeScale;
+use parquet::arrow::ArrowWriter;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Geodetic position of the sub-spacecraft point at a given epoch.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GroundTrackPoint {
+    pub epoch: Epoch,
+    /// Geodetic latitude, in degrees
+    pub latitude_deg: f64,
+    /// Longitude, between -180 and +180 degrees
+    pub longitude_deg: f64,
+    /// Height above the reference ellipsoid, in km
+    pub height_km: f64,
+}
+
+impl GroundTrackPoint {
+    /// Computes the sub-spacecraft point of this orbit, which must be expressed in a body fixed frame with a shape.
+    pub fn from_orbit(orbit: Orbit) -> Result<Self, NyxError> {
+        let unavailable = |param: StateParameter| {
+            move |e: anise::errors::PhysicsError| NyxError::StateParameterUnavailable {
+                param,
+                msg: format!("{e} in {}", orbit.frame),
+            }
+        };
+
+        Ok(Self {
+            epoch: orbit.epoch,
+            latitude_deg: orbit
+                .latitude_deg()
+                .map_err(unavailable(StateParameter::Latitude))?,
+            longitude_deg: between_pm_180(orbit.longitude_deg()),
+            height_km: orbit
+                .height_km()
+                .map_err(unavailable(StateParameter::Height))?,
+        })
+    }
+}
+
+/// Ground track of a trajectory, split in segments at the antimeridian.
+///
+/// Consecutive segments share the epoch of the antimeridian crossing: the last point of a segment is on one side
+/// of the antimeridian (e.g. +180 degrees) and the first point of the next segment on the other side (-180 degrees).
+#[derive(Clone, Debug)]
+pub struct GroundTrack {
+    /// Body fixed frame of the ground track
+    pub frame: Frame,
+    pub segments: Vec<Vec<GroundTrackPoint>>,
+}
+
+impl GroundTrack {
+    /// Samples the trajectory with the provided step and computes its ground track in the body fixed frame.
+    ///
+    /// The frame must be a body fixed frame with a shape, e.g. as returned by `almanac.frame_from_uid(IAU_EARTH_FRAME)`.
+    pub fn from_traj(
+        traj: &Traj<Spacecraft>,
+        step: Duration,
+        body_fixed: Frame,
+        almanac: Arc<Almanac>,
+    ) -> Result<Self, NyxError> {
+        let points = traj
+            .every(step)
+            .map(|sc| {
+                let orbit = almanac
+                    .transform_to(sc.orbit, body_fixed, None)
+                    .map_err(|e| NyxError::FromAlmanacError {
+                        source: Box::new(e),
+                        action: "computing the ground track",
+                    })?;
+                GroundTrackPoint::from_orbit(orbit)
+            })
+            .collect::<Result<Vec<GroundTrackPoint>, NyxError>>()?;
+
+        Ok(Self::from_points(body_fixed, points))
+    }
+
+    /// Builds a ground track from chronologically sorted points, splitting it at the antimeridian.
+    pub fn from_points(frame: Frame, points: Vec<GroundTrackPoint>) -> Self {
+        let mut segments = Vec::new();
+        let mut segment: Vec<GroundTrackPoint> = Vec::new();
+
+        for point in points {
+            if let Some(prev) = segment.last().copied() {
+                let delta_lon_deg = point.longitude_deg - prev.longitude_deg;
+                if delta_lon_deg.abs() > 180.0 {
+                    // Crossed the antimeridian: interpolate the crossing, accounting for the wrapping of the longitude.
+                    let (edge_deg, unwrapped_lon_deg) = if delta_lon_deg < 0.0 {
+                        (180.0, point.longitude_deg + 360.0)
+                    } else {
+                        (-180.0, point.longitude_deg - 360.0)
+                    };
+                    let frac =
+                        (edge_deg - prev.longitude_deg) / (unwrapped_lon_deg - prev.longitude_deg);
+
+                    let crossing = GroundTrackPoint {
+                        epoch: prev.epoch + (point.epoch - prev.epoch) * frac,
+                        latitude_deg: prev.latitude_deg
+                            + (point.latitude_deg - prev.latitude_deg) * frac,
+                        longitude_deg: edge_deg,
+                        height_km: prev.height_km + (point.height_km - prev.height_km) * frac,
+                    };
+
+                    segment.push(crossing);
+                    segments.push(segment);
+                    segment = vec![GroundTrackPoint {
+                        longitude_deg: -edge_deg,
+                        ..crossing
+                    }];
+                }
+            }
+            segment.push(point);
+        }
+
+        if !segment.is_empty() {
+            segments.push(segment);
+        }
+
+        Self { frame, segments }
+    }
+
+    /// Returns an iterator over all of the points of this ground track, including the antimeridian crossings.
+    pub fn points(&self) -> impl Iterator<Item = &GroundTrackPoint> {
+        self.segments.iter().flatten()
+    }
+
+    /// Exports this ground track to a parquet file, with one row per point.
+    pub fn to_parquet<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Box<dyn Error>> {
+        let path_buf = path.as_ref().to_path_buf();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("Segment", DataType::UInt64, false),
+            Field::new("Epoch (UTC)", DataType::Utf8, false),
+            Field::new("Latitude (deg)", DataType::Float64, false),
+            Field::new("Longitude (deg)", DataType::Float64, false),
+            Field::new("Height (km)", DataType::Float64, false),
+        ]));
+
+        let mut segment_idx = UInt64Builder::new();
+        let mut epochs = StringBuilder::new();
+        let mut latitudes = Float64Builder::new();
+        let mut longitudes = Float64Builder::new();
+        let mut heights = Float64Builder::new();
+        for (idx, segment) in self.segments.iter().enumerate() {
+            for point in segment {
+                segment_idx.append_value(idx as u64);
+                epochs.append_value(point.epoch.to_time_scale(TimeScale::UTC).to_isoformat());
+                latitudes.append_value(point.latitude_deg);
+                longitudes.append_value(point.longitude_deg);
+                heights.append_value(point.height_km);
+            }
+        }
+
+        let record: Vec<Arc<dyn Array>> = vec![
+            Arc::new(segment_idx.finish()),
+            Arc::new(epochs.finish()),
+            Arc::new(latitudes.finish()),
+            Arc::new(longitudes.finish()),
+            Arc::new(heights.finish()),
+        ];
+
+        let mut metadata = HashMap::new();
+        metadata.insert("Purpose".to_string(), "Ground track".to_string());
+        metadata.insert("Frame".to_string(), format!("{}", self.frame));
+
+        let file = File::create(&path_buf)?;
+        let mut writer = ArrowWriter::try_new(file, schema.clone(), pq_writer(Some(metadata)))?;
+        writer.write(&RecordBatch::try_new(schema, record)?)?;
+        writer.close()?;
+
+        info!("Ground track written to {}", path_buf.display());
+
+        Ok(path_buf)
+    }
+
+    /// Exports this ground track to a GeoJSON file, as a single feature whose geometry is a multi line string.
+    pub fn to_geojson<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Box<dyn Error>> {
+        let path_buf = path.as_ref().to_path_buf();
+        let mut writer = BufWriter::new(File::create(&path_buf)?);
+
+        let lines = self
+            .segments
+            .iter()
+            .map(|segment| {
+                let coords = segment
+                    .iter()
+                    .map(|point| format!("[{},{}]", point.longitude_deg, point.latitude_deg))
+                    .collect::<Vec<String>>()
+                    .join(",");
+                format!("[{coords}]")
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+
+        write!(
+            writer,
+            r#"{{"type":"FeatureCollection","features":[{{"type":"Feature","properties":{{"frame":"{}","start":"{}","end":"{}"}},"geometry":{{"type":"MultiLineString","coordinates":[{lines}]}}}}]}}"#,
+            self.frame,
+            self.first_epoch_utc(),
+            self.last_epoch_utc(),
+        )?;
+        writer.flush()?;
+
+        info!("Ground track written to {}", path_buf.display());
+
+        Ok(path_buf)
+    }
+
+    /// Exports this ground track to a KML file, as a single placemark with one line string per segment.
+    pub fn to_kml<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Box<dyn Error>> {
+        let path_buf = path.as_ref().to_path_buf();
+        let mut writer = BufWriter::new(File::create(&path_buf)?);
+
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(writer, r#"<kml xmlns="http://www.opengis.net/kml/2.2">"#)?;
+        writeln!(writer, "<Document>")?;
+        writeln!(writer, "<Placemark>")?;
+        writeln!(
+            writer,
+            "<name>Ground track from {} to {}</name>",
+            self.first_epoch_utc(),
+            self.last_epoch_utc()
+        )?;
+        writeln!(writer, "<description>{}</description>", self.frame)?;
+        writeln!(writer, "<MultiGeometry>")?;
+        for segment in &self.segments {
+            writeln!(writer, "<LineString>")?;
+            writeln!(writer, "<tessellate>1</tessellate>")?;
+            write!(writer, "<coordinates>")?;
+            for point in segment {
+                write!(writer, "{},{},0 ", point.longitude_deg, point.latitude_deg)?;
+            }
+            writeln!(writer, "</coordinates>")?;
+            writeln!(writer, "</LineString>")?;
+        }
+        writeln!(writer, "</MultiGeometry>")?;
+        writeln!(writer, "</Placemark>")?;
+        writeln!(writer, "</Document>")?;
+        writeln!(writer, "</kml>")?;
+        writer.flush()?;
+
+        info!("Ground track written to {}", path_buf.display());
+
+        Ok(path_buf)
+    }
+
+    fn first_epoch_utc(&self) -> String {
+        self.points()
+            .next()
+            .map(|point| point.epoch.to_time_scale(TimeScale::UTC).to_isoformat())
+            .unwrap_or_default()
+    }
+
+    fn last_epoch_utc(&self) -> String {
+        self.points()
+            .last()
+            .map(|point| point.epoch.to_time_scale(TimeScale::UTC).to_isoformat())
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Display for GroundTrack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ground track in {} of {} points in {} segments",
+            self.frame,
+            self.points().count(),
+            self.segments.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod ut_ground_track {
+    use super::*;
+    use crate::time::TimeUnits;
+    use anise::constants::frames::EARTH_J2000;
+
+    #[test]
+    fn antimeridian_split() {
+        let epoch = Epoch::from_gregorian_utc_at_midnight(2024, 1, 1);
+        // Track which crosses the antimeridian eastward, and then westward
+        let points = [160.0, 170.0, -150.0, -174.0, 178.0, 170.0]
+            .iter()
+            .enumerate()
+            .map(|(i, lon)| GroundTrackPoint {
+                epoch: epoch + (i as i64).minutes(),
+                latitude_deg: i as f64,
+                longitude_deg: *lon,
+                height_km: 400.0,
+            })
+            .collect::<Vec<GroundTrackPoint>>();
+
+        let track = GroundTrack::from_points(EARTH_J2000, points);
+        println!("{track}");
+        assert_eq!(track.segments.len(), 3);
+
+        // Eastward crossing between 170 and -150: 10 degrees out of 40, i.e. at 1.25 minutes.
+        let crossing = track.segments[0].last().unwrap();
+        assert_eq!(crossing.longitude_deg, 180.0);
+        assert!((crossing.latitude_deg - 1.25).abs() < 1e-12);
+        assert!((crossing.epoch - (epoch + 75.seconds())).abs() < 1.microseconds());
+        assert_eq!(track.segments[1][0].longitude_deg, -180.0);
+        assert_eq!(track.segments[1][0].epoch, crossing.epoch);
+
+        // Westward crossing between -174 and 178: 6 degrees out of 8.
+        assert_eq!(track.segments[1].last().unwrap().longitude_deg, -180.0);
+        assert_eq!(track.segments[2][0].longitude_deg, 180.0);
+        assert!((track.segments[2][0].latitude_deg - 3.75).abs() < 1e-12);
+        assert_eq!(track.points().count(), 6 + 4);
+
+        for segment in &track.segments {
+            for pair in segment.windows(2) {
+                assert!((pair[1].longitude_deg - pair[0].longitude_deg).abs() <= 180.0);
+            }
+        }
+
+        let dir = std::env::temp_dir();
+        track
+            .to_parquet(dir.join("nyx_ut_ground_track.parquet"))
+            .unwrap();
+        let geojson = track
+            .to_geojson(dir.join("nyx_ut_ground_track.geojson"))
+            .unwrap();
+        let contents = std::fs::read_to_string(geojson).unwrap();
+        assert!(contents.contains("MultiLineString"));
+        assert_eq!(contents.matches('{').count(), contents.matches('}').count());
+        let kml = track.to_kml(dir.join("nyx_ut_ground_track.kml")).unwrap();
+        let contents = std::fs::read_to_string(kml).unwrap();
+        assert_eq!(contents.matches("<LineString>").count(), 3);
+    }
+}
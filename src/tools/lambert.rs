@@ -16,8 +16,10 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use crate::cosmic::Orbit;
 use crate::errors::NyxError;
 use crate::linalg::Vector3;
+use crate::Spacecraft;
 use std::f64::consts::PI;
 
 const TAU: f64 = 2.0 * PI;
@@ -29,6 +31,7 @@ const LAMBERT_EPSILON_RAD: f64 = (5e-5 / 180.0) * PI; // 0.00005 degrees
 const MAX_ITERATIONS: usize = 1000;
 
 /// Define the transfer kind for a Lambert
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum TransferKind {
     Auto,
     ShortWay,
@@ -190,6 +193,346 @@ pub fn standard(
     })
 }
 
+/// Tolerance on the Lagrange parameter x in the Izzo Lambert solver.
+const IZZO_X_TOL: f64 = 1e-12;
+/// Maximum number of Householder or Halley iterations of the Izzo Lambert solver.
+const IZZO_MAX_ITERATIONS: usize = 50;
+
+/// A solution of the Lambert problem on a specific branch, as returned by the Izzo solver.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LambertBranch {
+    /// Number of complete revolutions of this transfer
+    pub n_revs: u8,
+    /// For multi revolution transfers, there are two solutions for each number of revolutions: this is true for the low path one.
+    /// Single revolution transfers are always flagged as the low path.
+    pub low_path: bool,
+    /// Velocity at the initial radius, in km/s
+    pub v_init: Vector3<f64>,
+    /// Velocity at the final radius, in km/s
+    pub v_final: Vector3<f64>,
+}
+
+/// Solve the Lambert boundary problem using Izzo's algorithm (Izzo, 2015, "Revisiting Lambert's problem"), and return all the branches.
+///
+/// The transfer kind specifies the direction of motion: the short and long way transfers move along and against the
+/// angular momentum of the initial and final radii, and the automatic and `NRevs` transfers are prograde, i.e. the angular
+/// momentum of the transfer is along the positive Z axis of the frame. All of the single and multi revolution solutions are
+/// returned, from the fewest revolutions to the most, unless a specific number of revolutions is requested with `NRevs`.
+///
+/// # Arguments
+///
+/// * `r_init` - The initial radius vector, in km.
+/// * `r_final` - The final radius vector, in km.
+/// * `tof_s` - The time of flight, in seconds.
+/// * `gm` - The gravitational parameter, in km^3/s^2.
+/// * `kind` - The kind of transfer.
+pub fn izzo(
+    r_init: Vector3<f64>,
+    r_final: Vector3<f64>,
+    tof_s: f64,
+    gm: f64,
+    kind: TransferKind,
+) -> Result<Vec<LambertBranch>, NyxError> {
+    if tof_s <= 0.0 || gm <= 0.0 {
+        return Err(NyxError::MathDomain {
+            msg: format!("Lambert requires a positive time of flight ({tof_s} s) and GM ({gm})"),
+        });
+    }
+
+    let r_init_norm = r_init.norm();
+    let r_final_norm = r_final.norm();
+    let chord = (r_final - r_init).norm();
+    let semi_perimeter = 0.5 * (r_init_norm + r_final_norm + chord);
+
+    let i_r_init = r_init / r_init_norm;
+    let i_r_final = r_final / r_final_norm;
+    let i_h = i_r_init.cross(&i_r_final);
+    if i_h.norm() < LAMBERT_EPSILON_RAD {
+        // Co-linear radii do not define a transfer plane.
+        return Err(NyxError::TargetsTooClose);
+    }
+    let i_h = i_h / i_h.norm();
+
+    let (short_way, n_revs) = match kind {
+        TransferKind::Auto => (i_h.z >= 0.0, None),
+        TransferKind::ShortWay => (true, None),
+        TransferKind::LongWay => (false, None),
+        TransferKind::NRevs(n_revs) => (i_h.z >= 0.0, Some(n_revs)),
+    };
+
+    let lambda_abs = (1.0 - (chord / semi_perimeter).min(1.0)).sqrt();
+    let (lambda, i_t_init, i_t_final) = if short_way {
+        (lambda_abs, i_h.cross(&i_r_init), i_h.cross(&i_r_final))
+    } else {
+        (-lambda_abs, i_r_init.cross(&i_h), i_r_final.cross(&i_h))
+    };
+
+    // Non dimensional time of flight
+    let tof = (2.0 * gm / semi_perimeter.powi(3)).sqrt() * tof_s;
+
+    let max_revs = izzo_max_revs(lambda, tof)?;
+    let revs = match n_revs {
+        Some(n_revs) if n_revs > max_revs => {
+            return Err(NyxError::LambertNoSolution { n_revs, max_revs })
+        }
+        Some(n_revs) => n_revs..=n_revs,
+        None => 0..=max_revs,
+    };
+
+    let gamma = (gm * semi_perimeter / 2.0).sqrt();
+    let rho = (r_init_norm - r_final_norm) / chord;
+    let sigma = (1.0 - rho.powi(2)).sqrt();
+
+    let mut branches = Vec::new();
+    for n_revs in revs {
+        let paths: &[bool] = if n_revs == 0 { &[true] } else { &[true, false] };
+        for &low_path in paths {
+            let x = izzo_householder(
+                izzo_initial_guess(tof, lambda, n_revs, low_path),
+                tof,
+                lambda,
+                n_revs,
+            )?;
+            let y = izzo_y(x, lambda);
+
+            let v_r_init = gamma * ((lambda * y - x) - rho * (lambda * y + x)) / r_init_norm;
+            let v_r_final = -gamma * ((lambda * y - x) + rho * (lambda * y + x)) / r_final_norm;
+            let v_t_init = gamma * sigma * (y + lambda * x) / r_init_norm;
+            let v_t_final = gamma * sigma * (y + lambda * x) / r_final_norm;
+
+            branches.push(LambertBranch {
+                n_revs,
+                low_path,
+                v_init: v_r_init * i_r_init + v_t_init * i_t_init,
+                v_final: v_r_final * i_r_final + v_t_final * i_t_final,
+            });
+        }
+    }
+
+    Ok(branches)
+}
+
+/// A Lambert transfer between two orbits, with the maneuvers needed at departure and arrival.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LambertTransfer {
+    /// Orbit at departure, prior to the departure maneuver
+    pub departure: Orbit,
+    /// Orbit at arrival, after the arrival maneuver
+    pub arrival: Orbit,
+    /// Lambert solution of this transfer
+    pub solution: LambertBranch,
+}
+
+impl LambertTransfer {
+    /// Returns the departure maneuver, in km/s
+    pub fn departure_dv_km_s(&self) -> Vector3<f64> {
+        self.solution.v_init - self.departure.velocity_km_s
+    }
+
+    /// Returns the arrival maneuver, in km/s
+    pub fn arrival_dv_km_s(&self) -> Vector3<f64> {
+        self.arrival.velocity_km_s - self.solution.v_final
+    }
+
+    /// Returns the sum of the magnitudes of the departure and arrival maneuvers, in km/s
+    pub fn total_dv_km_s(&self) -> f64 {
+        self.departure_dv_km_s().norm() + self.arrival_dv_km_s().norm()
+    }
+
+    /// Returns the orbit right after the departure maneuver, i.e. the start of the transfer arc
+    pub fn transfer_orbit(&self) -> Orbit {
+        let mut orbit = self.departure;
+        orbit.velocity_km_s = self.solution.v_init;
+        orbit
+    }
+}
+
+/// Computes all of the Lambert transfers between the two orbits, from the epoch of the departure to the epoch of the arrival.
+///
+/// Both orbits must be in the same frame, and the GM of that frame is used. See [izzo] for the meaning of the transfer kind.
+pub fn transfer(
+    departure: Orbit,
+    arrival: Orbit,
+    kind: TransferKind,
+) -> Result<Vec<LambertTransfer>, NyxError> {
+    if departure.frame != arrival.frame {
+        return Err(NyxError::LambertFrameMismatch {
+            departure: departure.frame,
+            arrival: arrival.frame,
+        });
+    }
+
+    let gm = departure
+        .frame
+        .mu_km3_s2()
+        .map_err(|source| NyxError::LambertPhysicsError { source })?;
+
+    Ok(izzo(
+        departure.radius_km,
+        arrival.radius_km,
+        (arrival.epoch - departure.epoch).to_seconds(),
+        gm,
+        kind,
+    )?
+    .into_iter()
+    .map(|solution| LambertTransfer {
+        departure,
+        arrival,
+        solution,
+    })
+    .collect())
+}
+
+/// Computes all of the Lambert transfers between two spacecraft states, e.g. from two trajectories.
+pub fn transfer_between(
+    departure: &Spacecraft,
+    arrival: &Spacecraft,
+    kind: TransferKind,
+) -> Result<Vec<LambertTransfer>, NyxError> {
+    transfer(departure.orbit, arrival.orbit, kind)
+}
+
+/// Returns the maximum number of revolutions feasible for this non dimensional time of flight.
+fn izzo_max_revs(lambda: f64, tof: f64) -> Result<u8, NyxError> {
+    let mut max_revs = (tof / PI).floor();
+    let tof_00 = lambda.acos() + lambda * (1.0 - lambda.powi(2)).sqrt();
+    if max_revs > 0.0 && tof < tof_00 + max_revs * PI {
+        // Find the minimum time of flight of the max_revs revolution transfer with Halley iterations on dT/dx = 0
+        let n_revs = max_revs.min(f64::from(u8::MAX)) as u8;
+        let mut x = 0.1;
+        let mut converged = false;
+        for _ in 0..IZZO_MAX_ITERATIONS {
+            let y = izzo_y(x, lambda);
+            let tof_x = izzo_tof(x, y, lambda, n_revs);
+            let dt = izzo_tof_dx(x, y, tof_x, lambda);
+            let ddt = izzo_tof_dx2(x, y, tof_x, dt, lambda);
+            let dddt = izzo_tof_dx3(x, y, dt, ddt, lambda);
+            let next_x = x - 2.0 * dt * ddt / (2.0 * ddt.powi(2) - dt * dddt);
+            converged = (next_x - x).abs() < IZZO_X_TOL;
+            x = next_x;
+            if converged {
+                break;
+            }
+        }
+        if !converged {
+            return Err(NyxError::MaxIterReached {
+                msg: format!(
+                    "{IZZO_MAX_ITERATIONS} for the minimum time of flight of the {n_revs} revolution Lambert"
+                ),
+            });
+        }
+        if tof < izzo_tof(x, izzo_y(x, lambda), lambda, n_revs) {
+            max_revs -= 1.0;
+        }
+    }
+    Ok(max_revs.min(f64::from(u8::MAX)) as u8)
+}
+
+fn izzo_initial_guess(tof: f64, lambda: f64, n_revs: u8, low_path: bool) -> f64 {
+    if n_revs == 0 {
+        let tof_0 = lambda.acos() + lambda * (1.0 - lambda.powi(2)).sqrt();
+        let tof_1 = 2.0 * (1.0 - lambda.powi(3)) / 3.0;
+        if tof >= tof_0 {
+            (tof_0 / tof).powf(2.0 / 3.0) - 1.0
+        } else if tof < tof_1 {
+            2.5 * tof_1 / tof * (tof_1 - tof) / (1.0 - lambda.powi(5)) + 1.0
+        } else {
+            (2.0_f64.ln() * (tof / tof_0).ln() / (tof_1 / tof_0).ln()).exp() - 1.0
+        }
+    } else {
+        let m_pi = f64::from(n_revs) * PI;
+        let left = ((m_pi + PI) / (8.0 * tof)).powf(2.0 / 3.0);
+        let right = ((8.0 * tof) / m_pi).powf(2.0 / 3.0);
+        let x_left = (left - 1.0) / (left + 1.0);
+        let x_right = (right - 1.0) / (right + 1.0);
+        if low_path {
+            x_left.max(x_right)
+        } else {
+            x_left.min(x_right)
+        }
+    }
+}
+
+/// Solves the time of flight equation for x with Householder iterations.
+fn izzo_householder(mut x: f64, tof: f64, lambda: f64, n_revs: u8) -> Result<f64, NyxError> {
+    for _ in 0..IZZO_MAX_ITERATIONS {
+        let y = izzo_y(x, lambda);
+        let tof_x = izzo_tof(x, y, lambda, n_revs);
+        let f = tof_x - tof;
+        let dt = izzo_tof_dx(x, y, tof_x, lambda);
+        let ddt = izzo_tof_dx2(x, y, tof_x, dt, lambda);
+        let dddt = izzo_tof_dx3(x, y, dt, ddt, lambda);
+        let next_x = x - f
+            * ((dt.powi(2) - f * ddt / 2.0)
+                / (dt * (dt.powi(2) - f * ddt) + dddt * f.powi(2) / 6.0));
+        if (next_x - x).abs() < IZZO_X_TOL {
+            return Ok(next_x);
+        }
+        x = next_x;
+    }
+    Err(NyxError::MaxIterReached {
+        msg: format!("{IZZO_MAX_ITERATIONS} for the {n_revs} revolution Lambert"),
+    })
+}
+
+fn izzo_y(x: f64, lambda: f64) -> f64 {
+    (1.0 - lambda.powi(2) * (1.0 - x.powi(2))).sqrt()
+}
+
+/// Non dimensional time of flight as a function of x.
+fn izzo_tof(x: f64, y: f64, lambda: f64, n_revs: u8) -> f64 {
+    if n_revs == 0 && x > 0.6_f64.sqrt() && x < 1.4_f64.sqrt() {
+        // Battin's series close to the parabolic case
+        let eta = y - lambda * x;
+        let s_1 = 0.5 * (1.0 - lambda - x * eta);
+        let q = 4.0 / 3.0 * hypergeometric_2f1(s_1);
+        0.5 * (eta.powi(3) * q + 4.0 * lambda * eta)
+    } else {
+        let psi = if (-1.0..1.0).contains(&x) {
+            (x * y + lambda * (1.0 - x.powi(2))).acos()
+        } else if x > 1.0 {
+            ((y - x * lambda) * (x.powi(2) - 1.0).sqrt()).asinh()
+        } else {
+            0.0
+        };
+        ((psi + f64::from(n_revs) * PI) / (1.0 - x.powi(2)).abs().sqrt() - x + lambda * y)
+            / (1.0 - x.powi(2))
+    }
+}
+
+fn izzo_tof_dx(x: f64, y: f64, tof: f64, lambda: f64) -> f64 {
+    (3.0 * tof * x - 2.0 + 2.0 * lambda.powi(3) * x / y) / (1.0 - x.powi(2))
+}
+
+fn izzo_tof_dx2(x: f64, y: f64, tof: f64, dt: f64, lambda: f64) -> f64 {
+    (3.0 * tof + 5.0 * x * dt + 2.0 * (1.0 - lambda.powi(2)) * lambda.powi(3) / y.powi(3))
+        / (1.0 - x.powi(2))
+}
+
+fn izzo_tof_dx3(x: f64, y: f64, dt: f64, ddt: f64, lambda: f64) -> f64 {
+    (7.0 * x * ddt + 8.0 * dt - 6.0 * (1.0 - lambda.powi(2)) * lambda.powi(5) * x / y.powi(5))
+        / (1.0 - x.powi(2))
+}
+
+/// Gauss hypergeometric function 2F1(3, 1, 5/2, x) used in Battin's series.
+fn hypergeometric_2f1(x: f64) -> f64 {
+    if x >= 1.0 {
+        return f64::INFINITY;
+    }
+    let mut res = 1.0;
+    let mut term = 1.0;
+    for ii in 0..MAX_ITERATIONS {
+        let ii = ii as f64;
+        term *= (3.0 + ii) * (1.0 + ii) / (2.5 + ii) * x / (ii + 1.0);
+        let prev = res;
+        res += term;
+        if prev == res {
+            break;
+        }
+    }
+    res
+}
+
 #[test]
 fn test_lambert_vallado_shortway() {
     let ri = Vector3::new(15945.34, 0.0, 0.0);
@@ -221,3 +564,61 @@ fn test_lambert_vallado_lonway() {
     assert!((sol.v_init - exp_vi).norm() < 1e-6);
     assert!((sol.v_final - exp_vf).norm() < 1e-6);
 }
+
+#[test]
+fn test_lambert_izzo_vallado() {
+    let ri = Vector3::new(15945.34, 0.0, 0.0);
+    let rf = Vector3::new(12214.83899, 10249.46731, 0.0);
+    let tof_s = 76.0 * 60.0;
+    let gm = 3.98600433e5;
+
+    let short = izzo(ri, rf, tof_s, gm, TransferKind::ShortWay).unwrap();
+    assert_eq!(short.len(), 1);
+    assert!((short[0].v_init - Vector3::new(2.058913, 2.915965, 0.0)).norm() < 1e-5);
+    assert!((short[0].v_final - Vector3::new(-3.451565, 0.910315, 0.0)).norm() < 1e-5);
+
+    let long = izzo(ri, rf, tof_s, gm, TransferKind::LongWay).unwrap();
+    assert_eq!(long.len(), 1);
+    assert!((long[0].v_init - Vector3::new(-3.811158, -2.003854, 0.0)).norm() < 1e-5);
+    assert!((long[0].v_final - Vector3::new(4.207569, 0.914724, 0.0)).norm() < 1e-5);
+}
+
+#[test]
+fn test_lambert_izzo_multi_rev() {
+    use crate::time::TimeUnits;
+
+    let epoch = crate::fixtures::epoch();
+    let departure = crate::fixtures::keplerian(7_000.0, 0.01, 28.5, 30.0, 45.0, 10.0);
+    let target = crate::fixtures::keplerian(9_000.0, 0.05, 30.0, 35.0, 45.0, 250.0);
+    let arrival = target.at_epoch(epoch + 12.hours()).unwrap();
+
+    let transfers = transfer(departure, arrival, TransferKind::Auto).unwrap();
+    // Twelve hours allow for several revolutions, each with two branches
+    assert!(transfers.len() >= 5);
+    assert_eq!(transfers[0].solution.n_revs, 0);
+    let max_revs = transfers.last().unwrap().solution.n_revs;
+    assert_eq!(transfers.len(), 1 + 2 * max_revs as usize);
+
+    for xfer in &transfers {
+        // Each branch connects both positions in the time of flight
+        let end = xfer.transfer_orbit().at_epoch(arrival.epoch).unwrap();
+        assert!(
+            (end.radius_km - arrival.radius_km).norm() < 1e-3,
+            "{:?}",
+            xfer.solution
+        );
+        assert!((end.velocity_km_s - xfer.solution.v_final).norm() < 1e-6);
+        // And the angular momentum is prograde
+        assert!(xfer.transfer_orbit().hvec().unwrap().z > 0.0);
+        assert!(xfer.total_dv_km_s() > 0.0);
+    }
+
+    // A single number of revolutions may be requested
+    let two_revs = transfer(departure, arrival, TransferKind::NRevs(2)).unwrap();
+    assert_eq!(two_revs.len(), 2);
+    assert!(two_revs.iter().all(|xfer| xfer.solution.n_revs == 2));
+    assert!(matches!(
+        transfer(departure, arrival, TransferKind::NRevs(max_revs + 1)),
+        Err(NyxError::LambertNoSolution { .. })
+    ));
+}
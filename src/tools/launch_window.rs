@@ -0,0 +1,463 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::porkchop::{Porkchop, PorkchopPoint};
+use crate::cosmic::eclipse::EclipseLocator;
+use crate::cosmic::{Frame, Orbit};
+use crate::errors::NyxError;
+use crate::io::watermark::pq_writer;
+use crate::time::{Duration, Epoch, TimeSeries, Unit};
+use anise::almanac::Almanac;
+use arrow::array::{Array, Float64Builder, StringBuilder, UInt32Builder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use hifitime::TimeScale;
+use parquet::arrow::ArrowWriter;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use typed_builder::TypedBuilder;
+
+/// Constraints of a launch window scan. Every constraint is optional, and a transfer satisfies the constraints if it
+/// satisfies all of the ones that are set.
+#[derive(Copy, Clone, Debug, Default, TypedBuilder)]
+#[builder(doc)]
+pub struct LaunchConstraints {
+    /// Maximum characteristic energy at departure, in km^2/s^2, i.e. the capability of the launch vehicle
+    #[builder(default, setter(strip_option))]
+    pub max_c3_km2_s2: Option<f64>,
+    /// Maximum absolute declination of the departure asymptote, in degrees, e.g. to match the latitude of the launch site
+    #[builder(default, setter(strip_option))]
+    pub max_abs_dla_deg: Option<f64>,
+    /// Maximum hyperbolic excess velocity at arrival, in km/s
+    #[builder(default, setter(strip_option))]
+    pub max_v_inf_arrival_km_s: Option<f64>,
+    #[builder(default, setter(strip_option))]
+    pub min_tof: Option<Duration>,
+    #[builder(default, setter(strip_option))]
+    pub max_tof: Option<Duration>,
+    /// Minimum illumination of the spacecraft at separation, between 0.0 (umbra) and 1.0 (fully lit).
+    /// Only used when the scan computes the eclipses at separation.
+    #[builder(default, setter(strip_option))]
+    pub min_separation_illumination: Option<f64>,
+}
+
+impl LaunchConstraints {
+    /// Returns true if this transfer satisfies the C3, departure asymptote, arrival velocity and time of flight constraints.
+    pub fn accepts(&self, point: &PorkchopPoint) -> bool {
+        point.is_feasible()
+            && self.max_c3_km2_s2.is_none_or(|max| point.c3_km2_s2 <= max)
+            && self
+                .max_abs_dla_deg
+                .is_none_or(|max| point.dla_deg.abs() <= max)
+            && self
+                .max_v_inf_arrival_km_s
+                .is_none_or(|max| point.v_inf_arrival_km_s <= max)
+            && self.min_tof.is_none_or(|min| point.tof() >= min)
+            && self.max_tof.is_none_or(|max| point.tof() <= max)
+    }
+}
+
+/// The best transfer of a departure epoch of a launch window.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LaunchOpportunity {
+    /// Rank of this opportunity in the launch window, starting at 1 for the lowest C3
+    pub rank: u32,
+    /// Lambert transfer with the lowest departure C3 of this departure epoch which satisfies the constraints
+    pub transfer: PorkchopPoint,
+    /// Illumination of the spacecraft at separation, if computed
+    pub separation_illumination: Option<f64>,
+}
+
+/// A launch window, i.e. the opportunities of each departure epoch that satisfy the launch constraints, ranked by
+/// increasing departure C3.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LaunchWindow {
+    pub opportunities: Vec<LaunchOpportunity>,
+    /// Number of departure epochs without any transfer satisfying the constraints
+    pub rejected_departures: usize,
+    /// Name of the departure object
+    pub departure: String,
+    /// Name of the arrival object
+    pub arrival: String,
+}
+
+impl LaunchWindow {
+    /// Scans the departure epochs of this porkchop, and keeps the lowest C3 transfer of each departure epoch which
+    /// satisfies the constraints. The eclipses at separation are not computed.
+    pub fn scan(porkchop: &Porkchop, constraints: LaunchConstraints) -> Self {
+        Self::rank(porkchop, constraints, |_| Ok(None))
+            .expect("launch window scan without eclipses is infallible")
+    }
+
+    /// Sweeps the departure epochs between two bodies as a porkchop plot (cf. [Porkchop::between_bodies]), and scans the
+    /// resulting launch window.
+    pub fn between_bodies(
+        departure_body: Frame,
+        arrival_body: Frame,
+        center: Frame,
+        departures: TimeSeries,
+        arrivals: TimeSeries,
+        constraints: LaunchConstraints,
+        almanac: Arc<Almanac>,
+    ) -> Result<Self, NyxError> {
+        let porkchop = Porkchop::between_bodies(
+            departure_body,
+            arrival_body,
+            center,
+            departures,
+            arrivals,
+            almanac,
+        )?;
+        Ok(Self::scan(&porkchop, constraints))
+    }
+
+    /// Scans the departure epochs of this porkchop as [Self::scan], and also computes the illumination of the spacecraft at
+    /// separation with the eclipse locator, rejecting the transfers whose illumination is below the minimum illumination
+    /// of the constraints, if set.
+    ///
+    /// The separation state of each transfer is computed by the provided function, e.g. from the parking orbit and the
+    /// departure asymptote of the transfer.
+    pub fn scan_with_eclipses<F>(
+        porkchop: &Porkchop,
+        constraints: LaunchConstraints,
+        e_loc: &EclipseLocator,
+        separation_state: F,
+        almanac: Arc<Almanac>,
+    ) -> Result<Self, NyxError>
+    where
+        F: Fn(&PorkchopPoint) -> Result<Orbit, NyxError> + Sync,
+    {
+        Self::rank(porkchop, constraints, |point| {
+            let observer = separation_state(point)?;
+            e_loc
+                .illumination(observer, almanac.clone())
+                .map(Some)
+                .map_err(|e| NyxError::FromAlmanacError {
+                    source: Box::new(e),
+                    action: "computing the eclipse at separation",
+                })
+        })
+    }
+
+    /// Keeps the lowest C3 transfer of each departure epoch satisfying the constraints and the separation illumination, and
+    /// ranks them by C3.
+    fn rank<I>(
+        porkchop: &Porkchop,
+        constraints: LaunchConstraints,
+        illumination: I,
+    ) -> Result<Self, NyxError>
+    where
+        I: Fn(&PorkchopPoint) -> Result<Option<f64>, NyxError> + Sync,
+    {
+        // The points are ordered by departure epoch.
+        let mut departures: Vec<Vec<PorkchopPoint>> = Vec::new();
+        for point in &porkchop.points {
+            match departures.last_mut() {
+                Some(group) if group[0].departure_epoch == point.departure_epoch => {
+                    group.push(*point)
+                }
+                _ => departures.push(vec![*point]),
+            }
+        }
+        let num_departures = departures.len();
+
+        let best = departures
+            .into_par_iter()
+            .map(|mut group| -> Result<Option<LaunchOpportunity>, NyxError> {
+                group.retain(|point| constraints.accepts(point));
+                group.sort_by(|a, b| a.c3_km2_s2.total_cmp(&b.c3_km2_s2));
+
+                for transfer in group {
+                    let separation_illumination = illumination(&transfer)?;
+                    if let (Some(min), Some(lit)) = (
+                        constraints.min_separation_illumination,
+                        separation_illumination,
+                    ) {
+                        if lit < min {
+                            debug!(
+                                "rejecting departure at {} in eclipse at separation ({lit:.3} illumination)",
+                                transfer.departure_epoch
+                            );
+                            continue;
+                        }
+                    }
+                    return Ok(Some(LaunchOpportunity {
+                        rank: 0,
+                        transfer,
+                        separation_illumination,
+                    }));
+                }
+
+                Ok(None)
+            })
+            .collect::<Result<Vec<Option<LaunchOpportunity>>, NyxError>>()?;
+
+        let mut opportunities = best.into_iter().flatten().collect::<Vec<_>>();
+        opportunities.sort_by(|a, b| a.transfer.c3_km2_s2.total_cmp(&b.transfer.c3_km2_s2));
+        for (idx, opportunity) in opportunities.iter_mut().enumerate() {
+            opportunity.rank = idx as u32 + 1;
+        }
+
+        info!(
+            "Launch window from {} to {}: {} of {num_departures} departure epochs satisfy the constraints",
+            porkchop.departure,
+            porkchop.arrival,
+            opportunities.len()
+        );
+
+        Ok(Self {
+            rejected_departures: num_departures - opportunities.len(),
+            opportunities,
+            departure: porkchop.departure.clone(),
+            arrival: porkchop.arrival.clone(),
+        })
+    }
+
+    /// Returns the best opportunity, if any.
+    pub fn best(&self) -> Option<&LaunchOpportunity> {
+        self.opportunities.first()
+    }
+
+    /// Returns the first and last departure epochs of the opportunities, if any.
+    pub fn span(&self) -> Option<(Epoch, Epoch)> {
+        let first = self
+            .opportunities
+            .iter()
+            .map(|opp| opp.transfer.departure_epoch)
+            .min()?;
+        let last = self
+            .opportunities
+            .iter()
+            .map(|opp| opp.transfer.departure_epoch)
+            .max()?;
+        Some((first, last))
+    }
+
+    /// Store the ranked table of opportunities to a parquet file.
+    pub fn to_parquet<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Box<dyn Error>> {
+        let path_buf = path.as_ref().to_path_buf();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("Rank", DataType::UInt32, false),
+            Field::new("Departure epoch (UTC)", DataType::Utf8, false),
+            Field::new("Arrival epoch (UTC)", DataType::Utf8, false),
+            Field::new("TOF (days)", DataType::Float64, false),
+            Field::new("C3 (km^2/s^2)", DataType::Float64, false),
+            Field::new("DLA (deg)", DataType::Float64, false),
+            Field::new("RLA (deg)", DataType::Float64, false),
+            Field::new("Arrival v_inf (km/s)", DataType::Float64, false),
+            Field::new("Separation illumination", DataType::Float64, true),
+        ]));
+
+        let mut rank = UInt32Builder::new();
+        let mut departure_epochs = StringBuilder::new();
+        let mut arrival_epochs = StringBuilder::new();
+        let mut tof_days = Float64Builder::new();
+        let mut c3 = Float64Builder::new();
+        let mut dla = Float64Builder::new();
+        let mut rla = Float64Builder::new();
+        let mut v_inf = Float64Builder::new();
+        let mut illumination = Float64Builder::new();
+        for opportunity in &self.opportunities {
+            let point = opportunity.transfer;
+            rank.append_value(opportunity.rank);
+            departure_epochs.append_value(
+                point
+                    .departure_epoch
+                    .to_time_scale(TimeScale::UTC)
+                    .to_isoformat(),
+            );
+            arrival_epochs.append_value(
+                point
+                    .arrival_epoch
+                    .to_time_scale(TimeScale::UTC)
+                    .to_isoformat(),
+            );
+            tof_days.append_value(point.tof().to_unit(Unit::Day));
+            c3.append_value(point.c3_km2_s2);
+            dla.append_value(point.dla_deg);
+            rla.append_value(point.rla_deg);
+            v_inf.append_value(point.v_inf_arrival_km_s);
+            illumination.append_option(opportunity.separation_illumination);
+        }
+
+        let record: Vec<Arc<dyn Array>> = vec![
+            Arc::new(rank.finish()),
+            Arc::new(departure_epochs.finish()),
+            Arc::new(arrival_epochs.finish()),
+            Arc::new(tof_days.finish()),
+            Arc::new(c3.finish()),
+            Arc::new(dla.finish()),
+            Arc::new(rla.finish()),
+            Arc::new(v_inf.finish()),
+            Arc::new(illumination.finish()),
+        ];
+
+        let mut metadata = HashMap::new();
+        metadata.insert("Purpose".to_string(), "Launch window".to_string());
+        metadata.insert("Departure".to_string(), self.departure.clone());
+        metadata.insert("Arrival".to_string(), self.arrival.clone());
+        metadata.insert(
+            "Rejected departures".to_string(),
+            format!("{}", self.rejected_departures),
+        );
+
+        let file = File::create(&path_buf)?;
+        let mut writer = ArrowWriter::try_new(file, schema.clone(), pq_writer(Some(metadata)))?;
+        writer.write(&RecordBatch::try_new(schema, record)?)?;
+        writer.close()?;
+
+        info!(
+            "Launch window of {} opportunities written to {}",
+            self.opportunities.len(),
+            path_buf.display()
+        );
+
+        Ok(path_buf)
+    }
+}
+
+impl fmt::Display for LaunchWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Launch window from {} to {} ({} opportunities, {} rejected departures)",
+            self.departure,
+            self.arrival,
+            self.opportunities.len(),
+            self.rejected_departures
+        )?;
+        writeln!(
+            f,
+            "{:>4}  {:<24}  {:>9}  {:>9}  {:>8}  {:>8}  {:>9}  {:>6}",
+            "Rank", "Departure (UTC)", "TOF (d)", "C3", "DLA", "RLA", "v_inf", "Light"
+        )?;
+        for opportunity in &self.opportunities {
+            let point = opportunity.transfer;
+            writeln!(
+                f,
+                "{:>4}  {:<24}  {:>9.2}  {:>9.4}  {:>8.3}  {:>8.3}  {:>9.4}  {:>6}",
+                opportunity.rank,
+                format!("{}", point.departure_epoch.to_time_scale(TimeScale::UTC)),
+                point.tof().to_unit(Unit::Day),
+                point.c3_km2_s2,
+                point.dla_deg,
+                point.rla_deg,
+                point.v_inf_arrival_km_s,
+                opportunity
+                    .separation_illumination
+                    .map_or("-".to_string(), |lit| format!("{lit:.3}"))
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod ut_launch_window {
+    use super::*;
+    use crate::time::TimeUnits;
+    use anise::constants::frames::SUN_J2000;
+
+    fn earth_mars_like() -> Porkchop {
+        let sun = SUN_J2000.with_mu_km3_s2(132_712_440_041.939_38);
+        let epoch = Epoch::from_gregorian_utc_at_midnight(2026, 1, 1);
+        let earth = Orbit::try_keplerian(149.6e6, 1e-4, 0.0, 0.0, 0.0, 0.0, epoch, sun).unwrap();
+        // Slightly inclined to have a non-zero declination of the departure asymptote
+        let mars = Orbit::try_keplerian(227.9e6, 1e-4, 1.85, 0.0, 0.0, 44.0, epoch, sun).unwrap();
+
+        Porkchop::sweep(
+            "Earth".to_string(),
+            "Mars".to_string(),
+            |epoch| Ok(earth.at_epoch(epoch).unwrap()),
+            |epoch| Ok(mars.at_epoch(epoch).unwrap()),
+            TimeSeries::inclusive(epoch, epoch + 60.days(), 10.days()),
+            TimeSeries::inclusive(epoch + 200.days(), epoch + 320.days(), 10.days()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn launch_window_scan() {
+        let porkchop = earth_mars_like();
+
+        // Without constraints, every departure epoch has an opportunity, whose best is the minimum C3 of the porkchop.
+        let unconstrained = LaunchWindow::scan(&porkchop, LaunchConstraints::default());
+        println!("{unconstrained}");
+        assert_eq!(unconstrained.opportunities.len(), 7);
+        assert_eq!(unconstrained.rejected_departures, 0);
+        assert_eq!(
+            unconstrained.best().unwrap().transfer,
+            *porkchop.min_c3().unwrap()
+        );
+        assert!(unconstrained
+            .opportunities
+            .windows(2)
+            .all(|w| w[0].transfer.c3_km2_s2 <= w[1].transfer.c3_km2_s2
+                && w[1].rank == w[0].rank + 1));
+        assert!(unconstrained
+            .opportunities
+            .iter()
+            .all(|opp| opp.separation_illumination.is_none()));
+
+        // Constrain the C3 and the time of flight
+        let constraints = LaunchConstraints::builder()
+            .max_c3_km2_s2(unconstrained.opportunities[3].transfer.c3_km2_s2)
+            .max_tof(300.days())
+            .build();
+        let window = LaunchWindow::scan(&porkchop, constraints);
+        println!("{window}");
+        assert!(window.opportunities.len() < 7);
+        assert_eq!(window.opportunities.len() + window.rejected_departures, 7);
+        assert!(window
+            .opportunities
+            .iter()
+            .all(|opp| constraints.accepts(&opp.transfer) && opp.transfer.tof() <= 300.days()));
+
+        // Reject the departures whose separation is in eclipse
+        let best_departure = unconstrained.best().unwrap().transfer.departure_epoch;
+        let eclipsed = LaunchWindow::rank(
+            &porkchop,
+            LaunchConstraints::builder()
+                .min_separation_illumination(0.5)
+                .build(),
+            |point| {
+                Ok(Some(if point.departure_epoch == best_departure {
+                    0.0
+                } else {
+                    1.0
+                }))
+            },
+        )
+        .unwrap();
+        assert_eq!(eclipsed.opportunities.len(), 6);
+        assert_eq!(eclipsed.rejected_departures, 1);
+        assert!(eclipsed.opportunities.iter().all(|opp| {
+            opp.transfer.departure_epoch != best_departure
+                && opp.separation_illumination == Some(1.0)
+        }));
+
+        let path = std::env::temp_dir().join("nyx_ut_launch_window.parquet");
+        assert_eq!(window.to_parquet(&path).unwrap(), path);
+    }
+}
@@ -16,4 +16,34 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+/// Access windows between a trajectory and ground stations or celestial targets
+pub mod access;
+/// Chaos indicators of trajectories: fast Lyapunov indicators and finite time Lyapunov exponents
+pub mod chaos;
+/// Close approach screening between trajectories
+pub mod conjunction;
+/// Circular restricted three body problem and its periodic orbits
+pub mod cr3bp;
+/// Eclipse reports of trajectories, with eclipse seasons statistics
+pub mod eclipse_report;
+/// Gravity assist design and flyby sequences
+pub mod flyby;
+/// Ground track generation and export to parquet, GeoJSON and KML
+pub mod ground_track;
 pub mod lambert;
+/// Launch window scans of Lambert transfers with launch constraints
+pub mod launch_window;
+/// Patched conic interplanetary transfer design
+pub mod patched_conic;
+/// Poincaré maps of trajectories on a surface of section
+pub mod poincare;
+/// Porkchop plot generation from Lambert transfers
+pub mod porkchop;
+/// South Atlantic Anomaly and radiation belt (L-shell) crossings
+pub mod radiation;
+/// Repeat ground track orbit design under J2
+pub mod repeat_ground_track;
+/// Mission scenario files declaring the dynamics, maneuvers, tracking and orbit determination of a complete simulation
+pub mod scenario;
+/// Finite difference and design of experiments sensitivity analyses of end states
+pub mod sensitivity;
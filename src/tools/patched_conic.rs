@@ -0,0 +1,283 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::lambert::{transfer, LambertTransfer, TransferKind};
+use crate::cosmic::{BPlaneTarget, Frame, Orbit};
+use crate::errors::NyxError;
+use crate::linalg::Vector3;
+use crate::time::Epoch;
+use anise::almanac::Almanac;
+use std::fmt;
+use std::sync::Arc;
+
+/// A planetocentric hyperbola of a patched conic, defined by its hyperbolic excess velocity and its periapsis.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HyperbolicLeg {
+    /// Hyperbolic excess velocity, in km/s, in the orientation of the frame of the heliocentric transfer
+    pub v_inf_km_s: Vector3<f64>,
+    /// Periapsis radius, in km
+    pub periapsis_km: f64,
+    /// Gravitational parameter of the body, in km^3/s^2
+    pub gm_km3_s2: f64,
+}
+
+impl HyperbolicLeg {
+    /// Returns the characteristic energy, i.e. the square of the hyperbolic excess velocity, in km^2/s^2
+    pub fn c3_km2_s2(&self) -> f64 {
+        self.v_inf_km_s.norm_squared()
+    }
+
+    /// Returns the declination of the asymptote, in degrees
+    pub fn declination_deg(&self) -> f64 {
+        (self.v_inf_km_s.z / self.v_inf_km_s.norm())
+            .asin()
+            .to_degrees()
+    }
+
+    /// Returns the right ascension of the asymptote, in degrees between 0 and 360
+    pub fn right_ascension_deg(&self) -> f64 {
+        self.v_inf_km_s
+            .y
+            .atan2(self.v_inf_km_s.x)
+            .to_degrees()
+            .rem_euclid(360.0)
+    }
+
+    /// Returns the eccentricity of the hyperbola
+    pub fn ecc(&self) -> f64 {
+        1.0 + self.periapsis_km * self.c3_km2_s2() / self.gm_km3_s2
+    }
+
+    /// Returns the speed at periapsis, in km/s
+    pub fn periapsis_speed_km_s(&self) -> f64 {
+        (self.c3_km2_s2() + 2.0 * self.gm_km3_s2 / self.periapsis_km).sqrt()
+    }
+
+    /// Returns the magnitude of the maneuver between this hyperbola and the circular orbit at periapsis, in km/s
+    pub fn circular_dv_km_s(&self) -> f64 {
+        self.periapsis_speed_km_s() - (self.gm_km3_s2 / self.periapsis_km).sqrt()
+    }
+
+    /// Returns the magnitude of the B vector, i.e. the impact parameter, in km
+    pub fn b_mag_km(&self) -> f64 {
+        self.gm_km3_s2 / self.c3_km2_s2() * (self.ecc().powi(2) - 1.0).sqrt()
+    }
+
+    /// Returns the B plane target of this hyperbola for the provided B plane angle, measured from T towards R, in degrees.
+    pub fn b_plane_target(&self, b_angle_deg: f64) -> BPlaneTarget {
+        let (sin_theta, cos_theta) = b_angle_deg.to_radians().sin_cos();
+        BPlaneTarget::from_bt_br(self.b_mag_km() * cos_theta, self.b_mag_km() * sin_theta)
+    }
+
+    /// Returns the periapsis state of this hyperbola as an incoming hyperbola, for the provided B plane angle in degrees.
+    ///
+    /// The frame must be centered on the body and share the orientation of the frame of the heliocentric transfer.
+    pub fn periapsis_state(&self, b_angle_deg: f64, epoch: Epoch, frame: Frame) -> Orbit {
+        let s_hat = self.v_inf_km_s / self.v_inf_km_s.norm();
+        let t_hat = s_hat.cross(&Vector3::z()).normalize();
+        let r_hat = s_hat.cross(&t_hat);
+        let (sin_theta, cos_theta) = b_angle_deg.to_radians().sin_cos();
+        let b_hat = cos_theta * t_hat + sin_theta * r_hat;
+
+        // Periapsis and velocity directions from the incoming asymptote and the B vector
+        let ecc = self.ecc();
+        let k = (1.0 - ecc.powi(-2)).sqrt();
+        let e_hat = s_hat / ecc + k * b_hat;
+        let n_hat = k * s_hat - b_hat / ecc;
+
+        Orbit::new(
+            self.periapsis_km * e_hat.x,
+            self.periapsis_km * e_hat.y,
+            self.periapsis_km * e_hat.z,
+            self.periapsis_speed_km_s() * n_hat.x,
+            self.periapsis_speed_km_s() * n_hat.y,
+            self.periapsis_speed_km_s() * n_hat.z,
+            epoch,
+            frame,
+        )
+    }
+}
+
+/// A patched conic interplanetary transfer: a departure hyperbola, a single revolution heliocentric Lambert transfer,
+/// and an arrival hyperbola.
+///
+/// The launch C3, the departure asymptote (DLA and RLA), and the arrival B plane target are meant as a quick trade
+/// study tool and as the initial guess of the numerical targeting.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PatchedConic {
+    /// Departure hyperbola
+    pub departure: HyperbolicLeg,
+    /// Heliocentric transfer between the departure and arrival bodies
+    pub transfer: LambertTransfer,
+    /// Arrival hyperbola
+    pub arrival: HyperbolicLeg,
+}
+
+impl PatchedConic {
+    /// Builds the patched conic between the heliocentric states of the departure and arrival bodies.
+    ///
+    /// The GM of each body and the periapsis radius of each hyperbola, e.g. the parking orbit and the capture orbit, must be provided.
+    pub fn new(
+        departure_body: Orbit,
+        departure_gm_km3_s2: f64,
+        departure_periapsis_km: f64,
+        arrival_body: Orbit,
+        arrival_gm_km3_s2: f64,
+        arrival_periapsis_km: f64,
+    ) -> Result<Self, NyxError> {
+        let transfer = transfer(departure_body, arrival_body, TransferKind::NRevs(0))?[0];
+
+        Ok(Self {
+            departure: HyperbolicLeg {
+                v_inf_km_s: transfer.departure_dv_km_s(),
+                periapsis_km: departure_periapsis_km,
+                gm_km3_s2: departure_gm_km3_s2,
+            },
+            transfer,
+            arrival: HyperbolicLeg {
+                v_inf_km_s: -transfer.arrival_dv_km_s(),
+                periapsis_km: arrival_periapsis_km,
+                gm_km3_s2: arrival_gm_km3_s2,
+            },
+        })
+    }
+
+    /// Builds the patched conic between two bodies, whose ephemerides are fetched from the Almanac relative to the center frame.
+    ///
+    /// The body frames and the center frame must include their GM, e.g. as returned by `almanac.frame_from_uid`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn between_bodies(
+        departure_body: Frame,
+        departure_epoch: Epoch,
+        departure_periapsis_km: f64,
+        arrival_body: Frame,
+        arrival_epoch: Epoch,
+        arrival_periapsis_km: f64,
+        center: Frame,
+        almanac: Arc<Almanac>,
+    ) -> Result<Self, NyxError> {
+        let ephemeris = |body: Frame, epoch: Epoch| -> Result<Orbit, NyxError> {
+            almanac
+                .transform(body, center, epoch, None)
+                .map_err(|e| NyxError::FromAlmanacError {
+                    source: Box::new(e),
+                    action: "computing the patched conic ephemeris",
+                })
+        };
+        let gm = |body: Frame| {
+            body.mu_km3_s2()
+                .map_err(|source| NyxError::LambertPhysicsError { source })
+        };
+
+        Self::new(
+            ephemeris(departure_body, departure_epoch)?,
+            gm(departure_body)?,
+            departure_periapsis_km,
+            ephemeris(arrival_body, arrival_epoch)?,
+            gm(arrival_body)?,
+            arrival_periapsis_km,
+        )
+    }
+
+    /// Returns the launch characteristic energy, in km^2/s^2
+    pub fn c3_km2_s2(&self) -> f64 {
+        self.departure.c3_km2_s2()
+    }
+
+    /// Returns the declination of the launch asymptote, in degrees
+    pub fn dla_deg(&self) -> f64 {
+        self.departure.declination_deg()
+    }
+
+    /// Returns the right ascension of the launch asymptote, in degrees
+    pub fn rla_deg(&self) -> f64 {
+        self.departure.right_ascension_deg()
+    }
+
+    /// Returns the arrival B plane target for the provided B plane angle in degrees, to seed the targeting of the arrival.
+    pub fn arrival_b_plane_target(&self, b_angle_deg: f64) -> BPlaneTarget {
+        self.arrival.b_plane_target(b_angle_deg)
+    }
+
+    /// Returns the sum of the injection maneuver from the circular parking orbit and of the capture maneuver into the
+    /// circular orbit at the arrival periapsis, in km/s
+    pub fn total_dv_km_s(&self) -> f64 {
+        self.departure.circular_dv_km_s() + self.arrival.circular_dv_km_s()
+    }
+}
+
+impl fmt::Display for PatchedConic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "patched conic from {} to {}: C3 = {:.3} km^2/s^2, DLA = {:.3} deg, RLA = {:.3} deg, arrival v_inf = {:.3} km/s, total Δv = {:.3} km/s",
+            self.transfer.departure.epoch,
+            self.transfer.arrival.epoch,
+            self.c3_km2_s2(),
+            self.dla_deg(),
+            self.rla_deg(),
+            self.arrival.v_inf_km_s.norm(),
+            self.total_dv_km_s()
+        )
+    }
+}
+
+#[cfg(test)]
+mod ut_patched_conic {
+    use super::PatchedConic;
+    use crate::cosmic::{BPlane, Orbit};
+    use crate::time::{Epoch, TimeUnits};
+    use anise::constants::frames::{MARS_BARYCENTER_J2000, SUN_J2000};
+
+    #[test]
+    fn hohmann_like_earth_mars() {
+        let sun = SUN_J2000.with_mu_km3_s2(132_712_440_041.939_38);
+        let epoch = Epoch::from_gregorian_utc_at_midnight(2026, 1, 1);
+        let earth = Orbit::try_keplerian(149.6e6, 1e-4, 0.0, 0.0, 0.0, 0.0, epoch, sun).unwrap();
+        let mars = Orbit::try_keplerian(227.9e6, 1e-4, 0.0, 0.0, 0.0, 44.0, epoch, sun).unwrap();
+        let arrival_epoch = epoch + 259.days();
+
+        let conic = PatchedConic::new(
+            earth,
+            398_600.435_436,
+            6_578.0,
+            mars.at_epoch(arrival_epoch).unwrap(),
+            42_828.375_214,
+            3_796.0,
+        )
+        .unwrap();
+        println!("{conic}");
+
+        assert!((conic.c3_km2_s2() - 8.7).abs() < 1.0);
+        assert!(conic.dla_deg().abs() < 1e-6);
+        // Close to the Hohmann injection and capture into low circular orbits
+        assert!((conic.total_dv_km_s() - 5.7).abs() < 0.5);
+
+        // The periapsis state of the arrival hyperbola has the targeted B plane
+        let mars_frame = MARS_BARYCENTER_J2000.with_mu_km3_s2(42_828.375_214);
+        let target = conic.arrival_b_plane_target(30.0);
+        let periapsis = conic
+            .arrival
+            .periapsis_state(30.0, arrival_epoch, mars_frame);
+        assert!((periapsis.rmag_km() - 3_796.0).abs() < 1e-6);
+        let b_plane = BPlane::new(periapsis).unwrap();
+        assert!((b_plane.b_dot_t() - target.b_t_km).abs() < 1e-6);
+        assert!((b_plane.b_dot_r() - target.b_r_km).abs() < 1e-6);
+        assert!((b_plane.angle() - 30.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,351 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::dynamics::SpacecraftDynamics;
+use crate::errors::{EventError, NyxError};
+use crate::io::watermark::pq_writer;
+use crate::md::events::details::EventEdge;
+use crate::md::prelude::Traj;
+use crate::md::{Event, StateParameter};
+use crate::propagators::Propagator;
+use crate::time::Duration;
+use crate::{Spacecraft, State};
+use anise::almanac::Almanac;
+use arrow::array::{Array, Float64Builder, StringBuilder, UInt64Builder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use hifitime::TimeScale;
+use parquet::arrow::ArrowWriter;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Surface of section of a Poincaré map: the hyperplane where the event function is zero, e.g. `Y = 0` to record
+/// the crossings of the XZ plane.
+///
+/// Only crossings in the direction of the edge are recorded if it is set, e.g. [EventEdge::Rising] for the crossings
+/// where Y goes from negative to positive. Grazing crossings of the hyperplane are never recorded.
+#[derive(Clone, Debug)]
+pub struct PoincareSection {
+    pub event: Event,
+    pub edge: Option<EventEdge>,
+}
+
+impl PoincareSection {
+    pub fn new(event: Event, edge: Option<EventEdge>) -> Self {
+        Self { event, edge }
+    }
+
+    /// Section where the provided parameter crosses the value, in the provided direction.
+    pub fn crossing(parameter: StateParameter, value: f64, edge: Option<EventEdge>) -> Self {
+        Self::new(Event::new(parameter, value), edge)
+    }
+}
+
+impl fmt::Display for PoincareSection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.edge {
+            Some(edge) => write!(f, "{} ({edge:?} crossings)", self.event),
+            None => write!(f, "{} (all crossings)", self.event),
+        }
+    }
+}
+
+/// A point of a Poincaré map, i.e. a crossing of the surface of section by one of the trajectories.
+#[derive(Copy, Clone, Debug)]
+pub struct PoincarePoint {
+    /// Index of the trajectory (or of its initial state) which crossed the section
+    pub trajectory: usize,
+    /// Index of this crossing along its trajectory, in chronological order
+    pub crossing: usize,
+    pub edge: EventEdge,
+    pub state: Spacecraft,
+}
+
+/// Poincaré map of a set of trajectories on a surface of section.
+#[derive(Clone, Debug)]
+pub struct PoincareMap {
+    pub section: PoincareSection,
+    /// Crossings of all of the trajectories, sorted by trajectory and then chronologically
+    pub points: Vec<PoincarePoint>,
+}
+
+impl PoincareMap {
+    /// Propagates each initial state for the provided duration (in parallel) and records the crossings of the section.
+    pub fn compute(
+        initial_states: &[Spacecraft],
+        prop: &Propagator<SpacecraftDynamics>,
+        duration: Duration,
+        section: PoincareSection,
+        almanac: Arc<Almanac>,
+    ) -> Result<Self, NyxError> {
+        let trajs = initial_states
+            .par_iter()
+            .enumerate()
+            .map(|(idx, state)| {
+                prop.with(*state, almanac.clone())
+                    .for_duration_with_traj(duration)
+                    .map(|(_, traj)| traj)
+                    .map_err(|e| NyxError::CustomError {
+                        msg: format!("propagating initial state #{idx} of the Poincaré map: {e}"),
+                    })
+            })
+            .collect::<Result<Vec<Traj<Spacecraft>>, NyxError>>()?;
+
+        Self::from_trajs(&trajs, section, almanac)
+    }
+
+    /// Records the crossings of the section by each of the trajectories.
+    pub fn from_trajs(
+        trajs: &[Traj<Spacecraft>],
+        section: PoincareSection,
+        almanac: Arc<Almanac>,
+    ) -> Result<Self, NyxError> {
+        let mut points = Vec::new();
+        for (idx, traj) in trajs.iter().enumerate() {
+            let mut crossings = match traj.find(&section.event, almanac.clone()) {
+                Ok(crossings) => crossings,
+                Err(EventError::NotFound { .. }) => Vec::new(),
+                Err(source) => return Err(NyxError::Event { source }),
+            };
+            crossings.retain(|details| match section.edge {
+                Some(edge) => details.edge == edge,
+                None => matches!(details.edge, EventEdge::Rising | EventEdge::Falling),
+            });
+            crossings.sort_by_key(|details| details.state.epoch());
+
+            points.extend(
+                crossings
+                    .into_iter()
+                    .enumerate()
+                    .map(|(crossing, details)| PoincarePoint {
+                        trajectory: idx,
+                        crossing,
+                        edge: details.edge,
+                        state: details.state,
+                    }),
+            );
+        }
+
+        info!(
+            "Poincaré map on {section}: {} crossings of {} trajectories",
+            points.len(),
+            trajs.len()
+        );
+
+        Ok(Self { section, points })
+    }
+
+    /// Returns an iterator over the crossings of the provided trajectory.
+    pub fn trajectory(&self, idx: usize) -> impl Iterator<Item = &PoincarePoint> {
+        self.points
+            .iter()
+            .filter(move |point| point.trajectory == idx)
+    }
+
+    /// Returns the coordinates of each point of the map in the two provided parameters, e.g. X and VX.
+    pub fn coordinates(
+        &self,
+        x: StateParameter,
+        y: StateParameter,
+    ) -> Result<Vec<(f64, f64)>, NyxError> {
+        self.points
+            .iter()
+            .map(|point| {
+                let value = |param: StateParameter| {
+                    point
+                        .state
+                        .value(param)
+                        .map_err(|e| NyxError::StateParameterUnavailable {
+                            param,
+                            msg: e.to_string(),
+                        })
+                };
+                Ok((value(x)?, value(y)?))
+            })
+            .collect()
+    }
+
+    /// Exports this map to a parquet file, with one row per point and its Cartesian state in the frame of the
+    /// trajectories.
+    pub fn to_parquet<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Box<dyn Error>> {
+        let path_buf = path.as_ref().to_path_buf();
+
+        let mut fields = vec![
+            Field::new("Trajectory", DataType::UInt64, false),
+            Field::new("Crossing", DataType::UInt64, false),
+            Field::new("Epoch (UTC)", DataType::Utf8, false),
+            Field::new("Edge", DataType::Utf8, false),
+        ];
+        let params = [
+            StateParameter::X,
+            StateParameter::Y,
+            StateParameter::Z,
+            StateParameter::VX,
+            StateParameter::VY,
+            StateParameter::VZ,
+        ];
+        for param in params {
+            fields.push(Field::new(
+                format!("{param:?} ({})", param.unit()),
+                DataType::Float64,
+                false,
+            ));
+        }
+        let schema = Arc::new(Schema::new(fields));
+
+        let mut trajectories = UInt64Builder::new();
+        let mut crossings = UInt64Builder::new();
+        let mut epochs = StringBuilder::new();
+        let mut edges = StringBuilder::new();
+        let mut components = params
+            .iter()
+            .map(|_| Float64Builder::new())
+            .collect::<Vec<Float64Builder>>();
+        for point in &self.points {
+            trajectories.append_value(point.trajectory as u64);
+            crossings.append_value(point.crossing as u64);
+            epochs.append_value(
+                point
+                    .state
+                    .epoch()
+                    .to_time_scale(TimeScale::UTC)
+                    .to_isoformat(),
+            );
+            edges.append_value(format!("{:?}", point.edge));
+            for (builder, value) in components
+                .iter_mut()
+                .zip(point.state.orbit.to_cartesian_pos_vel().iter())
+            {
+                builder.append_value(*value);
+            }
+        }
+
+        let mut record: Vec<Arc<dyn Array>> = vec![
+            Arc::new(trajectories.finish()),
+            Arc::new(crossings.finish()),
+            Arc::new(epochs.finish()),
+            Arc::new(edges.finish()),
+        ];
+        for mut builder in components {
+            record.push(Arc::new(builder.finish()));
+        }
+
+        let mut metadata = HashMap::new();
+        metadata.insert("Purpose".to_string(), "Poincaré map".to_string());
+        metadata.insert("Section".to_string(), format!("{}", self.section));
+        if let Some(point) = self.points.first() {
+            metadata.insert("Frame".to_string(), format!("{}", point.state.orbit.frame));
+        }
+
+        let file = File::create(&path_buf)?;
+        let mut writer = ArrowWriter::try_new(file, schema.clone(), pq_writer(Some(metadata)))?;
+        writer.write(&RecordBatch::try_new(schema, record)?)?;
+        writer.close()?;
+
+        info!("Poincaré map written to {}", path_buf.display());
+
+        Ok(path_buf)
+    }
+}
+
+impl fmt::Display for PoincareMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Poincaré map on {} with {} points",
+            self.section,
+            self.points.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod ut_poincare {
+    use super::*;
+    use crate::fixtures;
+    use crate::time::Unit;
+
+    #[test]
+    fn two_body_fixed_points() {
+        let initial_states = [7_000.0, 8_000.0, 12_000.0]
+            .iter()
+            .map(|sma_km| {
+                Spacecraft::builder()
+                    .orbit(fixtures::keplerian(*sma_km, 0.1, 30.0, 45.0, 60.0, 10.0))
+                    .build()
+            })
+            .collect::<Vec<Spacecraft>>();
+
+        // Crossings of the XZ plane toward positive Y
+        let section = PoincareSection::crossing(StateParameter::Y, 0.0, Some(EventEdge::Rising));
+        let map = PoincareMap::compute(
+            &initial_states,
+            &Propagator::default(SpacecraftDynamics::new(
+                crate::dynamics::OrbitalDynamics::two_body(),
+            )),
+            Unit::Day * 1,
+            section,
+            fixtures::almanac(),
+        )
+        .unwrap();
+        println!("{map}");
+
+        for (idx, sc) in initial_states.iter().enumerate() {
+            let period = sc.orbit.period().unwrap();
+            let points = map.trajectory(idx).collect::<Vec<&PoincarePoint>>();
+            // One crossing per revolution, give or take the partial revolutions at the bounds.
+            let revs = (Unit::Day * 1).to_seconds() / period.to_seconds();
+            assert!(
+                (points.len() as f64 - revs).abs() <= 1.0,
+                "{idx}: {} vs {revs}",
+                points.len()
+            );
+
+            // A Keplerian orbit is a fixed point of the map.
+            for pair in points.windows(2) {
+                assert_eq!(pair[1].crossing, pair[0].crossing + 1);
+                assert!(
+                    (pair[1].state.epoch() - pair[0].state.epoch() - period).abs()
+                        < Unit::Second * 1
+                );
+                assert!(
+                    (pair[1].state.orbit.radius_km - pair[0].state.orbit.radius_km).norm() < 1e-2
+                );
+            }
+            for point in &points {
+                assert!(point.state.orbit.radius_km.y.abs() < 1e-2);
+                assert!(point.state.orbit.velocity_km_s.y > 0.0);
+            }
+        }
+
+        let coords = map
+            .coordinates(StateParameter::X, StateParameter::VX)
+            .unwrap();
+        assert_eq!(coords.len(), map.points.len());
+
+        let path = map
+            .to_parquet(std::env::temp_dir().join("nyx_ut_poincare.parquet"))
+            .unwrap();
+        assert!(path.exists());
+    }
+}
@@ -0,0 +1,292 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::lambert::{transfer, TransferKind};
+use crate::cosmic::{Frame, Orbit};
+use crate::errors::NyxError;
+use crate::io::watermark::pq_writer;
+use crate::time::{Duration, Epoch, TimeSeries};
+use anise::almanac::Almanac;
+use arrow::array::{Array, Float64Builder, StringBuilder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use hifitime::TimeScale;
+use parquet::arrow::ArrowWriter;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A single departure and arrival epoch pair of a porkchop plot.
+///
+/// The characteristics are NaN if there is no transfer for this pair, e.g. if the arrival is prior to the departure.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PorkchopPoint {
+    pub departure_epoch: Epoch,
+    pub arrival_epoch: Epoch,
+    /// Characteristic energy at departure, in km^2/s^2
+    pub c3_km2_s2: f64,
+    /// Declination of the departure asymptote, in degrees
+    pub dla_deg: f64,
+    /// Right ascension of the departure asymptote, in degrees
+    pub rla_deg: f64,
+    /// Hyperbolic excess velocity at arrival, in km/s
+    pub v_inf_arrival_km_s: f64,
+}
+
+impl PorkchopPoint {
+    /// Returns the time of flight of this transfer
+    pub fn tof(&self) -> Duration {
+        self.arrival_epoch - self.departure_epoch
+    }
+
+    /// Returns true if there is a transfer for this pair of epochs
+    pub fn is_feasible(&self) -> bool {
+        self.c3_km2_s2.is_finite()
+    }
+}
+
+/// The grid of a porkchop plot, sweeping departure and arrival epochs, where each point is the single revolution
+/// prograde Lambert transfer between the departure and arrival states.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Porkchop {
+    /// All of the points, ordered by departure epoch and then by arrival epoch
+    pub points: Vec<PorkchopPoint>,
+    /// Name of the departure object
+    pub departure: String,
+    /// Name of the arrival object
+    pub arrival: String,
+}
+
+impl Porkchop {
+    /// Sweeps the departure and arrival epochs between two bodies, whose ephemerides are fetched from the Almanac
+    /// relative to the center frame, e.g. the Sun for interplanetary transfers. The center frame must include its GM.
+    pub fn between_bodies(
+        departure_body: Frame,
+        arrival_body: Frame,
+        center: Frame,
+        departures: TimeSeries,
+        arrivals: TimeSeries,
+        almanac: Arc<Almanac>,
+    ) -> Result<Self, NyxError> {
+        let ephemeris = |body: Frame, epoch: Epoch| -> Result<Orbit, NyxError> {
+            almanac
+                .transform(body, center, epoch, None)
+                .map_err(|e| NyxError::FromAlmanacError {
+                    source: Box::new(e),
+                    action: "computing the porkchop ephemeris",
+                })
+        };
+
+        Self::sweep(
+            format!("{departure_body}"),
+            format!("{arrival_body}"),
+            |epoch| ephemeris(departure_body, epoch),
+            |epoch| ephemeris(arrival_body, epoch),
+            departures,
+            arrivals,
+        )
+    }
+
+    /// Sweeps the departure and arrival epochs between the states returned by the provided functions, which must be in the same frame.
+    pub fn sweep<D, A>(
+        departure: String,
+        arrival: String,
+        departure_state: D,
+        arrival_state: A,
+        departures: TimeSeries,
+        arrivals: TimeSeries,
+    ) -> Result<Self, NyxError>
+    where
+        D: Fn(Epoch) -> Result<Orbit, NyxError> + Sync,
+        A: Fn(Epoch) -> Result<Orbit, NyxError> + Sync,
+    {
+        let departure_states = departures
+            .map(&departure_state)
+            .collect::<Result<Vec<Orbit>, NyxError>>()?;
+        let arrival_states = arrivals
+            .map(&arrival_state)
+            .collect::<Result<Vec<Orbit>, NyxError>>()?;
+
+        info!(
+            "Computing a porkchop of {} departures by {} arrivals from {departure} to {arrival}",
+            departure_states.len(),
+            arrival_states.len()
+        );
+
+        let points = departure_states
+            .par_iter()
+            .flat_map_iter(|dep| {
+                arrival_states.iter().map(move |arr| {
+                    let mut point = PorkchopPoint {
+                        departure_epoch: dep.epoch,
+                        arrival_epoch: arr.epoch,
+                        c3_km2_s2: f64::NAN,
+                        dla_deg: f64::NAN,
+                        rla_deg: f64::NAN,
+                        v_inf_arrival_km_s: f64::NAN,
+                    };
+
+                    if arr.epoch > dep.epoch {
+                        match transfer(*dep, *arr, TransferKind::NRevs(0)) {
+                            Ok(transfers) => {
+                                let xfer = transfers[0];
+                                let v_inf = xfer.departure_dv_km_s();
+                                point.c3_km2_s2 = v_inf.norm_squared();
+                                point.dla_deg = (v_inf.z / v_inf.norm()).asin().to_degrees();
+                                point.rla_deg =
+                                    v_inf.y.atan2(v_inf.x).to_degrees().rem_euclid(360.0);
+                                point.v_inf_arrival_km_s = xfer.arrival_dv_km_s().norm();
+                            }
+                            Err(e) => {
+                                debug!("no transfer from {} to {}: {e}", dep.epoch, arr.epoch)
+                            }
+                        }
+                    }
+
+                    point
+                })
+            })
+            .collect::<Vec<PorkchopPoint>>();
+
+        Ok(Self {
+            points,
+            departure,
+            arrival,
+        })
+    }
+
+    /// Returns the feasible point with the lowest departure C3, if any.
+    pub fn min_c3(&self) -> Option<&PorkchopPoint> {
+        self.points
+            .iter()
+            .filter(|point| point.is_feasible())
+            .min_by(|a, b| a.c3_km2_s2.total_cmp(&b.c3_km2_s2))
+    }
+
+    /// Store the C3, departure asymptote, arrival v_inf, and time of flight grids to a parquet file.
+    pub fn to_parquet<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Box<dyn Error>> {
+        let path_buf = path.as_ref().to_path_buf();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("Departure epoch (UTC)", DataType::Utf8, false),
+            Field::new("Arrival epoch (UTC)", DataType::Utf8, false),
+            Field::new("TOF (days)", DataType::Float64, false),
+            Field::new("C3 (km^2/s^2)", DataType::Float64, false),
+            Field::new("DLA (deg)", DataType::Float64, false),
+            Field::new("RLA (deg)", DataType::Float64, false),
+            Field::new("Arrival v_inf (km/s)", DataType::Float64, false),
+        ]));
+
+        let mut departure_epochs = StringBuilder::new();
+        let mut arrival_epochs = StringBuilder::new();
+        let mut tof_days = Float64Builder::new();
+        let mut c3 = Float64Builder::new();
+        let mut dla = Float64Builder::new();
+        let mut rla = Float64Builder::new();
+        let mut v_inf = Float64Builder::new();
+        for point in &self.points {
+            departure_epochs.append_value(
+                point
+                    .departure_epoch
+                    .to_time_scale(TimeScale::UTC)
+                    .to_isoformat(),
+            );
+            arrival_epochs.append_value(
+                point
+                    .arrival_epoch
+                    .to_time_scale(TimeScale::UTC)
+                    .to_isoformat(),
+            );
+            tof_days.append_value(point.tof().to_unit(hifitime::Unit::Day));
+            c3.append_value(point.c3_km2_s2);
+            dla.append_value(point.dla_deg);
+            rla.append_value(point.rla_deg);
+            v_inf.append_value(point.v_inf_arrival_km_s);
+        }
+
+        let record: Vec<Arc<dyn Array>> = vec![
+            Arc::new(departure_epochs.finish()),
+            Arc::new(arrival_epochs.finish()),
+            Arc::new(tof_days.finish()),
+            Arc::new(c3.finish()),
+            Arc::new(dla.finish()),
+            Arc::new(rla.finish()),
+            Arc::new(v_inf.finish()),
+        ];
+
+        let mut metadata = HashMap::new();
+        metadata.insert("Purpose".to_string(), "Porkchop data".to_string());
+        metadata.insert("Departure".to_string(), self.departure.clone());
+        metadata.insert("Arrival".to_string(), self.arrival.clone());
+
+        let file = File::create(&path_buf)?;
+        let mut writer = ArrowWriter::try_new(file, schema.clone(), pq_writer(Some(metadata)))?;
+        writer.write(&RecordBatch::try_new(schema, record)?)?;
+        writer.close()?;
+
+        info!(
+            "Porkchop of {} points written to {}",
+            self.points.len(),
+            path_buf.display()
+        );
+
+        Ok(path_buf)
+    }
+}
+
+#[cfg(test)]
+mod ut_porkchop {
+    use super::Porkchop;
+    use crate::cosmic::Orbit;
+    use crate::time::{Epoch, TimeSeries, TimeUnits};
+    use anise::constants::frames::SUN_J2000;
+
+    #[test]
+    fn earth_mars_like() {
+        let sun = SUN_J2000.with_mu_km3_s2(132_712_440_041.939_38);
+        let epoch = Epoch::from_gregorian_utc_at_midnight(2026, 1, 1);
+        // Near circular coplanar orbits at 1 and 1.52 AU, with Mars-like phasing
+        let earth = Orbit::try_keplerian(149.6e6, 1e-4, 0.0, 0.0, 0.0, 0.0, epoch, sun).unwrap();
+        let mars = Orbit::try_keplerian(227.9e6, 1e-4, 0.0, 0.0, 0.0, 44.0, epoch, sun).unwrap();
+
+        let porkchop = Porkchop::sweep(
+            "Earth".to_string(),
+            "Mars".to_string(),
+            |epoch| Ok(earth.at_epoch(epoch).unwrap()),
+            |epoch| Ok(mars.at_epoch(epoch).unwrap()),
+            TimeSeries::inclusive(epoch, epoch + 60.days(), 10.days()),
+            TimeSeries::inclusive(epoch + 200.days(), epoch + 320.days(), 10.days()),
+        )
+        .unwrap();
+
+        assert_eq!(porkchop.points.len(), 7 * 13);
+        assert!(porkchop.points.iter().all(|point| point.is_feasible()));
+
+        // The optimum is close to the Hohmann transfer: C3 of 8.7 km^2/s^2 and arrival v_inf of 2.6 km/s
+        let best = porkchop.min_c3().unwrap();
+        assert!((best.c3_km2_s2 - 8.7).abs() < 1.0, "{best:?}");
+        assert!((best.v_inf_arrival_km_s - 2.6).abs() < 0.6, "{best:?}");
+        assert!(best.dla_deg.abs() < 1e-6);
+
+        let path = std::env::temp_dir().join("nyx_ut_porkchop.parquet");
+        assert_eq!(porkchop.to_parquet(&path).unwrap(), path);
+    }
+}
@@ -0,0 +1,551 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::cosmic::{Frame, Orbit};
+use crate::errors::{EventAlmanacSnafu, EventError, EventSnafu, NyxError};
+use crate::io::watermark::pq_writer;
+use crate::linalg::Vector3;
+use crate::md::prelude::Traj;
+use crate::md::EventEvaluator;
+use crate::time::{Duration, Epoch, Unit};
+use crate::utils::between_pm_180;
+use crate::{Spacecraft, State};
+use anise::almanac::Almanac;
+use arrow::array::{Array, Float64Builder, StringBuilder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use hifitime::TimeScale;
+use parquet::arrow::ArrowWriter;
+use snafu::ResultExt;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Approximate boundary of the South Atlantic Anomaly at about 500 km of altitude, as (longitude, latitude) in degrees.
+pub const SAA_BOUNDARY_DEG: [(f64, f64); 12] = [
+    (-90.0, -30.0),
+    (-80.0, -10.0),
+    (-60.0, -2.0),
+    (-30.0, -2.0),
+    (0.0, -8.0),
+    (30.0, -20.0),
+    (40.0, -32.0),
+    (25.0, -45.0),
+    (0.0, -52.0),
+    (-40.0, -55.0),
+    (-70.0, -50.0),
+    (-88.0, -42.0),
+];
+
+/// Returns the orbit in the body fixed frame, transforming it only if needed.
+fn to_body_fixed(
+    orbit: Orbit,
+    body_fixed: Frame,
+    almanac: Arc<Almanac>,
+) -> Result<Orbit, EventError> {
+    if orbit.frame.ephem_origin_match(body_fixed) && orbit.frame.orient_origin_match(body_fixed) {
+        Ok(orbit)
+    } else {
+        almanac
+            .transform_to(orbit, body_fixed, None)
+            .context(EventAlmanacSnafu)
+    }
+}
+
+/// The South Atlantic Anomaly (or any other geographic region), as a polygon of the sub-spacecraft point.
+///
+/// As an event, it evaluates to the distance in degrees between the sub-spacecraft point and the edge of the polygon
+/// (in the longitude/latitude plane), positive inside of the region. The polygon must not cross the antimeridian.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SaaRegion {
+    /// Body fixed frame with a shape, e.g. as returned by `almanac.frame_from_uid(IAU_EARTH_FRAME)`
+    pub body_fixed: Frame,
+    /// Vertices of the polygon as (longitude, latitude), in degrees
+    pub vertices_deg: Vec<(f64, f64)>,
+}
+
+impl SaaRegion {
+    /// Initializes the South Atlantic Anomaly with its approximate boundary at about 500 km of altitude.
+    pub fn new(body_fixed: Frame) -> Self {
+        Self {
+            body_fixed,
+            vertices_deg: SAA_BOUNDARY_DEG.to_vec(),
+        }
+    }
+
+    /// Initializes a region from the vertices of its polygon, as (longitude, latitude) in degrees.
+    pub fn from_polygon(
+        body_fixed: Frame,
+        vertices_deg: Vec<(f64, f64)>,
+    ) -> Result<Self, NyxError> {
+        if vertices_deg.len() < 3 {
+            return Err(NyxError::CustomError {
+                msg: format!(
+                    "a region requires at least three vertices but {} were provided",
+                    vertices_deg.len()
+                ),
+            });
+        }
+        Ok(Self {
+            body_fixed,
+            vertices_deg,
+        })
+    }
+
+    /// Returns whether this point is inside of the polygon.
+    pub fn contains(&self, longitude_deg: f64, latitude_deg: f64) -> bool {
+        // Ray casting toward increasing longitudes
+        let mut inside = false;
+        let n = self.vertices_deg.len();
+        for i in 0..n {
+            let (lon_a, lat_a) = self.vertices_deg[i];
+            let (lon_b, lat_b) = self.vertices_deg[(i + 1) % n];
+            if (lat_a > latitude_deg) != (lat_b > latitude_deg) {
+                let lon_cross = lon_a + (latitude_deg - lat_a) / (lat_b - lat_a) * (lon_b - lon_a);
+                if longitude_deg < lon_cross {
+                    inside = !inside;
+                }
+            }
+        }
+        inside
+    }
+
+    /// Returns the distance in degrees between this point and the edge of the polygon, positive inside.
+    pub fn signed_distance_deg(&self, longitude_deg: f64, latitude_deg: f64) -> f64 {
+        let n = self.vertices_deg.len();
+        let distance = (0..n)
+            .map(|i| {
+                let (lon_a, lat_a) = self.vertices_deg[i];
+                let (lon_b, lat_b) = self.vertices_deg[(i + 1) % n];
+                let (dx, dy) = (lon_b - lon_a, lat_b - lat_a);
+                let frac = (((longitude_deg - lon_a) * dx + (latitude_deg - lat_a) * dy)
+                    / (dx * dx + dy * dy))
+                    .clamp(0.0, 1.0);
+                (longitude_deg - lon_a - frac * dx).hypot(latitude_deg - lat_a - frac * dy)
+            })
+            .fold(f64::INFINITY, f64::min);
+
+        if self.contains(longitude_deg, latitude_deg) {
+            distance
+        } else {
+            -distance
+        }
+    }
+}
+
+impl fmt::Display for SaaRegion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SAA")
+    }
+}
+
+impl EventEvaluator<Spacecraft> for SaaRegion {
+    fn eval(&self, sc: &Spacecraft, almanac: Arc<Almanac>) -> Result<f64, EventError> {
+        let orbit = to_body_fixed(sc.orbit, self.body_fixed, almanac)?;
+        let latitude_deg = orbit
+            .latitude_deg()
+            .map_err(|source| EventError::EventPhysicsError { source })?;
+        Ok(self.signed_distance_deg(between_pm_180(orbit.longitude_deg()), latitude_deg))
+    }
+
+    fn eval_string(&self, sc: &Spacecraft, almanac: Arc<Almanac>) -> Result<String, EventError> {
+        Ok(format!(
+            "{:.3} deg inside the {self} on {}",
+            self.eval(sc, almanac)?,
+            sc.epoch()
+        ))
+    }
+
+    fn epoch_precision(&self) -> Duration {
+        1 * Unit::Second
+    }
+
+    fn value_precision(&self) -> f64 {
+        1e-3
+    }
+}
+
+/// Centered tilted dipole model of the geomagnetic field, used to compute the McIlwain L-shell.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GeomagneticDipole {
+    /// Latitude of the north geomagnetic pole, in degrees
+    pub pole_latitude_deg: f64,
+    /// Longitude of the north geomagnetic pole, in degrees
+    pub pole_longitude_deg: f64,
+    /// Reference radius of the Earth, in km
+    pub radius_km: f64,
+}
+
+impl Default for GeomagneticDipole {
+    /// Dipole of the IGRF-13 model at epoch 2020.0
+    fn default() -> Self {
+        Self {
+            pole_latitude_deg: 80.65,
+            pole_longitude_deg: -72.68,
+            radius_km: 6_371.2,
+        }
+    }
+}
+
+impl GeomagneticDipole {
+    /// Returns the geomagnetic latitude of this position in the body fixed frame, in degrees.
+    pub fn magnetic_latitude_deg(&self, radius_km: &Vector3<f64>) -> f64 {
+        let (lat, lon) = (
+            self.pole_latitude_deg.to_radians(),
+            self.pole_longitude_deg.to_radians(),
+        );
+        let pole = Vector3::new(lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin());
+        (radius_km.dot(&pole) / radius_km.norm())
+            .clamp(-1.0, 1.0)
+            .asin()
+            .to_degrees()
+    }
+
+    /// Returns the L-shell of this position in the body fixed frame, i.e. the equatorial distance in Earth radii of
+    /// the dipole field line which goes through it.
+    pub fn l_shell(&self, radius_km: &Vector3<f64>) -> f64 {
+        let cos_lat = self.magnetic_latitude_deg(radius_km).to_radians().cos();
+        radius_km.norm() / self.radius_km / (cos_lat * cos_lat)
+    }
+}
+
+/// Event which is positive while the L-shell of the spacecraft is above the threshold, e.g. to detect the crossings of
+/// the horns of the outer radiation belt by LEO spacecraft.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LShellEvent {
+    /// Body fixed frame, e.g. as returned by `almanac.frame_from_uid(IAU_EARTH_FRAME)`
+    pub body_fixed: Frame,
+    pub threshold: f64,
+    pub dipole: GeomagneticDipole,
+}
+
+impl LShellEvent {
+    /// Initializes an L-shell threshold with the default geomagnetic dipole.
+    pub fn new(body_fixed: Frame, threshold: f64) -> Self {
+        Self {
+            body_fixed,
+            threshold,
+            dipole: GeomagneticDipole::default(),
+        }
+    }
+
+    /// Returns the L-shell of the spacecraft.
+    pub fn l_shell(&self, sc: &Spacecraft, almanac: Arc<Almanac>) -> Result<f64, EventError> {
+        let orbit = to_body_fixed(sc.orbit, self.body_fixed, almanac)?;
+        Ok(self.dipole.l_shell(&orbit.radius_km))
+    }
+}
+
+impl fmt::Display for LShellEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "L > {}", self.threshold)
+    }
+}
+
+impl EventEvaluator<Spacecraft> for LShellEvent {
+    fn eval(&self, sc: &Spacecraft, almanac: Arc<Almanac>) -> Result<f64, EventError> {
+        Ok(self.l_shell(sc, almanac)? - self.threshold)
+    }
+
+    fn eval_string(&self, sc: &Spacecraft, almanac: Arc<Almanac>) -> Result<String, EventError> {
+        Ok(format!(
+            "L-shell is {:.4} on {}",
+            self.l_shell(sc, almanac)?,
+            sc.epoch()
+        ))
+    }
+
+    fn epoch_precision(&self) -> Duration {
+        1 * Unit::Second
+    }
+
+    fn value_precision(&self) -> f64 {
+        1e-4
+    }
+}
+
+/// A crossing of a radiation region, from its entry to its exit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RadiationWindow {
+    /// Description of the region, e.g. "SAA" or "L > 4"
+    pub region: String,
+    pub entry: Epoch,
+    pub exit: Epoch,
+}
+
+impl RadiationWindow {
+    /// Returns the dwell time in the region
+    pub fn duration(&self) -> Duration {
+        self.exit - self.entry
+    }
+}
+
+impl fmt::Display for RadiationWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} from {} to {} ({})",
+            self.region,
+            self.entry,
+            self.exit,
+            self.duration()
+        )
+    }
+}
+
+/// Radiation report of a trajectory: its crossings of the SAA and of L-shell thresholds.
+#[derive(Clone, Debug, Default)]
+pub struct RadiationReport {
+    /// Windows of all regions, sorted by entry epoch
+    pub windows: Vec<RadiationWindow>,
+}
+
+impl RadiationReport {
+    /// Computes the crossings of this trajectory through the SAA (if provided) and above each L-shell threshold.
+    pub fn compute(
+        traj: &Traj<Spacecraft>,
+        saa: Option<&SaaRegion>,
+        l_shells: &[LShellEvent],
+        almanac: Arc<Almanac>,
+    ) -> Result<Self, NyxError> {
+        let mut report = Self::default();
+
+        if let Some(saa) = saa {
+            report
+                .add_windows(format!("{saa}"), traj, saa, almanac.clone())
+                .context(EventSnafu)?;
+        }
+
+        for l_shell in l_shells {
+            report
+                .add_windows(format!("{l_shell}"), traj, l_shell, almanac.clone())
+                .context(EventSnafu)?;
+        }
+
+        Ok(report)
+    }
+
+    /// Adds the windows where the event is positive.
+    pub fn add_windows<E: EventEvaluator<Spacecraft>>(
+        &mut self,
+        region: String,
+        traj: &Traj<Spacecraft>,
+        event: &E,
+        almanac: Arc<Almanac>,
+    ) -> Result<(), EventError> {
+        let arcs = match traj.find_arcs(event, almanac) {
+            Ok(arcs) => arcs,
+            Err(EventError::NotFound { .. }) => {
+                info!("No crossing of {region}");
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+
+        self.windows
+            .extend(arcs.into_iter().map(|arc| RadiationWindow {
+                region: region.clone(),
+                entry: arc.rise.state.epoch(),
+                exit: arc.fall.state.epoch(),
+            }));
+
+        self.windows.sort_by_key(|window| window.entry);
+
+        Ok(())
+    }
+
+    /// Returns the windows of the provided region
+    pub fn windows_of<'a>(&'a self, region: &'a str) -> impl Iterator<Item = &'a RadiationWindow> {
+        self.windows
+            .iter()
+            .filter(move |window| window.region == region)
+    }
+
+    /// Returns the cumulative dwell time in the provided region
+    pub fn total_dwell(&self, region: &str) -> Duration {
+        self.windows_of(region)
+            .fold(Duration::ZERO, |total, window| total + window.duration())
+    }
+
+    /// Exports this report to a parquet file, with one row per window.
+    pub fn to_parquet<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Box<dyn Error>> {
+        let path_buf = path.as_ref().to_path_buf();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("Region", DataType::Utf8, false),
+            Field::new("Entry (UTC)", DataType::Utf8, false),
+            Field::new("Exit (UTC)", DataType::Utf8, false),
+            Field::new("Duration (s)", DataType::Float64, false),
+        ]));
+
+        let utc = |epoch: Epoch| epoch.to_time_scale(TimeScale::UTC).to_isoformat();
+
+        let mut regions = StringBuilder::new();
+        let mut entries = StringBuilder::new();
+        let mut exits = StringBuilder::new();
+        let mut durations = Float64Builder::new();
+        for window in &self.windows {
+            regions.append_value(&window.region);
+            entries.append_value(utc(window.entry));
+            exits.append_value(utc(window.exit));
+            durations.append_value(window.duration().to_seconds());
+        }
+
+        let record: Vec<Arc<dyn Array>> = vec![
+            Arc::new(regions.finish()),
+            Arc::new(entries.finish()),
+            Arc::new(exits.finish()),
+            Arc::new(durations.finish()),
+        ];
+
+        let mut metadata = HashMap::new();
+        metadata.insert("Purpose".to_string(), "Radiation report".to_string());
+
+        let file = File::create(&path_buf)?;
+        let mut writer = ArrowWriter::try_new(file, schema.clone(), pq_writer(Some(metadata)))?;
+        writer.write(&RecordBatch::try_new(schema, record)?)?;
+        writer.close()?;
+
+        info!(
+            "Radiation report of {} windows written to {}",
+            self.windows.len(),
+            path_buf.display()
+        );
+
+        Ok(path_buf)
+    }
+}
+
+impl fmt::Display for RadiationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} radiation windows", self.windows.len())?;
+        for window in &self.windows {
+            writeln!(f, "\t{window}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod ut_radiation {
+    use super::*;
+    use crate::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+    use crate::fixtures;
+    use crate::propagators::Propagator;
+    use crate::GMAT_EARTH_GM;
+    use anise::constants::frames::IAU_EARTH_FRAME;
+    use anise::structure::planetocentric::ellipsoid::Ellipsoid;
+
+    #[test]
+    fn saa_polygon() {
+        let saa = SaaRegion::new(IAU_EARTH_FRAME);
+        // Over Brazil, in the heart of the SAA
+        assert!(saa.contains(-45.0, -25.0));
+        assert!(saa.signed_distance_deg(-45.0, -25.0) > 10.0);
+        // Over Europe and over the Pacific
+        assert!(!saa.contains(10.0, 45.0));
+        assert!(saa.signed_distance_deg(10.0, 45.0) < -40.0);
+        assert!(!saa.contains(-150.0, -25.0));
+        // On the boundary, between the first two vertices
+        assert!(saa.signed_distance_deg(-85.0, -20.0).abs() < 1e-12);
+        assert!(SaaRegion::from_polygon(IAU_EARTH_FRAME, vec![(0.0, 0.0), (1.0, 1.0)]).is_err());
+    }
+
+    #[test]
+    fn dipole_l_shell() {
+        let dipole = GeomagneticDipole::default();
+        // On the geomagnetic equator, the L-shell is the distance in Earth radii.
+        let (lat, lon) = (
+            dipole.pole_latitude_deg.to_radians(),
+            dipole.pole_longitude_deg.to_radians(),
+        );
+        let pole = Vector3::new(lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin());
+        let equatorial = pole.cross(&Vector3::z()).normalize() * 2.0 * dipole.radius_km;
+        assert!(dipole.magnetic_latitude_deg(&equatorial).abs() < 1e-12);
+        assert!((dipole.l_shell(&equatorial) - 2.0).abs() < 1e-12);
+        // At 60 degrees of magnetic latitude, the field line reaches four times further at the equator.
+        let high = (equatorial.normalize() * 0.5 + pole * 0.75_f64.sqrt()) * dipole.radius_km;
+        assert!((dipole.magnetic_latitude_deg(&high) - 60.0).abs() < 1e-9);
+        assert!((dipole.l_shell(&high) - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn leo_radiation_report() {
+        // Propagate directly in the body fixed frame, ignoring its rotation, such that no almanac is needed.
+        let mut frame = IAU_EARTH_FRAME.with_mu_km3_s2(GMAT_EARTH_GM);
+        frame.shape = Some(Ellipsoid::from_sphere(6_378.136_3));
+        let epoch = fixtures::epoch();
+        // Without the rotation, the ground track is a fixed great circle, southernmost over the South Atlantic.
+        let orbit = Orbit::keplerian(6_878.0, 0.001, 51.6, 60.0, 0.0, 0.0, epoch, frame);
+
+        let almanac = fixtures::almanac();
+        let (_, traj) = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()))
+            .with(Spacecraft::builder().orbit(orbit).build(), almanac.clone())
+            .for_duration_with_traj(Unit::Day * 1)
+            .unwrap();
+
+        let saa = SaaRegion::new(frame);
+        let l_shell = LShellEvent::new(frame, 2.0);
+        let report =
+            RadiationReport::compute(&traj, Some(&saa), &[l_shell], almanac.clone()).unwrap();
+        println!("{report}");
+
+        let saa_windows = report.windows_of("SAA").collect::<Vec<&RadiationWindow>>();
+        assert!(!saa_windows.is_empty());
+        for window in &saa_windows {
+            let middle = traj.at(window.entry + window.duration() * 0.5).unwrap();
+            assert!(
+                saa.eval(&middle, almanac.clone()).unwrap() > 0.0,
+                "{window}"
+            );
+            for epoch in [window.entry, window.exit] {
+                let state = traj.at(epoch).unwrap();
+                assert!(
+                    saa.eval(&state, almanac.clone()).unwrap().abs() < 1e-2,
+                    "{window}"
+                );
+            }
+        }
+
+        // At 51.6 degrees of inclination, the spacecraft reaches high magnetic latitudes twice per revolution.
+        let l_windows = report
+            .windows_of("L > 2")
+            .collect::<Vec<&RadiationWindow>>();
+        assert!(l_windows.len() >= 15, "{report}");
+        for window in &l_windows {
+            let state = traj.at(window.entry).unwrap();
+            assert!((l_shell.l_shell(&state, almanac.clone()).unwrap() - 2.0).abs() < 1e-3);
+        }
+
+        let total = report.total_dwell("SAA");
+        assert_eq!(
+            total,
+            saa_windows
+                .iter()
+                .fold(Duration::ZERO, |total, window| total + window.duration())
+        );
+        assert!(total > Unit::Hour * 1 && total < Unit::Hour * 12, "{total}");
+
+        report
+            .to_parquet(std::env::temp_dir().join("nyx_ut_radiation.parquet"))
+            .unwrap();
+    }
+}
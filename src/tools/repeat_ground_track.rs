@@ -0,0 +1,476 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::cosmic::{Frame, Orbit};
+use crate::dynamics::SpacecraftDynamics;
+use crate::errors::NyxError;
+use crate::md::events::details::EventEdge;
+use crate::md::stationkeeping::gmst_deg;
+use crate::md::{Event, StateParameter};
+use crate::propagators::Propagator;
+use crate::time::{Duration, Epoch, Unit};
+use crate::utils::between_pm_180;
+use crate::Spacecraft;
+use anise::almanac::Almanac;
+use std::f64::consts::TAU;
+use std::fmt;
+use std::sync::Arc;
+
+/// Zonal J2 coefficient of the Earth, from the JGM3 model
+pub const EARTH_J2: f64 = 1.082_626_683_6e-3;
+/// Rotation rate of the Earth, in rad/s
+const EARTH_ROTATION_RAD_S: f64 = 7.292_115_146_706_979e-5;
+/// Mean motion of the Sun around the Earth (one revolution per tropical year), in rad/s
+const SUN_SYNC_RAAN_RATE_RAD_S: f64 = TAU / (365.242_189_7 * 86_400.0);
+const MAX_ITERATIONS: usize = 50;
+
+/// Mean orbit whose ground track repeats exactly after `revs` revolutions in `days` nodal days, under J2.
+///
+/// The repeat condition is that `revs` nodal periods last exactly `days` nodal days, where the nodal day accounts
+/// for the precession of the node: `revs / days = (dM/dt + dω/dt) / (ω_E - dΩ/dt)`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RepeatGroundTrack {
+    /// Number of revolutions in the repeat cycle
+    pub revs: u32,
+    /// Number of nodal days in the repeat cycle
+    pub days: u32,
+    /// Mean semi-major axis, in km
+    pub sma_km: f64,
+    /// Mean eccentricity
+    pub ecc: f64,
+    /// Mean inclination, in degrees
+    pub inc_deg: f64,
+    /// Nodal period, i.e. between two ascending nodes
+    pub nodal_period: Duration,
+    /// Precession rate of the ascending node, in degrees per day
+    pub raan_rate_deg_day: f64,
+    /// Frame of the design, which provides the gravitational parameter and the equatorial radius
+    pub frame: Frame,
+}
+
+impl RepeatGroundTrack {
+    /// Solves for the mean semi-major axis of a repeat ground track orbit with the provided inclination and eccentricity.
+    pub fn with_inclination(
+        revs: u32,
+        days: u32,
+        inc_deg: f64,
+        ecc: f64,
+        frame: Frame,
+    ) -> Result<Self, NyxError> {
+        let (mu_km3_s2, radius_km) = Self::check(revs, days, ecc, frame)?;
+        let inc = inc_deg.to_radians();
+        let residual =
+            |sma_km: f64| repeat_residual(revs, days, sma_km, ecc, inc, mu_km3_s2, radius_km);
+        let sma_km = solve_sma(revs, days, mu_km3_s2, residual)?;
+
+        Ok(Self::build(
+            revs, days, sma_km, ecc, inc_deg, frame, mu_km3_s2, radius_km,
+        ))
+    }
+
+    /// Solves for the mean semi-major axis and inclination of a sun-synchronous repeat ground track orbit.
+    pub fn sun_synchronous(revs: u32, days: u32, ecc: f64, frame: Frame) -> Result<Self, NyxError> {
+        let (mu_km3_s2, radius_km) = Self::check(revs, days, ecc, frame)?;
+        let residual = |sma_km: f64| match sun_sync_inc(sma_km, ecc, mu_km3_s2, radius_km) {
+            Some(inc) => repeat_residual(revs, days, sma_km, ecc, inc, mu_km3_s2, radius_km),
+            None => f64::NAN,
+        };
+        let sma_km = solve_sma(revs, days, mu_km3_s2, residual)?;
+        let inc = sun_sync_inc(sma_km, ecc, mu_km3_s2, radius_km).ok_or(NyxError::MathDomain {
+            msg: format!("no sun-synchronous inclination at {sma_km:.3} km"),
+        })?;
+
+        Ok(Self::build(
+            revs,
+            days,
+            sma_km,
+            ecc,
+            inc.to_degrees(),
+            frame,
+            mu_km3_s2,
+            radius_km,
+        ))
+    }
+
+    /// Returns the initial osculating orbit of this design, from its mean elements and the provided angles.
+    ///
+    /// The semi-major axis is corrected by the first order J2 short-period terms (Brouwer) so that the mean semi-major
+    /// axis of the propagated orbit matches the design, since it drives the drift of the ground track. The other
+    /// elements are used as osculating elements.
+    pub fn orbit(
+        &self,
+        epoch: Epoch,
+        raan_deg: f64,
+        aop_deg: f64,
+        ta_deg: f64,
+    ) -> Result<Orbit, NyxError> {
+        let radius_km = self
+            .frame
+            .mean_equatorial_radius_km()
+            .map_err(|e| NyxError::MathDomain { msg: e.to_string() })?;
+        let sin2_inc = self.inc_deg.to_radians().sin().powi(2);
+        let ta = ta_deg.to_radians();
+        let eta2 = 1.0 - self.ecc.powi(2);
+        // Ratio of the semi-major axis to the radius
+        let a_r = (1.0 + self.ecc * ta.cos()) / eta2;
+        let delta_sma_km = EARTH_J2 * radius_km.powi(2) / self.sma_km
+            * ((1.0 - 1.5 * sin2_inc) * (a_r.powi(3) - eta2.powf(-1.5))
+                + 1.5 * sin2_inc * a_r.powi(3) * (2.0 * (aop_deg.to_radians() + ta)).cos());
+
+        Orbit::try_keplerian(
+            self.sma_km + delta_sma_km,
+            self.ecc,
+            self.inc_deg,
+            raan_deg,
+            aop_deg,
+            ta_deg,
+            epoch,
+            self.frame,
+        )
+        .map_err(|e| NyxError::MathDomain { msg: e.to_string() })
+    }
+
+    /// Returns the spacing between adjacent ground tracks at the equator, in degrees of longitude.
+    pub fn grid_spacing_deg(&self) -> f64 {
+        360.0 / f64::from(self.revs)
+    }
+
+    /// Propagates the orbit over one repeat cycle and measures the longitude drift of the ascending node after `revs`
+    /// revolutions, which is zero for an exact repeat ground track.
+    ///
+    /// The longitude is computed in the body fixed frame if provided, e.g. ITRF93. Otherwise, it is computed from the
+    /// Greenwich mean sidereal time, ignoring precession and nutation, which is only suitable for preliminary analyses.
+    pub fn verify(
+        &self,
+        orbit: Orbit,
+        prop: &Propagator<SpacecraftDynamics>,
+        body_fixed_frame: Option<Frame>,
+        almanac: Arc<Almanac>,
+    ) -> Result<RepeatGroundTrackVerification, NyxError> {
+        let duration = self.nodal_period * (f64::from(self.revs) + 1.5);
+        let (_, traj) = prop
+            .with(Spacecraft::builder().orbit(orbit).build(), almanac.clone())
+            .for_duration_with_traj(duration)
+            .map_err(|e| NyxError::CustomError {
+                msg: format!("propagating the repeat ground track: {e}"),
+            })?;
+
+        let event = Event::within_tolerance(StateParameter::Declination, 0.0, 1e-8);
+        let nodes = traj
+            .find(&event, almanac.clone())
+            .map_err(|source| NyxError::Event { source })?
+            .into_iter()
+            .filter(|node| node.edge == EventEdge::Rising)
+            .map(|node| node.state)
+            .collect::<Vec<Spacecraft>>();
+
+        let revs = self.revs as usize;
+        if nodes.len() <= revs {
+            return Err(NyxError::CustomError {
+                msg: format!(
+                    "found {} ascending nodes but {} are needed to verify the repeat ground track",
+                    nodes.len(),
+                    revs + 1
+                ),
+            });
+        }
+
+        let longitude_deg = |sc: &Spacecraft| -> Result<f64, NyxError> {
+            match body_fixed_frame {
+                Some(frame) => Ok(almanac
+                    .transform_to(sc.orbit, frame, None)
+                    .map_err(|e| NyxError::FromAlmanacError {
+                        source: Box::new(e),
+                        action: "computing the longitude of the ascending node",
+                    })?
+                    .longitude_deg()),
+                None => Ok(sc
+                    .orbit
+                    .radius_km
+                    .y
+                    .atan2(sc.orbit.radius_km.x)
+                    .to_degrees()
+                    - gmst_deg(sc.orbit.epoch)),
+            }
+        };
+
+        let first_node = nodes[0];
+        let repeat_node = nodes[revs];
+        let first_node_lon_deg = between_pm_180(longitude_deg(&first_node)?);
+        let repeat_node_lon_deg = between_pm_180(longitude_deg(&repeat_node)?);
+        let node_drift_deg = between_pm_180(repeat_node_lon_deg - first_node_lon_deg);
+        let radius_km = self
+            .frame
+            .mean_equatorial_radius_km()
+            .map_err(|e| NyxError::MathDomain { msg: e.to_string() })?;
+
+        Ok(RepeatGroundTrackVerification {
+            first_node_epoch: first_node.orbit.epoch,
+            first_node_lon_deg,
+            repeat_node_epoch: repeat_node.orbit.epoch,
+            repeat_node_lon_deg,
+            node_drift_deg,
+            node_drift_km: node_drift_deg.to_radians() * radius_km,
+            nodal_period: (repeat_node.orbit.epoch - first_node.orbit.epoch) / f64::from(self.revs),
+        })
+    }
+
+    fn check(revs: u32, days: u32, ecc: f64, frame: Frame) -> Result<(f64, f64), NyxError> {
+        if revs == 0 || days == 0 || !(0.0..1.0).contains(&ecc) {
+            return Err(NyxError::MathDomain {
+                msg: format!(
+                    "repeat ground track requires positive revolutions ({revs}) and days ({days}), and an elliptical orbit (ecc = {ecc})"
+                ),
+            });
+        }
+        let mu_km3_s2 = frame
+            .mu_km3_s2()
+            .map_err(|e| NyxError::MathDomain { msg: e.to_string() })?;
+        let radius_km = frame
+            .mean_equatorial_radius_km()
+            .map_err(|e| NyxError::MathDomain { msg: e.to_string() })?;
+        Ok((mu_km3_s2, radius_km))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn build(
+        revs: u32,
+        days: u32,
+        sma_km: f64,
+        ecc: f64,
+        inc_deg: f64,
+        frame: Frame,
+        mu_km3_s2: f64,
+        radius_km: f64,
+    ) -> Self {
+        let rates = J2Rates::new(sma_km, ecc, inc_deg.to_radians(), mu_km3_s2, radius_km);
+        Self {
+            revs,
+            days,
+            sma_km,
+            ecc,
+            inc_deg,
+            nodal_period: Unit::Second * (TAU / (rates.ma + rates.aop)),
+            raan_rate_deg_day: rates.raan.to_degrees() * 86_400.0,
+            frame,
+        }
+    }
+}
+
+impl fmt::Display for RepeatGroundTrack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} revs in {} days: sma = {:.3} km\tecc = {:.6}\tinc = {:.4} deg\tnodal period = {}\tRAAN rate = {:.6} deg/day",
+            self.revs,
+            self.days,
+            self.sma_km,
+            self.ecc,
+            self.inc_deg,
+            self.nodal_period,
+            self.raan_rate_deg_day
+        )
+    }
+}
+
+/// Longitude drift of the ascending node of a propagated repeat ground track orbit after one repeat cycle.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RepeatGroundTrackVerification {
+    pub first_node_epoch: Epoch,
+    /// Longitude of the first ascending node, in degrees
+    pub first_node_lon_deg: f64,
+    /// Epoch of the ascending node after one repeat cycle
+    pub repeat_node_epoch: Epoch,
+    /// Longitude of the ascending node after one repeat cycle, in degrees
+    pub repeat_node_lon_deg: f64,
+    /// Longitude drift of the ascending node over the repeat cycle, in degrees
+    pub node_drift_deg: f64,
+    /// Longitude drift of the ascending node over the repeat cycle along the equator, in km
+    pub node_drift_km: f64,
+    /// Mean nodal period over the repeat cycle
+    pub nodal_period: Duration,
+}
+
+impl fmt::Display for RepeatGroundTrackVerification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ascending node drifted by {:.6} deg ({:.3} km) from {:.4} deg on {} to {:.4} deg on {}, nodal period of {}",
+            self.node_drift_deg,
+            self.node_drift_km,
+            self.first_node_lon_deg,
+            self.first_node_epoch,
+            self.repeat_node_lon_deg,
+            self.repeat_node_epoch,
+            self.nodal_period
+        )
+    }
+}
+
+/// Secular rates of the mean anomaly, the argument of periapsis and the right ascension of the ascending node due to J2, in rad/s.
+struct J2Rates {
+    ma: f64,
+    aop: f64,
+    raan: f64,
+}
+
+impl J2Rates {
+    fn new(sma_km: f64, ecc: f64, inc: f64, mu_km3_s2: f64, radius_km: f64) -> Self {
+        let n = (mu_km3_s2 / sma_km.powi(3)).sqrt();
+        let eta2 = 1.0 - ecc * ecc;
+        let k = EARTH_J2 * (radius_km / (sma_km * eta2)).powi(2);
+        let sin2_inc = inc.sin().powi(2);
+        Self {
+            ma: n * (1.0 + 0.75 * k * eta2.sqrt() * (2.0 - 3.0 * sin2_inc)),
+            aop: 0.75 * n * k * (4.0 - 5.0 * sin2_inc),
+            raan: -1.5 * n * k * inc.cos(),
+        }
+    }
+}
+
+/// Returns the repeat condition `days * (dM/dt + dω/dt) - revs * (ω_E - dΩ/dt)`, in rad/s.
+fn repeat_residual(
+    revs: u32,
+    days: u32,
+    sma_km: f64,
+    ecc: f64,
+    inc: f64,
+    mu_km3_s2: f64,
+    radius_km: f64,
+) -> f64 {
+    let rates = J2Rates::new(sma_km, ecc, inc, mu_km3_s2, radius_km);
+    f64::from(days) * (rates.ma + rates.aop) - f64::from(revs) * (EARTH_ROTATION_RAD_S - rates.raan)
+}
+
+/// Returns the inclination (in radians) for which the node precesses at the rate of the mean Sun, if any.
+fn sun_sync_inc(sma_km: f64, ecc: f64, mu_km3_s2: f64, radius_km: f64) -> Option<f64> {
+    let n = (mu_km3_s2 / sma_km.powi(3)).sqrt();
+    let k = EARTH_J2 * (radius_km / (sma_km * (1.0 - ecc * ecc))).powi(2);
+    let cos_inc = -SUN_SYNC_RAAN_RATE_RAD_S / (1.5 * n * k);
+    (cos_inc.abs() <= 1.0).then(|| cos_inc.acos())
+}
+
+/// Solves the repeat condition for the semi-major axis with a secant method, starting from the Keplerian solution.
+fn solve_sma<F: Fn(f64) -> f64>(
+    revs: u32,
+    days: u32,
+    mu_km3_s2: f64,
+    residual: F,
+) -> Result<f64, NyxError> {
+    let n_kepler = f64::from(revs) / f64::from(days) * EARTH_ROTATION_RAD_S;
+    let mut sma_prev = (mu_km3_s2 / n_kepler.powi(2)).cbrt();
+    let mut sma = sma_prev * 1.001;
+    let mut res_prev = residual(sma_prev);
+
+    for _ in 0..MAX_ITERATIONS {
+        let res = residual(sma);
+        if !res.is_finite() || !res_prev.is_finite() {
+            return Err(NyxError::MathDomain {
+                msg: format!("repeat ground track of {revs} revs in {days} days is not achievable near {sma:.3} km"),
+            });
+        }
+        if res == res_prev {
+            return Ok(sma);
+        }
+        let next = sma - res * (sma - sma_prev) / (res - res_prev);
+        (sma_prev, res_prev) = (sma, res);
+        sma = next;
+        if (sma - sma_prev).abs() < 1e-9 {
+            return Ok(sma);
+        }
+    }
+
+    Err(NyxError::MaxIterReached {
+        msg: format!("repeat ground track of {revs} revs in {days} days"),
+    })
+}
+
+#[cfg(test)]
+mod ut_repeat_ground_track {
+    use super::*;
+    use crate::dynamics::{Harmonics, OrbitalDynamics};
+    use crate::fixtures;
+    use crate::io::gravity::HarmonicsMem;
+    use crate::GMAT_EARTH_GM;
+    use anise::structure::planetocentric::ellipsoid::Ellipsoid;
+
+    fn earth() -> Frame {
+        let mut frame = fixtures::eme2k();
+        frame.shape = Some(Ellipsoid::from_sphere(6_378.136_3));
+        frame
+    }
+
+    #[test]
+    fn sun_synchronous_repeat() {
+        let design = RepeatGroundTrack::sun_synchronous(15, 1, 0.0, earth()).unwrap();
+        println!("{design}");
+
+        // Well known 15 revs/day sun-synchronous repeat orbit, at about 560 km of altitude.
+        assert!((design.sma_km - 6_939.0).abs() < 5.0, "{design}");
+        assert!((design.inc_deg - 97.6).abs() < 0.1, "{design}");
+        assert!((design.raan_rate_deg_day - 0.985_6).abs() < 1e-3);
+        assert!((design.grid_spacing_deg() - 24.0).abs() < 1e-12);
+
+        // The repeat condition holds to numerical precision.
+        let rates = J2Rates::new(
+            design.sma_km,
+            design.ecc,
+            design.inc_deg.to_radians(),
+            GMAT_EARTH_GM,
+            6_378.136_3,
+        );
+        let nodal_day_s = TAU / (EARTH_ROTATION_RAD_S - rates.raan);
+        assert!((design.nodal_period.to_seconds() * 15.0 - nodal_day_s).abs() < 1e-3);
+
+        let epoch = fixtures::epoch();
+        let orbit = design.orbit(epoch, 30.0, 0.0, 0.0).unwrap();
+
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::new(vec![
+            Harmonics::from_stor(earth(), HarmonicsMem::j2_jgm3()),
+        ])));
+        let verif = design
+            .verify(orbit, &prop, None, fixtures::almanac())
+            .unwrap();
+        println!("{verif}");
+
+        assert!(verif.node_drift_km.abs() < 1.0, "{verif}");
+        assert!((verif.nodal_period - design.nodal_period).abs() < Unit::Second * 1);
+    }
+
+    #[test]
+    fn fixed_inclination_repeat() {
+        // 43 revolutions in 3 days
+        let design = RepeatGroundTrack::with_inclination(43, 3, 51.6, 0.001, earth()).unwrap();
+        println!("{design}");
+        assert_eq!(design.inc_deg, 51.6);
+        let residual = repeat_residual(
+            43,
+            3,
+            design.sma_km,
+            0.001,
+            51.6_f64.to_radians(),
+            GMAT_EARTH_GM,
+            6_378.136_3,
+        );
+        assert!(residual.abs() < 1e-15);
+
+        assert!(RepeatGroundTrack::with_inclination(0, 1, 51.6, 0.0, earth()).is_err());
+        // Far too few revolutions per day for a sun-synchronous orbit
+        assert!(RepeatGroundTrack::sun_synchronous(1, 1, 0.0, earth()).is_err());
+    }
+}
@@ -0,0 +1,528 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::cosmic::Frame;
+use crate::dynamics::guidance::FiniteBurns;
+use crate::dynamics::{
+    AccelModel, DynamicsError, Harmonics, OrbitalDynamics, PointMasses, SolarPressure,
+    SpacecraftDynamics,
+};
+use crate::errors::NyxError;
+use crate::io::gravity::HarmonicsMem;
+use crate::io::{ConfigError, ConfigRepr, ExportCfg, ParquetCfg};
+use crate::linalg::{SMatrix, SVector};
+use crate::md::plan::{ManeuverPlan, ManeuverPlanError, PlannedManeuver};
+use crate::od::prelude::{
+    GroundStation, KfEstimate, ResidRejectCrit, TrackingArcSim, TrkConfig, KF,
+};
+use crate::od::{ODError, SpacecraftODProcess};
+use crate::propagators::Propagator;
+use crate::runtime;
+use crate::time::{Duration, Epoch};
+use crate::{Spacecraft, State};
+use anise::almanac::planetary::PlanetaryDataError;
+use anise::almanac::Almanac;
+use anise::errors::AlmanacError;
+use serde::{Deserialize, Serialize};
+use snafu::prelude::*;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{create_dir_all, File};
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum ScenarioError {
+    #[snafu(display("invalid scenario: {msg}"))]
+    InvalidScenario { msg: String },
+    #[snafu(display("loading {path} in the almanac encountered {source}"))]
+    ScenarioAlmanac {
+        path: String,
+        #[snafu(source(from(AlmanacError, Box::new)))]
+        source: Box<AlmanacError>,
+    },
+    #[snafu(display("fetching the data of frame {frame} encountered {source}"))]
+    ScenarioFrame {
+        frame: Frame,
+        #[snafu(source(from(PlanetaryDataError, Box::new)))]
+        source: Box<PlanetaryDataError>,
+    },
+    #[snafu(display("loading the gravity field {path} encountered {source}"))]
+    ScenarioGravity {
+        path: String,
+        #[snafu(source(from(NyxError, Box::new)))]
+        source: Box<NyxError>,
+    },
+    #[snafu(display("building the dynamics encountered {source}"))]
+    ScenarioDynamics { source: DynamicsError },
+    #[snafu(display("propagating the scenario encountered {source}"))]
+    ScenarioPlan { source: ManeuverPlanError },
+    #[snafu(display("configuring the tracking simulation encountered {source}"))]
+    ScenarioTrackingConfig { source: ConfigError },
+    #[snafu(display("simulating the tracking data encountered {source}"))]
+    ScenarioTracking {
+        #[snafu(source(from(NyxError, Box::new)))]
+        source: Box<NyxError>,
+    },
+    #[snafu(display("orbit determination encountered {source}"))]
+    ScenarioOD {
+        #[snafu(source(from(ODError, Box::new)))]
+        source: Box<ODError>,
+    },
+    #[snafu(display("writing the {product} encountered {source}"))]
+    ScenarioExport {
+        product: &'static str,
+        source: Box<dyn Error>,
+    },
+}
+
+/// File format of a spherical harmonics gravity field
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GravityFormat {
+    Cof,
+    Shadr,
+    Egm,
+    Icgem,
+}
+
+/// Spherical harmonics gravity field of the scenario, read from a (possibly gzipped) file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HarmonicsScenario {
+    /// Body fixed frame in which the gravity field is computed, e.g. IAU Earth
+    pub frame: Frame,
+    /// Path to the coefficients file, which is gunzipped if its extension is `gz`
+    pub path: String,
+    pub format: GravityFormat,
+    pub degree: usize,
+    pub order: usize,
+}
+
+/// Force models of the scenario, two body dynamics by default.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DynamicsScenario {
+    /// NAIF IDs of the third bodies, e.g. 10 for the Sun and 301 for the Moon
+    #[serde(default)]
+    pub point_masses: Vec<i32>,
+    #[serde(default)]
+    pub harmonics: Option<HarmonicsScenario>,
+    /// Solar radiation pressure, eclipsed by the central body of the spacecraft
+    #[serde(default)]
+    pub solar_radiation_pressure: bool,
+}
+
+/// Propagation span of the scenario, from the epoch of the spacecraft until the end epoch.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PropagationScenario {
+    pub end_epoch: Epoch,
+    /// Step of the exported trajectory, defaults to every integration step
+    #[serde(default)]
+    pub output_step: Option<Duration>,
+}
+
+/// Simulation of the tracking data of the trajectory of the scenario.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TrackingScenario {
+    pub ground_stations: BTreeMap<String, GroundStation>,
+    /// Tracking configurations by ground station name
+    pub configs: BTreeMap<String, TrkConfig>,
+    /// Seed of the measurement noises, random if unset
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+/// Orbit determination of the simulated tracking data with a Kalman filter.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OdScenario {
+    /// Initial estimate of the filter, defaults to the initial spacecraft of the scenario
+    #[serde(default)]
+    pub initial_estimate: Option<Spacecraft>,
+    /// Uncertainty of the initial estimate in the inertial frame
+    pub position_sigma_km: f64,
+    pub velocity_sigma_km_s: f64,
+    #[serde(default)]
+    pub resid_reject: Option<ResidRejectCrit>,
+}
+
+/// A mission scenario declares the spacecraft, its dynamics and maneuvers, and optionally the tracking and orbit
+/// determination, such that a complete simulation can be run from a YAML file, e.g.
+///
+/// ```yaml
+/// almanac:
+///   - data/de440s.bsp
+/// spacecraft:
+///   orbit: ...
+///   mass: ...
+/// dynamics:
+///   point_masses: [10, 301]
+///   solar_radiation_pressure: true
+/// propagation:
+///   end_epoch: 2024-01-02T00:00:00 UTC
+///   output_step: 1 min
+/// maneuvers:
+///   - !Impulsive
+///     epoch: 2024-01-01T12:00:00 UTC
+///     dv_km_s: [0.01, 0.0, 0.0]
+///     frame: VNC
+/// output_dir: output_data/scenario
+/// ```
+///
+/// The `tracking` section uses the same ground station and tracking configurations as [TrackingArcSim], and the `od`
+/// section requires the `tracking` section. The filter models the finite burns of the scenario but not its impulsive
+/// maneuvers.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Scenario {
+    /// Files loaded in the almanac, e.g. planetary ephemerides and planetary constants
+    #[serde(default)]
+    pub almanac: Vec<String>,
+    /// Initial state of the spacecraft, whose frame data is fetched from the almanac if it is missing
+    pub spacecraft: Spacecraft,
+    #[serde(default)]
+    pub dynamics: DynamicsScenario,
+    pub propagation: PropagationScenario,
+    #[serde(default)]
+    pub maneuvers: Vec<PlannedManeuver>,
+    #[serde(default)]
+    pub tracking: Option<TrackingScenario>,
+    #[serde(default)]
+    pub od: Option<OdScenario>,
+    /// Directory of the products, created if needed
+    pub output_dir: PathBuf,
+    /// Compression, row group size, and metadata policy of the parquet products
+    #[serde(default)]
+    pub parquet: ParquetCfg,
+}
+
+impl ConfigRepr for Scenario {}
+
+/// Paths of the products of a scenario run.
+#[derive(Clone, Debug)]
+pub struct ScenarioProducts {
+    pub final_state: Spacecraft,
+    /// Final state of the spacecraft, as YAML
+    pub final_state_path: PathBuf,
+    pub trajectory: PathBuf,
+    pub tracking_arc: Option<PathBuf>,
+    pub od_results: Option<PathBuf>,
+}
+
+impl Scenario {
+    /// Loads the almanac files of this scenario, sharing the almanac already loaded from the same files if any.
+    ///
+    /// If this scenario does not list any almanac file, the almanac of the runtime configuration is used, if set.
+    pub fn load_almanac(&self) -> Result<Arc<Almanac>, ScenarioError> {
+        if self.almanac.is_empty() {
+            if let Some(almanac) = runtime::almanac() {
+                return Ok(almanac);
+            }
+        }
+        runtime::shared_almanac(&self.almanac, || {
+            let mut almanac = Almanac::default();
+            for path in &self.almanac {
+                almanac = almanac
+                    .load(path)
+                    .context(ScenarioAlmanacSnafu { path: path.clone() })?;
+            }
+            Ok(almanac)
+        })
+    }
+
+    /// Builds the dynamics of this scenario, without the maneuvers.
+    pub fn build_dynamics(
+        &self,
+        almanac: Arc<Almanac>,
+    ) -> Result<SpacecraftDynamics, ScenarioError> {
+        let mut accel_models: Vec<Arc<dyn AccelModel + Sync>> = Vec::new();
+
+        if !self.dynamics.point_masses.is_empty() {
+            accel_models.push(PointMasses::new(self.dynamics.point_masses.clone()));
+        }
+
+        if let Some(harmonics) = &self.dynamics.harmonics {
+            let gunzipped = harmonics.path.ends_with(".gz");
+            let (path, degree, order) = (&harmonics.path, harmonics.degree, harmonics.order);
+            let stor = match harmonics.format {
+                GravityFormat::Cof => HarmonicsMem::from_cof(path, degree, order, gunzipped),
+                GravityFormat::Shadr => HarmonicsMem::from_shadr(path, degree, order, gunzipped),
+                GravityFormat::Egm => HarmonicsMem::from_egm(path, degree, order, gunzipped),
+                GravityFormat::Icgem => HarmonicsMem::from_icgem(path, degree, order, gunzipped),
+            }
+            .context(ScenarioGravitySnafu { path: path.clone() })?;
+
+            let frame = with_frame_data(harmonics.frame, &almanac)?;
+            accel_models.push(Harmonics::from_stor(frame, stor));
+        }
+
+        let orbital_dyn = OrbitalDynamics::new(accel_models);
+
+        if self.dynamics.solar_radiation_pressure {
+            let center = with_frame_data(self.spacecraft.orbit.frame, &almanac)?;
+            let srp = SolarPressure::new(vec![center], almanac).context(ScenarioDynamicsSnafu)?;
+            Ok(SpacecraftDynamics::from_model(orbital_dyn, srp))
+        } else {
+            Ok(SpacecraftDynamics::new(orbital_dyn))
+        }
+    }
+
+    /// Runs this scenario and writes its products in the output directory:
+    /// + `trajectory.parquet` and `final_state.yaml`, after executing the maneuvers;
+    /// + `tracking_arc.parquet` if the scenario declares the tracking;
+    /// + `od_results.parquet` if the scenario declares the orbit determination.
+    pub fn run(&self, almanac: Arc<Almanac>) -> Result<ScenarioProducts, ScenarioError> {
+        ensure!(
+            self.od.is_none() || self.tracking.is_some(),
+            InvalidScenarioSnafu {
+                msg: "orbit determination requires the tracking section"
+            }
+        );
+
+        let mut spacecraft = self.spacecraft;
+        spacecraft.orbit.frame = with_frame_data(spacecraft.orbit.frame, &almanac)?;
+
+        let plan = ManeuverPlan::new(self.maneuvers.clone()).context(ScenarioPlanSnafu)?;
+        let dynamics = self.build_dynamics(almanac.clone())?;
+
+        info!(
+            "Running scenario from {} until {} with {plan}",
+            spacecraft.epoch(),
+            self.propagation.end_epoch
+        );
+
+        let (final_state, traj) = plan
+            .execute(
+                &Propagator::default(dynamics.clone()),
+                spacecraft,
+                self.propagation.end_epoch,
+                almanac.clone(),
+            )
+            .context(ScenarioPlanSnafu)?;
+        let traj = traj.to_traj();
+
+        create_dir_all(&self.output_dir)
+            .map_err(Box::from)
+            .context(ScenarioExportSnafu {
+                product: "output directory",
+            })?;
+
+        let final_state_path = self.output_dir.join("final_state.yaml");
+        File::create(&final_state_path)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|file| {
+                serde_yml::to_writer(BufWriter::new(file), &final_state).map_err(Box::from)
+            })
+            .context(ScenarioExportSnafu {
+                product: "final state",
+            })?;
+
+        let traj_cfg = ExportCfg {
+            step: self.propagation.output_step,
+            parquet: self.parquet,
+            ..Default::default()
+        };
+        let trajectory = traj
+            .to_parquet_with_cfg(
+                self.output_dir.join("trajectory.parquet"),
+                traj_cfg,
+                almanac.clone(),
+            )
+            .context(ScenarioExportSnafu {
+                product: "trajectory",
+            })?;
+
+        let mut products = ScenarioProducts {
+            final_state,
+            final_state_path,
+            trajectory,
+            tracking_arc: None,
+            od_results: None,
+        };
+
+        let Some(tracking) = &self.tracking else {
+            return Ok(products);
+        };
+
+        let mut devices = BTreeMap::new();
+        for (name, station) in &tracking.ground_stations {
+            let mut station = station.clone();
+            station.frame = with_frame_data(station.frame, &almanac)?;
+            devices.insert(name.clone(), station);
+        }
+
+        let mut arc_sim = match tracking.seed {
+            Some(seed) => TrackingArcSim::<Spacecraft, GroundStation>::with_seed(
+                devices.clone(),
+                traj,
+                tracking.configs.clone(),
+                seed,
+            ),
+            None => TrackingArcSim::<Spacecraft, GroundStation>::new(
+                devices.clone(),
+                traj,
+                tracking.configs.clone(),
+            ),
+        }
+        .context(ScenarioTrackingConfigSnafu)?;
+
+        arc_sim
+            .build_schedule(almanac.clone())
+            .context(ScenarioTrackingSnafu)?;
+        let arc = arc_sim
+            .generate_measurements(almanac.clone())
+            .context(ScenarioTrackingSnafu)?;
+
+        let pq_cfg = ExportCfg {
+            parquet: self.parquet,
+            ..Default::default()
+        };
+
+        products.tracking_arc = Some(
+            arc.to_parquet(self.output_dir.join("tracking_arc.parquet"), pq_cfg.clone())
+                .context(ScenarioExportSnafu {
+                    product: "tracking arc",
+                })?,
+        );
+
+        let Some(od) = &self.od else {
+            return Ok(products);
+        };
+
+        let mut estimate = od.initial_estimate.unwrap_or(spacecraft);
+        estimate.orbit.frame = with_frame_data(estimate.orbit.frame, &almanac)?;
+
+        let pos_var = od.position_sigma_km.powi(2);
+        let vel_var = od.velocity_sigma_km_s.powi(2);
+        let covar = SMatrix::<f64, 9, 9>::from_diagonal(&SVector::<f64, 9>::from_iterator([
+            pos_var, pos_var, pos_var, vel_var, vel_var, vel_var, 0.0, 0.0, 0.0,
+        ]));
+
+        // The filter flies the same finite burns as the truth.
+        let finite = plan.finite_burns();
+        let od_dynamics = if finite.is_empty() {
+            dynamics
+        } else {
+            dynamics.with_guidance_law(FiniteBurns::from_mnvrs(finite))
+        };
+        let prop = Propagator::default(od_dynamics);
+
+        let mut odp = SpacecraftODProcess::ckf(
+            prop.with(estimate.with_stm(), almanac.clone()),
+            KF::no_snc(KfEstimate::from_covar(estimate.with_stm(), covar)),
+            devices,
+            od.resid_reject,
+            almanac,
+        );
+
+        odp.process_arc(&arc).context(ScenarioODSnafu)?;
+
+        products.od_results = Some(
+            odp.to_parquet(&arc, self.output_dir.join("od_results.parquet"), pq_cfg)
+                .context(ScenarioODSnafu)?,
+        );
+
+        Ok(products)
+    }
+}
+
+/// Fills the gravitational parameter and shape of the frame from the almanac if they are missing.
+fn with_frame_data(frame: Frame, almanac: &Almanac) -> Result<Frame, ScenarioError> {
+    if frame.mu_km3_s2.is_some() {
+        Ok(frame)
+    } else {
+        almanac
+            .frame_from_uid(frame)
+            .context(ScenarioFrameSnafu { frame })
+    }
+}
+
+#[cfg(test)]
+mod ut_scenario {
+    use super::*;
+    use std::env;
+
+    const SCENARIO: &str = r#"
+spacecraft:
+  orbit:
+    radius_km: [7000.0, 0.0, 0.0]
+    velocity_km_s: [0.0, 7.546, 0.0]
+    epoch: 2024-01-01T00:00:00 UTC
+    frame:
+      ephemeris_id: 399
+      orientation_id: 1
+      mu_km3_s2: 398600.4415
+      shape:
+        semi_major_equatorial_radius_km: 6378.1363
+        semi_minor_equatorial_radius_km: 6378.1363
+        polar_radius_km: 6356.7519
+  mass:
+    dry_mass_kg: 500.0
+    prop_mass_kg: 100.0
+    extra_mass_kg: 0.0
+propagation:
+  end_epoch: 2024-01-01T03:00:00 UTC
+  output_step: 1 min
+maneuvers:
+  - !Impulsive
+    epoch: 2024-01-01T01:00:00 UTC
+    dv_km_s: [0.01, 0.0, 0.0]
+    frame: VNC
+output_dir: output_data/scenario
+"#;
+
+    #[test]
+    fn run_scenario() {
+        let mut scenario: Scenario = serde_yml::from_str(SCENARIO).unwrap();
+        assert!(scenario.tracking.is_none());
+        assert_eq!(scenario.dynamics, DynamicsScenario::default());
+
+        // Round trip through a file
+        scenario.output_dir = [env!("CARGO_MANIFEST_DIR"), "output_data", "scenario"]
+            .iter()
+            .collect();
+        let path = env::temp_dir().join("nyx_scenario.yaml");
+        serde_yml::to_writer(File::create(&path).unwrap(), &scenario).unwrap();
+        let loaded = Scenario::load(&path).unwrap();
+        assert_eq!(loaded, scenario);
+
+        let almanac = scenario.load_almanac().unwrap();
+        let products = scenario.run(almanac.clone()).unwrap();
+        assert_eq!(products.final_state.epoch(), scenario.propagation.end_epoch);
+        // The prograde maneuver raised the orbit
+        assert!(
+            products.final_state.orbit.sma_km().unwrap()
+                > scenario.spacecraft.orbit.sma_km().unwrap()
+        );
+        assert!(products.trajectory.exists());
+        assert!(products.tracking_arc.is_none());
+
+        let final_state = Spacecraft::load(&products.final_state_path).unwrap();
+        assert_eq!(final_state, products.final_state);
+
+        // Orbit determination requires tracking
+        scenario.od = Some(OdScenario {
+            initial_estimate: None,
+            position_sigma_km: 1.0,
+            velocity_sigma_km_s: 1e-3,
+            resid_reject: None,
+        });
+        assert!(matches!(
+            scenario.run(almanac),
+            Err(ScenarioError::InvalidScenario { .. })
+        ));
+    }
+}
@@ -0,0 +1,479 @@
+/*
+    Nyx, blazing fast astrodynamics
+    Copyright (C) 2018-onwards Christopher Rabotin <christopher.rabotin@gmail.com>
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published
+    by the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::dynamics::SpacecraftDynamics;
+use crate::errors::NyxError;
+use crate::io::watermark::pq_writer;
+use crate::linalg::DMatrix;
+use crate::md::StateParameter;
+use crate::propagators::Propagator;
+use crate::time::Duration;
+use crate::{Spacecraft, State};
+use anise::almanac::Almanac;
+use arrow::array::{Array, Float64Builder, StringBuilder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use typed_builder::TypedBuilder;
+
+/// Perturbation of an initial parameter, e.g. 1 km on the SMA or 0.1 on the Cr.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Perturbation {
+    pub param: StateParameter,
+    /// Perturbation step, in the unit of the parameter
+    pub step: f64,
+}
+
+/// Finite difference scheme of the one-at-a-time sensitivity analysis.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DifferenceScheme {
+    /// One propagation per perturbed parameter, first order accurate
+    Forward,
+    /// Two propagations per perturbed parameter, second order accurate
+    #[default]
+    Central,
+}
+
+/// Configuration of a sensitivity analysis of the end states of a propagation.
+#[derive(Clone, Debug, TypedBuilder)]
+#[builder(doc)]
+pub struct SensitivityConfig {
+    /// Perturbed initial parameters
+    pub inputs: Vec<Perturbation>,
+    /// Parameters of the end state whose partials are computed
+    pub outputs: Vec<StateParameter>,
+    /// Duration of the propagations
+    pub duration: Duration,
+    #[builder(default)]
+    pub scheme: DifferenceScheme,
+}
+
+/// Method used to compute the partials of a sensitivity report.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SensitivityMethod {
+    FiniteDifferences(DifferenceScheme),
+    /// Least squares fit of a linear model over the runs of a design of experiments
+    DesignOfExperiments {
+        runs: usize,
+    },
+}
+
+/// Partials of the end state parameters with respect to the initial parameters.
+#[derive(Clone, Debug)]
+pub struct SensitivityReport {
+    pub initial_state: Spacecraft,
+    pub inputs: Vec<Perturbation>,
+    pub outputs: Vec<StateParameter>,
+    /// Values of the outputs at the end of the nominal propagation
+    pub nominal_outputs: Vec<f64>,
+    /// Partial of each output (row) with respect to each input (column), in output unit per input unit
+    pub partials: DMatrix<f64>,
+    pub method: SensitivityMethod,
+}
+
+impl SensitivityReport {
+    /// Computes the partials by perturbing each input one at a time, propagating all of the perturbed states in parallel.
+    pub fn finite_differences(
+        state: Spacecraft,
+        prop: &Propagator<SpacecraftDynamics>,
+        config: &SensitivityConfig,
+        almanac: Arc<Almanac>,
+    ) -> Result<Self, NyxError> {
+        check_config(config)?;
+
+        let signs: &[f64] = match config.scheme {
+            DifferenceScheme::Forward => &[1.0],
+            DifferenceScheme::Central => &[1.0, -1.0],
+        };
+
+        let nominal_outputs = end_outputs(state, prop, config, almanac.clone())?;
+
+        // Each perturbed state is a row of deviations, in units of the perturbation steps
+        let mut design = DMatrix::zeros(config.inputs.len() * signs.len(), config.inputs.len());
+        for j in 0..config.inputs.len() {
+            for (k, sign) in signs.iter().enumerate() {
+                design[(j * signs.len() + k, j)] = *sign;
+            }
+        }
+        let outputs = propagate_design(state, prop, config, &design, almanac)?;
+
+        let mut partials = DMatrix::zeros(config.outputs.len(), config.inputs.len());
+        for (j, input) in config.inputs.iter().enumerate() {
+            for i in 0..config.outputs.len() {
+                partials[(i, j)] = match config.scheme {
+                    DifferenceScheme::Forward => (outputs[j][i] - nominal_outputs[i]) / input.step,
+                    DifferenceScheme::Central => {
+                        (outputs[2 * j][i] - outputs[2 * j + 1][i]) / (2.0 * input.step)
+                    }
+                };
+            }
+        }
+
+        Ok(Self {
+            initial_state: state,
+            inputs: config.inputs.clone(),
+            outputs: config.outputs.clone(),
+            nominal_outputs,
+            partials,
+            method: SensitivityMethod::FiniteDifferences(config.scheme),
+        })
+    }
+
+    /// Computes the partials from a design of experiments, where each row of the design is a run and each column the
+    /// deviation of an input in units of its perturbation step (e.g. [two_level_full_factorial]). The partials are the
+    /// least squares fit of a linear model, with an intercept, of the outputs with respect to the inputs.
+    pub fn design_of_experiments(
+        state: Spacecraft,
+        prop: &Propagator<SpacecraftDynamics>,
+        config: &SensitivityConfig,
+        design: &DMatrix<f64>,
+        almanac: Arc<Almanac>,
+    ) -> Result<Self, NyxError> {
+        check_config(config)?;
+        if design.ncols() != config.inputs.len() {
+            return Err(NyxError::CustomError {
+                msg: format!(
+                    "design of experiments has {} columns but there are {} inputs",
+                    design.ncols(),
+                    config.inputs.len()
+                ),
+            });
+        }
+        if design.nrows() <= config.inputs.len() {
+            return Err(NyxError::CustomError {
+                msg: format!(
+                    "design of experiments requires more than {} runs to fit {} inputs, got {}",
+                    config.inputs.len(),
+                    config.inputs.len(),
+                    design.nrows()
+                ),
+            });
+        }
+
+        let nominal_outputs = end_outputs(state, prop, config, almanac.clone())?;
+        let outputs = propagate_design(state, prop, config, design, almanac)?;
+
+        // Regressors: an intercept and the deviations of the inputs, in their units
+        let regressors = DMatrix::from_fn(design.nrows(), config.inputs.len() + 1, |r, c| {
+            if c == 0 {
+                1.0
+            } else {
+                design[(r, c - 1)] * config.inputs[c - 1].step
+            }
+        });
+        let observations =
+            DMatrix::from_fn(design.nrows(), config.outputs.len(), |r, c| outputs[r][c]);
+
+        let normal = regressors.transpose() * &regressors;
+        let coefficients = normal.try_inverse().ok_or_else(|| NyxError::CustomError {
+            msg: "design of experiments is singular: the inputs are not independent".to_string(),
+        })? * regressors.transpose()
+            * observations;
+
+        Ok(Self {
+            initial_state: state,
+            inputs: config.inputs.clone(),
+            outputs: config.outputs.clone(),
+            nominal_outputs,
+            partials: coefficients.rows(1, config.inputs.len()).transpose(),
+            method: SensitivityMethod::DesignOfExperiments {
+                runs: design.nrows(),
+            },
+        })
+    }
+
+    /// Returns the partial of this output with respect to this input, if both were analyzed
+    pub fn partial(&self, output: StateParameter, input: StateParameter) -> Option<f64> {
+        let i = self.outputs.iter().position(|param| *param == output)?;
+        let j = self.inputs.iter().position(|pert| pert.param == input)?;
+        Some(self.partials[(i, j)])
+    }
+
+    /// Exports this report to a parquet file, with one row per output and input.
+    pub fn to_parquet<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Box<dyn Error>> {
+        let path_buf = path.as_ref().to_path_buf();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("Output", DataType::Utf8, false),
+            Field::new("Input", DataType::Utf8, false),
+            Field::new("Nominal output", DataType::Float64, false),
+            Field::new("Input step", DataType::Float64, false),
+            Field::new("Partial", DataType::Float64, false),
+            Field::new("Output change per step", DataType::Float64, false),
+        ]));
+
+        let mut output = StringBuilder::new();
+        let mut input = StringBuilder::new();
+        let mut nominal = Float64Builder::new();
+        let mut step = Float64Builder::new();
+        let mut partial = Float64Builder::new();
+        let mut change = Float64Builder::new();
+        for (i, out) in self.outputs.iter().enumerate() {
+            for (j, pert) in self.inputs.iter().enumerate() {
+                output.append_value(format!("{out}"));
+                input.append_value(format!("{}", pert.param));
+                nominal.append_value(self.nominal_outputs[i]);
+                step.append_value(pert.step);
+                partial.append_value(self.partials[(i, j)]);
+                change.append_value(self.partials[(i, j)] * pert.step);
+            }
+        }
+
+        let record: Vec<Arc<dyn Array>> = vec![
+            Arc::new(output.finish()),
+            Arc::new(input.finish()),
+            Arc::new(nominal.finish()),
+            Arc::new(step.finish()),
+            Arc::new(partial.finish()),
+            Arc::new(change.finish()),
+        ];
+
+        let mut metadata = HashMap::new();
+        metadata.insert("Purpose".to_string(), "Sensitivity analysis".to_string());
+        metadata.insert("Method".to_string(), format!("{:?}", self.method));
+        metadata.insert(
+            "Initial state".to_string(),
+            format!("{}", self.initial_state),
+        );
+
+        let file = File::create(&path_buf)?;
+        let mut writer = ArrowWriter::try_new(file, schema.clone(), pq_writer(Some(metadata)))?;
+        writer.write(&RecordBatch::try_new(schema, record)?)?;
+        writer.close()?;
+
+        info!("Sensitivity report written to {}", path_buf.display());
+
+        Ok(path_buf)
+    }
+}
+
+impl fmt::Display for SensitivityReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Sensitivity report ({:?})", self.method)?;
+        write!(f, "{:>24}", "")?;
+        for pert in &self.inputs {
+            write!(f, "{:>24}", format!("∂/∂{}", pert.param))?;
+        }
+        for (i, output) in self.outputs.iter().enumerate() {
+            write!(f, "\n{:>24}", format!("{output}"))?;
+            for j in 0..self.inputs.len() {
+                write!(f, "{:>24.6e}", self.partials[(i, j)])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds a two-level full factorial design of experiments, with `2^num_inputs` runs where each input is at -1 or +1 step.
+pub fn two_level_full_factorial(num_inputs: usize) -> DMatrix<f64> {
+    DMatrix::from_fn(1 << num_inputs, num_inputs, |r, c| {
+        if (r >> c) & 1 == 1 {
+            1.0
+        } else {
+            -1.0
+        }
+    })
+}
+
+fn check_config(config: &SensitivityConfig) -> Result<(), NyxError> {
+    if config.inputs.is_empty() || config.outputs.is_empty() {
+        return Err(NyxError::CustomError {
+            msg: "sensitivity analysis requires at least one input and one output".to_string(),
+        });
+    }
+    if let Some(pert) = config.inputs.iter().find(|pert| pert.step == 0.0) {
+        return Err(NyxError::CustomError {
+            msg: format!("perturbation step of {} must not be zero", pert.param),
+        });
+    }
+    Ok(())
+}
+
+/// Propagates the state and returns the values of the outputs at the end.
+fn end_outputs(
+    state: Spacecraft,
+    prop: &Propagator<SpacecraftDynamics>,
+    config: &SensitivityConfig,
+    almanac: Arc<Almanac>,
+) -> Result<Vec<f64>, NyxError> {
+    let end = prop
+        .with(state, almanac)
+        .quiet()
+        .for_duration(config.duration)
+        .map_err(|e| NyxError::CustomError {
+            msg: format!("propagating the sensitivity analysis: {e}"),
+        })?;
+
+    config
+        .outputs
+        .iter()
+        .map(|param| {
+            end.value(*param)
+                .map_err(|e| NyxError::StateParameterUnavailable {
+                    param: *param,
+                    msg: e.to_string(),
+                })
+        })
+        .collect()
+}
+
+/// Propagates each run of the design in parallel and returns the values of the outputs at the end of each.
+fn propagate_design(
+    state: Spacecraft,
+    prop: &Propagator<SpacecraftDynamics>,
+    config: &SensitivityConfig,
+    design: &DMatrix<f64>,
+    almanac: Arc<Almanac>,
+) -> Result<Vec<Vec<f64>>, NyxError> {
+    (0..design.nrows())
+        .into_par_iter()
+        .map(|r| {
+            let mut perturbed = state;
+            for (j, pert) in config.inputs.iter().enumerate() {
+                let value = perturbed
+                    .value(pert.param)
+                    .and_then(|nominal| {
+                        perturbed.set_value(pert.param, nominal + design[(r, j)] * pert.step)
+                    })
+                    .map_err(|e| NyxError::StateParameterUnavailable {
+                        param: pert.param,
+                        msg: e.to_string(),
+                    });
+                value?;
+            }
+            end_outputs(perturbed, prop, config, almanac.clone())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod ut_sensitivity {
+    use super::*;
+    use crate::dynamics::OrbitalDynamics;
+    use crate::fixtures;
+    use crate::time::Unit;
+
+    #[test]
+    fn two_body_sensitivities() {
+        let state = Spacecraft::from(fixtures::keplerian(7_000.0, 0.01, 28.5, 10.0, 20.0, 30.0));
+        let almanac = fixtures::almanac();
+        let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+
+        let config = SensitivityConfig::builder()
+            .inputs(vec![
+                Perturbation {
+                    param: StateParameter::VX,
+                    step: 1e-4,
+                },
+                Perturbation {
+                    param: StateParameter::Cr,
+                    step: 0.1,
+                },
+            ])
+            .outputs(vec![
+                StateParameter::X,
+                StateParameter::Y,
+                StateParameter::Cr,
+            ])
+            .duration(Unit::Second * 10)
+            .build();
+
+        let report =
+            SensitivityReport::finite_differences(state, &prop, &config, almanac.clone()).unwrap();
+        println!("{report}");
+        assert_eq!(
+            report.method,
+            SensitivityMethod::FiniteDifferences(DifferenceScheme::Central)
+        );
+        // Over a short duration, the position moves with the initial velocity
+        let dx_dvx = report
+            .partial(StateParameter::X, StateParameter::VX)
+            .unwrap();
+        assert!((dx_dvx - 10.0).abs() < 1e-2, "{dx_dvx}");
+        assert!(
+            report
+                .partial(StateParameter::Y, StateParameter::VX)
+                .unwrap()
+                .abs()
+                < 1e-2
+        );
+        // The Cr has no effect in two body dynamics, but is carried over
+        assert!(
+            report
+                .partial(StateParameter::X, StateParameter::Cr)
+                .unwrap()
+                .abs()
+                < 1e-9
+        );
+        assert!(
+            (report
+                .partial(StateParameter::Cr, StateParameter::Cr)
+                .unwrap()
+                - 1.0)
+                .abs()
+                < 1e-9
+        );
+        assert_eq!(report.partial(StateParameter::Z, StateParameter::Cr), None);
+
+        // The forward differences and the full factorial design agree with the central differences
+        let forward = SensitivityReport::finite_differences(
+            state,
+            &prop,
+            &SensitivityConfig {
+                scheme: DifferenceScheme::Forward,
+                ..config.clone()
+            },
+            almanac.clone(),
+        )
+        .unwrap();
+        let design = two_level_full_factorial(2);
+        assert_eq!(design.nrows(), 4);
+        let doe = SensitivityReport::design_of_experiments(
+            state,
+            &prop,
+            &config,
+            &design,
+            almanac.clone(),
+        )
+        .unwrap();
+        println!("{doe}");
+        assert!((&forward.partials - &report.partials).norm() < 1e-3);
+        assert!((&doe.partials - &report.partials).norm() < 1e-6);
+
+        // Underdetermined designs are rejected
+        assert!(SensitivityReport::design_of_experiments(
+            state,
+            &prop,
+            &config,
+            &design.rows(0, 2).into_owned(),
+            almanac
+        )
+        .is_err());
+
+        let path = std::env::temp_dir().join("nyx_ut_sensitivity.parquet");
+        assert_eq!(report.to_parquet(&path).unwrap(), path);
+    }
+}
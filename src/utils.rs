@@ -650,6 +650,32 @@ macro_rules! pseudo_inverse {
     }};
 }
 
+/// Pseudo inverse of a targeting Jacobian where the rows of the inactive constraints are zero.
+///
+/// The inactive rows are ignored, such that the inverse exists even when the active constraints do not fully determine
+/// the problem. When all constraints are active, this is identical to `pseudo_inverse!`.
+#[macro_export]
+macro_rules! active_pseudo_inverse {
+    ($mat:expr, $active:expr) => {{
+        use $crate::md::TargetingError;
+        let num_active = $active.iter().filter(|active| **active).count();
+        if num_active < $mat.ncols() {
+            let mut gram = $mat * $mat.transpose();
+            for (i, active) in $active.iter().enumerate() {
+                if !*active {
+                    gram[(i, i)] = 1.0;
+                }
+            }
+            match gram.try_inverse() {
+                Some(gram_inv) => Ok($mat.transpose() * gram_inv),
+                None => Err(TargetingError::SingularJacobian),
+            }
+        } else {
+            $crate::pseudo_inverse!($mat)
+        }
+    }};
+}
+
 /// Returns the order of mangitude of the provided value
 /// ```
 /// use nyx_space::utils::mag_order;
@@ -0,0 +1,71 @@
+extern crate nyx_space as nyx;
+
+use anise::constants::frames::{EARTH_J2000, IAU_EARTH_FRAME, MOON_J2000};
+use nyx::cosmic::Orbit;
+use nyx::dynamics::orbital::OrbitalDynamics;
+use nyx::dynamics::SpacecraftDynamics;
+use nyx::od::GroundStation;
+use nyx::propagators::Propagator;
+use nyx::time::{Epoch, TimeUnits};
+use nyx::tools::access::{AccessReport, CelestialTarget};
+use std::sync::Arc;
+
+use anise::prelude::Almanac;
+use rstest::*;
+
+#[fixture]
+fn almanac() -> Arc<Almanac> {
+    use crate::test_almanac_arcd;
+    test_almanac_arcd()
+}
+
+#[rstest]
+fn leo_station_and_moon_access(almanac: Arc<Almanac>) {
+    let eme2k = almanac.frame_from_uid(EARTH_J2000).unwrap();
+    let iau_earth = almanac.frame_from_uid(IAU_EARTH_FRAME).unwrap();
+    let moon_j2k = almanac.frame_from_uid(MOON_J2000).unwrap();
+
+    let epoch = Epoch::from_gregorian_utc_at_midnight(2024, 1, 1);
+    let orbit = Orbit::keplerian(7_000.0, 0.001, 51.6, 30.0, 60.0, 0.0, epoch, eme2k);
+
+    let dynamics = SpacecraftDynamics::new(OrbitalDynamics::two_body());
+    let (_, traj) = Propagator::default(dynamics)
+        .with(orbit.into(), almanac.clone())
+        .for_duration_with_traj(1.days())
+        .unwrap();
+
+    let mut madrid = GroundStation::from_point(
+        "Madrid".to_string(),
+        40.427_222,
+        4.250_556,
+        0.834_939,
+        iau_earth,
+    );
+    madrid.elevation_mask_deg = 5.0;
+
+    let moon = CelestialTarget::new(moon_j2k, eme2k);
+
+    let report = AccessReport::compute(&traj, &[madrid], &[moon], almanac).unwrap();
+    println!("{report}");
+
+    let madrid_windows = report.windows_of("Madrid").collect::<Vec<_>>();
+    assert!(!madrid_windows.is_empty());
+    for window in madrid_windows {
+        // LEO passes last at most about a quarter of an hour.
+        assert!(window.duration() < 20.minutes(), "{window}");
+        assert!(
+            window.max_elevation_deg >= 5.0 && window.max_elevation_deg <= 90.0,
+            "{window}"
+        );
+        assert!(
+            window.max_elevation_epoch >= window.rise && window.max_elevation_epoch <= window.set
+        );
+    }
+
+    // The Moon is hidden by the Earth for less than half of each orbit.
+    let moon_name = format!("{moon_j2k}");
+    assert!(report.total_duration(&moon_name) > 12.hours());
+
+    let path = std::env::temp_dir().join("nyx_leo_access.parquet");
+    report.to_parquet(&path).unwrap();
+}
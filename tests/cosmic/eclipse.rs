@@ -7,12 +7,17 @@ use nyx::cosmic::eclipse::EclipseLocator;
 use nyx::cosmic::Orbit;
 use nyx::dynamics::orbital::OrbitalDynamics;
 use nyx::dynamics::SpacecraftDynamics;
+use nyx::md::EventEvaluator;
 use nyx::propagators::{IntegratorOptions, Propagator};
 use nyx::time::{Epoch, Unit};
+use nyx::State;
 use std::sync::{mpsc, Arc};
 use std::thread;
 
-use anise::{constants::frames::EARTH_J2000, prelude::Almanac};
+use anise::{
+    constants::frames::{EARTH_J2000, MOON_J2000},
+    prelude::Almanac,
+};
 use rstest::*;
 
 #[fixture]
@@ -122,3 +127,183 @@ fn geo_sun_earth_eclipses(almanac: Arc<Almanac>) {
 
     assert_eq!(cnt_changes, 14, "wrong number of eclipse state changes");
 }
+
+#[rstest]
+fn cislunar_worst_obstruction(almanac: Arc<Almanac>) {
+    let epoch = Epoch::from_gregorian_tai_at_midnight(2020, 1, 1);
+
+    let eme2k = almanac.frame_from_uid(EARTH_J2000).unwrap();
+    let moon_j2k = almanac.frame_from_uid(MOON_J2000).unwrap();
+
+    // Place the spacecraft right behind the Moon, as seen from the Sun.
+    let sun_from_moon = almanac
+        .translate(SUN_J2000, MOON_J2000, epoch, None)
+        .unwrap();
+    let anti_sun = -sun_from_moon.radius_km / sun_from_moon.radius_km.norm();
+    let radius_km = anti_sun * 2_000.0;
+    let behind_moon = Orbit::new(
+        radius_km[0],
+        radius_km[1],
+        radius_km[2],
+        0.0,
+        0.0,
+        0.0,
+        epoch,
+        moon_j2k,
+    );
+
+    let earth_only = EclipseLocator {
+        light_source: almanac.frame_from_uid(SUN_J2000).unwrap(),
+        shadow_bodies: vec![eme2k],
+    };
+
+    let cislunar = EclipseLocator::cislunar(almanac.clone());
+
+    // The Earth does not cast its shadow onto the far side of the Moon at this epoch.
+    assert!(earth_only
+        .compute(behind_moon, almanac.clone())
+        .unwrap()
+        .is_visible());
+    assert_eq!(
+        earth_only
+            .illumination(behind_moon, almanac.clone())
+            .unwrap(),
+        1.0
+    );
+
+    // Each shadow body is evaluated
+    let all = cislunar.compute_all(behind_moon, almanac.clone()).unwrap();
+    assert_eq!(all.len(), 2);
+    assert!(all[0].is_visible());
+    assert!(all[1].is_obstructed());
+
+    // And the Moon is selected as the worst obstruction.
+    let worst = cislunar.compute(behind_moon, almanac.clone()).unwrap();
+    println!("{worst}");
+    assert!(worst.is_obstructed());
+    assert_eq!(worst.front_frame, moon_j2k);
+    assert!(cislunar.illumination(behind_moon, almanac).unwrap() < 1e-3);
+}
+
+#[rstest]
+fn geo_eclipse_report(almanac: Arc<Almanac>) {
+    use nyx::time::TimeUnits;
+    use nyx::tools::eclipse_report::EclipseReport;
+
+    let eme2k = almanac.frame_from_uid(EARTH_J2000).unwrap();
+
+    // Around the spring equinox, a GEO is eclipsed once per day for about 70 minutes at most.
+    let start_time = Epoch::from_gregorian_utc_at_midnight(2020, 3, 10);
+    let geo = Orbit::keplerian(42_164.0, 0.0, 0.1, 0.0, 0.0, 0.0, start_time, eme2k);
+
+    let dynamics = SpacecraftDynamics::new(OrbitalDynamics::two_body());
+    let (_, traj) = Propagator::default(dynamics)
+        .with(geo.into(), almanac.clone())
+        .for_duration_with_traj(20.days())
+        .unwrap();
+
+    let e_loc = EclipseLocator {
+        light_source: almanac.frame_from_uid(SUN_J2000).unwrap(),
+        shadow_bodies: vec![eme2k],
+    };
+
+    let report = EclipseReport::compute(&traj, &e_loc, almanac).unwrap();
+    println!("{report}");
+
+    assert!(!report.eclipses.is_empty());
+    for eclipse in &report.eclipses {
+        assert!(eclipse.duration() < 75.minutes(), "{eclipse}");
+        assert!(eclipse.umbra_duration() <= eclipse.duration(), "{eclipse}");
+    }
+
+    let seasons = report.seasons(2.days());
+    assert_eq!(seasons.len(), 1, "expected a single eclipse season");
+    println!("{}", seasons[0]);
+    assert_eq!(seasons[0].num_eclipses, report.eclipses.len());
+
+    let path = std::env::temp_dir().join("nyx_geo_eclipse_report.parquet");
+    report.to_parquet(&path, 2.days()).unwrap();
+}
+
+#[rstest]
+fn leo_lighting_events(almanac: Arc<Almanac>) {
+    use anise::constants::frames::IAU_EARTH_FRAME;
+    use nyx::cosmic::lighting::{BetaAngleEvent, SunElevationEvent};
+    use nyx::time::TimeUnits;
+
+    let eme2k = almanac.frame_from_uid(EARTH_J2000).unwrap();
+    let iau_earth = almanac.frame_from_uid(IAU_EARTH_FRAME).unwrap();
+
+    let start_time = Epoch::from_gregorian_utc_at_midnight(2020, 3, 20);
+    let leo = Orbit::keplerian(6_878.0, 0.001, 51.6, 0.0, 0.0, 0.0, start_time, eme2k);
+
+    let (_, traj) = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()))
+        .with(leo.into(), almanac.clone())
+        .for_duration_with_traj(1.days())
+        .unwrap();
+
+    // The spacecraft crosses the terminator twice per revolution.
+    let day_side = SunElevationEvent::terminator(iau_earth);
+    let arcs = traj.find_arcs(&day_side, almanac.clone()).unwrap();
+    let revs = 1.days().to_seconds() / leo.period().unwrap().to_seconds();
+    assert!(
+        (arcs.len() as f64 - revs).abs() <= 1.0,
+        "{} arcs",
+        arcs.len()
+    );
+    for arc in &arcs {
+        let middle = traj
+            .at(arc.rise.state.epoch() + (arc.fall.state.epoch() - arc.rise.state.epoch()) * 0.5)
+            .unwrap();
+        assert!(day_side.eval(&middle, almanac.clone()).unwrap() > 0.0);
+    }
+
+    // The beta angle barely changes over a day of two-body propagation.
+    let beta = BetaAngleEvent::new(0.0);
+    let first = beta.beta_angle_deg(traj.first(), almanac.clone()).unwrap();
+    let last = beta.beta_angle_deg(traj.last(), almanac.clone()).unwrap();
+    println!("beta angle from {first:.3} to {last:.3} deg");
+    assert!((first - last).abs() < 1.5);
+    assert!(first.abs() <= 51.6 + 23.5);
+}
+
+#[rstest]
+fn leo_illumination_param(almanac: Arc<Almanac>) {
+    use nyx::cosmic::eclipse::EclipseState;
+    use nyx::md::{Event, StateParameter};
+
+    let eme2k = almanac.frame_from_uid(EARTH_J2000).unwrap();
+    let start_time = Epoch::from_gregorian_tai_at_midnight(2020, 1, 1);
+    let leo = Orbit::keplerian(6778.0, 0.1, 60.0, 0.0, 0.0, 0.0, start_time, eme2k);
+
+    let e_loc = EclipseLocator::cislunar(almanac.clone());
+    let (_, traj) = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()))
+        .with(leo.into(), almanac.clone())
+        .for_duration_with_traj(leo.period().unwrap())
+        .unwrap();
+
+    let mut states = [false; 3];
+    for sc in traj.every(Unit::Minute * 1) {
+        // The state parameter is consistent with the eclipse locator
+        let illumination = sc
+            .value_with_almanac(StateParameter::Illumination, &almanac)
+            .unwrap();
+        let expected = e_loc.illumination(sc.orbit, almanac.clone()).unwrap();
+        assert!((illumination - expected).abs() < 1e-12);
+        match e_loc.eclipse_state(sc.orbit, almanac.clone()).unwrap() {
+            EclipseState::Umbra => states[0] = true,
+            EclipseState::Penumbra(_) => states[1] = true,
+            EclipseState::Visibilis => states[2] = true,
+        }
+    }
+    assert!(states[0] && states[2], "expected both umbra and full sun");
+
+    // Events may search for the middle of the penumbra with the state parameter
+    let half_lit = traj
+        .find(
+            &Event::new(StateParameter::Illumination, 0.5),
+            almanac.clone(),
+        )
+        .unwrap();
+    assert_eq!(half_lit.len(), 2);
+}
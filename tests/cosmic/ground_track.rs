@@ -0,0 +1,55 @@
+extern crate nyx_space as nyx;
+
+use anise::constants::frames::{EARTH_J2000, IAU_EARTH_FRAME};
+use nyx::cosmic::Orbit;
+use nyx::dynamics::orbital::OrbitalDynamics;
+use nyx::dynamics::SpacecraftDynamics;
+use nyx::propagators::Propagator;
+use nyx::time::{Epoch, TimeUnits};
+use nyx::tools::ground_track::GroundTrack;
+use std::sync::Arc;
+
+use anise::prelude::Almanac;
+use rstest::*;
+
+#[fixture]
+fn almanac() -> Arc<Almanac> {
+    use crate::test_almanac_arcd;
+    test_almanac_arcd()
+}
+
+#[rstest]
+fn iss_ground_track(almanac: Arc<Almanac>) {
+    let eme2k = almanac.frame_from_uid(EARTH_J2000).unwrap();
+    let iau_earth = almanac.frame_from_uid(IAU_EARTH_FRAME).unwrap();
+
+    let epoch = Epoch::from_gregorian_utc_at_midnight(2024, 1, 1);
+    let orbit = Orbit::keplerian(6_790.0, 0.0005, 51.64, 30.0, 60.0, 0.0, epoch, eme2k);
+
+    let dynamics = SpacecraftDynamics::new(OrbitalDynamics::two_body());
+    let (_, traj) = Propagator::default(dynamics)
+        .with(orbit.into(), almanac.clone())
+        .for_duration_with_traj(1.days())
+        .unwrap();
+
+    let track = GroundTrack::from_traj(&traj, 30.seconds(), iau_earth, almanac).unwrap();
+    println!("{track}");
+
+    // About 15.5 revolutions per day, each crossing the antimeridian once.
+    assert!((15..=17).contains(&track.segments.len()));
+
+    for point in track.points() {
+        assert!(point.latitude_deg.abs() <= 51.7, "{point:?}");
+        assert!(point.longitude_deg.abs() <= 180.0, "{point:?}");
+        assert!((400.0..440.0).contains(&point.height_km), "{point:?}");
+    }
+
+    let dir = std::env::temp_dir();
+    track
+        .to_parquet(dir.join("nyx_iss_ground_track.parquet"))
+        .unwrap();
+    track
+        .to_geojson(dir.join("nyx_iss_ground_track.geojson"))
+        .unwrap();
+    track.to_kml(dir.join("nyx_iss_ground_track.kml")).unwrap();
+}
@@ -1,3 +1,6 @@
+mod access;
 mod bplane;
 mod eclipse;
+mod ground_track;
 mod orbit_dual;
+mod solar_params;
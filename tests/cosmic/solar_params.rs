@@ -0,0 +1,61 @@
+extern crate nyx_space as nyx;
+
+use anise::constants::frames::EARTH_J2000;
+use anise::prelude::Almanac;
+use nyx::cosmic::{Orbit, Spacecraft};
+use nyx::md::StateParameter;
+use nyx::md::{Event, EventEvaluator};
+use nyx::time::{Epoch, Unit};
+use nyx::State;
+use rstest::*;
+use std::sync::Arc;
+
+#[fixture]
+fn almanac() -> Arc<Almanac> {
+    use crate::test_almanac_arcd;
+    test_almanac_arcd()
+}
+
+#[rstest]
+fn solar_and_lunar_params(almanac: Arc<Almanac>) {
+    // Near the March equinox, the Sun is along the X axis of the EME2000 frame
+    let epoch = Epoch::from_gregorian_utc(2024, 3, 20, 3, 6, 0, 0);
+    let eme2k = almanac.frame_from_uid(EARTH_J2000).unwrap();
+    // Dawn-dusk orbit: the ascending node is at 6 PM local time, so the orbit normal points towards the Sun
+    let orbit = Orbit::keplerian(7_000.0, 0.001, 98.0, 90.0, 0.0, 0.0, epoch, eme2k);
+    let sc = Spacecraft::from(orbit);
+
+    let ltan = sc
+        .value_with_almanac(StateParameter::LTAN, &almanac)
+        .unwrap();
+    assert!((ltan - 18.0).abs() < 0.05, "LTAN = {ltan} h");
+    let beta = sc
+        .value_with_almanac(StateParameter::BetaAngle, &almanac)
+        .unwrap();
+    assert!(beta > 60.0, "beta = {beta} deg");
+
+    // The Sun-probe-Earth angle is the Sun angle for an Earth orbit
+    let spe = sc
+        .value_with_almanac(StateParameter::SunProbeEarthAngle, &almanac)
+        .unwrap();
+    let sun_angle = sc
+        .value_with_almanac(StateParameter::SunAngle, &almanac)
+        .unwrap();
+    assert!((spe - sun_angle).abs() < 1e-9);
+
+    // Full Moon on 2024-03-25, new Moon on 2024-04-08
+    let full = Spacecraft::from(orbit.at_epoch(epoch + Unit::Day * 5).unwrap())
+        .value_with_almanac(StateParameter::MoonPhaseAngle, &almanac)
+        .unwrap();
+    let new = Spacecraft::from(orbit.at_epoch(epoch + Unit::Day * 19).unwrap())
+        .value_with_almanac(StateParameter::MoonPhaseAngle, &almanac)
+        .unwrap();
+    assert!(full < 10.0, "full Moon phase angle = {full} deg");
+    assert!(new > 170.0, "new Moon phase angle = {new} deg");
+
+    // Events evaluate these parameters with the almanac
+    let event = Event::new(StateParameter::LTAN, 18.0);
+    let val = event.eval(&sc, almanac.clone()).unwrap();
+    assert!((val - (ltan - 18.0)).abs() < 1e-12);
+    println!("{}", event.eval_string(&sc, almanac).unwrap());
+}
@@ -0,0 +1,84 @@
+extern crate nyx_space as nyx;
+
+use anise::constants::celestial_objects::{MOON, SUN};
+use anise::constants::frames::{EARTH_J2000, MOON_J2000};
+use nyx::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+use nyx::linalg::Vector6;
+use nyx::propagators::Propagator;
+use nyx::time::{Epoch, Unit};
+use nyx::tools::cr3bp::*;
+use nyx::State;
+
+use anise::prelude::Almanac;
+use rstest::*;
+use std::sync::Arc;
+
+#[fixture]
+fn almanac() -> Arc<Almanac> {
+    use crate::test_almanac_arcd;
+    test_almanac_arcd()
+}
+
+#[rstest]
+fn cr3bp_synodic_frame(almanac: Arc<Almanac>) {
+    let system = Cr3bp::earth_moon();
+    let epoch = Epoch::from_gregorian_utc_at_midnight(2024, 6, 1);
+    let eme2k = almanac.frame_from_uid(EARTH_J2000).unwrap();
+
+    // The secondary is at (1 - μ, 0, 0) and at rest in the synodic frame.
+    let moon = almanac.transform(MOON_J2000, eme2k, epoch, None).unwrap();
+    let secondary = Vector6::new(1.0 - system.mu, 0.0, 0.0, 0.0, 0.0, 0.0);
+    let orbit = system
+        .to_inertial(&secondary, epoch, eme2k, MOON_J2000, &almanac)
+        .unwrap();
+    assert!((orbit.radius_km - moon.radius_km).norm() < 1e-6);
+    assert!((orbit.velocity_km_s - moon.velocity_km_s).norm() < 1e-9);
+
+    // Round trip
+    let state = Vector6::new(0.84, 0.01, 0.02, 0.001, 0.1, -0.02);
+    let orbit = system
+        .to_inertial(&state, epoch, eme2k, MOON_J2000, &almanac)
+        .unwrap();
+    let back = system.from_inertial(orbit, MOON_J2000, &almanac).unwrap();
+    assert!((back - state).norm() < 1e-12, "{}", back - state);
+}
+
+#[rstest]
+fn cr3bp_manifold_globalization(almanac: Arc<Almanac>) {
+    let system = Cr3bp::earth_moon();
+    let epoch = Epoch::from_gregorian_utc_at_midnight(2024, 6, 1);
+    let eme2k = almanac.frame_from_uid(EARTH_J2000).unwrap();
+
+    let orbit = PeriodicOrbit::lyapunov(&system, LibrationPoint::L1, 0.01).unwrap();
+    let config = ManifoldConfig::builder()
+        .stability(ManifoldStability::Unstable)
+        .num_arcs(2)
+        .duration(Unit::Day * 5)
+        .build();
+    let arcs = orbit.manifold(&system, config).unwrap();
+    assert_eq!(arcs.len(), 2);
+
+    let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::point_masses(
+        vec![MOON, SUN],
+    )));
+
+    for arc in &arcs {
+        let traj = arc
+            .globalize(&system, epoch, eme2k, MOON_J2000, &prop, almanac.clone())
+            .unwrap();
+        assert_eq!(traj.first().epoch(), epoch);
+
+        // The ephemeris trajectory remains near the CR3BP arc, in the synodic frame.
+        let end = traj.last();
+        let end_synodic = system
+            .from_inertial(end.orbit, MOON_J2000, &almanac)
+            .unwrap();
+        let error_km =
+            (end_synodic - arc.final_state()).fixed_rows::<3>(0).norm() * system.length_km;
+        println!(
+            "{arc}\n=> ephemeris model differs by {error_km:.3} km after {}",
+            end.epoch() - epoch
+        );
+        assert!(error_km < 10_000.0);
+    }
+}
@@ -1,3 +1,4 @@
+mod cr3bp;
 mod force_models;
 mod multishoot;
 mod orbitaldyn;
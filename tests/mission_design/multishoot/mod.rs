@@ -38,10 +38,7 @@ fn alt_orbit_raising_cov_test(almanac: Arc<Almanac>) {
     let sc = Spacecraft {
         orbit: start,
         mass: Mass::from_dry_and_prop_masses(100.0, 500.0),
-        thruster: Some(Thruster {
-            thrust_N: 150.0,
-            isp_s: 300.0,
-        }),
+        thruster: Some(Thruster::new(150.0, 300.0)),
         mode: GuidanceMode::Thrust,
 
         ..Default::default()
@@ -225,10 +222,7 @@ fn vmag_orbit_raising(almanac: Arc<Almanac>) {
     let sc = Spacecraft {
         orbit: start,
         mass: Mass::from_dry_and_prop_masses(100.0, 500.0),
-        thruster: Some(Thruster {
-            thrust_N: 150.0,
-            isp_s: 300.0,
-        }),
+        thruster: Some(Thruster::new(150.0, 300.0)),
         mode: GuidanceMode::Thrust,
 
         ..Default::default()
@@ -0,0 +1,139 @@
+extern crate nyx_space as nyx;
+
+use anise::constants::frames::{EARTH_J2000, IAU_EARTH_FRAME, MOON_J2000};
+use nyx::md::prelude::*;
+
+use anise::prelude::Almanac;
+use rstest::*;
+use std::sync::Arc;
+
+#[fixture]
+fn almanac() -> Arc<Almanac> {
+    use crate::test_almanac_arcd;
+    test_almanac_arcd()
+}
+
+#[rstest]
+fn tgt_ground_site(almanac: Arc<Almanac>) {
+    let _ = pretty_env_logger::try_init();
+
+    let eme2k = almanac.frame_from_uid(EARTH_J2000).unwrap();
+    let iau_earth = almanac.frame_from_uid(IAU_EARTH_FRAME).unwrap();
+
+    let orig_dt = Epoch::from_gregorian_utc_at_midnight(2020, 1, 1);
+
+    let xi_orig = Orbit::keplerian(7_500.0, 0.01, 51.6, 60.0, 60.0, 30.0, orig_dt, eme2k);
+    let spacecraft = Spacecraft::from_srp_defaults(xi_orig, 100.0, 0.0);
+
+    let target_dt = orig_dt + xi_orig.period().unwrap() * 0.4;
+
+    let dynamics = SpacecraftDynamics::new(OrbitalDynamics::two_body());
+    let setup = Propagator::default(dynamics);
+
+    // Grab the sub-spacecraft point of the nominal trajectory and shift it slightly, as a ground site would.
+    let xf_nominal = setup
+        .with(spacecraft, almanac.clone())
+        .until_epoch(target_dt)
+        .unwrap();
+    let xf_body_fixed = almanac
+        .transform_to(xf_nominal.orbit, iau_earth, None)
+        .unwrap();
+    let site_lat_deg = xf_body_fixed.latitude_deg().unwrap() + 0.5;
+    let site_long_deg = xf_body_fixed.longitude_deg() - 0.5;
+
+    let objectives = [
+        Objective::in_frame(StateParameter::Latitude, site_lat_deg, iau_earth),
+        Objective::in_frame(StateParameter::Longitude, site_long_deg, iau_earth),
+    ];
+
+    let tgt = Targeter::delta_v(&setup, objectives);
+
+    println!("{tgt}");
+
+    let solution_fd = tgt
+        .try_achieve_from(spacecraft, orig_dt, target_dt, almanac.clone())
+        .unwrap();
+
+    println!("Finite differencing solution: {solution_fd}");
+
+    let solution_stm = tgt
+        .try_achieve_stm(spacecraft, orig_dt, target_dt, almanac.clone())
+        .unwrap();
+
+    println!("STM solution: {solution_stm}");
+
+    assert!(
+        (solution_fd.correction - solution_stm.correction).norm() < 1e-5,
+        "STM and finite differencing solutions differ"
+    );
+
+    // Check that the sub-spacecraft point is over the site
+    let (xf, _) = tgt.apply_with_traj(&solution_stm, almanac.clone()).unwrap();
+    let xf_body_fixed = almanac.transform_to(xf.orbit, iau_earth, None).unwrap();
+    assert!((xf_body_fixed.latitude_deg().unwrap() - site_lat_deg).abs() < 1e-1);
+    assert!((xf_body_fixed.longitude_deg() - site_long_deg).abs() < 1e-1);
+}
+
+#[rstest]
+fn tgt_moon_range_sun_angle(almanac: Arc<Almanac>) {
+    let _ = pretty_env_logger::try_init();
+
+    let eme2k = almanac.frame_from_uid(EARTH_J2000).unwrap();
+    let moon_j2k = almanac.frame_from_uid(MOON_J2000).unwrap();
+
+    let orig_dt = Epoch::from_gregorian_utc_at_midnight(2020, 1, 1);
+
+    let xi_orig = Orbit::keplerian(24_000.0, 0.2, 10.0, 60.0, 60.0, 0.0, orig_dt, eme2k);
+    let spacecraft = Spacecraft::from_srp_defaults(xi_orig, 100.0, 0.0);
+
+    let target_dt = orig_dt + xi_orig.period().unwrap() * 0.5;
+
+    let dynamics = SpacecraftDynamics::new(OrbitalDynamics::two_body());
+    let setup = Propagator::default(dynamics);
+
+    let xf_nominal = setup
+        .with(spacecraft, almanac.clone())
+        .until_epoch(target_dt)
+        .unwrap();
+
+    // Get 1000 km closer to the Moon, and keep the Sun angle above its nominal value.
+    let nominal_range_km = almanac
+        .transform_to(xf_nominal.orbit, moon_j2k, None)
+        .unwrap()
+        .rmag_km();
+    let sun_angle = Objective::new(StateParameter::SunAngle, 0.0);
+    let (nominal_sun_angle_deg, _) = sun_angle.evaluate(xf_nominal.orbit, &almanac).unwrap();
+
+    let objectives = [
+        Objective::in_frame(StateParameter::Rmag, nominal_range_km - 1_000.0, moon_j2k),
+        Objective::at_least(StateParameter::SunAngle, nominal_sun_angle_deg),
+    ];
+
+    let tgt = Targeter::delta_v(&setup, objectives);
+
+    println!("{tgt}");
+
+    let solution_stm = tgt
+        .try_achieve_stm(spacecraft, orig_dt, target_dt, almanac.clone())
+        .unwrap();
+
+    println!("STM solution: {solution_stm}");
+
+    let solution_fd = tgt
+        .try_achieve_from(spacecraft, orig_dt, target_dt, almanac.clone())
+        .unwrap();
+
+    println!("Finite differencing solution: {solution_fd}");
+
+    for solution in [solution_stm, solution_fd] {
+        let (xf, _) = tgt.apply_with_traj(&solution, almanac.clone()).unwrap();
+        let range_km = almanac
+            .transform_to(xf.orbit, moon_j2k, None)
+            .unwrap()
+            .rmag_km();
+        assert!((range_km - nominal_range_km + 1_000.0).abs() < 1e-2);
+
+        let (sun_angle_deg, _) = sun_angle.evaluate(xf.orbit, &almanac).unwrap();
+        assert!(sun_angle_deg > nominal_sun_angle_deg - 1e-1);
+    }
+}
@@ -33,10 +33,7 @@ fn thrust_dir_tgt_sma_aop_raan(almanac: Arc<Almanac>) {
     let spacecraft = Spacecraft {
         orbit: xi_orig,
         mass: Mass::from_dry_and_prop_masses(10.0, 90.0),
-        thruster: Some(Thruster {
-            thrust_N: 500.0,
-            isp_s: 300.0,
-        }),
+        thruster: Some(Thruster::new(500.0, 300.0)),
         mode: GuidanceMode::Thrust,
         ..Default::default()
     };
@@ -79,10 +76,7 @@ fn thrust_dir_rate_tgt_sma_aop_raan(almanac: Arc<Almanac>) {
     let spacecraft = Spacecraft {
         orbit: xi_orig,
         mass: Mass::from_dry_and_prop_masses(10.0, 90.0),
-        thruster: Some(Thruster {
-            thrust_N: 500.0,
-            isp_s: 300.0,
-        }),
+        thruster: Some(Thruster::new(500.0, 300.0)),
         mode: GuidanceMode::Thrust,
         ..Default::default()
     };
@@ -127,10 +121,7 @@ fn thrust_profile_tgt_sma_aop_raan_cov_test(almanac: Arc<Almanac>) {
     let spacecraft = Spacecraft {
         orbit: xi_orig,
         mass: Mass::from_dry_and_prop_masses(10.0, 90.0),
-        thruster: Some(Thruster {
-            thrust_N: 500.0,
-            isp_s: 300.0,
-        }),
+        thruster: Some(Thruster::new(500.0, 300.0)),
         mode: GuidanceMode::Thrust,
         ..Default::default()
     };
@@ -176,10 +167,7 @@ fn val_tgt_finite_burn(almanac: Arc<Almanac>) {
     );
 
     // Define the thruster
-    let monoprop = Thruster {
-        thrust_N: 5000.0,
-        isp_s: 300.0,
-    };
+    let monoprop = Thruster::new(5000.0, 300.0);
     let dry_mass = 1e3;
     let fuel_mass = 756.0;
     let sc_state =
@@ -239,9 +227,15 @@ fn val_tgt_finite_burn(almanac: Arc<Almanac>) {
     println!("\n\nKNOWN SOLUTION\n{}", mnvr0);
 
     // Solve for this known solution
-    // let fb_mnvr =
-    //     Optimizer::convert_impulsive_mnvr(sc_state, impulsive_tgt.correction, &prop).unwrap();
-    // println!("Solution ended being:\n{}\n", fb_mnvr);
+    let fb_mnvr = Targeter::convert_impulsive_mnvr(
+        sc_state,
+        impulsive_tgt.correction,
+        &prop_no_thrust,
+        almanac,
+    )
+    .unwrap();
+    println!("Solution ended being:\n{}\n", fb_mnvr);
 
-    // Test that this solution works.
+    // The finite burn solution is equivalent to the known solution.
+    assert!((fb_mnvr.duration() - mnvr0.duration()).abs() < 1.seconds());
 }
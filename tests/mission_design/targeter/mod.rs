@@ -1,5 +1,6 @@
 extern crate nyx_space as nyx;
 
+mod almanac_objectives;
 mod b_plane;
 mod finite_burns;
 mod multi_oe;
@@ -90,10 +90,7 @@ fn conv_tgt_sma_ecc(almanac: Arc<Almanac>) {
             prop_mass_kg: 90.0,
             extra_mass_kg: 0.0,
         },
-        thruster: Some(Thruster {
-            thrust_N: 500.0,
-            isp_s: 300.0,
-        }),
+        thruster: Some(Thruster::new(500.0, 300.0)),
         mode: GuidanceMode::Thrust,
 
         ..Default::default()
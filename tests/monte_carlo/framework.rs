@@ -58,6 +58,10 @@ fn test_monte_carlo_epoch_cov_test(almanac: Arc<Almanac>) {
         nominal_state,
         seed: Some(0),
         scenario: "test_monte_carlo_epoch".to_string(),
+        sampling: Sampling::default(),
+        reporter: None,
+        cancellation: None,
+        traj_tolerance: None,
     };
 
     let rslts = my_mc.run_until_epoch(prop, almanac.clone(), dt + 1.0_f64 * Unit::Day, 10);
@@ -1,2 +1,3 @@
 mod framework;
 mod manual_montecarlo;
+mod realism;
@@ -0,0 +1,97 @@
+extern crate nyx_space as nyx;
+
+use anise::constants::frames::{EARTH_J2000, IAU_EARTH_FRAME};
+use anise::prelude::Almanac;
+use nyx::dynamics::{OrbitalDynamics, SpacecraftDynamics};
+use nyx::linalg::{SMatrix, SVector};
+use nyx::mc::{CovarianceRealism, CHI2_6DOF_95};
+use nyx::od::prelude::*;
+use nyx::propagators::Propagator;
+use nyx::{Orbit, Spacecraft};
+use rstest::*;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+#[fixture]
+fn almanac() -> Arc<Almanac> {
+    use crate::test_almanac_arcd;
+    test_almanac_arcd()
+}
+
+#[rstest]
+fn covariance_realism_two_body(almanac: Arc<Almanac>) {
+    let _ = pretty_env_logger::try_init();
+
+    let iau_earth = almanac.frame_from_uid(IAU_EARTH_FRAME).unwrap();
+    let mut devices = BTreeMap::new();
+    devices.insert(
+        "Madrid".to_string(),
+        GroundStation::dss65_madrid(
+            0.0,
+            StochasticNoise::default_range_km(),
+            StochasticNoise::default_doppler_km_s(),
+            iau_earth,
+        ),
+    );
+    devices.insert(
+        "Canberra".to_string(),
+        GroundStation::dss34_canberra(
+            0.0,
+            StochasticNoise::default_range_km(),
+            StochasticNoise::default_doppler_km_s(),
+            iau_earth,
+        ),
+    );
+
+    let mut configs = BTreeMap::new();
+    for name in devices.keys() {
+        configs.insert(name.clone(), TrkConfig::from_sample_rate(1.minutes()));
+    }
+
+    let eme2k = almanac.frame_from_uid(EARTH_J2000).unwrap();
+    let epoch = Epoch::from_gregorian_utc_at_midnight(2024, 1, 1);
+    let orbit = Orbit::keplerian(22_000.0, 0.01, 30.0, 80.0, 40.0, 0.0, epoch, eme2k);
+
+    // 1 km and 1 m/s of initial uncertainty
+    let initial_estimate = KfEstimate::from_covar(
+        Spacecraft::from(orbit),
+        SMatrix::<f64, 9, 9>::from_diagonal(&SVector::<f64, 9>::from_iterator([
+            1.0, 1.0, 1.0, 1e-6, 1e-6, 1e-6, 0.0, 0.0, 0.0,
+        ])),
+    );
+
+    // Same dynamics for the truth and the estimation, so the covariance should be realistic
+    let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+
+    let realism = CovarianceRealism::builder()
+        .scenario("two body realism".to_string())
+        .truth_prop(prop.clone())
+        .estimation_prop(prop)
+        .initial_estimate(initial_estimate)
+        .devices(devices)
+        .configs(configs)
+        .end_epoch(epoch + 12.hours())
+        .prediction((10.minutes(), epoch + 18.hours()))
+        .seed(0)
+        .build();
+
+    let results = realism.run(almanac, 50).unwrap();
+    println!("{results}");
+
+    assert_eq!(results.samples.len(), 50);
+    assert!(results.registry.is_complete());
+    assert!(results.samples.iter().all(|sample| sample.num_msr > 0));
+    assert!(results.epochs.iter().any(|epoch| epoch.predicted));
+
+    // The mean squared Mahalanobis distance has six degrees of freedom
+    let mean = results.mean_mahalanobis_sq();
+    assert!((mean - 6.0).abs() < 1.5, "mean squared Mahalanobis: {mean}");
+    assert!(results.within_95() > 0.85);
+    for epoch in &results.epochs {
+        assert!(epoch.within_95 <= 1.0);
+        assert!(epoch.mean_mahalanobis_sq < 10.0 * CHI2_6DOF_95);
+    }
+
+    let path = std::env::temp_dir().join("nyx_covariance_realism.parquet");
+    assert_eq!(results.to_parquet(&path).unwrap(), path);
+}
@@ -0,0 +1,154 @@
+extern crate nyx_space as nyx;
+
+use anise::constants::frames::IAU_EARTH_FRAME;
+use nyx::cosmic::Orbit;
+use nyx::dynamics::orbital::OrbitalDynamics;
+use nyx::dynamics::SpacecraftDynamics;
+use nyx::io::ConfigRepr;
+use nyx::linalg::{SVector, Vector3};
+use nyx::md::StateParameter;
+use nyx::od::prelude::*;
+use nyx::propagators::Propagator;
+use nyx::Spacecraft;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anise::{constants::frames::EARTH_J2000, prelude::Almanac};
+use rstest::*;
+use std::sync::Arc;
+
+#[fixture]
+fn almanac() -> Arc<Almanac> {
+    use crate::test_almanac_arcd;
+    test_almanac_arcd()
+}
+
+#[fixture]
+fn devices(almanac: Arc<Almanac>) -> BTreeMap<String, GroundStation> {
+    let iau_earth = almanac.frame_from_uid(IAU_EARTH_FRAME).unwrap();
+    let elevation_mask = 0.0;
+    let dss65_madrid = GroundStation::dss65_madrid(
+        elevation_mask,
+        StochasticNoise::MIN,
+        StochasticNoise::MIN,
+        iau_earth,
+    );
+    let dss34_canberra = GroundStation::dss34_canberra(
+        elevation_mask,
+        StochasticNoise::MIN,
+        StochasticNoise::MIN,
+        iau_earth,
+    );
+    let dss13_goldstone = GroundStation::dss13_goldstone(
+        elevation_mask,
+        StochasticNoise::MIN,
+        StochasticNoise::MIN,
+        iau_earth,
+    );
+
+    let mut devices = BTreeMap::new();
+    devices.insert("Madrid".to_string(), dss65_madrid);
+    devices.insert("Canberra".to_string(), dss34_canberra);
+    devices.insert("Goldstone".to_string(), dss13_goldstone);
+
+    devices
+}
+
+#[rstest]
+fn lincov_with_tracking_and_maneuver(
+    almanac: Arc<Almanac>,
+    devices: BTreeMap<String, GroundStation>,
+) {
+    let _ = pretty_env_logger::try_init();
+
+    let trkconfig_yaml: PathBuf = [
+        env!("CARGO_MANIFEST_DIR"),
+        "data",
+        "tests",
+        "config",
+        "trk_cfg_od_val.yaml",
+    ]
+    .iter()
+    .collect();
+    let cfg = TrkConfig::load(trkconfig_yaml).unwrap();
+    let configs = devices
+        .keys()
+        .map(|name| (name.clone(), cfg.clone()))
+        .collect::<BTreeMap<String, TrkConfig>>();
+
+    let eme2k = almanac.frame_from_uid(EARTH_J2000).unwrap();
+    let epoch = Epoch::from_gregorian_tai_at_midnight(2020, 1, 1);
+    let nominal = Spacecraft::from(Orbit::keplerian(
+        22000.0, 0.01, 30.0, 80.0, 40.0, 0.0, epoch, eme2k,
+    ));
+    let end_epoch = epoch + Unit::Day * 1;
+
+    let initial_estimate = KfEstimate::from_diag(
+        nominal,
+        SVector::<f64, 9>::from_iterator([1.0, 1.0, 1.0, 1e-6, 1e-6, 1e-6, 0.0, 0.0, 0.0]),
+    );
+
+    let prop = Propagator::default(SpacecraftDynamics::new(OrbitalDynamics::two_body()));
+
+    let mnvr = LinCovManeuver {
+        epoch: epoch + Unit::Hour * 12,
+        dv_km_s: Vector3::new(0.0, 0.01, 0.0),
+        magnitude_sigma: 0.05,
+        pointing_sigma_deg: 0.5,
+    };
+
+    // Without tracking, the dispersions only grow
+    let predicted = LinCov::builder()
+        .initial_estimate(initial_estimate)
+        .maneuvers(vec![mnvr])
+        .step(Unit::Minute * 30)
+        .build()
+        .run(&prop, end_epoch, almanac.clone())
+        .unwrap();
+    println!("{predicted}");
+    assert!(predicted.updates.is_empty());
+
+    let tracked = LinCov::builder()
+        .initial_estimate(initial_estimate)
+        .devices(devices)
+        .configs(configs)
+        .maneuvers(vec![mnvr])
+        .step(Unit::Minute * 30)
+        .build()
+        .run(&prop, end_epoch, almanac)
+        .unwrap();
+    println!("{tracked}");
+    assert!(!tracked.updates.is_empty());
+
+    let tracked_sigma = tracked.last().sigma_for(StateParameter::Rmag).unwrap();
+    let predicted_sigma = predicted.last().sigma_for(StateParameter::Rmag).unwrap();
+    assert!(
+        tracked_sigma < predicted_sigma,
+        "tracking should reduce the dispersions: {tracked_sigma} >= {predicted_sigma}"
+    );
+    assert!(
+        tracked_sigma < 1.0,
+        "tracking should shrink the initial covariance"
+    );
+
+    // The measurement updates are flagged in the timeline
+    assert!(tracked.estimates.iter().any(|est| !est.predicted));
+
+    let path: PathBuf = [
+        env!("CARGO_MANIFEST_DIR"),
+        "output_data",
+        "lincov_tracked.parquet",
+    ]
+    .iter()
+    .collect();
+    tracked
+        .to_parquet(
+            path,
+            &[
+                StateParameter::Rmag,
+                StateParameter::Vmag,
+                StateParameter::SMA,
+            ],
+        )
+        .unwrap();
+}
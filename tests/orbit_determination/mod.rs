@@ -7,6 +7,7 @@ use nyx::Spacecraft;
 use self::nyx::od::prelude::{Estimate, Filter, KfEstimate, KF};
 use self::nyx::State;
 
+mod lincov;
 mod measurements;
 mod multi_body;
 mod resid_reject;
@@ -192,6 +192,10 @@ fn od_val_sc_mb_srp_reals_duals_models(
     arc.to_parquet_simple(path.with_file_name("sc_msr_arc.parquet"))
         .unwrap();
 
+    // The same data is available in memory for Arrow consumers
+    let arc_batch = arc.to_record_batch(ExportCfg::default()).unwrap();
+    assert_eq!(arc_batch.num_rows(), arc.len());
+
     // Now that we have the truth data, let's start an OD with no noise at all and compute the estimates.
     // We expect the estimated orbit to be perfect since we're using strictly the same dynamics, no noise on
     // the measurements, and the same time step.
@@ -230,6 +234,9 @@ fn od_val_sc_mb_srp_reals_duals_models(
     )
     .unwrap();
 
+    let od_batch = odp.to_record_batch(&arc, ExportCfg::default()).unwrap();
+    assert_eq!(od_batch.num_rows(), odp.estimates.len());
+
     for (no, est) in odp.estimates.iter().enumerate() {
         if no == 0 {
             // Skip the first estimate which is the initial estimate provided by user
@@ -10,6 +10,7 @@ use nyx::dynamics::SpacecraftDynamics;
 use nyx::io::ConfigRepr;
 use nyx::io::{gravity::*, ExportCfg};
 use nyx::linalg::{SMatrix, SVector};
+use nyx::md::prelude::Traj;
 use nyx::od::prelude::*;
 use nyx::propagators::{IntegratorOptions, Propagator};
 use nyx::Spacecraft;
@@ -507,6 +508,16 @@ fn od_tb_val_ckf_fixed_step_perfect_stations(
 
     odp.to_parquet(&arc, path, ExportCfg::default()).unwrap();
 
+    // Export the navigation trajectory with its covariance, and reload it.
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "output_data", "tb_ckf.oem"]
+        .iter()
+        .collect();
+    let oem_path = odp.to_oem(path, ExportCfg::default()).unwrap();
+    let oem = std::fs::read_to_string(&oem_path).unwrap();
+    assert_eq!(oem.matches("EPOCH =").count(), odp.estimates.len());
+    let nav_traj = Traj::<Spacecraft>::from_oem_file(oem_path, None).unwrap();
+    assert_eq!(nav_traj.states.len(), odp.to_traj().unwrap().states.len());
+
     // Check that there are no duplicates of epochs.
     let mut prev_epoch = odp.estimates[0].epoch();
 
@@ -1467,3 +1478,88 @@ fn od_tb_ckf_fixed_step_perfect_stations_several_snc_covar_map(
         "Velocity error should be zero"
     );
 }
+
+#[allow(clippy::identity_op)]
+#[rstest]
+fn od_tb_ckf_dynamic_range_bias(
+    almanac: Arc<Almanac>,
+    sim_devices: BTreeMap<String, GroundStation>,
+    proc_devices: BTreeMap<String, GroundStation>,
+    cfg: TrkConfig,
+) {
+    let _ = pretty_env_logger::try_init();
+
+    // Define the propagator information.
+    let prop_time = 12 * Unit::Hour;
+    let step_size = 10.0 * Unit::Second;
+    let opts = IntegratorOptions::with_fixed_step(step_size);
+
+    // Define state information.
+    let eme2k = almanac.frame_from_uid(EARTH_J2000).unwrap();
+    let dt = Epoch::from_gregorian_tai_at_midnight(2020, 1, 1);
+    let initial_state = Orbit::keplerian(22000.0, 0.01, 30.0, 80.0, 40.0, 0.0, dt, eme2k);
+
+    let orbital_dyn = SpacecraftDynamics::new(OrbitalDynamics::two_body());
+    let setup = Propagator::new(orbital_dyn, IntegratorMethod::RungeKutta4, opts);
+    let (_, traj) = setup
+        .with(initial_state.into(), almanac.clone())
+        .for_duration_with_traj(prop_time)
+        .unwrap();
+
+    // Simulate perfect tracking data, and bias all of the ranges
+    let configs = sim_devices
+        .keys()
+        .map(|name| (name.clone(), cfg.clone()))
+        .collect::<BTreeMap<_, _>>();
+    let mut arc_sim = TrackingArcSim::with_seed(sim_devices, traj, configs, 0).unwrap();
+    arc_sim.build_schedule(almanac.clone()).unwrap();
+    let mut arc = arc_sim.generate_measurements(almanac.clone()).unwrap();
+    let range_bias_km = 0.05;
+    for msr in arc.measurements.values_mut() {
+        if let Some(range_km) = msr.data.get_mut(&MeasurementType::Range) {
+            *range_km += range_bias_km;
+        }
+    }
+
+    // Solve for the range bias, which is applied to the first row of the range and Doppler measurements
+    let bias = SolveFor::Bias("range".to_string());
+    let initial_estimate = DynamicEstimate::from_diag(
+        initial_state.into(),
+        vec![bias.clone()],
+        &[1e-6, 1e-6, 1e-6, 1e-6, 1e-6, 1e-6, 0.1_f64.powi(2)],
+    )
+    .unwrap();
+    let kf = DynamicKF::new(initial_estimate, vec![]).with_bias_row("range", 0);
+
+    let mut odp = DynamicODProcess::ckf(
+        setup.with(Spacecraft::from(initial_state).with_stm(), almanac.clone()),
+        kf,
+        proc_devices,
+        None,
+        almanac,
+    );
+    odp.process_arc(&arc).unwrap();
+
+    // The estimates of the process are the spacecraft part of the runtime-sized estimates
+    assert_eq!(odp.kf.history.len(), odp.estimates.len());
+    let last = odp.kf.history.last().unwrap();
+    assert_eq!(&last.to_estimate(), odp.estimates.last().unwrap());
+
+    let bias_km = last.param_value(&bias).unwrap();
+    println!("{last}");
+    assert!(
+        (bias_km - range_bias_km).abs() < 1e-3,
+        "range bias estimated at {bias_km} km instead of {range_bias_km} km"
+    );
+    assert!(last.param_sigma(&bias).unwrap() < 0.1);
+
+    // And the orbit is not corrupted by the bias
+    let delta = (odp.estimates.last().unwrap().state().orbit
+        - arc_sim.trajectory.at(last.epoch()).unwrap().orbit)
+        .unwrap();
+    assert!(
+        delta.rmag_km() < 1e-2,
+        "position error of {} km",
+        delta.rmag_km()
+    );
+}
@@ -400,10 +400,10 @@ fn sc_set_unset_static(almanac: Arc<Almanac>) {
     let mut init_sc = Spacecraft::from_srp_defaults(init, 100.0, 1.0).with_stm();
 
     // Change the full vector
-    let data = (0..90).map(|x| x as f64).collect::<Vec<f64>>();
+    let data = (0..306).map(|x| x as f64).collect::<Vec<f64>>();
     init_sc.set(
         init.epoch,
-        &OVector::<f64, Const<90>>::from_column_slice(&data),
+        &OVector::<f64, Const<306>>::from_column_slice(&data),
     );
 
     let init_vec = init_sc.to_vector();
@@ -413,3 +413,92 @@ fn sc_set_unset_static(almanac: Arc<Almanac>) {
 
     assert_eq!(init_sc, init2);
 }
+
+#[rstest]
+fn jacobian_vs_finite_differences(almanac: Arc<Almanac>) {
+    use anise::constants::frames::IAU_EARTH_FRAME;
+    use nyx::dynamics::{Drag, Dynamics, Harmonics, SolarPressure};
+    use nyx::io::gravity::HarmonicsMem;
+
+    // Checks that the dynamics Jacobian used for the STM includes every force of the nominal dynamics.
+    let eme2k = almanac.frame_from_uid(EARTH_J2000).unwrap();
+    let iau_earth = almanac.frame_from_uid(IAU_EARTH_FRAME).unwrap();
+    let epoch = Epoch::from_gregorian_tai_at_midnight(2020, 1, 1);
+
+    let harmonics = Harmonics::from_stor(
+        iau_earth,
+        HarmonicsMem::from_cof("data/JGM3.cof.gz", 8, 8, true).unwrap(),
+    );
+
+    let mut orbital_dyn = OrbitalDynamics::point_masses(vec![MOON, SUN]);
+    orbital_dyn.accel_models.push(harmonics);
+
+    let mut drag = Drag::std_atm1976(almanac.clone()).unwrap().as_ref().clone();
+    drag.estimate = true;
+
+    let dynamics = SpacecraftDynamics::from_models(
+        orbital_dyn,
+        vec![
+            SolarPressure::default(eme2k, almanac.clone()).unwrap(),
+            Arc::new(drag),
+        ],
+    );
+
+    let sc = Spacecraft::from_srp_defaults(
+        Orbit::try_keplerian_altitude(350.0, 0.01, 51.6, 25.0, 45.0, 10.0, epoch, eme2k).unwrap(),
+        100.0,
+        2.0,
+    )
+    .with_drag(2.0, 2.2)
+    .with_prop_mass(50.0);
+
+    let (_, grad) = dynamics.dual_eom(0.0, &sc, almanac.clone()).unwrap();
+
+    let accel = |state: Spacecraft| -> OVector<f64, Const<3>> {
+        let d_x = dynamics
+            .eom(0.0, &state.to_vector(), &state, almanac.clone())
+            .unwrap();
+        d_x.fixed_rows::<3>(3).into_owned()
+    };
+
+    // Perturb the position, velocity, Cr, Cd, and prop mass with central differences
+    let perturbations = [1e-3, 1e-3, 1e-3, 1e-4, 1e-4, 1e-4, 1e-4, 1e-4, 1e-3];
+    for (j, pert) in perturbations.iter().copied().enumerate() {
+        let mut plus = sc;
+        let mut minus = sc;
+        match j {
+            0..=2 => {
+                plus.orbit.radius_km[j] += pert;
+                minus.orbit.radius_km[j] -= pert;
+            }
+            3..=5 => {
+                plus.orbit.velocity_km_s[j - 3] += pert;
+                minus.orbit.velocity_km_s[j - 3] -= pert;
+            }
+            6 => {
+                plus.srp.coeff_reflectivity += pert;
+                minus.srp.coeff_reflectivity -= pert;
+            }
+            7 => {
+                plus.drag.coeff_drag += pert;
+                minus.drag.coeff_drag -= pert;
+            }
+            8 => {
+                plus.mass.prop_mass_kg += pert;
+                minus.mass.prop_mass_kg -= pert;
+            }
+            _ => unreachable!(),
+        }
+
+        let fd = (accel(plus) - accel(minus)) / (2.0 * pert);
+        for i in 0..3 {
+            let hd = grad[(i + 3, j)];
+            let err = (hd - fd[i]).abs();
+            assert!(
+                err < 1e-6 * fd.norm() + 1e-13,
+                "partial of accel[{i}] wrt state[{j}]: HD = {hd:e}\tFD = {:e}",
+                fd[i]
+            );
+        }
+    }
+}
@@ -300,10 +300,7 @@ fn event_and_combination(almanac: Arc<Almanac>) {
         orbit,
         100.0,
         50.0,
-        Thruster {
-            isp_s: 300.0,
-            thrust_N: 50.0,
-        },
+        Thruster::new(50.0, 300.0),
         GuidanceMode::Thrust,
     );
 
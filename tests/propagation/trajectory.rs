@@ -275,10 +275,7 @@ fn traj_spacecraft(almanac: Arc<Almanac>) {
     let prop_mass = 67.0;
     let dry_mass = 300.0;
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 89e-3,
-        isp_s: 1650.0,
-    };
+    let lowt = Thruster::new(89e-3, 1650.0);
     let start_state =
         Spacecraft::from_thruster(orbit, dry_mass, prop_mass, lowt, GuidanceMode::Thrust);
 
@@ -40,10 +40,7 @@ fn qlaw_as_ruggiero_case_a(almanac: Arc<Almanac>) {
     let orbital_dyn = OrbitalDynamics::two_body();
 
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 1.0,
-        isp_s: 3100.0,
-    };
+    let lowt = Thruster::new(1.0, 3100.0);
 
     let objectives = &[
         Objective::within_tolerance(StateParameter::SMA, 42_000.0, 1.0),
@@ -110,10 +107,7 @@ fn qlaw_as_ruggiero_case_b(almanac: Arc<Almanac>) {
     let orbital_dyn = OrbitalDynamics::two_body();
 
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 0.350,
-        isp_s: 2000.0,
-    };
+    let lowt = Thruster::new(0.350, 2000.0);
 
     let objectives = &[
         Objective::within_tolerance(StateParameter::SMA, 42_165.0, 20.0),
@@ -169,10 +163,7 @@ fn qlaw_as_ruggiero_case_c_cov_test(almanac: Arc<Almanac>) {
     let orbital_dyn = OrbitalDynamics::two_body();
 
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 9.3,
-        isp_s: 3100.0,
-    };
+    let lowt = Thruster::new(9.3, 3100.0);
 
     let objectives = &[
         Objective::within_tolerance(StateParameter::SMA, 30_000.0, 1.0),
@@ -228,10 +219,7 @@ fn qlaw_as_ruggiero_case_d(almanac: Arc<Almanac>) {
     let orbital_dyn = OrbitalDynamics::two_body();
 
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 89e-3,
-        isp_s: 1650.0,
-    };
+    let lowt = Thruster::new(89e-3, 1650.0);
 
     let objectives = &[
         Objective::within_tolerance(StateParameter::SMA, 26_500.0, 1.0),
@@ -290,10 +278,7 @@ fn qlaw_as_ruggiero_case_e(almanac: Arc<Almanac>) {
     let orbital_dyn = OrbitalDynamics::two_body();
 
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 89e-3,
-        isp_s: 1650.0,
-    };
+    let lowt = Thruster::new(89e-3, 1650.0);
 
     let objectives = &[
         Objective::within_tolerance(StateParameter::SMA, 26_500.0, 1.0),
@@ -355,10 +340,7 @@ fn qlaw_as_ruggiero_case_f(almanac: Arc<Almanac>) {
     let orbital_dyn = OrbitalDynamics::two_body();
 
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 89e-3,
-        isp_s: 1650.0,
-    };
+    let lowt = Thruster::new(89e-3, 1650.0);
 
     let objectives = &[Objective::new(StateParameter::Eccentricity, 0.15)];
 
@@ -414,10 +396,7 @@ fn ruggiero_iepc_2011_102(almanac: Arc<Almanac>) {
     let orbital_dyn = OrbitalDynamics::two_body();
 
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 89e-3,
-        isp_s: 1650.0,
-    };
+    let lowt = Thruster::new(89e-3, 1650.0);
 
     let objectives = &[
         Objective::within_tolerance(StateParameter::SMA, 42_164.0, 20.0),
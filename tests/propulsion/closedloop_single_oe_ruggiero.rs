@@ -32,10 +32,7 @@ fn rugg_sma(almanac: Arc<Almanac>) {
     let orbital_dyn = OrbitalDynamics::two_body();
 
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 89e-3,
-        isp_s: 1650.0,
-    };
+    let lowt = Thruster::new(89e-3, 1650.0);
 
     // Define the objectives
     let objectives = &[Objective::within_tolerance(
@@ -85,10 +82,7 @@ fn rugg_sma_regress_threshold(almanac: Arc<Almanac>) {
     let prop_time = 175 * Unit::Day;
 
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 89e-3,
-        isp_s: 1650.0,
-    };
+    let lowt = Thruster::new(89e-3, 1650.0);
 
     // Define the objectives
     let objectives = &[Objective::within_tolerance(
@@ -142,10 +136,7 @@ fn rugg_sma_decr(almanac: Arc<Almanac>) {
     let orbital_dyn = OrbitalDynamics::two_body();
 
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 89e-3,
-        isp_s: 1650.0,
-    };
+    let lowt = Thruster::new(89e-3, 1650.0);
 
     // Define the objectives
     let objectives = &[Objective::within_tolerance(
@@ -200,10 +191,7 @@ fn rugg_inc(almanac: Arc<Almanac>) {
     let orbital_dyn = OrbitalDynamics::two_body();
 
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 89e-3,
-        isp_s: 1650.0,
-    };
+    let lowt = Thruster::new(89e-3, 1650.0);
 
     // Define the objectives
     let objectives = &[Objective::within_tolerance(
@@ -259,10 +247,7 @@ fn rugg_inc_threshold(almanac: Arc<Almanac>) {
     let orbital_dyn = OrbitalDynamics::two_body();
 
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 89e-3,
-        isp_s: 1650.0,
-    };
+    let lowt = Thruster::new(89e-3, 1650.0);
 
     // Define the objectives
     let objectives = &[Objective::within_tolerance(
@@ -317,10 +302,7 @@ fn rugg_inc_decr(almanac: Arc<Almanac>) {
     let orbital_dyn = OrbitalDynamics::two_body();
 
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 89e-3,
-        isp_s: 1650.0,
-    };
+    let lowt = Thruster::new(89e-3, 1650.0);
 
     // Define the objectives
     let objectives = &[Objective::within_tolerance(
@@ -375,10 +357,7 @@ fn rugg_ecc(almanac: Arc<Almanac>) {
     let orbital_dyn = OrbitalDynamics::two_body();
 
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 89e-3,
-        isp_s: 1650.0,
-    };
+    let lowt = Thruster::new(89e-3, 1650.0);
 
     // Define the objectives
     let objectives = &[Objective::within_tolerance(
@@ -433,10 +412,7 @@ fn rugg_ecc_regress_threshold(almanac: Arc<Almanac>) {
     let dry_mass = 300.0;
 
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 89e-3,
-        isp_s: 1650.0,
-    };
+    let lowt = Thruster::new(89e-3, 1650.0);
 
     // Define the objectives
     let objectives = &[Objective::within_tolerance(
@@ -491,10 +467,7 @@ fn rugg_ecc_decr(almanac: Arc<Almanac>) {
     let orbital_dyn = OrbitalDynamics::two_body();
 
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 89e-3,
-        isp_s: 1650.0,
-    };
+    let lowt = Thruster::new(89e-3, 1650.0);
 
     // Define the objectives
     let objectives = &[Objective::within_tolerance(
@@ -551,10 +524,7 @@ fn rugg_aop(almanac: Arc<Almanac>) {
     let orbital_dyn = OrbitalDynamics::two_body();
 
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 89e-3,
-        isp_s: 1650.0,
-    };
+    let lowt = Thruster::new(89e-3, 1650.0);
 
     // Define the objectives
     let objectives = &[Objective::within_tolerance(
@@ -610,10 +580,7 @@ fn rugg_aop_decr(almanac: Arc<Almanac>) {
     let orbital_dyn = OrbitalDynamics::two_body();
 
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 89e-3,
-        isp_s: 1650.0,
-    };
+    let lowt = Thruster::new(89e-3, 1650.0);
 
     // Define the objectives
     let objectives = &[Objective::within_tolerance(
@@ -670,10 +637,7 @@ fn rugg_raan(almanac: Arc<Almanac>) {
     let orbital_dyn = OrbitalDynamics::two_body();
 
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 89e-3,
-        isp_s: 1650.0,
-    };
+    let lowt = Thruster::new(89e-3, 1650.0);
 
     // Define the objectives
     let objectives = &[Objective::within_tolerance(StateParameter::RAAN, 5.0, 5e-5)];
@@ -724,10 +688,7 @@ fn rugg_raan_regress_threshold(almanac: Arc<Almanac>) {
     let dry_mass = 300.0;
 
     // Define the thruster
-    let lowt = Thruster {
-        thrust_N: 89e-3,
-        isp_s: 1650.0,
-    };
+    let lowt = Thruster::new(89e-3, 1650.0);
 
     // Define the objectives
     let objectives = &[Objective::within_tolerance(StateParameter::RAAN, 5.0, 5e-5)];
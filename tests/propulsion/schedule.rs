@@ -39,10 +39,7 @@ fn val_transfer_schedule_no_depl(almanac: Arc<Almanac>) {
     );
 
     // Define the thruster
-    let monoprop = Thruster {
-        thrust_N: 10.0,
-        isp_s: 300.0,
-    };
+    let monoprop = Thruster::new(10.0, 300.0);
     let dry_mass = 1e3;
     let prop_mass = 756.0;
     let sc_state =
@@ -129,10 +126,7 @@ fn val_transfer_schedule_depl_cov_test(almanac: Arc<Almanac>) {
     );
 
     // Define the thruster
-    let monoprop = Thruster {
-        thrust_N: 10.0,
-        isp_s: 300.0,
-    };
+    let monoprop = Thruster::new(10.0, 300.0);
     let dry_mass = 1e3;
     let prop_mass = 756.0;
     let sc_state =
@@ -257,10 +251,7 @@ fn val_transfer_single_maneuver_depl_cov_test(almanac: Arc<Almanac>) {
     );
 
     // Define the thruster
-    let monoprop = Thruster {
-        thrust_N: 10.0,
-        isp_s: 300.0,
-    };
+    let monoprop = Thruster::new(10.0, 300.0);
     let dry_mass_kg = 1e3;
     let prop_mass_kg = 756.0;
     let sc_state = Spacecraft::from_thruster(